@@ -0,0 +1,80 @@
+//! Throughput of the fixed-step simulation core.
+//!
+//! Measures how many physics steps per second every built-in plant runs at in headless mode, for
+//! a few solver settings. Save a baseline before a change and compare against it afterwards:
+//!
+//! ```sh
+//! cargo bench --bench fixed_step -- --save-baseline main
+//! cargo bench --bench fixed_step -- --baseline main
+//! ```
+use std::num::NonZeroUsize;
+
+use bevy_rapier3d::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants,
+};
+
+/// Solver settings benchmarked for every plant.
+struct SolverSettings {
+    substeps: usize,
+    solver_iterations: usize,
+}
+
+const SOLVER_SETTINGS: [SolverSettings; 3] = [
+    SolverSettings {
+        substeps: 1,
+        solver_iterations: 4,
+    },
+    SolverSettings {
+        substeps: 1,
+        solver_iterations: 8,
+    },
+    SolverSettings {
+        substeps: 4,
+        solver_iterations: 4,
+    },
+];
+
+/// Simulated steps before measuring, so the plant is fully spawned and moving.
+const WARM_UP_STEPS: usize = 60;
+
+fn fixed_step(c: &mut Criterion) {
+    for plant in plants::builtin() {
+        let mut group = c.benchmark_group(format!("fixed_step/{}", plant.name));
+        group.throughput(Throughput::Elements(1));
+        for settings in &SOLVER_SETTINGS {
+            let mut app = headless_app(DEFAULT_TIME_STEP);
+            (plant.add)(&mut app);
+            app.insert_resource(TimestepMode::Fixed {
+                dt: DEFAULT_TIME_STEP,
+                substeps: settings.substeps,
+            });
+            app.finish();
+            app.cleanup();
+            app.update();
+
+            let world = app.world_mut();
+            for mut context in world.query::<&mut RapierContext>().iter_mut(world) {
+                context.integration_parameters.num_solver_iterations =
+                    NonZeroUsize::new(settings.solver_iterations).unwrap();
+            }
+            for _ in 0..WARM_UP_STEPS {
+                app.update();
+            }
+
+            group.bench_function(
+                BenchmarkId::new(
+                    "substeps_iterations",
+                    format!("{}x{}", settings.substeps, settings.solver_iterations),
+                ),
+                |b| b.iter(|| app.update()),
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, fixed_step);
+criterion_main!(benches);
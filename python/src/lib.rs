@@ -0,0 +1,104 @@
+//! Python bindings of the simulation core: a built-in plant stepped by actions, with the
+//! observations returned as lists of floats.
+//!
+//! ```python
+//! from playground_core import Simulation
+//!
+//! sim = Simulation(actions=["motor/velocity"], observations=["pendulum/angle"])
+//! observation = sim.reset(seed=1)
+//! for _ in range(500):
+//!     observation = sim.step([1.0])
+//! ```
+use digital_twin_playground::{
+    environment::{Environment, EnvironmentSettings},
+    error::Error,
+    plants,
+};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+fn to_py(error: Error) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+/// A built-in plant and its controllers, stepped by actions setting setpoints.
+///
+/// The Bevy application isn't `Send`, so a simulation stays on the thread that created it.
+#[pyclass(unsendable)]
+struct Simulation {
+    environment: Environment,
+}
+
+#[pymethods]
+impl Simulation {
+    #[new]
+    #[pyo3(signature = (plant=None, dt=None, substeps=None, actions=None, observations=None))]
+    fn new(
+        plant: Option<String>,
+        dt: Option<f32>,
+        substeps: Option<usize>,
+        actions: Option<Vec<String>>,
+        observations: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        let defaults = EnvironmentSettings::default();
+        let settings = EnvironmentSettings {
+            plant: plant.unwrap_or(defaults.plant),
+            dt: dt.unwrap_or(defaults.dt),
+            substeps: substeps.unwrap_or(defaults.substeps),
+            actions: actions.unwrap_or(defaults.actions),
+            observations: observations.unwrap_or(defaults.observations),
+        };
+        let environment = Environment::new(settings).map_err(to_py)?;
+        Ok(Self { environment })
+    }
+
+    /// Builds the plant anew and returns the first observation.
+    #[pyo3(signature = (seed=0))]
+    fn reset(&mut self, seed: u64) -> PyResult<Vec<f32>> {
+        self.environment.reset(seed).map_err(to_py)
+    }
+
+    /// Sets the setpoints of the action, advances by the substeps and returns the observation.
+    fn step(&mut self, action: Vec<f32>) -> PyResult<Vec<f32>> {
+        self.environment.step(&action).map_err(to_py)
+    }
+
+    /// Latest value of each observed signal, NaN for those not recorded yet.
+    fn observation(&self) -> Vec<f32> {
+        self.environment.observation()
+    }
+
+    /// Simulated time since the reset, in seconds.
+    #[getter]
+    fn time(&self) -> f32 {
+        self.environment.time()
+    }
+
+    /// Simulated time of a step, in seconds.
+    #[getter]
+    fn dt(&self) -> f32 {
+        self.environment.settings.dt * self.environment.settings.substeps as f32
+    }
+
+    #[getter]
+    fn actions(&self) -> Vec<String> {
+        self.environment.settings.actions.clone()
+    }
+
+    #[getter]
+    fn observations(&self) -> Vec<String> {
+        self.environment.settings.observations.clone()
+    }
+}
+
+/// Names of the built-in plants.
+#[pyfunction]
+fn builtin_plants() -> Vec<&'static str> {
+    plants::builtin().iter().map(|plant| plant.name).collect()
+}
+
+#[pymodule]
+fn playground_core(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Simulation>()?;
+    module.add_function(wrap_pyfunction!(builtin_plants, module)?)?;
+    Ok(())
+}
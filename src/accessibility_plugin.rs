@@ -0,0 +1,373 @@
+//! This module provides a plugin that makes the panels usable from the keyboard and with a
+//! screen reader, and scales them for high-DPI displays and projectors.
+//!
+//! egui moves the focus between the widgets with Tab, Shift+Tab and the arrows, activates the
+//! focused one with Space or Enter and releases the focus with Escape. While a widget has the
+//! focus, the keyboard controls of the simulation are suspended (see [`keyboard_available`]),
+//! so navigating a panel doesn't drive the motor. The focused widget is mirrored to an
+//! AccessKit node, so screen readers announce its label, role and value.
+//!
+//! The panels and the text are scaled by the `scale` of `ui.json`, on top of the scale factor of
+//! the display. Ctrl and +/- change it by steps of 10 %, Ctrl+0 resets it.
+use accesskit::{Node, Role, Toggled};
+use bevy::{
+    a11y::{AccessibilityNode, AccessibilitySystem, Focus},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_inspector_egui::bevy_egui::{
+    egui::{self, collapsing_header::CollapsingState, output::OutputEvent, WidgetInfo, WidgetType},
+    EguiContext, EguiOutput, EguiSet, EguiSettings, EguiUserTextures,
+};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_plugin,
+    error::{Error, ErrorEvent},
+};
+
+/// Smallest scale of the interface.
+pub const MIN_UI_SCALE: f32 = 0.5;
+/// Largest scale of the interface.
+pub const MAX_UI_SCALE: f32 = 4.0;
+/// Change of the scale of the interface per key press.
+pub const UI_SCALE_STEP: f32 = 0.1;
+
+pub struct UiAccessibilityPlugin;
+
+impl Plugin for UiAccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PanelTitles>()
+            .init_resource::<WidgetFocused>()
+            .add_systems(Startup, (setup, spawn_focus_proxy))
+            .add_systems(PreUpdate, track_focus.after(EguiSet::BeginPass))
+            .add_systems(
+                Update,
+                (
+                    configure_egui,
+                    (scale_hotkeys, apply_ui_scale)
+                        .chain()
+                        .run_if(resource_exists::<Persistent<UiSettings>>),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                mirror_focus
+                    .after(EguiSet::ProcessOutput)
+                    .before(AccessibilitySystem::Update),
+            );
+    }
+}
+
+/// Represents the configuration of the interface.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct UiSettings {
+    /// Scale of the panels and of the text, on top of the scale factor of the display.
+    pub scale: f32,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self { scale: 1.0 }
+    }
+}
+
+impl UiSettings {
+    /// The scale moved by `steps` steps, within the supported range.
+    pub fn stepped_scale(&self, steps: i32) -> f32 {
+        let scale = (self.scale + steps as f32 * UI_SCALE_STEP).clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        // Avoids drifting away from round values after a few steps.
+        (scale / UI_SCALE_STEP).round() * UI_SCALE_STEP
+    }
+}
+
+/// Titles of the collapsible panels. egui doesn't describe the buttons collapsing the windows,
+/// so they are named after these.
+#[derive(Resource)]
+pub struct PanelTitles(pub Vec<String>);
+
+impl Default for PanelTitles {
+    fn default() -> Self {
+        let titles = [
+            "Actuators",
+            "Anomalies",
+            "Audio",
+            "Autotune",
+            "Calibration",
+            "Camera sequence",
+            "Cameras",
+            "Capture",
+            "Colliders",
+            "Comparison",
+            "Contacts",
+            "Custom controllers",
+            "Disturbances",
+            "Dual-loop controller",
+            "Estimation",
+            "Estimator replay",
+            "Experiments",
+            "Extensions",
+            "Files",
+            "Fixtures",
+            "Frames",
+            "Frequency response",
+            "Friction",
+            "Gamepad mapping",
+            "Gantry controller",
+            "Haptics",
+            "Hardware log",
+            "HMI",
+            "Input bindings",
+            "Interaction",
+            "Jog",
+            "Joint authoring",
+            "Joint limits",
+            "Kinematic playback",
+            "Kinematics",
+            "Latency",
+            "Level of detail",
+            "Lighting",
+            "Log console",
+            "LQG",
+            "LQR controller",
+            "Mass overrides",
+            "Model library",
+            "Monitors",
+            "MPC controller",
+            "Overlays",
+            "Physics parameters",
+            "PID controller",
+            "Plots",
+            "Proximity",
+            "Recording",
+            "Reference governor",
+            "Replay",
+            "Run diff",
+            "Scripted controller",
+            "Selection",
+            "Self-collision",
+            "Sensorless",
+            "Sensors",
+            "Session",
+            "Setpoint command",
+            "Simulation thread",
+            "Snapshots",
+            "State machines",
+            "Step response",
+            "Stereo",
+            "Supervisor",
+            "Swing-up",
+            "Teach",
+            "Theme",
+            "Traces",
+            "Trajectory",
+            "Trajectory optimization",
+            "Transmissions",
+            "Transport",
+            "Watchdog",
+            "Waveforms",
+            "World Inspector",
+        ];
+        Self(titles.map(String::from).to_vec())
+    }
+}
+
+/// Whether a widget of the interface has the focus, as of the current frame.
+#[derive(Default, Resource)]
+pub struct WidgetFocused(pub bool);
+
+/// Whether the simulation may handle the keyboard: no widget of the interface has the focus.
+pub fn keyboard_available(focused: Option<Res<WidgetFocused>>) -> bool {
+    focused.is_none_or(|focused| !focused.0)
+}
+
+/// Whether the panels can be drawn, i.e. the application isn't headless. Run condition of the
+/// systems drawing a panel.
+pub fn has_ui(textures: Option<Res<EguiUserTextures>>) -> bool {
+    textures.is_some()
+}
+
+/// Describes a widget of egui to the screen readers.
+pub fn describe_widget(info: &WidgetInfo) -> Node {
+    let mut node = Node::new(match info.typ {
+        WidgetType::Label => Role::Label,
+        WidgetType::Link => Role::Link,
+        WidgetType::TextEdit => Role::TextInput,
+        WidgetType::Button
+        | WidgetType::ImageButton
+        | WidgetType::CollapsingHeader
+        | WidgetType::SelectableLabel => Role::Button,
+        WidgetType::Checkbox => Role::CheckBox,
+        WidgetType::RadioButton => Role::RadioButton,
+        WidgetType::RadioGroup => Role::RadioGroup,
+        WidgetType::ComboBox => Role::ComboBox,
+        WidgetType::Slider => Role::Slider,
+        WidgetType::DragValue => Role::SpinButton,
+        WidgetType::ColorButton => Role::ColorWell,
+        WidgetType::ProgressIndicator => Role::ProgressIndicator,
+        WidgetType::Other => Role::Unknown,
+    });
+    if !info.enabled {
+        node.set_disabled();
+    }
+    if let Some(label) = &info.label {
+        node.set_label(label.as_str());
+    }
+    if let Some(text) = &info.current_text_value {
+        node.set_value(text.as_str());
+    }
+    if let Some(value) = info.value {
+        node.set_numeric_value(value);
+    }
+    if let Some(selected) = info.selected {
+        node.set_toggled(if selected {
+            Toggled::True
+        } else {
+            Toggled::False
+        });
+    }
+    node
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<UiSettings>("ui", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Stands for the focused widget in the accessibility tree.
+#[derive(Component)]
+struct FocusProxy;
+
+fn spawn_focus_proxy(mut commands: Commands) {
+    commands.spawn((
+        FocusProxy,
+        AccessibilityNode::from(Node::new(Role::Unknown)),
+    ));
+}
+
+fn track_focus(
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut focused: ResMut<WidgetFocused>,
+) {
+    let Ok(mut context) = contexts.get_single_mut() else {
+        return;
+    };
+    let widget_focused = context
+        .get_mut()
+        .memory(|memory| memory.focused().is_some());
+    if focused.0 != widget_focused {
+        focused.0 = widget_focused;
+    }
+}
+
+/// Makes egui report the widgets gaining the focus, and lets the labels be focused too. The
+/// zoom of egui is disabled, as the scale of the interface takes over its shortcuts.
+fn configure_egui(mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>) {
+    let Ok(mut context) = contexts.get_single_mut() else {
+        return;
+    };
+    let ctx = context.get_mut();
+    if !ctx.options(|options| options.screen_reader && !options.zoom_with_keyboard) {
+        ctx.options_mut(|options| {
+            options.screen_reader = true;
+            options.zoom_with_keyboard = false;
+        });
+    }
+}
+
+/// Changes the scale of the interface with Ctrl and +/-, and resets it with Ctrl+0.
+fn scale_hotkeys(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Persistent<UiSettings>>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let scale = if keys.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        settings.stepped_scale(1)
+    } else if keys.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        settings.stepped_scale(-1)
+    } else if keys.any_just_pressed([KeyCode::Digit0, KeyCode::Numpad0]) {
+        UiSettings::default().scale
+    } else {
+        return;
+    };
+    if scale == settings.scale {
+        return;
+    }
+    info!("Scale of the interface: {:.0} %", scale * 100.0);
+    // Kept across runs right away, as the zoom of a browser.
+    if let Err(error) = settings.update(|settings| settings.scale = scale) {
+        commands.send_event(ErrorEvent::from(Error::save("ui", error)));
+    }
+}
+
+/// Applies the scale to the panels and to the text of the interface.
+fn apply_ui_scale(
+    settings: Res<Persistent<UiSettings>>,
+    mut egui_settings: Query<&mut EguiSettings, With<PrimaryWindow>>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    let scale = settings.scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+    // The settings of egui appear with the window.
+    if let Ok(mut egui_settings) = egui_settings.get_single_mut() {
+        if egui_settings.scale_factor != scale {
+            egui_settings.scale_factor = scale;
+        }
+    }
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+/// Mirrors the widget having the focus in egui to the focus proxy.
+fn mirror_focus(
+    mut contexts: Query<(&mut EguiContext, &EguiOutput), With<PrimaryWindow>>,
+    mut proxies: Query<(Entity, &mut AccessibilityNode), With<FocusProxy>>,
+    titles: Res<PanelTitles>,
+    mut focus: ResMut<Focus>,
+) {
+    let (Ok((mut context, output)), Ok((proxy, mut node))) =
+        (contexts.get_single_mut(), proxies.get_single_mut())
+    else {
+        return;
+    };
+    let ctx = context.get_mut();
+    let focused = ctx.memory(|memory| memory.focused());
+
+    if let Some(focused) = focused {
+        let panel = titles
+            .0
+            .iter()
+            .find(|title| egui::Id::new(title.as_str()).with("collapsing") == focused);
+        if let Some(title) = panel {
+            let mut button = Node::new(Role::Button);
+            button.set_label(format!("{title} panel"));
+            if let Some(state) = CollapsingState::load(ctx, focused) {
+                button.set_expanded(state.is_open());
+            }
+            *node = AccessibilityNode::from(button);
+        } else if let Some(info) = output
+            .platform_output
+            .events
+            .iter()
+            .rev()
+            .map(OutputEvent::widget_info)
+            .next()
+        {
+            *node = AccessibilityNode::from(describe_widget(info));
+        }
+    }
+
+    // Without a focused widget, the window has the focus.
+    let target = focused.map(|_| proxy);
+    if focus.0 != target {
+        focus.0 = target;
+    }
+}
@@ -0,0 +1,637 @@
+//! Actuators: the revolute joints are driven through a model of a geared DC motor rather than
+//! an ideal joint motor, so the torque the controllers get is the one a real drive delivers.
+//!
+//! The controllers still command a joint velocity, and the joint motor still pulls towards it,
+//! but before each physics step its torque is capped to what the motor gives at the current
+//! speed: the torque-speed curve of a DC motor, falling linearly from its stall torque at rest
+//! to nothing at its no-load speed, and the torque of its current limit. The torque constant
+//! and the supply voltage follow from the stall torque, the no-load speed and the resistance of
+//! the winding. The gearbox multiplies the torque by its ratio, less its losses, and divides the
+//! speed. When the motor brakes the joint, the back-EMF adds to the supply instead, and the
+//! torque grows with the speed up to the current limit.
+//!
+//! The current drawn and the torque available are recorded as the
+//! `actuator/<link>/current` and `actuator/<link>/torque_limit` telemetry channels. Over a run,
+//! the actuators also add up the electrical energy drawn from the supply, the losses in the
+//! winding and the gearbox included, and the mechanical work done on the joints, recorded as
+//! `actuator/<link>/power`, `actuator/<link>/energy` and `actuator/<link>/efficiency`. The
+//! energy a braking motor sends back isn't recovered by the drive.
+//!
+//! The winding heats up with the losses in its resistance, and cools down to the ambient
+//! temperature through its thermal resistance, over its thermal time constant. Past the derating
+//! temperature, the drive lets less and less torque through, down to none at the largest
+//! temperature of the winding, so a controller demanding too much current for too long loses
+//! its motor. The temperature and the heat given off are recorded as
+//! `actuator/<link>/temperature` and `actuator/<link>/heat`, and shown as a gauge per motor.
+//!
+//! The actuators are added to the joints of the configured links, as configured in
+//! `actuators.json`.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::{self, Link},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the actuators.
+pub const ACTUATOR_PREFIX: &str = "actuator/";
+
+pub struct ActuatorsPlugin;
+
+impl Plugin for ActuatorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_actuators.run_if(resource_exists::<Persistent<ActuatorSettings>>),
+            )
+            .add_systems(PostUpdate, drive.before(PhysicsSet::SyncBackend))
+            .add_systems(
+                Update,
+                actuators_panel
+                    .run_if(resource_exists::<Persistent<ActuatorSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A geared DC motor driving the joint of a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct DcActuator {
+    /// Name of the link whose joint is driven, in each plant.
+    pub link: String,
+    /// Torque of the motor at rest, at the supply voltage, in N·m.
+    pub stall_torque: f32,
+    /// Speed of the motor without load, in rad/s.
+    pub no_load_speed: f32,
+    /// Resistance of the winding, in Ω.
+    pub resistance: f32,
+    /// Turns of the motor per turn of the joint.
+    pub gear_ratio: f32,
+    /// Share of the torque of the motor reaching the joint.
+    pub efficiency: f32,
+    /// Largest current of the drive, in A, or 0 for none.
+    pub current_limit: f32,
+    /// Thermal resistance from the winding to the ambient air, in K/W.
+    pub thermal_resistance: f32,
+    /// Time constant of the heating of the winding, in s.
+    pub thermal_time_constant: f32,
+    /// Temperature of the air around the motor, in °C.
+    pub ambient_temperature: f32,
+    /// Temperature of the winding over which the drive derates the torque, in °C.
+    pub derating_temperature: f32,
+    /// Temperature of the winding at which the drive cuts the torque, in °C.
+    pub max_temperature: f32,
+}
+
+impl Default for DcActuator {
+    fn default() -> Self {
+        Self {
+            link: "motor".to_string(),
+            stall_torque: 0.5,
+            no_load_speed: 500.0,
+            resistance: 1.2,
+            gear_ratio: 10.0,
+            efficiency: 0.9,
+            current_limit: 5.0,
+            thermal_resistance: 3.0,
+            thermal_time_constant: 60.0,
+            ambient_temperature: 25.0,
+            derating_temperature: 100.0,
+            max_temperature: 130.0,
+        }
+    }
+}
+
+impl DcActuator {
+    /// Torque per unit of current, in N·m/A, equal to the back-EMF per unit of speed.
+    pub fn torque_constant(&self) -> f32 {
+        if self.no_load_speed <= 0.0 {
+            return 0.0;
+        }
+        (self.stall_torque * self.resistance / self.no_load_speed)
+            .max(0.0)
+            .sqrt()
+    }
+
+    /// Voltage of the supply, in V.
+    pub fn voltage(&self) -> f32 {
+        self.torque_constant() * self.no_load_speed
+    }
+
+    /// Torque of the gearbox per unit of torque of the motor.
+    fn multiplier(&self) -> f32 {
+        self.gear_ratio * self.efficiency
+    }
+
+    /// Largest torque on the joint turning at `speed` (rad/s), pushing in the direction of
+    /// `direction`, in N·m.
+    pub fn torque_limit(&self, speed: f32, direction: f32) -> f32 {
+        let motor_speed = speed * self.gear_ratio;
+        let slowdown = motor_speed.abs() / self.no_load_speed.max(f32::EPSILON);
+        let curve = if motor_speed * direction > 0.0 {
+            // Driving: the back-EMF leaves less voltage to the winding.
+            self.stall_torque * (1.0 - slowdown)
+        } else {
+            // Braking: the back-EMF adds to the voltage of the supply.
+            self.stall_torque * (1.0 + slowdown)
+        };
+        let current = if self.current_limit > 0.0 {
+            self.torque_constant() * self.current_limit
+        } else {
+            f32::INFINITY
+        };
+        (curve.min(current) * self.multiplier()).max(0.0)
+    }
+
+    /// Current drawn to apply `torque` (N·m) on the joint, in A.
+    pub fn current(&self, torque: f32) -> f32 {
+        let scale = self.torque_constant() * self.multiplier();
+        if scale == 0.0 {
+            0.0
+        } else {
+            torque / scale
+        }
+    }
+
+    /// Electrical power drawn to apply `torque` (N·m) on the joint turning at `speed` (rad/s),
+    /// in W: the losses in the winding and the power converted by the motor, negative when
+    /// braking beyond the losses.
+    pub fn power(&self, torque: f32, speed: f32) -> f32 {
+        let current = self.current(torque);
+        self.resistance * current * current
+            + self.torque_constant() * current * speed * self.gear_ratio
+    }
+
+    /// Heat given off by the winding to apply `torque` (N·m) on the joint, in W.
+    pub fn copper_losses(&self, torque: f32) -> f32 {
+        let current = self.current(torque);
+        self.resistance * current * current
+    }
+
+    /// Temperature of the winding at `temperature` (°C) after `dt` seconds giving off `losses`
+    /// (W), in °C: it settles exponentially where the losses are carried away to the air.
+    pub fn heat(&self, temperature: f32, losses: f32, dt: f32) -> f32 {
+        let settled = self.ambient_temperature + losses * self.thermal_resistance;
+        if self.thermal_time_constant <= 0.0 {
+            return settled;
+        }
+        settled + (temperature - settled) * (-dt / self.thermal_time_constant).exp()
+    }
+
+    /// Share of the torque the drive lets through with the winding at `temperature` (°C): all
+    /// of it up to the derating temperature, falling linearly to none at the largest
+    /// temperature.
+    pub fn derating(&self, temperature: f32) -> f32 {
+        if temperature <= self.derating_temperature {
+            1.0
+        } else if temperature >= self.max_temperature {
+            0.0
+        } else {
+            (self.max_temperature - temperature)
+                / (self.max_temperature - self.derating_temperature)
+        }
+    }
+}
+
+/// Energy drawn by actuators and work they do on their joints, over a run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Energy {
+    /// Electrical energy drawn from the supply, in J.
+    pub electrical: f32,
+    /// Mechanical work done on the joints, in J.
+    pub mechanical: f32,
+    /// Heat given off by the windings, in J.
+    pub heat: f32,
+}
+
+impl Energy {
+    /// Share of the electrical energy turned into work on the joints, unless none was drawn.
+    pub fn efficiency(&self) -> Option<f32> {
+        (self.electrical > 0.0).then(|| self.mechanical / self.electrical)
+    }
+}
+
+/// Energy of all the actuators of `world` over the run, unless there are none.
+pub fn total(world: &mut World) -> Option<Energy> {
+    world
+        .query::<&Actuator>()
+        .iter(world)
+        .map(Actuator::energy)
+        .reduce(|total, energy| Energy {
+            electrical: total.electrical + energy.electrical,
+            mechanical: total.mechanical + energy.mechanical,
+            heat: total.heat + energy.heat,
+        })
+}
+
+/// Represents the configuration of the actuators.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ActuatorSettings {
+    /// The actuators; the joints without one are driven by ideal motors.
+    pub actuators: Vec<DcActuator>,
+}
+
+/// An actuator driving the revolute joint of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Actuator {
+    pub model: DcActuator,
+    /// Prefix of the telemetry channels.
+    pub channel: String,
+    /// Time and angle of the joint at the last step.
+    last: Option<(f32, f32)>,
+    /// Speed of the joint, in rad/s.
+    speed: f32,
+    /// Torque applied on the joint since the last step, in N·m.
+    torque: f32,
+    energy: Energy,
+    /// Temperature of the winding, in °C.
+    temperature: f32,
+    peak_temperature: f32,
+    /// Whether the drive derated the torque at the last step.
+    derated: bool,
+}
+
+impl Actuator {
+    pub fn new(model: DcActuator, channel: String) -> Self {
+        let temperature = model.ambient_temperature;
+        Self {
+            model,
+            channel,
+            last: None,
+            speed: 0.0,
+            torque: 0.0,
+            energy: Energy::default(),
+            temperature,
+            peak_temperature: temperature,
+            derated: false,
+        }
+    }
+
+    /// Updates the speed of the joint from its `angle` at `time`, and the energy spent and the
+    /// temperature of the winding since the last step.
+    pub fn measure(&mut self, time: f32, angle: f32) {
+        match self.last {
+            Some((last, previous)) if time > last => {
+                let dt = time - last;
+                // The angle wraps around within a turn.
+                let turned = PI - (PI - (angle - previous)).rem_euclid(TAU);
+                self.speed = turned / dt;
+                let power = self.model.power(self.torque, self.speed);
+                self.energy.electrical += power.max(0.0) * dt;
+                self.energy.mechanical += (self.torque * self.speed).max(0.0) * dt;
+                let losses = self.model.copper_losses(self.torque);
+                self.energy.heat += losses * dt;
+                self.temperature = self.model.heat(self.temperature, losses, dt);
+                self.peak_temperature = self.peak_temperature.max(self.temperature);
+            }
+            Some((last, _)) if time == last => return,
+            // Rewound, or a new run.
+            _ => {
+                self.speed = 0.0;
+                self.torque = 0.0;
+                self.energy = Energy::default();
+                self.temperature = self.model.ambient_temperature;
+                self.peak_temperature = self.temperature;
+                self.derated = false;
+            }
+        }
+        self.last = Some((time, angle));
+    }
+
+    /// Speed of the joint, in rad/s.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets the torque applied on the joint until the next step, in N·m.
+    pub fn apply(&mut self, torque: f32) {
+        self.torque = torque;
+    }
+
+    /// Energy spent since the start of the run.
+    pub fn energy(&self) -> Energy {
+        self.energy
+    }
+
+    /// Electrical power drawn at the last step, in W.
+    pub fn power(&self) -> f32 {
+        self.model.power(self.torque, self.speed)
+    }
+
+    /// Temperature of the winding, in °C.
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    /// Highest temperature of the winding since the start of the run, in °C.
+    pub fn peak_temperature(&self) -> f32 {
+        self.peak_temperature
+    }
+
+    /// Share of the torque the drive lets through at the temperature of the winding.
+    pub fn derating(&self) -> f32 {
+        self.model.derating(self.temperature)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ActuatorSettings>("actuators", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds the configured actuators to the joints, as they spawn or when the configuration
+/// changes. A joint losing its actuator gets its ideal motor back.
+fn configure_actuators(
+    mut commands: Commands,
+    settings: Res<Persistent<ActuatorSettings>>,
+    mut joints: Query<(Entity, &Link, Option<&Actuator>, &mut ImpulseJoint)>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, actuator, mut joint) in &mut joints {
+        let configured = settings
+            .actuators
+            .iter()
+            .find(|actuator| actuator.link == link.name);
+        match (configured, actuator) {
+            (Some(configured), Some(actuator)) if actuator.model == *configured => {}
+            (Some(configured), _) => {
+                let channel = format!("{ACTUATOR_PREFIX}{}", link.name);
+                commands.entity(entity).insert(Actuator::new(
+                    configured.clone(),
+                    plants::namespaced(&link.plant, &channel),
+                ));
+                info!(target: subsystem::CONTROL, "Actuator on {}", link.path());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Actuator>();
+                joint
+                    .data
+                    .as_mut()
+                    .set_motor_max_force(JointAxis::AngX, f32::MAX);
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Caps the torque of the joint motors to what their actuators deliver, before each step.
+pub(crate) fn drive(
+    clock: Res<SimClock>,
+    mut actuators: Query<(Entity, &mut Actuator, &mut ImpulseJoint)>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    for (entity, mut actuator, mut joint) in &mut actuators {
+        let Some(angle) = context.impulse_revolute_joint_angle(entity) else {
+            continue;
+        };
+        actuator.measure(time, angle);
+        let Some(motor) = joint.data.as_ref().motor(JointAxis::AngX).copied() else {
+            continue;
+        };
+        // The joint motor is a damper pulling towards the commanded velocity.
+        let speed = actuator.speed();
+        let wanted = motor.damping * (motor.target_vel - speed);
+        let derating = actuator.derating();
+        if derating < 1.0 && !actuator.derated {
+            warn!(
+                target: subsystem::CONTROL,
+                "{} derated at {:.1} °C",
+                actuator.channel,
+                actuator.temperature()
+            );
+        }
+        actuator.derated = derating < 1.0;
+        let limit = actuator.model.torque_limit(speed, wanted) * derating;
+        joint
+            .data
+            .as_mut()
+            .set_motor_max_force(JointAxis::AngX, limit);
+        let torque = wanted.clamp(-limit, limit);
+        actuator.apply(torque);
+        let Some(telemetry) = telemetry.as_mut() else {
+            continue;
+        };
+        let channel = &actuator.channel;
+        let model = &actuator.model;
+        let energy = actuator.energy();
+        telemetry.record(&format!("{channel}/current"), time, model.current(torque));
+        telemetry.record(&format!("{channel}/torque_limit"), time, limit);
+        telemetry.record(
+            &format!("{channel}/power"),
+            time,
+            model.power(torque, speed),
+        );
+        telemetry.record(&format!("{channel}/energy"), time, energy.electrical);
+        if let Some(efficiency) = energy.efficiency() {
+            telemetry.record(&format!("{channel}/efficiency"), time, efficiency);
+        }
+        telemetry.record(
+            &format!("{channel}/temperature"),
+            time,
+            actuator.temperature(),
+        );
+        telemetry.record(&format!("{channel}/heat"), time, energy.heat);
+    }
+}
+
+/// Panel to tune the actuators.
+fn actuators_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ActuatorSettings>>,
+    actuators: Query<&Actuator>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Actuators")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if !actuators.is_empty() {
+                let mut running: Vec<_> = actuators.iter().collect();
+                running.sort_by(|a, b| a.channel.cmp(&b.channel));
+                for actuator in running {
+                    temperature_gauge(ui, actuator);
+                }
+                ui.separator();
+            }
+            let mut removed = None;
+            for (index, actuator) in edited.actuators.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut actuator.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                egui::Grid::new(("actuator", index)).show(ui, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.stall_torque)
+                            .range(0.0..=1000.0)
+                            .speed(0.01)
+                            .prefix("Stall torque: ")
+                            .suffix(" N·m"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.no_load_speed)
+                            .range(0.0..=10_000.0)
+                            .prefix("No-load speed: ")
+                            .suffix(" rad/s"),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.resistance)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Resistance: ")
+                            .suffix(" Ω"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.current_limit)
+                            .range(0.0..=1000.0)
+                            .speed(0.1)
+                            .prefix("Current limit: ")
+                            .suffix(" A"),
+                    )
+                    .on_hover_text("0 for none");
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.gear_ratio)
+                            .range(0.01..=1000.0)
+                            .speed(0.1)
+                            .prefix("Gear ratio: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.efficiency)
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .prefix("Efficiency: "),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.thermal_resistance)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Thermal resistance: ")
+                            .suffix(" K/W"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.thermal_time_constant)
+                            .range(0.0..=10_000.0)
+                            .prefix("Time constant: ")
+                            .suffix(" s"),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.ambient_temperature)
+                            .range(-50.0..=100.0)
+                            .prefix("Ambient: ")
+                            .suffix(" °C"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.derating_temperature)
+                            .range(-50.0..=300.0)
+                            .prefix("Derating from: ")
+                            .suffix(" °C"),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut actuator.max_temperature)
+                            .range(-50.0..=300.0)
+                            .prefix("Cut at: ")
+                            .suffix(" °C"),
+                    );
+                    ui.end_row();
+                });
+                ui.weak(format!(
+                    "{:.4} N·m/A at {:.1} V, up to {:.2} N·m on the joint",
+                    actuator.torque_constant(),
+                    actuator.voltage(),
+                    actuator.torque_limit(0.0, 1.0)
+                ));
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.actuators.remove(index);
+            }
+            if ui.button("Add an actuator").clicked() {
+                edited.actuators.push(DcActuator::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("actuators", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("actuators", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Gauge of the temperature of the winding of a running actuator, from the ambient temperature
+/// to the largest one, with its power and energy.
+fn temperature_gauge(ui: &mut egui::Ui, actuator: &Actuator) {
+    let model = &actuator.model;
+    let span = model.max_temperature - model.ambient_temperature;
+    let fraction = if span > 0.0 {
+        ((actuator.temperature() - model.ambient_temperature) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let derating = actuator.derating();
+    let mut gauge = egui::ProgressBar::new(fraction).text(format!(
+        "{:.1} °C (peak {:.1} °C)",
+        actuator.temperature(),
+        actuator.peak_temperature()
+    ));
+    if derating < 1.0 {
+        gauge = gauge.fill(egui::Color32::from_rgb(200, 60, 40));
+    }
+    ui.label(&actuator.channel);
+    ui.add(gauge).on_hover_text(format!(
+        "Derating from {:.0} °C, cut at {:.0} °C",
+        model.derating_temperature, model.max_temperature
+    ));
+    let energy = actuator.energy();
+    ui.weak(format!(
+        "{:.1} W, {:.1} J drawn, {:.1} J of heat, {:.0} % of the torque",
+        actuator.power(),
+        energy.electrical,
+        energy.heat,
+        derating * 100.0
+    ));
+}
@@ -0,0 +1,285 @@
+//! Controllability and observability of linear models, e.g. identified with
+//! [`identify`](crate::identification::identify).
+//!
+//! The ranks of the controllability and observability matrices tell whether every state can be
+//! reached and seen at all. The Gramians tell how much energy it takes: a small eigenvalue of
+//! the controllability Gramian is a direction the actuators hardly move, one of the
+//! observability Gramian a direction the sensors hardly see. Per mode, the Popov-Belevitch-Hautus
+//! test measures how far each eigenvalue is from losing controllability or observability, which
+//! points at the modes calling for another actuator or sensor.
+use nalgebra::{Complex, DMatrix};
+
+use crate::identification::LinearModel;
+
+/// Horizon of the Gramians of the reports, in seconds.
+pub const DEFAULT_HORIZON: f32 = 10.0;
+/// Modes whose measure is below this fraction of the norm of the model are reported as weak.
+pub const WEAK_MODE_THRESHOLD: f64 = 1e-3;
+
+/// Gramians over a finite horizon, defined even for unstable models.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gramians {
+    /// `Σ A^k B Bᵀ (Aᵀ)^k`
+    pub controllability: DMatrix<f64>,
+    /// `Σ (Aᵀ)^k Cᵀ C A^k`
+    pub observability: DMatrix<f64>,
+}
+
+impl Gramians {
+    /// Gramians of `model` over `steps` steps.
+    pub fn of(model: &LinearModel, steps: usize) -> Self {
+        let order = model.order();
+        let mut controllability = DMatrix::zeros(order, order);
+        let mut observability = DMatrix::zeros(order, order);
+        let mut reached = model.b.clone();
+        let mut seen = model.c.clone();
+        for _ in 0..steps {
+            controllability += &reached * reached.transpose();
+            observability += seen.transpose() * &seen;
+            reached = &model.a * reached;
+            seen *= &model.a;
+        }
+        Self {
+            controllability,
+            observability,
+        }
+    }
+}
+
+/// `[B, A B, …, A^(n-1) B]`
+pub fn controllability_matrix(model: &LinearModel) -> DMatrix<f64> {
+    let (order, inputs) = (model.order(), model.b.ncols());
+    let mut matrix = DMatrix::zeros(order, order * inputs);
+    let mut block = model.b.clone();
+    for k in 0..order {
+        matrix.columns_mut(k * inputs, inputs).copy_from(&block);
+        block = &model.a * block;
+    }
+    matrix
+}
+
+/// `[C; C A; …; C A^(n-1)]`
+pub fn observability_matrix(model: &LinearModel) -> DMatrix<f64> {
+    let (order, outputs) = (model.order(), model.c.nrows());
+    let mut matrix = DMatrix::zeros(order * outputs, order);
+    let mut block = model.c.clone();
+    for k in 0..order {
+        matrix.rows_mut(k * outputs, outputs).copy_from(&block);
+        block *= &model.a;
+    }
+    matrix
+}
+
+/// Numerical rank, counting the singular values above the usual tolerance.
+pub fn rank(matrix: &DMatrix<f64>) -> usize {
+    if matrix.is_empty() {
+        return 0;
+    }
+    let singular_values = matrix.singular_values();
+    let tolerance =
+        singular_values.max() * matrix.nrows().max(matrix.ncols()) as f64 * f64::EPSILON;
+    singular_values.iter().filter(|s| **s > tolerance).count()
+}
+
+/// A mode of the model: an eigenvalue of `A`, with a complex conjugate pair counted once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mode {
+    /// Eigenvalue of the discrete-time model.
+    pub eigenvalue: Complex<f64>,
+    /// Natural frequency of the equivalent continuous-time mode, in Hz.
+    pub frequency: f64,
+    /// Damping ratio of the equivalent continuous-time mode (negative if unstable).
+    pub damping: f64,
+    /// Smallest singular value of `[λI - A, B]`, relative to the norm of `[A, B]`: 0 if the mode
+    /// is uncontrollable.
+    pub controllability: f64,
+    /// Smallest singular value of `[λI - A; C]`, relative to the norm of `[A; C]`: 0 if the mode
+    /// is unobservable.
+    pub observability: f64,
+}
+
+impl Mode {
+    pub fn weakly_controllable(&self) -> bool {
+        self.controllability < WEAK_MODE_THRESHOLD
+    }
+
+    pub fn weakly_observable(&self) -> bool {
+        self.observability < WEAK_MODE_THRESHOLD
+    }
+}
+
+/// Modes of the model, from the slowest to the fastest.
+pub fn modes(model: &LinearModel) -> Vec<Mode> {
+    let order = model.order();
+    let a = model.a.map(|value| Complex::new(value, 0.0));
+    let b = model.b.map(|value| Complex::new(value, 0.0));
+    let c = model.c.map(|value| Complex::new(value, 0.0));
+    let input_scale = norm(&stack_columns(&a, &b)).max(f64::MIN_POSITIVE);
+    let output_scale = norm(&stack_rows(&a, &c)).max(f64::MIN_POSITIVE);
+
+    let mut modes = model
+        .a
+        .complex_eigenvalues()
+        .iter()
+        .filter(|eigenvalue| eigenvalue.im >= 0.0)
+        .map(|&eigenvalue| {
+            let shifted = DMatrix::from_diagonal_element(order, order, eigenvalue) - &a;
+            // The equivalent continuous-time pole.
+            let pole = eigenvalue.ln() / f64::from(model.dt);
+            Mode {
+                eigenvalue,
+                frequency: pole.norm() / std::f64::consts::TAU,
+                damping: if pole.norm() > 0.0 {
+                    -pole.re / pole.norm()
+                } else {
+                    0.0
+                },
+                controllability: smallest_singular_value(&stack_columns(&shifted, &b))
+                    / input_scale,
+                observability: smallest_singular_value(&stack_rows(&shifted, &c)) / output_scale,
+            }
+        })
+        .collect::<Vec<_>>();
+    modes.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    modes
+}
+
+/// The least damped stable resonance of the model that the inputs excite and the outputs show:
+/// the one ringing the longest after a move.
+pub fn dominant_resonance(model: &LinearModel) -> Option<Mode> {
+    modes(model)
+        .into_iter()
+        .filter(|mode| mode.eigenvalue.im > 0.0 && (0.0..1.0).contains(&mode.damping))
+        .filter(|mode| !mode.weakly_controllable() && !mode.weakly_observable())
+        .min_by(|a, b| a.damping.total_cmp(&b.damping))
+}
+
+/// Controllability and observability of a model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub order: usize,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Rank of the controllability matrix; the model is controllable if it equals the order.
+    pub controllable_rank: usize,
+    /// Rank of the observability matrix; the model is observable if it equals the order.
+    pub observable_rank: usize,
+    /// Horizon of the Gramians, in seconds.
+    pub horizon: f32,
+    pub gramians: Gramians,
+    pub modes: Vec<Mode>,
+}
+
+impl Analysis {
+    /// Analyzes `model`, with Gramians over `horizon` seconds.
+    pub fn of(model: &LinearModel, horizon: f32) -> Self {
+        let steps = (horizon / model.dt).round().max(1.0) as usize;
+        Self {
+            order: model.order(),
+            inputs: model.inputs.clone(),
+            outputs: model.outputs.clone(),
+            controllable_rank: rank(&controllability_matrix(model)),
+            observable_rank: rank(&observability_matrix(model)),
+            horizon,
+            gramians: Gramians::of(model, steps),
+            modes: modes(model),
+        }
+    }
+
+    pub fn controllable(&self) -> bool {
+        self.controllable_rank == self.order
+    }
+
+    pub fn observable(&self) -> bool {
+        self.observable_rank == self.order
+    }
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gramian = |gramian: &DMatrix<f64>| {
+            let eigenvalues = gramian.symmetric_eigenvalues();
+            format!(
+                "trace {:.3e}, smallest eigenvalue {:.3e}",
+                gramian.trace(),
+                eigenvalues.min()
+            )
+        };
+        writeln!(
+            f,
+            "order {}, inputs {}, outputs {}",
+            self.order,
+            self.inputs.join(", "),
+            self.outputs.join(", ")
+        )?;
+        writeln!(
+            f,
+            "controllability rank {}/{}, Gramian over {} s: {}",
+            self.controllable_rank,
+            self.order,
+            self.horizon,
+            gramian(&self.gramians.controllability)
+        )?;
+        writeln!(
+            f,
+            "observability rank {}/{}, Gramian over {} s: {}",
+            self.observable_rank,
+            self.order,
+            self.horizon,
+            gramian(&self.gramians.observability)
+        )?;
+        writeln!(
+            f,
+            "{:>24} {:>10} {:>8} {:>16} {:>16}",
+            "eigenvalue", "frequency", "damping", "controllability", "observability"
+        )?;
+        for mode in &self.modes {
+            let flag = |weak: bool| if weak { " (weak)" } else { "" };
+            writeln!(
+                f,
+                "{:>24} {:>7.3} Hz {:>8.3} {:>9.2e}{:<7} {:>9.2e}{:<7}",
+                format!("{:.4}{:+.4}i", mode.eigenvalue.re, mode.eigenvalue.im),
+                mode.frequency,
+                mode.damping,
+                mode.controllability,
+                flag(mode.weakly_controllable()),
+                mode.observability,
+                flag(mode.weakly_observable()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn stack_columns(
+    left: &DMatrix<Complex<f64>>,
+    right: &DMatrix<Complex<f64>>,
+) -> DMatrix<Complex<f64>> {
+    let mut stacked = DMatrix::zeros(left.nrows(), left.ncols() + right.ncols());
+    stacked.columns_mut(0, left.ncols()).copy_from(left);
+    stacked
+        .columns_mut(left.ncols(), right.ncols())
+        .copy_from(right);
+    stacked
+}
+
+fn stack_rows(
+    top: &DMatrix<Complex<f64>>,
+    bottom: &DMatrix<Complex<f64>>,
+) -> DMatrix<Complex<f64>> {
+    let mut stacked = DMatrix::zeros(top.nrows() + bottom.nrows(), top.ncols());
+    stacked.rows_mut(0, top.nrows()).copy_from(top);
+    stacked
+        .rows_mut(top.nrows(), bottom.nrows())
+        .copy_from(bottom);
+    stacked
+}
+
+fn norm(matrix: &DMatrix<Complex<f64>>) -> f64 {
+    matrix.singular_values().max()
+}
+
+/// Smallest of the `min(rows, columns)` singular values.
+fn smallest_singular_value(matrix: &DMatrix<Complex<f64>>) -> f64 {
+    matrix.singular_values().min()
+}
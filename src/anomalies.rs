@@ -0,0 +1,327 @@
+//! This module watches telemetry channels for abnormal behavior during long unattended runs,
+//! with control limits around an exponentially weighted moving average (EWMA) of each signal.
+//!
+//! The mean and the variance of each monitored channel are tracked with the same smoothing,
+//! and each new sample is scored by its distance to the mean, in standard deviations (z-score).
+//! After a warm-up, a score beyond the threshold of the channel starts an anomaly: it's logged
+//! as a warning, counted, and shown over the scene until the signal comes back within its
+//! limits. The scores are recorded as the `anomaly/<channel>` telemetry channels.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClockSet,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    telemetry::{Telemetry, MOTOR_TORQUE},
+};
+
+pub struct AnomaliesPlugin;
+
+impl Plugin for AnomaliesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Anomalies>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                detect
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<AnomalySettings>>),
+            )
+            .add_systems(
+                Update,
+                anomalies_panel
+                    .run_if(resource_exists::<Persistent<AnomalySettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A telemetry channel watched for anomalies.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Monitor {
+    pub channel: String,
+    /// Weight of each new sample in the averages, between 0 and 1.
+    #[serde(default = "smoothing")]
+    pub smoothing: f32,
+    /// Score starting an anomaly, in standard deviations.
+    #[serde(default = "threshold")]
+    pub threshold: f32,
+    /// Samples averaged before the samples are scored.
+    #[serde(default = "warmup")]
+    pub warmup: usize,
+    /// Smallest standard deviation, so a steady signal isn't abnormal for its noise.
+    #[serde(default = "min_deviation")]
+    pub min_deviation: f32,
+}
+
+fn smoothing() -> f32 {
+    0.02
+}
+
+fn threshold() -> f32 {
+    5.0
+}
+
+fn warmup() -> usize {
+    120
+}
+
+fn min_deviation() -> f32 {
+    1e-3
+}
+
+impl Monitor {
+    pub fn new(channel: &str) -> Self {
+        Self {
+            channel: channel.to_string(),
+            smoothing: smoothing(),
+            threshold: threshold(),
+            warmup: warmup(),
+            min_deviation: min_deviation(),
+        }
+    }
+
+    /// Telemetry channel of the scores.
+    pub fn score_channel(&self) -> String {
+        format!("anomaly/{}", self.channel)
+    }
+}
+
+/// Represents the configuration of the anomaly detection.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct AnomalySettings {
+    pub enabled: bool,
+    pub monitors: Vec<Monitor>,
+}
+
+impl Default for AnomalySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            monitors: vec![Monitor::new(MOTOR_TORQUE)],
+        }
+    }
+}
+
+/// State of the detection on a channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Detector {
+    /// Moving average of the signal.
+    pub mean: f32,
+    /// Moving variance of the signal.
+    pub variance: f32,
+    /// Samples seen.
+    pub samples: usize,
+    /// Score of the last sample.
+    pub score: f32,
+    /// Whether the signal is out of its limits.
+    pub active: bool,
+    /// Number of anomalies since the last reset.
+    pub count: usize,
+    /// Samples of the channel read so far.
+    read: usize,
+}
+
+impl Detector {
+    /// Scores a new sample, then updates the averages with it. Returns whether it starts an
+    /// anomaly.
+    pub fn update(&mut self, monitor: &Monitor, value: f32) -> bool {
+        let alpha = monitor.smoothing.clamp(f32::EPSILON, 1.0);
+        if self.samples == 0 {
+            self.mean = value;
+            self.variance = 0.0;
+        }
+        let deviation = value - self.mean;
+        let scored = self.samples >= monitor.warmup;
+        self.score = if scored {
+            deviation.abs() / self.variance.sqrt().max(monitor.min_deviation)
+        } else {
+            0.0
+        };
+        self.samples += 1;
+        self.mean += alpha * deviation;
+        self.variance = (1.0 - alpha) * (self.variance + alpha * deviation * deviation);
+
+        let abnormal = scored && self.score > monitor.threshold;
+        let started = abnormal && !self.active;
+        if started {
+            self.count += 1;
+        }
+        self.active = abnormal;
+        started
+    }
+}
+
+/// The detectors of the monitored channels, by channel.
+#[derive(Debug, Default, Resource)]
+pub struct Anomalies {
+    pub detectors: BTreeMap<String, Detector>,
+}
+
+impl Anomalies {
+    /// Channels out of their limits.
+    pub fn active(&self) -> impl Iterator<Item = &str> {
+        self.detectors
+            .iter()
+            .filter(|(_, detector)| detector.active)
+            .map(|(channel, _)| channel.as_str())
+    }
+
+    /// Restarts the averages and the counts from the next samples.
+    pub fn reset(&mut self) {
+        for detector in self.detectors.values_mut() {
+            *detector = Detector {
+                read: detector.read,
+                ..Default::default()
+            };
+        }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<AnomalySettings>("anomalies", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Scores the samples recorded since the last step.
+fn detect(
+    settings: Res<Persistent<AnomalySettings>>,
+    mut anomalies: ResMut<Anomalies>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let (true, Some(mut telemetry)) = (settings.enabled, telemetry) else {
+        return;
+    };
+    for monitor in &settings.monitors {
+        let detector = anomalies
+            .detectors
+            .entry(monitor.channel.clone())
+            .or_default();
+        let Some(samples) = telemetry.channels.get(&monitor.channel) else {
+            continue;
+        };
+        // The telemetry was cleared, e.g. by a new run.
+        if detector.read > samples.len() {
+            *detector = Detector::default();
+        }
+        let mut scores = Vec::new();
+        for &[time, value] in &samples[detector.read..] {
+            if detector.update(monitor, value) {
+                warn!(
+                    target: subsystem::CONTROL,
+                    "Anomaly of {} at {time:.2} s: {value:.3}, {:.1} standard deviations off",
+                    monitor.channel,
+                    detector.score
+                );
+            }
+            scores.push((time, detector.score));
+        }
+        detector.read = samples.len();
+        let channel = monitor.score_channel();
+        for (time, score) in scores {
+            telemetry.record(&channel, time, score);
+        }
+    }
+}
+
+/// Panel to tune the limits of the monitored channels, with a warning over the scene while a
+/// channel is out of its limits.
+fn anomalies_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<AnomalySettings>>,
+    mut anomalies: ResMut<Anomalies>,
+) {
+    let mut edited = settings.get().clone();
+    let ctx = contexts.ctx_mut();
+
+    let active: Vec<&str> = anomalies.active().collect();
+    if edited.enabled && !active.is_empty() {
+        egui::Area::new(egui::Id::new("anomaly_warning"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 28.0))
+            .show(ctx, |ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("Anomaly: {}", active.join(", ")),
+                );
+            });
+    }
+
+    egui::Window::new("Anomalies")
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut edited.enabled, "Watch the telemetry for anomalies");
+
+            ui.separator();
+            if edited.monitors.is_empty() {
+                ui.label("No channel: add them to anomalies.json.");
+            }
+            for monitor in &mut edited.monitors {
+                let detector = anomalies
+                    .detectors
+                    .get(&monitor.channel)
+                    .cloned()
+                    .unwrap_or_default();
+                ui.horizontal(|ui| {
+                    let state = format!(
+                        "{}: {:.1} σ, {} anomalies",
+                        monitor.channel, detector.score, detector.count
+                    );
+                    if detector.active {
+                        ui.colored_label(ui.visuals().warn_fg_color, state);
+                    } else {
+                        ui.label(state);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut monitor.threshold)
+                            .range(1.0..=100.0)
+                            .speed(0.1)
+                            .prefix("Threshold: ")
+                            .suffix(" σ"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut monitor.smoothing)
+                            .range(0.001..=1.0)
+                            .speed(0.001)
+                            .prefix("Smoothing: "),
+                    )
+                    .on_hover_text("Weight of each new sample in the averages");
+                });
+            }
+            if ui.button("Reset").clicked() {
+                anomalies.reset();
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("anomalies", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("anomalies", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,282 @@
+//! This module provides a plugin that plays audio cues following the simulation.
+//! The motor whines with a pitch rising with its speed, a click plays when a signal reaches a
+//! limit switch and an alarm beeps while a signal is beyond a safety threshold. The cues are
+//! synthesized tones, so no asset is needed; they are disabled until enabled in the mixer panel
+//! or in `audio.json`.
+use std::time::Duration;
+
+use bevy::{audio::Volume, prelude::*};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+/// Frequency of the whine of the motor at rest, in Hz.
+const WHINE_FREQUENCY: f32 = 220.0;
+/// Speed of the motor below which it is silent, in rad/s.
+const WHINE_THRESHOLD: f32 = 0.01;
+const CLICK_FREQUENCY: f32 = 2_000.0;
+const CLICK_DURATION: Duration = Duration::from_millis(15);
+const ALARM_FREQUENCY: f32 = 880.0;
+const ALARM_PERIOD: Duration = Duration::from_millis(500);
+
+pub struct AudioCuesPlugin;
+
+impl Plugin for AudioCuesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                (play_whine, play_clicks, play_alarm)
+                    .run_if(resource_exists::<Persistent<AudioSettings>>),
+                mixer_panel
+                    .run_if(resource_exists::<Persistent<AudioSettings>>)
+                    .run_if(has_ui),
+            ),
+        );
+    }
+}
+
+/// A range a signal is expected to stay within.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SignalLimit {
+    /// Name of the telemetry channel, or of the setpoint.
+    pub signal: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl SignalLimit {
+    pub fn contains(&self, value: f32) -> bool {
+        value > self.min && value < self.max
+    }
+}
+
+/// Represents the audio configuration: the levels of the mixer and the conditions of the cues.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub enabled: bool,
+    pub master_volume: f32,
+    pub whine_volume: f32,
+    pub click_volume: f32,
+    pub alarm_volume: f32,
+    /// Speed of the motor doubling the pitch of the whine, in rad/s.
+    pub whine_octave_speed: f32,
+    /// Limit switches: a click plays when a signal reaches either end.
+    pub limit_switches: Vec<SignalLimit>,
+    /// Safety limits: the alarm beeps while a signal is beyond either end.
+    pub safety_limits: Vec<SignalLimit>,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            master_volume: 0.5,
+            whine_volume: 0.3,
+            click_volume: 0.8,
+            alarm_volume: 0.6,
+            whine_octave_speed: 10.0,
+            limit_switches: vec![SignalLimit {
+                signal: PENDULUM_ANGLE.to_string(),
+                min: -std::f32::consts::FRAC_PI_4,
+                max: std::f32::consts::FRAC_PI_4,
+            }],
+            safety_limits: vec![SignalLimit {
+                signal: MOTOR_VELOCITY.to_string(),
+                min: -20.0,
+                max: 20.0,
+            }],
+        }
+    }
+}
+
+/// The synthesized tones of the cues.
+#[derive(Resource)]
+struct Tones {
+    whine: Handle<Pitch>,
+    click: Handle<Pitch>,
+    alarm: Handle<Pitch>,
+}
+
+/// Marks the looping whine of the motor.
+#[derive(Component)]
+struct Whine;
+
+/// Whether every limit switch was reached at the last update.
+#[derive(Default, Resource)]
+struct LimitSwitchStates(Vec<bool>);
+
+/// Repeats the alarm while a safety limit is exceeded.
+#[derive(Resource)]
+struct AlarmTimer(Timer);
+
+fn setup(mut commands: Commands, mut pitches: ResMut<Assets<Pitch>>) {
+    let (settings, error) = config_plugin::load_config::<AudioSettings>("audio", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+    commands.insert_resource(Tones {
+        // A whole number of periods, so the loop has no discontinuity.
+        whine: pitches.add(Pitch::new(WHINE_FREQUENCY, Duration::from_secs(1))),
+        click: pitches.add(Pitch::new(CLICK_FREQUENCY, CLICK_DURATION)),
+        alarm: pitches.add(Pitch::new(ALARM_FREQUENCY, ALARM_PERIOD / 2)),
+    });
+    commands.init_resource::<LimitSwitchStates>();
+    commands.insert_resource(AlarmTimer(Timer::new(ALARM_PERIOD, TimerMode::Repeating)));
+}
+
+/// Value of a signal: the last sample of its telemetry channel, or the setpoint.
+fn signal_value(
+    signal: &str,
+    setpoints: Option<&Setpoints>,
+    telemetry: Option<&Telemetry>,
+) -> Option<f32> {
+    telemetry
+        .and_then(|telemetry| telemetry.latest(signal))
+        .or_else(|| setpoints?.get(signal))
+}
+
+fn play_whine(
+    mut commands: Commands,
+    settings: Res<Persistent<AudioSettings>>,
+    tones: Res<Tones>,
+    setpoints: Option<Res<Setpoints>>,
+    whines: Query<(Entity, Option<&AudioSink>), With<Whine>>,
+) {
+    let speed = setpoints
+        .and_then(|setpoints| setpoints.get(MOTOR_VELOCITY))
+        .unwrap_or_default()
+        .abs();
+    let audible = settings.enabled && speed > WHINE_THRESHOLD;
+    let Ok((entity, sink)) = whines.get_single() else {
+        if audible {
+            commands.spawn((
+                AudioPlayer(tones.whine.clone()),
+                PlaybackSettings::LOOP.with_volume(Volume::new(0.0)),
+                Whine,
+            ));
+        }
+        return;
+    };
+    if !audible {
+        commands.entity(entity).despawn();
+        return;
+    }
+    // The sink appears once the tone started.
+    if let Some(sink) = sink {
+        sink.set_speed(1.0 + speed / settings.whine_octave_speed.max(f32::EPSILON));
+        sink.set_volume(settings.master_volume * settings.whine_volume);
+    }
+}
+
+fn play_clicks(
+    mut commands: Commands,
+    settings: Res<Persistent<AudioSettings>>,
+    tones: Res<Tones>,
+    mut states: ResMut<LimitSwitchStates>,
+    setpoints: Option<Res<Setpoints>>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let reached = settings
+        .limit_switches
+        .iter()
+        .map(|limit| {
+            signal_value(&limit.signal, setpoints.as_deref(), telemetry.as_deref())
+                .is_some_and(|value| !limit.contains(value))
+        })
+        .collect::<Vec<_>>();
+    let clicks = reached
+        .iter()
+        .enumerate()
+        .filter(|&(index, &reached)| reached && !states.0.get(index).copied().unwrap_or(false))
+        .count();
+    if settings.enabled {
+        for _ in 0..clicks {
+            commands.spawn((
+                AudioPlayer(tones.click.clone()),
+                PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new(settings.master_volume * settings.click_volume)),
+            ));
+        }
+    }
+    if states.0 != reached {
+        states.0 = reached;
+    }
+}
+
+fn play_alarm(
+    mut commands: Commands,
+    settings: Res<Persistent<AudioSettings>>,
+    tones: Res<Tones>,
+    mut timer: ResMut<AlarmTimer>,
+    time: Res<Time<Real>>,
+    setpoints: Option<Res<Setpoints>>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let fault = settings.safety_limits.iter().any(|limit| {
+        signal_value(&limit.signal, setpoints.as_deref(), telemetry.as_deref())
+            .is_some_and(|value| !limit.contains(value))
+    });
+    if !settings.enabled || !fault {
+        // The alarm starts with a beep as soon as the next fault occurs.
+        timer.0.set_elapsed(ALARM_PERIOD);
+        return;
+    }
+    timer.0.tick(time.delta());
+    if timer.0.finished() {
+        timer.0.reset();
+        commands.spawn((
+            AudioPlayer(tones.alarm.clone()),
+            PlaybackSettings::DESPAWN
+                .with_volume(Volume::new(settings.master_volume * settings.alarm_volume)),
+        ));
+    }
+}
+
+/// Panel to mix the audio cues at runtime.
+fn mixer_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<AudioSettings>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Audio")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            ui.add(egui::Slider::new(&mut edited.master_volume, 0.0..=1.0).text("Master"));
+            ui.add(egui::Slider::new(&mut edited.whine_volume, 0.0..=1.0).text("Motor whine"));
+            ui.add(egui::Slider::new(&mut edited.click_volume, 0.0..=1.0).text("Limit switches"));
+            ui.add(egui::Slider::new(&mut edited.alarm_volume, 0.0..=1.0).text("Safety alarm"));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("audio", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("audio", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
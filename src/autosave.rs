@@ -0,0 +1,538 @@
+//! Crash-safe autosave of the running session.
+//!
+//! While the application runs, the session lives in `<data dir>/sessions/current`. Every few
+//! seconds the telemetry recorded since the previous flush is written to a new chunk file and the
+//! state of the rigid bodies replaces `session.json`. Files are written to a temporary file and
+//! renamed over the destination, so a panic or GPU crash leaves either the previous or the new
+//! version on disk, never a torn one.
+//!
+//! The telemetry is streamed to `telemetry.dtlog`, in the format of [`stream_log`], or written
+//! as JSON chunks, as configured in `telemetry_log.json`. Long runs are kept small: each channel
+//! can be decimated on the fly, keeping the smallest and the largest sample of every run of
+//! samples so the envelope of the signals survives, and the JSON chunks can be compressed with
+//! zstd (`.json.zst`). Sessions are read back whichever way they were written.
+//!
+//! The definitions of the enabled controllers are kept in `controllers.json` as they're tuned,
+//! so each session records the controllers it ran with.
+//!
+//! A clean exit archives the session under its start time. If `current` still exists on the next
+//! start, the previous run was interrupted and the user is offered to restore it.
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, time::Real};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::SimClock,
+    config_plugin::{self, data_dir},
+    controller_definition::ControllerDefinition,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    lqr::LqrSettings,
+    migration,
+    pid_controller::PidSettings,
+    stream_log::{self, Schema, StreamReader, StreamWriter},
+    telemetry::{self, Telemetry},
+};
+
+/// Time between two flushes of the session.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+const SNAPSHOT_FILE: &str = "session.json";
+/// Definitions of the controllers the session ran with.
+pub const CONTROLLERS_FILE: &str = "controllers.json";
+const CHUNK_PREFIX: &str = "telemetry-";
+/// Extension of the compressed chunks, after `.json`.
+const COMPRESSED_EXTENSION: &str = ".zst";
+/// Name of the streamed telemetry, without its extension.
+const STREAM_FILE: &str = "telemetry";
+
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.insert_resource(AutosaveTimer(Timer::new(
+            AUTOSAVE_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(PreStartup, open_session)
+        .add_systems(
+            Update,
+            restore_prompt.run_if(resource_exists::<InterruptedSession>),
+        )
+        .add_systems(
+            Last,
+            (
+                record_controllers,
+                autosave,
+                close_session.run_if(on_event::<AppExit>),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Writes `contents` to `path` atomically: the data is written and synced to a temporary file in
+/// the same directory, which is then renamed over `path`.
+pub fn atomic_write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    {
+        let mut file = fs::File::create(&temporary)?;
+        io::Write::write_all(&mut file, contents.as_ref())?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, path)
+}
+
+/// Directory holding the current and the archived sessions.
+pub fn sessions_dir() -> PathBuf {
+    data_dir().join("sessions")
+}
+
+/// Format of the telemetry of the sessions.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// A single log streamed as the session runs, see [`stream_log`].
+    #[default]
+    Stream,
+    /// A JSON chunk per flush.
+    Json,
+}
+
+/// Represents how the telemetry of the sessions is written.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TelemetryLog {
+    pub format: LogFormat,
+    /// Samples of a channel reduced to their smallest and largest ones, 2 or less to keep
+    /// every sample.
+    pub decimation: usize,
+    /// Whether the JSON chunks are compressed with zstd.
+    pub compression: bool,
+}
+
+impl Default for TelemetryLog {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Stream,
+            decimation: 1,
+            compression: true,
+        }
+    }
+}
+
+/// State of the simulation at the last flush.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SessionSnapshot {
+    /// Physics step of the snapshot.
+    pub tick: u64,
+    /// Simulated time of the snapshot.
+    pub elapsed: Duration,
+    /// The dynamic bodies, in spawn order.
+    pub bodies: Vec<BodyState>,
+}
+
+/// The session being recorded.
+#[derive(Resource)]
+struct Session {
+    dir: PathBuf,
+    log: TelemetryLog,
+    started: u64,
+    next_chunk: usize,
+    /// The streamed telemetry, in the stream format.
+    stream: Option<StreamWriter<fs::File>>,
+    /// Number of samples of each channel already written.
+    flushed: BTreeMap<String, usize>,
+}
+
+impl Session {
+    fn create(dir: PathBuf, log: TelemetryLog) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut session = Self {
+            dir,
+            log,
+            started: unix_time(),
+            next_chunk: 0,
+            stream: None,
+            flushed: BTreeMap::new(),
+        };
+        session.open_stream()?;
+        Ok(session)
+    }
+
+    /// Starts the streamed telemetry, in the stream format.
+    fn open_stream(&mut self) -> io::Result<()> {
+        if self.log.format == LogFormat::Stream {
+            let path = self
+                .dir
+                .join(STREAM_FILE)
+                .with_extension(stream_log::EXTENSION);
+            self.stream = Some(StreamWriter::new(
+                fs::File::create(path)?,
+                Schema::default(),
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Writes the telemetry recorded since the previous flush and the snapshot. Until the
+    /// `last` flush, the samples of an unfinished run of decimated samples wait for the next
+    /// one.
+    fn flush(
+        &mut self,
+        telemetry: &Telemetry,
+        snapshot: &SessionSnapshot,
+        last: bool,
+    ) -> io::Result<()> {
+        let factor = self.log.decimation.max(1);
+        let mut chunk = Telemetry::default();
+        for (channel, samples) in &telemetry.channels {
+            let flushed = self.flushed.entry(channel.clone()).or_default();
+            let pending = samples.len().saturating_sub(*flushed);
+            let count = if last {
+                pending
+            } else {
+                pending / factor * factor
+            };
+            if count > 0 {
+                let samples = &samples[*flushed..*flushed + count];
+                chunk
+                    .channels
+                    .insert(channel.clone(), telemetry::decimate(samples, factor));
+                *flushed += count;
+            }
+        }
+        if let (Some(stream), false) = (self.stream.as_mut(), chunk.is_empty()) {
+            // The physics rate, halved by the decimation.
+            let rate = (snapshot.elapsed.as_secs_f32() > 0.0).then(|| {
+                let rate = snapshot.tick as f32 / snapshot.elapsed.as_secs_f32();
+                if factor > 2 {
+                    rate * 2.0 / factor as f32
+                } else {
+                    rate
+                }
+            });
+            stream.write_telemetry(&chunk, rate)?;
+            stream.flush()?;
+            stream.get_ref().sync_data()?;
+        } else if !chunk.is_empty() {
+            let json = serde_json::to_vec(&chunk)?;
+            let name = format!("{CHUNK_PREFIX}{:05}.json", self.next_chunk);
+            if self.log.compression {
+                let compressed = ruzstd::encoding::compress_to_vec(
+                    json.as_slice(),
+                    ruzstd::encoding::CompressionLevel::Fastest,
+                );
+                let path = self.dir.join(name + COMPRESSED_EXTENSION);
+                atomic_write(&path, compressed)?;
+            } else {
+                atomic_write(&self.dir.join(name), json)?;
+            }
+            self.next_chunk += 1;
+        }
+        let snapshot = migration::SESSION.to_value(snapshot)?;
+        atomic_write(
+            &self.dir.join(SNAPSHOT_FILE),
+            serde_json::to_vec(&snapshot)?,
+        )
+    }
+
+    /// Forgets what was written so far, so the next flush writes the whole telemetry again.
+    fn rewind(&mut self) -> io::Result<()> {
+        self.stream = None;
+        for path in chunk_paths(&self.dir)? {
+            fs::remove_file(path)?;
+        }
+        self.next_chunk = 0;
+        self.flushed.clear();
+        self.open_stream()
+    }
+}
+
+/// A session that wasn't closed cleanly, waiting for the user to restore or discard it.
+#[derive(Resource)]
+struct InterruptedSession {
+    dir: PathBuf,
+    snapshot: SessionSnapshot,
+    telemetry: Telemetry,
+}
+
+impl InterruptedSession {
+    fn load(dir: PathBuf) -> io::Result<Self> {
+        let snapshot = migration::SESSION
+            .read(&dir.join(SNAPSHOT_FILE))
+            .unwrap_or_default();
+        let telemetry = read_telemetry(&dir)?;
+        Ok(Self {
+            dir,
+            snapshot,
+            telemetry,
+        })
+    }
+}
+
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Reads a telemetry file: a streamed log, or a JSON chunk, compressed or not.
+pub fn read_telemetry_file(path: &Path) -> io::Result<Telemetry> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == stream_log::EXTENSION)
+    {
+        return StreamReader::new(io::BufReader::new(fs::File::open(path)?))?.read_telemetry();
+    }
+    let bytes = fs::read(path)?;
+    if !path.to_string_lossy().ends_with(COMPRESSED_EXTENSION) {
+        return Ok(serde_json::from_slice(&bytes)?);
+    }
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(bytes.as_slice())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+    let mut json = Vec::new();
+    io::Read::read_to_end(&mut decoder, &mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Reads the definitions of the controllers a session ran with, none if it didn't record them.
+pub fn read_controllers(dir: &Path) -> io::Result<Vec<ControllerDefinition>> {
+    match read_json(&dir.join(CONTROLLERS_FILE)) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+/// Reads the telemetry of a session directory, current or archived.
+pub fn read_telemetry(dir: &Path) -> io::Result<Telemetry> {
+    let mut telemetry = Telemetry::default();
+    for path in chunk_paths(dir)? {
+        // A chunk is either complete or absent, but keep what can be read anyway.
+        match read_telemetry_file(&path) {
+            Ok(chunk) => telemetry.extend(chunk),
+            Err(error) => {
+                warn!(target: subsystem::IO, "Skipping {}: {error}", path.display())
+            }
+        }
+    }
+    Ok(telemetry)
+}
+
+/// The telemetry files of a session directory: the JSON chunks, in write order, or the
+/// streamed log.
+fn chunk_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_chunk = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                (name.starts_with(CHUNK_PREFIX)
+                    && (name.ends_with(".json") || name.ends_with(".json.zst")))
+                    || name == format!("{STREAM_FILE}.{}", stream_log::EXTENSION)
+            });
+        if is_chunk {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Moves `dir` next to itself under a name that isn't taken yet.
+fn archive(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let parent = dir.parent().unwrap_or(Path::new("."));
+    let mut destination = parent.join(name);
+    let mut suffix = 1;
+    while destination.exists() {
+        destination = parent.join(format!("{name}-{suffix}"));
+        suffix += 1;
+    }
+    fs::rename(dir, &destination)?;
+    Ok(destination)
+}
+
+/// Picks up an interrupted session, if any, and starts recording a new one.
+fn open_session(mut commands: Commands) {
+    let current = sessions_dir().join("current");
+    if current.exists() {
+        let interrupted = archive(&current, &format!("interrupted-{}", unix_time()))
+            .and_then(InterruptedSession::load);
+        match interrupted {
+            Ok(interrupted) => {
+                warn!(
+                    target: subsystem::IO,
+                    "The previous session was interrupted, it's kept in {}",
+                    interrupted.dir.display()
+                );
+                commands.insert_resource(interrupted);
+            }
+            Err(error) => {
+                error!(target: subsystem::IO, "Failed to load the interrupted session: {error}")
+            }
+        }
+    }
+    let (log, error) = config_plugin::load_config::<TelemetryLog>("telemetry_log", false);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+    match Session::create(current.clone(), log.get().clone()) {
+        Ok(session) => commands.insert_resource(session),
+        Err(error) => {
+            warn!(target: subsystem::IO, "Autosave disabled");
+            commands.send_event(ErrorEvent::from(Error::io(current, error)));
+        }
+    }
+}
+
+fn snapshot(clock: &SimClock, bodies: &Bodies) -> SessionSnapshot {
+    SessionSnapshot {
+        tick: clock.tick(),
+        elapsed: clock.elapsed(),
+        bodies: body_state::capture(bodies),
+    }
+}
+
+fn autosave(
+    time: Res<Time<Real>>,
+    clock: Res<SimClock>,
+    mut timer: ResMut<AutosaveTimer>,
+    session: Option<ResMut<Session>>,
+    telemetry: Res<Telemetry>,
+    bodies: Bodies,
+    exit: EventReader<AppExit>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+    if !timer.0.tick(time.delta()).just_finished() && exit.is_empty() {
+        return;
+    }
+    let _span = info_span!(target: subsystem::IO, "autosave").entered();
+    let last = !exit.is_empty();
+    if let Err(error) = session.flush(&telemetry, &snapshot(&clock, &bodies), last) {
+        error!(target: subsystem::IO, "Failed to autosave the session: {error}");
+    }
+}
+
+/// Writes the definitions of the enabled controllers to the session whenever they change.
+fn record_controllers(
+    session: Option<Res<Session>>,
+    pid: Option<Res<Persistent<PidSettings>>>,
+    lqr: Option<Res<Persistent<LqrSettings>>>,
+    mut recorded: Local<Option<Vec<ControllerDefinition>>>,
+) {
+    let Some(session) = session else {
+        return;
+    };
+    let pid = pid
+        .as_deref()
+        .map(Persistent::get)
+        .filter(|pid| pid.enabled);
+    let lqr = lqr
+        .as_deref()
+        .map(Persistent::get)
+        .filter(|lqr| lqr.enabled);
+    let definitions: Vec<_> = pid
+        .map(ControllerDefinition::from)
+        .into_iter()
+        .chain(lqr.map(ControllerDefinition::from))
+        .collect();
+    if recorded.as_ref() == Some(&definitions) {
+        return;
+    }
+    let path = session.dir.join(CONTROLLERS_FILE);
+    let written = serde_json::to_vec_pretty(&definitions)
+        .map_err(io::Error::from)
+        .and_then(|json| atomic_write(&path, json));
+    if let Err(error) = written {
+        error!(target: subsystem::IO, "Failed to record the controllers: {error}");
+    }
+    *recorded = Some(definitions);
+}
+
+/// Archives the session once it has been flushed for the last time.
+fn close_session(mut commands: Commands, session: Option<Res<Session>>) {
+    let Some(session) = session else {
+        return;
+    };
+    match archive(&session.dir, &session.started.to_string()) {
+        Ok(destination) => {
+            info!(target: subsystem::IO, "Session saved to {}", destination.display())
+        }
+        Err(error) => error!(target: subsystem::IO, "Failed to archive the session: {error}"),
+    }
+    commands.remove_resource::<Session>();
+}
+
+/// Asks whether the interrupted session should be restored.
+fn restore_prompt(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    interrupted: Res<InterruptedSession>,
+    session: Option<ResMut<Session>>,
+    mut telemetry: ResMut<Telemetry>,
+    mut clock: ResMut<SimClock>,
+    mut bodies: BodiesMut,
+) {
+    let mut answered = false;
+    egui::Window::new("Restore session")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "The previous session was interrupted after {:.1} s, with {} telemetry samples.",
+                interrupted.snapshot.elapsed.as_secs_f32(),
+                interrupted.telemetry.len()
+            ));
+            ui.weak(format!("It's kept in {}", interrupted.dir.display()));
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    info!(target: subsystem::IO, "Restoring {}", interrupted.dir.display());
+                    if let Err(count) = body_state::apply(&interrupted.snapshot.bodies, &mut bodies) {
+                        warn!(
+                            target: subsystem::PHYSICS,
+                            "The saved session has {} bodies but the scene has {count}, only the telemetry is restored",
+                            interrupted.snapshot.bodies.len(),
+                        );
+                    }
+                    clock.restore(interrupted.snapshot.tick, interrupted.snapshot.elapsed);
+                    *telemetry = interrupted.telemetry.clone();
+                    if let Some(mut session) = session {
+                        if let Err(error) = session.rewind() {
+                            error!(target: subsystem::IO, "Failed to rewind the session: {error}");
+                        }
+                    }
+                    answered = true;
+                }
+                if ui.button("Discard").clicked() {
+                    answered = true;
+                }
+            });
+        });
+    if answered {
+        commands.remove_resource::<InterruptedSession>();
+    }
+}
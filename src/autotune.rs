@@ -0,0 +1,425 @@
+//! Automatic tuning of the position loop by a relay-feedback experiment, rather than by trial
+//! and error on the gains.
+//!
+//! On request, a relay takes over the joint of the configured link from the controllers: it
+//! commands the motor at plus or minus its amplitude, switching as the angle crosses the angle
+//! it started at, give or take the hysteresis, so the loop settles into a limit cycle at its
+//! ultimate period. After the configured number of cycles, the first left out, the ultimate
+//! gain and period give the gains of the [position loop](crate::pid_controller) by the
+//! Ziegler–Nichols or the Tyreus–Luyben rule, which the *Autotune* panel offers to apply. The
+//! error and the output of the relay are recorded as the `autotune/<link>/error` and
+//! `autotune/<link>/relay` telemetry channels, added to the plots while the experiment runs.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    actuators,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{PidGains, RelayFeedback, TuningRule, UltimateGain},
+    error::{Error, ErrorEvent},
+    estimation::{self, JointEstimate},
+    latency::{self, LoopLatency},
+    logging::subsystem,
+    pid_controller::PidSettings,
+    plants::{self, Link, MOTOR_FACTOR},
+    plots::PlotSettings,
+    sensors::{ControlSet, Encoder},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the experiment.
+pub const AUTOTUNE_PREFIX: &str = "autotune/";
+
+pub struct AutotunePlugin;
+
+impl Plugin for AutotunePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Autotune>()
+            .add_event::<StartAutotune>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                start_autotune.run_if(resource_exists::<Persistent<AutotuneSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_relay
+                    .run_if(resource_exists::<Persistent<AutotuneSettings>>)
+                    .after(SimClockSet::Advance)
+                    .after(ControlSet)
+                    .before(latency::delay_commands)
+                    .before(actuators::drive)
+                    .before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(
+                Update,
+                autotune_panel
+                    .run_if(resource_exists::<Persistent<AutotuneSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the experiment, `autotune.json`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct AutotuneSettings {
+    /// Name of the link whose joint is tuned, or its path to pick a plant, e.g. `feeder/motor`.
+    pub link: String,
+    /// Velocity commanded by the relay, in rad/s.
+    pub amplitude: f32,
+    /// Error the relay ignores around the starting angle, in rad.
+    pub hysteresis: f32,
+    /// Cycles measured, the first left out.
+    pub cycles: usize,
+    /// Simulated time after which the experiment gives up, in s.
+    pub timeout: f32,
+    pub rule: TuningRule,
+}
+
+impl Default for AutotuneSettings {
+    fn default() -> Self {
+        Self {
+            link: "motor".to_string(),
+            amplitude: 1.0,
+            hysteresis: 0.005,
+            cycles: 5,
+            timeout: 30.0,
+            rule: TuningRule::default(),
+        }
+    }
+}
+
+/// Request to run the experiment on the configured joint.
+#[derive(Clone, Debug, Default, Event)]
+pub struct StartAutotune;
+
+/// An experiment running.
+#[derive(Clone, Debug)]
+pub struct RelayRun {
+    pub entity: Entity,
+    /// Path of the link tuned.
+    pub path: String,
+    relay: RelayFeedback,
+    /// Simulated time the experiment started at, in s.
+    start: f32,
+    /// Angle the relay switches around, once measured.
+    center: Option<f32>,
+    /// Telemetry channels of the error and of the output.
+    channels: [String; 2],
+    /// Whether it's to be stopped at the next step.
+    pub stopped: bool,
+}
+
+/// Outcome of an experiment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    /// Path of the link tuned.
+    pub path: String,
+    pub ultimate: UltimateGain,
+    pub rule: TuningRule,
+    pub gains: PidGains,
+}
+
+/// State of the auto-tuning.
+#[derive(Debug, Default, Resource)]
+pub struct Autotune {
+    pub running: Option<RelayRun>,
+    /// Outcome of the last experiment, or why it failed.
+    pub last: Option<std::result::Result<Tuning, String>>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<AutotuneSettings>("autotune", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Starts the experiment on the joint of the configured link, as requested.
+fn start_autotune(
+    mut requests: EventReader<StartAutotune>,
+    settings: Res<Persistent<AutotuneSettings>>,
+    mut autotune: ResMut<Autotune>,
+    clock: Res<SimClock>,
+    joints: Query<(Entity, &Link), With<ImpulseJoint>>,
+    plots: Option<ResMut<Persistent<PlotSettings>>>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let mut candidates: Vec<_> = joints
+        .iter()
+        .filter(|(_, link)| link.name == settings.link || link.path() == settings.link)
+        .collect();
+    candidates.sort_by_key(|(_, link)| link.path());
+    let Some((entity, link)) = candidates.first() else {
+        warn!(
+            target: subsystem::CONTROL,
+            "No joint {} to tune", settings.link
+        );
+        autotune.last = Some(Err(format!("no joint {}", settings.link)));
+        return;
+    };
+    let channel = plants::namespaced(&link.plant, &format!("{AUTOTUNE_PREFIX}{}", link.name));
+    let channels = [format!("{channel}/error"), format!("{channel}/relay")];
+    if let Some(mut plots) = plots {
+        let mut series = plots.series.clone();
+        for channel in &channels {
+            if !series.contains(channel) {
+                series.push(channel.clone());
+            }
+        }
+        plots.get_mut().series = series;
+    }
+    info!(
+        target: subsystem::CONTROL,
+        "Relay experiment on {}", link.path()
+    );
+    autotune.running = Some(RelayRun {
+        entity: *entity,
+        path: link.path(),
+        relay: RelayFeedback::new(settings.amplitude, settings.hysteresis),
+        start: clock.elapsed_secs(),
+        center: None,
+        channels,
+        stopped: false,
+    });
+}
+
+/// Drives the joint tuned by the relay in place of its controllers, and computes the gains
+/// once enough cycles are measured.
+#[allow(clippy::too_many_arguments)]
+fn run_relay(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<AutotuneSettings>>,
+    mut autotune: ResMut<Autotune>,
+    mut joints: Query<&mut ImpulseJoint>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    let Some(run) = autotune.running.as_mut() else {
+        return;
+    };
+    if dt <= 0.0 && !run.stopped {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    let elapsed = time - run.start;
+    let outcome = if run.stopped {
+        Some(Err("stopped".to_string()))
+    } else if elapsed < 0.0 {
+        Some(Err("the simulation was rewound".to_string()))
+    } else if elapsed > settings.timeout {
+        Some(Err(format!(
+            "no steady oscillation within {:.0} s",
+            settings.timeout
+        )))
+    } else if let Ok(mut joint) = joints.get_mut(run.entity) {
+        let angle = estimation::joint_angle(context, &encoders, &latencies, &estimates, run.entity);
+        if let Some(angle) = angle {
+            let center = *run.center.get_or_insert(angle);
+            // Within half a turn, as the joints measure it.
+            let error = (center - angle + PI).rem_euclid(TAU) - PI;
+            let output = run.relay.update(time, error);
+            joint
+                .data
+                .as_mut()
+                .set_motor_velocity(JointAxis::AngX, output, MOTOR_FACTOR);
+            if let Some(telemetry) = telemetry.as_mut() {
+                telemetry.record(&run.channels[0], time, error);
+                telemetry.record(&run.channels[1], time, output);
+            }
+        }
+        (run.relay.cycles() >= settings.cycles.max(2)).then(|| {
+            run.relay
+                .ultimate()
+                .map(|ultimate| Tuning {
+                    path: run.path.clone(),
+                    ultimate,
+                    rule: settings.rule,
+                    gains: settings.rule.gains(ultimate),
+                })
+                .ok_or_else(|| "the oscillation couldn't be measured".to_string())
+        })
+    } else {
+        Some(Err(format!("the joint of {} is gone", run.path)))
+    };
+    let Some(outcome) = outcome else {
+        return;
+    };
+    match &outcome {
+        Ok(tuning) => info!(
+            target: subsystem::CONTROL,
+            "Ultimate gain of {}: {:.3}, period {:.3} s",
+            tuning.path,
+            tuning.ultimate.gain,
+            tuning.ultimate.period
+        ),
+        Err(message) => warn!(
+            target: subsystem::CONTROL,
+            "Relay experiment on {} stopped: {message}", run.path
+        ),
+    }
+    if let Ok(mut joint) = joints.get_mut(run.entity) {
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, 0.0, MOTOR_FACTOR);
+    }
+    autotune.running = None;
+    autotune.last = Some(outcome);
+}
+
+/// Panel to run the experiment and apply the gains it gives to the position loop.
+fn autotune_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<AutotuneSettings>>,
+    mut autotune: ResMut<Autotune>,
+    pid: Option<ResMut<Persistent<PidSettings>>>,
+    clock: Res<SimClock>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Autotune")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Link");
+                ui.text_edit_singleline(&mut edited.link);
+            });
+            egui::Grid::new("autotune_relay").show(ui, |ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.amplitude)
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .prefix("Amplitude: ")
+                        .suffix(" rad/s"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.hysteresis)
+                        .range(0.0..=1.0)
+                        .speed(0.0001)
+                        .prefix("Hysteresis: ")
+                        .suffix(" rad"),
+                )
+                .on_hover_text("Widen it if the noise of the sensor makes the relay chatter");
+                ui.end_row();
+                ui.add(
+                    egui::DragValue::new(&mut edited.cycles)
+                        .range(2..=50)
+                        .prefix("Cycles: "),
+                )
+                .on_hover_text("The first one is left out");
+                ui.add(
+                    egui::DragValue::new(&mut edited.timeout)
+                        .range(1.0..=600.0)
+                        .prefix("Timeout: ")
+                        .suffix(" s"),
+                );
+                ui.end_row();
+            });
+            egui::ComboBox::from_label("Rule")
+                .selected_text(edited.rule.name())
+                .show_ui(ui, |ui| {
+                    for rule in TuningRule::ALL {
+                        ui.selectable_value(&mut edited.rule, rule, rule.name());
+                    }
+                });
+
+            ui.separator();
+            match &autotune.running {
+                Some(run) if !run.stopped => {
+                    ui.label(format!(
+                        "Relay on {}: {} of {} cycles, {:.1} s",
+                        run.path,
+                        run.relay.cycles(),
+                        edited.cycles.max(2),
+                        clock.elapsed_secs() - run.start
+                    ));
+                    if ui.button("Stop").clicked() {
+                        if let Some(run) = autotune.running.as_mut() {
+                            run.stopped = true;
+                        }
+                    }
+                }
+                Some(_) => {
+                    ui.label("Stopping the relay");
+                }
+                None => {
+                    if ui
+                        .button("Run the relay experiment")
+                        .on_hover_text("The relay takes over the joint from its controllers")
+                        .clicked()
+                    {
+                        commands.send_event(StartAutotune);
+                    }
+                }
+            }
+            match &autotune.last {
+                Some(Ok(tuning)) => {
+                    ui.label(format!(
+                        "{}: ultimate gain {:.3}, period {:.3} s",
+                        tuning.path, tuning.ultimate.gain, tuning.ultimate.period
+                    ));
+                    // The gains of the rule chosen since.
+                    let gains = edited.rule.gains(tuning.ultimate);
+                    ui.monospace(format!(
+                        "kp {:.4}  ki {:.4}  kd {:.4}",
+                        gains.kp, gains.ki, gains.kd
+                    ));
+                    if let Some(mut pid) = pid {
+                        if ui
+                            .button("Apply to the position loop")
+                            .on_hover_text("Save them from the PID panel to keep them")
+                            .clicked()
+                        {
+                            pid.get_mut().gains = gains;
+                            info!(
+                                target: subsystem::CONTROL,
+                                "Applied the {} gains to the position loop",
+                                edited.rule.name()
+                            );
+                        }
+                    }
+                }
+                Some(Err(message)) => {
+                    ui.colored_label(ui.visuals().warn_fg_color, message);
+                }
+                None => {}
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("autotune", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("autotune", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
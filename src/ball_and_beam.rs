@@ -0,0 +1,233 @@
+//! The ball and beam: a ball rolling along a beam, balanced by tilting the beam.
+//!
+//! The beam turns about its middle on a revolute joint, driven like the motor of the rotary
+//! pendulum by a stiff velocity servo following the `beam/velocity` setpoint, in rad/s, within
+//! its limits. Stops at the ends of the beam keep the ball on it. The angle of the beam, in rad,
+//! and the position of the ball along it from its middle, in m, are recorded as the `beam/angle`
+//! and `ball/position` telemetry channels.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody, MOTOR_FACTOR},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Velocity of the beam, in rad/s.
+pub const BEAM_VELOCITY: &str = "beam/velocity";
+/// Angle of the beam from level, in rad.
+pub const BEAM_ANGLE: &str = "beam/angle";
+/// Position of the ball along the beam from its middle, in m.
+pub const BALL_POSITION: &str = "ball/position";
+/// Largest tilt of the beam, in rad.
+pub const BEAM_LIMIT: f32 = 0.5;
+/// Half of the length of the beam, in m.
+pub const BEAM_HALF_LENGTH: f32 = 1.0;
+
+/// The ball and beam, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "ball_and_beam",
+        add: |app| {
+            app.add_plugins(BallAndBeamPlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<BallAndBeamPlugin>() {
+                app.add_plugins(BallAndBeamPlugin);
+            }
+            app.insert_resource(BallAndBeamInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(BallAndBeamInstances::default());
+            plants::run_spawn(world, spawn_balls_and_beams);
+        },
+    }
+}
+
+pub struct BallAndBeamPlugin;
+
+impl Plugin for BallAndBeamPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BallAndBeamInstances>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, spawn_balls_and_beams)
+            .add_systems(
+                Update,
+                (
+                    apply_beam_setpoint.run_if(resource_changed::<Setpoints>),
+                    record_beam_state,
+                ),
+            );
+    }
+}
+
+/// The balls and beams spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct BallAndBeamInstances(pub Vec<Instance>);
+
+impl Default for BallAndBeamInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The servo of a beam, on the entity of its joint.
+#[derive(Component)]
+struct BeamServo {
+    /// Signals of the instance.
+    velocity: String,
+    angle: String,
+    position: String,
+    ball: Entity,
+}
+
+fn spawn_balls_and_beams(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<BallAndBeamInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const PIVOT_HEIGHT: f32 = 1.5;
+    const BEAM_THICKNESS: f32 = 0.04;
+    const BEAM_DEPTH: f32 = 0.1;
+    const STOP_HEIGHT: f32 = 0.12;
+    const BALL_RADIUS: f32 = 0.05;
+    const BALL_START: f32 = 0.2;
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let pivot = instance.offset + Vec3::Y * PIVOT_HEIGHT;
+        let support = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Mesh3d(meshes.add(Cuboid::new(0.1, PIVOT_HEIGHT, 0.1))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(pivot - Vec3::Y * PIVOT_HEIGHT / 2.0),
+            ))
+            .id();
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        let stop = |side: f32| {
+            (
+                Vec3::new(side * BEAM_HALF_LENGTH, STOP_HEIGHT / 2.0, 0.0),
+                Quat::IDENTITY,
+                Collider::cuboid(0.01, STOP_HEIGHT / 2.0, BEAM_DEPTH / 2.0),
+            )
+        };
+        let beam = commands
+            .spawn((
+                RigidBody::Dynamic,
+                link("beam"),
+                Collider::compound(vec![
+                    (
+                        Vec3::ZERO,
+                        Quat::IDENTITY,
+                        Collider::cuboid(BEAM_HALF_LENGTH, BEAM_THICKNESS / 2.0, BEAM_DEPTH / 2.0),
+                    ),
+                    stop(-1.0),
+                    stop(1.0),
+                ]),
+                ColliderMassProperties::Mass(1.0),
+                Mesh3d(meshes.add(Cuboid::new(
+                    2.0 * BEAM_HALF_LENGTH,
+                    BEAM_THICKNESS,
+                    BEAM_DEPTH,
+                ))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(pivot),
+            ))
+            .id();
+        let hinge = RevoluteJointBuilder::new(Vec3::Z)
+            .local_anchor1(Vec3::Y * PIVOT_HEIGHT / 2.0)
+            .limits([-BEAM_LIMIT, BEAM_LIMIT])
+            .motor_velocity(0.0, MOTOR_FACTOR);
+        commands
+            .entity(beam)
+            .insert(ImpulseJoint::new(support, hinge));
+
+        let ball = commands
+            .spawn((
+                RigidBody::Dynamic,
+                link("ball"),
+                Collider::ball(BALL_RADIUS),
+                ColliderMassProperties::Mass(0.1),
+                // Rolls along the beam only.
+                LockedAxes::TRANSLATION_LOCKED_Z
+                    | LockedAxes::ROTATION_LOCKED_X
+                    | LockedAxes::ROTATION_LOCKED_Y,
+                Friction::coefficient(1.0),
+                Mesh3d(meshes.add(Sphere::new(BALL_RADIUS))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(
+                    pivot + Vec3::new(BALL_START, BEAM_THICKNESS / 2.0 + BALL_RADIUS, 0.0),
+                ),
+            ))
+            .id();
+
+        commands.entity(beam).insert(BeamServo {
+            velocity: instance.signal(BEAM_VELOCITY),
+            angle: instance.signal(BEAM_ANGLE),
+            position: instance.signal(BALL_POSITION),
+            ball,
+        });
+    }
+}
+
+/// Applies the velocity setpoints to the servos of the beams.
+fn apply_beam_setpoint(
+    setpoints: Res<Setpoints>,
+    mut servos: Query<(&BeamServo, &mut ImpulseJoint)>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "apply_beam_setpoint").entered();
+    for (servo, mut joint) in &mut servos {
+        let Some(velocity) = setpoints.get(&servo.velocity) else {
+            continue;
+        };
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Records the angles of the beams and the positions of their balls.
+fn record_beam_state(
+    clock: Res<SimClock>,
+    servos: Query<(Entity, &BeamServo, &Transform)>,
+    transforms: Query<&Transform>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_beam_state").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    for (entity, servo, beam) in &servos {
+        if let Some(angle) = context.impulse_revolute_joint_angle(entity) {
+            telemetry.record(&servo.angle, time, angle);
+        }
+        if let Ok(ball) = transforms.get(servo.ball) {
+            let along = beam.rotation * Vec3::X;
+            let position = (ball.translation - beam.translation).dot(along);
+            telemetry.record(&servo.position, time, position);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Headless batch runs: the physics and the controllers of a plant run for a given simulated
+//! duration, without a window nor a GPU and as fast as they can, and the telemetry is written
+//! to a CSV or Parquet file, e.g. for parameter variations on CI or on a server.
+//!
+//! The controllers read their configuration files as in the application, so variations are run
+//! by changing them between runs. The output has the layout of the recordings, with a row per
+//! step, a column per channel and the setpoints as `setpoint/<name>` columns. The violations of
+//! the monitored requirements are reported with the run, and so is its
+//! [resource usage](crate::resource_usage).
+//!
+//! With a capture directory, the scene is rendered offscreen and [captured](crate::capture) at
+//! the pace of the simulated time too, which takes a GPU or a software adapter. With a
+//! [scenario](crate::scenario), the run follows it.
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    actuators::ActuatorsPlugin,
+    capture::{self, CapturePlugin, CaptureSettings},
+    config_plugin,
+    disturbances::DisturbancesPlugin,
+    error::{Error, Result},
+    estimation::EstimationPlugin,
+    fixed_step::FixedStepPlugin,
+    friction::FrictionPlugin,
+    governor::GovernorPlugin,
+    headless::{headless_app, offscreen_app},
+    lqr::LqrPlugin,
+    monitors::{Monitors, MonitorsPlugin, Violation},
+    pid_controller::PidControllerPlugin,
+    plants::Plant,
+    recording::{Recorder, RecordingFormat, RecordingSettings},
+    resource_usage::{ResourceUsage, UsageMeter},
+    scenario::{ScenarioDefinition, ScenarioPlugin},
+    sensorless::SensorlessPlugin,
+    sensors::SensorsPlugin,
+    setpoints::Setpoints,
+    state_machines::StateMachinesPlugin,
+    swing_up::SwingUpPlugin,
+    telemetry::{Telemetry, TelemetryPlugin},
+};
+
+/// A batch run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchRun {
+    /// Simulated duration, in seconds.
+    pub duration: f32,
+    /// Time step of the physics, in seconds.
+    pub dt: f32,
+    /// Seed of the random draws.
+    pub seed: u64,
+    /// File the telemetry is written to, as Parquet if its extension is `parquet`, as CSV
+    /// otherwise.
+    pub out: PathBuf,
+    /// Directory the frames of the run are captured to, if any.
+    pub capture: Option<PathBuf>,
+    /// Scenario the run follows, if any.
+    pub scenario: Option<ScenarioDefinition>,
+}
+
+/// Outcome of a batch run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchSummary {
+    pub steps: usize,
+    pub rows: usize,
+    pub path: PathBuf,
+    /// Wall-clock time the run took.
+    pub elapsed: Duration,
+    /// Violations of the monitored requirements.
+    pub violations: Vec<Violation>,
+    pub usage: ResourceUsage,
+}
+
+impl BatchSummary {
+    /// Simulated time per wall-clock time.
+    pub fn real_time_factor(&self, dt: f32) -> f32 {
+        self.steps as f32 * dt / self.elapsed.as_secs_f32().max(f32::EPSILON)
+    }
+}
+
+/// Parses a duration in seconds, e.g. `10`, `10s`, `250ms`, `2min` or `1h`.
+pub fn parse_duration(text: &str) -> Result<f32, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let scale = match unit {
+        "" | "s" => 1.0,
+        "ms" => 1e-3,
+        "min" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown unit `{unit}`, expected ms, s, min or h")),
+    };
+    match value.trim().parse::<f32>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value * scale),
+        _ => Err(format!("`{text}` isn't a positive duration")),
+    }
+}
+
+/// Format of the output of a batch run, from its extension.
+pub fn format_of(path: &Path) -> RecordingFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("parquet") => RecordingFormat::Parquet,
+        _ => RecordingFormat::Csv,
+    }
+}
+
+/// Builds a headless application of `plant` and its controllers, stepping by `dt`, ready to be
+/// updated.
+pub fn app(plant: &Plant, dt: f32, seed: u64) -> App {
+    build(headless_app(dt), plant, dt, seed, None)
+}
+
+/// Builds an application of `plant` and its controllers as [`app`] does, rendering offscreen
+/// for the captures.
+pub fn capture_app(plant: &Plant, dt: f32, seed: u64) -> App {
+    let mut app = offscreen_app(dt);
+    app.add_plugins(CapturePlugin);
+    build(app, plant, dt, seed, None)
+}
+
+fn build(
+    mut app: App,
+    plant: &Plant,
+    dt: f32,
+    seed: u64,
+    scenario: Option<&ScenarioDefinition>,
+) -> App {
+    (plant.add)(&mut app);
+    app.add_plugins((
+        FixedStepPlugin {
+            dt,
+            seed,
+            real_time: false,
+        },
+        TelemetryPlugin,
+        GovernorPlugin,
+        PidControllerPlugin,
+        LqrPlugin,
+        SwingUpPlugin,
+        DisturbancesPlugin,
+        SensorlessPlugin,
+        SensorsPlugin,
+        EstimationPlugin,
+        ActuatorsPlugin,
+        FrictionPlugin,
+        StateMachinesPlugin,
+        MonitorsPlugin,
+    ));
+    if let Some(scenario) = scenario {
+        app.add_plugins(ScenarioPlugin {
+            scenario: scenario.clone(),
+        });
+    }
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Runs `plant` and its controllers headlessly for the duration of `batch`, writing their
+/// telemetry as it goes.
+pub fn run(plant: &Plant, batch: &BatchRun) -> Result<BatchSummary> {
+    run_with_progress(plant, batch, |_| {})
+}
+
+/// Runs `batch` as [`run`] does, calling `on_step` with the fraction of the run done after each
+/// step.
+pub fn run_with_progress(
+    plant: &Plant,
+    batch: &BatchRun,
+    mut on_step: impl FnMut(f32),
+) -> Result<BatchSummary> {
+    let settings = RecordingSettings {
+        format: format_of(&batch.out),
+        ..default()
+    };
+    let mut recorder = Recorder::start(batch.out.clone(), &settings, &Telemetry::default())?;
+    let mut app = match &batch.capture {
+        Some(directory) => {
+            let mut app = offscreen_app(batch.dt);
+            app.add_plugins(CapturePlugin);
+            let mut app = build(app, plant, batch.dt, batch.seed, batch.scenario.as_ref());
+            let (settings, error) = config_plugin::load_config::<CaptureSettings>("capture", false);
+            if let Some(error) = error {
+                return Err(error);
+            }
+            capture::start(app.world_mut(), settings.get(), directory.clone())?;
+            app
+        }
+        None => build(
+            headless_app(batch.dt),
+            plant,
+            batch.dt,
+            batch.seed,
+            batch.scenario.as_ref(),
+        ),
+    };
+
+    let start = Instant::now();
+    let mut meter = UsageMeter::start();
+    let steps = (batch.duration / batch.dt).round() as usize;
+    let write_error = |error: io::Error| Error::io(&batch.out, error);
+    for index in 0..steps {
+        let step = Instant::now();
+        app.update();
+        meter.step(step.elapsed());
+        // A reported error stops headless applications.
+        if app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "batch".to_string(),
+                message: format!("{} stopped on the error logged above", plant.name),
+            });
+        }
+        let world = app.world();
+        recorder
+            .record(world.resource::<Telemetry>(), world.resource::<Setpoints>())
+            .map_err(write_error)?;
+        on_step((index + 1) as f32 / steps as f32);
+    }
+    if batch.capture.is_some() {
+        capture::finish(&mut app);
+    }
+    let rows = recorder.rows();
+    let path = recorder.finish().map_err(write_error)?;
+    Ok(BatchSummary {
+        steps,
+        rows,
+        path,
+        elapsed: start.elapsed(),
+        violations: app.world().resource::<Monitors>().violations.clone(),
+        usage: meter.usage(),
+    })
+}
@@ -0,0 +1,413 @@
+//! Controller benchmarks: a standard suite of scenarios per plant, which every registered
+//! controller is run through headlessly, so that contributed controllers are scored the same
+//! way and compared on a scoreboard.
+//!
+//! The registered controllers are the [definitions](crate::controller_definition) of the
+//! `controllers` directory of the configuration, next to the defaults of the built-in
+//! controllers. A [`BenchScenario`] benchmarks the controllers of one type on one plant: it
+//! binds them to the links of the plant, sets its parameters at the start, named as in a
+//! [sweep](crate::sweep) (setpoints and `initial/<link>` angles), kicks links with torque
+//! impulses as in [fuzzing](crate::fuzzing), and scores how a telemetry channel reaches its
+//! target. The other controllers are disabled during its runs.
+//!
+//! The runs of a scenario are ranked against each other, and each controller is scored by its
+//! wins and its mean rank over the scenarios it ran. The suite is read from `bench.json`, the
+//! [standard] one by default; the scenarios of plants missing from the build are skipped.
+use std::{collections::BTreeMap, fs, path::Path, time::Instant};
+
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actuators, batch,
+    controller_definition::{self, ControllerDefinition, ControllerType, PID_JOINT},
+    error::{Error, Result},
+    fuzzing::{self, Kick},
+    lqr::LqrSettings,
+    monitors::Monitors,
+    pid_controller::{PidSettings, PID_ERROR},
+    plants::Plant,
+    resource_usage::{ResourceUsage, UsageMeter},
+    setpoints::MOTOR_POSITION,
+    sweep::{self, Cost, Metrics, SweepRun},
+    swing_up::SwingUpSettings,
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+/// Represents the configuration of the benchmarks.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct BenchSettings {
+    pub scenarios: Vec<BenchScenario>,
+    /// Seed of every run.
+    pub seed: u64,
+    /// Metric the runs of a scenario are ranked by.
+    pub rank_by: Cost,
+}
+
+impl Default for BenchSettings {
+    fn default() -> Self {
+        Self {
+            scenarios: standard(),
+            seed: 1,
+            rank_by: Cost::default(),
+        }
+    }
+}
+
+/// A test of the controllers of a type on a plant.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct BenchScenario {
+    pub name: String,
+    /// Name of the plant, e.g. `rotary_pendulum`.
+    pub plant: String,
+    /// Type of the controllers run, e.g. `pid`.
+    pub controller: String,
+    /// Links the controllers are bound to by role, over the bindings of their definitions.
+    pub bindings: BTreeMap<String, String>,
+    /// Simulated duration, in s.
+    pub duration: f32,
+    /// Parameters set at the start, named as in a sweep.
+    pub parameters: BTreeMap<String, f32>,
+    /// Disturbances, in time order.
+    pub kicks: Vec<Kick>,
+    /// Channel scored.
+    pub channel: String,
+    /// Value the channel should reach.
+    pub target: f32,
+    /// Half width of the band around the target the channel settles in.
+    pub band: f32,
+}
+
+/// The standard suite: steps of the position loops and their rejection of a kick, on the
+/// rotary pendulum, the planar arm and the double pendulum; balancing of the rotary pendulum
+/// from a tilt and against a kick.
+pub fn standard() -> Vec<BenchScenario> {
+    let step = |name: &str, plant: &str, link: &str, kick: Option<Vec3>| BenchScenario {
+        name: name.to_string(),
+        plant: plant.to_string(),
+        controller: "pid".to_string(),
+        bindings: BTreeMap::from([(PID_JOINT.to_string(), link.to_string())]),
+        duration: 6.0,
+        parameters: BTreeMap::from([(MOTOR_POSITION.to_string(), 1.0)]),
+        kicks: kick
+            .map(|impulse| Kick {
+                time: 3.0,
+                link: link.to_string(),
+                impulse,
+            })
+            .into_iter()
+            .collect(),
+        channel: PID_ERROR.to_string(),
+        target: 0.0,
+        band: 0.02,
+    };
+    let balance = |name: &str, initial: f32, kick: Option<Vec3>| BenchScenario {
+        name: name.to_string(),
+        plant: "rotary_pendulum".to_string(),
+        controller: "lqr".to_string(),
+        bindings: BTreeMap::new(),
+        duration: 5.0,
+        parameters: BTreeMap::from([("initial/pendulum".to_string(), initial)]),
+        kicks: kick
+            .map(|impulse| Kick {
+                time: 1.0,
+                link: "pendulum".to_string(),
+                impulse,
+            })
+            .into_iter()
+            .collect(),
+        channel: PENDULUM_ANGLE.to_string(),
+        target: 0.0,
+        band: 0.05,
+    };
+    vec![
+        step("pendulum step", "rotary_pendulum", "motor", None),
+        step(
+            "pendulum step rejecting a kick",
+            "rotary_pendulum",
+            "motor",
+            Some(Vec3::Y * 1.0),
+        ),
+        step("arm step", "planar_arm", "upper_arm", None),
+        step(
+            "arm step rejecting a kick",
+            "planar_arm",
+            "upper_arm",
+            Some(Vec3::Z * 0.5),
+        ),
+        step("double pendulum step", "double_pendulum", "upper", None),
+        balance("balance from a tilt", 0.2, None),
+        balance(
+            "balance against a kick",
+            0.0,
+            Some(Vec3::new(0.2, 0.0, 0.2)),
+        ),
+    ]
+}
+
+/// A registered controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contender {
+    pub name: String,
+    pub definition: ControllerDefinition,
+}
+
+/// The defaults of the built-in controllers, then the definitions of `directory` named after
+/// their files.
+pub fn contenders(directory: &Path) -> Vec<Contender> {
+    let defaults = [
+        ControllerDefinition::from(&PidSettings::default()),
+        ControllerDefinition::from(&LqrSettings::default()),
+    ]
+    .map(|definition| Contender {
+        name: format!("default {}", definition.name),
+        definition,
+    });
+    let files = controller_definition::definitions(directory)
+        .into_iter()
+        .map(|(path, definition)| Contender {
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            definition,
+        });
+    defaults.into_iter().chain(files).collect()
+}
+
+/// A run of a controller through a scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchRun {
+    pub scenario: String,
+    pub controller: String,
+    pub run: SweepRun,
+}
+
+/// Enables `definition` bound over by `bindings`, alone among the controllers.
+fn configure(
+    world: &mut World,
+    definition: &ControllerDefinition,
+    bindings: &BTreeMap<String, String>,
+) -> Result<()> {
+    let mut definition = definition.clone();
+    definition.bindings.extend(bindings.clone());
+    let mut pid = PidSettings {
+        enabled: false,
+        ..world.resource::<Persistent<PidSettings>>().get().clone()
+    };
+    let mut lqr = LqrSettings {
+        enabled: false,
+        ..world.resource::<Persistent<LqrSettings>>().get().clone()
+    };
+    match definition.controller {
+        ControllerType::Pid { .. } => pid = PidSettings::try_from(&definition)?,
+        ControllerType::Lqr { .. } => lqr = LqrSettings::try_from(&definition)?,
+    }
+    *world.resource_mut::<Persistent<PidSettings>>().get_mut() = pid;
+    *world.resource_mut::<Persistent<LqrSettings>>().get_mut() = lqr;
+    world
+        .resource_mut::<Persistent<SwingUpSettings>>()
+        .get_mut()
+        .enabled = false;
+    Ok(())
+}
+
+/// Runs the controller `contender` through `scenario` on `plant`, stepping by `dt`.
+pub fn evaluate(
+    plant: &Plant,
+    scenario: &BenchScenario,
+    contender: &Contender,
+    seed: u64,
+    dt: f32,
+) -> Result<BenchRun> {
+    let mut meter = UsageMeter::start();
+    let mut app = batch::app(plant, dt, seed);
+    let steps = (scenario.duration / dt).round() as usize;
+    let mut kicks = scenario.kicks.iter().peekable();
+    for step in 0..steps {
+        let start = Instant::now();
+        app.update();
+        meter.step(start.elapsed());
+        if app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "bench".to_string(),
+                message: format!(
+                    "{} stopped on the error logged above, in {}",
+                    plant.name, scenario.name
+                ),
+            });
+        }
+        // The plant is spawned by the first update, so the controller and the parameters
+        // apply from the second step, and so do the kicks at the start.
+        let world = app.world_mut();
+        if step == 0 {
+            configure(world, &contender.definition, &scenario.bindings)?;
+            for (name, value) in &scenario.parameters {
+                sweep::apply(world, name, *value)?;
+            }
+        }
+        while let Some(kick) = kicks.next_if(|kick| kick.time < (step + 2) as f32 * dt) {
+            fuzzing::apply(world, kick);
+        }
+    }
+    let energy = actuators::total(app.world_mut());
+    let world = app.world();
+    let samples = world
+        .resource::<Telemetry>()
+        .channels
+        .get(&scenario.channel);
+    Ok(BenchRun {
+        scenario: scenario.name.clone(),
+        controller: contender.name.clone(),
+        run: SweepRun {
+            parameters: scenario.parameters.clone(),
+            metrics: samples
+                .and_then(|samples| Metrics::of(samples, scenario.target, scenario.band)),
+            energy,
+            violations: world.resource::<Monitors>().violations.len(),
+            usage: meter.usage(),
+            scripted: None,
+        },
+    })
+}
+
+/// Runs the `contenders` through the scenarios of `settings` of their type whose plant is among
+/// `plants`, stepping by `dt`, calling `on_run` with each run as it ends.
+pub fn bench(
+    plants: &[Plant],
+    settings: &BenchSettings,
+    contenders: &[Contender],
+    dt: f32,
+    mut on_run: impl FnMut(&BenchRun),
+) -> Result<Vec<BenchRun>> {
+    let mut runs = Vec::new();
+    for scenario in &settings.scenarios {
+        let Some(plant) = plants.iter().find(|plant| plant.name == scenario.plant) else {
+            continue;
+        };
+        for contender in contenders
+            .iter()
+            .filter(|contender| contender.definition.controller.name() == scenario.controller)
+        {
+            let run = evaluate(plant, scenario, contender, settings.seed, dt)?;
+            on_run(&run);
+            runs.push(run);
+        }
+    }
+    Ok(runs)
+}
+
+/// Rank of each run among the runs of its scenario by `cost`, from 1 for the best.
+pub fn ranks(runs: &[BenchRun], cost: Cost) -> Vec<usize> {
+    let mut ranks = vec![0; runs.len()];
+    let mut scenarios: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, run) in runs.iter().enumerate() {
+        scenarios
+            .entry(run.scenario.as_str())
+            .or_default()
+            .push(index);
+    }
+    for indices in scenarios.values() {
+        let scenario: Vec<SweepRun> = indices
+            .iter()
+            .map(|&index| runs[index].run.clone())
+            .collect();
+        for (rank, local) in sweep::ranking(&scenario, cost).into_iter().enumerate() {
+            ranks[indices[local]] = rank + 1;
+        }
+    }
+    ranks
+}
+
+/// The standing of a controller over its scenarios.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    pub controller: String,
+    pub scenarios: usize,
+    /// Scenarios it ranked first in, and those it settled in.
+    pub wins: usize,
+    pub settled: usize,
+    pub mean_rank: f32,
+}
+
+/// The scoreboard of the `runs` ranked by `cost`, best first: by mean rank, then by wins.
+pub fn scoreboard(runs: &[BenchRun], cost: Cost) -> Vec<Score> {
+    let ranks = ranks(runs, cost);
+    let mut scores: BTreeMap<&str, Score> = BTreeMap::new();
+    for (run, rank) in runs.iter().zip(ranks) {
+        let score = scores
+            .entry(run.controller.as_str())
+            .or_insert_with(|| Score {
+                controller: run.controller.clone(),
+                scenarios: 0,
+                wins: 0,
+                settled: 0,
+                mean_rank: 0.0,
+            });
+        score.scenarios += 1;
+        score.wins += usize::from(rank == 1 && run.run.cost(cost).is_finite());
+        score.settled += usize::from(
+            run.run
+                .metrics
+                .is_some_and(|metrics| metrics.settling_time.is_some()),
+        );
+        score.mean_rank += rank as f32;
+    }
+    let mut scores: Vec<Score> = scores
+        .into_values()
+        .map(|mut score| {
+            score.mean_rank /= score.scenarios as f32;
+            score
+        })
+        .collect();
+    scores.sort_by(|a, b| {
+        a.mean_rank
+            .total_cmp(&b.mean_rank)
+            .then(b.wins.cmp(&a.wins))
+    });
+    scores
+}
+
+/// Writes the scoreboard as CSV, one row per run with its metrics and its rank in its
+/// scenario.
+pub fn write_scoreboard(runs: &[BenchRun], cost: Cost, path: &Path) -> Result<()> {
+    let mut csv = format!(
+        "scenario,controller,settling_time,overshoot,rms_error,energy,violations,{},rank\n",
+        ResourceUsage::CSV_HEADER
+    );
+    for (run, rank) in runs.iter().zip(ranks(runs, cost)) {
+        csv.push_str(&format!("{},{}", run.scenario, run.controller));
+        match run.run.metrics {
+            Some(metrics) => {
+                let settling = metrics
+                    .settling_time
+                    .map_or(String::new(), |time| time.to_string());
+                csv.push_str(&format!(
+                    ",{settling},{},{}",
+                    metrics.overshoot, metrics.rms_error
+                ));
+            }
+            None => csv.push_str(",,,"),
+        }
+        let energy = run
+            .run
+            .energy
+            .map_or(String::new(), |energy| energy.electrical.to_string());
+        csv.push_str(&format!(
+            ",{energy},{},{},{rank}\n",
+            run.run.violations,
+            run.run.usage.csv()
+        ));
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+    }
+    fs::write(path, csv).map_err(|error| Error::io(path, error))
+}
@@ -0,0 +1,102 @@
+//! Capturing and applying the state of the dynamic rigid bodies of the scene.
+//!
+//! Bodies are identified by their spawn order, so a state captured in one application can be
+//! applied to another one running the same plant (a restored session, a remote instance).
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Lets Rapier write back the velocities of the dynamic bodies, so they can be captured.
+pub struct BodyStatePlugin;
+
+impl Plugin for BodyStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, track_velocities);
+    }
+}
+
+/// State of a dynamic rigid body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BodyState {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+}
+
+/// The rigid bodies, to capture their state.
+pub type Bodies<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static RigidBody,
+        &'static Transform,
+        Option<&'static Velocity>,
+    ),
+>;
+
+/// The rigid bodies, to apply a state.
+pub type BodiesMut<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static RigidBody,
+        &'static mut Transform,
+        Option<&'static mut Velocity>,
+    ),
+>;
+
+fn track_velocities(mut commands: Commands, bodies: Query<(Entity, &RigidBody), Added<RigidBody>>) {
+    for (entity, body) in &bodies {
+        if *body == RigidBody::Dynamic {
+            commands.entity(entity).insert_if_new(Velocity::default());
+        }
+    }
+}
+
+/// The state of the dynamic bodies, in spawn order.
+pub fn capture(bodies: &Bodies) -> Vec<BodyState> {
+    let mut dynamic = bodies
+        .iter()
+        .filter(|(_, body, ..)| **body == RigidBody::Dynamic)
+        .collect::<Vec<_>>();
+    dynamic.sort_by_key(|(entity, ..)| *entity);
+    dynamic
+        .into_iter()
+        .map(|(_, _, transform, velocity)| {
+            let velocity = velocity.copied().unwrap_or_default();
+            BodyState {
+                translation: transform.translation,
+                rotation: transform.rotation,
+                linear_velocity: velocity.linvel,
+                angular_velocity: velocity.angvel,
+            }
+        })
+        .collect()
+}
+
+/// Applies `states` to the dynamic bodies, matched in spawn order.
+///
+/// Nothing is applied if the number of bodies differs, in which case the number of dynamic
+/// bodies of the scene is returned as the error.
+pub fn apply(states: &[BodyState], bodies: &mut BodiesMut) -> Result<(), usize> {
+    let mut dynamic = bodies
+        .iter_mut()
+        .filter(|(_, body, ..)| **body == RigidBody::Dynamic)
+        .collect::<Vec<_>>();
+    if dynamic.len() != states.len() {
+        return Err(dynamic.len());
+    }
+    dynamic.sort_by_key(|(entity, ..)| *entity);
+    for ((_, _, mut transform, velocity), state) in dynamic.into_iter().zip(states) {
+        transform.translation = state.translation;
+        transform.rotation = state.rotation;
+        if let Some(mut velocity) = velocity {
+            velocity.linvel = state.linear_velocity;
+            velocity.angvel = state.angular_velocity;
+        }
+    }
+    Ok(())
+}
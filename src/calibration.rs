@@ -0,0 +1,490 @@
+//! Calibration of a model of the rig against its hardware logs, as a repeatable procedure.
+//!
+//! The *Calibration* window walks through four steps: the log, imported in the *Hardware log*
+//! window; the signals, the channels of the log replayed as inputs and the ones predicted as
+//! outputs; the fit, where [`calibrate`] identifies a linear model on the start of the log and
+//! validates it on the rest; and the model, written for the tools reading identified models.
+//! The fit of each output is reported next to the fit of a reference model, e.g. the one of the
+//! simulation written by `--identify`, on the same data, to tell how far the simulation is from
+//! the rig. The same procedure runs from the command line with `--calibrate`.
+//!
+//! The signals, the sample period and the identification are read from `calibration.json`.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    hardware_log::HardwareLog,
+    headless::DEFAULT_TIME_STEP,
+    identification::{self, Dataset, LinearModel},
+    logging::subsystem,
+    run_diff,
+    setpoints::MOTOR_VELOCITY,
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            calibration_panel
+                .run_if(resource_exists::<Persistent<CalibrationSettings>>)
+                .run_if(has_ui),
+        );
+    }
+}
+
+/// Represents how a model is calibrated on a log.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct CalibrationSettings {
+    /// Channels of the log driving the model.
+    pub inputs: Vec<String>,
+    /// Channels of the log the model predicts.
+    pub outputs: Vec<String>,
+    /// Sample period the log is resampled at, in s.
+    pub dt: f32,
+    /// Number of states of the model.
+    pub order: usize,
+    /// Number of block rows of the Hankel matrices.
+    pub horizon: usize,
+    /// Fraction of the log, at its end, kept to validate the model.
+    pub validation: f32,
+    /// Model the calibrated one is compared with, e.g. the one of the simulation.
+    pub reference: Option<PathBuf>,
+    /// File the calibrated model is written to.
+    pub model: PathBuf,
+}
+
+impl Default for CalibrationSettings {
+    fn default() -> Self {
+        Self {
+            inputs: vec![MOTOR_VELOCITY.to_string()],
+            outputs: vec![PENDULUM_ANGLE.to_string()],
+            dt: DEFAULT_TIME_STEP,
+            order: 4,
+            horizon: 30,
+            validation: 0.3,
+            reference: None,
+            model: PathBuf::from("calibrated_model.json"),
+        }
+    }
+}
+
+/// The channels of `log` sampled every `dt` over the time they all cover, interpolated
+/// linearly.
+pub fn dataset(log: &Telemetry, inputs: &[String], outputs: &[String], dt: f32) -> Result<Dataset> {
+    let mut channels = Vec::new();
+    for name in inputs.iter().chain(outputs) {
+        match log.channels.get(name) {
+            Some(samples) if !samples.is_empty() => channels.push(samples),
+            _ => {
+                return Err(Error::Identification(format!(
+                    "the log has no `{name}` channel"
+                )))
+            }
+        }
+    }
+    if dt <= 0.0 {
+        return Err(Error::Identification(format!(
+            "a sample period of {dt} s is not positive"
+        )));
+    }
+    let start = channels
+        .iter()
+        .map(|samples| samples[0][0])
+        .fold(f32::NEG_INFINITY, f32::max);
+    let end = channels
+        .iter()
+        .map(|samples| samples[samples.len() - 1][0])
+        .fold(f32::INFINITY, f32::min);
+    let count = ((end - start) / dt).floor().max(-1.0) as i64 + 1;
+    let mut data = Dataset { dt, ..default() };
+    for k in 0..count {
+        let time = (start + k as f32 * dt).min(end);
+        let mut values = channels
+            .iter()
+            .map(|samples| f64::from(run_diff::interpolate(samples, time).unwrap_or_default()));
+        data.inputs
+            .push(values.by_ref().take(inputs.len()).collect());
+        data.outputs.push(values.collect());
+    }
+    Ok(data)
+}
+
+/// How well the models predict an output of the log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignalFit {
+    pub signal: String,
+    /// Fit of the calibrated model, in percent.
+    pub calibrated: f64,
+    /// Fit of the reference model, in percent, if there is one.
+    pub reference: Option<f64>,
+}
+
+/// A model calibrated on a log, and how it fits the part of the log kept for validation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub model: LinearModel,
+    pub fits: Vec<SignalFit>,
+    /// Samples the model was identified on.
+    pub estimation_samples: usize,
+    /// The part of the log kept for validation, and the outputs the model predicts on it.
+    pub validation: Dataset,
+    pub predicted: Vec<Vec<f64>>,
+}
+
+impl Calibration {
+    /// Writes the model to `path`, and the validation next to it for plotting.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        self.model.write(path)?;
+        identification::write_validation(
+            &path.with_extension("validation.csv"),
+            &self.model,
+            &self.validation,
+            &self.predicted,
+        )
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "model of order {} identified on {} samples, fit on {} others:",
+            self.model.order(),
+            self.estimation_samples,
+            self.validation.inputs.len()
+        )?;
+        for fit in &self.fits {
+            write!(f, "  {}: {:.1} %", fit.signal, fit.calibrated)?;
+            if let Some(reference) = fit.reference {
+                write!(f, " (reference {reference:.1} %)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outputs of `model` on `data`, from the initial state explaining them best.
+fn predict(model: &LinearModel, data: &Dataset) -> Vec<Vec<f64>> {
+    let initial_state = model.estimate_initial_state(&data.inputs, &data.outputs);
+    model.simulate(&data.inputs, &initial_state)
+}
+
+/// Identifies a model of the rig on the start of `log` and validates it, and the reference
+/// model if any, on the rest.
+pub fn calibrate(log: &Telemetry, settings: &CalibrationSettings) -> Result<Calibration> {
+    let reference = match &settings.reference {
+        Some(path) => {
+            let model = LinearModel::read(path)?;
+            if model.inputs != settings.inputs || model.outputs != settings.outputs {
+                return Err(Error::Identification(format!(
+                    "the reference model maps {:?} to {:?}, not {:?} to {:?}",
+                    model.inputs, model.outputs, settings.inputs, settings.outputs
+                )));
+            }
+            if (model.dt - settings.dt).abs() > 1e-4 * settings.dt {
+                return Err(Error::Identification(format!(
+                    "the reference model is sampled every {} s, not {} s",
+                    model.dt, settings.dt
+                )));
+            }
+            Some(model)
+        }
+        None => None,
+    };
+    let data = dataset(log, &settings.inputs, &settings.outputs, settings.dt)?;
+    let samples = data.inputs.len();
+    let split = (samples as f32 * (1.0 - settings.validation.clamp(0.0, 1.0))).round() as usize;
+    if split == 0 || split >= samples {
+        return Err(Error::Identification(format!(
+            "{samples} samples can't be split into estimation and validation data"
+        )));
+    }
+    let estimation = Dataset {
+        dt: data.dt,
+        inputs: data.inputs[..split].to_vec(),
+        outputs: data.outputs[..split].to_vec(),
+    };
+    let validation = Dataset {
+        dt: data.dt,
+        inputs: data.inputs[split..].to_vec(),
+        outputs: data.outputs[split..].to_vec(),
+    };
+
+    let model = identification::identify(
+        &estimation,
+        &settings.inputs,
+        &settings.outputs,
+        settings.order,
+        settings.horizon,
+    )?;
+    let predicted = predict(&model, &validation);
+    let calibrated = identification::fit_percent(&validation.outputs, &predicted);
+    let references = reference.map(|reference| {
+        identification::fit_percent(&validation.outputs, &predict(&reference, &validation))
+    });
+    let fits = settings
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(index, signal)| SignalFit {
+            signal: signal.clone(),
+            calibrated: calibrated[index],
+            reference: references.as_ref().map(|fits| fits[index]),
+        })
+        .collect();
+    Ok(Calibration {
+        model,
+        fits,
+        estimation_samples: split,
+        validation,
+        predicted,
+    })
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<CalibrationSettings>("calibration", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Steps of the calibration wizard.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Step {
+    #[default]
+    Log,
+    Signals,
+    Fit,
+    Model,
+}
+
+impl Step {
+    const ALL: [Step; 4] = [Step::Log, Step::Signals, Step::Fit, Step::Model];
+
+    fn title(self) -> &'static str {
+        match self {
+            Step::Log => "Log",
+            Step::Signals => "Signals",
+            Step::Fit => "Fit",
+            Step::Model => "Model",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|step| *step == self).unwrap_or(0)
+    }
+}
+
+/// State of the calibration wizard.
+#[derive(Default)]
+struct Wizard {
+    step: Step,
+    calibration: Option<Calibration>,
+    /// Paths being edited.
+    reference: Option<String>,
+    model: Option<String>,
+}
+
+/// Panel walking through the calibration of a model on the imported log.
+fn calibration_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<CalibrationSettings>>,
+    log: Option<Res<HardwareLog>>,
+    mut wizard: Local<Wizard>,
+) {
+    let mut edited = settings.get().clone();
+    let wizard = &mut *wizard;
+    if log.as_ref().is_some_and(|log| log.is_changed()) {
+        wizard.calibration = None;
+    }
+    egui::Window::new("Calibration")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let step = wizard.step;
+            ui.heading(format!(
+                "Step {} of {}: {}",
+                step.index() + 1,
+                Step::ALL.len(),
+                step.title()
+            ));
+            let ready = match step {
+                Step::Log => match &log {
+                    Some(log) => {
+                        ui.label(format!("{}", log.path.display()));
+                        for (channel, samples) in &log.telemetry.channels {
+                            ui.weak(format!("{channel}: {} samples", samples.len()));
+                        }
+                        true
+                    }
+                    None => {
+                        ui.weak("Import a log in the Hardware log window");
+                        false
+                    }
+                },
+                Step::Signals => {
+                    let channels = log
+                        .as_ref()
+                        .map(|log| log.telemetry.channels.keys().cloned().collect::<Vec<_>>())
+                        .unwrap_or_default();
+                    egui::Grid::new("calibration_signals").show(ui, |ui| {
+                        for channel in &channels {
+                            ui.label(channel);
+                            for (label, signals) in [
+                                ("Input", &mut edited.inputs),
+                                ("Output", &mut edited.outputs),
+                            ] {
+                                let mut selected = signals.contains(channel);
+                                if ui.checkbox(&mut selected, label).changed() {
+                                    signals.retain(|signal| signal != channel);
+                                    if selected {
+                                        signals.push(channel.clone());
+                                    }
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.add(
+                        egui::DragValue::new(&mut edited.dt)
+                            .range(0.0001..=1.0)
+                            .speed(0.0001)
+                            .prefix("Sample period: ")
+                            .suffix(" s"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut edited.order)
+                                .range(1..=20)
+                                .prefix("Order: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut edited.horizon)
+                                .range(1..=200)
+                                .prefix("Horizon: "),
+                        );
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut edited.validation, 0.1..=0.9)
+                            .text("kept for validation"),
+                    );
+                    let reference = wizard.reference.get_or_insert_with(|| {
+                        let path = edited.reference.as_ref();
+                        path.map(|path| path.display().to_string())
+                            .unwrap_or_default()
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Reference");
+                        ui.text_edit_singleline(reference)
+                            .on_hover_text("Model of the simulation to compare, or empty");
+                    });
+                    edited.reference = (!reference.is_empty()).then(|| PathBuf::from(&*reference));
+                    !edited.inputs.is_empty() && !edited.outputs.is_empty()
+                }
+                Step::Fit => {
+                    if let Some(log) = &log {
+                        if ui.button("Identify").clicked() {
+                            match calibrate(&log.telemetry, &edited) {
+                                Ok(calibration) => wizard.calibration = Some(calibration),
+                                Err(error) => {
+                                    commands.send_event(ErrorEvent::from(error));
+                                }
+                            }
+                        }
+                    }
+                    match &wizard.calibration {
+                        Some(calibration) => {
+                            for line in calibration.to_string().lines() {
+                                ui.label(line.trim());
+                            }
+                            true
+                        }
+                        None => {
+                            ui.weak("No model identified");
+                            false
+                        }
+                    }
+                }
+                Step::Model => {
+                    let model = wizard
+                        .model
+                        .get_or_insert_with(|| edited.model.display().to_string());
+                    ui.horizontal(|ui| {
+                        ui.label("Model");
+                        ui.text_edit_singleline(model);
+                    });
+                    edited.model = PathBuf::from(&*model);
+                    if let Some(calibration) = &wizard.calibration {
+                        if ui.button("Write").clicked() {
+                            match calibration.write(&edited.model) {
+                                Ok(()) => info!(
+                                    target: subsystem::CONTROL,
+                                    "Calibrated model written to {}",
+                                    edited.model.display()
+                                ),
+                                Err(error) => {
+                                    commands.send_event(ErrorEvent::from(error));
+                                }
+                            }
+                        }
+                    }
+                    false
+                }
+            };
+            ui.horizontal(|ui| {
+                let index = step.index();
+                if ui
+                    .add_enabled(index > 0, egui::Button::new("Back"))
+                    .clicked()
+                {
+                    wizard.step = Step::ALL[index - 1];
+                }
+                if ui
+                    .add_enabled(
+                        ready && index + 1 < Step::ALL.len(),
+                        egui::Button::new("Next"),
+                    )
+                    .clicked()
+                {
+                    wizard.step = Step::ALL[index + 1];
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("calibration", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("calibration", error)));
+                    }
+                    edited = settings.get().clone();
+                    wizard.reference = None;
+                    wizard.model = None;
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
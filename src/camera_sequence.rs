@@ -0,0 +1,386 @@
+//! This module provides a camera sequencer, flying the camera through keyframes to capture demo
+//! videos reproducibly.
+//!
+//! Each keyframe places the camera at a position, looking at a target, at a time since the start
+//! of the sequence; in between, the position and the target are interpolated with the easing of
+//! the keyframe reached. The sequence plays on the simulated time, so it stays in step with the
+//! scenario it films however fast the frames are rendered, e.g. while recording. It's started
+//! from the *Camera sequence* panel, or along with the teach sequence or the trajectory
+//! generator, and loops if configured. While it plays, the camera can't be orbited by hand.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    teach::TeachPlayback,
+    trajectory::TrajectoryPlayback,
+};
+
+pub struct CameraSequencePlugin;
+
+impl Plugin for CameraSequencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraPlayback>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    (trigger_sequence, play_sequence).chain(),
+                    camera_sequence_panel.run_if(has_ui),
+                )
+                    .run_if(resource_exists::<Persistent<CameraSequence>>),
+            );
+    }
+}
+
+/// How the camera moves into a keyframe.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    /// Starting slowly.
+    EaseIn,
+    /// Arriving slowly.
+    EaseOut,
+    /// Both.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    pub const ALL: [Self; 4] = [Self::Linear, Self::EaseIn, Self::EaseOut, Self::EaseInOut];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::EaseIn => "Ease in",
+            Self::EaseOut => "Ease out",
+            Self::EaseInOut => "Ease in and out",
+        }
+    }
+
+    /// Eased fraction of the way, for the fraction `t` of the time, both within [0, 1].
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t * t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A pose of the camera in a sequence.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct CameraKeyframe {
+    /// Time since the start of the sequence, in s.
+    pub time: f32,
+    pub position: Vec3,
+    /// Point looked at.
+    pub target: Vec3,
+    /// How the camera moves from the previous keyframe.
+    pub easing: Easing,
+}
+
+impl Default for CameraKeyframe {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            position: Vec3::new(10.0, 10.0, 10.0),
+            target: Vec3::ZERO,
+            easing: Easing::EaseInOut,
+        }
+    }
+}
+
+/// When a sequence starts, besides from the panel.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceTrigger {
+    #[default]
+    Manual,
+    /// Along with the sequence of the teach panel.
+    Teach,
+    /// Along with the trajectory generator.
+    Trajectory,
+}
+
+impl SequenceTrigger {
+    pub const ALL: [Self; 3] = [Self::Manual, Self::Teach, Self::Trajectory];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Manual => "From the panel only",
+            Self::Teach => "With the teach sequence",
+            Self::Trajectory => "With the trajectory",
+        }
+    }
+}
+
+/// Represents the camera sequence, from the `camera_sequence.json` configuration file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct CameraSequence {
+    /// The keyframes, by time.
+    pub keyframes: Vec<CameraKeyframe>,
+    pub trigger: SequenceTrigger,
+    pub looped: bool,
+}
+
+impl CameraSequence {
+    /// Time of the last keyframe, in s.
+    pub fn duration(&self) -> f32 {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.time)
+            .fold(0.0, f32::max)
+    }
+
+    /// Position and target of the camera `time` seconds into the sequence, held past its ends,
+    /// or `None` without keyframes.
+    pub fn pose(&self, time: f32) -> Option<(Vec3, Vec3)> {
+        let mut keyframes: Vec<&CameraKeyframe> = self.keyframes.iter().collect();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        match (
+            next.checked_sub(1).map(|index| keyframes[index]),
+            keyframes.get(next),
+        ) {
+            (Some(from), Some(to)) => {
+                let t = to.easing.apply((time - from.time) / (to.time - from.time));
+                Some((
+                    from.position.lerp(to.position, t),
+                    from.target.lerp(to.target, t),
+                ))
+            }
+            (Some(keyframe), None) | (None, Some(keyframe)) => {
+                Some((keyframe.position, keyframe.target))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Yaw, pitch and radius of an orbit camera at `position`, looking at `target`.
+pub fn orbit_angles(position: Vec3, target: Vec3) -> (f32, f32, f32) {
+    let offset = position - target;
+    let radius = offset.length().max(f32::EPSILON);
+    let yaw = offset.x.atan2(offset.z);
+    let pitch = (offset.y / radius).clamp(-1.0, 1.0).asin();
+    (yaw, pitch.clamp(-PI / 2.0, PI / 2.0), radius)
+}
+
+/// The sequence playing, and the time it started at.
+#[derive(Default, Resource)]
+pub struct CameraPlayback {
+    pub playing: Option<f32>,
+    /// Whether the teach sequence and the trajectory were playing at the last frame.
+    teach: bool,
+    trajectory: bool,
+}
+
+fn setup(mut commands: Commands) {
+    let (sequence, error) = config_plugin::load_config::<CameraSequence>("camera_sequence", true);
+    commands.insert_resource(sequence);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Starts the sequence as the teach sequence or the trajectory it goes along with starts.
+fn trigger_sequence(
+    clock: Res<SimClock>,
+    sequence: Res<Persistent<CameraSequence>>,
+    mut playback: ResMut<CameraPlayback>,
+    teach: Option<Res<TeachPlayback>>,
+    trajectory: Option<Res<TrajectoryPlayback>>,
+) {
+    let teach = teach.is_some_and(|teach| teach.playback.is_some());
+    let trajectory = trajectory.is_some_and(|trajectory| trajectory.playing.is_some());
+    let started = match sequence.trigger {
+        SequenceTrigger::Manual => false,
+        SequenceTrigger::Teach => teach && !playback.teach,
+        SequenceTrigger::Trajectory => trajectory && !playback.trajectory,
+    };
+    if started && !sequence.keyframes.is_empty() {
+        info!(target: subsystem::UI, "Camera sequence started");
+        playback.playing = Some(clock.elapsed_secs());
+    }
+    playback.teach = teach;
+    playback.trajectory = trajectory;
+}
+
+/// Places the camera on the sequence, and hands it back once over.
+fn play_sequence(
+    clock: Res<SimClock>,
+    sequence: Res<Persistent<CameraSequence>>,
+    mut playback: ResMut<CameraPlayback>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut was_playing: Local<bool>,
+) {
+    let Some(started) = playback.playing else {
+        if std::mem::take(&mut *was_playing) {
+            for mut orbit in &mut orbits {
+                orbit.enabled = true;
+            }
+        }
+        return;
+    };
+    *was_playing = true;
+    let duration = sequence.duration();
+    let mut elapsed = clock.elapsed_secs() - started;
+    if elapsed > duration {
+        if sequence.looped && duration > 0.0 {
+            elapsed = elapsed.rem_euclid(duration);
+        } else {
+            playback.playing = None;
+        }
+    }
+    let Some((position, target)) = sequence.pose(elapsed) else {
+        playback.playing = None;
+        return;
+    };
+    let (yaw, pitch, radius) = orbit_angles(position, target);
+    for mut orbit in &mut orbits {
+        // Both the current and the target orbit, for the camera not to smooth its way there.
+        orbit.enabled = false;
+        orbit.focus = target;
+        orbit.target_focus = target;
+        orbit.yaw = Some(yaw);
+        orbit.target_yaw = yaw;
+        orbit.pitch = Some(pitch);
+        orbit.target_pitch = pitch;
+        orbit.radius = Some(radius);
+        orbit.target_radius = radius;
+        orbit.force_update = true;
+    }
+}
+
+/// Panel to edit the keyframes of the sequence and play it.
+fn camera_sequence_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut sequence: ResMut<Persistent<CameraSequence>>,
+    mut playback: ResMut<CameraPlayback>,
+    clock: Res<SimClock>,
+    cameras: Query<(&Transform, &PanOrbitCamera)>,
+) {
+    let mut edited = sequence.get().clone();
+
+    egui::Window::new("Camera sequence")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Start")
+                .selected_text(edited.trigger.name())
+                .show_ui(ui, |ui| {
+                    for trigger in SequenceTrigger::ALL {
+                        ui.selectable_value(&mut edited.trigger, trigger, trigger.name());
+                    }
+                });
+            ui.checkbox(&mut edited.looped, "Loop");
+
+            ui.separator();
+            let mut removed = None;
+            for (index, keyframe) in edited.keyframes.iter_mut().enumerate() {
+                let vector = |ui: &mut egui::Ui, label: &str, value: &mut Vec3| {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        for component in [&mut value.x, &mut value.y, &mut value.z] {
+                            ui.add(egui::DragValue::new(component).speed(0.05));
+                        }
+                    });
+                };
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut keyframe.time)
+                            .speed(0.1)
+                            .range(0.0..=f32::MAX)
+                            .prefix("At ")
+                            .suffix(" s"),
+                    );
+                    egui::ComboBox::from_id_salt(("camera_keyframe_easing", index))
+                        .selected_text(keyframe.easing.name())
+                        .show_ui(ui, |ui| {
+                            for easing in Easing::ALL {
+                                ui.selectable_value(&mut keyframe.easing, easing, easing.name());
+                            }
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                vector(ui, "Position", &mut keyframe.position);
+                vector(ui, "Target", &mut keyframe.target);
+            }
+            if let Some(index) = removed {
+                edited.keyframes.remove(index);
+            }
+            if ui.button("Add the current view").clicked() {
+                if let Some((transform, orbit)) = cameras.iter().next() {
+                    let time = edited
+                        .keyframes
+                        .last()
+                        .map_or(0.0, |keyframe| keyframe.time + 2.0);
+                    edited.keyframes.push(CameraKeyframe {
+                        time,
+                        position: transform.translation,
+                        target: orbit.focus,
+                        ..default()
+                    });
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                let playable = !edited.keyframes.is_empty();
+                if ui
+                    .add_enabled(playable, egui::Button::new("Play"))
+                    .clicked()
+                {
+                    playback.playing = Some(clock.elapsed_secs());
+                }
+                if ui.button("Stop").clicked() {
+                    playback.playing = None;
+                }
+            });
+            if let Some(started) = playback.playing {
+                ui.label(format!(
+                    "{:.2} s of {:.2} s",
+                    clock.elapsed_secs() - started,
+                    edited.duration()
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = sequence.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("camera_sequence", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = sequence.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("camera_sequence", error)));
+                    }
+                    edited = sequence.get().clone();
+                }
+            });
+        });
+
+    if edited != *sequence.get() {
+        *sequence.get_mut() = edited;
+    }
+}
@@ -0,0 +1,588 @@
+//! This module extends the orbit camera with views to jump to and follow.
+//!
+//! The *Cameras* panel moves the orbit camera to the named presets, front, side, top and
+//! isometric, around the point it looks at, and to the viewpoints saved by the user. The
+//! camera can also follow a point of a link, e.g. the tip of the pendulum while tuning: by
+//! keeping the orbit centered on it, still orbited and zoomed by hand, by chasing it from a
+//! fixed offset, or mounted on the link, turning with it.
+//!
+//! A second camera can show another of these views in a corner of the window, picture in
+//! picture. The views and the viewpoints are kept in `camera_views.json`.
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    camera_sequence::{orbit_angles, CameraPlayback},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+};
+
+pub struct CameraViewsPlugin;
+
+impl Plugin for CameraViewsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GoToView>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    camera_views_panel.run_if(has_ui),
+                    go_to_view,
+                    follow_link,
+                    (spawn_inset, place_inset).chain(),
+                )
+                    .chain()
+                    .run_if(resource_exists::<Persistent<CameraViews>>),
+            );
+    }
+}
+
+/// A named view around the point the camera looks at.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraPreset {
+    /// Looking along -Z.
+    Front,
+    /// Looking along -X.
+    Side,
+    /// Looking down.
+    Top,
+    /// Looking down the diagonal, as the camera starts.
+    #[default]
+    Isometric,
+}
+
+impl CameraPreset {
+    pub const ALL: [Self; 4] = [Self::Front, Self::Side, Self::Top, Self::Isometric];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Front => "Front",
+            Self::Side => "Side",
+            Self::Top => "Top",
+            Self::Isometric => "Isometric",
+        }
+    }
+
+    /// Position of the camera `distance` meters from the `target` it looks at.
+    pub fn position(self, target: Vec3, distance: f32) -> Vec3 {
+        let direction = match self {
+            Self::Front => Vec3::Z,
+            Self::Side => Vec3::X,
+            Self::Top => Vec3::Y,
+            Self::Isometric => Vec3::ONE.normalize(),
+        };
+        target + direction * distance
+    }
+}
+
+/// A viewpoint saved by the user.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Viewpoint {
+    pub name: String,
+    pub position: Vec3,
+    /// Point looked at.
+    pub target: Vec3,
+}
+
+impl Default for Viewpoint {
+    fn default() -> Self {
+        Self {
+            name: "Viewpoint".to_string(),
+            position: Vec3::new(10.0, 10.0, 10.0),
+            target: Vec3::ZERO,
+        }
+    }
+}
+
+/// How the camera follows the link.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowMode {
+    #[default]
+    Off,
+    /// The orbit stays centered on the point, and is still orbited by hand.
+    Orbit,
+    /// The camera keeps the offset from the point, in the frame of the world.
+    Chase,
+    /// The camera keeps the offset from the point in the frame of the link, turning with it.
+    Mounted,
+}
+
+impl FollowMode {
+    pub const ALL: [Self; 4] = [Self::Off, Self::Orbit, Self::Chase, Self::Mounted];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Orbit => "Orbit around it",
+            Self::Chase => "Chase it",
+            Self::Mounted => "Mounted on the link",
+        }
+    }
+}
+
+/// The point of a link the camera follows.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Follow {
+    pub mode: FollowMode,
+    /// Link of the first plant followed.
+    pub link: String,
+    /// Point followed, in the frame of the link.
+    pub point: Vec3,
+    /// Position of the camera from the point, when chasing it or mounted.
+    pub offset: Vec3,
+}
+
+impl Default for Follow {
+    fn default() -> Self {
+        Self {
+            mode: FollowMode::Off,
+            link: "pendulum".to_string(),
+            // The tip of the pendulum.
+            point: Vec3::Y * 1.5,
+            offset: Vec3::new(2.0, 1.5, 2.0),
+        }
+    }
+}
+
+impl Follow {
+    /// Position of the camera and point it looks at, for the link at `transform`.
+    pub fn pose(&self, transform: &GlobalTransform) -> (Vec3, Vec3) {
+        let target = transform.transform_point(self.point);
+        let offset = match self.mode {
+            FollowMode::Mounted => transform.affine().transform_vector3(self.offset),
+            _ => self.offset,
+        };
+        (target + offset, target)
+    }
+}
+
+/// What the picture in picture shows.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsetView {
+    /// A preset around the point the orbit camera looks at.
+    Preset(CameraPreset),
+    /// A saved viewpoint, by name.
+    Viewpoint(String),
+    /// The followed point, chased from its offset, whether the orbit camera follows it or not.
+    Follow,
+}
+
+impl Default for InsetView {
+    fn default() -> Self {
+        Self::Preset(CameraPreset::Top)
+    }
+}
+
+/// Corner of the window the picture in picture is drawn in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Self; 4] = [
+        Self::TopLeft,
+        Self::TopRight,
+        Self::BottomLeft,
+        Self::BottomRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TopLeft => "Top left",
+            Self::TopRight => "Top right",
+            Self::BottomLeft => "Bottom left",
+            Self::BottomRight => "Bottom right",
+        }
+    }
+}
+
+/// The picture in picture.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Inset {
+    pub enabled: bool,
+    pub view: InsetView,
+    pub corner: Corner,
+    /// Height of the inset, as a fraction of the height of the window.
+    pub size: f32,
+}
+
+impl Default for Inset {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            view: InsetView::default(),
+            corner: Corner::BottomRight,
+            size: 0.3,
+        }
+    }
+}
+
+/// Represents the views of the camera, from the `camera_views.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct CameraViews {
+    /// Distance of the presets from the point looked at, in m.
+    pub distance: f32,
+    pub viewpoints: Vec<Viewpoint>,
+    pub follow: Follow,
+    pub inset: Inset,
+}
+
+impl Default for CameraViews {
+    fn default() -> Self {
+        Self {
+            distance: 12.0,
+            viewpoints: Vec::new(),
+            follow: Follow::default(),
+            inset: Inset::default(),
+        }
+    }
+}
+
+/// Viewport of the picture in picture in a window of `size` physical pixels, with the aspect
+/// ratio of the window and a margin from its edges.
+pub fn inset_viewport(size: UVec2, corner: Corner, fraction: f32) -> Viewport {
+    let margin = size.y / 50;
+    let height = (size.y as f32 * fraction.clamp(0.05, 0.9)) as u32;
+    let width = (height as f32 * size.x as f32 / size.y.max(1) as f32) as u32;
+    let inset = UVec2::new(width, height).max(UVec2::ONE);
+    let left = margin;
+    let right = size.x.saturating_sub(inset.x + margin);
+    let top = margin;
+    let bottom = size.y.saturating_sub(inset.y + margin);
+    let position = match corner {
+        Corner::TopLeft => UVec2::new(left, top),
+        Corner::TopRight => UVec2::new(right, top),
+        Corner::BottomLeft => UVec2::new(left, bottom),
+        Corner::BottomRight => UVec2::new(right, bottom),
+    };
+    Viewport {
+        physical_position: position,
+        physical_size: inset,
+        ..default()
+    }
+}
+
+/// Moves the orbit camera to look from a position at a target.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct GoToView {
+    pub position: Vec3,
+    pub target: Vec3,
+}
+
+/// The camera of the picture in picture.
+#[derive(Clone, Copy, Component, Debug, Default)]
+pub struct InsetCamera;
+
+fn setup(mut commands: Commands) {
+    let (views, error) = config_plugin::load_config::<CameraViews>("camera_views", true);
+    commands.insert_resource(views);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Transform of the first link named `name`, by plant.
+fn link_transform<'a>(
+    links: impl Iterator<Item = (&'a Link, &'a GlobalTransform)>,
+    name: &str,
+) -> Option<GlobalTransform> {
+    links
+        .filter(|(link, _)| link.name == name)
+        .min_by(|(a, _), (b, _)| a.plant.cmp(&b.plant))
+        .map(|(_, transform)| *transform)
+}
+
+/// Orbits the camera smoothly to the views gone to.
+fn go_to_view(mut views: EventReader<GoToView>, mut orbits: Query<&mut PanOrbitCamera>) {
+    let Some(view) = views.read().last() else {
+        return;
+    };
+    let (yaw, pitch, radius) = orbit_angles(view.position, view.target);
+    for mut orbit in &mut orbits {
+        orbit.target_focus = view.target;
+        orbit.target_yaw = yaw;
+        orbit.target_pitch = pitch;
+        orbit.target_radius = radius;
+    }
+}
+
+/// Keeps the orbit camera on the followed point, and hands it back once the following stops.
+fn follow_link(
+    views: Res<Persistent<CameraViews>>,
+    playback: Option<Res<CameraPlayback>>,
+    links: Query<(&Link, &GlobalTransform)>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut was_chasing: Local<bool>,
+) {
+    let follow = &views.follow;
+    // The camera sequence takes the camera over while it plays.
+    let playing = playback.is_some_and(|playback| playback.playing.is_some());
+    let chasing = matches!(follow.mode, FollowMode::Chase | FollowMode::Mounted) && !playing;
+    if !chasing && std::mem::take(&mut *was_chasing) {
+        for mut orbit in &mut orbits {
+            orbit.enabled = true;
+        }
+    }
+    if follow.mode == FollowMode::Off || playing {
+        return;
+    }
+    let Some(transform) = link_transform(links.iter(), &follow.link) else {
+        return;
+    };
+    let (position, target) = follow.pose(&transform);
+    for mut orbit in &mut orbits {
+        if chasing {
+            // Both the current and the target orbit, for the camera not to lag behind.
+            let (yaw, pitch, radius) = orbit_angles(position, target);
+            orbit.enabled = false;
+            orbit.focus = target;
+            orbit.yaw = Some(yaw);
+            orbit.target_yaw = yaw;
+            orbit.pitch = Some(pitch);
+            orbit.target_pitch = pitch;
+            orbit.radius = Some(radius);
+            orbit.target_radius = radius;
+            orbit.force_update = true;
+        } else {
+            orbit.focus = target;
+        }
+        orbit.target_focus = target;
+    }
+    *was_chasing = chasing;
+}
+
+/// Spawns the camera of the picture in picture when enabled, and despawns it when disabled.
+fn spawn_inset(
+    mut commands: Commands,
+    views: Res<Persistent<CameraViews>>,
+    orbits: Query<&Camera, With<PanOrbitCamera>>,
+    insets: Query<Entity, With<InsetCamera>>,
+) {
+    if views.inset.enabled && insets.is_empty() {
+        // Over the views of the orbit camera, the stereo eye included.
+        let order = orbits.iter().map(|camera| camera.order).max().unwrap_or(0) + 2;
+        commands.spawn((
+            Camera3d::default(),
+            Camera { order, ..default() },
+            Transform::default(),
+            InsetCamera,
+        ));
+        debug!(target: subsystem::UI, "Picture in picture shown");
+    } else if !views.inset.enabled {
+        for inset in &insets {
+            commands.entity(inset).despawn_recursive();
+        }
+    }
+}
+
+/// Places the picture in picture in its corner, looking as configured.
+fn place_inset(
+    views: Res<Persistent<CameraViews>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    links: Query<(&Link, &GlobalTransform)>,
+    orbits: Query<&PanOrbitCamera>,
+    mut insets: Query<(&mut Camera, &mut Transform), With<InsetCamera>>,
+) {
+    let Ok((mut camera, mut transform)) = insets.get_single_mut() else {
+        return;
+    };
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = window.physical_size();
+    if size.x == 0 || size.y == 0 {
+        return;
+    }
+    camera.viewport = Some(inset_viewport(size, views.inset.corner, views.inset.size));
+    let focus = orbits.iter().next().map_or(Vec3::ZERO, |orbit| orbit.focus);
+    let pose = match &views.inset.view {
+        InsetView::Preset(preset) => Some((preset.position(focus, views.distance), focus)),
+        InsetView::Viewpoint(name) => views
+            .viewpoints
+            .iter()
+            .find(|viewpoint| viewpoint.name == *name)
+            .map(|viewpoint| (viewpoint.position, viewpoint.target)),
+        InsetView::Follow => link_transform(links.iter(), &views.follow.link).map(|link| {
+            let follow = Follow {
+                mode: FollowMode::Chase,
+                ..views.follow.clone()
+            };
+            follow.pose(&link)
+        }),
+    };
+    if let Some((position, target)) = pose {
+        // Looking straight down, the up of the view is along -Z, as for the orbit camera.
+        let up = if (position - target).cross(Vec3::Y).length_squared() < 1e-6 {
+            Vec3::NEG_Z
+        } else {
+            Vec3::Y
+        };
+        *transform = Transform::from_translation(position).looking_at(target, up);
+    }
+}
+
+/// Panel to go to the presets and the viewpoints, follow a link and set up the picture in
+/// picture.
+fn camera_views_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut views: ResMut<Persistent<CameraViews>>,
+    cameras: Query<(&Transform, &PanOrbitCamera)>,
+    links: Query<&Link>,
+) {
+    let mut edited = views.get().clone();
+    let current = cameras
+        .iter()
+        .next()
+        .map(|(transform, orbit)| (transform.translation, orbit.focus));
+
+    egui::Window::new("Cameras")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for preset in CameraPreset::ALL {
+                    if ui.button(preset.name()).clicked() {
+                        let target = current.map_or(Vec3::ZERO, |(_, target)| target);
+                        commands.send_event(GoToView {
+                            position: preset.position(target, edited.distance),
+                            target,
+                        });
+                    }
+                }
+            });
+            ui.add(egui::Slider::new(&mut edited.distance, 1.0..=50.0).text("Distance (m)"));
+
+            ui.separator();
+            ui.label("Viewpoints");
+            let mut removed = None;
+            for (index, viewpoint) in edited.viewpoints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut viewpoint.name);
+                    if ui.button("Go").clicked() {
+                        commands.send_event(GoToView {
+                            position: viewpoint.position,
+                            target: viewpoint.target,
+                        });
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.viewpoints.remove(index);
+            }
+            if ui.button("Save the current view").clicked() {
+                if let Some((position, target)) = current {
+                    edited.viewpoints.push(Viewpoint {
+                        name: format!("Viewpoint {}", edited.viewpoints.len() + 1),
+                        position,
+                        target,
+                    });
+                }
+            }
+
+            ui.separator();
+            let mut names: Vec<&str> = links.iter().map(|link| link.name.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            egui::ComboBox::from_label("Follow")
+                .selected_text(edited.follow.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in FollowMode::ALL {
+                        ui.selectable_value(&mut edited.follow.mode, mode, mode.name());
+                    }
+                });
+            egui::ComboBox::from_label("Link")
+                .selected_text(edited.follow.link.clone())
+                .show_ui(ui, |ui| {
+                    for name in names {
+                        ui.selectable_value(&mut edited.follow.link, name.to_string(), name);
+                    }
+                });
+            let vector = |ui: &mut egui::Ui, label: &str, value: &mut Vec3| {
+                ui.horizontal(|ui| {
+                    ui.label(label);
+                    for component in [&mut value.x, &mut value.y, &mut value.z] {
+                        ui.add(egui::DragValue::new(component).speed(0.05));
+                    }
+                });
+            };
+            vector(ui, "Point (link frame)", &mut edited.follow.point);
+            vector(ui, "Camera offset", &mut edited.follow.offset);
+
+            ui.separator();
+            let inset = &mut edited.inset;
+            ui.checkbox(&mut inset.enabled, "Picture in picture");
+            let view_name = |view: &InsetView| match view {
+                InsetView::Preset(preset) => preset.name().to_string(),
+                InsetView::Viewpoint(name) => name.clone(),
+                InsetView::Follow => "Followed link".to_string(),
+            };
+            let choices: Vec<InsetView> = CameraPreset::ALL
+                .into_iter()
+                .map(InsetView::Preset)
+                .chain(
+                    edited
+                        .viewpoints
+                        .iter()
+                        .map(|viewpoint| InsetView::Viewpoint(viewpoint.name.clone())),
+                )
+                .chain([InsetView::Follow])
+                .collect();
+            egui::ComboBox::from_label("Shows")
+                .selected_text(view_name(&inset.view))
+                .show_ui(ui, |ui| {
+                    for choice in choices {
+                        let name = view_name(&choice);
+                        ui.selectable_value(&mut inset.view, choice, name);
+                    }
+                });
+            egui::ComboBox::from_label("Corner")
+                .selected_text(inset.corner.name())
+                .show_ui(ui, |ui| {
+                    for corner in Corner::ALL {
+                        ui.selectable_value(&mut inset.corner, corner, corner.name());
+                    }
+                });
+            ui.add(egui::Slider::new(&mut inset.size, 0.1..=0.6).text("Height (of the window)"));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = views.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("camera_views", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = views.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("camera_views", error)));
+                    }
+                    edited = views.get().clone();
+                }
+            });
+        });
+
+    if edited != *views.get() {
+        *views.get_mut() = edited;
+    }
+}
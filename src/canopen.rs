@@ -0,0 +1,510 @@
+//! Emulation of a CANopen servo drive following the CiA 402 profile, over SocketCAN.
+//!
+//! The motor of the plant is exposed as a drive node, so real motion stacks can command it:
+//! - NMT commands and the boot-up and heartbeat messages;
+//! - expedited SDO access to the object dictionary below;
+//! - RPDO1 (`0x200 + node`): controlword and target velocity, RPDO2 (`0x300 + node`):
+//!   controlword and target position;
+//! - TPDO1 (`0x180 + node`): statusword and velocity actual value, TPDO2 (`0x280 + node`):
+//!   statusword and position actual value, sent on every SYNC.
+//!
+//! | Index    | Type  | Access | Object                                |
+//! |----------|-------|--------|---------------------------------------|
+//! | `0x1000` | `u32` | ro     | Device type                           |
+//! | `0x1017` | `u16` | rw     | Producer heartbeat time, in ms        |
+//! | `0x6040` | `u16` | rw     | Controlword                           |
+//! | `0x6041` | `u16` | ro     | Statusword                            |
+//! | `0x6060` | `i8`  | rw     | Modes of operation                    |
+//! | `0x6061` | `i8`  | ro     | Modes of operation display            |
+//! | `0x6064` | `i32` | ro     | Position actual value, in mrad        |
+//! | `0x606C` | `i32` | ro     | Velocity actual value, in mrad/s      |
+//! | `0x6071` | `i16` | rw     | Target torque, in ‰ of the rated one  |
+//! | `0x607A` | `i32` | rw     | Target position, in mrad              |
+//! | `0x60FF` | `i32` | rw     | Target velocity, in mrad/s            |
+//!
+//! The plant is commanded in velocity, so only the profile velocity and cyclic synchronous
+//! velocity modes can be selected; the position and torque targets are stored but not used.
+//! The position actual value is the angle of the pendulum.
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use socketcan::{CanFrame, CanSocket, EmbeddedFrame, Frame, Socket, StandardId};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+/// Node ID used when none is given.
+pub const DEFAULT_NODE_ID: u8 = 1;
+/// Device type of a CiA 402 servo drive.
+pub const DEVICE_TYPE: u32 = 0x0002_0192;
+
+/// Indexes of the object dictionary.
+pub mod index {
+    pub const DEVICE_TYPE: u16 = 0x1000;
+    pub const HEARTBEAT_TIME: u16 = 0x1017;
+    pub const CONTROLWORD: u16 = 0x6040;
+    pub const STATUSWORD: u16 = 0x6041;
+    pub const MODES_OF_OPERATION: u16 = 0x6060;
+    pub const MODES_OF_OPERATION_DISPLAY: u16 = 0x6061;
+    pub const POSITION_ACTUAL_VALUE: u16 = 0x6064;
+    pub const VELOCITY_ACTUAL_VALUE: u16 = 0x606C;
+    pub const TARGET_TORQUE: u16 = 0x6071;
+    pub const TARGET_POSITION: u16 = 0x607A;
+    pub const TARGET_VELOCITY: u16 = 0x60FF;
+}
+
+/// SDO abort codes.
+pub mod abort {
+    pub const COMMAND_SPECIFIER_INVALID: u32 = 0x0504_0001;
+    pub const READ_ONLY: u32 = 0x0601_0002;
+    pub const OBJECT_DOES_NOT_EXIST: u32 = 0x0602_0000;
+    pub const LENGTH_MISMATCH: u32 = 0x0607_0010;
+    pub const SUBINDEX_DOES_NOT_EXIST: u32 = 0x0609_0011;
+    pub const VALUE_RANGE_EXCEEDED: u32 = 0x0609_0030;
+}
+
+/// Modes of operation the plant supports.
+pub mod mode {
+    pub const PROFILE_VELOCITY: i8 = 3;
+    pub const CYCLIC_SYNC_VELOCITY: i8 = 9;
+}
+
+const NMT: u16 = 0x000;
+const SYNC: u16 = 0x080;
+const TPDO1: u16 = 0x180;
+const RPDO1: u16 = 0x200;
+const TPDO2: u16 = 0x280;
+const RPDO2: u16 = 0x300;
+const SDO_RESPONSE: u16 = 0x580;
+const SDO_REQUEST: u16 = 0x600;
+const HEARTBEAT: u16 = 0x700;
+
+/// A CAN message with a standard identifier.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Message {
+    pub cob_id: u16,
+    pub data: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(cob_id: u16, data: &[u8]) -> Self {
+        Self {
+            cob_id,
+            data: data.to_vec(),
+        }
+    }
+}
+
+/// NMT state of the node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NmtState {
+    PreOperational,
+    Operational,
+    Stopped,
+}
+
+impl NmtState {
+    /// State as reported in the heartbeat.
+    fn code(self) -> u8 {
+        match self {
+            NmtState::PreOperational => 0x7F,
+            NmtState::Operational => 0x05,
+            NmtState::Stopped => 0x04,
+        }
+    }
+}
+
+/// State of the CiA 402 power state machine.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DriveState {
+    SwitchOnDisabled,
+    ReadyToSwitchOn,
+    SwitchedOn,
+    OperationEnabled,
+    QuickStopActive,
+    Fault,
+}
+
+/// The object dictionary and state machines of the emulated drive.
+#[derive(Clone, Debug)]
+pub struct Drive {
+    node_id: u8,
+    nmt: NmtState,
+    state: DriveState,
+    controlword: u16,
+    heartbeat_time: u16,
+    mode: i8,
+    target_torque: i16,
+    target_position: i32,
+    target_velocity: i32,
+    position_actual: i32,
+    velocity_actual: i32,
+}
+
+impl Drive {
+    pub fn new(node_id: u8) -> Self {
+        Self {
+            node_id,
+            nmt: NmtState::PreOperational,
+            state: DriveState::SwitchOnDisabled,
+            controlword: 0,
+            heartbeat_time: 1000,
+            mode: mode::PROFILE_VELOCITY,
+            target_torque: 0,
+            target_position: 0,
+            target_velocity: 0,
+            position_actual: 0,
+            velocity_actual: 0,
+        }
+    }
+
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    pub fn nmt_state(&self) -> NmtState {
+        self.nmt
+    }
+
+    pub fn state(&self) -> DriveState {
+        self.state
+    }
+
+    /// Time between two heartbeats, if they are enabled.
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        (self.heartbeat_time > 0).then(|| Duration::from_millis(self.heartbeat_time.into()))
+    }
+
+    pub fn statusword(&self) -> u16 {
+        const REMOTE: u16 = 1 << 9;
+        let state = match self.state {
+            DriveState::SwitchOnDisabled => 0x0040,
+            DriveState::ReadyToSwitchOn => 0x0031,
+            DriveState::SwitchedOn => 0x0033,
+            DriveState::OperationEnabled => 0x0037,
+            DriveState::QuickStopActive => 0x0017,
+            DriveState::Fault => 0x0008,
+        };
+        state | REMOTE
+    }
+
+    /// Velocity the motor must follow, in rad/s.
+    pub fn velocity_demand(&self) -> f32 {
+        if self.state == DriveState::OperationEnabled {
+            self.target_velocity as f32 / 1000.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Updates the actual values, in rad and rad/s.
+    pub fn set_actual_values(&mut self, position: f32, velocity: f32) {
+        self.position_actual = (position * 1000.0).round() as i32;
+        self.velocity_actual = (velocity * 1000.0).round() as i32;
+    }
+
+    /// Message announcing the node after its initialization.
+    pub fn boot_up(&self) -> Message {
+        Message::new(HEARTBEAT + u16::from(self.node_id), &[0])
+    }
+
+    pub fn heartbeat(&self) -> Message {
+        Message::new(HEARTBEAT + u16::from(self.node_id), &[self.nmt.code()])
+    }
+
+    /// Handles a message of the bus, returning the messages to send in response.
+    pub fn handle(&mut self, message: &Message) -> Vec<Message> {
+        let node = u16::from(self.node_id);
+        let data = &message.data;
+        match message.cob_id {
+            NMT if data.len() == 2 && (data[1] == 0 || data[1] == self.node_id) => {
+                self.handle_nmt(data[0])
+            }
+            SYNC if self.nmt == NmtState::Operational => {
+                let statusword = self.statusword().to_le_bytes();
+                let mut tpdo1 = statusword.to_vec();
+                tpdo1.extend_from_slice(&self.velocity_actual.to_le_bytes());
+                let mut tpdo2 = statusword.to_vec();
+                tpdo2.extend_from_slice(&self.position_actual.to_le_bytes());
+                vec![
+                    Message::new(TPDO1 + node, &tpdo1),
+                    Message::new(TPDO2 + node, &tpdo2),
+                ]
+            }
+            id if id == RPDO1 + node && self.nmt == NmtState::Operational && data.len() == 6 => {
+                self.target_velocity = i32::from_le_bytes([data[2], data[3], data[4], data[5]]);
+                self.write_controlword(u16::from_le_bytes([data[0], data[1]]));
+                Vec::new()
+            }
+            id if id == RPDO2 + node && self.nmt == NmtState::Operational && data.len() == 6 => {
+                self.target_position = i32::from_le_bytes([data[2], data[3], data[4], data[5]]);
+                self.write_controlword(u16::from_le_bytes([data[0], data[1]]));
+                Vec::new()
+            }
+            id if id == SDO_REQUEST + node && self.nmt != NmtState::Stopped && data.len() == 8 => {
+                vec![Message::new(SDO_RESPONSE + node, &self.handle_sdo(data))]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn handle_nmt(&mut self, command: u8) -> Vec<Message> {
+        match command {
+            0x01 => self.nmt = NmtState::Operational,
+            0x02 => self.nmt = NmtState::Stopped,
+            0x80 => self.nmt = NmtState::PreOperational,
+            // Reset node and reset communication.
+            0x81 | 0x82 => {
+                *self = Drive::new(self.node_id);
+                return vec![self.boot_up()];
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn handle_sdo(&mut self, request: &[u8]) -> [u8; 8] {
+        let index = u16::from_le_bytes([request[1], request[2]]);
+        let subindex = request[3];
+        let result = match request[0] >> 5 {
+            // Initiate upload.
+            2 => self.read(index, subindex).map(|(value, size)| {
+                let mut response = [0; 8];
+                response[0] = 0x43 | ((4 - size as u8) << 2);
+                response[4..8].copy_from_slice(&value.to_le_bytes());
+                response
+            }),
+            // Initiate download, only expedited.
+            1 if request[0] & 0x02 != 0 => {
+                let size = if request[0] & 0x01 != 0 {
+                    4 - usize::from((request[0] >> 2) & 0x03)
+                } else {
+                    4
+                };
+                let value = u32::from_le_bytes([request[4], request[5], request[6], request[7]]);
+                self.write(index, subindex, value, size).map(|()| {
+                    let mut response = [0; 8];
+                    response[0] = 0x60;
+                    response
+                })
+            }
+            _ => Err(abort::COMMAND_SPECIFIER_INVALID),
+        };
+        let mut response = result.unwrap_or_else(|code| {
+            let mut response = [0; 8];
+            response[0] = 0x80;
+            response[4..8].copy_from_slice(&code.to_le_bytes());
+            response
+        });
+        response[1..3].copy_from_slice(&index.to_le_bytes());
+        response[3] = subindex;
+        response
+    }
+
+    /// The value of an object and its size in bytes.
+    fn read(&self, index: u16, subindex: u8) -> Result<(u32, usize), u32> {
+        let value = match index {
+            index::DEVICE_TYPE => (DEVICE_TYPE, 4),
+            index::HEARTBEAT_TIME => (self.heartbeat_time.into(), 2),
+            index::CONTROLWORD => (self.controlword.into(), 2),
+            index::STATUSWORD => (self.statusword().into(), 2),
+            index::MODES_OF_OPERATION | index::MODES_OF_OPERATION_DISPLAY => {
+                (u32::from(self.mode as u8), 1)
+            }
+            index::POSITION_ACTUAL_VALUE => (self.position_actual as u32, 4),
+            index::VELOCITY_ACTUAL_VALUE => (self.velocity_actual as u32, 4),
+            index::TARGET_TORQUE => (u32::from(self.target_torque as u16), 2),
+            index::TARGET_POSITION => (self.target_position as u32, 4),
+            index::TARGET_VELOCITY => (self.target_velocity as u32, 4),
+            _ => return Err(abort::OBJECT_DOES_NOT_EXIST),
+        };
+        if subindex != 0 {
+            return Err(abort::SUBINDEX_DOES_NOT_EXIST);
+        }
+        Ok(value)
+    }
+
+    fn write(&mut self, index: u16, subindex: u8, value: u32, size: usize) -> Result<(), u32> {
+        let (_, expected) = self.read(index, subindex)?;
+        if matches!(
+            index,
+            index::DEVICE_TYPE
+                | index::STATUSWORD
+                | index::MODES_OF_OPERATION_DISPLAY
+                | index::POSITION_ACTUAL_VALUE
+                | index::VELOCITY_ACTUAL_VALUE
+        ) {
+            return Err(abort::READ_ONLY);
+        }
+        if size != expected {
+            return Err(abort::LENGTH_MISMATCH);
+        }
+        match index {
+            index::HEARTBEAT_TIME => self.heartbeat_time = value as u16,
+            index::CONTROLWORD => self.write_controlword(value as u16),
+            index::MODES_OF_OPERATION => {
+                let requested = value as u8 as i8;
+                if ![mode::PROFILE_VELOCITY, mode::CYCLIC_SYNC_VELOCITY].contains(&requested) {
+                    return Err(abort::VALUE_RANGE_EXCEEDED);
+                }
+                self.mode = requested;
+            }
+            index::TARGET_TORQUE => self.target_torque = value as u16 as i16,
+            index::TARGET_POSITION => self.target_position = value as i32,
+            index::TARGET_VELOCITY => self.target_velocity = value as i32,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Runs the transitions of the power state machine commanded by the controlword.
+    fn write_controlword(&mut self, controlword: u16) {
+        const FAULT_RESET: u16 = 1 << 7;
+        let fault_reset = controlword & FAULT_RESET != 0 && self.controlword & FAULT_RESET == 0;
+        self.controlword = controlword;
+        use DriveState::*;
+        self.state = match (self.state, controlword & 0x0F) {
+            (Fault, _) if fault_reset => SwitchOnDisabled,
+            (Fault, _) => Fault,
+            // Disable voltage.
+            (_, command) if command & 0x02 == 0 => SwitchOnDisabled,
+            // Quick stop.
+            (OperationEnabled, command) if command & 0x04 == 0 => QuickStopActive,
+            (ReadyToSwitchOn | SwitchedOn, command) if command & 0x04 == 0 => SwitchOnDisabled,
+            // Shutdown.
+            (SwitchOnDisabled | SwitchedOn | OperationEnabled, 0x06 | 0x0E) => ReadyToSwitchOn,
+            // Switch on, or disable operation.
+            (ReadyToSwitchOn | OperationEnabled, 0x07) => SwitchedOn,
+            // Enable operation.
+            (SwitchedOn | QuickStopActive, 0x0F) => OperationEnabled,
+            (state, _) => state,
+        };
+    }
+}
+
+/// Exposes the motor of the plant as a CiA 402 drive on a SocketCAN interface.
+pub struct CanOpenPlugin {
+    /// Name of the interface, e.g. `can0` or `vcan0`.
+    pub interface: String,
+    pub node_id: u8,
+}
+
+impl Plugin for CanOpenPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match CanSocket::open(&self.interface).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.interface.clone(), error)));
+                return;
+            }
+        };
+        info!(
+            target: subsystem::IO,
+            "CANopen node {} on {}", self.node_id, self.interface
+        );
+        let node = CanOpenNode {
+            socket,
+            drive: Drive::new(self.node_id),
+            last_heartbeat: Instant::now(),
+            applied: None,
+        };
+        node.send(&node.drive.boot_up());
+        app.init_resource::<Setpoints>()
+            .insert_resource(node)
+            .add_systems(PreUpdate, receive_messages)
+            .add_systems(PostUpdate, update_drive.after(SimClockSet::Advance));
+    }
+}
+
+/// The drive and its connection to the bus.
+#[derive(Resource)]
+pub struct CanOpenNode {
+    socket: CanSocket,
+    pub drive: Drive,
+    last_heartbeat: Instant,
+    /// Velocity setpoint last written from the drive, in rad/s.
+    applied: Option<f32>,
+}
+
+impl CanOpenNode {
+    fn send(&self, message: &Message) {
+        let Some(frame) =
+            StandardId::new(message.cob_id).and_then(|id| CanFrame::new(id, &message.data))
+        else {
+            return;
+        };
+        if let Err(error) = self.socket.write_frame(&frame) {
+            warn!(
+                target: subsystem::IO,
+                "Failed to send the CAN message {:#05X}: {error}", message.cob_id
+            );
+        }
+    }
+}
+
+/// Handles the messages of the bus and applies the velocity demand to the setpoints.
+fn receive_messages(mut node: ResMut<CanOpenNode>, mut setpoints: ResMut<Setpoints>) {
+    loop {
+        let frame = match node.socket.read_frame() {
+            Ok(CanFrame::Data(frame)) if !frame.is_extended() => frame,
+            Ok(_) => continue,
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::WouldBlock {
+                    debug!(target: subsystem::IO, "CAN receive error: {error}");
+                }
+                break;
+            }
+        };
+        let message = Message::new(frame.raw_id() as u16, frame.data());
+        let state = node.drive.state();
+        for response in node.drive.handle(&message) {
+            node.send(&response);
+        }
+        if node.drive.state() != state {
+            debug!(
+                target: subsystem::IO,
+                "CiA 402 state {:?} -> {:?}",
+                state,
+                node.drive.state()
+            );
+        }
+    }
+
+    let velocity = node.drive.velocity_demand();
+    // Other inputs (e.g. the keyboard) keep working while the drive doesn't change its demand.
+    if node.applied != Some(velocity) {
+        setpoints.set(MOTOR_VELOCITY, velocity);
+        node.applied = Some(velocity);
+    }
+}
+
+/// Updates the actual values after the physics steps and produces the heartbeat.
+fn update_drive(
+    mut node: ResMut<CanOpenNode>,
+    clock: Res<SimClock>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    if clock.delta() > Duration::ZERO {
+        let angle = telemetry
+            .and_then(|telemetry| telemetry.latest(PENDULUM_ANGLE))
+            .unwrap_or_default();
+        let velocity = node.applied.unwrap_or_default();
+        node.drive.set_actual_values(angle, velocity);
+    }
+    let Some(interval) = node.drive.heartbeat_interval() else {
+        return;
+    };
+    if node.last_heartbeat.elapsed() >= interval {
+        node.last_heartbeat = Instant::now();
+        node.send(&node.drive.heartbeat());
+    }
+}
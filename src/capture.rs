@@ -0,0 +1,588 @@
+//! Video and screenshot capture: the scene is rendered by a camera of its own into an offscreen
+//! image, at a fixed resolution whatever the window, and its frames are read back and written
+//! as a PNG sequence or, in builds with the `video` feature, encoded into an MP4 file.
+//!
+//! Frames are taken at a fixed rate of the simulated time rather than of the wall clock: the
+//! `n`th frame shows the scene at `n / framerate` seconds, and a frame is repeated when a step
+//! of the physics spans several frame times, so a capture plays back at the pace of the
+//! simulation however fast it ran. The capture camera follows the view of the window, or looks
+//! from the configured eye at the configured target without one, as in headless batch runs
+//! with `--capture`.
+//!
+//! The *Capture* panel starts and stops captures into `<data dir>/captures`, and takes single
+//! screenshots; the settings are kept in `capture.json`.
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+    },
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+};
+
+/// Updates spent waiting for the frames in flight once a capture stops.
+const FLUSH_UPDATES: usize = 10;
+
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                follow_view,
+                request_frames.run_if(resource_exists::<Capture>),
+                write_frames.run_if(resource_exists::<Capture>),
+                capture_panel.run_if(has_ui),
+            )
+                .chain()
+                .run_if(resource_exists::<Persistent<CaptureSettings>>),
+        );
+    }
+}
+
+/// Format the frames are written in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureFormat {
+    /// A numbered PNG file per frame.
+    #[default]
+    Png,
+    /// An H.264 video in an MP4 file, in builds with the `video` feature.
+    Mp4,
+}
+
+impl CaptureFormat {
+    pub const ALL: [Self; 2] = [Self::Png, Self::Mp4];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG sequence",
+            Self::Mp4 => "MP4 video",
+        }
+    }
+}
+
+/// Represents the configuration of the captures.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    pub format: CaptureFormat,
+    /// Frames per second of simulated time.
+    pub framerate: f32,
+    /// Size of the frames, in pixels, rounded down to even numbers for the video.
+    pub width: u32,
+    pub height: u32,
+    /// Position of the camera and the point it looks at, without a view to follow.
+    pub eye: Vec3,
+    pub target: Vec3,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            format: CaptureFormat::Png,
+            framerate: 30.0,
+            width: 1280,
+            height: 720,
+            eye: Vec3::new(10.0, 10.0, 10.0),
+            target: Vec3::new(0.0, 3.0, 0.0),
+        }
+    }
+}
+
+impl CaptureSettings {
+    /// Size of the frames, even.
+    pub fn size(&self) -> (u32, u32) {
+        ((self.width & !1).max(2), (self.height & !1).max(2))
+    }
+}
+
+/// Number of frames due by `elapsed` seconds of simulated time at `framerate`, the first one at
+/// the start.
+pub fn frames_due(elapsed: f32, framerate: f32) -> usize {
+    if framerate <= 0.0 || elapsed < 0.0 {
+        return 0;
+    }
+    // A margin for the rounding of the steps landing on a frame time.
+    (elapsed * framerate + 1e-3).floor() as usize + 1
+}
+
+/// Name of the file of the `index`th frame of a PNG sequence.
+pub fn frame_name(index: usize) -> String {
+    format!("frame_{index:06}.png")
+}
+
+/// The camera rendering the captured frames.
+#[derive(Clone, Copy, Component, Debug, Default)]
+pub struct CaptureCamera;
+
+/// A capture in progress.
+#[derive(Resource)]
+pub struct Capture {
+    pub directory: PathBuf,
+    pub format: CaptureFormat,
+    pub framerate: f32,
+    /// Frames requested from the renderer, and written.
+    pub requested: usize,
+    pub written: usize,
+    /// Whether frames are still requested, until the capture stops.
+    pub recording: bool,
+    target: Handle<Image>,
+    camera: Entity,
+    /// Frames read back and not written yet, by index of their first copy, with their copies.
+    pending: BTreeMap<usize, (Image, usize)>,
+}
+
+impl Capture {
+    /// Whether the capture stopped and every frame requested was written.
+    pub fn done(&self) -> bool {
+        !self.recording && self.written >= self.requested
+    }
+}
+
+/// Spawns the camera rendering into an offscreen image, from the view of the window.
+fn spawn_camera(world: &mut World, settings: &CaptureSettings) -> (Entity, Handle<Image>) {
+    let (width, height) = settings.size();
+    let transform = view(
+        world
+            .query_filtered::<(&Camera, &GlobalTransform), Without<CaptureCamera>>()
+            .iter(world),
+        settings,
+    );
+    let mut image = Image::new_fill(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::RENDER_ATTACHMENT;
+    let target = world.resource_mut::<Assets<Image>>().add(image);
+    let camera = world
+        .spawn((
+            Camera3d::default(),
+            Camera {
+                target: RenderTarget::Image(target.clone()),
+                // Before the views of the window.
+                order: -1,
+                ..default()
+            },
+            transform,
+            CaptureCamera,
+        ))
+        .id();
+    (camera, target)
+}
+
+/// Starts capturing frames into `directory` as configured by `settings`.
+pub fn start(world: &mut World, settings: &CaptureSettings, directory: PathBuf) -> Result<()> {
+    if world.contains_resource::<Capture>() {
+        return Err(Error::Config {
+            name: "capture".to_string(),
+            message: "a capture is already in progress".to_string(),
+        });
+    }
+    fs::create_dir_all(&directory).map_err(|error| Error::io(&directory, error))?;
+    match settings.format {
+        CaptureFormat::Png => {}
+        #[cfg(feature = "video")]
+        CaptureFormat::Mp4 => {
+            let (width, height) = settings.size();
+            let path = directory.join("capture.mp4");
+            let writer = video::VideoWriter::new(path.clone(), width, height, settings.framerate)
+                .map_err(|error| Error::io(&path, error))?;
+            world.insert_non_send_resource(writer);
+        }
+        #[cfg(not(feature = "video"))]
+        CaptureFormat::Mp4 => {
+            return Err(Error::Config {
+                name: "capture".to_string(),
+                message: "MP4 videos need a build with the `video` feature".to_string(),
+            });
+        }
+    }
+    let (camera, target) = spawn_camera(world, settings);
+    info!(target: subsystem::IO, "Capturing to {}", directory.display());
+    world.insert_resource(Capture {
+        directory,
+        format: settings.format,
+        framerate: settings.framerate,
+        requested: 0,
+        written: 0,
+        recording: true,
+        target,
+        camera,
+        pending: BTreeMap::new(),
+    });
+    Ok(())
+}
+
+/// Stops requesting frames; the capture ends once those in flight are written.
+pub fn stop(world: &mut World) {
+    if let Some(mut capture) = world.get_resource_mut::<Capture>() {
+        capture.recording = false;
+    }
+}
+
+/// Stops the capture of `app`, updating it until the frames in flight are written.
+pub fn finish(app: &mut App) {
+    stop(app.world_mut());
+    for _ in 0..FLUSH_UPDATES {
+        if !app.world().contains_resource::<Capture>() {
+            return;
+        }
+        app.update();
+    }
+    if app.world().contains_resource::<Capture>() {
+        warn!(target: subsystem::IO, "The last frames of the capture were lost");
+        end(app.world_mut());
+    }
+}
+
+/// Takes a single frame of the view into `path`, as PNG.
+pub fn screenshot(world: &mut World, settings: &CaptureSettings, path: PathBuf) {
+    let (camera, target) = spawn_camera(world, settings);
+    world.spawn(Screenshot::image(target)).observe(
+        move |trigger: Trigger<ScreenshotCaptured>, mut commands: Commands| {
+            commands.entity(camera).despawn();
+            match write_png(&trigger.event().0, &path) {
+                Ok(()) => info!(target: subsystem::IO, "Screenshot saved to {}", path.display()),
+                Err(error) => {
+                    commands.send_event(ErrorEvent::from(Error::io(&path, error)));
+                }
+            }
+        },
+    );
+}
+
+fn write_png(image: &Image, path: &Path) -> io::Result<()> {
+    let image = image.clone().try_into_dynamic().map_err(io::Error::other)?;
+    image.to_rgba8().save(path).map_err(io::Error::other)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<CaptureSettings>("capture", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Transform of the first active view of the window, or of the configured one without views.
+fn view<'a>(
+    views: impl Iterator<Item = (&'a Camera, &'a GlobalTransform)>,
+    settings: &CaptureSettings,
+) -> Transform {
+    views
+        .filter(|(camera, _)| camera.is_active)
+        .min_by_key(|(camera, _)| camera.order)
+        .map_or_else(
+            || Transform::from_translation(settings.eye).looking_at(settings.target, Vec3::Y),
+            |(_, transform)| transform.compute_transform(),
+        )
+}
+
+/// Points the capture cameras as the view of the window.
+fn follow_view(
+    settings: Res<Persistent<CaptureSettings>>,
+    views: Query<(&Camera, &GlobalTransform), Without<CaptureCamera>>,
+    mut cameras: Query<&mut Transform, With<CaptureCamera>>,
+) {
+    let view = view(views.iter(), &settings);
+    for mut transform in &mut cameras {
+        *transform = view;
+    }
+}
+
+/// Requests the frames due by the simulated time, a single read back for the frames of a step.
+fn request_frames(mut commands: Commands, clock: Res<SimClock>, mut capture: ResMut<Capture>) {
+    if !capture.recording {
+        return;
+    }
+    let due = frames_due(clock.elapsed_secs(), capture.framerate);
+    let copies = due.saturating_sub(capture.requested);
+    if copies == 0 {
+        return;
+    }
+    let index = capture.requested;
+    capture.requested = due;
+    commands
+        .spawn(Screenshot::image(capture.target.clone()))
+        .observe(
+            move |trigger: Trigger<ScreenshotCaptured>, capture: Option<ResMut<Capture>>| {
+                if let Some(mut capture) = capture {
+                    capture
+                        .pending
+                        .insert(index, (trigger.event().0.clone(), copies));
+                }
+            },
+        );
+}
+
+/// Writes the frames read back, in order, and ends the capture once it's done.
+fn write_frames(world: &mut World) {
+    let Some(mut capture) = world.remove_resource::<Capture>() else {
+        return;
+    };
+    let mut failed = None;
+    while let Some((image, copies)) = capture.pending.remove(&capture.written) {
+        let written = match capture.format {
+            CaptureFormat::Png => (0..copies).try_for_each(|copy| {
+                let path = capture.directory.join(frame_name(capture.written + copy));
+                write_png(&image, &path).map_err(|error| Error::io(&path, error))
+            }),
+            #[cfg(feature = "video")]
+            CaptureFormat::Mp4 => world
+                .get_non_send_resource_mut::<video::VideoWriter>()
+                .map_or(Ok(()), |mut writer| {
+                    let path = writer.path().to_path_buf();
+                    (0..copies)
+                        .try_for_each(|_| writer.write(&image))
+                        .map_err(|error| Error::io(&path, error))
+                }),
+            #[cfg(not(feature = "video"))]
+            CaptureFormat::Mp4 => Ok(()),
+        };
+        if let Err(error) = written {
+            failed = Some(error);
+            break;
+        }
+        capture.written += copies;
+    }
+    let done = capture.done();
+    world.insert_resource(capture);
+    if let Some(error) = failed {
+        warn!(target: subsystem::IO, "The capture stopped");
+        world.send_event(ErrorEvent::from(error));
+        end(world);
+    } else if done {
+        end(world);
+    }
+}
+
+/// Ends the capture in progress, finishing its video.
+fn end(world: &mut World) {
+    let Some(capture) = world.remove_resource::<Capture>() else {
+        return;
+    };
+    if let Ok(camera) = world.get_entity_mut(capture.camera) {
+        camera.despawn();
+    }
+    #[cfg(feature = "video")]
+    if let Some(writer) = world.remove_non_send_resource::<video::VideoWriter>() {
+        let path = writer.path().to_path_buf();
+        if let Err(error) = writer.finish() {
+            world.send_event(ErrorEvent::from(Error::io(path, error)));
+            return;
+        }
+    }
+    info!(
+        target: subsystem::IO,
+        "Captured {} frames to {}",
+        capture.written,
+        capture.directory.display()
+    );
+}
+
+/// Panel to start and stop the captures, and to take screenshots.
+fn capture_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<CaptureSettings>>,
+    capture: Option<Res<Capture>>,
+) {
+    let mut edited = settings.get().clone();
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    };
+    egui::Window::new("Capture")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match &capture {
+                Some(capture) => {
+                    ui.label(format!(
+                        "Capturing to {}: {} frames written",
+                        capture.directory.display(),
+                        capture.written
+                    ));
+                    if capture.recording && ui.button("Stop").clicked() {
+                        commands.queue(stop);
+                    }
+                }
+                None => {
+                    if ui.button("Capture").clicked() {
+                        let directory = data_dir()
+                            .join("captures")
+                            .join(format!("capture-{}", now()));
+                        let settings = edited.clone();
+                        commands.queue(move |world: &mut World| {
+                            if let Err(error) = start(world, &settings, directory) {
+                                world.send_event(ErrorEvent::from(error));
+                            }
+                        });
+                    }
+                }
+            }
+            if ui.button("Screenshot").clicked() {
+                let directory = data_dir().join("captures");
+                let path = directory.join(format!("screenshot-{}.png", now()));
+                let settings = edited.clone();
+                commands.queue(
+                    move |world: &mut World| match fs::create_dir_all(&directory) {
+                        Ok(()) => screenshot(world, &settings, path),
+                        Err(error) => {
+                            world.send_event(ErrorEvent::from(Error::io(&directory, error)));
+                        }
+                    },
+                );
+            }
+
+            ui.separator();
+            ui.add_enabled_ui(capture.is_none(), |ui| {
+                egui::ComboBox::from_label("Format")
+                    .selected_text(edited.format.name())
+                    .show_ui(ui, |ui| {
+                        for format in CaptureFormat::ALL {
+                            ui.selectable_value(&mut edited.format, format, format.name());
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut edited.framerate, 1.0..=120.0)
+                        .text("Frames per simulated second"),
+                );
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut edited.width)
+                            .range(2..=7680)
+                            .prefix("Width: ")
+                            .suffix(" px"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut edited.height)
+                            .range(2..=4320)
+                            .prefix("Height: ")
+                            .suffix(" px"),
+                    );
+                });
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("capture", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("capture", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+#[cfg(feature = "video")]
+mod video {
+    use std::{
+        fs::File,
+        io,
+        path::{Path, PathBuf},
+    };
+
+    use bevy::prelude::*;
+    use minimp4::Mp4Muxer;
+    use openh264::{
+        encoder::{Encoder, EncoderConfig},
+        formats::{RgbaSliceU8, YUVBuffer},
+        OpenH264API,
+    };
+
+    /// Encodes the frames into an H.264 stream, muxed into an MP4 file once finished.
+    pub struct VideoWriter {
+        encoder: Encoder,
+        stream: Vec<u8>,
+        path: PathBuf,
+        size: (usize, usize),
+        framerate: f32,
+    }
+
+    impl VideoWriter {
+        pub fn new(path: PathBuf, width: u32, height: u32, framerate: f32) -> io::Result<Self> {
+            let config = EncoderConfig::new().max_frame_rate(framerate);
+            let encoder = Encoder::with_api_config(OpenH264API::from_source(), config)
+                .map_err(|error| io::Error::other(error.to_string()))?;
+            Ok(Self {
+                encoder,
+                stream: Vec::new(),
+                path,
+                size: (width as usize, height as usize),
+                framerate,
+            })
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Encodes a frame, cropped to the size of the video.
+        pub fn write(&mut self, image: &Image) -> io::Result<()> {
+            let frame = image
+                .clone()
+                .try_into_dynamic()
+                .map_err(io::Error::other)?
+                .crop_imm(0, 0, self.size.0 as u32, self.size.1 as u32)
+                .to_rgba8();
+            let yuv = YUVBuffer::from_rgb_source(RgbaSliceU8::new(frame.as_raw(), self.size));
+            let bitstream = self
+                .encoder
+                .encode(&yuv)
+                .map_err(|error| io::Error::other(error.to_string()))?;
+            bitstream.write_vec(&mut self.stream);
+            Ok(())
+        }
+
+        /// Writes the MP4 file.
+        pub fn finish(self) -> io::Result<()> {
+            let mut muxer = Mp4Muxer::new(File::create(&self.path)?);
+            muxer.init_video(self.size.0 as i32, self.size.1 as i32, false, "capture");
+            muxer.write_video_with_fps(&self.stream, self.framerate.round().max(1.0) as u32);
+            muxer.close();
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! The cart-pole, the classic benchmark of reinforcement learning: a pole hinged on a cart that
+//! slides along a rail, balanced by moving the cart.
+//!
+//! The cart slides on a prismatic joint, driven like the motor of the rotary pendulum by a stiff
+//! velocity servo following the `cart/velocity` setpoint, in m/s, within the length of the rail.
+//! The pole turns freely on a revolute joint at the top of the cart, and starts upright. The
+//! position of the cart along the rail, in m, and the angle of the pole from upright, in rad
+//! within `(-π, π]`, are recorded as the `cart/position` and `pole/angle` telemetry channels.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody, MOTOR_FACTOR},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Velocity of the cart, in m/s.
+pub const CART_VELOCITY: &str = "cart/velocity";
+/// Position of the cart along the rail, in m.
+pub const CART_POSITION: &str = "cart/position";
+/// Angle of the pole from upright, in rad.
+pub const POLE_ANGLE: &str = "pole/angle";
+/// Half of the travel of the cart along the rail, in m.
+pub const RAIL_HALF_LENGTH: f32 = 2.4;
+
+/// The cart-pole, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "cart_pole",
+        add: |app| {
+            app.add_plugins(CartPolePlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<CartPolePlugin>() {
+                app.add_plugins(CartPolePlugin);
+            }
+            app.insert_resource(CartPoleInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(CartPoleInstances::default());
+            plants::run_spawn(world, spawn_cart_poles);
+        },
+    }
+}
+
+pub struct CartPolePlugin;
+
+impl Plugin for CartPolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CartPoleInstances>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, spawn_cart_poles)
+            .add_systems(
+                Update,
+                (
+                    apply_cart_setpoint.run_if(resource_changed::<Setpoints>),
+                    record_cart_state,
+                ),
+            );
+    }
+}
+
+/// The cart-poles spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct CartPoleInstances(pub Vec<Instance>);
+
+impl Default for CartPoleInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The servo of a cart, on the entity of its joint.
+#[derive(Component)]
+struct CartServo {
+    /// Signals of the instance.
+    velocity: String,
+    position: String,
+    angle: String,
+    pole: Entity,
+    /// Position of the middle of the rail in the world.
+    origin: Vec3,
+}
+
+fn spawn_cart_poles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<CartPoleInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const RAIL_HEIGHT: f32 = 0.5;
+    const CART_SIZE: Vec3 = Vec3::new(0.6, 0.3, 0.4);
+    const POLE_RADIUS: f32 = 0.05;
+    const POLE_LENGTH: f32 = 1.0;
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let origin = instance.offset + Vec3::Y * RAIL_HEIGHT;
+        let rail = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Mesh3d(meshes.add(Cuboid::new(2.0 * RAIL_HALF_LENGTH, 0.05, 0.05))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(origin),
+            ))
+            .id();
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        let cart = commands
+            .spawn((
+                RigidBody::Dynamic,
+                link("cart"),
+                Collider::cuboid(CART_SIZE.x / 2.0, CART_SIZE.y / 2.0, CART_SIZE.z / 2.0),
+                ColliderMassProperties::Mass(1.0),
+                Mesh3d(meshes.add(Cuboid::from_size(CART_SIZE))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(origin),
+            ))
+            .id();
+        let slider = PrismaticJointBuilder::new(Vec3::X)
+            .limits([-RAIL_HALF_LENGTH, RAIL_HALF_LENGTH])
+            .motor_velocity(0.0, MOTOR_FACTOR);
+        commands
+            .entity(cart)
+            .insert(ImpulseJoint::new(rail, slider));
+
+        let pivot = Vec3::Y * CART_SIZE.y / 2.0;
+        let pole = commands
+            .spawn((
+                RigidBody::Dynamic,
+                link("pole"),
+                Collider::cylinder(POLE_LENGTH / 2.0, POLE_RADIUS),
+                ColliderMassProperties::Mass(0.1),
+                // Clear of the cart it's hinged on.
+                CollisionGroups::new(Group::GROUP_2, Group::ALL ^ Group::GROUP_2),
+                Mesh3d(meshes.add(Cylinder::new(POLE_RADIUS, POLE_LENGTH))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(origin + pivot + Vec3::Y * POLE_LENGTH / 2.0),
+            ))
+            .id();
+        let hinge = RevoluteJointBuilder::new(Vec3::Z)
+            .local_anchor1(pivot)
+            .local_anchor2(-Vec3::Y * POLE_LENGTH / 2.0);
+        commands.entity(pole).insert(ImpulseJoint::new(cart, hinge));
+
+        commands.entity(cart).insert(CartServo {
+            velocity: instance.signal(CART_VELOCITY),
+            position: instance.signal(CART_POSITION),
+            angle: instance.signal(POLE_ANGLE),
+            pole,
+            origin,
+        });
+    }
+}
+
+/// Applies the velocity setpoints to the servos of the carts.
+fn apply_cart_setpoint(
+    setpoints: Res<Setpoints>,
+    mut servos: Query<(&CartServo, &mut ImpulseJoint)>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "apply_cart_setpoint").entered();
+    for (servo, mut joint) in &mut servos {
+        let Some(velocity) = setpoints.get(&servo.velocity) else {
+            continue;
+        };
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::LinX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Records the positions of the carts and the angles of their poles.
+fn record_cart_state(
+    clock: Res<SimClock>,
+    servos: Query<(&CartServo, &Transform)>,
+    transforms: Query<&Transform>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_cart_state").entered();
+    let Some(telemetry) = telemetry.as_mut() else {
+        return;
+    };
+    for (servo, cart) in &servos {
+        let Ok(pole) = transforms.get(servo.pole) else {
+            continue;
+        };
+        let relative = cart.rotation.inverse() * pole.rotation;
+        // The hinge only turns about Z.
+        let angle = PI - (PI - 2.0 * relative.z.atan2(relative.w)).rem_euclid(TAU);
+        let time = clock.elapsed_secs();
+        telemetry.record(&servo.position, time, cart.translation.x - servo.origin.x);
+        telemetry.record(&servo.angle, time, angle);
+    }
+}
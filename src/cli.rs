@@ -0,0 +1,521 @@
+//! Command line interface of the playground.
+#[cfg(not(target_arch = "wasm32"))]
+use std::{net::SocketAddr, path::PathBuf};
+
+use bevy::prelude::*;
+use clap::Parser;
+#[cfg(not(target_arch = "wasm32"))]
+use clap::Subcommand;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{lockstep::Role, remote::ClientRole};
+
+#[derive(Clone, Debug, Default, Parser, Resource)]
+#[command(version, about)]
+pub struct Cli {
+    /// glTF scene to load, e.g. `model.glb#Scene1` (`blender-model` builds only).
+    pub scene: Option<String>,
+
+    /// Log levels per subsystem, e.g. `physics=debug,control=trace`.
+    ///
+    /// Overrides the levels of the configuration file.
+    #[arg(long, value_name = "FILTER")]
+    pub log: Option<String>,
+
+    /// Built-in plant to spawn, e.g. `cart_pole`, switched from the *Model library* panel, or
+    /// to run by the headless tools [default: rotary_pendulum, or that of the scenario, else the
+    /// first built-in plant, for the tools].
+    #[arg(long, value_name = "NAME")]
+    pub plant: Option<String>,
+
+    /// Compose several plants into the world from this scene composition file, e.g.
+    /// `composition.json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE")]
+    pub compose: Option<PathBuf>,
+
+    /// Compare tunings side by side from this comparison file, e.g. `comparison.json`: copies
+    /// of a plant in the same world, each closed by its own controllers.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["compose", "urdf", "sim_thread"])]
+    pub compare: Option<PathBuf>,
+
+    /// Spawn the robot described in this URDF file, e.g. `robot.urdf`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE")]
+    pub urdf: Option<PathBuf>,
+
+    /// Set the experiment up from this scenario file, in RON, YAML or JSON, e.g.
+    /// `scenario.ron`: the model, the initial state, the controllers, the disturbances and the
+    /// duration. The options given on the command line take precedence.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["connect", "sim_thread"])]
+    pub scenario: Option<PathBuf>,
+
+    /// Step the physics by this fixed time step on every frame, in seconds, so runs are
+    /// bit-identical whatever the frame rate [default: 1/60].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1)]
+    pub fixed_step: Option<Option<f32>>,
+
+    /// Simulate the plant and its controllers on a dedicated thread, paced at the fixed time
+    /// step, the window only drawing the states it sends, so heavy panels can't stall the
+    /// physics.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, conflicts_with_all = ["compose", "urdf", "lockstep", "connect"])]
+    pub sim_thread: bool,
+
+    /// Seed of the random draws, e.g. of the network impairments, overriding the configured ones.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Synchronize the simulated time with a co-simulator, as the master or the slave.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_enum, value_name = "ROLE")]
+    pub lockstep: Option<Role>,
+
+    /// Local address of the lockstep synchronization [default: 0.0.0.0:5700].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "ADDRESS", requires = "lockstep")]
+    pub lockstep_bind: Option<SocketAddr>,
+
+    /// Address of the lockstep peer, required for the master.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "lockstep",
+        required_if_eq("lockstep", "master")
+    )]
+    pub lockstep_peer: Option<SocketAddr>,
+
+    /// Exchange the process image of the drive with a PLC over UDP [default: 0.0.0.0:5720].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:5720"
+    )]
+    pub fieldbus: Option<SocketAddr>,
+
+    /// Serve the registers of `modbus.json` over Modbus TCP [default: 0.0.0.0:5020].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:5020"
+    )]
+    pub modbus: Option<SocketAddr>,
+
+    /// Exchange the signals of `udp_packets.json` in fixed-layout UDP packets, e.g. with the
+    /// UDP blocks of Simulink or LabVIEW [default: 0.0.0.0:25000].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:25000"
+    )]
+    pub udp_packets: Option<SocketAddr>,
+
+    /// Bridge the plant to a microcontroller on this serial port, e.g. `/dev/ttyACM0` or `COM3`:
+    /// send it the encoder counts and apply the torque it commands, as set in
+    /// `serial_bridge.json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "PORT")]
+    pub serial_bridge: Option<String>,
+
+    /// Stream the signals of `plotjuggler.json` to the UDP server of PlotJuggler at this address
+    /// [default: 127.0.0.1:9870].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "127.0.0.1:9870"
+    )]
+    pub plotjuggler: Option<SocketAddr>,
+
+    /// Serve the signals over OPC UA, with the users of `opcua.json` [default: 0.0.0.0:4840].
+    #[cfg(all(feature = "opcua", not(target_arch = "wasm32")))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:4840"
+    )]
+    pub opcua: Option<SocketAddr>,
+
+    /// Expose the motor as a CiA 402 drive on this SocketCAN interface, e.g. `vcan0`.
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "INTERFACE")]
+    pub canopen: Option<String>,
+
+    /// CANopen node ID of the drive [default: 1].
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "ID", requires = "canopen", value_parser = clap::value_parser!(u8).range(1..=127))]
+    pub canopen_node: Option<u8>,
+
+    /// Report the signals of `joystick.json` as the axes of a virtual joystick (uinput).
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub joystick: bool,
+
+    /// Host a collaborative session that other instances can join [default: 0.0.0.0:5710].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:5710",
+        conflicts_with = "connect"
+    )]
+    pub host: Option<SocketAddr>,
+
+    /// Join the collaborative session hosted at this address, e.g. `192.168.1.10:5710`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "ADDRESS")]
+    pub connect: Option<SocketAddr>,
+
+    /// Role requested when joining a session.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_enum, default_value = "observer", requires = "connect")]
+    pub role: ClientRole,
+
+    /// Name shown to the host when joining a session.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, requires = "connect")]
+    pub name: Option<String>,
+
+    /// Serve the web dashboard on this address, to monitor the run from a browser
+    /// [default: 0.0.0.0:5711].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:5711"
+    )]
+    pub dashboard: Option<SocketAddr>,
+
+    /// Only let the pages of the dashboard watch, without setpoints nor pausing.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, requires = "dashboard")]
+    pub dashboard_read_only: bool,
+
+    /// Serve the HTTP API orchestrating experiment campaigns on this address
+    /// [default: 127.0.0.1:5712].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "127.0.0.1:5712"
+    )]
+    pub api: Option<SocketAddr>,
+
+    /// Serve the WebSocket API streaming the telemetry and taking commands on this address
+    /// [default: 127.0.0.1:5713].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = "127.0.0.1:5713"
+    )]
+    pub websocket_api: Option<SocketAddr>,
+
+    /// Rate of the telemetry streamed by the WebSocket API, in Hz [default: 20].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "HZ", requires = "websocket_api")]
+    pub websocket_rate: Option<f32>,
+
+    /// Run the plant of `--plant`, else that of the scenario, else the first built-in one, and its
+    /// controllers without a window, as fast as possible, write their telemetry to the `--out`
+    /// file, then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Simulated duration of the headless run, e.g. `10s`, `250ms` or `2min` [default: 10s].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "DURATION",
+        requires = "headless",
+        value_parser = crate::batch::parse_duration
+    )]
+    pub duration: Option<f32>,
+
+    /// File the telemetry of the headless run is written to, as Parquet if it ends with
+    /// `.parquet` [default: results.csv].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pub out: Option<PathBuf>,
+
+    /// Capture the headless run offscreen into this directory, as configured in `capture.json`,
+    /// at the pace of the simulated time [default: captures]. A GPU, or a software adapter, is
+    /// required.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "captures",
+        requires = "headless"
+    )]
+    pub capture: Option<PathBuf>,
+
+    /// Search for failures of the controllers in random scenarios bounded by `fuzzing.json`,
+    /// headlessly, and save the minimized failing scenarios to this directory, then exit
+    /// [default: failures].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "failures",
+        conflicts_with = "headless"
+    )]
+    pub fuzz: Option<PathBuf>,
+
+    /// Replay a failing scenario saved by `--fuzz`, then exit with 1 if it still fails.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["headless", "fuzz"])]
+    pub replay_failure: Option<PathBuf>,
+
+    /// Run the combinations of parameters of `sweep.json` headlessly, score each run and write
+    /// the summary table to this file, then exit [default: sweep.csv].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "sweep.csv",
+        conflicts_with_all = ["headless", "fuzz", "replay_failure"]
+    )]
+    pub sweep: Option<PathBuf>,
+
+    /// Map the region of attraction of the controllers over the grid of `roa.json`, headlessly,
+    /// and write the estimate to this file, then exit [default: roa.csv].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "roa.csv",
+        conflicts_with_all = ["headless", "fuzz", "replay_failure", "sweep"]
+    )]
+    pub map_roa: Option<PathBuf>,
+
+    /// Drive the joints along the trajectory of `sizing.json` headlessly, check the motors of
+    /// its library against the torque and speed the motion takes, and write the envelopes to
+    /// this file, then exit [default: sizing.csv].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "sizing.csv",
+        conflicts_with_all = ["headless", "fuzz", "replay_failure", "sweep", "map_roa"]
+    )]
+    pub size_actuators: Option<PathBuf>,
+
+    /// Run the reference scenario headlessly, print the hash of its trajectory and write the
+    /// report to this file, then exit [default: determinism.json].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "REPORT",
+        num_args = 0..=1,
+        default_missing_value = "determinism.json"
+    )]
+    pub determinism: Option<PathBuf>,
+
+    /// Compare two determinism reports, e.g. from two platforms or builds, then exit with 1
+    /// if the trajectories differ.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["REPORT", "REPORT"],
+        conflicts_with = "determinism"
+    )]
+    pub compare_determinism: Option<Vec<PathBuf>>,
+
+    /// Simulate the double pendulum headlessly for a minute, by Rapier and by its analytic model
+    /// in f32 and in f64, print how far they diverged and write the report to this file, then
+    /// exit [default: precision.json].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "REPORT",
+        num_args = 0..=1,
+        default_missing_value = "precision.json",
+        conflicts_with_all = ["determinism", "compare_determinism"]
+    )]
+    pub precision: Option<PathBuf>,
+
+    /// Generate portable C code of the position loop of `pid.json` and of the regulator of
+    /// `lqr.json`, or of the `--controller` definitions, for the microcontroller of the rig,
+    /// into this directory, then exit [default: generated].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "generated"
+    )]
+    pub generate_c: Option<PathBuf>,
+
+    /// Sample period of the generated controllers without a sample rate, in seconds
+    /// [default: the physics step].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "SECONDS", requires = "generate_c")]
+    pub sample_period: Option<f32>,
+
+    /// Controller definition to generate the code of, e.g. one exported from a panel, instead
+    /// of the settings; repeat it for several.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "DEFINITION", requires = "generate_c")]
+    pub controller: Vec<PathBuf>,
+
+    /// Calibrate a linear model of the rig on this CSV log of the hardware, mapped as in
+    /// `hardware_log.json`, with the signals of `calibration.json`, print its fit and write it,
+    /// then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "LOG")]
+    pub calibrate: Option<PathBuf>,
+
+    /// File the calibrated model is written to, overriding `calibration.json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "MODEL", requires = "calibrate")]
+    pub calibrated_model: Option<PathBuf>,
+
+    /// Fit the physical parameters of the model structure of `ident.json` on this recorded run,
+    /// a session directory or a telemetry file, print them with their residuals, then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "RUN", conflicts_with = "calibrate")]
+    pub ident: Option<PathBuf>,
+
+    /// Write the recorded output of the fit, the simulated one and their residual to this file.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "CSV", requires = "ident")]
+    pub ident_residuals: Option<PathBuf>,
+
+    /// Replay the readings of this recorded run, a session directory or a telemetry file, into
+    /// the state estimation of `estimation.json`, without the physics, print the errors of the
+    /// estimates against the exact states, then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "RUN", conflicts_with_all = ["calibrate", "ident"])]
+    pub replay_estimator: Option<PathBuf>,
+
+    /// Identify a reduced-order linear model of the plant of `--plant` from the experiment of
+    /// `identification.json`, validate it on a second run and write it to this file, then exit
+    /// [default: model.json].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "MODEL",
+        num_args = 0..=1,
+        default_missing_value = "model.json"
+    )]
+    pub identify: Option<PathBuf>,
+
+    /// Number of states of the identified model, overriding the experiment.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "N", requires = "identify")]
+    pub order: Option<usize>,
+
+    /// Report the controllability and the observability of a model written by `--identify`,
+    /// then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, value_name = "MODEL", conflicts_with = "identify")]
+    pub analyze: Option<PathBuf>,
+
+    /// Compare the placements of sensors and actuators of `placement.json` on the plant of
+    /// `--plant`, then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, conflicts_with_all = ["identify", "analyze"])]
+    pub placement: bool,
+
+    /// Inject the faults of the scenarios of `fault_detection.json` into the plant of
+    /// `--plant`, detect them from the residuals of an observer of a model written by `--identify`
+    /// and print the detection latencies and the false alarms, then exit.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long, conflicts_with_all = ["identify", "analyze", "placement"])]
+    pub fault_detection: bool,
+
+    /// Step the plant of `--plant`, else that of `shaping.json`, without and with each input
+    /// shaper, as set up in `shaping.json`, print their residual vibrations and write the
+    /// responses to this file, then exit [default: shaping.csv].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(
+        long,
+        value_name = "CSV",
+        num_args = 0..=1,
+        default_missing_value = "shaping.csv",
+        conflicts_with_all = ["identify", "analyze", "placement"]
+    )]
+    pub shaping: Option<PathBuf>,
+
+    /// Tool to run instead of the application.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools of the playground that run without a window, then exit.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Run every registered controller through the benchmark scenarios of `bench.json`
+    /// headlessly, and write the scoreboard to a file, then exit.
+    BenchControllers {
+        /// File the scoreboard is written to.
+        #[arg(value_name = "FILE", default_value = "bench.csv")]
+        scoreboard: PathBuf,
+    },
+
+    /// Compare two recorded runs, session directories or telemetry files, channel by channel,
+    /// then exit.
+    Diff {
+        /// The reference run, then the compared one.
+        #[arg(num_args = 2, value_names = ["RUN", "RUN"], required = true)]
+        runs: Vec<PathBuf>,
+
+        /// Align the compared runs on the first change of this channel, instead of on their
+        /// time.
+        #[arg(long, value_name = "CHANNEL")]
+        align: Option<String>,
+
+        /// Smallest change of the alignment channel taken as the event [default: 0].
+        #[arg(long, value_name = "VALUE", requires = "align")]
+        align_threshold: Option<f32>,
+
+        /// Exit with 1 when a channel of the compared runs deviates by more than this value.
+        #[arg(long, value_name = "VALUE")]
+        tolerance: Option<f32>,
+
+        /// Read the first compared run as a CSV log of the hardware, mapped and aligned as in
+        /// `hardware_log.json`, to validate the simulated second one against it.
+        #[arg(long)]
+        hardware_log: bool,
+
+        /// Write the differences of the compared runs to this file, for plotting.
+        #[arg(long, value_name = "CSV")]
+        csv: Option<PathBuf>,
+    },
+
+    /// Step the controller of a test case on the measurements of a recorded trace, without
+    /// simulating the plant, check its outputs against the expected values, then exit with 1 if
+    /// one deviates beyond its tolerance.
+    ScriptTest {
+        /// Test case, naming its controller, trace and expectations.
+        #[arg(value_name = "CASE")]
+        case: PathBuf,
+    },
+}
@@ -0,0 +1,305 @@
+//! The simulation clock.
+//!
+//! [`SimClock`] counts the physics steps and the simulated time. It only advances when the
+//! physics does, by the step actually taken, so it never jumps with the wall clock, stops while
+//! paused, and is the same in windowed and headless runs. Subsystems that timestamp data (logs,
+//! telemetry, bridges, controllers) should read it rather than Bevy's [`Time`].
+//!
+//! The clock also runs the simulation slower or faster than real time, at one of the
+//! [`SPEEDS`]: by scaling the steps taken from the duration of the frames, or by pacing the
+//! frames of the [fixed-step mode](crate::fixed_step) to the scaled time step.
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::Real};
+use bevy_rapier3d::prelude::*;
+
+pub struct SimClockPlugin;
+
+impl Plugin for SimClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimClock>()
+            .configure_sets(
+                PostUpdate,
+                (
+                    SimClockSet::Gate.before(PhysicsSet::SyncBackend),
+                    SimClockSet::Advance.after(PhysicsSet::StepSimulation),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (apply_pause, apply_speed)
+                        .after(SimClockSet::Gate)
+                        .before(PhysicsSet::SyncBackend),
+                    advance_clock.in_set(SimClockSet::Advance),
+                ),
+            );
+    }
+}
+
+/// Where to run systems that interact with the clock around the physics step.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+pub enum SimClockSet {
+    /// Before the step: systems here can [`hold`](SimClock::hold) the clock.
+    Gate,
+    /// Right after the step, once the clock has advanced.
+    Advance,
+}
+
+/// Speeds of the simulation relative to real time offered by the transport controls.
+pub const SPEEDS: [f32; 4] = [0.1, 0.25, 1.0, 2.0];
+
+/// Smoothing factor of the real-time factor, per frame.
+const REAL_TIME_FACTOR_SMOOTHING: f32 = 0.1;
+
+#[derive(Clone, Debug, Resource)]
+pub struct SimClock {
+    tick: u64,
+    elapsed: Duration,
+    delta: Duration,
+    real_time_factor: f32,
+    paused: bool,
+    /// Whether the physics steps once while paused.
+    stepping: bool,
+    /// Simulated time per real time aimed at.
+    speed: f32,
+    held: bool,
+    /// Residual of the interpolated time step mode, in seconds.
+    accumulator: f32,
+    shared: SharedSimTime,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            elapsed: Duration::ZERO,
+            delta: Duration::ZERO,
+            real_time_factor: 1.0,
+            paused: false,
+            stepping: false,
+            speed: 1.0,
+            held: false,
+            accumulator: 0.0,
+            shared: SharedSimTime::default(),
+        }
+    }
+}
+
+impl SimClock {
+    /// Number of physics steps simulated so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Simulated time.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_secs(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+
+    pub fn elapsed_secs_f64(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    /// Simulated time of the last update, zero if the physics didn't step.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Simulated time per real time, smoothed over the last frames.
+    pub fn real_time_factor(&self) -> f32 {
+        self.real_time_factor
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pauses the clock, letting the physics step during the next update only.
+    pub fn step_once(&mut self) {
+        self.paused = true;
+        self.stepping = true;
+    }
+
+    /// Simulated time per real time aimed at, 1 by default.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Runs the simulation `speed` times as fast as real time, e.g. 0.1 for slow motion.
+    /// Speeds that aren't positive are ignored.
+    pub fn set_speed(&mut self, speed: f32) {
+        if speed.is_finite() && speed > 0.0 {
+            self.speed = speed;
+        }
+    }
+
+    /// Moves to the next of the [`SPEEDS`] up, or down when `faster` is false.
+    pub fn change_speed(&mut self, faster: bool) {
+        let speed = if faster {
+            SPEEDS.iter().find(|speed| **speed > self.speed)
+        } else {
+            SPEEDS.iter().rev().find(|speed| **speed < self.speed)
+        };
+        if let Some(speed) = speed {
+            self.speed = *speed;
+        }
+    }
+
+    /// Prevents the physics from stepping during the current update, e.g. while waiting for a
+    /// co-simulator. Call it from [`SimClockSet::Gate`].
+    pub fn hold(&mut self) {
+        self.held = true;
+    }
+
+    /// Whether the physics steps during the current update.
+    pub fn is_running(&self) -> bool {
+        (!self.paused || self.stepping) && !self.held
+    }
+
+    /// A handle to read the simulated time from other threads.
+    pub fn shared(&self) -> SharedSimTime {
+        self.shared.clone()
+    }
+
+    /// Advances the clock by `steps` physics steps of `dt`, taken in `real_delta` of wall time.
+    pub fn advance(&mut self, steps: u64, dt: Duration, real_delta: Duration) {
+        self.delta = dt * steps as u32;
+        self.tick += steps;
+        self.elapsed += self.delta;
+        if !real_delta.is_zero() {
+            let factor = self.delta.as_secs_f32() / real_delta.as_secs_f32();
+            self.real_time_factor += REAL_TIME_FACTOR_SMOOTHING * (factor - self.real_time_factor);
+        }
+        self.shared.store(self.elapsed);
+    }
+
+    /// Sets the clock to a previously saved state, e.g. when a session is restored.
+    pub fn restore(&mut self, tick: u64, elapsed: Duration) {
+        self.tick = tick;
+        self.elapsed = elapsed;
+        self.delta = Duration::ZERO;
+        self.shared.store(elapsed);
+    }
+
+    /// Number of whole steps of `dt` in `duration`.
+    pub fn ticks_in(duration: Duration, dt: Duration) -> u64 {
+        if dt.is_zero() {
+            return 0;
+        }
+        (duration.as_nanos() / dt.as_nanos()) as u64
+    }
+
+    /// Duration of `ticks` steps of `dt`.
+    pub fn duration_of(ticks: u64, dt: Duration) -> Duration {
+        Duration::from_nanos(ticks.saturating_mul(dt.as_nanos() as u64))
+    }
+}
+
+/// The simulated time, readable from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct SharedSimTime(Arc<AtomicU64>);
+
+impl SharedSimTime {
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, elapsed: Duration) {
+        self.0.store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Stops the physics pipeline while the clock is paused or held.
+fn apply_pause(clock: Res<SimClock>, mut configurations: Query<&mut RapierConfiguration>) {
+    for mut configuration in &mut configurations {
+        if configuration.physics_pipeline_active != clock.is_running() {
+            configuration.physics_pipeline_active = clock.is_running();
+        }
+    }
+}
+
+/// Scales the steps taken from the duration of the frames by the speed of the clock. Faster
+/// than real time, the steps are allowed to grow as much, as Rapier steps once per frame.
+fn apply_speed(
+    clock: Res<SimClock>,
+    timestep_mode: Option<ResMut<TimestepMode>>,
+    mut real_time_max_dt: Local<Option<f32>>,
+) {
+    let Some(mut timestep_mode) = timestep_mode else {
+        return;
+    };
+    let TimestepMode::Variable {
+        max_dt, time_scale, ..
+    } = *timestep_mode
+    else {
+        return;
+    };
+    let base = *real_time_max_dt.get_or_insert(max_dt);
+    if time_scale != clock.speed {
+        if let TimestepMode::Variable {
+            max_dt, time_scale, ..
+        } = &mut *timestep_mode
+        {
+            *time_scale = clock.speed;
+            *max_dt = base * clock.speed.max(1.0);
+        }
+    }
+}
+
+/// Advances the clock by the step Rapier just took, mirroring its [`TimestepMode`].
+fn advance_clock(
+    mut clock: ResMut<SimClock>,
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    timestep_mode: Res<TimestepMode>,
+) {
+    if !clock.is_running() {
+        clock.held = false;
+        clock.advance(0, Duration::ZERO, real_time.delta());
+        return;
+    }
+    let (steps, dt) = match *timestep_mode {
+        TimestepMode::Fixed { dt, .. } => (1, dt),
+        TimestepMode::Variable {
+            max_dt, time_scale, ..
+        } => (1, (time.delta_secs() * time_scale).min(max_dt)),
+        TimestepMode::Interpolated { dt, .. } => {
+            let mut steps = 0;
+            clock.accumulator += time.delta_secs();
+            while clock.accumulator > 0.0 {
+                clock.accumulator -= dt;
+                steps += 1;
+            }
+            (steps, dt)
+        }
+    };
+    clock.stepping = false;
+    clock.advance(steps, Duration::from_secs_f32(dt), real_time.delta());
+}
@@ -0,0 +1,312 @@
+//! Generation of portable C code of the tuned controllers, to flash the controller validated in
+//! simulation onto the microcontroller of the rig.
+//!
+//! Each controller becomes a header and a source file of C99, without allocation nor
+//! dependency beyond `<math.h>`: a state structure, an initialization function and an update
+//! function called at the fixed sample period the gains were discretized for. The generated
+//! code computes in single precision what the simulated controller computes: the position loop
+//! of `pid.json`, its anti-windup and the unwrapping of the angle over the turns included, and
+//! the regulator of `lqr.json`, its gain solved at the sample period and its estimate of the
+//! velocities by finite differences included. The controllers are given by their
+//! [definitions](ControllerDefinition), or by their settings.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    controller_definition::{ControllerDefinition, ControllerType},
+    error::{Error, Result},
+    lqr::LqrSettings,
+    pid_controller::PidSettings,
+};
+
+/// A generated controller: the contents of `<name>.h` and `<name>.c`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generated {
+    pub name: String,
+    pub header: String,
+    pub source: String,
+}
+
+impl Generated {
+    /// Writes the header and the source to `directory`, and returns their paths.
+    pub fn write(&self, directory: &Path) -> Result<[PathBuf; 2]> {
+        fs::create_dir_all(directory).map_err(|error| Error::io(directory, error))?;
+        let header = directory.join(format!("{}.h", self.name));
+        let source = directory.join(format!("{}.c", self.name));
+        for (path, contents) in [(&header, &self.header), (&source, &self.source)] {
+            fs::write(path, contents).map_err(|error| Error::io(path, error))?;
+        }
+        Ok([header, source])
+    }
+}
+
+/// The controller of `definition`, sampled at its rate, or every `dt` seconds without one.
+pub fn generate(definition: &ControllerDefinition, dt: f32) -> Result<Generated> {
+    let dt = definition.sample_period(dt);
+    match definition.controller {
+        ControllerType::Pid { .. } => Ok(pid(&PidSettings::try_from(definition)?, dt)),
+        ControllerType::Lqr { .. } => lqr(&LqrSettings::try_from(definition)?, dt),
+    }
+}
+
+/// C literal of a single-precision value, infinities included.
+fn literal(value: f32) -> String {
+    if value == f32::INFINITY {
+        "INFINITY".to_string()
+    } else if value == f32::NEG_INFINITY {
+        "-INFINITY".to_string()
+    } else {
+        format!("{value:?}f")
+    }
+}
+
+/// Opening of the header of `name`, up to its declarations.
+fn header_guard(name: &str, description: &str, dt: f32) -> String {
+    let guard = format!("{}_H", name.to_uppercase());
+    format!(
+        "/* {description}, sampled every {dt} s.\n \
+         * Generated by the motion control playground: regenerate it rather than editing it. */\n\
+         #ifndef {guard}\n\
+         #define {guard}\n\
+         \n\
+         #include <stdbool.h>\n\
+         \n\
+         /* Sample period, in s: call the update once per period. */\n\
+         #define {upper}_DT {period}\n",
+        upper = name.to_uppercase(),
+        period = literal(dt),
+    )
+}
+
+/// Angle within `(-π, π]` and the difference of two angles within a turn, as the simulated
+/// controllers wrap them.
+const WRAP: &str = "\
+static const float PI = 3.14159265358979f;
+static const float TAU = 6.28318530717959f;
+
+/* Angle within (-PI, PI]. */
+static float wrap(float angle)
+{
+    float turned = fmodf(PI - angle, TAU);
+    if (turned < 0.0f) {
+        turned += TAU;
+    }
+    return PI - turned;
+}
+";
+
+/// The position loop of `settings`, commanding the velocity of the joint from its angle.
+pub fn pid(settings: &PidSettings, dt: f32) -> Generated {
+    let name = "pid";
+    let limits = settings.limits();
+    let header = format!(
+        "{}\n\
+         typedef struct {{\n    \
+             float integral;\n    \
+             float previous_error;\n    \
+             /* Last angle within a turn, and the angle over the turns. */\n    \
+             float last_angle;\n    \
+             float position;\n    \
+             bool measured;\n\
+         }} pid_state;\n\
+         \n\
+         void pid_init(pid_state *state);\n\
+         \n\
+         /* Velocity to command, in rad/s, from the position aimed at, in rad, and the angle of\n \
+         * the joint within a turn. */\n\
+         float pid_update(pid_state *state, float setpoint, float angle);\n\
+         \n\
+         #endif\n",
+        header_guard(name, &format!("Position loop of `{}`", settings.link), dt),
+    );
+    let source = format!(
+        "#include \"pid.h\"\n\
+         \n\
+         #include <math.h>\n\
+         \n\
+         static const float KP = {kp};\n\
+         static const float KI = {ki};\n\
+         static const float KD = {kd};\n\
+         /* Limits of the velocity commanded, in rad/s. */\n\
+         static const float MIN = {min};\n\
+         static const float MAX = {max};\n\
+         \n\
+         {WRAP}\n\
+         static float clamp(float value, float min, float max)\n\
+         {{\n    \
+             return value < min ? min : (value > max ? max : value);\n\
+         }}\n\
+         \n\
+         void pid_init(pid_state *state)\n\
+         {{\n    \
+             state->integral = 0.0f;\n    \
+             state->previous_error = NAN;\n    \
+             state->last_angle = 0.0f;\n    \
+             state->position = 0.0f;\n    \
+             state->measured = false;\n\
+         }}\n\
+         \n\
+         float pid_update(pid_state *state, float setpoint, float angle)\n\
+         {{\n    \
+             if (state->measured) {{\n        \
+                 state->position += wrap(angle - state->last_angle);\n    \
+             }} else {{\n        \
+                 state->position = angle;\n        \
+                 state->measured = true;\n    \
+             }}\n    \
+             state->last_angle = angle;\n\
+         \n    \
+             float error = setpoint - state->position;\n    \
+             float derivative = isnan(state->previous_error)\n        \
+                 ? 0.0f\n        \
+                 : KD * (error - state->previous_error) / PID_DT;\n    \
+             state->previous_error = error;\n    \
+             float proportional = KP * error;\n    \
+             float unsaturated = proportional + state->integral + derivative;\n    \
+             /* Stop integrating while saturated in the direction of the error. */\n    \
+             bool pushing_further = (unsaturated >= MAX && error > 0.0f)\n        \
+                 || (unsaturated <= MIN && error < 0.0f);\n    \
+             if (!pushing_further) {{\n        \
+                 state->integral += KI * error * PID_DT;\n    \
+             }}\n    \
+             state->integral = clamp(state->integral, MIN, MAX);\n    \
+             return clamp(proportional + state->integral + derivative, MIN, MAX);\n\
+         }}\n",
+        kp = literal(settings.gains.kp),
+        ki = literal(settings.gains.ki),
+        kd = literal(settings.gains.kd),
+        min = literal(limits.min),
+        max = literal(limits.max),
+    );
+    Generated {
+        name: name.to_string(),
+        header,
+        source,
+    }
+}
+
+/// The regulator of `settings`, its gain solved at the sample period, commanding the
+/// acceleration and the velocity of the arm from the angles of the joints.
+pub fn lqr(settings: &LqrSettings, dt: f32) -> Result<Generated> {
+    let name = "lqr";
+    let lqr = settings.solve(dt)?;
+    let gain: Vec<String> = lqr
+        .gain
+        .row(0)
+        .iter()
+        .map(|gain| literal(*gain as f32))
+        .collect();
+    let header = format!(
+        "{}\n\
+         typedef struct {{\n    \
+             /* Last angles of the motor and pendulum joints, within a turn. */\n    \
+             float previous[2];\n    \
+             bool measured;\n    \
+             /* Angle of the arm since the pendulum was caught, its integral, and its velocity\n     \
+              * commanded. */\n    \
+             float arm;\n    \
+             float integral;\n    \
+             float velocity;\n    \
+             bool engaged;\n\
+         }} lqr_state;\n\
+         \n\
+         void lqr_init(lqr_state *state);\n\
+         \n\
+         /* Measures the angles of the motor and pendulum joints, within a turn, in rad, and\n \
+         * while the pendulum is caught, sets the acceleration of the arm, in rad/s², and the\n \
+         * velocity to command, in rad/s, and returns true. The pendulum is upright at PI. */\n\
+         bool lqr_update(lqr_state *state, float motor, float pendulum, float *acceleration,\n    \
+             float *velocity);\n\
+         \n\
+         #endif\n",
+        header_guard(
+            name,
+            &format!(
+                "Regulator of the pendulum on `{}`, driven by `{}`",
+                settings.pendulum, settings.motor
+            ),
+            dt
+        ),
+    );
+    // The servo form feeds the integral of the arm angle back too.
+    let (integrate, integral_feedback) = if settings.design.integral.is_some() {
+        (
+            "    state->integral += state->arm * LQR_DT;\n",
+            " + K[4] * state->integral",
+        )
+    } else {
+        ("", "")
+    };
+    let source = format!(
+        "#include \"lqr.h\"\n\
+         \n\
+         #include <math.h>\n\
+         \n\
+         /* Feedback gain on the angle and velocity of the arm and of the pendulum from upright,\n \
+          * and in the servo form on the integral of the arm angle. */\n\
+         static const float K[{count}] = {{{gain}}};\n\
+         /* Largest angle from upright at which the pendulum is caught, in rad. */\n\
+         static const float CAPTURE = {capture};\n\
+         /* Largest acceleration of the arm commanded, in rad/s². */\n\
+         static const float LIMIT = {limit};\n\
+         \n\
+         {WRAP}\n\
+         void lqr_init(lqr_state *state)\n\
+         {{\n    \
+             state->previous[0] = 0.0f;\n    \
+             state->previous[1] = 0.0f;\n    \
+             state->measured = false;\n    \
+             state->arm = 0.0f;\n    \
+             state->integral = 0.0f;\n    \
+             state->velocity = 0.0f;\n    \
+             state->engaged = false;\n\
+         }}\n\
+         \n\
+         bool lqr_update(lqr_state *state, float motor, float pendulum, float *acceleration,\n    \
+             float *velocity)\n\
+         {{\n    \
+             float last_motor = state->previous[0];\n    \
+             float last_pendulum = state->previous[1];\n    \
+             bool measured = state->measured;\n    \
+             state->previous[0] = motor;\n    \
+             state->previous[1] = pendulum;\n    \
+             state->measured = true;\n    \
+             if (!measured) {{\n        \
+                 return false;\n    \
+             }}\n    \
+             float step = wrap(motor - last_motor);\n    \
+             float angle = wrap(pendulum - PI);\n    \
+             if (fabsf(angle) > CAPTURE) {{\n        \
+                 state->engaged = false;\n        \
+                 return false;\n    \
+             }}\n    \
+             if (!state->engaged) {{\n        \
+                 state->engaged = true;\n        \
+                 state->arm = 0.0f;\n        \
+                 state->integral = 0.0f;\n        \
+                 state->velocity = step / LQR_DT;\n    \
+             }} else {{\n        \
+                 state->arm += step;\n    \
+             }}\n\
+         {integrate}    \
+             float command = -(K[0] * state->arm + K[1] * (step / LQR_DT) + K[2] * angle\n        \
+                 + K[3] * (wrap(pendulum - last_pendulum) / LQR_DT){integral_feedback});\n    \
+             command = command < -LIMIT ? -LIMIT : (command > LIMIT ? LIMIT : command);\n    \
+             state->velocity += command * LQR_DT;\n    \
+             *acceleration = command;\n    \
+             *velocity = state->velocity;\n    \
+             return true;\n\
+         }}\n",
+        count = gain.len(),
+        gain = gain.join(", "),
+        capture = literal(settings.capture),
+        limit = literal(settings.limit),
+    );
+    Ok(Generated {
+        name: name.to_string(),
+        header,
+        source,
+    })
+}
@@ -0,0 +1,366 @@
+//! This module generates the colliders of the links of the models loaded from glTF files, which
+//! only describe their looks: once the links are built, each mesh of a link gets a collider of
+//! the shape chosen for the link, attached to its body.
+//!
+//! The shapes are:
+//!
+//! - `trimesh`, the triangles of the mesh themselves: exact, but hollow and without mass, which
+//!   suits the fixed bodies only,
+//! - `convex_hull`, the smallest convex shape around the mesh, the default,
+//! - `convex_decomposition`, a union of convex hulls approximating a concave mesh, computed by
+//!   V-HACD at `resolution` voxels along the mesh, into at most `max_hulls` hulls,
+//! - `box` and `capsule`, the primitive fitted to the bounds of the mesh, the capsule along
+//!   their longest side: the cheapest to collide.
+//!
+//! The shape of a link is the one `colliders.json` sets for it, or else the `collider` of the
+//! extras of its node, or else the default shape of `colliders.json`. The colliders are generated
+//! again when the configuration changes.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    joint_builder::JointBuilderSet,
+    logging::subsystem,
+    plants::Link,
+};
+
+pub struct CollidersPlugin;
+
+impl Plugin for CollidersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                generate_colliders
+                    .after(JointBuilderSet)
+                    .run_if(resource_exists::<Persistent<ColliderSettings>>),
+            )
+            .add_systems(
+                Update,
+                colliders_panel
+                    .run_if(resource_exists::<Persistent<ColliderSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Shape of the colliders generated from the meshes of a link.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColliderShape {
+    /// The triangles of the mesh.
+    Trimesh,
+    /// The convex hull of the mesh.
+    #[default]
+    ConvexHull,
+    /// Convex hulls of the parts of the mesh.
+    ConvexDecomposition,
+    /// The box bounding the mesh.
+    Box,
+    /// The capsule bounding the mesh, along its longest side.
+    Capsule,
+}
+
+impl ColliderShape {
+    pub const ALL: [Self; 5] = [
+        Self::Trimesh,
+        Self::ConvexHull,
+        Self::ConvexDecomposition,
+        Self::Box,
+        Self::Capsule,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Trimesh => "Triangle mesh",
+            Self::ConvexHull => "Convex hull",
+            Self::ConvexDecomposition => "Convex decomposition",
+            Self::Box => "Box",
+            Self::Capsule => "Capsule",
+        }
+    }
+}
+
+/// Parameters of the convex decomposition.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Decomposition {
+    /// Voxels along the longest side of the mesh.
+    pub resolution: u32,
+    /// Largest number of convex hulls.
+    pub max_hulls: u32,
+}
+
+impl Default for Decomposition {
+    fn default() -> Self {
+        Self {
+            resolution: 64,
+            max_hulls: 16,
+        }
+    }
+}
+
+/// The shape of the colliders of a link.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LinkCollider {
+    pub link: String,
+    pub shape: ColliderShape,
+}
+
+/// Represents how the colliders are generated, from the `colliders.json` configuration file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ColliderSettings {
+    /// Shape of the links the model and the links don't choose one for.
+    pub shape: ColliderShape,
+    pub decomposition: Decomposition,
+    /// The links of a shape of their own, whatever the model chooses.
+    pub links: Vec<LinkCollider>,
+}
+
+impl ColliderSettings {
+    /// Shape of the colliders of `link`, whose model chose `chosen`.
+    pub fn shape(&self, link: &str, chosen: Option<ColliderShape>) -> ColliderShape {
+        self.links
+            .iter()
+            .find(|configured| configured.link == link)
+            .map(|configured| configured.shape)
+            .or(chosen)
+            .unwrap_or(self.shape)
+    }
+}
+
+/// A link whose meshes get generated colliders, of the shape its model chose if any.
+#[derive(Clone, Component, Copy, Debug, Default, PartialEq)]
+pub struct GenerateColliders {
+    pub shape: Option<ColliderShape>,
+}
+
+/// How the collider of a mesh was generated.
+#[derive(Clone, Component, Copy, Debug, PartialEq)]
+pub struct GeneratedCollider {
+    pub shape: ColliderShape,
+    /// The parameters of a convex decomposition.
+    pub decomposition: Option<Decomposition>,
+}
+
+/// Collider of the shape `shape` generated from `mesh`, or `None` when the mesh has no
+/// triangles to generate it from.
+pub fn generate(
+    shape: ColliderShape,
+    mesh: &Mesh,
+    decomposition: Decomposition,
+) -> Option<Collider> {
+    let computed = match shape {
+        ColliderShape::Trimesh => ComputedColliderShape::TriMesh(TriMeshFlags::default()),
+        ColliderShape::ConvexHull => ComputedColliderShape::ConvexHull,
+        ColliderShape::ConvexDecomposition => {
+            ComputedColliderShape::ConvexDecomposition(VHACDParameters {
+                resolution: decomposition.resolution.max(1),
+                max_convex_hulls: decomposition.max_hulls.max(1),
+                ..default()
+            })
+        }
+        ColliderShape::Box | ColliderShape::Capsule => {
+            let bounds = mesh.compute_aabb()?;
+            return fit_primitive(shape, bounds.center.into(), bounds.half_extents.into());
+        }
+    };
+    Collider::from_bevy_mesh(mesh, &computed)
+}
+
+/// The box or the capsule fitting the bounds of a mesh, of center `center` and half sides
+/// `half_extents`, in the frame of the mesh.
+pub fn fit_primitive(shape: ColliderShape, center: Vec3, half_extents: Vec3) -> Option<Collider> {
+    match shape {
+        ColliderShape::Box => {
+            let cuboid = Collider::cuboid(half_extents.x, half_extents.y, half_extents.z);
+            Some(if center == Vec3::ZERO {
+                cuboid
+            } else {
+                Collider::compound(vec![(center, Quat::IDENTITY, cuboid)])
+            })
+        }
+        ColliderShape::Capsule => {
+            let sides = half_extents.to_array();
+            let longest = (0..3).fold(0, |longest, axis| {
+                if sides[axis] > sides[longest] {
+                    axis
+                } else {
+                    longest
+                }
+            });
+            let radius = (0..3)
+                .filter(|&axis| axis != longest)
+                .map(|axis| sides[axis])
+                .fold(0.0, f32::max);
+            let half_segment = (sides[longest] - radius).max(0.0);
+            let axis = Vec3::AXES[longest] * half_segment;
+            Some(Collider::capsule(center - axis, center + axis, radius))
+        }
+        _ => None,
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ColliderSettings>("colliders", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Generates the colliders of the meshes of the links, as they spawn or when the configuration
+/// changes.
+fn generate_colliders(
+    mut commands: Commands,
+    settings: Res<Persistent<ColliderSettings>>,
+    meshes: Res<Assets<Mesh>>,
+    nodes: Query<(Entity, &Mesh3d, Option<&GeneratedCollider>)>,
+    links: Query<(&Link, &GenerateColliders)>,
+    parents: Query<&Parent>,
+) {
+    for (entity, mesh, generated) in &nodes {
+        let Some((link, choice)) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| links.get(ancestor).ok())
+        else {
+            continue;
+        };
+        let shape = settings.shape(&link.name, choice.shape);
+        let wanted = GeneratedCollider {
+            shape,
+            decomposition: (shape == ColliderShape::ConvexDecomposition)
+                .then_some(settings.decomposition),
+        };
+        if generated == Some(&wanted) {
+            continue;
+        }
+        // Until the mesh loads.
+        let Some(mesh) = meshes.get(&mesh.0) else {
+            continue;
+        };
+        let mut node = commands.entity(entity);
+        node.insert(wanted);
+        match generate(shape, mesh, settings.decomposition) {
+            Some(collider) => {
+                node.insert(collider);
+                debug!(
+                    target: subsystem::PHYSICS,
+                    "{} collider generated for a mesh of link {}",
+                    shape.name(),
+                    link.path()
+                );
+            }
+            None => {
+                node.remove::<Collider>();
+                warn!(
+                    target: subsystem::PHYSICS,
+                    "No {} collider could be generated for a mesh of link {}",
+                    shape.name().to_lowercase(),
+                    link.path()
+                );
+            }
+        }
+    }
+}
+
+/// Panel to choose the shapes of the colliders.
+fn colliders_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ColliderSettings>>,
+    generated: Query<&GeneratedCollider>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Colliders")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Default shape");
+                shape_combo(ui, "default_collider", &mut edited.shape);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.decomposition.resolution)
+                    .range(8..=512)
+                    .prefix("Decomposition resolution: ")
+                    .suffix(" voxels"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.decomposition.max_hulls)
+                    .range(1..=256)
+                    .prefix("Largest number of hulls: "),
+            );
+
+            ui.separator();
+            ui.label("Links of a shape of their own");
+            let mut removed = None;
+            for (index, link) in edited.links.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut link.link);
+                    shape_combo(ui, ("link_collider", index), &mut link.shape);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.links.remove(index);
+            }
+            if ui.button("Add a link").clicked() {
+                edited.links.push(LinkCollider::default());
+            }
+
+            ui.separator();
+            egui::Grid::new("generated_colliders").show(ui, |ui| {
+                for shape in ColliderShape::ALL {
+                    let count = generated
+                        .iter()
+                        .filter(|generated| generated.shape == shape)
+                        .count();
+                    if count > 0 {
+                        ui.label(shape.name());
+                        ui.label(format!("{count} meshes"));
+                        ui.end_row();
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("colliders", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("colliders", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+fn shape_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, shape: &mut ColliderShape) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(shape.name())
+        .show_ui(ui, |ui| {
+            for option in ColliderShape::ALL {
+                ui.selectable_value(shape, option, option.name());
+            }
+        });
+}
@@ -0,0 +1,83 @@
+//! Time-stamped commands of the external controllers.
+//!
+//! A controller can stamp a setpoint with the simulated time it applies from, e.g. to send it
+//! ahead of a network delay. The [`CommandBuffer`] holds it until the clock reaches that time,
+//! then writes it before the step starting there. Commands stamped in the past are written
+//! right away and counted as late.
+use bevy::prelude::*;
+
+use crate::{clock::SimClock, logging::subsystem, setpoints::Setpoints};
+
+pub struct CommandBufferPlugin;
+
+impl Plugin for CommandBufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandBuffer>()
+            .init_resource::<Setpoints>()
+            .add_systems(PreUpdate, apply_commands);
+    }
+}
+
+/// A setpoint to write at a simulated time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedCommand {
+    /// Simulated time, in seconds.
+    pub time: f32,
+    pub setpoint: String,
+    pub value: f32,
+}
+
+/// The commands waiting for their time.
+#[derive(Debug, Default, Resource)]
+pub struct CommandBuffer {
+    /// Sorted by time, then by arrival.
+    pending: Vec<TimedCommand>,
+    /// Number of commands received after their time.
+    pub late: u64,
+}
+
+impl CommandBuffer {
+    /// Schedules a command received at the simulated time `now`.
+    pub fn schedule(&mut self, command: TimedCommand, now: f32) {
+        if command.time < now {
+            self.late += 1;
+            debug!(
+                target: subsystem::CONTROL,
+                "{} for {} s received at {now} s", command.setpoint, command.time
+            );
+        }
+        let index = self
+            .pending
+            .partition_point(|pending| pending.time <= command.time);
+        self.pending.insert(index, command);
+    }
+
+    /// Takes the commands due at the simulated time `now`, in order.
+    pub fn due(&mut self, now: f32) -> Vec<TimedCommand> {
+        let due = self.pending.partition_point(|pending| pending.time <= now);
+        self.pending.drain(..due).collect()
+    }
+
+    /// Number of commands waiting.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Writes the commands due before the next step.
+fn apply_commands(
+    clock: Res<SimClock>,
+    mut buffer: ResMut<CommandBuffer>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    if buffer.is_empty() {
+        return;
+    }
+    for command in buffer.due(clock.elapsed_secs()) {
+        setpoints.set(&command.setpoint, command.value);
+    }
+}
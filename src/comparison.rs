@@ -0,0 +1,240 @@
+//! Side-by-side comparison of tunings: copies of a plant in the same world, each closed by its
+//! own controllers, stepped by the same clock so they can be watched together.
+//!
+//! A [`Comparison`] names the plant and its variants, each with the loops closing it, as in a
+//! [scene composition](crate::composition): the copies are composed into the world, offset from
+//! each other by the spacing, their signals prefixed with the names of the variants
+//! (`stiff/pendulum/angle`). The links of a copy only collide with the links of the same copy and
+//! with the shared bodies, like the ground, so the copies can be overlaid with a zero spacing.
+//! The *Comparison* panel shows a channel of every copy side by side, and sets a signal on every
+//! copy at once, e.g. the same reference.
+use std::{collections::BTreeSet, fs, path::Path};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    composition::{ComposedPlant, Composition, CompositionPlugin, LoopSettings},
+    error::{Error, Result},
+    logging::subsystem,
+    plants::{namespaced, Link, Plant},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+/// Largest number of copies compared, each taking a collision group.
+pub const MAX_COPIES: usize = 16;
+/// First collision group of the copies, the lower ones being left to the plants.
+const FIRST_COPY_GROUP: usize = 16;
+
+/// A tuning of the plant, closing a copy of it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Variant {
+    /// Namespace of the signals of the copy, unique in the comparison.
+    pub name: String,
+    #[serde(default)]
+    pub controllers: Vec<LoopSettings>,
+}
+
+/// Represents a comparison file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Comparison {
+    /// Name of the plant, built in or from an extension, e.g. `rotary_pendulum`.
+    pub plant: String,
+    /// Offset of each copy from the previous one, or zero to overlay them.
+    pub spacing: [f32; 3],
+    pub variants: Vec<Variant>,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Self {
+            plant: "rotary_pendulum".to_string(),
+            spacing: [10.0, 0.0, 0.0],
+            variants: Vec::new(),
+        }
+    }
+}
+
+impl Comparison {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&text).map_err(|error| Error::Config {
+            name: path.display().to_string(),
+            message: error.to_string(),
+        })
+    }
+
+    /// Checks that there are between two and [`MAX_COPIES`] variants, and that they compose.
+    pub fn validate(&self, available: &[Plant]) -> Result<()> {
+        if !(2..=MAX_COPIES).contains(&self.variants.len()) {
+            return Err(Error::Config {
+                name: "comparison".to_string(),
+                message: format!("compare between 2 and {MAX_COPIES} variants"),
+            });
+        }
+        self.composition().validate(available)
+    }
+
+    /// The composition of the copies.
+    pub fn composition(&self) -> Composition {
+        let spacing = Vec3::from(self.spacing);
+        Composition {
+            plants: self
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| ComposedPlant {
+                    name: variant.name.clone(),
+                    plant: self.plant.clone(),
+                    offset: (spacing * index as f32).to_array(),
+                    controllers: variant.controllers.clone(),
+                    follow: None,
+                })
+                .collect(),
+            ..default()
+        }
+    }
+}
+
+/// Collision groups of a collider of the `index`th copy, from the groups it had: a member of
+/// the group of its copy only, it leaves out the other copies, and its own copy if it left out
+/// its own groups, like the links of a plant clear of each other.
+pub fn copy_groups(index: usize, groups: CollisionGroups) -> CollisionGroups {
+    let copies = (0..MAX_COPIES).fold(Group::NONE, |copies, index| copies | copy_group(index));
+    let own = copy_group(index);
+    let mut filters = groups.filters - copies;
+    if groups.filters.contains(groups.memberships) {
+        filters |= own;
+    }
+    CollisionGroups::new(own, filters)
+}
+
+fn copy_group(index: usize) -> Group {
+    Group::from_bits_truncate(1 << (FIRST_COPY_GROUP + index))
+}
+
+/// Composes the copies of the comparison.
+pub struct ComparisonPlugin {
+    pub comparison: Comparison,
+}
+
+impl Plugin for ComparisonPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(CompositionPlugin {
+            composition: self.comparison.composition(),
+        })
+        .insert_resource(ComparedCopies {
+            names: self
+                .comparison
+                .variants
+                .iter()
+                .map(|variant| variant.name.clone())
+                .collect(),
+            channel: PENDULUM_ANGLE.to_string(),
+            signal: MOTOR_VELOCITY.to_string(),
+            value: 0.0,
+        })
+        .add_systems(Update, isolate_copies)
+        .add_systems(Update, comparison_panel.run_if(has_ui));
+    }
+}
+
+/// The copies compared, and what the panel shows and sets.
+#[derive(Clone, Debug, Resource)]
+pub struct ComparedCopies {
+    /// Names of the copies, in order.
+    pub names: Vec<String>,
+    /// Channel shown for every copy, without its namespace.
+    pub channel: String,
+    /// Signal set on every copy, without its namespace.
+    pub signal: String,
+    pub value: f32,
+}
+
+/// Marks the colliders put in the collision group of their copy.
+#[derive(Component)]
+struct Isolated;
+
+/// Puts the colliders of the links of the copies in the collision groups of their copies, as
+/// they spawn.
+fn isolate_copies(
+    mut commands: Commands,
+    copies: Res<ComparedCopies>,
+    colliders: Query<(Entity, Option<&CollisionGroups>), (With<Collider>, Without<Isolated>)>,
+    links: Query<&Link>,
+    parents: Query<&Parent>,
+) {
+    let mut isolated = BTreeSet::new();
+    for (entity, groups) in &colliders {
+        // The collider of a link, or of one of its children.
+        let link = std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find_map(|entity| links.get(entity).ok());
+        let Some(index) =
+            link.and_then(|link| copies.names.iter().position(|name| *name == link.plant))
+        else {
+            continue;
+        };
+        commands.entity(entity).insert((
+            copy_groups(index, groups.copied().unwrap_or_default()),
+            Isolated,
+        ));
+        isolated.insert(index);
+    }
+    for index in isolated {
+        debug!(
+            target: subsystem::PHYSICS,
+            "Isolated the colliders of the copy {}", copies.names[index]
+        );
+    }
+}
+
+/// Panel to watch a channel of every copy and set a signal on all of them.
+fn comparison_panel(
+    mut contexts: EguiContexts,
+    mut copies: ResMut<ComparedCopies>,
+    telemetry: Res<Telemetry>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    egui::Window::new("Comparison")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Channel");
+                ui.text_edit_singleline(&mut copies.channel);
+            });
+            egui::Grid::new("comparison_copies")
+                .striped(true)
+                .show(ui, |ui| {
+                    for name in &copies.names {
+                        ui.label(name);
+                        match telemetry.latest(&namespaced(name, &copies.channel)) {
+                            Some(value) => ui.monospace(format!("{value:.4}")),
+                            None => ui.weak("-"),
+                        };
+                        ui.end_row();
+                    }
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Signal");
+                ui.text_edit_singleline(&mut copies.signal);
+                ui.add(egui::DragValue::new(&mut copies.value).speed(0.01));
+            });
+            if ui.button("Set on every copy").clicked() {
+                for name in &copies.names {
+                    setpoints.set(&namespaced(name, &copies.signal), copies.value);
+                }
+                info!(
+                    target: subsystem::CONTROL,
+                    "Set {} to {} on every copy", copies.signal, copies.value
+                );
+            }
+        });
+}
@@ -0,0 +1,258 @@
+//! The compliant axis: a motor turning a load through a gearbox and a compliant shaft, as the
+//! axes of industrial machines whose loops are closed around their transmission.
+//!
+//! The rotor of the motor and the load, an arm, turn about the vertical on revolute joints of
+//! their own. The motor is driven by a torque, the `motor/torque` setpoint in N·m within its
+//! limit, and turns the load through a [`Transmission`] of [`GEAR_RATIO`] turns per turn of the
+//! load, the stiffness and the damping of the shaft, so the load lags behind the motor and rings
+//! as it's accelerated. The angles of the motor and of the load, in rad, are recorded as the
+//! `motor/angle` and `load/angle` telemetry channels, and the torque the shaft carries, in N·m,
+//! as `shaft/torque`. Encoders on the `motor` and `load` links give the motor-side and the
+//! load-side readings of a dual-loop drive.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody},
+    sensors::ControlSet,
+    setpoints::Setpoints,
+    telemetry::{Telemetry, MOTOR_ANGLE},
+    transmissions::{Coupler, Transmission},
+};
+
+/// Torque of the motor, in N·m.
+pub const MOTOR_TORQUE: &str = "motor/torque";
+/// Angle of the load, in rad.
+pub const LOAD_ANGLE: &str = "load/angle";
+/// Torque carried by the shaft to the load, in N·m.
+pub const SHAFT_TORQUE: &str = "shaft/torque";
+/// Turns of the motor per turn of the load.
+pub const GEAR_RATIO: f32 = 5.0;
+/// Largest torque of the motor, in N·m.
+pub const TORQUE_LIMIT: f32 = 2.0;
+
+/// The compliant axis, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "compliant_axis",
+        add: |app| {
+            app.add_plugins(CompliantAxisPlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<CompliantAxisPlugin>() {
+                app.add_plugins(CompliantAxisPlugin);
+            }
+            app.insert_resource(CompliantAxisInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(CompliantAxisInstances::default());
+            plants::run_spawn(world, spawn_compliant_axes);
+        },
+    }
+}
+
+pub struct CompliantAxisPlugin;
+
+impl Plugin for CompliantAxisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CompliantAxisInstances>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, spawn_compliant_axes)
+            .add_systems(
+                PostUpdate,
+                drive_axes.after(ControlSet).before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(Update, record_axis_angles);
+    }
+}
+
+/// The axes spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct CompliantAxisInstances(pub Vec<Instance>);
+
+impl Default for CompliantAxisInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The shaft of an axis, on the entity of the joint of its load.
+#[derive(Component)]
+struct Shaft {
+    model: Transmission,
+    coupler: Coupler,
+    /// Joint of the motor.
+    motor: Entity,
+    /// Signals of the instance.
+    torque: String,
+    motor_torque: String,
+    motor_angle: String,
+    load_angle: String,
+}
+
+fn spawn_compliant_axes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<CompliantAxisInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const BASE_HEIGHT: f32 = 0.2;
+    const MOTOR_HEIGHT: f32 = 0.4;
+    const ROTOR_RADIUS: f32 = 0.1;
+    const LOAD_HEIGHT: f32 = 1.0;
+    const ARM_LENGTH: f32 = 1.0;
+    const THICKNESS: f32 = 0.1;
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let base_center = instance.offset + Vec3::Y * BASE_HEIGHT / 2.0;
+        let base = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Mesh3d(meshes.add(Cuboid::new(0.4, BASE_HEIGHT, 0.4))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(base_center),
+            ))
+            .id();
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        // Both turn about the vertical, driven by torques alone.
+        let mut spawn_link =
+            |name: &str, collider: Collider, mesh: Mesh, mass: f32, height: f32| {
+                let body = commands
+                    .spawn((
+                        RigidBody::Dynamic,
+                        link(name),
+                        collider,
+                        ColliderMassProperties::Mass(mass),
+                        Velocity::default(),
+                        ExternalImpulse::default(),
+                        Mesh3d(meshes.add(mesh)),
+                        MeshMaterial3d(material.clone()),
+                        Transform::from_translation(instance.offset + Vec3::Y * height),
+                    ))
+                    .id();
+                let hinge = RevoluteJointBuilder::new(Vec3::Y)
+                    .local_anchor1(Vec3::Y * (height - BASE_HEIGHT / 2.0));
+                commands.entity(body).insert(ImpulseJoint::new(base, hinge));
+                body
+            };
+        let motor = spawn_link(
+            "motor",
+            Collider::cylinder(THICKNESS, ROTOR_RADIUS),
+            Cylinder::new(ROTOR_RADIUS, 2.0 * THICKNESS).into(),
+            1.0,
+            MOTOR_HEIGHT,
+        );
+        let load = spawn_link(
+            "load",
+            Collider::cuboid(ARM_LENGTH / 2.0, THICKNESS / 2.0, THICKNESS / 2.0),
+            Cuboid::new(ARM_LENGTH, THICKNESS, THICKNESS).into(),
+            2.0,
+            LOAD_HEIGHT,
+        );
+        commands.entity(load).insert(Shaft {
+            model: Transmission {
+                driver: "motor".to_string(),
+                driven: "load".to_string(),
+                ratio: GEAR_RATIO,
+                stiffness: 20.0,
+                damping: 0.05,
+                ..default()
+            },
+            coupler: Coupler::default(),
+            motor,
+            torque: instance.signal(SHAFT_TORQUE),
+            motor_torque: instance.signal(MOTOR_TORQUE),
+            motor_angle: instance.signal(MOTOR_ANGLE),
+            load_angle: instance.signal(LOAD_ANGLE),
+        });
+    }
+}
+
+/// Applies the torques of the motors and of the shafts, for the next step of the simulation.
+fn drive_axes(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    mut shafts: Query<(Entity, &mut Shaft)>,
+    velocities: Query<&Velocity>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "drive_axes").entered();
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt == 0.0 {
+        return;
+    }
+    // Angle and speed of the joint of a link, about the vertical.
+    let state = |entity: Entity| {
+        let speed = velocities.get(entity).ok()?.angvel.y;
+        Some([context.impulse_revolute_joint_angle(entity)?, speed])
+    };
+    for (load, mut shaft) in &mut shafts {
+        let (Some(load_state), Some(motor_state)) = (state(load), state(shaft.motor)) else {
+            continue;
+        };
+        let shaft = &mut *shaft;
+        let torque = shaft.coupler.torque(&shaft.model, motor_state, load_state);
+        let motor_torque = setpoints
+            .get(&shaft.motor_torque)
+            .unwrap_or_default()
+            .clamp(-TORQUE_LIMIT, TORQUE_LIMIT);
+        for (entity, torque) in [
+            (load, torque),
+            (
+                shaft.motor,
+                motor_torque + Coupler::driver_torque(&shaft.model, torque),
+            ),
+        ] {
+            // The base is fixed, so it needs no reaction.
+            if let Ok(mut impulse) = impulses.get_mut(entity) {
+                impulse.torque_impulse += torque * Vec3::Y * dt;
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&shaft.torque, clock.elapsed_secs(), torque);
+        }
+    }
+}
+
+/// Records the angles of the motors and of the loads.
+fn record_axis_angles(
+    clock: Res<SimClock>,
+    shafts: Query<(Entity, &Shaft)>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_axis_angles").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    for (load, shaft) in &shafts {
+        for (entity, channel) in [(shaft.motor, &shaft.motor_angle), (load, &shaft.load_angle)] {
+            if let Some(angle) = context.impulse_revolute_joint_angle(entity) {
+                telemetry.record(channel, time, angle);
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Composition of several plants into one world, from a scene composition file, e.g. an arm
+//! feeding a conveyor feeding a second arm.
+//!
+//! Each plant of the composition is an instance of a plant of this build or of its extensions,
+//! placed at an offset, whose
+//! signals are prefixed with its name (`feeder/motor/velocity`). A plant closes its own loops
+//! with PID controllers between its signals, and links copy a signal of a plant to a setpoint
+//! of another, scaled, so the plants step together in the same physics world.
+//!
+//! The links route through the signal bus (the setpoints and the telemetry), so a named
+//! controller also reads its reference from it, e.g. the arm tracking the speed of the conveyor
+//! with a link to `arm/tracking/reference`. The links are evaluated before the controllers, so
+//! a reference routed to a controller applies in the same step.
+//!
+//! A composition can also drive a virtual master encoder, turning at the `master/velocity`
+//! setpoint, that the plants follow through an electronic gear or cam, like the axes of an
+//! electronic line shaft. The phase error of each follower is recorded as its `phase_error`.
+use std::{collections::BTreeMap, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::SimClock,
+    control::{Coupling, Pid, PidGains, Saturation},
+    error::{Error, Result},
+    logging::subsystem,
+    migration,
+    plants::{self, namespaced, Instance, Plant},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_ANGLE},
+};
+
+pub struct CompositionPlugin {
+    pub composition: Composition,
+}
+
+impl Plugin for CompositionPlugin {
+    fn build(&self, app: &mut App) {
+        let available = plants::available();
+        let mut instances: BTreeMap<&str, Vec<Instance>> = BTreeMap::new();
+        for plant in &self.composition.plants {
+            instances
+                .entry(plant.plant.as_str())
+                .or_default()
+                .push(Instance {
+                    namespace: plant.name.clone(),
+                    offset: Vec3::from(plant.offset),
+                });
+        }
+        for (name, instances) in instances {
+            match available.iter().find(|plant| plant.name == name) {
+                Some(plant) => (plant.compose)(app, &instances),
+                None => warn!(target: subsystem::PHYSICS, "Unknown plant {name}"),
+            }
+        }
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(CompositeLoops::new(&self.composition))
+            .add_systems(PreUpdate, step_loops);
+    }
+}
+
+/// A loop closed around a plant, between two of its signals.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LoopSettings {
+    /// Name of the controller; a named one follows its `reference` signal, e.g.
+    /// `tracking/reference`, once a link sets it.
+    #[serde(default)]
+    pub name: String,
+    /// Telemetry channel (or setpoint) measured, e.g. `motor/angle`.
+    pub measurement: String,
+    /// Setpoint commanded, e.g. `motor/velocity`.
+    pub output: String,
+    /// Target of the measurement, until a reference is routed to the controller.
+    #[serde(default)]
+    pub setpoint: f32,
+    pub gains: PidGains,
+    /// Largest magnitude of the output, or 0 for an unlimited one.
+    #[serde(default)]
+    pub limit: f32,
+}
+
+impl LoopSettings {
+    /// Signal routed to the reference of the controller, without the namespace of its plant.
+    pub fn reference(&self) -> Option<String> {
+        (!self.name.is_empty()).then(|| format!("{}/reference", self.name))
+    }
+}
+
+/// Position of the virtual master encoder, in rad.
+pub const MASTER_ANGLE: &str = "master/angle";
+/// Setpoint of the velocity of the virtual master encoder, in rad/s.
+pub const MASTER_VELOCITY: &str = "master/velocity";
+/// Difference between the position commanded to a follower and its measurement.
+pub const PHASE_ERROR: &str = "phase_error";
+
+/// A virtual master encoder, turning at a constant velocity until the `master/velocity`
+/// setpoint changes it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct VirtualMaster {
+    /// Velocity, in rad/s.
+    pub velocity: f32,
+}
+
+/// An axis of a plant following a master: its position is commanded through the coupling,
+/// with the velocity of the coupling fed forward and a PID correcting the phase error.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Follower {
+    /// Position of the master, with the namespace of its plant.
+    #[serde(default = "master_angle")]
+    pub master: String,
+    pub coupling: Coupling,
+    /// Position of the axis, without the namespace of its plant.
+    #[serde(default = "motor_angle")]
+    pub measurement: String,
+    /// Velocity setpoint of the axis, without the namespace of its plant.
+    #[serde(default = "motor_velocity")]
+    pub output: String,
+    pub gains: PidGains,
+    /// Largest magnitude of the correction, or 0 for an unlimited one.
+    #[serde(default)]
+    pub limit: f32,
+}
+
+fn master_angle() -> String {
+    MASTER_ANGLE.to_string()
+}
+
+fn motor_angle() -> String {
+    MOTOR_ANGLE.to_string()
+}
+
+fn motor_velocity() -> String {
+    MOTOR_VELOCITY.to_string()
+}
+
+/// A plant of the composition.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ComposedPlant {
+    /// Namespace of the signals of the plant, unique in the composition.
+    pub name: String,
+    /// Name of the plant, built in or from an extension, e.g. `rotary_pendulum`.
+    pub plant: String,
+    /// Position of the plant in the world.
+    #[serde(default)]
+    pub offset: [f32; 3],
+    #[serde(default)]
+    pub controllers: Vec<LoopSettings>,
+    #[serde(default)]
+    pub follow: Option<Follower>,
+}
+
+/// Copies a signal to a setpoint, e.g. the velocity of a conveyor following the one of the
+/// arm feeding it: `to = gain * from + offset`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SignalLink {
+    /// Telemetry channel (or setpoint), with the namespace of its plant.
+    pub from: String,
+    /// Setpoint, with the namespace of its plant.
+    pub to: String,
+    #[serde(default = "unit_gain")]
+    pub gain: f32,
+    #[serde(default)]
+    pub offset: f32,
+}
+
+fn unit_gain() -> f32 {
+    1.0
+}
+
+/// Represents a scene composition file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Composition {
+    pub plants: Vec<ComposedPlant>,
+    pub links: Vec<SignalLink>,
+    pub master: Option<VirtualMaster>,
+}
+
+impl Composition {
+    pub fn read(path: &Path) -> Result<Self> {
+        migration::COMPOSITION.read(path)
+    }
+
+    /// Checks that the plants are available and uniquely named, and that the links connect
+    /// signals of those plants, each setpoint being driven by a single link or controller.
+    /// Followers need a valid coupling, and the virtual master the `master` namespace.
+    pub fn validate(&self, available: &[Plant]) -> Result<()> {
+        let invalid = |message: String| Error::Config {
+            name: "composition".to_string(),
+            message,
+        };
+        if self.plants.is_empty() {
+            return Err(invalid("no plant to compose".to_string()));
+        }
+        for (index, plant) in self.plants.iter().enumerate() {
+            if !available
+                .iter()
+                .any(|available| available.name == plant.plant)
+            {
+                return Err(invalid(format!(
+                    "{} isn't a plant of this build nor of its extensions",
+                    plant.plant
+                )));
+            }
+            if self.plants[..index]
+                .iter()
+                .any(|other| other.name == plant.name)
+            {
+                return Err(invalid(format!("two plants are named `{}`", plant.name)));
+            }
+            if self.master.is_some() && plant.name == "master" {
+                return Err(invalid(
+                    "`master` names the virtual master encoder".to_string(),
+                ));
+            }
+            if let Some(follower) = &plant.follow {
+                if !follower.coupling.is_valid() {
+                    return Err(invalid(format!("invalid coupling of `{}`", plant.name)));
+                }
+            }
+        }
+
+        // Signals without a namespace belong to the unnamed plant, if any.
+        let unnamed = self.plants.iter().any(|plant| plant.name.is_empty());
+        let owned = |signal: &str| {
+            unnamed
+                || (self.master.is_some() && (signal == MASTER_ANGLE || signal == MASTER_VELOCITY))
+                || signal.split_once('/').is_some_and(|(namespace, _)| {
+                    self.plants.iter().any(|plant| plant.name == namespace)
+                })
+        };
+        let mut driven: Vec<String> = self
+            .plants
+            .iter()
+            .flat_map(|plant| {
+                plant
+                    .controllers
+                    .iter()
+                    .map(|settings| &settings.output)
+                    .chain(plant.follow.iter().map(|follower| &follower.output))
+                    .map(|output| namespaced(&plant.name, output))
+            })
+            .collect();
+        for plant in &self.plants {
+            if let Some(follower) = &plant.follow {
+                if !owned(&follower.master) {
+                    return Err(invalid(format!(
+                        "`{}` isn't a signal of a plant of the composition",
+                        follower.master
+                    )));
+                }
+            }
+        }
+        for link in &self.links {
+            for signal in [&link.from, &link.to] {
+                if !owned(signal) {
+                    return Err(invalid(format!(
+                        "`{signal}` isn't a signal of a plant of the composition"
+                    )));
+                }
+            }
+            if driven.contains(&link.to) {
+                return Err(invalid(format!("`{}` is driven twice", link.to)));
+            }
+            driven.push(link.to.clone());
+        }
+        Ok(())
+    }
+}
+
+/// A controller of a plant, on its namespaced signals.
+#[derive(Debug)]
+struct CompositeLoop {
+    reference: Option<String>,
+    measurement: String,
+    output: String,
+    setpoint: f32,
+    pid: Pid,
+}
+
+/// A follower of a plant, on its namespaced signals.
+#[derive(Debug)]
+struct CompositeFollower {
+    master: String,
+    coupling: Coupling,
+    measurement: String,
+    output: String,
+    phase_error: String,
+    pid: Pid,
+    /// Position of the master at the last step.
+    last_master: Option<f32>,
+}
+
+/// The virtual master encoder, the controllers, the followers and the links of the
+/// composition.
+#[derive(Debug, Resource)]
+pub struct CompositeLoops {
+    master: Option<(VirtualMaster, f32)>,
+    loops: Vec<CompositeLoop>,
+    followers: Vec<CompositeFollower>,
+    links: Vec<SignalLink>,
+}
+
+fn limits(limit: f32) -> Saturation {
+    if limit > 0.0 {
+        Saturation::symmetric(limit)
+    } else {
+        Saturation::default()
+    }
+}
+
+/// Latest value of a telemetry channel, or else of a setpoint.
+fn signal(telemetry: &Telemetry, setpoints: &Setpoints, name: &str) -> Option<f32> {
+    telemetry.latest(name).or_else(|| setpoints.get(name))
+}
+
+impl CompositeLoops {
+    pub fn new(composition: &Composition) -> Self {
+        let loops = composition
+            .plants
+            .iter()
+            .flat_map(|plant| {
+                plant.controllers.iter().map(|settings| CompositeLoop {
+                    reference: settings
+                        .reference()
+                        .map(|reference| namespaced(&plant.name, &reference)),
+                    measurement: namespaced(&plant.name, &settings.measurement),
+                    output: namespaced(&plant.name, &settings.output),
+                    setpoint: settings.setpoint,
+                    pid: Pid::new(settings.gains, limits(settings.limit)),
+                })
+            })
+            .collect();
+        let followers = composition
+            .plants
+            .iter()
+            .filter_map(|plant| {
+                let follower = plant.follow.as_ref()?;
+                Some(CompositeFollower {
+                    master: follower.master.clone(),
+                    coupling: follower.coupling.clone(),
+                    measurement: namespaced(&plant.name, &follower.measurement),
+                    output: namespaced(&plant.name, &follower.output),
+                    phase_error: namespaced(&plant.name, PHASE_ERROR),
+                    pid: Pid::new(follower.gains, limits(follower.limit)),
+                    last_master: None,
+                })
+            })
+            .collect();
+        Self {
+            master: composition.master.clone().map(|master| (master, 0.0)),
+            loops,
+            followers,
+            links: composition.links.clone(),
+        }
+    }
+
+    /// Advances the virtual master, then updates the links, the followers and the controllers,
+    /// for a step of `dt` seconds starting at the simulated time `time`.
+    pub fn step(
+        &mut self,
+        telemetry: &mut Telemetry,
+        setpoints: &mut Setpoints,
+        time: f32,
+        dt: f32,
+    ) {
+        if let Some((master, angle)) = &mut self.master {
+            *angle += setpoints.get(MASTER_VELOCITY).unwrap_or(master.velocity) * dt;
+            telemetry.record(MASTER_ANGLE, time, *angle);
+        }
+        for link in &self.links {
+            if let Some(value) = signal(telemetry, setpoints, &link.from) {
+                setpoints.set(&link.to, link.gain * value + link.offset);
+            }
+        }
+        for follower in &mut self.followers {
+            let (Some(master), Some(measurement)) = (
+                signal(telemetry, setpoints, &follower.master),
+                signal(telemetry, setpoints, &follower.measurement),
+            ) else {
+                continue;
+            };
+            let master_velocity = follower
+                .last_master
+                .map_or(0.0, |last| (master - last) / dt);
+            follower.last_master = Some(master);
+            let (position, velocity) = follower.coupling.follow(master, master_velocity);
+            let correction = follower.pid.update(position, measurement, dt);
+            setpoints.set(&follower.output, velocity + correction);
+            telemetry.record(&follower.phase_error, time, position - measurement);
+        }
+        for control in &mut self.loops {
+            let reference = control
+                .reference
+                .as_deref()
+                .and_then(|reference| setpoints.get(reference))
+                .unwrap_or(control.setpoint);
+            if let Some(measurement) = signal(telemetry, setpoints, &control.measurement) {
+                let output = control.pid.update(reference, measurement, dt);
+                setpoints.set(&control.output, output);
+            }
+        }
+    }
+}
+
+/// Steps the composition before the physics.
+fn step_loops(
+    clock: Res<SimClock>,
+    mut telemetry: ResMut<Telemetry>,
+    mut loops: ResMut<CompositeLoops>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    let dt = clock.delta_secs();
+    if dt > 0.0 {
+        loops.step(&mut telemetry, &mut setpoints, clock.elapsed_secs(), dt);
+    }
+}
@@ -1,11 +1,14 @@
 //! This file contains the implementation of a Bevy plugin for managing configuration settings.
 //! It defines the `ConfigPlugin` struct and implements the `Plugin` trait for it.
 //! The plugin adds systems for startup and setup to the Bevy application.
-//! The `setup` function initializes the key bindings resource using the `Persistent` builder.
-use bevy::prelude::*;
+//! The `setup` function initializes the key bindings and lighting resources using the
+//! `Persistent` builder.
+use bevy::{pbr::light_consts, prelude::*};
 use bevy_persistent::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::path::Path;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, ErrorEvent};
 
 pub struct ConfigPlugin;
 
@@ -22,21 +25,144 @@ pub struct KeyBindings {
     pub rotate_counter_clockwise: KeyCode,
 }
 
-/// Sets up the key bindings resource using the `Persistent` builder.
-fn setup(mut commands: Commands) {
-    let config_dir = dirs::config_dir()
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            rotate_clockwise: KeyCode::ArrowLeft,
+            rotate_counter_clockwise: KeyCode::ArrowRight,
+        }
+    }
+}
+
+/// Represents the lighting configuration of the scene.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct LightingSettings {
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    pub environment_map: EnvironmentMap,
+    pub environment_intensity: f32,
+    /// Spawn a directional light when the scene doesn't provide one.
+    pub directional_light: bool,
+    pub directional_illuminance: f32,
+    /// Rotation of the directional light around the vertical axis, in degrees.
+    pub directional_yaw: f32,
+    /// Elevation of the directional light, in degrees (negative points downwards).
+    pub directional_pitch: f32,
+    pub shadows_enabled: bool,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            ambient_color: Color::WHITE,
+            ambient_brightness: 2_000.0,
+            environment_map: EnvironmentMap::Pisa,
+            environment_intensity: 900.0,
+            directional_light: true,
+            directional_illuminance: light_consts::lux::OVERCAST_DAY,
+            directional_yaw: 30.0,
+            directional_pitch: -45.0,
+            shadows_enabled: false,
+        }
+    }
+}
+
+/// The HDRI used to light the scene through an [`EnvironmentMapLight`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum EnvironmentMap {
+    None,
+    #[default]
+    Pisa,
+    /// Pre-filtered KTX2 cubemaps, relative to the asset directory.
+    Custom {
+        diffuse: String,
+        specular: String,
+    },
+}
+
+impl EnvironmentMap {
+    /// Returns the diffuse and specular map paths, if any.
+    pub fn paths(&self) -> Option<(&str, &str)> {
+        match self {
+            EnvironmentMap::None => None,
+            EnvironmentMap::Pisa => Some((
+                "assets/environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2",
+                "assets/environment_maps/pisa_specular_rgb9e5_zstd.ktx2",
+            )),
+            EnvironmentMap::Custom { diffuse, specular } => Some((diffuse, specular)),
+        }
+    }
+}
+
+/// Directory where the configuration files are stored.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
         .map(|native_config_dir| native_config_dir.join(env!("CARGO_PKG_NAME")))
-        .unwrap_or(Path::new("local").join("configuration")); // Fallback to `local/configuration` when using WebAssembly
-    commands.insert_resource(
-        Persistent::<KeyBindings>::builder()
-            .name("key_bindings")
+        .unwrap_or(Path::new("local").join("configuration")) // Fallback to `local/configuration` when using WebAssembly
+}
+
+/// Directory where the data produced by the runs (sessions, recordings) is stored.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|native_data_dir| native_data_dir.join(env!("CARGO_PKG_NAME")))
+        .unwrap_or(Path::new("local").join("data"))
+}
+
+/// Directory used when the configuration directory can't be written.
+fn fallback_config_dir() -> PathBuf {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
+    #[cfg(target_arch = "wasm32")]
+    return Path::new("session").join("configuration");
+}
+
+/// Loads the configuration file `<name>.json` using the `Persistent` builder.
+///
+/// A file that can't be read doesn't prevent the application from starting: the defaults are
+/// used instead, and the error is returned along with them to be reported. The invalid file is
+/// kept next to the configuration, with the `.invalid` extension.
+pub fn load_config<R>(name: &str, revertible: bool) -> (Persistent<R>, Option<Error>)
+where
+    R: Default + Resource + Serialize + DeserializeOwned,
+{
+    let path = config_dir().join(format!("{name}.json"));
+    let build = |path: PathBuf, revert_on_errors: bool| {
+        Persistent::<R>::builder()
+            .name(name)
             .format(StorageFormat::Json)
-            .path(config_dir.join("key_bindings.json"))
-            .default(KeyBindings {
-                rotate_clockwise: KeyCode::ArrowLeft,
-                rotate_counter_clockwise: KeyCode::ArrowRight,
-            })
+            .path(path)
+            .default(R::default())
+            .revertible(revertible || revert_on_errors)
+            .revert_to_default_on_deserialization_errors(revert_on_errors)
             .build()
-            .expect("Failed to initialize key bindings."),
-    )
+    };
+    let error = match build(path.clone(), false) {
+        Ok(resource) => return (resource, None),
+        Err(error) => Error::Config {
+            name: name.to_string(),
+            message: error.to_string(),
+        },
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(copy_error) = std::fs::copy(&path, path.with_extension("json.invalid")) {
+        warn!(
+            "Failed to keep the invalid {}: {copy_error}",
+            path.display()
+        );
+    }
+    let resource = build(path, true)
+        .or_else(|_| build(fallback_config_dir().join(format!("{name}.json")), true))
+        .unwrap_or_else(|error| panic!("Failed to initialize {name}: {error}"));
+    (resource, Some(error))
+}
+
+/// Sets up the configuration resources using the `Persistent` builder.
+fn setup(mut commands: Commands) {
+    let (key_bindings, key_bindings_error) = load_config::<KeyBindings>("key_bindings", false);
+    let (lighting, lighting_error) = load_config::<LightingSettings>("lighting", true);
+    commands.insert_resource(key_bindings);
+    commands.insert_resource(lighting);
+    for error in [key_bindings_error, lighting_error].into_iter().flatten() {
+        commands.send_event(ErrorEvent::from(error));
+    }
 }
@@ -0,0 +1,395 @@
+//! This module reports the contacts of the links, so controllers that must avoid self-collisions
+//! or end-stop impacts can be evaluated by how often, how hard and how long they hit.
+//!
+//! After each step of the simulation, the contacts of the physics involving a [`Link`] are
+//! followed from the step they start to the step they end: the force between the two bodies
+//! is recorded as the `contact/<first>-<second>` telemetry channel while they touch, and each
+//! contact, with its peak force and its duration, is logged when it ends and kept in the
+//! history of the *Contacts* panel. The bodies in contact can be flashed in the scene. The
+//! links joined to each other touch at their joints, so they're left out by default.
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::{subsystem, LogLevel},
+    plants::Link,
+    telemetry::Telemetry,
+    theme::Theme,
+};
+
+/// Prefix of the telemetry channels of the contact forces.
+pub const CONTACT_PREFIX: &str = "contact/";
+/// Number of ended contacts kept in the history.
+pub const HISTORY_LENGTH: usize = 100;
+
+pub struct ContactsPlugin;
+
+impl Plugin for ContactsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Contacts>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                follow_contacts
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<ContactSettings>>),
+            )
+            .add_systems(
+                Update,
+                (flash_contacts, contacts_panel)
+                    .run_if(resource_exists::<Persistent<ContactSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the contact reports.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ContactSettings {
+    pub enabled: bool,
+    /// Whether the contacts between links joined to each other are left out.
+    pub exclude_adjacent: bool,
+    /// Force under which bodies touching aren't in contact, in N.
+    pub min_force: f32,
+    /// Whether the bodies in contact are flashed in the scene.
+    pub flash: bool,
+    /// How long the bodies stay flashed after a contact ends, in s.
+    pub flash_time: f32,
+}
+
+impl Default for ContactSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exclude_adjacent: true,
+            min_force: 0.0,
+            flash: true,
+            flash_time: 0.3,
+        }
+    }
+}
+
+/// A contact between two bodies, one of them at least a link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contact {
+    pub bodies: [Entity; 2],
+    /// Names of the bodies: the paths of the links.
+    pub names: [String; 2],
+    /// Simulated time the contact started at, in s.
+    pub start: f32,
+    /// Simulated time since the start, in s, up to the end once it ended.
+    pub duration: f32,
+    /// Force between the bodies at the last step, in N.
+    pub force: f32,
+    /// Strongest force between the bodies, in N.
+    pub peak_force: f32,
+}
+
+impl Contact {
+    pub fn new(bodies: [Entity; 2], names: [String; 2], time: f32, force: f32) -> Self {
+        Self {
+            bodies,
+            names,
+            start: time,
+            duration: 0.0,
+            force,
+            peak_force: force,
+        }
+    }
+
+    /// Updates the contact still going on at `time` (s) with a force of `force` (N).
+    pub fn update(&mut self, time: f32, force: f32) {
+        self.duration = time - self.start;
+        self.force = force;
+        self.peak_force = self.peak_force.max(force);
+    }
+
+    /// Telemetry channel of the force.
+    pub fn channel(&self) -> String {
+        let [first, second] = &self.names;
+        format!("{CONTACT_PREFIX}{first}-{second}")
+    }
+}
+
+/// The contacts going on at the last step, by their bodies, and the ones that ended, the latest
+/// last.
+#[derive(Debug, Default, Resource)]
+pub struct Contacts {
+    pub current: BTreeMap<[Entity; 2], Contact>,
+    pub history: VecDeque<Contact>,
+    /// Number of contacts started since the history was cleared.
+    pub count: usize,
+}
+
+impl Contacts {
+    /// Ends the contact between `bodies` at `time` (s), moving it to the history.
+    pub fn end(&mut self, bodies: [Entity; 2], time: f32) -> Option<&Contact> {
+        let mut contact = self.current.remove(&bodies)?;
+        contact.duration = time - contact.start;
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(contact);
+        self.history.back()
+    }
+
+    /// Clears the history and the count.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.count = 0;
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ContactSettings>("contacts", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Follows the contacts of the links after each step of the simulation, recording their forces
+/// and logging them as they end.
+fn follow_contacts(
+    settings: Res<Persistent<ContactSettings>>,
+    clock: Res<SimClock>,
+    contexts: Query<&RapierContext>,
+    links: Query<(&Link, Option<&ImpulseJoint>)>,
+    names: Query<&Name>,
+    mut contacts: ResMut<Contacts>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    if clock.delta_secs() == 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    let mut touching = BTreeMap::new();
+    if let (true, Ok(context)) = (settings.enabled, contexts.get_single()) {
+        let dt = context.integration_parameters.dt;
+        let body = |collider: Entity| context.collider_parent(collider).unwrap_or(collider);
+        let name = |entity: Entity| match (links.get(entity), names.get(entity)) {
+            (Ok((link, _)), _) => link.path(),
+            (_, Ok(name)) => name.to_string(),
+            _ => entity.to_string(),
+        };
+        let joined = |a: Entity, b: Entity| {
+            let parent = |entity: Entity| {
+                links
+                    .get(entity)
+                    .ok()
+                    .and_then(|(_, joint)| joint)
+                    .map(|joint| joint.parent)
+            };
+            parent(a) == Some(b) || parent(b) == Some(a)
+        };
+        for pair in context.contact_pairs() {
+            if !pair.has_any_active_contact() {
+                continue;
+            }
+            let (a, b) = (body(pair.collider1()), body(pair.collider2()));
+            if a == b || (!links.contains(a) && !links.contains(b)) {
+                continue;
+            }
+            if settings.exclude_adjacent && joined(a, b) {
+                continue;
+            }
+            let impulse = pair
+                .manifolds()
+                .map(|manifold| {
+                    manifold
+                        .points()
+                        .map(|contact| contact.impulse())
+                        .sum::<f32>()
+                })
+                .sum::<f32>();
+            let force = if dt > 0.0 { impulse / dt } else { 0.0 };
+            if force < settings.min_force {
+                continue;
+            }
+            let bodies = if a < b { [a, b] } else { [b, a] };
+            *touching.entry(bodies).or_insert(0.0) += force;
+            if !contacts.current.contains_key(&bodies) {
+                let contact = Contact::new(bodies, bodies.map(name), time, 0.0);
+                let [first, second] = &contact.names;
+                info!(target: subsystem::PHYSICS, "Contact of {first} and {second}");
+                contacts.current.insert(bodies, contact);
+                contacts.count += 1;
+            }
+        }
+    }
+
+    let ended: Vec<[Entity; 2]> = contacts
+        .current
+        .keys()
+        .filter(|bodies| !touching.contains_key(*bodies))
+        .copied()
+        .collect();
+    for bodies in ended {
+        let Some(contact) = contacts.end(bodies, time) else {
+            continue;
+        };
+        let [first, second] = &contact.names;
+        info!(
+            target: subsystem::PHYSICS,
+            "Contact of {first} and {second} over {:.3} s, peak force {:.2} N",
+            contact.duration,
+            contact.peak_force
+        );
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&contact.channel(), time, 0.0);
+        }
+    }
+    for (bodies, force) in touching {
+        let Some(contact) = contacts.current.get_mut(&bodies) else {
+            continue;
+        };
+        contact.update(time, force);
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&contact.channel(), time, force);
+        }
+    }
+}
+
+/// Flashes the bodies in contact, and those whose contact just ended.
+fn flash_contacts(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<ContactSettings>>,
+    clock: Res<SimClock>,
+    contacts: Res<Contacts>,
+    bodies: Query<&GlobalTransform>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    if !settings.enabled || !settings.flash {
+        return;
+    }
+    let color = theme.map_or_else(
+        || Theme::default().log_level(LogLevel::Warn),
+        |theme| theme.log_level(LogLevel::Warn),
+    );
+    let time = clock.elapsed_secs();
+    let recent = contacts
+        .history
+        .iter()
+        .rev()
+        .take_while(|contact| time - (contact.start + contact.duration) < settings.flash_time);
+    for contact in contacts.current.values().chain(recent) {
+        for body in contact.bodies {
+            if let Ok(transform) = bodies.get(body) {
+                gizmos.sphere(
+                    Isometry3d::from_translation(transform.translation()),
+                    0.1,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Panel to configure the reports, with the contacts going on and the history.
+fn contacts_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ContactSettings>>,
+    mut contacts: ResMut<Contacts>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Contacts")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Report the contacts of the links");
+            ui.checkbox(&mut edited.exclude_adjacent, "Leave out the joined links")
+                .on_hover_text("Links joined to each other touch at their joint");
+            ui.add(
+                egui::DragValue::new(&mut edited.min_force)
+                    .range(0.0..=10000.0)
+                    .speed(0.1)
+                    .prefix("Minimum force: ")
+                    .suffix(" N"),
+            )
+            .on_hover_text("Bodies touching with less force aren't in contact");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut edited.flash, "Flash the bodies in contact");
+                ui.add_enabled(
+                    edited.flash,
+                    egui::DragValue::new(&mut edited.flash_time)
+                        .range(0.0..=10.0)
+                        .speed(0.01)
+                        .prefix("for ")
+                        .suffix(" s"),
+                );
+            });
+
+            ui.separator();
+            if contacts.current.is_empty() {
+                ui.label("No contact.");
+            }
+            for contact in contacts.current.values() {
+                let [first, second] = &contact.names;
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "{first} and {second}: {:.2} N for {:.3} s",
+                        contact.force, contact.duration
+                    ),
+                );
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(format!("{} contacts", contacts.count));
+                if ui.button("Clear").clicked() {
+                    contacts.clear();
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("contact_history")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Bodies");
+                            ui.strong("Start");
+                            ui.strong("Duration");
+                            ui.strong("Peak force");
+                            ui.end_row();
+                            for contact in contacts.history.iter().rev() {
+                                let [first, second] = &contact.names;
+                                ui.label(format!("{first} and {second}"));
+                                ui.label(format!("{:.3} s", contact.start));
+                                ui.label(format!("{:.3} s", contact.duration));
+                                ui.label(format!("{:.2} N", contact.peak_force));
+                                ui.end_row();
+                            }
+                        });
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("contacts", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("contacts", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
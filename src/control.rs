@@ -0,0 +1,49 @@
+//! Control blocks used to close loops around the simulated plants.
+//!
+//! The blocks are plain discrete-time structures, independent of Bevy, so they can be reused by
+//! systems, tools and tests alike.
+mod back_emf;
+mod collocation;
+mod cross_coupling;
+mod dual_loop;
+mod filter;
+mod gearing;
+mod lqr;
+mod mpc;
+mod nmpc;
+mod observer;
+mod path;
+mod pid;
+mod qp;
+mod relay;
+mod saturation;
+mod shaper;
+mod smith_predictor;
+#[cfg(feature = "mcp-core")]
+pub mod strategies;
+mod trajectory;
+mod turns;
+
+pub use back_emf::BackEmfObserver;
+pub use collocation::{Collocation, SwingUp};
+pub use cross_coupling::CrossCoupling;
+pub use dual_loop::DualLoop;
+pub use filter::{Discretization, LowPassFilter, NotchFilter};
+pub use gearing::Coupling;
+pub use lqr::{continuous_riccati, discretize, riccati, Lqr};
+pub use mpc::Mpc;
+pub use nmpc::{Nmpc, RotaryPendulum};
+pub use observer::{poles, Observer, Pole};
+pub use path::{BlendedPath, MAX_BLEND_TURN};
+pub use pid::{Pid, PidGains};
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+pub use qp::Osqp;
+pub use qp::{InteriorPoint, ProjectedGradient, QpData, QpProblem, QpSolution, QpSolver, QpStatus};
+pub use relay::{RelayFeedback, TuningRule, UltimateGain};
+pub use saturation::Saturation;
+pub use shaper::{InputShaper, Shaper};
+pub use smith_predictor::SmithPredictor;
+pub use trajectory::{
+    MotionLimits, MotionState, Profile, ProfileKind, SCurveProfile, Trajectory, TrapezoidalProfile,
+};
+pub(crate) use turns::unwrap_angle;
@@ -0,0 +1,73 @@
+/// Estimator of the speed of a DC motor from its terminal voltage and current, without an
+/// encoder.
+///
+/// The back-EMF of the motor, what's left of the voltage after the drops across its resistance
+/// and inductance, is proportional to its speed. The estimate goes through a first-order lag,
+/// as the derivative of the current amplifies the quantization of its measurement. An error in
+/// the resistance shows most at low speed under load, where the back-EMF is small next to the
+/// drop across the winding.
+#[derive(Clone, Debug)]
+pub struct BackEmfObserver {
+    /// Resistance of the winding, in Ω.
+    pub resistance: f32,
+    /// Inductance of the winding, in H.
+    pub inductance: f32,
+    /// Back-EMF per unit of speed, in V·s/rad.
+    pub back_emf_constant: f32,
+    /// Time constant of the filter of the estimate, in seconds.
+    pub time_constant: f32,
+    previous_current: Option<f32>,
+    estimate: f32,
+}
+
+impl BackEmfObserver {
+    pub fn new(
+        resistance: f32,
+        inductance: f32,
+        back_emf_constant: f32,
+        time_constant: f32,
+    ) -> Self {
+        Self {
+            resistance,
+            inductance,
+            back_emf_constant,
+            time_constant,
+            previous_current: None,
+            estimate: 0.0,
+        }
+    }
+
+    /// Speed estimated at the last update, in rad/s.
+    pub fn estimate(&self) -> f32 {
+        self.estimate
+    }
+
+    /// Clears the estimate and the last current.
+    pub fn reset(&mut self) {
+        self.previous_current = None;
+        self.estimate = 0.0;
+    }
+
+    /// Estimates the speed from the `voltage` and `current` measured after `dt` seconds.
+    pub fn update(&mut self, voltage: f32, current: f32, dt: f32) -> f32 {
+        let rate = match self.previous_current {
+            Some(previous) if dt > 0.0 => (current - previous) / dt,
+            _ => 0.0,
+        };
+        self.previous_current = Some(current);
+        if self.back_emf_constant == 0.0 {
+            return self.estimate;
+        }
+        let back_emf = voltage - self.resistance * current - self.inductance * rate;
+        let speed = back_emf / self.back_emf_constant;
+
+        // Exact discretization of the lag, held over the sample.
+        let decay = if self.time_constant > 0.0 {
+            (-dt / self.time_constant).exp()
+        } else {
+            0.0
+        };
+        self.estimate = decay * self.estimate + (1.0 - decay) * speed;
+        self.estimate
+    }
+}
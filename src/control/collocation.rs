@@ -0,0 +1,277 @@
+use nalgebra::{DMatrix, DVector, Matrix4, Matrix4xX, Vector3, Vector4};
+
+use super::{InteriorPoint, QpData, QpProblem, QpSolver, RotaryPendulum};
+
+/// Weight of the distance to upright at rest at the end of a swing-up, large against the cost
+/// of the accelerations.
+const TERMINAL_WEIGHT: f64 = 1e6;
+/// Weight of the defects of the collocation in the merit of a step of the SQP.
+const PENALTY: f64 = 100.0;
+/// Shortest fraction of a step of the SQP tried by the line search.
+const SHORTEST_STEP: f64 = 1e-2;
+/// Largest defect, and distance to upright at rest at the end, of a swing-up that gets there.
+const REACHED: f64 = 1e-3;
+/// Precision of the shortest duration of a swing-up, in s.
+const DURATION_TOLERANCE: f64 = 1e-2;
+
+/// Offline optimizer of the swing-up of a [`RotaryPendulum`] by direct collocation: the
+/// accelerations of the arm bringing the pendulum from a state to upright at rest, the arm
+/// anywhere, within the acceleration limit.
+///
+/// The swing-up is cut into `segments` of equal duration. The states and the accelerations at
+/// their ends, the nodes, are the variables; the acceleration changes linearly over a segment,
+/// and the dynamics hold by the trapezoidal rule between the nodes, their defects being zero at
+/// the solution. The program is solved by sequential quadratic programming, as that of the
+/// [`Nmpc`](super::Nmpc): each iteration linearizes the defects, eliminates the states node
+/// after node, solves the resulting program in the accelerations with an [`InteriorPoint`]
+/// solver and takes the step, or the fraction of it that improves the cost and the defects.
+///
+/// The minimum-energy swing-up minimizes the integral of the squared acceleration over a given
+/// duration. The minimum-time one is the shortest duration with a swing-up, found by bisection.
+#[derive(Clone, Debug)]
+pub struct Collocation {
+    pub model: RotaryPendulum,
+    pub segments: usize,
+    /// Iterations of the SQP at most, and the largest change of the accelerations, in rad/s²,
+    /// under which it has converged.
+    pub max_iterations: usize,
+    pub tolerance: f64,
+    limit: f64,
+}
+
+impl Collocation {
+    /// Sets up the optimizer of `model` over `segments`, with the acceleration within `limit`,
+    /// or `None` when they don't make sense.
+    pub fn new(model: RotaryPendulum, segments: usize, limit: f64) -> Option<Self> {
+        if segments == 0 || limit <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            model,
+            segments,
+            max_iterations: 100,
+            tolerance: 1e-4,
+            limit,
+        })
+    }
+
+    /// Largest acceleration of the arm, in rad/s².
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+
+    /// The swing-up from `start` over `duration` seconds with the least squared acceleration,
+    /// or `None` when it can't get upright in that time.
+    pub fn minimum_energy(&self, start: &Vector4<f64>, duration: f64) -> Option<SwingUp> {
+        if duration <= 0.0 {
+            return None;
+        }
+        Some(self.optimize(start, duration)).filter(SwingUp::reaches)
+    }
+
+    /// The fastest swing-up from `start`, lasting `max_duration` seconds at most, or `None`
+    /// when it can't get upright in that time.
+    pub fn minimum_time(&self, start: &Vector4<f64>, max_duration: f64) -> Option<SwingUp> {
+        let mut fastest = self.minimum_energy(start, max_duration)?;
+        let mut shortest = 0.0;
+        while fastest.duration - shortest > DURATION_TOLERANCE {
+            let duration = 0.5 * (shortest + fastest.duration);
+            match self.minimum_energy(start, duration) {
+                Some(swing_up) => fastest = swing_up,
+                None => shortest = duration,
+            }
+        }
+        Some(fastest)
+    }
+
+    /// The swing-up from `start` over `duration` seconds the SQP converges to, whether or not it
+    /// gets upright.
+    fn optimize(&self, start: &Vector4<f64>, duration: f64) -> SwingUp {
+        let n = self.segments;
+        let h = duration / n as f64;
+        // Start from the pendulum turning up steadily, the arm at rest.
+        let mut states: Vec<Vector4<f64>> = (0..=n)
+            .map(|node| {
+                let remaining = 1.0 - node as f64 / n as f64;
+                Vector4::new(start[0], 0.0, start[2] * remaining, -start[2] / duration)
+            })
+            .collect();
+        states[0] = *start;
+        let mut accelerations = DVector::zeros(n + 1);
+        // Weights of the accelerations at the nodes in the integral, by the trapezoidal rule.
+        let mut weights = DVector::from_element(n + 1, h);
+        weights[0] = h / 2.0;
+        weights[n] = h / 2.0;
+        let mut solver = InteriorPoint::default();
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            iterations += 1;
+            // The changes of the states are `responses · changes of the accelerations +
+            // offsets`, the offsets closing the defects of the linearized segments.
+            let mut responses = vec![Matrix4xX::<f64>::zeros(n + 1); n + 1];
+            let mut offsets = vec![Vector4::<f64>::zeros(); n + 1];
+            for node in 0..n {
+                let (a0, b0) = self.model.jacobians(&states[node], accelerations[node]);
+                let (a1, b1) = self
+                    .model
+                    .jacobians(&states[node + 1], accelerations[node + 1]);
+                let Some(implicit) = (Matrix4::identity() - a1 * (h / 2.0)).try_inverse() else {
+                    // Segments too long for the pendulum: keep the trajectory.
+                    return self.swing_up(states, &accelerations, duration, iterations);
+                };
+                let explicit = Matrix4::identity() + a0 * (h / 2.0);
+                let mut response = explicit * &responses[node];
+                response.column_mut(node).axpy(h / 2.0, &b0, 1.0);
+                response.column_mut(node + 1).axpy(h / 2.0, &b1, 1.0);
+                responses[node + 1] = implicit * response;
+                let defect = self.defect(&states, &accelerations, node, h);
+                offsets[node + 1] = implicit * (explicit * offsets[node] - defect);
+            }
+
+            // Cost of the accelerations, and of the distance to upright at rest at the end.
+            let end = responses[n].fixed_rows::<3>(1);
+            let distance: Vector3<f64> = (states[n] + offsets[n]).fixed_rows::<3>(1).into_owned();
+            let hessian =
+                DMatrix::from_diagonal(&weights) + end.transpose() * end * TERMINAL_WEIGHT;
+            let linear = weights.component_mul(&accelerations)
+                + end.transpose() * distance * TERMINAL_WEIGHT;
+            let problem = QpProblem {
+                hessian: (&hessian + hessian.transpose()) * 0.5,
+                constraints: DMatrix::zeros(0, n + 1),
+            };
+            if solver.setup(&problem).is_err() {
+                break;
+            }
+            let lower = accelerations.map(|acceleration| -self.limit - acceleration);
+            let upper = accelerations.map(|acceleration| self.limit - acceleration);
+            let unconstrained = DVector::zeros(0);
+            let solution = solver.solve(
+                &QpData {
+                    linear: &linear,
+                    lower: &lower,
+                    upper: &upper,
+                    constraint_lower: &unconstrained,
+                    constraint_upper: &unconstrained,
+                },
+                &DVector::zeros(n + 1),
+            );
+
+            // Backtrack until the step improves the merit.
+            let changes: Vec<Vector4<f64>> = (0..=n)
+                .map(|node| &responses[node] * &solution.x + offsets[node])
+                .collect();
+            let merit = self.merit(&states, &accelerations, &weights, h);
+            let mut fraction = 1.0;
+            loop {
+                let tried = (&accelerations + &solution.x * fraction)
+                    .map(|acceleration| acceleration.clamp(-self.limit, self.limit));
+                let moved: Vec<Vector4<f64>> = states
+                    .iter()
+                    .zip(&changes)
+                    .map(|(state, change)| state + change * fraction)
+                    .collect();
+                if self.merit(&moved, &tried, &weights, h) < merit || fraction < SHORTEST_STEP {
+                    accelerations = tried;
+                    states = moved;
+                    break;
+                }
+                fraction /= 2.0;
+            }
+            if solution.x.amax() * fraction < self.tolerance {
+                break;
+            }
+        }
+        self.swing_up(states, &accelerations, duration, iterations)
+    }
+
+    /// Defect of the trapezoidal rule over the segment from `node`.
+    fn defect(
+        &self,
+        states: &[Vector4<f64>],
+        accelerations: &DVector<f64>,
+        node: usize,
+        h: f64,
+    ) -> Vector4<f64> {
+        let before = self.model.derivative(&states[node], accelerations[node]);
+        let after = self
+            .model
+            .derivative(&states[node + 1], accelerations[node + 1]);
+        states[node + 1] - states[node] - (before + after) * (h / 2.0)
+    }
+
+    /// Cost of a trajectory, plus the weighted defects of its segments.
+    fn merit(
+        &self,
+        states: &[Vector4<f64>],
+        accelerations: &DVector<f64>,
+        weights: &DVector<f64>,
+        h: f64,
+    ) -> f64 {
+        let defects: f64 = (0..self.segments)
+            .map(|node| self.defect(states, accelerations, node, h).abs().sum())
+            .sum();
+        let distance = states[self.segments].fixed_rows::<3>(1).norm_squared();
+        0.5 * weights.dot(&accelerations.component_mul(accelerations))
+            + 0.5 * TERMINAL_WEIGHT * distance
+            + PENALTY * defects
+    }
+
+    fn swing_up(
+        &self,
+        states: Vec<Vector4<f64>>,
+        accelerations: &DVector<f64>,
+        duration: f64,
+        iterations: usize,
+    ) -> SwingUp {
+        let h = duration / self.segments as f64;
+        let defect = (0..self.segments)
+            .map(|node| self.defect(&states, accelerations, node, h).amax())
+            .fold(0.0, f64::max);
+        SwingUp {
+            duration,
+            distance: states[self.segments].fixed_rows::<3>(1).amax(),
+            states,
+            accelerations: accelerations.iter().copied().collect(),
+            iterations,
+            defect,
+        }
+    }
+}
+
+/// A swing-up planned by a [`Collocation`]: the states and the accelerations of the arm at the
+/// nodes, evenly spaced over its duration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwingUp {
+    /// Duration, in s.
+    pub duration: f64,
+    pub states: Vec<Vector4<f64>>,
+    /// Accelerations, in rad/s².
+    pub accelerations: Vec<f64>,
+    /// Iterations of the SQP it took.
+    pub iterations: usize,
+    /// Largest defect of the collocation, and distance to upright at rest at the end.
+    pub defect: f64,
+    pub distance: f64,
+}
+
+impl SwingUp {
+    /// Whether it gets upright at rest, with the dynamics met.
+    pub fn reaches(&self) -> bool {
+        self.defect < REACHED && self.distance < REACHED
+    }
+
+    /// Time of each node, in s.
+    pub fn times(&self) -> impl Iterator<Item = f64> + '_ {
+        let segments = (self.states.len() - 1) as f64;
+        (0..self.states.len()).map(move |node| self.duration * node as f64 / segments)
+    }
+
+    /// Integral of the squared acceleration, in rad²/s³.
+    pub fn effort(&self) -> f64 {
+        let h = self.duration / (self.states.len() - 1) as f64;
+        self.accelerations
+            .windows(2)
+            .map(|pair| 0.5 * h * (pair[0] * pair[0] + pair[1] * pair[1]))
+            .sum()
+    }
+}
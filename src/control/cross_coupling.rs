@@ -0,0 +1,68 @@
+use super::Pid;
+
+/// Two position loops coupled through their contour error, as on gantries: the axes correct
+/// together the error that matters for the part, instead of each its own.
+///
+/// The contour error is a weighted sum of the tracking errors of the axes. For the two parallel
+/// axes of a gantry it is their synchronization error, which racks the beam; for two orthogonal
+/// axes following a straight line, it is the distance to the line. The coupling loop turns it
+/// into a correction added to each axis along its weight, so the axes stop diverging even while
+/// each tracks its own setpoint with a lag.
+#[derive(Clone, Debug, Default)]
+pub struct CrossCoupling {
+    pub axes: [Pid; 2],
+    /// From the contour error to the correction of the axes.
+    pub coupling: Pid,
+    /// Weight of the error of each axis in the contour error.
+    pub weights: [f32; 2],
+    contour_error: f32,
+}
+
+impl CrossCoupling {
+    /// Couples two parallel axes carrying the same load, whose contour error is the difference
+    /// of their positions.
+    pub fn parallel(axes: [Pid; 2], coupling: Pid) -> Self {
+        Self::new(axes, coupling, [1.0, -1.0])
+    }
+
+    /// Couples two orthogonal axes following a straight line at `angle` rad from the first
+    /// axis, whose contour error is the distance to the line.
+    pub fn linear(axes: [Pid; 2], coupling: Pid, angle: f32) -> Self {
+        Self::new(axes, coupling, [-angle.sin(), angle.cos()])
+    }
+
+    pub fn new(axes: [Pid; 2], coupling: Pid, weights: [f32; 2]) -> Self {
+        Self {
+            axes,
+            coupling,
+            weights,
+            contour_error: 0.0,
+        }
+    }
+
+    /// Contour error at the last update.
+    pub fn contour_error(&self) -> f32 {
+        self.contour_error
+    }
+
+    /// Clears every loop.
+    pub fn reset(&mut self) {
+        self.axes.iter_mut().for_each(Pid::reset);
+        self.coupling.reset();
+        self.contour_error = 0.0;
+    }
+
+    /// Computes the commands of both axes for one sample period of `dt` seconds.
+    pub fn update(&mut self, setpoints: [f32; 2], positions: [f32; 2], dt: f32) -> [f32; 2] {
+        self.contour_error = (0..2)
+            .map(|axis| self.weights[axis] * (setpoints[axis] - positions[axis]))
+            .sum();
+        let correction = self.coupling.update(self.contour_error, 0.0, dt);
+        [0, 1].map(|axis| {
+            let command = self.axes[axis].update(setpoints[axis], positions[axis], dt);
+            self.axes[axis]
+                .limits
+                .apply(command + self.weights[axis] * correction)
+        })
+    }
+}
@@ -0,0 +1,56 @@
+use super::Pid;
+
+/// Cascade of a position loop on the load-side encoder around a velocity loop on the
+/// motor-side one, as industrial axes close their loops around a compliant transmission.
+///
+/// The velocity loop sees the motor directly, so it can be stiff without exciting the
+/// compliance; the position loop sees the load, so its integrator removes the deflection of the
+/// transmission under load that a loop on the motor encoder alone can't see.
+#[derive(Clone, Debug, Default)]
+pub struct DualLoop {
+    /// From the error on the load position to the velocity setpoint of the load.
+    pub position: Pid,
+    /// From the error on the motor velocity to the torque of the motor.
+    pub velocity: Pid,
+    /// Turns of the motor per turn of the load.
+    pub ratio: f32,
+    velocity_setpoint: f32,
+}
+
+impl DualLoop {
+    pub fn new(position: Pid, velocity: Pid, ratio: f32) -> Self {
+        Self {
+            position,
+            velocity,
+            ratio,
+            velocity_setpoint: 0.0,
+        }
+    }
+
+    /// Velocity setpoint of the motor after the last update.
+    pub fn velocity_setpoint(&self) -> f32 {
+        self.velocity_setpoint
+    }
+
+    /// Clears both loops.
+    pub fn reset(&mut self) {
+        self.position.reset();
+        self.velocity.reset();
+        self.velocity_setpoint = 0.0;
+    }
+
+    /// Computes the torque of the motor for one sample period of `dt` seconds, from the
+    /// position of the load and the velocity of the motor.
+    pub fn update(
+        &mut self,
+        load_setpoint: f32,
+        load_position: f32,
+        motor_velocity: f32,
+        dt: f32,
+    ) -> f32 {
+        self.velocity_setpoint =
+            self.ratio * self.position.update(load_setpoint, load_position, dt);
+        self.velocity
+            .update(self.velocity_setpoint, motor_velocity, dt)
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+/// Method used to turn a continuous transfer function into a discrete one.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Discretization {
+    ForwardEuler,
+    #[default]
+    ZeroOrderHold,
+    Tustin,
+}
+
+/// First-order low-pass filter `wc / (s + wc)` discretized at a fixed sample period.
+#[derive(Clone, Debug)]
+pub struct LowPassFilter {
+    pole: f32,
+    /// Gain applied to the current input.
+    b0: f32,
+    /// Gain applied to the previous input.
+    b1: f32,
+    previous_input: f32,
+    output: f32,
+}
+
+impl LowPassFilter {
+    /// Creates a filter with a cutoff of `cutoff_hz` sampled every `dt` seconds.
+    pub fn new(cutoff_hz: f32, dt: f32, discretization: Discretization) -> Self {
+        let wc = std::f32::consts::TAU * cutoff_hz;
+        let (pole, b0, b1) = match discretization {
+            Discretization::ForwardEuler => (1.0 - wc * dt, 0.0, wc * dt),
+            Discretization::ZeroOrderHold => {
+                let pole = (-wc * dt).exp();
+                (pole, 0.0, 1.0 - pole)
+            }
+            Discretization::Tustin => {
+                let k = 2.0 / dt;
+                let gain = wc / (k + wc);
+                ((k - wc) / (k + wc), gain, gain)
+            }
+        };
+        Self {
+            pole,
+            b0,
+            b1,
+            previous_input: 0.0,
+            output: 0.0,
+        }
+    }
+
+    /// Discrete pole of the filter.
+    pub fn pole(&self) -> f32 {
+        self.pole
+    }
+
+    /// Whether the discrete filter is asymptotically stable.
+    pub fn is_stable(&self) -> bool {
+        self.pole.abs() < 1.0
+    }
+
+    /// Sets the filter state as if `value` had been applied forever.
+    pub fn reset(&mut self, value: f32) {
+        self.previous_input = value;
+        self.output = value;
+    }
+
+    pub fn update(&mut self, input: f32) -> f32 {
+        self.output = self.pole * self.output + self.b0 * input + self.b1 * self.previous_input;
+        self.previous_input = input;
+        self.output
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+/// Notch filter `(s² + 2 ζz wn s + wn²) / (s² + 2 ζp wn s + wn²)`, discretized with the Tustin
+/// method prewarped at `wn`. Its gain is one away from the notch and `ζz / ζp` at its center, so
+/// zeros matching a lightly damped mode cancel it while the poles set the width of the notch.
+#[derive(Clone, Debug)]
+pub struct NotchFilter {
+    /// Gains applied to the current and the two previous inputs.
+    b: [f32; 3],
+    /// Gains applied to the two previous outputs.
+    a: [f32; 2],
+    inputs: [f32; 2],
+    outputs: [f32; 2],
+}
+
+impl NotchFilter {
+    /// Creates a filter centered on `frequency_hz`, sampled every `dt` seconds. The center must
+    /// be below the Nyquist frequency.
+    pub fn new(frequency_hz: f32, zero_damping: f32, pole_damping: f32, dt: f32) -> Self {
+        let wn = std::f32::consts::TAU * frequency_hz;
+        let k = wn / (wn * dt / 2.0).tan();
+        let coefficients = |damping: f32| {
+            [
+                k * k + 2.0 * damping * wn * k + wn * wn,
+                2.0 * (wn * wn - k * k),
+                k * k - 2.0 * damping * wn * k + wn * wn,
+            ]
+        };
+        let [b0, b1, b2] = coefficients(zero_damping);
+        let [a0, a1, a2] = coefficients(pole_damping);
+        Self {
+            b: [b0 / a0, b1 / a0, b2 / a0],
+            a: [a1 / a0, a2 / a0],
+            inputs: [0.0; 2],
+            outputs: [0.0; 2],
+        }
+    }
+
+    /// Whether the discrete filter is asymptotically stable.
+    pub fn is_stable(&self) -> bool {
+        let [a1, a2] = self.a;
+        a2.abs() < 1.0 && a1.abs() < 1.0 + a2
+    }
+
+    /// Sets the filter state as if `value` had been applied forever.
+    pub fn reset(&mut self, value: f32) {
+        self.inputs = [value; 2];
+        self.outputs = [value; 2];
+    }
+
+    pub fn update(&mut self, input: f32) -> f32 {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        let output = b0 * input + b1 * self.inputs[0] + b2 * self.inputs[1]
+            - a1 * self.outputs[0]
+            - a2 * self.outputs[1];
+        self.inputs = [input, self.inputs[0]];
+        self.outputs = [output, self.outputs[0]];
+        output
+    }
+
+    pub fn output(&self) -> f32 {
+        self.outputs[0]
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// How a slave axis follows the position of a master: a fixed ratio (electronic gearing) or a
+/// cam profile (electronic camming).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Coupling {
+    Gear {
+        ratio: f32,
+        #[serde(default)]
+        offset: f32,
+    },
+    /// Positions of the slave at increasing positions of the master over a cycle, interpolated
+    /// linearly. The cycle repeats every span of the master, the slave advancing by its rise
+    /// (last position minus first) at every cycle.
+    Cam { points: Vec<[f32; 2]> },
+}
+
+impl Coupling {
+    /// Whether the coupling defines a position for every master position: a cam needs at
+    /// least two points, at strictly increasing master positions.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Coupling::Gear { ratio, offset } => ratio.is_finite() && offset.is_finite(),
+            Coupling::Cam { points } => {
+                points.len() >= 2 && points.windows(2).all(|pair| pair[1][0] > pair[0][0])
+            }
+        }
+    }
+
+    /// Position of the slave, and its velocity for a master moving at `master_velocity`.
+    pub fn follow(&self, master: f32, master_velocity: f32) -> (f32, f32) {
+        match self {
+            Coupling::Gear { ratio, offset } => (ratio * master + offset, ratio * master_velocity),
+            Coupling::Cam { points } => {
+                let ([x0, y0], [x1, y1]) = (points[0], points[points.len() - 1]);
+                let span = x1 - x0;
+                let cycles = ((master - x0) / span).floor();
+                let local = master - cycles * span;
+                let index = points
+                    .partition_point(|point| point[0] <= local)
+                    .clamp(1, points.len() - 1);
+                let ([xa, ya], [xb, yb]) = (points[index - 1], points[index]);
+                let slope = (yb - ya) / (xb - xa);
+                (
+                    ya + slope * (local - xa) + cycles * (y1 - y0),
+                    slope * master_velocity,
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use nalgebra::{DMatrix, DVector};
+
+/// Iterations of the Riccati recursion before giving up on its convergence.
+const MAX_ITERATIONS: usize = 100_000;
+/// Relative change of the solution of the Riccati equation under which it has converged.
+const TOLERANCE: f64 = 1e-10;
+/// Iterations of the matrix sign function, which converges quadratically, before giving up.
+const MAX_SIGN_ITERATIONS: usize = 100;
+/// Relative change of the matrix sign function under which it has converged.
+const SIGN_TOLERANCE: f64 = 1e-10;
+/// Largest residual of the continuous Riccati equation, relative to its terms.
+const RESIDUAL_TOLERANCE: f64 = 1e-6;
+
+/// Linear-quadratic regulator: the state feedback `u = -K x` of a discrete-time linear system
+/// `x[k+1] = A x[k] + B u[k]` minimizing the sum of `x' Q x + u' R u` over an infinite horizon.
+///
+/// The gain comes from the solution of the discrete algebraic Riccati equation, found by
+/// iterating the Riccati recursion until it settles. [`Lqr::continuous`] solves the regulator of
+/// a continuous-time system instead, ignoring the sampling of its inputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lqr {
+    /// Feedback gain `K`, one row per input.
+    pub gain: DMatrix<f64>,
+}
+
+impl Lqr {
+    /// Solves the regulator of a discrete-time system, or `None` when the Riccati recursion
+    /// doesn't converge, e.g. when the system isn't stabilizable.
+    pub fn new(
+        a: &DMatrix<f64>,
+        b: &DMatrix<f64>,
+        q: &DMatrix<f64>,
+        r: &DMatrix<f64>,
+    ) -> Option<Self> {
+        let p = riccati(a, b, q, r)?;
+        Some(Self {
+            gain: Self::gain(a, b, r, &p)?,
+        })
+    }
+
+    /// Solves the regulator of a continuous-time system `x' = A x + B u` minimizing the integral
+    /// of `x' Q x + u' R u`, or `None` when it has no stabilizing solution.
+    pub fn continuous(
+        a: &DMatrix<f64>,
+        b: &DMatrix<f64>,
+        q: &DMatrix<f64>,
+        r: &DMatrix<f64>,
+    ) -> Option<Self> {
+        let p = continuous_riccati(a, b, q, r)?;
+        // `K = R^-1 B' P`.
+        Some(Self {
+            gain: r.clone().try_inverse()? * b.transpose() * p,
+        })
+    }
+
+    /// `K = (R + B' P B)^-1 B' P A`.
+    fn gain(
+        a: &DMatrix<f64>,
+        b: &DMatrix<f64>,
+        r: &DMatrix<f64>,
+        p: &DMatrix<f64>,
+    ) -> Option<DMatrix<f64>> {
+        let weighted = r + b.transpose() * p * b;
+        Some(weighted.try_inverse()? * b.transpose() * p * a)
+    }
+
+    /// Inputs for the deviation of the state from the operating point.
+    pub fn command(&self, state: &DVector<f64>) -> DVector<f64> {
+        -(&self.gain * state)
+    }
+}
+
+/// Solution `P` of the discrete algebraic Riccati equation of the system `x[k+1] = A x[k] + B u[k]`
+/// with the weights `Q` and `R`: the cost to go `x' P x` of the regulator. `None` when the
+/// Riccati recursion doesn't converge.
+pub fn riccati(
+    a: &DMatrix<f64>,
+    b: &DMatrix<f64>,
+    q: &DMatrix<f64>,
+    r: &DMatrix<f64>,
+) -> Option<DMatrix<f64>> {
+    let mut p = q.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let gain = Lqr::gain(a, b, r, &p)?;
+        let next = q + a.transpose() * &p * (a - b * &gain);
+        // Keep the solution symmetric despite the rounding errors.
+        let next = (&next + next.transpose()) * 0.5;
+        let change = (&next - &p).norm();
+        p = next;
+        if !change.is_finite() {
+            return None;
+        }
+        if change <= TOLERANCE * p.norm().max(1.0) {
+            return Some(p);
+        }
+    }
+    None
+}
+
+/// Solution `P` of the continuous algebraic Riccati equation `A' P + P A - P B R^-1 B' P + Q = 0`
+/// of the system `x' = A x + B u`, by the matrix sign function of its Hamiltonian. `None` when
+/// the Hamiltonian has eigenvalues on the imaginary axis, e.g. when the system isn't
+/// stabilizable.
+///
+/// The stable invariant subspace of the Hamiltonian `H`, spanned by `[I; P]`, is the kernel of
+/// `sign(H) + I`, which the scaled Newton iteration `Z = (Z / c + c Z^-1) / 2` converges to.
+pub fn continuous_riccati(
+    a: &DMatrix<f64>,
+    b: &DMatrix<f64>,
+    q: &DMatrix<f64>,
+    r: &DMatrix<f64>,
+) -> Option<DMatrix<f64>> {
+    let n = a.nrows();
+    let g = b * r.clone().try_inverse()? * b.transpose();
+    let mut hamiltonian = DMatrix::zeros(2 * n, 2 * n);
+    hamiltonian.view_mut((0, 0), (n, n)).copy_from(a);
+    hamiltonian.view_mut((0, n), (n, n)).copy_from(&-g);
+    hamiltonian.view_mut((n, 0), (n, n)).copy_from(&-q);
+    hamiltonian
+        .view_mut((n, n), (n, n))
+        .copy_from(&-a.transpose());
+    let mut sign = hamiltonian;
+    let mut converged = false;
+    for _ in 0..MAX_SIGN_ITERATIONS {
+        // Scaling by the determinant speeds up the first iterations.
+        let scale = sign.determinant().abs().powf(0.5 / n as f64);
+        let inverse = sign.clone().try_inverse()?;
+        if !scale.is_finite() || scale <= 0.0 {
+            return None;
+        }
+        let next = (&sign / scale + inverse * scale) * 0.5;
+        let change = (&next - &sign).norm();
+        sign = next;
+        if !change.is_finite() {
+            return None;
+        }
+        if change <= SIGN_TOLERANCE * sign.norm() {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return None;
+    }
+    // `(sign(H) + I) [I; P] = 0`, solved for `P` in the least squares.
+    let identity = DMatrix::<f64>::identity(n, n);
+    let mut lhs = DMatrix::zeros(2 * n, n);
+    lhs.view_mut((0, 0), (n, n))
+        .copy_from(&sign.view((0, n), (n, n)));
+    lhs.view_mut((n, 0), (n, n))
+        .copy_from(&(sign.view((n, n), (n, n)) + &identity));
+    let mut rhs = DMatrix::zeros(2 * n, n);
+    rhs.view_mut((0, 0), (n, n))
+        .copy_from(&-(sign.view((0, 0), (n, n)) + &identity));
+    rhs.view_mut((n, 0), (n, n))
+        .copy_from(&-sign.view((n, 0), (n, n)));
+    let p = (lhs.transpose() * &lhs).try_inverse()? * lhs.transpose() * rhs;
+    let p = (&p + p.transpose()) * 0.5;
+    // Without a stabilizing solution, the stable subspace isn't a graph and `P` is garbage.
+    let residual = a.transpose() * &p + &p * a - &p * &g * &p + q;
+    let scale = q.norm() + p.norm() * (2.0 * a.norm() + g.norm() * p.norm());
+    (residual.norm() <= RESIDUAL_TOLERANCE * scale.max(1.0)).then_some(p)
+}
+
+/// Discretizes the continuous-time system `x' = A x + B u` with a zero-order hold of `dt`
+/// seconds on its inputs: `(exp(A dt), ∫ exp(A t) dt B)`.
+pub fn discretize(a: &DMatrix<f64>, b: &DMatrix<f64>, dt: f64) -> (DMatrix<f64>, DMatrix<f64>) {
+    let (states, inputs) = b.shape();
+    let mut augmented = DMatrix::zeros(states + inputs, states + inputs);
+    augmented.view_mut((0, 0), (states, states)).copy_from(a);
+    augmented
+        .view_mut((0, states), (states, inputs))
+        .copy_from(b);
+    let exponential = exp(&(augmented * dt));
+    (
+        exponential.view((0, 0), (states, states)).into_owned(),
+        exponential.view((0, states), (states, inputs)).into_owned(),
+    )
+}
+
+/// Matrix exponential, by scaling and squaring a truncated Taylor series.
+fn exp(matrix: &DMatrix<f64>) -> DMatrix<f64> {
+    let norm = matrix.norm();
+    let squarings = if norm > 0.5 {
+        (norm / 0.5).log2().ceil() as i32
+    } else {
+        0
+    };
+    let scaled = matrix / 2f64.powi(squarings);
+    let identity = DMatrix::identity(matrix.nrows(), matrix.ncols());
+    let mut exponential = identity.clone();
+    let mut term = identity;
+    for k in 1..=16 {
+        term = &term * &scaled / k as f64;
+        exponential += &term;
+    }
+    for _ in 0..squarings {
+        exponential = &exponential * &exponential;
+    }
+    exponential
+}
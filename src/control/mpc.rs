@@ -0,0 +1,222 @@
+use nalgebra::{DMatrix, DVector};
+
+use super::{riccati, ProjectedGradient, QpData, QpProblem, QpSolver, QpStatus};
+
+/// Model predictive controller: the inputs of a discrete-time linear system
+/// `x[k+1] = A x[k] + B u[k]` minimizing the sum of `x' Q x + u' R u` over a finite horizon,
+/// plus the cost to go of the regulator at its end, within bounds on the inputs and, optionally,
+/// on the states.
+///
+/// The states are eliminated from the problem, which leaves a quadratic program in the inputs
+/// alone, its Hessian computed once. Each command solves it with a [`QpSolver`], by default an
+/// accelerated projected gradient, warm-started from the last plan shifted by a step, and
+/// applies the first input of the plan. Without active bounds, the first input is that of the
+/// [`Lqr`](super::Lqr). When the bounds of the states can't be met, the command drops them for
+/// the step, and [`Mpc::status`] tells so.
+#[derive(Clone, Debug)]
+pub struct Mpc {
+    /// Number of steps of the plan.
+    pub horizon: usize,
+    inputs: usize,
+    /// The states over the horizon per unit of the initial state and of the inputs.
+    free: DMatrix<f64>,
+    forced: DMatrix<f64>,
+    problem: QpProblem,
+    /// Gradient of the cost in the inputs per unit of the initial state.
+    gradient: DMatrix<f64>,
+    /// Bounds of the inputs, and of the states constrained, over the horizon.
+    lower: DVector<f64>,
+    upper: DVector<f64>,
+    constrained: Vec<usize>,
+    state_lower: DVector<f64>,
+    state_upper: DVector<f64>,
+    solver: Box<dyn QpSolver>,
+    /// The inputs planned over the horizon by the last command, stacked.
+    plan: DVector<f64>,
+    iterations: usize,
+    status: QpStatus,
+}
+
+impl Mpc {
+    /// Sets up the controller of a discrete-time system over `horizon` steps, with the inputs
+    /// within `lower` and `upper`, or `None` when the Riccati recursion of the cost to go
+    /// doesn't converge, the horizon is empty or the bounds don't match the inputs.
+    pub fn new(
+        a: &DMatrix<f64>,
+        b: &DMatrix<f64>,
+        q: &DMatrix<f64>,
+        r: &DMatrix<f64>,
+        horizon: usize,
+        lower: &[f64],
+        upper: &[f64],
+    ) -> Option<Self> {
+        let (states, inputs) = b.shape();
+        let ordered = lower.iter().zip(upper).all(|(lower, upper)| lower <= upper);
+        if horizon == 0 || lower.len() != inputs || upper.len() != inputs || !ordered {
+            return None;
+        }
+        let terminal = riccati(a, b, q, r)?;
+        // The states x[1]..x[N] are `free · x[0] + forced · u`, with the inputs u[0]..u[N-1].
+        let mut free = DMatrix::zeros(states * horizon, states);
+        let mut forced = DMatrix::zeros(states * horizon, inputs * horizon);
+        let mut power = DMatrix::identity(states, states);
+        let mut responses = Vec::with_capacity(horizon);
+        for step in 0..horizon {
+            responses.push(&power * b);
+            power = a * &power;
+            free.view_mut((step * states, 0), (states, states))
+                .copy_from(&power);
+        }
+        for step in 0..horizon {
+            for input in 0..=step {
+                forced
+                    .view_mut((step * states, input * inputs), (states, inputs))
+                    .copy_from(&responses[step - input]);
+            }
+        }
+        let mut state_weight = DMatrix::zeros(states * horizon, states * horizon);
+        let mut input_weight = DMatrix::zeros(inputs * horizon, inputs * horizon);
+        for step in 0..horizon {
+            let weight = if step + 1 == horizon { &terminal } else { q };
+            state_weight
+                .view_mut((step * states, step * states), (states, states))
+                .copy_from(weight);
+            input_weight
+                .view_mut((step * inputs, step * inputs), (inputs, inputs))
+                .copy_from(r);
+        }
+        let weighted = forced.transpose() * &state_weight;
+        let hessian = &weighted * &forced + input_weight;
+        let hessian = (&hessian + hessian.transpose()) * 0.5;
+        let gradient = weighted * &free;
+        let stacked = |bounds: &[f64]| {
+            DVector::from_iterator(
+                inputs * horizon,
+                bounds.iter().copied().cycle().take(inputs * horizon),
+            )
+        };
+        let mut mpc = Self {
+            horizon,
+            inputs,
+            free,
+            forced,
+            problem: QpProblem {
+                hessian,
+                constraints: DMatrix::zeros(0, inputs * horizon),
+            },
+            gradient,
+            lower: stacked(lower),
+            upper: stacked(upper),
+            constrained: Vec::new(),
+            state_lower: DVector::zeros(0),
+            state_upper: DVector::zeros(0),
+            solver: Box::new(ProjectedGradient::default()),
+            plan: DVector::zeros(inputs * horizon),
+            iterations: 0,
+            status: QpStatus::Solved,
+        };
+        mpc.solver.setup(&mpc.problem).ok()?;
+        Some(mpc)
+    }
+
+    /// The controller solving its programs with `solver`, or why `solver` can't.
+    pub fn with_solver(mut self, solver: Box<dyn QpSolver>) -> Result<Self, String> {
+        self.solver = solver;
+        self.solver.setup(&self.problem)?;
+        Ok(self)
+    }
+
+    /// The controller keeping the states within `lower` and `upper` over the horizon, infinite
+    /// for the states left free, or why its solver can't.
+    pub fn with_state_bounds(mut self, lower: &[f64], upper: &[f64]) -> Result<Self, String> {
+        let states = self.free.ncols();
+        if lower.len() != states || upper.len() != states {
+            return Err(format!("the bounds of the states aren't {states} long"));
+        }
+        if lower.iter().zip(upper).any(|(lower, upper)| lower > upper) {
+            return Err("the lower bounds of the states exceed the upper ones".to_string());
+        }
+        let bounded: Vec<usize> = (0..states)
+            .filter(|&state| lower[state].is_finite() || upper[state].is_finite())
+            .collect();
+        self.constrained = (0..self.horizon)
+            .flat_map(|step| bounded.iter().map(move |state| step * states + state))
+            .collect();
+        self.problem.constraints = self.forced.select_rows(&self.constrained);
+        let bound = |bounds: &[f64]| {
+            DVector::from_iterator(
+                self.constrained.len(),
+                self.constrained.iter().map(|row| bounds[row % states]),
+            )
+        };
+        self.state_lower = bound(lower);
+        self.state_upper = bound(upper);
+        self.solver.setup(&self.problem)?;
+        Ok(self)
+    }
+
+    /// Inputs for the deviation of the state from the operating point: the first ones of the
+    /// plan over the horizon.
+    pub fn command(&mut self, state: &DVector<f64>) -> DVector<f64> {
+        let linear = &self.gradient * state;
+        // Warm start from the last plan, a step later, repeating its last inputs.
+        let (m, length) = (self.inputs, self.plan.len());
+        let mut warm = DVector::zeros(length);
+        warm.rows_mut(0, length - m)
+            .copy_from(&self.plan.rows(m, length - m));
+        warm.rows_mut(length - m, m)
+            .copy_from(&self.plan.rows(length - m, m));
+        // The constraints on the states are on the response to the inputs.
+        let free = self.free.select_rows(&self.constrained) * state;
+        let constraint_lower = &self.state_lower - &free;
+        let constraint_upper = &self.state_upper - &free;
+        let mut data = QpData {
+            linear: &linear,
+            lower: &self.lower,
+            upper: &self.upper,
+            constraint_lower: &constraint_lower,
+            constraint_upper: &constraint_upper,
+        };
+        let mut solution = self.solver.solve(&data, &warm);
+        self.status = solution.status;
+        if solution.status == QpStatus::Infeasible && !self.constrained.is_empty() {
+            // Within the bounds of the inputs, the program always has a solution.
+            let unbounded = DVector::from_element(self.constrained.len(), f64::INFINITY);
+            let unbounded_below = -&unbounded;
+            data.constraint_lower = &unbounded_below;
+            data.constraint_upper = &unbounded;
+            solution = self.solver.solve(&data, &warm);
+        }
+        self.iterations = solution.iterations;
+        self.plan = solution.x;
+        self.plan.rows(0, m).into_owned()
+    }
+
+    /// The inputs planned over the horizon by the last command, one row per step.
+    pub fn plan(&self) -> DMatrix<f64> {
+        DMatrix::from_fn(self.horizon, self.inputs, |step, input| {
+            self.plan[step * self.inputs + input]
+        })
+    }
+
+    /// Iterations the last command took to converge.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Outcome of the last command: infeasible when the bounds of the states couldn't be met,
+    /// and were dropped for it.
+    pub fn status(&self) -> QpStatus {
+        self.status
+    }
+
+    /// Name of the solver of the programs.
+    pub fn solver(&self) -> &'static str {
+        self.solver.name()
+    }
+
+    /// Forgets the last plan, e.g. when the controller takes over the system.
+    pub fn reset(&mut self) {
+        self.plan.fill(0.0);
+    }
+}
@@ -0,0 +1,430 @@
+use std::f64::consts::TAU;
+
+use nalgebra::{DMatrix, DVector, Matrix4, Matrix4xX, Vector4};
+
+use super::{ProjectedGradient, QpData, QpProblem, QpSolver, QpStatus};
+
+/// Runge-Kutta steps of the integration of an interval of the plan.
+const SUBSTEPS: usize = 2;
+/// Perturbation of the central differences of the Jacobians of the steps.
+const PERTURBATION: f64 = 1e-6;
+/// Weight of the defects between the shooting nodes in the merit of a step of the SQP, large
+/// against its costs.
+const PENALTY: f64 = 100.0;
+/// Shortest fraction of a step of the SQP tried by the line search.
+const SHORTEST_STEP: f64 = 1e-2;
+
+/// Nonlinear model of a rotary pendulum driven by the acceleration of its arm, the state being
+/// `[arm angle, arm velocity, pendulum angle from upright, pendulum velocity]`: the pendulum
+/// accelerates by `gravity · sin(angle) + coupling · cos(angle) · acceleration`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RotaryPendulum {
+    pub gravity: f64,
+    pub coupling: f64,
+}
+
+impl RotaryPendulum {
+    pub fn derivative(&self, state: &Vector4<f64>, acceleration: f64) -> Vector4<f64> {
+        let (sin, cos) = state[2].sin_cos();
+        Vector4::new(
+            state[1],
+            acceleration,
+            state[3],
+            self.gravity * sin + self.coupling * cos * acceleration,
+        )
+    }
+
+    /// Jacobians of the derivative in the state and the acceleration.
+    pub fn jacobians(
+        &self,
+        state: &Vector4<f64>,
+        acceleration: f64,
+    ) -> (Matrix4<f64>, Vector4<f64>) {
+        let (sin, cos) = state[2].sin_cos();
+        let mut a = Matrix4::zeros();
+        a[(0, 1)] = 1.0;
+        a[(2, 3)] = 1.0;
+        a[(3, 2)] = self.gravity * cos - self.coupling * sin * acceleration;
+        (a, Vector4::new(0.0, 1.0, 0.0, self.coupling * cos))
+    }
+
+    /// The state `dt` seconds later, the acceleration held, by the classical Runge-Kutta
+    /// method.
+    pub fn step(&self, state: &Vector4<f64>, acceleration: f64, dt: f64) -> Vector4<f64> {
+        let h = dt / SUBSTEPS as f64;
+        let mut state = *state;
+        for _ in 0..SUBSTEPS {
+            let k1 = self.derivative(&state, acceleration);
+            let k2 = self.derivative(&(state + k1 * (h / 2.0)), acceleration);
+            let k3 = self.derivative(&(state + k2 * (h / 2.0)), acceleration);
+            let k4 = self.derivative(&(state + k3 * h), acceleration);
+            state += (k1 + k2 * 2.0 + k3 * 2.0 + k4) * (h / 6.0);
+        }
+        state
+    }
+
+    /// The step and its Jacobians in the state and the acceleration, by central differences.
+    fn linearize(
+        &self,
+        state: &Vector4<f64>,
+        acceleration: f64,
+        dt: f64,
+    ) -> (Vector4<f64>, Matrix4<f64>, Vector4<f64>) {
+        let mut a = Matrix4::zeros();
+        for column in 0..4 {
+            let mut delta = Vector4::zeros();
+            delta[column] = PERTURBATION;
+            let difference = self.step(&(state + delta), acceleration, dt)
+                - self.step(&(state - delta), acceleration, dt);
+            a.set_column(column, &(difference / (2.0 * PERTURBATION)));
+        }
+        let b = (self.step(state, acceleration + PERTURBATION, dt)
+            - self.step(state, acceleration - PERTURBATION, dt))
+            / (2.0 * PERTURBATION);
+        (self.step(state, acceleration, dt), a, b)
+    }
+}
+
+/// Nonlinear model predictive controller of a [`RotaryPendulum`], swinging it up and balancing
+/// it upright: the accelerations of the arm over `horizon` intervals minimizing the sum of
+/// `x' Q x + r u²`, with `terminal` instead of `Q` at the end, within the acceleration limit and,
+/// optionally, within a limit of the arm angle.
+///
+/// The pendulum angle is weighed as `2 sin(angle / 2)`, so the cost is that of the distance to
+/// upright, whichever way the pendulum turns. The program is solved by multiple shooting: the
+/// states at the start of each interval are variables too, linked to the previous ones by
+/// the dynamics at the solution only. Each iteration of the sequential quadratic programming
+/// linearizes the intervals, eliminates the states from the resulting quadratic program, solves
+/// it with a [`QpSolver`] and takes the step, or the fraction of it that improves the cost and
+/// the defects between the intervals. Each command starts from the last plan, an interval later,
+/// so a few iterations are enough.
+#[derive(Clone, Debug)]
+pub struct Nmpc {
+    pub model: RotaryPendulum,
+    /// Number of intervals of the plan, and their duration, in s, over which the acceleration
+    /// is held.
+    pub horizon: usize,
+    pub interval: f64,
+    /// Iterations of the SQP a command at most, and the largest change of the accelerations,
+    /// in rad/s², under which it has converged.
+    pub max_iterations: usize,
+    pub tolerance: f64,
+    weights: Vector4<f64>,
+    terminal: Vector4<f64>,
+    input_weight: f64,
+    limit: f64,
+    arm_limit: Option<f64>,
+    solver: Box<dyn QpSolver>,
+    /// The accelerations planned, and the states at the start of the intervals, the first being
+    /// the current one.
+    inputs: DVector<f64>,
+    nodes: Vec<Vector4<f64>>,
+    /// Whether the nodes follow from the last command.
+    warm: bool,
+    iterations: usize,
+    qp_iterations: usize,
+    status: QpStatus,
+}
+
+impl Nmpc {
+    /// Sets up the controller of `model` over `horizon` intervals of `interval` seconds, with
+    /// the weights of the state, at the end and of the input, and the acceleration within
+    /// `limit`, or `None` when they don't make sense.
+    pub fn new(
+        model: RotaryPendulum,
+        horizon: usize,
+        interval: f64,
+        [weights, terminal]: [[f64; 4]; 2],
+        input_weight: f64,
+        limit: f64,
+    ) -> Option<Self> {
+        let positive = weights.iter().chain(&terminal).all(|weight| *weight >= 0.0);
+        if horizon == 0 || interval <= 0.0 || input_weight <= 0.0 || limit < 0.0 || !positive {
+            return None;
+        }
+        Some(Self {
+            model,
+            horizon,
+            interval,
+            max_iterations: 3,
+            tolerance: 1e-3,
+            weights: Vector4::from(weights),
+            terminal: Vector4::from(terminal),
+            input_weight,
+            limit,
+            arm_limit: None,
+            solver: Box::new(ProjectedGradient::default()),
+            inputs: DVector::zeros(horizon),
+            nodes: vec![Vector4::zeros(); horizon + 1],
+            warm: false,
+            iterations: 0,
+            qp_iterations: 0,
+            status: QpStatus::Solved,
+        })
+    }
+
+    /// The controller solving its programs with `solver`, or why `solver` can't.
+    pub fn with_solver(mut self, solver: Box<dyn QpSolver>) -> Result<Self, String> {
+        self.solver = solver;
+        self.check_solver()?;
+        Ok(self)
+    }
+
+    /// The controller keeping the arm within `limit` rad of zero over the plan, or why its
+    /// solver can't.
+    pub fn with_arm_limit(mut self, limit: f64) -> Result<Self, String> {
+        if limit <= 0.0 {
+            return Err("the limit of the arm must be positive".to_string());
+        }
+        self.arm_limit = Some(limit);
+        self.check_solver()?;
+        Ok(self)
+    }
+
+    /// Sets up the solver with a program of the right shape, for it to tell what it can't
+    /// solve.
+    fn check_solver(&mut self) -> Result<(), String> {
+        let rows = if self.arm_limit.is_some() {
+            self.horizon
+        } else {
+            0
+        };
+        let mut constraints = DMatrix::zeros(rows, self.horizon);
+        for row in 0..rows {
+            constraints.row_mut(row).columns_mut(0, row + 1).fill(1.0);
+        }
+        self.solver.setup(&QpProblem {
+            hessian: DMatrix::identity(self.horizon, self.horizon),
+            constraints,
+        })
+    }
+
+    /// Acceleration of the arm for the `state`, held until the next command: the first of the
+    /// plan.
+    pub fn command(&mut self, state: &Vector4<f64>) -> f64 {
+        let mut state = *state;
+        if self.warm {
+            // The plan turns with the pendulum: it's brought back within a turn, and the angle
+            // measured unwrapped next to it.
+            let turns = TAU * (self.nodes[0][2] / TAU).round();
+            for node in &mut self.nodes {
+                node[2] -= turns;
+            }
+            state[2] = self.nodes[0][2] + wrap(state[2] - self.nodes[0][2]);
+            self.nodes[0] = state;
+        } else {
+            self.nodes[0] = state;
+            for step in 0..self.horizon {
+                self.nodes[step + 1] =
+                    self.model
+                        .step(&self.nodes[step], self.inputs[step], self.interval);
+            }
+            self.warm = true;
+        }
+
+        self.status = QpStatus::Solved;
+        self.qp_iterations = 0;
+        self.iterations = 0;
+        while self.iterations < self.max_iterations {
+            self.iterations += 1;
+            if self.iterate() < self.tolerance {
+                break;
+            }
+        }
+
+        let acceleration = self.inputs[0];
+        // Start the next command from the plan an interval later, repeating its last input.
+        let last = self.inputs[self.horizon - 1];
+        self.inputs = self
+            .inputs
+            .clone()
+            .remove_row(0)
+            .insert_row(self.horizon - 1, last);
+        self.nodes.remove(0);
+        let end = self
+            .model
+            .step(&self.nodes[self.horizon - 1], last, self.interval);
+        self.nodes.push(end);
+        acceleration
+    }
+
+    /// An iteration of the SQP, returning the largest change of the accelerations.
+    fn iterate(&mut self) -> f64 {
+        let (n, dt) = (self.horizon, self.interval);
+        // The changes of the states are `responses · changes of the inputs + offsets`, the
+        // offsets closing the defects of the linearized intervals.
+        let mut responses = vec![Matrix4xX::<f64>::zeros(n); n + 1];
+        let mut offsets = vec![Vector4::<f64>::zeros(); n + 1];
+        for step in 0..n {
+            let (end, a, b) = self
+                .model
+                .linearize(&self.nodes[step], self.inputs[step], dt);
+            let mut response = a * &responses[step];
+            for row in 0..4 {
+                response[(row, step)] += b[row];
+            }
+            responses[step + 1] = response;
+            offsets[step + 1] = a * offsets[step] + end - self.nodes[step + 1];
+        }
+
+        // Gauss-Newton approximation of the cost of the changes of the inputs.
+        let mut hessian = DMatrix::identity(n, n) * self.input_weight;
+        let mut linear = &self.inputs * self.input_weight;
+        for step in 1..=n {
+            let weights = if step == n {
+                &self.terminal
+            } else {
+                &self.weights
+            };
+            let predicted = self.nodes[step] + offsets[step];
+            let (residual, slope) = residual(&predicted);
+            let mut jacobian = responses[step].clone();
+            jacobian.row_mut(2).scale_mut(slope);
+            let weighted = Matrix4::from_diagonal(weights) * &jacobian;
+            hessian += jacobian.transpose() * &weighted;
+            linear += weighted.transpose() * residual;
+        }
+        let hessian = (&hessian + hessian.transpose()) * 0.5;
+
+        let (constraints, constraint_lower, constraint_upper) = match self.arm_limit {
+            Some(limit) => {
+                let rows: Vec<_> = (1..=n)
+                    .map(|step| responses[step].row(0).into_owned())
+                    .collect();
+                let arms = DVector::from_iterator(
+                    n,
+                    (1..=n).map(|step| self.nodes[step][0] + offsets[step][0]),
+                );
+                (
+                    DMatrix::from_rows(&rows),
+                    arms.map(|arm| -limit - arm),
+                    arms.map(|arm| limit - arm),
+                )
+            }
+            None => (DMatrix::zeros(0, n), DVector::zeros(0), DVector::zeros(0)),
+        };
+        let problem = QpProblem {
+            hessian,
+            constraints,
+        };
+        if self.solver.setup(&problem).is_err() {
+            // The program has the shape checked at setup, but is ill-conditioned: keep the plan.
+            self.status = QpStatus::MaxIterations;
+            return 0.0;
+        }
+        let lower = self.inputs.map(|input| -self.limit - input);
+        let upper = self.inputs.map(|input| self.limit - input);
+        let mut data = QpData {
+            linear: &linear,
+            lower: &lower,
+            upper: &upper,
+            constraint_lower: &constraint_lower,
+            constraint_upper: &constraint_upper,
+        };
+        let zero = DVector::zeros(n);
+        let mut solution = self.solver.solve(&data, &zero);
+        self.qp_iterations += solution.iterations;
+        if solution.status == QpStatus::Infeasible && self.arm_limit.is_some() {
+            // Within the bounds of the inputs, the program always has a solution.
+            let unbounded = DVector::from_element(n, f64::INFINITY);
+            let unbounded_below = -&unbounded;
+            data.constraint_lower = &unbounded_below;
+            data.constraint_upper = &unbounded;
+            solution = self.solver.solve(&data, &zero);
+            self.qp_iterations += solution.iterations;
+            self.status = QpStatus::Infeasible;
+        } else if solution.status == QpStatus::MaxIterations && self.status == QpStatus::Solved {
+            self.status = QpStatus::MaxIterations;
+        }
+
+        // Backtrack until the step improves the merit.
+        let changes: Vec<Vector4<f64>> = (0..=n)
+            .map(|step| &responses[step] * &solution.x + offsets[step])
+            .collect();
+        let merit = self.merit(&self.inputs, &self.nodes);
+        let mut fraction = 1.0;
+        loop {
+            let inputs = &self.inputs + &solution.x * fraction;
+            let nodes: Vec<Vector4<f64>> = self
+                .nodes
+                .iter()
+                .zip(&changes)
+                .map(|(node, change)| node + change * fraction)
+                .collect();
+            if self.merit(&inputs, &nodes) < merit || fraction < SHORTEST_STEP {
+                self.inputs = inputs;
+                self.nodes = nodes;
+                return solution.x.amax() * fraction;
+            }
+            fraction /= 2.0;
+        }
+    }
+
+    /// Cost of a plan, plus the weighted defects between its intervals.
+    fn merit(&self, inputs: &DVector<f64>, nodes: &[Vector4<f64>]) -> f64 {
+        let mut merit = 0.0;
+        for step in 0..self.horizon {
+            let end = self.model.step(&nodes[step], inputs[step], self.interval);
+            merit += PENALTY * (end - nodes[step + 1]).abs().sum();
+            let weights = if step + 1 == self.horizon {
+                &self.terminal
+            } else {
+                &self.weights
+            };
+            let (residual, _) = residual(&nodes[step + 1]);
+            merit += 0.5 * (residual.component_mul(&residual).dot(weights))
+                + 0.5 * self.input_weight * inputs[step] * inputs[step];
+        }
+        merit
+    }
+
+    /// The accelerations planned by the last command, from the next interval on, in rad/s².
+    pub fn plan(&self) -> &DVector<f64> {
+        &self.inputs
+    }
+
+    /// The states predicted at the start of the intervals of the plan, from the next one on.
+    pub fn trajectory(&self) -> &[Vector4<f64>] {
+        &self.nodes
+    }
+
+    /// Iterations of the SQP the last command took, and of the solver of its programs in all.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn qp_iterations(&self) -> usize {
+        self.qp_iterations
+    }
+
+    /// Outcome of the last command: infeasible when the limit of the arm couldn't be met, and
+    /// was dropped for it.
+    pub fn status(&self) -> QpStatus {
+        self.status
+    }
+
+    /// Name of the solver of the programs.
+    pub fn solver(&self) -> &'static str {
+        self.solver.name()
+    }
+
+    /// Forgets the last plan, e.g. when the controller takes over the system.
+    pub fn reset(&mut self) {
+        self.inputs.fill(0.0);
+        self.warm = false;
+    }
+}
+
+/// The residual of the cost of a state, with the pendulum angle as `2 sin(angle / 2)`, and the
+/// slope of the latter.
+fn residual(state: &Vector4<f64>) -> (Vector4<f64>, f64) {
+    let (sin, cos) = (state[2] / 2.0).sin_cos();
+    let mut residual = *state;
+    residual[2] = 2.0 * sin;
+    (residual, cos)
+}
+
+/// Angle within `[-π, π]`.
+fn wrap(angle: f64) -> f64 {
+    angle - TAU * (angle / TAU).round()
+}
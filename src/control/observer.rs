@@ -0,0 +1,89 @@
+use std::f64::consts::TAU;
+
+use nalgebra::{Complex, DMatrix};
+
+use super::riccati;
+
+/// Luenberger observer of a discrete-time linear system `x[k+1] = A x[k] + B u[k] + w[k]`,
+/// `y[k] = C x[k] + v[k]`: the predicted state `x̂[k+1] = A x̂[k] + B u[k] + L (y[k] - C x̂[k])`,
+/// its error decaying as `A - L C`.
+///
+/// The gain `L` is that of the steady-state Kalman filter for white noises `w` and `v` of the
+/// given covariances, from the discrete algebraic Riccati equation of the dual system `(A', C')`,
+/// and the covariances are those of the error of the estimate it settles to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observer {
+    /// Gain `L`, one column per output.
+    pub gain: DMatrix<f64>,
+    /// Covariance of the error of the predicted state, and of the state corrected with the
+    /// last output.
+    pub predicted: DMatrix<f64>,
+    pub corrected: DMatrix<f64>,
+}
+
+impl Observer {
+    /// The steady-state Kalman filter of the system under the covariances `process` of `w` and
+    /// `measurement` of `v`, or `None` when the Riccati recursion doesn't converge, e.g. when a
+    /// mode the outputs don't see is unstable.
+    pub fn kalman(
+        a: &DMatrix<f64>,
+        c: &DMatrix<f64>,
+        process: &DMatrix<f64>,
+        measurement: &DMatrix<f64>,
+    ) -> Option<Self> {
+        let predicted = riccati(&a.transpose(), &c.transpose(), process, measurement)?;
+        let innovation = (c * &predicted * c.transpose() + measurement).try_inverse()?;
+        let correction = &predicted * c.transpose() * innovation;
+        let corrected = &predicted - &correction * c * &predicted;
+        Some(Self {
+            gain: a * correction,
+            corrected: (&corrected + corrected.transpose()) * 0.5,
+            predicted,
+        })
+    }
+
+    /// `A - L C`, the dynamics of the error of the estimate.
+    pub fn error_dynamics(&self, a: &DMatrix<f64>, c: &DMatrix<f64>) -> DMatrix<f64> {
+        a - &self.gain * c
+    }
+}
+
+/// A pole of a discrete-time closed loop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pole {
+    pub eigenvalue: Complex<f64>,
+    /// Natural frequency of the equivalent continuous-time pole, in Hz.
+    pub frequency: f64,
+    /// Damping ratio of the equivalent continuous-time pole (negative if unstable).
+    pub damping: f64,
+}
+
+impl Pole {
+    pub fn stable(&self) -> bool {
+        self.eigenvalue.norm() < 1.0
+    }
+}
+
+/// Poles of the closed loop `x[k+1] = M x[k]` sampled every `dt` seconds, a complex conjugate
+/// pair counted once, from the slowest to the fastest.
+pub fn poles(closed: &DMatrix<f64>, dt: f64) -> Vec<Pole> {
+    let mut poles: Vec<Pole> = closed
+        .complex_eigenvalues()
+        .iter()
+        .filter(|eigenvalue| eigenvalue.im >= 0.0)
+        .map(|&eigenvalue| {
+            let pole = eigenvalue.ln() / dt;
+            Pole {
+                eigenvalue,
+                frequency: pole.norm() / TAU,
+                damping: if pole.norm() > 0.0 {
+                    -pole.re / pole.norm()
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect();
+    poles.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    poles
+}
@@ -0,0 +1,283 @@
+/// Turn angles above this one (nearly reversing corners) can't be blended: the path stops there.
+pub const MAX_BLEND_TURN: f32 = 0.9 * std::f32::consts::PI;
+
+/// A piece of a [`BlendedPath`].
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Line {
+        start: Vec<f32>,
+        /// Unit direction.
+        direction: Vec<f32>,
+        length: f32,
+    },
+    /// Circular arc from `center + radius * u`, turning towards `v` (both unit and orthogonal)
+    /// by `angle`.
+    Arc {
+        center: Vec<f32>,
+        u: Vec<f32>,
+        v: Vec<f32>,
+        radius: f32,
+        angle: f32,
+    },
+}
+
+impl Piece {
+    fn length(&self) -> f32 {
+        match self {
+            Piece::Line { length, .. } => *length,
+            Piece::Arc { radius, angle, .. } => radius * angle,
+        }
+    }
+
+    /// Position and unit tangent `s` along the piece.
+    fn sample(&self, s: f32) -> (Vec<f32>, Vec<f32>) {
+        match self {
+            Piece::Line {
+                start, direction, ..
+            } => (
+                start
+                    .iter()
+                    .zip(direction)
+                    .map(|(start, direction)| start + s * direction)
+                    .collect(),
+                direction.clone(),
+            ),
+            Piece::Arc {
+                center,
+                u,
+                v,
+                radius,
+                ..
+            } => {
+                let theta = s / radius;
+                let (sin, cos) = theta.sin_cos();
+                (
+                    (0..center.len())
+                        .map(|i| center[i] + radius * (cos * u[i] + sin * v[i]))
+                        .collect(),
+                    (0..center.len())
+                        .map(|i| -sin * u[i] + cos * v[i])
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Distance from `point` to the piece.
+    fn distance(&self, point: &[f32]) -> f32 {
+        let nearest = match self {
+            Piece::Line {
+                start,
+                direction,
+                length,
+            } => {
+                let along = dot(&difference(point, start), direction).clamp(0.0, *length);
+                self.sample(along).0
+            }
+            Piece::Arc {
+                center,
+                u,
+                v,
+                radius,
+                angle,
+            } => {
+                let offset = difference(point, center);
+                let theta = dot(&offset, v).atan2(dot(&offset, u));
+                // Past either end, the nearest point is the closest end.
+                let theta = if (0.0..=*angle).contains(&theta) {
+                    theta
+                } else if (theta - angle)
+                    .abs()
+                    .min((theta + std::f32::consts::TAU - angle).abs())
+                    < theta.abs()
+                {
+                    *angle
+                } else {
+                    0.0
+                };
+                self.sample(theta * radius).0
+            }
+        };
+        norm(&difference(point, &nearest))
+    }
+}
+
+fn difference(a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter().zip(b).map(|(a, b)| a - b).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+/// Angle between two vectors, given the product of their norms.
+fn turn(a: &[f32], b: &[f32], norms: f32) -> f32 {
+    (dot(a, b) / norms).clamp(-1.0, 1.0).acos()
+}
+
+fn scaled(a: &[f32], factor: f32) -> Vec<f32> {
+    a.iter().map(|a| a * factor).collect()
+}
+
+/// Path through a sequence of points of any dimension (e.g. the positions of the joints),
+/// whose corners are blended with circular arcs so it can be followed without stopping at
+/// every point.
+///
+/// Each arc is tangent to both segments of its corner and passes within `tolerance` of the
+/// corner; it's tightened to use at most half of each segment. Corners turning more than
+/// [`MAX_BLEND_TURN`] and repeated points aren't blended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlendedPath {
+    pieces: Vec<Piece>,
+    /// Distance along the path to the closest point to each vertex but the first.
+    vertices: Vec<f32>,
+}
+
+impl BlendedPath {
+    pub fn new(points: &[Vec<f32>], tolerance: f32) -> Self {
+        let tolerance = tolerance.max(0.0);
+        let directions: Vec<(Vec<f32>, f32)> = points
+            .windows(2)
+            .map(|pair| {
+                let delta = difference(&pair[1], &pair[0]);
+                let length = norm(&delta);
+                let direction = if length > 0.0 {
+                    scaled(&delta, 1.0 / length)
+                } else {
+                    delta
+                };
+                (direction, length)
+            })
+            .collect();
+
+        // Blend of each interior vertex: its tangent length and its arc, if any.
+        let blends: Vec<Option<(f32, Piece)>> = (1..points.len().saturating_sub(1))
+            .map(|k| {
+                let ((d1, l1), (d2, l2)) = (&directions[k - 1], &directions[k]);
+                if tolerance == 0.0 || *l1 == 0.0 || *l2 == 0.0 {
+                    return None;
+                }
+                let angle = turn(d1, d2, 1.0);
+                if angle < 1e-4 || angle > MAX_BLEND_TURN {
+                    return None;
+                }
+                let half = angle / 2.0;
+                let mut radius = tolerance / (1.0 / half.cos() - 1.0);
+                let mut tangent = radius * half.tan();
+                let longest = 0.5 * l1.min(*l2);
+                if tangent > longest {
+                    tangent = longest;
+                    radius = tangent / half.tan();
+                }
+                let vertex = &points[k];
+                let entry = difference(vertex, &scaled(d1, tangent));
+                let bisector = difference(d2, d1);
+                let center: Vec<f32> = vertex
+                    .iter()
+                    .zip(scaled(&bisector, radius / half.cos() / norm(&bisector)))
+                    .map(|(vertex, offset)| vertex + offset)
+                    .collect();
+                let u = scaled(&difference(&entry, &center), 1.0 / radius);
+                Some((
+                    tangent,
+                    Piece::Arc {
+                        center,
+                        u,
+                        v: d1.clone(),
+                        radius,
+                        angle,
+                    },
+                ))
+            })
+            .collect();
+        let tangent = |k: usize| -> f32 {
+            k.checked_sub(1)
+                .and_then(|k| blends.get(k))
+                .and_then(|blend| blend.as_ref())
+                .map_or(0.0, |(tangent, _)| *tangent)
+        };
+
+        let mut pieces = Vec::new();
+        let mut vertices = Vec::new();
+        let mut covered = 0.0;
+        for (k, (direction, length)) in directions.iter().enumerate() {
+            let (before, after) = (tangent(k), tangent(k + 1));
+            let line = Piece::Line {
+                start: points[k]
+                    .iter()
+                    .zip(direction)
+                    .map(|(point, direction)| point + before * direction)
+                    .collect(),
+                direction: direction.clone(),
+                length: (length - before - after).max(0.0),
+            };
+            covered += line.length();
+            pieces.push(line);
+            match blends.get(k).and_then(Option::as_ref) {
+                Some((_, arc)) => {
+                    vertices.push(covered + arc.length() / 2.0);
+                    covered += arc.length();
+                    pieces.push(arc.clone());
+                }
+                None => vertices.push(covered),
+            }
+        }
+        Self { pieces, vertices }
+    }
+
+    /// Whether the corner at `b`, from `a` to `c`, can be blended.
+    pub fn is_blendable(a: &[f32], b: &[f32], c: &[f32]) -> bool {
+        let (incoming, outgoing) = (difference(b, a), difference(c, b));
+        let (l1, l2) = (norm(&incoming), norm(&outgoing));
+        l1 > 0.0 && l2 > 0.0 && turn(&incoming, &outgoing, l1 * l2) <= MAX_BLEND_TURN
+    }
+
+    pub fn length(&self) -> f32 {
+        self.pieces.iter().map(Piece::length).sum()
+    }
+
+    /// Distance along the path to the closest point to each vertex but the first.
+    pub fn vertices(&self) -> &[f32] {
+        &self.vertices
+    }
+
+    /// Radius of the tightest blend, if any.
+    pub fn min_radius(&self) -> Option<f32> {
+        self.pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Arc { radius, .. } => Some(*radius),
+                Piece::Line { .. } => None,
+            })
+            .reduce(f32::min)
+    }
+
+    /// Position and unit tangent at the distance `s` along the path, clamped to its ends.
+    ///
+    /// # Panics
+    ///
+    /// When the path has fewer than two points.
+    pub fn sample(&self, s: f32) -> (Vec<f32>, Vec<f32>) {
+        let mut s = s.max(0.0);
+        for piece in &self.pieces {
+            if s <= piece.length() {
+                return piece.sample(s);
+            }
+            s -= piece.length();
+        }
+        let last = self.pieces.last().expect("a path of at least two points");
+        last.sample(last.length())
+    }
+
+    /// Contour error of `point`: its distance to the path.
+    pub fn distance(&self, point: &[f32]) -> f32 {
+        self.pieces
+            .iter()
+            .map(|piece| piece.distance(point))
+            .fold(f32::INFINITY, f32::min)
+    }
+}
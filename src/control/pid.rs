@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use super::Saturation;
+
+/// Gains of a [`Pid`] controller.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+/// Discrete PID controller with output saturation.
+///
+/// The integral term is clamped to the output limits and stops integrating while the output is
+/// saturated in the direction of the error, so it cannot wind up.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Pid {
+    pub gains: PidGains,
+    pub limits: Saturation,
+    integral: f32,
+    previous_error: Option<f32>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains, limits: Saturation) -> Self {
+        Self {
+            gains,
+            limits,
+            integral: 0.0,
+            previous_error: None,
+        }
+    }
+
+    /// Contribution of the integral term to the output.
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Clears the integrator and the derivative history.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.previous_error = None;
+    }
+
+    /// Takes the integrator and the derivative history of `saved`, keeping its own gains and
+    /// limits, or clears them without it.
+    pub fn restore(&mut self, saved: Option<&Pid>) {
+        match saved {
+            Some(saved) => {
+                self.integral = saved.integral;
+                self.previous_error = saved.previous_error;
+            }
+            None => self.reset(),
+        }
+    }
+
+    /// Computes the saturated command for one sample period of `dt` seconds.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let error = setpoint - measurement;
+        let derivative = match self.previous_error {
+            Some(previous_error) if dt > 0.0 => (error - previous_error) / dt,
+            _ => 0.0,
+        };
+        self.previous_error = Some(error);
+
+        let proportional = self.gains.kp * error;
+        let derivative = self.gains.kd * derivative;
+        let unsaturated = proportional + self.integral + derivative;
+        let pushing_further = (unsaturated >= self.limits.max && error > 0.0)
+            || (unsaturated <= self.limits.min && error < 0.0);
+        if !pushing_further {
+            self.integral += self.gains.ki * error * dt.max(0.0);
+        }
+        self.integral = self.limits.apply(self.integral);
+
+        self.limits.apply(proportional + self.integral + derivative)
+    }
+}
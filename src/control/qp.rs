@@ -0,0 +1,480 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+/// The fixed part of a convex quadratic program in `x`: minimizing `½ x' H x + g' x` with `x`
+/// within bounds and the constraints `C x` within theirs, the cost `g` and the bounds changing
+/// from one solve to the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QpProblem {
+    /// Hessian `H` of the cost, symmetric positive definite.
+    pub hessian: DMatrix<f64>,
+    /// Rows `C` of the constraints besides the bounds of the variables, none for a box.
+    pub constraints: DMatrix<f64>,
+}
+
+/// The part of a quadratic program that changes from one solve to the next. The bounds may be
+/// infinite.
+#[derive(Clone, Copy, Debug)]
+pub struct QpData<'a> {
+    pub linear: &'a DVector<f64>,
+    pub lower: &'a DVector<f64>,
+    pub upper: &'a DVector<f64>,
+    pub constraint_lower: &'a DVector<f64>,
+    pub constraint_upper: &'a DVector<f64>,
+}
+
+/// Outcome of a solve.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum QpStatus {
+    #[default]
+    Solved,
+    /// The solver ran out of iterations, with its best solution.
+    MaxIterations,
+    /// No solution satisfies the constraints.
+    Infeasible,
+}
+
+/// Solution of a quadratic program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QpSolution {
+    pub x: DVector<f64>,
+    pub status: QpStatus,
+    /// Iterations the solver took, 0 when it doesn't tell.
+    pub iterations: usize,
+}
+
+/// A solver of the quadratic programs of a [`QpProblem`].
+pub trait QpSolver: fmt::Debug + Send + Sync {
+    /// Name of the solver, for the messages.
+    fn name(&self) -> &'static str;
+
+    /// Prepares to solve the programs of `problem`, or tells why it can't.
+    fn setup(&mut self, problem: &QpProblem) -> Result<(), String>;
+
+    /// Solves the program of the last problem set up with `data`, from the solution `warm`.
+    fn solve(&mut self, data: &QpData, warm: &DVector<f64>) -> QpSolution;
+
+    fn boxed_clone(&self) -> Box<dyn QpSolver>;
+}
+
+impl Clone for Box<dyn QpSolver> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+/// Accelerated projected gradient (FISTA): cheap iterations and no factorization, but bounds
+/// on the variables only.
+#[derive(Clone, Debug)]
+pub struct ProjectedGradient {
+    pub max_iterations: usize,
+    /// Change of the solution, relative to its largest variable, under which it has converged.
+    pub tolerance: f64,
+    hessian: DMatrix<f64>,
+    /// Step of the gradient, the inverse of the largest eigenvalue of the Hessian.
+    step: f64,
+}
+
+impl Default for ProjectedGradient {
+    fn default() -> Self {
+        Self {
+            max_iterations: 500,
+            tolerance: 1e-9,
+            hessian: DMatrix::zeros(0, 0),
+            step: 0.0,
+        }
+    }
+}
+
+impl QpSolver for ProjectedGradient {
+    fn name(&self) -> &'static str {
+        "projected gradient"
+    }
+
+    fn setup(&mut self, problem: &QpProblem) -> Result<(), String> {
+        if problem.constraints.nrows() > 0 {
+            return Err("the projected gradient only bounds the variables".to_string());
+        }
+        let largest = problem.hessian.clone().symmetric_eigenvalues().max();
+        if !(largest.is_finite() && largest > 0.0) {
+            return Err("the Hessian of the cost isn't positive definite".to_string());
+        }
+        self.hessian = problem.hessian.clone();
+        self.step = 1.0 / largest;
+        Ok(())
+    }
+
+    fn solve(&mut self, data: &QpData, warm: &DVector<f64>) -> QpSolution {
+        let project = |x: &mut DVector<f64>| {
+            for ((value, lower), upper) in
+                x.iter_mut().zip(data.lower.iter()).zip(data.upper.iter())
+            {
+                *value = value.max(*lower).min(*upper);
+            }
+        };
+        let mut x = warm.clone();
+        project(&mut x);
+        let (mut extrapolated, mut momentum) = (x.clone(), 1.0);
+        for iteration in 1..=self.max_iterations {
+            let mut next =
+                &extrapolated - (&self.hessian * &extrapolated + data.linear) * self.step;
+            project(&mut next);
+            let next_momentum = (1.0 + (1.0 + 4.0 * momentum * momentum).sqrt()) / 2.0;
+            let change = &next - &x;
+            extrapolated = &next + &change * ((momentum - 1.0) / next_momentum);
+            momentum = next_momentum;
+            x = next;
+            if change.amax() <= self.tolerance * x.amax().max(1.0) {
+                return QpSolution {
+                    x,
+                    status: QpStatus::Solved,
+                    iterations: iteration,
+                };
+            }
+        }
+        QpSolution {
+            x,
+            status: QpStatus::MaxIterations,
+            iterations: self.max_iterations,
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn QpSolver> {
+        Box::new(self.clone())
+    }
+}
+
+/// Primal-dual interior point method with Mehrotra's predictor-corrector: a few iterations of
+/// a dense factorization each, for any constraints, and telling infeasible programs apart.
+/// Pure Rust, so it runs on the web too.
+///
+/// Close to the solution, the factorization loses the precision the iterations gain; once
+/// they stop improving, the best iterate so far is the solution if within a thousand times
+/// the tolerance.
+#[derive(Clone, Debug)]
+pub struct InteriorPoint {
+    pub max_iterations: usize,
+    /// Residuals, relative to the terms they sum, and complementarity under which the solution
+    /// has converged.
+    pub tolerance: f64,
+    problem: Option<QpProblem>,
+}
+
+/// Duals past which the constraints can't be met.
+const DIVERGED_DUAL: f64 = 1e12;
+
+impl Default for InteriorPoint {
+    fn default() -> Self {
+        Self {
+            max_iterations: 50,
+            tolerance: 1e-8,
+            problem: None,
+        }
+    }
+}
+
+impl QpSolver for InteriorPoint {
+    fn name(&self) -> &'static str {
+        "interior point"
+    }
+
+    fn setup(&mut self, problem: &QpProblem) -> Result<(), String> {
+        if problem.hessian.clone().cholesky().is_none() {
+            return Err("the Hessian of the cost isn't positive definite".to_string());
+        }
+        self.problem = Some(problem.clone());
+        Ok(())
+    }
+
+    fn solve(&mut self, data: &QpData, warm: &DVector<f64>) -> QpSolution {
+        let problem = self
+            .problem
+            .as_ref()
+            .expect("the interior point is solved once set up");
+        let variables = problem.hessian.nrows();
+        let infeasible = QpSolution {
+            x: warm.clone(),
+            status: QpStatus::Infeasible,
+            iterations: 0,
+        };
+        // The finite bounds as rows of `G x ≤ h`.
+        let mut rows: Vec<(DVector<f64>, f64)> = Vec::new();
+        let unit = |index: usize, sign: f64| {
+            let mut row = DVector::zeros(variables);
+            row[index] = sign;
+            row
+        };
+        for index in 0..variables {
+            let (lower, upper) = (data.lower[index], data.upper[index]);
+            if lower > upper {
+                return infeasible;
+            }
+            if upper.is_finite() {
+                rows.push((unit(index, 1.0), upper));
+            }
+            if lower.is_finite() {
+                rows.push((unit(index, -1.0), -lower));
+            }
+        }
+        for (index, constraint) in problem.constraints.row_iter().enumerate() {
+            let (lower, upper) = (data.constraint_lower[index], data.constraint_upper[index]);
+            if lower > upper {
+                return infeasible;
+            }
+            if upper.is_finite() {
+                rows.push((constraint.transpose(), upper));
+            }
+            if lower.is_finite() {
+                rows.push((-constraint.transpose(), -lower));
+            }
+        }
+        let hessian = &problem.hessian;
+        if rows.is_empty() {
+            let x = hessian
+                .clone()
+                .cholesky()
+                .map_or_else(|| warm.clone(), |cholesky| cholesky.solve(&-data.linear));
+            return QpSolution {
+                x,
+                status: QpStatus::Solved,
+                iterations: 0,
+            };
+        }
+        let g = DMatrix::from_fn(rows.len(), variables, |row, column| rows[row].0[column]);
+        let h = DVector::from_iterator(rows.len(), rows.iter().map(|(_, bound)| *bound));
+        let count = rows.len() as f64;
+
+        let mut x = warm.clone();
+        let mut s = (&h - &g * &x).map(|slack| slack.max(1.0));
+        let mut z = DVector::from_element(rows.len(), 1.0);
+        let close = 1e3 * self.tolerance;
+        // The largest of the residuals, relative to their largest term, and of the gap.
+        let mut best = (f64::INFINITY, x.clone());
+        for iteration in 0..self.max_iterations {
+            let (curvature, reaction, constrained) = (hessian * &x, g.transpose() * &z, &g * &x);
+            let dual = &curvature + data.linear + &reaction;
+            let primal = &constrained + &s - &h;
+            let gap = s.dot(&z) / count;
+            let largest = |terms: [&DVector<f64>; 3]| {
+                1.0 + terms
+                    .map(|term| term.amax())
+                    .into_iter()
+                    .fold(0.0, f64::max)
+            };
+            let error = (dual.amax() / largest([&curvature, data.linear, &reaction]))
+                .max(primal.amax() / largest([&constrained, &s, &h]))
+                .max(gap);
+            if error <= self.tolerance {
+                return QpSolution {
+                    x,
+                    status: QpStatus::Solved,
+                    iterations: iteration,
+                };
+            }
+            if error < best.0 {
+                best = (error, x.clone());
+            } else if best.0 <= close && error > 1e3 * best.0 {
+                return QpSolution {
+                    x: best.1,
+                    status: QpStatus::Solved,
+                    iterations: iteration,
+                };
+            }
+            if z.amax() > DIVERGED_DUAL {
+                return QpSolution {
+                    status: QpStatus::Infeasible,
+                    iterations: iteration,
+                    ..infeasible
+                };
+            }
+
+            let weights = z.component_div(&s);
+            let mut reduced = hessian.clone();
+            for (row, weight) in g.row_iter().zip(weights.iter()) {
+                reduced += row.transpose() * row * *weight;
+            }
+            let Some(cholesky) = reduced.cholesky() else {
+                break;
+            };
+            // The step for the complementarity `s ∘ z` to reach the residual `complementarity`.
+            let direction = |complementarity: &DVector<f64>| {
+                let shifted = weights.component_mul(&primal) - complementarity.component_div(&s);
+                let dx = cholesky.solve(&(-&dual - g.transpose() * &shifted));
+                let dz = weights.component_mul(&(&g * &dx + &primal))
+                    - complementarity.component_div(&s);
+                let ds = -(complementarity + s.component_mul(&dz)).component_div(&z);
+                (dx, ds, dz)
+            };
+            let longest = |ds: &DVector<f64>, dz: &DVector<f64>| {
+                let mut step: f64 = 1.0;
+                for (value, change) in s.iter().zip(ds.iter()).chain(z.iter().zip(dz.iter())) {
+                    if *change < 0.0 {
+                        step = step.min(-value / change);
+                    }
+                }
+                step
+            };
+
+            let affine = s.component_mul(&z);
+            let (_, ds, dz) = direction(&affine);
+            let step = longest(&ds, &dz);
+            let affine_gap = (&s + &ds * step).dot(&(&z + &dz * step)) / count;
+            let centering = (affine_gap / gap).powi(3);
+            let corrected =
+                affine + ds.component_mul(&dz) - DVector::from_element(s.len(), centering * gap);
+            let (dx, ds, dz) = direction(&corrected);
+            let step = (0.99 * longest(&ds, &dz)).min(1.0);
+            x += dx * step;
+            s += ds * step;
+            z += dz * step;
+        }
+        if best.0 <= close {
+            return QpSolution {
+                x: best.1,
+                status: QpStatus::Solved,
+                iterations: self.max_iterations,
+            };
+        }
+        // Out of iterations, the constraints are met or can't be.
+        let primal = &g * &x + &s - &h;
+        let status = if primal.amax() <= close * (1.0 + h.amax()) {
+            QpStatus::MaxIterations
+        } else {
+            QpStatus::Infeasible
+        };
+        QpSolution {
+            x,
+            status,
+            iterations: self.max_iterations,
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn QpSolver> {
+        Box::new(self.clone())
+    }
+}
+
+/// OSQP, the operator splitting solver of the C library: sparse factorizations cached across
+/// the solves, for large problems. Native builds with the `osqp` feature only.
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+pub struct Osqp {
+    pub max_iterations: u32,
+    pub tolerance: f64,
+    problem: Option<QpProblem>,
+    solver: Option<osqp::Problem>,
+}
+
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+impl Osqp {
+    pub fn new(max_iterations: u32, tolerance: f64) -> Self {
+        Self {
+            max_iterations,
+            tolerance,
+            problem: None,
+            solver: None,
+        }
+    }
+}
+
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+impl fmt::Debug for Osqp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Osqp")
+            .field("max_iterations", &self.max_iterations)
+            .field("tolerance", &self.tolerance)
+            .field("problem", &self.problem)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Bounds OSQP takes as infinite.
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+const OSQP_INFINITY: f64 = 1e30;
+
+#[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+impl QpSolver for Osqp {
+    fn name(&self) -> &'static str {
+        "OSQP"
+    }
+
+    fn setup(&mut self, problem: &QpProblem) -> Result<(), String> {
+        let variables = problem.hessian.nrows();
+        // The bounds of the variables are the first constraints.
+        let mut constraints = DMatrix::identity(variables, variables)
+            .resize_vertically(variables + problem.constraints.nrows(), 0.0);
+        constraints
+            .rows_mut(variables, problem.constraints.nrows())
+            .copy_from(&problem.constraints);
+        let csc = |matrix: &DMatrix<f64>| {
+            osqp::CscMatrix::from_column_iter_dense(
+                matrix.nrows(),
+                matrix.ncols(),
+                matrix.iter().copied(),
+            )
+        };
+        let unbounded = vec![OSQP_INFINITY; constraints.nrows()];
+        let settings = osqp::Settings::default()
+            .verbose(false)
+            .warm_start(true)
+            .max_iter(self.max_iterations)
+            .eps_abs(self.tolerance)
+            .eps_rel(self.tolerance);
+        let solver = osqp::Problem::new(
+            csc(&problem.hessian).into_upper_tri(),
+            &vec![0.0; variables],
+            csc(&constraints),
+            &unbounded.iter().map(|bound| -bound).collect::<Vec<_>>(),
+            &unbounded,
+            &settings,
+        )
+        .map_err(|error| format!("OSQP can't set up the problem: {error:?}"))?;
+        self.problem = Some(problem.clone());
+        self.solver = Some(solver);
+        Ok(())
+    }
+
+    fn solve(&mut self, data: &QpData, warm: &DVector<f64>) -> QpSolution {
+        let solver = self.solver.as_mut().expect("OSQP is solved once set up");
+        let clamp = |bound: &f64| bound.clamp(-OSQP_INFINITY, OSQP_INFINITY);
+        let lower: Vec<f64> = data
+            .lower
+            .iter()
+            .chain(data.constraint_lower.iter())
+            .map(clamp)
+            .collect();
+        let upper: Vec<f64> = data
+            .upper
+            .iter()
+            .chain(data.constraint_upper.iter())
+            .map(clamp)
+            .collect();
+        solver.update_lin_cost(data.linear.as_slice());
+        solver.update_bounds(&lower, &upper);
+        solver.warm_start_x(warm.as_slice());
+        let solution = |x: &[f64], status, iterations: u32| QpSolution {
+            x: DVector::from_column_slice(x),
+            status,
+            iterations: iterations as usize,
+        };
+        match solver.solve() {
+            osqp::Status::Solved(result) | osqp::Status::SolvedInaccurate(result) => {
+                solution(result.x(), QpStatus::Solved, result.iter())
+            }
+            osqp::Status::MaxIterationsReached(result) | osqp::Status::TimeLimitReached(result) => {
+                solution(result.x(), QpStatus::MaxIterations, result.iter())
+            }
+            status => solution(warm.as_slice(), QpStatus::Infeasible, status.iter()),
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn QpSolver> {
+        // The workspace of the library isn't shared: the clone sets up its own.
+        let mut clone = Self::new(self.max_iterations, self.tolerance);
+        if let Some(problem) = &self.problem {
+            // It was set up once already.
+            let _ = clone.setup(problem);
+        }
+        Box::new(clone)
+    }
+}
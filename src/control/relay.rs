@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use super::PidGains;
+
+/// Gain and period at which a loop closed by a proportional controller oscillates steadily.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct UltimateGain {
+    pub gain: f32,
+    /// Period of the oscillation, in seconds.
+    pub period: f32,
+}
+
+/// Rule turning the ultimate gain and period into the gains of a PID controller.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TuningRule {
+    /// The classic rule, fast with about a quarter decay ratio.
+    #[default]
+    ZieglerNichols,
+    /// A more conservative rule, with less overshoot.
+    TyreusLuyben,
+}
+
+impl TuningRule {
+    pub const ALL: [Self; 2] = [Self::ZieglerNichols, Self::TyreusLuyben];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ZieglerNichols => "Ziegler–Nichols",
+            Self::TyreusLuyben => "Tyreus–Luyben",
+        }
+    }
+
+    /// The gains of the rule, in the parallel form of [`Pid`](super::Pid).
+    pub fn gains(self, ultimate: UltimateGain) -> PidGains {
+        let UltimateGain { gain, period } = ultimate;
+        // Proportional gain, integral time and derivative time.
+        let (kp, ti, td) = match self {
+            Self::ZieglerNichols => (0.6 * gain, period / 2.0, period / 8.0),
+            Self::TyreusLuyben => (gain / 2.2, 2.2 * period, period / 6.3),
+        };
+        PidGains {
+            kp,
+            ki: kp / ti,
+            kd: kp * td,
+        }
+    }
+}
+
+/// Relay with hysteresis closing a loop in place of its controller, so it settles into a limit
+/// cycle at its ultimate period (Åström–Hägglund).
+///
+/// The output switches to `+amplitude` when the error goes over the hysteresis, and to
+/// `-amplitude` when it goes under its opposite. Each cycle, from a switch up to the next one,
+/// gives a period and the amplitude of the error; by the describing function of the relay, the
+/// ultimate gain is `4 d / (π √(a² − ε²))` for an output `d`, an error of amplitude `a` and a
+/// hysteresis `ε`.
+#[derive(Clone, Debug)]
+pub struct RelayFeedback {
+    pub amplitude: f32,
+    pub hysteresis: f32,
+    output: f32,
+    /// Smallest and largest errors since the last switch up.
+    extrema: Option<(f32, f32)>,
+    /// Time of the last switch up.
+    switched: Option<f32>,
+    /// Period and amplitude of the error of each cycle completed.
+    cycles: Vec<(f32, f32)>,
+}
+
+impl RelayFeedback {
+    pub fn new(amplitude: f32, hysteresis: f32) -> Self {
+        Self {
+            amplitude,
+            hysteresis: hysteresis.max(0.0),
+            output: amplitude,
+            extrema: None,
+            switched: None,
+            cycles: Vec::new(),
+        }
+    }
+
+    /// Output of the relay from the `error` at `time`, in seconds.
+    pub fn update(&mut self, time: f32, error: f32) -> f32 {
+        let previous = self.output;
+        if error > self.hysteresis {
+            self.output = self.amplitude;
+        } else if error < -self.hysteresis {
+            self.output = -self.amplitude;
+        }
+        let (low, high) = self.extrema.unwrap_or((error, error));
+        self.extrema = Some((low.min(error), high.max(error)));
+        if previous < 0.0 && self.output > 0.0 {
+            if let (Some(switched), Some((low, high))) = (self.switched, self.extrema) {
+                self.cycles.push((time - switched, (high - low) / 2.0));
+            }
+            self.switched = Some(time);
+            self.extrema = Some((error, error));
+        }
+        self.output
+    }
+
+    /// Number of cycles completed.
+    pub fn cycles(&self) -> usize {
+        self.cycles.len()
+    }
+
+    /// The ultimate gain and period averaged over the cycles completed but the first, still
+    /// settling, once there are at least two.
+    pub fn ultimate(&self) -> Option<UltimateGain> {
+        let settled = self.cycles.get(1..).filter(|cycles| !cycles.is_empty())?;
+        let count = settled.len() as f32;
+        let period = settled.iter().map(|(period, _)| period).sum::<f32>() / count;
+        let amplitude = settled.iter().map(|(_, amplitude)| amplitude).sum::<f32>() / count;
+        let swing = (amplitude * amplitude - self.hysteresis * self.hysteresis).sqrt();
+        let gain = 4.0 * self.amplitude.abs() / (std::f32::consts::PI * swing);
+        (gain.is_finite() && period > 0.0).then_some(UltimateGain { gain, period })
+    }
+}
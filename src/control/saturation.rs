@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// Limits a signal to a closed interval.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Saturation {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Saturation {
+    /// # Panics
+    ///
+    /// When `min` is greater than `max`.
+    pub fn new(min: f32, max: f32) -> Self {
+        assert!(min <= max, "invalid saturation limits [{min}, {max}]");
+        Self { min, max }
+    }
+
+    /// Symmetric limits around zero.
+    pub fn symmetric(limit: f32) -> Self {
+        Self::new(-limit.abs(), limit.abs())
+    }
+
+    pub fn apply(&self, value: f32) -> f32 {
+        value.clamp(self.min, self.max)
+    }
+
+    pub fn is_saturated(&self, value: f32) -> bool {
+        value <= self.min || value >= self.max
+    }
+}
+
+impl Default for Saturation {
+    fn default() -> Self {
+        Self {
+            min: f32::NEG_INFINITY,
+            max: f32::INFINITY,
+        }
+    }
+}
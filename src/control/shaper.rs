@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Tolerated residual vibration of the extra-insensitive shaper, as a fraction of the unshaped
+/// one.
+const EI_TOLERANCE: f32 = 0.05;
+
+/// Design of an [`InputShaper`], trading delay for robustness to errors on the mode.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shaper {
+    /// Zero vibration: two impulses over half a period, sensitive to the frequency.
+    Zv,
+    /// Zero vibration and derivative: three impulses over a period, robust to ±20 % or so.
+    #[default]
+    Zvd,
+    /// Extra-insensitive: three impulses over a period, letting 5 % of the vibration through at
+    /// the design frequency to widen the insensitive band further. Designed for lightly damped
+    /// modes.
+    Ei,
+}
+
+impl Shaper {
+    pub const ALL: [Shaper; 3] = [Shaper::Zv, Shaper::Zvd, Shaper::Ei];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shaper::Zv => "ZV",
+            Shaper::Zvd => "ZVD",
+            Shaper::Ei => "EI",
+        }
+    }
+}
+
+/// Filter splitting a reference into delayed impulses that cancel the vibration of a mode of
+/// `frequency_hz` and `damping`.
+#[derive(Clone, Debug)]
+pub struct InputShaper {
+    /// Delay in samples and amplitude of each impulse; the amplitudes sum to one.
+    impulses: Vec<(usize, f32)>,
+    /// Past inputs, the latest first.
+    history: VecDeque<f32>,
+}
+
+impl InputShaper {
+    /// Creates a shaper sampled every `dt` seconds. The delays are rounded to whole samples, so
+    /// the mode should last many samples.
+    pub fn new(shaper: Shaper, frequency_hz: f32, damping: f32, dt: f32) -> Self {
+        let damping = damping.clamp(0.0, 0.99);
+        let damped_period = 1.0 / (frequency_hz * (1.0 - damping * damping).sqrt());
+        let k = (-damping * std::f32::consts::PI / (1.0 - damping * damping).sqrt()).exp();
+        let amplitudes = match shaper {
+            Shaper::Zv => vec![1.0, k],
+            Shaper::Zvd => vec![1.0, 2.0 * k, k * k],
+            Shaper::Ei => vec![
+                (1.0 + EI_TOLERANCE) / 4.0,
+                (1.0 - EI_TOLERANCE) / 2.0,
+                (1.0 + EI_TOLERANCE) / 4.0,
+            ],
+        };
+        let sum: f32 = amplitudes.iter().sum();
+        let impulses = amplitudes
+            .into_iter()
+            .enumerate()
+            .map(|(i, amplitude)| {
+                let delay = i as f32 * damped_period / 2.0 / dt;
+                let delay = if delay.is_finite() {
+                    delay.round()
+                } else {
+                    0.0
+                };
+                (delay as usize, amplitude / sum)
+            })
+            .collect::<Vec<_>>();
+        let length = impulses.last().map_or(0, |(delay, _)| *delay) + 1;
+        Self {
+            impulses,
+            history: VecDeque::from(vec![0.0; length]),
+        }
+    }
+
+    /// Delay in samples and amplitude of each impulse.
+    pub fn impulses(&self) -> &[(usize, f32)] {
+        &self.impulses
+    }
+
+    /// Number of samples the shaped reference takes to settle after a step.
+    pub fn duration(&self) -> usize {
+        self.history.len() - 1
+    }
+
+    /// Sets the shaper state as if `value` had been applied forever.
+    pub fn reset(&mut self, value: f32) {
+        self.history.iter_mut().for_each(|input| *input = value);
+    }
+
+    pub fn update(&mut self, input: f32) -> f32 {
+        self.history.pop_back();
+        self.history.push_front(input);
+        self.impulses
+            .iter()
+            .map(|(delay, amplitude)| amplitude * self.history[*delay])
+            .sum()
+    }
+}
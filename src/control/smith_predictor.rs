@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use super::Pid;
+
+/// Controller compensating a known dead time, e.g. of a network between the controller and the
+/// plant.
+///
+/// A model of the plant without the dead time, a first-order lag or an integrator, predicts the
+/// measurement the controller would see without it. The controller closes its loop on that prediction,
+/// corrected by the difference between the measurement and the model delayed by the dead time,
+/// so it can be tuned for the plant alone while model errors and disturbances still reach it.
+#[derive(Clone, Debug)]
+pub struct SmithPredictor {
+    pub controller: Pid,
+    /// Static gain of the model.
+    pub gain: f32,
+    /// Time constant of the model, in seconds, or infinite for an integrating model, e.g. from
+    /// the velocity commanded to a joint to its angle.
+    pub time_constant: f32,
+    /// Output of the model without the dead time.
+    prediction: f32,
+    /// Outputs of the model over the last dead time, the oldest first.
+    delayed: VecDeque<f32>,
+}
+
+impl SmithPredictor {
+    /// Creates a predictor for a plant of `gain` and `time_constant` behind `dead_time`
+    /// seconds, sampled every `dt` seconds. The dead time is rounded to whole samples.
+    pub fn new(controller: Pid, gain: f32, time_constant: f32, dead_time: f32, dt: f32) -> Self {
+        let samples = (dead_time / dt).round();
+        let samples = if samples.is_finite() {
+            samples.max(0.0) as usize
+        } else {
+            0
+        };
+        Self {
+            controller,
+            gain,
+            time_constant,
+            prediction: 0.0,
+            delayed: VecDeque::from(vec![0.0; samples]),
+        }
+    }
+
+    /// Dead time compensated, in samples.
+    pub fn dead_time(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// Output of the model without the dead time after the last update.
+    pub fn prediction(&self) -> f32 {
+        self.prediction
+    }
+
+    /// Clears the controller and the model.
+    pub fn reset(&mut self) {
+        self.controller.reset();
+        self.prediction = 0.0;
+        self.delayed.iter_mut().for_each(|output| *output = 0.0);
+    }
+
+    /// Computes the command for one sample period of `dt` seconds.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        self.delayed.push_back(self.prediction);
+        let delayed = self.delayed.pop_front().unwrap_or(self.prediction);
+        let feedback = self.prediction + measurement - delayed;
+        let command = self.controller.update(setpoint, feedback, dt);
+
+        if self.time_constant.is_infinite() {
+            self.prediction += self.gain * command * dt;
+            return command;
+        }
+        // Exact discretization of the lag, held over the sample.
+        let decay = if self.time_constant > 0.0 {
+            (-dt / self.time_constant).exp()
+        } else {
+            0.0
+        };
+        self.prediction = decay * self.prediction + (1.0 - decay) * self.gain * command;
+        command
+    }
+}
@@ -0,0 +1,35 @@
+//! [`proptest`] strategies generating valid parameters for the control blocks, so downstream
+//! crates can property-test their own blocks the same way.
+use proptest::prelude::*;
+
+use super::{Discretization, PidGains, Saturation};
+
+pub fn pid_gains() -> impl Strategy<Value = PidGains> {
+    (0.0f32..100.0, 0.0f32..100.0, 0.0f32..10.0).prop_map(|(kp, ki, kd)| PidGains { kp, ki, kd })
+}
+
+pub fn saturation() -> impl Strategy<Value = Saturation> {
+    (-100.0f32..100.0, 0.0f32..200.0).prop_map(|(min, width)| Saturation::new(min, min + width))
+}
+
+/// Sample periods between 10 kHz and 10 Hz.
+pub fn sample_time() -> impl Strategy<Value = f32> {
+    1e-4f32..0.1
+}
+
+pub fn cutoff_frequency() -> impl Strategy<Value = f32> {
+    0.1f32..50.0
+}
+
+pub fn discretization() -> impl Strategy<Value = Discretization> {
+    prop_oneof![
+        Just(Discretization::ForwardEuler),
+        Just(Discretization::ZeroOrderHold),
+        Just(Discretization::Tustin),
+    ]
+}
+
+/// Bounded signal samples.
+pub fn signal(len: usize) -> impl Strategy<Value = Vec<f32>> {
+    prop::collection::vec(-1_000.0f32..1_000.0, len)
+}
@@ -0,0 +1,588 @@
+use serde::{Deserialize, Serialize};
+
+/// Points at which the splines are checked against the limits, per segment.
+const LIMIT_SAMPLES: usize = 64;
+
+/// Position, velocity and acceleration of a motion at an instant.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionState {
+    pub position: f32,
+    pub velocity: f32,
+    pub acceleration: f32,
+}
+
+/// A motion planned over a duration.
+pub trait Profile {
+    /// Time the motion takes, in seconds.
+    fn duration(&self) -> f32;
+
+    /// State of the motion `t` seconds after its start, held before and after it.
+    fn state(&self, t: f32) -> MotionState;
+}
+
+/// Point-to-point move with a bounded velocity and acceleration: it accelerates, cruises, then
+/// decelerates to stop on the target, or only accelerates and decelerates (a triangular
+/// profile) when the distance is too short to reach the velocity limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrapezoidalProfile {
+    start: f32,
+    /// Signed distance to the target.
+    distance: f32,
+    /// Peak velocity reached, unsigned.
+    velocity: f32,
+    acceleration: f32,
+    /// Durations of the acceleration (and deceleration) and of the cruise.
+    acceleration_time: f32,
+    cruise_time: f32,
+}
+
+impl TrapezoidalProfile {
+    /// Plans a move from `start` to `end`. Without a positive velocity and acceleration, the
+    /// move is empty and stays at `start`.
+    pub fn new(start: f32, end: f32, max_velocity: f32, max_acceleration: f32) -> Self {
+        let length = (end - start).abs();
+        if max_velocity <= 0.0 || max_acceleration <= 0.0 || length == 0.0 {
+            return Self {
+                start,
+                distance: 0.0,
+                velocity: 0.0,
+                acceleration: 0.0,
+                acceleration_time: 0.0,
+                cruise_time: 0.0,
+            };
+        }
+        let velocity = max_velocity.min((length * max_acceleration).sqrt());
+        let acceleration_time = velocity / max_acceleration;
+        Self {
+            start,
+            distance: end - start,
+            velocity,
+            acceleration: max_acceleration,
+            acceleration_time,
+            cruise_time: (length - velocity * acceleration_time) / velocity,
+        }
+    }
+
+    /// Time the move takes, in seconds.
+    pub fn duration(&self) -> f32 {
+        2.0 * self.acceleration_time + self.cruise_time
+    }
+
+    pub fn end(&self) -> f32 {
+        self.start + self.distance
+    }
+
+    /// Position and velocity `t` seconds after the start.
+    pub fn sample(&self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, self.duration());
+        let (a, ta) = (self.acceleration, self.acceleration_time);
+        let (covered, velocity) = if t < ta {
+            (0.5 * a * t * t, a * t)
+        } else if t < ta + self.cruise_time {
+            (0.5 * a * ta * ta + self.velocity * (t - ta), self.velocity)
+        } else {
+            let left = self.duration() - t;
+            (self.distance.abs() - 0.5 * a * left * left, a * left)
+        };
+        let sign = self.distance.signum();
+        (self.start + sign * covered, sign * velocity)
+    }
+}
+
+impl Profile for TrapezoidalProfile {
+    fn duration(&self) -> f32 {
+        TrapezoidalProfile::duration(self)
+    }
+
+    fn state(&self, t: f32) -> MotionState {
+        let (position, velocity) = self.sample(t);
+        let t = t.clamp(0.0, self.duration());
+        let acceleration = if self.duration() == 0.0 || t == self.duration() {
+            0.0
+        } else if t < self.acceleration_time {
+            self.acceleration
+        } else if t < self.acceleration_time + self.cruise_time {
+            0.0
+        } else {
+            -self.acceleration
+        };
+        MotionState {
+            position,
+            velocity,
+            acceleration: self.distance.signum() * acceleration,
+        }
+    }
+}
+
+/// Point-to-point move with a bounded velocity, acceleration and jerk (an S-curve): the
+/// acceleration ramps up and down instead of stepping, which excites the flexible modes of the
+/// plant far less. Each ramp lasts `max_acceleration / max_jerk`; the acceleration only reaches
+/// its limit, and the velocity its own, when the move is long enough.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SCurveProfile {
+    start: f32,
+    /// Signed distance to the target.
+    distance: f32,
+    /// Peak velocity and acceleration reached, unsigned.
+    velocity: f32,
+    acceleration: f32,
+    jerk: f32,
+    /// Durations of a ramp of the acceleration, of the acceleration (and deceleration) and of
+    /// the cruise.
+    jerk_time: f32,
+    acceleration_time: f32,
+    cruise_time: f32,
+}
+
+impl SCurveProfile {
+    /// Plans a move from `start` to `end`. Without a positive velocity, acceleration and jerk,
+    /// the move is empty and stays at `start`.
+    pub fn new(
+        start: f32,
+        end: f32,
+        max_velocity: f32,
+        max_acceleration: f32,
+        max_jerk: f32,
+    ) -> Self {
+        let length = (end - start).abs();
+        if max_velocity <= 0.0 || max_acceleration <= 0.0 || max_jerk <= 0.0 || length == 0.0 {
+            return Self {
+                start,
+                distance: 0.0,
+                velocity: 0.0,
+                acceleration: 0.0,
+                jerk: 0.0,
+                jerk_time: 0.0,
+                acceleration_time: 0.0,
+                cruise_time: 0.0,
+            };
+        }
+        let (a, j) = (max_acceleration, max_jerk);
+        // Time to accelerate up to a velocity, and the distance covered accelerating up to it
+        // and decelerating back down.
+        let acceleration_time = |v: f32| {
+            if v * j >= a * a {
+                v / a + a / j
+            } else {
+                2.0 * (v / j).sqrt()
+            }
+        };
+        let mut velocity = max_velocity;
+        if velocity * acceleration_time(velocity) > length {
+            // Too short to cruise: the fastest move reaching the limit of the acceleration, or
+            // only ramping it up and down.
+            velocity = a / 2.0 * (-a / j + ((a / j).powi(2) + 4.0 * length / a).sqrt());
+            if velocity * j < a * a {
+                velocity = (length * j.sqrt() / 2.0).powf(2.0 / 3.0);
+            }
+        }
+        let jerk_time = (a / j).min((velocity / j).sqrt());
+        let acceleration = j * jerk_time;
+        let acceleration_time = velocity / acceleration + jerk_time;
+        Self {
+            start,
+            distance: end - start,
+            velocity,
+            acceleration,
+            jerk: j,
+            jerk_time,
+            acceleration_time,
+            cruise_time: (length / velocity - acceleration_time).max(0.0),
+        }
+    }
+
+    pub fn end(&self) -> f32 {
+        self.start + self.distance
+    }
+
+    /// Distance covered, velocity and acceleration `t` seconds into the acceleration.
+    fn accelerating(&self, t: f32) -> (f32, f32, f32) {
+        let (j, tj, ta) = (self.jerk, self.jerk_time, self.acceleration_time);
+        if t < tj {
+            (j * t.powi(3) / 6.0, j * t * t / 2.0, j * t)
+        } else if t < ta - tj {
+            let (v1, p1) = (j * tj * tj / 2.0, j * tj.powi(3) / 6.0);
+            let (a, dt) = (self.acceleration, t - tj);
+            (p1 + v1 * dt + a * dt * dt / 2.0, v1 + a * dt, a)
+        } else {
+            // Mirrors the ramp up, from the end of the acceleration.
+            let left = ta - t;
+            (
+                self.velocity * ta / 2.0 - self.velocity * left + j * left.powi(3) / 6.0,
+                self.velocity - j * left * left / 2.0,
+                j * left,
+            )
+        }
+    }
+}
+
+impl Profile for SCurveProfile {
+    fn duration(&self) -> f32 {
+        2.0 * self.acceleration_time + self.cruise_time
+    }
+
+    fn state(&self, t: f32) -> MotionState {
+        let t = t.clamp(0.0, self.duration());
+        let ta = self.acceleration_time;
+        let (covered, velocity, acceleration) = if self.distance == 0.0 {
+            (0.0, 0.0, 0.0)
+        } else if t < ta {
+            self.accelerating(t)
+        } else if t < ta + self.cruise_time {
+            (self.velocity * (ta / 2.0 + t - ta), self.velocity, 0.0)
+        } else {
+            let (covered, velocity, acceleration) = self.accelerating(self.duration() - t);
+            (self.distance.abs() - covered, velocity, -acceleration)
+        };
+        let sign = self.distance.signum();
+        MotionState {
+            position: self.start + sign * covered,
+            velocity: sign * velocity,
+            acceleration: sign * acceleration,
+        }
+    }
+}
+
+/// A polynomial of time, `Σ cᵢ tⁱ`, over a segment of a spline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Polynomial {
+    coefficients: [f32; 6],
+    duration: f32,
+}
+
+impl Polynomial {
+    /// The cubic from `p0` at velocity `v0` to `p1` at velocity `v1` over `h` seconds.
+    fn cubic(p0: f32, v0: f32, p1: f32, v1: f32, h: f32) -> Self {
+        let slope = (p1 - p0) / h;
+        Self {
+            coefficients: [
+                p0,
+                v0,
+                (3.0 * slope - 2.0 * v0 - v1) / h,
+                (v0 + v1 - 2.0 * slope) / (h * h),
+                0.0,
+                0.0,
+            ],
+            duration: h,
+        }
+    }
+
+    /// The quintic from `p0` at velocity `v0` to `p1` at velocity `v1` over `h` seconds, without
+    /// acceleration at either end.
+    fn quintic(p0: f32, v0: f32, p1: f32, v1: f32, h: f32) -> Self {
+        let distance = p1 - p0;
+        Self {
+            coefficients: [
+                p0,
+                v0,
+                0.0,
+                (20.0 * distance - (8.0 * v1 + 12.0 * v0) * h) / (2.0 * h.powi(3)),
+                (-30.0 * distance + (14.0 * v1 + 16.0 * v0) * h) / (2.0 * h.powi(4)),
+                (12.0 * distance - 6.0 * (v0 + v1) * h) / (2.0 * h.powi(5)),
+            ],
+            duration: h,
+        }
+    }
+
+    fn state(&self, t: f32) -> MotionState {
+        let c = &self.coefficients;
+        let mut state = MotionState::default();
+        for i in (0..6).rev() {
+            let i_f = i as f32;
+            state.position = state.position * t + c[i];
+            if i >= 1 {
+                state.velocity = state.velocity * t + i_f * c[i];
+            }
+            if i >= 2 {
+                state.acceleration = state.acceleration * t + i_f * (i_f - 1.0) * c[i];
+            }
+        }
+        state
+    }
+}
+
+/// Shape of the profile of a [`Trajectory`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+    /// Trapezoidal moves, stopping at each waypoint.
+    #[default]
+    Trapezoidal,
+    /// Jerk-limited moves, stopping at each waypoint.
+    SCurve,
+    /// A cubic spline through the waypoints, with a continuous acceleration, at rest at both
+    /// ends.
+    Cubic,
+    /// Quintic segments through the waypoints, with a continuous acceleration that is zero at
+    /// every waypoint, at rest at both ends.
+    Quintic,
+}
+
+impl ProfileKind {
+    pub const ALL: [ProfileKind; 4] = [
+        ProfileKind::Trapezoidal,
+        ProfileKind::SCurve,
+        ProfileKind::Cubic,
+        ProfileKind::Quintic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProfileKind::Trapezoidal => "Trapezoidal",
+            ProfileKind::SCurve => "S-curve",
+            ProfileKind::Cubic => "Cubic spline",
+            ProfileKind::Quintic => "Quintic spline",
+        }
+    }
+}
+
+/// Bounds of a [`Trajectory`].
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MotionLimits {
+    pub max_velocity: f32,
+    pub max_acceleration: f32,
+    /// Only bounds the S-curves.
+    pub max_jerk: f32,
+}
+
+impl Default for MotionLimits {
+    fn default() -> Self {
+        Self {
+            max_velocity: 2.0,
+            max_acceleration: 4.0,
+            max_jerk: 40.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Trapezoidal(TrapezoidalProfile),
+    SCurve(SCurveProfile),
+    Polynomial(Polynomial),
+}
+
+impl Segment {
+    fn duration(&self) -> f32 {
+        match self {
+            Segment::Trapezoidal(profile) => profile.duration(),
+            Segment::SCurve(profile) => Profile::duration(profile),
+            Segment::Polynomial(polynomial) => polynomial.duration,
+        }
+    }
+
+    fn state(&self, t: f32) -> MotionState {
+        match self {
+            Segment::Trapezoidal(profile) => Profile::state(profile, t),
+            Segment::SCurve(profile) => profile.state(t),
+            Segment::Polynomial(polynomial) => polynomial.state(t.clamp(0.0, polynomial.duration)),
+        }
+    }
+}
+
+/// A motion through a sequence of waypoints, within velocity and acceleration limits.
+///
+/// The trapezoidal and S-curve profiles stop at every waypoint. The splines pass through them
+/// without stopping: each segment is first given the duration of a trapezoidal move over it,
+/// then all of them are stretched alike until the velocity and the acceleration of the spline
+/// are within the limits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    start: f32,
+    segments: Vec<Segment>,
+}
+
+impl Trajectory {
+    /// Plans a motion through `waypoints`, staying at the first one, or at 0, without another.
+    pub fn new(kind: ProfileKind, waypoints: &[f32], limits: MotionLimits) -> Self {
+        let mut points = waypoints.to_vec();
+        // Repeated waypoints would make empty spline segments.
+        points.dedup();
+        let start = points.first().copied().unwrap_or_default();
+        let MotionLimits {
+            max_velocity,
+            max_acceleration,
+            max_jerk,
+        } = limits;
+        let pairs = points.windows(2);
+        let segments = match kind {
+            ProfileKind::Trapezoidal => pairs
+                .map(|pair| {
+                    Segment::Trapezoidal(TrapezoidalProfile::new(
+                        pair[0],
+                        pair[1],
+                        max_velocity,
+                        max_acceleration,
+                    ))
+                })
+                .collect(),
+            ProfileKind::SCurve => pairs
+                .map(|pair| {
+                    Segment::SCurve(SCurveProfile::new(
+                        pair[0],
+                        pair[1],
+                        max_velocity,
+                        max_acceleration,
+                        max_jerk,
+                    ))
+                })
+                .collect(),
+            ProfileKind::Cubic | ProfileKind::Quintic => {
+                spline(kind, &points, max_velocity, max_acceleration)
+            }
+        };
+        Self { start, segments }
+    }
+
+    /// Position at the end of the motion.
+    pub fn end(&self) -> f32 {
+        self.state(self.duration()).position
+    }
+}
+
+impl Profile for Trajectory {
+    fn duration(&self) -> f32 {
+        self.segments.iter().map(Segment::duration).sum()
+    }
+
+    fn state(&self, t: f32) -> MotionState {
+        let mut t = t.max(0.0);
+        for (index, segment) in self.segments.iter().enumerate() {
+            let duration = segment.duration();
+            if t < duration || index + 1 == self.segments.len() {
+                return segment.state(t);
+            }
+            t -= duration;
+        }
+        MotionState {
+            position: self.start,
+            ..Default::default()
+        }
+    }
+}
+
+/// Segments of a spline through `points`, stretched within the limits.
+fn spline(
+    kind: ProfileKind,
+    points: &[f32],
+    max_velocity: f32,
+    max_acceleration: f32,
+) -> Vec<Segment> {
+    if points.len() < 2 || max_velocity <= 0.0 || max_acceleration <= 0.0 {
+        return Vec::new();
+    }
+    let mut durations: Vec<f32> = points
+        .windows(2)
+        .map(|pair| {
+            TrapezoidalProfile::new(pair[0], pair[1], max_velocity, max_acceleration).duration()
+        })
+        .collect();
+    let mut polynomials = spline_segments(kind, points, &durations);
+    // Stretching time by k divides the velocity by k and the acceleration by k².
+    let (velocity, acceleration) = peaks(&polynomials);
+    let stretch = 1f32
+        .max(velocity / max_velocity)
+        .max((acceleration / max_acceleration).sqrt());
+    if stretch > 1.0 {
+        for duration in &mut durations {
+            *duration *= stretch;
+        }
+        polynomials = spline_segments(kind, points, &durations);
+    }
+    polynomials.into_iter().map(Segment::Polynomial).collect()
+}
+
+/// Segments of a spline through `points`, each lasting its duration.
+fn spline_segments(kind: ProfileKind, points: &[f32], durations: &[f32]) -> Vec<Polynomial> {
+    let velocities = match kind {
+        ProfileKind::Cubic => cubic_velocities(points, durations),
+        _ => heuristic_velocities(points, durations),
+    };
+    (0..durations.len())
+        .map(|i| {
+            let (p0, p1, v0, v1, h) = (
+                points[i],
+                points[i + 1],
+                velocities[i],
+                velocities[i + 1],
+                durations[i],
+            );
+            if kind == ProfileKind::Cubic {
+                Polynomial::cubic(p0, v0, p1, v1, h)
+            } else {
+                Polynomial::quintic(p0, v0, p1, v1, h)
+            }
+        })
+        .collect()
+}
+
+/// Velocities at the waypoints making the acceleration of a cubic spline continuous, at rest at
+/// both ends: a tridiagonal system, solved by elimination.
+fn cubic_velocities(points: &[f32], h: &[f32]) -> Vec<f32> {
+    let n = points.len();
+    let mut velocities = vec![0.0; n];
+    if n < 3 {
+        return velocities;
+    }
+    // Rows of the interior waypoints: lower, diagonal and upper coefficients, and right side.
+    let interior = n - 2;
+    let (mut diagonal, mut upper, mut right) = (
+        vec![0.0; interior],
+        vec![0.0; interior],
+        vec![0.0; interior],
+    );
+    let mut lower = vec![0.0; interior];
+    for row in 0..interior {
+        let i = row + 1;
+        lower[row] = h[i];
+        diagonal[row] = 2.0 * (h[i - 1] + h[i]);
+        upper[row] = h[i - 1];
+        right[row] = 3.0
+            * (h[i] * (points[i] - points[i - 1]) / h[i - 1]
+                + h[i - 1] * (points[i + 1] - points[i]) / h[i]);
+    }
+    for row in 1..interior {
+        let factor = lower[row] / diagonal[row - 1];
+        diagonal[row] -= factor * upper[row - 1];
+        right[row] -= factor * right[row - 1];
+    }
+    for row in (0..interior).rev() {
+        let next = if row + 1 < interior {
+            velocities[row + 2]
+        } else {
+            0.0
+        };
+        velocities[row + 1] = (right[row] - upper[row] * next) / diagonal[row];
+    }
+    velocities
+}
+
+/// Velocities at the waypoints of the quintic segments: the mean of the slopes on either side,
+/// or zero where the motion turns back, at rest at both ends.
+fn heuristic_velocities(points: &[f32], h: &[f32]) -> Vec<f32> {
+    let n = points.len();
+    let mut velocities = vec![0.0; n];
+    for i in 1..n.saturating_sub(1) {
+        let before = (points[i] - points[i - 1]) / h[i - 1];
+        let after = (points[i + 1] - points[i]) / h[i];
+        if before * after > 0.0 {
+            velocities[i] = (before + after) / 2.0;
+        }
+    }
+    velocities
+}
+
+/// Largest velocity and acceleration along segments, sampled.
+fn peaks(polynomials: &[Polynomial]) -> (f32, f32) {
+    let (mut velocity, mut acceleration) = (0.0f32, 0.0f32);
+    for polynomial in polynomials {
+        for sample in 0..=LIMIT_SAMPLES {
+            let t = polynomial.duration * sample as f32 / LIMIT_SAMPLES as f32;
+            let state = polynomial.state(t);
+            velocity = velocity.max(state.velocity.abs());
+            acceleration = acceleration.max(state.acceleration.abs());
+        }
+    }
+    (velocity, acceleration)
+}
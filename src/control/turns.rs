@@ -0,0 +1,13 @@
+use std::f32::consts::{PI, TAU};
+
+/// Angle of a joint over the turns, from its angle within a turn and `turns`, the last angle
+/// within a turn and over the turns, updated; the shortest way round is taken between two
+/// measurements.
+pub(crate) fn unwrap_angle(turns: &mut Option<(f32, f32)>, angle: f32) -> f32 {
+    let total = match *turns {
+        Some((previous, total)) => total + (angle - previous + PI).rem_euclid(TAU) - PI,
+        None => angle,
+    };
+    *turns = Some((angle, total));
+    total
+}
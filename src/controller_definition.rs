@@ -0,0 +1,274 @@
+//! A portable description of a tuned controller: its type, its parameters, its sample rate and
+//! the signals it's bound to, in one serde format shared by the panels, the configuration files,
+//! the code generation and the archived sessions, so a tuned controller is an artifact that can
+//! be kept, compared and flashed rather than values scattered over the inspector.
+//!
+//! The panels of the controllers export their settings as definitions to the `controllers`
+//! directory of the configuration, and import them back; `--generate-c` generates the code of
+//! definitions; and each session keeps the definitions of the controllers it ran with.
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy_inspector_egui::bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{config_plugin, migration};
+use crate::{
+    control::PidGains,
+    error::{Error, Result},
+    lqr::{LqrDesign, LqrSettings},
+    pid_controller::PidSettings,
+};
+
+/// Binding of the joint controlled by a position loop.
+pub const PID_JOINT: &str = "joint";
+/// Bindings of the joints of the motor and of the pendulum of a regulator.
+pub const LQR_MOTOR: &str = "motor";
+pub const LQR_PENDULUM: &str = "pendulum";
+
+/// The type of a controller and its parameters.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControllerType {
+    /// Position loop of a joint, commanding the velocity of its motor.
+    Pid {
+        gains: PidGains,
+        /// Largest velocity commanded, in rad/s, or 0 for an unlimited one.
+        limit: f32,
+    },
+    /// Regulator balancing a pendulum, commanding the acceleration of the arm.
+    Lqr {
+        /// Continuous-time model `x' = A x + B u` linearized around upright, by rows.
+        a: [[f64; 4]; 4],
+        b: [f64; 4],
+        /// Diagonal of the weight of the state, and weight of the input.
+        q: [f64; 4],
+        r: f64,
+        /// Largest angle from upright at which the pendulum is caught, in rad.
+        capture: f32,
+        /// Largest acceleration of the arm commanded, in rad/s².
+        limit: f32,
+        #[serde(default)]
+        design: LqrDesign,
+    },
+}
+
+impl ControllerType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pid { .. } => "pid",
+            Self::Lqr { .. } => "lqr",
+        }
+    }
+}
+
+/// A tuned controller.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ControllerDefinition {
+    pub name: String,
+    #[serde(flatten)]
+    pub controller: ControllerType,
+    /// Rate the controller is sampled at, in Hz, or `None` for the physics step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f32>,
+    /// Signals bound to the controller, by role: the links whose joints it measures and
+    /// drives.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, String>,
+}
+
+impl ControllerDefinition {
+    /// Sample period, in s, or `default` without a sample rate.
+    pub fn sample_period(&self, default: f32) -> f32 {
+        match self.sample_rate {
+            Some(rate) if rate > 0.0 => 1.0 / rate,
+            _ => default,
+        }
+    }
+
+    /// The signal bound to `role`.
+    pub fn binding(&self, role: &str) -> Result<&str> {
+        self.bindings
+            .get(role)
+            .map(String::as_str)
+            .ok_or_else(|| self.invalid(format!("no `{role}` binding")))
+    }
+
+    fn invalid(&self, message: String) -> Error {
+        Error::Config {
+            name: self.name.clone(),
+            message,
+        }
+    }
+
+    /// Reads a definition from a JSON file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(path: &Path) -> Result<Self> {
+        migration::CONTROLLER_DEFINITION.read(path)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        let json = migration::CONTROLLER_DEFINITION
+            .to_value(self)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+impl From<&PidSettings> for ControllerDefinition {
+    fn from(settings: &PidSettings) -> Self {
+        Self {
+            name: "pid".to_string(),
+            controller: ControllerType::Pid {
+                gains: settings.gains,
+                limit: settings.limit,
+            },
+            sample_rate: None,
+            bindings: BTreeMap::from([(PID_JOINT.to_string(), settings.link.clone())]),
+        }
+    }
+}
+
+impl From<&LqrSettings> for ControllerDefinition {
+    fn from(settings: &LqrSettings) -> Self {
+        Self {
+            name: "lqr".to_string(),
+            controller: ControllerType::Lqr {
+                a: settings.a,
+                b: settings.b,
+                q: settings.q,
+                r: settings.r,
+                capture: settings.capture,
+                limit: settings.limit,
+                design: settings.design.clone(),
+            },
+            sample_rate: settings.sample_rate,
+            bindings: BTreeMap::from([
+                (LQR_MOTOR.to_string(), settings.motor.clone()),
+                (LQR_PENDULUM.to_string(), settings.pendulum.clone()),
+            ]),
+        }
+    }
+}
+
+impl TryFrom<&ControllerDefinition> for PidSettings {
+    type Error = Error;
+
+    /// Enabled settings of a position loop.
+    fn try_from(definition: &ControllerDefinition) -> Result<Self> {
+        let ControllerType::Pid { gains, limit } = definition.controller else {
+            return Err(definition.invalid("not a position loop".to_string()));
+        };
+        Ok(Self {
+            enabled: true,
+            link: definition.binding(PID_JOINT)?.to_string(),
+            gains,
+            limit,
+            smith_predictor: false,
+        })
+    }
+}
+
+impl TryFrom<&ControllerDefinition> for LqrSettings {
+    type Error = Error;
+
+    /// Enabled settings of a regulator.
+    fn try_from(definition: &ControllerDefinition) -> Result<Self> {
+        let ControllerType::Lqr {
+            a,
+            b,
+            q,
+            r,
+            capture,
+            limit,
+            design,
+        } = &definition.controller
+        else {
+            return Err(definition.invalid("not a regulator".to_string()));
+        };
+        Ok(Self {
+            enabled: true,
+            motor: definition.binding(LQR_MOTOR)?.to_string(),
+            pendulum: definition.binding(LQR_PENDULUM)?.to_string(),
+            a: *a,
+            b: *b,
+            q: *q,
+            r: *r,
+            capture: *capture,
+            limit: *limit,
+            design: design.clone(),
+            sample_rate: definition.sample_rate,
+        })
+    }
+}
+
+/// Directory of the exported definitions, in the configuration directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn definitions_dir() -> PathBuf {
+    config_plugin::config_dir().join("controllers")
+}
+
+/// The definitions of a directory, by file name, skipping the files that aren't.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn definitions(directory: &Path) -> Vec<(PathBuf, ControllerDefinition)> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut definitions: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let definition = ControllerDefinition::read(&path).ok()?;
+            Some((path, definition))
+        })
+        .collect();
+    definitions.sort_by(|a, b| a.0.cmp(&b.0));
+    definitions
+}
+
+/// Buttons exporting `current` to the definitions directory, and importing a definition of the
+/// same type from it: returns the imported definition, or the error of the export.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn definition_buttons(
+    ui: &mut egui::Ui,
+    current: &ControllerDefinition,
+) -> Option<Result<ControllerDefinition>> {
+    let directory = definitions_dir();
+    let mut result = None;
+    ui.horizontal(|ui| {
+        if ui.button("Export definition").clicked() {
+            let path = directory.join(format!("{}.json", current.name));
+            if let Err(error) = current.write(&path) {
+                result = Some(Err(error));
+            }
+        }
+        ui.menu_button("Import definition", |ui| {
+            let definitions: Vec<_> = definitions(&directory)
+                .into_iter()
+                .filter(|(_, definition)| definition.controller.name() == current.controller.name())
+                .collect();
+            if definitions.is_empty() {
+                ui.label(format!("No definition in {}", directory.display()));
+            }
+            for (path, definition) in definitions {
+                let file = path.file_name().unwrap_or_default().to_string_lossy();
+                if ui.button(format!("{} ({file})", definition.name)).clicked() {
+                    result = Some(Ok(definition));
+                    ui.close_menu();
+                }
+            }
+        });
+    });
+    result
+}
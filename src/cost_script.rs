@@ -0,0 +1,149 @@
+//! Cost functions of the [sweeps](crate::sweep) written as [Rhai](https://rhai.rs) scripts, so
+//! the runs are ranked by a bespoke objective without recompiling the playground.
+//!
+//! The script defines a `cost(run)` function, called once each run ends, that returns a number,
+//! the lower the better. `run` is a map of:
+//! - `signals`: every recorded telemetry channel by name, each a map of its `time` and `value`
+//!   arrays, in s and in the unit of the channel;
+//! - `parameters`: the values of the parameters of the run, by name;
+//! - `metrics`: the settling time (`()` when it doesn't settle), the overshoot and the RMS error
+//!   of the scored channel, or `()` without samples of it;
+//! - `energy`: the electrical energy drawn by the actuators, in J, or `()` without any;
+//! - `violations`: the number of violations of the monitored requirements;
+//! - `duration`: the simulated duration of the run, in s.
+//!
+//! A cost that isn't a number, or NaN, ranks the run last; a script that fails stops the sweep
+//! with its error.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST, FLOAT, INT};
+
+use crate::{
+    error::{Error, Result},
+    sweep::SweepRun,
+    telemetry::Telemetry,
+};
+
+/// Function of the script called with each run.
+pub const COST_FN: &str = "cost";
+/// Largest number of operations of a call, so a script stuck in a loop fails instead of hanging
+/// the sweep; enough to go over every sample of the run a few times.
+pub const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// A compiled cost function.
+pub struct CostScript {
+    engine: Engine,
+    ast: AST,
+    path: PathBuf,
+}
+
+impl CostScript {
+    /// Compiles `source`, read from `path`, if it defines the cost function.
+    pub fn compile(source: &str, path: &Path) -> Result<Self> {
+        let error = |message: String| Error::Script {
+            path: path.to_path_buf(),
+            message,
+        };
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|compile| error(compile.to_string()))?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == COST_FN && function.params.len() == 1)
+        {
+            return Err(error(format!("no `{COST_FN}(run)` function")));
+        }
+        Ok(Self {
+            engine,
+            ast,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Reads and compiles the script at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        Self::compile(&source, path)
+    }
+
+    /// Cost of `run`, which recorded `telemetry` over `duration` seconds, infinite if it isn't
+    /// a number.
+    pub fn cost(&self, run: &SweepRun, telemetry: &Telemetry, duration: f32) -> Result<f32> {
+        let output: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                COST_FN,
+                (Dynamic::from_map(run_map(run, telemetry, duration)),),
+            )
+            .map_err(|error| Error::Script {
+                path: self.path.clone(),
+                message: error.to_string(),
+            })?;
+        let cost = output
+            .as_float()
+            .map(|cost| cost as f32)
+            .or_else(|_| output.as_int().map(|cost| cost as f32))
+            .unwrap_or(f32::INFINITY);
+        Ok(if cost.is_nan() { f32::INFINITY } else { cost })
+    }
+}
+
+fn float(value: f32) -> Dynamic {
+    Dynamic::from_float(FLOAT::from(value))
+}
+
+fn optional(value: Option<f32>) -> Dynamic {
+    value.map_or(Dynamic::UNIT, float)
+}
+
+/// The map `run` is passed to the script as.
+fn run_map(run: &SweepRun, telemetry: &Telemetry, duration: f32) -> Map {
+    let signals: Map = telemetry
+        .channels
+        .iter()
+        .map(|(channel, samples)| {
+            let (time, value): (Array, Array) = samples
+                .iter()
+                .map(|[time, value]| (float(*time), float(*value)))
+                .unzip();
+            let signal = Map::from([
+                ("time".into(), Dynamic::from_array(time)),
+                ("value".into(), Dynamic::from_array(value)),
+            ]);
+            (channel.as_str().into(), Dynamic::from_map(signal))
+        })
+        .collect();
+    let parameters: Map = run
+        .parameters
+        .iter()
+        .map(|(name, value)| (name.as_str().into(), float(*value)))
+        .collect();
+    let metrics = run.metrics.map_or(Dynamic::UNIT, |metrics| {
+        Dynamic::from_map(Map::from([
+            ("settling_time".into(), optional(metrics.settling_time)),
+            ("overshoot".into(), float(metrics.overshoot)),
+            ("rms_error".into(), float(metrics.rms_error)),
+        ]))
+    });
+    Map::from([
+        ("signals".into(), Dynamic::from_map(signals)),
+        ("parameters".into(), Dynamic::from_map(parameters)),
+        ("metrics".into(), metrics),
+        (
+            "energy".into(),
+            optional(run.energy.map(|energy| energy.electrical)),
+        ),
+        (
+            "violations".into(),
+            Dynamic::from_int(run.violations as INT),
+        ),
+        ("duration".into(), float(duration)),
+    ])
+}
@@ -0,0 +1,567 @@
+//! Custom controllers: control laws written in Rust, by other modules or crates, attached to the
+//! joints of the plants without changing the control loop.
+//!
+//! A control law implements [`Controller`]: it's told the joints it's attached to, stepped after
+//! each physics step with their state, and returns their torques for the next step; it's reset
+//! whenever it starts over. It's [`register`]ed by name with a function building one, e.g. from
+//! the `add` function of an [extension](crate::extensions), and the registered controllers are
+//! offered in the *Custom controllers* panel, where one is picked and attached to the joints of
+//! the chosen links of each plant, each plant with its own controller. Like the scripted
+//! controller, the motors of the joints are released, and the torques applied as impulses on the
+//! two links of each joint; they are recorded as the `custom/<link>` telemetry channels. The
+//! controller and the attached links are configured in `custom_controllers.json`.
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin, control,
+    error::{Error, ErrorEvent, Result},
+    estimation::{self, EstimationSet, JointEstimate},
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link},
+    sensors::{ControlSet, Encoder, SensorSet},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the torques, followed by the name of the link.
+pub const CUSTOM_PREFIX: &str = "custom/";
+
+/// State of a joint as a controller sees it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JointState {
+    /// Angle over the turns, in rad.
+    pub position: f32,
+    /// In rad/s.
+    pub velocity: f32,
+}
+
+/// A control law driving the joints of a plant.
+pub trait Controller: Send + Sync {
+    /// Called once attached to the joints of `links`, before the first step.
+    fn init(&mut self, _links: &[String]) {}
+
+    /// Called when the controller starts over, to clear its integrators and filters.
+    fn reset(&mut self) {}
+
+    /// Torques of the joints for the next `dt` seconds, in N·m, from their `state`, both in the
+    /// order of the attached links.
+    fn step(&mut self, state: &[JointState], dt: f32) -> Vec<f32>;
+}
+
+/// A registered controller.
+#[derive(Clone, Copy)]
+pub struct Registration {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Builds a controller, once for each plant.
+    pub build: fn() -> Box<dyn Controller>,
+}
+
+/// PD loop holding the joints at 0 rad.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HoldController {
+    /// In N·m/rad.
+    pub kp: f32,
+    /// In N·m·s/rad.
+    pub kd: f32,
+}
+
+impl Default for HoldController {
+    fn default() -> Self {
+        Self { kp: 2.0, kd: 0.2 }
+    }
+}
+
+impl Controller for HoldController {
+    fn step(&mut self, state: &[JointState], _dt: f32) -> Vec<f32> {
+        state
+            .iter()
+            .map(|joint| -self.kp * joint.position - self.kd * joint.velocity)
+            .collect()
+    }
+}
+
+/// The built-in controllers.
+pub fn builtin() -> Vec<Registration> {
+    vec![Registration {
+        name: "hold",
+        description: "PD loop holding the joints at 0 rad",
+        build: || Box::<HoldController>::default(),
+    }]
+}
+
+static REGISTRY: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+/// Registers a controller, unless its name is taken.
+pub fn register(
+    name: &'static str,
+    description: &'static str,
+    build: fn() -> Box<dyn Controller>,
+) -> Result<()> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+    if builtin()
+        .iter()
+        .chain(registry.iter())
+        .any(|registered| registered.name == name)
+    {
+        return Err(Error::Config {
+            name: name.to_string(),
+            message: "a controller of that name is registered".to_string(),
+        });
+    }
+    registry.push(Registration {
+        name,
+        description,
+        build,
+    });
+    Ok(())
+}
+
+/// The built-in controllers, then the registered ones in registration order.
+pub fn registered() -> Vec<Registration> {
+    let registry = REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+    builtin()
+        .into_iter()
+        .chain(registry.iter().copied())
+        .collect()
+}
+
+/// The controller registered as `name`, if any.
+pub fn find(name: &str) -> Option<Registration> {
+    registered()
+        .into_iter()
+        .find(|registration| registration.name == name)
+}
+
+pub struct CustomControllersPlugin;
+
+impl Plugin for CustomControllersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_controllers
+                    .run_if(resource_exists_and_changed::<Persistent<CustomControllerSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .run_if(resource_exists::<Persistent<CustomControllerSettings>>)
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                custom_controllers_panel
+                    .run_if(resource_exists::<Persistent<CustomControllerSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the custom controllers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct CustomControllerSettings {
+    pub enabled: bool,
+    /// Name of the registered controller.
+    pub controller: String,
+    /// Names of the links whose joints are controlled, in each plant, in the order of the state
+    /// and of the torques.
+    pub links: Vec<String>,
+    /// Largest torque applied, in N·m, or 0 for an unlimited one.
+    pub limit: f32,
+}
+
+impl Default for CustomControllerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            controller: "hold".to_string(),
+            links: vec!["motor".to_string()],
+            limit: 1.0,
+        }
+    }
+}
+
+impl CustomControllerSettings {
+    /// The torque applied for the one returned by the controller.
+    pub fn saturate(&self, torque: f32) -> f32 {
+        if self.limit > 0.0 {
+            torque.clamp(-self.limit, self.limit)
+        } else {
+            torque
+        }
+    }
+}
+
+/// A joint driven by a custom controller.
+#[derive(Clone, Debug)]
+pub struct CustomJoint {
+    pub entity: Entity,
+    pub link: String,
+    /// Telemetry channel of the torque.
+    pub channel: String,
+    /// Last angle within a turn, and the angle over the turns.
+    turns: Option<(f32, f32)>,
+}
+
+impl CustomJoint {
+    pub fn new(entity: Entity, link: &Link) -> Self {
+        Self {
+            entity,
+            link: link.name.clone(),
+            channel: plants::namespaced(&link.plant, &format!("{CUSTOM_PREFIX}{}", link.name)),
+            turns: None,
+        }
+    }
+
+    /// State of the joint from its angle within a turn measured `dt` seconds after the last one.
+    pub fn measure(&mut self, angle: f32, dt: f32) -> JointState {
+        let previous = self.turns.map(|(_, total)| total);
+        let position = control::unwrap_angle(&mut self.turns, angle);
+        let velocity = previous.map_or(0.0, |previous| (position - previous) / dt);
+        JointState { position, velocity }
+    }
+}
+
+/// The custom controller of a plant, on the joint of its first attached link.
+#[derive(Component)]
+pub struct CustomController {
+    /// Name it's registered as.
+    pub name: String,
+    pub joints: Vec<CustomJoint>,
+    controller: Box<dyn Controller>,
+    /// Whether it returned the wrong number of torques, leaving the joints coasting until reset.
+    pub failed: bool,
+}
+
+impl CustomController {
+    /// Builds the `registration` controller and attaches it to `joints`.
+    pub fn new(registration: &Registration, joints: Vec<CustomJoint>) -> Self {
+        let mut controller = (registration.build)();
+        let links: Vec<String> = joints.iter().map(|joint| joint.link.clone()).collect();
+        controller.init(&links);
+        Self {
+            name: registration.name.to_string(),
+            joints,
+            controller,
+            failed: false,
+        }
+    }
+
+    /// Torques of the joints for the next `dt` seconds from their `angles` within a turn.
+    pub fn step(&mut self, angles: &[f32], dt: f32) -> Result<Vec<f32>, String> {
+        let state: Vec<JointState> = self
+            .joints
+            .iter_mut()
+            .zip(angles)
+            .map(|(joint, angle)| joint.measure(*angle, dt))
+            .collect();
+        let torques = self.controller.step(&state, dt);
+        if torques.len() == self.joints.len() {
+            Ok(torques)
+        } else {
+            Err(format!(
+                "the {} controller returned {} torques for {} joints",
+                self.name,
+                torques.len(),
+                self.joints.len()
+            ))
+        }
+    }
+
+    /// Starts the controller over.
+    pub fn reset(&mut self) {
+        for joint in &mut self.joints {
+            joint.turns = None;
+        }
+        self.controller.reset();
+        self.failed = false;
+    }
+
+    /// Whether it's the `name` controller driving the joints of `links`, in this order.
+    fn drives(&self, name: &str, links: &[String]) -> bool {
+        self.name == name && self.joints.iter().map(|joint| &joint.link).eq(links)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<CustomControllerSettings>("custom_controllers", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers, on the joints of the configured links of each plant.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<CustomControllerSettings>>,
+    joints: Query<(Entity, &Link, &ImpulseJoint, Option<&CustomController>)>,
+) {
+    let registration = find(&settings.controller);
+    if settings.enabled && registration.is_none() {
+        commands.send_event(ErrorEvent::from(Error::Config {
+            name: "custom_controllers".to_string(),
+            message: format!("no controller is registered as {}", settings.controller),
+        }));
+    }
+    for (entity, link, _, controller) in &joints {
+        let attached = settings.enabled && settings.links.first() == Some(&link.name);
+        let found = registration.filter(|_| attached).and_then(|registration| {
+            settings
+                .links
+                .iter()
+                .map(|name| {
+                    joints
+                        .iter()
+                        .find(|(_, other, _, _)| other.plant == link.plant && other.name == *name)
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|found| (registration, found))
+        });
+        match (controller, found) {
+            (Some(controller), Some(_))
+                if controller.drives(&settings.controller, &settings.links) => {}
+            (_, Some((registration, found))) => {
+                for (joint_entity, _, joint, _) in &found {
+                    // The parent takes the reaction.
+                    for body in [*joint_entity, joint.parent] {
+                        commands
+                            .entity(body)
+                            .insert_if_new(ExternalImpulse::default());
+                    }
+                }
+                let joints = found
+                    .into_iter()
+                    .map(|(entity, link, _, _)| CustomJoint::new(entity, link))
+                    .collect();
+                commands
+                    .entity(entity)
+                    .insert(CustomController::new(&registration, joints));
+                info!(
+                    target: subsystem::CONTROL,
+                    "The {} controller controls {}",
+                    registration.name,
+                    link.path()
+                );
+            }
+            (Some(controller), None) => {
+                info!(
+                    target: subsystem::CONTROL,
+                    "The {} controller released {}",
+                    controller.name,
+                    link.path()
+                );
+                commands.entity(entity).remove::<CustomController>();
+            }
+            (None, None) if attached && registration.is_some() => {
+                warn!(
+                    target: subsystem::CONTROL,
+                    "Links {:?} not all found in {}", settings.links, link.plant
+                );
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step and applies the torques of the controllers for
+/// the next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<CustomControllerSettings>>,
+    mut controllers: Query<&mut CustomController>,
+    mut joints: Query<(&mut ImpulseJoint, &Transform)>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_custom_controllers").entered();
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    for mut controller in &mut controllers {
+        if controller.failed {
+            continue;
+        }
+        let angles = controller
+            .joints
+            .iter()
+            .map(|joint| {
+                estimation::joint_angle(context, &encoders, &latencies, &estimates, joint.entity)
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(angles) = angles else {
+            continue;
+        };
+        let torques = match controller.step(&angles, dt) {
+            Ok(torques) => torques,
+            Err(message) => {
+                controller.failed = true;
+                commands.send_event(ErrorEvent::from(Error::Config {
+                    name: "custom_controllers".to_string(),
+                    message,
+                }));
+                continue;
+            }
+        };
+        for (joint, torque) in controller.joints.iter().zip(torques) {
+            let torque = settings.saturate(torque);
+            let Ok((mut impulse_joint, transform)) = joints.get_mut(joint.entity) else {
+                continue;
+            };
+            impulse_joint
+                .data
+                .as_mut()
+                .set_motor_velocity(JointAxis::AngX, 0.0, 0.0);
+            let axis = (transform.rotation * impulse_joint.data.as_ref().local_axis2())
+                .normalize_or_zero();
+            let torque_impulse = torque * axis * dt;
+            if let Ok(mut impulse) = impulses.get_mut(joint.entity) {
+                impulse.torque_impulse += torque_impulse;
+            }
+            if let Ok(mut impulse) = impulses.get_mut(impulse_joint.parent) {
+                impulse.torque_impulse -= torque_impulse;
+            }
+            if let Some(telemetry) = telemetry.as_mut() {
+                telemetry.record(&joint.channel, clock.elapsed_secs(), torque);
+            }
+        }
+    }
+}
+
+/// Panel to pick a registered controller and attach it to the joints.
+fn custom_controllers_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<CustomControllerSettings>>,
+    mut controllers: Query<&mut CustomController>,
+    links: Query<&Link, With<ImpulseJoint>>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Custom controllers")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Run the controller");
+            let registered = registered();
+            egui::ComboBox::from_label("Controller")
+                .selected_text(edited.controller.clone())
+                .show_ui(ui, |ui| {
+                    for registration in &registered {
+                        ui.selectable_value(
+                            &mut edited.controller,
+                            registration.name.to_string(),
+                            registration.name,
+                        )
+                        .on_hover_text(registration.description);
+                    }
+                });
+            match registered
+                .iter()
+                .find(|registration| registration.name == edited.controller)
+            {
+                Some(registration) => {
+                    ui.label(registration.description);
+                }
+                None => {
+                    ui.colored_label(egui::Color32::RED, "Not registered in this build");
+                }
+            }
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=100.0)
+                    .speed(0.01)
+                    .prefix("Torque limit: ")
+                    .suffix(" N·m"),
+            );
+
+            ui.separator();
+            ui.label("Joints, in the order of the state:");
+            let mut names: Vec<&str> = links.iter().map(|link| link.name.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                let position = edited.links.iter().position(|link| link == name);
+                let mut attached = position.is_some();
+                let label = match position {
+                    Some(index) => format!("{name} (#{index})"),
+                    None => name.to_string(),
+                };
+                if ui.checkbox(&mut attached, label).changed() {
+                    match position {
+                        Some(index) => {
+                            edited.links.remove(index);
+                        }
+                        None => edited.links.push(name.to_string()),
+                    }
+                }
+            }
+
+            ui.separator();
+            for controller in &controllers {
+                if controller.failed {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("{} failed, reset to run it again", controller.name),
+                    );
+                }
+                for joint in &controller.joints {
+                    let torque = telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(&joint.channel))
+                        .unwrap_or_default();
+                    ui.label(format!("{}: {torque:.3} N·m", joint.channel));
+                }
+            }
+            if ui
+                .add_enabled(!controllers.is_empty(), egui::Button::new("Reset"))
+                .clicked()
+            {
+                for mut controller in &mut controllers {
+                    controller.reset();
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("custom_controllers", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("custom_controllers", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
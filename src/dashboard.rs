@@ -0,0 +1,380 @@
+//! Built-in web dashboard, to monitor a long run from a phone or a second laptop with nothing
+//! but a browser.
+//!
+//! An instance started with `--dashboard` serves a page at `http://<address>/`, which connects
+//! back to `ws://<address>/ws` for the live telemetry and draws it. Both share the port, and the
+//! server is the WebSocket stack of the collaborative sessions, on threads of its own. Messages
+//! are JSON text frames:
+//! - page to instance: `{"type":"setpoint","name":"motor/velocity","value":5.0}`,
+//!   `{"type":"pause"}` and `{"type":"resume"}`, ignored when the dashboard is read-only;
+//! - instance to page: `{"type":"update","time":...,"running":...,"read_only":...,
+//!   "setpoints":{...},"channels":{"motor/angle":[[time,value],...],...}}` every
+//!   [`UPDATE_INTERVAL`], with the samples recorded since the previous update. The first
+//!   update of a page holds the last [`HISTORY`] seconds instead.
+//!
+//! The HTTP of the page is parsed by hand rather than served by a framework such as axum: the
+//! server answers a single page and upgrades a single path, on the blocking threads of the
+//! WebSocket stack, and a framework would pull an async runtime into builds that have none. A
+//! request is therefore held to [`REQUEST_TIMEOUT`] and [`MAX_HEADER_LENGTH`], so a client that
+//! stalls or floods its headers only costs its own connection.
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, time::Real};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    remote::{self, SocketResult},
+    setpoints::Setpoints,
+    telemetry::{self, Sample, Telemetry},
+};
+
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5711;
+/// Time between two updates sent to the pages.
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// Seconds of telemetry sent to a page when it connects.
+pub const HISTORY: f32 = 30.0;
+/// Most samples of a channel in an update; longer runs are decimated.
+pub const MAX_SAMPLES: usize = 400;
+/// Time a client has to send the head of its request.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest head of a request, in bytes.
+pub const MAX_HEADER_LENGTH: usize = 16 * 1024;
+/// Longest request line, in bytes.
+const MAX_REQUEST_LINE: usize = 1024;
+/// Path of the WebSocket of the page.
+const SOCKET_PATH: &str = "/ws";
+/// The page, with its scripts.
+const PAGE: &str = include_str!("../web/dashboard.html");
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DashboardCommand {
+    Setpoint { name: String, value: f32 },
+    Pause,
+    Resume,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DashboardMessage {
+    Update {
+        /// Simulated time, in seconds.
+        time: f32,
+        running: bool,
+        read_only: bool,
+        setpoints: BTreeMap<String, f32>,
+        channels: BTreeMap<String, Vec<Sample>>,
+    },
+}
+
+/// Serves the dashboard on the given address.
+pub struct DashboardPlugin {
+    pub bind: SocketAddr,
+    /// Whether the pages only watch, without setpoints nor pausing.
+    pub read_only: bool,
+}
+
+impl Plugin for DashboardPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        let address = listener.local_addr().unwrap_or(self.bind);
+        info!(target: subsystem::IO, "Serving the dashboard on http://{address}/");
+        let (events, receiver) = mpsc::channel();
+        thread::spawn(move || accept_viewers(listener, events));
+
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(Dashboard {
+                events: Mutex::new(receiver),
+                address,
+                viewers: BTreeMap::new(),
+                read_only: self.read_only,
+                timer: Timer::new(UPDATE_INTERVAL, TimerMode::Repeating),
+                sent: BTreeMap::new(),
+            })
+            .add_systems(Update, dashboard_receive)
+            .add_systems(PostUpdate, dashboard_broadcast.after(SimClockSet::Advance));
+    }
+}
+
+enum DashboardEvent {
+    Connected {
+        id: u64,
+        address: SocketAddr,
+        outgoing: mpsc::Sender<String>,
+    },
+    Command(u64, DashboardCommand),
+    Disconnected(u64),
+}
+
+fn accept_viewers(listener: TcpListener, events: mpsc::Sender<DashboardEvent>) {
+    for (id, stream) in (0..).zip(listener.incoming()) {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let events = events.clone();
+        thread::spawn(move || {
+            let address = stream.peer_addr().ok();
+            if let Err(error) = handle_connection(id, stream, &events) {
+                debug!(target: subsystem::IO, "Dashboard {address:?}: {error}");
+            }
+            let _ = events.send(DashboardEvent::Disconnected(id));
+        });
+    }
+}
+
+/// Path requested by the HTTP request at the start of `stream`, without consuming it, so the
+/// WebSocket handshake can read it again, if its request line comes before `deadline`.
+fn requested_path(stream: &TcpStream, deadline: Instant) -> io::Result<String> {
+    let mut buffer = [0; MAX_REQUEST_LINE];
+    loop {
+        let length = stream.peek(&mut buffer)?;
+        let request = &buffer[..length];
+        if let Some(end) = request.windows(2).position(|line_end| line_end == b"\r\n") {
+            let line = String::from_utf8_lossy(&request[..end]);
+            return match line.split(' ').collect::<Vec<_>>()[..] {
+                [_, path, _] => Ok(path.to_string()),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, "not HTTP")),
+            };
+        }
+        if length == 0 || length == buffer.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not HTTP"));
+        }
+        if Instant::now() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        thread::sleep(remote::POLL_INTERVAL);
+    }
+}
+
+/// Answers a request for anything but the WebSocket, whose head comes before `deadline`: the
+/// page, or not found.
+fn respond(mut stream: TcpStream, path: &str, deadline: Instant) -> io::Result<()> {
+    // Reads the request up to its blank line; browsers don't send a body with `GET`.
+    let mut request = Vec::new();
+    let mut byte = [0];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_HEADER_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "headers too long",
+            ));
+        }
+        if Instant::now() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        if stream.read(&mut byte)? == 0 {
+            break;
+        }
+        request.push(byte[0]);
+    }
+    let (status, body) = match path.split('?').next() {
+        Some("/" | "/index.html") => ("200 OK", PAGE),
+        _ => ("404 Not Found", "Not found"),
+    };
+    let content_type = if status.starts_with("200") {
+        "text/html; charset=utf-8"
+    } else {
+        "text/plain; charset=utf-8"
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+fn handle_connection(
+    id: u64,
+    stream: TcpStream,
+    events: &mpsc::Sender<DashboardEvent>,
+) -> SocketResult {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(tungstenite::Error::Io)?;
+    let path = requested_path(&stream, deadline).map_err(tungstenite::Error::Io)?;
+    if path != SOCKET_PATH {
+        return respond(stream, &path, deadline)
+            .map_err(|error| tungstenite::Error::Io(error).into());
+    }
+    let address = stream.peer_addr().map_err(tungstenite::Error::Io)?;
+    let socket = tungstenite::accept(stream).map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => error,
+        tungstenite::HandshakeError::Interrupted(_) => {
+            tungstenite::Error::Io(io::ErrorKind::WouldBlock.into())
+        }
+    })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(remote::POLL_INTERVAL))
+        .map_err(tungstenite::Error::Io)?;
+    let (outgoing, receiver) = mpsc::channel();
+    let _ = events.send(DashboardEvent::Connected {
+        id,
+        address,
+        outgoing,
+    });
+    remote::serve(socket, receiver, |command| {
+        let _ = events.send(DashboardEvent::Command(id, command));
+    })
+}
+
+/// A page connected to the dashboard.
+pub struct Viewer {
+    pub address: SocketAddr,
+    /// Whether the page has had its first update.
+    welcomed: bool,
+    outgoing: mpsc::Sender<String>,
+}
+
+impl Viewer {
+    fn send(&self, message: &DashboardMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            let _ = self.outgoing.send(text);
+        }
+    }
+}
+
+/// The dashboard served by this instance.
+#[derive(Resource)]
+pub struct Dashboard {
+    events: Mutex<mpsc::Receiver<DashboardEvent>>,
+    /// Address served, with the port picked by the system when bound to port 0.
+    pub address: SocketAddr,
+    pub viewers: BTreeMap<u64, Viewer>,
+    /// Whether the commands of the pages are ignored.
+    pub read_only: bool,
+    timer: Timer,
+    /// Number of samples of each channel already sent.
+    sent: BTreeMap<String, usize>,
+}
+
+fn dashboard_receive(
+    mut dashboard: ResMut<Dashboard>,
+    mut clock: ResMut<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    let dashboard = &mut *dashboard;
+    let events = dashboard
+        .events
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner());
+    for event in events.try_iter() {
+        match event {
+            DashboardEvent::Connected {
+                id,
+                address,
+                outgoing,
+            } => {
+                info!(target: subsystem::IO, "{address} opened the dashboard");
+                dashboard.viewers.insert(
+                    id,
+                    Viewer {
+                        address,
+                        welcomed: false,
+                        outgoing,
+                    },
+                );
+            }
+            DashboardEvent::Command(id, command) => {
+                let Some(viewer) = dashboard.viewers.get(&id) else {
+                    continue;
+                };
+                if dashboard.read_only {
+                    debug!(target: subsystem::CONTROL, "Ignoring {command:?}, read-only dashboard");
+                    continue;
+                }
+                debug!(target: subsystem::CONTROL, "{} sends {command:?}", viewer.address);
+                match command {
+                    DashboardCommand::Setpoint { name, value } => setpoints.set(&name, value),
+                    DashboardCommand::Pause => clock.pause(),
+                    DashboardCommand::Resume => clock.resume(),
+                }
+            }
+            DashboardEvent::Disconnected(id) => {
+                if let Some(viewer) = dashboard.viewers.remove(&id) {
+                    info!(target: subsystem::IO, "{} closed the dashboard", viewer.address);
+                }
+            }
+        }
+    }
+}
+
+/// `samples` with at most [`MAX_SAMPLES`], keeping their envelope.
+fn thin(samples: &[Sample]) -> Vec<Sample> {
+    telemetry::decimate(samples, samples.len().div_ceil(MAX_SAMPLES / 2))
+}
+
+fn dashboard_broadcast(
+    mut dashboard: ResMut<Dashboard>,
+    time: Res<Time<Real>>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    telemetry: Res<Telemetry>,
+) {
+    if !dashboard.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let dashboard = &mut *dashboard;
+    let (now, read_only) = (clock.elapsed_secs(), dashboard.read_only);
+    let update = |channels| DashboardMessage::Update {
+        time: now,
+        running: !clock.is_paused(),
+        read_only,
+        setpoints: setpoints.values.clone(),
+        channels,
+    };
+    let mut recent = BTreeMap::new();
+    for (name, samples) in &telemetry.channels {
+        let sent = dashboard.sent.entry(name.clone()).or_default();
+        // The telemetry restarts when the session is rewound.
+        if *sent > samples.len() {
+            *sent = 0;
+        }
+        recent.insert(name.clone(), thin(&samples[*sent..]));
+        *sent = samples.len();
+    }
+    let recent = update(recent);
+    let mut history = None;
+    for viewer in dashboard.viewers.values_mut() {
+        if viewer.welcomed {
+            viewer.send(&recent);
+            continue;
+        }
+        let history = history.get_or_insert_with(|| {
+            update(
+                telemetry
+                    .channels
+                    .iter()
+                    .map(|(name, samples)| {
+                        let start = samples.partition_point(|[time, _]| *time < now - HISTORY);
+                        (name.clone(), thin(&samples[start..]))
+                    })
+                    .collect(),
+            )
+        });
+        viewer.send(history);
+        viewer.welcomed = true;
+    }
+}
@@ -0,0 +1,227 @@
+//! Determinism checks: a reference scenario is simulated headlessly and its trajectory hashed,
+//! so runs on different platforms or builds can be compared.
+//!
+//! The poses of the dynamic bodies are hashed after every step. The report keeps the hash of
+//! the whole trajectory, along with checkpoints holding the hash so far and the poses, which
+//! tell when two runs diverged and by how much. Replays and reinforcement learning rely on
+//! bit-exact trajectories; the checkpoints quantify the drift when they aren't.
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants::Plant,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+/// Number of physics steps of the reference scenario (10 s).
+pub const REFERENCE_STEPS: usize = 600;
+/// A checkpoint is recorded every `CHECKPOINT_INTERVAL` steps.
+pub const CHECKPOINT_INTERVAL: usize = 60;
+/// Velocity of the motor commanded from each step of the scenario on, in rad/s.
+const MOTOR_PROFILE: [(usize, f32); 4] = [(0, 10.0), (150, -10.0), (300, 5.0), (450, 0.0)];
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// 64-bit FNV-1a hash of the trajectory: simple, and stable across platforms and releases,
+/// unlike the hasher of the standard library.
+#[derive(Clone, Copy, Debug)]
+struct TrajectoryHasher(u64);
+
+impl Default for TrajectoryHasher {
+    fn default() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+}
+
+impl TrajectoryHasher {
+    fn write(&mut self, value: f32) {
+        // Both zeros and every NaN are the same state.
+        let value = if value == 0.0 {
+            0.0
+        } else if value.is_nan() {
+            f32::NAN
+        } else {
+            value
+        };
+        for byte in value.to_bits().to_le_bytes() {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn hex(self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// State of the trajectory after a step.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Checkpoint {
+    /// Number of steps simulated.
+    pub step: usize,
+    /// Hash of the trajectory up to this step.
+    pub hash: String,
+    /// Translation and rotation of every dynamic body, in spawn order.
+    pub poses: Vec<[f32; 7]>,
+}
+
+/// Outcome of a run of the reference scenario.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DeterminismReport {
+    pub plant: String,
+    pub steps: usize,
+    pub dt: f32,
+    /// Operating system and architecture, e.g. `linux-x86_64`.
+    pub platform: String,
+    /// Version and profile of the build.
+    pub build: String,
+    /// Hash of the whole trajectory.
+    pub hash: String,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl DeterminismReport {
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+/// Result of the comparison of two reports.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Comparison {
+    Identical,
+    /// The trajectories differ from the checkpoint at `step` on, by `max_deviation` at most
+    /// over the checkpoints (infinite if the bodies differ).
+    Diverged {
+        step: usize,
+        max_deviation: f32,
+    },
+    /// The reports don't come from the same scenario.
+    Incomparable(String),
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Comparison::Identical => write!(f, "identical trajectories"),
+            Comparison::Diverged {
+                step,
+                max_deviation,
+            } => write!(
+                f,
+                "trajectories diverged by step {step}, deviating by up to {max_deviation:e}"
+            ),
+            Comparison::Incomparable(reason) => write!(f, "not comparable: {reason}"),
+        }
+    }
+}
+
+/// Compares the trajectories of two runs of the reference scenario.
+pub fn compare(a: &DeterminismReport, b: &DeterminismReport) -> Comparison {
+    if a.plant != b.plant || a.steps != b.steps || a.dt != b.dt {
+        return Comparison::Incomparable(format!(
+            "{} steps of {} s of {} against {} steps of {} s of {}",
+            a.steps, a.dt, a.plant, b.steps, b.dt, b.plant
+        ));
+    }
+    if a.hash == b.hash {
+        return Comparison::Identical;
+    }
+    let mut diverged_at = None;
+    let mut max_deviation = 0.0f32;
+    for (a, b) in a.checkpoints.iter().zip(&b.checkpoints) {
+        if a.hash != b.hash && diverged_at.is_none() {
+            diverged_at = Some(a.step);
+        }
+        if a.poses.len() != b.poses.len() {
+            max_deviation = f32::INFINITY;
+            continue;
+        }
+        for (a, b) in a.poses.iter().flatten().zip(b.poses.iter().flatten()) {
+            max_deviation = max_deviation.max((a - b).abs());
+        }
+    }
+    Comparison::Diverged {
+        // Past the last checkpoint, if none differ.
+        step: diverged_at.unwrap_or(a.steps),
+        max_deviation,
+    }
+}
+
+/// Simulates the reference scenario on `plant` for `steps` steps.
+pub fn run_reference(plant: &Plant, steps: usize) -> DeterminismReport {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    (plant.add)(&mut app);
+    app.init_resource::<Setpoints>();
+    app.finish();
+    app.cleanup();
+
+    let mut hasher = TrajectoryHasher::default();
+    let mut checkpoints = Vec::new();
+    for step in 0..steps {
+        if let Some(&(_, velocity)) = MOTOR_PROFILE.iter().find(|(from, _)| *from == step) {
+            app.world_mut()
+                .resource_mut::<Setpoints>()
+                .set(MOTOR_VELOCITY, velocity);
+        }
+        app.update();
+
+        let poses = poses(app.world_mut());
+        for value in poses.iter().flatten() {
+            hasher.write(*value);
+        }
+        let simulated = step + 1;
+        if simulated % CHECKPOINT_INTERVAL == 0 || simulated == steps {
+            checkpoints.push(Checkpoint {
+                step: simulated,
+                hash: hasher.hex(),
+                poses,
+            });
+        }
+    }
+
+    DeterminismReport {
+        plant: plant.name.to_string(),
+        steps,
+        dt: DEFAULT_TIME_STEP,
+        platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+        build: format!(
+            "{} {}",
+            env!("CARGO_PKG_VERSION"),
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            }
+        ),
+        hash: hasher.hex(),
+        checkpoints,
+    }
+}
+
+fn poses(world: &mut World) -> Vec<[f32; 7]> {
+    let mut bodies = world
+        .query::<(Entity, &RigidBody, &Transform)>()
+        .iter(world)
+        .filter(|(_, body, _)| **body == RigidBody::Dynamic)
+        .map(|(entity, _, transform)| {
+            let t = transform.translation;
+            let r = transform.rotation;
+            (entity, [t.x, t.y, t.z, r.x, r.y, r.z, r.w])
+        })
+        .collect::<Vec<_>>();
+    bodies.sort_by_key(|(entity, _)| *entity);
+    bodies.into_iter().map(|(_, pose)| pose).collect()
+}
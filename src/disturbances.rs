@@ -0,0 +1,609 @@
+//! This module adds the periodic disturbances of a real motor, so the tuning of the velocity
+//! loop faces them: the cogging torque, pulling the rotor towards its preferred angles, and the
+//! force of an unbalanced rotor, rotating with it.
+//!
+//! The cogging torque is a sum of harmonics of the angle of the rotor, read from the
+//! telemetry. The imbalance force grows with the square of the speed of the rotor, estimated
+//! from the last two angles, and points along a radius of the rotor. Both are applied to the
+//! rotor link as an impulse per step, adding to the other forces on it, and recorded as the
+//! `disturbance/cogging` and `disturbance/imbalance` telemetry channels.
+//!
+//! Disturbances are also injected on demand, to test the robustness of the controllers or to
+//! excite the plant for identification: an impulse, a constant force for a while, or a torque
+//! sweeping a range of frequencies (a chirp), applied to a link when the key is pressed, or to
+//! the body shift-clicked. The injected magnitude is recorded as `disturbance/injected`.
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+    telemetry::{Telemetry, MOTOR_ANGLE},
+};
+
+/// Cogging torque on the rotor, in N·m.
+pub const DISTURBANCE_COGGING: &str = "disturbance/cogging";
+/// Magnitude of the imbalance force on the rotor, in N.
+pub const DISTURBANCE_IMBALANCE: &str = "disturbance/imbalance";
+/// Magnitude of the injected disturbance: the impulse, in N·s, the force, in N, or the torque,
+/// in N·m.
+pub const DISTURBANCE_INJECTED: &str = "disturbance/injected";
+/// Farthest body that can be clicked, in m.
+const MAX_REACH: f32 = 1000.0;
+
+pub struct DisturbancesPlugin;
+
+impl Plugin for DisturbancesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Inject>()
+            .init_resource::<Injections>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (disturb, start_injections, inject)
+                    .chain()
+                    .run_if(resource_exists::<Persistent<DisturbanceSettings>>),
+            )
+            .add_systems(
+                Update,
+                (
+                    trigger_injections.before(start_injections),
+                    disturbances_panel,
+                )
+                    .run_if(resource_exists::<Persistent<DisturbanceSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A harmonic of the cogging torque.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Harmonic {
+    /// Periods per turn of the rotor, e.g. the number of slots.
+    pub order: u32,
+    /// Amplitude of the torque, in N·m.
+    pub amplitude: f32,
+    /// Phase at the zero angle, in radians.
+    #[serde(default)]
+    pub phase: f32,
+}
+
+/// Shape of an injected disturbance.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionKind {
+    /// An impulse, at once.
+    #[default]
+    Impulse,
+    /// A constant force for the duration.
+    Force,
+    /// A torque sweeping linearly from the start to the end frequency over the duration.
+    Chirp,
+}
+
+impl InjectionKind {
+    pub const ALL: [Self; 3] = [Self::Impulse, Self::Force, Self::Chirp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Impulse => "Impulse",
+            Self::Force => "Force",
+            Self::Chirp => "Chirp",
+        }
+    }
+
+    /// Unit of the amplitude.
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Impulse => "N·s",
+            Self::Force => "N",
+            Self::Chirp => "N·m",
+        }
+    }
+}
+
+/// Represents the configuration of the injected disturbances.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Injection {
+    pub kind: InjectionKind,
+    /// Path of the link the key disturbs.
+    pub link: String,
+    /// Direction of the impulse or the force, or axis of the torque, in the frame of the link.
+    pub direction: Vec3,
+    /// Impulse, in N·s, force, in N, or amplitude of the torque, in N·m.
+    pub amplitude: f32,
+    /// Duration of the force or of the chirp, in s.
+    pub duration: f32,
+    /// Frequencies of the chirp at its start and at its end, in Hz.
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    /// Key injecting the disturbance into the link.
+    pub key: KeyCode,
+}
+
+impl Default for Injection {
+    fn default() -> Self {
+        Self {
+            kind: InjectionKind::Impulse,
+            link: "pendulum".to_string(),
+            direction: Vec3::X,
+            amplitude: 1.0,
+            duration: 2.0,
+            start_frequency: 0.1,
+            end_frequency: 5.0,
+            key: KeyCode::F10,
+        }
+    }
+}
+
+impl Injection {
+    /// Force or torque `elapsed` seconds after the start of the injection, or `None` once
+    /// over. An impulse is applied at once, in full.
+    pub fn magnitude(&self, elapsed: f32) -> Option<f32> {
+        if elapsed < 0.0 || elapsed >= self.duration {
+            return None;
+        }
+        match self.kind {
+            InjectionKind::Impulse => None,
+            InjectionKind::Force => Some(self.amplitude),
+            InjectionKind::Chirp => {
+                let sweep = (self.end_frequency - self.start_frequency) / self.duration;
+                let cycles = self.start_frequency * elapsed + sweep * elapsed * elapsed / 2.0;
+                Some(self.amplitude * (TAU * cycles).sin())
+            }
+        }
+    }
+}
+
+/// Request to inject the configured disturbance into a body, at a point, or into the
+/// configured link.
+#[derive(Clone, Debug, Default, Event)]
+pub struct Inject {
+    pub body: Option<Entity>,
+    /// Point the impulse or the force is applied at, in the world, instead of the center of
+    /// the body.
+    pub point: Option<Vec3>,
+    /// Disturbance injected instead of the configured one.
+    pub injection: Option<Injection>,
+}
+
+/// A disturbance being injected.
+#[derive(Clone, Debug)]
+pub struct ActiveInjection {
+    pub body: Entity,
+    pub injection: Injection,
+    /// Time of the start, in s.
+    pub start: f32,
+    /// Point the impulse or the force is applied at, in the frame of the body.
+    pub local_point: Vec3,
+}
+
+/// The disturbances being injected.
+#[derive(Debug, Default, Resource)]
+pub struct Injections(pub Vec<ActiveInjection>);
+
+/// Represents the configuration of the disturbances of the motor.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct DisturbanceSettings {
+    pub enabled: bool,
+    /// Path of the link turned by the motor.
+    pub rotor: String,
+    /// Telemetry channel of the angle of the rotor, over the turns, in radians.
+    pub angle: String,
+    /// Axis of the rotor, in the frame of its link.
+    pub axis: Vec3,
+    pub cogging: Vec<Harmonic>,
+    /// Unbalanced mass times its distance to the axis, in kg·m.
+    pub imbalance: f32,
+    pub injection: Injection,
+}
+
+impl Default for DisturbanceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rotor: "column".to_string(),
+            angle: MOTOR_ANGLE.to_string(),
+            axis: Vec3::Y,
+            cogging: vec![Harmonic {
+                order: 6,
+                amplitude: 0.2,
+                phase: 0.0,
+            }],
+            imbalance: 0.01,
+            injection: Injection::default(),
+        }
+    }
+}
+
+impl DisturbanceSettings {
+    /// Cogging torque at the `angle` of the rotor, in N·m.
+    pub fn cogging_torque(&self, angle: f32) -> f32 {
+        self.cogging
+            .iter()
+            .map(|harmonic| {
+                harmonic.amplitude * (harmonic.order as f32 * angle + harmonic.phase).sin()
+            })
+            .sum()
+    }
+
+    /// Magnitude of the imbalance force at the `velocity` of the rotor, in N.
+    pub fn imbalance_force(&self, velocity: f32) -> f32 {
+        self.imbalance * velocity * velocity
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<DisturbanceSettings>("disturbances", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Applies the disturbances to the rotor, for the next step of the simulation.
+fn disturb(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<DisturbanceSettings>>,
+    mut rotors: Query<(Entity, &Link, &Transform, Option<&mut ExternalImpulse>)>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    let Some(mut telemetry) = telemetry else {
+        return;
+    };
+    if !settings.enabled || dt == 0.0 {
+        return;
+    }
+    let Some((entity, _, transform, impulse)) = rotors
+        .iter_mut()
+        .find(|(_, link, _, _)| link.path() == settings.rotor)
+    else {
+        return;
+    };
+    let samples = telemetry
+        .channels
+        .get(&settings.angle)
+        .map_or(&[][..], Vec::as_slice);
+    let (angle, velocity) = match samples {
+        [.., [t0, a0], [t1, a1]] if t1 > t0 => (*a1, (a1 - a0) / (t1 - t0)),
+        [.., [_, angle]] => (*angle, 0.0),
+        [] => return,
+    };
+
+    let axis = settings.axis.normalize_or(Vec3::Y);
+    // Turns with the rotor, as the unbalanced mass.
+    let radius = transform.rotation * axis.any_orthonormal_vector();
+    let axis = transform.rotation * axis;
+    let torque = settings.cogging_torque(angle);
+    let force = settings.imbalance_force(velocity);
+    let disturbance = ExternalImpulse {
+        impulse: force * radius * dt,
+        torque_impulse: torque * axis * dt,
+    };
+    match impulse {
+        Some(mut impulse) => {
+            impulse.impulse += disturbance.impulse;
+            impulse.torque_impulse += disturbance.torque_impulse;
+        }
+        None => {
+            commands.entity(entity).insert(disturbance);
+        }
+    }
+    let time = clock.elapsed_secs();
+    telemetry.record(DISTURBANCE_COGGING, time, torque);
+    telemetry.record(DISTURBANCE_IMBALANCE, time, force);
+}
+
+/// Starts the requested injections, into the configured link unless a body is given.
+fn start_injections(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<DisturbanceSettings>>,
+    mut requests: EventReader<Inject>,
+    bodies: Query<(Entity, &Link, &Transform), With<RigidBody>>,
+    mut injections: ResMut<Injections>,
+) {
+    for request in requests.read() {
+        let injection = request.injection.as_ref().unwrap_or(&settings.injection);
+        let body = match request.body {
+            Some(body) => bodies.get(body).ok(),
+            None => bodies
+                .iter()
+                .find(|(_, link, _)| link.path() == injection.link),
+        };
+        let Some((body, link, transform)) = body else {
+            warn!(
+                target: subsystem::PHYSICS,
+                "No `{}` link to disturb",
+                injection.link
+            );
+            continue;
+        };
+        let local_point = request.point.map_or(Vec3::ZERO, |point| {
+            transform.rotation.inverse() * (point - transform.translation)
+        });
+        info!(
+            target: subsystem::PHYSICS,
+            "{} injected into {}",
+            injection.kind.name(),
+            link.path()
+        );
+        injections.0.push(ActiveInjection {
+            body,
+            injection: injection.clone(),
+            start: clock.elapsed_secs(),
+            local_point,
+        });
+    }
+}
+
+/// Applies the injected disturbances for the next step of the simulation, and drops the
+/// finished ones.
+fn inject(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    mut injections: ResMut<Injections>,
+    mut bodies: Query<(&Transform, Option<&mut ExternalImpulse>), With<RigidBody>>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    if dt == 0.0 || injections.0.is_empty() {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    injections.0.retain(|active| {
+        let Ok((transform, impulse)) = bodies.get_mut(active.body) else {
+            return false;
+        };
+        let injection = &active.injection;
+        let direction = transform.rotation * injection.direction.normalize_or(Vec3::X);
+        let point = transform.translation + transform.rotation * active.local_point;
+        let (disturbance, magnitude, ongoing) = match injection.kind {
+            InjectionKind::Impulse => (
+                ExternalImpulse::at_point(
+                    injection.amplitude * direction,
+                    point,
+                    transform.translation,
+                ),
+                injection.amplitude,
+                false,
+            ),
+            InjectionKind::Force | InjectionKind::Chirp => {
+                let Some(magnitude) = injection.magnitude(time - active.start) else {
+                    return false;
+                };
+                let disturbance = if injection.kind == InjectionKind::Force {
+                    ExternalImpulse::at_point(
+                        magnitude * direction * dt,
+                        point,
+                        transform.translation,
+                    )
+                } else {
+                    ExternalImpulse {
+                        impulse: Vec3::ZERO,
+                        torque_impulse: magnitude * direction * dt,
+                    }
+                };
+                (disturbance, magnitude, true)
+            }
+        };
+        match impulse {
+            Some(mut impulse) => {
+                impulse.impulse += disturbance.impulse;
+                impulse.torque_impulse += disturbance.torque_impulse;
+            }
+            None => {
+                commands.entity(active.body).insert(disturbance);
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(DISTURBANCE_INJECTED, time, magnitude);
+        }
+        ongoing
+    });
+}
+
+/// Injects the disturbance into the configured link when its key is pressed, or into the
+/// body shift-clicked, unless the cursor is over the interface.
+#[allow(clippy::too_many_arguments)]
+fn trigger_injections(
+    mut egui: EguiContexts,
+    settings: Res<Persistent<DisturbanceSettings>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    contexts: Query<&RapierContext>,
+    mut requests: EventWriter<Inject>,
+) {
+    if keys.just_pressed(settings.injection.key) {
+        requests.send(Inject::default());
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !shift || !buttons.just_pressed(MouseButton::Left) || egui.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    let ray = windows
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .and_then(|cursor| {
+            let (camera, transform) = cameras.iter().find(|(camera, _)| camera.is_active)?;
+            camera.viewport_to_world(transform, cursor).ok()
+        });
+    let (Some(ray), Ok(context)) = (ray, contexts.get_single()) else {
+        return;
+    };
+    if let Some((body, depth)) = context.cast_ray(
+        ray.origin,
+        *ray.direction,
+        MAX_REACH,
+        true,
+        QueryFilter::only_dynamic(),
+    ) {
+        requests.send(Inject {
+            body: Some(body),
+            point: Some(ray.get_point(depth)),
+            ..default()
+        });
+    }
+}
+
+/// Panel to enable the disturbances and set their amplitudes.
+fn disturbances_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<DisturbanceSettings>>,
+    injections: Res<Injections>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Disturbances")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Disturb the motor")
+                .on_hover_text(format!("Applies the disturbances to `{}`", edited.rotor));
+
+            ui.separator();
+            ui.label("Cogging torque");
+            let mut removed = None;
+            for (index, harmonic) in edited.cogging.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut harmonic.order)
+                            .range(1..=1000)
+                            .prefix("Order: "),
+                    )
+                    .on_hover_text("Periods per turn");
+                    ui.add(
+                        egui::DragValue::new(&mut harmonic.amplitude)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Amplitude: ")
+                            .suffix(" N·m"),
+                    );
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.cogging.remove(index);
+            }
+            if ui.button("Add a harmonic").clicked() {
+                let order = edited.cogging.last().map_or(6, |last| 2 * last.order);
+                edited.cogging.push(Harmonic {
+                    order,
+                    amplitude: 0.1,
+                    phase: 0.0,
+                });
+            }
+
+            ui.separator();
+            ui.add(
+                egui::DragValue::new(&mut edited.imbalance)
+                    .range(0.0..=10.0)
+                    .speed(0.001)
+                    .prefix("Imbalance: ")
+                    .suffix(" kg·m"),
+            )
+            .on_hover_text("Unbalanced mass times its distance to the axis");
+
+            ui.separator();
+            ui.label("Injection");
+            let injection = &mut edited.injection;
+            ui.horizontal(|ui| {
+                for kind in InjectionKind::ALL {
+                    ui.selectable_value(&mut injection.kind, kind, kind.name());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Link");
+                ui.text_edit_singleline(&mut injection.link);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Direction");
+                let direction = &mut injection.direction;
+                for value in [&mut direction.x, &mut direction.y, &mut direction.z] {
+                    ui.add(egui::DragValue::new(value).range(-1.0..=1.0).speed(0.01));
+                }
+            })
+            .response
+            .on_hover_text("In the frame of the link: the axis of the torque of a chirp");
+            ui.add(
+                egui::DragValue::new(&mut injection.amplitude)
+                    .range(-1000.0..=1000.0)
+                    .speed(0.01)
+                    .prefix("Amplitude: ")
+                    .suffix(format!(" {}", injection.kind.unit())),
+            );
+            if injection.kind != InjectionKind::Impulse {
+                ui.add(
+                    egui::DragValue::new(&mut injection.duration)
+                        .range(0.0..=60.0)
+                        .speed(0.01)
+                        .prefix("Duration: ")
+                        .suffix(" s"),
+                );
+            }
+            if injection.kind == InjectionKind::Chirp {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut injection.start_frequency)
+                            .range(0.0..=1000.0)
+                            .speed(0.01)
+                            .prefix("From ")
+                            .suffix(" Hz"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut injection.end_frequency)
+                            .range(0.0..=1000.0)
+                            .speed(0.01)
+                            .prefix("to ")
+                            .suffix(" Hz"),
+                    );
+                });
+            }
+            if ui
+                .button(format!("Inject ({:?})", injection.key))
+                .on_hover_text("Or shift-click a body to disturb it")
+                .clicked()
+            {
+                commands.send_event(Inject::default());
+            }
+            if !injections.0.is_empty() {
+                ui.label(format!("{} injections running", injections.0.len()));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("disturbances", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("disturbances", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,176 @@
+//! The double pendulum, the classic chaotic system: two links hinged end to end from a fixed
+//! pivot, swinging freely under gravity.
+//!
+//! Both links are released horizontal, so the pendulum starts to swing at once. The angle of the
+//! upper link from hanging, and that of the lower link from the upper one, in rad within
+//! `(-π, π]`, are recorded as the `upper/angle` and `lower/angle` telemetry channels.
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody},
+    telemetry::Telemetry,
+};
+
+/// Angle of the upper link from hanging, in rad.
+pub const UPPER_ANGLE: &str = "upper/angle";
+/// Angle of the lower link from the upper one, in rad.
+pub const LOWER_ANGLE: &str = "lower/angle";
+/// Lengths of the upper and of the lower links, in m.
+pub const UPPER_LENGTH: f32 = 1.0;
+pub const LOWER_LENGTH: f32 = 1.0;
+/// Width and height of the links, in m.
+pub const THICKNESS: f32 = 0.1;
+/// Mass of each link, in kg.
+pub const LINK_MASS: f32 = 1.0;
+
+/// The double pendulum, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "double_pendulum",
+        add: |app| {
+            app.add_plugins(DoublePendulumPlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<DoublePendulumPlugin>() {
+                app.add_plugins(DoublePendulumPlugin);
+            }
+            app.insert_resource(DoublePendulumInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(DoublePendulumInstances::default());
+            plants::run_spawn(world, spawn_double_pendulums);
+        },
+    }
+}
+
+pub struct DoublePendulumPlugin;
+
+impl Plugin for DoublePendulumPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DoublePendulumInstances>()
+            .add_systems(Startup, spawn_double_pendulums)
+            .add_systems(Update, record_link_angles);
+    }
+}
+
+/// The double pendulums spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct DoublePendulumInstances(pub Vec<Instance>);
+
+impl Default for DoublePendulumInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The joints of a double pendulum, on the entity of its upper link.
+#[derive(Component)]
+struct Hinges {
+    /// Signals of the instance.
+    upper_angle: String,
+    lower_angle: String,
+    /// The lower link, carrying the joint to the upper one.
+    lower: Entity,
+}
+
+fn spawn_double_pendulums(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<DoublePendulumInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const PIVOT_HEIGHT: f32 = 3.0;
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let pivot = instance.offset + Vec3::Y * PIVOT_HEIGHT;
+        let base = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Mesh3d(meshes.add(Cuboid::from_length(2.0 * THICKNESS))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(pivot),
+            ))
+            .id();
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        // Clear of each other, where they are hinged.
+        let groups = CollisionGroups::new(Group::GROUP_2, Group::ALL ^ Group::GROUP_2);
+        let mut spawn_link = |name: &str, length: f32, start: f32| {
+            commands
+                .spawn((
+                    RigidBody::Dynamic,
+                    link(name),
+                    Collider::cuboid(length / 2.0, THICKNESS / 2.0, THICKNESS / 2.0),
+                    ColliderMassProperties::Mass(LINK_MASS),
+                    groups,
+                    Mesh3d(meshes.add(Cuboid::new(length, THICKNESS, THICKNESS))),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_translation(pivot + Vec3::X * (start + length / 2.0)),
+                ))
+                .id()
+        };
+        let upper = spawn_link("upper", UPPER_LENGTH, 0.0);
+        let lower = spawn_link("lower", LOWER_LENGTH, UPPER_LENGTH);
+
+        let shoulder =
+            RevoluteJointBuilder::new(Vec3::Z).local_anchor2(-Vec3::X * UPPER_LENGTH / 2.0);
+        let elbow = RevoluteJointBuilder::new(Vec3::Z)
+            .local_anchor1(Vec3::X * UPPER_LENGTH / 2.0)
+            .local_anchor2(-Vec3::X * LOWER_LENGTH / 2.0);
+        commands.entity(upper).insert((
+            ImpulseJoint::new(base, shoulder),
+            Hinges {
+                upper_angle: instance.signal(UPPER_ANGLE),
+                lower_angle: instance.signal(LOWER_ANGLE),
+                lower,
+            },
+        ));
+        commands
+            .entity(lower)
+            .insert(ImpulseJoint::new(upper, elbow));
+    }
+}
+
+/// Records the angles of the links.
+fn record_link_angles(
+    clock: Res<SimClock>,
+    hinges: Query<(Entity, &Hinges)>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_link_angles").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    let wrap = |angle: f32| PI - (PI - angle).rem_euclid(TAU);
+    let time = clock.elapsed_secs();
+    for (upper, hinges) in &hinges {
+        // Released horizontal, a quarter turn from hanging.
+        if let Some(angle) = context.impulse_revolute_joint_angle(upper) {
+            telemetry.record(&hinges.upper_angle, time, wrap(angle + FRAC_PI_2));
+        }
+        if let Some(angle) = context.impulse_revolute_joint_angle(hinges.lower) {
+            telemetry.record(&hinges.lower_angle, time, wrap(angle));
+        }
+    }
+}
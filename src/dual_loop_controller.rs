@@ -0,0 +1,434 @@
+//! This module closes the loops of an axis driven through a compliant transmission, such as the
+//! compliant axis, with a [`DualLoop`]: a position loop on the load-side encoder around a
+//! velocity loop on the motor-side one, as industrial drives do.
+//!
+//! A [`DualLoopController`] on the entity of the joint of the load measures the angles of the
+//! load and of the motor after each physics step, from their encoders when they have some, and
+//! applies the torque of the motor for the next step. The velocity of the motor is the change
+//! of its angle over the step, as a drive differentiates its encoder. Its target is the
+//! `load/position` setpoint of the plant, in rad; the error on the load, the velocity setpoint
+//! of the motor and the torque are recorded as the `dual_loop/error`, `dual_loop/velocity` and
+//! `dual_loop/torque` telemetry channels.
+//!
+//! The controllers are added to the joints of the configured load when enabled, next to the
+//! joint of the configured motor in the same plant, and take the configured gains whenever they
+//! change.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    compliant_axis::{GEAR_RATIO, TORQUE_LIMIT},
+    config_plugin,
+    control::{DualLoop, Pid, PidGains, Saturation},
+    error::{Error, ErrorEvent},
+    estimation::EstimationSet,
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link},
+    sensors::{self, ControlSet, Encoder, SensorSet},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Position of the load, in rad.
+pub const LOAD_POSITION: &str = "load/position";
+/// Error of the position loop on the load, in rad.
+pub const DUAL_LOOP_ERROR: &str = "dual_loop/error";
+/// Velocity setpoint of the motor, in rad/s.
+pub const DUAL_LOOP_VELOCITY: &str = "dual_loop/velocity";
+/// Torque commanded to the motor, in N·m.
+pub const DUAL_LOOP_TORQUE: &str = "dual_loop/torque";
+
+pub struct DualLoopControllerPlugin;
+
+impl Plugin for DualLoopControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_controllers.run_if(resource_exists::<Persistent<DualLoopSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                dual_loop_panel
+                    .run_if(resource_exists::<Persistent<DualLoopSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the dual loops.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct DualLoopSettings {
+    pub enabled: bool,
+    /// Name of the link whose joint drives, with the motor-side encoder, in each plant.
+    pub motor: String,
+    /// Name of the link whose joint is driven, with the load-side encoder.
+    pub load: String,
+    /// Gains of the position loop, from the error on the load, in rad, to its velocity.
+    pub position: PidGains,
+    /// Gains of the velocity loop, from the error on the motor, in rad/s, to its torque.
+    pub velocity: PidGains,
+    /// Turns of the motor per turn of the load.
+    pub ratio: f32,
+    /// Largest velocity of the load commanded, in rad/s, or 0 for an unlimited one.
+    pub velocity_limit: f32,
+    /// Largest torque commanded, in N·m, or 0 for an unlimited one.
+    pub torque_limit: f32,
+}
+
+impl Default for DualLoopSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            motor: "motor".to_string(),
+            load: "load".to_string(),
+            position: PidGains {
+                kp: 2.0,
+                ki: 0.5,
+                kd: 0.0,
+            },
+            velocity: PidGains {
+                kp: 0.1,
+                ki: 0.5,
+                kd: 0.0,
+            },
+            ratio: GEAR_RATIO,
+            velocity_limit: 5.0,
+            torque_limit: TORQUE_LIMIT,
+        }
+    }
+}
+
+impl DualLoopSettings {
+    pub fn dual_loop(&self) -> DualLoop {
+        let limits = |limit: f32| {
+            if limit > 0.0 {
+                Saturation::symmetric(limit)
+            } else {
+                Saturation::default()
+            }
+        };
+        DualLoop::new(
+            Pid::new(self.position, limits(self.velocity_limit)),
+            Pid::new(self.velocity, limits(self.torque_limit)),
+            self.ratio,
+        )
+    }
+}
+
+/// The loops of an axis, on the entity of the joint of its load, driving the joint of its
+/// motor.
+#[derive(Clone, Component, Debug)]
+pub struct DualLoopController {
+    pub controller: DualLoop,
+    /// Joint of the motor.
+    pub motor: Entity,
+    /// Setpoint of the position of the load, in rad.
+    pub setpoint: String,
+    /// Telemetry channels of the error, of the velocity setpoint and of the torque.
+    pub error: String,
+    pub velocity: String,
+    pub torque: String,
+    /// Last angles of the motor and of the load within a turn, and of the load over the turns.
+    angles: Option<[f32; 3]>,
+}
+
+impl DualLoopController {
+    /// A controller with the signals of a plant.
+    pub fn new(controller: DualLoop, motor: Entity, plant: &str) -> Self {
+        Self {
+            controller,
+            motor,
+            setpoint: plants::namespaced(plant, LOAD_POSITION),
+            error: plants::namespaced(plant, DUAL_LOOP_ERROR),
+            velocity: plants::namespaced(plant, DUAL_LOOP_VELOCITY),
+            torque: plants::namespaced(plant, DUAL_LOOP_TORQUE),
+            angles: None,
+        }
+    }
+
+    /// Torque of the motor for the next `dt` seconds, from the angles of the motor and of the
+    /// load within a turn.
+    pub fn update(&mut self, target: f32, motor: f32, load: f32, dt: f32) -> f32 {
+        let wrap = |angle: f32| (angle + PI).rem_euclid(TAU) - PI;
+        let (motor_velocity, position) = match self.angles {
+            Some([last_motor, last_load, position]) => (
+                wrap(motor - last_motor) / dt,
+                position + wrap(load - last_load),
+            ),
+            None => (0.0, load),
+        };
+        self.angles = Some([motor, load, position]);
+        self.controller.update(target, position, motor_velocity, dt)
+    }
+
+    /// Position of the load over the turns, once measured.
+    pub fn position(&self) -> Option<f32> {
+        self.angles.map(|[_, _, position]| position)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<DualLoopSettings>("dual_loop", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers, as the joints spawn or when the configuration changes, and
+/// gives them the configured gains.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<DualLoopSettings>>,
+    mut joints: Query<(
+        Entity,
+        &Link,
+        &ImpulseJoint,
+        Option<&mut DualLoopController>,
+    )>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    let motors = joints
+        .iter()
+        .filter(|(_, link, ..)| link.name == settings.motor)
+        .map(|(entity, link, joint, _)| (link.plant.clone(), entity, joint.parent))
+        .collect::<Vec<_>>();
+    for (entity, link, _, controller) in &mut joints {
+        let motor = motors
+            .iter()
+            .filter(|_| settings.enabled && link.name == settings.load)
+            .find(|(plant, ..)| *plant == link.plant);
+        match (controller, motor) {
+            (Some(mut controller), Some(&(_, motor, _))) if controller.motor == motor => {
+                let configured = settings.dual_loop();
+                controller.controller.position.gains = configured.position.gains;
+                controller.controller.position.limits = configured.position.limits;
+                controller.controller.velocity.gains = configured.velocity.gains;
+                controller.controller.velocity.limits = configured.velocity.limits;
+                controller.controller.ratio = configured.ratio;
+            }
+            (_, Some(&(_, motor, motor_parent))) => {
+                commands.entity(entity).insert(DualLoopController::new(
+                    settings.dual_loop(),
+                    motor,
+                    &link.plant,
+                ));
+                // The parent of the motor takes the reaction.
+                for body in [motor, motor_parent] {
+                    commands
+                        .entity(body)
+                        .insert_if_new(ExternalImpulse::default());
+                }
+                info!(
+                    target: subsystem::CONTROL,
+                    "Dual loop of {} closed around {}",
+                    link.path(),
+                    plants::namespaced(&link.plant, &settings.motor)
+                );
+            }
+            (Some(_), None) => {
+                commands.entity(entity).remove::<DualLoopController>();
+                info!(target: subsystem::CONTROL, "Dual loop of {} opened", link.path());
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step and applies the torques of the motors for the
+/// next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    mut controllers: Query<(Entity, &mut DualLoopController)>,
+    mut joints: Query<(&mut ImpulseJoint, &Transform)>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_dual_loops").entered();
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    for (entity, mut controller) in &mut controllers {
+        let angle = |entity: Entity| sensors::joint_angle(context, &encoders, &latencies, entity);
+        let (Some(motor), Some(load)) = (angle(controller.motor), angle(entity)) else {
+            continue;
+        };
+        let target = setpoints.get(&controller.setpoint).unwrap_or_default();
+        let torque = controller.update(target, motor, load, dt);
+        let Ok((mut joint, transform)) = joints.get_mut(controller.motor) else {
+            continue;
+        };
+        // Released from its servo, if it has one.
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, 0.0, 0.0);
+        let axis = (transform.rotation * joint.data.as_ref().local_axis2()).normalize_or_zero();
+        let torque_impulse = torque * axis * dt;
+        if let Ok(mut impulse) = impulses.get_mut(controller.motor) {
+            impulse.torque_impulse += torque_impulse;
+        }
+        if let Ok(mut impulse) = impulses.get_mut(joint.parent) {
+            impulse.torque_impulse -= torque_impulse;
+        }
+        if let (Some(telemetry), Some(position)) = (telemetry.as_mut(), controller.position()) {
+            let time = clock.elapsed_secs();
+            telemetry.record(&controller.error, time, target - position);
+            telemetry.record(
+                &controller.velocity,
+                time,
+                controller.controller.velocity_setpoint(),
+            );
+            telemetry.record(&controller.torque, time, torque);
+        }
+    }
+}
+
+/// Drag values of the gains of a loop.
+fn gains(ui: &mut egui::Ui, name: &str, gains: &mut PidGains) {
+    ui.label(name);
+    ui.add(
+        egui::DragValue::new(&mut gains.kp)
+            .range(0.0..=1000.0)
+            .speed(0.01)
+            .prefix("Kp: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gains.ki)
+            .range(0.0..=1000.0)
+            .speed(0.01)
+            .prefix("Ki: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gains.kd)
+            .range(0.0..=100.0)
+            .speed(0.001)
+            .prefix("Kd: "),
+    );
+    ui.end_row();
+}
+
+/// Panel to tune the dual loops while they run.
+fn dual_loop_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<DualLoopSettings>>,
+    mut setpoints: ResMut<Setpoints>,
+    controllers: Query<&DualLoopController>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Dual-loop controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Control the position of the load");
+            ui.horizontal(|ui| {
+                ui.label("Motor");
+                ui.text_edit_singleline(&mut edited.motor);
+                ui.label("Load");
+                ui.text_edit_singleline(&mut edited.load);
+            });
+            egui::Grid::new("dual loop gains").show(ui, |ui| {
+                gains(ui, "Position", &mut edited.position);
+                gains(ui, "Velocity", &mut edited.velocity);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.ratio)
+                    .range(-100.0..=100.0)
+                    .speed(0.01)
+                    .prefix("Ratio: "),
+            )
+            .on_hover_text("Turns of the motor per turn of the load");
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.velocity_limit)
+                        .range(0.0..=100.0)
+                        .speed(0.1)
+                        .prefix("Velocity limit: ")
+                        .suffix(" rad/s"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.torque_limit)
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .prefix("Torque limit: ")
+                        .suffix(" N·m"),
+                );
+            });
+
+            ui.separator();
+            for controller in &controllers {
+                let mut target = setpoints.get(&controller.setpoint).unwrap_or_default();
+                let response = ui.add(
+                    egui::DragValue::new(&mut target)
+                        .speed(0.01)
+                        .prefix(format!("{}: ", controller.setpoint))
+                        .suffix(" rad"),
+                );
+                if response.changed() {
+                    setpoints.set(&controller.setpoint, target);
+                }
+                let latest = |channel: &str| {
+                    telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(channel))
+                        .unwrap_or_default()
+                };
+                ui.label(format!(
+                    "Error: {:.3} rad, motor velocity: {:.2} rad/s, torque: {:.2} N·m",
+                    latest(&controller.error),
+                    latest(&controller.velocity),
+                    latest(&controller.torque)
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("dual_loop", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("dual_loop", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
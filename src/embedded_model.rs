@@ -5,13 +5,24 @@ use bevy::prelude::*;
 use bevy_persistent::Persistent;
 use bevy_rapier3d::prelude::*;
 
-use crate::config_plugin::KeyBindings;
+use crate::{
+    accessibility_plugin::keyboard_available,
+    clock::SimClock,
+    config_plugin::KeyBindings,
+    control,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::{Instance, Link, PlantBody},
+    setpoints::{Setpoints, MOTOR_RELEASE, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_ANGLE, MOTOR_TORQUE, PENDULUM_ANGLE},
+};
 
 pub struct EmbeddedModelPlugin;
 
 impl Plugin for EmbeddedModelPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Motor>()
+        app.init_resource::<PendulumInstances>()
+            .init_resource::<Setpoints>()
             // .add_systems(PreStartup, |mut rapier_config: ResMut<RapierConfiguration>| {
             //     rapier_config.physics_pipeline_active = false;
             // })
@@ -21,59 +32,104 @@ impl Plugin for EmbeddedModelPlugin {
             // })
             .add_systems(
                 Update,
-                control_motor.run_if(resource_changed::<ButtonInput<KeyCode>>),
+                (
+                    control_motor
+                        .run_if(resource_changed::<ButtonInput<KeyCode>>)
+                        .run_if(keyboard_available),
+                    apply_motor_setpoint.run_if(resource_changed::<Setpoints>),
+                )
+                    .chain(),
             )
-            .add_systems(Update, get_pendulum_state);
+            .add_systems(
+                Update,
+                (get_pendulum_state, get_motor_torque, get_motor_angle),
+            );
     }
 }
 
-#[derive(Resource, Default)]
-struct Motor {
-    /// The entity of the joint. It's used to control the motor.
-    joint_entity: Option<Entity>,
-}
-
-#[derive(Component)]
-struct Name(String);
+/// The pendulums spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct PendulumInstances(pub Vec<Instance>);
 
-impl Name {
-    fn new(name: &str) -> Self {
-        Name(name.to_string())
+impl Default for PendulumInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
     }
+}
 
-    fn as_str(&self) -> &str {
-        &self.0
-    }
+/// The motor of a pendulum, on the entity of its joint. It's used to control the motor.
+#[derive(Component)]
+struct Motor {
+    /// Signals of the instance.
+    velocity: String,
+    release: String,
+    torque: String,
+    angle: String,
+    pendulum_angle: String,
+    /// The bodies whose relative rotation is the angle of the pendulum.
+    arm: Entity,
+    pendulum: Entity,
+    /// Last angle within a turn, and the angle over the turns.
+    turns: Option<(f32, f32)>,
 }
+
 /// This system is used to create the scene with embedded model.
-fn add_rotary_interved_pendulum(
+pub(crate) fn add_rotary_interved_pendulum(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut motor: ResMut<Motor>,
+    instances: Res<PendulumInstances>,
 ) {
     const GROUND_THICKNESS: f32 = 0.01;
     const GROUND_SIDE_SIZE: f32 = 100.0;
+
+    for (index, instance) in instances.0.iter().enumerate() {
+        // Every pendulum is fixed to its own base, the first one being the ground.
+        let base = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ))
+            .id();
+        if index == 0 {
+            commands.entity(base).insert(Collider::cuboid(
+                GROUND_SIDE_SIZE,
+                GROUND_THICKNESS,
+                GROUND_SIDE_SIZE,
+            ));
+        }
+        spawn_pendulum(&mut commands, &mut meshes, &mut materials, base, instance);
+    }
+}
+
+fn spawn_pendulum(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    base: Entity,
+    instance: &Instance,
+) {
     const CUBE_SIZE: f32 = 1.0;
     const CYLINDER_RADIUS: f32 = 0.25;
     const CYLINDER_HEIGHT: f32 = 3.0;
 
-    let ground = commands
-        .spawn((
-            RigidBody::Fixed,
-            Transform::from_xyz(0.0, -GROUND_THICKNESS, 0.0),
-            Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
-        ))
-        .id();
+    let at =
+        |x: f32, y: f32, z: f32| Transform::from_translation(instance.offset + Vec3::new(x, y, z));
+    let link = |name: &str| Link {
+        plant: instance.namespace.clone(),
+        name: name.to_string(),
+    };
 
     let cube_1 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("motor"),
             Collider::cuboid(CUBE_SIZE / 2.0, CUBE_SIZE / 2.0, CUBE_SIZE / 2.0),
             ColliderMassProperties::Mass(1.0),
             Mesh3d(meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(0.0, CUBE_SIZE, 0.0),
+            at(0.0, CUBE_SIZE, 0.0),
         ))
         .id();
 
@@ -81,12 +137,13 @@ fn add_rotary_interved_pendulum(
         FixedJointBuilder::new().local_anchor2(Vec3::new(0.0, CUBE_SIZE / 2.0, 0.0));
 
     commands
-        .entity(ground)
+        .entity(base)
         .insert(ImpulseJoint::new(cube_1, fixed_joint_1));
 
     let cylinder_1 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("column"),
             Collider::cylinder(CYLINDER_HEIGHT / 2.0, CYLINDER_RADIUS),
             ColliderMassProperties::Mass(1.0),
             LockedAxes::TRANSLATION_LOCKED
@@ -97,7 +154,7 @@ fn add_rotary_interved_pendulum(
                 half_height: CYLINDER_HEIGHT / 2.0,
             }))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(0.0, CUBE_SIZE + CYLINDER_HEIGHT / 2.0, 0.0),
+            at(0.0, CUBE_SIZE + CYLINDER_HEIGHT / 2.0, 0.0),
         ))
         .id();
 
@@ -105,21 +162,19 @@ fn add_rotary_interved_pendulum(
         .local_anchor1(Vec3::new(0.0, CUBE_SIZE / 2.0, 0.0))
         .local_anchor2(Vec3::new(0.0, CUBE_SIZE + CYLINDER_HEIGHT / 2.0, 0.0));
 
-    let rev = commands
+    commands
         .entity(cube_1)
-        .insert(ImpulseJoint::new(cylinder_1, revolute_joint_1))
-        .id();
-
-    motor.joint_entity = Some(rev);
+        .insert(ImpulseJoint::new(cylinder_1, revolute_joint_1));
 
     let cube_2 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("hub"),
             Collider::cuboid(CUBE_SIZE / 2.0, CUBE_SIZE / 2.0, CUBE_SIZE / 2.0),
             ColliderMassProperties::Mass(1.0),
             Mesh3d(meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(0.0, CUBE_SIZE + CYLINDER_HEIGHT + CUBE_SIZE / 2.0, 0.0),
+            at(0.0, CUBE_SIZE + CYLINDER_HEIGHT + CUBE_SIZE / 2.0, 0.0),
         ))
         .id();
 
@@ -136,6 +191,7 @@ fn add_rotary_interved_pendulum(
     let cylinder_2 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("arm"),
             Collider::cylinder(CYLINDER_HEIGHT / 2.0, CYLINDER_RADIUS),
             LockedAxes::TRANSLATION_LOCKED_Y,
             ColliderMassProperties::Mass(1.0),
@@ -144,13 +200,12 @@ fn add_rotary_interved_pendulum(
                 half_height: CYLINDER_HEIGHT / 2.0,
             }))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(
+            at(
                 0.0,
                 CUBE_SIZE + CYLINDER_HEIGHT + CUBE_SIZE / 2.0,
                 CUBE_SIZE / 2.0 + CYLINDER_HEIGHT / 2.0,
             )
             .with_rotation(Quat::from_rotation_x(std::f32::consts::PI / 2.0)),
-            Name::new("cylinder_2"),
         ))
         .id();
 
@@ -165,16 +220,16 @@ fn add_rotary_interved_pendulum(
     let cube_3 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("pivot"),
             Collider::cuboid(CUBE_SIZE / 2.0, CUBE_SIZE / 2.0, CUBE_SIZE / 2.0),
             ColliderMassProperties::Mass(1.0),
             Mesh3d(meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(
+            at(
                 0.0,
                 CUBE_SIZE + CYLINDER_HEIGHT + CUBE_SIZE / 2.0,
                 CUBE_SIZE + CYLINDER_HEIGHT,
             ),
-            Name::new("cube_3"),
         ))
         .id();
 
@@ -191,6 +246,7 @@ fn add_rotary_interved_pendulum(
     let cylinder_3 = commands
         .spawn((
             RigidBody::Dynamic,
+            link("pendulum"),
             Collider::cylinder(CYLINDER_HEIGHT / 2.0, CYLINDER_RADIUS),
             ColliderMassProperties::Mass(1.0),
             Mesh3d(meshes.add(Mesh::from(Cylinder {
@@ -198,12 +254,11 @@ fn add_rotary_interved_pendulum(
                 half_height: CYLINDER_HEIGHT / 2.0,
             }))),
             MeshMaterial3d(materials.add(Color::srgb_u8(124, 124, 124))),
-            Transform::from_xyz(
+            at(
                 0.0,
                 CUBE_SIZE + CYLINDER_HEIGHT / 2.0,
                 CUBE_SIZE / 2.0 + CYLINDER_HEIGHT + CUBE_SIZE / 2.0,
             ),
-            Name::new("cylinder_3"),
         ))
         .id();
 
@@ -218,73 +273,137 @@ fn add_rotary_interved_pendulum(
     commands
         .entity(cylinder_3)
         .insert(ImpulseJoint::new(cube_3, fixed_joint_3));
+
+    commands.entity(cube_1).insert(Motor {
+        velocity: instance.signal(MOTOR_VELOCITY),
+        release: instance.signal(MOTOR_RELEASE),
+        torque: instance.signal(MOTOR_TORQUE),
+        angle: instance.signal(MOTOR_ANGLE),
+        pendulum_angle: instance.signal(PENDULUM_ANGLE),
+        arm: cylinder_2,
+        pendulum: cube_3,
+        turns: None,
+    });
 }
 
-/// This system is used to control the motor.
+/// This system sets the velocity of the motor from the keyboard.
 fn control_motor(
     key: Res<ButtonInput<KeyCode>>,
-    motor: ResMut<Motor>,
-    mut query: Query<&mut ImpulseJoint>,
+    mut setpoints: ResMut<Setpoints>,
     key_bindings: Res<Persistent<KeyBindings>>,
 ) {
-    match motor.joint_entity {
-        Some(entity) => {
-            let velocity = 10.0;
-            let factor = 10000.0;
-            let mut joint = query.get_mut(entity).unwrap();
-            if key.just_pressed(key_bindings.rotate_clockwise) {
-                joint
-                    .data
-                    .as_mut()
-                    .as_revolute_mut()
-                    .unwrap()
-                    .set_motor_velocity(velocity, factor);
-            } else if key.just_pressed(key_bindings.rotate_counter_clockwise) {
-                joint
-                    .data
-                    .as_mut()
-                    .as_revolute_mut()
-                    .unwrap()
-                    .set_motor_velocity(-velocity, factor);
-            } else if key.just_pressed(KeyCode::ArrowDown) {
-                debug!("Stop");
-                joint
-                    .data
-                    .as_mut()
-                    .as_revolute_mut()
-                    .unwrap()
-                    .set_motor_velocity(0.0, factor);
-            }
-        }
-        _ => {
-            warn!("No joint entity");
-        }
+    let _span = info_span!(target: subsystem::CONTROL, "control_motor").entered();
+    let velocity = 10.0;
+    if key.just_pressed(key_bindings.rotate_clockwise) {
+        setpoints.set(MOTOR_VELOCITY, velocity);
+    } else if key.just_pressed(key_bindings.rotate_counter_clockwise) {
+        setpoints.set(MOTOR_VELOCITY, -velocity);
+    } else if key.just_pressed(KeyCode::ArrowDown) {
+        debug!(target: subsystem::CONTROL, "Stop");
+        setpoints.set(MOTOR_VELOCITY, 0.0);
     }
 }
 
-fn get_pendulum_state(query: Query<(&Transform, &Name)>) {
-    let mut cube_3_transform = None;
-    let mut cylinder_2_transform = None;
-
-    for (transform, name) in query.iter() {
-        match name.as_str() {
-            "cube_3" => cube_3_transform = Some(*transform),
-            "cylinder_2" => cylinder_2_transform = Some(*transform),
-            _ => {}
+/// This system applies the velocity setpoints to the motors, or releases them.
+fn apply_motor_setpoint(
+    mut commands: Commands,
+    setpoints: Res<Setpoints>,
+    mut motors: Query<(&Motor, &mut ImpulseJoint)>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "apply_motor_setpoint").entered();
+    let factor = 10000.0;
+    for (motor, mut joint) in &mut motors {
+        let released = setpoints
+            .get(&motor.release)
+            .is_some_and(|release| release != 0.0);
+        let (velocity, factor) = match setpoints.get(&motor.velocity) {
+            _ if released => (0.0, 0.0),
+            Some(velocity) => (velocity, factor),
+            None => continue,
+        };
+        match joint.data.as_mut().as_revolute_mut() {
+            Some(revolute) => {
+                revolute.set_motor_velocity(velocity, factor);
+            }
+            None => {
+                commands.send_event(ErrorEvent::from(Error::Model(
+                    "the motor joint isn't a revolute joint".to_string(),
+                )));
+            }
         }
     }
+}
 
-    if let (Some(cube_3_transform), Some(cylinder_2_transform)) =
-        (cube_3_transform, cylinder_2_transform)
-    {
+fn get_pendulum_state(
+    clock: Res<SimClock>,
+    motors: Query<&Motor>,
+    transforms: Query<&Transform>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "get_pendulum_state").entered();
+    for motor in &motors {
+        let (Ok(cube_3_transform), Ok(cylinder_2_transform)) =
+            (transforms.get(motor.pendulum), transforms.get(motor.arm))
+        else {
+            warn!(target: subsystem::PHYSICS, "cube_3 or cylinder_2 not found");
+            continue;
+        };
         // Calculate the relative rotation
         let relative_rotation = cube_3_transform.rotation * cylinder_2_transform.rotation.inverse();
 
         // The angle of the relative rotation is between 0 and 2*PI.
         let (_axis, angle) = relative_rotation.to_axis_angle();
 
-        println!("Relative angle: {:?}", angle);
-    } else {
-        println!("cube_3 or cylinder_2 not found");
+        debug!(target: subsystem::PHYSICS, "Relative angle of {}: {:?}", motor.pendulum_angle, angle);
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&motor.pendulum_angle, clock.elapsed_secs(), angle);
+        }
+    }
+}
+
+/// This system records the torque the motors applied during the last physics step.
+fn get_motor_torque(
+    clock: Res<SimClock>,
+    motors: Query<(&Motor, &RapierImpulseJointHandle)>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "get_motor_torque").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    if clock.delta().is_zero() {
+        return;
+    }
+    let dt = context.integration_parameters.dt;
+    for (motor, handle) in &motors {
+        let Some(joint) = context.impulse_joints.get(handle.0) else {
+            continue;
+        };
+        // The free axis of a revolute joint is its first angular axis.
+        let impulse = joint.data.motors[JointAxis::AngX as usize].impulse;
+        if dt > 0.0 {
+            telemetry.record(&motor.torque, clock.elapsed_secs(), impulse / dt);
+        }
+    }
+}
+
+/// This system records the angles of the motors, counting the turns.
+fn get_motor_angle(
+    clock: Res<SimClock>,
+    mut motors: Query<(Entity, &mut Motor)>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "get_motor_angle").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    for (entity, mut motor) in &mut motors {
+        let Some(angle) = context.impulse_revolute_joint_angle(entity) else {
+            continue;
+        };
+        let total = control::unwrap_angle(&mut motor.turns, angle);
+        telemetry.record(&motor.angle, clock.elapsed_secs(), total);
     }
 }
@@ -0,0 +1,160 @@
+//! A plant driven step by step from code, as the environments of reinforcement learning are:
+//! an action sets setpoints, the physics and the controllers advance by a number of steps, and
+//! the observation is the latest value of telemetry channels.
+//!
+//! The [`Environment`] wraps the headless application of the batch runs, so the controllers
+//! read their configuration files as in the application; [`Environment::reset`] builds it anew,
+//! with the seed of its random draws. The Python bindings in `python/` expose it as the
+//! `playground_core.Simulation` class, and `tools/playground/env.py` as a Gymnasium environment.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch,
+    clock::SimClock,
+    error::{Error, Result},
+    headless::DEFAULT_TIME_STEP,
+    plants::{self, Plant},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_ANGLE, PENDULUM_ANGLE},
+};
+
+/// Represents the configuration of an environment.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct EnvironmentSettings {
+    /// Name of the plant, among the built-in ones.
+    pub plant: String,
+    /// Time step of the physics, in seconds.
+    pub dt: f32,
+    /// Physics steps per action.
+    pub substeps: usize,
+    /// Setpoints set by the actions, in order.
+    pub actions: Vec<String>,
+    /// Telemetry channels or setpoints of the observations, in order.
+    pub observations: Vec<String>,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            plant: "rotary_pendulum".to_string(),
+            dt: DEFAULT_TIME_STEP,
+            substeps: 1,
+            actions: vec![MOTOR_VELOCITY.to_string()],
+            observations: vec![MOTOR_ANGLE.to_string(), PENDULUM_ANGLE.to_string()],
+        }
+    }
+}
+
+/// A plant stepped by actions.
+pub struct Environment {
+    pub settings: EnvironmentSettings,
+    plant: Plant,
+    app: App,
+}
+
+impl Environment {
+    /// The environment of the built-in plant of `settings`, reset with the seed 0.
+    pub fn new(settings: EnvironmentSettings) -> Result<Self> {
+        let plant = plants::builtin()
+            .into_iter()
+            .find(|plant| plant.name == settings.plant)
+            .ok_or_else(|| Error::Config {
+                name: "environment".to_string(),
+                message: format!("no built-in plant `{}`", settings.plant),
+            })?;
+        Self::with_plant(plant, settings)
+    }
+
+    /// The environment of `plant`, e.g. of an extension, reset with the seed 0.
+    pub fn with_plant(plant: Plant, mut settings: EnvironmentSettings) -> Result<Self> {
+        settings.plant = plant.name.to_string();
+        if settings.dt <= 0.0 || settings.substeps == 0 {
+            return Err(Error::Config {
+                name: "environment".to_string(),
+                message: "the time step and the substeps must be positive".to_string(),
+            });
+        }
+        let mut environment = Self {
+            settings,
+            plant,
+            app: App::new(),
+        };
+        environment.reset(0)?;
+        Ok(environment)
+    }
+
+    /// Builds the plant anew, with `seed` for its random draws, and returns the first
+    /// observation.
+    pub fn reset(&mut self, seed: u64) -> Result<Vec<f32>> {
+        self.app = batch::app(&self.plant, self.settings.dt, seed);
+        // Spawns the plant and records its initial state.
+        self.update()?;
+        Ok(self.observation())
+    }
+
+    /// Sets the setpoints of `action`, advances by the substeps and returns the observation.
+    pub fn step(&mut self, action: &[f32]) -> Result<Vec<f32>> {
+        if action.len() != self.settings.actions.len() {
+            return Err(Error::Config {
+                name: "environment".to_string(),
+                message: format!(
+                    "{} values in an action of {} setpoints",
+                    action.len(),
+                    self.settings.actions.len()
+                ),
+            });
+        }
+        let mut setpoints = self.app.world_mut().resource_mut::<Setpoints>();
+        for (name, value) in self.settings.actions.iter().zip(action) {
+            setpoints.set(name, *value);
+        }
+        for _ in 0..self.settings.substeps {
+            self.update()?;
+        }
+        Ok(self.observation())
+    }
+
+    /// Latest value of each observed signal, or NaN for a signal not recorded yet.
+    pub fn observation(&self) -> Vec<f32> {
+        let world = self.app.world();
+        let telemetry = world.resource::<Telemetry>();
+        let setpoints = world.resource::<Setpoints>();
+        self.settings
+            .observations
+            .iter()
+            .map(|name| {
+                telemetry
+                    .latest(name)
+                    .or_else(|| setpoints.get(name))
+                    .unwrap_or(f32::NAN)
+            })
+            .collect()
+    }
+
+    /// Simulated time since the reset, in seconds.
+    pub fn time(&self) -> f32 {
+        self.app
+            .world()
+            .get_resource::<SimClock>()
+            .map_or(0.0, SimClock::elapsed_secs)
+    }
+
+    /// The application, e.g. to read its telemetry.
+    pub fn app(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    fn update(&mut self) -> Result<()> {
+        self.app.update();
+        // A reported error stops headless applications.
+        if self.app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "environment".to_string(),
+                message: format!("{} stopped on the error logged above", self.plant.name),
+            });
+        }
+        Ok(())
+    }
+}
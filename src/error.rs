@@ -0,0 +1,191 @@
+//! Errors of the playground and how they reach the user.
+//!
+//! Fallible operations return an [`Error`]. Systems that can't propagate it send an
+//! [`ErrorEvent`] instead: the [`ErrorPlugin`] logs it and shows it in a modal dialog, or, in
+//! headless applications, exits with a non-zero code.
+use std::{collections::VecDeque, io, path::PathBuf, sync::Arc};
+
+use bevy::{asset::AssetLoadFailedEvent, prelude::*};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use thiserror::Error;
+
+use crate::logging::subsystem;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to access {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid configuration `{name}`: {message}")]
+    Config { name: String, message: String },
+    #[error("failed to load asset `{path}`: {message}")]
+    Asset { path: String, message: String },
+    #[error("invalid model: {0}")]
+    Model(String),
+    #[error("identification failed: {0}")]
+    Identification(String),
+    #[error("script {} failed: {message}", path.display())]
+    Script { path: PathBuf, message: String },
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// A configuration that couldn't be saved.
+    pub fn save(name: &str, error: impl std::fmt::Display) -> Self {
+        Error::Config {
+            name: name.to_string(),
+            message: format!("failed to save: {error}"),
+        }
+    }
+
+    /// Exit code of a headless application stopped by this error, following `sysexits.h`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Model(_) | Error::Identification(_) | Error::Script { .. } => 65,
+            Error::Asset { .. } => 66,
+            Error::Io { .. } => 74,
+            Error::Config { .. } => 78,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Error::Io { .. } => "File error",
+            Error::Config { .. } => "Configuration error",
+            Error::Asset { .. } => "Asset error",
+            Error::Model(_) => "Model error",
+            Error::Identification(_) => "Identification error",
+            Error::Script { .. } => "Script error",
+        }
+    }
+
+    fn log(&self) {
+        match self {
+            Error::Model(_) => error!(target: subsystem::PHYSICS, "{self}"),
+            Error::Identification(_) | Error::Script { .. } => {
+                error!(target: subsystem::CONTROL, "{self}")
+            }
+            _ => error!(target: subsystem::IO, "{self}"),
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Reports an error to the user.
+#[derive(Clone, Debug, Event)]
+pub struct ErrorEvent(pub Arc<Error>);
+
+impl From<Error> for ErrorEvent {
+    fn from(error: Error) -> Self {
+        ErrorEvent(Arc::new(error))
+    }
+}
+
+#[derive(Default)]
+pub struct ErrorPlugin {
+    /// Exit the application on the first error instead of showing it, for headless runs.
+    pub exit_on_error: bool,
+}
+
+impl Plugin for ErrorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ErrorEvent>()
+            .add_systems(PostUpdate, report_failed_images);
+        if self.exit_on_error {
+            app.add_systems(Last, exit_on_error);
+        } else {
+            app.init_resource::<ErrorDialog>()
+                .add_systems(Last, queue_errors)
+                .add_systems(Update, error_dialog);
+        }
+    }
+}
+
+/// Errors waiting to be acknowledged by the user.
+#[derive(Default, Resource)]
+pub struct ErrorDialog {
+    pub pending: VecDeque<Arc<Error>>,
+}
+
+fn report_failed_images(
+    mut failures: EventReader<AssetLoadFailedEvent<Image>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for failure in failures.read() {
+        errors.send(
+            Error::Asset {
+                path: failure.path.to_string(),
+                message: failure.error.to_string(),
+            }
+            .into(),
+        );
+    }
+}
+
+fn queue_errors(mut events: EventReader<ErrorEvent>, mut dialog: ResMut<ErrorDialog>) {
+    for ErrorEvent(error) in events.read() {
+        error.log();
+        dialog.pending.push_back(error.clone());
+    }
+}
+
+fn exit_on_error(mut events: EventReader<ErrorEvent>, mut exit: EventWriter<AppExit>) {
+    let mut code = None;
+    for ErrorEvent(error) in events.read() {
+        error.log();
+        code.get_or_insert(error.exit_code());
+    }
+    if let Some(code) = code {
+        exit.send(AppExit::from_code(code));
+    }
+}
+
+/// Modal dialog showing the oldest error not acknowledged yet.
+fn error_dialog(mut contexts: EguiContexts, mut dialog: ResMut<ErrorDialog>) {
+    let Some(error) = dialog.pending.front() else {
+        return;
+    };
+    let ctx = contexts.ctx_mut();
+    // Dim the rest of the interface while the dialog is open.
+    egui::Area::new(egui::Id::new("error_dialog_backdrop"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(egui::Pos2::ZERO)
+        .show(ctx, |ui| {
+            let screen = ui.ctx().screen_rect();
+            ui.allocate_rect(screen, egui::Sense::click());
+            ui.painter()
+                .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(160));
+        });
+    let mut acknowledged = false;
+    egui::Window::new(error.title())
+        .id(egui::Id::new("error_dialog"))
+        .order(egui::Order::Tooltip)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(error.to_string());
+            if dialog.pending.len() > 1 {
+                ui.weak(format!("{} more errors", dialog.pending.len() - 1));
+            }
+            let ok = ui.button("OK");
+            // The dialog is modal: the keyboard comes back to it, so Enter acknowledges.
+            if ui.memory(|memory| memory.focused().is_none()) {
+                ok.request_focus();
+            }
+            acknowledged = ok.clicked();
+        });
+    if acknowledged {
+        dialog.pending.pop_front();
+    }
+}
@@ -0,0 +1,607 @@
+//! State estimation: an extended Kalman filter ([`Ekf`]) fuses the noisy readings of the
+//! sensors of the rotary pendulum into an estimate of its state `[arm angle, arm velocity,
+//! pendulum angle, pendulum velocity]`, and the controllers read the estimated angles instead
+//! of the readings, through [`joint_angle`].
+//!
+//! The filter predicts the state with the nonlinear model of the pendulum, whose coefficients
+//! are those of the model linearized upright in the LQR settings, driven by the acceleration
+//! of the arm: the motor is a stiff velocity servo, reaching the velocity commanded over a
+//! step. It then corrects the prediction, at every step, with the angles of the joints, read
+//! by their encoders or exactly without one, and with the gyroscopes of the configured IMUs,
+//! along the axes measuring the velocity of a joint. The readings are held between the samples
+//! of the sensors. The accelerometers aren't fused.
+//!
+//! The estimated and the exact angles and velocities of the joints are recorded as the
+//! `estimate/<link>/{angle,velocity}` and `estimate/<link>/{true_angle,true_velocity}`
+//! telemetry channels, to plot against each other. The estimation is configured in
+//! `estimation.json`.
+use std::f64::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use nalgebra::{Matrix4, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    latency::LoopLatency,
+    logging::subsystem,
+    lqr::LqrSettings,
+    plants::{self, Link},
+    plots::PlotSettings,
+    sensors::{self, Encoder, Imu, SensorSet},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the estimates.
+pub const ESTIMATE_PREFIX: &str = "estimate/";
+/// Variance of the exact angles, in rad², keeping the filter well conditioned.
+const EXACT_VARIANCE: f64 = 1e-8;
+
+pub struct EstimationPlugin;
+
+impl Plugin for EstimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(PostUpdate, EstimationSet.after(SensorSet))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_estimators.run_if(resource_exists::<Persistent<EstimationSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                estimate
+                    .run_if(resource_exists::<Persistent<EstimationSettings>>)
+                    .in_set(EstimationSet),
+            )
+            .add_systems(
+                Update,
+                estimation_panel
+                    .run_if(resource_exists::<Persistent<EstimationSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// The estimation of the state after each physics step; the controllers run after it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+pub struct EstimationSet;
+
+/// The axis of the gyroscope of an IMU measuring the velocity of a joint.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GyroscopeFusion {
+    /// Name of the link carrying the IMU, in each plant.
+    pub imu: String,
+    /// Axis of the gyroscope along the velocity of the joint, in the frame of the IMU.
+    pub axis: Vec3,
+    /// Name of the link whose joint is measured: the motor or the pendulum.
+    pub joint: String,
+}
+
+/// Represents the configuration of the estimation.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct EstimationSettings {
+    pub enabled: bool,
+    /// Names of the links whose joints are the motor and the pivot of the pendulum, in each
+    /// plant.
+    pub motor: String,
+    pub pendulum: String,
+    /// Standard deviations of the accelerations of the arm and of the pendulum the model
+    /// misses, in rad/s².
+    pub arm_noise: f32,
+    pub pendulum_noise: f32,
+    pub gyroscopes: Vec<GyroscopeFusion>,
+}
+
+impl Default for EstimationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            motor: "motor".to_string(),
+            pendulum: "pivot".to_string(),
+            arm_noise: 20.0,
+            pendulum_noise: 2.0,
+            gyroscopes: Vec::new(),
+        }
+    }
+}
+
+/// Model of the pendulum: its angular acceleration from upright is
+/// `gravity · sin(angle) + coupling · cos(angle) · arm acceleration`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PendulumModel {
+    pub gravity: f64,
+    pub coupling: f64,
+}
+
+impl PendulumModel {
+    /// The model whose linearization upright is that of `settings`.
+    pub fn of(settings: &LqrSettings) -> Self {
+        Self {
+            gravity: settings.a[3][2],
+            coupling: settings.b[3],
+        }
+    }
+}
+
+/// Extended Kalman filter of the state of a rotary pendulum, `[arm angle, arm velocity,
+/// pendulum angle, pendulum velocity]`, the angles being those of the joints, within a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ekf {
+    pub state: Vector4<f64>,
+    pub covariance: Matrix4<f64>,
+}
+
+impl Ekf {
+    /// A filter starting from `state`, uncertain by a tenth of a radian and a radian per
+    /// second.
+    pub fn new(state: Vector4<f64>) -> Self {
+        Self {
+            state,
+            covariance: Matrix4::from_diagonal(&Vector4::new(0.01, 1.0, 0.01, 1.0)),
+        }
+    }
+
+    /// Predicts the state `dt` seconds later, the motor driven at the velocity `command`
+    /// (rad/s) unless it's released, the model missing accelerations of the arm and of the
+    /// pendulum of the standard deviations `noise` (rad/s²).
+    pub fn predict(
+        &mut self,
+        model: &PendulumModel,
+        command: Option<f64>,
+        noise: [f64; 2],
+        dt: f64,
+    ) {
+        let [arm, velocity, angle, rate] = [0, 1, 2, 3].map(|index| self.state[index]);
+        // Derivatives of the velocity of the arm and of its acceleration on its velocity.
+        let (next_velocity, held, pull) = match command {
+            Some(command) => (command, 0.0, -1.0 / dt),
+            None => (velocity, 1.0, 0.0),
+        };
+        let acceleration = (next_velocity - velocity) / dt;
+        let upright = angle - PI;
+        let (sin, cos) = upright.sin_cos();
+        let next_rate = rate + dt * (model.gravity * sin + model.coupling * cos * acceleration);
+        self.state = Vector4::new(
+            arm + dt * next_velocity,
+            next_velocity,
+            angle + dt * next_rate,
+            next_rate,
+        );
+        wrap_angles(&mut self.state);
+
+        let rate_row = [
+            0.0,
+            dt * model.coupling * cos * pull,
+            dt * (model.gravity * cos - model.coupling * sin * acceleration),
+            1.0,
+        ];
+        #[rustfmt::skip]
+        let jacobian = Matrix4::new(
+            1.0, dt * held, 0.0, 0.0,
+            0.0, held, 0.0, 0.0,
+            0.0, dt * rate_row[1], 1.0 + dt * rate_row[2], dt,
+            rate_row[0], rate_row[1], rate_row[2], rate_row[3],
+        );
+        self.covariance =
+            jacobian * self.covariance * jacobian.transpose() + process_noise(noise, dt);
+    }
+
+    /// Corrects the state with a measurement `value` of its `index`th component, of
+    /// `variance`. The angles are measured within a turn.
+    pub fn correct(&mut self, index: usize, value: f64, variance: f64) {
+        let mut innovation = value - self.state[index];
+        if index % 2 == 0 {
+            innovation = wrap(innovation);
+        }
+        let spread = self.covariance[(index, index)] + variance;
+        if spread <= 0.0 {
+            return;
+        }
+        let gain = self.covariance.column(index) / spread;
+        self.state += gain * innovation;
+        wrap_angles(&mut self.state);
+        self.covariance -= gain * self.covariance.row(index);
+        self.covariance = (self.covariance + self.covariance.transpose()) / 2.0;
+    }
+}
+
+/// Covariance of the state added over a step of `dt` seconds by white accelerations of the arm
+/// and of the pendulum of the standard deviations `noise` (rad/s²).
+pub fn process_noise(noise: [f64; 2], dt: f64) -> Matrix4<f64> {
+    let mut process = Matrix4::zeros();
+    for (joint, deviation) in noise.into_iter().enumerate() {
+        let variance = deviation * deviation;
+        let [angle, velocity] = [2 * joint, 2 * joint + 1];
+        process[(angle, angle)] = variance * dt.powi(4) / 4.0;
+        process[(angle, velocity)] = variance * dt.powi(3) / 2.0;
+        process[(velocity, angle)] = variance * dt.powi(3) / 2.0;
+        process[(velocity, velocity)] = variance * dt * dt;
+    }
+    process
+}
+
+/// Angle within `(-π, π]`.
+pub(crate) fn wrap(angle: f64) -> f64 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+fn wrap_angles(state: &mut Vector4<f64>) {
+    state[0] = wrap(state[0]);
+    state[2] = wrap(state[2]);
+}
+
+/// The estimated angle and velocity of the revolute joint of its entity.
+#[derive(Clone, Component, Copy, Debug, Default, PartialEq)]
+pub struct JointEstimate {
+    /// Angle of the joint, within a turn, in rad.
+    pub angle: f32,
+    /// Velocity of the joint, in rad/s.
+    pub velocity: f32,
+}
+
+/// Angle of the revolute joint of `entity` as the controllers see it: its estimate, if the
+/// state is estimated, or the angle [read](sensors::joint_angle) through the sensors.
+pub fn joint_angle(
+    context: &RapierContext,
+    encoders: &Query<&Encoder>,
+    latencies: &Query<&LoopLatency>,
+    estimates: &Query<&JointEstimate>,
+    entity: Entity,
+) -> Option<f32> {
+    match estimates.get(entity) {
+        Ok(estimate) => Some(estimate.angle),
+        Err(_) => sensors::joint_angle(context, encoders, latencies, entity),
+    }
+}
+
+/// The filter of the state of the plant whose motor joint is its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Estimator {
+    pub pendulum: Entity,
+    /// Prefixes of the telemetry channels of the motor and of the pendulum.
+    pub channels: [String; 2],
+    filter: Option<Ekf>,
+    /// Time and exact angles of the joints at the last step.
+    last: Option<(f32, [f32; 2])>,
+}
+
+impl Estimator {
+    pub fn new(pendulum: Entity, channels: [String; 2]) -> Self {
+        Self {
+            pendulum,
+            channels,
+            filter: None,
+            last: None,
+        }
+    }
+
+    /// The filter, once started.
+    pub fn filter(&self) -> Option<&Ekf> {
+        self.filter.as_ref()
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<EstimationSettings>("estimation", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the estimators on the motor joints of the plants with a pendulum joint.
+fn configure_estimators(
+    mut commands: Commands,
+    settings: Res<Persistent<EstimationSettings>>,
+    joints: Query<(Entity, &Link, Option<&Estimator>), With<ImpulseJoint>>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, estimator) in &joints {
+        let pendulum = joints
+            .iter()
+            .find(|(_, other, _)| other.plant == link.plant && other.name == settings.pendulum)
+            .map(|(pendulum, _, _)| pendulum);
+        let estimated = settings.enabled && link.name == settings.motor;
+        match (estimator, pendulum) {
+            (Some(_), _) if estimated => {}
+            (Some(estimator), _) => {
+                commands
+                    .entity(estimator.pendulum)
+                    .remove::<JointEstimate>();
+                commands
+                    .entity(entity)
+                    .remove::<(Estimator, JointEstimate)>();
+                info!(target: subsystem::CONTROL, "Estimator of {} removed", link.path());
+            }
+            (None, Some(pendulum)) if estimated => {
+                let channel = |name: &str| {
+                    plants::namespaced(&link.plant, &format!("{ESTIMATE_PREFIX}{name}"))
+                };
+                commands.entity(entity).insert(Estimator::new(
+                    pendulum,
+                    [channel(&settings.motor), channel(&settings.pendulum)],
+                ));
+                info!(target: subsystem::CONTROL, "Estimator of {} added", link.path());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Variance of the readings of `encoder`, in rad², or of an exact angle without one.
+pub fn encoder_variance(encoder: Option<&Encoder>) -> f64 {
+    encoder.map_or(EXACT_VARIANCE, |encoder| {
+        let noise = f64::from(encoder.settings.noise);
+        let resolution = f64::from(encoder.resolution());
+        (noise * noise + resolution * resolution / 12.0).max(EXACT_VARIANCE)
+    })
+}
+
+/// Estimates the state of the plants after each physics step, for the controllers.
+#[allow(clippy::too_many_arguments)]
+fn estimate(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<EstimationSettings>>,
+    lqr: Option<Res<Persistent<LqrSettings>>>,
+    mut estimators: Query<(Entity, &Link, &mut Estimator, &ImpulseJoint)>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    imus: Query<(&Link, &Imu)>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    let model = PendulumModel::of(
+        lqr.as_deref()
+            .map_or(&LqrSettings::default(), |lqr| lqr.get()),
+    );
+    let time = clock.elapsed_secs();
+    for (entity, link, mut estimator, joint) in &mut estimators {
+        let pendulum = estimator.pendulum;
+        let readings = [entity, pendulum]
+            .map(|joint| sensors::joint_angle(context, &encoders, &latencies, joint));
+        let [Some(motor), Some(pendulum_angle)] = readings else {
+            continue;
+        };
+        let variances = [entity, pendulum].map(|joint| encoder_variance(encoders.get(joint).ok()));
+
+        let motor_joint = joint.data.as_ref().motor(JointAxis::AngX).copied();
+        let command = motor_joint
+            .filter(|motor| motor.damping > 0.0)
+            .map(|motor| f64::from(motor.target_vel));
+        let estimator = &mut *estimator;
+        // Rewound: start over.
+        if estimator.last.is_some_and(|(last, _)| time < last) {
+            estimator.filter = None;
+        }
+        if let Some(filter) = estimator.filter.as_mut() {
+            filter.predict(
+                &model,
+                command,
+                [settings.arm_noise, settings.pendulum_noise].map(f64::from),
+                f64::from(dt),
+            );
+        }
+        let filter = estimator.filter.get_or_insert_with(|| {
+            Ekf::new(Vector4::new(
+                f64::from(motor),
+                0.0,
+                f64::from(pendulum_angle),
+                0.0,
+            ))
+        });
+        filter.correct(0, f64::from(motor), variances[0]);
+        filter.correct(2, f64::from(pendulum_angle), variances[1]);
+        for fusion in &settings.gyroscopes {
+            let index = if fusion.joint == settings.motor {
+                1
+            } else if fusion.joint == settings.pendulum {
+                3
+            } else {
+                continue;
+            };
+            let Some((imu, reading)) = imus
+                .iter()
+                .find(|(imu, _)| imu.plant == link.plant && imu.name == fusion.imu)
+                .and_then(|(_, imu)| Some((imu, imu.reading()?)))
+            else {
+                continue;
+            };
+            let axis = fusion.axis.normalize_or_zero();
+            let rate = match imu.settings.rate {
+                rate if rate > 0.0 => rate,
+                _ => 1.0 / dt,
+            };
+            let density = f64::from(imu.settings.gyroscope_noise_density);
+            filter.correct(
+                index,
+                f64::from(axis.dot(reading.angular_velocity)),
+                (density * density * f64::from(rate)).max(EXACT_VARIANCE),
+            );
+        }
+
+        let state = filter.state.map(|value| value as f32);
+        let estimates = [
+            JointEstimate {
+                angle: state[0],
+                velocity: state[1],
+            },
+            JointEstimate {
+                angle: state[2],
+                velocity: state[3],
+            },
+        ];
+        commands.entity(entity).insert(estimates[0]);
+        commands.entity(pendulum).insert(estimates[1]);
+
+        let exact = [entity, pendulum].map(|joint| context.impulse_revolute_joint_angle(joint));
+        let exact = match exact {
+            [Some(motor), Some(pendulum)] => Some([motor, pendulum]),
+            _ => None,
+        };
+        let last = estimator.last.filter(|(last, _)| time > *last);
+        estimator.last = exact.map(|exact| (time, exact));
+        let Some(telemetry) = telemetry.as_mut() else {
+            continue;
+        };
+        for (index, estimate) in estimates.iter().enumerate() {
+            let channel = &estimator.channels[index];
+            telemetry.record(&format!("{channel}/angle"), time, estimate.angle);
+            telemetry.record(&format!("{channel}/velocity"), time, estimate.velocity);
+            let Some(exact) = exact else {
+                continue;
+            };
+            telemetry.record(&format!("{channel}/true_angle"), time, exact[index]);
+            if let Some((last, previous)) = last {
+                let turned = wrap(f64::from(exact[index] - previous[index])) as f32;
+                telemetry.record(
+                    &format!("{channel}/true_velocity"),
+                    time,
+                    turned / (time - last),
+                );
+            }
+        }
+    }
+}
+
+/// Panel to enable the estimation, tune its noises and plot the estimates.
+fn estimation_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<EstimationSettings>>,
+    plots: Option<ResMut<Persistent<PlotSettings>>>,
+    estimators: Query<(&Link, &Estimator)>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Estimation")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Estimate the state")
+                .on_hover_text("The controllers read the estimated angles");
+            ui.horizontal(|ui| {
+                ui.label("Motor");
+                ui.text_edit_singleline(&mut edited.motor);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Pendulum");
+                ui.text_edit_singleline(&mut edited.pendulum);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.arm_noise)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Arm noise: ")
+                    .suffix(" rad/s²"),
+            )
+            .on_hover_text("Acceleration of the arm the model misses");
+            ui.add(
+                egui::DragValue::new(&mut edited.pendulum_noise)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Pendulum noise: ")
+                    .suffix(" rad/s²"),
+            )
+            .on_hover_text("Acceleration of the pendulum the model misses");
+
+            ui.separator();
+            ui.label("Gyroscopes");
+            let mut removed = None;
+            for (index, fusion) in edited.gyroscopes.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("IMU on");
+                    ui.add(egui::TextEdit::singleline(&mut fusion.imu).desired_width(60.0));
+                    ui.label("axis");
+                    for value in [&mut fusion.axis.x, &mut fusion.axis.y, &mut fusion.axis.z] {
+                        ui.add(egui::DragValue::new(value).range(-1.0..=1.0).speed(0.01));
+                    }
+                    ui.label("measures");
+                    ui.add(egui::TextEdit::singleline(&mut fusion.joint).desired_width(60.0));
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.gyroscopes.remove(index);
+            }
+            if ui.button("Add a gyroscope").clicked() {
+                edited.gyroscopes.push(GyroscopeFusion {
+                    imu: "arm".to_string(),
+                    axis: Vec3::Y,
+                    joint: edited.motor.clone(),
+                });
+            }
+
+            ui.separator();
+            for (link, estimator) in &estimators {
+                let Some(filter) = estimator.filter() else {
+                    continue;
+                };
+                let deviations = filter.covariance.diagonal().map(f64::sqrt);
+                ui.label(format!(
+                    "{}: ±{:.4} rad, ±{:.3} rad/s; pendulum ±{:.4} rad, ±{:.3} rad/s",
+                    link.path(),
+                    deviations[0],
+                    deviations[1],
+                    deviations[2],
+                    deviations[3]
+                ));
+            }
+            if let Some(mut plots) = plots {
+                if ui
+                    .button("Plot against the true state")
+                    .on_hover_text("Adds the estimated and true angles to the plots")
+                    .clicked()
+                {
+                    let mut series = plots.series.clone();
+                    for (_, estimator) in &estimators {
+                        for channel in &estimator.channels {
+                            for name in ["angle", "true_angle"] {
+                                let name = format!("{channel}/{name}");
+                                if !series.contains(&name) {
+                                    series.push(name);
+                                }
+                            }
+                        }
+                    }
+                    plots.get_mut().series = series;
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("estimation", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("estimation", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
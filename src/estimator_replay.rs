@@ -0,0 +1,428 @@
+//! Offline development of the state estimation: the measurements of a recorded run are replayed
+//! into the [extended Kalman filter](crate::estimation::Ekf) alone, without the physics, so the
+//! filter is retuned against the very same data in a fraction of the time of a run.
+//!
+//! [`replay`] steps the filter at the samples of the reading of the motor: it predicts with the
+//! velocity commanded to the motor at the previous step, if the run recorded it, the arm
+//! released otherwise, then corrects with the readings of both joints, held between their
+//! samples. The noises of the filter are those of `estimation.json` and its model that of the
+//! LQR settings, so the run replays again as they're tuned in the *Estimation* and *LQR*
+//! panels. The gyroscopes aren't replayed.
+//!
+//! The estimates are compared with the exact angles of the run, recorded by the estimation as
+//! `estimate/<link>/true_angle`, and with the velocities differentiated from them. The RMS
+//! errors are printed with `--replay-estimator`, and the *Estimator replay* panel plots the
+//! estimate of a state against the truth along with its error. The channels are read from
+//! `estimator_replay.json`.
+use std::{fmt, path::PathBuf};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use nalgebra::Vector4;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    estimation::{self, Ekf, EstimationSettings, PendulumModel, ESTIMATE_PREFIX},
+    logging::subsystem,
+    lqr::LqrSettings,
+    run_diff,
+    sensors::ENCODER_PREFIX,
+    telemetry::{Sample, Telemetry},
+    theme::{to_egui, Theme},
+};
+
+/// Names and units of the components of the state.
+pub const STATES: [(&str, &str); 4] = [
+    ("arm angle", "rad"),
+    ("arm velocity", "rad/s"),
+    ("pendulum angle", "rad"),
+    ("pendulum velocity", "rad/s"),
+];
+
+pub struct EstimatorReplayPlugin;
+
+impl Plugin for EstimatorReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EstimatorReplayView>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                estimator_replay_panel
+                    .run_if(resource_exists::<Persistent<EstimatorReplaySettings>>)
+                    .run_if(resource_exists::<Persistent<EstimationSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the channels the measurements are replayed from, from the
+/// `estimator_replay.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct EstimatorReplaySettings {
+    /// Run replayed in the panel, the last archived session without one.
+    pub run: Option<PathBuf>,
+    /// Channels of the readings of the motor and of the pendulum joints, in rad.
+    pub motor: String,
+    pub pendulum: String,
+    /// Channel of the velocity commanded to the motor, in rad/s, or none for a released arm.
+    pub command: Option<String>,
+    /// Channels of the exact angles of the motor and of the pendulum joints, in rad.
+    pub true_motor: String,
+    pub true_pendulum: String,
+    /// Standard deviation of the readings, in rad.
+    pub reading_noise: f32,
+}
+
+impl Default for EstimatorReplaySettings {
+    fn default() -> Self {
+        let estimation = EstimationSettings::default();
+        Self {
+            run: None,
+            motor: format!("{ENCODER_PREFIX}{}", estimation.motor),
+            pendulum: format!("{ENCODER_PREFIX}{}", estimation.pendulum),
+            command: None,
+            true_motor: format!("{ESTIMATE_PREFIX}{}/true_angle", estimation.motor),
+            true_pendulum: format!("{ESTIMATE_PREFIX}{}/true_angle", estimation.pendulum),
+            // The quantization of an encoder of 4096 counts per revolution.
+            reading_noise: 4.4e-4,
+        }
+    }
+}
+
+/// A step of the replayed filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayedStep {
+    pub time: f32,
+    /// Estimated state, `[arm angle, arm velocity, pendulum angle, pendulum velocity]`.
+    pub estimate: [f32; 4],
+    /// Exact state, where the run has it.
+    pub truth: [Option<f32>; 4],
+}
+
+impl ReplayedStep {
+    /// Errors of the estimate, the angles within a turn.
+    pub fn errors(&self) -> [Option<f32>; 4] {
+        [0, 1, 2, 3].map(|index| {
+            let error = self.estimate[index] - self.truth[index]?;
+            Some(if index % 2 == 0 {
+                estimation::wrap(f64::from(error)) as f32
+            } else {
+                error
+            })
+        })
+    }
+}
+
+/// The filter replayed over a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EstimatorReplay {
+    pub steps: Vec<ReplayedStep>,
+}
+
+impl EstimatorReplay {
+    /// RMS errors of the components of the state, over the steps where they're known.
+    pub fn rms(&self) -> [Option<f32>; 4] {
+        [0, 1, 2, 3].map(|index| {
+            let errors: Vec<f32> = self
+                .steps
+                .iter()
+                .filter_map(|step| step.errors()[index])
+                .collect();
+            (!errors.is_empty()).then(|| {
+                (errors.iter().map(|error| error * error).sum::<f32>() / errors.len() as f32).sqrt()
+            })
+        })
+    }
+}
+
+impl fmt::Display for EstimatorReplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = match (self.steps.first(), self.steps.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        };
+        writeln!(
+            f,
+            "{} steps replayed over {duration:.3} s, RMS errors:",
+            self.steps.len()
+        )?;
+        for ((name, unit), rms) in STATES.iter().zip(self.rms()) {
+            match rms {
+                Some(rms) => writeln!(f, "  {name}: {rms:.6} {unit}")?,
+                None => writeln!(f, "  {name}: no truth in the run")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Value of the last sample of `samples` at or before `time`.
+fn held(samples: &[Sample], time: f32) -> Option<f32> {
+    let next = samples.partition_point(|[sample, _]| *sample <= time);
+    next.checked_sub(1).map(|index| samples[index][1])
+}
+
+/// Replays the readings of `run` into the filter tuned by `estimation`, predicting with `model`.
+pub fn replay(
+    run: &Telemetry,
+    settings: &EstimatorReplaySettings,
+    estimation: &EstimationSettings,
+    model: &PendulumModel,
+) -> Result<EstimatorReplay> {
+    let channel = |name: &str| run.channels.get(name).map_or(&[][..], Vec::as_slice);
+    let (motor, pendulum) = (channel(&settings.motor), channel(&settings.pendulum));
+    if motor.is_empty() || pendulum.is_empty() {
+        return Err(Error::Config {
+            name: "estimator_replay".to_string(),
+            message: format!(
+                "the run has no readings of `{}` and `{}`",
+                settings.motor, settings.pendulum
+            ),
+        });
+    }
+    let command = settings.command.as_deref().map(channel);
+    let truth = [
+        channel(&settings.true_motor),
+        channel(&settings.true_pendulum),
+    ];
+    let noise = [estimation.arm_noise, estimation.pendulum_noise].map(f64::from);
+    let variance = f64::from(settings.reading_noise).powi(2).max(1e-8);
+
+    let mut filter: Option<Ekf> = None;
+    // Time and exact angles of the last step.
+    let mut last: Option<(f32, [Option<f32>; 2])> = None;
+    let mut steps = Vec::with_capacity(motor.len());
+    for &[time, motor_reading] in motor {
+        let Some(pendulum_reading) = held(pendulum, time) else {
+            continue;
+        };
+        if last.is_some_and(|(previous, _)| time <= previous) {
+            continue;
+        }
+        if let (Some(filter), Some((previous, _))) = (filter.as_mut(), last) {
+            let command = command
+                .and_then(|samples| held(samples, previous))
+                .map(f64::from);
+            filter.predict(model, command, noise, f64::from(time - previous));
+        }
+        let readings = [motor_reading, pendulum_reading].map(f64::from);
+        let filter = filter
+            .get_or_insert_with(|| Ekf::new(Vector4::new(readings[0], 0.0, readings[1], 0.0)));
+        filter.correct(0, readings[0], variance);
+        filter.correct(2, readings[1], variance);
+
+        let angles = truth.map(|samples| held(samples, time));
+        let velocities = [0, 1].map(|joint| {
+            let (previous, before) = last?;
+            let turned = estimation::wrap(f64::from(angles[joint]? - before[joint]?)) as f32;
+            Some(turned / (time - previous))
+        });
+        let state = filter.state.map(|value| value as f32);
+        steps.push(ReplayedStep {
+            time,
+            estimate: [state[0], state[1], state[2], state[3]],
+            truth: [angles[0], velocities[0], angles[1], velocities[1]],
+        });
+        last = Some((time, angles));
+    }
+    Ok(EstimatorReplay { steps })
+}
+
+/// Path of the run of `settings`, the last archived session without one.
+pub fn run_path(settings: &EstimatorReplaySettings) -> Result<PathBuf> {
+    settings
+        .run
+        .clone()
+        .or_else(|| run_diff::archived_sessions().into_iter().next())
+        .ok_or_else(|| Error::Config {
+            name: "estimator_replay".to_string(),
+            message: "no run to replay: set `run` or archive a session".to_string(),
+        })
+}
+
+/// Reads the run of `settings` and replays it into the filter tuned by `estimation`, with the
+/// model linearized in `lqr`.
+pub fn replay_run(
+    settings: &EstimatorReplaySettings,
+    estimation: &EstimationSettings,
+    lqr: &LqrSettings,
+) -> Result<EstimatorReplay> {
+    let run = run_diff::read_run(&run_path(settings)?)?;
+    replay(&run, settings, estimation, &PendulumModel::of(lqr))
+}
+
+/// State of the estimator replay panel.
+#[derive(Default, Resource)]
+struct EstimatorReplayView {
+    /// The run read, replayed again as the tuning changes.
+    run: Option<Telemetry>,
+    replay: Option<EstimatorReplay>,
+    /// Component of the state plotted.
+    selected: usize,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<EstimatorReplaySettings>("estimator_replay", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Panel to replay a run into the filter and plot the estimates against the truth.
+fn estimator_replay_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<EstimatorReplaySettings>>,
+    estimation: Res<Persistent<EstimationSettings>>,
+    lqr: Option<Res<Persistent<LqrSettings>>>,
+    theme: Option<Res<Persistent<Theme>>>,
+    mut view: ResMut<EstimatorReplayView>,
+) {
+    let mut edited = settings.get().clone();
+    let view = &mut *view;
+    let model = PendulumModel::of(
+        lqr.as_deref()
+            .map_or(&LqrSettings::default(), |lqr| lqr.get()),
+    );
+    let retuned = estimation.is_changed()
+        || settings.is_changed()
+        || lqr.as_ref().is_some_and(|lqr| lqr.is_changed());
+    egui::Window::new("Estimator replay")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut run = edited
+                .run
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            egui::Grid::new("estimator_replay_channels").show(ui, |ui| {
+                ui.label("Run");
+                ui.text_edit_singleline(&mut run).on_hover_text(
+                    "Session directory or telemetry file, the last session if empty",
+                );
+                ui.end_row();
+                for (label, channel) in [
+                    ("Motor reading", &mut edited.motor),
+                    ("Pendulum reading", &mut edited.pendulum),
+                    ("Exact motor angle", &mut edited.true_motor),
+                    ("Exact pendulum angle", &mut edited.true_pendulum),
+                ] {
+                    ui.label(label);
+                    ui.text_edit_singleline(channel);
+                    ui.end_row();
+                }
+                let mut command = edited.command.clone().unwrap_or_default();
+                ui.label("Velocity command");
+                ui.text_edit_singleline(&mut command)
+                    .on_hover_text("Channel of the velocity commanded, empty for a released arm");
+                ui.end_row();
+                edited.command = (!command.is_empty()).then_some(command);
+            });
+            edited.run = (!run.is_empty()).then(|| PathBuf::from(run));
+            ui.add(
+                egui::DragValue::new(&mut edited.reading_noise)
+                    .range(0.0..=1.0)
+                    .speed(1e-5)
+                    .prefix("Reading noise: ")
+                    .suffix(" rad"),
+            );
+
+            ui.horizontal(|ui| {
+                if ui.button("Replay").clicked() {
+                    view.replay = None;
+                    match run_path(&edited).and_then(|path| run_diff::read_run(&path)) {
+                        Ok(run) => view.run = Some(run),
+                        Err(error) => {
+                            view.run = None;
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("estimator_replay", error)));
+                    }
+                }
+            });
+            if let Some(run) = &view.run {
+                // Replays on reading the run, then as the filter is retuned.
+                if view.replay.is_none() || retuned {
+                    match replay(run, &edited, &estimation, &model) {
+                        Ok(replay) => {
+                            debug!(
+                                target: subsystem::CONTROL,
+                                "Replayed {} steps into the filter",
+                                replay.steps.len()
+                            );
+                            view.replay = Some(replay);
+                        }
+                        Err(error) => {
+                            view.run = None;
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+            }
+
+            let Some(replay) = &view.replay else {
+                return;
+            };
+            ui.separator();
+            for (index, ((name, unit), rms)) in STATES.iter().zip(replay.rms()).enumerate() {
+                let label = match rms {
+                    Some(rms) => format!("{name}: RMS error {rms:.2e} {unit}"),
+                    None => format!("{name}: no truth in the run"),
+                };
+                ui.radio_value(&mut view.selected, index, label);
+            }
+            let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+            plot_state(ui, replay, view.selected, &theme);
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Plots the estimate of a component of the state, the truth and the error over the run.
+fn plot_state(ui: &mut egui::Ui, replay: &EstimatorReplay, index: usize, theme: &Theme) {
+    let series = |value: &dyn Fn(&ReplayedStep) -> Option<f32>| -> Vec<[f64; 2]> {
+        replay
+            .steps
+            .iter()
+            .filter_map(|step| Some([f64::from(step.time), f64::from(value(step)?)]))
+            .collect()
+    };
+    let estimate = series(&|step| Some(step.estimate[index]));
+    let truth = series(&|step| step.truth[index]);
+    let error = series(&|step| step.errors()[index]);
+    let (name, _) = STATES[index];
+    Plot::new("estimator_replay")
+        .legend(Legend::default())
+        .height(180.0)
+        .x_axis_label("Time (s)")
+        .show(ui, |plot_ui| {
+            for (series, (points, label)) in [
+                (estimate, format!("estimated {name}")),
+                (truth, format!("exact {name}")),
+                (error, "error".to_string()),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let line = Line::new(PlotPoints::new(points))
+                    .name(label)
+                    .color(to_egui(theme.series(series)));
+                plot_ui.line(line);
+            }
+        });
+}
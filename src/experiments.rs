@@ -0,0 +1,550 @@
+//! A queue of experiments run in the background while the interactive session goes on, for long
+//! campaigns of [batch runs](crate::batch) and [sweeps](crate::sweep).
+//!
+//! The experiments queued in the *Experiments* panel run one after the other on a worker thread,
+//! each in a headless application of its own, so the window stays responsive. The panel shows
+//! the progress of the running experiment, by step of a batch run and by run of a sweep, and a
+//! notification pops up when an experiment completes; a failed one is reported as an error too.
+//! Queued experiments can be removed until they start.
+//!
+//! A batch run writes its telemetry to `batch_<id>.csv`, and a sweep its summary table to
+//! `sweep_<id>.csv`, in the output directory. A sweep takes the settings of `sweep.json` as
+//! they are when it's queued; the controllers of both read their configuration files when the
+//! experiment starts.
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    batch::{self, BatchRun},
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    fixed_step,
+    headless::DEFAULT_TIME_STEP,
+    logging::subsystem,
+    plants::{self, Plant},
+    sweep::{self, SweepSettings},
+};
+
+/// How long a notification stays up.
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(6);
+
+pub struct ExperimentsPlugin;
+
+impl Plugin for ExperimentsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ExperimentQueue::spawn())
+            .init_resource::<Notifications>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, receive_updates)
+            .add_systems(
+                Update,
+                (experiments_panel, show_notifications)
+                    .after(receive_updates)
+                    .run_if(resource_exists::<Persistent<ExperimentSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the experiments queued from the panel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ExperimentSettings {
+    /// Name of the plant the experiments run.
+    pub plant: String,
+    /// Simulated duration of a batch run, in s.
+    pub duration: f32,
+    /// Time step of the physics, in s.
+    pub dt: f32,
+    pub seed: u64,
+    /// Directory the outputs are written to.
+    pub directory: PathBuf,
+}
+
+impl Default for ExperimentSettings {
+    fn default() -> Self {
+        Self {
+            plant: "rotary_pendulum".to_string(),
+            duration: 10.0,
+            dt: DEFAULT_TIME_STEP,
+            seed: fixed_step::DEFAULT_SEED,
+            directory: PathBuf::from("experiments"),
+        }
+    }
+}
+
+/// What an experiment runs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExperimentKind {
+    Batch(BatchRun),
+    /// A sweep stepping by `dt`, summarized to `out`.
+    Sweep {
+        settings: SweepSettings,
+        dt: f32,
+        out: PathBuf,
+    },
+}
+
+impl ExperimentKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Batch(_) => "Batch run",
+            Self::Sweep { .. } => "Sweep",
+        }
+    }
+
+    /// File the experiment writes.
+    pub fn out(&self) -> &PathBuf {
+        match self {
+            Self::Batch(batch) => &batch.out,
+            Self::Sweep { out, .. } => out,
+        }
+    }
+}
+
+/// Where an experiment is.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExperimentStatus {
+    Queued,
+    /// Running, with the fraction done.
+    Running(f32),
+    /// Done, with a summary of its outcome.
+    Done(String),
+    Failed(String),
+    Removed,
+}
+
+impl ExperimentStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done(_) | Self::Failed(_) | Self::Removed)
+    }
+}
+
+/// A queued experiment.
+#[derive(Clone, Debug)]
+pub struct Experiment {
+    pub id: usize,
+    pub plant: &'static str,
+    pub kind: ExperimentKind,
+    pub status: ExperimentStatus,
+    /// Set to skip the experiment if it hasn't started yet.
+    removed: Arc<AtomicBool>,
+}
+
+/// An experiment sent to the worker.
+struct Job {
+    id: usize,
+    plant: Plant,
+    kind: ExperimentKind,
+    removed: Arc<AtomicBool>,
+}
+
+/// Messages from the worker.
+#[derive(Debug)]
+enum Update {
+    Started(usize),
+    Progress(usize, f32),
+    Finished(usize, Result<String>),
+}
+
+/// An experiment that completed since the last poll.
+#[derive(Debug)]
+pub struct Completion {
+    pub id: usize,
+    pub result: Result<String>,
+}
+
+/// The experiments queued, and the worker running them.
+#[derive(Resource)]
+pub struct ExperimentQueue {
+    pub experiments: Vec<Experiment>,
+    jobs: Sender<Job>,
+    updates: Mutex<Receiver<Update>>,
+    next_id: usize,
+}
+
+impl ExperimentQueue {
+    /// Starts the worker, waiting for experiments.
+    pub fn spawn() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (updates, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for job in job_receiver {
+                if job.removed.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // The window has gone once it stops listening.
+                if updates.send(Update::Started(job.id)).is_err() {
+                    break;
+                }
+                let result = run(&job, &updates);
+                if updates.send(Update::Finished(job.id, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            experiments: Vec::new(),
+            jobs,
+            updates: Mutex::new(receiver),
+            next_id: 1,
+        }
+    }
+
+    /// Queues `kind` on `plant`, returning its id.
+    pub fn enqueue(&mut self, plant: Plant, kind: ExperimentKind) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let removed = Arc::new(AtomicBool::new(false));
+        let job = Job {
+            id,
+            plant,
+            kind: kind.clone(),
+            removed: removed.clone(),
+        };
+        let status = match self.jobs.send(job) {
+            Ok(()) => ExperimentStatus::Queued,
+            Err(_) => ExperimentStatus::Failed("the worker stopped".to_string()),
+        };
+        self.experiments.push(Experiment {
+            id,
+            plant: plant.name,
+            kind,
+            status,
+            removed,
+        });
+        id
+    }
+
+    /// Removes the experiment `id` if it hasn't started yet.
+    pub fn remove(&mut self, id: usize) {
+        for experiment in &mut self.experiments {
+            if experiment.id == id && experiment.status == ExperimentStatus::Queued {
+                experiment.removed.store(true, Ordering::Relaxed);
+                experiment.status = ExperimentStatus::Removed;
+            }
+        }
+    }
+
+    /// Forgets the finished experiments.
+    pub fn clear_finished(&mut self) {
+        self.experiments
+            .retain(|experiment| !experiment.status.is_finished());
+    }
+
+    /// Applies the updates of the worker, returning the experiments that completed.
+    pub fn poll(&mut self) -> Vec<Completion> {
+        let updates: Vec<Update> = match self.updates.lock() {
+            Ok(receiver) => receiver.try_iter().collect(),
+            Err(_) => return Vec::new(),
+        };
+        let mut completions = Vec::new();
+        for update in updates {
+            let (id, status) = match update {
+                Update::Started(id) => (id, ExperimentStatus::Running(0.0)),
+                Update::Progress(id, done) => (id, ExperimentStatus::Running(done)),
+                Update::Finished(id, result) => {
+                    let status = match &result {
+                        Ok(summary) => ExperimentStatus::Done(summary.clone()),
+                        Err(error) => ExperimentStatus::Failed(error.to_string()),
+                    };
+                    completions.push(Completion { id, result });
+                    (id, status)
+                }
+            };
+            let experiment = self
+                .experiments
+                .iter_mut()
+                .find(|experiment| experiment.id == id);
+            if let Some(experiment) = experiment {
+                experiment.status = status;
+            }
+        }
+        completions
+    }
+}
+
+/// Runs an experiment on the worker, reporting its progress by hundredths.
+fn run(job: &Job, updates: &Sender<Update>) -> Result<String> {
+    let mut reported = -1;
+    let mut progress = |done: f32| {
+        let percent = (100.0 * done) as i32;
+        if percent != reported {
+            reported = percent;
+            let _ = updates.send(Update::Progress(job.id, done));
+        }
+    };
+    info!(target: subsystem::IO, "Experiment {} started", job.id);
+    match &job.kind {
+        ExperimentKind::Batch(batch) => {
+            let summary = batch::run_with_progress(&job.plant, batch, progress)?;
+            Ok(format!(
+                "{} steps in {:.1} s, {} violations",
+                summary.steps,
+                summary.elapsed.as_secs_f32(),
+                summary.violations.len()
+            ))
+        }
+        ExperimentKind::Sweep { settings, dt, out } => {
+            let count = sweep::combinations(settings).len().max(1);
+            let runs = sweep::sweep(&job.plant, settings, *dt, |index, _| {
+                progress((index + 1) as f32 / count as f32);
+            })?;
+            sweep::write_summary(settings, &runs, out)?;
+            let best = sweep::ranking(&runs, settings.rank_by)
+                .first()
+                .map_or("none".to_string(), |best| best.to_string());
+            Ok(format!("{} runs, best run {best}", runs.len()))
+        }
+    }
+}
+
+/// Notifications of completed experiments, and when they were raised.
+#[derive(Default, Resource)]
+pub struct Notifications(pub Vec<(String, Duration)>);
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ExperimentSettings>("experiments", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Notifies the completed experiments, and reports the failed ones.
+fn receive_updates(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut queue: ResMut<ExperimentQueue>,
+    mut notifications: ResMut<Notifications>,
+) {
+    for completion in queue.poll() {
+        let message = match completion.result {
+            Ok(summary) => {
+                info!(target: subsystem::IO, "Experiment {} done: {summary}", completion.id);
+                format!("Experiment {} done: {summary}", completion.id)
+            }
+            Err(error) => {
+                let message = format!("Experiment {} failed", completion.id);
+                commands.send_event(ErrorEvent::from(error));
+                message
+            }
+        };
+        notifications.0.push((message, time.elapsed()));
+    }
+}
+
+/// Shows the recent notifications in a corner of the window.
+fn show_notifications(
+    mut contexts: EguiContexts,
+    time: Res<Time<Real>>,
+    mut notifications: ResMut<Notifications>,
+) {
+    let now = time.elapsed();
+    notifications
+        .0
+        .retain(|(_, raised)| now.saturating_sub(*raised) < NOTIFICATION_DURATION);
+    if notifications.0.is_empty() {
+        return;
+    }
+    egui::Area::new(egui::Id::new("experiment_notifications"))
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -12.0))
+        .show(contexts.ctx_mut(), |ui| {
+            for (message, _) in &notifications.0 {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(message);
+                });
+            }
+        });
+}
+
+/// Panel to queue experiments and follow them.
+fn experiments_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ExperimentSettings>>,
+    mut queue: ResMut<ExperimentQueue>,
+) {
+    let mut edited = settings.get().clone();
+    let mut requested = None;
+
+    egui::Window::new("Experiments")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let available = plants::available();
+            egui::ComboBox::from_label("Plant")
+                .selected_text(&edited.plant)
+                .show_ui(ui, |ui| {
+                    for plant in &available {
+                        ui.selectable_value(&mut edited.plant, plant.name.to_string(), plant.name);
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut edited.duration)
+                    .range(0.01..=86_400.0)
+                    .prefix("Batch duration: ")
+                    .suffix(" s"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.dt)
+                    .range(1e-4..=0.1)
+                    .speed(1e-4)
+                    .prefix("Time step: ")
+                    .suffix(" s"),
+            );
+            ui.add(egui::DragValue::new(&mut edited.seed).prefix("Seed: "));
+            let mut directory = edited.directory.display().to_string();
+            ui.horizontal(|ui| {
+                ui.label("Output directory");
+                if ui.text_edit_singleline(&mut directory).changed() {
+                    edited.directory = PathBuf::from(&directory);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Queue batch run").clicked() {
+                    requested = Some(Request::Batch);
+                }
+                if ui
+                    .button("Queue sweep")
+                    .on_hover_text("Runs the sweep of sweep.json as it is now")
+                    .clicked()
+                {
+                    requested = Some(Request::Sweep);
+                }
+            });
+
+            ui.separator();
+            let mut removed = None;
+            for experiment in queue.experiments.iter().rev() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "#{} {} of {} to {}",
+                        experiment.id,
+                        experiment.kind.name(),
+                        experiment.plant,
+                        experiment.kind.out().display()
+                    ));
+                    match &experiment.status {
+                        ExperimentStatus::Queued => {
+                            ui.label("queued");
+                            if ui.small_button("Remove").clicked() {
+                                removed = Some(experiment.id);
+                            }
+                        }
+                        ExperimentStatus::Running(done) => {
+                            ui.add(
+                                egui::ProgressBar::new(*done)
+                                    .desired_width(120.0)
+                                    .show_percentage(),
+                            );
+                        }
+                        ExperimentStatus::Done(summary) => {
+                            ui.label(summary);
+                        }
+                        ExperimentStatus::Failed(message) => {
+                            ui.colored_label(ui.visuals().error_fg_color, message);
+                        }
+                        ExperimentStatus::Removed => {
+                            ui.label("removed");
+                        }
+                    }
+                });
+            }
+            if queue.experiments.is_empty() {
+                ui.label("No experiment queued");
+            }
+            if let Some(id) = removed {
+                queue.remove(id);
+            }
+            if ui.button("Clear finished").clicked() {
+                queue.clear_finished();
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("experiments", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("experiments", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if let Some(request) = requested {
+        if let Err(error) = enqueue(&mut queue, &edited, request) {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// An experiment requested from the panel.
+#[derive(Clone, Copy)]
+enum Request {
+    Batch,
+    Sweep,
+}
+
+/// Queues the experiment requested with `settings`.
+fn enqueue(
+    queue: &mut ExperimentQueue,
+    settings: &ExperimentSettings,
+    request: Request,
+) -> Result<()> {
+    let plant = plants::available()
+        .into_iter()
+        .find(|plant| plant.name == settings.plant)
+        .ok_or_else(|| Error::Config {
+            name: "experiments".to_string(),
+            message: format!("no plant `{}` in this build", settings.plant),
+        })?;
+    let id = queue.next_id;
+    let out = |name: &str| settings.directory.join(format!("{name}_{id}.csv"));
+    let experiment = match request {
+        Request::Sweep => {
+            let (sweep, error) = config_plugin::load_config::<SweepSettings>("sweep", false);
+            if let Some(error) = error {
+                return Err(error);
+            }
+            let mut sweep = sweep.get().clone();
+            sweep.seed = settings.seed;
+            ExperimentKind::Sweep {
+                settings: sweep,
+                dt: settings.dt,
+                out: out("sweep"),
+            }
+        }
+        Request::Batch => ExperimentKind::Batch(BatchRun {
+            duration: settings.duration,
+            dt: settings.dt,
+            seed: settings.seed,
+            out: out("batch"),
+            capture: None,
+            scenario: None,
+        }),
+    };
+    queue.enqueue(plant, experiment);
+    Ok(())
+}
@@ -0,0 +1,282 @@
+//! Extensions: plants, sensors and controllers provided by other crates, so models can live
+//! outside this repository.
+//!
+//! A crate depending on the playground describes what it provides in an [`Extension`], its
+//! manifest, and [`register`]s it before building its application. The plants of the
+//! registered extensions join the built-in ones wherever plants are picked by name, e.g. in the
+//! scene compositions, and the [`ExtensionsPlugin`] adds their sensors and controllers to the
+//! application and lists everything in the *Extensions* panel, where each extension can be
+//! disabled from the next start on, as kept in `extensions.json`.
+//!
+//! Rust has no stable ABI, so extensions are linked into the application at build time rather
+//! than loaded from dynamic libraries; the [`API_VERSION`] of the manifest guards against an
+//! extension written for another version of this interface.
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    plants::{self, Plant},
+};
+
+/// Version of the interface of the extensions, bumped whenever [`Extension`] changes.
+pub const API_VERSION: u32 = 2;
+
+/// A sensor or a controller provided by an extension, and how to add it to an application.
+#[derive(Clone, Copy)]
+pub struct Contribution {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub add: fn(&mut App),
+}
+
+/// The manifest of an extension: what it provides.
+#[derive(Clone)]
+pub struct Extension {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub description: &'static str,
+    /// Version of the interface the extension was written for.
+    pub api_version: u32,
+    pub plants: Vec<Plant>,
+    pub sensors: Vec<Contribution>,
+    pub controllers: Vec<Contribution>,
+}
+
+impl Extension {
+    pub fn new(name: &'static str, version: &'static str) -> Self {
+        Self {
+            name,
+            version,
+            description: "",
+            api_version: API_VERSION,
+            plants: Vec::new(),
+            sensors: Vec::new(),
+            controllers: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn plant(mut self, plant: Plant) -> Self {
+        self.plants.push(plant);
+        self
+    }
+
+    pub fn sensor(
+        mut self,
+        name: &'static str,
+        description: &'static str,
+        add: fn(&mut App),
+    ) -> Self {
+        self.sensors.push(Contribution {
+            name,
+            description,
+            add,
+        });
+        self
+    }
+
+    pub fn controller(
+        mut self,
+        name: &'static str,
+        description: &'static str,
+        add: fn(&mut App),
+    ) -> Self {
+        self.controllers.push(Contribution {
+            name,
+            description,
+            add,
+        });
+        self
+    }
+}
+
+static REGISTRY: Mutex<Vec<Extension>> = Mutex::new(Vec::new());
+
+/// Registers an extension, unless it was written for another interface, or its name or the
+/// name of one of its plants is taken.
+pub fn register(extension: Extension) -> Result<()> {
+    let invalid = |message: String| Error::Config {
+        name: extension.name.to_string(),
+        message,
+    };
+    if extension.api_version != API_VERSION {
+        return Err(invalid(format!(
+            "written for version {} of the extension interface, this build has version \
+             {API_VERSION}",
+            extension.api_version
+        )));
+    }
+    let mut registry = REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+    if registry
+        .iter()
+        .any(|registered| registered.name == extension.name)
+    {
+        return Err(invalid(
+            "an extension of that name is registered".to_string(),
+        ));
+    }
+    let taken = plants::builtin()
+        .into_iter()
+        .chain(
+            registry
+                .iter()
+                .flat_map(|registered| registered.plants.clone()),
+        )
+        .map(|plant| plant.name)
+        .collect::<Vec<_>>();
+    for (index, plant) in extension.plants.iter().enumerate() {
+        if taken.contains(&plant.name)
+            || extension.plants[..index]
+                .iter()
+                .any(|other| other.name == plant.name)
+        {
+            return Err(invalid(format!(
+                "the {} plant is already provided",
+                plant.name
+            )));
+        }
+    }
+    registry.push(extension);
+    Ok(())
+}
+
+/// The registered extensions, in registration order.
+pub fn registered() -> Vec<Extension> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .clone()
+}
+
+/// Represents which registered extensions are used.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ExtensionSettings {
+    /// Names of the extensions whose sensors and controllers aren't added.
+    pub disabled: Vec<String>,
+}
+
+impl ExtensionSettings {
+    pub fn enabled(&self, extension: &Extension) -> bool {
+        !self.disabled.iter().any(|name| name == extension.name)
+    }
+}
+
+/// Adds the sensors and controllers of the enabled extensions, and the panel listing the
+/// extensions.
+pub struct ExtensionsPlugin;
+
+impl Plugin for ExtensionsPlugin {
+    fn build(&self, app: &mut App) {
+        let (settings, error) = config_plugin::load_config::<ExtensionSettings>("extensions", true);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        for extension in registered() {
+            if !settings.enabled(&extension) {
+                continue;
+            }
+            info!(
+                target: subsystem::IO,
+                "Adding the {} extension {}",
+                extension.name,
+                extension.version
+            );
+            for contribution in extension.sensors.iter().chain(&extension.controllers) {
+                (contribution.add)(app);
+            }
+        }
+        app.insert_resource(EnabledOnStart(settings.get().clone()))
+            .insert_resource(settings)
+            .add_systems(Update, extensions_panel.run_if(has_ui));
+    }
+}
+
+/// The settings the application started with.
+#[derive(Resource)]
+struct EnabledOnStart(ExtensionSettings);
+
+/// Panel listing the registered extensions and what they provide.
+fn extensions_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ExtensionSettings>>,
+    on_start: Res<EnabledOnStart>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Extensions")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let extensions = registered();
+            if extensions.is_empty() {
+                ui.label("No extension is registered in this build.");
+            }
+            for extension in &extensions {
+                ui.separator();
+                let mut enabled = edited.enabled(extension);
+                if ui
+                    .checkbox(
+                        &mut enabled,
+                        format!("{} {}", extension.name, extension.version),
+                    )
+                    .changed()
+                {
+                    edited.disabled.retain(|name| name != extension.name);
+                    if !enabled {
+                        edited.disabled.push(extension.name.to_string());
+                    }
+                }
+                if !extension.description.is_empty() {
+                    ui.label(extension.description);
+                }
+                for plant in &extension.plants {
+                    ui.label(format!("Plant: {}", plant.name));
+                }
+                for (kind, contributions) in [
+                    ("Sensor", &extension.sensors),
+                    ("Controller", &extension.controllers),
+                ] {
+                    for contribution in contributions {
+                        ui.label(format!(
+                            "{kind}: {}, {}",
+                            contribution.name, contribution.description
+                        ));
+                    }
+                }
+                if on_start.0.enabled(extension) != enabled {
+                    ui.weak("Applies on the next start");
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("extensions", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("extensions", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,470 @@
+//! Model-based fault detection: an observer predicts the outputs of the plant from its inputs,
+//! and a fault shows as a residual, the difference between the measured and the predicted
+//! outputs, growing beyond its usual spread.
+//!
+//! The [`Observer`] is a steady-state Kalman filter of an identified [`LinearModel`], its gain
+//! following from the variances of the process and of the measurement noises. The [`Detector`]
+//! raises an alarm when the residual of an output stays beyond its threshold for a number of
+//! samples, which trades the detection latency for the false alarms.
+//!
+//! The demo of [`run`] excites the plant as an identification experiment and injects the faults
+//! of its scenarios into the sensors, on the recorded outputs, or into the actuators, on the
+//! inputs applied to the plant. The thresholds are set from the spread of the residuals of a
+//! run without fault, and every scenario is reported with the share of its runs whose fault was
+//! detected, the detection latency and the false alarms before the fault. The demo is read from
+//! `fault_detection.json`.
+use std::{fmt, path::PathBuf};
+
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    identification::{self, Dataset, Experiment, LinearModel},
+    plants::Plant,
+    setpoints::MOTOR_VELOCITY,
+    telemetry::PENDULUM_ANGLE,
+};
+
+/// Iterations of the Riccati equation before its solution is deemed not to converge.
+const RICCATI_ITERATIONS: usize = 10_000;
+
+/// A steady-state Kalman filter of a model, generating the residuals of its outputs.
+#[derive(Clone, Debug)]
+pub struct Observer {
+    model: LinearModel,
+    /// Gain correcting the predicted state with the residuals.
+    gain: DMatrix<f64>,
+    /// State predicted for the next sample.
+    state: DVector<f64>,
+}
+
+impl Observer {
+    /// The observer of `model`, with white process and measurement noises of the given
+    /// variances on every state and output.
+    pub fn new(model: LinearModel, process_noise: f64, measurement_noise: f64) -> Result<Self> {
+        let (order, outputs) = (model.order(), model.outputs.len());
+        let (a, c) = (&model.a, &model.c);
+        let q = DMatrix::identity(order, order) * process_noise;
+        let r = DMatrix::identity(outputs, outputs) * measurement_noise;
+        let kalman_gain = |covariance: &DMatrix<f64>| -> Result<DMatrix<f64>> {
+            let innovation = c * covariance * c.transpose() + &r;
+            let inverse = innovation.try_inverse().ok_or_else(|| {
+                Error::Identification("the outputs of the model aren't independent".to_string())
+            })?;
+            Ok(covariance * c.transpose() * inverse)
+        };
+        // Covariance of the predicted state, from the discrete algebraic Riccati equation.
+        let mut covariance = q.clone();
+        for _ in 0..RICCATI_ITERATIONS {
+            let gain = kalman_gain(&covariance)?;
+            let corrected = &covariance - &gain * c * &covariance;
+            let next = a * corrected * a.transpose() + &q;
+            let change = (&next - &covariance).norm();
+            covariance = next;
+            if change <= 1e-12 * covariance.norm().max(f64::MIN_POSITIVE) {
+                return Ok(Self {
+                    gain: kalman_gain(&covariance)?,
+                    state: DVector::zeros(order),
+                    model,
+                });
+            }
+        }
+        Err(Error::Identification(
+            "the observer of the model doesn't converge".to_string(),
+        ))
+    }
+
+    pub fn model(&self) -> &LinearModel {
+        &self.model
+    }
+
+    /// Residuals of the measured `outputs` given the `inputs` of the same sample, then the
+    /// state predicted for the next one.
+    pub fn step(&mut self, inputs: &[f64], outputs: &[f64]) -> Vec<f64> {
+        let model = &self.model;
+        let input = DVector::from_iterator(
+            inputs.len(),
+            inputs
+                .iter()
+                .zip(&model.input_offsets)
+                .map(|(u, u0)| u - u0),
+        );
+        let output = DVector::from_iterator(
+            outputs.len(),
+            outputs
+                .iter()
+                .zip(&model.output_offsets)
+                .map(|(y, y0)| y - y0),
+        );
+        let residual = output - (&model.c * &self.state + &model.d * &input);
+        let corrected = &self.state + &self.gain * &residual;
+        self.state = &model.a * corrected + &model.b * &input;
+        residual.iter().copied().collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.state.fill(0.0);
+    }
+}
+
+/// Residuals of every sample of `data`, one row per sample.
+pub fn residuals(observer: &mut Observer, data: &Dataset) -> Vec<Vec<f64>> {
+    observer.reset();
+    data.inputs
+        .iter()
+        .zip(&data.outputs)
+        .map(|(inputs, outputs)| observer.step(inputs, outputs))
+        .collect()
+}
+
+/// Raises an alarm when a residual stays beyond its threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detector {
+    /// Largest usual residual of each output.
+    pub thresholds: Vec<f64>,
+    /// Consecutive samples beyond a threshold raising the alarm.
+    pub persistence: usize,
+    /// Consecutive samples each output has been beyond its threshold.
+    counts: Vec<usize>,
+}
+
+impl Detector {
+    pub fn new(thresholds: Vec<f64>, persistence: usize) -> Self {
+        Self {
+            counts: vec![0; thresholds.len()],
+            thresholds,
+            persistence: persistence.max(1),
+        }
+    }
+
+    /// Whether the alarm is raised after the residuals of a sample.
+    pub fn step(&mut self, residuals: &[f64]) -> bool {
+        let mut alarm = false;
+        for ((count, threshold), residual) in
+            self.counts.iter_mut().zip(&self.thresholds).zip(residuals)
+        {
+            *count = if residual.abs() > *threshold {
+                *count + 1
+            } else {
+                0
+            };
+            alarm |= *count >= self.persistence;
+        }
+        alarm
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// Whether the alarm is raised after each sample.
+    pub fn alarms(&mut self, residuals: &[Vec<f64>]) -> Vec<bool> {
+        self.reset();
+        residuals
+            .iter()
+            .map(|residuals| self.step(residuals))
+            .collect()
+    }
+}
+
+/// Thresholds of `deviations` times the standard deviation of each residual.
+pub fn thresholds(residuals: &[Vec<f64>], deviations: f64) -> Vec<f64> {
+    let channels = residuals.first().map_or(0, Vec::len);
+    (0..channels)
+        .map(|channel| {
+            let count = residuals.len().max(1) as f64;
+            let mean = residuals.iter().map(|r| r[channel]).sum::<f64>() / count;
+            let variance = residuals
+                .iter()
+                .map(|r| (r[channel] - mean).powi(2))
+                .sum::<f64>()
+                / count;
+            // A perfect model still tolerates the rounding.
+            deviations * variance.sqrt().max(1e-9)
+        })
+        .collect()
+}
+
+/// How a fault changes a signal.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FaultKind {
+    /// The signal is offset by a constant.
+    Bias { value: f64 },
+    /// The signal drifts away at a constant rate, per second.
+    Drift { rate: f64 },
+    /// The signal is scaled.
+    Gain { factor: f64 },
+    /// The signal keeps its value at the start of the fault.
+    Stuck,
+}
+
+impl FaultKind {
+    /// The faulty value of a signal worth `value`, `elapsed` seconds after the start of the
+    /// fault, when it was worth `initial`.
+    pub fn apply(&self, value: f64, elapsed: f64, initial: f64) -> f64 {
+        match *self {
+            FaultKind::Bias { value: bias } => value + bias,
+            FaultKind::Drift { rate } => value + rate * elapsed,
+            FaultKind::Gain { factor } => value * factor,
+            FaultKind::Stuck => initial,
+        }
+    }
+}
+
+/// A fault injected into a signal of the plant.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Fault {
+    /// An output of the model for a sensor fault, or one of its inputs for an actuator fault.
+    pub signal: String,
+    #[serde(flatten)]
+    pub kind: FaultKind,
+    /// Time of the fault from the start of the excitation, in s.
+    pub start: f32,
+}
+
+/// A scenario of the demo, without fault to measure the false alarms alone.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Scenario {
+    pub name: String,
+    pub fault: Option<Fault>,
+}
+
+/// Represents the configuration of the demo.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FaultDetectionSettings {
+    /// Model the observer runs, e.g. written by `--identify`; its inputs and outputs replace the
+    /// ones of the experiment.
+    pub model: PathBuf,
+    /// Variance of the process noise on each state.
+    pub process_noise: f64,
+    /// Variance of the measurement noise on each output.
+    pub measurement_noise: f64,
+    /// Thresholds, in standard deviations of the residuals of the run without fault.
+    pub deviations: f64,
+    /// Consecutive samples beyond a threshold raising the alarm.
+    pub persistence: usize,
+    /// Samples ignored at the start of each run, while the observer converges.
+    pub warmup_steps: usize,
+    /// Runs of each scenario, with different excitations.
+    pub runs: usize,
+    pub scenarios: Vec<Scenario>,
+}
+
+impl Default for FaultDetectionSettings {
+    fn default() -> Self {
+        let scenario = |name: &str, signal: &str, kind| Scenario {
+            name: name.to_string(),
+            fault: Some(Fault {
+                signal: signal.to_string(),
+                kind,
+                start: 20.0,
+            }),
+        };
+        Self {
+            model: PathBuf::from("model.json"),
+            process_noise: 1e-4,
+            measurement_noise: 1e-2,
+            deviations: 5.0,
+            persistence: 5,
+            warmup_steps: 60,
+            runs: 3,
+            scenarios: vec![
+                Scenario {
+                    name: "nominal".to_string(),
+                    fault: None,
+                },
+                scenario(
+                    "biased angle sensor",
+                    PENDULUM_ANGLE,
+                    FaultKind::Bias { value: 0.05 },
+                ),
+                scenario("stuck angle sensor", PENDULUM_ANGLE, FaultKind::Stuck),
+                scenario(
+                    "weak motor",
+                    MOTOR_VELOCITY,
+                    FaultKind::Gain { factor: 0.5 },
+                ),
+            ],
+        }
+    }
+}
+
+/// Outcome of the runs of a scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub runs: usize,
+    /// Runs whose fault raised the alarm.
+    pub detected: usize,
+    /// Mean time from the faults to their alarms, in s.
+    pub latency: Option<f32>,
+    /// Alarms raised without fault, before it if any.
+    pub false_alarms: usize,
+    /// Time watched for false alarms, in s.
+    pub watched: f32,
+}
+
+impl ScenarioReport {
+    /// False alarms per minute.
+    pub fn false_alarm_rate(&self) -> f32 {
+        if self.watched > 0.0 {
+            60.0 * self.false_alarms as f32 / self.watched
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The detection of the demo, one row per scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub thresholds: Vec<(String, f64)>,
+    pub scenarios: Vec<ScenarioReport>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (output, threshold) in &self.thresholds {
+            writeln!(f, "threshold of {output}: {threshold:.3e}")?;
+        }
+        writeln!(
+            f,
+            "{:<24} {:>9} {:>10} {:>14}",
+            "scenario", "detected", "latency", "false alarms"
+        )?;
+        for scenario in &self.scenarios {
+            let latency = scenario
+                .latency
+                .map_or("-".to_string(), |latency| format!("{latency:.3} s"));
+            writeln!(
+                f,
+                "{:<24} {:>9} {:>10} {:>8.2}/min",
+                scenario.name,
+                format!("{}/{}", scenario.detected, scenario.runs),
+                latency,
+                scenario.false_alarm_rate()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Injects a sensor fault into the outputs of `data`, from the sample `start` on.
+pub fn inject(data: &mut Dataset, output: usize, kind: FaultKind, start: usize) {
+    let Some(initial) = data.outputs.get(start).map(|outputs| outputs[output]) else {
+        return;
+    };
+    for (k, outputs) in data.outputs.iter_mut().enumerate().skip(start) {
+        let elapsed = f64::from((k - start) as f32 * data.dt);
+        outputs[output] = kind.apply(outputs[output], elapsed, initial);
+    }
+}
+
+/// Alarms of a run: the first one from the sample `start` on, and the ones raised before it,
+/// from the sample `warmup` on.
+fn alarms(alarms: &[bool], warmup: usize, start: usize) -> (Option<usize>, usize) {
+    let onsets = (warmup..start.min(alarms.len()))
+        .filter(|&k| alarms[k] && (k == warmup || !alarms[k - 1]))
+        .count();
+    let first = (start.max(warmup)..alarms.len()).find(|&k| alarms[k]);
+    (first, onsets)
+}
+
+/// Runs the scenarios of `settings` on `plant`, excited as `experiment` stepping by `dt`.
+pub fn run(
+    plant: &Plant,
+    experiment: &Experiment,
+    settings: &FaultDetectionSettings,
+    dt: f32,
+) -> Result<Report> {
+    let model = LinearModel::read(&settings.model)?;
+    let experiment = Experiment {
+        inputs: model.inputs.clone(),
+        outputs: model.outputs.clone(),
+        ..experiment.clone()
+    };
+    let mut observer = Observer::new(model, settings.process_noise, settings.measurement_noise)?;
+    let warmup = settings.warmup_steps;
+
+    // The thresholds follow the residuals of a run without fault, with its own excitation.
+    let nominal = identification::run_experiment(plant, &experiment, dt, 1);
+    let spread = residuals(&mut observer, &nominal);
+    let thresholds = thresholds(
+        spread.get(warmup..).unwrap_or_default(),
+        settings.deviations,
+    );
+    let mut detector = Detector::new(thresholds.clone(), settings.persistence);
+
+    let mut scenarios = Vec::new();
+    for scenario in &settings.scenarios {
+        let fault = scenario.fault.as_ref();
+        let sensor = fault.and_then(|fault| {
+            let output = experiment.outputs.iter().position(|o| *o == fault.signal);
+            output.map(|output| (output, fault))
+        });
+        let actuator = fault.and_then(|fault| {
+            let input = experiment.inputs.iter().position(|i| *i == fault.signal);
+            input.map(|input| (input, fault))
+        });
+        if let Some(fault) = fault.filter(|_| sensor.is_none() && actuator.is_none()) {
+            return Err(Error::Config {
+                name: "fault_detection".to_string(),
+                message: format!(
+                    "`{}` of the scenario `{}` isn't an input nor an output of the model",
+                    fault.signal, scenario.name
+                ),
+            });
+        }
+        let start = fault.map_or(experiment.steps, |fault| {
+            (fault.start / dt).round().max(0.0) as usize
+        });
+
+        let mut report = ScenarioReport {
+            name: scenario.name.clone(),
+            runs: settings.runs,
+            detected: 0,
+            latency: None,
+            false_alarms: 0,
+            watched: 0.0,
+        };
+        let mut latencies = Vec::new();
+        for run in 0..settings.runs {
+            let seed = run as u64 + 2;
+            let mut initial = None;
+            let mut data =
+                identification::excite(plant, &experiment, dt, seed, |step, input, level| {
+                    match actuator {
+                        Some((faulty, fault)) if faulty == input && step >= start => {
+                            let initial = *initial.get_or_insert(f64::from(level));
+                            let elapsed = f64::from((step - start) as f32 * dt);
+                            fault.kind.apply(f64::from(level), elapsed, initial) as f32
+                        }
+                        _ => level,
+                    }
+                });
+            if let Some((output, fault)) = sensor {
+                inject(&mut data, output, fault.kind, start);
+            }
+            let raised = detector.alarms(&residuals(&mut observer, &data));
+            let (first, false_alarms) = alarms(&raised, warmup, start);
+            report.false_alarms += false_alarms;
+            report.watched += start.min(raised.len()).saturating_sub(warmup) as f32 * dt;
+            if let (Some(first), Some(_)) = (first, fault) {
+                report.detected += 1;
+                latencies.push((first - start) as f32 * dt);
+            }
+        }
+        if !latencies.is_empty() {
+            report.latency = Some(latencies.iter().sum::<f32>() / latencies.len() as f32);
+        }
+        scenarios.push(report);
+    }
+    Ok(Report {
+        thresholds: experiment.outputs.iter().cloned().zip(thresholds).collect(),
+        scenarios,
+    })
+}
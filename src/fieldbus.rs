@@ -0,0 +1,291 @@
+//! Emulation of the cyclic process image of a fieldbus servo drive, e.g. on EtherCAT.
+//!
+//! A PLC (or any control program) exchanges fixed-layout byte maps with the simulator over UDP,
+//! as it would with the process data of a drive. Every frame of the PLC carries its outputs;
+//! the simulator answers every physics step with its inputs, to the sender of the last frame.
+//!
+//! Frames start with the magic `MCPI` and a cycle counter (`u32`), echoed back by the
+//! simulator, followed by the image. Everything is little endian.
+//!
+//! Outputs, PLC to simulator (8 bytes):
+//!
+//! | Offset | Type  | Content                                 |
+//! |--------|-------|-----------------------------------------|
+//! | 0      | `u16` | Control word: bit 0 = enable operation  |
+//! | 2      | `u16` | Reserved, zero                          |
+//! | 4      | `i32` | Target velocity of the motor, in mrad/s |
+//!
+//! Inputs, simulator to PLC (12 bytes):
+//!
+//! | Offset | Type  | Content                                                          |
+//! |--------|-------|------------------------------------------------------------------|
+//! | 0      | `u16` | Status word: bit 0 = operation enabled, bit 1 = watchdog expired |
+//! | 2      | `u16` | Reserved, zero                                                   |
+//! | 4      | `i32` | Angle of the pendulum, in mrad                                   |
+//! | 8      | `i32` | Velocity demand applied to the motor, in mrad/s                  |
+//!
+//! Like the watchdog of an EtherCAT slave, the drive disables itself when the outputs stop
+//! coming for [`WATCHDOG`]. Both directions go through the impaired [`Link`]s of `network.json`.
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    network::{Link, NetworkSettings},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+const MAGIC: &[u8; 4] = b"MCPI";
+const HEADER_SIZE: usize = 8;
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5720;
+/// Size of a frame carrying the outputs.
+pub const OUTPUTS_FRAME_SIZE: usize = HEADER_SIZE + 8;
+/// Size of a frame carrying the inputs.
+pub const INPUTS_FRAME_SIZE: usize = HEADER_SIZE + 12;
+/// Time without outputs after which the drive disables itself.
+pub const WATCHDOG: Duration = Duration::from_millis(100);
+
+/// Bit of the control word enabling the drive.
+pub const ENABLE_OPERATION: u16 = 1 << 0;
+/// Bit of the status word set while the drive is enabled.
+pub const OPERATION_ENABLED: u16 = 1 << 0;
+/// Bit of the status word set when the outputs stopped coming.
+pub const WATCHDOG_EXPIRED: u16 = 1 << 1;
+
+/// Process data written by the PLC.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Outputs {
+    pub control_word: u16,
+    /// In mrad/s.
+    pub target_velocity: i32,
+}
+
+impl Outputs {
+    pub fn encode(&self, cycle: u32) -> [u8; OUTPUTS_FRAME_SIZE] {
+        let mut bytes = [0; OUTPUTS_FRAME_SIZE];
+        write_header(&mut bytes, cycle);
+        bytes[8..10].copy_from_slice(&self.control_word.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.target_velocity.to_le_bytes());
+        bytes
+    }
+
+    /// The cycle counter and the outputs of a frame.
+    pub fn decode(bytes: &[u8]) -> Option<(u32, Outputs)> {
+        let cycle = read_header(bytes, OUTPUTS_FRAME_SIZE)?;
+        let outputs = Outputs {
+            control_word: u16::from_le_bytes(bytes[8..10].try_into().ok()?),
+            target_velocity: i32::from_le_bytes(bytes[12..16].try_into().ok()?),
+        };
+        Some((cycle, outputs))
+    }
+}
+
+/// Process data read by the PLC.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Inputs {
+    pub status_word: u16,
+    /// In mrad.
+    pub actual_position: i32,
+    /// In mrad/s.
+    pub velocity_demand: i32,
+}
+
+impl Inputs {
+    pub fn encode(&self, cycle: u32) -> [u8; INPUTS_FRAME_SIZE] {
+        let mut bytes = [0; INPUTS_FRAME_SIZE];
+        write_header(&mut bytes, cycle);
+        bytes[8..10].copy_from_slice(&self.status_word.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.actual_position.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.velocity_demand.to_le_bytes());
+        bytes
+    }
+
+    /// The cycle counter and the inputs of a frame.
+    pub fn decode(bytes: &[u8]) -> Option<(u32, Inputs)> {
+        let cycle = read_header(bytes, INPUTS_FRAME_SIZE)?;
+        let inputs = Inputs {
+            status_word: u16::from_le_bytes(bytes[8..10].try_into().ok()?),
+            actual_position: i32::from_le_bytes(bytes[12..16].try_into().ok()?),
+            velocity_demand: i32::from_le_bytes(bytes[16..20].try_into().ok()?),
+        };
+        Some((cycle, inputs))
+    }
+}
+
+fn write_header(bytes: &mut [u8], cycle: u32) {
+    bytes[0..4].copy_from_slice(MAGIC);
+    bytes[4..8].copy_from_slice(&cycle.to_le_bytes());
+}
+
+fn read_header(bytes: &[u8], size: usize) -> Option<u32> {
+    if bytes.len() != size || &bytes[0..4] != MAGIC {
+        return None;
+    }
+    Some(u32::from_le_bytes(bytes[4..8].try_into().ok()?))
+}
+
+/// Exposes the motor and the pendulum as the process image of a servo drive.
+pub struct FieldbusPlugin {
+    /// Local address to listen on.
+    pub bind: SocketAddr,
+    pub network: NetworkSettings,
+}
+
+impl Plugin for FieldbusPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match UdpSocket::bind(self.bind).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "Process image listening on {}", self.bind);
+        app.init_resource::<Setpoints>()
+            .insert_resource(ProcessImage {
+                socket,
+                plc: None,
+                cycle: 0,
+                outputs: Outputs::default(),
+                last_frame: None,
+                applied: None,
+                incoming: Link::new(self.network.incoming.clone(), self.network.seed),
+                outgoing: Link::new(self.network.outgoing.clone(), self.network.seed + 1),
+            })
+            .add_systems(PreUpdate, receive_outputs)
+            .add_systems(PostUpdate, send_inputs.after(SimClockSet::Advance));
+    }
+}
+
+/// State of the exchange with the PLC.
+#[derive(Resource)]
+pub struct ProcessImage {
+    socket: UdpSocket,
+    plc: Option<SocketAddr>,
+    /// Cycle counter of the last frame received.
+    cycle: u32,
+    outputs: Outputs,
+    last_frame: Option<Instant>,
+    /// Velocity setpoint last written from the outputs, in rad/s.
+    applied: Option<f32>,
+    /// Frames received, with their sender.
+    incoming: Link<(SocketAddr, Vec<u8>)>,
+    /// Frames to send, with their destination.
+    outgoing: Link<(SocketAddr, Vec<u8>)>,
+}
+
+impl ProcessImage {
+    /// Address of the PLC, once it sent a frame.
+    pub fn plc(&self) -> Option<SocketAddr> {
+        self.plc
+    }
+
+    pub fn outputs(&self) -> Outputs {
+        self.outputs
+    }
+
+    pub fn watchdog_expired(&self) -> bool {
+        self.last_frame
+            .is_some_and(|instant| instant.elapsed() >= WATCHDOG)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.outputs.control_word & ENABLE_OPERATION != 0 && !self.watchdog_expired()
+    }
+}
+
+/// Applies the last outputs of the PLC to the setpoints.
+fn receive_outputs(mut image: ResMut<ProcessImage>, mut setpoints: ResMut<Setpoints>) {
+    let mut buffer = [0; 64];
+    let now = Instant::now();
+    loop {
+        match image.socket.recv_from(&mut buffer) {
+            Ok((size, from)) => image
+                .incoming
+                .send((from, buffer[..size].to_vec()), size, now),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                debug!(target: subsystem::IO, "Process image receive error: {error}");
+                break;
+            }
+        }
+    }
+    for (from, frame) in image.incoming.receive(now) {
+        match Outputs::decode(&frame) {
+            Some((cycle, outputs)) => {
+                if image.plc != Some(from) {
+                    info!(target: subsystem::IO, "Process image exchanged with {from}");
+                }
+                image.plc = Some(from);
+                image.cycle = cycle;
+                image.outputs = outputs;
+                image.last_frame = Some(now);
+            }
+            None => debug!(target: subsystem::IO, "Ignoring a datagram from {from}"),
+        }
+    }
+    if image.plc.is_none() {
+        return;
+    }
+
+    let velocity = if image.is_enabled() {
+        image.outputs.target_velocity as f32 / 1000.0
+    } else {
+        0.0
+    };
+    // Other inputs (e.g. the keyboard) keep working while the PLC doesn't change its command.
+    if image.applied != Some(velocity) {
+        if image.watchdog_expired() {
+            warn!(target: subsystem::IO, "Process image watchdog expired, disabling the drive");
+        }
+        setpoints.set(MOTOR_VELOCITY, velocity);
+        image.applied = Some(velocity);
+    }
+}
+
+/// Sends the inputs after every physics step.
+fn send_inputs(
+    mut image: ResMut<ProcessImage>,
+    clock: Res<SimClock>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let now = Instant::now();
+    // Frames are only produced by the physics steps, but those in flight arrive in real time.
+    if let Some(plc) = image.plc.filter(|_| clock.delta() > Duration::ZERO) {
+        let mut status_word = 0;
+        if image.is_enabled() {
+            status_word |= OPERATION_ENABLED;
+        }
+        if image.watchdog_expired() {
+            status_word |= WATCHDOG_EXPIRED;
+        }
+        let angle = telemetry
+            .and_then(|telemetry| telemetry.latest(PENDULUM_ANGLE))
+            .unwrap_or_default();
+        let inputs = Inputs {
+            status_word,
+            actual_position: (angle * 1000.0).round() as i32,
+            velocity_demand: (image.applied.unwrap_or_default() * 1000.0).round() as i32,
+        };
+        let frame = inputs.encode(image.cycle);
+        image.outgoing.send((plc, frame.to_vec()), frame.len(), now);
+    }
+    for (plc, frame) in image.outgoing.receive(now) {
+        if let Err(error) = image.socket.send_to(&frame, plc) {
+            warn!(target: subsystem::IO, "Failed to send the process image to {plc}: {error}");
+        }
+    }
+}
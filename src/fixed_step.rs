@@ -0,0 +1,96 @@
+//! Deterministic fixed-step mode, for regression-testing controllers.
+//!
+//! By default, Rapier steps by the duration of the last frame, so a controller sees different
+//! steps, and behaves differently, depending on the frame rate. In this mode every frame steps
+//! the physics by exactly the same time step, so the controllers, which run once per frame on
+//! the [`SimClock`](crate::clock::SimClock), see the same sequence of steps whatever the
+//! rendering does. Given the same seed and the same inputs, runs are then bit-identical on the
+//! same build and platform, as headless runs already are.
+//!
+//! The frames are paced to the time step, so the simulation runs in real time as long as a frame
+//! takes less than a step; slower frames slow the simulation down instead of stretching its
+//! steps, as the real-time factor of the clock tells. At another [speed](SimClock::speed) of the
+//! clock, the frames are paced to the time step scaled by it.
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::TimestepMode;
+
+use crate::{
+    clock::SimClock,
+    error::{Error, ErrorEvent},
+};
+
+/// Seed of the random draws when none is given.
+pub const DEFAULT_SEED: u64 = 1;
+
+pub struct FixedStepPlugin {
+    /// Time step of the physics, in seconds.
+    pub dt: f32,
+    /// Seed of the random draws of the session.
+    pub seed: u64,
+    /// Whether the frames are paced to the time step; otherwise they run as fast as they can.
+    pub real_time: bool,
+}
+
+impl Plugin for FixedStepPlugin {
+    fn build(&self, app: &mut App) {
+        if !(self.dt.is_finite() && self.dt > 0.0) {
+            app.world_mut().send_event(ErrorEvent::from(Error::Config {
+                name: "fixed_step".to_string(),
+                message: format!("the time step must be positive, not {}", self.dt),
+            }));
+            return;
+        }
+        app.insert_resource(TimestepMode::Fixed {
+            dt: self.dt,
+            substeps: 1,
+        })
+        .insert_resource(FixedStep {
+            dt: self.dt,
+            seed: self.seed,
+            real_time: self.real_time,
+        })
+        .add_systems(Last, pace);
+    }
+}
+
+/// Settings of the deterministic fixed-step mode, present while it is enabled.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct FixedStep {
+    /// Time step of the physics, in seconds.
+    pub dt: f32,
+    /// Seed of the random draws of the session, for the sources that don't configure their
+    /// own (the network impairments read theirs from `network.json`).
+    pub seed: u64,
+    /// Whether the frames are paced to the time step.
+    pub real_time: bool,
+}
+
+/// Waits for the wall clock to catch up with the step, when the frame took less than one.
+///
+/// A late frame moves the deadline instead of being caught up on, which would step the physics
+/// several times per frame.
+fn pace(
+    fixed_step: Res<FixedStep>,
+    clock: Option<Res<SimClock>>,
+    mut deadline: Local<Option<Instant>>,
+) {
+    if !fixed_step.real_time {
+        return;
+    }
+    let speed = clock.map_or(1.0, |clock| clock.speed());
+    let now = Instant::now();
+    let next = deadline.map_or(now, |deadline| {
+        deadline + Duration::from_secs_f32(fixed_step.dt / speed)
+    });
+    if next > now {
+        thread::sleep(next - now);
+        *deadline = Some(next);
+    } else {
+        *deadline = Some(now);
+    }
+}
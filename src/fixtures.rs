@@ -0,0 +1,353 @@
+//! This module adds virtual fixtures: force fields guiding the end effector of a plant while an
+//! operator moves it, as in assistive teleoperation. A plane keeps the end effector on it, a
+//! corridor within a radius of a segment and a funnel within a cone narrowing to its apex.
+//!
+//! Outside of a fixture, a spring of the stiffness of the fixture pulls the end effector to the
+//! closest point of its geometry; inside, the end effector moves freely. The forces of the
+//! fixtures add up, limited to a largest force, and are applied at the end effector as an
+//! impulse per step, so they add to the drag of the *Interaction* panel or to the motors. Their
+//! magnitude is recorded as the `fixture/force` telemetry channel, and the fixtures are drawn in
+//! the scene.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    plants::Link,
+    telemetry::Telemetry,
+    theme::Theme,
+};
+
+/// Magnitude of the force of the fixtures on the end effector, in N.
+pub const FIXTURE_FORCE: &str = "fixture/force";
+/// Length of the segments drawing the fixtures extending to infinity, in m.
+const DRAWN_EXTENT: f32 = 5.0;
+
+pub struct FixturesPlugin;
+
+impl Plugin for FixturesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                guide.run_if(resource_exists::<Persistent<FixtureSettings>>),
+            )
+            .add_systems(
+                Update,
+                (draw_fixtures, fixtures_panel)
+                    .run_if(resource_exists::<Persistent<FixtureSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Geometry of a fixture, in the world.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Geometry {
+    /// Keeps the end effector on the plane through `point`.
+    Plane { point: Vec3, normal: Vec3 },
+    /// Keeps the end effector within `radius` of the segment from `start` to `end`.
+    Corridor { start: Vec3, end: Vec3, radius: f32 },
+    /// Keeps the end effector within the cone from `apex`, opening along `axis` with an angle of
+    /// `half_angle` (in radians) to the axis, guiding it towards the apex.
+    Funnel {
+        apex: Vec3,
+        axis: Vec3,
+        half_angle: f32,
+    },
+}
+
+impl Geometry {
+    /// Vector from `point` to the closest point of the fixture, zero inside of it.
+    pub fn correction(&self, point: Vec3) -> Vec3 {
+        match self {
+            Geometry::Plane {
+                point: origin,
+                normal,
+            } => {
+                let normal = normal.normalize_or_zero();
+                -(point - *origin).dot(normal) * normal
+            }
+            Geometry::Corridor { start, end, radius } => {
+                let axis = *end - *start;
+                let along = (point - *start).dot(axis) / axis.length_squared().max(f32::EPSILON);
+                let closest = *start + along.clamp(0.0, 1.0) * axis;
+                outside(point, closest, radius.max(0.0))
+            }
+            Geometry::Funnel {
+                apex,
+                axis,
+                half_angle,
+            } => {
+                let axis = axis.normalize_or_zero();
+                let offset = point - *apex;
+                let along = offset.dot(axis);
+                let radial = offset - along * axis;
+                let (sin, cos) = half_angle.clamp(0.0, std::f32::consts::FRAC_PI_2).sin_cos();
+                // Distance to the side of the cone, along its normal in the plane of the point.
+                let outwards = radial.length() * cos - along * sin;
+                if outwards <= 0.0 {
+                    return Vec3::ZERO;
+                }
+                let normal = cos * radial.normalize_or_zero() - sin * axis;
+                let correction = -outwards * normal;
+                // Beyond the apex along the side, the apex is the closest point.
+                if (offset + correction).dot(axis) < 0.0 {
+                    -offset
+                } else {
+                    correction
+                }
+            }
+        }
+    }
+}
+
+/// Vector from `point` to the ball of `radius` around `center`.
+fn outside(point: Vec3, center: Vec3, radius: f32) -> Vec3 {
+    let offset = center - point;
+    let distance = offset.length();
+    if distance <= radius {
+        Vec3::ZERO
+    } else {
+        offset * (1.0 - radius / distance)
+    }
+}
+
+/// A named force field.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Fixture {
+    pub name: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Stiffness of the spring pulling the end effector into the fixture, in N/m.
+    pub stiffness: f32,
+    #[serde(flatten)]
+    pub geometry: Geometry,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Represents the configuration of the virtual fixtures.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FixtureSettings {
+    pub enabled: bool,
+    /// Path of the link guided by the fixtures.
+    pub end_effector: String,
+    /// Point of the link guided by the fixtures, in the frame of the link.
+    pub tip: Vec3,
+    /// Largest force of the fixtures together, in N.
+    pub max_force: f32,
+    pub fixtures: Vec<Fixture>,
+}
+
+impl Default for FixtureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            end_effector: "pendulum".to_string(),
+            tip: Vec3::new(0.0, -1.5, 0.0),
+            max_force: 50.0,
+            fixtures: vec![Fixture {
+                name: "Floor".to_string(),
+                enabled: true,
+                stiffness: 100.0,
+                geometry: Geometry::Plane {
+                    point: Vec3::new(0.0, 1.0, 0.0),
+                    normal: Vec3::Y,
+                },
+            }],
+        }
+    }
+}
+
+impl FixtureSettings {
+    /// Force of the enabled fixtures on the end effector at `point`.
+    pub fn force(&self, point: Vec3) -> Vec3 {
+        self.fixtures
+            .iter()
+            .filter(|fixture| fixture.enabled)
+            .map(|fixture| fixture.stiffness * fixture.geometry.correction(point))
+            .sum::<Vec3>()
+            .clamp_length_max(self.max_force.max(0.0))
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<FixtureSettings>("fixtures", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Applies the force of the fixtures at the end effector, for the next step of the simulation.
+fn guide(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<FixtureSettings>>,
+    links: Query<(Entity, &Link, &Transform)>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let Some((entity, _, transform)) = links
+        .iter()
+        .find(|(_, link, _)| link.path() == settings.end_effector)
+    else {
+        return;
+    };
+    let tip = transform.transform_point(settings.tip);
+    let force = settings.force(tip);
+    let dt = clock.delta_secs();
+    if force != Vec3::ZERO && dt > 0.0 {
+        commands.entity(entity).insert(ExternalImpulse::at_point(
+            force * dt,
+            tip,
+            transform.translation,
+        ));
+    }
+    if let Some(mut telemetry) = telemetry {
+        telemetry.record(FIXTURE_FORCE, clock.elapsed_secs(), force.length());
+    }
+}
+
+/// Draws the enabled fixtures and the guided point.
+fn draw_fixtures(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<FixtureSettings>>,
+    links: Query<(&Link, &Transform)>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+    for (index, fixture) in settings.fixtures.iter().enumerate() {
+        if !fixture.enabled {
+            continue;
+        }
+        let color = theme.series(index);
+        match &fixture.geometry {
+            Geometry::Plane { point, normal } => {
+                let rotation = Quat::from_rotation_arc(Vec3::Z, normal.normalize_or(Vec3::Z));
+                let isometry = Isometry3d::new(*point, rotation);
+                gizmos
+                    .grid(
+                        isometry,
+                        UVec2::splat(10),
+                        Vec2::splat(DRAWN_EXTENT / 5.0),
+                        color,
+                    )
+                    .outer_edges();
+            }
+            Geometry::Corridor { start, end, radius } => {
+                let axis = (*end - *start).normalize_or(Vec3::Y);
+                let rotation = Quat::from_rotation_arc(Vec3::Z, axis);
+                gizmos.line(*start, *end, color);
+                for center in [*start, *end] {
+                    gizmos.circle(Isometry3d::new(center, rotation), *radius, color);
+                }
+                for angle in [0.0f32, 90.0, 180.0, 270.0] {
+                    let side = rotation * Quat::from_rotation_z(angle.to_radians()) * Vec3::X;
+                    gizmos.line(*start + side * *radius, *end + side * *radius, color);
+                }
+            }
+            Geometry::Funnel {
+                apex,
+                axis,
+                half_angle,
+            } => {
+                let axis = axis.normalize_or(Vec3::Y);
+                let rotation = Quat::from_rotation_arc(Vec3::Z, axis);
+                let radius = |along: f32| along * half_angle.clamp(0.0, 1.5).tan();
+                for along in [0.25, 0.5, 1.0].map(|fraction| fraction * DRAWN_EXTENT) {
+                    gizmos.circle(
+                        Isometry3d::new(*apex + along * axis, rotation),
+                        radius(along),
+                        color,
+                    );
+                }
+                for angle in [0.0f32, 90.0, 180.0, 270.0] {
+                    let side = rotation * Quat::from_rotation_z(angle.to_radians()) * Vec3::X;
+                    let rim = *apex + DRAWN_EXTENT * axis + radius(DRAWN_EXTENT) * side;
+                    gizmos.line(*apex, rim, color);
+                }
+            }
+        }
+    }
+    if let Some((_, transform)) = links
+        .iter()
+        .find(|(link, _)| link.path() == settings.end_effector)
+    {
+        let tip = transform.transform_point(settings.tip);
+        gizmos.sphere(Isometry3d::from_translation(tip), 0.1, theme.grid());
+    }
+}
+
+/// Panel to enable the fixtures and tune their stiffness.
+fn fixtures_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<FixtureSettings>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Fixtures")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Guide the end effector")
+                .on_hover_text(format!("Guides a point of `{}`", edited.end_effector));
+            ui.add(
+                egui::Slider::new(&mut edited.max_force, 1.0..=1000.0)
+                    .logarithmic(true)
+                    .text("Largest force (N)"),
+            );
+
+            ui.separator();
+            if edited.fixtures.is_empty() {
+                ui.label("No fixture: add them to fixtures.json.");
+            }
+            for fixture in &mut edited.fixtures {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut fixture.enabled, &fixture.name);
+                    ui.add(
+                        egui::DragValue::new(&mut fixture.stiffness)
+                            .range(0.0..=10_000.0)
+                            .speed(1.0)
+                            .prefix("Stiffness: ")
+                            .suffix(" N/m"),
+                    );
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("fixtures", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("fixtures", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,344 @@
+//! This module draws the coordinate frames of the scene as triads, to debug mismatched axis
+//! conventions, e.g. between Blender, glTF and Rapier: the world frame, the frames of the links,
+//! the frames of the joints on their parent, the centers of mass along their principal axes of
+//! inertia, and the frames of the sensors as mounted.
+//!
+//! Each kind of frame is toggled in the *Frames* panel, which sizes the triads and labels them
+//! with the names of the frames. The panel also gives the transform of any frame relative to any
+//! other, optionally in the axes of Blender: Bevy, glTF and Rapier are Y-up, Blender is Z-up, and
+//! its glTF exporter turns the scene by a quarter turn about X.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts, EguiSettings};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    plants::Link,
+    sensors::Imu,
+};
+
+/// Name of the world frame.
+pub const WORLD_FRAME: &str = "world";
+
+pub struct FramesPlugin;
+
+impl Plugin for FramesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Frames>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (collect_frames, draw_frames, frames_panel.run_if(has_ui))
+                    .chain()
+                    .run_if(resource_exists::<Persistent<FrameSettings>>),
+            );
+    }
+}
+
+/// What a frame is attached to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameKind {
+    World,
+    Link,
+    Joint,
+    CenterOfMass,
+    Sensor,
+}
+
+/// A frame of the scene, by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub name: String,
+    /// Pose of the frame in the world.
+    pub pose: Transform,
+}
+
+/// The frames of the scene, gathered every frame.
+#[derive(Default, Resource)]
+pub struct Frames(pub Vec<Frame>);
+
+impl Frames {
+    pub fn get(&self, name: &str) -> Option<&Frame> {
+        self.0.iter().find(|frame| frame.name == name)
+    }
+}
+
+/// Represents the configuration of the frames drawn.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FrameSettings {
+    pub world: bool,
+    pub links: bool,
+    pub joints: bool,
+    pub centers_of_mass: bool,
+    pub sensors: bool,
+    /// Length of the axes of the triads, in m.
+    pub size: f32,
+    /// Whether the triads are labeled with the names of their frames.
+    pub labels: bool,
+    /// Frames whose relative transform is queried, by name: `to` in `from`.
+    pub from: String,
+    pub to: String,
+    /// Whether the relative transform is given in the axes of Blender.
+    pub blender_axes: bool,
+}
+
+impl Default for FrameSettings {
+    fn default() -> Self {
+        Self {
+            world: false,
+            links: false,
+            joints: false,
+            centers_of_mass: false,
+            sensors: false,
+            size: 0.2,
+            labels: true,
+            from: WORLD_FRAME.to_string(),
+            to: WORLD_FRAME.to_string(),
+            blender_axes: false,
+        }
+    }
+}
+
+impl FrameSettings {
+    /// Whether the frames of a kind are drawn.
+    pub fn shows(&self, kind: FrameKind) -> bool {
+        match kind {
+            FrameKind::World => self.world,
+            FrameKind::Link => self.links,
+            FrameKind::Joint => self.joints,
+            FrameKind::CenterOfMass => self.centers_of_mass,
+            FrameKind::Sensor => self.sensors,
+        }
+    }
+}
+
+/// Pose of the frame `to` in the frame `from`, both rigid poses in the world.
+pub fn relative(from: &Transform, to: &Transform) -> Transform {
+    let inverse = from.rotation.inverse();
+    Transform {
+        translation: inverse * (to.translation - from.translation),
+        rotation: inverse * to.rotation,
+        ..default()
+    }
+}
+
+/// A transform between Y-up frames, as between the Z-up frames Blender shows them as.
+pub fn in_blender_axes(transform: &Transform) -> Transform {
+    let turn = Quat::from_rotation_x(FRAC_PI_2);
+    Transform {
+        translation: turn * transform.translation,
+        rotation: turn * transform.rotation * turn.inverse(),
+        ..default()
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<FrameSettings>("frames", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// The links, with what carries frames on them.
+type Links<'a> = (
+    &'a Link,
+    &'a GlobalTransform,
+    Option<&'a ImpulseJoint>,
+    Option<&'a ReadMassProperties>,
+    Option<&'a Imu>,
+);
+
+/// Gathers the frames of the scene.
+fn collect_frames(
+    links: Query<Links>,
+    transforms: Query<&GlobalTransform>,
+    mut frames: ResMut<Frames>,
+) {
+    frames.0.clear();
+    frames.0.push(Frame {
+        kind: FrameKind::World,
+        name: WORLD_FRAME.to_string(),
+        pose: Transform::IDENTITY,
+    });
+    let mut links: Vec<_> = links.iter().collect();
+    links.sort_by_key(|(link, ..)| link.path());
+    for (link, transform, joint, properties, imu) in links {
+        let pose = transform.compute_transform().with_scale(Vec3::ONE);
+        let path = link.path();
+        frames.0.push(Frame {
+            kind: FrameKind::Link,
+            name: path.clone(),
+            pose,
+        });
+        if let Some(joint) = joint {
+            if let Ok(parent) = transforms.get(joint.parent) {
+                let data = joint.data.as_ref();
+                let parent = parent.compute_transform().with_scale(Vec3::ONE);
+                frames.0.push(Frame {
+                    kind: FrameKind::Joint,
+                    name: format!("{path}/joint"),
+                    pose: parent.mul_transform(Transform {
+                        translation: data.local_anchor1(),
+                        rotation: data.local_basis1(),
+                        ..default()
+                    }),
+                });
+            }
+        }
+        if let Some(properties) = properties.map(|properties| properties.get()) {
+            frames.0.push(Frame {
+                kind: FrameKind::CenterOfMass,
+                name: format!("{path}/com"),
+                pose: pose.mul_transform(Transform {
+                    translation: properties.local_center_of_mass,
+                    rotation: properties.principal_inertia_local_frame,
+                    ..default()
+                }),
+            });
+        }
+        if let Some(imu) = imu {
+            frames.0.push(Frame {
+                kind: FrameKind::Sensor,
+                name: format!("{path}/imu"),
+                pose: pose.with_rotation(pose.rotation * imu.settings.mounting()),
+            });
+        }
+    }
+}
+
+/// Draws the triads of the frames shown.
+fn draw_frames(mut gizmos: Gizmos, settings: Res<Persistent<FrameSettings>>, frames: Res<Frames>) {
+    for frame in &frames.0 {
+        if settings.shows(frame.kind) {
+            gizmos.axes(frame.pose, settings.size);
+        }
+    }
+}
+
+/// Panel to toggle the frames, label them and query their relative transforms.
+fn frames_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<FrameSettings>>,
+    frames: Res<Frames>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    egui_settings: Query<&EguiSettings, With<PrimaryWindow>>,
+) {
+    let mut edited = settings.get().clone();
+    let ctx = contexts.ctx_mut();
+
+    if edited.labels {
+        // The viewport is in logical pixels, scaled by the interface scale in egui.
+        let scale = egui_settings
+            .get_single()
+            .map_or(1.0, |egui_settings| egui_settings.scale_factor);
+        let painter = ctx.layer_painter(egui::LayerId::background());
+        if let Some((camera, camera_transform)) =
+            cameras.iter().find(|(camera, _)| camera.is_active)
+        {
+            for frame in frames.0.iter().filter(|frame| edited.shows(frame.kind)) {
+                let Ok(position) =
+                    camera.world_to_viewport(camera_transform, frame.pose.translation)
+                else {
+                    continue;
+                };
+                painter.text(
+                    egui::pos2(position.x / scale, position.y / scale),
+                    egui::Align2::LEFT_BOTTOM,
+                    &frame.name,
+                    egui::FontId::monospace(12.0),
+                    ctx.style().visuals.text_color(),
+                );
+            }
+        }
+    }
+
+    egui::Window::new("Frames")
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut edited.world, "World");
+            ui.checkbox(&mut edited.links, "Links");
+            ui.checkbox(&mut edited.joints, "Joints");
+            ui.checkbox(&mut edited.centers_of_mass, "Centers of mass");
+            ui.checkbox(&mut edited.sensors, "Sensors");
+            ui.add(
+                egui::Slider::new(&mut edited.size, 0.01..=2.0)
+                    .logarithmic(true)
+                    .text("Size (m)"),
+            );
+            ui.checkbox(&mut edited.labels, "Labels");
+
+            ui.separator();
+            for (label, selected) in [("From", &mut edited.from), ("To", &mut edited.to)] {
+                egui::ComboBox::from_label(label)
+                    .selected_text(selected.as_str())
+                    .show_ui(ui, |ui| {
+                        for frame in &frames.0 {
+                            ui.selectable_value(selected, frame.name.clone(), &frame.name);
+                        }
+                    });
+            }
+            ui.checkbox(&mut edited.blender_axes, "In the axes of Blender (Z up)");
+            match (frames.get(&edited.from), frames.get(&edited.to)) {
+                (Some(from), Some(to)) => {
+                    let mut transform = relative(&from.pose, &to.pose);
+                    if edited.blender_axes {
+                        transform = in_blender_axes(&transform);
+                    }
+                    let [x, y, z] = transform.translation.to_array();
+                    ui.label(format!("Translation: ({x:.4}, {y:.4}, {z:.4}) m"));
+                    let (rx, ry, rz) = transform.rotation.to_euler(EulerRot::XYZ);
+                    ui.label(format!(
+                        "Rotation, XYZ Euler: ({:.2}, {:.2}, {:.2})°",
+                        rx.to_degrees(),
+                        ry.to_degrees(),
+                        rz.to_degrees()
+                    ));
+                    let (axis, angle) = transform.rotation.to_axis_angle();
+                    ui.label(format!(
+                        "Rotation: {:.2}° about ({:.3}, {:.3}, {:.3})",
+                        angle.to_degrees(),
+                        axis.x,
+                        axis.y,
+                        axis.z
+                    ));
+                    // Where the axes of `to` point, in `from`.
+                    for (name, axis) in ["X", "Y", "Z"].into_iter().zip(Vec3::AXES) {
+                        let [x, y, z] = (transform.rotation * axis).to_array();
+                        ui.label(format!("{name} axis: ({x:+.3}, {y:+.3}, {z:+.3})"));
+                    }
+                }
+                _ => {
+                    ui.label("Pick two frames of the scene");
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("frames", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("frames", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,441 @@
+//! This module measures the frequency response of a plant in the running simulation, for a Bode
+//! plot, instead of identifying it by hand.
+//!
+//! The experiment sweeps log-spaced frequencies: at each one, a setpoint, e.g. `motor/position`,
+//! is excited by a sine around its value at the start, for a number of whole cycles. The first
+//! cycles let the transient die out; over the next ones, the response of a telemetry channel,
+//! e.g. `motor/angle`, is correlated with a sine and a cosine of the frequency, which gives the
+//! gain and the phase of its fundamental relative to the excitation. The phases are unwrapped
+//! along the sweep. The *Frequency response* panel draws the Bode plot as the points come, and
+//! exports them to `<data dir>/frequency_responses` as CSV. The excitation is recorded as the
+//! `bode/excitation` telemetry channel.
+use std::{
+    f32::consts::TAU,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{Line, Plot, PlotPoints, Points};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    recording::{self, RecordingFormat},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::{Sample, Telemetry, MOTOR_ANGLE},
+    theme::{to_egui, Theme},
+};
+
+/// Excitation of the frequency-response experiment.
+pub const BODE_EXCITATION: &str = "bode/excitation";
+
+pub struct FrequencyResponsePlugin;
+
+impl Plugin for FrequencyResponsePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrequencyResponse>()
+            .init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                excite
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<FrequencyResponseSettings>>),
+            )
+            .add_systems(
+                Update,
+                frequency_response_panel
+                    .run_if(resource_exists::<Persistent<FrequencyResponseSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the frequency-response experiment.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FrequencyResponseSettings {
+    /// Setpoint excited.
+    pub setpoint: String,
+    /// Telemetry channel measured.
+    pub channel: String,
+    /// Amplitude of the excitation, in the unit of the setpoint.
+    pub amplitude: f32,
+    /// Frequencies of the sweep, in Hz.
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    pub points: usize,
+    /// Cycles let settle, then measured, at each frequency.
+    pub settle_cycles: u32,
+    pub measure_cycles: u32,
+}
+
+impl Default for FrequencyResponseSettings {
+    fn default() -> Self {
+        Self {
+            setpoint: MOTOR_POSITION.to_string(),
+            channel: MOTOR_ANGLE.to_string(),
+            amplitude: 0.2,
+            start_frequency: 0.1,
+            end_frequency: 10.0,
+            points: 20,
+            settle_cycles: 2,
+            measure_cycles: 4,
+        }
+    }
+}
+
+impl FrequencyResponseSettings {
+    /// The frequencies of the sweep, log-spaced from the start to the end frequency.
+    pub fn frequencies(&self) -> Vec<f32> {
+        let (start, end) = (self.start_frequency.ln(), self.end_frequency.ln());
+        match self.points {
+            0 => Vec::new(),
+            1 => vec![self.start_frequency],
+            points => (0..points)
+                .map(|index| (start + (end - start) * index as f32 / (points - 1) as f32).exp())
+                .collect(),
+        }
+    }
+
+    /// Time spent at `frequency`, in s.
+    pub fn duration(&self, frequency: f32) -> f32 {
+        (self.settle_cycles + self.measure_cycles) as f32 / frequency
+    }
+}
+
+/// Gain and phase of the fundamental at `frequency` of `samples`, on the time since the start of
+/// the excitation `amplitude · sin(2π f t)`: the gain as a ratio, the phase in rad. The samples
+/// are expected to cover whole cycles.
+pub fn fundamental(samples: &[Sample], frequency: f32, amplitude: f32) -> Option<(f32, f32)> {
+    let (first, last) = (samples.first()?[0], samples.last()?[0]);
+    if samples.len() < 2 || last <= first || amplitude == 0.0 {
+        return None;
+    }
+    let mean = samples.iter().map(|[_, value]| value).sum::<f32>() / samples.len() as f32;
+    let omega = TAU * frequency;
+    let (mut in_phase, mut quadrature) = (0.0, 0.0);
+    for pair in samples.windows(2) {
+        let ([from, a], [to, b]) = (pair[0], pair[1]);
+        // The trapezoidal rule on each interval.
+        let dt = to - from;
+        let term = |time: f32, value: f32| {
+            let (sin, cos) = (omega * time).sin_cos();
+            ((value - mean) * sin, (value - mean) * cos)
+        };
+        let ((sa, ca), (sb, cb)) = (term(from, a), term(to, b));
+        in_phase += (sa + sb) / 2.0 * dt;
+        quadrature += (ca + cb) / 2.0 * dt;
+    }
+    let scale = 2.0 / (last - first);
+    let (in_phase, quadrature) = (in_phase * scale, quadrature * scale);
+    Some((
+        in_phase.hypot(quadrature) / amplitude.abs(),
+        quadrature.atan2(in_phase) + if amplitude < 0.0 { TAU / 2.0 } else { 0.0 },
+    ))
+}
+
+/// A point of the Bode plot.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BodePoint {
+    /// In Hz.
+    pub frequency: f32,
+    /// In dB.
+    pub gain: f32,
+    /// In degrees, unwrapped along the sweep.
+    pub phase: f32,
+}
+
+/// Adds the point of `frequency` from its gain ratio and phase in rad, unwrapping the phase
+/// from the last point.
+pub fn push_point(points: &mut Vec<BodePoint>, frequency: f32, gain: f32, phase: f32) {
+    let mut phase = phase.to_degrees();
+    if let Some(last) = points.last() {
+        phase -= 360.0 * ((phase - last.phase) / 360.0).round();
+    } else if phase > 90.0 {
+        // A lag past half a turn reads as a lead.
+        phase -= 360.0;
+    }
+    points.push(BodePoint {
+        frequency,
+        gain: 20.0 * gain.max(f32::MIN_POSITIVE).log10(),
+        phase,
+    });
+}
+
+/// Writes the points of the Bode plot into `directory`, returning the path written.
+pub fn export(points: &[BodePoint], directory: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(directory).map_err(|error| Error::io(directory, error))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = directory.join(recording::file_name(now, RecordingFormat::Csv).replacen(
+        "recording",
+        "bode",
+        1,
+    ));
+    let mut csv = "frequency,gain_db,phase_deg\n".to_string();
+    for point in points {
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            point.frequency, point.gain, point.phase
+        ));
+    }
+    fs::write(&path, csv).map_err(|error| Error::io(&path, error))?;
+    Ok(path)
+}
+
+/// A sweep in progress.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sweep {
+    pub frequencies: Vec<f32>,
+    /// Index of the frequency excited, and the time it started at.
+    pub index: usize,
+    pub started: f32,
+    /// Value of the setpoint the excitation is centered on.
+    pub center: f32,
+}
+
+/// State of the experiment.
+#[derive(Debug, Default, Resource)]
+pub struct FrequencyResponse {
+    pub sweep: Option<Sweep>,
+    /// The points measured by the last sweep.
+    pub points: Vec<BodePoint>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<FrequencyResponseSettings>("frequency_response", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the excitation after each step of the simulation, and measures the response at the end
+/// of each frequency.
+fn excite(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<FrequencyResponseSettings>>,
+    mut response: ResMut<FrequencyResponse>,
+    mut setpoints: ResMut<Setpoints>,
+    mut telemetry: ResMut<Telemetry>,
+) {
+    let response = &mut *response;
+    let Some(sweep) = &mut response.sweep else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    let Some(&frequency) = sweep.frequencies.get(sweep.index) else {
+        response.sweep = None;
+        return;
+    };
+    let elapsed = time - sweep.started;
+    if elapsed >= settings.duration(frequency) {
+        let measured_from = sweep.started + settings.settle_cycles as f32 / frequency;
+        let samples: Vec<Sample> = telemetry
+            .channels
+            .get(&settings.channel)
+            .map(|samples| {
+                samples
+                    .iter()
+                    .filter(|[at, _]| *at >= measured_from && *at <= time)
+                    .map(|[at, value]| [at - sweep.started, *value])
+                    .collect()
+            })
+            .unwrap_or_default();
+        match fundamental(&samples, frequency, settings.amplitude) {
+            Some((gain, phase)) => push_point(&mut response.points, frequency, gain, phase),
+            None => warn!(
+                target: subsystem::CONTROL,
+                "No response of {} at {frequency:.3} Hz",
+                settings.channel
+            ),
+        }
+        sweep.index += 1;
+        sweep.started = time;
+        if sweep.index == sweep.frequencies.len() {
+            info!(
+                target: subsystem::CONTROL,
+                "Frequency response of {} measured at {} frequencies",
+                settings.channel,
+                response.points.len()
+            );
+            setpoints.set(&settings.setpoint, sweep.center);
+            response.sweep = None;
+            return;
+        }
+    }
+    let Some(sweep) = &response.sweep else {
+        return;
+    };
+    let frequency = sweep.frequencies[sweep.index];
+    let excitation = settings.amplitude * (TAU * frequency * (time - sweep.started)).sin();
+    setpoints.set(&settings.setpoint, sweep.center + excitation);
+    telemetry.record(BODE_EXCITATION, time, excitation);
+}
+
+/// Panel to set up the sweep, run it and draw the Bode plot.
+fn frequency_response_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<FrequencyResponseSettings>>,
+    mut response: ResMut<FrequencyResponse>,
+    mut setpoints: ResMut<Setpoints>,
+    clock: Res<SimClock>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Frequency response")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Setpoint");
+                ui.text_edit_singleline(&mut edited.setpoint);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Channel");
+                ui.text_edit_singleline(&mut edited.channel);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.amplitude)
+                    .speed(0.01)
+                    .prefix("Amplitude: "),
+            );
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.start_frequency)
+                        .speed(0.01)
+                        .range(0.001..=1000.0)
+                        .prefix("From ")
+                        .suffix(" Hz"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.end_frequency)
+                        .speed(0.1)
+                        .range(0.001..=1000.0)
+                        .prefix("to ")
+                        .suffix(" Hz"),
+                );
+            });
+            ui.add(egui::Slider::new(&mut edited.points, 2..=100).text("Frequencies"));
+            ui.add(egui::Slider::new(&mut edited.settle_cycles, 0..=20).text("Cycles settling"));
+            ui.add(egui::Slider::new(&mut edited.measure_cycles, 1..=50).text("Cycles measured"));
+            let total: f32 = edited
+                .frequencies()
+                .iter()
+                .map(|frequency| edited.duration(*frequency))
+                .sum();
+            ui.label(format!("The sweep takes {total:.1} s of simulated time"));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Start").clicked() {
+                    response.points.clear();
+                    response.sweep = Some(Sweep {
+                        frequencies: edited.frequencies(),
+                        index: 0,
+                        started: clock.elapsed_secs(),
+                        center: setpoints.get(&edited.setpoint).unwrap_or_default(),
+                    });
+                }
+                if ui.button("Stop").clicked() {
+                    if let Some(sweep) = response.sweep.take() {
+                        setpoints.set(&edited.setpoint, sweep.center);
+                    }
+                }
+                if ui
+                    .add_enabled(!response.points.is_empty(), egui::Button::new("Export"))
+                    .clicked()
+                {
+                    let directory = data_dir().join("frequency_responses");
+                    match export(&response.points, &directory) {
+                        Ok(path) => info!(
+                            target: subsystem::IO,
+                            "Frequency response exported to {}",
+                            path.display()
+                        ),
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                }
+            });
+            if let Some(sweep) = &response.sweep {
+                if let Some(frequency) = sweep.frequencies.get(sweep.index) {
+                    ui.label(format!(
+                        "Exciting at {frequency:.3} Hz ({} of {})",
+                        sweep.index + 1,
+                        sweep.frequencies.len()
+                    ));
+                }
+            }
+
+            if !response.points.is_empty() {
+                bode_plot(ui, &response.points, theme.as_deref());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("frequency_response", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("frequency_response", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Draws the gain and the phase of `points` over the logarithm of the frequency.
+fn bode_plot(ui: &mut egui::Ui, points: &[BodePoint], theme: Option<&Persistent<Theme>>) {
+    let color = theme.map(|theme| to_egui(theme.series(0)));
+    let gain: Vec<[f64; 2]> = points
+        .iter()
+        .map(|point| [f64::from(point.frequency.log10()), f64::from(point.gain)])
+        .collect();
+    let phase: Vec<[f64; 2]> = points
+        .iter()
+        .map(|point| [f64::from(point.frequency.log10()), f64::from(point.phase)])
+        .collect();
+    for (id, label, series) in [
+        ("bode_gain", "Gain (dB)", gain),
+        ("bode_phase", "Phase (°)", phase),
+    ] {
+        Plot::new(id)
+            .height(140.0)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label(label)
+            .x_axis_formatter(|mark, _| format!("{:.3}", 10f64.powf(mark.value)))
+            .show(ui, |plot_ui| {
+                let mut line = Line::new(PlotPoints::new(series.clone()));
+                let mut marks = Points::new(series).radius(2.0);
+                if let Some(color) = color {
+                    line = line.color(color);
+                    marks = marks.color(color);
+                }
+                plot_ui.line(line);
+                plot_ui.points(marks);
+            });
+    }
+}
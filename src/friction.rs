@@ -0,0 +1,391 @@
+//! This module adds the nonidealities of real joints, so controllers tuned on the frictionless
+//! model face them: friction opposing the motion of a joint, and the backlash of the gears
+//! driving it.
+//!
+//! The friction is the sum of a Coulomb friction, constant while the joint slips, a viscous
+//! friction, growing with its speed, and a stiction, the extra torque it takes to break the
+//! joint away from rest, falling off to the Coulomb friction over the Stribeck speed. Below the
+//! stick speed, the dry friction falls to nothing with the speed, so a joint pushed by less
+//! than the stiction comes to rest instead of chattering around it. The friction is applied as
+//! an impulse per step on the two links of the joint, the link and its parent, in opposite
+//! directions, and recorded as the `friction/<link>` telemetry channel.
+//!
+//! The backlash is the play of the gears between the motor and the joint: after the motor
+//! reverses, it turns freely across the play before the gears engage again and the joint is
+//! driven. The position of the motor within the play is recorded as the `backlash/<link>`
+//! telemetry channel. The nonidealities are added to the joints of the configured links, as
+//! configured in `friction.json`.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    actuators,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::{self, Link},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the friction torques.
+pub const FRICTION_PREFIX: &str = "friction/";
+/// Prefix of the telemetry channels of the positions within the backlash.
+pub const BACKLASH_PREFIX: &str = "backlash/";
+
+pub struct FrictionPlugin;
+
+impl Plugin for FrictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_friction.run_if(resource_exists::<Persistent<FrictionSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                apply_friction
+                    .before(actuators::drive)
+                    .before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(
+                Update,
+                friction_panel
+                    .run_if(resource_exists::<Persistent<FrictionSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// The nonidealities of the joint of a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct JointFriction {
+    /// Name of the link whose joint is affected, in each plant.
+    pub link: String,
+    /// Friction of the joint slipping, in N·m.
+    pub coulomb: f32,
+    /// Friction per unit of speed, in N·m·s/rad.
+    pub viscous: f32,
+    /// Friction breaking the joint away from rest, in N·m, at least the Coulomb friction.
+    pub stiction: f32,
+    /// Speed over which the stiction falls off to the Coulomb friction, in rad/s.
+    pub stribeck_speed: f32,
+    /// Speed below which the joint sticks, in rad/s.
+    pub stick_speed: f32,
+    /// Play of the gears driving the joint, in rad, or 0 for none.
+    pub backlash: f32,
+}
+
+impl Default for JointFriction {
+    fn default() -> Self {
+        Self {
+            link: "pendulum".to_string(),
+            coulomb: 0.002,
+            viscous: 0.001,
+            stiction: 0.004,
+            stribeck_speed: 0.1,
+            stick_speed: 0.05,
+            backlash: 0.0,
+        }
+    }
+}
+
+impl JointFriction {
+    /// Friction torque on the joint turning at `speed` (rad/s), in N·m.
+    pub fn torque(&self, speed: f32) -> f32 {
+        let stiction = self.stiction.max(self.coulomb) - self.coulomb;
+        let falloff = if self.stribeck_speed > 0.0 {
+            (-(speed / self.stribeck_speed).powi(2)).exp()
+        } else {
+            0.0
+        };
+        let dry = self.coulomb + stiction * falloff;
+        let slip = if self.stick_speed > 0.0 {
+            (speed / self.stick_speed).clamp(-1.0, 1.0)
+        } else if speed == 0.0 {
+            0.0
+        } else {
+            speed.signum()
+        };
+        -(dry * slip + self.viscous * speed)
+    }
+}
+
+/// Represents the configuration of the nonidealities of the joints.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FrictionSettings {
+    /// The joints; the others are ideal.
+    pub joints: Vec<JointFriction>,
+}
+
+/// The play of the gears between a motor and its joint.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Backlash {
+    /// Width of the play, in rad.
+    pub width: f32,
+    /// Position of the motor within the play, from its center, in rad.
+    gap: f32,
+}
+
+impl Backlash {
+    /// Play of `width` rad, the motor starting at its center.
+    pub fn new(width: f32) -> Self {
+        Self {
+            width: width.max(0.0),
+            gap: 0.0,
+        }
+    }
+
+    /// Position of the motor within the play, from its center, in rad.
+    pub fn gap(&self) -> f32 {
+        self.gap
+    }
+
+    /// Moves the motor turning at `command` (rad/s) against the joint turning at `speed`
+    /// (rad/s) for `dt` seconds, and returns whether the gears engage, the motor driving the
+    /// joint.
+    pub fn step(&mut self, command: f32, speed: f32, dt: f32) -> bool {
+        let half = self.width / 2.0;
+        self.gap = (self.gap + (command - speed) * dt).clamp(-half, half);
+        (self.gap >= half && command >= speed) || (self.gap <= -half && command <= speed)
+    }
+}
+
+/// The nonidealities of the revolute joint of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Friction {
+    pub model: JointFriction,
+    pub backlash: Backlash,
+    /// Telemetry channel of the friction torque.
+    pub friction_channel: String,
+    /// Telemetry channel of the position within the backlash.
+    pub backlash_channel: String,
+    /// Damping of the motor while it turns within the backlash, taken away from the joint.
+    released: Option<f32>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<FrictionSettings>("friction", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds the configured nonidealities to the joints, as they spawn or when the configuration
+/// changes.
+fn configure_friction(
+    mut commands: Commands,
+    settings: Res<Persistent<FrictionSettings>>,
+    joints: Query<(Entity, &Link, &ImpulseJoint, Option<&Friction>)>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, joint, friction) in &joints {
+        let configured = settings
+            .joints
+            .iter()
+            .find(|friction| friction.link == link.name);
+        match (configured, friction) {
+            (Some(configured), Some(friction)) if friction.model == *configured => {}
+            (Some(configured), _) => {
+                commands
+                    .entity(entity)
+                    .insert(Friction {
+                        model: configured.clone(),
+                        backlash: Backlash::new(configured.backlash),
+                        friction_channel: plants::namespaced(
+                            &link.plant,
+                            &format!("{FRICTION_PREFIX}{}", link.name),
+                        ),
+                        backlash_channel: plants::namespaced(
+                            &link.plant,
+                            &format!("{BACKLASH_PREFIX}{}", link.name),
+                        ),
+                        released: None,
+                    })
+                    .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+                // The parent takes the reaction.
+                commands
+                    .entity(joint.parent)
+                    .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+                info!(target: subsystem::CONTROL, "Friction on {}", link.path());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Friction>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Applies the friction to the joints and disengages the motors within the backlash, for the
+/// next step of the simulation.
+fn apply_friction(
+    clock: Res<SimClock>,
+    mut joints: Query<(Entity, &mut Friction, &mut ImpulseJoint, &Transform)>,
+    velocities: Query<&Velocity>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    if dt == 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (entity, mut friction, mut joint, transform) in &mut joints {
+        let angular = |entity: Entity| {
+            velocities
+                .get(entity)
+                .map_or(Vec3::ZERO, |velocity| velocity.angvel)
+        };
+        let axis = (transform.rotation * joint.data.as_ref().local_axis2()).normalize_or_zero();
+        let speed = (angular(entity) - angular(joint.parent)).dot(axis);
+
+        if let Some(motor) = joint.data.as_ref().motor(JointAxis::AngX).copied() {
+            let engaged = friction.backlash.width == 0.0
+                || friction.backlash.step(motor.target_vel, speed, dt);
+            let damping = match (engaged, friction.released) {
+                (false, _) if motor.damping > 0.0 => {
+                    friction.released = Some(motor.damping);
+                    Some(0.0)
+                }
+                // Unless a controller drove the motor since.
+                (true, Some(released)) => {
+                    friction.released = None;
+                    (motor.damping == 0.0).then_some(released)
+                }
+                _ => None,
+            };
+            if let Some(damping) = damping {
+                joint
+                    .data
+                    .as_mut()
+                    .set_motor_velocity(JointAxis::AngX, motor.target_vel, damping);
+            }
+        }
+
+        let torque = friction.model.torque(speed);
+        let torque_impulse = torque * axis * dt;
+        if let Ok(mut impulse) = impulses.get_mut(entity) {
+            impulse.torque_impulse += torque_impulse;
+        }
+        if let Ok(mut impulse) = impulses.get_mut(joint.parent) {
+            impulse.torque_impulse -= torque_impulse;
+        }
+
+        let Some(telemetry) = telemetry.as_mut() else {
+            continue;
+        };
+        telemetry.record(&friction.friction_channel, time, torque);
+        if friction.backlash.width > 0.0 {
+            telemetry.record(&friction.backlash_channel, time, friction.backlash.gap());
+        }
+    }
+}
+
+/// Panel to tune the friction and the backlash of the joints.
+fn friction_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<FrictionSettings>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Friction")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut removed = None;
+            for (index, joint) in edited.joints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut joint.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                egui::Grid::new(("friction", index)).show(ui, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut joint.coulomb)
+                            .range(0.0..=100.0)
+                            .speed(0.001)
+                            .prefix("Coulomb: ")
+                            .suffix(" N·m"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut joint.viscous)
+                            .range(0.0..=100.0)
+                            .speed(0.001)
+                            .prefix("Viscous: ")
+                            .suffix(" N·m·s/rad"),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut joint.stiction)
+                            .range(0.0..=100.0)
+                            .speed(0.001)
+                            .prefix("Stiction: ")
+                            .suffix(" N·m"),
+                    )
+                    .on_hover_text("Torque breaking the joint away from rest");
+                    ui.add(
+                        egui::DragValue::new(&mut joint.stribeck_speed)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Stribeck speed: ")
+                            .suffix(" rad/s"),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut joint.stick_speed)
+                            .range(0.0..=10.0)
+                            .speed(0.001)
+                            .prefix("Stick speed: ")
+                            .suffix(" rad/s"),
+                    )
+                    .on_hover_text("Widen it if the joint chatters at rest");
+                    ui.add(
+                        egui::DragValue::new(&mut joint.backlash)
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .prefix("Backlash: ")
+                            .suffix(" rad"),
+                    );
+                    ui.end_row();
+                });
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.joints.remove(index);
+            }
+            if ui.button("Add a joint").clicked() {
+                edited.joints.push(JointFriction::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("friction", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("friction", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
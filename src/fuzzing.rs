@@ -0,0 +1,420 @@
+//! Scenario fuzzing: random but bounded scenarios are run headlessly against the controllers,
+//! searching for failures, and the failing scenarios are minimized into small scripts that
+//! replay them.
+//!
+//! A [`Scenario`] sets some setpoints to values drawn within their bounds (the parameters),
+//! then kicks links with torque impulses: a kick at the start sets the initial state of the
+//! plant, kicks at random times are disturbances. A scenario fails when a [`Requirement`] on
+//! a telemetry channel is violated, or when a channel stops being finite. The bounds and the
+//! requirements are read from `fuzzing.json`.
+//!
+//! A failing scenario is minimized by cutting it short after the failure, dropping its kicks
+//! and its parameters one at a time, then halving its kicks, as long as it still fails. Runs are
+//! deterministic, so the saved script fails again when replayed.
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch,
+    clock::SimClock,
+    error::{Error, Result},
+    logging::subsystem,
+    plants::{Link, Plant},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Represents the configuration of the fuzzer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct FuzzSettings {
+    /// Number of scenarios generated.
+    pub runs: usize,
+    /// Seed of the first scenario; the others follow from it.
+    pub seed: u64,
+    /// Simulated duration of a scenario, in s.
+    pub duration: f32,
+    /// Setpoints drawn at the start of a scenario.
+    pub parameters: Vec<Bound>,
+    /// Paths of the links kicked.
+    pub links: Vec<String>,
+    /// Largest torque impulse of the kick at the start, in N·m·s, or 0 for none.
+    pub initial: f32,
+    /// Most kicks of a scenario after the start.
+    pub kicks: usize,
+    /// Largest torque impulse of a kick after the start, in N·m·s.
+    pub impulse: f32,
+    pub requirements: Vec<Requirement>,
+    /// Most runs spent minimizing a failing scenario.
+    pub shrink_runs: usize,
+}
+
+impl Default for FuzzSettings {
+    fn default() -> Self {
+        Self {
+            runs: 100,
+            seed: 1,
+            duration: 10.0,
+            parameters: Vec::new(),
+            links: vec!["pendulum".to_string()],
+            initial: 1.0,
+            kicks: 3,
+            impulse: 2.0,
+            requirements: Vec::new(),
+            shrink_runs: 50,
+        }
+    }
+}
+
+/// Range of the values drawn for a setpoint.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Bound {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Bounds of a telemetry channel, checked from `after` seconds on.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Requirement {
+    pub channel: String,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+    #[serde(default)]
+    pub after: f32,
+}
+
+impl Requirement {
+    /// Whether `value`, sampled at `time`, violates the requirement.
+    pub fn violated(&self, time: f32, value: f32) -> bool {
+        time >= self.after
+            && (self.min.is_some_and(|min| value < min) || self.max.is_some_and(|max| value > max))
+    }
+
+    fn describe(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{min} <= {} <= {max}", self.channel),
+            (Some(min), None) => format!("{} >= {min}", self.channel),
+            (None, Some(max)) => format!("{} <= {max}", self.channel),
+            (None, None) => self.channel.clone(),
+        }
+    }
+}
+
+/// A torque impulse on a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Kick {
+    /// Simulated time of the kick, in s.
+    pub time: f32,
+    /// Path of the link kicked.
+    pub link: String,
+    /// Torque impulse, in N·m·s.
+    pub impulse: Vec3,
+}
+
+/// A scenario run against the controllers.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Scenario {
+    pub seed: u64,
+    /// Simulated duration, in s.
+    pub duration: f32,
+    /// Setpoints set at the start.
+    pub parameters: BTreeMap<String, f32>,
+    /// Kicks, in time order.
+    pub kicks: Vec<Kick>,
+}
+
+/// A violation found while running a scenario.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Failure {
+    /// The requirement violated, e.g. `pendulum/angle <= 0.5`.
+    pub requirement: String,
+    pub time: f32,
+    /// Value of the channel, unless it isn't finite.
+    pub value: Option<f32>,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} violated at {:.3} s", self.requirement, self.time)?;
+        match self.value {
+            Some(value) => write!(f, " by {value}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A minimized failing scenario, with what it violates, saved to replay it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FailureReport {
+    pub requirements: Vec<Requirement>,
+    pub scenario: Scenario,
+    pub failure: Failure,
+}
+
+impl FailureReport {
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+/// xorshift64 draws, in [0, 1).
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub(crate) fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+/// The `index`th scenario of the fuzzer.
+pub fn generate(settings: &FuzzSettings, index: usize) -> Scenario {
+    // Spreads the seeds of consecutive scenarios over the whole state.
+    let seed = settings
+        .seed
+        .wrapping_add(index as u64)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let mut random = Random::new(seed);
+    let parameters = settings
+        .parameters
+        .iter()
+        .map(|bound| (bound.name.clone(), random.range(bound.min, bound.max)))
+        .collect();
+    let mut kicks = Vec::new();
+    let mut kick = |random: &mut Random, time: f32, impulse: f32| {
+        let link = (random.next() * settings.links.len() as f32) as usize;
+        let direction = Vec3::new(
+            random.range(-1.0, 1.0),
+            random.range(-1.0, 1.0),
+            random.range(-1.0, 1.0),
+        );
+        kicks.push(Kick {
+            time,
+            link: settings.links[link.min(settings.links.len() - 1)].clone(),
+            impulse: direction.normalize_or_zero() * random.range(0.0, impulse),
+        });
+    };
+    if !settings.links.is_empty() {
+        if settings.initial > 0.0 {
+            kick(&mut random, 0.0, settings.initial);
+        }
+        let count = (random.next() * (settings.kicks + 1) as f32) as usize;
+        for _ in 0..count.min(settings.kicks) {
+            let time = random.range(0.0, settings.duration);
+            kick(&mut random, time, settings.impulse);
+        }
+    }
+    kicks.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Scenario {
+        seed,
+        duration: settings.duration,
+        parameters,
+        kicks,
+    }
+}
+
+/// Runs `scenario` on `plant` and its controllers, stepping by `dt`, up to the first violation
+/// of the requirements, if any.
+pub fn check(
+    plant: &Plant,
+    scenario: &Scenario,
+    requirements: &[Requirement],
+    dt: f32,
+) -> Result<Option<Failure>> {
+    let mut app = batch::app(plant, dt, scenario.seed);
+    let mut setpoints = app.world_mut().resource_mut::<Setpoints>();
+    for (name, value) in &scenario.parameters {
+        setpoints.set(name, *value);
+    }
+    let steps = (scenario.duration / dt).round() as usize;
+    let mut kicks = scenario.kicks.iter().peekable();
+    for step in 0..steps {
+        app.update();
+        if app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "fuzzing".to_string(),
+                message: format!("{} stopped on the error logged above", plant.name),
+            });
+        }
+        let world = app.world();
+        let time = world.resource::<SimClock>().elapsed_secs();
+        let telemetry = world.resource::<Telemetry>();
+        for requirement in requirements {
+            if let Some(value) = telemetry.latest(&requirement.channel) {
+                if requirement.violated(time, value) {
+                    return Ok(Some(Failure {
+                        requirement: requirement.describe(),
+                        time,
+                        value: Some(value),
+                    }));
+                }
+            }
+        }
+        for (channel, samples) in &telemetry.channels {
+            if samples.last().is_some_and(|[_, value]| !value.is_finite()) {
+                return Ok(Some(Failure {
+                    requirement: format!("{channel} is finite"),
+                    time,
+                    value: None,
+                }));
+            }
+        }
+        // The plant is spawned by the first update, so the kicks at the start land on the
+        // second step.
+        let world = app.world_mut();
+        while let Some(kick) = kicks.next_if(|kick| kick.time < (step + 2) as f32 * dt) {
+            apply(world, kick);
+        }
+    }
+    Ok(None)
+}
+
+/// Adds the impulse of `kick` to the next step of its link.
+pub(crate) fn apply(world: &mut World, kick: &Kick) {
+    let Some(entity) = world
+        .query::<(Entity, &Link)>()
+        .iter(world)
+        .find(|(_, link)| link.path() == kick.link)
+        .map(|(entity, _)| entity)
+    else {
+        warn!(target: subsystem::PHYSICS, "No link {} to kick", kick.link);
+        return;
+    };
+    match world.get_mut::<ExternalImpulse>(entity) {
+        Some(mut impulse) => impulse.torque_impulse += kick.impulse,
+        None => {
+            world.entity_mut(entity).insert(ExternalImpulse {
+                impulse: Vec3::ZERO,
+                torque_impulse: kick.impulse,
+            });
+        }
+    }
+}
+
+/// Runs candidate scenarios while the budget of runs lasts.
+struct Shrinker<'a> {
+    plant: &'a Plant,
+    requirements: &'a [Requirement],
+    dt: f32,
+    runs: usize,
+}
+
+impl Shrinker<'_> {
+    /// The failure of `candidate`, if it still fails and the budget allows running it.
+    fn fails(&mut self, candidate: &Scenario) -> Result<Option<Failure>> {
+        if self.runs == 0 {
+            return Ok(None);
+        }
+        self.runs -= 1;
+        check(self.plant, candidate, self.requirements, self.dt)
+    }
+}
+
+/// Minimizes a scenario failing with `failure` into a smaller one that still fails.
+pub fn minimize(
+    plant: &Plant,
+    settings: &FuzzSettings,
+    dt: f32,
+    mut scenario: Scenario,
+    mut failure: Failure,
+) -> Result<(Scenario, Failure)> {
+    let mut shrinker = Shrinker {
+        plant,
+        requirements: &settings.requirements,
+        dt,
+        runs: settings.shrink_runs,
+    };
+
+    // Nothing after the failure matters.
+    let mut candidate = scenario.clone();
+    candidate.duration = (failure.time + dt).min(scenario.duration);
+    candidate.kicks.retain(|kick| kick.time <= failure.time);
+    if candidate != scenario {
+        if let Some(found) = shrinker.fails(&candidate)? {
+            (scenario, failure) = (candidate, found);
+        }
+    }
+
+    let mut index = 0;
+    while index < scenario.kicks.len() {
+        let mut candidate = scenario.clone();
+        candidate.kicks.remove(index);
+        match shrinker.fails(&candidate)? {
+            Some(found) => (scenario, failure) = (candidate, found),
+            None => index += 1,
+        }
+    }
+
+    let names: Vec<String> = scenario.parameters.keys().cloned().collect();
+    for name in names {
+        let mut candidate = scenario.clone();
+        candidate.parameters.remove(&name);
+        if let Some(found) = shrinker.fails(&candidate)? {
+            (scenario, failure) = (candidate, found);
+        }
+    }
+
+    for index in 0..scenario.kicks.len() {
+        loop {
+            let mut candidate = scenario.clone();
+            candidate.kicks[index].impulse /= 2.0;
+            match shrinker.fails(&candidate)? {
+                Some(found) => (scenario, failure) = (candidate, found),
+                None => break,
+            }
+        }
+    }
+    Ok((scenario, failure))
+}
+
+/// Runs the scenarios of `settings` on `plant`, stepping by `dt`, and returns the minimized
+/// failures, calling `on_failure` with each one as it's found.
+pub fn fuzz(
+    plant: &Plant,
+    settings: &FuzzSettings,
+    dt: f32,
+    mut on_failure: impl FnMut(usize, &FailureReport) -> Result<()>,
+) -> Result<Vec<FailureReport>> {
+    let mut reports = Vec::new();
+    for index in 0..settings.runs {
+        let scenario = generate(settings, index);
+        let Some(failure) = check(plant, &scenario, &settings.requirements, dt)? else {
+            continue;
+        };
+        let (scenario, failure) = minimize(plant, settings, dt, scenario, failure)?;
+        let report = FailureReport {
+            requirements: settings.requirements.clone(),
+            scenario,
+            failure,
+        };
+        on_failure(index, &report)?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
@@ -0,0 +1,398 @@
+//! This module provides the mapping of the gamepads onto teleoperation commands, and a panel to
+//! edit it, since no hardcoded mapping matches every controller.
+//!
+//! Each command drives a setpoint from an axis or a button of the gamepads: the travel of the
+//! input past its deadzone, within [-1, 1] for an axis and [0, 1] for a button, is scaled to the
+//! full-scale value of the command. The *Gamepad mapping* panel lists the inputs the connected
+//! gamepads have reported, with their live values; dragging one onto a command binds it. As with
+//! the jog, a command only writes its setpoint while its input is moved, and once more as it comes
+//! back to rest, leaving the setpoint to the other drivers otherwise.
+//!
+//! The inputs are read once per rendered frame, but the commands are sampled on a fixed schedule
+//! of the simulated time, `sample_rate` times per second: each tick holds the input last read
+//! before it, or interpolates linearly between the reads around it, and the setpoint keeps the
+//! value of the last tick. The commands then change at the same simulated times whatever the
+//! refresh rate of the display, and their samples are recorded as the `teleop/<setpoint>`
+//! telemetry channels, a dataset at a fixed rate, e.g. for imitation learning.
+use bevy::{
+    input::gamepad::{GamepadAxis, GamepadInput},
+    prelude::*,
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+
+/// Most ticks sampled at once, e.g. after the clock jumped.
+const MAX_TICKS: u64 = 1_000;
+
+pub struct GamepadMappingPlugin;
+
+impl Plugin for GamepadMappingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WrittenCommands>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                apply_commands
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<GamepadMapping>>),
+            )
+            .add_systems(
+                Update,
+                gamepad_mapping_panel
+                    .run_if(resource_exists::<Persistent<GamepadMapping>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A setpoint driven by an input of the gamepads.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct TeleopCommand {
+    pub name: String,
+    pub setpoint: String,
+    /// Axis or button driving the setpoint, if bound.
+    pub input: Option<GamepadInput>,
+    /// Value of the setpoint at the end of the travel of the input.
+    pub scale: f32,
+    /// Travel from rest ignored, as a fraction of the full travel.
+    pub deadzone: f32,
+    pub invert: bool,
+}
+
+impl Default for TeleopCommand {
+    fn default() -> Self {
+        Self {
+            name: "Command".to_string(),
+            setpoint: String::new(),
+            input: None,
+            scale: 1.0,
+            deadzone: 0.1,
+            invert: false,
+        }
+    }
+}
+
+impl TeleopCommand {
+    /// Value of the setpoint for a position of the input: zero within the deadzone, then rising
+    /// linearly to the full-scale value at the end of the travel.
+    pub fn value(&self, position: f32) -> f32 {
+        let sign = if self.invert { -1.0 } else { 1.0 };
+        sign * travel(position, self.deadzone) * self.scale
+    }
+}
+
+/// Travel of an input past its `deadzone`, within [-1, 1], with the sign of its position.
+pub fn travel(position: f32, deadzone: f32) -> f32 {
+    if !position.is_finite() {
+        return 0.0;
+    }
+    let deadzone = deadzone.clamp(0.0, 0.99);
+    let position = position.clamp(-1.0, 1.0);
+    let travel = ((position.abs() - deadzone) / (1.0 - deadzone)).max(0.0);
+    if travel == 0.0 {
+        return 0.0;
+    }
+    position.signum() * travel
+}
+
+/// Represents the mapping of the gamepads, from the `gamepad_mapping.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct GamepadMapping {
+    pub enabled: bool,
+    pub commands: Vec<TeleopCommand>,
+    /// Ticks per second of simulated time the commands are sampled at, or 0 to sample them once
+    /// per frame.
+    pub sample_rate: f32,
+    /// Whether the ticks interpolate between the reads of the inputs, rather than hold the last.
+    pub interpolate: bool,
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            commands: vec![TeleopCommand {
+                name: "Motor".to_string(),
+                setpoint: MOTOR_VELOCITY.to_string(),
+                input: Some(GamepadInput::Axis(GamepadAxis::LeftStickX)),
+                scale: 10.0,
+                ..default()
+            }],
+            sample_rate: 100.0,
+            interpolate: false,
+        }
+    }
+}
+
+/// Samples an input read once per frame on the ticks of a fixed schedule of the simulated time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputSampler {
+    /// The last read of the input, and the time of the read.
+    previous: Option<(f64, f32)>,
+    /// The last tick sampled, and its value.
+    last: Option<(u64, f32)>,
+}
+
+impl InputSampler {
+    /// Reads `value` at `time`, returning the time and the value of each tick of `period` since
+    /// the last read: the input held from the read before the tick, or interpolated between the
+    /// reads around it.
+    pub fn read(
+        &mut self,
+        time: f64,
+        value: f32,
+        period: f64,
+        interpolate: bool,
+    ) -> Vec<(f64, f32)> {
+        if self.previous.is_some_and(|(previous, _)| time < previous) {
+            // The clock went back, e.g. to a restored session.
+            *self = Self::default();
+        }
+        let mut samples = Vec::new();
+        if period > 0.0 && time >= 0.0 {
+            let tick = (time / period + 1e-9).floor() as u64;
+            let first = self.last.map_or(tick, |(last, _)| last + 1);
+            for tick in first.max(tick.saturating_sub(MAX_TICKS))..=tick {
+                let at = tick as f64 * period;
+                let sampled = match self.previous {
+                    Some((from, previous)) if at < time => {
+                        if interpolate && time > from {
+                            let ratio = ((at - from) / (time - from)).clamp(0.0, 1.0) as f32;
+                            previous + (value - previous) * ratio
+                        } else {
+                            previous
+                        }
+                    }
+                    _ => value,
+                };
+                samples.push((at, sampled));
+                self.last = Some((tick, sampled));
+            }
+        }
+        self.previous = Some((time, value));
+        samples
+    }
+
+    /// Value of the last tick sampled.
+    pub fn value(&self) -> Option<f32> {
+        self.last.map(|(_, value)| value)
+    }
+}
+
+/// Name of an input, as listed in the panel.
+pub fn input_label(input: GamepadInput) -> String {
+    match input {
+        GamepadInput::Axis(axis) => format!("{axis:?} axis"),
+        GamepadInput::Button(button) => format!("{button:?} button"),
+    }
+}
+
+/// Position of an input: that of the gamepad moving it the furthest from rest.
+pub fn input_position<'a>(
+    gamepads: impl IntoIterator<Item = &'a Gamepad>,
+    input: GamepadInput,
+) -> f32 {
+    gamepads
+        .into_iter()
+        .filter_map(|gamepad| gamepad.get(input))
+        .fold(0.0, |position: f32, value| {
+            if value.abs() > position.abs() {
+                value
+            } else {
+                position
+            }
+        })
+}
+
+/// The value last written to the setpoint of each command, and the sampler of its input.
+#[derive(Default, Resource)]
+struct WrittenCommands(Vec<(Option<f32>, InputSampler)>);
+
+fn setup(mut commands: Commands) {
+    let (mapping, error) = config_plugin::load_config::<GamepadMapping>("gamepad_mapping", true);
+    commands.insert_resource(mapping);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the setpoints of the commands from the inputs they are bound to, sampled on the ticks
+/// of the simulated time, after each step of the simulation.
+fn apply_commands(
+    clock: Res<SimClock>,
+    mapping: Res<Persistent<GamepadMapping>>,
+    gamepads: Query<&Gamepad>,
+    mut written: ResMut<WrittenCommands>,
+    mut setpoints: ResMut<Setpoints>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    if mapping.is_changed() {
+        written.0.clear();
+    }
+    written.0.resize(mapping.commands.len(), default());
+    for (command, (written, sampler)) in mapping.commands.iter().zip(&mut written.0) {
+        let read = match command.input {
+            Some(input) if mapping.enabled => command.value(input_position(&gamepads, input)),
+            _ => 0.0,
+        };
+        let (value, samples) = if mapping.sample_rate > 0.0 {
+            let period = 1.0 / f64::from(mapping.sample_rate);
+            let samples = sampler.read(clock.elapsed_secs_f64(), read, period, mapping.interpolate);
+            (sampler.value().unwrap_or(read), samples)
+        } else {
+            (read, Vec::new())
+        };
+        if value == 0.0 && written.is_none_or(|written| written == 0.0) {
+            // Leave the setpoint to the keyboard and the other drivers.
+            *written = None;
+            continue;
+        }
+        if setpoints.get(&command.setpoint) != Some(value) {
+            setpoints.set(&command.setpoint, value);
+        }
+        *written = Some(value);
+        if let Some(telemetry) = telemetry.as_mut() {
+            let channel = format!("teleop/{}", command.setpoint);
+            for (time, value) in samples {
+                telemetry.record(&channel, time as f32, value);
+            }
+        }
+    }
+}
+
+/// Panel to bind the inputs of the gamepads to the commands.
+fn gamepad_mapping_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut mapping: ResMut<Persistent<GamepadMapping>>,
+    gamepads: Query<(Entity, &Gamepad, Option<&Name>)>,
+) {
+    let mut edited = mapping.get().clone();
+
+    egui::Window::new("Gamepad mapping")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.sample_rate)
+                        .speed(1.0)
+                        .range(0.0..=1000.0)
+                        .prefix("Sampled at ")
+                        .suffix(" Hz"),
+                )
+                .on_hover_text("Of simulated time, whatever the frame rate; 0 samples every frame");
+                ui.checkbox(&mut edited.interpolate, "Interpolate");
+            });
+
+            ui.separator();
+            ui.label("Drag an input onto a command to bind it.");
+            if gamepads.is_empty() {
+                ui.label("No gamepad connected");
+            }
+            for (entity, gamepad, name) in &gamepads {
+                ui.strong(name.map_or_else(|| format!("Gamepad {entity}"), Name::to_string));
+                // Inputs only show up once moved, as most drivers don't list them.
+                let mut inputs: Vec<_> = gamepad.analog().all_axes().copied().collect();
+                inputs.sort_by_key(|input| input_label(*input));
+                if inputs.is_empty() {
+                    ui.label("Move a stick or press a button");
+                }
+                for input in inputs {
+                    let value = gamepad.get(input).unwrap_or_default();
+                    let fill = match input {
+                        GamepadInput::Axis(_) => (value + 1.0) / 2.0,
+                        GamepadInput::Button(_) => value,
+                    };
+                    let id = egui::Id::new(("gamepad input", entity, input));
+                    ui.dnd_drag_source(id, input, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::ProgressBar::new(fill).desired_width(80.0));
+                            ui.label(format!("{} {value:+.2}", input_label(input)));
+                        });
+                    });
+                }
+            }
+
+            ui.separator();
+            let mut removed = None;
+            for (index, command) in edited.commands.iter_mut().enumerate() {
+                let frame = egui::Frame::group(ui.style());
+                let (_, dropped) = ui.dnd_drop_zone::<GamepadInput, ()>(frame, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut command.name);
+                        if ui.button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Setpoint");
+                        ui.text_edit_singleline(&mut command.setpoint);
+                    });
+                    ui.horizontal(|ui| match command.input {
+                        Some(input) => {
+                            ui.label(input_label(input));
+                            if ui.button("Unbind").clicked() {
+                                command.input = None;
+                            }
+                        }
+                        None => {
+                            ui.label("Unbound: drop an input here");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut command.scale)
+                                .speed(0.1)
+                                .prefix("Full scale: "),
+                        );
+                        ui.checkbox(&mut command.invert, "Invert");
+                    });
+                    ui.add(egui::Slider::new(&mut command.deadzone, 0.0..=0.5).text("Deadzone"));
+                });
+                if let Some(input) = dropped {
+                    command.input = Some(*input);
+                }
+            }
+            if let Some(index) = removed {
+                edited.commands.remove(index);
+            }
+            if ui.button("Add a command").clicked() {
+                edited.commands.push(TeleopCommand::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = mapping.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("gamepad_mapping", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = mapping.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("gamepad_mapping", error)));
+                    }
+                    edited = mapping.get().clone();
+                }
+            });
+        });
+
+    if edited != *mapping.get() {
+        *mapping.get_mut() = edited;
+    }
+}
@@ -0,0 +1,253 @@
+//! The gantry: a beam carried by two carriages on parallel rails, each driven by a motor of its
+//! own, as the axes of gantry robots and of large machine tools.
+//!
+//! The carriages slide along their rails on prismatic joints, driven by forces, the
+//! `left/force` and `right/force` setpoints in N within their limit, against the friction of
+//! their rails, higher on the right one. The beam ties them together: it resists their racking,
+//! the difference of their positions, like a spring of [`BEAM_STIFFNESS`], so a carriage pulls
+//! the other along as it leads. The positions of the carriages along their rails, in m, are
+//! recorded as the `left/position` and `right/position` telemetry channels, and their racking,
+//! in m, as `beam/racking`.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody},
+    sensors::ControlSet,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Force of the motor of the left carriage, in N.
+pub const LEFT_FORCE: &str = "left/force";
+/// Force of the motor of the right carriage, in N.
+pub const RIGHT_FORCE: &str = "right/force";
+/// Position of the left carriage along its rail, in m.
+pub const LEFT_POSITION: &str = "left/position";
+/// Position of the right carriage along its rail, in m.
+pub const RIGHT_POSITION: &str = "right/position";
+/// Position of the left carriage ahead of the right one, in m.
+pub const BEAM_RACKING: &str = "beam/racking";
+/// Half of the travel of the carriages along their rails, in m.
+pub const RAIL_HALF_LENGTH: f32 = 2.0;
+/// Largest force of the motors, in N.
+pub const FORCE_LIMIT: f32 = 100.0;
+/// Force of the beam against the racking of the carriages, in N/m.
+pub const BEAM_STIFFNESS: f32 = 200.0;
+
+/// The gantry, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "gantry",
+        add: |app| {
+            app.add_plugins(GantryPlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<GantryPlugin>() {
+                app.add_plugins(GantryPlugin);
+            }
+            app.insert_resource(GantryInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(GantryInstances::default());
+            plants::run_spawn(world, spawn_gantries);
+        },
+    }
+}
+
+pub struct GantryPlugin;
+
+impl Plugin for GantryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GantryInstances>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, spawn_gantries)
+            .add_systems(
+                PostUpdate,
+                drive_carriages
+                    .after(ControlSet)
+                    .before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(Update, record_carriage_positions);
+    }
+}
+
+/// The gantries spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct GantryInstances(pub Vec<Instance>);
+
+impl Default for GantryInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The beam of a gantry, on the entity of its left carriage.
+#[derive(Component)]
+struct Beam {
+    /// The right carriage.
+    right: Entity,
+    /// Position of the middle of the rails in the world.
+    origin: Vec3,
+    /// Signals of the instance.
+    forces: [String; 2],
+    positions: [String; 2],
+    racking: String,
+}
+
+fn spawn_gantries(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<GantryInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const RAIL_HEIGHT: f32 = 0.5;
+    const SPAN: f32 = 1.0;
+    const CARRIAGE_SIZE: Vec3 = Vec3::new(0.4, 0.2, 0.3);
+    const CARRIAGE_MASS: f32 = 2.0;
+    /// Damping of the velocity of each carriage by the friction of its rail, in 1/s.
+    const FRICTION: [f32; 2] = [2.0, 6.0];
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let origin = instance.offset + Vec3::Y * RAIL_HEIGHT;
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        let [left, right] = [("left", -1.0), ("right", 1.0)].map(|(name, side)| {
+            let rail_center = origin + Vec3::Z * side * SPAN / 2.0;
+            let rail = commands
+                .spawn((
+                    RigidBody::Fixed,
+                    PlantBody,
+                    Mesh3d(meshes.add(Cuboid::new(2.0 * RAIL_HALF_LENGTH, 0.05, 0.05))),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_translation(rail_center),
+                ))
+                .id();
+            let friction = FRICTION[usize::from(side > 0.0)];
+            let carriage = commands
+                .spawn((
+                    RigidBody::Dynamic,
+                    link(name),
+                    Collider::cuboid(
+                        CARRIAGE_SIZE.x / 2.0,
+                        CARRIAGE_SIZE.y / 2.0,
+                        CARRIAGE_SIZE.z / 2.0,
+                    ),
+                    ColliderMassProperties::Mass(CARRIAGE_MASS),
+                    Damping {
+                        linear_damping: friction,
+                        angular_damping: 0.0,
+                    },
+                    Velocity::default(),
+                    ExternalImpulse::default(),
+                    Mesh3d(meshes.add(Cuboid::from_size(CARRIAGE_SIZE))),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_translation(rail_center),
+                ))
+                .id();
+            let slider =
+                PrismaticJointBuilder::new(Vec3::X).limits([-RAIL_HALF_LENGTH, RAIL_HALF_LENGTH]);
+            commands
+                .entity(carriage)
+                .insert(ImpulseJoint::new(rail, slider));
+            carriage
+        });
+        // The beam is drawn across from the left carriage; its mass is shared by the carriages,
+        // and its stiffness applied by `drive_carriages`.
+        let beam = commands
+            .spawn((
+                Mesh3d(meshes.add(Cuboid::new(0.1, 0.1, SPAN))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(Vec3::new(0.0, 0.15, SPAN / 2.0)),
+            ))
+            .id();
+        commands.entity(left).add_child(beam).insert(Beam {
+            right,
+            origin,
+            forces: [LEFT_FORCE, RIGHT_FORCE].map(|signal| instance.signal(signal)),
+            positions: [LEFT_POSITION, RIGHT_POSITION].map(|signal| instance.signal(signal)),
+            racking: instance.signal(BEAM_RACKING),
+        });
+    }
+}
+
+/// Applies the forces of the motors and of the beams, for the next step of the simulation.
+fn drive_carriages(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    beams: Query<(Entity, &Beam)>,
+    transforms: Query<&Transform>,
+    mut impulses: Query<&mut ExternalImpulse>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "drive_carriages").entered();
+    let dt = clock.delta_secs();
+    if dt == 0.0 {
+        return;
+    }
+    for (left, beam) in &beams {
+        let (Ok(left_transform), Ok(right_transform)) =
+            (transforms.get(left), transforms.get(beam.right))
+        else {
+            continue;
+        };
+        let racking =
+            BEAM_STIFFNESS * (left_transform.translation.x - right_transform.translation.x);
+        for ((entity, signal), reaction) in [left, beam.right]
+            .into_iter()
+            .zip(&beam.forces)
+            .zip([-racking, racking])
+        {
+            let force = setpoints
+                .get(signal)
+                .unwrap_or_default()
+                .clamp(-FORCE_LIMIT, FORCE_LIMIT);
+            // The rails are fixed, so they need no reaction.
+            if let Ok(mut impulse) = impulses.get_mut(entity) {
+                impulse.impulse += (force + reaction) * Vec3::X * dt;
+            }
+        }
+    }
+}
+
+/// Records the positions of the carriages and their racking.
+fn record_carriage_positions(
+    clock: Res<SimClock>,
+    beams: Query<(Entity, &Beam)>,
+    transforms: Query<&Transform>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_carriage_positions").entered();
+    let Some(telemetry) = telemetry.as_mut() else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    for (left, beam) in &beams {
+        let (Ok(left_transform), Ok(right_transform)) =
+            (transforms.get(left), transforms.get(beam.right))
+        else {
+            continue;
+        };
+        let positions = [left_transform, right_transform]
+            .map(|transform| transform.translation.x - beam.origin.x);
+        for (channel, position) in beam.positions.iter().zip(positions) {
+            telemetry.record(channel, time, position);
+        }
+        telemetry.record(&beam.racking, time, positions[0] - positions[1]);
+    }
+}
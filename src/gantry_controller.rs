@@ -0,0 +1,366 @@
+//! This module drives the two parallel axes of a gantry together with a [`CrossCoupling`]: a
+//! position loop on each carriage, coupled through their synchronization error, so the beam
+//! they carry stays square as they move.
+//!
+//! A [`GantryController`] on the entity of the joint of the first carriage measures the
+//! positions of both carriages along their rails after each physics step and applies the forces
+//! of their motors for the next step. Both follow the `gantry/position` setpoint of the plant, in
+//! m; the contour error, the difference of their tracking errors, is recorded as the
+//! `gantry/contour_error` telemetry channel.
+//!
+//! The controllers are added to the joints of the configured first carriage when enabled, next
+//! to the joint of the configured second one in the same plant, and take the configured gains
+//! whenever they change.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{CrossCoupling, Pid, PidGains, Saturation},
+    error::{Error, ErrorEvent},
+    estimation::EstimationSet,
+    gantry::FORCE_LIMIT,
+    logging::subsystem,
+    plants::{self, Link},
+    sensors::{ControlSet, SensorSet},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Position of the gantry along its rails, in m.
+pub const GANTRY_POSITION: &str = "gantry/position";
+/// Synchronization error of the carriages, in m.
+pub const CONTOUR_ERROR: &str = "gantry/contour_error";
+
+pub struct GantryControllerPlugin;
+
+impl Plugin for GantryControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_controllers.run_if(resource_exists::<Persistent<GantrySettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                gantry_panel
+                    .run_if(resource_exists::<Persistent<GantrySettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the coupled loops of the gantries.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct GantrySettings {
+    pub enabled: bool,
+    /// Names of the links of the carriages, whose joints slide, in each plant.
+    pub first: String,
+    pub second: String,
+    /// Gains of the loop of each carriage, from its error, in m, to its force.
+    pub axis: PidGains,
+    /// Gains of the coupling, from the contour error, in m, to the correction of the forces.
+    pub coupling: PidGains,
+    /// Largest force commanded, in N, or 0 for an unlimited one.
+    pub force_limit: f32,
+}
+
+impl Default for GantrySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            first: "left".to_string(),
+            second: "right".to_string(),
+            axis: PidGains {
+                kp: 100.0,
+                ki: 20.0,
+                kd: 20.0,
+            },
+            coupling: PidGains {
+                kp: 200.0,
+                ki: 100.0,
+                kd: 5.0,
+            },
+            force_limit: FORCE_LIMIT,
+        }
+    }
+}
+
+impl GantrySettings {
+    /// The coupled loops of the settings.
+    pub fn cross_coupling(&self) -> CrossCoupling {
+        let limits = if self.force_limit > 0.0 {
+            Saturation::symmetric(self.force_limit)
+        } else {
+            Saturation::default()
+        };
+        let axis = Pid::new(self.axis, limits);
+        CrossCoupling::parallel(
+            [axis.clone(), axis],
+            Pid::new(self.coupling, Saturation::default()),
+        )
+    }
+}
+
+/// The coupled loops of a gantry, on the entity of the joint of its first carriage.
+#[derive(Clone, Component, Debug)]
+pub struct GantryController {
+    pub controller: CrossCoupling,
+    /// Joint of the second carriage.
+    pub second: Entity,
+    /// Setpoint of the position of the carriages, in m.
+    pub setpoint: String,
+    /// Telemetry channel of the contour error.
+    pub contour_error: String,
+}
+
+impl GantryController {
+    /// A controller with the signals of a plant.
+    pub fn new(controller: CrossCoupling, second: Entity, plant: &str) -> Self {
+        Self {
+            controller,
+            second,
+            setpoint: plants::namespaced(plant, GANTRY_POSITION),
+            contour_error: plants::namespaced(plant, CONTOUR_ERROR),
+        }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<GantrySettings>("gantry", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers, as the joints spawn or when the configuration changes, and
+/// gives them the configured gains.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<GantrySettings>>,
+    mut joints: Query<(Entity, &Link, &ImpulseJoint, Option<&mut GantryController>)>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    let seconds = joints
+        .iter()
+        .filter(|(_, link, ..)| link.name == settings.second)
+        .map(|(entity, link, joint, _)| (link.plant.clone(), entity, joint.parent))
+        .collect::<Vec<_>>();
+    for (entity, link, joint, controller) in &mut joints {
+        let second = seconds
+            .iter()
+            .filter(|_| settings.enabled && link.name == settings.first)
+            .find(|(plant, ..)| *plant == link.plant);
+        match (controller, second) {
+            (Some(mut controller), Some(&(_, second, _))) if controller.second == second => {
+                let configured = settings.cross_coupling();
+                for (axis, configured) in controller.controller.axes.iter_mut().zip(configured.axes)
+                {
+                    axis.gains = configured.gains;
+                    axis.limits = configured.limits;
+                }
+                controller.controller.coupling.gains = configured.coupling.gains;
+            }
+            (_, Some(&(_, second, second_parent))) => {
+                commands.entity(entity).insert(GantryController::new(
+                    settings.cross_coupling(),
+                    second,
+                    &link.plant,
+                ));
+                // The parents of the carriages take the reactions.
+                for body in [entity, joint.parent, second, second_parent] {
+                    commands
+                        .entity(body)
+                        .insert_if_new(ExternalImpulse::default());
+                }
+                info!(
+                    target: subsystem::CONTROL,
+                    "Gantry of {} coupled with {}",
+                    link.path(),
+                    plants::namespaced(&link.plant, &settings.second)
+                );
+            }
+            (Some(_), None) => {
+                commands.entity(entity).remove::<GantryController>();
+                info!(target: subsystem::CONTROL, "Gantry of {} uncoupled", link.path());
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Position of the prismatic joint of a body along its axis, from its parent.
+fn joint_position(
+    joints: &Query<&ImpulseJoint>,
+    transforms: &Query<&Transform>,
+    entity: Entity,
+) -> Option<(f32, Vec3, Entity)> {
+    let joint = joints.get(entity).ok()?;
+    let [body, parent] = transforms.get_many([entity, joint.parent]).ok()?;
+    let data = joint.data.as_ref();
+    let anchor = parent.transform_point(data.local_anchor1());
+    let axis = (parent.rotation * data.local_axis1()).normalize_or_zero();
+    let position = (body.transform_point(data.local_anchor2()) - anchor).dot(axis);
+    Some((position, axis, joint.parent))
+}
+
+/// Measures the carriages after each physics step and applies the forces of their motors for
+/// the next one.
+fn run_controllers(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    mut controllers: Query<(Entity, &mut GantryController)>,
+    joints: Query<&ImpulseJoint>,
+    transforms: Query<&Transform>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_gantries").entered();
+    let dt = clock.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    for (entity, mut controller) in &mut controllers {
+        let measure = |entity: Entity| joint_position(&joints, &transforms, entity);
+        let (Some(first), Some(second)) = (measure(entity), measure(controller.second)) else {
+            continue;
+        };
+        let target = setpoints.get(&controller.setpoint).unwrap_or_default();
+        let forces = controller
+            .controller
+            .update([target; 2], [first.0, second.0], dt);
+        for (body, (_, axis, parent), force) in [
+            (entity, first, forces[0]),
+            (controller.second, second, forces[1]),
+        ] {
+            let impulse = force * axis * dt;
+            if let Ok(mut body) = impulses.get_mut(body) {
+                body.impulse += impulse;
+            }
+            if let Ok(mut parent) = impulses.get_mut(parent) {
+                parent.impulse -= impulse;
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(
+                &controller.contour_error,
+                clock.elapsed_secs(),
+                controller.controller.contour_error(),
+            );
+        }
+    }
+}
+
+/// Drag values of the gains of a loop.
+fn gains(ui: &mut egui::Ui, name: &str, gains: &mut PidGains) {
+    ui.label(name);
+    ui.add(
+        egui::DragValue::new(&mut gains.kp)
+            .range(0.0..=10000.0)
+            .speed(1.0)
+            .prefix("Kp: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gains.ki)
+            .range(0.0..=10000.0)
+            .speed(1.0)
+            .prefix("Ki: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gains.kd)
+            .range(0.0..=1000.0)
+            .speed(0.1)
+            .prefix("Kd: "),
+    );
+    ui.end_row();
+}
+
+/// Panel to tune the coupled loops while they run.
+fn gantry_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<GantrySettings>>,
+    mut setpoints: ResMut<Setpoints>,
+    controllers: Query<&GantryController>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Gantry controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Control the position of the gantry");
+            ui.horizontal(|ui| {
+                ui.label("Carriages");
+                ui.text_edit_singleline(&mut edited.first);
+                ui.text_edit_singleline(&mut edited.second);
+            });
+            egui::Grid::new("gantry gains").show(ui, |ui| {
+                gains(ui, "Axes", &mut edited.axis);
+                gains(ui, "Coupling", &mut edited.coupling);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.force_limit)
+                    .range(0.0..=10000.0)
+                    .speed(1.0)
+                    .prefix("Force limit: ")
+                    .suffix(" N"),
+            );
+
+            ui.separator();
+            for controller in &controllers {
+                let mut target = setpoints.get(&controller.setpoint).unwrap_or_default();
+                let response = ui.add(
+                    egui::DragValue::new(&mut target)
+                        .speed(0.01)
+                        .prefix(format!("{}: ", controller.setpoint))
+                        .suffix(" m"),
+                );
+                if response.changed() {
+                    setpoints.set(&controller.setpoint, target);
+                }
+                let contour_error = telemetry
+                    .as_ref()
+                    .and_then(|telemetry| telemetry.latest(&controller.contour_error))
+                    .unwrap_or_default();
+                ui.label(format!("Contour error: {:.4} m", contour_error));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("gantry", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("gantry", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,699 @@
+//! This module provides a reference governor: it filters a setpoint so that the closed loop it
+//! drives never violates the limits of its signals, e.g. the torque of the motor.
+//!
+//! The governor predicts the response of the closed loop with a linear model identified with
+//! `--identify`, whose input is the governed setpoint and whose outputs include the constrained
+//! signals. Every step, the governed reference moves toward the raw one by the largest fraction
+//! keeping the predicted outputs within their limits over the horizon, and at steady state. The
+//! model is run alongside the plant, without correction by the measurements, so it holds as long
+//! as the model does.
+//!
+//! A notch filter can be inserted ahead of the governor, to keep the raw reference from
+//! exciting a resonance of the plant. The panel proposes one centered on the dominant resonance
+//! of the model, or on that of the spectrum of a telemetry channel, found by FFT without a model
+//! showing it, and inserts it once confirmed.
+//!
+//! The raw and the governed references are recorded as telemetry and plotted in the governor
+//! panel.
+use std::{path::PathBuf, time::Duration};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{Line, Plot, PlotPoints, VLine};
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    analysis::{self, Mode},
+    clock::SimClock,
+    config_plugin,
+    control::{NotchFilter, Saturation},
+    error::{Error, ErrorEvent, Result},
+    identification::LinearModel,
+    logging::subsystem,
+    setpoints::Setpoints,
+    spectrum::{Resonance, Spectrum},
+    telemetry::{Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+    theme::{to_egui, Theme},
+};
+
+/// Raw reference, before the governor.
+pub const GOVERNOR_RAW: &str = "governor/raw";
+/// Reference applied by the governor.
+pub const GOVERNOR_REFERENCE: &str = "governor/reference";
+/// Number of steps plotted in the governor panel.
+const PLOTTED_STEPS: usize = 600;
+/// Most recent samples of a channel whose spectrum is analyzed.
+const SPECTRUM_SAMPLES: usize = 4096;
+
+pub struct GovernorPlugin;
+
+impl Plugin for GovernorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveGovernor>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                (load_governor, govern)
+                    .chain()
+                    .run_if(resource_exists::<Persistent<GovernorSettings>>),
+            )
+            .add_systems(
+                Update,
+                governor_panel
+                    .run_if(resource_exists::<Persistent<GovernorSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Limits of a signal of the closed loop.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Constraint {
+    /// Output of the model.
+    pub signal: String,
+    #[serde(flatten)]
+    pub limits: Saturation,
+}
+
+/// Represents the configuration of the governor.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct GovernorSettings {
+    pub enabled: bool,
+    /// Model of the closed loop, written by `--identify`; its input is the governed setpoint.
+    pub model: PathBuf,
+    pub constraints: Vec<Constraint>,
+    /// Number of steps over which the outputs are predicted.
+    pub horizon: usize,
+    /// Notch filter applied to the raw reference ahead of the governor, even when it is
+    /// disabled.
+    pub notch: Option<Notch>,
+    /// Telemetry channel whose spectrum locates a resonance to notch, when the model doesn't.
+    pub spectrum: String,
+}
+
+/// Notch filter canceling a resonance of the plant from the raw reference.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Notch {
+    /// Center of the notch, in Hz.
+    pub frequency: f32,
+    /// Damping of the zeros, that of the resonance to cancel it.
+    pub zero_damping: f32,
+    /// Damping of the poles, setting the width of the notch.
+    pub pole_damping: f32,
+}
+
+impl Notch {
+    /// Damping of the poles of a notch canceling a mode: wide enough to tolerate errors on its
+    /// frequency.
+    pub const POLE_DAMPING: f32 = 0.7;
+
+    /// A notch canceling `mode`.
+    pub fn of(mode: &Mode) -> Self {
+        Self {
+            frequency: mode.frequency as f32,
+            zero_damping: mode.damping as f32,
+            pole_damping: Self::POLE_DAMPING,
+        }
+    }
+
+    /// A notch canceling a resonance found in a spectrum.
+    pub fn at(resonance: &Resonance) -> Self {
+        Self {
+            frequency: resonance.frequency,
+            zero_damping: resonance.damping,
+            pole_damping: Self::POLE_DAMPING,
+        }
+    }
+
+    /// The filter of the notch, sampled every `dt` seconds.
+    pub fn filter(&self, dt: f32) -> Result<NotchFilter> {
+        let filter = NotchFilter::new(self.frequency, self.zero_damping, self.pole_damping, dt);
+        if self.frequency > 0.0 && self.frequency < 0.5 / dt && filter.is_stable() {
+            Ok(filter)
+        } else {
+            Err(Error::Config {
+                name: "governor".to_string(),
+                message: format!(
+                    "a notch at {} Hz with a pole damping of {} isn't stable at {} Hz",
+                    self.frequency,
+                    self.pole_damping,
+                    1.0 / dt
+                ),
+            })
+        }
+    }
+}
+
+impl Default for GovernorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: PathBuf::from("model.json"),
+            constraints: vec![Constraint {
+                signal: MOTOR_TORQUE.to_string(),
+                limits: Saturation::symmetric(20.0),
+            }],
+            horizon: 120,
+            notch: None,
+            spectrum: PENDULUM_ANGLE.to_string(),
+        }
+    }
+}
+
+/// Filters a reference so that the predicted outputs of a linear model stay within limits.
+#[derive(Clone, Debug)]
+pub struct ReferenceGovernor {
+    model: LinearModel,
+    /// Rows of the model outputs that are constrained, with their limits.
+    constraints: Vec<(usize, Saturation)>,
+    /// `C A^j`, for each step `j` of the horizon.
+    free_responses: Vec<DMatrix<f64>>,
+    /// `C (I + A + … + A^(j-1)) B + D`: response at step `j` to a constant input.
+    step_responses: Vec<DMatrix<f64>>,
+    /// Response at steady state to a constant input, if the model is stable.
+    steady_state: Option<DMatrix<f64>>,
+    state: DVector<f64>,
+    output: f32,
+    fraction: f32,
+}
+
+impl ReferenceGovernor {
+    /// Creates a governor for the single input of `model`, starting at its operating point.
+    pub fn new(model: LinearModel, constraints: &[Constraint], horizon: usize) -> Result<Self> {
+        let invalid = |message: String| Error::Config {
+            name: "governor".to_string(),
+            message,
+        };
+        if model.inputs.len() != 1 {
+            return Err(invalid(format!(
+                "the model has {} inputs instead of the governed setpoint",
+                model.inputs.len()
+            )));
+        }
+        let constraints = constraints
+            .iter()
+            .map(|constraint| {
+                let row = model.outputs.iter().position(|o| *o == constraint.signal);
+                row.map(|row| (row, constraint.limits)).ok_or_else(|| {
+                    invalid(format!("the model has no output `{}`", constraint.signal))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let order = model.order();
+        let mut free_responses = Vec::with_capacity(horizon + 1);
+        let mut step_responses = Vec::with_capacity(horizon + 1);
+        let mut free = model.c.clone();
+        let mut step = model.d.clone();
+        for _ in 0..=horizon {
+            step_responses.push(step.clone());
+            step += &free * &model.b;
+            free_responses.push(free.clone());
+            free *= &model.a;
+        }
+        let stable = model
+            .a
+            .complex_eigenvalues()
+            .iter()
+            .all(|eigenvalue| eigenvalue.norm() < 1.0);
+        let steady_state = stable
+            .then(|| (DMatrix::identity(order, order) - &model.a).try_inverse())
+            .flatten()
+            .map(|inverse| &model.c * inverse * &model.b + &model.d);
+
+        let output = model.input_offsets[0] as f32;
+        Ok(Self {
+            state: DVector::zeros(order),
+            model,
+            constraints,
+            free_responses,
+            step_responses,
+            steady_state,
+            output,
+            fraction: 1.0,
+        })
+    }
+
+    /// Name of the governed setpoint.
+    pub fn reference(&self) -> &str {
+        &self.model.inputs[0]
+    }
+
+    /// Reference applied after the last update.
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// Fraction of the way to the raw reference taken by the last update, from 0 (held) to 1.
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    /// Sets the governor as if `reference` had been applied forever.
+    pub fn reset(&mut self, reference: f32) {
+        let order = self.model.order();
+        let input = f64::from(reference) - self.model.input_offsets[0];
+        self.state = (DMatrix::identity(order, order) - &self.model.a)
+            .try_inverse()
+            .map(|inverse| inverse * &self.model.b * input)
+            .map(|state| state.column(0).into_owned())
+            .unwrap_or_else(|| DVector::zeros(order));
+        self.output = reference;
+        self.fraction = 1.0;
+    }
+
+    /// Governs the raw reference for one step of the model.
+    pub fn update(&mut self, raw: f32) -> f32 {
+        let previous = f64::from(self.output) - self.model.input_offsets[0];
+        let change = f64::from(raw) - f64::from(self.output);
+
+        // Every predicted output is `base + fraction * slope`.
+        let mut fraction: f64 = 1.0;
+        let mut bound = |base: f64, slope: f64, limits: Saturation| {
+            let limit = if slope > 0.0 {
+                f64::from(limits.max)
+            } else if slope < 0.0 {
+                f64::from(limits.min)
+            } else {
+                return;
+            };
+            fraction = fraction.min((limit - base) / slope);
+        };
+        for (free, step) in self.free_responses.iter().zip(&self.step_responses) {
+            let free = free * &self.state;
+            for &(row, limits) in &self.constraints {
+                let offset = self.model.output_offsets[row];
+                let gain = step[(row, 0)];
+                bound(offset + free[row] + gain * previous, gain * change, limits);
+            }
+        }
+        if let Some(steady_state) = &self.steady_state {
+            for &(row, limits) in &self.constraints {
+                let offset = self.model.output_offsets[row];
+                let gain = steady_state[(row, 0)];
+                bound(offset + gain * previous, gain * change, limits);
+            }
+        }
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        // The model follows the reference as applied, in single precision.
+        self.output = (previous + fraction * change + self.model.input_offsets[0]) as f32;
+        let input = f64::from(self.output) - self.model.input_offsets[0];
+        self.state = &self.model.a * &self.state + &self.model.b * input;
+        self.fraction = fraction as f32;
+        self.output
+    }
+}
+
+/// The governor and the notch in use, and the references they saw.
+#[derive(Default, Resource)]
+pub struct ActiveGovernor {
+    pub governor: Option<ReferenceGovernor>,
+    pub notch: Option<NotchFilter>,
+    /// Filtered setpoint and sample period of the filters, from the model.
+    chain: Option<(String, Duration)>,
+    /// Last raw reference, set by whoever drives the setpoint.
+    pub raw: Option<f32>,
+    /// Last reference written by the governor, to tell it from new raw references.
+    written: Option<f32>,
+    /// Residual of the steps of the simulation that didn't make a whole step of the model.
+    pending: Duration,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<GovernorSettings>("governor", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// (Re)creates the governor when its settings change.
+fn load_governor(
+    mut commands: Commands,
+    settings: Res<Persistent<GovernorSettings>>,
+    mut active: ResMut<ActiveGovernor>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let raw = active.raw.take();
+    *active = ActiveGovernor::default();
+    if !settings.enabled && settings.notch.is_none() {
+        return;
+    }
+    match load_chain(&settings, raw) {
+        Ok(chain) => *active = chain,
+        Err(error) => {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Creates the notch and the governor of the settings, as if `raw` had been applied forever.
+fn load_chain(settings: &GovernorSettings, raw: Option<f32>) -> Result<ActiveGovernor> {
+    let model = LinearModel::read(&settings.model)?;
+    let reference = model.inputs.first().cloned().unwrap_or_default();
+    let dt = Duration::from_secs_f32(model.dt);
+    let mut notch = settings
+        .notch
+        .map(|notch| notch.filter(model.dt))
+        .transpose()?;
+    let mut governor = settings
+        .enabled
+        .then(|| ReferenceGovernor::new(model, &settings.constraints, settings.horizon))
+        .transpose()?;
+    if let Some(raw) = raw {
+        notch.iter_mut().for_each(|notch| notch.reset(raw));
+        governor.iter_mut().for_each(|governor| governor.reset(raw));
+    }
+    let stages = [
+        notch.is_some().then_some("a notch"),
+        governor.is_some().then_some("the governor"),
+    ];
+    info!(
+        target: subsystem::CONTROL,
+        "Filtering {reference} with {} from {}",
+        stages.into_iter().flatten().collect::<Vec<_>>().join(" and "),
+        settings.model.display()
+    );
+    Ok(ActiveGovernor {
+        governor,
+        notch,
+        chain: Some((reference, dt)),
+        raw,
+        ..default()
+    })
+}
+
+/// Governs the setpoint for the steps the simulation just took.
+fn govern(
+    clock: Res<SimClock>,
+    mut active: ResMut<ActiveGovernor>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let active = active.as_mut();
+    let Some((reference, dt)) = active.chain.clone() else {
+        return;
+    };
+    let current = setpoints.get(&reference);
+    if current.is_some() && current != active.written {
+        // Set by the keyboard, a remote client or a fieldbus since the last step.
+        active.raw = current;
+    }
+    let Some(raw) = active.raw else {
+        return;
+    };
+
+    active.pending += clock.delta();
+    let steps = SimClock::ticks_in(active.pending, dt);
+    active.pending -= SimClock::duration_of(steps, dt);
+    let mut output = active.written.unwrap_or(raw);
+    for _ in 0..steps {
+        output = active.notch.as_mut().map_or(raw, |notch| notch.update(raw));
+        if let Some(governor) = active.governor.as_mut() {
+            output = governor.update(output);
+        }
+    }
+
+    if current != Some(output) {
+        setpoints.set(&reference, output);
+    }
+    active.written = Some(output);
+    if let (Some(mut telemetry), true) = (telemetry, steps > 0) {
+        telemetry.record(GOVERNOR_RAW, clock.elapsed_secs(), raw);
+        telemetry.record(GOVERNOR_REFERENCE, clock.elapsed_secs(), output);
+    }
+}
+
+/// Panel to configure the governor, plotting the raw and the governed references.
+fn governor_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<GovernorSettings>>,
+    active: Res<ActiveGovernor>,
+    telemetry: Option<Res<Telemetry>>,
+    theme: Option<Res<Persistent<Theme>>>,
+    mut proposed: Local<Option<ProposedNotch>>,
+) {
+    let mut edited = settings.get().clone();
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+
+    egui::Window::new("Reference governor")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            ui.horizontal(|ui| {
+                ui.label("Model");
+                let mut path = edited.model.display().to_string();
+                if ui.text_edit_singleline(&mut path).lost_focus() {
+                    edited.model = PathBuf::from(path);
+                }
+            });
+            ui.add(egui::Slider::new(&mut edited.horizon, 1..=600).text("Horizon (steps)"));
+            let mut removed = None;
+            for (index, constraint) in edited.constraints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut constraint.signal);
+                    ui.add(egui::DragValue::new(&mut constraint.limits.min).prefix("min "));
+                    ui.add(egui::DragValue::new(&mut constraint.limits.max).prefix("max "));
+                    constraint.limits.min = constraint.limits.min.min(constraint.limits.max);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.constraints.remove(index);
+            }
+            if ui.button("Add constraint").clicked() {
+                edited.constraints.push(Constraint {
+                    signal: String::new(),
+                    limits: Saturation::symmetric(1.0),
+                });
+            }
+
+            ui.separator();
+            match edited.notch {
+                Some(notch) => {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Notch at {:.3} Hz (damping {:.4} / {:.2})",
+                            notch.frequency, notch.zero_damping, notch.pole_damping
+                        ));
+                        if ui.button("Remove").clicked() {
+                            edited.notch = None;
+                        }
+                    });
+                }
+                None => {
+                    let mut proposal = None;
+                    if ui.button("Notch the dominant resonance").clicked() {
+                        proposal = Some(propose_notch(&edited));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Notch the spectral peak of").clicked() {
+                            proposal = Some(propose_spectral_notch(&edited, telemetry.as_deref()));
+                        }
+                        ui.text_edit_singleline(&mut edited.spectrum);
+                    });
+                    match proposal {
+                        Some(Ok(proposal)) => *proposed = Some(proposal),
+                        Some(Err(error)) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                        None => {}
+                    }
+                }
+            }
+
+            if let Some(governor) = &active.governor {
+                ui.separator();
+                ui.label(format!(
+                    "{}: {:.0} % of the way to the raw reference",
+                    governor.reference(),
+                    governor.fraction() * 100.0
+                ));
+                if let Some(telemetry) = &telemetry {
+                    plot_references(ui, telemetry, &theme);
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("governor", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("governor", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    let mut inserted = None;
+    if let Some(proposal) = proposed.as_ref() {
+        let notch = proposal.notch;
+        egui::Window::new("Insert a notch filter?")
+            .collapsible(false)
+            .resizable(false)
+            .show(contexts.ctx_mut(), |ui| {
+                ui.label(format!(
+                    "The dominant resonance of {} is at {:.3} Hz, with a damping of {:.4}. \
+                     Filter it out of the raw reference, ahead of the governor?",
+                    proposal.source, notch.frequency, notch.zero_damping
+                ));
+                if let Some(spectrum) = &proposal.spectrum {
+                    Plot::new("notch spectrum")
+                        .height(120.0)
+                        .x_axis_label("Frequency (Hz)")
+                        .y_axis_label("Magnitude")
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(Line::new(PlotPoints::new(spectrum.clone())));
+                            plot_ui.vline(VLine::new(notch.frequency));
+                        });
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Insert").clicked() {
+                        inserted = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        inserted = Some(false);
+                    }
+                });
+            });
+    }
+    if let Some(inserted) = inserted {
+        if inserted {
+            edited.notch = proposed.as_ref().map(|proposal| proposal.notch);
+        }
+        *proposed = None;
+    }
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// A notch proposed in the panel, until it is confirmed.
+struct ProposedNotch {
+    notch: Notch,
+    /// Where the resonance was found.
+    source: String,
+    /// Magnitudes of the spectrum the resonance was found in, against their frequencies.
+    spectrum: Option<Vec<[f64; 2]>>,
+}
+
+/// A notch canceling the dominant resonance of the model of the settings.
+fn propose_notch(settings: &GovernorSettings) -> Result<ProposedNotch> {
+    let model = LinearModel::read(&settings.model)?;
+    let mode = analysis::dominant_resonance(&model).ok_or_else(|| Error::Config {
+        name: "governor".to_string(),
+        message: format!("{} has no resonance to notch", settings.model.display()),
+    })?;
+    let notch = Notch::of(&mode);
+    notch.filter(model.dt)?;
+    Ok(ProposedNotch {
+        notch,
+        source: settings.model.display().to_string(),
+        spectrum: None,
+    })
+}
+
+/// A notch canceling the dominant resonance of the spectrum of the last samples of the channel
+/// of the settings, sampled at the period of the model.
+fn propose_spectral_notch(
+    settings: &GovernorSettings,
+    telemetry: Option<&Telemetry>,
+) -> Result<ProposedNotch> {
+    let model = LinearModel::read(&settings.model)?;
+    let channel = &settings.spectrum;
+    let unsuitable = |reason: &str| Error::Config {
+        name: "governor".to_string(),
+        message: format!("{channel} {reason}"),
+    };
+    let samples = telemetry
+        .and_then(|telemetry| telemetry.channels.get(channel))
+        .ok_or_else(|| unsuitable("isn't recorded"))?;
+    let recent = &samples[samples.len().saturating_sub(SPECTRUM_SAMPLES)..];
+    let spectrum =
+        Spectrum::of(recent).ok_or_else(|| unsuitable("has too few samples to analyze"))?;
+    let resonance = spectrum
+        .dominant_resonance()
+        .ok_or_else(|| unsuitable("has no resonance to notch"))?;
+    let notch = Notch::at(&resonance);
+    notch.filter(model.dt)?;
+    // Up to a few times the resonance, where the notch acts.
+    let shown = (0..spectrum.magnitudes.len())
+        .take_while(|&bin| spectrum.frequency(bin) <= 3.0 * resonance.frequency)
+        .map(|bin| {
+            [
+                f64::from(spectrum.frequency(bin)),
+                f64::from(spectrum.magnitudes[bin]),
+            ]
+        })
+        .collect();
+    Ok(ProposedNotch {
+        notch,
+        source: format!("the spectrum of {channel}"),
+        spectrum: Some(shown),
+    })
+}
+
+/// Plots the last raw and governed references.
+fn plot_references(ui: &mut egui::Ui, telemetry: &Telemetry, theme: &Theme) {
+    let recent = |channel: &str| {
+        let samples = telemetry
+            .channels
+            .get(channel)
+            .map_or(&[][..], Vec::as_slice);
+        samples[samples.len().saturating_sub(PLOTTED_STEPS)..]
+            .iter()
+            .map(|[_, value]| *value)
+            .collect::<Vec<_>>()
+    };
+    let series = [recent(GOVERNOR_RAW), recent(GOVERNOR_REFERENCE)];
+    let (low, high) = series
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+            (low.min(*value), high.max(*value))
+        });
+    if !low.is_finite() {
+        return;
+    }
+    let span = (high - low).max(f32::EPSILON);
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(300.0, 100.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    for (index, (samples, name)) in series.iter().zip(["raw", "governed"]).enumerate() {
+        let color = to_egui(theme.series(index));
+        let points = samples
+            .iter()
+            .enumerate()
+            .map(|(k, value)| {
+                egui::pos2(
+                    rect.left() + rect.width() * k as f32 / PLOTTED_STEPS as f32,
+                    rect.bottom() - rect.height() * (value - low) / span,
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+        painter.text(
+            rect.left_top() + egui::vec2(4.0, 4.0 + 14.0 * index as f32),
+            egui::Align2::LEFT_TOP,
+            name,
+            egui::FontId::proportional(12.0),
+            color,
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! This module provides a plugin for drawing a 2D grid on XZ plane.
-use bevy::{color::palettes::css::GRAY, prelude::*};
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+
+use crate::theme::Theme;
 
 pub struct GridPlugin;
 
@@ -9,8 +12,14 @@ impl Plugin for GridPlugin {
     }
 }
 
-fn update(mut gizmos: Gizmos) {
+fn update(mut gizmos: Gizmos, theme: Option<Res<Persistent<Theme>>>) {
+    let color = theme.map_or_else(|| Theme::default().grid(), |theme| theme.grid());
     gizmos
-        .grid_3d(Quat::IDENTITY, UVec3::new(10, 0, 10), Vec3::splat(1.), GRAY)
+        .grid_3d(
+            Quat::IDENTITY,
+            UVec3::new(10, 0, 10),
+            Vec3::splat(1.),
+            color,
+        )
         .outer_edges();
 }
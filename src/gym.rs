@@ -0,0 +1,304 @@
+//! Gymnasium-style environments in Rust, for training loops written against the [`Gym`] trait:
+//! each episode starts with [`Gym::reset`], and each [`Gym::step`] applies an action and gives
+//! the next observation, a reward and whether the episode is done.
+//!
+//! [`PoleBalancing`] implements it for the pole-balancing scenes, the rotary pendulum and the
+//! [cart-pole](crate::cart_pole), stepped headlessly by an [`Environment`]. The action is the
+//! velocity setpoint of the motor, in rad/s, or of the cart, in m/s; the observation is the
+//! position of the motor or of the cart and its velocity, then the angle of the joint of the pole
+//! from upright and its velocity, the velocities by finite differences over a step. Each episode
+//! starts with the pole turned from upright by an angle drawn within `initial_angle`, from the
+//! seed of the episode. The reward and the end of the episodes are configured by a [`Reward`]
+//! and a [`Termination`].
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cart_pole::{self, CART_POSITION, CART_VELOCITY},
+    environment::{Environment, EnvironmentSettings},
+    error::{Error, Result},
+    fuzzing::Random,
+    headless::DEFAULT_TIME_STEP,
+    plants::{self, Link, Plant},
+    setpoints::MOTOR_VELOCITY,
+    sweep,
+    telemetry::MOTOR_ANGLE,
+};
+
+/// An environment stepped episode by episode.
+pub trait Gym {
+    type Observation;
+    type Action;
+
+    /// Starts an episode, returning its first observation.
+    fn reset(&mut self) -> Result<Self::Observation>;
+
+    /// Applies `action` for a step, returning the observation, the reward and whether the
+    /// episode is done.
+    fn step(&mut self, action: Self::Action) -> Result<(Self::Observation, f32, bool)>;
+}
+
+/// Observation of a pole-balancing scene: position and velocity of the base, angle of the pole
+/// from upright and its velocity.
+pub type PoleState = [f32; 4];
+
+/// The pole-balancing scenes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scene {
+    #[default]
+    RotaryPendulum,
+    CartPole,
+}
+
+impl Scene {
+    pub fn plant(self) -> Option<Plant> {
+        match self {
+            Scene::RotaryPendulum => plants::builtin()
+                .into_iter()
+                .find(|plant| plant.name == "rotary_pendulum"),
+            Scene::CartPole => Some(cart_pole::plant()),
+        }
+    }
+
+    /// Setpoint set by the actions.
+    pub fn action(self) -> &'static str {
+        match self {
+            Scene::RotaryPendulum => MOTOR_VELOCITY,
+            Scene::CartPole => CART_VELOCITY,
+        }
+    }
+
+    /// Telemetry channel of the position of the base.
+    pub fn position(self) -> &'static str {
+        match self {
+            Scene::RotaryPendulum => MOTOR_ANGLE,
+            Scene::CartPole => CART_POSITION,
+        }
+    }
+
+    /// Link turned by the revolute joint of the pole.
+    pub fn pole(self) -> &'static str {
+        match self {
+            Scene::RotaryPendulum => "pivot",
+            Scene::CartPole => "pole",
+        }
+    }
+
+    /// Angle of the joint of the pole upright; the pole is spawned at 0.
+    pub fn upright(self) -> f32 {
+        match self {
+            Scene::RotaryPendulum => PI,
+            Scene::CartPole => 0.0,
+        }
+    }
+}
+
+/// Reward of a step.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reward {
+    /// 1 per step, so the longer the pole is kept up, the larger the return.
+    Alive,
+    /// Cosine of the angle of the pole from upright: 1 upright, -1 hanging.
+    Upright,
+    /// `-(Σ qᵢ xᵢ² + r u²)`, from the observation `x` and the action `u`.
+    Quadratic { q: [f32; 4], r: f32 },
+}
+
+impl Reward {
+    pub fn of(&self, observation: &PoleState, action: f32) -> f32 {
+        match self {
+            Reward::Alive => 1.0,
+            Reward::Upright => observation[2].cos(),
+            Reward::Quadratic { q, r } => {
+                let state: f32 = q.iter().zip(observation).map(|(q, x)| q * x * x).sum();
+                -(state + r * action * action)
+            }
+        }
+    }
+}
+
+/// When an episode ends.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Termination {
+    /// Largest angle of the pole from upright, in rad, or none to let it swing.
+    pub angle: Option<f32>,
+    /// Largest distance of the base from its start, in rad or m, or none.
+    pub position: Option<f32>,
+    /// Steps after which the episode ends, or none.
+    pub max_steps: Option<usize>,
+}
+
+impl Default for Termination {
+    fn default() -> Self {
+        Self {
+            angle: Some(0.5),
+            position: None,
+            max_steps: Some(500),
+        }
+    }
+}
+
+impl Termination {
+    /// Whether the episode is done at `observation`, after `steps` steps.
+    pub fn done(&self, observation: &PoleState, steps: usize) -> bool {
+        let beyond =
+            |value: f32, limit: Option<f32>| limit.is_some_and(|limit| value.abs() > limit);
+        beyond(observation[0], self.position)
+            || beyond(observation[2], self.angle)
+            || self.max_steps.is_some_and(|max_steps| steps >= max_steps)
+            || observation.iter().any(|value| !value.is_finite())
+    }
+}
+
+/// Represents the configuration of a pole-balancing gym.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct GymSettings {
+    pub scene: Scene,
+    /// Time step of the physics, in s.
+    pub dt: f32,
+    /// Physics steps per action.
+    pub substeps: usize,
+    pub reward: Reward,
+    pub termination: Termination,
+    /// Bounds of the initial angle of the pole from upright, in rad.
+    pub initial_angle: [f32; 2],
+    /// Largest action, in rad/s or m/s.
+    pub action_limit: f32,
+    /// Seed of the first episode; each reset draws from the next one.
+    pub seed: u64,
+}
+
+impl Default for GymSettings {
+    fn default() -> Self {
+        Self {
+            scene: Scene::RotaryPendulum,
+            dt: DEFAULT_TIME_STEP,
+            substeps: 2,
+            reward: Reward::Upright,
+            termination: Termination::default(),
+            initial_angle: [-0.05, 0.05],
+            action_limit: 10.0,
+            seed: 1,
+        }
+    }
+}
+
+/// Angle within `(-π, π]`.
+fn wrap(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+/// Balancing the pole of a scene.
+pub struct PoleBalancing {
+    pub settings: GymSettings,
+    environment: Environment,
+    random: Random,
+    /// Steps of the episode.
+    steps: usize,
+    /// Position of the base and angle of the pole at the last step.
+    last: [f32; 2],
+}
+
+impl PoleBalancing {
+    pub fn new(settings: GymSettings) -> Result<Self> {
+        let plant = settings.scene.plant().ok_or_else(|| Error::Config {
+            name: "gym".to_string(),
+            message: format!("no {:?} plant in this build", settings.scene),
+        })?;
+        let environment = Environment::with_plant(
+            plant,
+            EnvironmentSettings {
+                plant: plant.name.to_string(),
+                dt: settings.dt,
+                substeps: settings.substeps,
+                actions: vec![settings.scene.action().to_string()],
+                observations: vec![settings.scene.position().to_string()],
+            },
+        )?;
+        Ok(Self {
+            random: Random::new(settings.seed),
+            settings,
+            environment,
+            steps: 0,
+            last: [0.0; 2],
+        })
+    }
+
+    /// Simulated time of the episode, in s.
+    pub fn time(&self) -> f32 {
+        self.environment.time()
+    }
+
+    /// The environment, e.g. to read the telemetry of the episode.
+    pub fn environment(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Angle of the joint of the pole from upright.
+    fn pole_angle(&mut self) -> Option<f32> {
+        let scene = self.settings.scene;
+        let world = self.environment.app().world_mut();
+        let mut links = world.query::<(Entity, &Link)>();
+        let (pole, _) = links
+            .iter(world)
+            .find(|(_, link)| link.plant.is_empty() && link.name == scene.pole())?;
+        let mut contexts = world.query::<&RapierContext>();
+        let angle = contexts
+            .get_single(world)
+            .ok()?
+            .impulse_revolute_joint_angle(pole)?;
+        Some(wrap(angle - scene.upright()))
+    }
+}
+
+impl Gym for PoleBalancing {
+    type Observation = PoleState;
+    type Action = f32;
+
+    fn reset(&mut self) -> Result<PoleState> {
+        let seed = (self.random.next() * (1u32 << 24) as f32) as u64;
+        let position = self.environment.reset(seed)?[0];
+        let [min, max] = self.settings.initial_angle;
+        let angle = self.random.range(min, max);
+        let scene = self.settings.scene;
+        sweep::turn(
+            self.environment.app().world_mut(),
+            scene.pole(),
+            scene.upright() + angle,
+        )
+        .map_err(|message| Error::Config {
+            name: "gym".to_string(),
+            message,
+        })?;
+        self.steps = 0;
+        self.last = [position, angle];
+        Ok([position, 0.0, angle, 0.0])
+    }
+
+    fn step(&mut self, action: f32) -> Result<(PoleState, f32, bool)> {
+        let limit = self.settings.action_limit.abs();
+        let action = action.clamp(-limit, limit);
+        let position = self.environment.step(&[action])?[0];
+        let angle = self.pole_angle().unwrap_or(f32::NAN);
+        let dt = self.settings.dt * self.settings.substeps as f32;
+        let [last_position, last_angle] = self.last;
+        let observation = [
+            position,
+            (position - last_position) / dt,
+            angle,
+            wrap(angle - last_angle) / dt,
+        ];
+        self.last = [position, angle];
+        self.steps += 1;
+        let reward = self.settings.reward.of(&observation, action);
+        let done = self.settings.termination.done(&observation, self.steps);
+        Ok((observation, reward, done))
+    }
+}
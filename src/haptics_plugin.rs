@@ -0,0 +1,217 @@
+//! This module provides a plugin that drives the rumble of the gamepads, as a crude force
+//! feedback during teleoperation. The weak motor follows the torque of the motor and the strong
+//! motor the contact forces between the bodies. It is disabled until enabled in the haptics
+//! panel or in `haptics.json`.
+use std::time::Duration;
+
+use bevy::{
+    input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+    prelude::*,
+    time::Real,
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    telemetry::{Telemetry, MOTOR_TORQUE},
+};
+
+/// Interval between two updates of the rumble.
+const RUMBLE_PERIOD: Duration = Duration::from_millis(100);
+
+pub struct HapticsPlugin;
+
+impl Plugin for HapticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                (report_contact_forces, rumble)
+                    .chain()
+                    .run_if(resource_exists::<Persistent<HapticsSettings>>),
+                haptics_panel
+                    .run_if(resource_exists::<Persistent<HapticsSettings>>)
+                    .run_if(has_ui),
+            ),
+        );
+    }
+}
+
+/// Represents the haptics configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct HapticsSettings {
+    pub enabled: bool,
+    /// Torque of the motor rumbling the weak motor at full intensity, in N·m.
+    pub torque_full_scale: f32,
+    /// Contact force rumbling the strong motor at full intensity, in N.
+    pub contact_full_scale: f32,
+    /// Contact forces below this one are ignored, in N.
+    pub contact_threshold: f32,
+}
+
+impl Default for HapticsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            torque_full_scale: 50.0,
+            contact_full_scale: 200.0,
+            contact_threshold: 5.0,
+        }
+    }
+}
+
+impl HapticsSettings {
+    /// Intensity of the rumble for a torque of the motor and a contact force.
+    pub fn intensity(&self, torque: f32, contact_force: f32) -> GamepadRumbleIntensity {
+        let ratio = |value: f32, full_scale: f32| {
+            if full_scale > 0.0 && value.is_finite() {
+                (value.abs() / full_scale).min(1.0)
+            } else {
+                0.0
+            }
+        };
+        GamepadRumbleIntensity {
+            strong_motor: ratio(contact_force, self.contact_full_scale),
+            weak_motor: ratio(torque, self.torque_full_scale),
+        }
+    }
+}
+
+/// Strongest contact force since the last update of the rumble, in N.
+#[derive(Default, Resource)]
+struct PeakContactForce(f32);
+
+#[derive(Resource)]
+struct RumbleTimer(Timer);
+
+/// The colliders, with their contact force reporting.
+type ColliderEvents<'a> = (
+    Entity,
+    Option<&'a ActiveEvents>,
+    Option<&'a ContactForceEventThreshold>,
+);
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<HapticsSettings>("haptics", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+    commands.init_resource::<PeakContactForce>();
+    commands.insert_resource(RumbleTimer(Timer::new(RUMBLE_PERIOD, TimerMode::Repeating)));
+}
+
+/// Enables the contact force events of the colliders, and keeps the strongest force.
+fn report_contact_forces(
+    mut commands: Commands,
+    settings: Res<Persistent<HapticsSettings>>,
+    colliders: Query<ColliderEvents, With<Collider>>,
+    mut events: EventReader<ContactForceEvent>,
+    mut peak: ResMut<PeakContactForce>,
+) {
+    if settings.enabled {
+        for (entity, active_events, threshold) in &colliders {
+            let active_events = active_events.copied().unwrap_or_default();
+            if active_events.contains(ActiveEvents::CONTACT_FORCE_EVENTS)
+                && threshold.is_some_and(|threshold| threshold.0 == settings.contact_threshold)
+            {
+                continue;
+            }
+            commands.entity(entity).insert((
+                active_events | ActiveEvents::CONTACT_FORCE_EVENTS,
+                ContactForceEventThreshold(settings.contact_threshold),
+            ));
+        }
+    }
+    for event in events.read() {
+        peak.0 = peak.0.max(event.max_force_magnitude);
+    }
+}
+
+fn rumble(
+    settings: Res<Persistent<HapticsSettings>>,
+    time: Res<Time<Real>>,
+    mut timer: ResMut<RumbleTimer>,
+    mut peak: ResMut<PeakContactForce>,
+    telemetry: Option<Res<Telemetry>>,
+    gamepads: Query<Entity, With<Gamepad>>,
+    mut requests: EventWriter<GamepadRumbleRequest>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let contact_force = std::mem::take(&mut peak.0);
+    if !settings.enabled {
+        return;
+    }
+    let torque = telemetry
+        .and_then(|telemetry| telemetry.latest(MOTOR_TORQUE))
+        .unwrap_or_default();
+    let intensity = settings.intensity(torque, contact_force);
+    for gamepad in &gamepads {
+        // Rumbles add up: the previous one is replaced rather than reinforced.
+        requests.send(GamepadRumbleRequest::Stop { gamepad });
+        if intensity.strong_motor > 0.0 || intensity.weak_motor > 0.0 {
+            requests.send(GamepadRumbleRequest::Add {
+                // Overlaps the next update, so the rumble doesn't pause in between.
+                duration: RUMBLE_PERIOD * 2,
+                intensity,
+                gamepad,
+            });
+        }
+    }
+}
+
+/// Panel to tune the haptic feedback at runtime.
+fn haptics_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<HapticsSettings>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Haptics")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Gamepad rumble");
+            ui.add(
+                egui::Slider::new(&mut edited.torque_full_scale, 1.0..=500.0)
+                    .logarithmic(true)
+                    .text("Full-scale torque (N·m)"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.contact_full_scale, 1.0..=5_000.0)
+                    .logarithmic(true)
+                    .text("Full-scale contact force (N)"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.contact_threshold, 0.0..=100.0)
+                    .text("Contact threshold (N)"),
+            );
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("haptics", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("haptics", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,400 @@
+//! Logs of the physical rig, imported from CSV and overlaid on the simulated runs to validate
+//! the model against the hardware.
+//!
+//! A log has a header row naming its columns, a time column and a column per signal. The
+//! columns are mapped to telemetry channels in `hardware_log.json`, with a scale and an offset
+//! each, e.g. to turn encoder counts into radians, and the time column is read in a configurable
+//! unit. The log starts at its first row, then is shifted onto the simulated time: by a fixed
+//! offset, or so that the first change of a marker channel, e.g. the step of a setpoint, happens
+//! at the same time in the log and in the run.
+//!
+//! The imported channels are plotted in the *Plots* window as `hardware/<channel>`, next to
+//! their simulated counterparts, and [`compare`] reports how far the run is from the log.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    plots::Overlays,
+    run_diff::{Alignment, RunDiff},
+    telemetry::{Telemetry, MOTOR_ANGLE, PENDULUM_ANGLE},
+};
+
+/// Prefix of the overlaid channels of the log.
+pub const HARDWARE_PREFIX: &str = "hardware/";
+
+pub struct HardwareLogPlugin;
+
+impl Plugin for HardwareLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Telemetry>()
+            .init_resource::<Overlays>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                hardware_log_panel
+                    .run_if(resource_exists::<Persistent<HardwareLogSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A column of the log and the channel it is imported as, `scale * value + offset`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub column: String,
+    pub channel: String,
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            column: String::new(),
+            channel: String::new(),
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+/// Represents how the logs are read and aligned.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct HardwareLogSettings {
+    /// Log imported at start.
+    pub path: Option<PathBuf>,
+    pub delimiter: char,
+    pub time_column: String,
+    /// Seconds per unit of the time column, e.g. 0.001 for milliseconds.
+    pub time_unit: f32,
+    pub columns: Vec<ColumnMapping>,
+    /// Channel whose first change, beyond the threshold, aligns the log on the run, or none to
+    /// start the log with the run.
+    pub marker: Option<String>,
+    pub threshold: f32,
+    /// Time of the run at the start of the log, or at its marker, in s.
+    pub offset: f32,
+}
+
+impl Default for HardwareLogSettings {
+    fn default() -> Self {
+        let column = |column: &str, channel: &str| ColumnMapping {
+            column: column.to_string(),
+            channel: channel.to_string(),
+            ..default()
+        };
+        Self {
+            path: None,
+            delimiter: ',',
+            time_column: "time".to_string(),
+            time_unit: 1.0,
+            columns: vec![
+                column("pendulum_angle", PENDULUM_ANGLE),
+                column("motor_angle", MOTOR_ANGLE),
+            ],
+            marker: None,
+            threshold: 0.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl HardwareLogSettings {
+    fn alignment(&self) -> Alignment {
+        match &self.marker {
+            Some(channel) => Alignment::Event {
+                channel: channel.clone(),
+                threshold: self.threshold,
+            },
+            None => Alignment::Time,
+        }
+    }
+}
+
+fn invalid(path: &Path, message: String) -> Error {
+    Error::io(path, io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Parses the CSV log `text` into the mapped channels, on the time since its first row.
+///
+/// Empty or unreadable fields are skipped, so signals logged at different rates can share the
+/// log.
+pub fn parse(text: &str, settings: &HardwareLogSettings, path: &Path) -> Result<Telemetry> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid(path, "the log is empty".to_string()))?;
+    let header: Vec<&str> = header
+        .split(settings.delimiter)
+        .map(|name| name.trim().trim_matches('"'))
+        .collect();
+    let index = |column: &str| {
+        header
+            .iter()
+            .position(|name| *name == column)
+            .ok_or_else(|| invalid(path, format!("the log has no `{column}` column")))
+    };
+    let time = index(&settings.time_column)?;
+    let columns = settings
+        .columns
+        .iter()
+        .map(|mapping| Ok((index(&mapping.column)?, mapping)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut log = Telemetry::default();
+    let mut start = None;
+    for (row, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(settings.delimiter).map(str::trim).collect();
+        let Some(Ok(value)) = fields.get(time).map(|field| field.parse::<f64>()) else {
+            return Err(invalid(path, format!("row {} has no time", row + 2)));
+        };
+        let seconds = value * f64::from(settings.time_unit);
+        let start = *start.get_or_insert(seconds);
+        for (column, mapping) in &columns {
+            let Some(Ok(value)) = fields.get(*column).map(|field| field.parse::<f32>()) else {
+                continue;
+            };
+            log.record(
+                &mapping.channel,
+                (seconds - start) as f32,
+                mapping.scale * value + mapping.offset,
+            );
+        }
+    }
+    Ok(log)
+}
+
+/// Reads the log of `path`.
+pub fn read(path: &Path, settings: &HardwareLogSettings) -> Result<Telemetry> {
+    let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+    parse(&text, settings, path)
+}
+
+/// Time of the run at the start of `log`: the offset of the settings, plus the time of the
+/// marker in the run less its time in the log.
+pub fn shift(log: &Telemetry, run: &Telemetry, settings: &HardwareLogSettings) -> Result<f32> {
+    let alignment = settings.alignment();
+    Ok(settings.offset + alignment.origin(run)? - alignment.origin(log)?)
+}
+
+/// `log` on the time of the run, `shift` later.
+pub fn shifted(log: &Telemetry, shift: f32) -> Telemetry {
+    let mut channels = log.channels.clone();
+    for samples in channels.values_mut() {
+        for sample in samples {
+            sample[0] += shift;
+        }
+    }
+    Telemetry { channels }
+}
+
+/// Deviations of the simulated run from the log, aligned as configured.
+pub fn compare(
+    log: &Telemetry,
+    run: &Telemetry,
+    settings: &HardwareLogSettings,
+) -> Result<RunDiff> {
+    let log = shifted(log, shift(log, run, settings)?);
+    RunDiff::compare(&log, run, &Alignment::Time)
+}
+
+/// The imported log, on its own time.
+#[derive(Default, Resource)]
+pub struct HardwareLog {
+    pub path: PathBuf,
+    pub telemetry: Telemetry,
+    /// Time of the run at the start of the log.
+    pub shift: f32,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<HardwareLogSettings>("hardware_log", true);
+    if let Some(path) = &settings.path {
+        match read(path, &settings) {
+            Ok(telemetry) => commands.insert_resource(HardwareLog {
+                path: path.clone(),
+                shift: settings.offset,
+                telemetry,
+            }),
+            Err(error) => {
+                commands.send_event(ErrorEvent::from(error));
+            }
+        }
+    }
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Replaces the overlaid channels with the ones of `log`.
+fn overlay(overlays: &mut Overlays, log: Option<&HardwareLog>) {
+    overlays
+        .series
+        .retain(|name, _| !name.starts_with(HARDWARE_PREFIX));
+    let Some(log) = log else {
+        return;
+    };
+    for (channel, samples) in shifted(&log.telemetry, log.shift).channels {
+        overlays
+            .series
+            .insert(format!("{HARDWARE_PREFIX}{channel}"), samples);
+    }
+}
+
+/// Panel to import a log, align it on the run and compare them.
+fn hardware_log_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<HardwareLogSettings>>,
+    mut log: Option<ResMut<HardwareLog>>,
+    mut overlays: ResMut<Overlays>,
+    telemetry: Res<Telemetry>,
+    mut path: Local<Option<String>>,
+    mut diff: Local<Option<RunDiff>>,
+) {
+    if log.as_ref().is_some_and(|log| log.is_changed()) {
+        overlay(&mut overlays, log.as_deref());
+    }
+    let mut edited = settings.get().clone();
+    let path = path.get_or_insert_with(|| {
+        let path = edited.path.as_ref().or(log.as_ref().map(|log| &log.path));
+        path.map(|path| path.display().to_string())
+            .unwrap_or_default()
+    });
+    egui::Window::new("Hardware log")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Log");
+                ui.text_edit_singleline(path);
+                if ui.button("Import").clicked() {
+                    match read(Path::new(path.as_str()), &edited) {
+                        Ok(telemetry) => {
+                            commands.insert_resource(HardwareLog {
+                                path: PathBuf::from(&*path),
+                                shift: edited.offset,
+                                telemetry,
+                            });
+                            *diff = None;
+                        }
+                        Err(error) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.time_unit)
+                    .speed(0.001)
+                    .prefix("Time unit: ")
+                    .suffix(" s"),
+            );
+            ui.label("Columns:");
+            egui::Grid::new("hardware_log_columns").show(ui, |ui| {
+                for mapping in &mut edited.columns {
+                    ui.text_edit_singleline(&mut mapping.column);
+                    ui.text_edit_singleline(&mut mapping.channel);
+                    ui.add(egui::DragValue::new(&mut mapping.scale).prefix("× "));
+                    ui.add(egui::DragValue::new(&mut mapping.offset).prefix("+ "));
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Add a column").clicked() {
+                    edited.columns.push(ColumnMapping::default());
+                }
+                if ui.button("Remove the last one").clicked() {
+                    edited.columns.pop();
+                }
+            });
+
+            ui.separator();
+            let mut marker = edited.marker.clone().unwrap_or_default();
+            ui.horizontal(|ui| {
+                ui.label("Marker");
+                ui.text_edit_singleline(&mut marker)
+                    .on_hover_text("Channel whose first change aligns the log, or empty");
+            });
+            edited.marker = (!marker.is_empty()).then_some(marker);
+            ui.add(
+                egui::DragValue::new(&mut edited.offset)
+                    .speed(0.01)
+                    .prefix("Offset: ")
+                    .suffix(" s"),
+            );
+            match log.as_mut() {
+                Some(log) => {
+                    ui.label(format!(
+                        "{} channels, starting at {:.3} s of the run",
+                        log.telemetry.channels.len(),
+                        log.shift
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui.button("Align").clicked() {
+                            match shift(&log.telemetry, &telemetry, &edited) {
+                                Ok(shift) => log.shift = shift,
+                                Err(error) => {
+                                    commands.send_event(ErrorEvent::from(error));
+                                }
+                            }
+                        }
+                        if ui.button("Compare").clicked() {
+                            let log = shifted(&log.telemetry, log.shift);
+                            match RunDiff::compare(&log, &telemetry, &Alignment::Time) {
+                                Ok(compared) => *diff = Some(compared),
+                                Err(error) => {
+                                    commands.send_event(ErrorEvent::from(error));
+                                }
+                            }
+                        }
+                    });
+                    if let Some(diff) = &*diff {
+                        for channel in &diff.channels {
+                            ui.label(format!(
+                                "{}: max {:.3e} at {:.2} s, RMS {:.3e}",
+                                channel.channel, channel.max, channel.max_time, channel.rms
+                            ));
+                        }
+                        for channel in &diff.only_first {
+                            ui.weak(format!("{channel}: not simulated"));
+                        }
+                    }
+                }
+                None => {
+                    ui.weak("No log imported");
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("hardware_log", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("hardware_log", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
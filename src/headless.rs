@@ -0,0 +1,74 @@
+//! Applications running without a window, used to step the simulation from tests and tools.
+//!
+//! Every [`App::update`] of these applications advances the physics by exactly one fixed
+//! time step, independently of the wall clock. Reported errors stop them with a non-zero
+//! [`AppExit`] code instead of opening a dialog.
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    prelude::*,
+    render::{
+        settings::{RenderCreation, WgpuSettings},
+        RenderPlugin,
+    },
+    time::TimeUpdateStrategy,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{clock::SimClockPlugin, error::ErrorPlugin};
+
+/// Default physics time step, in seconds.
+pub const DEFAULT_TIME_STEP: f32 = 1.0 / 60.0;
+
+/// Builds an application that steps the physics without creating a window nor a GPU device.
+pub fn headless_app(dt: f32) -> App {
+    build(
+        dt,
+        RenderCreation::Automatic(WgpuSettings {
+            backends: None,
+            ..default()
+        }),
+    )
+}
+
+/// Builds an application that steps the physics and renders into offscreen images.
+///
+/// A GPU adapter (or a software one) is required.
+pub fn offscreen_app(dt: f32) -> App {
+    build(dt, RenderCreation::Automatic(WgpuSettings::default()))
+}
+
+fn build(dt: f32, render_creation: RenderCreation) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .set(RenderPlugin {
+                render_creation,
+                synchronous_pipeline_compilation: true,
+            })
+            .set(AssetPlugin {
+                file_path: std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+        ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+        RapierPhysicsPlugin::<NoUserData>::default(),
+        ErrorPlugin {
+            exit_on_error: true,
+        },
+        SimClockPlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        dt,
+    )))
+    .insert_resource(TimestepMode::Fixed { dt, substeps: 1 });
+    app
+}
@@ -0,0 +1,291 @@
+//! This module provides an operator panel built from a layout file, `hmi.json`: buttons,
+//! indicator lamps, numeric displays and sliders bound to the signals of the plant, so an operator
+//! screen can be laid out for a plant without touching the code.
+//!
+//! A signal is read from the telemetry channel of that name, or else from the setpoint of that
+//! name; buttons and sliders write setpoints. Edit the layout while the application runs and press
+//! *Reload layout* to see the changes.
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+};
+
+pub struct HmiPlugin;
+
+impl Plugin for HmiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                hmi_panel
+                    .run_if(resource_exists::<Persistent<HmiLayout>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Writes `value` to a setpoint when pressed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Button {
+    pub label: String,
+    pub signal: String,
+    pub value: f32,
+}
+
+/// Lit while the magnitude of a signal exceeds a threshold.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Lamp {
+    pub label: String,
+    pub signal: String,
+    #[serde(default)]
+    pub threshold: f32,
+    /// Color when lit, as RGB.
+    #[serde(default = "Lamp::default_color")]
+    pub color: [u8; 3],
+}
+
+impl Lamp {
+    fn default_color() -> [u8; 3] {
+        [0, 200, 0]
+    }
+
+    /// Whether the lamp is lit by `value`. A signal without value leaves it off.
+    pub fn is_lit(&self, value: Option<f32>) -> bool {
+        value.is_some_and(|value| value.abs() > self.threshold)
+    }
+}
+
+/// Shows the value of a signal, scaled to the unit displayed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct NumericDisplay {
+    pub label: String,
+    pub signal: String,
+    /// Value of one unit of the signal in the unit displayed.
+    #[serde(default = "NumericDisplay::default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default = "NumericDisplay::default_decimals")]
+    pub decimals: usize,
+}
+
+impl NumericDisplay {
+    fn default_scale() -> f32 {
+        1.0
+    }
+
+    fn default_decimals() -> usize {
+        2
+    }
+
+    /// Text displayed for `value`, dashes when the signal has no value.
+    pub fn format(&self, value: Option<f32>) -> String {
+        let number = match value {
+            Some(value) => format!("{:.*}", self.decimals, value * self.scale),
+            None => "---".to_string(),
+        };
+        format!("{number} {}", self.unit).trim_end().to_string()
+    }
+}
+
+/// Writes a setpoint within a range.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Slider {
+    pub label: String,
+    pub signal: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// An element of the operator panel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Widget {
+    Button(Button),
+    Lamp(Lamp),
+    Display(NumericDisplay),
+    Slider(Slider),
+}
+
+impl Widget {
+    pub fn signal(&self) -> &str {
+        match self {
+            Widget::Button(button) => &button.signal,
+            Widget::Lamp(lamp) => &lamp.signal,
+            Widget::Display(display) => &display.signal,
+            Widget::Slider(slider) => &slider.signal,
+        }
+    }
+}
+
+/// Represents the layout of the operator panel: its widgets, from top to bottom.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct HmiLayout {
+    pub widgets: Vec<Widget>,
+}
+
+impl Default for HmiLayout {
+    fn default() -> Self {
+        Self {
+            widgets: vec![
+                Widget::Display(NumericDisplay {
+                    label: "Pendulum".to_string(),
+                    signal: PENDULUM_ANGLE.to_string(),
+                    scale: 180.0 / std::f32::consts::PI,
+                    unit: "°".to_string(),
+                    decimals: 1,
+                }),
+                Widget::Display(NumericDisplay {
+                    label: "Torque".to_string(),
+                    signal: MOTOR_TORQUE.to_string(),
+                    scale: 1.0,
+                    unit: "N·m".to_string(),
+                    decimals: 2,
+                }),
+                Widget::Lamp(Lamp {
+                    label: "Running".to_string(),
+                    signal: MOTOR_VELOCITY.to_string(),
+                    threshold: 0.0,
+                    color: Lamp::default_color(),
+                }),
+                Widget::Slider(Slider {
+                    label: "Motor velocity (rad/s)".to_string(),
+                    signal: MOTOR_VELOCITY.to_string(),
+                    min: -5.0,
+                    max: 5.0,
+                }),
+                Widget::Button(Button {
+                    label: "Stop".to_string(),
+                    signal: MOTOR_VELOCITY.to_string(),
+                    value: 0.0,
+                }),
+            ],
+        }
+    }
+}
+
+impl HmiLayout {
+    /// Checks that every widget is bound to a signal, and that the sliders have a range.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Error::Config {
+            name: "hmi".to_string(),
+            message,
+        };
+        for (index, widget) in self.widgets.iter().enumerate() {
+            if widget.signal().is_empty() {
+                return Err(invalid(format!("widget {} has no signal", index + 1)));
+            }
+            if let Widget::Slider(slider) = widget {
+                if slider.min.partial_cmp(&slider.max) != Some(Ordering::Less) {
+                    return Err(invalid(format!(
+                        "the slider `{}` ranges from {} to {}",
+                        slider.label, slider.min, slider.max
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Value of a signal: the last sample of its telemetry channel, or else its setpoint.
+pub fn signal_value(
+    signal: &str,
+    telemetry: Option<&Telemetry>,
+    setpoints: &Setpoints,
+) -> Option<f32> {
+    telemetry
+        .and_then(|telemetry| telemetry.latest(signal))
+        .or_else(|| setpoints.get(signal))
+}
+
+fn setup(mut commands: Commands) {
+    let (layout, error) = config_plugin::load_config::<HmiLayout>("hmi", true);
+    let invalid = layout.validate().err();
+    commands.insert_resource(layout);
+    for error in [error, invalid].into_iter().flatten() {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Operator panel, laid out from `hmi.json`.
+fn hmi_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut layout: ResMut<Persistent<HmiLayout>>,
+    telemetry: Option<Res<Telemetry>>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    let mut reload = false;
+    egui::Window::new("HMI")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for widget in &layout.widgets {
+                let value = signal_value(widget.signal(), telemetry.as_deref(), &setpoints);
+                match widget {
+                    Widget::Button(button) => {
+                        if ui.button(&button.label).clicked() {
+                            setpoints.set(&button.signal, button.value);
+                        }
+                    }
+                    Widget::Lamp(lamp) => {
+                        ui.horizontal(|ui| {
+                            let [r, g, b] = lamp.color;
+                            let color = if lamp.is_lit(value) {
+                                egui::Color32::from_rgb(r, g, b)
+                            } else {
+                                egui::Color32::from_gray(60)
+                            };
+                            ui.label(egui::RichText::new("⏺").color(color));
+                            ui.label(&lamp.label);
+                        });
+                    }
+                    Widget::Display(display) => {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}:", display.label));
+                            ui.monospace(display.format(value));
+                        });
+                    }
+                    Widget::Slider(slider) => {
+                        let mut edited = value.unwrap_or(0.0).clamp(slider.min, slider.max);
+                        let changed = ui
+                            .add(
+                                egui::Slider::new(&mut edited, slider.min..=slider.max)
+                                    .text(&slider.label),
+                            )
+                            .changed();
+                        if changed {
+                            setpoints.set(&slider.signal, edited);
+                        }
+                    }
+                }
+            }
+            ui.separator();
+            reload = ui.button("Reload layout").clicked();
+        });
+
+    if reload {
+        let result = layout
+            .reload()
+            .map_err(|error| Error::Config {
+                name: "hmi".to_string(),
+                message: error.to_string(),
+            })
+            .and_then(|()| layout.validate());
+        if let Err(error) = result {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Physical parameters of a plant fitted on a recorded run, as opposed to the black-box models of
+//! [`identification`](crate::identification).
+//!
+//! [`fit`] resamples the input and the output channels of the run, differentiates the output
+//! twice by central differences and fits the coefficients of a model structure, linear in them,
+//! by least squares:
+//!
+//! - a pendulum driven by a torque `u` on its joint, `J θ'' = -m g l sin θ - b θ' - c sign θ' + u`,
+//!   with `θ` from hanging down and the mass at the end of a massless rod, `J = m l²`, which
+//!   gives the mass, the length and the viscous and Coulomb friction,
+//! - a second-order transfer function `y'' + 2 ζ ω y' + ω² y = ω² (K u + y₀)`, which gives the
+//!   natural frequency, the damping ratio, the gain and the offset.
+//!
+//! The fitted model is then simulated on the recorded input from the initial output, and the
+//! residuals are reported both on the equation and on the simulated output. The structure and the
+//! channels are read from `ident.json`, and the fit runs from the command line with `--ident`.
+use std::{fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calibration,
+    error::{Error, Result},
+    headless::DEFAULT_TIME_STEP,
+    identification,
+    setpoints::MOTOR_POSITION,
+    telemetry::{Telemetry, MOTOR_ANGLE},
+};
+
+/// Model structure fitted.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelStructure {
+    Pendulum,
+    #[default]
+    SecondOrder,
+}
+
+impl ModelStructure {
+    /// Regressors of the second derivative of the output: `sin θ, θ', sign θ', u` for the
+    /// pendulum, `y, y', u, 1` for the transfer function.
+    fn regressors(self, output: f64, derivative: f64, input: f64) -> [f64; 4] {
+        match self {
+            Self::Pendulum => [output.sin(), derivative, derivative.signum(), input],
+            Self::SecondOrder => [output, derivative, input, 1.0],
+        }
+    }
+}
+
+/// Represents how the parameters are fitted, from the `ident.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct IdentSettings {
+    pub model: ModelStructure,
+    /// Channel of the run driving the model.
+    pub input: String,
+    /// Channel of the run the model predicts.
+    pub output: String,
+    /// Sample period the run is resampled at, in s.
+    pub dt: f32,
+    /// Width of the moving average smoothing the output before it is differentiated, in
+    /// samples.
+    pub smoothing: usize,
+    /// Acceleration of gravity of the pendulum, in m/s².
+    pub gravity: f64,
+}
+
+impl Default for IdentSettings {
+    fn default() -> Self {
+        Self {
+            model: ModelStructure::SecondOrder,
+            input: MOTOR_POSITION.to_string(),
+            output: MOTOR_ANGLE.to_string(),
+            dt: DEFAULT_TIME_STEP,
+            smoothing: 5,
+            gravity: 9.81,
+        }
+    }
+}
+
+/// A fitted physical parameter.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+/// The parameters fitted on a run, and their residuals.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fit {
+    pub model: ModelStructure,
+    pub parameters: Vec<Parameter>,
+    /// Coefficients of the regressors of the second derivative of the output.
+    pub coefficients: Vec<f64>,
+    /// RMS residual of the second derivative of the output.
+    pub equation_rms: f64,
+    /// RMS and largest residuals of the simulated output, and its fit in percent.
+    pub output_rms: f64,
+    pub output_max: f64,
+    pub output_fit: f64,
+    pub dt: f32,
+    /// The recorded input and output, and the output simulated, one per sample.
+    pub inputs: Vec<f64>,
+    pub outputs: Vec<f64>,
+    pub simulated: Vec<f64>,
+}
+
+impl Fit {
+    /// Writes the recorded output side by side with the simulated one and the residual, as CSV.
+    pub fn write_residuals(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from("time,input,output,output (model),residual\n");
+        for (k, ((input, output), simulated)) in self
+            .inputs
+            .iter()
+            .zip(&self.outputs)
+            .zip(&self.simulated)
+            .enumerate()
+        {
+            csv.push_str(&format!(
+                "{},{input},{output},{simulated},{}\n",
+                k as f32 * self.dt,
+                output - simulated
+            ));
+        }
+        fs::write(path, csv).map_err(|error| Error::io(path, error))
+    }
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let structure = match self.model {
+            ModelStructure::Pendulum => "pendulum",
+            ModelStructure::SecondOrder => "second-order transfer function",
+        };
+        writeln!(f, "{structure} fitted on {} samples:", self.outputs.len())?;
+        for parameter in &self.parameters {
+            writeln!(
+                f,
+                "  {}: {:.6} {}",
+                parameter.name, parameter.value, parameter.unit
+            )?;
+        }
+        writeln!(
+            f,
+            "residuals: {:.6} RMS on the second derivative, {:.6} RMS and {:.6} at most on the \
+             output, fit {:.1} %",
+            self.equation_rms, self.output_rms, self.output_max, self.output_fit
+        )
+    }
+}
+
+/// Centered moving average of `values` over `width` samples, narrower at the ends.
+fn smoothed(values: &[f64], width: usize) -> Vec<f64> {
+    let half = width / 2;
+    (0..values.len())
+        .map(|k| {
+            let window = &values[k.saturating_sub(half)..(k + half + 1).min(values.len())];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect()
+}
+
+/// The physical parameters of `model` from its coefficients.
+fn parameters(model: ModelStructure, coefficients: &[f64], gravity: f64) -> Result<Vec<Parameter>> {
+    let parameter = |name, value, unit| Parameter { name, value, unit };
+    match model {
+        ModelStructure::Pendulum => {
+            let [gravity_term, viscous, coulomb, gain] = coefficients[..] else {
+                unreachable!()
+            };
+            if gain <= 0.0 || gravity_term >= 0.0 {
+                return Err(Error::Identification(format!(
+                    "the fitted coefficients {coefficients:?} aren't those of a pendulum"
+                )));
+            }
+            let inertia = 1.0 / gain;
+            let length = -gravity / gravity_term;
+            Ok(vec![
+                parameter("mass", inertia / (length * length), "kg"),
+                parameter("length", length, "m"),
+                parameter("inertia", inertia, "kg·m²"),
+                parameter("viscous friction", -viscous * inertia, "N·m·s/rad"),
+                parameter("Coulomb friction", -coulomb * inertia, "N·m"),
+            ])
+        }
+        ModelStructure::SecondOrder => {
+            let [stiffness, damping, gain, offset] = coefficients[..] else {
+                unreachable!()
+            };
+            if stiffness >= 0.0 {
+                return Err(Error::Identification(format!(
+                    "the fitted coefficients {coefficients:?} have no natural frequency"
+                )));
+            }
+            let frequency = (-stiffness).sqrt();
+            Ok(vec![
+                parameter("natural frequency", frequency, "rad/s"),
+                parameter("damping ratio", -damping / (2.0 * frequency), ""),
+                parameter("gain", -gain / stiffness, ""),
+                parameter("offset", -offset / stiffness, ""),
+            ])
+        }
+    }
+}
+
+/// Fits the parameters of the structure of `settings` on `run`.
+pub fn fit(run: &Telemetry, settings: &IdentSettings) -> Result<Fit> {
+    let data = calibration::dataset(
+        run,
+        &[settings.input.clone()],
+        &[settings.output.clone()],
+        settings.dt,
+    )?;
+    let inputs: Vec<f64> = data.inputs.iter().map(|input| input[0]).collect();
+    let outputs: Vec<f64> = data.outputs.iter().map(|output| output[0]).collect();
+    if outputs.len() < 6 {
+        return Err(Error::Identification(format!(
+            "{} samples are too few to fit 4 coefficients",
+            outputs.len()
+        )));
+    }
+
+    let dt = f64::from(data.dt);
+    let smooth = smoothed(&outputs, settings.smoothing.max(1));
+    let rows = outputs.len() - 2;
+    let mut regressors = DMatrix::zeros(rows, 4);
+    let mut accelerations = DVector::zeros(rows);
+    for k in 1..outputs.len() - 1 {
+        let derivative = (smooth[k + 1] - smooth[k - 1]) / (2.0 * dt);
+        let row = settings.model.regressors(smooth[k], derivative, inputs[k]);
+        for (column, value) in row.into_iter().enumerate() {
+            regressors[(k - 1, column)] = value;
+        }
+        accelerations[k - 1] = (smooth[k + 1] - 2.0 * smooth[k] + smooth[k - 1]) / (dt * dt);
+    }
+    let coefficients = regressors
+        .clone()
+        .svd(true, true)
+        .solve(&accelerations, 1e-12)
+        .map_err(|error| Error::Identification(error.to_string()))?;
+    let equation_rms = (&regressors * &coefficients - &accelerations).norm() / (rows as f64).sqrt();
+    let coefficients: Vec<f64> = coefficients.iter().copied().collect();
+    let parameters = parameters(settings.model, &coefficients, settings.gravity)?;
+
+    // Simulates the fitted model with the semi-implicit Euler method, from the initial output.
+    let mut output = smooth[0];
+    let mut derivative = (smooth[1] - smooth[0]) / dt;
+    let simulated: Vec<f64> = inputs
+        .iter()
+        .map(|input| {
+            let current = output;
+            let acceleration: f64 = settings
+                .model
+                .regressors(output, derivative, *input)
+                .iter()
+                .zip(&coefficients)
+                .map(|(regressor, coefficient)| regressor * coefficient)
+                .sum();
+            derivative += acceleration * dt;
+            output += derivative * dt;
+            current
+        })
+        .collect();
+    let residuals = outputs.iter().zip(&simulated).map(|(y, s)| y - s);
+    let output_rms = (residuals.clone().map(|r| r * r).sum::<f64>() / outputs.len() as f64).sqrt();
+    let output_max = residuals.map(f64::abs).fold(0.0, f64::max);
+    let rows = |values: &[f64]| values.iter().map(|value| vec![*value]).collect::<Vec<_>>();
+    let output_fit = identification::fit_percent(&rows(&outputs), &rows(&simulated))[0];
+
+    Ok(Fit {
+        model: settings.model,
+        parameters,
+        coefficients,
+        equation_rms,
+        output_rms,
+        output_max,
+        output_fit,
+        dt: data.dt,
+        inputs,
+        outputs,
+        simulated,
+    })
+}
@@ -0,0 +1,443 @@
+//! Reduced-order models identified from input/output data of the simulation.
+//!
+//! [`identify`] fits a discrete-time linear state-space model of a chosen order with a subspace
+//! method in the spirit of N4SID: the oblique projection of the future outputs on the past data
+//! is factored by an SVD, which gives a state sequence, and the matrices follow by least
+//! squares. [`run_experiment`] collects the data by exciting the setpoints of a plant with a
+//! pseudo-random binary sequence in a headless application.
+//!
+//! The data are centered and scaled before the fit; the model keeps the offsets, so it works in
+//! the units of the signals.
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    headless::headless_app,
+    plants::Plant,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+};
+
+/// Singular values below this fraction of the largest one are treated as zero.
+const RANK_TOLERANCE: f64 = 1e-10;
+
+/// Discrete-time linear model `x' = A x + B u`, `y = C x + D u`, around operating offsets.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LinearModel {
+    /// Sample period, in seconds.
+    pub dt: f32,
+    /// Names of the inputs (setpoints).
+    pub inputs: Vec<String>,
+    /// Names of the outputs (telemetry channels, or setpoints).
+    pub outputs: Vec<String>,
+    #[serde(with = "rows")]
+    pub a: DMatrix<f64>,
+    #[serde(with = "rows")]
+    pub b: DMatrix<f64>,
+    #[serde(with = "rows")]
+    pub c: DMatrix<f64>,
+    #[serde(with = "rows")]
+    pub d: DMatrix<f64>,
+    /// Inputs and outputs at the operating point the model is linearized around.
+    pub input_offsets: Vec<f64>,
+    pub output_offsets: Vec<f64>,
+}
+
+impl LinearModel {
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+
+    pub fn order(&self) -> usize {
+        self.a.nrows()
+    }
+
+    /// Simulates the model from `initial_state` for the given inputs, one row per sample.
+    pub fn simulate(&self, inputs: &[Vec<f64>], initial_state: &DVector<f64>) -> Vec<Vec<f64>> {
+        let mut state = initial_state.clone();
+        inputs
+            .iter()
+            .map(|input| {
+                let input = DVector::from_iterator(
+                    input.len(),
+                    input.iter().zip(&self.input_offsets).map(|(u, u0)| u - u0),
+                );
+                let output = &self.c * &state + &self.d * &input;
+                state = &self.a * &state + &self.b * &input;
+                output
+                    .iter()
+                    .zip(&self.output_offsets)
+                    .map(|(y, y0)| y + y0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Initial state explaining the outputs best, in the least-squares sense.
+    pub fn estimate_initial_state(
+        &self,
+        inputs: &[Vec<f64>],
+        outputs: &[Vec<f64>],
+    ) -> DVector<f64> {
+        let order = self.order();
+        let outputs_count = self.outputs.len();
+        let forced = self.simulate(inputs, &DVector::zeros(order));
+        // The free response is `C A^k x0`.
+        let mut free = DMatrix::zeros(outputs.len() * outputs_count, order);
+        let mut residual = DVector::zeros(outputs.len() * outputs_count);
+        let mut power = self.c.clone();
+        for (k, (measured, forced)) in outputs.iter().zip(&forced).enumerate() {
+            free.rows_mut(k * outputs_count, outputs_count)
+                .copy_from(&power);
+            for (channel, (y, forced)) in measured.iter().zip(forced).enumerate() {
+                residual[k * outputs_count + channel] = y - forced;
+            }
+            power = &power * &self.a;
+        }
+        pseudo_inverse(free) * residual
+    }
+}
+
+/// Normalized root-mean-square fit of a prediction, in percent per output: 100 is a perfect fit,
+/// 0 is no better than the mean of the measurement.
+pub fn fit_percent(measured: &[Vec<f64>], predicted: &[Vec<f64>]) -> Vec<f64> {
+    let channels = measured.first().map_or(0, Vec::len);
+    (0..channels)
+        .map(|channel| {
+            let mean = measured.iter().map(|y| y[channel]).sum::<f64>() / measured.len() as f64;
+            let (error, spread) = measured.iter().zip(predicted).fold(
+                (0.0, 0.0),
+                |(error, spread), (y, prediction)| {
+                    (
+                        error + (y[channel] - prediction[channel]).powi(2),
+                        spread + (y[channel] - mean).powi(2),
+                    )
+                },
+            );
+            if spread > 0.0 {
+                100.0 * (1.0 - (error / spread).sqrt())
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Writes the data of a validation run side by side with the outputs predicted by the model, as
+/// CSV: the time, the inputs, then each output followed by its prediction.
+pub fn write_validation(
+    path: &Path,
+    model: &LinearModel,
+    data: &Dataset,
+    predicted: &[Vec<f64>],
+) -> Result<()> {
+    let mut csv = String::from("time");
+    for input in &model.inputs {
+        csv.push_str(&format!(",{input}"));
+    }
+    for output in &model.outputs {
+        csv.push_str(&format!(",{output},{output} (model)"));
+    }
+    csv.push('\n');
+    for (k, ((inputs, outputs), predicted)) in data
+        .inputs
+        .iter()
+        .zip(&data.outputs)
+        .zip(predicted)
+        .enumerate()
+    {
+        csv.push_str(&format!("{}", k as f32 * data.dt));
+        for input in inputs {
+            csv.push_str(&format!(",{input}"));
+        }
+        for (output, prediction) in outputs.iter().zip(predicted) {
+            csv.push_str(&format!(",{output},{prediction}"));
+        }
+        csv.push('\n');
+    }
+    fs::write(path, csv).map_err(|error| Error::io(path, error))
+}
+
+/// Samples of the inputs and outputs of an experiment, one row per step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dataset {
+    pub dt: f32,
+    pub inputs: Vec<Vec<f64>>,
+    pub outputs: Vec<Vec<f64>>,
+}
+
+/// Fits a model of `order` states to the data, using `horizon` block rows for the past and the
+/// future (a few times the order is a good start).
+pub fn identify(
+    data: &Dataset,
+    input_names: &[String],
+    output_names: &[String],
+    order: usize,
+    horizon: usize,
+) -> Result<LinearModel> {
+    let samples = data.inputs.len().min(data.outputs.len());
+    let (m, l) = (input_names.len(), output_names.len());
+    if order == 0 || horizon < order.div_ceil(l.max(1)) + 1 {
+        return Err(Error::Identification(format!(
+            "a horizon of {horizon} can't reveal {order} states from {l} outputs"
+        )));
+    }
+    if samples < 2 * horizon + 2 * ((m + l) * horizon + m * horizon) {
+        return Err(Error::Identification(format!(
+            "{samples} samples aren't enough for a horizon of {horizon}"
+        )));
+    }
+    let (inputs, input_offsets, input_scales) = standardize(&data.inputs[..samples], m);
+    let (outputs, output_offsets, output_scales) = standardize(&data.outputs[..samples], l);
+
+    // Past and future block Hankel matrices.
+    let columns = samples - 2 * horizon + 1;
+    let past_inputs = block_hankel(&inputs, 0, horizon, columns);
+    let future_inputs = block_hankel(&inputs, horizon, horizon, columns);
+    let past_outputs = block_hankel(&outputs, 0, horizon, columns);
+    let future_outputs = block_hankel(&outputs, horizon, horizon, columns);
+    let past = stack(&past_inputs, &past_outputs);
+
+    // Oblique projection of the future outputs along the future inputs on the past.
+    let regressors = stack(&past, &future_inputs);
+    let coefficients = &future_outputs * pseudo_inverse(regressors);
+    let projection = coefficients.columns(0, past.nrows()) * &past;
+
+    let svd = projection.svd(false, true);
+    let v_t = svd
+        .v_t
+        .ok_or_else(|| Error::Identification("the SVD didn't converge".to_string()))?;
+    if order > svd.singular_values.len()
+        || svd.singular_values[order - 1] <= svd.singular_values[0] * RANK_TOLERANCE
+    {
+        return Err(Error::Identification(format!(
+            "the data don't excite {order} states"
+        )));
+    }
+    // State sequence, from the dominant singular vectors.
+    let mut states = v_t.rows(0, order).into_owned();
+    for (row, value) in svd.singular_values.iter().take(order).enumerate() {
+        states.row_mut(row).scale_mut(value.sqrt());
+    }
+
+    // Least squares on `[x(k+1); y(k)] = [A B; C D] [x(k); u(k)]`.
+    let steps = columns - 1;
+    let current = stack(
+        &states.columns(0, steps).into_owned(),
+        &inputs.columns(horizon, steps).into_owned(),
+    );
+    let next = stack(
+        &states.columns(1, steps).into_owned(),
+        &outputs.columns(horizon, steps).into_owned(),
+    );
+    let theta = next * pseudo_inverse(current);
+
+    // Back to the units of the signals.
+    let input_scaling = DMatrix::from_diagonal(&DVector::from_vec(input_scales).map(|s| 1.0 / s));
+    let output_scaling = DMatrix::from_diagonal(&DVector::from_vec(output_scales));
+    Ok(LinearModel {
+        dt: data.dt,
+        inputs: input_names.to_vec(),
+        outputs: output_names.to_vec(),
+        a: theta.view((0, 0), (order, order)).into_owned(),
+        b: theta.view((0, order), (order, m)) * &input_scaling,
+        c: &output_scaling * theta.view((order, 0), (l, order)),
+        d: &output_scaling * theta.view((order, order), (l, m)) * &input_scaling,
+        input_offsets,
+        output_offsets,
+    })
+}
+
+/// Centers and scales the samples to a unit standard deviation, returning them as one row per
+/// channel, along with the means and the scales.
+fn standardize(samples: &[Vec<f64>], channels: usize) -> (DMatrix<f64>, Vec<f64>, Vec<f64>) {
+    let mut matrix = DMatrix::from_fn(channels, samples.len(), |channel, k| samples[k][channel]);
+    let mut offsets = Vec::with_capacity(channels);
+    let mut scales = Vec::with_capacity(channels);
+    for mut row in matrix.row_iter_mut() {
+        let mean = row.mean();
+        row.add_scalar_mut(-mean);
+        let deviation = row.norm() / (row.len() as f64).sqrt();
+        // A constant channel is kept as is.
+        let scale = if deviation > 0.0 { deviation } else { 1.0 };
+        row.unscale_mut(scale);
+        offsets.push(mean);
+        scales.push(scale);
+    }
+    (matrix, offsets, scales)
+}
+
+/// Block Hankel matrix of `blocks` block rows starting at sample `first`.
+fn block_hankel(
+    signals: &DMatrix<f64>,
+    first: usize,
+    blocks: usize,
+    columns: usize,
+) -> DMatrix<f64> {
+    let channels = signals.nrows();
+    DMatrix::from_fn(blocks * channels, columns, |row, column| {
+        signals[(row % channels, first + row / channels + column)]
+    })
+}
+
+fn stack(top: &DMatrix<f64>, bottom: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut stacked = DMatrix::zeros(top.nrows() + bottom.nrows(), top.ncols());
+    stacked.rows_mut(0, top.nrows()).copy_from(top);
+    stacked
+        .rows_mut(top.nrows(), bottom.nrows())
+        .copy_from(bottom);
+    stacked
+}
+
+fn pseudo_inverse(matrix: DMatrix<f64>) -> DMatrix<f64> {
+    let (rows, columns) = matrix.shape();
+    let svd = matrix.svd(true, true);
+    let tolerance = svd.singular_values.max() * RANK_TOLERANCE * rows.max(columns) as f64;
+    svd.pseudo_inverse(tolerance)
+        .unwrap_or_else(|_| DMatrix::zeros(columns, rows))
+}
+
+/// An identification experiment, from the `identification.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct Experiment {
+    /// Setpoints excited by the experiment.
+    pub inputs: Vec<String>,
+    /// Telemetry channels (or setpoints) measured.
+    pub outputs: Vec<String>,
+    /// Number of physics steps left to the plant to settle before each run.
+    pub settle_steps: usize,
+    /// Number of physics steps of each run.
+    pub steps: usize,
+    /// The setpoints switch between plus and minus this amplitude.
+    pub amplitude: f32,
+    /// The setpoints are held for at least this many steps between two switches.
+    pub hold_steps: usize,
+    /// Number of states of the model.
+    pub order: usize,
+    /// Number of block rows of the Hankel matrices.
+    pub horizon: usize,
+}
+
+impl Default for Experiment {
+    fn default() -> Self {
+        Self {
+            inputs: vec![MOTOR_VELOCITY.to_string()],
+            outputs: vec![PENDULUM_ANGLE.to_string(), MOTOR_TORQUE.to_string()],
+            settle_steps: 600,
+            steps: 2_400,
+            amplitude: 0.5,
+            hold_steps: 30,
+            order: 4,
+            horizon: 30,
+        }
+    }
+}
+
+/// Runs the experiment on `plant` in a headless application. The pseudo-random excitation
+/// follows from `seed`, so runs with different seeds validate each other.
+pub fn run_experiment(plant: &Plant, experiment: &Experiment, dt: f32, seed: u64) -> Dataset {
+    excite(plant, experiment, dt, seed, |_, _, level| level)
+}
+
+/// Runs the experiment as [`run_experiment`], setting the inputs to `applied(step, input,
+/// level)` rather than to the levels of the excitation, e.g. to inject faults into the
+/// actuators. The data keep the levels.
+pub fn excite(
+    plant: &Plant,
+    experiment: &Experiment,
+    dt: f32,
+    seed: u64,
+    mut applied: impl FnMut(usize, usize, f32) -> f32,
+) -> Dataset {
+    let mut app = headless_app(dt);
+    (plant.add)(&mut app);
+    app.init_resource::<Setpoints>()
+        .init_resource::<Telemetry>();
+    app.finish();
+    app.cleanup();
+    for _ in 0..experiment.settle_steps {
+        app.update();
+    }
+
+    let mut random = seed.max(1);
+    let mut levels = vec![experiment.amplitude; experiment.inputs.len()];
+    let mut data = Dataset { dt, ..default() };
+    for step in 0..experiment.steps {
+        if step % experiment.hold_steps.max(1) == 0 {
+            for level in &mut levels {
+                // xorshift64: the low bit of each draw decides the sign.
+                random ^= random << 13;
+                random ^= random >> 7;
+                random ^= random << 17;
+                *level = if random & 1 == 0 {
+                    experiment.amplitude
+                } else {
+                    -experiment.amplitude
+                };
+            }
+        }
+        let mut setpoints = app.world_mut().resource_mut::<Setpoints>();
+        for (index, (input, level)) in experiment.inputs.iter().zip(&levels).enumerate() {
+            setpoints.set(input, applied(step, index, *level));
+        }
+        app.update();
+
+        let world = app.world();
+        let (setpoints, telemetry) = (world.resource::<Setpoints>(), world.resource::<Telemetry>());
+        data.inputs
+            .push(levels.iter().map(|level| f64::from(*level)).collect());
+        data.outputs.push(
+            experiment
+                .outputs
+                .iter()
+                .map(|signal| {
+                    let value = telemetry.latest(signal).or_else(|| setpoints.get(signal));
+                    f64::from(value.unwrap_or_default())
+                })
+                .collect(),
+        );
+    }
+    data
+}
+
+/// (De)serializes a matrix as a list of rows, readable and easy to load elsewhere.
+mod rows {
+    use nalgebra::DMatrix;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        matrix: &DMatrix<f64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let rows = matrix
+            .row_iter()
+            .map(|row| row.iter().copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DMatrix<f64>, D::Error> {
+        let rows = Vec::<Vec<f64>>::deserialize(deserializer)?;
+        let columns = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != columns) {
+            return Err(D::Error::custom("rows of different lengths"));
+        }
+        Ok(DMatrix::from_fn(rows.len(), columns, |row, column| {
+            rows[row][column]
+        }))
+    }
+}
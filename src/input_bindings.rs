@@ -0,0 +1,629 @@
+//! This module maps the keyboard and the gamepads onto the actions of the scene, from the
+//! `input_bindings.json` configuration file, so every hotkey can be remapped in one place.
+//!
+//! Each action is bound to any number of keys and gamepad buttons: orbiting and zooming the
+//! camera, pausing the simulation, stepping it once or changing its speed, resetting the scene,
+//! starting and stopping a recording, and jogging the motor. The [`InputActions`] resource holds
+//! the actions pressed this frame, for the modules performing them: the snapshots reset the
+//! scene and the recording toggles on them, besides their own keys. The keys are ignored while a
+//! text field has the focus.
+//!
+//! Gamepad axes also drive torques on the joints, to move a link by hand: the travel of the axis
+//! past its deadzone is scaled to the full-scale torque, applied to the link about the axis of
+//! its joint as an impulse per step, and recorded as the `teleop/<link>/torque` telemetry
+//! channel. The motor holding the arm resists the torque, unless released.
+//!
+//! The *Input bindings* panel lists the bindings: *Bind* waits for the next key or gamepad
+//! button pressed, Escape cancelling.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    input::{
+        gamepad::{GamepadAxis, GamepadButton, GamepadInput},
+        InputSystem,
+    },
+    prelude::*,
+    time::Real,
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    gamepad_mapping::{input_position, travel},
+    plants::Link,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+
+/// Closest the camera gets to looking straight up or down, in rad.
+const PITCH_MARGIN: f32 = 0.01;
+
+pub struct InputBindingsPlugin;
+
+impl Plugin for InputBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputActions>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PreUpdate,
+                read_actions
+                    .after(InputSystem)
+                    .run_if(resource_exists::<Persistent<InputBindings>>),
+            )
+            .add_systems(
+                Update,
+                (control_clock, move_cameras, jog, apply_torques)
+                    .run_if(resource_exists::<Persistent<InputBindings>>),
+            )
+            .add_systems(
+                Update,
+                (
+                    track_typing,
+                    input_bindings_panel.run_if(resource_exists::<Persistent<InputBindings>>),
+                )
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// An action of the scene triggered from the keyboard or the gamepads.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+    OrbitLeft,
+    OrbitRight,
+    OrbitUp,
+    OrbitDown,
+    ZoomIn,
+    ZoomOut,
+    TogglePause,
+    /// Steps the physics once, pausing the simulation.
+    Step,
+    /// Moves to the next slower or faster speed of the simulation.
+    Slower,
+    Faster,
+    Reset,
+    Record,
+    JogPositive,
+    JogNegative,
+}
+
+impl InputAction {
+    pub const ALL: [Self; 14] = [
+        Self::OrbitLeft,
+        Self::OrbitRight,
+        Self::OrbitUp,
+        Self::OrbitDown,
+        Self::ZoomIn,
+        Self::ZoomOut,
+        Self::TogglePause,
+        Self::Step,
+        Self::Slower,
+        Self::Faster,
+        Self::Reset,
+        Self::Record,
+        Self::JogPositive,
+        Self::JogNegative,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OrbitLeft => "Orbit left",
+            Self::OrbitRight => "Orbit right",
+            Self::OrbitUp => "Orbit up",
+            Self::OrbitDown => "Orbit down",
+            Self::ZoomIn => "Zoom in",
+            Self::ZoomOut => "Zoom out",
+            Self::TogglePause => "Pause and resume",
+            Self::Step => "Step once",
+            Self::Slower => "Slow down",
+            Self::Faster => "Speed up",
+            Self::Reset => "Reset the scene",
+            Self::Record => "Start and stop recording",
+            Self::JogPositive => "Jog forward",
+            Self::JogNegative => "Jog backward",
+        }
+    }
+}
+
+/// A key or a button of the gamepads.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputButton {
+    Key(KeyCode),
+    Gamepad(GamepadButton),
+}
+
+impl InputButton {
+    /// Name of the button, as listed in the panel.
+    pub fn label(self) -> String {
+        match self {
+            Self::Key(key) => format!("{key:?}"),
+            Self::Gamepad(button) => format!("{button:?} button"),
+        }
+    }
+}
+
+/// An input bound to an action.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ActionBinding {
+    pub action: InputAction,
+    pub button: InputButton,
+}
+
+/// A torque on the joint of a link driven by an axis of the gamepads.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct TorqueCommand {
+    /// Path of the link, e.g. `feeder/arm`.
+    pub link: String,
+    pub axis: Option<GamepadAxis>,
+    /// Torque at the end of the travel of the axis, in N·m.
+    pub scale: f32,
+    /// Travel from rest ignored, as a fraction of the full travel.
+    pub deadzone: f32,
+    pub invert: bool,
+}
+
+impl Default for TorqueCommand {
+    fn default() -> Self {
+        Self {
+            link: "arm".to_string(),
+            axis: None,
+            scale: 0.5,
+            deadzone: 0.1,
+            invert: false,
+        }
+    }
+}
+
+impl TorqueCommand {
+    /// Torque for a position of the axis, in N·m.
+    pub fn torque(&self, position: f32) -> f32 {
+        let sign = if self.invert { -1.0 } else { 1.0 };
+        sign * travel(position, self.deadzone) * self.scale
+    }
+}
+
+/// Represents the bindings of the inputs, from the `input_bindings.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct InputBindings {
+    pub enabled: bool,
+    pub actions: Vec<ActionBinding>,
+    /// Speed at which the camera orbits, in rad/s, and zooms, as a fraction of its distance per
+    /// second.
+    pub orbit_speed: f32,
+    pub zoom_speed: f32,
+    /// Velocity setpoint jogged, and its speed, in its unit.
+    pub jog_setpoint: String,
+    pub jog_speed: f32,
+    pub torques: Vec<TorqueCommand>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use GamepadButton as Pad;
+        use InputAction as Action;
+        let bindings = [
+            (Action::OrbitLeft, InputButton::Key(KeyCode::KeyA)),
+            (Action::OrbitRight, InputButton::Key(KeyCode::KeyD)),
+            (Action::OrbitUp, InputButton::Key(KeyCode::KeyW)),
+            (Action::OrbitDown, InputButton::Key(KeyCode::KeyS)),
+            (Action::ZoomIn, InputButton::Key(KeyCode::KeyE)),
+            (Action::ZoomOut, InputButton::Key(KeyCode::KeyQ)),
+            (Action::ZoomIn, InputButton::Gamepad(Pad::RightTrigger)),
+            (Action::ZoomOut, InputButton::Gamepad(Pad::LeftTrigger)),
+            (Action::TogglePause, InputButton::Key(KeyCode::Space)),
+            (Action::TogglePause, InputButton::Gamepad(Pad::Start)),
+            (Action::Step, InputButton::Key(KeyCode::Period)),
+            (Action::Step, InputButton::Gamepad(Pad::East)),
+            (Action::Slower, InputButton::Key(KeyCode::BracketLeft)),
+            (Action::Faster, InputButton::Key(KeyCode::BracketRight)),
+            (Action::Slower, InputButton::Gamepad(Pad::DPadDown)),
+            (Action::Faster, InputButton::Gamepad(Pad::DPadUp)),
+            (Action::Reset, InputButton::Gamepad(Pad::Select)),
+            (Action::Record, InputButton::Gamepad(Pad::North)),
+            (Action::JogPositive, InputButton::Key(KeyCode::KeyK)),
+            (Action::JogNegative, InputButton::Key(KeyCode::KeyJ)),
+            (Action::JogPositive, InputButton::Gamepad(Pad::DPadRight)),
+            (Action::JogNegative, InputButton::Gamepad(Pad::DPadLeft)),
+        ];
+        Self {
+            enabled: true,
+            actions: bindings
+                .into_iter()
+                .map(|(action, button)| ActionBinding { action, button })
+                .collect(),
+            orbit_speed: 1.0,
+            zoom_speed: 1.0,
+            jog_setpoint: MOTOR_VELOCITY.to_string(),
+            jog_speed: 2.0,
+            torques: vec![TorqueCommand {
+                axis: Some(GamepadAxis::RightStickX),
+                ..default()
+            }],
+        }
+    }
+}
+
+impl InputBindings {
+    /// The actions with a button down, each once, given whether each button is down.
+    pub fn pressed(&self, down: impl Fn(InputButton) -> bool) -> Vec<InputAction> {
+        let mut pressed = Vec::new();
+        if !self.enabled {
+            return pressed;
+        }
+        for binding in &self.actions {
+            if !pressed.contains(&binding.action) && down(binding.button) {
+                pressed.push(binding.action);
+            }
+        }
+        pressed
+    }
+
+    /// The buttons bound to `action`.
+    pub fn buttons(&self, action: InputAction) -> impl Iterator<Item = InputButton> + '_ {
+        self.actions
+            .iter()
+            .filter(move |binding| binding.action == action)
+            .map(|binding| binding.button)
+    }
+}
+
+/// The actions pressed this frame, and those pressed since the last one.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct InputActions {
+    pressed: Vec<InputAction>,
+    just_pressed: Vec<InputAction>,
+    /// Whether a text field has the focus, the keys typing into it.
+    typing: bool,
+}
+
+impl InputActions {
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// 1 while the `positive` action alone is pressed, -1 while the `negative` one is, 0
+    /// otherwise.
+    pub fn direction(&self, positive: InputAction, negative: InputAction) -> f32 {
+        match (self.pressed(positive), self.pressed(negative)) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Takes the actions pressed this frame.
+    pub fn update(&mut self, pressed: Vec<InputAction>) {
+        self.just_pressed = pressed
+            .iter()
+            .copied()
+            .filter(|action| !self.pressed.contains(action))
+            .collect();
+        self.pressed = pressed;
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (bindings, error) = config_plugin::load_config::<InputBindings>("input_bindings", true);
+    commands.insert_resource(bindings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn track_typing(mut contexts: EguiContexts, mut actions: ResMut<InputActions>) {
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+    if actions.typing != typing {
+        actions.typing = typing;
+    }
+}
+
+/// Reads the buttons bound to the actions.
+fn read_actions(
+    bindings: Res<Persistent<InputBindings>>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    gamepads: Query<&Gamepad>,
+    mut actions: ResMut<InputActions>,
+) {
+    let typing = actions.typing;
+    let pressed = bindings.pressed(|button| match button {
+        InputButton::Key(key) => !typing && keys.as_ref().is_some_and(|keys| keys.pressed(key)),
+        InputButton::Gamepad(button) => gamepads.iter().any(|gamepad| gamepad.pressed(button)),
+    });
+    actions.update(pressed);
+}
+
+fn control_clock(actions: Res<InputActions>, mut clock: ResMut<SimClock>) {
+    if actions.just_pressed(InputAction::TogglePause) {
+        clock.toggle_pause();
+        let state = if clock.is_paused() {
+            "paused"
+        } else {
+            "resumed"
+        };
+        info!("Simulation {state} at {:.2} s", clock.elapsed_secs());
+    }
+    if actions.just_pressed(InputAction::Step) {
+        clock.step_once();
+    }
+    let faster = actions.just_pressed(InputAction::Faster);
+    if faster || actions.just_pressed(InputAction::Slower) {
+        clock.change_speed(faster);
+        info!("Simulation at {}x real time", clock.speed());
+    }
+}
+
+/// Orbits and zooms the cameras the mouse controls, in real time, so they move while paused.
+fn move_cameras(
+    bindings: Res<Persistent<InputBindings>>,
+    actions: Res<InputActions>,
+    time: Res<Time<Real>>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+) {
+    let yaw = actions.direction(InputAction::OrbitLeft, InputAction::OrbitRight);
+    let pitch = actions.direction(InputAction::OrbitUp, InputAction::OrbitDown);
+    let zoom = actions.direction(InputAction::ZoomIn, InputAction::ZoomOut);
+    if yaw == 0.0 && pitch == 0.0 && zoom == 0.0 {
+        return;
+    }
+    let dt = time.delta_secs();
+    for mut orbit in orbits.iter_mut().filter(|orbit| orbit.enabled) {
+        orbit.target_yaw += yaw * bindings.orbit_speed * dt;
+        orbit.target_pitch = (orbit.target_pitch + pitch * bindings.orbit_speed * dt)
+            .clamp(-FRAC_PI_2 + PITCH_MARGIN, FRAC_PI_2 - PITCH_MARGIN);
+        orbit.target_radius *= (1.0 - zoom * bindings.zoom_speed * dt).max(0.1);
+    }
+}
+
+/// Writes the jog setpoint while a jog action is pressed, and once more as it's released.
+fn jog(
+    bindings: Res<Persistent<InputBindings>>,
+    actions: Res<InputActions>,
+    mut setpoints: ResMut<Setpoints>,
+    mut jogging: Local<bool>,
+) {
+    let direction = actions.direction(InputAction::JogPositive, InputAction::JogNegative);
+    if direction == 0.0 && !*jogging {
+        // Leave the setpoint to the other drivers.
+        return;
+    }
+    let velocity = direction * bindings.jog_speed;
+    if setpoints.get(&bindings.jog_setpoint) != Some(velocity) {
+        setpoints.set(&bindings.jog_setpoint, velocity);
+    }
+    *jogging = direction != 0.0;
+}
+
+/// Applies the torques of the gamepad axes to their links, about the axes of their joints.
+fn apply_torques(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    bindings: Res<Persistent<InputBindings>>,
+    gamepads: Query<&Gamepad>,
+    mut links: Query<(
+        Entity,
+        &Link,
+        &Transform,
+        &ImpulseJoint,
+        Option<&mut ExternalImpulse>,
+    )>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    if !bindings.enabled || dt == 0.0 {
+        return;
+    }
+    for command in &bindings.torques {
+        let Some(axis) = command.axis else {
+            continue;
+        };
+        let torque = command.torque(input_position(&gamepads, GamepadInput::Axis(axis)));
+        if let Some(telemetry) = telemetry.as_mut() {
+            let channel = format!("teleop/{}/torque", command.link);
+            telemetry.record(&channel, clock.elapsed_secs(), torque);
+        }
+        if torque == 0.0 {
+            continue;
+        }
+        let Some((entity, _, transform, joint, impulse)) = links
+            .iter_mut()
+            .find(|(_, link, ..)| link.path() == command.link)
+        else {
+            continue;
+        };
+        let axis = (transform.rotation * joint.data.as_ref().local_axis2()).normalize_or_zero();
+        let torque_impulse = torque * axis * dt;
+        match impulse {
+            Some(mut impulse) => impulse.torque_impulse += torque_impulse,
+            None => {
+                commands.entity(entity).insert(ExternalImpulse {
+                    torque_impulse,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+/// Panel to bind the keys and gamepad buttons to the actions, and the gamepad axes to torques.
+fn input_bindings_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut bindings: ResMut<Persistent<InputBindings>>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    gamepads: Query<&Gamepad>,
+    mut listening: Local<Option<InputAction>>,
+) {
+    let mut edited = bindings.get().clone();
+
+    // The next button pressed is bound to the action listening.
+    if let Some(action) = *listening {
+        let key = keys
+            .as_ref()
+            .and_then(|keys| keys.get_just_pressed().next().copied());
+        let button = gamepads
+            .iter()
+            .find_map(|gamepad| gamepad.get_just_pressed().next().copied());
+        match (key, button) {
+            (Some(KeyCode::Escape), _) => *listening = None,
+            (Some(key), _) => {
+                edited.actions.push(ActionBinding {
+                    action,
+                    button: InputButton::Key(key),
+                });
+                *listening = None;
+            }
+            (None, Some(button)) => {
+                edited.actions.push(ActionBinding {
+                    action,
+                    button: InputButton::Gamepad(button),
+                });
+                *listening = None;
+            }
+            (None, None) => {}
+        }
+    }
+
+    egui::Window::new("Input bindings")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            egui::Grid::new("input_bindings").show(ui, |ui| {
+                for action in InputAction::ALL {
+                    ui.label(action.name());
+                    ui.horizontal(|ui| {
+                        let mut removed = None;
+                        for (index, binding) in edited.actions.iter().enumerate() {
+                            if binding.action == action
+                                && ui
+                                    .button(binding.button.label())
+                                    .on_hover_text("Click to unbind")
+                                    .clicked()
+                            {
+                                removed = Some(index);
+                            }
+                        }
+                        if let Some(index) = removed {
+                            edited.actions.remove(index);
+                        }
+                        if *listening == Some(action) {
+                            ui.label("Press a key or a button…");
+                        } else if ui.button("Bind").clicked() {
+                            *listening = Some(action);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.orbit_speed)
+                    .range(0.0..=10.0)
+                    .speed(0.05)
+                    .prefix("Orbit: ")
+                    .suffix(" rad/s"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.zoom_speed)
+                    .range(0.0..=5.0)
+                    .speed(0.05)
+                    .prefix("Zoom: ")
+                    .suffix(" /s"),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Jog");
+                ui.text_edit_singleline(&mut edited.jog_setpoint);
+                ui.add(
+                    egui::DragValue::new(&mut edited.jog_speed)
+                        .range(0.0..=100.0)
+                        .speed(0.1)
+                        .prefix("at "),
+                );
+            });
+
+            ui.separator();
+            ui.label("Torques of the gamepad axes on the joints");
+            let mut removed = None;
+            for (index, torque) in edited.torques.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut torque.link);
+                    egui::ComboBox::from_id_salt(("torque axis", index))
+                        .selected_text(
+                            torque
+                                .axis
+                                .map_or("Unbound".to_string(), |axis| format!("{axis:?}")),
+                        )
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut torque.axis, None, "Unbound");
+                            for axis in [
+                                GamepadAxis::LeftStickX,
+                                GamepadAxis::LeftStickY,
+                                GamepadAxis::RightStickX,
+                                GamepadAxis::RightStickY,
+                                GamepadAxis::LeftZ,
+                                GamepadAxis::RightZ,
+                            ] {
+                                ui.selectable_value(
+                                    &mut torque.axis,
+                                    Some(axis),
+                                    format!("{axis:?}"),
+                                );
+                            }
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut torque.scale)
+                            .speed(0.01)
+                            .prefix("Full scale: ")
+                            .suffix(" N·m"),
+                    );
+                    ui.add(egui::Slider::new(&mut torque.deadzone, 0.0..=0.5).text("Deadzone"));
+                    ui.checkbox(&mut torque.invert, "Invert");
+                });
+            }
+            if let Some(index) = removed {
+                edited.torques.remove(index);
+            }
+            if ui.button("Add a torque").clicked() {
+                edited.torques.push(TorqueCommand::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = bindings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("input_bindings", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = bindings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("input_bindings", error)));
+                    }
+                    edited = bindings.get().clone();
+                }
+            });
+        });
+
+    if edited != *bindings.get() {
+        *bindings.get_mut() = edited;
+    }
+}
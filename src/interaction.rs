@@ -0,0 +1,306 @@
+//! This module lets the user grab the links of the plants and drag them with the mouse, to feel
+//! how backdrivable they are and to disturb them by hand, whether their controllers are active
+//! or not.
+//!
+//! With the mode enabled in the *Interaction* panel, pressing the left button on a dynamic body
+//! grabs it at the point under the cursor, instead of orbiting the camera. While the button is
+//! held, a damped spring pulls that point towards the cursor ray, at the depth it was grabbed
+//! at. The force of the spring, limited so a drag can't throw the plant around, is applied at
+//! the grabbed point and recorded as the `drag/force` telemetry channel.
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    telemetry::Telemetry,
+    theme::Theme,
+};
+
+/// Magnitude of the force of the spring dragging a link, in N.
+pub const DRAG_FORCE: &str = "drag/force";
+/// Farthest body that can be grabbed, in m.
+const MAX_REACH: f32 = 1000.0;
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Drag>()
+            .init_resource::<CursorRay>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    (track_cursor, release, grab, pull, draw_drag).chain(),
+                    interaction_panel,
+                )
+                    .run_if(resource_exists::<Persistent<InteractionSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the manual interaction.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct InteractionSettings {
+    /// Whether the left button drags the links instead of orbiting the camera.
+    pub enabled: bool,
+    /// Stiffness of the spring from the grabbed point to the cursor, in N/m.
+    pub stiffness: f32,
+    /// Damping of the spring, in N·s/m.
+    pub damping: f32,
+    /// Largest force of the spring, in N.
+    pub max_force: f32,
+}
+
+impl Default for InteractionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stiffness: 50.0,
+            damping: 5.0,
+            max_force: 100.0,
+        }
+    }
+}
+
+impl InteractionSettings {
+    /// Force of the spring pulling the grabbed point, at `anchor` and moving at `velocity`,
+    /// towards the `target` on the cursor ray.
+    pub fn spring_force(&self, anchor: Vec3, target: Vec3, velocity: Vec3) -> Vec3 {
+        (self.stiffness * (target - anchor) - self.damping * velocity)
+            .clamp_length_max(self.max_force.max(0.0))
+    }
+}
+
+/// A grabbed body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grab {
+    pub entity: Entity,
+    /// Grabbed point, in the frame of the body.
+    pub local_anchor: Vec3,
+    /// Distance from the camera along the cursor ray the point is dragged at.
+    pub depth: f32,
+    /// Grabbed point and the point of the cursor ray it's pulled towards, in the world.
+    pub anchor: Vec3,
+    pub target: Vec3,
+    pub force: Vec3,
+}
+
+/// The body being dragged, if any.
+#[derive(Debug, Default, Resource)]
+pub struct Drag {
+    pub grab: Option<Grab>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<InteractionSettings>("interaction", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Ray under the cursor, from the active camera.
+#[derive(Debug, Default, Resource)]
+struct CursorRay(Option<Ray3d>);
+
+fn track_cursor(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut cursor: ResMut<CursorRay>,
+) {
+    cursor.0 = windows
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .and_then(|cursor| {
+            // The camera whose viewport holds the cursor, when the window is split.
+            let (camera, transform) = cameras.iter().find(|(camera, _)| {
+                camera.is_active
+                    && camera
+                        .logical_viewport_rect()
+                        .map_or(true, |rect| rect.contains(cursor))
+            })?;
+            camera.viewport_to_world(transform, cursor).ok()
+        });
+}
+
+/// Releases the body when the button is released, the mode disabled or the body despawned.
+fn release(
+    mut commands: Commands,
+    settings: Res<Persistent<InteractionSettings>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    bodies: Query<&Transform, With<RigidBody>>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut drag: ResMut<Drag>,
+) {
+    let held = settings.enabled && buttons.pressed(MouseButton::Left);
+    let Some(grab) = drag
+        .grab
+        .take_if(|grab| !held || bodies.get(grab.entity).is_err())
+    else {
+        return;
+    };
+    debug!(target: subsystem::PHYSICS, "Released {:?}", grab.entity);
+    if let Some(mut entity) = commands.get_entity(grab.entity) {
+        entity.remove::<ExternalForce>();
+    }
+    for mut orbit in &mut orbits {
+        orbit.enabled = true;
+    }
+}
+
+/// Grabs the dynamic body under the cursor, unless it's over the interface.
+fn grab(
+    mut egui: EguiContexts,
+    buttons: Res<ButtonInput<MouseButton>>,
+    cursor: Res<CursorRay>,
+    contexts: Query<&RapierContext>,
+    bodies: Query<&Transform, With<RigidBody>>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut drag: ResMut<Drag>,
+) {
+    if drag.grab.is_some() || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Some(ray), Ok(context)) = (cursor.0, contexts.get_single()) else {
+        return;
+    };
+    if egui.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    let Some((entity, depth)) = context.cast_ray(
+        ray.origin,
+        *ray.direction,
+        MAX_REACH,
+        true,
+        QueryFilter::only_dynamic(),
+    ) else {
+        return;
+    };
+    let Ok(transform) = bodies.get(entity) else {
+        return;
+    };
+    debug!(target: subsystem::PHYSICS, "Grabbed {entity:?}");
+    let point = ray.get_point(depth);
+    drag.grab = Some(Grab {
+        entity,
+        local_anchor: transform.rotation.inverse() * (point - transform.translation),
+        depth,
+        anchor: point,
+        target: point,
+        force: Vec3::ZERO,
+    });
+    for mut orbit in &mut orbits {
+        orbit.enabled = false;
+    }
+}
+
+/// Pulls the grabbed point towards the cursor ray.
+fn pull(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<InteractionSettings>>,
+    cursor: Res<CursorRay>,
+    bodies: Query<&Transform, With<RigidBody>>,
+    mut drag: ResMut<Drag>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let Some(grab) = &mut drag.grab else {
+        return;
+    };
+    let Ok(transform) = bodies.get(grab.entity) else {
+        return;
+    };
+    if let Some(ray) = cursor.0 {
+        grab.target = ray.get_point(grab.depth);
+    }
+    let anchor = transform.translation + transform.rotation * grab.local_anchor;
+    let dt = clock.delta_secs();
+    let velocity = if dt > 0.0 {
+        (anchor - grab.anchor) / dt
+    } else {
+        Vec3::ZERO
+    };
+    grab.anchor = anchor;
+    grab.force = settings.spring_force(anchor, grab.target, velocity);
+    commands.entity(grab.entity).insert(ExternalForce::at_point(
+        grab.force,
+        anchor,
+        transform.translation,
+    ));
+    if let Some(mut telemetry) = telemetry {
+        telemetry.record(DRAG_FORCE, clock.elapsed_secs(), grab.force.length());
+    }
+}
+
+/// Draws the spring between the grabbed point and the cursor.
+fn draw_drag(mut gizmos: Gizmos, drag: Res<Drag>, theme: Option<Res<Persistent<Theme>>>) {
+    let Some(grab) = &drag.grab else {
+        return;
+    };
+    let color = theme.map_or_else(|| Theme::default().series(1), |theme| theme.series(1));
+    gizmos.line(grab.anchor, grab.target, color);
+    gizmos.sphere(Isometry3d::from_translation(grab.target), 0.05, color);
+}
+
+/// Panel to enable the mode and tune the spring.
+fn interaction_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<InteractionSettings>>,
+    drag: Res<Drag>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Interaction")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Drag the links with the mouse")
+                .on_hover_text("The left button grabs a link instead of orbiting the camera");
+            ui.add(
+                egui::Slider::new(&mut edited.stiffness, 1.0..=1000.0)
+                    .logarithmic(true)
+                    .text("Stiffness (N/m)"),
+            );
+            ui.add(egui::Slider::new(&mut edited.damping, 0.0..=100.0).text("Damping (N·s/m)"));
+            ui.add(
+                egui::Slider::new(&mut edited.max_force, 1.0..=1000.0)
+                    .logarithmic(true)
+                    .text("Largest force (N)"),
+            );
+            match &drag.grab {
+                Some(grab) => ui.label(format!("Pulling with {:.1} N", grab.force.length())),
+                None => ui.label("Nothing grabbed"),
+            };
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("interaction", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("interaction", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
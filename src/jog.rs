@@ -0,0 +1,312 @@
+//! This module provides a jog panel to move the axes by hand, as when commissioning a machine:
+//! continuous jogs while a button is held, and incremental moves of a fixed distance.
+//!
+//! The axes are driven through their velocity setpoints, at a jog speed that never exceeds the
+//! velocity limit of the axis. Incremental moves integrate the commanded velocity over the
+//! simulated time, so they need no position encoder: they end on the requested distance, within
+//! what the axis covers in a physics step, as long as it tracks its velocity setpoint.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+pub struct JogPlugin;
+
+impl Plugin for JogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Jogs>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                jog.after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<JogSettings>>),
+            )
+            .add_systems(
+                Update,
+                jog_panel
+                    .run_if(resource_exists::<Persistent<JogSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Unit in which an axis is jogged.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JogUnit {
+    /// A rotary axis, whose setpoint is in rad/s.
+    #[default]
+    Degrees,
+    /// A linear axis, whose setpoint is in m/s.
+    Millimeters,
+}
+
+impl JogUnit {
+    /// Value of one unit in the unit of the setpoint.
+    pub fn scale(self) -> f32 {
+        match self {
+            JogUnit::Degrees => std::f32::consts::PI / 180.0,
+            JogUnit::Millimeters => 1e-3,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            JogUnit::Degrees => "°",
+            JogUnit::Millimeters => "mm",
+        }
+    }
+}
+
+/// An axis driven by a velocity setpoint.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct JogAxis {
+    pub name: String,
+    /// Velocity setpoint of the axis.
+    pub setpoint: String,
+    pub unit: JogUnit,
+    /// Jog speed, in units per second.
+    pub speed: f32,
+    /// Velocity limit of the axis, in units per second.
+    pub max_velocity: f32,
+}
+
+impl JogAxis {
+    /// Jog speed within the velocity limit, in the unit of the setpoint.
+    pub fn velocity(&self) -> f32 {
+        self.speed.clamp(0.0, self.max_velocity.max(0.0)) * self.unit.scale()
+    }
+}
+
+/// Represents the configuration of the jog panel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct JogSettings {
+    pub axes: Vec<JogAxis>,
+    /// Distances of the incremental moves offered, in units.
+    pub increments: Vec<f32>,
+}
+
+impl Default for JogSettings {
+    fn default() -> Self {
+        Self {
+            axes: vec![JogAxis {
+                name: "Motor".to_string(),
+                setpoint: MOTOR_VELOCITY.to_string(),
+                unit: JogUnit::Degrees,
+                speed: 30.0,
+                max_velocity: 180.0,
+            }],
+            increments: vec![0.1, 1.0, 10.0],
+        }
+    }
+}
+
+/// A move of a fixed distance at a limited speed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncrementalMove {
+    /// Distance left, in the unit of the setpoint.
+    remaining: f32,
+    speed: f32,
+}
+
+impl IncrementalMove {
+    /// Creates a move of `distance` (signed) at `speed`.
+    pub fn new(distance: f32, speed: f32) -> Self {
+        Self {
+            remaining: distance,
+            speed: speed.abs(),
+        }
+    }
+
+    pub fn remaining(&self) -> f32 {
+        self.remaining
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0.0 || self.speed == 0.0
+    }
+
+    /// Velocity to apply over the next `dt` seconds: the speed, slowed down to stop on the
+    /// distance left.
+    pub fn velocity(&self, dt: f32) -> f32 {
+        if self.is_done() || dt <= 0.0 {
+            return 0.0;
+        }
+        self.remaining.signum() * self.speed.min(self.remaining.abs() / dt)
+    }
+
+    /// Accounts for `velocity` applied for `elapsed` seconds.
+    pub fn advance(&mut self, velocity: f32, elapsed: f32) {
+        let remaining = self.remaining - velocity * elapsed;
+        // Done once the distance is covered, up to the rounding of the last step.
+        self.remaining = if remaining * self.remaining.signum() <= self.remaining.abs() * 1e-4 {
+            0.0
+        } else {
+            remaining
+        };
+    }
+}
+
+/// What an axis is doing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Jog {
+    #[default]
+    Idle,
+    /// Moving at the jog speed while a button is held, forward if `true`.
+    Continuous(bool),
+    Incremental(IncrementalMove),
+}
+
+/// The jog of each axis, and the velocity last written to its setpoint.
+#[derive(Default, Resource)]
+pub struct Jogs {
+    pub axes: Vec<(Jog, Option<f32>)>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<JogSettings>("jog", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the velocity setpoints of the jogged axes, after each step of the simulation.
+fn jog(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<JogSettings>>,
+    mut jogs: ResMut<Jogs>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    jogs.axes.resize(settings.axes.len(), default());
+    // The next step is assumed as long as the last one.
+    let dt = clock.delta_secs();
+    for (axis, (jog, written)) in settings.axes.iter().zip(&mut jogs.axes) {
+        let velocity = match jog {
+            Jog::Idle => 0.0,
+            Jog::Continuous(forward) => axis.velocity() * if *forward { 1.0 } else { -1.0 },
+            Jog::Incremental(incremental) => {
+                if dt == 0.0 {
+                    // Paused, or no step this frame: hold the setpoint until the next one.
+                    continue;
+                }
+                incremental.advance(written.unwrap_or_default(), dt);
+                let velocity = incremental.velocity(dt);
+                if incremental.is_done() {
+                    *jog = Jog::Idle;
+                }
+                velocity
+            }
+        };
+        if *jog == Jog::Idle && written.is_none_or(|written| written == 0.0) {
+            // Leave the setpoint to the keyboard and the other drivers.
+            *written = None;
+            continue;
+        }
+        if setpoints.get(&axis.setpoint) != Some(velocity) {
+            setpoints.set(&axis.setpoint, velocity);
+        }
+        *written = Some(velocity);
+    }
+}
+
+/// Panel to jog the axes.
+fn jog_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<JogSettings>>,
+    mut jogs: ResMut<Jogs>,
+    mut increment: Local<usize>,
+) {
+    let mut edited = settings.get().clone();
+    jogs.axes.resize(edited.axes.len(), default());
+
+    egui::Window::new("Jog")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if let Some(&distance) = edited.increments.get(*increment) {
+                egui::ComboBox::from_label("Increment")
+                    .selected_text(format!("{distance}"))
+                    .show_ui(ui, |ui| {
+                        for (index, distance) in edited.increments.iter().enumerate() {
+                            ui.selectable_value(&mut *increment, index, format!("{distance}"));
+                        }
+                    });
+            } else {
+                *increment = 0;
+            }
+            let distance = edited.increments.get(*increment).copied().unwrap_or(0.0);
+
+            for (axis, (jog, _)) in edited.axes.iter_mut().zip(&mut jogs.axes) {
+                ui.separator();
+                let symbol = axis.unit.symbol();
+                ui.label(&axis.name);
+                ui.add(
+                    egui::Slider::new(&mut axis.speed, 0.0..=axis.max_velocity.max(0.0))
+                        .text(format!("Speed ({symbol}/s)")),
+                );
+                ui.horizontal(|ui| {
+                    let backward = ui.button("◀ Jog");
+                    let forward = ui.button("Jog ▶");
+                    if backward.is_pointer_button_down_on() {
+                        *jog = Jog::Continuous(false);
+                    } else if forward.is_pointer_button_down_on() {
+                        *jog = Jog::Continuous(true);
+                    } else if matches!(jog, Jog::Continuous(_)) {
+                        *jog = Jog::Idle;
+                    }
+                    let scale = axis.unit.scale();
+                    if ui.button(format!("−{distance} {symbol}")).clicked() {
+                        *jog = Jog::Incremental(IncrementalMove::new(
+                            -distance * scale,
+                            axis.velocity(),
+                        ));
+                    }
+                    if ui.button(format!("+{distance} {symbol}")).clicked() {
+                        *jog = Jog::Incremental(IncrementalMove::new(
+                            distance * scale,
+                            axis.velocity(),
+                        ));
+                    }
+                    if ui.button("Stop").clicked() {
+                        *jog = Jog::Idle;
+                    }
+                });
+                if let Jog::Incremental(incremental) = jog {
+                    ui.label(format!(
+                        "{:.2} {symbol} to go",
+                        incremental.remaining() / axis.unit.scale()
+                    ));
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("jog", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("jog", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,512 @@
+//! This module lets the user assemble plants in the application: join two links with a joint
+//! placed in the scene, instead of writing its definition by hand.
+//!
+//! With picking enabled in the *Joint authoring* panel, a left click on a body picks its link
+//! as the parent, the next one on another link picks the child and places the anchor of the
+//! joint at the clicked point; further clicks move the anchor. The axis, the kind and the
+//! limits of the joint are set in the panel, the axis drawn through the anchor. Added joints are
+//! kept in `joints.json`, by the paths of their links, and built between the links as soon as
+//! both exist, at their relative pose when the joint was authored.
+//!
+//! A link can only have one joint to its parent: a child already joined by its plant isn't
+//! joined again, and the error is reported; pick it as the parent instead.
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    joint_builder::JointKind,
+    logging::subsystem,
+    plants::Link,
+    theme::Theme,
+};
+
+/// Farthest body that can be picked, in m.
+const MAX_REACH: f32 = 1000.0;
+/// Length of the drawn axis, in m.
+const AXIS_LENGTH: f32 = 0.5;
+
+pub struct JointAuthoringPlugin;
+
+impl Plugin for JointAuthoringPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<JointAuthoring>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                build_authored_joints.run_if(resource_exists::<Persistent<AuthoredJoints>>),
+            )
+            .add_systems(
+                Update,
+                (pick_links, draw_joints, joint_authoring_panel)
+                    .chain()
+                    .run_if(resource_exists::<Persistent<AuthoredJoints>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A joint between two links, authored in the application.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AuthoredJoint {
+    /// Paths of the links, e.g. `feeder/arm`.
+    pub parent: String,
+    pub child: String,
+    pub kind: JointKind,
+    /// Anchor of the joint, in the frame of the parent link.
+    pub anchor: Vec3,
+    /// Axis of rotation or of translation, in the frame of the parent link.
+    pub axis: Vec3,
+    /// Smallest and largest positions of the joint, in rad or m.
+    #[serde(default)]
+    pub limits: Option<[f32; 2]>,
+    /// Pose of the child link in the frame of the parent link, as authored.
+    pub child_pose: Transform,
+}
+
+impl AuthoredJoint {
+    /// Anchor of the joint, in the frame of the child link.
+    pub fn child_anchor(&self) -> Vec3 {
+        self.child_pose.rotation.inverse() * (self.anchor - self.child_pose.translation)
+    }
+
+    /// The joint, holding the links at their authored relative pose.
+    pub fn joint(&self) -> TypedJoint {
+        let axis = self.axis.normalize_or(Vec3::Y);
+        let child_axis = self.child_pose.rotation.inverse() * axis;
+        let mut joint: TypedJoint = match self.kind {
+            JointKind::Fixed => FixedJointBuilder::new()
+                .local_anchor1(self.anchor)
+                .local_basis1(self.child_pose.rotation)
+                .local_anchor2(self.child_anchor())
+                .into(),
+            JointKind::Revolute => {
+                let mut builder = RevoluteJointBuilder::new(axis)
+                    .local_anchor1(self.anchor)
+                    .local_anchor2(self.child_anchor());
+                if let Some(limits) = self.limits {
+                    builder = builder.limits(limits);
+                }
+                builder.into()
+            }
+            JointKind::Prismatic => {
+                let mut builder = PrismaticJointBuilder::new(axis)
+                    .local_anchor1(self.anchor)
+                    .local_anchor2(self.child_anchor());
+                if let Some(limits) = self.limits {
+                    builder = builder.limits(limits);
+                }
+                builder.into()
+            }
+            JointKind::Spherical => SphericalJointBuilder::new()
+                .local_anchor1(self.anchor)
+                .local_anchor2(self.child_anchor())
+                .into(),
+        };
+        if matches!(self.kind, JointKind::Revolute | JointKind::Prismatic) {
+            // The child sees the axis rotated by its pose.
+            joint.as_mut().set_local_axis2(child_axis);
+        }
+        joint
+    }
+}
+
+/// Represents the joints authored in the application.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct AuthoredJoints {
+    pub joints: Vec<AuthoredJoint>,
+}
+
+/// A link picked in the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickedLink {
+    pub entity: Entity,
+    pub path: String,
+}
+
+/// The joint being authored.
+#[derive(Debug, Resource)]
+pub struct JointAuthoring {
+    /// Whether the left button picks the links.
+    pub picking: bool,
+    pub parent: Option<PickedLink>,
+    pub child: Option<PickedLink>,
+    pub kind: JointKind,
+    /// Anchor and axis, in the frame of the parent link.
+    pub anchor: Vec3,
+    pub axis: Vec3,
+    pub limits: Option<[f32; 2]>,
+}
+
+impl Default for JointAuthoring {
+    fn default() -> Self {
+        Self {
+            picking: false,
+            parent: None,
+            child: None,
+            kind: JointKind::Revolute,
+            anchor: Vec3::ZERO,
+            axis: Vec3::Y,
+            limits: None,
+        }
+    }
+}
+
+/// The joint built from an authored one, on its child link.
+#[derive(Component)]
+struct Authored(AuthoredJoint);
+
+fn setup(mut commands: Commands) {
+    let (joints, error) = config_plugin::load_config::<AuthoredJoints>("joints", true);
+    commands.insert_resource(joints);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Builds the authored joints between their links, and removes those no longer authored.
+fn build_authored_joints(
+    mut commands: Commands,
+    joints: Res<Persistent<AuthoredJoints>>,
+    links: Query<(Entity, &Link, Option<&ImpulseJoint>, Option<&Authored>)>,
+    mut reported: Local<Vec<AuthoredJoint>>,
+) {
+    for (entity, _, _, authored) in &links {
+        if authored.is_some_and(|authored| !joints.joints.contains(&authored.0)) {
+            commands.entity(entity).remove::<(ImpulseJoint, Authored)>();
+        }
+    }
+    for joint in &joints.joints {
+        let find = |path: &str| links.iter().find(|(_, link, _, _)| link.path() == path);
+        let (Some((parent, ..)), Some((child, _, impulse_joint, authored))) =
+            (find(&joint.parent), find(&joint.child))
+        else {
+            continue;
+        };
+        if authored.is_some_and(|authored| authored.0 == *joint) {
+            continue;
+        }
+        if impulse_joint.is_some() && authored.is_none() {
+            if !reported.contains(joint) {
+                reported.push(joint.clone());
+                commands.send_event(ErrorEvent::from(Error::Config {
+                    name: "joints".to_string(),
+                    message: format!(
+                        "{} is already joined to its parent, it can't be joined to {}",
+                        joint.child, joint.parent
+                    ),
+                }));
+            }
+            continue;
+        }
+        info!(
+            target: subsystem::PHYSICS,
+            "Joining {} to {}", joint.child, joint.parent
+        );
+        commands.entity(child).insert((
+            ImpulseJoint::new(parent, joint.joint()),
+            Authored(joint.clone()),
+        ));
+    }
+}
+
+/// The link of a body, or of its closest ancestor with one.
+fn link_of(
+    entity: Entity,
+    links: &Query<&Link>,
+    parents: &Query<&Parent>,
+) -> Option<(Entity, String)> {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find_map(|entity| Some((entity, links.get(entity).ok()?.path())))
+}
+
+/// Picks the links and places the anchor where the left button is pressed.
+#[allow(clippy::too_many_arguments)]
+fn pick_links(
+    mut egui: EguiContexts,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    contexts: Query<&RapierContext>,
+    links: Query<&Link>,
+    parents: Query<&Parent>,
+    transforms: Query<&Transform>,
+    mut authoring: ResMut<JointAuthoring>,
+) {
+    if !authoring.picking || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if egui.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    let ray = windows
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .and_then(|cursor| {
+            let (camera, transform) = cameras.iter().find(|(camera, _)| camera.is_active)?;
+            camera.viewport_to_world(transform, cursor).ok()
+        });
+    let (Some(ray), Ok(context)) = (ray, contexts.get_single()) else {
+        return;
+    };
+    let Some((hit, depth)) = context.cast_ray(
+        ray.origin,
+        *ray.direction,
+        MAX_REACH,
+        true,
+        QueryFilter::default(),
+    ) else {
+        return;
+    };
+    let point = ray.get_point(depth);
+    let picked = link_of(hit, &links, &parents);
+    let authoring = &mut *authoring;
+    match (&authoring.parent, &authoring.child, picked) {
+        (None, _, Some((entity, path))) => {
+            debug!(target: subsystem::PHYSICS, "Picked {path} as the parent");
+            authoring.parent = Some(PickedLink { entity, path });
+        }
+        (Some(parent), None, Some((entity, path))) if parent.entity != entity => {
+            debug!(target: subsystem::PHYSICS, "Picked {path} as the child");
+            authoring.child = Some(PickedLink { entity, path });
+            if let Ok(transform) = transforms.get(parent.entity) {
+                authoring.anchor = transform.compute_affine().inverse().transform_point3(point);
+            }
+        }
+        (Some(parent), Some(_), _) => {
+            if let Ok(transform) = transforms.get(parent.entity) {
+                authoring.anchor = transform.compute_affine().inverse().transform_point3(point);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Draws the joint being authored and the authored ones.
+fn draw_joints(
+    mut gizmos: Gizmos,
+    authoring: Res<JointAuthoring>,
+    joints: Res<Persistent<AuthoredJoints>>,
+    links: Query<(&Link, &Transform)>,
+    transforms: Query<&Transform>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+    let mut draw = |parent: &Transform, anchor: Vec3, axis: Vec3, kind: JointKind, color: Srgba| {
+        let anchor = parent.transform_point(anchor);
+        let axis = parent.rotation * axis.normalize_or(Vec3::Y);
+        match kind {
+            JointKind::Fixed | JointKind::Spherical => {
+                gizmos.sphere(Isometry3d::from_translation(anchor), 0.05, color);
+            }
+            JointKind::Revolute => {
+                gizmos.arrow(
+                    anchor - axis * AXIS_LENGTH,
+                    anchor + axis * AXIS_LENGTH,
+                    color,
+                );
+                let rotation = Quat::from_rotation_arc(Vec3::Z, axis);
+                gizmos.circle(Isometry3d::new(anchor, rotation), 0.15, color);
+            }
+            JointKind::Prismatic => {
+                gizmos.arrow(
+                    anchor - axis * AXIS_LENGTH,
+                    anchor + axis * AXIS_LENGTH,
+                    color,
+                );
+            }
+        }
+    };
+    for joint in &joints.joints {
+        if let Some((_, parent)) = links.iter().find(|(link, _)| link.path() == joint.parent) {
+            draw(
+                parent,
+                joint.anchor,
+                joint.axis,
+                joint.kind,
+                theme.series(2),
+            );
+        }
+    }
+    if !authoring.picking {
+        return;
+    }
+    let parent = authoring
+        .parent
+        .as_ref()
+        .and_then(|parent| transforms.get(parent.entity).ok());
+    if let (Some(parent), Some(_)) = (parent, &authoring.child) {
+        draw(
+            parent,
+            authoring.anchor,
+            authoring.axis,
+            authoring.kind,
+            theme.series(1),
+        );
+    }
+}
+
+/// Panel to pick the links, set the joint and keep the authored joints.
+fn joint_authoring_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut joints: ResMut<Persistent<AuthoredJoints>>,
+    mut authoring: ResMut<JointAuthoring>,
+    transforms: Query<&Transform>,
+) {
+    let mut edited = joints.get().clone();
+    let authoring = &mut *authoring;
+
+    egui::Window::new("Joint authoring")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut authoring.picking, "Pick the links with the mouse")
+                .on_hover_text(
+                    "Click the parent link, then the child link at the anchor of the joint",
+                );
+            let name = |picked: &Option<PickedLink>| {
+                picked
+                    .as_ref()
+                    .map_or("not picked".to_string(), |picked| picked.path.clone())
+            };
+            ui.label(format!("Parent: {}", name(&authoring.parent)));
+            ui.label(format!("Child: {}", name(&authoring.child)));
+
+            egui::ComboBox::from_label("Kind")
+                .selected_text(format!("{:?}", authoring.kind))
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        JointKind::Fixed,
+                        JointKind::Revolute,
+                        JointKind::Prismatic,
+                        JointKind::Spherical,
+                    ] {
+                        ui.selectable_value(&mut authoring.kind, kind, format!("{kind:?}"));
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.label("Anchor (m)");
+                for value in [
+                    &mut authoring.anchor.x,
+                    &mut authoring.anchor.y,
+                    &mut authoring.anchor.z,
+                ] {
+                    ui.add(egui::DragValue::new(value).speed(0.01));
+                }
+            })
+            .response
+            .on_hover_text("In the frame of the parent link; a click on a body moves it there");
+            ui.horizontal(|ui| {
+                ui.label("Axis");
+                for (label, axis) in [("X", Vec3::X), ("Y", Vec3::Y), ("Z", Vec3::Z)] {
+                    if ui.button(label).clicked() {
+                        authoring.axis = axis;
+                    }
+                }
+                for value in [
+                    &mut authoring.axis.x,
+                    &mut authoring.axis.y,
+                    &mut authoring.axis.z,
+                ] {
+                    ui.add(egui::DragValue::new(value).speed(0.01).range(-1.0..=1.0));
+                }
+            });
+            if matches!(authoring.kind, JointKind::Revolute | JointKind::Prismatic) {
+                let mut limited = authoring.limits.is_some();
+                ui.checkbox(&mut limited, "Limits (rad or m)");
+                match (limited, &mut authoring.limits) {
+                    (true, Some([low, high])) => {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(low).speed(0.01));
+                            ui.add(egui::DragValue::new(high).speed(0.01));
+                        });
+                    }
+                    (true, limits @ None) => *limits = Some([-1.0, 1.0]),
+                    (false, limits) => *limits = None,
+                }
+            }
+
+            let poses = authoring
+                .parent
+                .as_ref()
+                .zip(authoring.child.as_ref())
+                .and_then(|(parent, child)| {
+                    Some((
+                        transforms.get(parent.entity).ok()?,
+                        transforms.get(child.entity).ok()?,
+                    ))
+                });
+            ui.horizontal(|ui| {
+                let add = ui.add_enabled(poses.is_some(), egui::Button::new("Add joint"));
+                if let (true, Some((parent_pose, child_pose))) = (add.clicked(), poses) {
+                    let (Some(parent), Some(child)) = (&authoring.parent, &authoring.child) else {
+                        return;
+                    };
+                    edited.joints.retain(|joint| joint.child != child.path);
+                    edited.joints.push(AuthoredJoint {
+                        parent: parent.path.clone(),
+                        child: child.path.clone(),
+                        kind: authoring.kind,
+                        anchor: authoring.anchor,
+                        axis: authoring.axis.normalize_or(Vec3::Y),
+                        limits: authoring.limits.filter(|_| {
+                            matches!(authoring.kind, JointKind::Revolute | JointKind::Prismatic)
+                        }),
+                        child_pose: Transform::from_matrix(
+                            parent_pose.compute_matrix().inverse() * child_pose.compute_matrix(),
+                        ),
+                    });
+                    authoring.parent = None;
+                    authoring.child = None;
+                }
+                if ui.button("Clear").clicked() {
+                    authoring.parent = None;
+                    authoring.child = None;
+                }
+            });
+
+            ui.separator();
+            let mut removed = None;
+            for (index, joint) in edited.joints.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{:?}: {} to {}",
+                        joint.kind, joint.child, joint.parent
+                    ));
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.joints.remove(index);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = joints.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("joints", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = joints.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("joints", error)));
+                    }
+                    edited = joints.get().clone();
+                }
+            });
+        });
+
+    if edited != *joints.get() {
+        *joints.get_mut() = edited;
+    }
+}
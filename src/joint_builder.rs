@@ -0,0 +1,278 @@
+//! This module articulates the models loaded from glTF files: the nodes describing links become
+//! rigid bodies, joined to their parent link, from the custom properties Blender exports as
+//! the extras of the nodes.
+//!
+//! A node is a link when its extras name it (`"link": "arm"`) or its name starts with `link_`
+//! (`link_arm`). Its extras also give its body (`dynamic` or `fixed`), its inertial properties
+//! (`mass`, `center_of_mass` and the principal `inertia`), and its joint to its parent link:
+//! `fixed`, `revolute`, `prismatic` or `spherical`, about or along its `axis` in the frame of
+//! the link, within its `limits`, as an impulse joint or a multibody one. The parent is the
+//! `parent` link, or else the closest ancestor link of the node, to which a link is fixed
+//! unless its joint says otherwise; a link without a parent moves freely. The joints are
+//! anchored at the origin of the link, as the scene places it. The meshes of the link get
+//! [colliders](crate::colliders) of the shape its `collider` chooses.
+//!
+//! The links are built once the scene is spawned, and get a [`Link`], so the tooling working
+//! with the links of the built-in plants works with the links of the model too.
+use std::collections::BTreeMap;
+
+use bevy::{gltf::GltfExtras, prelude::*, transform::TransformSystem};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colliders::{ColliderShape, GenerateColliders},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+};
+
+/// Prefix of the names of the nodes describing links.
+pub const LINK_PREFIX: &str = "link_";
+
+pub struct JointBuilderPlugin;
+
+impl Plugin for JointBuilderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            build_links
+                .in_set(JointBuilderSet)
+                .after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// The system building the links of the spawned scenes.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+pub struct JointBuilderSet;
+
+/// Kind of the body of a link.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyKind {
+    #[default]
+    Dynamic,
+    Fixed,
+}
+
+/// Kind of the joint of a link to its parent.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JointKind {
+    #[default]
+    Fixed,
+    Revolute,
+    Prismatic,
+    Spherical,
+}
+
+/// Description of a link, from the extras of its node.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LinkMetadata {
+    /// Name of the link.
+    pub link: Option<String>,
+    pub body: BodyKind,
+    /// Mass, in kg, added to the mass of the colliders.
+    pub mass: Option<f32>,
+    /// Center of mass, in the frame of the link.
+    pub center_of_mass: Vec3,
+    /// Principal moments of inertia, in kg·m², computed from the mass otherwise.
+    pub inertia: Option<Vec3>,
+    /// Joint to the parent link.
+    pub joint: Option<JointKind>,
+    /// Name of the parent link, the closest ancestor link of the node otherwise.
+    pub parent: Option<String>,
+    /// Axis of the joint, in the frame of the link.
+    pub axis: Option<Vec3>,
+    /// Smallest and largest positions of the joint, in rad or m.
+    pub limits: Option<[f32; 2]>,
+    /// Whether the joint is a multibody joint rather than an impulse joint.
+    pub multibody: bool,
+    /// Shape of the colliders generated from the meshes of the link.
+    pub collider: Option<ColliderShape>,
+}
+
+impl LinkMetadata {
+    /// Description of the link of a node, or `None` when the node isn't a link.
+    pub fn parse(name: &str, extras: Option<&str>) -> serde_json::Result<Option<Self>> {
+        let extras = match extras {
+            Some(extras) => serde_json::from_str(extras)?,
+            None => serde_json::Value::Null,
+        };
+        // The other nodes may have extras of their own.
+        let link = name.strip_prefix(LINK_PREFIX);
+        if link.is_none() && extras.get("link").is_none() {
+            return Ok(None);
+        }
+        let mut metadata: Self = match extras {
+            serde_json::Value::Null => Self::default(),
+            extras => serde_json::from_value(extras)?,
+        };
+        if metadata.link.is_none() {
+            metadata.link = link.map(str::to_string);
+        }
+        Ok(metadata.link.is_some().then_some(metadata))
+    }
+
+    /// Name of the link.
+    pub fn name(&self) -> &str {
+        self.link.as_deref().unwrap_or_default()
+    }
+
+    /// Mass properties of the link, if it declares a mass.
+    pub fn mass_properties(&self) -> Option<AdditionalMassProperties> {
+        let mass = self.mass?;
+        Some(match self.inertia {
+            Some(inertia) => AdditionalMassProperties::MassProperties(MassProperties {
+                local_center_of_mass: self.center_of_mass,
+                mass,
+                principal_inertia_local_frame: Quat::IDENTITY,
+                principal_inertia: inertia,
+            }),
+            None => AdditionalMassProperties::Mass(mass),
+        })
+    }
+
+    /// Joint to the parent link, from the pose of the link in the frame of its parent.
+    pub fn joint(&self, relative: Transform) -> TypedJoint {
+        let axis = self.axis.unwrap_or(Vec3::X).normalize_or(Vec3::X);
+        let anchor = relative.translation;
+        let mut joint: TypedJoint = match self.joint.unwrap_or_default() {
+            JointKind::Fixed => FixedJointBuilder::new()
+                .local_anchor1(anchor)
+                .local_basis1(relative.rotation)
+                .into(),
+            JointKind::Revolute => {
+                let mut builder = RevoluteJointBuilder::new(axis).local_anchor1(anchor);
+                if let Some(limits) = self.limits {
+                    builder = builder.limits(limits);
+                }
+                builder.into()
+            }
+            JointKind::Prismatic => {
+                let mut builder = PrismaticJointBuilder::new(axis).local_anchor1(anchor);
+                if let Some(limits) = self.limits {
+                    builder = builder.limits(limits);
+                }
+                builder.into()
+            }
+            JointKind::Spherical => SphericalJointBuilder::new().local_anchor1(anchor).into(),
+        };
+        if matches!(
+            self.joint,
+            Some(JointKind::Revolute) | Some(JointKind::Prismatic)
+        ) {
+            // The axis is given in the frame of the link, which the parent sees rotated.
+            joint.as_mut().set_local_axis1(relative.rotation * axis);
+        }
+        joint
+    }
+}
+
+/// A node describing a link, with its description.
+struct LinkNode {
+    entity: Entity,
+    metadata: LinkMetadata,
+    transform: GlobalTransform,
+}
+
+/// Turns the nodes of the scenes spawned during this frame into links.
+fn build_links(
+    mut commands: Commands,
+    nodes: Query<(Entity, &Name, Option<&GltfExtras>, &GlobalTransform), Added<Name>>,
+    links: Query<(Entity, &Link, &GlobalTransform)>,
+    parents: Query<&Parent>,
+) {
+    let mut built = Vec::new();
+    for (entity, name, extras, transform) in &nodes {
+        match LinkMetadata::parse(name, extras.map(|extras| extras.value.as_str())) {
+            Ok(Some(metadata)) => built.push(LinkNode {
+                entity,
+                metadata,
+                transform: *transform,
+            }),
+            Ok(None) => {}
+            Err(error) => {
+                commands.send_event(ErrorEvent::from(Error::Model(format!(
+                    "the extras of node `{name}` don't describe a link: {error}"
+                ))));
+            }
+        }
+    }
+    if built.is_empty() {
+        return;
+    }
+
+    let mut by_name: BTreeMap<&str, Entity> = links
+        .iter()
+        .filter(|(_, link, _)| link.plant.is_empty())
+        .map(|(entity, link, _)| (link.name.as_str(), entity))
+        .collect();
+    let mut transforms: BTreeMap<Entity, GlobalTransform> = links
+        .iter()
+        .map(|(entity, _, transform)| (entity, *transform))
+        .collect();
+    for node in &built {
+        by_name.insert(node.metadata.name(), node.entity);
+        transforms.insert(node.entity, node.transform);
+    }
+
+    for node in &built {
+        let name = node.metadata.name();
+        let parent = match &node.metadata.parent {
+            Some(parent) => match by_name.get(parent.as_str()) {
+                Some(&entity) => Some(entity),
+                None => {
+                    commands.send_event(ErrorEvent::from(Error::Model(format!(
+                        "link `{name}` is joined to the unknown link `{parent}`"
+                    ))));
+                    continue;
+                }
+            },
+            None => parents
+                .iter_ancestors(node.entity)
+                .find(|ancestor| transforms.contains_key(ancestor)),
+        };
+
+        let mut entity = commands.entity(node.entity);
+        entity.insert((
+            Link {
+                plant: String::new(),
+                name: name.to_string(),
+            },
+            match node.metadata.body {
+                BodyKind::Dynamic => RigidBody::Dynamic,
+                BodyKind::Fixed => RigidBody::Fixed,
+            },
+            GenerateColliders {
+                shape: node.metadata.collider,
+            },
+        ));
+        if let Some(mass) = node.metadata.mass_properties() {
+            entity.insert(mass);
+        }
+        // A body follows the physics in the world frame, not its ancestors.
+        if parents.contains(node.entity) {
+            entity.remove_parent_in_place();
+        }
+        match parent.and_then(|parent| Some((parent, transforms.get(&parent)?))) {
+            Some((parent, parent_transform)) => {
+                let joint = node
+                    .metadata
+                    .joint(node.transform.reparented_to(parent_transform));
+                if node.metadata.multibody {
+                    entity.insert(MultibodyJoint::new(parent, joint));
+                } else {
+                    entity.insert(ImpulseJoint::new(parent, joint));
+                }
+            }
+            None if node.metadata.joint.is_some() => {
+                warn!(target: subsystem::PHYSICS, "Link {name} has a joint but no parent link");
+            }
+            None => {}
+        }
+        info!(target: subsystem::PHYSICS, "Built link {name}");
+    }
+}
@@ -0,0 +1,471 @@
+//! This module enforces the limits of the joints, and tells when a controller drives a joint
+//! into one.
+//!
+//! The limits come from the model: the `limits` of a link in the extras of a glTF node, the
+//! `<limit>` of a URDF joint, or those of a joint authored in the application, which the
+//! joints are built with. They are enforced in one of two ways, as configured in
+//! `joint_limits.json`:
+//!
+//! - hard, by Rapier: the joint stops dead at the limit,
+//! - soft, by a virtual spring and damper: within `margin` of a limit, the joint is pushed back
+//!   by `stiffness` times its depth into the margin, and braked by `damping` times its speed
+//!   towards the limit, as a rubber end stop would. Rapier doesn't limit the joint anymore, so a
+//!   motor strong enough still pushes it beyond.
+//!
+//! A joint within `margin` of a limit is at that limit. When its motor drives it further, the
+//! [`LimitReached`] event is sent, a warning logged and shown in the *Joint limits* panel, and
+//! the `limit/<link>` telemetry channel steps to 1 at the upper limit or -1 at the lower one,
+//! back to 0 when it leaves.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    kinematics::JointKind,
+    logging::subsystem,
+    plants::{self, Link},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the limits reached.
+pub const LIMIT_PREFIX: &str = "limit/";
+
+pub struct JointLimitsPlugin;
+
+impl Plugin for JointLimitsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LimitReached>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    read_limits,
+                    apply_behaviors.run_if(resource_changed::<Persistent<JointLimitSettings>>),
+                )
+                    .chain()
+                    .run_if(resource_exists::<Persistent<JointLimitSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                enforce_limits
+                    .run_if(resource_exists::<Persistent<JointLimitSettings>>)
+                    .before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(
+                Update,
+                joint_limits_panel
+                    .run_if(resource_exists::<Persistent<JointLimitSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// How the limits of the joints are enforced.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitBehavior {
+    /// By Rapier, stopping the joint at the limit.
+    #[default]
+    Hard,
+    /// By a spring and a damper within the margin of the limit.
+    Soft,
+}
+
+impl LimitBehavior {
+    pub const ALL: [Self; 2] = [Self::Hard, Self::Soft];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hard => "Hard",
+            Self::Soft => "Soft",
+        }
+    }
+}
+
+/// Represents how the limits are enforced, from the `joint_limits.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct JointLimitSettings {
+    pub behavior: LimitBehavior,
+    /// Width of the zone within each limit where the joint is at the limit, and the spring
+    /// acts, in rad or m.
+    pub margin: f32,
+    /// Stiffness of the spring, in N·m/rad or N/m.
+    pub stiffness: f32,
+    /// Damping of the motion towards the limit, in N·m·s/rad or N·s/m.
+    pub damping: f32,
+    /// Whether a warning is logged when a controller drives a joint into its limit.
+    pub warn: bool,
+}
+
+impl Default for JointLimitSettings {
+    fn default() -> Self {
+        Self {
+            behavior: LimitBehavior::Hard,
+            margin: 0.05,
+            stiffness: 200.0,
+            damping: 5.0,
+            warn: true,
+        }
+    }
+}
+
+/// The limit of a joint a position is at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LimitSide {
+    Lower,
+    Upper,
+}
+
+impl LimitSide {
+    /// Value of the telemetry channel of the limits.
+    pub fn sign(self) -> f32 {
+        match self {
+            Self::Lower => -1.0,
+            Self::Upper => 1.0,
+        }
+    }
+}
+
+/// The limit `position` is at, within `margin` of it or beyond.
+pub fn limit_side(position: f32, [lower, upper]: [f32; 2], margin: f32) -> Option<LimitSide> {
+    if position >= upper - margin {
+        Some(LimitSide::Upper)
+    } else if position <= lower + margin {
+        Some(LimitSide::Lower)
+    } else {
+        None
+    }
+}
+
+/// Torque or force of the soft limits on a joint at `position` moving at `speed`: the spring
+/// pushing it back out of the margin, and the damper braking it towards the limit.
+pub fn soft_limit_force(
+    position: f32,
+    speed: f32,
+    [lower, upper]: [f32; 2],
+    settings: &JointLimitSettings,
+) -> f32 {
+    let depth_upper = position - (upper - settings.margin);
+    let depth_lower = lower + settings.margin - position;
+    if depth_upper > 0.0 {
+        -settings.stiffness * depth_upper - settings.damping * speed.max(0.0)
+    } else if depth_lower > 0.0 {
+        settings.stiffness * depth_lower - settings.damping * speed.min(0.0)
+    } else {
+        0.0
+    }
+}
+
+/// Whether `motor` drives a joint at `position` further into the limit on `side`: towards it
+/// in velocity, or to a target position past the joint in position.
+pub fn driven_into(side: LimitSide, position: f32, motor: &JointMotor) -> bool {
+    let towards = |value: f32| match side {
+        LimitSide::Lower => value < 0.0,
+        LimitSide::Upper => value > 0.0,
+    };
+    (motor.damping > 0.0 && towards(motor.target_vel))
+        || (motor.stiffness > 0.0 && towards(motor.target_pos - position))
+}
+
+/// A controller drove a joint into its limit.
+#[derive(Clone, Debug, Event)]
+pub struct LimitReached {
+    pub link: Entity,
+    /// Path of the link, e.g. `arm` or `feeder/arm`.
+    pub path: String,
+    pub side: LimitSide,
+    pub position: f32,
+}
+
+/// The limits of the joint of a link, as the model declares them.
+#[derive(Clone, Component, Debug)]
+pub struct JointLimit {
+    pub kind: JointKind,
+    /// Lower and upper limits, in rad or m.
+    pub range: [f32; 2],
+    /// Telemetry channel of the limits reached.
+    pub channel: String,
+    /// Position of the joint at the last step, and the limit it was at.
+    pub position: Option<f32>,
+    pub side: Option<LimitSide>,
+    /// Whether its motor drives it into the limit it is at.
+    pub driven: bool,
+}
+
+impl JointLimit {
+    fn axis(&self) -> JointAxis {
+        match self.kind {
+            JointKind::Revolute => JointAxis::AngX,
+            JointKind::Prismatic => JointAxis::LinX,
+        }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<JointLimitSettings>("joint_limits", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Reads the limits of the joints as they spawn, and lets Rapier enforce them or not as
+/// configured.
+fn read_limits(
+    mut commands: Commands,
+    settings: Res<Persistent<JointLimitSettings>>,
+    mut added: Query<(Entity, &Link, &mut ImpulseJoint), Added<ImpulseJoint>>,
+) {
+    for (entity, link, mut joint) in &mut added {
+        let data = joint.data.as_ref();
+        let locked = data.locked_axes();
+        let (kind, axis) = if !locked.contains(JointAxesMask::ANG_X) {
+            (JointKind::Revolute, JointAxis::AngX)
+        } else if !locked.contains(JointAxesMask::LIN_X) {
+            (JointKind::Prismatic, JointAxis::LinX)
+        } else {
+            continue;
+        };
+        let Some(limits) = data.limits(axis) else {
+            continue;
+        };
+        let limit = JointLimit {
+            kind,
+            range: [limits.min, limits.max],
+            channel: plants::namespaced(&link.plant, &format!("{LIMIT_PREFIX}{}", link.name)),
+            position: None,
+            side: None,
+            driven: false,
+        };
+        apply_behavior(&limit, &mut joint, settings.behavior);
+        let parent = joint.parent;
+        commands
+            .entity(entity)
+            .insert(limit)
+            .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+        // The parent takes the reaction of the soft limits.
+        commands
+            .entity(parent)
+            .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+    }
+}
+
+/// Lets Rapier enforce the limits or not when the configuration changes.
+fn apply_behaviors(
+    settings: Res<Persistent<JointLimitSettings>>,
+    mut joints: Query<(&JointLimit, &mut ImpulseJoint)>,
+) {
+    for (limit, mut joint) in &mut joints {
+        apply_behavior(limit, &mut joint, settings.behavior);
+    }
+}
+
+/// Sets or removes the Rapier limit of `joint`, touching it only when it changes.
+fn apply_behavior(limit: &JointLimit, joint: &mut Mut<ImpulseJoint>, behavior: LimitBehavior) {
+    let axis = limit.axis();
+    let hard = joint.data.as_ref().limits(axis).is_some();
+    match behavior {
+        LimitBehavior::Hard if !hard => {
+            joint.data.as_mut().set_limits(axis, limit.range);
+        }
+        LimitBehavior::Soft if hard => {
+            joint
+                .data
+                .as_mut()
+                .raw
+                .limit_axes
+                .remove(JointAxesMask::from(axis));
+        }
+        _ => {}
+    }
+}
+
+/// Measures the joints against their limits, pushes them back out of the soft limits for the
+/// next step, and reports those driven into a limit.
+fn enforce_limits(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<JointLimitSettings>>,
+    mut joints: Query<(Entity, &Link, &mut JointLimit, &ImpulseJoint, &Transform)>,
+    transforms: Query<&Transform>,
+    velocities: Query<&Velocity>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    contexts: Query<&RapierContext>,
+    mut reached: EventWriter<LimitReached>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let dt = clock.delta_secs();
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    if dt == 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (entity, link, mut limit, joint, transform) in &mut joints {
+        let data = joint.data.as_ref();
+        let axis = (transform.rotation * data.local_axis2()).normalize_or_zero();
+        let velocity = |entity: Entity| velocities.get(entity).copied().unwrap_or_default();
+        let (child, parent) = (velocity(entity), velocity(joint.parent));
+        let (position, speed) = match limit.kind {
+            JointKind::Revolute => (
+                context.impulse_revolute_joint_angle(entity),
+                (child.angvel - parent.angvel).dot(axis),
+            ),
+            JointKind::Prismatic => (
+                transforms.get(joint.parent).ok().map(|parent| {
+                    let offset = transform.transform_point(data.local_anchor2())
+                        - parent.transform_point(data.local_anchor1());
+                    offset.dot(parent.rotation * data.local_axis1())
+                }),
+                (child.linvel - parent.linvel).dot(axis),
+            ),
+        };
+        let Some(position) = position else {
+            continue;
+        };
+        limit.position = Some(position);
+
+        if settings.behavior == LimitBehavior::Soft {
+            let force = soft_limit_force(position, speed, limit.range, &settings) * dt;
+            let apply = |impulse: &mut ExternalImpulse, sign: f32| match limit.kind {
+                JointKind::Revolute => impulse.torque_impulse += sign * force * axis,
+                JointKind::Prismatic => impulse.impulse += sign * force * axis,
+            };
+            if let Ok(mut impulse) = impulses.get_mut(entity) {
+                apply(&mut impulse, 1.0);
+            }
+            if let Ok(mut impulse) = impulses.get_mut(joint.parent) {
+                apply(&mut impulse, -1.0);
+            }
+        }
+
+        let side = limit_side(position, limit.range, settings.margin);
+        let motor = data.motor(limit.axis());
+        let driven = side
+            .zip(motor)
+            .is_some_and(|(side, motor)| driven_into(side, position, motor));
+        if let (Some(side), true) = (side, driven && !limit.driven) {
+            if settings.warn {
+                warn!(
+                    target: subsystem::CONTROL,
+                    "{} driven into its {} limit at {position:.3} ({:.2} s)",
+                    link.path(),
+                    if side == LimitSide::Upper { "upper" } else { "lower" },
+                    time
+                );
+            }
+            reached.send(LimitReached {
+                link: entity,
+                path: link.path(),
+                side,
+                position,
+            });
+        }
+        limit.driven = driven;
+        if side != limit.side {
+            limit.side = side;
+            if let Some(telemetry) = telemetry.as_mut() {
+                telemetry.record(&limit.channel, time, side.map_or(0.0, LimitSide::sign));
+            }
+        }
+    }
+}
+
+/// Panel to choose how the limits are enforced, and show the joints at their limits.
+fn joint_limits_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<JointLimitSettings>>,
+    joints: Query<(&Link, &JointLimit)>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Joint limits")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Enforcement")
+                .selected_text(edited.behavior.name())
+                .show_ui(ui, |ui| {
+                    for behavior in LimitBehavior::ALL {
+                        ui.selectable_value(&mut edited.behavior, behavior, behavior.name());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut edited.margin)
+                    .range(0.0..=1.0)
+                    .speed(0.001)
+                    .prefix("Margin: ")
+                    .suffix(" rad or m"),
+            );
+            ui.add_enabled_ui(edited.behavior == LimitBehavior::Soft, |ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.stiffness)
+                        .range(0.0..=100_000.0)
+                        .speed(1.0)
+                        .prefix("Stiffness: "),
+                )
+                .on_hover_text("In N·m/rad, or N/m for sliding joints");
+                ui.add(
+                    egui::DragValue::new(&mut edited.damping)
+                        .range(0.0..=10_000.0)
+                        .speed(0.1)
+                        .prefix("Damping: "),
+                )
+                .on_hover_text("In N·m·s/rad, or N·s/m for sliding joints");
+            });
+            ui.checkbox(
+                &mut edited.warn,
+                "Warn when a controller drives a joint into a limit",
+            );
+
+            ui.separator();
+            egui::Grid::new("joint_limits").show(ui, |ui| {
+                for (link, limit) in &joints {
+                    ui.label(link.path());
+                    ui.label(format!("{:.3} to {:.3}", limit.range[0], limit.range[1]));
+                    ui.label(
+                        limit
+                            .position
+                            .map_or("-".to_string(), |position| format!("{position:.3}")),
+                    );
+                    match (limit.side, limit.driven) {
+                        (Some(_), true) => {
+                            ui.colored_label(egui::Color32::RED, "Driven into the limit")
+                        }
+                        (Some(LimitSide::Lower), false) => ui.label("At the lower limit"),
+                        (Some(LimitSide::Upper), false) => ui.label("At the upper limit"),
+                        (None, _) => ui.label(""),
+                    };
+                    ui.end_row();
+                }
+            });
+            if joints.is_empty() {
+                ui.label("No joint of the model has limits");
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("joint_limits", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("joint_limits", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,583 @@
+//! This module provides a kinematic-only playback mode, posing the links from the positions of
+//! their joints with the dynamics off, to check the assembly of a model, its reach and its
+//! collisions before running the physics.
+//!
+//! While the mode is on, the dynamic links are switched to kinematic bodies: gravity, the motors
+//! and the contacts don't move them anymore. Each link is posed from the body its joint hangs
+//! from, through the frames of the joint on both bodies and the motion of its free axis, down
+//! from the bodies without a joint, which stay where they were. The positions of the joints come
+//! from the sliders of the *Kinematic playback* panel, from zero, the configuration the joints
+//! assemble the model in, or from a trajectory file played at a chosen speed: a CSV file with a
+//! time column and a column of positions per joint, named by the path of its link, interpolated
+//! between the rows. The links of a plant intersecting, but for those joined together, are
+//! listed and logged. Switching the mode off puts the links back where they were, at rest, and
+//! back under the dynamics.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    kinematics::JointKind,
+    logging::subsystem,
+    plants::Link,
+    proximity,
+};
+
+pub struct KinematicPlaybackPlugin;
+
+impl Plugin for KinematicPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KinematicPlayback>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    (switch_mode, play_file, pose_links).chain(),
+                    kinematic_playback_panel.run_if(has_ui),
+                )
+                    .run_if(resource_exists::<Persistent<KinematicPlaybackSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                check_intersections.after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Represents the configuration of the kinematic playback.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct KinematicPlaybackSettings {
+    /// Trajectory file, in CSV.
+    pub path: PathBuf,
+    pub time_column: String,
+    pub delimiter: char,
+    /// Playback speed, as a multiple of real time.
+    pub speed: f32,
+    pub looped: bool,
+}
+
+impl Default for KinematicPlaybackSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("joint_trajectory.csv"),
+            time_column: "time".to_string(),
+            delimiter: ',',
+            speed: 1.0,
+            looped: false,
+        }
+    }
+}
+
+/// Positions of joints over time, read from a trajectory file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JointTrajectory {
+    /// Paths of the links of the joints, in the order of the columns.
+    pub joints: Vec<String>,
+    /// Time since the first row, in s, and the positions of the joints then.
+    pub rows: Vec<(f32, Vec<f32>)>,
+}
+
+fn invalid(path: &Path, message: String) -> Error {
+    Error::io(path, io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+impl JointTrajectory {
+    /// Parses the CSV `text`: the `time_column`, in s, and every other column as the positions
+    /// of a joint, in rad or m, on the time since the first row.
+    pub fn parse(text: &str, time_column: &str, delimiter: char, path: &Path) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid(path, "the file is empty".to_string()))?;
+        let header: Vec<&str> = header
+            .split(delimiter)
+            .map(|name| name.trim().trim_matches('"'))
+            .collect();
+        let time = header
+            .iter()
+            .position(|name| *name == time_column)
+            .ok_or_else(|| invalid(path, format!("the file has no `{time_column}` column")))?;
+        let joints = header
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != time)
+            .map(|(_, name)| name.to_string())
+            .collect();
+        let mut rows: Vec<(f32, Vec<f32>)> = Vec::new();
+        let mut start = None;
+        for (row, line) in lines.enumerate() {
+            let fields: Vec<f64> = line
+                .split(delimiter)
+                .map(|field| field.trim().parse::<f64>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| {
+                    invalid(
+                        path,
+                        format!("row {} has a field that isn't a number", row + 2),
+                    )
+                })?;
+            if fields.len() != header.len() {
+                return Err(invalid(
+                    path,
+                    format!(
+                        "row {} has {} fields, not {}",
+                        row + 2,
+                        fields.len(),
+                        header.len()
+                    ),
+                ));
+            }
+            let start = *start.get_or_insert(fields[time]);
+            let seconds = (fields[time] - start) as f32;
+            if rows.last().is_some_and(|(last, _)| seconds < *last) {
+                return Err(invalid(path, format!("row {} goes back in time", row + 2)));
+            }
+            let positions = fields
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != time)
+                .map(|(_, position)| *position as f32)
+                .collect();
+            rows.push((seconds, positions));
+        }
+        if rows.is_empty() {
+            return Err(invalid(path, "the file has no rows".to_string()));
+        }
+        Ok(Self { joints, rows })
+    }
+
+    /// Reads the trajectory file of `settings`.
+    pub fn read(settings: &KinematicPlaybackSettings) -> Result<Self> {
+        let path = &settings.path;
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        Self::parse(&text, &settings.time_column, settings.delimiter, path)
+    }
+
+    /// Time of the last row, in s.
+    pub fn duration(&self) -> f32 {
+        self.rows.last().map_or(0.0, |(time, _)| *time)
+    }
+
+    /// Positions of the joints at `time`, interpolated between the rows and held past the ends.
+    pub fn positions(&self, time: f32) -> Vec<f32> {
+        let next = self.rows.partition_point(|(row, _)| *row <= time);
+        match (
+            next.checked_sub(1).map(|index| &self.rows[index]),
+            self.rows.get(next),
+        ) {
+            (Some((before, from)), Some((after, to))) => {
+                let fraction = (time - before) / (after - before);
+                from.iter()
+                    .zip(to)
+                    .map(|(from, to)| from + fraction * (to - from))
+                    .collect()
+            }
+            (Some((_, positions)), None) | (None, Some((_, positions))) => positions.clone(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// The free axis of a joint, if it has one.
+pub fn joint_kind(joint: &GenericJoint) -> Option<JointKind> {
+    let locked = joint.locked_axes();
+    if !locked.contains(JointAxesMask::ANG_X) {
+        Some(JointKind::Revolute)
+    } else if !locked.contains(JointAxesMask::LIN_X) {
+        Some(JointKind::Prismatic)
+    } else {
+        None
+    }
+}
+
+/// Pose of the body a joint moves, at `position` along the free axis of the joint, from the pose
+/// of the body it hangs from: the frames of the joint on both bodies then coincide but for the
+/// motion of the free axis.
+pub fn joint_pose(parent: &Transform, joint: &GenericJoint, position: f32) -> Transform {
+    let motion = match joint_kind(joint) {
+        Some(JointKind::Revolute) => Transform::from_rotation(Quat::from_rotation_x(position)),
+        Some(JointKind::Prismatic) => Transform::from_translation(Vec3::X * position),
+        None => Transform::IDENTITY,
+    };
+    let frame = |translation, rotation| Transform {
+        translation,
+        rotation,
+        ..default()
+    };
+    let child = frame(joint.local_anchor2(), joint.local_basis2());
+    let inverse = child.rotation.inverse();
+    parent
+        .with_scale(Vec3::ONE)
+        .mul_transform(frame(joint.local_anchor1(), joint.local_basis1()))
+        .mul_transform(motion)
+        .mul_transform(frame(-(inverse * child.translation), inverse))
+}
+
+/// A joint posed by the playback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaybackJoint {
+    /// Path of the link the joint moves.
+    pub path: String,
+    pub kind: JointKind,
+    /// Bounds of the position of the joint, if limited.
+    pub limits: Option<[f32; 2]>,
+}
+
+/// State of the kinematic playback.
+#[derive(Debug, Default, Resource)]
+pub struct KinematicPlayback {
+    /// Whether the mode is on.
+    pub enabled: bool,
+    /// The bodies switched to kinematic, with their pose before.
+    switched: Vec<(Entity, Transform)>,
+    /// The joints with a free axis, ordered by the path of their link.
+    pub joints: Vec<PlaybackJoint>,
+    /// Positions of the joints, by the path of their link.
+    pub positions: BTreeMap<String, f32>,
+    /// The trajectory file read, and the time played in it if playing.
+    pub trajectory: Option<JointTrajectory>,
+    pub playing: Option<f32>,
+    /// The pairs of links of a plant intersecting, by path.
+    pub intersections: BTreeSet<[String; 2]>,
+}
+
+impl KinematicPlayback {
+    /// Whether the links are posed kinematically.
+    pub fn is_active(&self) -> bool {
+        !self.switched.is_empty()
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<KinematicPlaybackSettings>("kinematic_playback", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Switches the dynamic links to kinematic bodies as the mode is turned on, and back, where they
+/// were and at rest, as it's turned off.
+fn switch_mode(
+    mut playback: ResMut<KinematicPlayback>,
+    mut bodies: Query<(
+        Entity,
+        &mut RigidBody,
+        &mut Transform,
+        Option<&mut Velocity>,
+        Has<Link>,
+    )>,
+) {
+    // Despawned plants.
+    playback
+        .switched
+        .retain(|(entity, _)| bodies.contains(*entity));
+    if playback.enabled && !playback.is_active() {
+        for (entity, mut body, transform, _, is_link) in &mut bodies {
+            if *body == RigidBody::Dynamic && is_link {
+                *body = RigidBody::KinematicPositionBased;
+                playback.switched.push((entity, *transform));
+            }
+        }
+        if playback.is_active() {
+            info!(target: subsystem::PHYSICS, "Kinematic playback on");
+        }
+    } else if !playback.enabled && playback.is_active() {
+        for (entity, pose) in std::mem::take(&mut playback.switched) {
+            let Ok((_, mut body, mut transform, velocity, _)) = bodies.get_mut(entity) else {
+                continue;
+            };
+            *body = RigidBody::Dynamic;
+            *transform = pose;
+            if let Some(mut velocity) = velocity {
+                *velocity = Velocity::zero();
+            }
+        }
+        playback.playing = None;
+        playback.intersections.clear();
+        info!(target: subsystem::PHYSICS, "Kinematic playback off");
+    }
+}
+
+/// Advances the trajectory file being played, writing the positions of its joints.
+fn play_file(
+    time: Res<Time>,
+    settings: Res<Persistent<KinematicPlaybackSettings>>,
+    mut playback: ResMut<KinematicPlayback>,
+) {
+    let playback = &mut *playback;
+    let (Some(trajectory), Some(played)) = (&playback.trajectory, &mut playback.playing) else {
+        return;
+    };
+    *played += time.delta_secs() * settings.speed;
+    let duration = trajectory.duration();
+    if *played > duration {
+        if settings.looped && duration > 0.0 {
+            *played = played.rem_euclid(duration);
+        } else {
+            *played = duration;
+        }
+    }
+    let at = *played;
+    if at >= duration && !settings.looped {
+        playback.playing = None;
+    }
+    for (joint, position) in trajectory.joints.iter().zip(trajectory.positions(at)) {
+        playback.positions.insert(joint.clone(), position);
+    }
+}
+
+/// Poses the switched links from the positions of their joints, down from the bodies without a
+/// joint.
+fn pose_links(
+    mut playback: ResMut<KinematicPlayback>,
+    links: Query<(&Link, Option<&ImpulseJoint>)>,
+    mut transforms: Query<&mut Transform>,
+) {
+    if !playback.is_active() {
+        return;
+    }
+    let mut joints: Vec<PlaybackJoint> = Vec::new();
+    let mut unposed: Vec<(Entity, Entity, GenericJoint, f32)> = Vec::new();
+    for (entity, _) in &playback.switched {
+        let Ok((link, joint)) = links.get(*entity) else {
+            continue;
+        };
+        let Some(joint) = joint else {
+            continue;
+        };
+        let data = *joint.data.as_ref();
+        let path = link.path();
+        let position = playback.positions.get(&path).copied().unwrap_or_default();
+        if let Some(kind) = joint_kind(&data) {
+            let axis = match kind {
+                JointKind::Revolute => JointAxis::AngX,
+                JointKind::Prismatic => JointAxis::LinX,
+            };
+            joints.push(PlaybackJoint {
+                path,
+                kind,
+                limits: data.limits(axis).map(|limits| [limits.min, limits.max]),
+            });
+        }
+        unposed.push((*entity, joint.parent, data, position));
+    }
+    joints.sort_by(|a, b| a.path.cmp(&b.path));
+    playback.joints = joints;
+
+    // Down the tree, each body once its parent is posed, or isn't switched.
+    let mut pending: BTreeSet<Entity> = unposed.iter().map(|(entity, ..)| *entity).collect();
+    while !pending.is_empty() {
+        let count = pending.len();
+        for (entity, parent, joint, position) in &unposed {
+            if !pending.contains(entity) || pending.contains(parent) {
+                continue;
+            }
+            pending.remove(entity);
+            let Ok(parent) = transforms.get(*parent).copied() else {
+                continue;
+            };
+            if let Ok(mut transform) = transforms.get_mut(*entity) {
+                let pose = joint_pose(&parent, joint, *position).with_scale(transform.scale);
+                if *transform != pose {
+                    *transform = pose;
+                }
+            }
+        }
+        if pending.len() == count {
+            // The joints loop.
+            break;
+        }
+    }
+}
+
+/// Lists the links of a plant intersecting, but for those joined together.
+fn check_intersections(
+    mut playback: ResMut<KinematicPlayback>,
+    links: Query<(
+        Entity,
+        &Link,
+        &Collider,
+        &GlobalTransform,
+        Option<&ImpulseJoint>,
+    )>,
+) {
+    if !playback.is_active() {
+        return;
+    }
+    let links: Vec<_> = links.iter().collect();
+    let mut intersections = BTreeSet::new();
+    for (index, (a, first, first_collider, first_at, first_joint)) in links.iter().enumerate() {
+        for (b, second, second_collider, second_at, second_joint) in &links[index + 1..] {
+            let joined = first_joint.is_some_and(|joint| joint.parent == *b)
+                || second_joint.is_some_and(|joint| joint.parent == *a);
+            if first.plant != second.plant || joined {
+                continue;
+            }
+            let distance =
+                proximity::distance(first_collider, first_at, second_collider, second_at);
+            if distance.is_some_and(|distance| distance <= 0.0) {
+                let mut pair = [first.path(), second.path()];
+                pair.sort();
+                intersections.insert(pair);
+            }
+        }
+    }
+    for [first, second] in intersections.difference(&playback.intersections) {
+        warn!(target: subsystem::PHYSICS, "{first} intersects {second}");
+    }
+    if intersections != playback.intersections {
+        playback.intersections = intersections;
+    }
+}
+
+/// Panel to switch the mode, set the positions of the joints and play trajectory files.
+fn kinematic_playback_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<KinematicPlaybackSettings>>,
+    mut playback: ResMut<KinematicPlayback>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Kinematic playback")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut playback.enabled, "Kinematic only (dynamics off)");
+            if !playback.is_active() {
+                ui.label("The links move under the dynamics");
+            }
+
+            ui.separator();
+            if ui.button("Zero").clicked() {
+                playback.playing = None;
+                playback.positions.clear();
+            }
+            let playback = &mut *playback;
+            for joint in &playback.joints {
+                let position = playback.positions.entry(joint.path.clone()).or_default();
+                let (range, unit) = match joint.kind {
+                    JointKind::Revolute => ([-std::f32::consts::PI, std::f32::consts::PI], "rad"),
+                    JointKind::Prismatic => ([-1.0, 1.0], "m"),
+                };
+                let [min, max] = joint.limits.unwrap_or(range);
+                let slider = ui.add(
+                    egui::Slider::new(position, min..=max).text(format!("{} ({unit})", joint.path)),
+                );
+                if slider.changed() {
+                    // The sliders take over from the file.
+                    playback.playing = None;
+                }
+            }
+            if playback.is_active() && playback.joints.is_empty() {
+                ui.label("No joint to move");
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Trajectory file");
+                let mut path = edited.path.display().to_string();
+                if ui.text_edit_singleline(&mut path).changed() {
+                    edited.path = PathBuf::from(path);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Time column");
+                ui.text_edit_singleline(&mut edited.time_column);
+            });
+            ui.add(
+                egui::Slider::new(&mut edited.speed, 0.1..=10.0)
+                    .logarithmic(true)
+                    .text("Speed"),
+            );
+            ui.checkbox(&mut edited.looped, "Loop");
+            ui.horizontal(|ui| {
+                if ui.button("Read the file").clicked() {
+                    match JointTrajectory::read(&edited) {
+                        Ok(trajectory) => playback.trajectory = Some(trajectory),
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                    playback.playing = None;
+                }
+                let loaded = playback.trajectory.is_some();
+                if ui
+                    .add_enabled(loaded && playback.is_active(), egui::Button::new("Play"))
+                    .clicked()
+                {
+                    playback.playing = Some(0.0);
+                }
+                if ui.button("Stop").clicked() {
+                    playback.playing = None;
+                }
+            });
+            if let Some(trajectory) = &playback.trajectory {
+                ui.label(format!(
+                    "{} joints over {:.2} s",
+                    trajectory.joints.len(),
+                    trajectory.duration()
+                ));
+                let unknown: Vec<&str> = trajectory
+                    .joints
+                    .iter()
+                    .filter(|path| playback.joints.iter().all(|joint| joint.path != **path))
+                    .map(String::as_str)
+                    .collect();
+                if playback.is_active() && !unknown.is_empty() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("No such joint: {}", unknown.join(", ")),
+                    );
+                }
+            }
+            if let Some(played) = playback.playing {
+                ui.label(format!("{played:.2} s"));
+            }
+
+            ui.separator();
+            if playback.is_active() {
+                if playback.intersections.is_empty() {
+                    ui.label("No links intersect");
+                }
+                for [first, second] in &playback.intersections {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("{first} intersects {second}"),
+                    );
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("kinematic_playback", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("kinematic_playback", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
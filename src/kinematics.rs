@@ -0,0 +1,678 @@
+//! This module provides the kinematics of the articulated chains of the plants: the forward
+//! kinematics placing the end effector from the positions of the joints, and a numerical inverse
+//! kinematics solver finding the positions of the joints placing it on a target.
+//!
+//! A [`KinematicChain`] is built from the loaded joints, walking up from the end-effector link
+//! through the parents of its joints to the base. Each joint is kept as its screw axis in the
+//! world at the configuration it was built in, so the forward kinematics is a product of
+//! exponentials: the rigid motions of the joints away from that configuration, composed from the
+//! base. The inverse kinematics iterates damped least squares steps, `Δq = Jᵀ (J Jᵀ + λ² I)⁻¹ e`,
+//! on the error `e` of the position of the end effector; the damping keeps the steps bounded near
+//! the singular configurations, where a plain pseudo-inverse blows up. Only the position of the
+//! end effector is solved for, not its orientation.
+//!
+//! With the solver enabled in the *Kinematics* panel, the target is drawn as a sphere that the
+//! left button drags around, and the joints follow the solution through their velocity setpoints:
+//! the error to the solved position, times a gain. The distance from the end effector to the
+//! target is recorded as the `ik/error` telemetry channel.
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+    theme::Theme,
+};
+
+/// Distance from the end effector to the target of the inverse kinematics, in m.
+pub const IK_ERROR: &str = "ik/error";
+/// Radius of the sphere drawn on the target, in m.
+const TARGET_RADIUS: f32 = 0.05;
+
+/// How a joint of a chain moves.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JointKind {
+    /// Turns about its axis, by an angle in rad.
+    Revolute,
+    /// Slides along its axis, by a distance in m.
+    Prismatic,
+}
+
+/// A joint of a [`KinematicChain`], at the configuration the chain was built in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainJoint {
+    /// Name of the link the joint moves.
+    pub name: String,
+    pub kind: JointKind,
+    /// A point of the axis, and its unit direction, in the world.
+    pub anchor: Vec3,
+    pub axis: Vec3,
+    /// Position of the joint.
+    pub reference: f32,
+    /// Bounds of the position of the joint, if limited.
+    pub limits: Option<[f32; 2]>,
+    /// Whether the solver keeps the joint where it is.
+    pub locked: bool,
+}
+
+impl ChainJoint {
+    /// Rigid motion of the joint moving by `delta` from its reference.
+    fn motion(&self, delta: f32) -> Transform {
+        match self.kind {
+            JointKind::Revolute => {
+                let rotation = Quat::from_axis_angle(self.axis, delta);
+                Transform {
+                    translation: self.anchor - rotation * self.anchor,
+                    rotation,
+                    ..default()
+                }
+            }
+            JointKind::Prismatic => Transform::from_translation(self.axis * delta),
+        }
+    }
+}
+
+/// A serial chain of joints, from its base to its end effector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KinematicChain {
+    /// The joints, from the base.
+    pub joints: Vec<ChainJoint>,
+    /// The end effector, in the world, at the reference positions of the joints.
+    pub tip: Vec3,
+}
+
+/// Tuning of the inverse kinematics solver.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct IkSolver {
+    /// Damping `λ` of the least squares steps, in m.
+    pub damping: f32,
+    pub max_iterations: usize,
+    /// Distance from the target below which the solution is found, in m.
+    pub tolerance: f32,
+    /// Largest change of the position of a joint per iteration.
+    pub max_step: f32,
+}
+
+impl Default for IkSolver {
+    fn default() -> Self {
+        Self {
+            damping: 0.05,
+            max_iterations: 100,
+            tolerance: 1e-3,
+            max_step: 0.2,
+        }
+    }
+}
+
+/// Positions of the joints reaching a target, or getting as close as the solver could.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IkSolution {
+    pub positions: Vec<f32>,
+    /// Distance left from the end effector to the target, in m.
+    pub error: f32,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl KinematicChain {
+    /// Motions of the frames of the links, from the base, with the joints at `positions`, or at
+    /// their reference past them.
+    fn motions(&self, positions: &[f32]) -> Vec<Transform> {
+        let mut motion = Transform::IDENTITY;
+        self.joints
+            .iter()
+            .enumerate()
+            .map(|(index, joint)| {
+                let position = positions.get(index).copied().unwrap_or(joint.reference);
+                motion = motion.mul_transform(joint.motion(position - joint.reference));
+                motion
+            })
+            .collect()
+    }
+
+    /// Points of the chain with the joints at `positions`: the anchor of each joint, then the end
+    /// effector.
+    pub fn forward(&self, positions: &[f32]) -> Vec<Vec3> {
+        let motions = self.motions(positions);
+        let mut points: Vec<Vec3> = self
+            .joints
+            .iter()
+            .enumerate()
+            .map(|(index, joint)| match index {
+                0 => joint.anchor,
+                _ => motions[index - 1].transform_point(joint.anchor),
+            })
+            .collect();
+        points.push(
+            motions
+                .last()
+                .map_or(self.tip, |motion| motion.transform_point(self.tip)),
+        );
+        points
+    }
+
+    /// Position of the end effector with the joints at `positions`.
+    pub fn end_effector(&self, positions: &[f32]) -> Vec3 {
+        self.forward(positions).last().copied().unwrap_or(self.tip)
+    }
+
+    /// Columns of the Jacobian of the position of the end effector, with the joints at
+    /// `positions`: its velocity per unit velocity of each joint, zero for the locked ones.
+    pub fn jacobian(&self, positions: &[f32]) -> Vec<Vec3> {
+        let motions = self.motions(positions);
+        let points = self.forward(positions);
+        let tip = points[points.len() - 1];
+        self.joints
+            .iter()
+            .enumerate()
+            .map(|(index, joint)| {
+                if joint.locked {
+                    return Vec3::ZERO;
+                }
+                let rotation = match index {
+                    0 => Quat::IDENTITY,
+                    _ => motions[index - 1].rotation,
+                };
+                let axis = rotation * joint.axis;
+                match joint.kind {
+                    JointKind::Revolute => axis.cross(tip - points[index]),
+                    JointKind::Prismatic => axis,
+                }
+            })
+            .collect()
+    }
+
+    /// Positions of the joints placing the end effector on `target`, by damped least squares
+    /// from `initial`, within the limits of the joints.
+    pub fn solve(&self, initial: &[f32], target: Vec3, solver: &IkSolver) -> IkSolution {
+        let mut positions: Vec<f32> = self
+            .joints
+            .iter()
+            .enumerate()
+            .map(|(index, joint)| initial.get(index).copied().unwrap_or(joint.reference))
+            .collect();
+        let mut error = target - self.end_effector(&positions);
+        let mut iterations = 0;
+        while error.length() > solver.tolerance && iterations < solver.max_iterations {
+            let columns = self.jacobian(&positions);
+            // J Jᵀ + λ² I, summing the outer products of the columns.
+            let mut normal = Mat3::from_diagonal(Vec3::splat(solver.damping * solver.damping));
+            for column in &columns {
+                normal +=
+                    Mat3::from_cols(*column * column.x, *column * column.y, *column * column.z);
+            }
+            if normal.determinant().abs() < f32::EPSILON {
+                // Undamped at a singularity: no step is defined.
+                break;
+            }
+            let along = normal.inverse() * error;
+            let mut steps: Vec<f32> = columns.iter().map(|column| column.dot(along)).collect();
+            let largest = steps
+                .iter()
+                .fold(0.0f32, |largest, step| largest.max(step.abs()));
+            if solver.max_step > 0.0 && largest > solver.max_step {
+                for step in &mut steps {
+                    *step *= solver.max_step / largest;
+                }
+            }
+            for ((position, step), joint) in positions.iter_mut().zip(steps).zip(&self.joints) {
+                *position += step;
+                if let Some([min, max]) = joint.limits {
+                    *position = position.clamp(min, max);
+                }
+            }
+            error = target - self.end_effector(&positions);
+            iterations += 1;
+        }
+        IkSolution {
+            positions,
+            error: error.length(),
+            iterations,
+            converged: error.length() <= solver.tolerance,
+        }
+    }
+}
+
+pub struct KinematicsPlugin;
+
+impl Plugin for KinematicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Kinematics>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                follow_target
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<KinematicsSettings>>),
+            )
+            .add_systems(
+                Update,
+                (
+                    build_chain.run_if(resource_exists::<Persistent<KinematicsSettings>>),
+                    (drag_target, draw_chain, kinematics_panel)
+                        .chain()
+                        .run_if(resource_exists::<Persistent<KinematicsSettings>>)
+                        .run_if(has_ui),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Represents the configuration of the kinematics.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct KinematicsSettings {
+    /// Whether the joints follow the target.
+    pub enabled: bool,
+    /// Name of the link carrying the end effector, in the first plant having it.
+    pub end_effector: String,
+    /// The end effector, in the frame of its link.
+    pub tip: Vec3,
+    /// Velocity setpoint driving each joint, by the name of the link it moves; the other joints
+    /// are kept where they are.
+    pub setpoints: BTreeMap<String, String>,
+    /// Gain from the error to the solved position to the velocity setpoint, in 1/s.
+    pub gain: f32,
+    /// Largest velocity setpoint, in units of the position per second.
+    pub max_velocity: f32,
+    pub solver: IkSolver,
+}
+
+impl Default for KinematicsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            end_effector: "forearm".to_string(),
+            tip: Vec3::Y * 0.4,
+            setpoints: BTreeMap::from([
+                ("upper_arm".to_string(), "shoulder/velocity".to_string()),
+                ("forearm".to_string(), "elbow/velocity".to_string()),
+            ]),
+            gain: 5.0,
+            max_velocity: 3.0,
+            solver: IkSolver::default(),
+        }
+    }
+}
+
+/// The chain of the end effector, with the entities of its joints, and its target.
+#[derive(Default, Resource)]
+pub struct Kinematics {
+    pub chain: Option<(KinematicChain, Vec<Entity>)>,
+    /// Measured positions of the joints.
+    pub positions: Vec<f32>,
+    pub target: Option<Vec3>,
+    pub solution: Option<IkSolution>,
+    /// End effector, tip and driven joints the chain was built for.
+    built_for: Option<(String, Vec3, BTreeMap<String, String>)>,
+    /// Distance along the cursor ray at which the target is being dragged.
+    dragging: Option<f32>,
+    /// Whether the velocity setpoints were written, to stop the joints once disabled.
+    driving: bool,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<KinematicsSettings>("kinematics", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// The bodies of the plants, with their joints.
+type Bodies<'a> = (
+    Entity,
+    Option<&'a Link>,
+    &'a GlobalTransform,
+    Option<&'a ImpulseJoint>,
+);
+
+/// Position of the joint on `entity`, from the bodies it joins.
+fn joint_position(
+    context: &RapierContext,
+    entity: Entity,
+    kind: JointKind,
+    bodies: &Query<Bodies>,
+) -> Option<f32> {
+    match kind {
+        JointKind::Revolute => context.impulse_revolute_joint_angle(entity),
+        JointKind::Prismatic => {
+            let (_, _, child, joint) = bodies.get(entity).ok()?;
+            let joint = joint?;
+            let (_, _, parent, _) = bodies.get(joint.parent).ok()?;
+            let data = joint.data.as_ref();
+            let axis = (parent.rotation() * data.local_axis1()).normalize();
+            let offset = child.transform_point(data.local_anchor2())
+                - parent.transform_point(data.local_anchor1());
+            Some(offset.dot(axis))
+        }
+    }
+}
+
+/// Builds the chain of the end effector when missing, and measures the positions of its joints.
+fn build_chain(
+    settings: Res<Persistent<KinematicsSettings>>,
+    mut kinematics: ResMut<Kinematics>,
+    bodies: Query<Bodies>,
+    contexts: Query<&RapierContext>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    let built_for = (
+        settings.end_effector.clone(),
+        settings.tip,
+        settings.setpoints.clone(),
+    );
+    if kinematics.built_for.as_ref() != Some(&built_for)
+        || kinematics
+            .chain
+            .as_ref()
+            .is_some_and(|(_, entities)| entities.iter().any(|entity| bodies.get(*entity).is_err()))
+    {
+        // Another end effector, or another plant.
+        kinematics.built_for = Some(built_for);
+        kinematics.chain = None;
+        kinematics.target = None;
+        kinematics.solution = None;
+    }
+    if kinematics.chain.is_none() {
+        let Some((end, _, end_transform, _)) = bodies
+            .iter()
+            .filter(|(_, link, ..)| link.is_some_and(|link| link.name == settings.end_effector))
+            .min_by(|(_, a, ..), (_, b, ..)| a.map(|a| &a.plant).cmp(&b.map(|b| &b.plant)))
+        else {
+            return;
+        };
+        let (mut joints, mut entities) = (Vec::new(), Vec::new());
+        let mut body = end;
+        // Up to the base, bounded in case the joints loop.
+        for _ in 0..bodies.iter().count() {
+            let Ok((entity, link, _, Some(joint))) = bodies.get(body) else {
+                break;
+            };
+            let Ok((_, _, parent, _)) = bodies.get(joint.parent) else {
+                break;
+            };
+            body = joint.parent;
+            let data = joint.data.as_ref();
+            let locked = data.locked_axes();
+            let (kind, axis) = if !locked.contains(JointAxesMask::ANG_X) {
+                (JointKind::Revolute, JointAxis::AngX)
+            } else if !locked.contains(JointAxesMask::LIN_X) {
+                (JointKind::Prismatic, JointAxis::LinX)
+            } else {
+                continue;
+            };
+            let Some(reference) = joint_position(context, entity, kind, &bodies) else {
+                continue;
+            };
+            let name = link.map_or_else(|| format!("{entity}"), |link| link.name.clone());
+            joints.push(ChainJoint {
+                locked: !settings.setpoints.contains_key(&name),
+                name,
+                kind,
+                anchor: parent.transform_point(data.local_anchor1()),
+                axis: (parent.rotation() * data.local_axis1()).normalize(),
+                reference,
+                limits: data.limits(axis).map(|limits| [limits.min, limits.max]),
+            });
+            entities.push(entity);
+        }
+        if joints.is_empty() {
+            return;
+        }
+        joints.reverse();
+        entities.reverse();
+        let tip = end_transform.transform_point(settings.tip);
+        info!(
+            target: subsystem::CONTROL,
+            "Kinematic chain of {}: {}",
+            settings.end_effector,
+            joints
+                .iter()
+                .map(|joint| joint.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        kinematics.target = Some(tip);
+        kinematics.chain = Some((KinematicChain { joints, tip }, entities));
+    }
+    let Some((chain, entities)) = &kinematics.chain else {
+        return;
+    };
+    let positions = chain
+        .joints
+        .iter()
+        .zip(entities)
+        .map(|(joint, entity)| {
+            joint_position(context, *entity, joint.kind, &bodies).unwrap_or(joint.reference)
+        })
+        .collect();
+    kinematics.positions = positions;
+}
+
+/// Solves for the target, and drives the joints towards the solution.
+fn follow_target(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<KinematicsSettings>>,
+    mut kinematics: ResMut<Kinematics>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let kinematics = &mut *kinematics;
+    let (Some((chain, _)), Some(target), true) =
+        (&kinematics.chain, kinematics.target, settings.enabled)
+    else {
+        if kinematics.driving {
+            for setpoint in settings.setpoints.values() {
+                setpoints.set(setpoint, 0.0);
+            }
+            kinematics.driving = false;
+        }
+        return;
+    };
+    let solution = chain.solve(&kinematics.positions, target, &settings.solver);
+    let limit = settings.max_velocity.max(0.0);
+    for ((joint, measured), solved) in chain
+        .joints
+        .iter()
+        .zip(&kinematics.positions)
+        .zip(&solution.positions)
+    {
+        if let Some(setpoint) = settings.setpoints.get(&joint.name) {
+            let velocity = (settings.gain * (solved - measured)).clamp(-limit, limit);
+            setpoints.set(setpoint, velocity);
+        }
+    }
+    kinematics.driving = true;
+    if let Some(mut telemetry) = telemetry {
+        let error = target.distance(chain.end_effector(&kinematics.positions));
+        telemetry.record(IK_ERROR, clock.elapsed_secs(), error);
+    }
+    kinematics.solution = Some(solution);
+}
+
+/// Drags the target along the cursor ray with the left button, at the depth it was grabbed at.
+fn drag_target(
+    mut egui: EguiContexts,
+    settings: Res<Persistent<KinematicsSettings>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut kinematics: ResMut<Kinematics>,
+) {
+    let held = settings.enabled && buttons.pressed(MouseButton::Left);
+    if !held {
+        if kinematics.dragging.take().is_some() {
+            for mut orbit in &mut orbits {
+                orbit.enabled = true;
+            }
+        }
+        return;
+    }
+    let Some(ray) = windows
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .and_then(|cursor| {
+            let (camera, transform) = cameras.iter().find(|(camera, _)| camera.is_active)?;
+            camera.viewport_to_world(transform, cursor).ok()
+        })
+    else {
+        return;
+    };
+    let Some(target) = kinematics.target else {
+        return;
+    };
+    if let Some(depth) = kinematics.dragging {
+        kinematics.target = Some(ray.get_point(depth));
+        return;
+    }
+    if !buttons.just_pressed(MouseButton::Left) || egui.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    let depth = (target - ray.origin).dot(*ray.direction);
+    if depth > 0.0 && ray.get_point(depth).distance(target) <= 2.0 * TARGET_RADIUS {
+        kinematics.dragging = Some(depth);
+        for mut orbit in &mut orbits {
+            orbit.enabled = false;
+        }
+    }
+}
+
+/// Draws the chain at its measured positions, and the target.
+fn draw_chain(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<KinematicsSettings>>,
+    kinematics: Res<Kinematics>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let (Some((chain, _)), Some(target)) = (&kinematics.chain, kinematics.target) else {
+        return;
+    };
+    if !settings.enabled {
+        return;
+    }
+    let color = |index| {
+        theme.as_ref().map_or_else(
+            || Theme::default().series(index),
+            |theme| theme.series(index),
+        )
+    };
+    let points = chain.forward(&kinematics.positions);
+    gizmos.linestrip(points.iter().copied(), color(2));
+    for point in &points {
+        gizmos.sphere(Isometry3d::from_translation(*point), 0.02, color(2));
+    }
+    gizmos.sphere(
+        Isometry3d::from_translation(target),
+        TARGET_RADIUS,
+        color(1),
+    );
+}
+
+/// Panel to enable the solver, move its target and tune it.
+fn kinematics_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<KinematicsSettings>>,
+    mut kinematics: ResMut<Kinematics>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Kinematics")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Follow the target");
+            ui.horizontal(|ui| {
+                ui.label("End effector");
+                ui.text_edit_singleline(&mut edited.end_effector);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Tip (m)");
+                ui.add(egui::DragValue::new(&mut edited.tip.x).speed(0.01));
+                ui.add(egui::DragValue::new(&mut edited.tip.y).speed(0.01));
+                ui.add(egui::DragValue::new(&mut edited.tip.z).speed(0.01));
+            });
+
+            ui.separator();
+            let kinematics = &mut *kinematics;
+            match (&kinematics.chain, &mut kinematics.target) {
+                (Some((chain, _)), Some(target)) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Target (m)");
+                        ui.add(egui::DragValue::new(&mut target.x).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut target.y).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut target.z).speed(0.01));
+                    });
+                    if ui.button("Target the end effector").clicked() {
+                        *target = chain.end_effector(&kinematics.positions);
+                    }
+                    for (joint, position) in chain.joints.iter().zip(&kinematics.positions) {
+                        let driven = if joint.locked { " (held)" } else { "" };
+                        ui.label(format!("{}: {position:.3}{driven}", joint.name));
+                    }
+                    if let Some(solution) = &kinematics.solution {
+                        ui.label(format!(
+                            "{} after {} iterations, {:.4} m away",
+                            if solution.converged {
+                                "Reached"
+                            } else {
+                                "Out of reach"
+                            },
+                            solution.iterations,
+                            solution.error
+                        ));
+                    }
+                }
+                _ => {
+                    ui.label(format!("No joint moves the {} link", edited.end_effector));
+                }
+            }
+
+            ui.separator();
+            ui.add(egui::Slider::new(&mut edited.gain, 0.0..=50.0).text("Gain (1/s)"));
+            ui.add(egui::Slider::new(&mut edited.max_velocity, 0.0..=20.0).text("Velocity limit"));
+            ui.add(
+                egui::Slider::new(&mut edited.solver.damping, 0.0..=1.0)
+                    .logarithmic(true)
+                    .text("Damping (m)"),
+            );
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("kinematics", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("kinematics", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,468 @@
+//! Latency of the control loops, to study how the communication between the sensors, the
+//! controllers and the actuators destabilizes a loop before deploying it on networked hardware.
+//!
+//! Each loop has two paths, each a [`DeadTime`] buffer of its own: the sensing path, from the
+//! sampling of the angle of a joint to the controllers reading it, and the actuation path, from
+//! the controllers commanding its motor to the motor applying the command. A value sent on a
+//! path arrives after the `delay` of the path plus a uniform random `jitter`, so values can
+//! overtake each other: a value arriving after a fresher one is dropped, as a receiver keeping
+//! the latest sample would. Until the first value arrives, the controllers read nothing and the
+//! motor holds still.
+//!
+//! A loop can also be closed through the network links of the datagram bridges (native only),
+//! as for a controller on the peer: its sensing path takes the latency of the outgoing link of
+//! `network.json`, and its actuation path the one of the incoming link.
+//!
+//! The values out of the paths are recorded as the `latency/<link>/reading` and
+//! `latency/<link>/command` telemetry channels, to be compared with the angles and the commands
+//! sent. The latencies are added to the joints of the configured links, as configured in
+//! `latency.json`. The jitter is drawn from a seeded generator, so runs are reproducible.
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::network::NetworkSettings;
+use crate::{
+    accessibility_plugin::has_ui,
+    actuators,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    estimation::EstimationSet,
+    logging::subsystem,
+    plants::{self, Link},
+    sensors::{self, ControlSet, Encoder, SensorSet},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the loops.
+pub const LATENCY_PREFIX: &str = "latency/";
+
+pub struct LatencyPlugin;
+
+impl Plugin for LatencyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkLatency>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_latencies.run_if(resource_exists::<Persistent<LatencySettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    delay_readings
+                        .after(SensorSet)
+                        .before(EstimationSet)
+                        .before(ControlSet),
+                    delay_commands
+                        .after(ControlSet)
+                        .before(actuators::drive)
+                        .before(PhysicsSet::SyncBackend),
+                ),
+            )
+            .add_systems(
+                Update,
+                latency_panel
+                    .run_if(resource_exists::<Persistent<LatencySettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Latency of one path of a loop.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct PathLatency {
+    /// Dead time of the path, in s.
+    pub delay: f32,
+    /// Largest random time added to the delay, in s.
+    pub jitter: f32,
+}
+
+/// Represents the latency of the loop closed around the joint of a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LoopLatencySettings {
+    /// Name of the link whose joint is measured and driven, in each plant.
+    pub link: String,
+    /// From the sampling of the angle to the controllers reading it.
+    pub sensing: PathLatency,
+    /// From the controllers commanding the motor to the motor applying the command.
+    pub actuation: PathLatency,
+    /// Whether the paths take the latency of the network links instead.
+    pub network: bool,
+}
+
+impl Default for LoopLatencySettings {
+    fn default() -> Self {
+        Self {
+            link: "motor".to_string(),
+            sensing: PathLatency::default(),
+            actuation: PathLatency::default(),
+            network: false,
+        }
+    }
+}
+
+impl LoopLatencySettings {
+    /// The settings with the latency of the `network` links, when the loop is closed through
+    /// them.
+    pub fn resolve(&self, network: &NetworkLatency) -> Self {
+        if !self.network {
+            return self.clone();
+        }
+        Self {
+            sensing: network.sensing,
+            actuation: network.actuation,
+            ..self.clone()
+        }
+    }
+}
+
+/// Latency of the paths of a loop closed through the network links of `network.json`.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct NetworkLatency {
+    /// The outgoing link, sending the measurements to the peer.
+    pub sensing: PathLatency,
+    /// The incoming link, receiving the commands of the peer.
+    pub actuation: PathLatency,
+}
+
+/// Represents the configuration of the latencies, `latency.json`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct LatencySettings {
+    /// Whether the latencies are applied, to compare the loops with and without them.
+    pub enabled: bool,
+    pub loops: Vec<LoopLatencySettings>,
+    /// Seed of the random draws.
+    pub seed: u64,
+}
+
+impl Default for LatencySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            loops: Vec::new(),
+            seed: 1,
+        }
+    }
+}
+
+/// A dead-time buffer: the values sent on a path, delivered after its latency.
+#[derive(Clone, Debug)]
+pub struct DeadTime {
+    latency: PathLatency,
+    /// State of the xorshift generator.
+    random: u64,
+    /// Values on their way, with the time they were sent at and the time they arrive at.
+    pending: VecDeque<(f32, f32, f32)>,
+    /// Last value delivered, with the time it was sent at.
+    output: Option<(f32, f32)>,
+    /// Time of the last value sent or received.
+    last: Option<f32>,
+}
+
+impl DeadTime {
+    pub fn new(latency: PathLatency, seed: u64) -> Self {
+        Self {
+            latency,
+            random: seed.max(1),
+            pending: VecDeque::new(),
+            output: None,
+            last: None,
+        }
+    }
+
+    /// Sends `value` at `time`.
+    pub fn send(&mut self, time: f32, value: f32) {
+        self.rewind(time);
+        let jitter = self.uniform() * self.latency.jitter.max(0.0);
+        let arrival = time + self.latency.delay.max(0.0) + jitter;
+        self.pending.push_back((time, arrival, value));
+    }
+
+    /// The freshest value arrived by `time`, once the first one arrived.
+    pub fn receive(&mut self, time: f32) -> Option<f32> {
+        self.rewind(time);
+        let output = &mut self.output;
+        self.pending.retain(|&(sent, arrival, value)| {
+            if arrival > time + 1e-6 {
+                return true;
+            }
+            // Overtaken by a fresher value.
+            if output.is_none_or(|(delivered, _)| sent >= delivered) {
+                *output = Some((sent, value));
+            }
+            false
+        });
+        self.output.map(|(_, value)| value)
+    }
+
+    /// Number of values on their way.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Empties the path when the time goes back, for a new run.
+    fn rewind(&mut self, time: f32) {
+        if self.last.is_some_and(|last| time < last) {
+            self.pending.clear();
+            self.output = None;
+        }
+        self.last = Some(time);
+    }
+
+    /// A random number in [0, 1).
+    fn uniform(&mut self) -> f32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// The latency of the loop closed around the revolute joint of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct LoopLatency {
+    pub settings: LoopLatencySettings,
+    /// Prefix of the telemetry channels.
+    pub channel: String,
+    sensing: DeadTime,
+    actuation: DeadTime,
+    /// Command the motor was last given out of the actuation path.
+    applied: Option<f32>,
+}
+
+impl LoopLatency {
+    pub fn new(settings: LoopLatencySettings, channel: String, seed: u64) -> Self {
+        Self {
+            sensing: DeadTime::new(settings.sensing, seed),
+            actuation: DeadTime::new(settings.actuation, seed.wrapping_add(1 << 32)),
+            settings,
+            channel,
+            applied: None,
+        }
+    }
+
+    /// Angle the controllers read, once the first sample arrived.
+    pub fn reading(&self) -> Option<f32> {
+        self.sensing.output.map(|(_, value)| value)
+    }
+
+    /// Dead time of the loop, from the sampling of the angle to the motor applying the command
+    /// computed from it, without the jitter, in s.
+    pub fn dead_time(&self) -> f32 {
+        self.settings.sensing.delay.max(0.0) + self.settings.actuation.delay.max(0.0)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<LatencySettings>("latency", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let (network, error) = config_plugin::load_config::<NetworkSettings>("network", false);
+        commands.insert_resource(NetworkLatency {
+            sensing: network.get().outgoing.latency(),
+            actuation: network.get().incoming.latency(),
+        });
+        if let Some(error) = error {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Adds the configured latencies to the joints, as they spawn or when the configuration
+/// changes.
+fn configure_latencies(
+    mut commands: Commands,
+    settings: Res<Persistent<LatencySettings>>,
+    network: Res<NetworkLatency>,
+    joints: Query<(Entity, &Link, Option<&LoopLatency>), With<ImpulseJoint>>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && !network.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, latency) in &joints {
+        let configured = settings
+            .loops
+            .iter()
+            .enumerate()
+            .filter(|_| settings.enabled)
+            .find(|(_, latency)| latency.link == link.name)
+            .map(|(index, latency)| (index, latency.resolve(&network)));
+        match (configured, latency) {
+            (Some((_, configured)), Some(latency)) if latency.settings == configured => {}
+            (Some((index, configured)), _) => {
+                let channel = format!("{LATENCY_PREFIX}{}", link.name);
+                commands.entity(entity).insert(LoopLatency::new(
+                    configured,
+                    plants::namespaced(&link.plant, &channel),
+                    settings.seed + index as u64,
+                ));
+                info!(target: subsystem::CONTROL, "Latency on {}", link.path());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<LoopLatency>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Sends the angles measured after the physics step down the sensing paths, and delivers those
+/// arrived to the controllers.
+fn delay_readings(
+    clock: Res<SimClock>,
+    mut latencies: Query<(Entity, &mut LoopLatency)>,
+    encoders: Query<&Encoder>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (entity, mut latency) in &mut latencies {
+        if let Some(angle) = sensors::measured_angle(context, &encoders, entity) {
+            latency.sensing.send(time, angle);
+        }
+        let reading = latency.sensing.receive(time);
+        if let (Some(telemetry), Some(reading)) = (telemetry.as_mut(), reading) {
+            telemetry.record(&format!("{}/reading", latency.channel), time, reading);
+        }
+    }
+}
+
+/// Sends the commands of the controllers down the actuation paths, and gives the motors those
+/// arrived.
+pub(crate) fn delay_commands(
+    clock: Res<SimClock>,
+    mut joints: Query<(&mut LoopLatency, &mut ImpulseJoint)>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    if clock.delta_secs() <= 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (mut latency, mut joint) in &mut joints {
+        let Some(motor) = joint.data.as_ref().motor(JointAxis::AngX).copied() else {
+            continue;
+        };
+        if motor.damping <= 0.0 {
+            continue;
+        }
+        // Unless no controller commanded the motor since the last step.
+        if latency.applied != Some(motor.target_vel) {
+            latency.actuation.send(time, motor.target_vel);
+        }
+        let command = latency.actuation.receive(time).unwrap_or_default();
+        latency.applied = Some(command);
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, command, motor.damping);
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&format!("{}/command", latency.channel), time, command);
+        }
+    }
+}
+
+/// Drag values of the delay and the jitter of a path.
+fn path_latency(ui: &mut egui::Ui, name: &str, latency: &mut PathLatency) {
+    ui.label(name);
+    ui.add(
+        egui::DragValue::new(&mut latency.delay)
+            .range(0.0..=1.0)
+            .speed(0.0001)
+            .prefix("Delay: ")
+            .suffix(" s"),
+    );
+    ui.add(
+        egui::DragValue::new(&mut latency.jitter)
+            .range(0.0..=1.0)
+            .speed(0.0001)
+            .prefix("Jitter: ")
+            .suffix(" s"),
+    )
+    .on_hover_text("Largest random time added to the delay");
+    ui.end_row();
+}
+
+/// Panel to set the latencies of the loops.
+fn latency_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<LatencySettings>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Latency")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            ui.horizontal(|ui| {
+                ui.label("Seed");
+                ui.add(egui::DragValue::new(&mut edited.seed));
+            });
+            ui.separator();
+            let mut removed = None;
+            for (index, latency) in edited.loops.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut latency.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.checkbox(&mut latency.network, "Through the network links")
+                    .on_hover_text("Take the latency of the links of network.json");
+                ui.add_enabled_ui(!latency.network, |ui| {
+                    egui::Grid::new(("latency", index)).show(ui, |ui| {
+                        path_latency(ui, "Sensing", &mut latency.sensing);
+                        path_latency(ui, "Actuation", &mut latency.actuation);
+                    });
+                });
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.loops.remove(index);
+            }
+            if ui.button("Add a loop").clicked() {
+                edited.loops.push(LoopLatencySettings::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("latency", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("latency", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
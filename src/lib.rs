@@ -0,0 +1,208 @@
+//! Library part of the playground: the plugins that make up the application, so they can be
+//! reused by the binary, the integration tests and other front-ends.
+
+pub mod accessibility_plugin;
+pub mod audio_plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod autosave;
+#[cfg(feature = "embedded-model")]
+pub mod embedded_model;
+#[cfg(feature = "blender-model")]
+pub mod scene_viewer_plugin;
+
+pub mod actuators;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod analysis;
+pub mod anomalies;
+pub mod autotune;
+pub mod ball_and_beam;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod batch;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+pub mod body_state;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod calibration;
+pub mod camera_sequence;
+pub mod camera_views;
+#[cfg(target_os = "linux")]
+pub mod canopen;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capture;
+pub mod cart_pole;
+pub mod cli;
+pub mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod codegen;
+pub mod colliders;
+pub mod command_buffer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod comparison;
+pub mod compliant_axis;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod composition;
+pub mod config_plugin;
+pub mod contacts;
+pub mod control;
+pub mod controller_definition;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cost_script;
+pub mod custom_controllers;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dashboard;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod determinism;
+pub mod disturbances;
+pub mod double_pendulum;
+pub mod dual_loop_controller;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod environment;
+pub mod error;
+pub mod estimation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod estimator_replay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod experiments;
+pub mod extensions;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fault_detection;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fieldbus;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fixed_step;
+pub mod fixtures;
+pub mod frames;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod frequency_response;
+pub mod friction;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fuzzing;
+pub mod gamepad_mapping;
+pub mod gantry;
+pub mod gantry_controller;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod governor;
+pub mod grid_plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gym;
+pub mod haptics_plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hardware_log;
+pub mod headless;
+pub mod hmi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ident;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod identification;
+pub mod input_bindings;
+pub mod interaction;
+pub mod jog;
+pub mod joint_authoring;
+pub mod joint_builder;
+pub mod joint_limits;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod kinematic_playback;
+pub mod kinematics;
+pub mod latency;
+pub mod lighting_plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lockstep;
+pub mod lod;
+pub mod logging;
+pub mod lqg;
+pub mod lqr;
+pub mod mass_overrides;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod migration;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod modbus;
+pub mod model_library;
+pub mod monitors;
+pub mod mpc;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod network;
+#[cfg(all(feature = "opcua", not(target_arch = "wasm32")))]
+pub mod opcua;
+pub mod overlays;
+pub mod physics_parameters;
+pub mod pid_controller;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod placement;
+pub mod planar_arm;
+pub mod plants;
+pub mod platform;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod plotjuggler;
+pub mod plots;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod precision;
+pub mod proximity;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod recording;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod resource_usage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rest_api;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod roa;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod run_diff;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scenario;
+pub mod scene_diff;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod script_test;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scripted_controller;
+pub mod selection;
+pub mod self_collision;
+pub mod sensorless;
+pub mod sensors;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod serial_bridge;
+pub mod setpoint_command;
+pub mod setpoints;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod shaping;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sim_thread;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sizing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshots;
+pub mod spectrum;
+pub mod state_machines;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod step_response;
+pub mod stereo;
+pub mod stream_log;
+pub mod supervisor;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sweep;
+pub mod swing_up;
+pub mod teach;
+pub mod telemetry;
+pub mod theme;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod traces;
+pub mod trajectory;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod trajectory_optimization;
+pub mod transmissions;
+pub mod transport;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod udp_packets;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod urdf;
+#[cfg(target_os = "linux")]
+pub mod virtual_joystick;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watchdog;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod waveforms;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod websocket_api;
@@ -0,0 +1,215 @@
+//! This module provides a plugin that applies the [`LightingSettings`] to the scene.
+//! It keeps the ambient light, the environment map of every 3D camera and the auto-spawned
+//! directional light in sync with the configuration, and offers a panel to edit it at runtime.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+
+use crate::{
+    config_plugin::{EnvironmentMap, LightingSettings},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+};
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                apply_lighting.run_if(resource_changed::<Persistent<LightingSettings>>),
+                attach_environment_map,
+                lighting_panel.run_if(resource_exists::<Persistent<LightingSettings>>),
+            ),
+        );
+    }
+}
+
+/// Marks the directional light spawned from the [`LightingSettings`].
+#[derive(Component)]
+pub struct AutoDirectionalLight;
+
+fn directional_rotation(settings: &LightingSettings) -> Quat {
+    Quat::from_euler(
+        EulerRot::YXZ,
+        settings.directional_yaw.to_radians(),
+        settings.directional_pitch.to_radians(),
+        0.0,
+    )
+}
+
+fn environment_map_light(
+    settings: &LightingSettings,
+    asset_server: &AssetServer,
+) -> Option<EnvironmentMapLight> {
+    settings
+        .environment_map
+        .paths()
+        .map(|(diffuse, specular)| EnvironmentMapLight {
+            diffuse_map: asset_server.load(diffuse.to_string()),
+            specular_map: asset_server.load(specular.to_string()),
+            intensity: settings.environment_intensity,
+            ..default()
+        })
+}
+
+/// Applies the lighting settings whenever they change.
+fn apply_lighting(
+    mut commands: Commands,
+    settings: Res<Persistent<LightingSettings>>,
+    asset_server: Res<AssetServer>,
+    cameras: Query<Entity, With<Camera3d>>,
+    mut lights: Query<(Entity, &mut DirectionalLight, &mut Transform), With<AutoDirectionalLight>>,
+) {
+    commands.insert_resource(AmbientLight {
+        color: settings.ambient_color,
+        brightness: settings.ambient_brightness,
+    });
+
+    for camera in &cameras {
+        match environment_map_light(&settings, &asset_server) {
+            Some(environment_map) => commands.entity(camera).insert(environment_map),
+            None => commands.entity(camera).remove::<EnvironmentMapLight>(),
+        };
+    }
+
+    if !settings.directional_light {
+        for (entity, ..) in &lights {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let rotation = directional_rotation(&settings);
+    if lights.is_empty() {
+        info!(target: subsystem::UI, "Spawning a directional light");
+        commands.spawn((
+            DirectionalLight {
+                illuminance: settings.directional_illuminance,
+                shadows_enabled: settings.shadows_enabled,
+                ..default()
+            },
+            Transform::from_rotation(rotation),
+            AutoDirectionalLight,
+        ));
+    }
+    for (_, mut light, mut transform) in &mut lights {
+        light.illuminance = settings.directional_illuminance;
+        light.shadows_enabled = settings.shadows_enabled;
+        transform.rotation = rotation;
+    }
+}
+
+/// Gives cameras spawned after the settings were applied the configured environment map.
+fn attach_environment_map(
+    mut commands: Commands,
+    settings: Option<Res<Persistent<LightingSettings>>>,
+    asset_server: Res<AssetServer>,
+    cameras: Query<Entity, Added<Camera3d>>,
+) {
+    let Some(settings) = settings else {
+        return;
+    };
+    for camera in &cameras {
+        if let Some(environment_map) = environment_map_light(&settings, &asset_server) {
+            commands.entity(camera).insert(environment_map);
+        }
+    }
+}
+
+/// Panel to edit the lighting settings at runtime.
+fn lighting_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<LightingSettings>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Lighting")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading("Ambient");
+            let mut ambient_color = edited.ambient_color.to_srgba().to_f32_array_no_alpha();
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                if ui.color_edit_button_rgb(&mut ambient_color).changed() {
+                    edited.ambient_color = Color::srgb_from_array(ambient_color);
+                }
+            });
+            ui.add(
+                egui::Slider::new(&mut edited.ambient_brightness, 0.0..=10_000.0)
+                    .text("Brightness"),
+            );
+
+            ui.separator();
+            ui.heading("Environment map");
+            egui::ComboBox::from_label("HDRI")
+                .selected_text(match edited.environment_map {
+                    EnvironmentMap::None => "None",
+                    EnvironmentMap::Pisa => "Pisa",
+                    EnvironmentMap::Custom { .. } => "Custom",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut edited.environment_map, EnvironmentMap::None, "None");
+                    ui.selectable_value(&mut edited.environment_map, EnvironmentMap::Pisa, "Pisa");
+                    if ui
+                        .selectable_label(
+                            matches!(edited.environment_map, EnvironmentMap::Custom { .. }),
+                            "Custom",
+                        )
+                        .clicked()
+                    {
+                        edited.environment_map = EnvironmentMap::Custom {
+                            diffuse: String::new(),
+                            specular: String::new(),
+                        };
+                    }
+                });
+            if let EnvironmentMap::Custom { diffuse, specular } = &mut edited.environment_map {
+                ui.horizontal(|ui| {
+                    ui.label("Diffuse");
+                    ui.text_edit_singleline(diffuse);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Specular");
+                    ui.text_edit_singleline(specular);
+                });
+            }
+            ui.add(
+                egui::Slider::new(&mut edited.environment_intensity, 0.0..=5_000.0)
+                    .text("Intensity"),
+            );
+
+            ui.separator();
+            ui.heading("Directional light");
+            ui.checkbox(&mut edited.directional_light, "Enabled");
+            ui.checkbox(&mut edited.shadows_enabled, "Shadows");
+            ui.add(
+                egui::Slider::new(&mut edited.directional_illuminance, 0.0..=100_000.0)
+                    .logarithmic(true)
+                    .text("Illuminance (lux)"),
+            );
+            ui.add(egui::Slider::new(&mut edited.directional_yaw, -180.0..=180.0).text("Yaw (°)"));
+            ui.add(egui::Slider::new(&mut edited.directional_pitch, -90.0..=0.0).text("Pitch (°)"));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("lighting", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("lighting", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
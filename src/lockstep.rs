@@ -0,0 +1,289 @@
+//! Lockstep time synchronization with a co-simulator or a HIL rig over UDP.
+//!
+//! The master steps once, sends a *barrier* with its new tick and holds its clock until the
+//! peer acknowledges that tick. The slave holds its clock until it receives a barrier ahead of
+//! it, steps up to the barrier and acknowledges it. Both sides must use the same time step.
+//!
+//! Every datagram is 24 bytes, little endian:
+//!
+//! | Offset | Size | Content                                 |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | Magic `MCPS`                            |
+//! | 4      | 1    | Kind: 1 = barrier, 2 = acknowledgement |
+//! | 5      | 3    | Reserved, zero                          |
+//! | 8      | 8    | Tick (`u64`)                            |
+//! | 16     | 8    | Simulated time, in nanoseconds (`u64`)  |
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::TimestepMode;
+use clap::ValueEnum;
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+};
+
+const MAGIC: &[u8; 4] = b"MCPS";
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5700;
+pub const PACKET_SIZE: usize = 24;
+/// Time after which an unanswered packet is sent again.
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+/// Time after which waiting for the peer is reported.
+const STALL_WARNING: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Role {
+    /// Drives the time: the peer follows the ticks of this simulator.
+    Master,
+    /// Follows the ticks of the peer.
+    Slave,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PacketKind {
+    Barrier = 1,
+    Ack = 2,
+}
+
+/// A synchronization datagram.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub kind: PacketKind,
+    pub tick: u64,
+    pub elapsed: Duration,
+}
+
+impl Packet {
+    pub fn encode(&self) -> [u8; PACKET_SIZE] {
+        let mut bytes = [0; PACKET_SIZE];
+        bytes[0..4].copy_from_slice(MAGIC);
+        bytes[4] = self.kind as u8;
+        bytes[8..16].copy_from_slice(&self.tick.to_le_bytes());
+        bytes[16..24].copy_from_slice(&(self.elapsed.as_nanos() as u64).to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() != PACKET_SIZE || &bytes[0..4] != MAGIC {
+            return None;
+        }
+        let kind = match bytes[4] {
+            1 => PacketKind::Barrier,
+            2 => PacketKind::Ack,
+            _ => return None,
+        };
+        let tick = u64::from_le_bytes(bytes[8..16].try_into().ok()?);
+        let elapsed = u64::from_le_bytes(bytes[16..24].try_into().ok()?);
+        Some(Packet {
+            kind,
+            tick,
+            elapsed: Duration::from_nanos(elapsed),
+        })
+    }
+}
+
+/// Synchronizes the [`SimClock`] with a peer.
+pub struct LockstepPlugin {
+    pub role: Role,
+    /// Local address to listen on.
+    pub bind: SocketAddr,
+    /// Address of the peer. A slave without one answers whoever sent the last barrier.
+    pub peer: Option<SocketAddr>,
+    /// Time step of the physics, in seconds. It must be the same on both sides.
+    pub dt: f32,
+}
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        let link = match Link::open(self.bind, self.peer) {
+            Ok(link) => link,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(
+            target: subsystem::IO,
+            "Lockstep {:?} listening on {}", self.role, self.bind
+        );
+        app.insert_resource(TimestepMode::Fixed {
+            dt: self.dt,
+            substeps: 1,
+        })
+        .insert_resource(Lockstep {
+            role: self.role,
+            link,
+            pending: None,
+            target: 0,
+            acknowledged: 0,
+            waiting_since: None,
+        })
+        .add_systems(PostUpdate, gate.in_set(SimClockSet::Gate))
+        .add_systems(PostUpdate, publish.after(SimClockSet::Advance));
+    }
+}
+
+struct Link {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    last_sent: Option<(Packet, Instant)>,
+}
+
+impl Link {
+    fn open(bind: SocketAddr, peer: Option<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peer,
+            last_sent: None,
+        })
+    }
+
+    fn send(&mut self, packet: Packet) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+        if let Err(error) = self.socket.send_to(&packet.encode(), peer) {
+            warn!(target: subsystem::IO, "Failed to send {packet:?} to {peer}: {error}");
+        }
+        self.last_sent = Some((packet, Instant::now()));
+    }
+
+    /// Sends the last packet again if it's unanswered for too long, in case it was lost.
+    fn resend_if_stale(&mut self) {
+        if let Some((packet, sent)) = self.last_sent {
+            if sent.elapsed() >= RESEND_INTERVAL {
+                self.send(packet);
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        let mut buffer = [0; 64];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => match Packet::decode(&buffer[..size]) {
+                    Some(packet) => {
+                        self.peer.get_or_insert(from);
+                        packets.push(packet);
+                    }
+                    None => debug!(target: subsystem::IO, "Ignoring a datagram from {from}"),
+                },
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    // E.g. the peer isn't listening yet (ICMP port unreachable).
+                    debug!(target: subsystem::IO, "Lockstep receive error: {error}");
+                    break;
+                }
+            }
+        }
+        packets
+    }
+}
+
+/// State of the synchronization.
+#[derive(Resource)]
+pub struct Lockstep {
+    role: Role,
+    link: Link,
+    /// Master: tick sent in a barrier and not acknowledged yet.
+    pending: Option<u64>,
+    /// Slave: tick of the last barrier received.
+    target: u64,
+    /// Slave: last tick acknowledged.
+    acknowledged: u64,
+    waiting_since: Option<Instant>,
+}
+
+impl Lockstep {
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Whether the clock is held waiting for the peer.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting_since.is_some()
+    }
+}
+
+/// Holds the clock until the peer allows the next step.
+fn gate(mut lockstep: ResMut<Lockstep>, mut clock: ResMut<SimClock>) {
+    let lockstep = &mut *lockstep;
+    for packet in lockstep.link.receive() {
+        match (lockstep.role, packet.kind) {
+            (Role::Master, PacketKind::Ack) if lockstep.pending == Some(packet.tick) => {
+                lockstep.pending = None;
+            }
+            (Role::Slave, PacketKind::Barrier) => {
+                lockstep.target = lockstep.target.max(packet.tick);
+                // The acknowledgement was lost: the master sends the barrier again.
+                if packet.tick <= lockstep.acknowledged {
+                    lockstep.link.send(Packet {
+                        kind: PacketKind::Ack,
+                        tick: lockstep.acknowledged,
+                        elapsed: clock.elapsed(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let waiting = match lockstep.role {
+        Role::Master => lockstep.pending.is_some(),
+        Role::Slave => clock.tick() >= lockstep.target,
+    };
+    if !waiting {
+        lockstep.waiting_since = None;
+        return;
+    }
+    clock.hold();
+    if lockstep.role == Role::Master {
+        lockstep.link.resend_if_stale();
+    }
+    let since = *lockstep.waiting_since.get_or_insert_with(Instant::now);
+    if since.elapsed() >= STALL_WARNING {
+        warn!(
+            target: subsystem::IO,
+            "Lockstep waiting for the peer at tick {}",
+            clock.tick()
+        );
+        lockstep.waiting_since = Some(Instant::now());
+    }
+}
+
+/// Tells the peer about the step just taken.
+fn publish(mut lockstep: ResMut<Lockstep>, clock: Res<SimClock>) {
+    match lockstep.role {
+        Role::Master => {
+            if clock.delta() > Duration::ZERO {
+                lockstep.pending = Some(clock.tick());
+                lockstep.link.send(Packet {
+                    kind: PacketKind::Barrier,
+                    tick: clock.tick(),
+                    elapsed: clock.elapsed(),
+                });
+            }
+        }
+        Role::Slave => {
+            if clock.tick() >= lockstep.target && lockstep.acknowledged < clock.tick() {
+                lockstep.acknowledged = clock.tick();
+                lockstep.link.send(Packet {
+                    kind: PacketKind::Ack,
+                    tick: clock.tick(),
+                    elapsed: clock.elapsed(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! This module keeps many instances of the plants, e.g. a composition of dozens of pendulums,
+//! rendering at interactive frame rates.
+//!
+//! The meshes and the materials of the links are shared between the instances: when a link is
+//! spawned with the same vertices or the same material as one before, it takes the handle of the
+//! first, so the renderer draws all of them in a single instanced batch. Each link is also given a
+//! low-detail stand-in, the box bounding its mesh, drawn instead of it beyond `lod_distance`
+//! meters from the camera. The *Level of detail* panel sets the distance, 0 drawing the full
+//! meshes at any distance.
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, view::VisibilityRange},
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    plants::Link,
+};
+
+pub struct LodPlugin;
+
+impl Plugin for LodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SharedAssets>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    lod_panel.run_if(has_ui),
+                    (share_assets, update_ranges).chain(),
+                )
+                    .run_if(resource_exists::<Persistent<LodSettings>>),
+            );
+    }
+}
+
+/// Represents the level-of-detail settings, from the `lod.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct LodSettings {
+    /// Whether the links spawned share their meshes and materials.
+    pub share_assets: bool,
+    /// Distance from the camera past which the links are drawn in low detail, in m, or 0.
+    pub lod_distance: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            share_assets: true,
+            lod_distance: 30.0,
+        }
+    }
+}
+
+/// Key of the vertices of `mesh`: equal for meshes drawing the same.
+pub fn mesh_key(mesh: &Mesh) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mesh.primitive_topology().hash(&mut hasher);
+    for (attribute, values) in mesh.attributes() {
+        attribute.id.hash(&mut hasher);
+        values.get_bytes().hash(&mut hasher);
+    }
+    match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.hash(&mut hasher),
+        Some(Indices::U32(indices)) => indices.hash(&mut hasher),
+        None => {}
+    }
+    hasher.finish()
+}
+
+/// Key of `material`: equal for materials drawing the same.
+pub fn material_key(material: &StandardMaterial) -> u64 {
+    // The debug representation holds every field, the textures by their asset ids.
+    let mut hasher = DefaultHasher::new();
+    format!("{material:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Low-detail stand-in of `mesh`: the box bounding it, or `None` when the mesh isn't more
+/// detailed than the box.
+pub fn proxy(mesh: &Mesh) -> Option<Mesh> {
+    let proxy = Mesh::from(Cuboid::default());
+    if mesh.count_vertices() <= proxy.count_vertices() {
+        return None;
+    }
+    let aabb = mesh.compute_aabb()?;
+    Some(
+        Mesh::from(Cuboid::from_size(Vec3::from(aabb.half_extents) * 2.0))
+            .translated_by(Vec3::from(aabb.center)),
+    )
+}
+
+/// The visibility ranges of the full mesh and of its stand-in, for `distance`.
+pub fn ranges(distance: f32) -> (VisibilityRange, VisibilityRange) {
+    if distance > 0.0 {
+        (
+            VisibilityRange::abrupt(0.0, distance),
+            VisibilityRange::abrupt(distance, f32::MAX),
+        )
+    } else {
+        (
+            VisibilityRange::abrupt(0.0, f32::MAX),
+            VisibilityRange::abrupt(f32::MAX, f32::MAX),
+        )
+    }
+}
+
+/// The meshes and the materials shared between the links, by key, and the stand-ins of the
+/// meshes.
+#[derive(Debug, Default, Resource)]
+pub struct SharedAssets {
+    pub meshes: HashMap<u64, Handle<Mesh>>,
+    pub materials: HashMap<u64, Handle<StandardMaterial>>,
+    pub proxies: HashMap<AssetId<Mesh>, Handle<Mesh>>,
+    /// Links drawn, with a shared mesh or not.
+    pub links: usize,
+}
+
+/// A link drawn in full detail up to the LOD distance.
+#[derive(Component)]
+pub struct LodDetail;
+
+/// The low-detail stand-in of a link, its child.
+#[derive(Component)]
+pub struct LodProxy;
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<LodSettings>("lod", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Gives the links spawned the shared meshes and materials, and their stand-ins.
+fn share_assets(
+    mut commands: Commands,
+    settings: Res<Persistent<LodSettings>>,
+    mut shared: ResMut<SharedAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut links: Query<
+        (
+            Entity,
+            &mut Mesh3d,
+            Option<&mut MeshMaterial3d<StandardMaterial>>,
+        ),
+        (Added<Mesh3d>, With<Link>),
+    >,
+) {
+    let shared = &mut *shared;
+    for (entity, mut mesh, material) in &mut links {
+        shared.links += 1;
+        let Some(vertices) = meshes.get(&mesh.0) else {
+            continue;
+        };
+        if settings.share_assets {
+            let handle = shared
+                .meshes
+                .entry(mesh_key(vertices))
+                .or_insert_with(|| mesh.0.clone());
+            if *handle != mesh.0 {
+                mesh.0 = handle.clone();
+            }
+        }
+        let material = material.map(|mut material| {
+            if let Some(properties) = materials.get(&material.0) {
+                if settings.share_assets {
+                    let handle = shared
+                        .materials
+                        .entry(material_key(properties))
+                        .or_insert_with(|| material.0.clone());
+                    if *handle != material.0 {
+                        material.0 = handle.clone();
+                    }
+                }
+            }
+            material.0.clone()
+        });
+
+        let proxy = match shared.proxies.get(&mesh.id()) {
+            Some(proxy) => proxy.clone(),
+            None => {
+                let Some(proxy) = meshes.get(&mesh.0).and_then(self::proxy) else {
+                    continue;
+                };
+                let proxy = meshes.add(proxy);
+                shared.proxies.insert(mesh.id(), proxy.clone());
+                proxy
+            }
+        };
+        let (detail, far) = ranges(settings.lod_distance);
+        commands
+            .entity(entity)
+            .insert((LodDetail, detail))
+            .with_children(|parent| {
+                let mut stand_in =
+                    parent.spawn((Mesh3d(proxy), Transform::IDENTITY, far, LodProxy));
+                if let Some(material) = material {
+                    stand_in.insert(MeshMaterial3d(material));
+                }
+            });
+    }
+}
+
+/// Moves the switch between the full meshes and their stand-ins when the distance changes.
+fn update_ranges(
+    settings: Res<Persistent<LodSettings>>,
+    mut details: Query<&mut VisibilityRange, (With<LodDetail>, Without<LodProxy>)>,
+    mut proxies: Query<&mut VisibilityRange, With<LodProxy>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let (detail, far) = ranges(settings.lod_distance);
+    for mut range in &mut details {
+        *range = detail.clone();
+    }
+    for mut range in &mut proxies {
+        *range = far.clone();
+    }
+}
+
+/// Panel to set the distance of the stand-ins, and show how many meshes are shared.
+fn lod_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<LodSettings>>,
+    shared: Res<SharedAssets>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Level of detail")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(
+                &mut edited.share_assets,
+                "Share the meshes and the materials of the links",
+            )
+            .on_hover_text("Applies to the links spawned from now on");
+            ui.add(
+                egui::Slider::new(&mut edited.lod_distance, 0.0..=200.0)
+                    .text("Low detail past (m)"),
+            )
+            .on_hover_text("0 draws the full meshes at any distance");
+            ui.label(format!(
+                "{} links drawn from {} meshes and {} materials",
+                shared.links,
+                shared.meshes.len(),
+                shared.materials.len()
+            ));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("lod", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("lod", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
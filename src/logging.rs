@@ -0,0 +1,366 @@
+//! Structured logging for the playground.
+//!
+//! Log records are tagged with the subsystem that emitted them (physics, control, io or ui)
+//! through their target, so levels can be tuned per subsystem from the configuration file or the
+//! command line, and browsed in the log console panel.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::mpsc,
+    time::Duration,
+};
+
+use bevy::{
+    log::{
+        tracing_subscriber::{self, registry::LookupSpan, Layer},
+        BoxedLayer, Level, LogPlugin, DEFAULT_FILTER,
+    },
+    prelude::*,
+    utils::tracing::{self, Subscriber},
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SharedSimTime, SimClock},
+    config_plugin::load_config,
+    error::{Error, ErrorEvent},
+    theme::{to_egui, Theme},
+};
+
+/// Targets identifying each subsystem. Use them as the target of log macros and spans, e.g.
+/// `info!(target: subsystem::PHYSICS, "...")`.
+pub mod subsystem {
+    pub const PHYSICS: &str = "physics";
+    pub const CONTROL: &str = "control";
+    pub const IO: &str = "io";
+    pub const UI: &str = "ui";
+
+    pub const ALL: [&str; 4] = [PHYSICS, CONTROL, IO, UI];
+}
+
+/// Maximum number of records kept by the [`LogConsole`].
+const CONSOLE_CAPACITY: usize = 2_000;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+/// Represents the logging configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct LogSettings {
+    /// Level of everything that isn't part of a subsystem.
+    pub default_level: LogLevel,
+    /// Level of each subsystem, by target.
+    pub subsystems: BTreeMap<String, LogLevel>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            default_level: LogLevel::Info,
+            subsystems: subsystem::ALL
+                .iter()
+                .map(|subsystem| (subsystem.to_string(), LogLevel::Info))
+                .collect(),
+        }
+    }
+}
+
+impl LogSettings {
+    /// Builds the [`EnvFilter`](tracing_subscriber::EnvFilter) directives for these settings,
+    /// followed by the `overrides` given on the command line.
+    pub fn filter(&self, overrides: Option<&str>) -> String {
+        let mut filter = DEFAULT_FILTER.to_string();
+        for (subsystem, level) in &self.subsystems {
+            let _ = write!(filter, ",{subsystem}={}", level.as_str());
+        }
+        if let Some(overrides) = overrides.filter(|overrides| !overrides.is_empty()) {
+            let _ = write!(filter, ",{overrides}");
+        }
+        filter
+    }
+}
+
+/// Loads the logging configuration. It's needed before the application is built, so it isn't
+/// part of the [`ConfigPlugin`](crate::config_plugin::ConfigPlugin). The error, if any, can
+/// only be reported once the application is built.
+pub fn load_settings() -> (Persistent<LogSettings>, Option<Error>) {
+    load_config("log", false)
+}
+
+/// Builds the [`LogPlugin`] for the given settings and command line overrides.
+pub fn log_plugin(settings: &LogSettings, overrides: Option<&str>) -> LogPlugin {
+    LogPlugin {
+        filter: settings.filter(overrides),
+        level: settings.default_level.into(),
+        custom_layer: console_layer,
+    }
+}
+
+/// A log record captured for the [`LogConsole`].
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    /// Simulated time when the record was emitted.
+    pub time: Duration,
+    pub level: LogLevel,
+    pub target: String,
+    /// Subsystem of the record, from its target or from the spans it was emitted in.
+    pub subsystem: Option<&'static str>,
+    pub message: String,
+}
+
+/// The most recent log records.
+#[derive(Default, Resource)]
+pub struct LogConsole {
+    pub records: VecDeque<LogRecord>,
+}
+
+struct LogReceiver(mpsc::Receiver<LogRecord>);
+
+struct ConsoleLayer {
+    sender: mpsc::Sender<LogRecord>,
+    sim_time: SharedSimTime,
+}
+
+fn subsystem_of(name: &str) -> Option<&'static str> {
+    subsystem::ALL
+        .into_iter()
+        .find(|subsystem| name == *subsystem || name.starts_with(&format!("{subsystem}::")))
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut message = String::new();
+        event.record(&mut MessageVisitor(&mut message));
+        let subsystem = subsystem_of(metadata.target()).or_else(|| {
+            ctx.event_scope(event)?
+                .from_root()
+                .find_map(|span| subsystem_of(span.metadata().target()))
+        });
+        // The console may be gone while the application shuts down.
+        let _ = self.sender.send(LogRecord {
+            time: self.sim_time.elapsed(),
+            level: (*metadata.level()).into(),
+            target: metadata.target().to_string(),
+            subsystem,
+            message,
+        });
+    }
+}
+
+struct MessageVisitor<'a>(&'a mut String);
+
+impl tracing::field::Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+fn console_layer(app: &mut App) -> Option<BoxedLayer> {
+    let (sender, receiver) = mpsc::channel();
+    let sim_time = app
+        .init_resource::<SimClock>()
+        .world()
+        .resource::<SimClock>()
+        .shared();
+    app.insert_non_send_resource(LogReceiver(receiver))
+        .init_resource::<LogConsole>()
+        .init_resource::<ConsoleFilter>()
+        .add_systems(
+            Update,
+            (
+                collect_records,
+                log_console_panel.run_if(resource_exists::<Persistent<LogSettings>>),
+            )
+                .chain(),
+        );
+    Some(ConsoleLayer { sender, sim_time }.boxed())
+}
+
+fn collect_records(receiver: NonSend<LogReceiver>, mut console: ResMut<LogConsole>) {
+    for record in receiver.0.try_iter() {
+        if console.records.len() == CONSOLE_CAPACITY {
+            console.records.pop_front();
+        }
+        console.records.push_back(record);
+    }
+}
+
+/// What the log console currently displays.
+#[derive(Resource)]
+struct ConsoleFilter {
+    level: LogLevel,
+    subsystems: BTreeMap<&'static str, bool>,
+    others: bool,
+    text: String,
+}
+
+impl Default for ConsoleFilter {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            subsystems: subsystem::ALL
+                .into_iter()
+                .map(|subsystem| (subsystem, true))
+                .collect(),
+            others: true,
+            text: String::new(),
+        }
+    }
+}
+
+impl ConsoleFilter {
+    fn accepts(&self, record: &LogRecord) -> bool {
+        let subsystem_shown = match record.subsystem {
+            Some(subsystem) => self.subsystems.get(subsystem).copied().unwrap_or(true),
+            None => self.others,
+        };
+        record.level <= self.level
+            && subsystem_shown
+            && (self.text.is_empty()
+                || record.message.contains(&self.text)
+                || record.target.contains(&self.text))
+    }
+}
+
+fn level_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, level: &mut LogLevel) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(level.as_str())
+        .show_ui(ui, |ui| {
+            for candidate in LogLevel::ALL {
+                ui.selectable_value(level, candidate, candidate.as_str());
+            }
+        });
+}
+
+/// Panel listing the captured log records, with filters.
+fn log_console_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut console: ResMut<LogConsole>,
+    mut filter: ResMut<ConsoleFilter>,
+    mut settings: ResMut<Persistent<LogSettings>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let theme = theme.map_or_else(Theme::default, |theme| theme.get().clone());
+    egui::Window::new("Log console")
+        .default_open(false)
+        .default_width(600.0)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Level");
+                level_combo(ui, "console_level", &mut filter.level);
+                ui.label("Search");
+                ui.text_edit_singleline(&mut filter.text);
+                if ui.button("Clear").clicked() {
+                    console.records.clear();
+                }
+            });
+            ui.horizontal(|ui| {
+                for (subsystem, shown) in filter.subsystems.iter_mut() {
+                    ui.checkbox(shown, *subsystem);
+                }
+                ui.checkbox(&mut filter.others, "other");
+            });
+
+            ui.collapsing("Captured levels", |ui| {
+                ui.label("Applied on the next start, unless overridden with --log.");
+                let mut edited = settings.get().clone();
+                egui::Grid::new("log_levels").show(ui, |ui| {
+                    ui.label("default");
+                    level_combo(ui, "default_level", &mut edited.default_level);
+                    ui.end_row();
+                    for (subsystem, level) in edited.subsystems.iter_mut() {
+                        ui.label(subsystem.as_str());
+                        level_combo(ui, subsystem.as_str(), level);
+                        ui.end_row();
+                    }
+                });
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.set(edited) {
+                        commands.send_event(ErrorEvent::from(Error::save("log", error)));
+                    }
+                } else if edited != *settings.get() {
+                    *settings.get_mut() = edited;
+                }
+            });
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for record in console
+                        .records
+                        .iter()
+                        .filter(|record| filter.accepts(record))
+                    {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.monospace(format!("{:>9.3}", record.time.as_secs_f32()));
+                            ui.colored_label(
+                                to_egui(theme.log_level(record.level)),
+                                record.level.as_str(),
+                            );
+                            ui.weak(record.subsystem.unwrap_or(record.target.as_str()));
+                            ui.label(&record.message);
+                        });
+                    }
+                });
+        });
+}
@@ -0,0 +1,242 @@
+//! This module sets up output feedback of the pendulum in one click: the [LQR](crate::lqr)
+//! regulator on the state estimated by the Kalman filter of the [estimation](crate::estimation),
+//! the linear-quadratic-Gaussian (LQG) controller.
+//!
+//! *Set up LQG* in the *LQG* panel linearizes the model from the bodies of the first plant,
+//! enables the regulator and enables the estimation on the same joints. The regulator then
+//! feeds back the estimated angles and velocities rather than differentiating the readings of
+//! the encoders.
+//!
+//! The panel summarizes the controller on the model linearized upright: the poles of the
+//! regulated loop at the sample period of the regulator, and those of the steady-state Kalman
+//! filter, a Luenberger [`Observer`], at the physics step, under the process noise of the
+//! estimation and the noise of the encoders of the first plant, with the standard deviations of
+//! the error of the estimated state it settles to. The gyroscopes aren't part of the summary.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use nalgebra::{DMatrix, DVector, Matrix4};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    control::{self, Observer, Pole},
+    error::{Error, ErrorEvent, Result},
+    estimation::{self, EstimationSettings},
+    headless::DEFAULT_TIME_STEP,
+    logging::subsystem,
+    lqr::{Linearize, LqrSettings},
+    plants::Link,
+    sensors::Encoder,
+};
+
+pub struct LqgPlugin;
+
+impl Plugin for LqgPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lqg>().add_systems(
+            Update,
+            (summarize_lqg, lqg_panel.run_if(has_ui))
+                .chain()
+                .run_if(resource_exists::<Persistent<LqrSettings>>)
+                .run_if(resource_exists::<Persistent<EstimationSettings>>),
+        );
+    }
+}
+
+/// Summary of the output feedback on the model linearized upright.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LqgSummary {
+    /// Sample periods of the regulator and of the observer, in s.
+    pub period: f32,
+    pub step: f32,
+    /// Poles of the regulated loop, and of the error of the estimate.
+    pub regulator: Vec<Pole>,
+    pub observer: Vec<Pole>,
+    pub observer_gain: DMatrix<f64>,
+    /// Steady-state standard deviations of the error of the estimated arm angle and velocity,
+    /// pendulum angle and velocity.
+    pub deviations: [f64; 4],
+}
+
+impl LqgSummary {
+    /// Whether both the regulated loop and the estimate settle.
+    pub fn stable(&self) -> bool {
+        self.regulator
+            .iter()
+            .chain(&self.observer)
+            .all(Pole::stable)
+    }
+}
+
+/// Summarizes the regulator of `lqr` on the state estimated under the noise of `estimation`,
+/// the angles of the joints being read with the `variances` (rad²), physics steps lasting `step`
+/// seconds.
+pub fn summarize(
+    lqr: &LqrSettings,
+    estimation: &EstimationSettings,
+    variances: [f64; 2],
+    step: f32,
+) -> Result<LqgSummary> {
+    let period = lqr.sample_period(step);
+    let regulator = lqr.solve(period)?;
+    let (a, b) = lqr.model();
+    let (a, b) = control::discretize(&a, &b, f64::from(period));
+    let regulator = control::poles(&(&a - &b * &regulator.gain), f64::from(period));
+
+    // The filter estimates the four states of the model from the angles of the joints.
+    let a = DMatrix::from_fn(4, 4, |row, column| lqr.a[row][column]);
+    let b = DMatrix::from_column_slice(4, 1, &lqr.b);
+    let (a, _) = control::discretize(&a, &b, f64::from(step));
+    let c = DMatrix::from_row_slice(2, 4, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    let noise = [estimation.arm_noise, estimation.pendulum_noise].map(f64::from);
+    let process: Matrix4<f64> = estimation::process_noise(noise, f64::from(step));
+    let process = DMatrix::from_column_slice(4, 4, process.as_slice());
+    let measurement = DMatrix::from_diagonal(&DVector::from_column_slice(&variances));
+    let observer =
+        Observer::kalman(&a, &c, &process, &measurement).ok_or_else(|| Error::Config {
+            name: "estimation".to_string(),
+            message: "the Kalman filter doesn't converge: is the model detectable?".to_string(),
+        })?;
+    let deviations = [0, 1, 2, 3].map(|index| observer.corrected[(index, index)].max(0.0).sqrt());
+    Ok(LqgSummary {
+        period,
+        step,
+        regulator,
+        observer: control::poles(&observer.error_dynamics(&a, &c), f64::from(step)),
+        observer_gain: observer.gain,
+        deviations,
+    })
+}
+
+/// The summary of the current settings, or why there's none.
+#[derive(Default, Resource)]
+pub struct Lqg {
+    pub summary: Option<Result<LqgSummary>>,
+}
+
+/// Summarizes the controller whenever its settings or the joints change.
+fn summarize_lqg(
+    lqr: Res<Persistent<LqrSettings>>,
+    estimation: Res<Persistent<EstimationSettings>>,
+    timestep_mode: Option<Res<TimestepMode>>,
+    joints: Query<(&Link, Option<&Encoder>), With<ImpulseJoint>>,
+    added: Query<(), Added<ImpulseJoint>>,
+    mut lqg: ResMut<Lqg>,
+) {
+    if !lqr.is_changed() && !estimation.is_changed() && added.is_empty() {
+        return;
+    }
+    let step = match timestep_mode.as_deref() {
+        Some(TimestepMode::Fixed { dt, .. } | TimestepMode::Interpolated { dt, .. }) => *dt,
+        _ => DEFAULT_TIME_STEP,
+    };
+    // The encoders of the first plant.
+    let variance = |name: &str| {
+        let encoder = joints
+            .iter()
+            .filter(|(link, _)| link.name == name)
+            .min_by(|(a, _), (b, _)| a.plant.cmp(&b.plant))
+            .and_then(|(_, encoder)| encoder);
+        estimation::encoder_variance(encoder)
+    };
+    let variances = [variance(&lqr.motor), variance(&lqr.pendulum)];
+    lqg.summary = Some(summarize(&lqr, &estimation, variances, step));
+}
+
+fn pole_rows(ui: &mut egui::Ui, poles: &[Pole]) {
+    for pole in poles {
+        let text = format!(
+            "{:.4}{:+.4}i: {:.3} Hz, damping {:.3}",
+            pole.eigenvalue.re, pole.eigenvalue.im, pole.frequency, pole.damping
+        );
+        if pole.stable() {
+            ui.monospace(text);
+        } else {
+            ui.colored_label(egui::Color32::RED, egui::RichText::new(text).monospace());
+        }
+    }
+}
+
+/// Panel to set up the output feedback and summarize it.
+fn lqg_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut lqr: ResMut<Persistent<LqrSettings>>,
+    mut estimation: ResMut<Persistent<EstimationSettings>>,
+    lqg: Res<Lqg>,
+) {
+    egui::Window::new("LQG")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Regulator and estimate of the `{}` and `{}` joints",
+                lqr.motor, lqr.pendulum
+            ));
+            let enabled = lqr.enabled && estimation.enabled;
+            let set_up = ui
+                .button("Set up LQG")
+                .on_hover_text(
+                    "Linearize the model from the bodies, enable the regulator and estimate its \
+                     state",
+                )
+                .clicked();
+            if set_up {
+                commands.send_event(Linearize);
+                let lqr = lqr.get_mut();
+                lqr.enabled = true;
+                let estimation = estimation.get_mut();
+                estimation.enabled = true;
+                estimation.motor = lqr.motor.clone();
+                estimation.pendulum = lqr.pendulum.clone();
+                info!(target: subsystem::CONTROL, "LQG set up on {}", lqr.motor);
+            }
+            ui.label(if enabled {
+                "Output feedback: the regulator feeds back the estimated state"
+            } else {
+                "Not set up: the regulator or the estimation is disabled"
+            });
+
+            ui.separator();
+            match &lqg.summary {
+                Some(Ok(summary)) => {
+                    ui.label(format!(
+                        "Regulated loop, sampled every {:.2} ms",
+                        summary.period * 1000.0
+                    ));
+                    pole_rows(ui, &summary.regulator);
+                    ui.label(format!(
+                        "Estimate, corrected every {:.2} ms",
+                        summary.step * 1000.0
+                    ));
+                    pole_rows(ui, &summary.observer);
+                    let [arm, velocity, angle, rate] = summary.deviations;
+                    ui.label(format!(
+                        "Standard deviations of the estimate: arm {arm:.2e} rad, \
+                         {velocity:.2e} rad/s, pendulum {angle:.2e} rad, {rate:.2e} rad/s"
+                    ));
+                    ui.label(format!(
+                        "Observer gain L = {:.3?}",
+                        summary.observer_gain.as_slice()
+                    ));
+                    if !summary.stable() {
+                        ui.colored_label(egui::Color32::RED, "Unstable on the linearized model");
+                    }
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error.to_string());
+                }
+                None => {}
+            }
+
+            ui.separator();
+            if ui.button("Save").clicked() {
+                if let Err(error) = lqr.persist() {
+                    commands.send_event(ErrorEvent::from(Error::save("lqr", error)));
+                }
+                if let Err(error) = estimation.persist() {
+                    commands.send_event(ErrorEvent::from(Error::save("estimation", error)));
+                }
+            }
+        });
+}
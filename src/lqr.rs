@@ -0,0 +1,875 @@
+//! This module balances the pendulum upright with a linear-quadratic regulator ([`Lqr`]).
+//!
+//! The regulator acts on the state `[arm angle, arm velocity, pendulum angle, pendulum
+//! velocity]`, the pendulum angle being measured from upright, around the upright position
+//! where the pendulum is linearized. The only actuator is the motor, a stiff velocity servo:
+//! the input of the model is the acceleration of the arm, which tilts the pivot of the
+//! pendulum, and the regulator integrates the acceleration into the velocity commanded to the
+//! motor for the next physics step. The angles are measured from the Rapier joints after each
+//! step, and the velocities by finite differences, unless the [estimation](crate::estimation)
+//! estimates them too: the regulator then feeds back the estimated state.
+//!
+//! The continuous-time matrices `A` and `B` of the linearized model come from the settings;
+//! their defaults are those of the embedded pendulum, and the panel can recompute them from the
+//! mass properties of the bodies in the scene. The model is discretized at the sample period of
+//! the regulator, the physics time step unless it samples at a lower rate, and the Riccati
+//! equation solved at startup, and again whenever the settings change. The [`LqrDesign`] picks
+//! the form of the regulator: the servo form integrates the arm angle into a fifth state to
+//! hold the arm without a steady offset, the cost can weigh outputs `y = C x` rather than the
+//! state, and the gain can come from the continuous-time Riccati equation instead, ignoring the
+//! sampling.
+//!
+//! The regulator only catches the pendulum within its capture angle of upright, e.g. after
+//! swinging it up by hand, and lets it go beyond; the arm is held where it caught the pendulum.
+//! With a [`ModeSwitch`] on the motor joint, it also waits for the swing-up to hand over.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::controller_definition::{self, ControllerDefinition};
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{self, Lqr},
+    error::{Error, ErrorEvent, Result},
+    estimation::{self, EstimationSet, JointEstimate},
+    headless::DEFAULT_TIME_STEP,
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link, MOTOR_FACTOR},
+    sensors::{ControlSet, Encoder, SensorSet},
+    swing_up::{Mode, ModeSwitch},
+    telemetry::Telemetry,
+};
+
+/// Angle of the pendulum from upright, in rad.
+pub const LQR_ANGLE: &str = "lqr/angle";
+/// Acceleration of the arm commanded by the regulator, in rad/s².
+pub const LQR_ACCELERATION: &str = "lqr/acceleration";
+
+pub struct LqrPlugin;
+
+impl Plugin for LqrPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LqrGain>()
+            .add_event::<Linearize>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (solve_gain, configure_controllers)
+                    .run_if(resource_exists_and_changed::<Persistent<LqrSettings>>),
+            )
+            .add_systems(
+                Update,
+                linearize.run_if(resource_exists::<Persistent<LqrSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                lqr_panel
+                    .run_if(resource_exists::<Persistent<LqrSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the regulators.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct LqrSettings {
+    pub enabled: bool,
+    /// Names of the links whose joints are the motor and the pivot of the pendulum, in each
+    /// plant.
+    pub motor: String,
+    pub pendulum: String,
+    /// Continuous-time model `x' = A x + B u` linearized around upright, by rows.
+    pub a: [[f64; 4]; 4],
+    pub b: [f64; 4],
+    /// Diagonal of the weight of the state, and weight of the input.
+    pub q: [f64; 4],
+    pub r: f64,
+    /// Largest angle from upright at which the pendulum is caught, in rad.
+    pub capture: f32,
+    /// Largest acceleration of the arm commanded, in rad/s².
+    pub limit: f32,
+    pub design: LqrDesign,
+    /// Rate the regulator samples at, holding its acceleration in between, in Hz, or `None`
+    /// for every physics step.
+    pub sample_rate: Option<f32>,
+}
+
+impl Default for LqrSettings {
+    fn default() -> Self {
+        // The embedded pendulum: its pivot and rod, 1 kg each, swing 4 m from the motor axis,
+        // with their center of mass 1 m from the pivot and 4.93 kg m² of inertia about it.
+        let (a, b) = pendulum_model(2.0, 4.93, 4.0, 9.81);
+        Self {
+            enabled: false,
+            motor: "motor".to_string(),
+            pendulum: "pivot".to_string(),
+            a,
+            b,
+            q: [1.0, 0.1, 10.0, 1.0],
+            r: 1.0,
+            capture: 0.3,
+            limit: 50.0,
+            design: LqrDesign::default(),
+            sample_rate: None,
+        }
+    }
+}
+
+/// Form of the regulator beyond the plain state feedback.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LqrDesign {
+    /// Weight of the integral of the arm angle since the pendulum was caught, in the servo
+    /// form, or `None` for the plain regulator.
+    pub integral: Option<f64>,
+    /// Outputs whose squares the cost weighs instead of the state, or none to weigh the state
+    /// by `q`.
+    pub outputs: Vec<WeightedOutput>,
+    pub riccati: Riccati,
+}
+
+/// An output `y = C x` of the state weighed in the cost.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WeightedOutput {
+    /// Row of `C`, on the arm angle and velocity, pendulum angle and velocity.
+    pub c: [f64; 4],
+    pub weight: f64,
+}
+
+impl WeightedOutput {
+    /// The angles of the arm and of the pendulum, weighed as by the default `q`.
+    pub fn angles() -> Vec<Self> {
+        vec![
+            Self {
+                c: [1.0, 0.0, 0.0, 0.0],
+                weight: 1.0,
+            },
+            Self {
+                c: [0.0, 0.0, 1.0, 0.0],
+                weight: 10.0,
+            },
+        ]
+    }
+}
+
+/// Riccati equation the gain of the regulator solves.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Riccati {
+    /// Discrete-time, of the model discretized at the sample period.
+    #[default]
+    Discrete,
+    /// Continuous-time, ignoring the sampling: only right at high rates.
+    Continuous,
+}
+
+impl Riccati {
+    pub const ALL: [Self; 2] = [Self::Discrete, Self::Continuous];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Discrete => "Discrete, at the sample period",
+            Self::Continuous => "Continuous, ignoring the sampling",
+        }
+    }
+}
+
+impl LqrSettings {
+    /// Continuous-time model the regulator acts on, with the integral of the arm angle in the
+    /// servo form.
+    pub fn model(&self) -> (DMatrix<f64>, DMatrix<f64>) {
+        let mut a = DMatrix::from_fn(4, 4, |row, column| self.a[row][column]);
+        let mut b = DMatrix::from_column_slice(4, 1, &self.b);
+        if self.design.integral.is_some() {
+            a = a.insert_row(4, 0.0).insert_column(4, 0.0);
+            a[(4, 0)] = 1.0;
+            b = b.insert_row(4, 0.0);
+        }
+        (a, b)
+    }
+
+    /// Regulator of the model sampled every `dt` seconds.
+    pub fn solve(&self, dt: f32) -> Result<Lqr> {
+        let (a, b) = self.model();
+        let r = DMatrix::from_element(1, 1, self.r);
+        let invalid = |message: &str| Error::Config {
+            name: "lqr".to_string(),
+            message: message.to_string(),
+        };
+        let outputs = &self.design.outputs;
+        if self.q.iter().any(|weight| *weight < 0.0)
+            || outputs.iter().any(|output| output.weight < 0.0)
+            || self.design.integral.is_some_and(|weight| weight < 0.0)
+            || self.r <= 0.0
+        {
+            return Err(invalid(
+                "the weights of the state must be positive, and of the input strictly",
+            ));
+        }
+        let mut q = if outputs.is_empty() {
+            DMatrix::from_diagonal(&DVector::from_column_slice(&self.q))
+        } else {
+            outputs.iter().fold(DMatrix::zeros(4, 4), |q, output| {
+                let c = DMatrix::from_row_slice(1, 4, &output.c);
+                q + c.transpose() * c * output.weight
+            })
+        };
+        if let Some(weight) = self.design.integral {
+            // The integral of the arm angle, a fifth state.
+            q = q.insert_row(4, 0.0).insert_column(4, 0.0);
+            q[(4, 4)] = weight;
+        }
+        let lqr = match self.design.riccati {
+            Riccati::Discrete => {
+                let (a, b) = control::discretize(&a, &b, f64::from(dt));
+                Lqr::new(&a, &b, &q, &r)
+            }
+            Riccati::Continuous => Lqr::continuous(&a, &b, &q, &r),
+        };
+        lqr.ok_or_else(|| {
+            invalid("the Riccati equation has no solution: is the model stabilizable?")
+        })
+    }
+
+    /// Sample period, in s, or `default` without a sample rate.
+    pub fn sample_period(&self, default: f32) -> f32 {
+        match self.sample_rate {
+            Some(rate) if rate > 0.0 => 1.0 / rate,
+            _ => default,
+        }
+    }
+}
+
+/// Model of a rotary pendulum driven by the acceleration of its arm, linearized upright, from
+/// the product of its mass and the distance of its center of mass to the pivot, in kg m, its
+/// inertia about the pivot, in kg m², the distance of the pivot to the motor axis, in m, and
+/// the gravity, in m/s².
+///
+/// The angles are those of the joints: accelerating the motor joint tilts the pendulum back.
+pub fn pendulum_model(
+    mass_length: f64,
+    inertia: f64,
+    radius: f64,
+    gravity: f64,
+) -> ([[f64; 4]; 4], [f64; 4]) {
+    let a = [
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+        [0.0, 0.0, mass_length * gravity / inertia, 0.0],
+    ];
+    let b = [0.0, 1.0, 0.0, -mass_length * radius / inertia];
+    (a, b)
+}
+
+/// The regulator of the current settings, if they're valid.
+#[derive(Default, Resource)]
+pub struct LqrGain(pub Option<Lqr>);
+
+/// Request to recompute the model from the bodies of the first plant.
+#[derive(Event)]
+pub struct Linearize;
+
+/// A regulator on the motor joint of its entity, balancing the pendulum on the joint of
+/// `pendulum`.
+#[derive(Clone, Component, Debug, Deserialize, Serialize)]
+pub struct LqrController {
+    pub pendulum: Entity,
+    /// Telemetry channels of the angle from upright and of the acceleration commanded.
+    pub angle: String,
+    pub acceleration: String,
+    /// Last angles of the motor and pendulum joints, within a turn.
+    previous: Option<[f32; 2]>,
+    /// Angle of the arm since the pendulum was caught, its integral, and its velocity
+    /// commanded.
+    arm: f32,
+    integral: f32,
+    velocity: f32,
+    /// Acceleration commanded at the last sample, and the time since.
+    held: f32,
+    elapsed: f32,
+    engaged: bool,
+}
+
+impl LqrController {
+    /// A regulator with the signals of a plant.
+    pub fn new(pendulum: Entity, plant: &str) -> Self {
+        Self {
+            pendulum,
+            angle: plants::namespaced(plant, LQR_ANGLE),
+            acceleration: plants::namespaced(plant, LQR_ACCELERATION),
+            previous: None,
+            arm: 0.0,
+            integral: 0.0,
+            velocity: 0.0,
+            held: 0.0,
+            elapsed: 0.0,
+            engaged: false,
+        }
+    }
+
+    /// Whether the pendulum is within the capture angle and balanced.
+    pub fn engaged(&self) -> bool {
+        self.engaged
+    }
+
+    /// Takes the state of `saved`, keeping its own pendulum and signals, or starts over without
+    /// it.
+    pub fn restore(&mut self, saved: Option<&Self>) {
+        self.previous = saved.and_then(|saved| saved.previous);
+        self.arm = saved.map_or(0.0, |saved| saved.arm);
+        self.integral = saved.map_or(0.0, |saved| saved.integral);
+        self.velocity = saved.map_or(0.0, |saved| saved.velocity);
+        self.held = saved.map_or(0.0, |saved| saved.held);
+        self.elapsed = saved.map_or(0.0, |saved| saved.elapsed);
+        self.engaged = saved.is_some_and(|saved| saved.engaged);
+    }
+
+    /// Measures the angles of the joints without balancing, while another controller drives
+    /// the motor.
+    pub fn observe(&mut self, angles: [f32; 2]) {
+        self.previous = Some(angles);
+        self.elapsed = 0.0;
+        self.engaged = false;
+    }
+
+    /// Acceleration of the arm and velocity to command for the next `dt` seconds, from the
+    /// angles of the motor and pendulum joints, while the pendulum is caught. Between the
+    /// samples of the regulator, the acceleration is held.
+    pub fn update(
+        &mut self,
+        lqr: &Lqr,
+        settings: &LqrSettings,
+        angles: [f32; 2],
+        dt: f32,
+    ) -> Option<[f32; 2]> {
+        self.update_with(lqr, settings, angles, None, dt)
+    }
+
+    /// As [`update`](Self::update), with the velocities of the joints estimated rather than
+    /// differentiated from their angles.
+    pub fn update_with(
+        &mut self,
+        lqr: &Lqr,
+        settings: &LqrSettings,
+        [motor, pendulum]: [f32; 2],
+        velocities: Option<[f32; 2]>,
+        dt: f32,
+    ) -> Option<[f32; 2]> {
+        self.elapsed += dt;
+        if self.engaged && self.elapsed < settings.sample_period(dt) - 0.5 * dt {
+            self.velocity += self.held * dt;
+            return Some([self.held, self.velocity]);
+        }
+        // The velocities are measured over the time since the last sample.
+        let period = std::mem::take(&mut self.elapsed);
+        let Some([last_motor, last_pendulum]) = self.previous.replace([motor, pendulum]) else {
+            return None;
+        };
+        let step = wrap(motor - last_motor);
+        let angle = wrap(pendulum - PI);
+        if angle.abs() > settings.capture {
+            self.engaged = false;
+            return None;
+        }
+        let [arm_velocity, pendulum_velocity] =
+            velocities.unwrap_or([step / period, wrap(pendulum - last_pendulum) / period]);
+        if !self.engaged {
+            self.engaged = true;
+            self.arm = 0.0;
+            self.integral = 0.0;
+            self.velocity = arm_velocity;
+        } else {
+            self.arm += step;
+        }
+        self.integral += self.arm * period;
+        let mut state = vec![
+            f64::from(self.arm),
+            f64::from(arm_velocity),
+            f64::from(angle),
+            f64::from(pendulum_velocity),
+        ];
+        if settings.design.integral.is_some() {
+            state.push(f64::from(self.integral));
+        }
+        let command = lqr.command(&DVector::from_vec(state))[0] as f32;
+        let acceleration = command.clamp(-settings.limit, settings.limit);
+        self.held = acceleration;
+        self.velocity += acceleration * dt;
+        Some([acceleration, self.velocity])
+    }
+}
+
+/// Angle within `(-π, π]`.
+fn wrap(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<LqrSettings>("lqr", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Solves the regulator at its sample period.
+fn solve_gain(
+    mut commands: Commands,
+    settings: Res<Persistent<LqrSettings>>,
+    timestep_mode: Option<Res<TimestepMode>>,
+    mut gain: ResMut<LqrGain>,
+) {
+    let dt = match timestep_mode.as_deref() {
+        Some(TimestepMode::Fixed { dt, .. } | TimestepMode::Interpolated { dt, .. }) => *dt,
+        _ => DEFAULT_TIME_STEP,
+    };
+    match settings.solve(settings.sample_period(dt)) {
+        Ok(lqr) => {
+            debug!(target: subsystem::CONTROL, "LQR gain {:?}", lqr.gain.as_slice());
+            gain.0 = Some(lqr);
+        }
+        Err(error) => {
+            gain.0 = None;
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Adds or removes the regulators on the motor joints of the plants with a pendulum joint.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<LqrSettings>>,
+    joints: Query<(Entity, &Link, Option<&LqrController>), With<ImpulseJoint>>,
+) {
+    for (entity, link, controller) in &joints {
+        let pendulum = joints
+            .iter()
+            .find(|(_, other, _)| other.plant == link.plant && other.name == settings.pendulum)
+            .map(|(pendulum, _, _)| pendulum);
+        let controlled = settings.enabled && link.name == settings.motor;
+        match (controller, pendulum) {
+            (Some(_), _) if controlled => {}
+            (Some(_), _) => {
+                commands.entity(entity).remove::<LqrController>();
+                info!(target: subsystem::CONTROL, "Regulator of {} removed", link.path());
+            }
+            (None, Some(pendulum)) if controlled => {
+                commands
+                    .entity(entity)
+                    .insert(LqrController::new(pendulum, &link.plant));
+                info!(target: subsystem::CONTROL, "Regulator of {} added", link.path());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step and commands the motors for the next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    clock: Res<SimClock>,
+    settings: Option<Res<Persistent<LqrSettings>>>,
+    gain: Res<LqrGain>,
+    mut controllers: Query<(
+        Entity,
+        &mut LqrController,
+        &mut ImpulseJoint,
+        Option<&ModeSwitch>,
+    )>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_lqr").entered();
+    let (dt, Some(settings), Some(lqr), Ok(context)) = (
+        clock.delta_secs(),
+        settings,
+        gain.0.as_ref(),
+        contexts.get_single(),
+    ) else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    for (entity, mut controller, mut joint, switch) in &mut controllers {
+        let motor = estimation::joint_angle(context, &encoders, &latencies, &estimates, entity);
+        let pendulum = estimation::joint_angle(
+            context,
+            &encoders,
+            &latencies,
+            &estimates,
+            controller.pendulum,
+        );
+        let angles = motor.zip(pendulum);
+        let Some([motor, pendulum]) = angles.map(<[f32; 2]>::from) else {
+            continue;
+        };
+        // Wait for the swing-up to hand over.
+        if switch.is_some_and(|switch| switch.mode() != Mode::Balance) {
+            controller.observe([motor, pendulum]);
+            continue;
+        }
+        // With the state estimated, the regulator feeds back the estimated velocities.
+        let velocities = estimates
+            .get(entity)
+            .ok()
+            .zip(estimates.get(controller.pendulum).ok())
+            .map(|(motor, pendulum)| [motor.velocity, pendulum.velocity]);
+        let engaged = controller.engaged();
+        let command = controller.update_with(lqr, &settings, [motor, pendulum], velocities, dt);
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&controller.angle, clock.elapsed_secs(), wrap(pendulum - PI));
+        }
+        let velocity = match command {
+            Some([acceleration, velocity]) => {
+                if let Some(telemetry) = telemetry.as_mut() {
+                    telemetry.record(&controller.acceleration, clock.elapsed_secs(), acceleration);
+                }
+                velocity
+            }
+            // Stop the arm once the pendulum falls out of reach.
+            None if engaged => 0.0,
+            None => continue,
+        };
+        if engaged != controller.engaged() {
+            let state = if engaged { "lost" } else { "caught" };
+            info!(target: subsystem::CONTROL, "Pendulum {state} at {:.2} s", clock.elapsed_secs());
+        }
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Recomputes the model from the mass properties of the bodies swinging on the pendulum joint
+/// of the first plant, in their current pose.
+fn linearize(
+    mut commands: Commands,
+    mut requests: EventReader<Linearize>,
+    mut settings: ResMut<Persistent<LqrSettings>>,
+    bodies: Query<(Entity, &Link, &GlobalTransform, Option<&ReadMassProperties>)>,
+    joints: Query<&ImpulseJoint>,
+    configurations: Query<&RapierConfiguration>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let find = |name: &str| {
+        bodies
+            .iter()
+            .filter(|(_, link, _, _)| link.name == name)
+            .min_by(|(_, a, _, _), (_, b, _, _)| a.plant.cmp(&b.plant))
+    };
+    let (Some(motor), Some(pivot)) = (find(&settings.motor), find(&settings.pendulum)) else {
+        commands.send_event(ErrorEvent::from(Error::Model(format!(
+            "no `{}` and `{}` links to linearize",
+            settings.motor, settings.pendulum
+        ))));
+        return;
+    };
+    // The pivot, and the bodies fixed to it.
+    let mut swinging = vec![pivot.0];
+    let mut grown = true;
+    while grown {
+        grown = false;
+        for (entity, ..) in &bodies {
+            let fixed = joints.get(entity).is_ok_and(|joint| {
+                swinging.contains(&joint.parent)
+                    && joint.data.as_ref().locked_axes() == JointAxesMask::LOCKED_FIXED_AXES
+            });
+            if fixed && !swinging.contains(&entity) {
+                swinging.push(entity);
+                grown = true;
+            }
+        }
+    }
+
+    // The anchor and axis of a joint in the world, from its parent.
+    let frame = |entity: Entity| {
+        let joint = joints.get(entity).ok()?;
+        let (_, _, parent, _) = bodies.get(joint.parent).ok()?;
+        let data = joint.data.as_ref();
+        Some((
+            parent.transform_point(data.local_anchor1()),
+            parent.rotation() * data.local_axis1().normalize(),
+        ))
+    };
+    let (Some((center, axis)), Some((pivot_anchor, pivot_axis))) = (frame(motor.0), frame(pivot.0))
+    else {
+        return;
+    };
+    let across = |offset: Vec3, axis: Vec3| offset - offset.dot(axis) * axis;
+    let (mut moment, mut inertia) = (Vec3::ZERO, 0.0);
+    for (_, _, transform, properties) in swinging
+        .iter()
+        .filter_map(|entity| bodies.get(*entity).ok())
+    {
+        let Some(properties) = properties.map(|properties| properties.get()) else {
+            continue;
+        };
+        let rotation = transform.rotation() * properties.principal_inertia_local_frame;
+        let offset = across(
+            transform.transform_point(properties.local_center_of_mass) - pivot_anchor,
+            pivot_axis,
+        );
+        moment += properties.mass * offset;
+        inertia += properties.mass * offset.length_squared()
+            + (0..3)
+                .map(|k| {
+                    let principal = rotation * Vec3::AXES[k];
+                    properties.principal_inertia[k] * principal.dot(pivot_axis).powi(2)
+                })
+                .sum::<f32>();
+    }
+    let gravity = configurations
+        .iter()
+        .next()
+        .map_or(9.81, |configuration| configuration.gravity.length());
+    if inertia <= 0.0 {
+        commands.send_event(ErrorEvent::from(Error::Model(
+            "the pendulum has no mass properties".to_string(),
+        )));
+        return;
+    }
+    let radius = across(pivot_anchor - center, axis).length();
+    let (a, b) = pendulum_model(
+        f64::from(moment.length()),
+        f64::from(inertia),
+        f64::from(radius),
+        f64::from(gravity),
+    );
+    info!(
+        target: subsystem::CONTROL,
+        "Pendulum linearized: {:.3} kg m, {:.3} kg m², {:.3} m from the motor axis",
+        moment.length(),
+        inertia,
+        radius
+    );
+    let settings = settings.get_mut();
+    settings.a = a;
+    settings.b = b;
+}
+
+/// Panel to tune the weights of the regulators and edit their model.
+fn lqr_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<LqrSettings>>,
+    gain: Res<LqrGain>,
+    controllers: Query<&LqrController>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("LQR controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Balance the pendulum upright");
+            ui.label("Model, linearized upright");
+            egui::Grid::new("lqr_model").show(ui, |ui| {
+                for (row, b) in edited.a.iter_mut().zip(&mut edited.b) {
+                    ui.label("A:");
+                    for value in row {
+                        ui.add(egui::DragValue::new(value).speed(0.01));
+                    }
+                    ui.label("B:");
+                    ui.add(egui::DragValue::new(b).speed(0.01));
+                    ui.end_row();
+                }
+            });
+            if ui.button("Linearize from the bodies").clicked() {
+                commands.send_event(Linearize);
+            }
+
+            ui.label("Weights of the arm angle and velocity, pendulum angle and velocity");
+            ui.horizontal(|ui| {
+                for weight in &mut edited.q {
+                    ui.add(egui::DragValue::new(weight).range(0.0..=1000.0).speed(0.1));
+                }
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.r)
+                    .range(0.001..=1000.0)
+                    .speed(0.01)
+                    .prefix("Weight of the input: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.capture)
+                    .range(0.0..=PI)
+                    .speed(0.01)
+                    .prefix("Capture angle: ")
+                    .suffix(" rad"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Acceleration limit: ")
+                    .suffix(" rad/s²"),
+            );
+
+            ui.separator();
+            let mut servo = edited.design.integral.is_some();
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut servo, "Integrate the arm angle (servo form)");
+                let weight = edited.design.integral.get_or_insert(1.0);
+                ui.add_enabled(
+                    servo,
+                    egui::DragValue::new(weight)
+                        .range(0.0..=1000.0)
+                        .speed(0.1)
+                        .prefix("Weight: "),
+                );
+            });
+            if !servo {
+                edited.design.integral = None;
+            }
+            let mut weigh_outputs = !edited.design.outputs.is_empty();
+            ui.checkbox(
+                &mut weigh_outputs,
+                "Weigh outputs y = C x instead of the state",
+            );
+            if !weigh_outputs {
+                edited.design.outputs.clear();
+            } else if edited.design.outputs.is_empty() {
+                edited.design.outputs = WeightedOutput::angles();
+            }
+            let mut removed = None;
+            egui::Grid::new("lqr_outputs").show(ui, |ui| {
+                for (index, output) in edited.design.outputs.iter_mut().enumerate() {
+                    ui.label("C:");
+                    for value in &mut output.c {
+                        ui.add(egui::DragValue::new(value).speed(0.01));
+                    }
+                    ui.add(
+                        egui::DragValue::new(&mut output.weight)
+                            .range(0.0..=1000.0)
+                            .speed(0.1)
+                            .prefix("Weight: "),
+                    );
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = removed {
+                edited.design.outputs.remove(index);
+            }
+            if weigh_outputs && ui.button("Add an output").clicked() {
+                edited.design.outputs.push(WeightedOutput {
+                    c: [0.0; 4],
+                    weight: 1.0,
+                });
+            }
+            egui::ComboBox::from_label("Riccati equation")
+                .selected_text(edited.design.riccati.name())
+                .show_ui(ui, |ui| {
+                    for riccati in Riccati::ALL {
+                        ui.selectable_value(&mut edited.design.riccati, riccati, riccati.name());
+                    }
+                });
+            let mut sampled = edited.sample_rate.is_some();
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut sampled, "Sample at");
+                let rate = edited.sample_rate.get_or_insert(100.0);
+                ui.add_enabled(
+                    sampled,
+                    egui::DragValue::new(rate)
+                        .range(1.0..=10000.0)
+                        .speed(1.0)
+                        .suffix(" Hz"),
+                );
+            });
+            if !sampled {
+                edited.sample_rate = None;
+            }
+            match &gain.0 {
+                Some(lqr) => ui.label(format!("K = {:.3?}", lqr.gain.as_slice())),
+                None => ui.colored_label(egui::Color32::RED, "No stabilizing gain"),
+            };
+
+            ui.separator();
+            for controller in &controllers {
+                let latest = |channel: &str| {
+                    telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(channel))
+                        .unwrap_or_default()
+                };
+                let state = if controller.engaged() {
+                    "balanced"
+                } else {
+                    "out of reach"
+                };
+                ui.label(format!(
+                    "{}: {state}, {:.3} rad from upright, {:.2} rad/s²",
+                    controller.angle,
+                    latest(&controller.angle),
+                    latest(&controller.acceleration)
+                ));
+            }
+
+            ui.separator();
+            #[cfg(not(target_arch = "wasm32"))]
+            match controller_definition::definition_buttons(
+                ui,
+                &ControllerDefinition::from(&edited),
+            ) {
+                Some(Ok(definition)) => match LqrSettings::try_from(&definition) {
+                    Ok(imported) => {
+                        edited = LqrSettings {
+                            enabled: edited.enabled,
+                            ..imported
+                        }
+                    }
+                    Err(error) => {
+                        commands.send_event(ErrorEvent::from(error));
+                    }
+                },
+                Some(Err(error)) => {
+                    commands.send_event(ErrorEvent::from(error));
+                }
+                None => {}
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("lqr", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("lqr", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -12,31 +12,198 @@ use bevy::{
 };
 
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
 use bevy_rapier3d::prelude::*;
+
+use clap::Parser;
+#[cfg(feature = "blender-model")]
+use digital_twin_playground::colliders::CollidersPlugin;
+#[cfg(feature = "blender-model")]
+use digital_twin_playground::joint_builder::JointBuilderPlugin;
+#[cfg(feature = "blender-model")]
+use digital_twin_playground::lighting_plugin::AutoDirectionalLight;
 #[cfg(feature = "embedded-model")]
-mod embedded_model;
+use digital_twin_playground::model_library::ModelLibraryPlugin;
+#[cfg(feature = "blender-model")]
+use digital_twin_playground::scene_diff::SceneDiffPlugin;
 #[cfg(feature = "blender-model")]
-mod scene_viewer_plugin;
+use digital_twin_playground::scene_viewer_plugin::{SceneHandle, SceneViewerPlugin};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{net::SocketAddr, path::Path};
 
-mod config_plugin;
-mod grid_plugin;
+#[cfg(target_os = "linux")]
+use digital_twin_playground::{
+    canopen::{self, CanOpenPlugin},
+    virtual_joystick::{JoystickMap, VirtualJoystickPlugin},
+};
 
-use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
-#[cfg(feature = "embedded-model")]
-use embedded_model::EmbeddedModelPlugin;
-use grid_plugin::GridPlugin;
-#[cfg(feature = "blender-model")]
-use scene_viewer_plugin::{SceneHandle, SceneViewerPlugin};
+#[cfg(all(feature = "opcua", not(target_arch = "wasm32")))]
+use digital_twin_playground::opcua::{OpcUaPlugin, OpcUaSettings};
+use digital_twin_playground::{
+    accessibility_plugin::UiAccessibilityPlugin,
+    actuators::ActuatorsPlugin,
+    anomalies::AnomaliesPlugin,
+    audio_plugin::AudioCuesPlugin,
+    autotune::AutotunePlugin,
+    camera_sequence::CameraSequencePlugin,
+    camera_views::CameraViewsPlugin,
+    cli::Cli,
+    clock::SimClockPlugin,
+    config_plugin::{self, ConfigPlugin},
+    contacts::ContactsPlugin,
+    custom_controllers::CustomControllersPlugin,
+    disturbances::DisturbancesPlugin,
+    dual_loop_controller::DualLoopControllerPlugin,
+    error::{ErrorEvent, ErrorPlugin},
+    estimation::EstimationPlugin,
+    extensions::ExtensionsPlugin,
+    fixtures::FixturesPlugin,
+    frames::FramesPlugin,
+    friction::FrictionPlugin,
+    gamepad_mapping::GamepadMappingPlugin,
+    gantry_controller::GantryControllerPlugin,
+    grid_plugin::GridPlugin,
+    haptics_plugin::HapticsPlugin,
+    hmi::HmiPlugin,
+    input_bindings::InputBindingsPlugin,
+    interaction::InteractionPlugin,
+    jog::JogPlugin,
+    joint_authoring::JointAuthoringPlugin,
+    joint_limits::JointLimitsPlugin,
+    kinematics::KinematicsPlugin,
+    latency::LatencyPlugin,
+    lighting_plugin::LightingPlugin,
+    lod::LodPlugin,
+    logging,
+    lqg::LqgPlugin,
+    lqr::LqrPlugin,
+    mass_overrides::MassOverridesPlugin,
+    monitors::MonitorsPlugin,
+    mpc::MpcPlugin,
+    overlays::OverlaysPlugin,
+    physics_parameters::PhysicsParametersPlugin,
+    pid_controller::PidControllerPlugin,
+    plants,
+    platform::PlatformPlugin,
+    plots::PlotsPlugin,
+    proximity::ProximityPlugin,
+    selection::SelectionPlugin,
+    self_collision::SelfCollisionPlugin,
+    sensorless::SensorlessPlugin,
+    sensors::SensorsPlugin,
+    setpoint_command::SetpointCommandPlugin,
+    state_machines::StateMachinesPlugin,
+    stereo::StereoPlugin,
+    supervisor::SupervisorPlugin,
+    swing_up::SwingUpPlugin,
+    teach::TeachPlugin,
+    telemetry::TelemetryPlugin,
+    theme::ThemePlugin,
+    trajectory::TrajectoryPlugin,
+    transmissions::TransmissionsPlugin,
+    transport::TransportPlugin,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use digital_twin_playground::{
+    analysis::{self, Analysis},
+    autosave::AutosavePlugin,
+    batch::{self, BatchRun},
+    bench::{self, BenchSettings},
+    calibration::{self, CalibrationPlugin, CalibrationSettings},
+    capture::CapturePlugin,
+    cli::Command,
+    codegen,
+    comparison::{self, ComparisonPlugin},
+    composition::{Composition, CompositionPlugin},
+    control::Shaper,
+    controller_definition::{self, ControllerDefinition},
+    dashboard::DashboardPlugin,
+    determinism::{self, Comparison, DeterminismReport},
+    estimation::EstimationSettings,
+    estimator_replay::{self, EstimatorReplayPlugin, EstimatorReplaySettings},
+    experiments::ExperimentsPlugin,
+    fault_detection::{self, FaultDetectionSettings},
+    fieldbus::FieldbusPlugin,
+    fixed_step::{self, FixedStepPlugin},
+    frequency_response::FrequencyResponsePlugin,
+    fuzzing::{self, FailureReport, FuzzSettings},
+    governor::GovernorPlugin,
+    hardware_log::{self, HardwareLogPlugin, HardwareLogSettings},
+    headless::DEFAULT_TIME_STEP,
+    ident::{self, IdentSettings},
+    identification::{self, Experiment, LinearModel},
+    kinematic_playback::KinematicPlaybackPlugin,
+    lockstep::{self, LockstepPlugin},
+    lqr::LqrSettings,
+    migration::{self, MigrationPlugin},
+    modbus::{ModbusMap, ModbusPlugin},
+    network::NetworkSettings,
+    pid_controller::PidSettings,
+    placement::{self, Placement},
+    plotjuggler::{PlotJugglerPlugin, PlotJugglerSettings},
+    precision,
+    recording::RecordingPlugin,
+    remote::{RemoteClientPlugin, RemoteHostPlugin},
+    replay::ReplayPlugin,
+    rest_api::RestApiPlugin,
+    roa::{self, RoaSettings},
+    run_diff::{self, Alignment, RunDiff, RunDiffPlugin},
+    scenario::{ScenarioDefinition, ScenarioPlugin},
+    script_test::ScriptTest,
+    scripted_controller::ScriptedControllerPlugin,
+    serial_bridge::{SerialBridgePlugin, SerialBridgeSettings},
+    shaping::{self, ShapingExperiment},
+    sim_thread::SimThreadPlugin,
+    sizing::{self, SizingSettings},
+    snapshots::SnapshotsPlugin,
+    step_response::StepResponsePlugin,
+    sweep::{self, SweepSettings},
+    traces::TracesPlugin,
+    trajectory_optimization::TrajectoryOptimizationPlugin,
+    udp_packets::{PacketLayout, UdpPacketsPlugin},
+    urdf::{Robot, UrdfPlugin},
+    watchdog::WatchdogPlugin,
+    waveforms::WaveformsPlugin,
+    websocket_api::{self, WebSocketApiPlugin},
+};
 
-use config_plugin::ConfigPlugin;
+fn main() -> AppExit {
+    let cli = Cli::parse();
+    #[cfg(not(target_arch = "wasm32"))]
+    let (cli, scenario) = load_scenario(cli);
+    #[cfg(not(target_arch = "wasm32"))]
+    let (migrated, migration_errors) =
+        migration::migrate_configs(&config_plugin::config_dir(), migration::CONFIG_FORMATS);
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(exit) = run_determinism_tools(&cli)
+        .or_else(|| run_batch(&cli, &scenario))
+        .or_else(|| run_fuzzer(&cli))
+        .or_else(|| run_sweep(&cli))
+        .or_else(|| run_roa_mapper(&cli))
+        .or_else(|| run_sizing(&cli))
+        .or_else(|| run_bench(&cli))
+        .or_else(|| run_model_tools(&cli))
+        .or_else(|| run_diff_tool(&cli))
+        .or_else(|| run_calibration(&cli))
+        .or_else(|| run_ident(&cli))
+        .or_else(|| run_estimator_replay(&cli))
+        .or_else(|| run_script_test(&cli))
+        .or_else(|| run_codegen(&cli))
+    {
+        for migrated in &migrated {
+            eprintln!("{migrated}");
+        }
+        for error in &migration_errors {
+            eprintln!("{error}");
+        }
+        return exit;
+    }
+    let (log_settings, log_settings_error) = logging::load_settings();
 
-fn main() {
     let mut app = App::new();
-    app.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 2_000.0,
-    })
-    .add_plugins((
+    // Before the assets, so the opened files can be loaded as assets.
+    app.add_plugins(PlatformPlugin);
+    app.add_plugins((
         DefaultPlugins
             .set(WindowPlugin {
                 primary_window: Some(Window {
@@ -47,25 +214,1209 @@ fn main() {
             })
             .set(AssetPlugin {
                 file_path: std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
+                // The model is reloaded as it's exported again.
+                watch_for_changes_override: cfg!(feature = "blender-model").then_some(true),
                 ..default()
-            }),
-        PanOrbitCameraPlugin,
+            })
+            .set(logging::log_plugin(&log_settings, cli.log.as_deref())),
+        (
+            PanOrbitCameraPlugin,
+            CameraSequencePlugin,
+            CameraViewsPlugin,
+            StereoPlugin,
+        ),
         #[cfg(feature = "blender-model")]
-        SceneViewerPlugin,
-        #[cfg(feature = "embedded-model")]
-        EmbeddedModelPlugin,
+        (
+            SceneViewerPlugin,
+            JointBuilderPlugin,
+            CollidersPlugin,
+            SceneDiffPlugin,
+        ),
         WorldInspectorPlugin::new(),
         RapierPhysicsPlugin::<NoUserData>::default(),
         RapierDebugRenderPlugin::default(),
         ConfigPlugin,
+        #[cfg(feature = "embedded-model")]
+        ModelLibraryPlugin {
+            plant: library_plant(&cli),
+        },
         GridPlugin,
+        (LightingPlugin, LodPlugin),
+        AudioCuesPlugin,
+        HapticsPlugin,
+        ErrorPlugin::default(),
+        SimClockPlugin,
+        TelemetryPlugin,
+        #[cfg(not(target_arch = "wasm32"))]
+        (
+            AutosavePlugin,
+            RunDiffPlugin,
+            RecordingPlugin,
+            SnapshotsPlugin,
+            ReplayPlugin,
+            HardwareLogPlugin,
+            EstimatorReplayPlugin,
+            CalibrationPlugin,
+            WaveformsPlugin,
+            CapturePlugin,
+            ExperimentsPlugin,
+        ),
     ))
+    .add_plugins((
+        UiAccessibilityPlugin,
+        ThemePlugin,
+        (
+            JogPlugin,
+            GamepadMappingPlugin,
+            InputBindingsPlugin,
+            SetpointCommandPlugin,
+        ),
+        (TeachPlugin, TrajectoryPlugin, TransportPlugin),
+        HmiPlugin,
+        (
+            InteractionPlugin,
+            JointAuthoringPlugin,
+            #[cfg(not(target_arch = "wasm32"))]
+            KinematicPlaybackPlugin,
+            KinematicsPlugin,
+            MassOverridesPlugin,
+            PhysicsParametersPlugin,
+            SelectionPlugin,
+        ),
+        (
+            FixturesPlugin,
+            FramesPlugin,
+            OverlaysPlugin,
+            #[cfg(not(target_arch = "wasm32"))]
+            TracesPlugin,
+        ),
+        (SelfCollisionPlugin, ContactsPlugin),
+        ProximityPlugin,
+        (
+            PlotsPlugin,
+            #[cfg(not(target_arch = "wasm32"))]
+            StepResponsePlugin,
+            #[cfg(not(target_arch = "wasm32"))]
+            FrequencyResponsePlugin,
+        ),
+        (
+            (
+                PidControllerPlugin,
+                CustomControllersPlugin,
+                AutotunePlugin,
+                DualLoopControllerPlugin,
+                GantryControllerPlugin,
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            ScriptedControllerPlugin,
+            (LqrPlugin, LqgPlugin),
+            MpcPlugin,
+            SwingUpPlugin,
+            #[cfg(not(target_arch = "wasm32"))]
+            TrajectoryOptimizationPlugin,
+            DisturbancesPlugin,
+            SensorlessPlugin,
+            (SensorsPlugin, LatencyPlugin),
+            EstimationPlugin,
+            ActuatorsPlugin,
+            (FrictionPlugin, TransmissionsPlugin),
+            (JointLimitsPlugin, SupervisorPlugin),
+            AnomaliesPlugin,
+            MonitorsPlugin,
+        ),
+        StateMachinesPlugin,
+        #[cfg(not(target_arch = "wasm32"))]
+        GovernorPlugin,
+        #[cfg(not(target_arch = "wasm32"))]
+        WatchdogPlugin,
+        ExtensionsPlugin,
+    ))
+    .insert_resource(log_settings)
+    .insert_resource(cli.clone())
     .add_systems(Startup, setup);
 
     #[cfg(feature = "blender-model")]
     app.add_systems(PreUpdate, setup_scene_after_load);
 
-    app.run();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        app.add_plugins(MigrationPlugin { migrated });
+        for error in migration_errors {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_composition(&mut app, &cli);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_comparison(&mut app, &cli);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_urdf(&mut app, &cli);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_scenario(&mut app, scenario);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_fixed_step(&mut app, &cli);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_sim_thread(&mut app, &cli);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    add_network_plugins(&mut app, &cli);
+
+    if let Some(error) = log_settings_error {
+        app.world_mut().send_event(ErrorEvent::from(error));
+    }
+
+    app.run()
+}
+
+/// The built-in plant to spawn: that of `--plant`, or none when composing the scene.
+#[cfg(feature = "embedded-model")]
+fn library_plant(cli: &Cli) -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if cli.compose.is_some() || cli.compare.is_some() {
+        return None;
+    }
+    Some(
+        cli.plant
+            .clone()
+            .unwrap_or_else(|| "rotary_pendulum".to_string()),
+    )
+}
+
+/// Runs the determinism and precision checks instead of the application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_determinism_tools(cli: &Cli) -> Option<AppExit> {
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    if let Some(path) = &cli.determinism {
+        let plant = match selected_plant(cli, None) {
+            Ok(plant) => plant,
+            Err(exit) => return Some(exit),
+        };
+        let report = determinism::run_reference(&plant, determinism::REFERENCE_STEPS);
+        println!(
+            "{} {} ({}, {})",
+            report.hash, report.plant, report.platform, report.build
+        );
+        return Some(match report.write(path) {
+            Ok(()) => AppExit::Success,
+            Err(error) => fail(error),
+        });
+    }
+    if let Some([a, b]) = cli.compare_determinism.as_deref() {
+        let reports = DeterminismReport::read(a).and_then(|a| Ok((a, DeterminismReport::read(b)?)));
+        let (a, b) = match reports {
+            Ok(reports) => reports,
+            Err(error) => return Some(fail(error)),
+        };
+        let comparison = determinism::compare(&a, &b);
+        println!("{} against {}: {comparison}", a.platform, b.platform);
+        return Some(match comparison {
+            Comparison::Identical => AppExit::Success,
+            _ => AppExit::from_code(1),
+        });
+    }
+    if let Some(path) = &cli.precision {
+        let report = precision::run_comparison(precision::PRECISION_STEPS);
+        println!("{report}");
+        return Some(match report.write(path) {
+            Ok(()) => AppExit::Success,
+            Err(error) => fail(error),
+        });
+    }
+    None
+}
+
+/// The plant the tools run: that of `--plant`, else `fallback`, e.g. that of the scenario,
+/// else the first built-in one, or the exit of the application when there is none of that name.
+#[cfg(not(target_arch = "wasm32"))]
+fn selected_plant(cli: &Cli, fallback: Option<&str>) -> Result<plants::Plant, AppExit> {
+    let name = cli.plant.as_deref().or(fallback);
+    let Some(name) = name else {
+        return plants::builtin().into_iter().next().ok_or_else(|| {
+            eprintln!("no built-in plant in this build");
+            AppExit::from_code(69)
+        });
+    };
+    plants::available()
+        .into_iter()
+        .find(|plant| plant.name == name)
+        .ok_or_else(|| {
+            let error = digital_twin_playground::error::Error::Config {
+                name: "plant".to_string(),
+                message: format!("no built-in plant `{name}`"),
+            };
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        })
+}
+
+/// Reads the scenario of `--scenario`, if given, the options of `cli` it sets filled from it
+/// unless given on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_scenario(
+    mut cli: Cli,
+) -> (
+    Cli,
+    Result<Option<ScenarioDefinition>, digital_twin_playground::error::Error>,
+) {
+    let Some(path) = &cli.scenario else {
+        return (cli, Ok(None));
+    };
+    let scenario = ScenarioDefinition::read(path).and_then(|scenario| {
+        scenario.validate(&plants::available())?;
+        Ok(scenario)
+    });
+    if let Ok(scenario) = &scenario {
+        if cli.plant.is_none() && cli.compose.is_none() && cli.urdf.is_none() {
+            cli.plant.clone_from(&scenario.plant);
+            cli.compose.clone_from(&scenario.composition);
+            cli.urdf.clone_from(&scenario.urdf);
+        }
+        if cli.fixed_step.is_none() {
+            cli.fixed_step = scenario.dt.map(Some);
+        }
+        cli.seed = cli.seed.or(scenario.seed);
+        cli.duration = cli.duration.or(scenario.duration);
+    }
+    (cli, scenario.map(Some))
+}
+
+/// Runs the plant of `--plant`, or that of the scenario, headlessly instead of the application,
+/// if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_batch(
+    cli: &Cli,
+    scenario: &Result<Option<ScenarioDefinition>, digital_twin_playground::error::Error>,
+) -> Option<AppExit> {
+    if !cli.headless {
+        return None;
+    }
+    let scenario = match scenario {
+        Ok(scenario) => scenario.clone(),
+        Err(error) => {
+            eprintln!("{error}");
+            return Some(AppExit::from_code(error.exit_code()));
+        }
+    };
+    if scenario
+        .as_ref()
+        .is_some_and(|scenario| scenario.composition.is_some() || scenario.urdf.is_some())
+    {
+        eprintln!("headless runs spawn a plant, not a composition nor a robot");
+        return Some(AppExit::from_code(64));
+    }
+    let plant = match selected_plant(
+        cli,
+        scenario
+            .as_ref()
+            .and_then(|scenario| scenario.plant.as_deref()),
+    ) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let batch = BatchRun {
+        duration: cli.duration.unwrap_or(10.0),
+        dt: cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP),
+        seed: cli.seed.unwrap_or(fixed_step::DEFAULT_SEED),
+        out: cli.out.clone().unwrap_or_else(|| "results.csv".into()),
+        capture: cli.capture.clone(),
+        scenario,
+    };
+    Some(match batch::run(&plant, &batch) {
+        Ok(summary) => {
+            println!(
+                "{} steps of {} in {:.2} s ({:.0}x real time), {} rows written to {}",
+                summary.steps,
+                plant.name,
+                summary.elapsed.as_secs_f32(),
+                summary.real_time_factor(batch.dt),
+                summary.rows,
+                summary.path.display()
+            );
+            if let Some(directory) = &batch.capture {
+                println!("Frames captured to {}", directory.display());
+            }
+            println!("{}", summary.usage);
+            for violation in &summary.violations {
+                let end = violation
+                    .end
+                    .map_or("the end".to_string(), |end| format!("{end:.3} s"));
+                println!(
+                    "{} violated from {:.3} s to {end}",
+                    violation.requirement, violation.start
+                );
+            }
+            AppExit::Success
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Searches for failing scenarios, or replays one, instead of running the application, if
+/// requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_fuzzer(cli: &Cli) -> Option<AppExit> {
+    if cli.fuzz.is_none() && cli.replay_failure.is_none() {
+        return None;
+    }
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    let plant = match selected_plant(cli, None) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let dt = cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP);
+    if let Some(path) = &cli.replay_failure {
+        let replay = FailureReport::read(path)
+            .and_then(|report| fuzzing::check(&plant, &report.scenario, &report.requirements, dt));
+        return Some(match replay {
+            Ok(Some(failure)) => {
+                println!("{failure}");
+                AppExit::from_code(1)
+            }
+            Ok(None) => {
+                println!("no failure");
+                AppExit::Success
+            }
+            Err(error) => fail(error),
+        });
+    }
+    let directory = cli.fuzz.as_ref()?;
+    let (settings, error) = config_plugin::load_config::<FuzzSettings>("fuzzing", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    let mut settings = settings.get().clone();
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    let found = fuzzing::fuzz(&plant, &settings, dt, |index, report| {
+        let path = directory.join(format!("failure-{index:04}.json"));
+        println!("{}: {}", path.display(), report.failure);
+        report.write(&path)
+    });
+    Some(match found {
+        Ok(reports) => {
+            println!(
+                "{} failing scenarios out of {}",
+                reports.len(),
+                settings.runs
+            );
+            AppExit::Success
+        }
+        Err(error) => fail(error),
+    })
+}
+
+/// Sweeps the parameters of the controllers instead of running the application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_sweep(cli: &Cli) -> Option<AppExit> {
+    let path = cli.sweep.as_ref()?;
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    let plant = match selected_plant(cli, None) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let (settings, error) = config_plugin::load_config::<SweepSettings>("sweep", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    let mut settings = settings.get().clone();
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    let dt = cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP);
+    let describe = |run: &sweep::SweepRun| {
+        let parameters = run
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let energy = run.energy.map_or(String::new(), |energy| {
+            let efficiency = energy
+                .efficiency()
+                .map_or("none".to_string(), |efficiency| {
+                    format!("{:.1} %", 100.0 * efficiency)
+                });
+            format!(
+                ", energy {:.3} J, efficiency {efficiency}",
+                energy.electrical
+            )
+        });
+        let scripted = run
+            .scripted
+            .map_or(String::new(), |cost| format!(", cost {cost:.4}"));
+        match run.metrics {
+            Some(metrics) => format!(
+                "{parameters}: settling {}, overshoot {:.4}, RMS error {:.4}{energy}{scripted}",
+                metrics
+                    .settling_time
+                    .map_or("never".to_string(), |time| format!("{time:.3} s")),
+                metrics.overshoot,
+                metrics.rms_error
+            ),
+            None => format!("{parameters}: no {}{energy}{scripted}", settings.channel),
+        }
+    };
+    let runs = sweep::sweep(&plant, &settings, dt, |index, run| {
+        println!("run {index}, {}", describe(run));
+        println!("  {}", run.usage);
+    })
+    .and_then(|runs| sweep::write_summary(&settings, &runs, path).map(|()| runs));
+    Some(match runs {
+        Ok(runs) => {
+            if let Some(&best) = sweep::ranking(&runs, settings.rank_by).first() {
+                println!("best: run {best}, {}", describe(&runs[best]));
+            }
+            println!("{} runs summarized in {}", runs.len(), path.display());
+            AppExit::Success
+        }
+        Err(error) => fail(error),
+    })
+}
+
+/// Maps the region of attraction of the controllers instead of running the application, if
+/// requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_roa_mapper(cli: &Cli) -> Option<AppExit> {
+    let path = cli.map_roa.as_ref()?;
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    let plant = match selected_plant(cli, None) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let (settings, error) = config_plugin::load_config::<RoaSettings>("roa", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    let mut settings = settings.get().clone();
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    let dt = cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP);
+    let region =
+        roa::map(&plant, &settings, dt).and_then(|region| region.write_csv(path).map(|()| region));
+    Some(match region {
+        Ok(region) => {
+            let [lower, area, upper] = region.area();
+            let samples: usize = region.cells.iter().map(|cell| cell.samples).sum();
+            println!(
+                "region of attraction over {} x {}: area {area:.4} ({lower:.4} to {upper:.4} at {:.0}% confidence), {samples} runs, written to {}",
+                region.x,
+                region.y,
+                100.0 * region.confidence,
+                path.display()
+            );
+            AppExit::Success
+        }
+        Err(error) => fail(error),
+    })
+}
+
+/// Sizes the actuators of the plant of `--plant` instead of running the application, if
+/// requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_sizing(cli: &Cli) -> Option<AppExit> {
+    let path = cli.size_actuators.as_ref()?;
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    let plant = match selected_plant(cli, None) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let (settings, error) = config_plugin::load_config::<SizingSettings>("sizing", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    let mut settings = settings.get().clone();
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    let dt = cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP);
+    let report = sizing::run(&plant, &settings, dt)
+        .and_then(|report| report.write_csv(path).map(|()| report));
+    Some(match report {
+        Ok(report) => {
+            print!("{report}");
+            println!("envelopes written to {}", path.display());
+            AppExit::Success
+        }
+        Err(error) => fail(error),
+    })
+}
+
+/// Benchmarks the registered controllers instead of running the application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_bench(cli: &Cli) -> Option<AppExit> {
+    let Some(Command::BenchControllers { scoreboard: path }) = &cli.command else {
+        return None;
+    };
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    let (settings, error) = config_plugin::load_config::<BenchSettings>("bench", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    let mut settings = settings.get().clone();
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    let dt = cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP);
+    let contenders = bench::contenders(&controller_definition::definitions_dir());
+    let runs = bench::bench(&plants::available(), &settings, &contenders, dt, |run| {
+        let metrics = run.run.metrics.map_or("no channel".to_string(), |metrics| {
+            format!(
+                "settling {}, overshoot {:.4}, RMS error {:.4}",
+                metrics
+                    .settling_time
+                    .map_or("never".to_string(), |time| format!("{time:.3} s")),
+                metrics.overshoot,
+                metrics.rms_error
+            )
+        });
+        println!("{}, {}: {metrics}", run.scenario, run.controller);
+    })
+    .and_then(|runs| bench::write_scoreboard(&runs, settings.rank_by, path).map(|()| runs));
+    Some(match runs {
+        Ok(runs) => {
+            for (place, score) in bench::scoreboard(&runs, settings.rank_by)
+                .iter()
+                .enumerate()
+            {
+                println!(
+                    "{}. {}: mean rank {:.2}, {} wins, settled in {} of {} scenarios",
+                    place + 1,
+                    score.controller,
+                    score.mean_rank,
+                    score.wins,
+                    score.settled,
+                    score.scenarios
+                );
+            }
+            println!("{} runs scored in {}", runs.len(), path.display());
+            AppExit::Success
+        }
+        Err(error) => fail(error),
+    })
+}
+
+/// Compares two recorded runs instead of running the application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_diff_tool(cli: &Cli) -> Option<AppExit> {
+    let Some(Command::Diff {
+        runs,
+        align,
+        align_threshold,
+        tolerance,
+        hardware_log,
+        csv,
+    }) = &cli.command
+    else {
+        return None;
+    };
+    let [first, second] = runs.as_slice() else {
+        return None;
+    };
+    let alignment = match align {
+        Some(channel) => Alignment::Event {
+            channel: channel.clone(),
+            threshold: align_threshold.unwrap_or_default(),
+        },
+        None => Alignment::Time,
+    };
+    let diff = if *hardware_log {
+        let (settings, error) =
+            config_plugin::load_config::<HardwareLogSettings>("hardware_log", false);
+        let mut settings = settings.get().clone();
+        if let Some(channel) = align {
+            settings.marker = Some(channel.clone());
+            settings.threshold = align_threshold.unwrap_or_default();
+        }
+        error.map_or(Ok(()), Err).and_then(|()| {
+            let log = hardware_log::read(first, &settings)?;
+            let run = run_diff::read_run(second)?;
+            hardware_log::compare(&log, &run, &settings)
+        })
+    } else {
+        run_diff::read_run(first).and_then(|first| {
+            let second = run_diff::read_run(second)?;
+            RunDiff::compare(&first, &second, &alignment)
+        })
+    };
+    let written = diff.and_then(|diff| {
+        if let Some(path) = csv {
+            diff.write_csv(path)?;
+        }
+        Ok(diff)
+    });
+    Some(match written {
+        Ok(diff) => {
+            println!("{} against {}:", second.display(), first.display());
+            print!("{diff}");
+            match tolerance {
+                Some(tolerance) if diff.max() > *tolerance => AppExit::from_code(1),
+                _ => AppExit::Success,
+            }
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Calibrates a model of the rig on a hardware log instead of running the application, if
+/// requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_calibration(cli: &Cli) -> Option<AppExit> {
+    let path = cli.calibrate.as_ref()?;
+    let (log_settings, log_error) =
+        config_plugin::load_config::<HardwareLogSettings>("hardware_log", false);
+    let (settings, error) = config_plugin::load_config::<CalibrationSettings>("calibration", false);
+    let mut settings = settings.get().clone();
+    if let Some(model) = &cli.calibrated_model {
+        settings.model = model.clone();
+    }
+    let calibration = log_error
+        .or(error)
+        .map_or(Ok(()), Err)
+        .and_then(|()| hardware_log::read(path, &log_settings))
+        .and_then(|log| calibration::calibrate(&log, &settings))
+        .and_then(|calibration| calibration.write(&settings.model).map(|()| calibration));
+    Some(match calibration {
+        Ok(calibration) => {
+            print!("{calibration}");
+            println!("written to {}", settings.model.display());
+            AppExit::Success
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Fits the physical parameters of a plant on a recorded run instead of running the
+/// application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_ident(cli: &Cli) -> Option<AppExit> {
+    let path = cli.ident.as_ref()?;
+    let (settings, error) = config_plugin::load_config::<IdentSettings>("ident", false);
+    let fit = error
+        .map_or(Ok(()), Err)
+        .and_then(|()| run_diff::read_run(path))
+        .and_then(|run| ident::fit(&run, &settings))
+        .and_then(|fit| {
+            if let Some(residuals) = &cli.ident_residuals {
+                fit.write_residuals(residuals)?;
+            }
+            Ok(fit)
+        });
+    Some(match fit {
+        Ok(fit) => {
+            println!("{}:", path.display());
+            print!("{fit}");
+            AppExit::Success
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Replays the readings of a recorded run into the state estimation instead of running the
+/// application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_estimator_replay(cli: &Cli) -> Option<AppExit> {
+    let path = cli.replay_estimator.as_ref()?;
+    let (settings, settings_error) =
+        config_plugin::load_config::<EstimatorReplaySettings>("estimator_replay", false);
+    let (estimation, estimation_error) =
+        config_plugin::load_config::<EstimationSettings>("estimation", false);
+    let (lqr, lqr_error) = config_plugin::load_config::<LqrSettings>("lqr", false);
+    let settings = EstimatorReplaySettings {
+        run: Some(path.clone()),
+        ..settings.get().clone()
+    };
+    let replay = settings_error
+        .or(estimation_error)
+        .or(lqr_error)
+        .map_or(Ok(()), Err)
+        .and_then(|()| estimator_replay::replay_run(&settings, estimation.get(), lqr.get()));
+    Some(match replay {
+        Ok(replay) => {
+            println!("{}:", path.display());
+            print!("{replay}");
+            AppExit::Success
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Tests a controller open-loop on a recorded trace instead of running the application, if
+/// requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_script_test(cli: &Cli) -> Option<AppExit> {
+    let Some(Command::ScriptTest { case: path }) = &cli.command else {
+        return None;
+    };
+    Some(match ScriptTest::read(path).and_then(|test| test.run()) {
+        Ok(report) => {
+            println!("{}:", path.display());
+            print!("{report}");
+            if report.passed() {
+                AppExit::Success
+            } else {
+                AppExit::from_code(1)
+            }
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Generates the C code of the controllers instead of running the application, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_codegen(cli: &Cli) -> Option<AppExit> {
+    let directory = cli.generate_c.as_ref()?;
+    let dt = cli
+        .sample_period
+        .or(cli.fixed_step.flatten())
+        .unwrap_or(DEFAULT_TIME_STEP);
+    let definitions = if cli.controller.is_empty() {
+        let (pid, pid_error) = config_plugin::load_config::<PidSettings>("pid", false);
+        let (lqr, lqr_error) = config_plugin::load_config::<LqrSettings>("lqr", false);
+        pid_error.or(lqr_error).map_or(Ok(()), Err).map(|()| {
+            vec![
+                ControllerDefinition::from(pid.get()),
+                ControllerDefinition::from(lqr.get()),
+            ]
+        })
+    } else {
+        cli.controller
+            .iter()
+            .map(|path| ControllerDefinition::read(path))
+            .collect()
+    };
+    let written = definitions.and_then(|definitions| {
+        definitions
+            .iter()
+            .map(|definition| {
+                let generated = codegen::generate(definition, dt)?;
+                let paths = generated.write(directory)?;
+                Ok((definition.sample_period(dt), paths))
+            })
+            .collect::<Result<Vec<_>, digital_twin_playground::error::Error>>()
+    });
+    Some(match written {
+        Ok(written) => {
+            for (dt, paths) in written {
+                for path in paths {
+                    println!("written {}, sampled every {dt} s", path.display());
+                }
+            }
+            AppExit::Success
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            AppExit::from_code(error.exit_code())
+        }
+    })
+}
+
+/// Identifies a reduced-order model of the plant of `--plant`, analyzes one, compares
+/// placements of sensors and actuators, compares input shapers, or runs the fault detection
+/// demo, instead of running the application, if requested. An
+/// identified model is validated on a run with another excitation, written next to it for
+/// plotting.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_model_tools(cli: &Cli) -> Option<AppExit> {
+    let fail = |error: digital_twin_playground::error::Error| {
+        eprintln!("{error}");
+        AppExit::from_code(error.exit_code())
+    };
+    if let Some(path) = &cli.analyze {
+        return Some(match LinearModel::read(path) {
+            Ok(model) => {
+                print!("{}", Analysis::of(&model, analysis::DEFAULT_HORIZON));
+                AppExit::Success
+            }
+            Err(error) => fail(error),
+        });
+    }
+    if cli.identify.is_none() && !cli.placement && cli.shaping.is_none() && !cli.fault_detection {
+        return None;
+    }
+    if let Some(path) = &cli.shaping {
+        let (experiment, error) = config_plugin::load_config::<ShapingExperiment>("shaping", false);
+        if let Some(error) = error {
+            return Some(fail(error));
+        }
+        let plant = match selected_plant(cli, Some(&experiment.plant)) {
+            Ok(plant) => plant,
+            Err(exit) => return Some(exit),
+        };
+        let comparison = shaping::compare(&plant, &experiment, DEFAULT_TIME_STEP);
+        return Some(
+            match comparison.and_then(|c| c.write_csv(path).map(|()| c)) {
+                Ok(comparison) => {
+                    let resonance = comparison.resonance;
+                    println!(
+                        "{} at {:.3} Hz, damping {:.4}; residual vibration:",
+                        experiment.output, resonance.frequency, resonance.damping
+                    );
+                    for step in &comparison.steps {
+                        let name = step.shaper.map_or("unshaped", Shaper::name);
+                        println!("  {name}: {:.4e}", step.residual);
+                    }
+                    AppExit::Success
+                }
+                Err(error) => fail(error),
+            },
+        );
+    }
+    let plant = match selected_plant(cli, None) {
+        Ok(plant) => plant,
+        Err(exit) => return Some(exit),
+    };
+    let (experiment, error) = config_plugin::load_config::<Experiment>("identification", false);
+    if let Some(error) = error {
+        return Some(fail(error));
+    }
+    if cli.fault_detection {
+        let (settings, error) =
+            config_plugin::load_config::<FaultDetectionSettings>("fault_detection", false);
+        if let Some(error) = error {
+            return Some(fail(error));
+        }
+        return Some(
+            match fault_detection::run(&plant, &experiment, &settings, DEFAULT_TIME_STEP) {
+                Ok(report) => {
+                    print!("{report}");
+                    AppExit::Success
+                }
+                Err(error) => fail(error),
+            },
+        );
+    }
+    if cli.placement {
+        let (placement, error) = config_plugin::load_config::<Placement>("placement", false);
+        if let Some(error) = error {
+            return Some(fail(error));
+        }
+        let evaluations = placement::run(&plant, &experiment, &placement, DEFAULT_TIME_STEP);
+        return Some(match evaluations {
+            Ok(evaluations) => {
+                println!("{:?}, best first:", placement.metric);
+                for evaluation in evaluations {
+                    println!("  {}: {:.4e}", evaluation.name, evaluation.score);
+                }
+                AppExit::Success
+            }
+            Err(error) => fail(error),
+        });
+    }
+    let path = cli.identify.as_ref()?;
+    let order = cli.order.unwrap_or(experiment.order);
+
+    let estimation = identification::run_experiment(&plant, &experiment, DEFAULT_TIME_STEP, 1);
+    let model = match identification::identify(
+        &estimation,
+        &experiment.inputs,
+        &experiment.outputs,
+        order,
+        experiment.horizon,
+    ) {
+        Ok(model) => model,
+        Err(error) => return Some(fail(error)),
+    };
+    let validation = identification::run_experiment(&plant, &experiment, DEFAULT_TIME_STEP, 2);
+    // The plant doesn't start from the operating point of the model.
+    let initial_state = model.estimate_initial_state(&validation.inputs, &validation.outputs);
+    let predicted = model.simulate(&validation.inputs, &initial_state);
+    let fits = identification::fit_percent(&validation.outputs, &predicted);
+    println!(
+        "model of order {order} for {}, fit on validation data:",
+        plant.name
+    );
+    for (output, fit) in model.outputs.iter().zip(fits) {
+        println!("  {output}: {fit:.1} %");
+    }
+
+    let written = model.write(path).and_then(|()| {
+        identification::write_validation(
+            &path.with_extension("validation.csv"),
+            &model,
+            &validation,
+            &predicted,
+        )
+    });
+    Some(match written {
+        Ok(()) => AppExit::Success,
+        Err(error) => fail(error),
+    })
+}
+
+/// Composes the plants of the scene composition file, if given on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_composition(app: &mut App, cli: &Cli) {
+    let Some(path) = &cli.compose else {
+        return;
+    };
+    let composition = Composition::read(path).and_then(|composition| {
+        composition.validate(&plants::available())?;
+        Ok(composition)
+    });
+    match composition {
+        Ok(composition) => {
+            app.add_plugins(CompositionPlugin { composition });
+        }
+        Err(error) => {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Composes the copies of the comparison file, if given on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_comparison(app: &mut App, cli: &Cli) {
+    let Some(path) = &cli.compare else {
+        return;
+    };
+    let comparison = comparison::Comparison::read(path).and_then(|comparison| {
+        comparison.validate(&plants::available())?;
+        Ok(comparison)
+    });
+    match comparison {
+        Ok(comparison) => {
+            app.add_plugins(ComparisonPlugin { comparison });
+        }
+        Err(error) => {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Follows the scenario of `--scenario`, if given, or reports why it can't be.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_scenario(
+    app: &mut App,
+    scenario: Result<Option<ScenarioDefinition>, digital_twin_playground::error::Error>,
+) {
+    match scenario {
+        Ok(Some(scenario)) => {
+            app.add_plugins(ScenarioPlugin { scenario });
+        }
+        Ok(None) => {}
+        Err(error) => {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Spawns the robot of the URDF file, if given on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_urdf(app: &mut App, cli: &Cli) {
+    let Some(path) = &cli.urdf else {
+        return;
+    };
+    match Robot::read(path) {
+        Ok(robot) => {
+            app.add_plugins(UrdfPlugin {
+                robot,
+                directory: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            });
+        }
+        Err(error) => {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Steps the physics by a fixed time step, if requested on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_fixed_step(app: &mut App, cli: &Cli) {
+    let Some(dt) = cli.fixed_step else {
+        return;
+    };
+    app.add_plugins(FixedStepPlugin {
+        dt: dt.unwrap_or(DEFAULT_TIME_STEP),
+        seed: cli.seed.unwrap_or(fixed_step::DEFAULT_SEED),
+        real_time: true,
+    });
+}
+
+/// Moves the physics and the controllers of the plant to a dedicated thread, if requested.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_sim_thread(app: &mut App, cli: &Cli) {
+    if !cli.sim_thread {
+        return;
+    }
+    let name = cli.plant.as_deref().unwrap_or("rotary_pendulum");
+    let Some(plant) = plants::available()
+        .into_iter()
+        .find(|plant| plant.name == name)
+    else {
+        let error = digital_twin_playground::error::Error::Config {
+            name: "sim_thread".to_string(),
+            message: format!("no built-in plant `{name}` to simulate"),
+        };
+        app.world_mut().send_event(ErrorEvent::from(error));
+        return;
+    };
+    app.add_plugins(SimThreadPlugin {
+        plant,
+        dt: cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP),
+        seed: cli.seed.unwrap_or(fixed_step::DEFAULT_SEED),
+    });
+}
+
+/// Adds the plugins talking to other instances or tools, as requested on the command line.
+#[cfg(not(target_arch = "wasm32"))]
+fn add_network_plugins(app: &mut App, cli: &Cli) {
+    if let Some(role) = cli.lockstep {
+        app.add_plugins(LockstepPlugin {
+            role,
+            bind: cli
+                .lockstep_bind
+                .unwrap_or(SocketAddr::from(([0, 0, 0, 0], lockstep::DEFAULT_PORT))),
+            peer: cli.lockstep_peer,
+            dt: cli.fixed_step.flatten().unwrap_or(DEFAULT_TIME_STEP),
+        });
+    }
+    let network = if cli.fieldbus.is_some() || cli.udp_packets.is_some() {
+        let (network, error) = config_plugin::load_config::<NetworkSettings>("network", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        let mut network = network.get().clone();
+        network.seed = cli.seed.unwrap_or(network.seed);
+        network
+    } else {
+        NetworkSettings::default()
+    };
+    if let Some(bind) = cli.fieldbus {
+        app.add_plugins(FieldbusPlugin {
+            bind,
+            network: network.clone(),
+        });
+    }
+    if let Some(bind) = cli.modbus {
+        let (map, error) = config_plugin::load_config::<ModbusMap>("modbus", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(ModbusPlugin {
+            bind,
+            map: map.get().clone(),
+        });
+    }
+    if let Some(bind) = cli.udp_packets {
+        let (layout, error) = config_plugin::load_config::<PacketLayout>("udp_packets", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(UdpPacketsPlugin {
+            bind,
+            layout: layout.get().clone(),
+            network,
+        });
+    }
+    if let Some(port) = &cli.serial_bridge {
+        let (settings, error) =
+            config_plugin::load_config::<SerialBridgeSettings>("serial_bridge", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(SerialBridgePlugin {
+            port: port.clone(),
+            settings: settings.get().clone(),
+        });
+    }
+    if let Some(remote) = cli.plotjuggler {
+        let (settings, error) =
+            config_plugin::load_config::<PlotJugglerSettings>("plotjuggler", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(PlotJugglerPlugin {
+            remote,
+            settings: settings.get().clone(),
+        });
+    }
+    #[cfg(feature = "opcua")]
+    if let Some(bind) = cli.opcua {
+        let (settings, error) = config_plugin::load_config::<OpcUaSettings>("opcua", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(OpcUaPlugin {
+            bind,
+            settings: settings.get().clone(),
+        });
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(interface) = &cli.canopen {
+        app.add_plugins(CanOpenPlugin {
+            interface: interface.clone(),
+            node_id: cli.canopen_node.unwrap_or(canopen::DEFAULT_NODE_ID),
+        });
+    }
+    #[cfg(target_os = "linux")]
+    if cli.joystick {
+        let (map, error) = config_plugin::load_config::<JoystickMap>("joystick", false);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.add_plugins(VirtualJoystickPlugin {
+            map: map.get().clone(),
+        });
+    }
+    if let Some(bind) = cli.host {
+        app.add_plugins(RemoteHostPlugin { bind });
+    }
+    if let Some(bind) = cli.dashboard {
+        app.add_plugins(DashboardPlugin {
+            bind,
+            read_only: cli.dashboard_read_only,
+        });
+    }
+    if let Some(bind) = cli.api {
+        app.add_plugins(RestApiPlugin { bind });
+    }
+    if let Some(bind) = cli.websocket_api {
+        app.add_plugins(WebSocketApiPlugin {
+            bind,
+            rate: cli.websocket_rate.unwrap_or(websocket_api::DEFAULT_RATE),
+        });
+    }
+    if let Some(host) = cli.connect {
+        app.add_plugins(RemoteClientPlugin {
+            host,
+            name: cli.name.clone().unwrap_or_else(|| "anonymous".to_string()),
+            role: cli.role,
+        });
+    }
 }
 
 fn _parse_scene(scene_path: String) -> (String, usize) {
@@ -84,11 +1435,12 @@ fn _parse_scene(scene_path: String) -> (String, usize) {
 }
 
 #[cfg(feature = "blender-model")]
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let scene_path = std::env::args()
-        .nth(1)
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, cli: Res<Cli>) {
+    let scene_path = cli
+        .scene
+        .clone()
         .unwrap_or_else(|| "3d-models/rotary-inverted-pendulum/rotary_pendulum.glb".to_string());
-    info!("Loading {}", scene_path);
+    info!(target: logging::subsystem::IO, "Loading {}", scene_path);
     let (file_path, scene_index) = parse_scene(scene_path);
     commands.insert_resource(SceneHandle::new(asset_server.load(file_path), scene_index));
 }
@@ -105,8 +1457,8 @@ fn setup(mut commands: Commands) {
 fn setup_scene_after_load(
     mut commands: Commands,
     mut setup: Local<bool>,
-    mut scene_handle: ResMut<SceneHandle>,
-    asset_server: Res<AssetServer>,
+    scene_handle: Res<SceneHandle>,
+    auto_lights: Query<Entity, With<AutoDirectionalLight>>,
     meshes: Query<(&GlobalTransform, Option<&Aabb>), With<Handle<Mesh>>>,
 ) {
     if scene_handle.is_loaded && !*setup {
@@ -133,34 +1485,19 @@ fn setup_scene_after_load(
         }
 
         // Display the controls of the scene viewer
-        info!("{}", *scene_handle);
+        info!(target: logging::subsystem::UI, "{}", *scene_handle);
 
         commands.spawn((
-            Camera3dBundle {
-                transform: Transform::from_translation(Vec3::new(10.0, 10.0, 10.0)),
-                ..default()
-            },
+            Camera3d::default(),
+            Transform::from_translation(Vec3::new(10.0, 10.0, 10.0)),
             PanOrbitCamera::default(),
-            EnvironmentMapLight {
-                diffuse_map: asset_server
-                    .load("assets/environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
-                specular_map: asset_server
-                    .load("assets/environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
-            },
         ));
 
-        // Spawn a default light if the scene does not have one
-        if !scene_handle.has_light {
-            info!("Spawning a directional light");
-            commands.spawn(DirectionalLightBundle {
-                directional_light: DirectionalLight {
-                    shadows_enabled: false,
-                    ..default()
-                },
-                ..default()
-            });
-
-            scene_handle.has_light = true;
+        // The scene brings its own lights, so the configured directional light isn't needed
+        if scene_handle.has_light {
+            for entity in &auto_lights {
+                commands.entity(entity).despawn();
+            }
         }
     }
 }
@@ -0,0 +1,250 @@
+//! This module overrides the inertial properties of the links, so the sensitivity of the
+//! controllers to the model can be explored while the plant runs.
+//!
+//! A [`MassOverride`] gives the mass, the center of mass and the principal inertia of a link, in
+//! each plant, in the frame of the link. It replaces the mass of the collider of the link, or its
+//! additional mass properties when it has no collider, and Rapier recomputes the mass properties
+//! of the body for the next step; the properties the link had are kept in an [`OriginalMass`],
+//! and restored when the override is removed. The overrides are configured in
+//! `mass_overrides.json`, and applied again to the links spawned later, e.g. by a reloaded model.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+};
+
+pub struct MassOverridesPlugin;
+
+impl Plugin for MassOverridesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                apply_overrides.run_if(resource_exists::<Persistent<MassOverrides>>),
+            )
+            .add_systems(
+                Update,
+                mass_overrides_panel
+                    .run_if(resource_exists::<Persistent<MassOverrides>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// The inertial properties of a link, in its frame.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MassOverride {
+    /// Name of the link, in each plant.
+    pub link: String,
+    /// Mass, in kg.
+    pub mass: f32,
+    pub center_of_mass: Vec3,
+    /// Principal inertia about the axes of the link, in kg·m².
+    pub inertia: Vec3,
+}
+
+impl MassOverride {
+    /// The override of `link`, starting from its current mass `properties`.
+    pub fn from_properties(link: &str, properties: &MassProperties) -> Self {
+        Self {
+            link: link.to_string(),
+            mass: properties.mass,
+            center_of_mass: properties.local_center_of_mass,
+            inertia: properties.principal_inertia,
+        }
+    }
+
+    pub fn mass_properties(&self) -> MassProperties {
+        MassProperties {
+            local_center_of_mass: self.center_of_mass,
+            mass: self.mass.max(0.0),
+            principal_inertia_local_frame: Quat::IDENTITY,
+            principal_inertia: self.inertia.max(Vec3::ZERO),
+        }
+    }
+}
+
+/// Represents the configuration of the overrides.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct MassOverrides {
+    pub enabled: bool,
+    pub overrides: Vec<MassOverride>,
+}
+
+impl MassOverrides {
+    /// The override of `link`, if enabled.
+    pub fn find(&self, link: &str) -> Option<&MassOverride> {
+        self.overrides
+            .iter()
+            .find(|entry| entry.link == link)
+            .filter(|_| self.enabled)
+    }
+}
+
+/// The mass properties of a link before its override.
+#[derive(Clone, Component, Debug)]
+pub struct OriginalMass {
+    pub collider: ColliderMassProperties,
+    pub additional: AdditionalMassProperties,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<MassOverrides>("mass_overrides", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Overrides the mass properties of the links, or restores them, when the overrides change or
+/// links are spawned.
+#[allow(clippy::type_complexity)]
+fn apply_overrides(
+    mut commands: Commands,
+    settings: Res<Persistent<MassOverrides>>,
+    added: Query<(), (Added<Link>, With<RigidBody>)>,
+    links: Query<
+        (
+            Entity,
+            &Link,
+            Has<Collider>,
+            Option<&ColliderMassProperties>,
+            Option<&AdditionalMassProperties>,
+            Option<&OriginalMass>,
+        ),
+        With<RigidBody>,
+    >,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, has_collider, collider, additional, original) in &links {
+        match (settings.find(&link.name), original) {
+            (Some(entry), _) => {
+                let mut entity_commands = commands.entity(entity);
+                if original.is_none() {
+                    entity_commands.insert(OriginalMass {
+                        collider: collider.cloned().unwrap_or_default(),
+                        additional: additional.cloned().unwrap_or_default(),
+                    });
+                    info!(target: subsystem::PHYSICS, "Mass of {} overridden", link.path());
+                }
+                let properties = entry.mass_properties();
+                if has_collider {
+                    entity_commands.insert((
+                        ColliderMassProperties::MassProperties(properties),
+                        AdditionalMassProperties::Mass(0.0),
+                    ));
+                } else {
+                    entity_commands.insert(AdditionalMassProperties::MassProperties(properties));
+                }
+            }
+            (None, Some(original)) => {
+                commands
+                    .entity(entity)
+                    .insert((original.collider.clone(), original.additional.clone()))
+                    .remove::<OriginalMass>();
+                info!(target: subsystem::PHYSICS, "Mass of {} restored", link.path());
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Panel to override the inertial properties of the links.
+fn mass_overrides_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<MassOverrides>>,
+    links: Query<(&Link, Option<&ReadMassProperties>), With<RigidBody>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Mass overrides")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Override the masses of the links");
+            let mut names: Vec<(&str, Option<&MassProperties>)> = links
+                .iter()
+                .map(|(link, read)| (link.name.as_str(), read.map(|read| read.get())))
+                .collect();
+            names.sort_by(|a, b| a.0.cmp(b.0));
+            names.dedup_by(|a, b| a.0 == b.0);
+
+            egui::Grid::new("mass_overrides").show(ui, |ui| {
+                for (name, read) in names {
+                    ui.label(name);
+                    let index = edited.overrides.iter().position(|entry| entry.link == name);
+                    match index {
+                        Some(index) => {
+                            let entry = &mut edited.overrides[index];
+                            ui.vertical(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut entry.mass)
+                                        .range(0.0..=1000.0)
+                                        .speed(0.01)
+                                        .prefix("Mass: ")
+                                        .suffix(" kg"),
+                                );
+                                vector(ui, "Center of mass (m):", &mut entry.center_of_mass, 0.01);
+                                vector(ui, "Inertia (kg·m²):", &mut entry.inertia, 0.001);
+                            });
+                            if ui.button("Remove").clicked() {
+                                edited.overrides.remove(index);
+                            }
+                        }
+                        None => {
+                            match read {
+                                Some(read) => ui.label(format!("{:.3} kg", read.mass)),
+                                None => ui.label("Mass unknown"),
+                            };
+                            if ui.button("Override").clicked() {
+                                let properties = read.copied().unwrap_or_default();
+                                edited
+                                    .overrides
+                                    .push(MassOverride::from_properties(name, &properties));
+                            }
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("mass_overrides", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("mass_overrides", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Edits the components of a vector on a row.
+fn vector(ui: &mut egui::Ui, label: &str, value: &mut Vec3, speed: f64) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for component in [&mut value.x, &mut value.y, &mut value.z] {
+            ui.add(egui::DragValue::new(component).speed(speed));
+        }
+    });
+}
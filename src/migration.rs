@@ -0,0 +1,313 @@
+//! Versions of the formats of the files the application reads, and their migrations, so the
+//! files written by older releases keep loading as the formats evolve.
+//!
+//! The version of a [`Format`] is its number of migrations: each [`Migration`] upgrades a JSON
+//! value from its version to the next, and files from before the format was versioned are at
+//! version 0. Documents, the sessions, the plant compositions and the controller definitions,
+//! carry their version in a top-level `version` key and are migrated as they're read. The
+//! configuration files are written by the settings themselves, so their versions are kept in
+//! `versions.json` in the configuration directory: they're migrated in place on start, before
+//! any is loaded, the original kept next to them with the `.v<version>` extension, and the
+//! migrations are reported in a panel.
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    accessibility_plugin::has_ui,
+    error::{Error, Result},
+    headless::DEFAULT_TIME_STEP,
+    logging::subsystem,
+};
+
+/// Key holding the version of a document.
+pub const VERSION_KEY: &str = "version";
+/// File of the versions of the configuration files, in the configuration directory.
+pub const VERSIONS_FILE: &str = "versions.json";
+
+/// An upgrade of a format from a version to the next.
+pub struct Migration {
+    /// What changed, as reported to the user.
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> std::result::Result<(), String>,
+}
+
+/// A versioned file format.
+pub struct Format {
+    /// Name of the format, the name of the file for the configuration.
+    pub name: &'static str,
+    /// Migrations from version 0 on.
+    pub migrations: &'static [Migration],
+}
+
+impl Format {
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    fn invalid(&self, message: String) -> Error {
+        Error::Config {
+            name: self.name.to_string(),
+            message,
+        }
+    }
+
+    /// Migrates `value` from version `from` to the current one, and returns the descriptions of
+    /// the migrations applied.
+    pub fn migrate(&self, value: &mut Value, from: u32) -> Result<Vec<&'static str>> {
+        let Some(migrations) = self.migrations.get(from as usize..) else {
+            return Err(self.invalid(format!(
+                "version {from} is newer than this release reads ({})",
+                self.version()
+            )));
+        };
+        (from..)
+            .zip(migrations)
+            .map(|(version, migration)| {
+                (migration.apply)(value).map_err(|message| {
+                    self.invalid(format!(
+                        "failed to migrate from version {version}: {message}"
+                    ))
+                })?;
+                Ok(migration.description)
+            })
+            .collect()
+    }
+
+    /// Migrates a document from its version to the current one, and sets its version.
+    pub fn migrate_document(&self, value: &mut Value) -> Result<Vec<&'static str>> {
+        let from = match value.get(VERSION_KEY) {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| self.invalid(format!("invalid version {version}")))?,
+        };
+        let migrated = self.migrate(value, from)?;
+        if let Value::Object(document) = value {
+            document.insert(VERSION_KEY.to_string(), self.version().into());
+        }
+        Ok(migrated)
+    }
+
+    /// `document` as JSON, with the current version.
+    pub fn to_value<T: Serialize>(&self, document: &T) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(document)?;
+        if let Value::Object(document) = &mut value {
+            document.insert(VERSION_KEY.to_string(), self.version().into());
+        }
+        Ok(value)
+    }
+
+    /// Reads a document from a JSON file, migrated to the current version.
+    pub fn read<T: DeserializeOwned>(&self, path: &Path) -> Result<T> {
+        let json = fs::read(path).map_err(|error| Error::io(path, error))?;
+        let mut value: Value = serde_json::from_slice(&json)
+            .map_err(|error| Error::io(path, io::Error::from(error)))?;
+        let migrated = self.migrate_document(&mut value)?;
+        if !migrated.is_empty() {
+            info!(
+                target: subsystem::IO,
+                "Read {} as version {}: {}",
+                path.display(),
+                self.version(),
+                migrated.join("; ")
+            );
+        }
+        serde_json::from_value(value).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+}
+
+/// The autosaved sessions.
+pub const SESSION: Format = Format {
+    name: "session",
+    migrations: &[Migration {
+        description: "the simulated time is kept as the physics step and the elapsed duration",
+        apply: session_clock,
+    }],
+};
+
+/// The plant compositions.
+pub const COMPOSITION: Format = Format {
+    name: "composition",
+    migrations: &[],
+};
+
+/// The controller definitions.
+pub const CONTROLLER_DEFINITION: Format = Format {
+    name: "controller definition",
+    migrations: &[],
+};
+
+/// The configuration files with versions, by name of their file; a configuration file is at
+/// version 0 until its first migration is added here.
+pub const CONFIG_FORMATS: &[Format] = &[];
+
+/// Sessions written before the simulated clock counted the physics steps held their time in
+/// seconds.
+fn session_clock(value: &mut Value) -> std::result::Result<(), String> {
+    let session = value.as_object_mut().ok_or("not an object")?;
+    let Some(time) = session.remove("time") else {
+        return Ok(());
+    };
+    let time = time.as_f64().ok_or("the time isn't a number")?.max(0.0);
+    let elapsed =
+        serde_json::to_value(Duration::from_secs_f64(time)).map_err(|error| error.to_string())?;
+    let tick = (time / f64::from(DEFAULT_TIME_STEP)).round() as u64;
+    session.insert("tick".to_string(), tick.into());
+    session.insert("elapsed".to_string(), elapsed);
+    Ok(())
+}
+
+/// A configuration file migrated in place.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Migrated {
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the migrations applied.
+    pub migrations: Vec<&'static str>,
+    /// Copy of the file before the migration.
+    pub original: PathBuf,
+}
+
+impl fmt::Display for Migrated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} migrated from version {} to {}: {}; the original is kept as {}",
+            self.path.display(),
+            self.from,
+            self.to,
+            self.migrations.join("; "),
+            self.original.display()
+        )
+    }
+}
+
+/// Migrates the configuration files of `formats` in `directory` to their current versions, and
+/// records the versions. Files that fail to migrate are left as they are.
+pub fn migrate_configs(directory: &Path, formats: &[Format]) -> (Vec<Migrated>, Vec<Error>) {
+    let versions_path = directory.join(VERSIONS_FILE);
+    let mut versions: BTreeMap<String, u32> = fs::read(&versions_path)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default();
+    let (mut migrated, mut errors) = (Vec::new(), Vec::new());
+    let mut changed = false;
+    for format in formats {
+        let from = versions.get(format.name).copied().unwrap_or_default();
+        if from == format.version() {
+            continue;
+        }
+        let path = directory.join(format!("{}.json", format.name));
+        // A missing file is written with the defaults, in the current format.
+        if path.exists() {
+            match migrate_config(format, &path, from) {
+                Ok(file) => migrated.push(file),
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            }
+        }
+        versions.insert(format.name.to_string(), format.version());
+        changed = true;
+    }
+    if changed {
+        let written = serde_json::to_vec_pretty(&versions)
+            .map_err(io::Error::from)
+            .and_then(|json| {
+                fs::create_dir_all(directory)?;
+                fs::write(&versions_path, json)
+            });
+        if let Err(error) = written {
+            errors.push(Error::io(&versions_path, error));
+        }
+    }
+    (migrated, errors)
+}
+
+fn migrate_config(format: &Format, path: &Path, from: u32) -> Result<Migrated> {
+    let json = fs::read(path).map_err(|error| Error::io(path, error))?;
+    let mut value: Value =
+        serde_json::from_slice(&json).map_err(|error| Error::io(path, io::Error::from(error)))?;
+    let migrations = format.migrate(&mut value, from)?;
+    let original = path.with_extension(format!("json.v{from}"));
+    fs::write(&original, &json).map_err(|error| Error::io(&original, error))?;
+    let json = serde_json::to_vec_pretty(&value).map_err(io::Error::from);
+    json.and_then(|json| fs::write(path, json))
+        .map_err(|error| Error::io(path, error))?;
+    Ok(Migrated {
+        path: path.to_path_buf(),
+        from,
+        to: format.version(),
+        migrations,
+        original,
+    })
+}
+
+/// Reports the configuration files migrated on start.
+pub struct MigrationPlugin {
+    pub migrated: Vec<Migrated>,
+}
+
+impl Plugin for MigrationPlugin {
+    fn build(&self, app: &mut App) {
+        if self.migrated.is_empty() {
+            return;
+        }
+        for migrated in &self.migrated {
+            info!(target: subsystem::IO, "{migrated}");
+        }
+        app.insert_resource(MigrationReport(self.migrated.clone()))
+            .add_systems(
+                Update,
+                migration_panel
+                    .run_if(has_ui)
+                    .run_if(resource_exists::<MigrationReport>),
+            );
+    }
+}
+
+/// The configuration files migrated on start, until dismissed.
+#[derive(Resource)]
+pub struct MigrationReport(pub Vec<Migrated>);
+
+fn migration_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    report: Res<MigrationReport>,
+) {
+    egui::Window::new("Migrated configuration").show(contexts.ctx_mut(), |ui| {
+        ui.label("These files were written by an older release and were upgraded:");
+        for migrated in &report.0 {
+            ui.separator();
+            ui.label(format!(
+                "{}, from version {} to {}",
+                migrated.path.display(),
+                migrated.from,
+                migrated.to
+            ));
+            for migration in &migrated.migrations {
+                ui.label(format!("• {migration}"));
+            }
+            ui.weak(format!(
+                "The original is kept as {}",
+                migrated.original.display()
+            ));
+        }
+        ui.separator();
+        if ui.button("Dismiss").clicked() {
+            commands.remove_resource::<MigrationReport>();
+        }
+    });
+}
@@ -0,0 +1,367 @@
+//! Modbus TCP server exposing the setpoints and the telemetry, for SCADA and HMI tools.
+//!
+//! The register map is read from the `modbus.json` configuration file: every holding register
+//! (read/write) is bound to a setpoint and every input register (read-only) to a telemetry
+//! channel, whose last value is served. Values are signed 16-bit integers, multiplied by the
+//! `scale` of the register; `float32` registers span two registers instead, high word first.
+//!
+//! The function codes supported are 3 (read holding registers), 4 (read input registers),
+//! 6 (write single register) and 16 (write multiple registers).
+//!
+//! Every request feeds the [`Watchdog`]; while it is tripped, writes are refused with the
+//! server device failure exception.
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+    watchdog::Watchdog,
+};
+
+/// Port used when none is given. The standard port 502 requires privileges on most systems.
+pub const DEFAULT_PORT: u16 = 5020;
+
+/// Exception codes.
+pub mod exception {
+    pub const ILLEGAL_FUNCTION: u8 = 0x01;
+    pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+    pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+    pub const SERVER_DEVICE_FAILURE: u8 = 0x04;
+}
+
+/// Encoding of a value in the registers.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterFormat {
+    /// One register holding the value multiplied by the scale.
+    #[default]
+    Int16,
+    /// Two registers holding an IEEE 754 float, high word first.
+    Float32,
+}
+
+impl RegisterFormat {
+    fn len(self) -> u16 {
+        match self {
+            RegisterFormat::Int16 => 1,
+            RegisterFormat::Float32 => 2,
+        }
+    }
+}
+
+/// A value exposed in the registers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Register {
+    /// Name of the setpoint or of the telemetry channel.
+    pub signal: String,
+    #[serde(default)]
+    pub format: RegisterFormat,
+    /// Factor applied to `int16` values, e.g. 1000 to transfer rad as mrad.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// The register map, by address of the first register of every value.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct ModbusMap {
+    pub holding_registers: BTreeMap<u16, Register>,
+    pub input_registers: BTreeMap<u16, Register>,
+}
+
+impl Default for ModbusMap {
+    fn default() -> Self {
+        Self {
+            holding_registers: BTreeMap::from([(
+                0,
+                Register {
+                    signal: MOTOR_VELOCITY.to_string(),
+                    format: RegisterFormat::Int16,
+                    scale: 1000.0,
+                },
+            )]),
+            input_registers: BTreeMap::from([(
+                0,
+                Register {
+                    signal: PENDULUM_ANGLE.to_string(),
+                    format: RegisterFormat::Int16,
+                    scale: 1000.0,
+                },
+            )]),
+        }
+    }
+}
+
+/// Reads `count` registers from `start`. Registers not mapped read as zero, but the range
+/// must not cut a `float32` value in half.
+fn read_registers(
+    registers: &BTreeMap<u16, Register>,
+    start: u16,
+    count: u16,
+    value_of: impl Fn(&str) -> Option<f32>,
+) -> Result<Vec<u16>, u8> {
+    let end = u32::from(start) + u32::from(count);
+    let mut words = vec![0; count.into()];
+    for (&address, register) in registers {
+        let len = register.format.len();
+        let last = u32::from(address) + u32::from(len);
+        if last <= u32::from(start) || u32::from(address) >= end {
+            continue;
+        }
+        if u32::from(address) < u32::from(start) || last > end {
+            return Err(exception::ILLEGAL_DATA_ADDRESS);
+        }
+        let value = value_of(&register.signal).unwrap_or_default();
+        let offset = usize::from(address - start);
+        match register.format {
+            RegisterFormat::Int16 => {
+                words[offset] = (value * register.scale)
+                    .round()
+                    .clamp(i16::MIN.into(), i16::MAX.into()) as i16
+                    as u16;
+            }
+            RegisterFormat::Float32 => {
+                let bits = value.to_bits();
+                words[offset] = (bits >> 16) as u16;
+                words[offset + 1] = bits as u16;
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// Writes `words` from `start` into the setpoints. Every register written must be mapped.
+fn write_registers(
+    registers: &BTreeMap<u16, Register>,
+    start: u16,
+    words: &[u16],
+    setpoints: &mut Setpoints,
+) -> Result<(), u8> {
+    let end = u32::from(start) + words.len() as u32;
+    let mut values = Vec::new();
+    let mut address = u32::from(start);
+    while address < end {
+        let register = u16::try_from(address)
+            .ok()
+            .and_then(|address| registers.get(&address))
+            .ok_or(exception::ILLEGAL_DATA_ADDRESS)?;
+        let offset = (address - u32::from(start)) as usize;
+        let value = match register.format {
+            RegisterFormat::Int16 => f32::from(words[offset] as i16) / register.scale,
+            RegisterFormat::Float32 => {
+                let low = words
+                    .get(offset + 1)
+                    .ok_or(exception::ILLEGAL_DATA_ADDRESS)?;
+                f32::from_bits(u32::from(words[offset]) << 16 | u32::from(*low))
+            }
+        };
+        if !value.is_finite() {
+            return Err(exception::ILLEGAL_DATA_VALUE);
+        }
+        values.push((register.signal.as_str(), value));
+        address += u32::from(register.format.len());
+    }
+    // Nothing is written if a register of the range is invalid.
+    for (signal, value) in values {
+        if setpoints.get(signal) != Some(value) {
+            setpoints.set(signal, value);
+        }
+    }
+    Ok(())
+}
+
+/// Handles the protocol data unit of a request, returning the one of the response.
+pub fn process(
+    map: &ModbusMap,
+    request: &[u8],
+    setpoints: &mut Setpoints,
+    telemetry: Option<&Telemetry>,
+) -> Vec<u8> {
+    let Some(&function) = request.first() else {
+        return Vec::new();
+    };
+    let word = |index: usize| {
+        request
+            .get(index..index + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let result = match function {
+        0x03 | 0x04 => match (word(1), word(3)) {
+            (Some(start), Some(count)) if (1..=125).contains(&count) => {
+                let words = if function == 0x03 {
+                    read_registers(&map.holding_registers, start, count, |signal| {
+                        setpoints.get(signal)
+                    })
+                } else {
+                    read_registers(&map.input_registers, start, count, |signal| {
+                        telemetry.and_then(|telemetry| telemetry.latest(signal))
+                    })
+                };
+                words.map(|words| {
+                    let mut response = vec![function, (words.len() * 2) as u8];
+                    response.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+                    response
+                })
+            }
+            _ => Err(exception::ILLEGAL_DATA_VALUE),
+        },
+        0x06 => match (word(1), word(3)) {
+            (Some(address), Some(value)) => {
+                write_registers(&map.holding_registers, address, &[value], setpoints)
+                    .map(|()| request[..5].to_vec())
+            }
+            _ => Err(exception::ILLEGAL_DATA_VALUE),
+        },
+        0x10 => match (word(1), word(3), request.get(5)) {
+            (Some(start), Some(count), Some(&bytes))
+                if (1..=123).contains(&count)
+                    && usize::from(bytes) == usize::from(count) * 2
+                    && request.len() == 6 + usize::from(bytes) =>
+            {
+                let words = request[6..]
+                    .chunks_exact(2)
+                    .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+                    .collect::<Vec<_>>();
+                write_registers(&map.holding_registers, start, &words, setpoints)
+                    .map(|()| request[..5].to_vec())
+            }
+            _ => Err(exception::ILLEGAL_DATA_VALUE),
+        },
+        _ => Err(exception::ILLEGAL_FUNCTION),
+    };
+    result.unwrap_or_else(|code| vec![function | 0x80, code])
+}
+
+/// Serves the register map on the given address.
+pub struct ModbusPlugin {
+    pub bind: SocketAddr,
+    pub map: ModbusMap,
+}
+
+impl Plugin for ModbusPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        let address = listener.local_addr().unwrap_or(self.bind);
+        info!(target: subsystem::IO, "Modbus TCP server listening on {address}");
+        let (requests, receiver) = mpsc::channel();
+        thread::spawn(move || accept_clients(listener, requests));
+        app.init_resource::<Setpoints>()
+            .insert_resource(self.map.clone())
+            .insert_resource(ModbusServer {
+                requests: Mutex::new(receiver),
+                address,
+            })
+            .add_systems(PreUpdate, answer_requests);
+    }
+}
+
+/// A request of a client, with the channel to send the response to.
+struct Request {
+    pdu: Vec<u8>,
+    response: mpsc::Sender<Vec<u8>>,
+}
+
+/// The server of the register map.
+#[derive(Resource)]
+pub struct ModbusServer {
+    requests: Mutex<mpsc::Receiver<Request>>,
+    /// Address served, with the port picked by the system when bound to port 0.
+    pub address: SocketAddr,
+}
+
+fn accept_clients(listener: TcpListener, requests: mpsc::Sender<Request>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let requests = requests.clone();
+        thread::spawn(move || {
+            let address = stream.peer_addr().ok();
+            debug!(target: subsystem::IO, "Modbus client {address:?} connected");
+            if let Err(error) = handle_client(stream, &requests) {
+                debug!(target: subsystem::IO, "Modbus client {address:?}: {error}");
+            }
+        });
+    }
+}
+
+/// Relays the requests of a client until it disconnects.
+fn handle_client(mut stream: TcpStream, requests: &mpsc::Sender<Request>) -> io::Result<()> {
+    loop {
+        // MBAP header: transaction, protocol, length, unit.
+        let mut header = [0; 7];
+        match stream.read_exact(&mut header) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        if header[2..4] != [0, 0] || !(2..=254).contains(&length) {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let mut pdu = vec![0; length - 1];
+        stream.read_exact(&mut pdu)?;
+
+        let (response, receiver) = mpsc::channel();
+        if requests.send(Request { pdu, response }).is_err() {
+            return Ok(());
+        }
+        let Ok(pdu) = receiver.recv() else {
+            return Ok(());
+        };
+        let mut frame = header.to_vec();
+        frame[4..6].copy_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.extend(pdu);
+        stream.write_all(&frame)?;
+    }
+}
+
+fn answer_requests(
+    server: Res<ModbusServer>,
+    map: Res<ModbusMap>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Option<Res<Telemetry>>,
+    mut watchdog: Option<ResMut<Watchdog>>,
+) {
+    let requests = server
+        .requests
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    for request in requests.try_iter() {
+        // Only borrow the setpoints mutably when written, not to trigger their change detection.
+        let mut copy = setpoints.clone();
+        let mut response = process(&map, &request.pdu, &mut copy, telemetry.as_deref());
+        let accepted = watchdog
+            .as_deref_mut()
+            .is_none_or(|watchdog| watchdog.feed("modbus", Instant::now()));
+        if copy != *setpoints {
+            if accepted {
+                *setpoints = copy;
+            } else {
+                response = vec![request.pdu[0] | 0x80, exception::SERVER_DEVICE_FAILURE];
+            }
+        }
+        let _ = request.response.send(response);
+    }
+}
@@ -0,0 +1,108 @@
+//! Switching between the built-in plants while the application runs.
+//!
+//! The [`ModelLibraryPlugin`] adds every [built-in plant](plants::builtin), with their joints,
+//! servos and telemetry, and spawns one of them, picked with `--plant`. The *Model library*
+//! panel switches to another: the links of the scene and the bodies of its plants are despawned,
+//! and the other plant is spawned in their place, without restarting.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::{self, Instance, Link, PlantBody},
+};
+
+/// Adds the built-in plants, spawning `plant`, or none to leave the scene to a composition.
+pub struct ModelLibraryPlugin {
+    pub plant: Option<String>,
+}
+
+impl Plugin for ModelLibraryPlugin {
+    fn build(&self, app: &mut App) {
+        let builtin = plants::builtin();
+        let current = self
+            .plant
+            .as_deref()
+            .and_then(|name| builtin.iter().find(|plant| plant.name == name));
+        for plant in &builtin {
+            let spawned = current.is_some_and(|current| current.name == plant.name);
+            let instances = if spawned {
+                vec![Instance::default()]
+            } else {
+                Vec::new()
+            };
+            (plant.compose)(app, &instances);
+        }
+        app.insert_resource(ModelLibrary {
+            current: current.map(|plant| plant.name.to_string()),
+            requested: None,
+        })
+        .add_systems(Update, switch_plant)
+        .add_systems(Update, model_library_panel.run_if(has_ui));
+
+        if let (Some(name), None) = (&self.plant, current) {
+            let message = format!("no built-in plant `{name}`");
+            app.add_systems(Startup, move |mut commands: Commands| {
+                commands.send_event(ErrorEvent::from(Error::Config {
+                    name: "plant".to_string(),
+                    message: message.clone(),
+                }));
+            });
+        }
+    }
+}
+
+/// The plant of the scene, and the one to switch to.
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct ModelLibrary {
+    /// Name of the spawned plant, if any.
+    pub current: Option<String>,
+    /// Name of the plant to spawn in its place on the next update.
+    pub requested: Option<String>,
+}
+
+/// Despawns the plants of the scene and spawns the requested one.
+fn switch_plant(world: &mut World) {
+    let Some(name) = world.resource_mut::<ModelLibrary>().requested.take() else {
+        return;
+    };
+    let Some(plant) = plants::builtin()
+        .into_iter()
+        .find(|plant| plant.name == name)
+    else {
+        world.send_event(ErrorEvent::from(Error::Config {
+            name: "plant".to_string(),
+            message: format!("no built-in plant `{name}`"),
+        }));
+        return;
+    };
+    let bodies: Vec<Entity> = world
+        .query_filtered::<Entity, Or<(With<Link>, With<PlantBody>)>>()
+        .iter(world)
+        .collect();
+    let mut commands = world.commands();
+    for entity in bodies {
+        commands.entity(entity).despawn_recursive();
+    }
+    world.flush();
+    (plant.spawn)(world);
+    world.resource_mut::<ModelLibrary>().current = Some(name);
+    info!(target: subsystem::PHYSICS, "Switched to the {} plant", plant.name);
+}
+
+/// Panel to switch between the built-in plants.
+fn model_library_panel(mut contexts: EguiContexts, mut library: ResMut<ModelLibrary>) {
+    egui::Window::new("Model library")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for plant in plants::builtin() {
+                let spawned = library.current.as_deref() == Some(plant.name);
+                if ui.radio(spawned, plant.name).clicked() && !spawned {
+                    library.requested = Some(plant.name.to_string());
+                }
+            }
+            ui.label("Switching spawns the plant anew, in place of the scene.");
+        });
+}
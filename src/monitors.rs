@@ -0,0 +1,625 @@
+//! Runtime monitors of requirements written in a simple temporal logic, checked online on the
+//! telemetry.
+//!
+//! A requirement is a sentence such as:
+//! - `always |pendulum/angle| < 5° after 3 s`: the condition holds at every step of its window;
+//! - `never |motor/torque| >= 20 for more than 0.5 s`: the condition holds for no longer than
+//!   that at a time (at no step at all, without `for more than`);
+//! - `eventually motor/angle > 1.5 within 2 s`: the condition holds at some step before the end
+//!   of that time, counted from the start of the window.
+//!
+//! A condition compares a telemetry channel, or its absolute value between bars, to a number,
+//! with `<`, `<=`, `>` or `>=`; angles can be given in degrees with `°` or `deg`. Comparisons
+//! combine with `and` and `or`, `and` first. The window of a requirement is the whole run unless
+//! it ends with `after 3 s`, `before 10 s` or `between 3 s and 10 s`; durations are in `s`, `ms`
+//! or `min`.
+//!
+//! The requirements are read from `monitors.json` and listed in the *Monitors* panel. Every
+//! violation is recorded with the simulated times it started and ended at; the runs of the
+//! experiment API and the headless runs report theirs.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    telemetry::Telemetry,
+};
+
+pub struct MonitorsPlugin;
+
+impl Plugin for MonitorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Telemetry>()
+            .init_resource::<Monitors>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_monitors
+                    .run_if(resource_exists_and_changed::<Persistent<MonitorSettings>>),
+            )
+            .add_systems(Last, monitor)
+            .add_systems(
+                Update,
+                monitors_panel
+                    .run_if(resource_exists::<Persistent<MonitorSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the monitors.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct MonitorSettings {
+    /// Requirements, one sentence each.
+    pub requirements: Vec<String>,
+}
+
+/// Comparison operator of a condition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A condition on the latest values of the telemetry channels.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Compare {
+        channel: String,
+        /// Whether the absolute value of the channel is compared.
+        absolute: bool,
+        comparison: Comparison,
+        value: f32,
+    },
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Whether the condition holds on the latest samples, unless a channel has none yet.
+    pub fn holds(&self, telemetry: &Telemetry) -> Option<bool> {
+        match self {
+            Condition::Compare {
+                channel,
+                absolute,
+                comparison,
+                value,
+            } => {
+                let latest = telemetry.latest(channel)?;
+                let latest = if *absolute { latest.abs() } else { latest };
+                Some(match comparison {
+                    Comparison::Less => latest < *value,
+                    Comparison::LessOrEqual => latest <= *value,
+                    Comparison::Greater => latest > *value,
+                    Comparison::GreaterOrEqual => latest >= *value,
+                })
+            }
+            Condition::And(a, b) => Some(a.holds(telemetry)? && b.holds(telemetry)?),
+            Condition::Or(a, b) => Some(a.holds(telemetry)? || b.holds(telemetry)?),
+        }
+    }
+}
+
+/// Temporal operator of a requirement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Temporal {
+    Always,
+    /// The condition holds for no longer than `tolerance` seconds at a time.
+    Never {
+        tolerance: f32,
+    },
+    /// The condition holds within `within` seconds of the start of the window.
+    Eventually {
+        within: f32,
+    },
+}
+
+/// A parsed requirement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Requirement {
+    pub temporal: Temporal,
+    pub condition: Condition,
+    /// Simulated times the requirement applies between, in seconds.
+    pub from: f32,
+    pub until: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f32),
+    Bar,
+    Degree,
+    Comparison(Comparison),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '|' => {
+                chars.next();
+                tokens.push(Token::Bar);
+            }
+            '°' => {
+                chars.next();
+                tokens.push(Token::Degree);
+            }
+            '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Comparison(match (c, or_equal) {
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('>', false) => Comparison::Greater,
+                    _ => Comparison::GreaterOrEqual,
+                }));
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| {
+                    c.is_ascii_digit() || *c == '.' || (*c == '-' && number.is_empty())
+                }) {
+                    number.push(c);
+                }
+                let value = number
+                    .parse()
+                    .map_err(|_| format!("`{number}` isn't a number"))?;
+                tokens.push(Token::Number(value));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"|<>°".contains(*c)) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consumes the word `word` if it comes next.
+    fn word(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(next)) if next == word);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.word(word) {
+            Ok(())
+        } else {
+            Err(format!("expected `{word}`"))
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            _ => Err("expected a number".to_string()),
+        }
+    }
+
+    /// A duration, in seconds.
+    fn duration(&mut self) -> Result<f32, String> {
+        let value = self.number()?;
+        Ok(if self.word("ms") {
+            value / 1000.0
+        } else if self.word("min") {
+            value * 60.0
+        } else {
+            self.word("s");
+            value
+        })
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut condition = self.conjunction()?;
+        while self.word("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+        }
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Condition, String> {
+        let mut condition = self.comparison()?;
+        while self.word("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.comparison()?));
+        }
+        Ok(condition)
+    }
+
+    fn comparison(&mut self) -> Result<Condition, String> {
+        let absolute = self.peek() == Some(&Token::Bar);
+        if absolute {
+            self.position += 1;
+        }
+        let channel = match self.next() {
+            Some(Token::Word(channel)) => channel,
+            _ => return Err("expected a telemetry channel".to_string()),
+        };
+        if absolute && self.next() != Some(Token::Bar) {
+            return Err(format!("expected `|` after `{channel}`"));
+        }
+        let Some(Token::Comparison(comparison)) = self.next() else {
+            return Err(format!("expected <, <=, > or >= after `{channel}`"));
+        };
+        let mut value = self.number()?;
+        if self.peek() == Some(&Token::Degree) || self.word("deg") {
+            if self.peek() == Some(&Token::Degree) {
+                self.position += 1;
+            }
+            value = value * PI / 180.0;
+        } else {
+            self.word("rad");
+        }
+        Ok(Condition::Compare {
+            channel,
+            absolute,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl Requirement {
+    /// Parses a requirement, e.g. `always |pendulum/angle| < 5° after 3 s`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let temporal = match parser.next() {
+            Some(Token::Word(word)) if word == "always" => Temporal::Always,
+            Some(Token::Word(word)) if word == "never" => Temporal::Never { tolerance: 0.0 },
+            Some(Token::Word(word)) if word == "eventually" => Temporal::Eventually { within: 0.0 },
+            _ => return Err("expected `always`, `never` or `eventually`".to_string()),
+        };
+        let condition = parser.condition()?;
+        let temporal = match temporal {
+            Temporal::Never { .. } if parser.word("for") => {
+                parser.expect("more")?;
+                parser.expect("than")?;
+                Temporal::Never {
+                    tolerance: parser.duration()?,
+                }
+            }
+            Temporal::Eventually { .. } => {
+                parser.expect("within")?;
+                Temporal::Eventually {
+                    within: parser.duration()?,
+                }
+            }
+            temporal => temporal,
+        };
+        let (mut from, mut until) = (0.0, f32::INFINITY);
+        if parser.word("after") {
+            from = parser.duration()?;
+        } else if parser.word("before") {
+            until = parser.duration()?;
+        } else if parser.word("between") {
+            from = parser.duration()?;
+            parser.expect("and")?;
+            until = parser.duration()?;
+        }
+        match parser.peek() {
+            Some(Token::Word(word)) => return Err(format!("unexpected `{word}`")),
+            Some(Token::Number(value)) => return Err(format!("unexpected `{value}`")),
+            Some(_) => return Err("unexpected symbol".to_string()),
+            None => {}
+        }
+        Ok(Self {
+            temporal,
+            condition,
+            from,
+            until,
+        })
+    }
+}
+
+/// A violation of a requirement.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Violation {
+    /// The requirement, as written.
+    pub requirement: String,
+    /// Simulated time the violation started at, in seconds.
+    pub start: f32,
+    /// Simulated time it ended at, once it did.
+    pub end: Option<f32>,
+}
+
+/// A requirement and the state of its monitoring.
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    pub text: String,
+    pub requirement: Requirement,
+    /// Time the condition started holding at, for `never`.
+    since: Option<f32>,
+    /// Whether the condition held, for `eventually`.
+    satisfied: bool,
+    /// Index of the violation in progress.
+    open: Option<usize>,
+}
+
+impl Monitor {
+    /// Whether the requirement is violated at `time`, given whether its condition holds.
+    fn violated(&mut self, time: f32, holds: bool) -> bool {
+        let requirement = &self.requirement;
+        let within = (requirement.from..=requirement.until).contains(&time);
+        match requirement.temporal {
+            Temporal::Always => within && !holds,
+            Temporal::Never { tolerance } => {
+                if !(within && holds) {
+                    self.since = None;
+                    return false;
+                }
+                let since = *self.since.get_or_insert(time);
+                time - since > tolerance || tolerance == 0.0
+            }
+            Temporal::Eventually { within: deadline } => {
+                if within && holds {
+                    self.satisfied = true;
+                }
+                // Reported once, when the deadline passes.
+                !self.satisfied && time > requirement.from + deadline && self.open.is_none()
+            }
+        }
+    }
+}
+
+/// The monitored requirements and their violations.
+#[derive(Debug, Default, Resource)]
+pub struct Monitors {
+    pub monitors: Vec<Monitor>,
+    pub violations: Vec<Violation>,
+    /// Simulated time of the last step monitored.
+    last: Option<f32>,
+}
+
+impl Monitors {
+    /// Monitors the requirements that parse, returning the errors of the others.
+    pub fn new(requirements: &[String]) -> (Self, Vec<String>) {
+        let mut monitors = Vec::new();
+        let mut errors = Vec::new();
+        for text in requirements {
+            match Requirement::parse(text) {
+                Ok(requirement) => monitors.push(Monitor {
+                    text: text.clone(),
+                    requirement,
+                    since: None,
+                    satisfied: false,
+                    open: None,
+                }),
+                Err(error) => errors.push(format!("`{text}`: {error}")),
+            }
+        }
+        (
+            Self {
+                monitors,
+                ..default()
+            },
+            errors,
+        )
+    }
+
+    /// Checks the requirements on the telemetry of the step simulated up to `time`.
+    pub fn update(&mut self, time: f32, telemetry: &Telemetry) {
+        match self.last {
+            Some(last) if last == time => return,
+            // The simulated time restarts when the session is rewound.
+            Some(last) if last > time => self.restart(time),
+            _ => {}
+        }
+        self.last = Some(time);
+        for monitor in &mut self.monitors {
+            let Some(holds) = monitor.requirement.condition.holds(telemetry) else {
+                continue;
+            };
+            let violated = monitor.violated(time, holds);
+            match monitor.open {
+                None if violated => {
+                    warn!(
+                        target: subsystem::CONTROL,
+                        "Requirement `{}` violated at {time:.3} s", monitor.text
+                    );
+                    monitor.open = Some(self.violations.len());
+                    self.violations.push(Violation {
+                        requirement: monitor.text.clone(),
+                        start: time,
+                        end: None,
+                    });
+                }
+                Some(index) if !violated => {
+                    // A missed deadline stays violated.
+                    if !matches!(monitor.requirement.temporal, Temporal::Eventually { .. }) {
+                        self.violations[index].end = Some(time);
+                        monitor.open = None;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Forgets the violations from `time` on.
+    fn restart(&mut self, time: f32) {
+        self.violations.retain(|violation| violation.start < time);
+        for violation in &mut self.violations {
+            if violation.end.is_none_or(|end| end > time) {
+                violation.end = Some(time);
+            }
+        }
+        for monitor in &mut self.monitors {
+            monitor.since = None;
+            monitor.satisfied = false;
+            monitor.open = None;
+        }
+    }
+
+    /// The violations overlapping the span from `start` to `end`.
+    pub fn between(&self, start: f32, end: f32) -> Vec<Violation> {
+        self.violations
+            .iter()
+            .filter(|violation| {
+                violation.start <= end && violation.end.is_none_or(|stop| stop >= start)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Number of violations of the `index`th monitor.
+    pub fn count(&self, index: usize) -> usize {
+        let text = &self.monitors[index].text;
+        self.violations
+            .iter()
+            .filter(|violation| violation.requirement == *text)
+            .count()
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<MonitorSettings>("monitors", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Monitors the requirements of the settings, from scratch.
+fn configure_monitors(
+    mut commands: Commands,
+    settings: Res<Persistent<MonitorSettings>>,
+    mut monitors: ResMut<Monitors>,
+) {
+    let (configured, errors) = Monitors::new(&settings.requirements);
+    *monitors = configured;
+    for message in errors {
+        commands.send_event(ErrorEvent::from(Error::Config {
+            name: "monitors".to_string(),
+            message,
+        }));
+    }
+}
+
+fn monitor(clock: Res<SimClock>, telemetry: Res<Telemetry>, mut monitors: ResMut<Monitors>) {
+    monitors.update(clock.elapsed_secs(), &telemetry);
+}
+
+/// Panel listing the requirements and their violations.
+fn monitors_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<MonitorSettings>>,
+    monitors: Res<Monitors>,
+    mut draft: Local<String>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Monitors")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut removed = None;
+            for (index, monitor) in monitors.monitors.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let count = monitors.count(index);
+                    if count == 0 {
+                        ui.label("OK");
+                    } else {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("{count} ✖"));
+                    }
+                    ui.label(monitor.text.as_str());
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(monitor.text.clone());
+                    }
+                });
+            }
+            if let Some(text) = removed {
+                edited
+                    .requirements
+                    .retain(|requirement| *requirement != text);
+            }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut *draft);
+                let parsed = Requirement::parse(&draft);
+                if ui
+                    .add_enabled(parsed.is_ok(), egui::Button::new("Add"))
+                    .clicked()
+                {
+                    edited.requirements.push(std::mem::take(&mut *draft));
+                }
+                if let (Err(error), false) = (parsed, draft.is_empty()) {
+                    ui.weak(error);
+                }
+            });
+
+            ui.separator();
+            egui::CollapsingHeader::new(format!("Violations ({})", monitors.violations.len()))
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for violation in &monitors.violations {
+                                let end = violation
+                                    .end
+                                    .map_or("…".to_string(), |end| format!("{end:.3} s"));
+                                ui.label(format!(
+                                    "{:.3} s to {end}: {}",
+                                    violation.start, violation.requirement
+                                ));
+                            }
+                        });
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("monitors", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("monitors", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,902 @@
+//! This module balances the pendulum upright with a model predictive controller ([`Mpc`]).
+//!
+//! It complements the [LQR](crate::lqr) regulator on the same linearized model, state and
+//! input: the acceleration of the arm, integrated into the velocity commanded to the motor.
+//! Each physics step, the controller plans the accelerations over a finite horizon minimizing
+//! the same quadratic cost, with the cost to go of the regulator at its end, within the
+//! acceleration limit of the arm, and applies the first one. Where the regulator clips its
+//! command at the limit, the controller plans around it, e.g. braking earlier.
+//!
+//! The plan is warm-started from the last one, so the solver converges in a few iterations and
+//! keeps up with the fixed time step of the physics. The solver is configurable:
+//!
+//! - `projected_gradient`, the default: the cheapest, but it only bounds the acceleration,
+//! - `interior_point`: a dense primal-dual method in pure Rust, which also keeps the arm within
+//!   `arm_limit` of where the pendulum was caught, and tells when it can't,
+//! - `osqp`: the OSQP library, in native builds with the `osqp` feature.
+//!
+//! When the arm can't be kept within its limit, the controller plans without it for the step,
+//! and warns. Like the regulator, the controller only
+//! catches the pendulum within its capture angle of upright, waits for the swing-up to hand
+//! over with a [`ModeSwitch`], and gives way to a regulator on the same joint.
+//!
+//! The `nonlinear` variant ([`Nmpc`]) plans on the nonlinear model of the pendulum instead,
+//! over intervals of a few physics steps, so it swings the pendulum up as well as it balances
+//! it, within the same limits, without a capture angle. Its iterations and solve times are
+//! recorded in the telemetry, like those of the linear variant.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use egui_plot::{HLine, Line, Plot, PlotPoints};
+use nalgebra::{DMatrix, DVector, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{
+        self, InteriorPoint, Mpc, Nmpc, ProjectedGradient, QpSolver, QpStatus, RotaryPendulum,
+    },
+    error::{Error, ErrorEvent, Result},
+    estimation::{self, EstimationSet, JointEstimate},
+    headless::DEFAULT_TIME_STEP,
+    latency::LoopLatency,
+    logging::subsystem,
+    lqr::{self, LqrController, LqrSettings},
+    plants::{self, Link, MOTOR_FACTOR},
+    sensors::{ControlSet, Encoder, SensorSet},
+    swing_up::{Mode, ModeSwitch},
+    telemetry::Telemetry,
+};
+
+/// Angle of the pendulum from upright, in rad.
+pub const MPC_ANGLE: &str = "mpc/angle";
+/// Acceleration of the arm commanded by the controller, in rad/s².
+pub const MPC_ACCELERATION: &str = "mpc/acceleration";
+/// 1 while the arm can't be kept within its limit, 0 otherwise.
+pub const MPC_INFEASIBLE: &str = "mpc/infeasible";
+/// Iterations of the last solve: of the solver in the linear variant, of the SQP in the
+/// nonlinear one.
+pub const MPC_ITERATIONS: &str = "mpc/iterations";
+/// Duration of the last solve, in ms.
+pub const MPC_SOLVE_TIME: &str = "mpc/solve_time";
+
+pub struct MpcPlugin;
+
+impl Plugin for MpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MpcPlan>()
+            .init_resource::<NonlinearPlan>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (solve_plan, configure_controllers)
+                    .run_if(resource_exists_and_changed::<Persistent<MpcSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                mpc_panel
+                    .run_if(resource_exists::<Persistent<MpcSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Solver of the quadratic programs of the controllers.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MpcSolver {
+    #[default]
+    ProjectedGradient,
+    InteriorPoint,
+    Osqp,
+}
+
+impl MpcSolver {
+    pub const ALL: [Self; 3] = [Self::ProjectedGradient, Self::InteriorPoint, Self::Osqp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ProjectedGradient => "Projected gradient",
+            Self::InteriorPoint => "Interior point",
+            Self::Osqp => "OSQP",
+        }
+    }
+
+    /// A solver of this kind, unless this build lacks it.
+    pub fn build(self) -> Option<Box<dyn QpSolver>> {
+        match self {
+            Self::ProjectedGradient => Some(Box::new(ProjectedGradient::default())),
+            Self::InteriorPoint => Some(Box::new(InteriorPoint::default())),
+            #[cfg(all(feature = "osqp", not(target_arch = "wasm32")))]
+            Self::Osqp => Some(Box::new(control::Osqp::new(4000, 1e-7))),
+            #[cfg(not(all(feature = "osqp", not(target_arch = "wasm32"))))]
+            Self::Osqp => None,
+        }
+    }
+}
+
+/// Model the controllers plan on.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MpcVariant {
+    /// Linearized upright, balancing the pendulum once caught.
+    #[default]
+    Linear,
+    /// Nonlinear, swinging the pendulum up and balancing it.
+    Nonlinear,
+}
+
+impl MpcVariant {
+    pub const ALL: [Self; 2] = [Self::Linear, Self::Nonlinear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear, balancing",
+            Self::Nonlinear => "Nonlinear, swinging up",
+        }
+    }
+}
+
+/// Represents the configuration of the nonlinear variant of the controllers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct NonlinearMpcSettings {
+    /// Number of intervals planned, and physics steps over which each holds its acceleration.
+    pub horizon: usize,
+    pub interval: usize,
+    /// Diagonal of the weight of the state, and at the end of the plan, and weight of the input.
+    pub q: [f64; 4],
+    pub terminal: [f64; 4],
+    pub r: f64,
+    /// Iterations of the SQP a solve at most.
+    pub iterations: usize,
+}
+
+impl Default for NonlinearMpcSettings {
+    fn default() -> Self {
+        Self {
+            horizon: 40,
+            interval: 3,
+            q: [1.0, 0.1, 10.0, 1.0],
+            terminal: [10.0, 1.0, 100.0, 10.0],
+            r: 0.1,
+            iterations: 3,
+        }
+    }
+}
+
+/// Represents the configuration of the model predictive controllers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct MpcSettings {
+    pub enabled: bool,
+    /// Names of the links whose joints are the motor and the pivot of the pendulum, in each
+    /// plant.
+    pub motor: String,
+    pub pendulum: String,
+    /// Continuous-time model `x' = A x + B u` linearized around upright, by rows.
+    pub a: [[f64; 4]; 4],
+    pub b: [f64; 4],
+    /// Diagonal of the weight of the state, and weight of the input.
+    pub q: [f64; 4],
+    pub r: f64,
+    /// Number of physics steps planned.
+    pub horizon: usize,
+    /// Largest angle from upright at which the pendulum is caught, in rad.
+    pub capture: f32,
+    /// Largest acceleration of the arm planned, in rad/s².
+    pub limit: f32,
+    pub solver: MpcSolver,
+    /// Largest angle of the arm from where the pendulum was caught, in rad, if any.
+    pub arm_limit: Option<f32>,
+    pub variant: MpcVariant,
+    pub nonlinear: NonlinearMpcSettings,
+}
+
+impl Default for MpcSettings {
+    fn default() -> Self {
+        let (a, b) = lqr::pendulum_model(2.0, 4.93, 4.0, 9.81);
+        Self {
+            enabled: false,
+            motor: "motor".to_string(),
+            pendulum: "pivot".to_string(),
+            a,
+            b,
+            q: [1.0, 0.1, 10.0, 1.0],
+            r: 1.0,
+            horizon: 20,
+            capture: 0.3,
+            limit: 50.0,
+            solver: MpcSolver::ProjectedGradient,
+            arm_limit: None,
+            variant: MpcVariant::Linear,
+            nonlinear: NonlinearMpcSettings::default(),
+        }
+    }
+}
+
+impl MpcSettings {
+    /// Controller of the model discretized with a time step of `dt` seconds.
+    pub fn solve(&self, dt: f32) -> Result<Mpc> {
+        let a = DMatrix::from_fn(4, 4, |row, column| self.a[row][column]);
+        let b = DMatrix::from_column_slice(4, 1, &self.b);
+        let q = DMatrix::from_diagonal(&DVector::from_column_slice(&self.q));
+        let r = DMatrix::from_element(1, 1, self.r);
+        if self.q.iter().any(|weight| *weight < 0.0) || self.r <= 0.0 {
+            return Err(invalid(
+                "the weights of the state must be positive, and of the input strictly",
+            ));
+        }
+        if self.horizon == 0 || self.limit < 0.0 || self.arm_limit.is_some_and(|arm| arm <= 0.0) {
+            return Err(invalid(
+                "the horizon must be a step at least, and the limits positive",
+            ));
+        }
+        let solver = self.build_solver()?;
+        let (a, b) = control::discretize(&a, &b, f64::from(dt));
+        let limit = f64::from(self.limit);
+        let mpc = Mpc::new(&a, &b, &q, &r, self.horizon, &[-limit], &[limit]).ok_or_else(|| {
+            invalid("the Riccati equation doesn't converge: is the model stabilizable?")
+        })?;
+        let mpc = mpc
+            .with_solver(solver)
+            .map_err(|message| invalid(&message))?;
+        let Some(arm) = self.arm_limit.map(f64::from) else {
+            return Ok(mpc);
+        };
+        let free = f64::INFINITY;
+        mpc.with_state_bounds(&[-arm, -free, -free, -free], &[arm, free, free, free])
+            .map_err(|message| {
+                invalid(&format!(
+                    "the arm limit needs the interior point or OSQP solver: {message}"
+                ))
+            })
+    }
+
+    /// Nonlinear controller of the model, its intervals lasting `dt` seconds per physics step.
+    /// The model linearized upright gives the gravity and coupling terms of the pendulum.
+    pub fn solve_nonlinear(&self, dt: f32) -> Result<Nmpc> {
+        let settings = &self.nonlinear;
+        let model = RotaryPendulum {
+            gravity: self.a[3][2],
+            coupling: self.b[3],
+        };
+        let interval = f64::from(dt) * settings.interval as f64;
+        let nmpc = Nmpc::new(
+            model,
+            settings.horizon,
+            interval,
+            [settings.q, settings.terminal],
+            settings.r,
+            f64::from(self.limit),
+        )
+        .filter(|_| settings.iterations > 0)
+        .ok_or_else(|| {
+            invalid(
+                "the weights of the nonlinear variant must be positive, its horizon, intervals \
+                 and iterations at least one, and the acceleration limit positive",
+            )
+        })?;
+        let mut nmpc = nmpc
+            .with_solver(self.build_solver()?)
+            .map_err(|message| invalid(&message))?;
+        nmpc.max_iterations = settings.iterations;
+        let Some(arm) = self.arm_limit else {
+            return Ok(nmpc);
+        };
+        nmpc.with_arm_limit(f64::from(arm)).map_err(|message| {
+            invalid(&format!(
+                "the arm limit needs the interior point or OSQP solver: {message}"
+            ))
+        })
+    }
+
+    fn build_solver(&self) -> Result<Box<dyn QpSolver>> {
+        self.solver.build().ok_or_else(|| {
+            invalid(&format!(
+                "this build has no {} solver: build it with the osqp feature",
+                self.solver.name()
+            ))
+        })
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Config {
+        name: "mpc".to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// The controller of the current settings, if they're valid.
+#[derive(Default, Resource)]
+pub struct MpcPlan(pub Option<Mpc>);
+
+/// The nonlinear controller of the current settings, if they're valid and select it.
+#[derive(Default, Resource)]
+pub struct NonlinearPlan(pub Option<Nmpc>);
+
+/// A model predictive controller on the motor joint of its entity, balancing the pendulum on
+/// the joint of `pendulum`.
+#[derive(Clone, Component, Debug)]
+pub struct MpcController {
+    pub pendulum: Entity,
+    /// Telemetry channels of the angle from upright, of the acceleration commanded, of the
+    /// arm limit dropped, and of the iterations and duration of the solves.
+    pub angle: String,
+    pub acceleration: String,
+    pub infeasible: String,
+    pub iterations: String,
+    pub solve_time: String,
+    /// The controller of this joint, linear or not, with its own plan to warm-start from.
+    mpc: Option<Mpc>,
+    nonlinear: Option<Nmpc>,
+    /// Acceleration of the nonlinear controller, and physics steps left to hold it.
+    held: f32,
+    countdown: usize,
+    /// Duration of the last solve, if the last step solved.
+    solved: Option<Duration>,
+    /// Last angles of the motor and pendulum joints, within a turn.
+    previous: Option<[f32; 2]>,
+    /// Angle of the arm since the pendulum was caught, and its velocity commanded.
+    arm: f32,
+    velocity: f32,
+    engaged: bool,
+}
+
+impl MpcController {
+    /// A controller with the signals of a plant.
+    pub fn new(pendulum: Entity, plant: &str) -> Self {
+        Self {
+            pendulum,
+            angle: plants::namespaced(plant, MPC_ANGLE),
+            acceleration: plants::namespaced(plant, MPC_ACCELERATION),
+            infeasible: plants::namespaced(plant, MPC_INFEASIBLE),
+            iterations: plants::namespaced(plant, MPC_ITERATIONS),
+            solve_time: plants::namespaced(plant, MPC_SOLVE_TIME),
+            mpc: None,
+            nonlinear: None,
+            held: 0.0,
+            countdown: 0,
+            solved: None,
+            previous: None,
+            arm: 0.0,
+            velocity: 0.0,
+            engaged: false,
+        }
+    }
+
+    /// Whether the pendulum is within the capture angle and balanced.
+    pub fn engaged(&self) -> bool {
+        self.engaged
+    }
+
+    /// The accelerations planned over the horizon by the last solve, in rad/s², per physics
+    /// step of the linear variant or per interval of the nonlinear one.
+    pub fn plan(&self) -> Vec<f64> {
+        match (&self.nonlinear, &self.mpc) {
+            (Some(nmpc), _) => nmpc.plan().iter().copied().collect(),
+            (None, Some(mpc)) => mpc.plan().iter().copied().collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Iterations of the last solve: of the solver in the linear variant, of the SQP in the
+    /// nonlinear one.
+    pub fn iterations(&self) -> usize {
+        match (&self.nonlinear, &self.mpc) {
+            (Some(nmpc), _) => nmpc.iterations(),
+            (None, Some(mpc)) => mpc.iterations(),
+            (None, None) => 0,
+        }
+    }
+
+    /// Duration of the solve of the last step, if it solved: the nonlinear variant only solves
+    /// once per interval.
+    pub fn solve_time(&self) -> Option<Duration> {
+        self.solved
+    }
+
+    /// Whether the last solve couldn't keep the arm within its limit.
+    pub fn infeasible(&self) -> bool {
+        let status = match (&self.nonlinear, &self.mpc) {
+            (Some(nmpc), _) => nmpc.status(),
+            (None, Some(mpc)) => mpc.status(),
+            (None, None) => QpStatus::Solved,
+        };
+        self.engaged && status == QpStatus::Infeasible
+    }
+
+    /// Measures the angles of the joints without balancing, while another controller drives
+    /// the motor.
+    pub fn observe(&mut self, angles: [f32; 2]) {
+        self.previous = Some(angles);
+        self.engaged = false;
+        self.solved = None;
+    }
+
+    /// Acceleration of the arm and velocity to command for the next `dt` seconds, from the
+    /// angles of the motor and pendulum joints, while the pendulum is caught. Each catch
+    /// starts over from the controller `mpc`, without a plan.
+    pub fn update(
+        &mut self,
+        mpc: &Mpc,
+        settings: &MpcSettings,
+        [motor, pendulum]: [f32; 2],
+        dt: f32,
+    ) -> Option<[f32; 2]> {
+        self.solved = None;
+        self.nonlinear = None;
+        let Some([last_motor, last_pendulum]) = self.previous.replace([motor, pendulum]) else {
+            return None;
+        };
+        let step = wrap(motor - last_motor);
+        let angle = wrap(pendulum - PI);
+        if angle.abs() > settings.capture {
+            self.engaged = false;
+            return None;
+        }
+        let controller = match &mut self.mpc {
+            Some(own) if self.engaged => own,
+            own => own.insert(mpc.clone()),
+        };
+        if !self.engaged {
+            self.engaged = true;
+            self.arm = 0.0;
+            self.velocity = step / dt;
+        } else {
+            self.arm += step;
+        }
+        let state = DVector::from_vec(vec![
+            f64::from(self.arm),
+            f64::from(step / dt),
+            f64::from(angle),
+            f64::from(wrap(pendulum - last_pendulum) / dt),
+        ]);
+        let start = Instant::now();
+        let acceleration = controller.command(&state)[0] as f32;
+        self.solved = Some(start.elapsed());
+        self.velocity += acceleration * dt;
+        Some([acceleration, self.velocity])
+    }
+
+    /// Acceleration of the arm and velocity to command for the next `dt` seconds, from the
+    /// angles of the motor and pendulum joints, with the nonlinear controller `nmpc`: from any
+    /// angle, solving once per interval of its plan and holding the acceleration in between.
+    /// The arm limit is from where the controller took over.
+    pub fn update_nonlinear(
+        &mut self,
+        nmpc: &Nmpc,
+        settings: &MpcSettings,
+        [motor, pendulum]: [f32; 2],
+        dt: f32,
+    ) -> Option<[f32; 2]> {
+        self.solved = None;
+        self.mpc = None;
+        let Some([last_motor, last_pendulum]) = self.previous.replace([motor, pendulum]) else {
+            return None;
+        };
+        let step = wrap(motor - last_motor);
+        let controller = match &mut self.nonlinear {
+            Some(own) if self.engaged => own,
+            own => own.insert(nmpc.clone()),
+        };
+        if !self.engaged {
+            self.engaged = true;
+            self.arm = 0.0;
+            self.velocity = step / dt;
+            self.countdown = 0;
+        } else {
+            self.arm += step;
+        }
+        if self.countdown == 0 {
+            let state = Vector4::new(
+                f64::from(self.arm),
+                f64::from(step / dt),
+                f64::from(wrap(pendulum - PI)),
+                f64::from(wrap(pendulum - last_pendulum) / dt),
+            );
+            let start = Instant::now();
+            self.held = controller.command(&state) as f32;
+            self.solved = Some(start.elapsed());
+            self.countdown = settings.nonlinear.interval.max(1);
+        }
+        self.countdown -= 1;
+        self.velocity += self.held * dt;
+        Some([self.held, self.velocity])
+    }
+
+    /// Replaces the controller by that of new settings, keeping the pendulum caught.
+    fn replan(&mut self, mpc: Option<&Mpc>, nmpc: Option<&Nmpc>) {
+        self.mpc = mpc.cloned();
+        self.nonlinear = nmpc.cloned();
+        if nmpc.is_none() && self.mpc.is_none() {
+            self.engaged = false;
+        }
+    }
+}
+
+/// Angle within `(-π, π]`.
+fn wrap(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<MpcSettings>("mpc", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Sets up the controller of the variant selected at the time step of the physics.
+fn solve_plan(
+    mut commands: Commands,
+    settings: Res<Persistent<MpcSettings>>,
+    timestep_mode: Option<Res<TimestepMode>>,
+    mut plan: ResMut<MpcPlan>,
+    mut nonlinear: ResMut<NonlinearPlan>,
+) {
+    let dt = match timestep_mode.as_deref() {
+        Some(TimestepMode::Fixed { dt, .. } | TimestepMode::Interpolated { dt, .. }) => *dt,
+        _ => DEFAULT_TIME_STEP,
+    };
+    plan.0 = None;
+    nonlinear.0 = None;
+    let solved = match settings.variant {
+        MpcVariant::Linear => settings.solve(dt).map(|mpc| {
+            debug!(target: subsystem::CONTROL, "MPC over {} steps", mpc.horizon);
+            plan.0 = Some(mpc);
+        }),
+        MpcVariant::Nonlinear => settings.solve_nonlinear(dt).map(|nmpc| {
+            debug!(
+                target: subsystem::CONTROL,
+                "Nonlinear MPC over {} intervals of {:.3} s", nmpc.horizon, nmpc.interval
+            );
+            nonlinear.0 = Some(nmpc);
+        }),
+    };
+    if let Err(error) = solved {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers on the motor joints of the plants with a pendulum joint.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<MpcSettings>>,
+    joints: Query<(Entity, &Link, Option<&MpcController>), With<ImpulseJoint>>,
+) {
+    for (entity, link, controller) in &joints {
+        let pendulum = joints
+            .iter()
+            .find(|(_, other, _)| other.plant == link.plant && other.name == settings.pendulum)
+            .map(|(pendulum, _, _)| pendulum);
+        let controlled = settings.enabled && link.name == settings.motor;
+        match (controller, pendulum) {
+            (Some(_), _) if controlled => {}
+            (Some(_), _) => {
+                commands.entity(entity).remove::<MpcController>();
+                info!(target: subsystem::CONTROL, "MPC of {} removed", link.path());
+            }
+            (None, Some(pendulum)) if controlled => {
+                commands
+                    .entity(entity)
+                    .insert(MpcController::new(pendulum, &link.plant));
+                info!(target: subsystem::CONTROL, "MPC of {} added", link.path());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step and commands the motors for the next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    clock: Res<SimClock>,
+    settings: Option<Res<Persistent<MpcSettings>>>,
+    plan: Res<MpcPlan>,
+    nonlinear: Res<NonlinearPlan>,
+    mut controllers: Query<(
+        Entity,
+        &mut MpcController,
+        &mut ImpulseJoint,
+        Option<&ModeSwitch>,
+        Has<LqrController>,
+    )>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_mpc").entered();
+    let (dt, Some(settings), Ok(context)) = (clock.delta_secs(), settings, contexts.get_single())
+    else {
+        return;
+    };
+    if dt <= 0.0 || (plan.0.is_none() && nonlinear.0.is_none()) {
+        return;
+    }
+    for (entity, mut controller, mut joint, switch, regulated) in &mut controllers {
+        if plan.is_changed() || nonlinear.is_changed() {
+            controller.replan(plan.0.as_ref(), nonlinear.0.as_ref());
+        }
+        let motor = estimation::joint_angle(context, &encoders, &latencies, &estimates, entity);
+        let pendulum = estimation::joint_angle(
+            context,
+            &encoders,
+            &latencies,
+            &estimates,
+            controller.pendulum,
+        );
+        let angles = motor.zip(pendulum);
+        let Some([motor, pendulum]) = angles.map(<[f32; 2]>::from) else {
+            continue;
+        };
+        // Give way to a regulator on the same joint, and wait for the swing-up to hand over.
+        if regulated || switch.is_some_and(|switch| switch.mode() != Mode::Balance) {
+            controller.observe([motor, pendulum]);
+            continue;
+        }
+        let engaged = controller.engaged();
+        let infeasible = controller.infeasible();
+        let command = match (&plan.0, &nonlinear.0) {
+            (Some(mpc), _) => controller.update(mpc, &settings, [motor, pendulum], dt),
+            (None, Some(nmpc)) => {
+                controller.update_nonlinear(nmpc, &settings, [motor, pendulum], dt)
+            }
+            (None, None) => None,
+        };
+        if let (Some(solve_time), Some(telemetry)) = (controller.solve_time(), telemetry.as_mut()) {
+            let iterations = controller.iterations() as f32;
+            telemetry.record(&controller.iterations, clock.elapsed_secs(), iterations);
+            let milliseconds = solve_time.as_secs_f32() * 1e3;
+            telemetry.record(&controller.solve_time, clock.elapsed_secs(), milliseconds);
+        }
+        if infeasible != controller.infeasible() {
+            if controller.infeasible() {
+                warn!(
+                    target: subsystem::CONTROL,
+                    "The arm can't be kept within its limit at {:.2} s, planning without it",
+                    clock.elapsed_secs()
+                );
+            }
+            if let Some(telemetry) = telemetry.as_mut() {
+                let value = if controller.infeasible() { 1.0 } else { 0.0 };
+                telemetry.record(&controller.infeasible, clock.elapsed_secs(), value);
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&controller.angle, clock.elapsed_secs(), wrap(pendulum - PI));
+        }
+        let velocity = match command {
+            Some([acceleration, velocity]) => {
+                if let Some(telemetry) = telemetry.as_mut() {
+                    telemetry.record(&controller.acceleration, clock.elapsed_secs(), acceleration);
+                }
+                velocity
+            }
+            // Stop the arm once the pendulum falls out of reach.
+            None if engaged => 0.0,
+            None => continue,
+        };
+        if engaged != controller.engaged() {
+            let state = if engaged { "lost" } else { "caught" };
+            info!(target: subsystem::CONTROL, "Pendulum {state} at {:.2} s", clock.elapsed_secs());
+        }
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Panel to tune the horizon, weights and limit of the controllers, and show their plans.
+fn mpc_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<MpcSettings>>,
+    lqr_settings: Option<Res<Persistent<LqrSettings>>>,
+    plan: Res<MpcPlan>,
+    nonlinear: Res<NonlinearPlan>,
+    controllers: Query<&MpcController>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("MPC controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Balance the pendulum upright");
+            egui::ComboBox::from_label("Variant")
+                .selected_text(edited.variant.name())
+                .show_ui(ui, |ui| {
+                    for variant in MpcVariant::ALL {
+                        ui.selectable_value(&mut edited.variant, variant, variant.name());
+                    }
+                });
+            ui.label("Model, linearized upright");
+            egui::Grid::new("mpc_model").show(ui, |ui| {
+                for (row, b) in edited.a.iter_mut().zip(&mut edited.b) {
+                    ui.label("A:");
+                    for value in row {
+                        ui.add(egui::DragValue::new(value).speed(0.01));
+                    }
+                    ui.label("B:");
+                    ui.add(egui::DragValue::new(b).speed(0.01));
+                    ui.end_row();
+                }
+            });
+            if let Some(lqr_settings) = &lqr_settings {
+                if ui.button("Use the model of the LQR controller").clicked() {
+                    edited.a = lqr_settings.a;
+                    edited.b = lqr_settings.b;
+                }
+            }
+
+            let (q, r) = match edited.variant {
+                MpcVariant::Linear => (&mut edited.q, &mut edited.r),
+                MpcVariant::Nonlinear => (&mut edited.nonlinear.q, &mut edited.nonlinear.r),
+            };
+            ui.label("Weights of the arm angle and velocity, pendulum angle and velocity");
+            ui.horizontal(|ui| {
+                for weight in q {
+                    ui.add(egui::DragValue::new(weight).range(0.0..=1000.0).speed(0.1));
+                }
+            });
+            ui.add(
+                egui::DragValue::new(r)
+                    .range(0.001..=1000.0)
+                    .speed(0.01)
+                    .prefix("Weight of the input: "),
+            );
+            match edited.variant {
+                MpcVariant::Linear => {
+                    ui.add(egui::Slider::new(&mut edited.horizon, 1..=100).text("Horizon (steps)"));
+                    ui.add(
+                        egui::DragValue::new(&mut edited.capture)
+                            .range(0.0..=PI)
+                            .speed(0.01)
+                            .prefix("Capture angle: ")
+                            .suffix(" rad"),
+                    );
+                }
+                MpcVariant::Nonlinear => nonlinear_settings(ui, &mut edited.nonlinear),
+            }
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Acceleration limit: ")
+                    .suffix(" rad/s²"),
+            );
+            ui.horizontal(|ui| {
+                let mut limited = edited.arm_limit.is_some();
+                ui.checkbox(&mut limited, "Arm limit");
+                let mut arm = edited.arm_limit.unwrap_or(PI);
+                ui.add_enabled(
+                    limited,
+                    egui::DragValue::new(&mut arm)
+                        .range(0.01..=TAU)
+                        .speed(0.01)
+                        .suffix(" rad"),
+                );
+                edited.arm_limit = limited.then_some(arm);
+            });
+            egui::ComboBox::from_label("Solver")
+                .selected_text(edited.solver.name())
+                .show_ui(ui, |ui| {
+                    for solver in MpcSolver::ALL {
+                        ui.selectable_value(&mut edited.solver, solver, solver.name());
+                    }
+                });
+            match (&plan.0, &nonlinear.0) {
+                (Some(mpc), _) => {
+                    ui.label(format!("Solved by the {}", mpc.solver()));
+                }
+                (None, Some(nmpc)) => {
+                    ui.label(format!(
+                        "Solved by SQP with the {}, over {:.2} s",
+                        nmpc.solver(),
+                        nmpc.interval * nmpc.horizon as f64
+                    ));
+                }
+                (None, None) => {
+                    ui.colored_label(egui::Color32::RED, "No controller: see the error log");
+                }
+            }
+
+            ui.separator();
+            for controller in &controllers {
+                let latest = |channel: &str| {
+                    telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(channel))
+                        .unwrap_or_default()
+                };
+                let state = if controller.infeasible() {
+                    "balanced past the arm limit"
+                } else if controller.engaged() {
+                    "balanced"
+                } else {
+                    "out of reach"
+                };
+                ui.label(format!(
+                    "{}: {state}, {:.3} rad from upright, {:.2} rad/s², {} iterations in {:.2} ms",
+                    controller.angle,
+                    latest(&controller.angle),
+                    latest(&controller.acceleration),
+                    controller.iterations(),
+                    latest(&controller.solve_time)
+                ));
+            }
+            if let Some(controller) = controllers.iter().find(|controller| controller.engaged()) {
+                let points: Vec<[f64; 2]> = controller
+                    .plan()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(step, acceleration)| [step as f64, acceleration])
+                    .collect();
+                let limit = f64::from(edited.limit);
+                let ahead = match edited.variant {
+                    MpcVariant::Linear => "Steps ahead",
+                    MpcVariant::Nonlinear => "Intervals ahead",
+                };
+                Plot::new("mpc_plan")
+                    .height(120.0)
+                    .x_axis_label(ahead)
+                    .y_axis_label("rad/s²")
+                    .show(ui, |plot| {
+                        plot.line(Line::new(PlotPoints::new(points)).name("Plan"));
+                        plot.hline(HLine::new(limit).name("Limit"));
+                        plot.hline(HLine::new(-limit).name("Limit"));
+                    });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("mpc", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("mpc", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Widgets of the terminal weights, horizon and iterations of the nonlinear variant.
+fn nonlinear_settings(ui: &mut egui::Ui, settings: &mut NonlinearMpcSettings) {
+    ui.label("Weights of the state at the end of the plan");
+    ui.horizontal(|ui| {
+        for weight in &mut settings.terminal {
+            ui.add(egui::DragValue::new(weight).range(0.0..=10000.0).speed(0.1));
+        }
+    });
+    ui.add(egui::Slider::new(&mut settings.horizon, 1..=100).text("Horizon (intervals)"));
+    ui.add(egui::Slider::new(&mut settings.interval, 1..=10).text("Interval (steps)"));
+    ui.add(egui::Slider::new(&mut settings.iterations, 1..=20).text("SQP iterations at most"));
+}
@@ -0,0 +1,129 @@
+//! Impairments of the network links of the datagram bridges (UDP packets and the fieldbus
+//! process image), to study networked control under realistic link conditions.
+//!
+//! Each direction of a bridge goes through a [`Link`]: a datagram is lost with the `loss`
+//! probability, otherwise it's delivered after the time to transmit it at the `bandwidth`, plus
+//! the `delay` and a uniform random `jitter`, so datagrams can arrive out of order. The
+//! impairments are read from `network.json`; the default is a perfect link. The random draws
+//! are seeded, so runs are reproducible.
+use std::time::{Duration, Instant};
+
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::latency::PathLatency;
+
+/// Conditions of one direction of a link.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    /// Probability that a datagram is lost, from 0 to 1.
+    pub loss: f32,
+    /// Latency of the link, in seconds.
+    pub delay: f32,
+    /// Largest random latency added to the delay, in seconds.
+    pub jitter: f32,
+    /// Throughput of the link, in bytes per second, or 0 for an unlimited one.
+    pub bandwidth: f32,
+}
+
+impl LinkSettings {
+    /// Latency of the link, as a path of a control loop.
+    pub fn latency(&self) -> PathLatency {
+        PathLatency {
+            delay: self.delay,
+            jitter: self.jitter,
+        }
+    }
+}
+
+/// Represents the configuration of the links, `network.json`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Commands received from the peer.
+    pub incoming: LinkSettings,
+    /// Telemetry sent to the peer.
+    pub outgoing: LinkSettings,
+    /// Seed of the random draws.
+    pub seed: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            incoming: LinkSettings::default(),
+            outgoing: LinkSettings::default(),
+            seed: 1,
+        }
+    }
+}
+
+/// One direction of a link, holding the datagrams in flight.
+#[derive(Clone, Debug)]
+pub struct Link<T> {
+    settings: LinkSettings,
+    /// State of the xorshift generator.
+    random: u64,
+    /// Datagrams in flight, with their arrival time.
+    in_flight: Vec<(Instant, T)>,
+    /// Time the link is done transmitting the datagrams sent so far.
+    busy_until: Option<Instant>,
+}
+
+impl<T> Link<T> {
+    pub fn new(settings: LinkSettings, seed: u64) -> Self {
+        Self {
+            settings,
+            random: seed.max(1),
+            in_flight: Vec::new(),
+            busy_until: None,
+        }
+    }
+
+    /// Sends a datagram of `size` bytes at `now`.
+    pub fn send(&mut self, datagram: T, size: usize, now: Instant) {
+        if self.settings.loss > 0.0 && self.uniform() < self.settings.loss {
+            return;
+        }
+        let mut sent = now;
+        if self.settings.bandwidth > 0.0 {
+            let start = self.busy_until.map_or(now, |busy| busy.max(now));
+            sent = start + Duration::from_secs_f32(size as f32 / self.settings.bandwidth);
+            self.busy_until = Some(sent);
+        }
+        let jitter = if self.settings.jitter > 0.0 {
+            self.uniform() * self.settings.jitter
+        } else {
+            0.0
+        };
+        let latency = Duration::from_secs_f32((self.settings.delay + jitter).max(0.0));
+        self.in_flight.push((sent + latency, datagram));
+    }
+
+    /// Datagrams arrived by `now`, in their order of arrival.
+    pub fn receive(&mut self, now: Instant) -> Vec<T> {
+        // Stable, so datagrams arriving at the same time keep the order they were sent in.
+        self.in_flight.sort_by_key(|(arrival, _)| *arrival);
+        let arrived = self
+            .in_flight
+            .partition_point(|(arrival, _)| *arrival <= now);
+        self.in_flight
+            .drain(..arrived)
+            .map(|(_, datagram)| datagram)
+            .collect()
+    }
+
+    /// Number of datagrams in flight.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// A random number in [0, 1).
+    fn uniform(&mut self) -> f32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
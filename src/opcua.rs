@@ -0,0 +1,341 @@
+//! OPC UA server exposing the setpoints and the telemetry, for industrial monitoring tools.
+//!
+//! The signals are variables of the namespace `urn:motion-control-playground`, organized under
+//! the `Objects/Playground` folder:
+//!
+//! | Node                                | Access     | Content                                |
+//! |-------------------------------------|------------|----------------------------------------|
+//! | `Playground/Setpoints/<setpoint>`   | Read/write | Value of the setpoint, e.g. `motor/velocity` |
+//! | `Playground/Telemetry/<channel>`    | Read       | Last sample of the channel, e.g. `pendulum/angle` |
+//!
+//! Node IDs are strings made of the folder and of the signal, e.g. `Setpoints/motor/velocity`.
+//! Values are `Float`. Variables appear as the signals do, and are refreshed every
+//! [`PUBLISH_PERIOD`].
+//!
+//! The endpoint has no security policy. Anonymous clients can only read; the users of the
+//! `opcua.json` configuration file log in with their password, and can write the setpoints if
+//! they are allowed to.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{SocketAddr, TcpListener},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bevy::prelude::*;
+use opcua::{
+    server::{
+        address_space::{AccessLevel, VariableBuilder},
+        authenticator::{AuthManager, DefaultAuthenticator, Password, UserToken},
+        diagnostics::NamespaceMetadata,
+        node_manager::memory::{simple_node_manager, SimpleNodeManager},
+        ServerBuilder, ServerEndpoint, ServerUserToken, SubscriptionCache, ANONYMOUS_USER_TOKEN_ID,
+    },
+    types::{
+        DataTypeId, DataValue, Error as OpcUaError, NodeId, StatusCode, UserTokenPolicy, Variant,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Port used when none is given, the one registered for OPC UA.
+pub const DEFAULT_PORT: u16 = 4840;
+/// URI of the namespace of the signals.
+pub const NAMESPACE_URI: &str = "urn:motion-control-playground";
+/// Interval between two refreshes of the variables.
+pub const PUBLISH_PERIOD: Duration = Duration::from_millis(100);
+
+const SETPOINTS_FOLDER: &str = "Setpoints";
+const TELEMETRY_FOLDER: &str = "Telemetry";
+
+/// A user allowed to log in.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OpcUaUser {
+    pub name: String,
+    pub password: String,
+    /// Whether the user can write the setpoints.
+    #[serde(default)]
+    pub write: bool,
+}
+
+/// Settings of the server, from the `opcua.json` configuration file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+pub struct OpcUaSettings {
+    pub users: Vec<OpcUaUser>,
+}
+
+/// Serves the signals on the given address.
+pub struct OpcUaPlugin {
+    pub bind: SocketAddr,
+    pub settings: OpcUaSettings,
+}
+
+impl Plugin for OpcUaPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+
+        // The server spawns its tasks on creation: it must be built within its runtime.
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        let guard = runtime.enter();
+        let mut builder = ServerBuilder::new_anonymous("Motion Control Playground")
+            .application_uri(NAMESPACE_URI)
+            .host(self.bind.ip().to_string())
+            .port(self.bind.port())
+            .pki_dir(crate::config_plugin::data_dir().join("pki"))
+            .product_uri(NAMESPACE_URI)
+            // A self-signed certificate, for the clients requiring one even without security.
+            .create_sample_keypair(true)
+            .with_node_manager(simple_node_manager(
+                NamespaceMetadata {
+                    namespace_uri: NAMESPACE_URI.to_string(),
+                    ..Default::default()
+                },
+                "playground",
+            ));
+        let mut token_ids = vec![ANONYMOUS_USER_TOKEN_ID.to_string()];
+        let mut tokens = BTreeMap::new();
+        let mut writers = BTreeSet::new();
+        for user in &self.settings.users {
+            let token = ServerUserToken::user_pass(user.name.as_str(), user.password.as_str());
+            builder = builder.add_user_token(&user.name, token.clone());
+            tokens.insert(user.name.clone(), token);
+            token_ids.push(user.name.clone());
+            if user.write {
+                writers.insert(user.name.clone());
+            }
+        }
+        let result = builder
+            .add_endpoint("none", ServerEndpoint::new_none("/", &token_ids))
+            .with_authenticator(Arc::new(AccessControl {
+                users: DefaultAuthenticator::new(tokens),
+                writers,
+            }))
+            .build();
+        let (server, handle) = match result {
+            Ok(server) => server,
+            Err(error) => {
+                error!(target: subsystem::IO, "Failed to create the OPC UA server: {error}");
+                return;
+            }
+        };
+        let Some(manager) = handle.node_managers().get_of_type::<SimpleNodeManager>() else {
+            return;
+        };
+        let Some(namespace) = manager
+            .namespaces()
+            .iter()
+            .find_map(|(index, uri)| (uri == NAMESPACE_URI).then_some(*index))
+        else {
+            return;
+        };
+        {
+            let mut address_space = manager.address_space().write();
+            let root = NodeId::new(namespace, "Playground");
+            address_space.add_folder(
+                &root,
+                "Playground",
+                "Playground",
+                &NodeId::objects_folder_id(),
+            );
+            for folder in [SETPOINTS_FOLDER, TELEMETRY_FOLDER] {
+                address_space.add_folder(&NodeId::new(namespace, folder), folder, folder, &root);
+            }
+        }
+        drop(guard);
+
+        thread::spawn(move || {
+            let result = runtime.block_on(async {
+                let listener = tokio::net::TcpListener::from_std(listener)
+                    .map_err(|error| error.to_string())?;
+                server.run_with(listener).await
+            });
+            if let Err(error) = result {
+                error!(target: subsystem::IO, "OPC UA server stopped: {error}");
+            }
+        });
+        info!(target: subsystem::IO, "OPC UA server listening on {}", self.bind);
+
+        let (writes, receiver) = mpsc::channel();
+        app.init_resource::<Setpoints>()
+            .insert_resource(OpcUaServer {
+                manager,
+                subscriptions: handle.subscriptions().clone(),
+                namespace,
+                writes,
+                written: Mutex::new(receiver),
+                variables: BTreeSet::new(),
+                timer: Timer::new(PUBLISH_PERIOD, TimerMode::Repeating),
+            })
+            .add_systems(PreUpdate, apply_writes)
+            .add_systems(Update, publish_signals);
+    }
+}
+
+/// Authenticates the users and hides the write access from those not allowed to.
+struct AccessControl {
+    users: DefaultAuthenticator,
+    writers: BTreeSet<String>,
+}
+
+#[async_trait]
+impl AuthManager for AccessControl {
+    async fn authenticate_anonymous_token(
+        &self,
+        endpoint: &ServerEndpoint,
+    ) -> Result<(), OpcUaError> {
+        self.users.authenticate_anonymous_token(endpoint).await
+    }
+
+    async fn authenticate_username_identity_token(
+        &self,
+        endpoint: &ServerEndpoint,
+        username: &str,
+        password: &Password,
+    ) -> Result<UserToken, OpcUaError> {
+        self.users
+            .authenticate_username_identity_token(endpoint, username, password)
+            .await
+    }
+
+    fn effective_user_access_level(
+        &self,
+        token: &UserToken,
+        user_access_level: AccessLevel,
+        _node_id: &NodeId,
+    ) -> AccessLevel {
+        // Tokens are the names of the users, anonymous clients included.
+        if self.writers.contains(&token.0) {
+            user_access_level
+        } else {
+            user_access_level - AccessLevel::CURRENT_WRITE
+        }
+    }
+
+    fn user_token_policies(&self, endpoint: &ServerEndpoint) -> Vec<UserTokenPolicy> {
+        self.users.user_token_policies(endpoint)
+    }
+}
+
+#[derive(Resource)]
+struct OpcUaServer {
+    manager: Arc<SimpleNodeManager>,
+    subscriptions: Arc<SubscriptionCache>,
+    namespace: u16,
+    /// Setpoints written by the clients, sent from the server thread.
+    writes: mpsc::Sender<(String, f32)>,
+    written: Mutex<mpsc::Receiver<(String, f32)>>,
+    /// Node IDs of the variables created so far.
+    variables: BTreeSet<String>,
+    timer: Timer,
+}
+
+impl OpcUaServer {
+    /// Creates the variable of a signal if needed, returning its node ID.
+    fn variable(&mut self, folder: &str, signal: &str) -> NodeId {
+        let id = format!("{folder}/{signal}");
+        let node_id = NodeId::new(self.namespace, id.as_str());
+        if self.variables.contains(&id) {
+            return node_id;
+        }
+        let mut builder = VariableBuilder::new(&node_id, signal, signal)
+            .data_type(DataTypeId::Float)
+            .value(0.0f32)
+            .organized_by(NodeId::new(self.namespace, folder));
+        if folder == SETPOINTS_FOLDER {
+            builder = builder.writable();
+            let writes = self.writes.clone();
+            let signal = signal.to_string();
+            self.manager
+                .inner()
+                .add_write_callback(node_id.clone(), move |value, _range| {
+                    let value = match value.value {
+                        Some(Variant::Float(value)) => value,
+                        Some(Variant::Double(value)) => value as f32,
+                        _ => return StatusCode::BadTypeMismatch,
+                    };
+                    if !value.is_finite() {
+                        return StatusCode::BadOutOfRange;
+                    }
+                    match writes.send((signal.clone(), value)) {
+                        Ok(()) => StatusCode::Good,
+                        Err(_) => StatusCode::BadServerHalted,
+                    }
+                });
+        }
+        builder.insert(&mut *self.manager.address_space().write());
+        self.variables.insert(id);
+        node_id
+    }
+}
+
+/// Applies the setpoints written by the clients.
+fn apply_writes(server: Res<OpcUaServer>, mut setpoints: ResMut<Setpoints>) {
+    let written = server
+        .written
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    for (signal, value) in written.try_iter() {
+        debug!(target: subsystem::IO, "OPC UA client wrote {signal} = {value}");
+        setpoints.set(&signal, value);
+    }
+}
+
+/// Refreshes the variables, creating those of new signals.
+fn publish_signals(
+    mut server: ResMut<OpcUaServer>,
+    time: Res<Time<Real>>,
+    setpoints: Res<Setpoints>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    if !server.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let mut values = Vec::new();
+    for (signal, value) in &setpoints.values {
+        values.push((server.variable(SETPOINTS_FOLDER, signal), *value));
+    }
+    if let Some(telemetry) = &telemetry {
+        for channel in telemetry.channels.keys() {
+            if let Some(value) = telemetry.latest(channel) {
+                values.push((server.variable(TELEMETRY_FOLDER, channel), value));
+            }
+        }
+    }
+    let result = server.manager.set_values(
+        &server.subscriptions,
+        values
+            .iter()
+            .map(|(node_id, value)| (node_id, None, DataValue::new_now(*value))),
+    );
+    if let Err(status) = result {
+        warn!(target: subsystem::IO, "Failed to refresh the OPC UA variables: {status}");
+    }
+}
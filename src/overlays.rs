@@ -0,0 +1,375 @@
+//! This module draws the quantities the controllers act on over the scene, which the debug
+//! render of Rapier doesn't show: the torques the motors apply at the joints, the forces at the
+//! contacts, the linear and angular velocities of the bodies, and the setpoint of the position
+//! loops against the angle of their joints.
+//!
+//! Each category is toggled in the *Overlays* panel, with the scale of its arrows. A torque is an
+//! arc about the axis of its joint, sweeping an angle proportional to it in its sense; a force
+//! or a velocity is an arrow, an angular velocity along its axis by the right-hand rule. The
+//! setpoint and the angle of a joint are rays about its axis, from the zero of the joint, with
+//! the arc between them.
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    pid_controller::PidController,
+    setpoints::Setpoints,
+    theme::Theme,
+};
+
+/// Segments of an arc over a full turn.
+const ARC_RESOLUTION: usize = 48;
+/// Largest sweep of the arc of a torque, short of a full turn to keep its sense readable.
+const MAX_SWEEP: f32 = 0.9 * TAU;
+
+pub struct OverlaysPlugin;
+
+impl Plugin for OverlaysPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                draw_joint_torques,
+                draw_contact_forces,
+                draw_velocities,
+                draw_setpoints,
+                overlays_panel.run_if(has_ui),
+            )
+                .run_if(resource_exists::<Persistent<OverlaySettings>>),
+        );
+    }
+}
+
+/// Represents the configuration of the overlays drawn.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct OverlaySettings {
+    pub joint_torques: bool,
+    pub contact_forces: bool,
+    pub linear_velocities: bool,
+    pub angular_velocities: bool,
+    pub setpoints: bool,
+    /// Sweep of the arc of a torque, in rad per N·m.
+    pub torque_scale: f32,
+    /// Length of the arrow of a force, in m per N.
+    pub force_scale: f32,
+    /// Length of the arrow of a linear velocity, in m per m/s.
+    pub linear_velocity_scale: f32,
+    /// Length of the arrow of an angular velocity, in m per rad/s.
+    pub angular_velocity_scale: f32,
+    /// Radius of the arcs about the joints, in m.
+    pub radius: f32,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        Self {
+            joint_torques: false,
+            contact_forces: false,
+            linear_velocities: false,
+            angular_velocities: false,
+            setpoints: false,
+            torque_scale: 0.5,
+            force_scale: 0.01,
+            linear_velocity_scale: 0.2,
+            angular_velocity_scale: 0.1,
+            radius: 0.15,
+        }
+    }
+}
+
+/// Points of the arc of `radius` about `axis` through `center`, sweeping `angle` from the
+/// direction `start`, perpendicular to the axis.
+pub fn arc(center: Vec3, axis: Vec3, start: Vec3, angle: f32, radius: f32) -> Vec<Vec3> {
+    let segments = ((angle.abs() / TAU * ARC_RESOLUTION as f32).ceil() as usize).max(1);
+    let start = start.normalize_or_zero() * radius;
+    let axis = axis.normalize_or_zero();
+    (0..=segments)
+        .map(|segment| {
+            let turned = angle * segment as f32 / segments as f32;
+            center + Quat::from_axis_angle(axis, turned) * start
+        })
+        .collect()
+}
+
+/// Frame of a joint in the world: on its anchor on the parent, its X along the free axis of a
+/// revolute joint and its Y at the zero of the angle.
+pub fn joint_frame(parent: &GlobalTransform, joint: &ImpulseJoint) -> Transform {
+    let data = joint.data.as_ref();
+    parent
+        .compute_transform()
+        .with_scale(Vec3::ONE)
+        .mul_transform(Transform {
+            translation: data.local_anchor1(),
+            rotation: data.local_basis1(),
+            ..default()
+        })
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<OverlaySettings>("overlays", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn color(theme: &Option<Res<Persistent<Theme>>>, index: usize) -> Srgba {
+    theme.as_ref().map_or_else(
+        || Theme::default().series(index),
+        |theme| theme.series(index),
+    )
+}
+
+/// Draws the torque each motor applied during the last physics step, as an arc about its joint.
+fn draw_joint_torques(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<OverlaySettings>>,
+    joints: Query<(&ImpulseJoint, &RapierImpulseJointHandle)>,
+    transforms: Query<&GlobalTransform>,
+    contexts: Query<&RapierContext>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    let dt = context.integration_parameters.dt;
+    if !settings.joint_torques || dt <= 0.0 {
+        return;
+    }
+    let color = color(&theme, 0);
+    for (joint, handle) in &joints {
+        let (Some(rapier_joint), Ok(parent)) = (
+            context.impulse_joints.get(handle.0),
+            transforms.get(joint.parent),
+        ) else {
+            continue;
+        };
+        // The free axis of a revolute joint is its first angular axis.
+        let torque = rapier_joint.data.motors[JointAxis::AngX as usize].impulse / dt;
+        let sweep = (torque * settings.torque_scale).clamp(-MAX_SWEEP, MAX_SWEEP);
+        if sweep.abs() < 1e-3 {
+            continue;
+        }
+        let frame = joint_frame(parent, joint);
+        let points = arc(
+            frame.translation,
+            frame.rotation * Vec3::X,
+            frame.rotation * Vec3::Y,
+            sweep,
+            settings.radius,
+        );
+        if let [.., before, end] = points[..] {
+            gizmos.linestrip(points.iter().copied(), color);
+            gizmos
+                .arrow(before, end, color)
+                .with_tip_length(0.2 * settings.radius);
+        }
+    }
+}
+
+/// Draws the force at each contact manifold during the last physics step, along its normal
+/// from its contact points.
+fn draw_contact_forces(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<OverlaySettings>>,
+    contexts: Query<&RapierContext>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    let dt = context.integration_parameters.dt;
+    if !settings.contact_forces || dt <= 0.0 {
+        return;
+    }
+    let color = color(&theme, 1);
+    for pair in context.contact_pairs() {
+        if !pair.has_any_active_contact() {
+            continue;
+        }
+        for manifold in pair.manifolds() {
+            let count = manifold.num_solver_contacts();
+            if count == 0 {
+                continue;
+            }
+            let point = manifold
+                .solver_contacts()
+                .map(|contact| contact.point())
+                .sum::<Vec3>()
+                / count as f32;
+            let force = manifold
+                .points()
+                .map(|contact| contact.impulse())
+                .sum::<f32>()
+                / dt;
+            // The force the first collider exerts on the second.
+            let end = point + manifold.normal() * force * settings.force_scale;
+            if point.distance(end) > 1e-4 {
+                gizmos.arrow(point, end, color);
+            }
+        }
+    }
+}
+
+/// Draws the linear and angular velocities of the bodies from their centers of mass.
+fn draw_velocities(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<OverlaySettings>>,
+    bodies: Query<(&GlobalTransform, &Velocity, Option<&ReadMassProperties>), With<RigidBody>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    if !settings.linear_velocities && !settings.angular_velocities {
+        return;
+    }
+    let (linear, angular) = (color(&theme, 2), color(&theme, 3));
+    for (transform, velocity, properties) in &bodies {
+        let center = properties.map_or(transform.translation(), |properties| {
+            transform.transform_point(properties.get().local_center_of_mass)
+        });
+        let arrows = [
+            (
+                settings.linear_velocities,
+                velocity.linvel * settings.linear_velocity_scale,
+                linear,
+            ),
+            (
+                settings.angular_velocities,
+                velocity.angvel * settings.angular_velocity_scale,
+                angular,
+            ),
+        ];
+        for (shown, arrow, color) in arrows {
+            if shown && arrow.length() > 1e-4 {
+                gizmos.arrow(center, center + arrow, color);
+            }
+        }
+    }
+}
+
+/// Draws the setpoint of each position loop against the angle of its joint.
+fn draw_setpoints(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<OverlaySettings>>,
+    setpoints: Res<Setpoints>,
+    controllers: Query<(Entity, &PidController, &ImpulseJoint)>,
+    transforms: Query<&GlobalTransform>,
+    contexts: Query<&RapierContext>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    if !settings.setpoints {
+        return;
+    }
+    let (actual_color, setpoint_color) = (color(&theme, 4), color(&theme, 5));
+    for (entity, controller, joint) in &controllers {
+        let (Some(actual), Some(setpoint), Ok(parent)) = (
+            controller
+                .position()
+                .or_else(|| context.impulse_revolute_joint_angle(entity)),
+            setpoints.get(&controller.setpoint),
+            transforms.get(joint.parent),
+        ) else {
+            continue;
+        };
+        let frame = joint_frame(parent, joint);
+        let (center, axis) = (frame.translation, frame.rotation * Vec3::X);
+        let zero = frame.rotation * Vec3::Y;
+        let ray = |angle: f32| center + Quat::from_axis_angle(axis, angle) * zero * settings.radius;
+        gizmos.line(center, ray(actual), actual_color);
+        gizmos.line(center, ray(setpoint), setpoint_color);
+        // The error, from the angle to the setpoint, over the turns between them.
+        let start = Quat::from_axis_angle(axis, actual) * zero;
+        let error = arc(
+            center,
+            axis,
+            start,
+            setpoint - actual,
+            0.8 * settings.radius,
+        );
+        gizmos.linestrip(error, setpoint_color);
+    }
+}
+
+/// Panel to toggle the overlays and scale them.
+fn overlays_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<OverlaySettings>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Overlays")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let categories = [
+                (
+                    &mut edited.joint_torques,
+                    "Joint torques",
+                    &mut edited.torque_scale,
+                    "Scale (rad/(N·m))",
+                ),
+                (
+                    &mut edited.contact_forces,
+                    "Contact forces",
+                    &mut edited.force_scale,
+                    "Scale (m/N)",
+                ),
+                (
+                    &mut edited.linear_velocities,
+                    "Linear velocities",
+                    &mut edited.linear_velocity_scale,
+                    "Scale (m/(m/s))",
+                ),
+                (
+                    &mut edited.angular_velocities,
+                    "Angular velocities",
+                    &mut edited.angular_velocity_scale,
+                    "Scale (m/(rad/s))",
+                ),
+            ];
+            for (shown, name, scale, unit) in categories {
+                ui.checkbox(shown, name);
+                ui.add_enabled(
+                    *shown,
+                    egui::Slider::new(scale, 1e-4..=10.0)
+                        .logarithmic(true)
+                        .text(unit),
+                );
+            }
+            ui.checkbox(&mut edited.setpoints, "Setpoints against joint angles");
+            ui.add(
+                egui::Slider::new(&mut edited.radius, 0.01..=1.0)
+                    .logarithmic(true)
+                    .text("Radius of the arcs (m)"),
+            );
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("overlays", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("overlays", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,536 @@
+//! This module edits the physical parameters of the simulation while it runs, so their effect on
+//! the controllers can be explored without touching the model: the gravity and the iterations
+//! of the solver of Rapier, and the damping, the friction and the restitution of the links.
+//!
+//! A parameter left unset keeps the value of the model. The values the links had before their
+//! parameters were set are kept in an [`OriginalBody`] on their bodies, and an
+//! [`OriginalContact`] on their colliders, those of the link and of its meshes, and restored
+//! when the parameters are unset. The parameters are configured in `physics_parameters.json`, and
+//! applied again to the links spawned later, e.g. by a reloaded model.
+//!
+//! The *Physics parameters* window also edits the [mass overrides](crate::mass_overrides) of the
+//! links. Unlike the other windows, its edits are staged until applied, so several parameters
+//! change in the same step, and can be discarded.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    mass_overrides::{MassOverride, MassOverrides},
+    plants::Link,
+};
+
+pub struct PhysicsParametersPlugin;
+
+impl Plugin for PhysicsParametersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (apply_world, apply_bodies)
+                    .run_if(resource_exists::<Persistent<PhysicsParameters>>),
+            )
+            .add_systems(
+                Update,
+                physics_parameters_panel
+                    .run_if(resource_exists::<Persistent<PhysicsParameters>>)
+                    .run_if(resource_exists::<Persistent<MassOverrides>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// The parameters of a link, in each plant, unset to keep those of the model.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct BodyParameters {
+    /// Name of the link, in each plant.
+    pub link: String,
+    /// Damping of the linear and angular velocities of the body, in 1/s.
+    pub linear_damping: Option<f32>,
+    pub angular_damping: Option<f32>,
+    /// Coefficient of friction and restitution of the colliders of the link.
+    pub friction: Option<f32>,
+    pub restitution: Option<f32>,
+}
+
+impl BodyParameters {
+    /// The damping of a body which had `original`, if any.
+    pub fn damping(&self, original: Option<Damping>) -> Option<Damping> {
+        if self.linear_damping.is_none() && self.angular_damping.is_none() {
+            return original;
+        }
+        let original = original.unwrap_or_default();
+        Some(Damping {
+            linear_damping: self
+                .linear_damping
+                .unwrap_or(original.linear_damping)
+                .max(0.0),
+            angular_damping: self
+                .angular_damping
+                .unwrap_or(original.angular_damping)
+                .max(0.0),
+        })
+    }
+
+    /// Whether it sets anything.
+    pub fn is_set(&self) -> bool {
+        self.linear_damping.is_some()
+            || self.angular_damping.is_some()
+            || self.friction.is_some()
+            || self.restitution.is_some()
+    }
+}
+
+/// Represents the configuration of the physical parameters.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct PhysicsParameters {
+    /// Acceleration of gravity, in m/s².
+    pub gravity: Option<Vec3>,
+    /// Iterations of the constraint solver a step.
+    pub solver_iterations: Option<usize>,
+    pub bodies: Vec<BodyParameters>,
+}
+
+impl PhysicsParameters {
+    /// The parameters of `link`, if any.
+    pub fn find(&self, link: &str) -> Option<&BodyParameters> {
+        self.bodies
+            .iter()
+            .find(|body| body.link == link)
+            .filter(|body| body.is_set())
+    }
+}
+
+/// The damping of a link before its parameters were set.
+#[derive(Clone, Component, Debug)]
+pub struct OriginalBody {
+    pub damping: Option<Damping>,
+}
+
+/// The friction and restitution of a collider of a link before its parameters were set.
+#[derive(Clone, Component, Debug)]
+pub struct OriginalContact {
+    pub friction: Option<Friction>,
+    pub restitution: Option<Restitution>,
+}
+
+/// The gravity and iterations of the solver of the model.
+#[derive(Clone, Copy, Debug)]
+struct OriginalWorld {
+    gravity: Vec3,
+    solver_iterations: NonZeroUsize,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<PhysicsParameters>("physics_parameters", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Sets the gravity and the iterations of the solver, or restores them, when they change.
+fn apply_world(
+    settings: Res<Persistent<PhysicsParameters>>,
+    mut configurations: Query<&mut RapierConfiguration>,
+    mut contexts: Query<&mut RapierContext>,
+    mut original: Local<Option<OriginalWorld>>,
+) {
+    // Until the physics is set up, there is nothing to set.
+    if !settings.is_changed() && original.is_some() {
+        return;
+    }
+    let (Ok(mut configuration), Ok(mut context)) =
+        (configurations.get_single_mut(), contexts.get_single_mut())
+    else {
+        return;
+    };
+    let original = *original.get_or_insert(OriginalWorld {
+        gravity: configuration.gravity,
+        solver_iterations: context.integration_parameters.num_solver_iterations,
+    });
+    let gravity = settings.gravity.unwrap_or(original.gravity);
+    if configuration.gravity != gravity {
+        configuration.gravity = gravity;
+        info!(target: subsystem::PHYSICS, "Gravity set to {gravity}");
+    }
+    let iterations = settings
+        .solver_iterations
+        .and_then(NonZeroUsize::new)
+        .unwrap_or(original.solver_iterations);
+    let parameters = &mut context.integration_parameters;
+    if parameters.num_solver_iterations != iterations {
+        parameters.num_solver_iterations = iterations;
+        info!(target: subsystem::PHYSICS, "Solver iterations set to {iterations}");
+    }
+}
+
+/// Sets the parameters of the links, or restores them, when they change or links and colliders
+/// are spawned.
+#[allow(clippy::type_complexity)]
+fn apply_bodies(
+    mut commands: Commands,
+    settings: Res<Persistent<PhysicsParameters>>,
+    added: Query<(), Or<(Added<Link>, Added<Collider>)>>,
+    bodies: Query<(Entity, &Link, Option<&Damping>, Option<&OriginalBody>), With<RigidBody>>,
+    colliders: Query<
+        (
+            Entity,
+            Option<&Friction>,
+            Option<&Restitution>,
+            Option<&OriginalContact>,
+        ),
+        With<Collider>,
+    >,
+    links: Query<&Link>,
+    parents: Query<&Parent>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, damping, original) in &bodies {
+        match (settings.find(&link.name), original) {
+            (Some(parameters), _) => {
+                let original_damping = match original {
+                    Some(original) => original.damping,
+                    None => {
+                        commands.entity(entity).insert(OriginalBody {
+                            damping: damping.copied(),
+                        });
+                        info!(
+                            target: subsystem::PHYSICS,
+                            "Physical parameters of {} set",
+                            link.path()
+                        );
+                        damping.copied()
+                    }
+                };
+                match parameters.damping(original_damping) {
+                    Some(damping) => commands.entity(entity).insert(damping),
+                    None => commands.entity(entity).remove::<Damping>(),
+                };
+            }
+            (None, Some(original)) => {
+                let mut entity_commands = commands.entity(entity);
+                match original.damping {
+                    Some(damping) => entity_commands.insert(damping),
+                    None => entity_commands.remove::<Damping>(),
+                };
+                entity_commands.remove::<OriginalBody>();
+                info!(
+                    target: subsystem::PHYSICS,
+                    "Physical parameters of {} restored",
+                    link.path()
+                );
+            }
+            (None, None) => {}
+        }
+    }
+    for (entity, friction, restitution, original) in &colliders {
+        // The colliders of a link are on its body or on its meshes.
+        let link = std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find_map(|entity| links.get(entity).ok());
+        let parameters = link.and_then(|link| settings.find(&link.name));
+        let mut entity_commands = commands.entity(entity);
+        match (parameters, original) {
+            (Some(parameters), _) => {
+                let original = original.cloned().unwrap_or_else(|| {
+                    let original = OriginalContact {
+                        friction: friction.copied(),
+                        restitution: restitution.copied(),
+                    };
+                    entity_commands.insert(original.clone());
+                    original
+                });
+                match (parameters.friction, original.friction) {
+                    (Some(coefficient), _) => {
+                        entity_commands.insert(Friction::coefficient(coefficient.max(0.0)))
+                    }
+                    (None, Some(friction)) => entity_commands.insert(friction),
+                    (None, None) => entity_commands.remove::<Friction>(),
+                };
+                match (parameters.restitution, original.restitution) {
+                    (Some(coefficient), _) => entity_commands
+                        .insert(Restitution::coefficient(coefficient.clamp(0.0, 1.0))),
+                    (None, Some(restitution)) => entity_commands.insert(restitution),
+                    (None, None) => entity_commands.remove::<Restitution>(),
+                };
+            }
+            (None, Some(original)) => {
+                match original.friction {
+                    Some(friction) => entity_commands.insert(friction),
+                    None => entity_commands.remove::<Friction>(),
+                };
+                match original.restitution {
+                    Some(restitution) => entity_commands.insert(restitution),
+                    None => entity_commands.remove::<Restitution>(),
+                };
+                entity_commands.remove::<OriginalContact>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// The edits of the window, until applied.
+#[derive(Clone, Debug, PartialEq)]
+struct Draft {
+    parameters: PhysicsParameters,
+    mass_overrides: MassOverrides,
+}
+
+/// The parameters of a link as they are now, to start editing from.
+#[derive(Clone, Copy, Debug, Default)]
+struct Current {
+    damping: Damping,
+    friction: f32,
+    restitution: f32,
+    mass: MassProperties,
+}
+
+/// Panel to edit the physical parameters, applied together.
+#[allow(clippy::too_many_arguments)]
+fn physics_parameters_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<PhysicsParameters>>,
+    mut mass_overrides: ResMut<Persistent<MassOverrides>>,
+    configurations: Query<&RapierConfiguration>,
+    rapier_contexts: Query<&RapierContext>,
+    links: Query<(
+        &Link,
+        Option<&Damping>,
+        Option<&Friction>,
+        Option<&Restitution>,
+        Option<&ReadMassProperties>,
+    )>,
+    mut draft: Local<Option<Draft>>,
+) {
+    let applied = Draft {
+        parameters: settings.get().clone(),
+        mass_overrides: mass_overrides.get().clone(),
+    };
+    let edited = draft.get_or_insert_with(|| applied.clone());
+    let gravity = configurations
+        .get_single()
+        .map_or(Vec3::NEG_Y * 9.81, |configuration| configuration.gravity);
+    let iterations = rapier_contexts.get_single().map_or(4, |context| {
+        context.integration_parameters.num_solver_iterations.get()
+    });
+    let mut current: Vec<(&str, Current)> = links
+        .iter()
+        .map(|(link, damping, friction, restitution, mass)| {
+            let current = Current {
+                damping: damping.copied().unwrap_or_default(),
+                friction: friction.map_or(Friction::default().coefficient, |f| f.coefficient),
+                restitution: restitution.map_or(0.0, |restitution| restitution.coefficient),
+                mass: mass.map(|mass| *mass.get()).unwrap_or_default(),
+            };
+            (link.name.as_str(), current)
+        })
+        .collect();
+    current.sort_by(|a, b| a.0.cmp(b.0));
+    current.dedup_by(|a, b| a.0 == b.0);
+
+    let (mut apply, mut discard, mut save, mut revert) = (false, false, false, false);
+    egui::Window::new("Physics parameters")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("World");
+            ui.horizontal(|ui| {
+                let mut set = edited.parameters.gravity.is_some();
+                ui.checkbox(&mut set, "Gravity (m/s²):");
+                let mut value = edited.parameters.gravity.unwrap_or(gravity);
+                ui.add_enabled_ui(set, |ui| {
+                    for component in [&mut value.x, &mut value.y, &mut value.z] {
+                        ui.add(egui::DragValue::new(component).speed(0.01));
+                    }
+                });
+                edited.parameters.gravity = set.then_some(value);
+            });
+            ui.horizontal(|ui| {
+                let mut set = edited.parameters.solver_iterations.is_some();
+                ui.checkbox(&mut set, "Solver iterations:");
+                let mut value = edited.parameters.solver_iterations.unwrap_or(iterations);
+                ui.add_enabled(set, egui::DragValue::new(&mut value).range(1..=64));
+                edited.parameters.solver_iterations = set.then_some(value);
+            });
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(400.0)
+                .show(ui, |ui| {
+                    for (name, current) in &current {
+                        egui::CollapsingHeader::new(*name)
+                            .id_salt(("physics_parameters", *name))
+                            .show(ui, |ui| link_parameters(ui, edited, name, current));
+                    }
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                let changed = *edited != applied;
+                apply = ui
+                    .add_enabled(changed, egui::Button::new("Apply"))
+                    .clicked();
+                discard = ui
+                    .add_enabled(changed, egui::Button::new("Discard"))
+                    .clicked();
+                save = ui.button("Save").clicked();
+                revert = ui.button("Revert to defaults").clicked();
+            });
+        });
+
+    if apply {
+        if edited.parameters != *settings.get() {
+            *settings.get_mut() = edited.parameters.clone();
+        }
+        if edited.mass_overrides != *mass_overrides.get() {
+            *mass_overrides.get_mut() = edited.mass_overrides.clone();
+        }
+    }
+    if save {
+        if let Err(error) = settings.persist() {
+            commands.send_event(ErrorEvent::from(Error::save("physics_parameters", error)));
+        }
+        if let Err(error) = mass_overrides.persist() {
+            commands.send_event(ErrorEvent::from(Error::save("mass_overrides", error)));
+        }
+    }
+    if revert {
+        if let Err(error) = settings.revert_to_default() {
+            commands.send_event(ErrorEvent::from(Error::save("physics_parameters", error)));
+        }
+        if let Err(error) = mass_overrides.revert_to_default() {
+            commands.send_event(ErrorEvent::from(Error::save("mass_overrides", error)));
+        }
+    }
+    // Without edits pending, the window follows the parameters changed elsewhere.
+    let latest = Draft {
+        parameters: settings.get().clone(),
+        mass_overrides: mass_overrides.get().clone(),
+    };
+    if discard || draft.as_ref() == Some(&latest) {
+        *draft = None;
+    }
+}
+
+/// Edits the parameters of the link `name`, starting from the `current` ones.
+fn link_parameters(ui: &mut egui::Ui, edited: &mut Draft, name: &str, current: &Current) {
+    let index = match edited
+        .parameters
+        .bodies
+        .iter()
+        .position(|body| body.link == name)
+    {
+        Some(index) => index,
+        None => {
+            edited.parameters.bodies.push(BodyParameters {
+                link: name.to_string(),
+                ..default()
+            });
+            edited.parameters.bodies.len() - 1
+        }
+    };
+    let body = &mut edited.parameters.bodies[index];
+    let damping = current.damping;
+    optional(
+        ui,
+        "Linear damping",
+        &mut body.linear_damping,
+        damping.linear_damping,
+        " 1/s",
+    );
+    optional(
+        ui,
+        "Angular damping",
+        &mut body.angular_damping,
+        damping.angular_damping,
+        " 1/s",
+    );
+    optional(ui, "Friction", &mut body.friction, current.friction, "");
+    optional(
+        ui,
+        "Restitution",
+        &mut body.restitution,
+        current.restitution,
+        "",
+    );
+    // Only the links set are kept.
+    if !body.is_set() {
+        edited.parameters.bodies.remove(index);
+    }
+
+    let overrides = &mut edited.mass_overrides;
+    let index = overrides
+        .overrides
+        .iter()
+        .position(|entry| entry.link == name);
+    let mut set = index.is_some() && overrides.enabled;
+    ui.checkbox(&mut set, "Mass properties");
+    match (set, index) {
+        (true, Some(index)) => {
+            overrides.enabled = true;
+            let entry = &mut overrides.overrides[index];
+            ui.add(
+                egui::DragValue::new(&mut entry.mass)
+                    .range(0.0..=1000.0)
+                    .speed(0.01)
+                    .prefix("Mass: ")
+                    .suffix(" kg"),
+            );
+            vector(ui, "Center of mass (m):", &mut entry.center_of_mass, 0.01);
+            vector(ui, "Inertia (kg·m²):", &mut entry.inertia, 0.001);
+        }
+        (true, None) => {
+            overrides.enabled = true;
+            overrides
+                .overrides
+                .push(MassOverride::from_properties(name, &current.mass));
+        }
+        (false, Some(index)) if overrides.enabled => {
+            overrides.overrides.remove(index);
+        }
+        (false, _) => {
+            ui.label(format!("{:.3} kg", current.mass.mass));
+        }
+    }
+}
+
+/// Edits a parameter unset to keep the `current` value.
+fn optional(ui: &mut egui::Ui, label: &str, value: &mut Option<f32>, current: f32, suffix: &str) {
+    ui.horizontal(|ui| {
+        let mut set = value.is_some();
+        ui.checkbox(&mut set, label);
+        let mut edited = value.unwrap_or(current);
+        ui.add_enabled(
+            set,
+            egui::DragValue::new(&mut edited)
+                .range(0.0..=100.0)
+                .speed(0.01)
+                .suffix(suffix),
+        );
+        *value = set.then_some(edited);
+    });
+}
+
+/// Edits the components of a vector on a row.
+fn vector(ui: &mut egui::Ui, label: &str, value: &mut Vec3, speed: f64) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for component in [&mut value.x, &mut value.y, &mut value.z] {
+            ui.add(egui::DragValue::new(component).speed(speed));
+        }
+    });
+}
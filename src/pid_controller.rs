@@ -0,0 +1,399 @@
+//! This module closes a position loop around the revolute joints of the plants, with a
+//! [`Pid`] tuned live from its panel while the plant runs.
+//!
+//! A [`PidController`] on the entity of a joint measures the angle of the joint after each
+//! physics step, counting the turns, and commands the velocity of the motor of the joint for
+//! the next step, so it runs at the rate of the physics rather than of the frames. Its target
+//! is the `motor/position` setpoint of the plant; its error and output are recorded as the
+//! `pid/error` and `pid/output` telemetry channels. The output is saturated, and the integrator
+//! stops while the output saturates, so it doesn't wind up.
+//!
+//! The controllers are added to the joints of the configured link when enabled, and take the
+//! configured gains whenever they change.
+//!
+//! With `smith_predictor`, a loop with a latency is closed through a [`SmithPredictor`] of the
+//! dead time of its latency, e.g. of the network links of `network.json` (see
+//! [`latency`](crate::latency)), so its gains can be tuned for the joint alone. The model of the
+//! predictor integrates the velocity commanded, which the motor tracks, into the angle.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::controller_definition::{self, ControllerDefinition};
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{self, Pid, PidGains, Saturation, SmithPredictor},
+    error::{Error, ErrorEvent},
+    estimation::{self, EstimationSet, JointEstimate},
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link, MOTOR_FACTOR},
+    sensors::{ControlSet, Encoder, SensorSet},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::Telemetry,
+};
+
+/// Error of the position loop, in rad.
+pub const PID_ERROR: &str = "pid/error";
+/// Velocity commanded by the position loop, in rad/s.
+pub const PID_OUTPUT: &str = "pid/output";
+
+pub struct PidControllerPlugin;
+
+impl Plugin for PidControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_controllers
+                    .run_if(resource_exists_and_changed::<Persistent<PidSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                pid_panel
+                    .run_if(resource_exists::<Persistent<PidSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the position loops.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct PidSettings {
+    pub enabled: bool,
+    /// Name of the link whose joint is controlled, in each plant.
+    pub link: String,
+    pub gains: PidGains,
+    /// Largest velocity commanded, in rad/s, or 0 for an unlimited one.
+    pub limit: f32,
+    /// Whether the loops are closed through a Smith predictor of the dead time of their latency.
+    pub smith_predictor: bool,
+}
+
+impl Default for PidSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            link: "motor".to_string(),
+            gains: PidGains {
+                kp: 5.0,
+                ki: 0.5,
+                kd: 0.1,
+            },
+            limit: 10.0,
+            smith_predictor: false,
+        }
+    }
+}
+
+impl PidSettings {
+    pub fn limits(&self) -> Saturation {
+        if self.limit > 0.0 {
+            Saturation::symmetric(self.limit)
+        } else {
+            Saturation::default()
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        Pid::new(self.gains, self.limits())
+    }
+}
+
+/// A position loop around the revolute joint of its entity, commanding the velocity of its
+/// motor.
+#[derive(Clone, Component, Debug, Deserialize, Serialize)]
+pub struct PidController {
+    pub pid: Pid,
+    /// Setpoint of the position, in rad.
+    pub setpoint: String,
+    /// Telemetry channels of the error and of the output.
+    pub error: String,
+    pub output: String,
+    /// Whether the loop is closed through a Smith predictor.
+    pub smith_predictor: bool,
+    /// Last angle within a turn, and the angle over the turns.
+    turns: Option<(f32, f32)>,
+    /// The predictor, for the dead time the loop had when it was made.
+    #[serde(skip)]
+    predictor: Option<SmithPredictor>,
+}
+
+impl PidController {
+    /// A controller with the signals of a plant.
+    pub fn new(pid: Pid, plant: &str) -> Self {
+        Self {
+            pid,
+            setpoint: plants::namespaced(plant, MOTOR_POSITION),
+            error: plants::namespaced(plant, PID_ERROR),
+            output: plants::namespaced(plant, PID_OUTPUT),
+            smith_predictor: false,
+            turns: None,
+            predictor: None,
+        }
+    }
+
+    /// Angle of the joint over the turns, from its angle within a turn.
+    pub fn unwrap(&mut self, angle: f32) -> f32 {
+        control::unwrap_angle(&mut self.turns, angle)
+    }
+
+    /// Closes the loop through a predictor of a `dead_time` in seconds, sampled every `dt`
+    /// seconds, when it has a Smith predictor, as the dead time changes.
+    pub fn compensate(&mut self, dead_time: f32, dt: f32) {
+        if !self.smith_predictor {
+            self.predictor = None;
+            return;
+        }
+        let samples = (dead_time / dt).round().max(0.0) as usize;
+        if self
+            .predictor
+            .as_ref()
+            .is_none_or(|predictor| predictor.dead_time() != samples)
+        {
+            self.predictor = Some(SmithPredictor::new(
+                Pid::default(),
+                1.0,
+                f32::INFINITY,
+                dead_time,
+                dt,
+            ));
+        }
+    }
+
+    /// Velocity to command for the next `dt` seconds, from the angle of the joint within a
+    /// turn.
+    pub fn update(&mut self, target: f32, angle: f32, dt: f32) -> f32 {
+        let position = self.unwrap(angle);
+        let Some(predictor) = self.predictor.as_mut() else {
+            return self.pid.update(target, position, dt);
+        };
+        // The predictor closes the loop with the controller of the loop, lent for the update.
+        std::mem::swap(&mut predictor.controller, &mut self.pid);
+        let velocity = predictor.update(target, position, dt);
+        std::mem::swap(&mut predictor.controller, &mut self.pid);
+        velocity
+    }
+
+    /// Position of the joint over the turns, once measured.
+    pub fn position(&self) -> Option<f32> {
+        self.turns.map(|(_, total)| total)
+    }
+
+    /// Takes the state of `saved`, keeping its own gains and signals, or starts over without
+    /// it.
+    pub fn restore(&mut self, saved: Option<&Self>) {
+        self.pid.restore(saved.map(|saved| &saved.pid));
+        self.turns = saved.and_then(|saved| saved.turns);
+        self.predictor = saved.and_then(|saved| saved.predictor.clone());
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<PidSettings>("pid", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers, and gives them the configured gains.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<PidSettings>>,
+    mut joints: Query<(Entity, &Link, Option<&mut PidController>), With<ImpulseJoint>>,
+) {
+    for (entity, link, controller) in &mut joints {
+        let controlled = settings.enabled && link.name == settings.link;
+        match controller {
+            Some(mut controller) if controlled => {
+                controller.pid.gains = settings.gains;
+                controller.pid.limits = settings.limits();
+                controller.smith_predictor = settings.smith_predictor;
+            }
+            Some(_) => {
+                commands.entity(entity).remove::<PidController>();
+                info!(target: subsystem::CONTROL, "Position loop of {} opened", link.path());
+            }
+            None if controlled => {
+                commands.entity(entity).insert(PidController {
+                    smith_predictor: settings.smith_predictor,
+                    ..PidController::new(settings.pid(), &link.plant)
+                });
+                info!(target: subsystem::CONTROL, "Position loop of {} closed", link.path());
+            }
+            None => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step and commands their motors for the next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    mut controllers: Query<(Entity, &mut PidController, &mut ImpulseJoint)>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_controllers").entered();
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    for (entity, mut controller, mut joint) in &mut controllers {
+        let Some(angle) =
+            estimation::joint_angle(context, &encoders, &latencies, &estimates, entity)
+        else {
+            continue;
+        };
+        let target = setpoints.get(&controller.setpoint).unwrap_or_default();
+        let dead_time = latencies.get(entity).map_or(0.0, LoopLatency::dead_time);
+        controller.compensate(dead_time, dt);
+        let velocity = controller.update(target, angle, dt);
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+        if let (Some(telemetry), Some(position)) = (telemetry.as_mut(), controller.position()) {
+            telemetry.record(&controller.error, clock.elapsed_secs(), target - position);
+            telemetry.record(&controller.output, clock.elapsed_secs(), velocity);
+        }
+    }
+}
+
+/// Panel to tune the gains of the position loops while they run.
+fn pid_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<PidSettings>>,
+    mut setpoints: ResMut<Setpoints>,
+    controllers: Query<&PidController>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("PID controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(
+                &mut edited.enabled,
+                format!("Control the position of the {} joint", edited.link),
+            );
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.gains.kp)
+                        .range(0.0..=1000.0)
+                        .speed(0.1)
+                        .prefix("Kp: "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.gains.ki)
+                        .range(0.0..=1000.0)
+                        .speed(0.05)
+                        .prefix("Ki: "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.gains.kd)
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .prefix("Kd: "),
+                );
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=100.0)
+                    .speed(0.1)
+                    .prefix("Output limit: ")
+                    .suffix(" rad/s"),
+            );
+            ui.checkbox(&mut edited.smith_predictor, "Smith predictor")
+                .on_hover_text("Compensate the dead time of the latency of the loops");
+
+            ui.separator();
+            for controller in &controllers {
+                let mut target = setpoints.get(&controller.setpoint).unwrap_or_default();
+                let response = ui.add(
+                    egui::DragValue::new(&mut target)
+                        .speed(0.01)
+                        .prefix(format!("{}: ", controller.setpoint))
+                        .suffix(" rad"),
+                );
+                if response.changed() {
+                    setpoints.set(&controller.setpoint, target);
+                }
+                let latest = |channel: &str| {
+                    telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(channel))
+                        .unwrap_or_default()
+                };
+                ui.label(format!(
+                    "Error: {:.3} rad, output: {:.2} rad/s, integral: {:.2} rad/s",
+                    latest(&controller.error),
+                    latest(&controller.output),
+                    controller.pid.integral()
+                ));
+            }
+
+            ui.separator();
+            #[cfg(not(target_arch = "wasm32"))]
+            match controller_definition::definition_buttons(
+                ui,
+                &ControllerDefinition::from(&edited),
+            ) {
+                Some(Ok(definition)) => match PidSettings::try_from(&definition) {
+                    Ok(imported) => {
+                        edited = PidSettings {
+                            enabled: edited.enabled,
+                            smith_predictor: edited.smith_predictor,
+                            ..imported
+                        }
+                    }
+                    Err(error) => {
+                        commands.send_event(ErrorEvent::from(error));
+                    }
+                },
+                Some(Err(error)) => {
+                    commands.send_event(ErrorEvent::from(error));
+                }
+                None => {}
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("pid", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("pid", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
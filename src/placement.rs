@@ -0,0 +1,265 @@
+//! Comparison of candidate placements of the sensors and the actuators of a plant.
+//!
+//! Comparing the Gramians of models identified separately would be meaningless, as each has its
+//! own state basis. Instead, a single model is identified from every signal of the candidates,
+//! then each candidate keeps its own inputs (columns of `B`) and outputs (rows of `C`). The
+//! signals are normalized by their spread during the experiment, so their units don't bias the
+//! comparison.
+use bevy::prelude::*;
+use nalgebra::DMatrix;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{self, Gramians},
+    error::{Error, Result},
+    identification::{self, Dataset, Experiment, LinearModel},
+    plants::Plant,
+    setpoints::MOTOR_VELOCITY,
+    telemetry::{MOTOR_TORQUE, PENDULUM_ANGLE},
+};
+
+/// Iterations of the Riccati equation before giving up on its convergence.
+const RICCATI_ITERATIONS: usize = 100_000;
+
+/// A set of actuators (setpoints) and sensors (telemetry channels, or setpoints).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Candidate {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+/// Figure of merit of a candidate.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Trace of the observability Gramian: how much the states show in the outputs.
+    #[default]
+    ObservabilityTrace,
+    /// Smallest eigenvalue of the observability Gramian: how much the least visible direction
+    /// of the state shows.
+    ObservabilityMinEigenvalue,
+    /// Trace of the controllability Gramian: how much the inputs move the states.
+    ControllabilityTrace,
+    /// Smallest eigenvalue of the controllability Gramian: how much the inputs move the least
+    /// reachable direction of the state.
+    ControllabilityMinEigenvalue,
+    /// Cost of the optimal regulation of every output of the model, from unit initial states,
+    /// with unit weights on the inputs; infinite if the inputs can't stabilize the model.
+    LqrCost,
+}
+
+impl Metric {
+    /// Whether the best candidates have the lowest scores.
+    pub fn lower_is_better(self) -> bool {
+        self == Metric::LqrCost
+    }
+}
+
+/// The candidates to compare, from the `placement.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct Placement {
+    pub metric: Metric,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        let candidate = |name: &str, outputs: &[&str]| Candidate {
+            name: name.to_string(),
+            inputs: vec![MOTOR_VELOCITY.to_string()],
+            outputs: outputs.iter().map(|output| output.to_string()).collect(),
+        };
+        Self {
+            metric: Metric::default(),
+            candidates: vec![
+                candidate("angle encoder", &[PENDULUM_ANGLE]),
+                candidate("torque sensor", &[MOTOR_TORQUE]),
+                candidate("both", &[PENDULUM_ANGLE, MOTOR_TORQUE]),
+            ],
+        }
+    }
+}
+
+impl Placement {
+    /// Every input and every output of the candidates, in order of appearance.
+    pub fn signals(&self) -> (Vec<String>, Vec<String>) {
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for candidate in &self.candidates {
+            for input in &candidate.inputs {
+                if !inputs.contains(input) {
+                    inputs.push(input.clone());
+                }
+            }
+            for output in &candidate.outputs {
+                if !outputs.contains(output) {
+                    outputs.push(output.clone());
+                }
+            }
+        }
+        (inputs, outputs)
+    }
+}
+
+/// Score of a candidate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    pub name: String,
+    pub score: f64,
+}
+
+/// The model restricted to the inputs and the outputs of `candidate`.
+pub fn select(model: &LinearModel, candidate: &Candidate) -> Result<LinearModel> {
+    let position = |names: &[String], signal: &String| {
+        names
+            .iter()
+            .position(|name| name == signal)
+            .ok_or_else(|| Error::Config {
+                name: "placement".to_string(),
+                message: format!("`{}` uses the unknown signal `{signal}`", candidate.name),
+            })
+    };
+    let inputs = candidate
+        .inputs
+        .iter()
+        .map(|input| position(&model.inputs, input))
+        .collect::<Result<Vec<_>>>()?;
+    let outputs = candidate
+        .outputs
+        .iter()
+        .map(|output| position(&model.outputs, output))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(LinearModel {
+        dt: model.dt,
+        inputs: candidate.inputs.clone(),
+        outputs: candidate.outputs.clone(),
+        a: model.a.clone(),
+        b: model.b.select_columns(&inputs),
+        c: model.c.select_rows(&outputs),
+        d: model.d.select_rows(&outputs).select_columns(&inputs),
+        input_offsets: inputs.iter().map(|i| model.input_offsets[*i]).collect(),
+        output_offsets: outputs.iter().map(|i| model.output_offsets[*i]).collect(),
+    })
+}
+
+/// Cost of the optimal regulation of the outputs of `model` (unit weights on the outputs and on
+/// the inputs), summed over unit initial states along each state: the trace of the solution of
+/// the discrete algebraic Riccati equation. Infinite if the inputs can't stabilize the model.
+pub fn lqr_cost(model: &LinearModel) -> f64 {
+    let q = model.c.transpose() * &model.c;
+    let r = DMatrix::identity(model.b.ncols(), model.b.ncols());
+    let (a, b) = (&model.a, &model.b);
+    let mut p = q.clone();
+    for _ in 0..RICCATI_ITERATIONS {
+        let Some(gain) = (&r + b.transpose() * &p * b).try_inverse() else {
+            return f64::INFINITY;
+        };
+        let next =
+            &q + a.transpose() * &p * a - a.transpose() * &p * b * gain * b.transpose() * &p * a;
+        let change = (&next - &p).norm();
+        p = next;
+        let norm = p.norm();
+        if !change.is_finite() || !norm.is_finite() {
+            return f64::INFINITY;
+        }
+        if change <= 1e-9 * (1.0 + norm) {
+            return p.trace();
+        }
+    }
+    f64::INFINITY
+}
+
+/// Scores the candidates on `model`, which must cover all their signals, from the best to the
+/// worst.
+pub fn evaluate(model: &LinearModel, placement: &Placement) -> Result<Vec<Evaluation>> {
+    let steps = (analysis::DEFAULT_HORIZON / model.dt).round().max(1.0) as usize;
+    let mut evaluations = placement
+        .candidates
+        .iter()
+        .map(|candidate| {
+            let model = select(model, candidate)?;
+            let score = match placement.metric {
+                Metric::LqrCost => lqr_cost(&model),
+                metric => {
+                    let gramians = Gramians::of(&model, steps);
+                    match metric {
+                        Metric::ObservabilityTrace => gramians.observability.trace(),
+                        Metric::ObservabilityMinEigenvalue => {
+                            gramians.observability.symmetric_eigenvalues().min()
+                        }
+                        Metric::ControllabilityTrace => gramians.controllability.trace(),
+                        _ => gramians.controllability.symmetric_eigenvalues().min(),
+                    }
+                }
+            };
+            Ok(Evaluation {
+                name: candidate.name.clone(),
+                score,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    evaluations.sort_by(|a, b| a.score.total_cmp(&b.score));
+    if !placement.metric.lower_is_better() {
+        evaluations.reverse();
+    }
+    Ok(evaluations)
+}
+
+/// Scales the inputs and the outputs of `model` by their spread in `data`, so a unit of each is
+/// a typical excursion of the signal.
+pub fn normalize(model: &LinearModel, data: &Dataset) -> LinearModel {
+    let spread = |samples: &[Vec<f64>], channel: usize| {
+        let mean = samples.iter().map(|s| s[channel]).sum::<f64>() / samples.len() as f64;
+        let variance = samples
+            .iter()
+            .map(|s| (s[channel] - mean).powi(2))
+            .sum::<f64>()
+            / samples.len() as f64;
+        if variance > 0.0 {
+            variance.sqrt()
+        } else {
+            1.0
+        }
+    };
+    let mut normalized = model.clone();
+    for input in 0..model.inputs.len() {
+        let scale = spread(&data.inputs, input);
+        normalized.b.column_mut(input).scale_mut(scale);
+        normalized.d.column_mut(input).scale_mut(scale);
+        normalized.input_offsets[input] /= scale;
+    }
+    for output in 0..model.outputs.len() {
+        let scale = spread(&data.outputs, output);
+        normalized.c.row_mut(output).unscale_mut(scale);
+        normalized.d.row_mut(output).unscale_mut(scale);
+        normalized.output_offsets[output] /= scale;
+    }
+    normalized
+}
+
+/// Runs the experiment on `plant` with every signal of the candidates, identifies a model of
+/// them and scores the candidates on it.
+pub fn run(
+    plant: &Plant,
+    experiment: &Experiment,
+    placement: &Placement,
+    dt: f32,
+) -> Result<Vec<Evaluation>> {
+    let (inputs, outputs) = placement.signals();
+    let experiment = Experiment {
+        inputs,
+        outputs,
+        ..experiment.clone()
+    };
+    let data = identification::run_experiment(plant, &experiment, dt, 1);
+    let model = identification::identify(
+        &data,
+        &experiment.inputs,
+        &experiment.outputs,
+        experiment.order,
+        experiment.horizon,
+    )?;
+    evaluate(&normalize(&model, &data), placement)
+}
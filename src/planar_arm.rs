@@ -0,0 +1,200 @@
+//! The two-link planar arm: an upper arm and a forearm turning in a vertical plane, the simplest
+//! serial manipulator.
+//!
+//! The shoulder and the elbow are revolute joints driven like the motor of the rotary pendulum
+//! by stiff velocity servos, following the `shoulder/velocity` and `elbow/velocity` setpoints, in
+//! rad/s, so the arm holds its pose against gravity until moved. It starts pointing up. The
+//! angles of the joints, in rad, are recorded as the `shoulder/angle` and `elbow/angle` telemetry
+//! channels.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::SimClock,
+    logging::subsystem,
+    plants::{self, Instance, Link, Plant, PlantBody, MOTOR_FACTOR},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Velocity of the shoulder, in rad/s.
+pub const SHOULDER_VELOCITY: &str = "shoulder/velocity";
+/// Velocity of the elbow, in rad/s.
+pub const ELBOW_VELOCITY: &str = "elbow/velocity";
+/// Angle of the shoulder from pointing up, in rad.
+pub const SHOULDER_ANGLE: &str = "shoulder/angle";
+/// Angle of the elbow from straight, in rad.
+pub const ELBOW_ANGLE: &str = "elbow/angle";
+
+/// The two-link planar arm, as a plant.
+pub fn plant() -> Plant {
+    Plant {
+        name: "planar_arm",
+        add: |app| {
+            app.add_plugins(PlanarArmPlugin);
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<PlanarArmPlugin>() {
+                app.add_plugins(PlanarArmPlugin);
+            }
+            app.insert_resource(PlanarArmInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(PlanarArmInstances::default());
+            plants::run_spawn(world, spawn_planar_arms);
+        },
+    }
+}
+
+pub struct PlanarArmPlugin;
+
+impl Plugin for PlanarArmPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlanarArmInstances>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, spawn_planar_arms)
+            .add_systems(
+                Update,
+                (
+                    apply_joint_setpoints.run_if(resource_changed::<Setpoints>),
+                    record_joint_angles,
+                ),
+            );
+    }
+}
+
+/// The arms spawned at startup: a single one at the origin, unless composed.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct PlanarArmInstances(pub Vec<Instance>);
+
+impl Default for PlanarArmInstances {
+    fn default() -> Self {
+        Self(vec![Instance::default()])
+    }
+}
+
+/// The servo of a joint of an arm, on the entity of the joint.
+#[derive(Component)]
+struct JointServo {
+    /// Signals of the instance.
+    velocity: String,
+    angle: String,
+}
+
+fn spawn_planar_arms(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    instances: Res<PlanarArmInstances>,
+) {
+    const GROUND_THICKNESS: f32 = 0.01;
+    const GROUND_SIDE_SIZE: f32 = 100.0;
+    const SHOULDER_HEIGHT: f32 = 0.5;
+    const UPPER_ARM_LENGTH: f32 = 1.0;
+    const FOREARM_LENGTH: f32 = 0.8;
+    const THICKNESS: f32 = 0.1;
+
+    let material = materials.add(Color::srgb_u8(124, 124, 124));
+    for (index, instance) in instances.0.iter().enumerate() {
+        let link = |name: &str| Link {
+            plant: instance.namespace.clone(),
+            name: name.to_string(),
+        };
+        let shoulder = instance.offset + Vec3::Y * SHOULDER_HEIGHT;
+        let base = commands
+            .spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Mesh3d(meshes.add(Cuboid::new(0.3, SHOULDER_HEIGHT, 0.3))),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(shoulder - Vec3::Y * SHOULDER_HEIGHT / 2.0),
+            ))
+            .id();
+        if index == 0 {
+            commands.spawn((
+                RigidBody::Fixed,
+                PlantBody,
+                Collider::cuboid(GROUND_SIDE_SIZE, GROUND_THICKNESS, GROUND_SIDE_SIZE),
+                Transform::from_translation(instance.offset - Vec3::Y * GROUND_THICKNESS),
+            ));
+        }
+
+        // Clear of each other, where they are hinged.
+        let groups = CollisionGroups::new(Group::GROUP_2, Group::ALL ^ Group::GROUP_2);
+        let mut spawn_link = |name: &str, length: f32, start: f32| {
+            commands
+                .spawn((
+                    RigidBody::Dynamic,
+                    link(name),
+                    Collider::cuboid(THICKNESS / 2.0, length / 2.0, THICKNESS / 2.0),
+                    ColliderMassProperties::Mass(1.0),
+                    groups,
+                    Mesh3d(meshes.add(Cuboid::new(THICKNESS, length, THICKNESS))),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_translation(shoulder + Vec3::Y * (start + length / 2.0)),
+                ))
+                .id()
+        };
+        let upper_arm = spawn_link("upper_arm", UPPER_ARM_LENGTH, 0.0);
+        let forearm = spawn_link("forearm", FOREARM_LENGTH, UPPER_ARM_LENGTH);
+
+        let shoulder_joint = RevoluteJointBuilder::new(Vec3::Z)
+            .local_anchor1(Vec3::Y * SHOULDER_HEIGHT / 2.0)
+            .local_anchor2(-Vec3::Y * UPPER_ARM_LENGTH / 2.0)
+            .motor_velocity(0.0, MOTOR_FACTOR);
+        let elbow_joint = RevoluteJointBuilder::new(Vec3::Z)
+            .local_anchor1(Vec3::Y * UPPER_ARM_LENGTH / 2.0)
+            .local_anchor2(-Vec3::Y * FOREARM_LENGTH / 2.0)
+            .motor_velocity(0.0, MOTOR_FACTOR);
+        commands.entity(upper_arm).insert((
+            ImpulseJoint::new(base, shoulder_joint),
+            JointServo {
+                velocity: instance.signal(SHOULDER_VELOCITY),
+                angle: instance.signal(SHOULDER_ANGLE),
+            },
+        ));
+        commands.entity(forearm).insert((
+            ImpulseJoint::new(upper_arm, elbow_joint),
+            JointServo {
+                velocity: instance.signal(ELBOW_VELOCITY),
+                angle: instance.signal(ELBOW_ANGLE),
+            },
+        ));
+    }
+}
+
+/// Applies the velocity setpoints to the servos of the joints.
+fn apply_joint_setpoints(
+    setpoints: Res<Setpoints>,
+    mut servos: Query<(&JointServo, &mut ImpulseJoint)>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "apply_joint_setpoints").entered();
+    for (servo, mut joint) in &mut servos {
+        let Some(velocity) = setpoints.get(&servo.velocity) else {
+            continue;
+        };
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Records the angles of the joints.
+fn record_joint_angles(
+    clock: Res<SimClock>,
+    servos: Query<(Entity, &JointServo)>,
+    contexts: Query<&RapierContext>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::PHYSICS, "record_joint_angles").entered();
+    let (Some(mut telemetry), Ok(context)) = (telemetry, contexts.get_single()) else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    for (entity, servo) in &servos {
+        if let Some(angle) = context.impulse_revolute_joint_angle(entity) {
+            telemetry.record(&servo.angle, time, angle);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Registry of the plants shipped with the playground, and of those of the extensions.
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+
+use crate::{extensions, logging::subsystem};
+
+#[cfg(feature = "embedded-model")]
+use crate::{
+    ball_and_beam, cart_pole, compliant_axis,
+    config_plugin::ConfigPlugin,
+    double_pendulum,
+    embedded_model::{self, EmbeddedModelPlugin, PendulumInstances},
+    gantry, planar_arm,
+};
+
+/// Gain of the motors of the joints on their velocity error, high enough for them to follow the
+/// commanded velocity as servos.
+pub const MOTOR_FACTOR: f32 = 10000.0;
+
+/// A plant and how to add it to an application.
+#[derive(Clone, Copy)]
+pub struct Plant {
+    pub name: &'static str,
+    pub add: fn(&mut App),
+    /// Adds several instances of the plant, replacing the default one.
+    pub compose: fn(&mut App, &[Instance]),
+    /// Spawns a single instance into a running world, whose application has the plant added,
+    /// e.g. when switching plants.
+    pub spawn: fn(&mut World),
+}
+
+/// An instance of a plant in a composed world.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Instance {
+    /// Prefix of the signals of the instance; empty for the signals of a single plant.
+    pub namespace: String,
+    /// Position of the instance in the world.
+    pub offset: Vec3,
+}
+
+impl Instance {
+    /// Name of a signal of this instance, e.g. `arm/motor/velocity`.
+    pub fn signal(&self, name: &str) -> String {
+        namespaced(&self.namespace, name)
+    }
+}
+
+/// A rigid body of an articulated plant.
+#[derive(Clone, Component, Debug, PartialEq)]
+pub struct Link {
+    /// Namespace of the instance of the plant.
+    pub plant: String,
+    pub name: String,
+}
+
+impl Link {
+    /// Name of the link across the plants, e.g. `feeder/arm`.
+    pub fn path(&self) -> String {
+        namespaced(&self.plant, &self.name)
+    }
+}
+
+/// A body of a plant that isn't one of its links, e.g. its base or the ground, despawned with
+/// the links when switching plants.
+#[derive(Clone, Component, Copy, Debug, Default)]
+pub struct PlantBody;
+
+/// Runs the startup `system` spawning a plant, to spawn it into a running world.
+pub fn run_spawn<M>(world: &mut World, system: impl IntoSystem<(), (), M>) {
+    if let Err(error) = world.run_system_once(system) {
+        error!(target: subsystem::PHYSICS, "Spawning the plant failed: {error}");
+    }
+}
+
+/// Prefixes a signal with a namespace, unless it's empty.
+pub fn namespaced(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{namespace}/{name}")
+    }
+}
+
+/// The plants available in this build.
+pub fn builtin() -> Vec<Plant> {
+    #[allow(unused_mut)]
+    let mut plants = Vec::new();
+    #[cfg(feature = "embedded-model")]
+    plants.push(Plant {
+        name: "rotary_pendulum",
+        add: |app| {
+            app.add_plugins((ConfigPlugin, EmbeddedModelPlugin));
+        },
+        compose: |app, instances| {
+            if !app.is_plugin_added::<ConfigPlugin>() {
+                app.add_plugins(ConfigPlugin);
+            }
+            if !app.is_plugin_added::<EmbeddedModelPlugin>() {
+                app.add_plugins(EmbeddedModelPlugin);
+            }
+            app.insert_resource(PendulumInstances(instances.to_vec()));
+        },
+        spawn: |world| {
+            world.insert_resource(PendulumInstances::default());
+            run_spawn(world, embedded_model::add_rotary_interved_pendulum);
+        },
+    });
+    #[cfg(feature = "embedded-model")]
+    plants.extend([
+        cart_pole::plant(),
+        double_pendulum::plant(),
+        ball_and_beam::plant(),
+        planar_arm::plant(),
+        compliant_axis::plant(),
+        gantry::plant(),
+    ]);
+    plants
+}
+
+/// The plants of this build and of the registered extensions.
+pub fn available() -> Vec<Plant> {
+    let mut plants = builtin();
+    for extension in extensions::registered() {
+        plants.extend(extension.plants);
+    }
+    plants
+}
@@ -0,0 +1,403 @@
+//! This module abstracts over the platforms the files of the playground come from and go to, so
+//! the web build exposes the same workflow as the desktop one without paths on the command line
+//! or files written next to it.
+//!
+//! Files are opened by dropping them onto the window, or, in the browser, from its file picker:
+//! their contents are sent as [`FileOpened`] events, whatever the platform. The glTF models
+//! opened are kept in memory, as the `opened://` asset source, and spawned into the scene,
+//! replacing the one opened before. The telemetry is exported as CSV with [`save_file`]: written
+//! to a file on the desktop, and downloaded in the browser. The *Files* panel opens the models and
+//! exports the telemetry, and lists the subsystems of the desktop build missing from the web one.
+use std::path::Path;
+#[cfg(target_arch = "wasm32")]
+use std::sync::Mutex;
+
+use bevy::{
+    asset::io::{
+        memory::{Dir, MemoryAssetReader},
+        AssetSource,
+    },
+    prelude::*,
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    telemetry::Telemetry,
+};
+
+/// Asset source of the files opened while the application runs.
+pub const OPENED_SOURCE: &str = "opened";
+/// File the telemetry is exported to, or downloaded as in the browser.
+pub const TELEMETRY_FILE: &str = "telemetry.csv";
+/// Extensions of the models that can be opened.
+pub const MODEL_EXTENSIONS: [&str; 2] = ["glb", "gltf"];
+/// Subsystems of the desktop build, reading or writing files, running threads or opening
+/// sockets, which the web build leaves out.
+pub const DESKTOP_ONLY: [&str; 11] = [
+    "Autosave",
+    "Recordings, replays and snapshots",
+    "Capture",
+    "Experiment queue",
+    "Scripted controller",
+    "Scenarios",
+    "Scene compositions",
+    "URDF robots",
+    "Remote sessions",
+    "Co-simulation and fieldbuses",
+    "Headless batch runs and sweeps",
+];
+
+/// Files dropped or picked in the browser, until the next update sends them.
+#[cfg(target_arch = "wasm32")]
+static RECEIVED: Mutex<Vec<FileOpened>> = Mutex::new(Vec::new());
+
+/// Opens files and exports the telemetry on every platform. Add it before the `AssetPlugin`, so
+/// the opened files can be loaded as assets.
+pub struct PlatformPlugin;
+
+impl Plugin for PlatformPlugin {
+    fn build(&self, app: &mut App) {
+        let files = Dir::default();
+        let root = files.clone();
+        app.register_asset_source(
+            OPENED_SOURCE,
+            AssetSource::build()
+                .with_reader(move || Box::new(MemoryAssetReader { root: root.clone() })),
+        )
+        .insert_resource(OpenedFiles {
+            files,
+            opened: 0,
+            model: None,
+        })
+        .add_event::<FileOpened>()
+        .add_systems(Update, (receive_files, open_models).chain())
+        .add_systems(Update, files_panel.run_if(has_ui));
+
+        #[cfg(target_arch = "wasm32")]
+        app.add_systems(Startup, listen);
+    }
+}
+
+/// A file opened, with its contents.
+#[derive(Clone, Debug, Event)]
+pub struct FileOpened {
+    /// Name of the file, without its directory.
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The files opened so far, in memory.
+#[derive(Resource)]
+pub struct OpenedFiles {
+    files: Dir,
+    /// Number of files opened, numbering their directories so they're loaded again when opened
+    /// again.
+    opened: usize,
+    /// Name of the model in the scene.
+    pub model: Option<String>,
+}
+
+/// The model opened, spawned into the scene.
+#[derive(Component)]
+pub struct OpenedModel;
+
+/// Whether the file is a model that can be opened, after its extension.
+pub fn is_model(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            MODEL_EXTENSIONS
+                .iter()
+                .any(|model| extension.eq_ignore_ascii_case(model))
+        })
+}
+
+/// Saves `bytes` of the given `mime` type to `path`, or downloads them as a file of its name in
+/// the browser, and tells where they went.
+pub fn save_file(path: &Path, bytes: &[u8], mime: &str) -> Result<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = mime;
+        std::fs::write(path, bytes).map_err(|error| Error::io(path, error))?;
+        Ok(path.display().to_string())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        web::download(&name, bytes, mime).map_err(|message| Error::Config {
+            name: name.clone(),
+            message: format!("failed to download: {message}"),
+        })?;
+        Ok(format!("{name}, downloaded"))
+    }
+}
+
+/// Receives the files dropped onto the page of the browser.
+#[cfg(target_arch = "wasm32")]
+fn listen(mut commands: Commands) {
+    if let Err(message) = web::listen() {
+        commands.send_event(ErrorEvent::from(Error::Config {
+            name: "files".to_string(),
+            message: format!("failed to receive the dropped files: {message}"),
+        }));
+    }
+}
+
+/// Sends the files dropped onto the window as they're read.
+#[cfg(not(target_arch = "wasm32"))]
+fn receive_files(
+    mut commands: Commands,
+    mut dropped: EventReader<FileDragAndDrop>,
+    mut opened: EventWriter<FileOpened>,
+) {
+    for event in dropped.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+        match std::fs::read(path_buf) {
+            Ok(bytes) => {
+                let name = path_buf
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                opened.send(FileOpened { name, bytes });
+            }
+            Err(error) => {
+                commands.send_event(ErrorEvent::from(Error::io(path_buf, error)));
+            }
+        }
+    }
+}
+
+/// Sends the files dropped or picked in the browser as they're read.
+#[cfg(target_arch = "wasm32")]
+fn receive_files(mut opened: EventWriter<FileOpened>) {
+    if let Ok(mut received) = RECEIVED.lock() {
+        opened.send_batch(received.drain(..));
+    }
+}
+
+/// Spawns the models opened into the scene, in place of the one opened before.
+fn open_models(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut files: ResMut<OpenedFiles>,
+    mut opened: EventReader<FileOpened>,
+    models: Query<Entity, With<OpenedModel>>,
+) {
+    for file in opened.read() {
+        if !is_model(&file.name) {
+            commands.send_event(ErrorEvent::from(Error::Config {
+                name: file.name.clone(),
+                message: "only glTF models (.glb or .gltf) can be opened".to_string(),
+            }));
+            continue;
+        }
+        files.opened += 1;
+        let path = format!("{}/{}", files.opened, file.name);
+        files
+            .files
+            .insert_asset(Path::new(&path), file.bytes.clone());
+        for model in &models {
+            commands.entity(model).despawn_recursive();
+        }
+        let source = format!("{OPENED_SOURCE}://{path}");
+        commands.spawn((
+            Name::new(file.name.clone()),
+            OpenedModel,
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(source))),
+        ));
+        info!(target: subsystem::IO, "Opened {}", file.name);
+        files.model = Some(file.name.clone());
+    }
+}
+
+/// Panel to open the models and export the telemetry.
+fn files_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut files: ResMut<OpenedFiles>,
+    models: Query<Entity, With<OpenedModel>>,
+    telemetry: Option<Res<Telemetry>>,
+    mut exported: Local<Option<String>>,
+) {
+    egui::Window::new("Files")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            #[cfg(target_arch = "wasm32")]
+            if ui.button("Open a glTF model").clicked() {
+                if let Err(message) = web::pick(".glb,.gltf") {
+                    commands.send_event(ErrorEvent::from(Error::Config {
+                        name: "files".to_string(),
+                        message: format!("failed to open the file picker: {message}"),
+                    }));
+                }
+            }
+            ui.label("Drop a glTF model (.glb) onto the window to open it.")
+                .on_hover_text("A .gltf model opens only if its buffers are embedded");
+            if let Some(model) = files.model.clone() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Model: {model}"));
+                    if ui.button("Remove").clicked() {
+                        for entity in &models {
+                            commands.entity(entity).despawn_recursive();
+                        }
+                        files.model = None;
+                    }
+                });
+            }
+
+            ui.separator();
+            let samples = telemetry.as_ref().map_or(0, |telemetry| telemetry.len());
+            ui.add_enabled_ui(samples > 0, |ui| {
+                if ui.button("Export the telemetry").clicked() {
+                    let Some(telemetry) = telemetry.as_ref() else {
+                        return;
+                    };
+                    let csv = telemetry.to_csv();
+                    match save_file(Path::new(TELEMETRY_FILE), csv.as_bytes(), "text/csv") {
+                        Ok(saved) => {
+                            info!(target: subsystem::IO, "Exported the telemetry to {saved}");
+                            *exported = Some(saved);
+                        }
+                        Err(error) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+            });
+            ui.label(format!("{samples} samples"));
+            if let Some(exported) = exported.as_ref() {
+                ui.label(format!("Exported to {exported}"));
+            }
+
+            #[cfg(target_arch = "wasm32")]
+            ui.collapsing("Desktop only", |ui| {
+                for subsystem in DESKTOP_ONLY {
+                    ui.label(subsystem);
+                }
+            });
+        });
+}
+
+/// The browser side: the listeners of the page, its file picker and its downloads.
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use js_sys::{Array, Uint8Array};
+    use wasm_bindgen::{prelude::*, JsCast};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{
+        Blob, BlobPropertyBag, DragEvent, FileList, HtmlAnchorElement, HtmlInputElement, Url,
+    };
+
+    use super::{FileOpened, RECEIVED};
+    use crate::logging::subsystem;
+
+    fn failed(error: JsValue) -> String {
+        error.as_string().unwrap_or_else(|| format!("{error:?}"))
+    }
+
+    /// Reads the files, adding them to the received ones as their contents arrive.
+    fn read(files: FileList) {
+        for index in 0..files.length() {
+            let Some(file) = files.get(index) else {
+                continue;
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                match JsFuture::from(file.array_buffer()).await {
+                    Ok(buffer) => {
+                        let bytes = Uint8Array::new(&buffer).to_vec();
+                        if let Ok(mut received) = RECEIVED.lock() {
+                            received.push(FileOpened {
+                                name: file.name(),
+                                bytes,
+                            });
+                        }
+                    }
+                    Err(error) => bevy::log::warn!(
+                        target: subsystem::IO,
+                        "Failed to read {}: {}",
+                        file.name(),
+                        failed(error)
+                    ),
+                }
+            });
+        }
+    }
+
+    /// Receives the files dropped onto the page, rather than letting the browser open them.
+    pub fn listen() -> Result<(), String> {
+        let window = web_sys::window().ok_or("no window")?;
+        let over = Closure::<dyn FnMut(DragEvent)>::new(|event: DragEvent| {
+            event.prevent_default();
+        });
+        window
+            .add_event_listener_with_callback("dragover", over.as_ref().unchecked_ref())
+            .map_err(failed)?;
+        over.forget();
+        let dropped = Closure::<dyn FnMut(DragEvent)>::new(|event: DragEvent| {
+            event.prevent_default();
+            if let Some(files) = event.data_transfer().and_then(|transfer| transfer.files()) {
+                read(files);
+            }
+        });
+        window
+            .add_event_listener_with_callback("drop", dropped.as_ref().unchecked_ref())
+            .map_err(failed)?;
+        dropped.forget();
+        Ok(())
+    }
+
+    /// Opens the file picker of the browser, for the files of the `accept`ed extensions.
+    pub fn pick(accept: &str) -> Result<(), String> {
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or("no document")?;
+        let input: HtmlInputElement = document
+            .create_element("input")
+            .map_err(failed)?
+            .dyn_into()
+            .map_err(|_| "not an input")?;
+        input.set_type("file");
+        input.set_accept(accept);
+        let picked = input.clone();
+        let change = Closure::<dyn FnMut()>::new(move || {
+            if let Some(files) = picked.files() {
+                read(files);
+            }
+        });
+        input.set_onchange(Some(change.as_ref().unchecked_ref()));
+        change.forget();
+        input.click();
+        Ok(())
+    }
+
+    /// Downloads `bytes` of the given `mime` type as a file named `name`.
+    pub fn download(name: &str, bytes: &[u8], mime: &str) -> Result<(), String> {
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or("no document")?;
+        let parts = Array::of1(&Uint8Array::from(bytes));
+        let options = BlobPropertyBag::new();
+        options.set_type(mime);
+        let blob =
+            Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(failed)?;
+        let url = Url::create_object_url_with_blob(&blob).map_err(failed)?;
+        let anchor: HtmlAnchorElement = document
+            .create_element("a")
+            .map_err(failed)?
+            .dyn_into()
+            .map_err(|_| "not an anchor")?;
+        anchor.set_href(&url);
+        anchor.set_download(name);
+        anchor.click();
+        Url::revoke_object_url(&url).map_err(failed)
+    }
+}
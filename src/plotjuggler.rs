@@ -0,0 +1,236 @@
+//! Telemetry stream for PlotJuggler, or any other plotting tool with a UDP input.
+//!
+//! An instance started with `--plotjuggler` sends a datagram to the given address after the
+//! physics steps, holding the simulated time in `timestamp` and the latest value of the signals
+//! due, by name, e.g. `{"timestamp":1.25,"motor/angle":0.42}`. In PlotJuggler, start the *UDP
+//! Server* streamer on the same port with the JSON or MessagePack protocol, and pick
+//! `timestamp` as the time field; the names split into a tree at the `/`s.
+//!
+//! The signals, telemetry channels or setpoints, and their rates are read from
+//! `plotjuggler.json`: each signal is sent at most at its `rate`, or at the common one, in Hz of
+//! real time, so a run faster than real time doesn't flood the plots. Without signals, every
+//! telemetry channel is sent.
+use std::{
+    collections::BTreeMap,
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::Real};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Port used when none is given, the default of the UDP server of PlotJuggler.
+pub const DEFAULT_PORT: u16 = 9870;
+/// Rate of the signals when none is given, in Hz.
+pub const DEFAULT_RATE: f32 = 100.0;
+/// Highest rate of a signal, in Hz.
+pub const MAX_RATE: f32 = 1000.0;
+/// Key of the simulated time in the datagrams.
+pub const TIMESTAMP_KEY: &str = "timestamp";
+
+/// Encoding of the datagrams.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    /// A datagram with the simulated `time` and the `values`, by name.
+    pub fn encode(self, time: f32, values: &BTreeMap<String, f32>) -> Vec<u8> {
+        let entries = std::iter::once((TIMESTAMP_KEY, time))
+            .chain(values.iter().map(|(name, value)| (name.as_str(), *value)));
+        match self {
+            Encoding::Json => {
+                let object = entries
+                    .map(|(name, value)| (name.to_string(), serde_json::Value::from(value)))
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::Value::Object(object).to_string().into_bytes()
+            }
+            Encoding::MessagePack => {
+                let mut bytes = Vec::new();
+                write_map_len(&mut bytes, values.len() + 1);
+                for (name, value) in entries {
+                    write_str(&mut bytes, name);
+                    bytes.push(0xcb);
+                    bytes.extend(f64::from(value).to_be_bytes());
+                }
+                bytes
+            }
+        }
+    }
+}
+
+/// Writes the header of a MessagePack map of `len` entries.
+fn write_map_len(bytes: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=15 => bytes.push(0x80 | len as u8),
+        16..=0xffff => {
+            bytes.push(0xde);
+            bytes.extend((len as u16).to_be_bytes());
+        }
+        _ => {
+            bytes.push(0xdf);
+            bytes.extend((len as u32).to_be_bytes());
+        }
+    }
+}
+
+/// Writes a MessagePack string.
+fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    match text.len() {
+        len @ 0..=31 => bytes.push(0xa0 | len as u8),
+        len @ 32..=0xff => bytes.extend([0xd9, len as u8]),
+        len @ 0x100..=0xffff => {
+            bytes.push(0xda);
+            bytes.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            bytes.push(0xdb);
+            bytes.extend((len as u32).to_be_bytes());
+        }
+    }
+    bytes.extend(text.as_bytes());
+}
+
+/// A signal sent to the plots.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StreamedSignal {
+    /// Telemetry channel or setpoint.
+    pub name: String,
+    /// Rate of the signal, in Hz, instead of the common one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f32>,
+}
+
+/// The signals sent to the plots.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct PlotJugglerSettings {
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Rate of the signals without a rate of their own, in Hz.
+    pub rate: f32,
+    /// Signals sent, every telemetry channel when empty.
+    #[serde(default)]
+    pub signals: Vec<StreamedSignal>,
+}
+
+impl Default for PlotJugglerSettings {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Json,
+            rate: DEFAULT_RATE,
+            signals: Vec::new(),
+        }
+    }
+}
+
+/// Time between two values at `rate` Hz.
+fn interval(rate: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / rate.clamp(f32::EPSILON, MAX_RATE))
+}
+
+/// Streams the telemetry to the given address.
+pub struct PlotJugglerPlugin {
+    pub remote: SocketAddr,
+    pub settings: PlotJugglerSettings,
+}
+
+impl Plugin for PlotJugglerPlugin {
+    fn build(&self, app: &mut App) {
+        let bind = match self.remote {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0; 16], 0)),
+        };
+        let socket = match UdpSocket::bind(bind).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "Streaming the telemetry to udp://{}", self.remote);
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(self.settings.clone())
+            .insert_resource(PlotJuggler {
+                socket,
+                remote: self.remote,
+                last: BTreeMap::new(),
+            })
+            .add_systems(PostUpdate, stream_telemetry.after(SimClockSet::Advance));
+    }
+}
+
+/// State of the stream.
+#[derive(Resource)]
+pub struct PlotJuggler {
+    socket: UdpSocket,
+    remote: SocketAddr,
+    /// Real time each signal was last sent at.
+    last: BTreeMap<String, Duration>,
+}
+
+/// Sends the signals due after the physics steps.
+fn stream_telemetry(
+    mut stream: ResMut<PlotJuggler>,
+    settings: Res<PlotJugglerSettings>,
+    time: Res<Time<Real>>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    telemetry: Res<Telemetry>,
+) {
+    if clock.delta() == Duration::ZERO {
+        return;
+    }
+    let now = time.elapsed();
+    let signals = if settings.signals.is_empty() {
+        telemetry
+            .channels
+            .keys()
+            .map(|name| (name.as_str(), settings.rate))
+            .collect::<Vec<_>>()
+    } else {
+        settings
+            .signals
+            .iter()
+            .map(|signal| (signal.name.as_str(), signal.rate.unwrap_or(settings.rate)))
+            .collect()
+    };
+    let mut values = BTreeMap::new();
+    for (name, rate) in signals {
+        let due = stream
+            .last
+            .get(name)
+            .is_none_or(|last| now.saturating_sub(*last) >= interval(rate));
+        let value = telemetry.latest(name).or_else(|| setpoints.get(name));
+        if let Some(value) = value.filter(|_| due) {
+            stream.last.insert(name.to_string(), now);
+            values.insert(name.to_string(), value);
+        }
+    }
+    if values.is_empty() {
+        return;
+    }
+    let datagram = settings.encoding.encode(clock.elapsed_secs(), &values);
+    if let Err(error) = stream.socket.send_to(&datagram, stream.remote) {
+        debug!(
+            target: subsystem::IO,
+            "Failed to send the telemetry to {}: {error}", stream.remote
+        );
+    }
+}
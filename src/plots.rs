@@ -0,0 +1,317 @@
+//! This module plots the telemetry live, for tuning the controllers while the plant runs.
+//!
+//! The *Plots* window charts any telemetry channel, e.g. the angle of the pendulum, the torque
+//! of the motor or the error of a controller, and the setpoints, so a setpoint and its
+//! measurement can be compared on the same axes. The channels plotted are toggled one by one.
+//! The plot scrolls with the last seconds of the run; paused, it holds the run up to the pause,
+//! to be zoomed and dragged through. The window length and the channels are configured in
+//! `plots.json`. Recorded series, e.g. logs of the hardware, can be overlaid on the live ones
+//! through [`Overlays`].
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{Legend, Line, Plot, PlotBounds, PlotPoints};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    setpoints::Setpoints,
+    telemetry::{self, Sample, Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+    theme::{to_egui, Theme},
+};
+
+/// Prefix of the series of the setpoints.
+pub const SETPOINT_PREFIX: &str = "setpoint/";
+/// Most points of a series drawn; longer series are decimated, keeping their envelope.
+const MAX_POINTS: usize = 2000;
+
+pub struct PlotsPlugin;
+
+impl Plugin for PlotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Telemetry>()
+            .init_resource::<Setpoints>()
+            .init_resource::<SetpointHistory>()
+            .init_resource::<Overlays>()
+            .init_resource::<PlotView>()
+            .add_systems(Startup, setup)
+            .add_systems(PostUpdate, record_setpoints.after(SimClockSet::Advance))
+            .add_systems(
+                Update,
+                plots_panel
+                    .run_if(resource_exists::<Persistent<PlotSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the plots.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct PlotSettings {
+    /// Length of the scrolling time window, in s.
+    pub window: f32,
+    /// Series plotted: telemetry channels, or setpoints as `setpoint/<name>`.
+    pub series: Vec<String>,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        Self {
+            window: 10.0,
+            series: vec![PENDULUM_ANGLE.to_string(), MOTOR_TORQUE.to_string()],
+        }
+    }
+}
+
+/// The values taken by the setpoints, as step-shaped series.
+#[derive(Debug, Default, Resource)]
+pub struct SetpointHistory {
+    pub series: BTreeMap<String, Vec<Sample>>,
+}
+
+impl SetpointHistory {
+    /// Records the value of a setpoint at `time`, if it changed: the previous value is held
+    /// until then.
+    pub fn record(&mut self, name: &str, time: f32, value: f32) {
+        let samples = self.series.entry(name.to_string()).or_default();
+        match samples.last() {
+            Some([_, last]) if *last == value => {}
+            Some([_, last]) => {
+                let last = *last;
+                samples.extend([[time, last], [time, value]]);
+            }
+            None => samples.push([time, value]),
+        }
+    }
+
+    /// The samples of a setpoint up to `time`, its last value held until then.
+    pub fn samples(&self, name: &str, time: f32) -> Vec<Sample> {
+        let Some(samples) = self.series.get(name) else {
+            return Vec::new();
+        };
+        let mut samples: Vec<Sample> = samples
+            .iter()
+            .copied()
+            .filter(|[t, _]| *t <= time)
+            .collect();
+        if let Some([_, last]) = samples.last().copied() {
+            samples.push([time, last]);
+        }
+        samples
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+}
+
+/// Recorded series plotted with the telemetry, by name, on the simulated time.
+#[derive(Debug, Default, Resource)]
+pub struct Overlays {
+    pub series: BTreeMap<String, Vec<Sample>>,
+}
+
+/// State of the plot window.
+#[derive(Debug, Default, Resource)]
+pub struct PlotView {
+    /// Time the plot was paused at, if paused.
+    pub paused_at: Option<f32>,
+    /// Whether the bounds of the plot are set back to the last window on the next frame.
+    reset: bool,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<PlotSettings>("plots", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn record_setpoints(
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    mut history: ResMut<SetpointHistory>,
+) {
+    let time = clock.elapsed_secs();
+    // The simulated time restarts when the session is rewound.
+    if history
+        .series
+        .values()
+        .filter_map(|samples| samples.last())
+        .any(|[last, _]| *last > time)
+    {
+        history.clear();
+    }
+    for (name, value) in &setpoints.values {
+        history.record(name, time, *value);
+    }
+}
+
+/// Samples of `samples` between `start` and `end`, decimated to about [`MAX_POINTS`].
+fn visible(samples: &[Sample], start: f32, end: f32) -> Vec<[f64; 2]> {
+    let first = samples.partition_point(|[time, _]| *time < start);
+    let last = samples.partition_point(|[time, _]| *time <= end);
+    let samples = &samples[first..last.max(first)];
+    telemetry::decimate(samples, samples.len().div_ceil(MAX_POINTS / 2))
+        .into_iter()
+        .map(|[time, value]| [f64::from(time), f64::from(value)])
+        .collect()
+}
+
+/// Window charting the selected series.
+fn plots_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<PlotSettings>>,
+    mut view: ResMut<PlotView>,
+    (clock, telemetry, history, overlays): (
+        Res<SimClock>,
+        Res<Telemetry>,
+        Res<SetpointHistory>,
+        Res<Overlays>,
+    ),
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let mut edited = settings.get().clone();
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+    let now = view.paused_at.unwrap_or_else(|| clock.elapsed_secs());
+    egui::Window::new("Plots")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let label = if view.paused_at.is_some() {
+                    "Resume"
+                } else {
+                    "Pause"
+                };
+                if ui.button(label).clicked() {
+                    view.paused_at = match view.paused_at {
+                        Some(_) => None,
+                        None => Some(now),
+                    };
+                    view.reset = true;
+                }
+                if ui
+                    .add_enabled(view.paused_at.is_some(), egui::Button::new("Reset zoom"))
+                    .clicked()
+                {
+                    view.reset = true;
+                }
+                ui.add(
+                    egui::DragValue::new(&mut edited.window)
+                        .range(0.5..=600.0)
+                        .speed(0.1)
+                        .prefix("Window: ")
+                        .suffix(" s"),
+                );
+            });
+
+            let mut available: Vec<String> = telemetry.channels.keys().cloned().collect();
+            available.extend(
+                history
+                    .series
+                    .keys()
+                    .map(|name| format!("{SETPOINT_PREFIX}{name}")),
+            );
+            available.extend(overlays.series.keys().cloned());
+            egui::CollapsingHeader::new("Series")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for name in &available {
+                            let mut selected = edited.series.contains(name);
+                            if ui.checkbox(&mut selected, name).changed() {
+                                if selected {
+                                    edited.series.push(name.clone());
+                                } else {
+                                    edited.series.retain(|series| series != name);
+                                }
+                            }
+                        }
+                    });
+                });
+
+            let paused = view.paused_at.is_some();
+            // Live, only the window is drawn; paused, the run up to the pause.
+            let start = if paused {
+                f32::NEG_INFINITY
+            } else {
+                now - edited.window
+            };
+            let mut plot = Plot::new("live_plot")
+                .legend(Legend::default())
+                .height(240.0)
+                .x_axis_label("Time (s)")
+                .allow_zoom(paused)
+                .allow_drag(paused)
+                .allow_scroll(paused)
+                .allow_boxed_zoom(paused);
+            let reset = std::mem::take(&mut view.reset);
+            if !paused {
+                plot = plot.include_x(now - edited.window).include_x(now);
+                if reset {
+                    plot = plot.reset();
+                }
+            }
+            // Range of the values in the window, to zoom back to.
+            let (mut low, mut high) = (f64::INFINITY, f64::NEG_INFINITY);
+            plot.show(ui, |plot_ui| {
+                for (index, name) in edited.series.iter().enumerate() {
+                    let points = match name.strip_prefix(SETPOINT_PREFIX) {
+                        Some(setpoint) if !telemetry.channels.contains_key(name) => {
+                            visible(&history.samples(setpoint, now), start, now)
+                        }
+                        _ => telemetry
+                            .channels
+                            .get(name)
+                            .or_else(|| overlays.series.get(name))
+                            .map(|samples| visible(samples, start, now))
+                            .unwrap_or_default(),
+                    };
+                    for [time, value] in &points {
+                        if *time >= f64::from(now - edited.window) {
+                            (low, high) = (low.min(*value), high.max(*value));
+                        }
+                    }
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(points))
+                            .name(name)
+                            .color(to_egui(theme.series(index))),
+                    );
+                }
+                if reset && paused && low <= high {
+                    let margin = ((high - low) * 0.05).max(1e-3);
+                    plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                        [f64::from(now - edited.window), low - margin],
+                        [f64::from(now), high + margin],
+                    ));
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("plots", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("plots", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
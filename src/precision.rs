@@ -0,0 +1,279 @@
+//! Numerical precision of the dynamics: the double pendulum is simulated by Rapier, in `f32`,
+//! alongside an analytic model of the same pendulum built in `f32` and in `f64`, and their
+//! divergence over a long run is reported.
+//!
+//! The analytic model integrates the equations of motion of the links, uniform rods hinged end
+//! to end, by the classic Runge–Kutta method over [`SUBSTEPS`] substeps of each physics step.
+//! Built in `f64`, it is the reference. The divergence of the same model built in `f32` is the
+//! effect of the precision alone; that of Rapier adds those of its integrator and its joints.
+//! The pendulum being chaotic, both grow exponentially, so a long run tells how long a
+//! trajectory holds. The energy of the pendulum is conserved, so the drift of the energy of the
+//! models measures the errors of their integration.
+use std::{array, fmt, fs, io, path::Path};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use nalgebra::RealField;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::SimClock,
+    determinism::CHECKPOINT_INTERVAL,
+    double_pendulum::{self, LINK_MASS, LOWER_LENGTH, THICKNESS, UPPER_LENGTH},
+    error::{Error, Result},
+    estimation::wrap,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants::Link,
+};
+
+/// Number of physics steps of the comparison (60 s).
+pub const PRECISION_STEPS: usize = 3600;
+/// Number of steps of the analytic model per physics step.
+pub const SUBSTEPS: usize = 10;
+
+/// Analytic model of the double pendulum, in the precision `T`: two uniform rods hinged end to
+/// end from a fixed pivot, swinging about parallel axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DoublePendulumModel<T> {
+    /// Angles of the upper and of the lower links from hanging, in rad, each followed by its
+    /// velocity, in rad/s.
+    pub state: [T; 4],
+    /// Terms of the mass matrix: of the upper link, of their coupling and of the lower link.
+    inertia: [T; 3],
+    /// Torques of gravity on the upper and on the lower links, at right angles from hanging.
+    weight: [T; 2],
+}
+
+impl<T: RealField + Copy> DoublePendulumModel<T> {
+    /// A pendulum of links of `masses`, in kg, `lengths` and `thickness`, in m, under `gravity`,
+    /// in m/s², at rest with the upper link at `angles[0]` from hanging and the lower one at
+    /// `angles[1]` from the upper one.
+    pub fn new(
+        masses: [f64; 2],
+        lengths: [f64; 2],
+        thickness: f64,
+        gravity: f64,
+        angles: [f64; 2],
+    ) -> Self {
+        let [upper, lower] = masses;
+        let [upper_length, lower_length] = lengths;
+        // About their middles.
+        let own = |mass: f64, length: f64| mass * (length * length + thickness * thickness) / 12.0;
+        let inertia = [
+            upper * upper_length * upper_length / 4.0
+                + own(upper, upper_length)
+                + lower * upper_length * upper_length,
+            lower * upper_length * lower_length / 2.0,
+            lower * lower_length * lower_length / 4.0 + own(lower, lower_length),
+        ];
+        let weight = [
+            (upper / 2.0 + lower) * upper_length * gravity,
+            lower * lower_length / 2.0 * gravity,
+        ];
+        let state = [angles[0], 0.0, angles[0] + angles[1], 0.0];
+        Self {
+            state: state.map(nalgebra::convert),
+            inertia: inertia.map(nalgebra::convert),
+            weight: weight.map(nalgebra::convert),
+        }
+    }
+
+    /// The pendulum of the plant, released horizontal under `gravity`, in m/s².
+    pub fn released(gravity: f64) -> Self {
+        Self::new(
+            [LINK_MASS.into(); 2],
+            [UPPER_LENGTH.into(), LOWER_LENGTH.into()],
+            THICKNESS.into(),
+            gravity,
+            [std::f64::consts::FRAC_PI_2, 0.0],
+        )
+    }
+
+    /// Angle of the upper link from hanging, and of the lower link from the upper one, in rad,
+    /// over the turns.
+    pub fn angles(&self) -> [T; 2] {
+        [self.state[0], self.state[2] - self.state[0]]
+    }
+
+    /// Kinetic and potential energy, in J, from the pivot.
+    pub fn energy(&self) -> T {
+        let [upper, upper_velocity, lower, lower_velocity] = self.state;
+        let [upper_inertia, coupling, lower_inertia] = self.inertia;
+        let half = nalgebra::convert::<f64, T>(0.5);
+        half * upper_inertia * upper_velocity * upper_velocity
+            + coupling * (upper - lower).cos() * upper_velocity * lower_velocity
+            + half * lower_inertia * lower_velocity * lower_velocity
+            - self.weight[0] * upper.cos()
+            - self.weight[1] * lower.cos()
+    }
+
+    /// Integrates the motion over `dt` seconds.
+    pub fn step(&mut self, dt: T) {
+        let half = dt * nalgebra::convert::<f64, T>(0.5);
+        let shifted = |rates: [T; 4], by: T| array::from_fn(|i| self.state[i] + rates[i] * by);
+        let first = self.rates(self.state);
+        let second = self.rates(shifted(first, half));
+        let third = self.rates(shifted(second, half));
+        let fourth = self.rates(shifted(third, dt));
+        let two = nalgebra::convert::<f64, T>(2.0);
+        let sixth = dt / nalgebra::convert::<f64, T>(6.0);
+        self.state = array::from_fn(|i| {
+            self.state[i] + sixth * (first[i] + two * second[i] + two * third[i] + fourth[i])
+        });
+    }
+
+    /// Derivative of a state.
+    fn rates(&self, state: [T; 4]) -> [T; 4] {
+        let [upper, upper_velocity, lower, lower_velocity] = state;
+        let [upper_inertia, coupling, lower_inertia] = self.inertia;
+        let (sin, cos) = (upper - lower).sin_cos();
+        // Torques of gravity and of the motion of the other link.
+        let upper_torque =
+            -coupling * sin * lower_velocity * lower_velocity - self.weight[0] * upper.sin();
+        let lower_torque =
+            coupling * sin * upper_velocity * upper_velocity - self.weight[1] * lower.sin();
+        let mixed = coupling * cos;
+        let determinant = upper_inertia * lower_inertia - mixed * mixed;
+        [
+            upper_velocity,
+            (lower_inertia * upper_torque - mixed * lower_torque) / determinant,
+            lower_velocity,
+            (upper_inertia * lower_torque - mixed * upper_torque) / determinant,
+        ]
+    }
+}
+
+/// Divergences after a step.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PrecisionCheckpoint {
+    /// Number of steps simulated.
+    pub step: usize,
+    /// Angles of the links of the `f64` model, in rad within a turn, as those of the telemetry.
+    pub reference: [f64; 2],
+    /// Largest difference of the angles of Rapier and of the `f32` model from the reference, in
+    /// rad.
+    pub rapier_divergence: f64,
+    pub single_divergence: f64,
+    /// Change of the energy of the `f32` and of the `f64` models since the release, in J.
+    pub single_energy_drift: f64,
+    pub double_energy_drift: f64,
+}
+
+/// Outcome of a comparison of the precisions.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PrecisionReport {
+    pub plant: String,
+    pub steps: usize,
+    pub dt: f32,
+    pub substeps: usize,
+    pub checkpoints: Vec<PrecisionCheckpoint>,
+}
+
+impl PrecisionReport {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+impl fmt::Display for PrecisionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let largest = |value: fn(&PrecisionCheckpoint) -> f64| {
+            self.checkpoints
+                .iter()
+                .map(|checkpoint| value(checkpoint).abs())
+                .fold(0.0, f64::max)
+        };
+        write!(
+            f,
+            "{} over {} s: Rapier diverged from the f64 model by up to {:e} rad, the f32 model \
+             by up to {:e} rad; the energy drifted by up to {:e} J in f32, {:e} J in f64",
+            self.plant,
+            self.steps as f32 * self.dt,
+            largest(|checkpoint| checkpoint.rapier_divergence),
+            largest(|checkpoint| checkpoint.single_divergence),
+            largest(|checkpoint| checkpoint.single_energy_drift),
+            largest(|checkpoint| checkpoint.double_energy_drift),
+        )
+    }
+}
+
+/// Simulates the double pendulum for `steps` steps, by Rapier and by its model in both
+/// precisions.
+pub fn run_comparison(steps: usize) -> PrecisionReport {
+    let plant = double_pendulum::plant();
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    (plant.add)(&mut app);
+    app.finish();
+    app.cleanup();
+
+    let world = app.world_mut();
+    let gravity = world
+        .query::<&RapierConfiguration>()
+        .iter(world)
+        .next()
+        .map_or(9.81, |configuration| configuration.gravity.length());
+    let mut single = DoublePendulumModel::<f32>::released(gravity.into());
+    let mut double = DoublePendulumModel::<f64>::released(gravity.into());
+    let energies = (single.energy(), double.energy());
+    let substep = DEFAULT_TIME_STEP / SUBSTEPS as f32;
+    let mut ticks = 0;
+    let mut checkpoints = Vec::new();
+    for step in 0..steps {
+        app.update();
+        // As many steps of the models as of the physics.
+        let tick = app.world().resource::<SimClock>().tick();
+        for _ in ticks..tick {
+            for _ in 0..SUBSTEPS {
+                single.step(substep);
+                double.step(substep.into());
+            }
+        }
+        ticks = tick;
+
+        let simulated = step + 1;
+        if simulated % CHECKPOINT_INTERVAL != 0 && simulated != steps {
+            continue;
+        }
+        let reference = double.angles().map(wrap);
+        let divergence = |angles: [f64; 2]| {
+            (0..2)
+                .map(|link| wrap(angles[link] - reference[link]).abs())
+                .fold(0.0, f64::max)
+        };
+        checkpoints.push(PrecisionCheckpoint {
+            step: simulated,
+            reference,
+            rapier_divergence: rapier_angles(app.world_mut()).map_or(f64::INFINITY, divergence),
+            single_divergence: divergence(single.angles().map(f64::from)),
+            single_energy_drift: f64::from(single.energy() - energies.0),
+            double_energy_drift: double.energy() - energies.1,
+        });
+    }
+
+    PrecisionReport {
+        plant: plant.name.to_string(),
+        steps,
+        dt: DEFAULT_TIME_STEP,
+        substeps: SUBSTEPS,
+        checkpoints,
+    }
+}
+
+/// Angles of the links simulated by Rapier, as those of the model.
+fn rapier_angles(world: &mut World) -> Option<[f64; 2]> {
+    let mut links = world.query::<(Entity, &Link)>();
+    let mut joint = |name: &str| {
+        links
+            .iter(world)
+            .find(|(_, link)| link.name == name)
+            .map(|(entity, _)| entity)
+    };
+    let (upper, lower) = (joint("upper")?, joint("lower")?);
+    let context = world.query::<&RapierContext>().get_single(world).ok()?;
+    // Released horizontal, a quarter turn from hanging.
+    let upper = f64::from(context.impulse_revolute_joint_angle(upper)?);
+    let lower = f64::from(context.impulse_revolute_joint_angle(lower)?);
+    Some([wrap(upper + std::f64::consts::FRAC_PI_2), wrap(lower)])
+}
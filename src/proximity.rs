@@ -0,0 +1,294 @@
+//! This module measures the distance between pairs of links after each step of the simulation,
+//! to analyze how close planned or taught motions come to a collision (near misses).
+//!
+//! The pairs are configured in `proximity.json`, naming the links by their path (e.g. `arm`, or
+//! `feeder/arm` in a composed world). The smallest distance between the colliders of each pair,
+//! zero when they touch, is recorded as the `distance/<first>-<second>` telemetry channel, so
+//! it's plotted, recorded and usable by the limits and constraints reading the telemetry. A
+//! pair coming closer than its near-miss distance is logged as a warning and counted.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::{parry, prelude::*};
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+    telemetry::Telemetry,
+    theme::{to_egui, Theme},
+};
+
+/// Number of steps plotted in the proximity panel.
+const PLOTTED_STEPS: usize = 600;
+
+pub struct ProximityPlugin;
+
+impl Plugin for ProximityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Proximity>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                measure
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<ProximitySettings>>),
+            )
+            .add_systems(
+                Update,
+                proximity_panel
+                    .run_if(resource_exists::<Persistent<ProximitySettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Two links whose distance is measured.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LinkPair {
+    pub first: String,
+    pub second: String,
+    /// Distance under which the links are reported as a near miss, in m, or 0 for none.
+    #[serde(default)]
+    pub near_miss: f32,
+}
+
+impl LinkPair {
+    /// Telemetry channel of the distance.
+    pub fn channel(&self) -> String {
+        format!("distance/{}-{}", self.first, self.second)
+    }
+}
+
+/// Represents the configuration of the distance measurements.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ProximitySettings {
+    pub pairs: Vec<LinkPair>,
+}
+
+impl Default for ProximitySettings {
+    fn default() -> Self {
+        Self {
+            pairs: vec![LinkPair {
+                first: "pendulum".to_string(),
+                second: "column".to_string(),
+                near_miss: 0.0,
+            }],
+        }
+    }
+}
+
+/// Measurements of a pair of links.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PairDistance {
+    /// Distance at the last step, if both links exist.
+    pub latest: Option<f32>,
+    /// Smallest distance since the last reset.
+    pub closest: Option<f32>,
+    /// Number of times the links came closer than the near-miss distance.
+    pub near_misses: usize,
+}
+
+/// Distances of the pairs of links, by channel.
+#[derive(Debug, Default, Resource)]
+pub struct Proximity {
+    pub pairs: BTreeMap<String, PairDistance>,
+}
+
+impl Proximity {
+    /// Records the `distance` of a pair, returning whether it's a new near miss.
+    pub fn update(&mut self, pair: &LinkPair, distance: Option<f32>) -> bool {
+        let measured = self.pairs.entry(pair.channel()).or_default();
+        let near = |distance: Option<f32>| distance.is_some_and(|d| d < pair.near_miss);
+        let near_miss = near(distance) && !near(measured.latest);
+        if near_miss {
+            measured.near_misses += 1;
+        }
+        if let Some(distance) = distance {
+            measured.closest = Some(measured.closest.map_or(distance, |c| c.min(distance)));
+        }
+        measured.latest = distance;
+        near_miss
+    }
+}
+
+/// Smallest distance between two colliders, zero when they intersect, or `None` when the
+/// shapes aren't supported by the distance queries.
+pub fn distance(
+    first: &Collider,
+    first_at: &GlobalTransform,
+    second: &Collider,
+    second_at: &GlobalTransform,
+) -> Option<f32> {
+    parry::query::distance(
+        &isometry(first_at),
+        &*first.raw,
+        &isometry(second_at),
+        &*second.raw,
+    )
+    .ok()
+}
+
+fn isometry(transform: &GlobalTransform) -> Isometry3<f32> {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    Isometry3::from_parts(
+        Translation3::new(translation.x, translation.y, translation.z),
+        UnitQuaternion::new_normalize(Quaternion::new(
+            rotation.w, rotation.x, rotation.y, rotation.z,
+        )),
+    )
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ProximitySettings>("proximity", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Measures the distance of each pair after each step of the simulation.
+fn measure(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<ProximitySettings>>,
+    links: Query<(&Link, &Collider, &GlobalTransform)>,
+    mut proximity: ResMut<Proximity>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    if settings.pairs.is_empty() {
+        return;
+    }
+    let links: BTreeMap<String, (&Collider, &GlobalTransform)> = links
+        .iter()
+        .map(|(link, collider, transform)| (link.path(), (collider, transform)))
+        .collect();
+    for pair in &settings.pairs {
+        let measured = match (links.get(&pair.first), links.get(&pair.second)) {
+            (Some((first, first_at)), Some((second, second_at))) => {
+                distance(first, first_at, second, second_at)
+            }
+            _ => None,
+        };
+        if proximity.update(pair, measured) {
+            warn!(
+                target: subsystem::PHYSICS,
+                "Near miss of {} and {}: {:.3} m",
+                pair.first,
+                pair.second,
+                measured.unwrap_or_default()
+            );
+        }
+        if let (Some(distance), Some(telemetry)) = (measured, telemetry.as_mut()) {
+            telemetry.record(&pair.channel(), clock.elapsed_secs(), distance);
+        }
+    }
+}
+
+/// Panel to list the distances of the pairs, plot them and set the near-miss distances.
+fn proximity_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ProximitySettings>>,
+    mut proximity: ResMut<Proximity>,
+    telemetry: Option<Res<Telemetry>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let mut edited = settings.get().clone();
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+
+    egui::Window::new("Proximity")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if edited.pairs.is_empty() {
+                ui.label("No pair of links is measured.");
+            }
+            for (index, pair) in edited.pairs.iter_mut().enumerate() {
+                let measured = proximity
+                    .pairs
+                    .get(&pair.channel())
+                    .cloned()
+                    .unwrap_or_default();
+                let color = to_egui(theme.series(index));
+                ui.horizontal(|ui| {
+                    ui.colored_label(color, format!("{} to {}", pair.first, pair.second));
+                    ui.add(
+                        egui::DragValue::new(&mut pair.near_miss)
+                            .range(0.0..=10.0)
+                            .speed(0.01)
+                            .prefix("Near miss: ")
+                            .suffix(" m"),
+                    );
+                });
+                match (measured.latest, measured.closest) {
+                    (Some(latest), Some(closest)) => ui.label(format!(
+                        "{latest:.3} m, closest {closest:.3} m, {} near misses",
+                        measured.near_misses
+                    )),
+                    _ => ui.label("Links not found"),
+                };
+                if let Some(telemetry) = &telemetry {
+                    plot_distance(ui, telemetry, &pair.channel(), color);
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    proximity.pairs.clear();
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("proximity", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("proximity", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Plots the last distances of a pair, from zero.
+fn plot_distance(ui: &mut egui::Ui, telemetry: &Telemetry, channel: &str, color: egui::Color32) {
+    let samples = telemetry
+        .channels
+        .get(channel)
+        .map_or(&[][..], Vec::as_slice);
+    let samples = &samples[samples.len().saturating_sub(PLOTTED_STEPS)..];
+    let high = samples
+        .iter()
+        .map(|[_, distance]| *distance)
+        .fold(0.0f32, f32::max)
+        .max(f32::EPSILON);
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(300.0, 60.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(k, [_, distance])| {
+            egui::pos2(
+                rect.left() + rect.width() * k as f32 / PLOTTED_STEPS as f32,
+                rect.bottom() - rect.height() * distance / high,
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+}
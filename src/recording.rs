@@ -0,0 +1,613 @@
+//! Recordings of telemetry channels to CSV or Parquet files, for offline analysis.
+//!
+//! A recording is started and stopped with a key (F9 by default), the `record` action of the
+//! [input bindings](crate::input_bindings) or from the *Recording* panel.
+//! It has one row per simulated time at which a channel was sampled, i.e. one per physics step
+//! for the channels recorded by the controllers and the plants, with a column per channel:
+//! empty (CSV) or null (Parquet) where a channel has no sample at that time. The configured
+//! channels are recorded, or, when none are configured, every channel recorded when the first
+//! row is written. The current setpoints can be added as `setpoint/<name>` columns.
+//!
+//! Recordings are written to `<data dir>/recordings` under the time they started, e.g.
+//! `recording-2026-10-15_09-30-00.csv`. CSV rows are written as they come; Parquet rows are
+//! written in row groups of [`ROW_GROUP`] rows, and the file is complete once the recording
+//! stops, which it does when the application exits. Parquet needs the `parquet` feature.
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent},
+    input_bindings::{InputAction, InputActions},
+    logging::subsystem,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Rows of a Parquet row group.
+pub const ROW_GROUP: usize = 4096;
+/// Prefix of the columns of the setpoints.
+pub const SETPOINT_PREFIX: &str = "setpoint/";
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Telemetry>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_recording.run_if(resource_exists::<Persistent<RecordingSettings>>),
+                    recording_panel
+                        .run_if(resource_exists::<Persistent<RecordingSettings>>)
+                        .run_if(has_ui),
+                ),
+            )
+            .add_systems(
+                Last,
+                (
+                    record.run_if(resource_exists::<Recorder>),
+                    // Completes the recording when the application exits.
+                    stop.run_if(resource_exists::<Recorder>)
+                        .run_if(on_event::<AppExit>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// File format of the recordings.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum RecordingFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl RecordingFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::Csv => "csv",
+            RecordingFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Represents the configuration of the recordings.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    pub format: RecordingFormat,
+    /// Channels recorded, or every channel when empty.
+    pub channels: Vec<String>,
+    /// Whether the setpoints are recorded too.
+    pub setpoints: bool,
+    /// Key starting and stopping a recording.
+    pub key: KeyCode,
+    /// Directory of the recordings, instead of `<data dir>/recordings`.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            format: RecordingFormat::Csv,
+            channels: Vec::new(),
+            setpoints: true,
+            key: KeyCode::F9,
+            directory: None,
+        }
+    }
+}
+
+impl RecordingSettings {
+    pub fn directory(&self) -> PathBuf {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| data_dir().join("recordings"))
+    }
+}
+
+/// Name of a recording started at `unix_time`, in seconds, e.g.
+/// `recording-2026-10-15_09-30-00.csv`; the time is UTC.
+pub fn file_name(unix_time: u64, format: RecordingFormat) -> String {
+    let (days, seconds) = (unix_time / 86_400, unix_time % 86_400);
+    // The civil date of a day count, after Howard Hinnant's `civil_from_days`.
+    let shifted = days + 719_468;
+    let (era, day_of_era) = (shifted / 146_097, shifted % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "recording-{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}.{}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        format.extension()
+    )
+}
+
+/// A row of a recording: its time, in seconds, and the value of each column, if sampled.
+type Row = (f32, Vec<Option<f32>>);
+
+/// Writes the rows of a recording in a file format.
+trait RowWriter: Send {
+    fn write_rows(&mut self, rows: &[Row]) -> io::Result<()>;
+    /// Completes the file; later calls do nothing.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+struct CsvWriter(Option<BufWriter<fs::File>>);
+
+/// `field` as a CSV field, quoted when needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl CsvWriter {
+    fn create(path: &Path, columns: &[String]) -> io::Result<Self> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        write!(file, "time")?;
+        for column in columns {
+            write!(file, ",{}", csv_field(column))?;
+        }
+        writeln!(file)?;
+        Ok(Self(Some(file)))
+    }
+}
+
+impl RowWriter for CsvWriter {
+    fn write_rows(&mut self, rows: &[Row]) -> io::Result<()> {
+        let Some(file) = &mut self.0 else {
+            return Ok(());
+        };
+        for (time, values) in rows {
+            write!(file, "{time}")?;
+            for value in values {
+                match value {
+                    Some(value) => write!(file, ",{value}")?,
+                    None => write!(file, ",")?,
+                }
+            }
+            writeln!(file)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.0.take() {
+            Some(file) => file.into_inner()?.sync_data(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+struct ParquetWriter {
+    writer: Option<parquet::file::writer::SerializedFileWriter<fs::File>>,
+    /// Rows not written yet, until they fill a row group.
+    pending: Vec<Row>,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetWriter {
+    fn create(path: &Path, columns: &[String]) -> io::Result<Self> {
+        use std::sync::Arc;
+
+        use parquet::{
+            basic::{Repetition, Type as PhysicalType},
+            file::{properties::WriterProperties, writer::SerializedFileWriter},
+            schema::types::Type,
+        };
+
+        let column = |name: &str, repetition| {
+            Type::primitive_type_builder(name, PhysicalType::FLOAT)
+                .with_repetition(repetition)
+                .build()
+                .map(Arc::new)
+        };
+        let mut fields = vec![column("time", Repetition::REQUIRED).map_err(io::Error::other)?];
+        for name in columns {
+            fields.push(column(name, Repetition::OPTIONAL).map_err(io::Error::other)?);
+        }
+        let schema = Type::group_type_builder("recording")
+            .with_fields(fields)
+            .build()
+            .map_err(io::Error::other)?;
+        let writer = SerializedFileWriter::new(
+            fs::File::create(path)?,
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .map_err(io::Error::other)?;
+        Ok(Self {
+            writer: Some(writer),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Writes the pending rows as a row group.
+    fn write_group(&mut self) -> io::Result<()> {
+        use parquet::data_type::FloatType;
+
+        let (Some(writer), false) = (&mut self.writer, self.pending.is_empty()) else {
+            return Ok(());
+        };
+        let mut group = writer.next_row_group().map_err(io::Error::other)?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column().map_err(io::Error::other)? {
+            let result = if index == 0 {
+                let times: Vec<f32> = self.pending.iter().map(|(time, _)| *time).collect();
+                column.typed::<FloatType>().write_batch(&times, None, None)
+            } else {
+                let values = self.pending.iter().map(|(_, values)| values[index - 1]);
+                let levels: Vec<i16> = values.clone().map(|value| value.is_some().into()).collect();
+                let values: Vec<f32> = values.flatten().collect();
+                column
+                    .typed::<FloatType>()
+                    .write_batch(&values, Some(&levels), None)
+            };
+            result.map_err(io::Error::other)?;
+            column.close().map_err(io::Error::other)?;
+            index += 1;
+        }
+        group.close().map_err(io::Error::other)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl RowWriter for ParquetWriter {
+    fn write_rows(&mut self, rows: &[Row]) -> io::Result<()> {
+        self.pending.extend_from_slice(rows);
+        if self.pending.len() >= ROW_GROUP {
+            self.write_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_group()?;
+        match self.writer.take() {
+            Some(writer) => writer.close().map(|_| ()).map_err(io::Error::other),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A recording in progress.
+#[derive(Resource)]
+pub struct Recorder {
+    path: PathBuf,
+    format: RecordingFormat,
+    /// Configured channels; every channel when empty.
+    channels: Vec<String>,
+    setpoints: bool,
+    /// The columns, once the first row is written, then its writer.
+    columns: Option<(Vec<String>, Mutex<Box<dyn RowWriter>>)>,
+    /// Number of samples of each channel already recorded.
+    read: BTreeMap<String, usize>,
+    rows: usize,
+}
+
+impl Recorder {
+    /// Starts a recording to `path` of the samples recorded from now on.
+    pub fn start(
+        path: PathBuf,
+        settings: &RecordingSettings,
+        telemetry: &Telemetry,
+    ) -> Result<Self, Error> {
+        if settings.format == RecordingFormat::Parquet && !cfg!(feature = "parquet") {
+            return Err(Error::Config {
+                name: "recording".to_string(),
+                message: "Parquet recordings need a build with the `parquet` feature".to_string(),
+            });
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        Ok(Self {
+            path,
+            format: settings.format,
+            channels: settings.channels.clone(),
+            setpoints: settings.setpoints,
+            columns: None,
+            read: telemetry
+                .channels
+                .iter()
+                .map(|(name, samples)| (name.clone(), samples.len()))
+                .collect(),
+            rows: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of rows recorded so far.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Names of the columns after the time, once the first row is written.
+    pub fn columns(&self) -> Option<&[String]> {
+        self.columns.as_ref().map(|(columns, _)| columns.as_slice())
+    }
+
+    fn create(&self, columns: &[String]) -> io::Result<Box<dyn RowWriter>> {
+        Ok(match self.format {
+            RecordingFormat::Csv => Box::new(CsvWriter::create(&self.path, columns)?),
+            #[cfg(feature = "parquet")]
+            RecordingFormat::Parquet => Box::new(ParquetWriter::create(&self.path, columns)?),
+            #[cfg(not(feature = "parquet"))]
+            RecordingFormat::Parquet => return Err(io::ErrorKind::Unsupported.into()),
+        })
+    }
+
+    /// Writes the rows of the samples recorded since the previous call, with the current
+    /// values of the setpoints.
+    pub fn record(&mut self, telemetry: &Telemetry, setpoints: &Setpoints) -> io::Result<()> {
+        let mut samples = Vec::new();
+        for (name, channel) in &telemetry.channels {
+            let read = self.read.entry(name.clone()).or_default();
+            // The telemetry restarts when the session is rewound.
+            if *read > channel.len() {
+                *read = 0;
+            }
+            samples.extend(channel[*read..].iter().map(|sample| (name, *sample)));
+            *read = channel.len();
+        }
+        if samples.is_empty() {
+            return Ok(());
+        }
+        if self.columns.is_none() {
+            let mut columns = if self.channels.is_empty() {
+                telemetry.channels.keys().cloned().collect()
+            } else {
+                self.channels.clone()
+            };
+            if self.setpoints {
+                columns.extend(
+                    setpoints
+                        .values
+                        .keys()
+                        .map(|name| format!("{SETPOINT_PREFIX}{name}")),
+                );
+            }
+            let writer = self.create(&columns)?;
+            self.columns = Some((columns, Mutex::new(writer)));
+        }
+        let Some((columns, writer)) = &mut self.columns else {
+            return Ok(());
+        };
+        let indexes: BTreeMap<&str, usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+        samples.sort_by(|(_, [a, _]), (_, [b, _])| a.total_cmp(b));
+        let mut rows: Vec<Row> = Vec::new();
+        for (name, [time, value]) in samples {
+            let Some(&index) = indexes.get(name.as_str()) else {
+                continue;
+            };
+            match rows.last_mut() {
+                Some((last, values)) if *last == time => values[index] = Some(value),
+                _ => {
+                    let mut values = vec![None; columns.len()];
+                    values[index] = Some(value);
+                    rows.push((time, values));
+                }
+            }
+        }
+        for (_, values) in &mut rows {
+            for (name, value) in &setpoints.values {
+                if let Some(&index) = indexes.get(format!("{SETPOINT_PREFIX}{name}").as_str()) {
+                    values[index] = Some(*value);
+                }
+            }
+        }
+        self.rows += rows.len();
+        writer
+            .get_mut()
+            .unwrap_or_else(|error| error.into_inner())
+            .write_rows(&rows)
+    }
+
+    /// Completes the file of the recording, returning its path.
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        if let Some((_, writer)) = &mut self.columns {
+            writer
+                .get_mut()
+                .unwrap_or_else(|error| error.into_inner())
+                .finish()?;
+        }
+        Ok(std::mem::take(&mut self.path))
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Some((_, writer)) = &mut self.columns {
+            let _ = writer
+                .get_mut()
+                .unwrap_or_else(|error| error.into_inner())
+                .finish();
+        }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<RecordingSettings>("recording", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn start(commands: &mut Commands, settings: &RecordingSettings, telemetry: &Telemetry) {
+    let unix_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let path = settings
+        .directory()
+        .join(file_name(unix_time, settings.format));
+    match Recorder::start(path, settings, telemetry) {
+        Ok(recorder) => {
+            info!(target: subsystem::IO, "Recording to {}", recorder.path().display());
+            commands.insert_resource(recorder);
+        }
+        Err(error) => {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Stops the recording in progress, if any.
+fn stop(world: &mut World) {
+    let Some(recorder) = world.remove_resource::<Recorder>() else {
+        return;
+    };
+    let path = recorder.path().to_path_buf();
+    let rows = recorder.rows();
+    match recorder.finish() {
+        Ok(path) => info!(target: subsystem::IO, "Recorded {rows} rows to {}", path.display()),
+        Err(error) => {
+            world.send_event(ErrorEvent::from(Error::io(path, error)));
+        }
+    }
+}
+
+fn toggle_recording(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    actions: Option<Res<InputActions>>,
+    settings: Res<Persistent<RecordingSettings>>,
+    telemetry: Res<Telemetry>,
+    recorder: Option<Res<Recorder>>,
+) {
+    let action = actions.is_some_and(|actions| actions.just_pressed(InputAction::Record));
+    if !keys.just_pressed(settings.key) && !action {
+        return;
+    }
+    if recorder.is_some() {
+        commands.queue(stop);
+    } else {
+        start(&mut commands, &settings, &telemetry);
+    }
+}
+
+fn record(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    telemetry: Res<Telemetry>,
+    setpoints: Res<Setpoints>,
+) {
+    if let Err(error) = recorder.record(&telemetry, &setpoints) {
+        warn!(target: subsystem::IO, "The recording stopped");
+        commands.send_event(ErrorEvent::from(Error::io(recorder.path(), error)));
+        commands.remove_resource::<Recorder>();
+    }
+}
+
+/// Panel to start and stop the recordings, and to choose what they record.
+fn recording_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<RecordingSettings>>,
+    telemetry: Res<Telemetry>,
+    recorder: Option<Res<Recorder>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Recording")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match &recorder {
+                Some(recorder) => {
+                    ui.label(format!(
+                        "Recording {} rows to {}",
+                        recorder.rows(),
+                        recorder.path().display()
+                    ));
+                    if ui.button(format!("Stop ({:?})", settings.key)).clicked() {
+                        commands.queue(stop);
+                    }
+                }
+                None => {
+                    ui.label(format!("Recordings go to {}", edited.directory().display()));
+                    if ui.button(format!("Record ({:?})", settings.key)).clicked() {
+                        start(&mut commands, &edited, &telemetry);
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.add_enabled_ui(recorder.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    ui.radio_value(&mut edited.format, RecordingFormat::Csv, "CSV");
+                    ui.radio_value(&mut edited.format, RecordingFormat::Parquet, "Parquet");
+                });
+                ui.checkbox(&mut edited.setpoints, "Record the setpoints");
+                ui.label("Channels (none for every channel):");
+                for name in telemetry.channels.keys() {
+                    let mut selected = edited.channels.contains(name);
+                    if ui.checkbox(&mut selected, name).changed() {
+                        if selected {
+                            edited.channels.push(name.clone());
+                        } else {
+                            edited.channels.retain(|channel| channel != name);
+                        }
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("recording", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("recording", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
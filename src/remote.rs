@@ -0,0 +1,908 @@
+//! Collaborative sessions over WebSocket.
+//!
+//! An instance started with `--host` accepts other instances started with `--connect`. Every
+//! client sees the live state of the host's bodies; operators can also send setpoints, e.g. the
+//! instructor driving a demonstration while the students watch. The host decides who may
+//! operate from its *Session* panel, and what each operator may change: which setpoints, the
+//! gains of the position loop, the disturbances. The [`Permissions`] of the operators, and those
+//! of the seats given to named clients, are read from `session.json`; names are taken at their
+//! word, so the permissions keep a classroom demo orderly rather than secure it.
+//!
+//! Messages are JSON text frames, so other clients (e.g. a browser) can join:
+//! - client to host: `{"type":"hello","name":"...","role":"observer"|"operator"}`,
+//!   `{"type":"setpoint","name":"motor/velocity","value":5.0}`, `{"type":"pid_gains","kp":8.0}`,
+//!   `{"type":"disturb","injection":{...}}` and `{"type":"heartbeat"}`; a setpoint can carry the
+//!   simulated `"time"` it applies from, in seconds;
+//! - host to client: `{"type":"welcome","role":...,"permissions":{...}}` with the granted role
+//!   and permissions, then `{"type":"state","tick":...,"elapsed":...,"bodies":[...],
+//!   "setpoints":{...}}` at 30 Hz, and `{"type":"refused","message":"..."}` when a message
+//!   isn't permitted.
+//!
+//! Operators feed the [`Watchdog`] with their messages, so they send a heartbeat every
+//! [`HEARTBEAT_INTERVAL`] when they have no setpoint to send.
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, time::Real};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::{SimClock, SimClockSet},
+    command_buffer::{CommandBuffer, CommandBufferPlugin, TimedCommand},
+    config_plugin,
+    control::PidGains,
+    disturbances::{DisturbanceSettings, Inject, Injection},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    pid_controller::PidSettings,
+    setpoints::Setpoints,
+    watchdog::Watchdog,
+};
+
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5710;
+/// Time between two states sent to the clients.
+const STATE_INTERVAL: Duration = Duration::from_millis(33);
+/// How long the network threads wait for a message before checking their outgoing queue.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Time between two heartbeats of an operator.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientRole {
+    /// Sees the live state.
+    #[default]
+    Observer,
+    /// Sees the live state and sends setpoints.
+    Operator,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello {
+        name: String,
+        role: ClientRole,
+    },
+    Setpoint {
+        name: String,
+        value: f32,
+        /// Simulated time to apply the setpoint at, in seconds, instead of on receipt.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time: Option<f32>,
+    },
+    /// Gains of the position loop, those left out unchanged.
+    PidGains {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kp: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ki: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kd: Option<f32>,
+    },
+    /// Injects the configured disturbance of the host, or `injection`, into its link.
+    Disturb {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        injection: Option<Injection>,
+    },
+    /// Keeps the watchdog of the host fed.
+    Heartbeat,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+    Welcome {
+        role: ClientRole,
+        /// What the client may change as an operator.
+        #[serde(default)]
+        permissions: Permissions,
+    },
+    State {
+        tick: u64,
+        elapsed: Duration,
+        bodies: Vec<BodyState>,
+        setpoints: BTreeMap<String, f32>,
+    },
+    /// A message of the client wasn't permitted.
+    Refused { message: String },
+}
+
+/// What an operator may change.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Names of the setpoints it may set; a name ending with `*` stands for every setpoint
+    /// starting with what precedes it.
+    pub setpoints: Vec<String>,
+    /// Whether it may change the gains of the position loop.
+    pub gains: bool,
+    /// Whether it may inject disturbances.
+    pub disturbances: bool,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            setpoints: vec!["*".to_string()],
+            gains: true,
+            disturbances: true,
+        }
+    }
+}
+
+impl Permissions {
+    /// Permits nothing, e.g. for the operators of a classroom demo without a seat.
+    pub fn none() -> Self {
+        Self {
+            setpoints: Vec::new(),
+            gains: false,
+            disturbances: false,
+        }
+    }
+
+    /// Whether the setpoint `name` may be set.
+    pub fn may_set(&self, name: &str) -> bool {
+        self.setpoints
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+}
+
+/// Represents the configuration of the hosted sessions, `session.json`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    /// Whether clients asking to operate are granted to.
+    pub allow_operators: bool,
+    /// Permissions of the operators without a seat.
+    pub operators: Permissions,
+    /// Permissions of the operators by the name they join with.
+    pub seats: BTreeMap<String, Permissions>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            allow_operators: true,
+            operators: Permissions::default(),
+            seats: BTreeMap::new(),
+        }
+    }
+}
+
+impl SessionSettings {
+    /// Permissions of the operator joining as `name`.
+    pub fn permissions(&self, name: &str) -> &Permissions {
+        self.seats.get(name).unwrap_or(&self.operators)
+    }
+}
+
+/// Result of running a connection. The error is boxed as it's only reported.
+pub(crate) type SocketResult = Result<(), Box<tungstenite::Error>>;
+
+/// Runs `socket` until the peer disconnects: messages received are decoded and given to
+/// `on_message`, messages queued in `outgoing` are sent.
+pub(crate) fn serve<S: io::Read + io::Write, In: for<'a> Deserialize<'a>>(
+    mut socket: WebSocket<S>,
+    outgoing: mpsc::Receiver<String>,
+    mut on_message: impl FnMut(In),
+) -> SocketResult {
+    loop {
+        for text in outgoing.try_iter() {
+            socket.send(Message::text(text))?;
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(message) => on_message(message),
+                Err(error) => warn!(target: subsystem::IO, "Invalid session message: {error}"),
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Accepts clients on the given address.
+pub struct RemoteHostPlugin {
+    pub bind: SocketAddr,
+}
+
+impl Plugin for RemoteHostPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "Hosting the session on {}", self.bind);
+        let (events, receiver) = mpsc::channel();
+        thread::spawn(move || accept_clients(listener, events));
+
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        if !app.is_plugin_added::<CommandBufferPlugin>() {
+            app.add_plugins(CommandBufferPlugin);
+        }
+        let (settings, error) = config_plugin::load_config::<SessionSettings>("session", true);
+        if let Some(error) = error {
+            app.world_mut().send_event(ErrorEvent::from(error));
+        }
+        app.init_resource::<Setpoints>()
+            .insert_resource(RemoteHost {
+                events: Mutex::new(receiver),
+                clients: BTreeMap::new(),
+                allow_operators: settings.allow_operators,
+                timer: Timer::new(STATE_INTERVAL, TimerMode::Repeating),
+            })
+            .insert_resource(settings)
+            .add_systems(Update, (host_receive, host_panel.run_if(has_ui)).chain())
+            .add_systems(PostUpdate, host_broadcast.after(SimClockSet::Advance));
+    }
+}
+
+enum HostEvent {
+    Connected {
+        id: u64,
+        address: SocketAddr,
+        outgoing: mpsc::Sender<String>,
+    },
+    Message(u64, ClientMessage),
+    Disconnected(u64),
+}
+
+fn accept_clients(listener: TcpListener, events: mpsc::Sender<HostEvent>) {
+    for (id, stream) in (0..).zip(listener.incoming()) {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let events = events.clone();
+        thread::spawn(move || {
+            let address = stream.peer_addr().ok();
+            let result = handle_client(id, stream, &events);
+            if let Err(error) = result {
+                debug!(target: subsystem::IO, "Client {address:?}: {error}");
+            }
+            let _ = events.send(HostEvent::Disconnected(id));
+        });
+    }
+}
+
+fn handle_client(id: u64, stream: TcpStream, events: &mpsc::Sender<HostEvent>) -> SocketResult {
+    let address = stream.peer_addr().map_err(tungstenite::Error::Io)?;
+    let socket = tungstenite::accept(stream).map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => error,
+        tungstenite::HandshakeError::Interrupted(_) => {
+            tungstenite::Error::Io(io::ErrorKind::WouldBlock.into())
+        }
+    })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(tungstenite::Error::Io)?;
+    let (outgoing, receiver) = mpsc::channel();
+    let _ = events.send(HostEvent::Connected {
+        id,
+        address,
+        outgoing,
+    });
+    serve(socket, receiver, |message| {
+        let _ = events.send(HostEvent::Message(id, message));
+    })
+}
+
+/// A client of the session.
+pub struct RemoteClient {
+    pub name: String,
+    pub address: SocketAddr,
+    /// Role requested by the client.
+    pub requested_role: ClientRole,
+    /// Role granted by the host.
+    pub role: ClientRole,
+    /// What it may change as an operator.
+    pub permissions: Permissions,
+    outgoing: mpsc::Sender<String>,
+}
+
+impl RemoteClient {
+    fn send(&self, message: &HostMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            let _ = self.outgoing.send(text);
+        }
+    }
+
+    fn welcome(&self) {
+        self.send(&HostMessage::Welcome {
+            role: self.role,
+            permissions: self.permissions.clone(),
+        });
+    }
+
+    /// Whether it may operate, refusing `what` otherwise.
+    fn permits(&self, permitted: bool, what: &str) -> bool {
+        let permitted = permitted && self.role == ClientRole::Operator;
+        if !permitted {
+            debug!(target: subsystem::CONTROL, "{} may not change {what}", self.name);
+            self.send(&HostMessage::Refused {
+                message: format!("{} may not change {what}", self.name),
+            });
+        }
+        permitted
+    }
+}
+
+/// The session hosted by this instance.
+#[derive(Resource)]
+pub struct RemoteHost {
+    events: Mutex<mpsc::Receiver<HostEvent>>,
+    pub clients: BTreeMap<u64, RemoteClient>,
+    /// Whether clients asking to operate are granted to.
+    pub allow_operators: bool,
+    timer: Timer,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn host_receive(
+    mut host: ResMut<RemoteHost>,
+    settings: Res<Persistent<SessionSettings>>,
+    clock: Res<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    mut buffer: ResMut<CommandBuffer>,
+    mut pid: Option<ResMut<Persistent<PidSettings>>>,
+    mut injections: Option<ResMut<Events<Inject>>>,
+    mut watchdog: Option<ResMut<Watchdog>>,
+) {
+    let host = &mut *host;
+    let events = host
+        .events
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner());
+    for event in events.try_iter() {
+        match event {
+            HostEvent::Connected {
+                id,
+                address,
+                outgoing,
+            } => {
+                info!(target: subsystem::IO, "{address} joined the session");
+                host.clients.insert(
+                    id,
+                    RemoteClient {
+                        name: address.to_string(),
+                        address,
+                        requested_role: ClientRole::Observer,
+                        role: ClientRole::Observer,
+                        permissions: settings.operators.clone(),
+                        outgoing,
+                    },
+                );
+            }
+            HostEvent::Message(id, ClientMessage::Hello { name, role }) => {
+                let granted = if host.allow_operators {
+                    role
+                } else {
+                    ClientRole::Observer
+                };
+                if let Some(client) = host.clients.get_mut(&id) {
+                    info!(target: subsystem::IO, "{name} ({}) is {granted:?}", client.address);
+                    client.permissions = settings.permissions(&name).clone();
+                    client.name = name;
+                    client.requested_role = role;
+                    client.role = granted;
+                    client.welcome();
+                }
+            }
+            HostEvent::Message(id, ClientMessage::Setpoint { name, value, time }) => {
+                match host.clients.get(&id) {
+                    Some(client) if client.permits(client.permissions.may_set(&name), &name) => {
+                        if feed_watchdog(watchdog.as_deref_mut(), client) {
+                            debug!(
+                                target: subsystem::CONTROL,
+                                "{} sets {name} to {value}", client.name
+                            );
+                            match time {
+                                Some(time) => buffer.schedule(
+                                    TimedCommand {
+                                        time,
+                                        setpoint: name,
+                                        value,
+                                    },
+                                    clock.elapsed_secs(),
+                                ),
+                                None => setpoints.set(&name, value),
+                            }
+                        } else {
+                            debug!(
+                                target: subsystem::CONTROL,
+                                "Ignoring a setpoint from {}, the watchdog tripped", client.name
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            HostEvent::Message(id, ClientMessage::PidGains { kp, ki, kd }) => {
+                let Some(client) = host.clients.get(&id) else {
+                    continue;
+                };
+                match pid.as_mut() {
+                    Some(pid) if client.permits(client.permissions.gains, "the gains") => {
+                        debug!(target: subsystem::CONTROL, "{} changes the gains", client.name);
+                        let gains = &mut pid.get_mut().gains;
+                        gains.kp = kp.unwrap_or(gains.kp);
+                        gains.ki = ki.unwrap_or(gains.ki);
+                        gains.kd = kd.unwrap_or(gains.kd);
+                    }
+                    _ => {}
+                }
+            }
+            HostEvent::Message(id, ClientMessage::Disturb { injection }) => {
+                let Some(client) = host.clients.get(&id) else {
+                    continue;
+                };
+                match injections.as_mut() {
+                    Some(injections)
+                        if client.permits(client.permissions.disturbances, "the disturbances") =>
+                    {
+                        debug!(target: subsystem::CONTROL, "{} injects a disturbance", client.name);
+                        injections.send(Inject {
+                            injection,
+                            ..default()
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            HostEvent::Message(id, ClientMessage::Heartbeat) => {
+                if let Some(client) = host.clients.get(&id) {
+                    if client.role == ClientRole::Operator {
+                        feed_watchdog(watchdog.as_deref_mut(), client);
+                    }
+                }
+            }
+            HostEvent::Disconnected(id) => {
+                if let Some(client) = host.clients.remove(&id) {
+                    info!(target: subsystem::IO, "{} left the session", client.name);
+                }
+            }
+        }
+    }
+}
+
+/// Feeds the watchdog with a message of an operator, returning whether its commands are
+/// accepted.
+fn feed_watchdog(watchdog: Option<&mut Watchdog>, client: &RemoteClient) -> bool {
+    watchdog
+        .is_none_or(|watchdog| watchdog.feed(&format!("session/{}", client.name), Instant::now()))
+}
+
+fn host_broadcast(
+    mut host: ResMut<RemoteHost>,
+    time: Res<Time<Real>>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    bodies: Bodies,
+) {
+    if !host.timer.tick(time.delta()).just_finished() || host.clients.is_empty() {
+        return;
+    }
+    let state = HostMessage::State {
+        tick: clock.tick(),
+        elapsed: clock.elapsed(),
+        bodies: body_state::capture(&bodies),
+        setpoints: setpoints.values.clone(),
+    };
+    for client in host.clients.values() {
+        client.send(&state);
+    }
+}
+
+/// Panel listing the participants, to change their roles and permissions.
+fn host_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut host: ResMut<RemoteHost>,
+    mut settings: ResMut<Persistent<SessionSettings>>,
+) {
+    egui::Window::new("Session")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let host = &mut *host;
+            if ui
+                .checkbox(&mut host.allow_operators, "Allow operators")
+                .changed()
+            {
+                for client in host.clients.values_mut() {
+                    client.role = if host.allow_operators {
+                        client.requested_role
+                    } else {
+                        ClientRole::Observer
+                    };
+                    client.welcome();
+                }
+            }
+            ui.separator();
+            if host.clients.is_empty() {
+                ui.weak("Nobody joined yet.");
+            }
+            let allow_operators = host.allow_operators;
+            egui::Grid::new("session_clients").show(ui, |ui| {
+                for client in host.clients.values_mut() {
+                    ui.label(&client.name);
+                    ui.weak(client.address.to_string());
+                    let mut operator = client.role == ClientRole::Operator;
+                    if ui
+                        .add_enabled(
+                            allow_operators,
+                            egui::Checkbox::new(&mut operator, "Operator"),
+                        )
+                        .changed()
+                    {
+                        client.role = if operator {
+                            ClientRole::Operator
+                        } else {
+                            ClientRole::Observer
+                        };
+                        client.welcome();
+                    }
+                    let permissions = &mut client.permissions;
+                    let mut setpoints = permissions.setpoints.join(", ");
+                    let mut changed = ui
+                        .add(
+                            egui::TextEdit::singleline(&mut setpoints)
+                                .hint_text("No setpoint")
+                                .desired_width(120.0),
+                        )
+                        .on_hover_text("Setpoints it may set, `*` ending a prefix")
+                        .changed();
+                    if changed {
+                        permissions.setpoints = setpoints
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|name| !name.is_empty())
+                            .map(str::to_string)
+                            .collect();
+                    }
+                    changed |= ui.checkbox(&mut permissions.gains, "Gains").changed();
+                    changed |= ui
+                        .checkbox(&mut permissions.disturbances, "Disturbances")
+                        .changed();
+                    if changed {
+                        client.welcome();
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            if ui
+                .button("Save the seats")
+                .on_hover_text("Keeps the permissions of each operator for the next sessions")
+                .clicked()
+            {
+                let edited = settings.get_mut();
+                edited.allow_operators = host.allow_operators;
+                for client in host.clients.values() {
+                    if client.role == ClientRole::Operator {
+                        edited
+                            .seats
+                            .insert(client.name.clone(), client.permissions.clone());
+                    }
+                }
+                if let Err(error) = settings.persist() {
+                    commands.send_event(ErrorEvent::from(Error::save("session", error)));
+                }
+            }
+        });
+}
+
+/// Joins the session hosted at the given address.
+pub struct RemoteClientPlugin {
+    pub host: SocketAddr,
+    pub name: String,
+    pub role: ClientRole,
+}
+
+impl Plugin for RemoteClientPlugin {
+    fn build(&self, app: &mut App) {
+        let (events, receiver) = mpsc::channel();
+        let (outgoing, outgoing_receiver) = mpsc::channel();
+        let hello = ClientMessage::Hello {
+            name: self.name.clone(),
+            role: self.role,
+        };
+        if let Ok(text) = serde_json::to_string(&hello) {
+            let _ = outgoing.send(text);
+        }
+        let host = self.host;
+        thread::spawn(move || {
+            let result = connect(host, outgoing_receiver, &events);
+            let _ = events.send(ClientEvent::Disconnected(
+                result.err().map(|error| error.to_string()),
+            ));
+        });
+
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.init_resource::<Setpoints>()
+            .insert_resource(RemoteSession {
+                host,
+                events: Mutex::new(receiver),
+                outgoing,
+                status: SessionStatus::Connecting,
+                permissions: Permissions::none(),
+                refused: None,
+                host_setpoints: BTreeMap::new(),
+                heartbeat: Timer::new(HEARTBEAT_INTERVAL, TimerMode::Repeating),
+            })
+            .add_systems(PostUpdate, hold_clock.in_set(SimClockSet::Gate))
+            .add_systems(
+                Update,
+                (
+                    client_receive,
+                    client_send.run_if(resource_changed::<Setpoints>),
+                    client_send_gains.run_if(resource_exists::<Persistent<PidSettings>>),
+                    client_send_disturbances.run_if(resource_exists::<Events<Inject>>),
+                    client_heartbeat,
+                    client_panel.run_if(has_ui),
+                )
+                    .chain(),
+            );
+    }
+}
+
+enum ClientEvent {
+    Connected,
+    Message(HostMessage),
+    Disconnected(Option<String>),
+}
+
+fn connect(
+    host: SocketAddr,
+    outgoing: mpsc::Receiver<String>,
+    events: &mpsc::Sender<ClientEvent>,
+) -> SocketResult {
+    let stream = TcpStream::connect(host).map_err(tungstenite::Error::Io)?;
+    let (socket, _) =
+        tungstenite::client(format!("ws://{host}/"), stream).map_err(|error| match error {
+            tungstenite::HandshakeError::Failure(error) => error,
+            tungstenite::HandshakeError::Interrupted(_) => {
+                tungstenite::Error::Io(io::ErrorKind::WouldBlock.into())
+            }
+        })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(tungstenite::Error::Io)?;
+    let _ = events.send(ClientEvent::Connected);
+    serve(socket, outgoing, |message| {
+        let _ = events.send(ClientEvent::Message(message));
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionStatus {
+    Connecting,
+    Connected(Option<ClientRole>),
+    Disconnected(Option<String>),
+}
+
+/// The session this instance joined.
+#[derive(Resource)]
+pub struct RemoteSession {
+    pub host: SocketAddr,
+    events: Mutex<mpsc::Receiver<ClientEvent>>,
+    outgoing: mpsc::Sender<String>,
+    pub status: SessionStatus,
+    /// What the host permits this instance to change as an operator.
+    pub permissions: Permissions,
+    /// Last message of this instance the host refused, and why.
+    pub refused: Option<String>,
+    /// Setpoints of the host, to only send the ones changed locally.
+    host_setpoints: BTreeMap<String, f32>,
+    heartbeat: Timer,
+}
+
+impl RemoteSession {
+    fn send(&self, message: &ClientMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            let _ = self.outgoing.send(text);
+        }
+    }
+
+    fn is_operator(&self) -> bool {
+        self.status == SessionStatus::Connected(Some(ClientRole::Operator))
+    }
+}
+
+/// The host simulates, this instance only mirrors it.
+fn hold_clock(mut clock: ResMut<SimClock>) {
+    clock.hold();
+}
+
+fn client_receive(
+    mut session: ResMut<RemoteSession>,
+    mut clock: ResMut<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    mut bodies: BodiesMut,
+) {
+    let session = &mut *session;
+    let events = session
+        .events
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner());
+    // Only the most recent state matters.
+    let mut latest = None;
+    for event in events.try_iter() {
+        match event {
+            ClientEvent::Connected => {
+                info!(target: subsystem::IO, "Joined the session of {}", session.host);
+                session.status = SessionStatus::Connected(None);
+            }
+            ClientEvent::Message(HostMessage::Welcome { role, permissions }) => {
+                info!(target: subsystem::IO, "Joined as {role:?}, permitted {permissions:?}");
+                session.status = SessionStatus::Connected(Some(role));
+                session.permissions = permissions;
+            }
+            ClientEvent::Message(state @ HostMessage::State { .. }) => latest = Some(state),
+            ClientEvent::Message(HostMessage::Refused { message }) => {
+                warn!(target: subsystem::IO, "The host refused: {message}");
+                session.refused = Some(message);
+            }
+            ClientEvent::Disconnected(reason) => {
+                warn!(target: subsystem::IO, "Left the session: {reason:?}");
+                session.status = SessionStatus::Disconnected(reason);
+            }
+        }
+    }
+    let Some(HostMessage::State {
+        tick,
+        elapsed,
+        bodies: states,
+        setpoints: host_setpoints,
+    }) = latest
+    else {
+        return;
+    };
+    clock.restore(tick, elapsed);
+    if let Err(count) = body_state::apply(&states, &mut bodies) {
+        warn!(
+            target: subsystem::PHYSICS,
+            "The host has {} bodies but this scene has {count}",
+            states.len()
+        );
+    }
+    // Setpoints changed locally and not applied by the host yet are kept.
+    for (name, value) in &host_setpoints {
+        if session.host_setpoints.get(name) != Some(value) && setpoints.get(name) != Some(*value) {
+            setpoints.set(name, *value);
+        }
+    }
+    session.host_setpoints = host_setpoints;
+}
+
+/// Sends the setpoints changed locally, e.g. from the keyboard, that the host permits.
+fn client_send(session: Res<RemoteSession>, setpoints: Res<Setpoints>) {
+    if !session.is_operator() {
+        return;
+    }
+    for (name, value) in &setpoints.values {
+        if session.host_setpoints.get(name) != Some(value) && session.permissions.may_set(name) {
+            session.send(&ClientMessage::Setpoint {
+                name: name.clone(),
+                value: *value,
+                time: None,
+            });
+        }
+    }
+}
+
+/// Sends the gains of the position loop whenever they're changed locally, e.g. from its panel,
+/// if the host permits.
+fn client_send_gains(
+    session: Res<RemoteSession>,
+    pid: Res<Persistent<PidSettings>>,
+    mut last: Local<Option<PidGains>>,
+) {
+    let gains = pid.gains;
+    let changed = last.replace(gains).is_some_and(|last| last != gains);
+    if changed && session.is_operator() && session.permissions.gains {
+        session.send(&ClientMessage::PidGains {
+            kp: Some(gains.kp),
+            ki: Some(gains.ki),
+            kd: Some(gains.kd),
+        });
+    }
+}
+
+/// Sends the disturbances injected locally to the host, if it permits; those of the bodies of
+/// this instance go to the configured link of the host.
+fn client_send_disturbances(
+    session: Res<RemoteSession>,
+    mut requests: EventReader<Inject>,
+    settings: Option<Res<Persistent<DisturbanceSettings>>>,
+) {
+    for request in requests.read() {
+        if !session.is_operator() || !session.permissions.disturbances {
+            continue;
+        }
+        let injection = request
+            .injection
+            .clone()
+            .or_else(|| settings.as_ref().map(|settings| settings.injection.clone()));
+        session.send(&ClientMessage::Disturb { injection });
+    }
+}
+
+/// Sends a heartbeat to the host every [`HEARTBEAT_INTERVAL`] while operating.
+fn client_heartbeat(mut session: ResMut<RemoteSession>, time: Res<Time<Real>>) {
+    if !session.heartbeat.tick(time.delta()).just_finished() {
+        return;
+    }
+    if session.is_operator() {
+        session.send(&ClientMessage::Heartbeat);
+    }
+}
+
+fn client_panel(mut contexts: EguiContexts, session: Res<RemoteSession>) {
+    egui::Window::new("Session")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("Host: {}", session.host));
+            ui.label(match &session.status {
+                SessionStatus::Connecting => "Connecting...".to_string(),
+                SessionStatus::Connected(None) => "Connected".to_string(),
+                SessionStatus::Connected(Some(role)) => format!("Connected as {role:?}"),
+                SessionStatus::Disconnected(Some(reason)) => format!("Disconnected: {reason}"),
+                SessionStatus::Disconnected(None) => "Disconnected".to_string(),
+            });
+            if session.is_operator() {
+                let permissions = &session.permissions;
+                ui.label(if permissions.setpoints.is_empty() {
+                    "May set no setpoint".to_string()
+                } else {
+                    format!("May set {}", permissions.setpoints.join(", "))
+                });
+                ui.label(format!(
+                    "May change the gains: {}, the disturbances: {}",
+                    if permissions.gains { "yes" } else { "no" },
+                    if permissions.disturbances {
+                        "yes"
+                    } else {
+                        "no"
+                    }
+                ));
+            }
+            if let Some(refused) = &session.refused {
+                ui.colored_label(egui::Color32::YELLOW, format!("Refused: {refused}"));
+            }
+        });
+}
@@ -0,0 +1,458 @@
+//! Replays of the last moments of the simulation, step by step, e.g. to see what went wrong
+//! during a failed swing-up.
+//!
+//! The state of the bodies is kept after every physics step, up to a configured number of
+//! steps. Playback (F8 by default, or the *Replay* panel) holds the physics and shows the kept
+//! steps instead: the timeline is scrubbed with a slider, played at a variable speed, forwards
+//! or paused, and stepped frame by frame. Leaving playback puts the scene back as it was live.
+//! Trajectories can be saved to `<data dir>/replays` and loaded back for playback, in any
+//! application running the same plant.
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::Real};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::{SimClock, SimClockSet},
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    snapshots::{self, Snapshot},
+};
+
+/// Playback speeds offered by the panel.
+const SPEEDS: [f32; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0];
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.init_resource::<Trajectory>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_playback.run_if(resource_exists::<Persistent<ReplaySettings>>),
+                    play.run_if(resource_exists::<Playback>),
+                    replay_panel
+                        .run_if(resource_exists::<Persistent<ReplaySettings>>)
+                        .run_if(has_ui),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    hold_clock
+                        .in_set(SimClockSet::Gate)
+                        .run_if(resource_exists::<Playback>),
+                    record
+                        .after(SimClockSet::Advance)
+                        .after(PhysicsSet::Writeback)
+                        .run_if(not(resource_exists::<Playback>))
+                        .run_if(resource_exists::<Persistent<ReplaySettings>>),
+                ),
+            );
+    }
+}
+
+/// Represents the configuration of the replays.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ReplaySettings {
+    /// Physics steps kept, the oldest dropped first; 0 keeps none.
+    pub capacity: usize,
+    /// Key entering and leaving playback.
+    pub key: KeyCode,
+    /// Directory of the saved trajectories, instead of `<data dir>/replays`.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            // A minute at the default time step.
+            capacity: 3600,
+            key: KeyCode::F8,
+            directory: None,
+        }
+    }
+}
+
+impl ReplaySettings {
+    pub fn directory(&self) -> PathBuf {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| data_dir().join("replays"))
+    }
+}
+
+/// State of the bodies after a physics step.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Frame {
+    pub tick: u64,
+    /// Simulated time of the step.
+    pub elapsed: Duration,
+    /// The dynamic bodies, in spawn order.
+    pub bodies: Vec<BodyState>,
+}
+
+/// The last physics steps, oldest first.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+pub struct Trajectory {
+    pub frames: VecDeque<Frame>,
+}
+
+impl Trajectory {
+    /// Appends a frame, dropping the oldest ones beyond `capacity`. A frame from before the last
+    /// one, e.g. after a snapshot is restored, starts the trajectory over.
+    pub fn push(&mut self, frame: Frame, capacity: usize) {
+        if self
+            .frames
+            .back()
+            .is_some_and(|last| last.tick >= frame.tick)
+        {
+            self.frames.clear();
+        }
+        self.frames.push_back(frame);
+        while self.frames.len() > capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Simulated times of the first and the last frames.
+    pub fn span(&self) -> Option<(f32, f32)> {
+        let first = self.frames.front()?.elapsed.as_secs_f32();
+        let last = self.frames.back()?.elapsed.as_secs_f32();
+        Some((first, last))
+    }
+
+    /// Index of the frame shown at simulated `time`: the last one at or before it, or the first.
+    pub fn index_at(&self, time: f32) -> Option<usize> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let after = self
+            .frames
+            .partition_point(|frame| frame.elapsed.as_secs_f32() <= time);
+        Some(after.saturating_sub(1))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+/// Playback of the trajectory, while it lasts.
+#[derive(Clone, Debug, Resource)]
+pub struct Playback {
+    /// Simulated time shown.
+    pub time: f32,
+    pub playing: bool,
+    /// Simulated time per real time.
+    pub speed: f32,
+    /// The scene as it was live, restored when the playback ends.
+    pub live: Option<Snapshot>,
+}
+
+impl Playback {
+    /// A paused playback at the end of `trajectory`.
+    pub fn new(trajectory: &Trajectory) -> Self {
+        Self {
+            time: trajectory.span().map_or(0.0, |(_, last)| last),
+            playing: false,
+            speed: 1.0,
+            live: None,
+        }
+    }
+
+    /// Plays `real_delta` of real time, stopping at either end of `trajectory`.
+    pub fn advance(&mut self, real_delta: f32, trajectory: &Trajectory) {
+        let Some((first, last)) = trajectory.span() else {
+            return;
+        };
+        if self.playing {
+            self.time += self.speed * real_delta;
+        }
+        if self.time >= last || self.time < first {
+            self.playing = false;
+        }
+        self.time = self.time.clamp(first, last);
+    }
+
+    /// Moves `frames` frames forwards, or backwards if negative, and pauses.
+    pub fn step(&mut self, frames: isize, trajectory: &Trajectory) {
+        self.playing = false;
+        let Some(index) = trajectory.index_at(self.time) else {
+            return;
+        };
+        let last = trajectory.frames.len() - 1;
+        let index = index.saturating_add_signed(frames).min(last);
+        self.time = trajectory.frames[index].elapsed.as_secs_f32();
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<ReplaySettings>("replay", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn record(
+    clock: Res<SimClock>,
+    bodies: Bodies,
+    settings: Res<Persistent<ReplaySettings>>,
+    mut trajectory: ResMut<Trajectory>,
+) {
+    if clock.delta().is_zero() || settings.capacity == 0 {
+        return;
+    }
+    let frame = Frame {
+        tick: clock.tick(),
+        elapsed: clock.elapsed(),
+        bodies: body_state::capture(&bodies),
+    };
+    trajectory.push(frame, settings.capacity);
+}
+
+/// Enters playback, keeping the live scene to come back to.
+pub fn start(world: &mut World) {
+    if world.resource::<Trajectory>().frames.is_empty() {
+        warn!(target: subsystem::IO, "No step to play back");
+        return;
+    }
+    let mut playback = Playback::new(world.resource::<Trajectory>());
+    playback.live = Some(snapshots::capture(world));
+    world.insert_resource(playback);
+}
+
+/// Leaves playback, putting the live scene back.
+pub fn stop(world: &mut World) {
+    let Some(playback) = world.remove_resource::<Playback>() else {
+        return;
+    };
+    if let Some(live) = playback.live {
+        if let Err(error) = snapshots::restore(world, &live) {
+            world.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+fn toggle_playback(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Persistent<ReplaySettings>>,
+    playback: Option<Res<Playback>>,
+) {
+    if !keys.just_pressed(settings.key) {
+        return;
+    }
+    if playback.is_some() {
+        commands.queue(stop);
+    } else {
+        commands.queue(start);
+    }
+}
+
+/// Shows the frame of the playback.
+fn play(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    trajectory: Res<Trajectory>,
+    real_time: Res<Time<Real>>,
+    mut bodies: BodiesMut,
+) {
+    playback.advance(real_time.delta_secs(), &trajectory);
+    let Some(index) = trajectory.index_at(playback.time) else {
+        return;
+    };
+    if let Err(count) = body_state::apply(&trajectory.frames[index].bodies, &mut bodies) {
+        warn!(
+            target: subsystem::IO,
+            "The trajectory has {} bodies but the scene has {count}",
+            trajectory.frames[index].bodies.len()
+        );
+        commands.queue(stop);
+    }
+}
+
+fn hold_clock(mut clock: ResMut<SimClock>) {
+    clock.hold();
+}
+
+/// The saved trajectories of `directory`, by name.
+fn saved(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Panel with the timeline of the playback, and to save and load the trajectories.
+fn replay_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ReplaySettings>>,
+    mut trajectory: ResMut<Trajectory>,
+    mut playback: Option<ResMut<Playback>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Replay")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let span = trajectory.span();
+            ui.label(match span {
+                Some((first, last)) => format!(
+                    "{} steps kept, from {first:.2} s to {last:.2} s",
+                    trajectory.frames.len()
+                ),
+                None => "No step kept yet".to_string(),
+            });
+            match playback.as_mut() {
+                None => {
+                    ui.add_enabled_ui(span.is_some(), |ui| {
+                        if ui
+                            .button(format!("Play back ({:?})", settings.key))
+                            .clicked()
+                        {
+                            commands.queue(start);
+                        }
+                    });
+                }
+                Some(playback) => {
+                    if ui
+                        .button(format!("Back to live ({:?})", settings.key))
+                        .clicked()
+                    {
+                        commands.queue(stop);
+                    }
+                    if let Some((first, last)) = span {
+                        ui.add(
+                            egui::Slider::new(&mut playback.time, first..=last)
+                                .suffix(" s")
+                                .text("Time"),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("◀ Step").clicked() {
+                            playback.step(-1, &trajectory);
+                        }
+                        let label = if playback.playing { "Pause" } else { "Play" };
+                        if ui.button(label).clicked() {
+                            if span.is_some_and(|(_, last)| playback.time >= last) {
+                                // Played again from the start.
+                                playback.time = span.map_or(0.0, |(first, _)| first);
+                            }
+                            playback.playing = !playback.playing;
+                        }
+                        if ui.button("Step ▶").clicked() {
+                            playback.step(1, &trajectory);
+                        }
+                        egui::ComboBox::from_id_salt("replay_speed")
+                            .selected_text(format!("{}×", playback.speed))
+                            .show_ui(ui, |ui| {
+                                for speed in SPEEDS {
+                                    ui.selectable_value(
+                                        &mut playback.speed,
+                                        speed,
+                                        format!("{speed}×"),
+                                    );
+                                }
+                            });
+                    });
+                    if let Some(index) = trajectory.index_at(playback.time) {
+                        ui.label(format!("Step {}", trajectory.frames[index].tick));
+                    }
+                }
+            }
+
+            ui.separator();
+            let directory = edited.directory();
+            ui.label(format!("Trajectories are saved to {}", directory.display()));
+            ui.add_enabled_ui(span.is_some(), |ui| {
+                if ui.button("Save the trajectory").clicked() {
+                    let tick = trajectory.frames.back().map_or(0, |frame| frame.tick);
+                    let path = directory.join(format!("trajectory-{tick}.json"));
+                    if let Err(error) = trajectory.write(&path) {
+                        commands.send_event(ErrorEvent::from(error));
+                    }
+                }
+            });
+            for path in saved(&directory) {
+                ui.horizontal(|ui| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(name.into_owned());
+                    if ui.button("Play back").clicked() {
+                        match Trajectory::read(&path) {
+                            Ok(loaded) => {
+                                *trajectory = loaded;
+                                if playback.is_none() {
+                                    commands.queue(start);
+                                }
+                            }
+                            Err(error) => {
+                                commands.send_event(ErrorEvent::from(error));
+                            }
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.add(
+                egui::DragValue::new(&mut edited.capacity)
+                    .range(0..=1_000_000)
+                    .prefix("Steps kept: "),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("replay", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("replay", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,167 @@
+//! The compute cost of the headless runs: the CPU time the process spent on a run, its peak
+//! memory, and the distribution of the wall-clock durations of the steps, so the cost of a
+//! controller, e.g. of the horizon of the MPC, can be tracked from run to run.
+//!
+//! The CPU time and the peak memory are read from `/proc` on Linux, and are unknown elsewhere.
+//! The CPU time counts every thread of the process, in hundredths of a second. The peak of the
+//! resident memory is reset when a run starts, where the kernel allows it, so that it's the peak
+//! of the run rather than of the whole process.
+use std::{fmt, fs, time::Duration};
+
+/// Clock ticks per second of the times of `/proc`, fixed by the ABI of Linux.
+const USER_HZ: f64 = 100.0;
+
+/// What a run cost.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// CPU time of the process during the run, if known.
+    pub cpu_time: Option<Duration>,
+    /// Peak resident memory of the process, in bytes, if known.
+    pub peak_memory: Option<u64>,
+    pub steps: usize,
+    /// Median, 90th and 99th percentiles and longest duration of the steps.
+    pub step_p50: Duration,
+    pub step_p90: Duration,
+    pub step_p99: Duration,
+    pub step_max: Duration,
+}
+
+impl ResourceUsage {
+    /// Header of the CSV columns of [`ResourceUsage::csv`].
+    pub const CSV_HEADER: &'static str =
+        "cpu_time,peak_memory,step_p50_us,step_p90_us,step_p99_us,step_max_us";
+
+    /// The usage as CSV columns, in seconds, bytes and microseconds, empty when unknown.
+    pub fn csv(&self) -> String {
+        let micros = |duration: Duration| duration.as_micros();
+        format!(
+            "{},{},{},{},{},{}",
+            self.cpu_time
+                .map_or(String::new(), |time| time.as_secs_f64().to_string()),
+            self.peak_memory
+                .map_or(String::new(), |bytes| bytes.to_string()),
+            micros(self.step_p50),
+            micros(self.step_p90),
+            micros(self.step_p99),
+            micros(self.step_max)
+        )
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cpu_time {
+            Some(time) => write!(f, "CPU time {:.2} s", time.as_secs_f64())?,
+            None => write!(f, "CPU time unknown")?,
+        }
+        match self.peak_memory {
+            Some(bytes) => write!(f, ", peak memory {:.1} MiB", bytes as f64 / 1048576.0)?,
+            None => write!(f, ", peak memory unknown")?,
+        }
+        let millis = |duration: Duration| duration.as_secs_f64() * 1e3;
+        write!(
+            f,
+            ", steps of {:.3} ms median, {:.3} ms at the 90th percentile, {:.3} ms at the 99th, \
+             {:.3} ms at most",
+            millis(self.step_p50),
+            millis(self.step_p90),
+            millis(self.step_p99),
+            millis(self.step_max)
+        )
+    }
+}
+
+/// Measures the usage of a run, from its start.
+#[derive(Clone, Debug, Default)]
+pub struct UsageMeter {
+    cpu_start: Option<Duration>,
+    steps: Vec<Duration>,
+}
+
+impl UsageMeter {
+    /// Starts measuring, resetting the peak memory.
+    pub fn start() -> Self {
+        reset_peak_memory();
+        Self {
+            cpu_start: cpu_time(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Counts a step of the run, which took `duration`.
+    pub fn step(&mut self, duration: Duration) {
+        self.steps.push(duration);
+    }
+
+    /// The usage since the start.
+    pub fn usage(&self) -> ResourceUsage {
+        let mut sorted = self.steps.clone();
+        sorted.sort_unstable();
+        ResourceUsage {
+            cpu_time: self
+                .cpu_start
+                .zip(cpu_time())
+                .map(|(start, end)| end.saturating_sub(start)),
+            peak_memory: peak_memory(),
+            steps: sorted.len(),
+            step_p50: percentile(&sorted, 50.0),
+            step_p90: percentile(&sorted, 90.0),
+            step_p99: percentile(&sorted, 99.0),
+            step_max: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// The `percent` percentile of the `sorted` durations, by the nearest rank, or zero when
+/// there are none.
+pub fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// User and system CPU time of a process, from its `/proc/<pid>/stat`.
+pub fn parse_cpu_time(stat: &str) -> Option<Duration> {
+    // The name of the command, in parentheses, may contain spaces.
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    // After the name, the state is the 3rd field, the user and system times the 14th and 15th.
+    let ticks = |index: usize| fields.get(index - 3)?.parse::<u64>().ok();
+    let ticks = ticks(14)? + ticks(15)?;
+    Some(Duration::from_secs_f64(ticks as f64 / USER_HZ))
+}
+
+/// Peak resident memory of a process, in bytes, from its `/proc/<pid>/status`.
+pub fn parse_peak_memory(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn cpu_time() -> Option<Duration> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    parse_cpu_time(&fs::read_to_string("/proc/self/stat").ok()?)
+}
+
+fn peak_memory() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    parse_peak_memory(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn reset_peak_memory() {
+    if cfg!(target_os = "linux") {
+        // Older kernels and sandboxes don't allow it; the peak is then the process's.
+        let _ = fs::write("/proc/self/clear_refs", "5");
+    }
+}
@@ -0,0 +1,486 @@
+//! HTTP API to orchestrate experiment campaigns from scripts and notebooks.
+//!
+//! An instance started with `--api` answers JSON over HTTP:
+//! - `GET /status`: the simulated time, whether it runs, and the run in progress;
+//! - `GET /plants`: the built-in plants and the instances in the scene;
+//! - `GET /parameters`, `PUT /parameters/<name>`: the setpoints, e.g.
+//!   `PUT /parameters/motor/velocity` with `2.5` or `{"value":2.5}`;
+//! - `GET /runs`, `POST /runs`: the runs, and starting one with a [`RunRequest`];
+//! - `GET /runs/<id>`, `POST /runs/<id>/stop`: a run, with the violations of the monitored
+//!   requirements, and stopping it;
+//! - `GET /runs/<id>/metrics`: the [`ChannelMetrics`] of every telemetry channel over the run.
+//!
+//! A run is a named span of the simulation: starting one applies its parameters and resumes the
+//! clock, stopping it, or reaching its duration, pauses the clock. With `reset`, the bodies are
+//! first put back in the state the first reset run started from, so the runs of a campaign start
+//! alike. Errors are answered with their status and `{"error":"..."}`.
+//!
+//! Requests are read on threads of their own and answered by the application between two
+//! frames, like the messages of the collaborative sessions.
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    monitors::{Monitors, Violation},
+    plants::{self, Link},
+    setpoints::Setpoints,
+    telemetry::{Sample, Telemetry},
+};
+
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5712;
+/// How long a request waits for the application to answer it.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request accepted, headers and body.
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// An endpoint of the API.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Route {
+    Status,
+    Plants,
+    Parameters,
+    SetParameter(String),
+    Runs,
+    StartRun,
+    Run(usize),
+    StopRun(usize),
+    Metrics(usize),
+}
+
+/// The endpoint of a request, `Err` with the status to answer when there's none.
+pub fn route(method: &str, path: &str) -> Result<Route, u16> {
+    let path = path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+    let route = match segments[..] {
+        ["status"] => [("GET", Route::Status)].to_vec(),
+        ["plants"] => [("GET", Route::Plants)].to_vec(),
+        ["parameters"] => [("GET", Route::Parameters)].to_vec(),
+        ["parameters", _, ..] => {
+            let name = path["/parameters/".len()..].to_string();
+            [("PUT", Route::SetParameter(name))].to_vec()
+        }
+        ["runs"] => [("GET", Route::Runs), ("POST", Route::StartRun)].to_vec(),
+        ["runs", id, rest @ ..] => {
+            let id = id.parse().map_err(|_| 404u16)?;
+            match rest {
+                [] => [("GET", Route::Run(id))].to_vec(),
+                ["stop"] => [("POST", Route::StopRun(id))].to_vec(),
+                ["metrics"] => [("GET", Route::Metrics(id))].to_vec(),
+                _ => return Err(404),
+            }
+        }
+        _ => return Err(404),
+    };
+    route
+        .into_iter()
+        .find(|(allowed, _)| *allowed == method)
+        .map(|(_, route)| route)
+        .ok_or(405)
+}
+
+/// Body of `POST /runs`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct RunRequest {
+    pub name: String,
+    /// Simulated seconds after which the run stops on its own.
+    pub duration: Option<f32>,
+    /// Whether the bodies are put back in the state the first reset run started from.
+    pub reset: bool,
+    /// Setpoints applied when the run starts.
+    pub parameters: BTreeMap<String, f32>,
+}
+
+/// A run of a campaign.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Run {
+    pub id: usize,
+    pub name: String,
+    /// Simulated time the run started at, in seconds.
+    pub start: f32,
+    /// Simulated time the run stopped at, once stopped.
+    pub end: Option<f32>,
+    pub duration: Option<f32>,
+    pub parameters: BTreeMap<String, f32>,
+    /// Violations of the monitored requirements during the run.
+    pub violations: Vec<Violation>,
+}
+
+/// Statistics of a channel over a run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChannelMetrics {
+    pub samples: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub rms: f32,
+    /// Value of the last sample.
+    pub last: f32,
+}
+
+impl ChannelMetrics {
+    /// Statistics of the samples taken from `start` to `end`, if any.
+    pub fn of(samples: &[Sample], start: f32, end: f32) -> Option<Self> {
+        let first = samples.partition_point(|[time, _]| *time < start);
+        let last = samples.partition_point(|[time, _]| *time <= end);
+        let values: Vec<f32> = samples[first..last.max(first)]
+            .iter()
+            .map(|[_, value]| *value)
+            .collect();
+        let count = values.len() as f64;
+        Some(Self {
+            samples: values.len(),
+            min: values.iter().copied().reduce(f32::min)?,
+            max: values.iter().copied().reduce(f32::max)?,
+            mean: (values.iter().map(|value| f64::from(*value)).sum::<f64>() / count) as f32,
+            rms: (values
+                .iter()
+                .map(|value| f64::from(*value).powi(2))
+                .sum::<f64>()
+                / count)
+                .sqrt() as f32,
+            last: *values.last()?,
+        })
+    }
+}
+
+/// Statistics of every channel of `telemetry` sampled from `start` to `end`.
+pub fn metrics(telemetry: &Telemetry, start: f32, end: f32) -> BTreeMap<String, ChannelMetrics> {
+    telemetry
+        .channels
+        .iter()
+        .filter_map(|(name, samples)| {
+            Some((name.clone(), ChannelMetrics::of(samples, start, end)?))
+        })
+        .collect()
+}
+
+/// Serves the API on the given address.
+pub struct RestApiPlugin {
+    pub bind: SocketAddr,
+}
+
+impl Plugin for RestApiPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        let address = listener.local_addr().unwrap_or(self.bind);
+        info!(target: subsystem::IO, "Serving the API on http://{address}/");
+        let (requests, receiver) = mpsc::channel();
+        thread::spawn(move || accept_requests(listener, requests));
+
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(RestApi {
+                requests: Mutex::new(receiver),
+                address,
+                runs: Vec::new(),
+                initial: None,
+            })
+            .add_systems(Update, answer_requests)
+            .add_systems(PostUpdate, stop_finished_runs.after(SimClockSet::Advance))
+            .add_systems(Last, report_violations.run_if(resource_exists::<Monitors>));
+    }
+}
+
+/// A request read by a connection thread, waiting for its answer.
+struct Request {
+    route: Route,
+    body: Vec<u8>,
+    answer: mpsc::Sender<(u16, Value)>,
+}
+
+fn accept_requests(listener: TcpListener, requests: mpsc::Sender<Request>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let requests = requests.clone();
+        thread::spawn(move || {
+            let address = stream.peer_addr().ok();
+            if let Err(error) = handle_connection(stream, &requests) {
+                debug!(target: subsystem::IO, "API client {address:?}: {error}");
+            }
+        });
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a request: its method, its path and its body.
+fn read_request(stream: &TcpStream) -> io::Result<(String, String, Vec<u8>)> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let (Some(method), Some(path)) = (words.next(), words.next()) else {
+        return Err(invalid("not HTTP"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("truncated request"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| invalid("bad length"))?;
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok((method, path, body))
+}
+
+fn handle_connection(mut stream: TcpStream, requests: &mpsc::Sender<Request>) -> io::Result<()> {
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    let (method, path, body) = read_request(&stream)?;
+    let (status, body) = match route(&method, &path) {
+        Ok(route) => {
+            let (answer, receiver) = mpsc::channel();
+            let _ = requests.send(Request {
+                route,
+                body,
+                answer,
+            });
+            receiver
+                .recv_timeout(ANSWER_TIMEOUT)
+                .unwrap_or_else(|_| (503, json!({"error": "the application didn't answer"})))
+        }
+        Err(404) => (404, json!({"error": format!("no endpoint {path}")})),
+        Err(status) => (
+            status,
+            json!({"error": format!("{method} isn't allowed on {path}")}),
+        ),
+    };
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Service Unavailable",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// The API served by this instance, and the runs it started.
+#[derive(Resource)]
+pub struct RestApi {
+    requests: Mutex<mpsc::Receiver<Request>>,
+    /// Address served, with the port picked by the system when bound to port 0.
+    pub address: SocketAddr,
+    pub runs: Vec<Run>,
+    /// State of the bodies the reset runs start from, once one started.
+    initial: Option<Vec<BodyState>>,
+}
+
+impl RestApi {
+    /// The run in progress, if any.
+    pub fn current(&self) -> Option<&Run> {
+        self.runs.last().filter(|run| run.end.is_none())
+    }
+
+    fn stop(&mut self, id: usize, clock: &mut SimClock) -> Option<&Run> {
+        let run = self.runs.get_mut(id)?;
+        if run.end.is_none() {
+            run.end = Some(clock.elapsed_secs());
+            clock.pause();
+            info!(target: subsystem::CONTROL, "Run {} ({}) stopped", run.id, run.name);
+        }
+        Some(run)
+    }
+}
+
+fn bad_request(message: impl std::fmt::Display) -> (u16, Value) {
+    (400, json!({"error": message.to_string()}))
+}
+
+fn unknown_run(id: usize) -> (u16, Value) {
+    (404, json!({"error": format!("no run {id}")}))
+}
+
+fn answer_requests(
+    mut api: ResMut<RestApi>,
+    mut clock: ResMut<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Res<Telemetry>,
+    links: Query<&Link>,
+    mut bodies: ParamSet<(Bodies, BodiesMut)>,
+) {
+    let api = &mut *api;
+    let requests: Vec<Request> = api
+        .requests
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner())
+        .try_iter()
+        .collect();
+    for request in requests {
+        let answer = match request.route {
+            Route::Status => (
+                200,
+                json!({
+                    "time": clock.elapsed_secs(),
+                    "tick": clock.tick(),
+                    "running": !clock.is_paused(),
+                    "run": api.current(),
+                }),
+            ),
+            Route::Plants => {
+                let mut instances: Vec<&str> =
+                    links.iter().map(|link| link.plant.as_str()).collect();
+                instances.sort_unstable();
+                instances.dedup();
+                let builtin: Vec<&str> =
+                    plants::available().iter().map(|plant| plant.name).collect();
+                (200, json!({"builtin": builtin, "instances": instances}))
+            }
+            Route::Parameters => (200, json!(setpoints.values)),
+            Route::SetParameter(name) => {
+                let value = match serde_json::from_slice::<Value>(&request.body) {
+                    Ok(Value::Number(value)) => value.as_f64(),
+                    Ok(Value::Object(object)) => object.get("value").and_then(Value::as_f64),
+                    _ => None,
+                };
+                match value {
+                    Some(value) => {
+                        setpoints.set(&name, value as f32);
+                        (200, json!({"name": name, "value": value}))
+                    }
+                    None => bad_request("expected a number, or {\"value\": number}"),
+                }
+            }
+            Route::Runs => (200, json!(api.runs)),
+            Route::StartRun => {
+                let body = if request.body.is_empty() {
+                    b"{}".as_slice()
+                } else {
+                    &request.body
+                };
+                match serde_json::from_slice::<RunRequest>(body) {
+                    Err(error) => bad_request(error),
+                    Ok(_) if api.current().is_some() => {
+                        (409, json!({"error": "a run is in progress"}))
+                    }
+                    Ok(run) => {
+                        if run.reset {
+                            let initial = api
+                                .initial
+                                .get_or_insert_with(|| body_state::capture(&bodies.p0()));
+                            if let Err(count) = body_state::apply(initial, &mut bodies.p1()) {
+                                warn!(
+                                    target: subsystem::PHYSICS,
+                                    "The scene has {count} bodies instead of {}, not reset",
+                                    initial.len()
+                                );
+                            }
+                        }
+                        for (name, value) in &run.parameters {
+                            setpoints.set(name, *value);
+                        }
+                        let id = api.runs.len();
+                        let name = if run.name.is_empty() {
+                            format!("run {id}")
+                        } else {
+                            run.name
+                        };
+                        info!(target: subsystem::CONTROL, "Run {id} ({name}) started");
+                        api.runs.push(Run {
+                            id,
+                            name,
+                            start: clock.elapsed_secs(),
+                            end: None,
+                            duration: run.duration,
+                            parameters: run.parameters,
+                            violations: Vec::new(),
+                        });
+                        clock.resume();
+                        (201, json!(api.runs[id]))
+                    }
+                }
+            }
+            Route::Run(id) => match api.runs.get(id) {
+                Some(run) => (200, json!(run)),
+                None => unknown_run(id),
+            },
+            Route::StopRun(id) => match api.stop(id, &mut clock) {
+                Some(run) => (200, json!(run)),
+                None => unknown_run(id),
+            },
+            Route::Metrics(id) => match api.runs.get(id) {
+                Some(run) => {
+                    let end = run.end.unwrap_or_else(|| clock.elapsed_secs());
+                    (200, json!(metrics(&telemetry, run.start, end)))
+                }
+                None => unknown_run(id),
+            },
+        };
+        let _ = request.answer.send(answer);
+    }
+}
+
+/// Stops the run in progress once it lasted its duration.
+fn stop_finished_runs(mut api: ResMut<RestApi>, mut clock: ResMut<SimClock>) {
+    let Some(run) = api.current() else {
+        return;
+    };
+    let id = run.id;
+    if run
+        .duration
+        .is_some_and(|duration| clock.elapsed_secs() >= run.start + duration)
+    {
+        api.stop(id, &mut clock);
+    }
+}
+
+/// Reports the violations of the monitored requirements with the runs they happened in.
+fn report_violations(mut api: ResMut<RestApi>, clock: Res<SimClock>, monitors: Res<Monitors>) {
+    let now = clock.elapsed_secs();
+    for run in &mut api.runs {
+        run.violations = monitors.between(run.start, run.end.unwrap_or(now));
+    }
+}
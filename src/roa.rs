@@ -0,0 +1,309 @@
+//! Region of attraction: the initial states from which the controllers bring a channel to its
+//! target, estimated by sampling them headlessly over a grid of two parameters.
+//!
+//! Each cell of the grid is run from initial states drawn at random within it, as in a
+//! [sweep](crate::sweep), and a run is attracted when the channel settles within the band of
+//! its target without violating the monitored requirements. The attracted fraction of a cell
+//! is bounded by its Wilson score interval at the configured confidence. The region is then
+//! refined where it is uncertain: the cells with both outcomes, or whose neighbours mostly have
+//! the other one, are sampled again, so the samples concentrate near the boundary of the
+//! region. The mapper is read from `roa.json`.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    fuzzing::Random,
+    plants::Plant,
+    sweep::{self, Range, SweepSettings},
+    telemetry::PENDULUM_ANGLE,
+};
+
+/// Represents the configuration of the mapper.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct RoaSettings {
+    /// Parameters along the two axes of the grid, named as in a sweep, with their number of
+    /// cells as their steps.
+    pub x: Range,
+    pub y: Range,
+    /// Runs per cell of the first pass.
+    pub samples: usize,
+    /// Passes refining the uncertain cells, and runs added per cell and pass.
+    pub refinements: usize,
+    pub refine_samples: usize,
+    /// Confidence of the bounds of the attracted fractions, e.g. 0.95.
+    pub confidence: f32,
+    /// Seed of the draws of the initial states and of every run.
+    pub seed: u64,
+    /// Simulated duration of a run, in s.
+    pub duration: f32,
+    /// Setpoints set at the start of every run, before the initial state.
+    pub setpoints: BTreeMap<String, f32>,
+    /// Channel that reaches its target from the attracted states, within the band.
+    pub channel: String,
+    pub target: f32,
+    pub band: f32,
+}
+
+impl Default for RoaSettings {
+    fn default() -> Self {
+        Self {
+            x: Range {
+                name: "initial/pendulum".to_string(),
+                min: -0.5,
+                max: 0.5,
+                steps: 10,
+            },
+            y: Range {
+                name: "initial/arm".to_string(),
+                min: -1.0,
+                max: 1.0,
+                steps: 10,
+            },
+            samples: 4,
+            refinements: 2,
+            refine_samples: 8,
+            confidence: 0.95,
+            seed: 1,
+            duration: 5.0,
+            setpoints: BTreeMap::new(),
+            channel: PENDULUM_ANGLE.to_string(),
+            target: 0.0,
+            band: 0.05,
+        }
+    }
+}
+
+impl RoaSettings {
+    /// The settings of the runs, as a sweep.
+    pub fn runs(&self) -> SweepSettings {
+        SweepSettings {
+            seed: self.seed,
+            duration: self.duration,
+            setpoints: self.setpoints.clone(),
+            channel: self.channel.clone(),
+            target: self.target,
+            band: self.band,
+            ..default()
+        }
+    }
+}
+
+/// A cell of the grid, and the outcomes of its runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    /// Bounds of the cell along each axis.
+    pub x: [f32; 2],
+    pub y: [f32; 2],
+    pub samples: usize,
+    /// Runs attracted.
+    pub attracted: usize,
+}
+
+impl Cell {
+    /// Attracted fraction of the runs, or 0 without runs.
+    pub fn fraction(&self) -> f32 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.attracted as f32 / self.samples as f32
+    }
+
+    /// Whether most runs are attracted.
+    pub fn inside(&self) -> bool {
+        2 * self.attracted > self.samples
+    }
+
+    pub fn area(&self) -> f32 {
+        (self.x[1] - self.x[0]) * (self.y[1] - self.y[0])
+    }
+}
+
+/// Estimate of a region of attraction over a grid, row by row along `y`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionEstimate {
+    /// Parameters along the axes.
+    pub x: String,
+    pub y: String,
+    /// Cells per row.
+    pub columns: usize,
+    pub cells: Vec<Cell>,
+    pub confidence: f32,
+}
+
+impl RegionEstimate {
+    /// Bounds of the attracted fraction of `cell` at the confidence of the estimate.
+    pub fn bounds(&self, cell: &Cell) -> [f32; 2] {
+        wilson(cell.attracted, cell.samples, z_score(self.confidence))
+    }
+
+    /// Area of the region, in the units of the parameters, with its lower and upper bounds.
+    pub fn area(&self) -> [f32; 3] {
+        self.cells
+            .iter()
+            .fold([0.0; 3], |[lower, area, upper], cell| {
+                let [low, high] = self.bounds(cell);
+                [
+                    lower + low * cell.area(),
+                    area + cell.fraction() * cell.area(),
+                    upper + high * cell.area(),
+                ]
+            })
+    }
+
+    /// Indices of the cells on the boundary of the region: with both outcomes, or beside a
+    /// cell mostly with the other one.
+    pub fn boundary(&self) -> Vec<usize> {
+        let columns = self.columns.max(1);
+        let rows = self.cells.len() / columns;
+        (0..self.cells.len())
+            .filter(|&index| {
+                let cell = &self.cells[index];
+                if cell.attracted > 0 && cell.attracted < cell.samples {
+                    return true;
+                }
+                let (row, column) = (index / columns, index % columns);
+                let neighbours = [
+                    (column > 0).then(|| index - 1),
+                    (column + 1 < columns).then(|| index + 1),
+                    (row > 0).then(|| index - columns),
+                    (row + 1 < rows).then(|| index + columns),
+                ];
+                neighbours
+                    .into_iter()
+                    .flatten()
+                    .any(|neighbour| self.cells[neighbour].inside() != cell.inside())
+            })
+            .collect()
+    }
+
+    /// Writes the estimate as CSV, one row per cell with its bounds along the axes, its runs
+    /// and the bounds of its attracted fraction.
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from(
+            "x_min,x_max,y_min,y_max,samples,attracted,fraction,fraction_min,fraction_max\n",
+        );
+        for cell in &self.cells {
+            let [low, high] = self.bounds(cell);
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{low},{high}\n",
+                cell.x[0],
+                cell.x[1],
+                cell.y[0],
+                cell.y[1],
+                cell.samples,
+                cell.attracted,
+                cell.fraction()
+            ));
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        fs::write(path, csv).map_err(|error| Error::io(path, error))
+    }
+}
+
+/// Two-sided standard normal quantile of `confidence`, by the rational approximation of
+/// Abramowitz and Stegun (26.2.23), within 5e-4.
+pub fn z_score(confidence: f32) -> f32 {
+    let tail = ((1.0 - confidence) / 2.0).clamp(1e-9, 0.5);
+    let t = (-2.0 * tail.ln()).sqrt();
+    t - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+        / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+}
+
+/// Wilson score interval of a fraction of `successes` out of `samples`, at the quantile `z`.
+pub fn wilson(successes: usize, samples: usize, z: f32) -> [f32; 2] {
+    if samples == 0 {
+        return [0.0, 1.0];
+    }
+    let n = samples as f32;
+    let p = successes as f32 / n;
+    let z2 = z * z;
+    let denominator = 1.0 + z2 / n;
+    let centre = (p + z2 / (2.0 * n)) / denominator;
+    let half = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    [(centre - half).max(0.0), (centre + half).min(1.0)]
+}
+
+/// Estimates the region of `settings`, asking `attracted` whether a run from the given
+/// parameters is.
+pub fn estimate(
+    settings: &RoaSettings,
+    mut attracted: impl FnMut(&BTreeMap<String, f32>) -> Result<bool>,
+) -> Result<RegionEstimate> {
+    if !(settings.confidence > 0.0 && settings.confidence < 1.0) {
+        return Err(Error::Config {
+            name: "roa".to_string(),
+            message: format!(
+                "the confidence must be between 0 and 1, not {}",
+                settings.confidence
+            ),
+        });
+    }
+    let (columns, rows) = (settings.x.steps.max(1), settings.y.steps.max(1));
+    let bounds = |range: &Range, index: usize, count: usize| {
+        let width = (range.max - range.min) / count as f32;
+        [
+            range.min + width * index as f32,
+            range.min + width * (index + 1) as f32,
+        ]
+    };
+    let mut region = RegionEstimate {
+        x: settings.x.name.clone(),
+        y: settings.y.name.clone(),
+        columns,
+        cells: (0..rows * columns)
+            .map(|index| Cell {
+                x: bounds(&settings.x, index % columns, columns),
+                y: bounds(&settings.y, index / columns, rows),
+                samples: 0,
+                attracted: 0,
+            })
+            .collect(),
+        confidence: settings.confidence,
+    };
+
+    let mut random = Random::new(settings.seed);
+    let mut sample = |region: &mut RegionEstimate, index: usize, count: usize| {
+        for _ in 0..count {
+            let cell = &mut region.cells[index];
+            let parameters = BTreeMap::from([
+                (region.x.clone(), random.range(cell.x[0], cell.x[1])),
+                (region.y.clone(), random.range(cell.y[0], cell.y[1])),
+            ]);
+            cell.samples += 1;
+            if attracted(&parameters)? {
+                cell.attracted += 1;
+            }
+        }
+        Ok::<_, Error>(())
+    };
+    for index in 0..region.cells.len() {
+        sample(&mut region, index, settings.samples.max(1))?;
+    }
+    for _ in 0..settings.refinements {
+        for index in region.boundary() {
+            sample(&mut region, index, settings.refine_samples)?;
+        }
+    }
+    Ok(region)
+}
+
+/// Maps the region of attraction of the controllers of `plant`, stepping by `dt`.
+pub fn map(plant: &Plant, settings: &RoaSettings, dt: f32) -> Result<RegionEstimate> {
+    let runs = settings.runs();
+    estimate(settings, |parameters| {
+        let run = sweep::evaluate(plant, &runs, parameters, dt)?;
+        Ok(run.violations == 0
+            && run
+                .metrics
+                .is_some_and(|metrics| metrics.settling_time.is_some()))
+    })
+}
@@ -0,0 +1,340 @@
+//! This module compares two recorded runs channel by channel, e.g. before and after a change of
+//! a controller, with the `diff` command or in the *Run diff* panel.
+//!
+//! A run is the telemetry of a session directory, as archived by the autosave, or a telemetry
+//! file, streamed log or JSON. The runs are aligned on their time, or on an event: the first
+//! sample of a marker channel leaving its initial value, e.g. the step of a setpoint, so runs
+//! started at different times still line up. Each channel of the first run is compared with the
+//! second run at its sample times, interpolating linearly, over the time both runs cover. The
+//! largest and the RMS deviations are reported, and the differences can be written as CSV or
+//! plotted.
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+
+use crate::{
+    accessibility_plugin::has_ui,
+    autosave,
+    error::{Error, ErrorEvent, Result},
+    telemetry::{Sample, Telemetry},
+    theme::{to_egui, Theme},
+};
+
+/// Number of points of the plot of a difference.
+const PLOTTED_POINTS: usize = 300;
+
+pub struct RunDiffPlugin;
+
+impl Plugin for RunDiffPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunDiffView>()
+            .add_systems(Update, run_diff_panel.run_if(has_ui));
+    }
+}
+
+/// How the times of the two runs are matched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Alignment {
+    /// The same times in both runs.
+    #[default]
+    Time,
+    /// The times since the first sample of `channel` more than `threshold` away from its first
+    /// value.
+    Event { channel: String, threshold: f32 },
+}
+
+impl Alignment {
+    /// Time of the event in a run, 0 when aligned on time.
+    pub fn origin(&self, run: &Telemetry) -> Result<f32> {
+        let Alignment::Event { channel, threshold } = self else {
+            return Ok(0.0);
+        };
+        let samples = run.channels.get(channel).map_or(&[][..], Vec::as_slice);
+        let initial = samples.first().map(|[_, value]| *value);
+        samples
+            .iter()
+            .find(|[_, value]| initial.is_some_and(|initial| (value - initial).abs() > *threshold))
+            .map(|[time, _]| *time)
+            .ok_or_else(|| Error::Model(format!("the marker `{channel}` never changes")))
+    }
+}
+
+/// Value of a channel at `time`, interpolated linearly between its samples, if they cover it.
+pub fn interpolate(samples: &[Sample], time: f32) -> Option<f32> {
+    let after = samples.partition_point(|[t, _]| *t < time);
+    let [t1, v1] = *samples.get(after)?;
+    if t1 == time {
+        return Some(v1);
+    }
+    let [t0, v0] = *samples.get(after.checked_sub(1)?)?;
+    Some(v0 + (v1 - v0) * (time - t0) / (t1 - t0))
+}
+
+/// Deviation of a channel of the second run from the first one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelDiff {
+    pub channel: String,
+    /// Largest deviation, in absolute value, and its time in the first run.
+    pub max: f32,
+    pub max_time: f32,
+    /// Root mean square of the deviations.
+    pub rms: f32,
+    /// Time in the first run, value of the first run minus value of the second one.
+    pub differences: Vec<Sample>,
+}
+
+/// Comparison of two runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunDiff {
+    /// Time of the second run at the time zero of the first one.
+    pub offset: f32,
+    /// The channels of both runs, by name.
+    pub channels: Vec<ChannelDiff>,
+    /// Channels of one run only.
+    pub only_first: Vec<String>,
+    pub only_second: Vec<String>,
+}
+
+impl RunDiff {
+    pub fn compare(first: &Telemetry, second: &Telemetry, alignment: &Alignment) -> Result<Self> {
+        let offset = alignment.origin(second)? - alignment.origin(first)?;
+        let mut diff = RunDiff {
+            offset,
+            ..Default::default()
+        };
+        for (channel, samples) in &first.channels {
+            let Some(other) = second.channels.get(channel) else {
+                diff.only_first.push(channel.clone());
+                continue;
+            };
+            let differences: Vec<Sample> = samples
+                .iter()
+                .filter_map(|&[time, value]| {
+                    Some([time, value - interpolate(other, time + offset)?])
+                })
+                .collect();
+            if differences.is_empty() {
+                continue;
+            }
+            let [max_time, max] = differences
+                .iter()
+                .map(|&[time, difference]| [time, difference.abs()])
+                .fold([0.0, 0.0], |largest, sample| {
+                    if sample[1] > largest[1] {
+                        sample
+                    } else {
+                        largest
+                    }
+                });
+            let squares: f32 = differences.iter().map(|[_, d]| d * d).sum();
+            diff.channels.push(ChannelDiff {
+                channel: channel.clone(),
+                max,
+                max_time,
+                rms: (squares / differences.len() as f32).sqrt(),
+                differences,
+            });
+        }
+        diff.only_second = second
+            .channels
+            .keys()
+            .filter(|channel| !first.channels.contains_key(*channel))
+            .cloned()
+            .collect();
+        Ok(diff)
+    }
+
+    /// Largest deviation over the channels.
+    pub fn max(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(|channel| channel.max)
+            .fold(0.0, f32::max)
+    }
+
+    /// Writes the differences as CSV, one row per channel and time, for plotting.
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from("channel,time,difference\n");
+        for channel in &self.channels {
+            for [time, difference] in &channel.differences {
+                csv.push_str(&format!("{},{time},{difference}\n", channel.channel));
+            }
+        }
+        fs::write(path, csv).map_err(|error| Error::io(path, error))
+    }
+}
+
+impl fmt::Display for RunDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.offset != 0.0 {
+            writeln!(f, "second run shifted by {:.3} s", -self.offset)?;
+        }
+        for channel in &self.channels {
+            writeln!(
+                f,
+                "  {}: max {:.4e} at {:.3} s, RMS {:.4e}",
+                channel.channel, channel.max, channel.max_time, channel.rms
+            )?;
+        }
+        for channel in &self.only_first {
+            writeln!(f, "  {channel}: only in the first run")?;
+        }
+        for channel in &self.only_second {
+            writeln!(f, "  {channel}: only in the second run")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a recorded run: a session directory, or a telemetry file, streamed log or JSON.
+pub fn read_run(path: &Path) -> Result<Telemetry> {
+    if path.is_dir() {
+        return autosave::read_telemetry(path).map_err(|error| Error::io(path, error));
+    }
+    autosave::read_telemetry_file(path).map_err(|error| Error::io(path, error))
+}
+
+/// The archived sessions, the most recent first.
+pub fn archived_sessions() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(autosave::sessions_dir()) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            (entry.path().is_dir() && entry.file_name() != "current")
+                .then(|| (modified, entry.path()))
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.0.cmp(&a.0));
+    sessions.into_iter().map(|(_, path)| path).collect()
+}
+
+/// State of the run diff panel.
+#[derive(Default, Resource)]
+struct RunDiffView {
+    first: String,
+    second: String,
+    /// Marker channel, empty to align on time.
+    marker: String,
+    diff: Option<RunDiff>,
+    /// Channel plotted.
+    selected: usize,
+}
+
+/// Panel to compare two archived sessions, with a plot of the differences of a channel.
+fn run_diff_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut view: ResMut<RunDiffView>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+    let view = &mut *view;
+    egui::Window::new("Run diff")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if view.first.is_empty() && view.second.is_empty() {
+                // The last two runs, the older one first.
+                let sessions = archived_sessions();
+                if let [second, first, ..] = sessions.as_slice() {
+                    view.first = first.display().to_string();
+                    view.second = second.display().to_string();
+                }
+            }
+            egui::Grid::new("run_diff_runs").show(ui, |ui| {
+                ui.label("First run");
+                ui.text_edit_singleline(&mut view.first);
+                ui.end_row();
+                ui.label("Second run");
+                ui.text_edit_singleline(&mut view.second);
+                ui.end_row();
+                ui.label("Marker");
+                ui.text_edit_singleline(&mut view.marker)
+                    .on_hover_text("Channel whose first change aligns the runs, or empty");
+                ui.end_row();
+            });
+            if ui.button("Compare").clicked() {
+                let alignment = if view.marker.is_empty() {
+                    Alignment::Time
+                } else {
+                    Alignment::Event {
+                        channel: view.marker.clone(),
+                        threshold: 0.0,
+                    }
+                };
+                let diff = read_run(Path::new(&view.first)).and_then(|first| {
+                    let second = read_run(Path::new(&view.second))?;
+                    RunDiff::compare(&first, &second, &alignment)
+                });
+                match diff {
+                    Ok(diff) => view.diff = Some(diff),
+                    Err(error) => {
+                        view.diff = None;
+                        commands.send_event(ErrorEvent::from(error));
+                    }
+                }
+                view.selected = 0;
+            }
+
+            let Some(diff) = &view.diff else {
+                return;
+            };
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (index, channel) in diff.channels.iter().enumerate() {
+                        let label = format!(
+                            "{}: max {:.3e} at {:.2} s, RMS {:.3e}",
+                            channel.channel, channel.max, channel.max_time, channel.rms
+                        );
+                        ui.radio_value(&mut view.selected, index, label);
+                    }
+                    for channel in diff.only_first.iter().chain(&diff.only_second) {
+                        ui.weak(format!("{channel}: in one run only"));
+                    }
+                });
+            if let Some(channel) = diff.channels.get(view.selected) {
+                plot_differences(ui, channel, &theme);
+            }
+        });
+}
+
+/// Plots the differences of a channel over the run, centered on zero.
+fn plot_differences(ui: &mut egui::Ui, channel: &ChannelDiff, theme: &Theme) {
+    let step = channel.differences.len().div_ceil(PLOTTED_POINTS).max(1);
+    let high = channel.max.max(f32::EPSILON);
+    let (start, end) = match (channel.differences.first(), channel.differences.last()) {
+        (Some([start, _]), Some([end, _])) => (*start, end.max(start + f32::EPSILON)),
+        _ => return,
+    };
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(300.0, 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let points = channel
+        .differences
+        .iter()
+        .step_by(step)
+        .map(|[time, difference]| {
+            egui::pos2(
+                rect.left() + rect.width() * (time - start) / (end - start),
+                rect.center().y - 0.5 * rect.height() * difference / high,
+            )
+        })
+        .collect();
+    let axis = to_egui(theme.series(1));
+    painter.hline(
+        rect.x_range(),
+        rect.center().y,
+        egui::Stroke::new(0.5, axis),
+    );
+    let color = to_egui(theme.series(0));
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+}
@@ -0,0 +1,306 @@
+//! Scenario files: an experiment declared in one file, so it can be reproduced and shared rather
+//! than set up by hand from the panels and the configuration files.
+//!
+//! A [`ScenarioDefinition`] names the model to load (a plant of this build, a scene composition
+//! or a URDF robot), the initial angles of its joints, the setpoints, the controllers with their
+//! gains as [controller definitions](crate::controller_definition), the disturbances injected
+//! at given times, and the duration, time step and seed of the run. It's written in RON, YAML
+//! or JSON, after the extension of the file, and loaded with `--scenario`: the options of the
+//! command line take precedence over it, and the paths it gives are relative to its file.
+//!
+//! The [`ScenarioPlugin`] sets the scenario up once the model is spawned, its controllers
+//! replacing those of the configuration files without saving them, then injects the scheduled
+//! disturbances and pauses the simulation at the end of the run. Headless runs follow the
+//! scenario for its duration.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use ron::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    controller_definition::ControllerDefinition,
+    disturbances::{Inject, Injection},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    lqr::LqrSettings,
+    pid_controller::PidSettings,
+    plants::{Link, Plant},
+    setpoints::Setpoints,
+    sweep,
+};
+
+/// A disturbance injected at a given time of the run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScheduledInjection {
+    /// Simulated time from the start of the run, in s.
+    pub time: f32,
+    #[serde(default)]
+    pub injection: Injection,
+}
+
+/// An experiment.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ScenarioDefinition {
+    /// Plant of this build or of its extensions to spawn, e.g. `cart_pole`.
+    pub plant: Option<String>,
+    /// Scene composition file to compose instead.
+    pub composition: Option<PathBuf>,
+    /// URDF file of the robot to spawn instead.
+    pub urdf: Option<PathBuf>,
+    /// Initial angles of the joints turning the links, by path, in rad.
+    pub initial: BTreeMap<String, f32>,
+    /// Setpoints set at the start.
+    pub setpoints: BTreeMap<String, f32>,
+    /// Controllers run, with their gains and bindings.
+    pub controllers: Vec<ControllerDefinition>,
+    pub disturbances: Vec<ScheduledInjection>,
+    /// Simulated duration of the run, in s.
+    pub duration: Option<f32>,
+    /// Fixed time step of the physics, in s.
+    pub dt: Option<f32>,
+    /// Seed of the random draws.
+    pub seed: Option<u64>,
+}
+
+impl ScenarioDefinition {
+    /// Reads a scenario from a RON (`.ron`), YAML (`.yaml` or `.yml`) or JSON file, its paths
+    /// made relative to the directory of the file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        let invalid = |message: String| Error::Config {
+            name: path.display().to_string(),
+            message,
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let mut scenario: Self = match extension.as_deref() {
+            Some("ron") => ron::Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_str(&text)
+                .map_err(|error| invalid(error.to_string()))?,
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&text).map_err(|error| invalid(error.to_string()))?
+            }
+            _ => serde_json::from_str(&text).map_err(|error| invalid(error.to_string()))?,
+        };
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for file in [&mut scenario.composition, &mut scenario.urdf]
+            .into_iter()
+            .flatten()
+        {
+            *file = directory.join(&*file);
+        }
+        Ok(scenario)
+    }
+
+    /// Checks that a single model is given, among the `available` plants for a plant, that the
+    /// controllers are position loops or regulators, and that the times make sense.
+    pub fn validate(&self, available: &[Plant]) -> Result<()> {
+        let invalid = |message: String| Error::Config {
+            name: "scenario".to_string(),
+            message,
+        };
+        let models = [
+            self.plant.is_some(),
+            self.composition.is_some(),
+            self.urdf.is_some(),
+        ];
+        if models.into_iter().filter(|given| *given).count() > 1 {
+            return Err(invalid(
+                "give one of `plant`, `composition` and `urdf`".to_string(),
+            ));
+        }
+        if let Some(name) = &self.plant {
+            if !available.iter().any(|plant| plant.name == name) {
+                return Err(invalid(format!("no plant {name} in this build")));
+            }
+        }
+        for definition in &self.controllers {
+            match definition.controller.name() {
+                "pid" => PidSettings::try_from(definition).map(drop)?,
+                _ => LqrSettings::try_from(definition).map(drop)?,
+            }
+        }
+        if self.duration.is_some_and(|duration| duration <= 0.0)
+            || self.dt.is_some_and(|dt| dt <= 0.0)
+        {
+            return Err(invalid(
+                "the duration and the time step must be positive".to_string(),
+            ));
+        }
+        if let Some(scheduled) = self
+            .disturbances
+            .iter()
+            .find(|scheduled| scheduled.time < 0.0)
+        {
+            return Err(invalid(format!(
+                "the disturbance of {} is injected before the start",
+                scheduled.injection.link
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Sets the scenario up, injects its disturbances and ends it.
+pub struct ScenarioPlugin {
+    pub scenario: ScenarioDefinition,
+}
+
+impl Plugin for ScenarioPlugin {
+    fn build(&self, app: &mut App) {
+        let mut scenario = self.scenario.clone();
+        scenario
+            .disturbances
+            .sort_by(|a, b| a.time.total_cmp(&b.time));
+        app.insert_resource(ActiveScenario {
+            scenario,
+            start: None,
+            injected: 0,
+            over: false,
+        })
+        .add_systems(Update, start.run_if(not_started))
+        .add_systems(
+            PostUpdate,
+            (inject_disturbances, end)
+                .chain()
+                .after(SimClockSet::Advance),
+        );
+    }
+}
+
+/// The scenario followed, and how far it went.
+#[derive(Resource)]
+pub struct ActiveScenario {
+    pub scenario: ScenarioDefinition,
+    /// Simulated time it started at, in s, once the model is spawned.
+    pub start: Option<f32>,
+    /// Number of disturbances injected so far.
+    pub injected: usize,
+    /// Whether its duration elapsed.
+    pub over: bool,
+}
+
+impl ActiveScenario {
+    /// Simulated time since the start, in s.
+    pub fn elapsed(&self, clock: &SimClock) -> Option<f32> {
+        self.start.map(|start| clock.elapsed_secs() - start)
+    }
+}
+
+fn not_started(scenario: Res<ActiveScenario>, links: Query<(), With<Link>>) -> bool {
+    scenario.start.is_none() && !links.is_empty()
+}
+
+/// Sets the controllers, the setpoints and the initial angles of the scenario once the model is
+/// spawned.
+fn start(world: &mut World) {
+    let scenario = world.resource::<ActiveScenario>().scenario.clone();
+    let mut errors = Vec::new();
+    for definition in &scenario.controllers {
+        let applied = match definition.controller.name() {
+            "pid" => PidSettings::try_from(definition).and_then(|settings| {
+                replace(world, settings, "the position loop", &definition.name)
+            }),
+            _ => LqrSettings::try_from(definition)
+                .and_then(|settings| replace(world, settings, "the regulator", &definition.name)),
+        };
+        errors.extend(applied.err());
+    }
+    if let Some(mut setpoints) = world.get_resource_mut::<Setpoints>() {
+        for (name, value) in &scenario.setpoints {
+            setpoints.set(name, *value);
+        }
+    }
+    for (link, angle) in &scenario.initial {
+        if let Err(message) = sweep::turn(world, link, *angle) {
+            errors.push(Error::Config {
+                name: "scenario".to_string(),
+                message,
+            });
+        }
+    }
+    for error in errors {
+        world.send_event(ErrorEvent::from(error));
+    }
+    let now = world.resource::<SimClock>().elapsed_secs();
+    world.resource_mut::<ActiveScenario>().start = Some(now);
+    info!(target: subsystem::CONTROL, "Scenario started at {now:.3} s");
+}
+
+/// Replaces the settings of a controller, without saving them.
+fn replace<R: Resource + Serialize + for<'de> Deserialize<'de>>(
+    world: &mut World,
+    settings: R,
+    controller: &str,
+    name: &str,
+) -> Result<()> {
+    match world.get_resource_mut::<Persistent<R>>() {
+        Some(mut persistent) => {
+            *persistent.get_mut() = settings;
+            Ok(())
+        }
+        None => Err(Error::Config {
+            name: name.to_string(),
+            message: format!("no {controller} in this application"),
+        }),
+    }
+}
+
+/// Injects the disturbances whose time came.
+fn inject_disturbances(
+    mut scenario: ResMut<ActiveScenario>,
+    clock: Res<SimClock>,
+    mut injections: Option<ResMut<Events<Inject>>>,
+) {
+    let Some(elapsed) = scenario.elapsed(&clock) else {
+        return;
+    };
+    let scenario = &mut *scenario;
+    while let Some(scheduled) = scenario.scenario.disturbances.get(scenario.injected) {
+        if scheduled.time > elapsed {
+            break;
+        }
+        match injections.as_mut() {
+            Some(injections) => {
+                info!(
+                    target: subsystem::CONTROL,
+                    "Injecting the scheduled disturbance of {} at {elapsed:.3} s",
+                    scheduled.injection.link
+                );
+                injections.send(Inject {
+                    injection: Some(scheduled.injection.clone()),
+                    ..default()
+                });
+            }
+            None => warn!(
+                target: subsystem::CONTROL,
+                "No disturbances to inject into {}", scheduled.injection.link
+            ),
+        }
+        scenario.injected += 1;
+    }
+}
+
+/// Pauses the simulation once the duration of the scenario elapsed.
+fn end(mut scenario: ResMut<ActiveScenario>, mut clock: ResMut<SimClock>) {
+    let (Some(elapsed), Some(duration)) = (scenario.elapsed(&clock), scenario.scenario.duration)
+    else {
+        return;
+    };
+    if !scenario.over && elapsed >= duration {
+        scenario.over = true;
+        clock.pause();
+        info!(target: subsystem::CONTROL, "Scenario over after {elapsed:.3} s");
+    }
+}
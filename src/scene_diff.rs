@@ -0,0 +1,502 @@
+//! Report of the changes of a glTF model reloaded while the application runs, so an iteration
+//! in Blender doesn't silently invalidate the physics set up from the model.
+//!
+//! When a loaded model is modified on disk, its nodes are compared with those of the previous
+//! version: the nodes added, removed or renamed (a node removed and another added at the same
+//! place under the same parent), the transforms that moved, and the links whose mass
+//! properties or joint changed. Joints to a link the model no longer has, and the settings
+//! naming a link that was in the previous version but isn't anymore, are reported as broken.
+//! The *Model changes* panel lists the report until dismissed, the broken items highlighted.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use bevy::{
+    gltf::{Gltf, GltfNode},
+    prelude::*,
+};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+
+use crate::{
+    accessibility_plugin::has_ui, actuators::ActuatorSettings, colliders::ColliderSettings,
+    disturbances::DisturbanceSettings, fixtures::FixtureSettings, friction::FrictionSettings,
+    input_bindings::InputBindings, joint_builder::LinkMetadata, logging::subsystem,
+    mass_overrides::MassOverrides, physics_parameters::PhysicsParameters,
+    pid_controller::PidSettings, sensors::SensorSettings,
+};
+
+/// Difference under which positions and rotations are the same.
+const TOLERANCE: f32 = 1e-5;
+
+/// A node of a model, as far as the physics is concerned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeSummary {
+    pub parent: Option<String>,
+    pub transform: Transform,
+    /// Description of the link, if the node is one.
+    pub link: Option<LinkMetadata>,
+}
+
+impl NodeSummary {
+    /// Whether another node is at the same place in the model, whatever its name.
+    fn same_place(&self, other: &NodeSummary) -> bool {
+        let anonymous = |link: &Option<LinkMetadata>| {
+            link.clone().map(|link| LinkMetadata { link: None, ..link })
+        };
+        self.parent == other.parent
+            && same_transform(&self.transform, &other.transform)
+            && anonymous(&self.link) == anonymous(&other.link)
+    }
+}
+
+fn same_transform(a: &Transform, b: &Transform) -> bool {
+    a.translation.abs_diff_eq(b.translation, TOLERANCE)
+        && a.rotation.abs_diff_eq(b.rotation, TOLERANCE)
+        && a.scale.abs_diff_eq(b.scale, TOLERANCE)
+}
+
+/// The nodes of a model, by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelSummary {
+    pub nodes: BTreeMap<String, NodeSummary>,
+}
+
+impl ModelSummary {
+    pub fn from_gltf(gltf: &Gltf, nodes: &Assets<GltfNode>) -> Self {
+        let nodes = gltf
+            .nodes
+            .iter()
+            .filter_map(|handle| nodes.get(handle))
+            .collect::<Vec<_>>();
+        let parents = nodes
+            .iter()
+            .flat_map(|node| {
+                node.children
+                    .iter()
+                    .map(move |child| (child.id(), node.name.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+        let summary = gltf
+            .nodes
+            .iter()
+            .zip(&nodes)
+            .map(|(handle, node)| {
+                let extras = node.extras.as_ref().map(|extras| extras.value.as_str());
+                let summary = NodeSummary {
+                    parent: parents.get(&handle.id()).cloned(),
+                    transform: node.transform,
+                    // Invalid extras are reported as the links are built.
+                    link: LinkMetadata::parse(&node.name, extras).ok().flatten(),
+                };
+                (node.name.clone(), summary)
+            })
+            .collect();
+        Self { nodes: summary }
+    }
+
+    /// The names of the links, with the names of their nodes.
+    pub fn links(&self) -> BTreeMap<&str, &str> {
+        self.nodes
+            .iter()
+            .filter_map(|(name, node)| Some((node.link.as_ref()?.name(), name.as_str())))
+            .collect()
+    }
+
+    /// The link a link is joined to: its `parent`, or else its closest ancestor link.
+    fn parent_link(&self, node: &NodeSummary) -> Option<String> {
+        if let Some(parent) = node.link.as_ref().and_then(|link| link.parent.clone()) {
+            return Some(parent);
+        }
+        let mut ancestor = node.parent.as_ref();
+        while let Some(name) = ancestor {
+            let node = self.nodes.get(name)?;
+            if let Some(link) = &node.link {
+                return Some(link.name().to_string());
+            }
+            ancestor = node.parent.as_ref();
+        }
+        None
+    }
+}
+
+/// A change of a model.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModelChange {
+    Added(String),
+    Removed(String),
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Moved {
+        node: String,
+        before: Transform,
+        after: Transform,
+    },
+    /// The mass, the center of mass or the inertia of a link changed.
+    Inertia {
+        link: String,
+        before: Option<f32>,
+        after: Option<f32>,
+    },
+    /// The body, the joint or the parent link of a link changed.
+    Joint(String),
+}
+
+impl fmt::Display for ModelChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelChange::Added(node) => write!(f, "Added {node}"),
+            ModelChange::Removed(node) => write!(f, "Removed {node}"),
+            ModelChange::Renamed { from, to } => write!(f, "Renamed {from} to {to}"),
+            ModelChange::Moved {
+                node,
+                before,
+                after,
+            } => write!(
+                f,
+                "Moved {node} by {:.3} m, turned by {:.1}°",
+                after.translation.distance(before.translation),
+                before.rotation.angle_between(after.rotation).to_degrees()
+            ),
+            ModelChange::Inertia {
+                link,
+                before,
+                after,
+            } if before != after => {
+                let mass = |mass: &Option<f32>| {
+                    mass.map_or("from the colliders".to_string(), |mass| {
+                        format!("{mass} kg")
+                    })
+                };
+                write!(f, "Mass of {link}: {} to {}", mass(before), mass(after))
+            }
+            ModelChange::Inertia { link, .. } => write!(f, "Inertia of {link} changed"),
+            ModelChange::Joint(link) => write!(f, "Joint of {link} changed"),
+        }
+    }
+}
+
+/// Something set up from the previous version of a model that the new one breaks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakage {
+    /// A link is joined to a link the model no longer has.
+    Joint { link: String, parent: String },
+    /// A setting names a link the model no longer has.
+    Binding { setting: String, link: String },
+}
+
+impl fmt::Display for Breakage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakage::Joint { link, parent } => {
+                write!(
+                    f,
+                    "{link} is joined to {parent}, which the model no longer has"
+                )
+            }
+            Breakage::Binding { setting, link } => {
+                write!(
+                    f,
+                    "The {setting} names {link}, which the model no longer has"
+                )
+            }
+        }
+    }
+}
+
+/// A setting naming a link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    /// What names the link, e.g. `position loop`.
+    pub setting: String,
+    pub link: String,
+}
+
+/// The changes from `before` to `after`.
+pub fn diff(before: &ModelSummary, after: &ModelSummary) -> Vec<ModelChange> {
+    let mut removed = before
+        .nodes
+        .keys()
+        .filter(|name| !after.nodes.contains_key(*name))
+        .collect::<Vec<_>>();
+    let mut changes = Vec::new();
+    for (name, node) in &after.nodes {
+        let Some(previous) = before.nodes.get(name) else {
+            let renamed = removed
+                .iter()
+                .position(|old| before.nodes[*old].same_place(node));
+            changes.push(match renamed {
+                Some(index) => ModelChange::Renamed {
+                    from: removed.remove(index).clone(),
+                    to: name.clone(),
+                },
+                None => ModelChange::Added(name.clone()),
+            });
+            continue;
+        };
+        if !same_transform(&previous.transform, &node.transform) {
+            changes.push(ModelChange::Moved {
+                node: name.clone(),
+                before: previous.transform,
+                after: node.transform,
+            });
+        }
+        let (Some(old), Some(new)) = (&previous.link, &node.link) else {
+            continue;
+        };
+        if (old.mass, old.center_of_mass, old.inertia)
+            != (new.mass, new.center_of_mass, new.inertia)
+        {
+            changes.push(ModelChange::Inertia {
+                link: new.name().to_string(),
+                before: old.mass,
+                after: new.mass,
+            });
+        }
+        if (old.body, old.joint, old.axis, old.limits, old.multibody)
+            != (new.body, new.joint, new.axis, new.limits, new.multibody)
+            || before.parent_link(previous) != after.parent_link(node)
+        {
+            changes.push(ModelChange::Joint(new.name().to_string()));
+        }
+    }
+    changes.extend(
+        removed
+            .into_iter()
+            .map(|name| ModelChange::Removed(name.clone())),
+    );
+    changes
+}
+
+/// The joints and the `bindings` that `after` breaks.
+pub fn breakages(
+    before: &ModelSummary,
+    after: &ModelSummary,
+    bindings: &[Binding],
+) -> Vec<Breakage> {
+    let links = after.links();
+    let previous_links = before.links();
+    let joints = after.nodes.values().filter_map(|node| {
+        let link = node.link.as_ref()?;
+        let parent = link.parent.as_ref()?;
+        (!links.contains_key(parent.as_str())).then(|| Breakage::Joint {
+            link: link.name().to_string(),
+            parent: parent.clone(),
+        })
+    });
+    let bindings = bindings.iter().filter_map(|binding| {
+        let link = binding.link.as_str();
+        (previous_links.contains_key(link) && !links.contains_key(link)).then(|| {
+            Breakage::Binding {
+                setting: binding.setting.clone(),
+                link: binding.link.clone(),
+            }
+        })
+    });
+    joints.chain(bindings).collect()
+}
+
+/// Reports the changes of the models reloaded while the application runs.
+pub struct SceneDiffPlugin;
+
+impl Plugin for SceneDiffPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ModelSummaries>()
+            .add_systems(Update, compare_reloaded_models)
+            .add_systems(
+                Update,
+                scene_diff_panel
+                    .run_if(has_ui)
+                    .run_if(resource_exists::<SceneDiffReport>),
+            );
+    }
+}
+
+/// The last version of each loaded model.
+#[derive(Default, Resource)]
+pub struct ModelSummaries(pub HashMap<AssetId<Gltf>, ModelSummary>);
+
+/// The changes of the last model reloaded, until dismissed.
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct SceneDiffReport {
+    /// Path of the model.
+    pub path: String,
+    pub changes: Vec<ModelChange>,
+    pub broken: Vec<Breakage>,
+}
+
+/// The settings naming links, by what they set.
+#[derive(bevy::ecs::system::SystemParam)]
+pub struct LinkBindings<'w> {
+    pid: Option<Res<'w, Persistent<PidSettings>>>,
+    disturbances: Option<Res<'w, Persistent<DisturbanceSettings>>>,
+    fixtures: Option<Res<'w, Persistent<FixtureSettings>>>,
+    sensors: Option<Res<'w, Persistent<SensorSettings>>>,
+    actuators: Option<Res<'w, Persistent<ActuatorSettings>>>,
+    friction: Option<Res<'w, Persistent<FrictionSettings>>>,
+    mass_overrides: Option<Res<'w, Persistent<MassOverrides>>>,
+    colliders: Option<Res<'w, Persistent<ColliderSettings>>>,
+    physics: Option<Res<'w, Persistent<PhysicsParameters>>>,
+    inputs: Option<Res<'w, Persistent<InputBindings>>>,
+}
+
+impl LinkBindings<'_> {
+    pub fn bindings(&self) -> Vec<Binding> {
+        let binding = |setting: &str, link: &str| Binding {
+            setting: setting.to_string(),
+            link: link.to_string(),
+        };
+        let mut bindings = Vec::new();
+        bindings.extend(
+            self.pid
+                .iter()
+                .map(|pid| binding("position loop", &pid.link)),
+        );
+        bindings.extend(
+            self.disturbances
+                .iter()
+                .map(|disturbances| binding("disturbances", &disturbances.rotor)),
+        );
+        bindings.extend(
+            self.fixtures
+                .iter()
+                .map(|fixtures| binding("fixtures", &fixtures.end_effector)),
+        );
+        if let Some(sensors) = &self.sensors {
+            bindings.extend(
+                sensors
+                    .encoders
+                    .iter()
+                    .map(|encoder| binding("encoder", &encoder.link)),
+            );
+            bindings.extend(sensors.imus.iter().map(|imu| binding("IMU", &imu.link)));
+        }
+        if let Some(actuators) = &self.actuators {
+            bindings.extend(
+                actuators
+                    .actuators
+                    .iter()
+                    .map(|actuator| binding("actuator", &actuator.link)),
+            );
+        }
+        if let Some(friction) = &self.friction {
+            bindings.extend(
+                friction
+                    .joints
+                    .iter()
+                    .map(|joint| binding("friction", &joint.link)),
+            );
+        }
+        if let Some(mass_overrides) = &self.mass_overrides {
+            bindings.extend(
+                mass_overrides
+                    .overrides
+                    .iter()
+                    .map(|entry| binding("mass override", &entry.link)),
+            );
+        }
+        if let Some(colliders) = &self.colliders {
+            bindings.extend(
+                colliders
+                    .links
+                    .iter()
+                    .map(|link| binding("collider", &link.link)),
+            );
+        }
+        if let Some(physics) = &self.physics {
+            bindings.extend(
+                physics
+                    .bodies
+                    .iter()
+                    .map(|body| binding("physical parameters", &body.link)),
+            );
+        }
+        if let Some(inputs) = &self.inputs {
+            bindings.extend(
+                inputs
+                    .torques
+                    .iter()
+                    .map(|torque| binding("gamepad torque", &torque.link)),
+            );
+        }
+        bindings
+    }
+}
+
+/// Compares the models modified on disk with their previous version.
+fn compare_reloaded_models(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Gltf>>,
+    gltfs: Res<Assets<Gltf>>,
+    nodes: Res<Assets<GltfNode>>,
+    asset_server: Res<AssetServer>,
+    mut summaries: ResMut<ModelSummaries>,
+    bindings: LinkBindings,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = *event
+        else {
+            continue;
+        };
+        let Some(gltf) = gltfs.get(id) else {
+            continue;
+        };
+        let summary = ModelSummary::from_gltf(gltf, &nodes);
+        let Some(before) = summaries.0.insert(id, summary.clone()) else {
+            continue;
+        };
+        let changes = diff(&before, &summary);
+        let broken = breakages(&before, &summary, &bindings.bindings());
+        let path = asset_server
+            .get_path(id)
+            .map(|path| path.to_string())
+            .unwrap_or_default();
+        info!(
+            target: subsystem::IO,
+            "{path} reloaded: {} changes, {} broken",
+            changes.len(),
+            broken.len()
+        );
+        for breakage in &broken {
+            warn!(target: subsystem::PHYSICS, "{path}: {breakage}");
+        }
+        if !changes.is_empty() || !broken.is_empty() {
+            commands.insert_resource(SceneDiffReport {
+                path,
+                changes,
+                broken,
+            });
+        }
+    }
+}
+
+/// Panel listing the changes of the last model reloaded.
+fn scene_diff_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    report: Res<SceneDiffReport>,
+) {
+    egui::Window::new("Model changes").show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("{} was reloaded:", report.path));
+        for breakage in &report.broken {
+            ui.colored_label(ui.visuals().error_fg_color, breakage.to_string());
+        }
+        if !report.broken.is_empty() {
+            ui.separator();
+        }
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for change in &report.changes {
+                    ui.label(change.to_string());
+                }
+            });
+        ui.separator();
+        if ui.button("Dismiss").clicked() {
+            commands.remove_resource::<SceneDiffReport>();
+        }
+    });
+}
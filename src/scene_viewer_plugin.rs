@@ -11,6 +11,13 @@ use bevy_rapier3d::prelude::{
     AdditionalMassProperties, Collider, ComputedColliderShape, GravityScale, RigidBody,
 };
 
+use crate::{
+    error::{Error, ErrorEvent},
+    joint_builder::JointBuilderSet,
+    logging::subsystem,
+    plants::Link,
+};
+
 use std::f32::consts::*;
 use std::fmt;
 
@@ -80,8 +87,8 @@ impl Plugin for SceneViewerPlugin {
                     toggle_bounding_boxes.run_if(input_just_pressed(KeyCode::B)),
                 ),
             )
-            .add_systems(PostUpdate, add_colliders)
-            .add_systems(PostUpdate, add_rigid_bodies);
+            .add_systems(PostUpdate, add_colliders.after(JointBuilderSet))
+            .add_systems(PostUpdate, add_rigid_bodies.after(JointBuilderSet));
     }
 }
 
@@ -90,6 +97,7 @@ fn toggle_bounding_boxes(mut config: ResMut<GizmoConfig>) {
 }
 
 fn scene_load_check(
+    mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut scenes: ResMut<Assets<Scene>>,
     gltf_assets: Res<Assets<Gltf>>,
@@ -98,27 +106,44 @@ fn scene_load_check(
 ) {
     match scene_handle.instance_id {
         None => {
+            if let LoadState::Failed(error) = asset_server.load_state(&scene_handle.gltf_handle) {
+                commands.send_event(ErrorEvent::from(Error::Asset {
+                    path: scene_handle
+                        .gltf_handle
+                        .path()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                    message: error.to_string(),
+                }));
+                // Don't report it again.
+                scene_handle.is_loaded = true;
+                return;
+            }
             if asset_server.load_state(&scene_handle.gltf_handle) == LoadState::Loaded {
-                let gltf = gltf_assets.get(&scene_handle.gltf_handle).unwrap();
+                let Some(gltf) = gltf_assets.get(&scene_handle.gltf_handle) else {
+                    return;
+                };
                 if gltf.scenes.len() > 1 {
                     info!(
+                        target: subsystem::IO,
                         "Displaying scene {} out of {}",
                         scene_handle.scene_index,
                         gltf.scenes.len()
                     );
-                    info!("You can select the scene by adding '#Scene' followed by a number to the end of the file path (e.g '#Scene1' to load the second scene).");
+                    info!(target: subsystem::IO, "You can select the scene by adding '#Scene' followed by a number to the end of the file path (e.g '#Scene1' to load the second scene).");
                 }
 
-                let gltf_scene_handle =
-                    gltf.scenes
-                        .get(scene_handle.scene_index)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "glTF file doesn't contain scene {}!",
-                                scene_handle.scene_index
-                            )
-                        });
-                let scene = scenes.get_mut(gltf_scene_handle).unwrap();
+                let Some(gltf_scene_handle) = gltf.scenes.get(scene_handle.scene_index) else {
+                    commands.send_event(ErrorEvent::from(Error::Model(format!(
+                        "the glTF file doesn't contain scene {}",
+                        scene_handle.scene_index
+                    ))));
+                    scene_handle.is_loaded = true;
+                    return;
+                };
+                let Some(scene) = scenes.get_mut(gltf_scene_handle) else {
+                    return;
+                };
 
                 let mut query = scene
                     .world
@@ -133,12 +158,12 @@ fn scene_load_check(
                 scene_handle.instance_id =
                     Some(scene_spawner.spawn(gltf_scene_handle.clone_weak()));
 
-                info!("Spawning scene...");
+                info!(target: subsystem::IO, "Spawning scene...");
             }
         }
         Some(instance_id) if !scene_handle.is_loaded => {
             if scene_spawner.instance_is_ready(instance_id) {
-                info!("...done!");
+                info!(target: subsystem::IO, "...done!");
                 scene_handle.is_loaded = true;
             }
         }
@@ -178,25 +203,35 @@ fn add_colliders(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut query: Query<(Entity, &Handle<Mesh>, &Handle<StandardMaterial>)>,
+    parents: Query<&Parent>,
+    links: Query<(), With<Link>>,
 ) {
     if scene_handle.has_colliders {
         return;
     }
 
     for (entity, mesh_handle, material_handle) in &mut query {
+        // The colliders of the meshes of the links are generated in the shapes chosen for them.
+        if parents
+            .iter_ancestors(entity)
+            .any(|ancestor| links.contains(ancestor))
+        {
+            scene_handle.has_colliders = true;
+            continue;
+        }
         let mesh = meshes.get_mut(mesh_handle).unwrap();
         let _material = materials.get_mut(material_handle).unwrap();
         let collider = Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh);
         if let Some(collider) = collider {
             commands.entity(entity).insert(collider);
-            info!("Added collider to entity {:?}", entity);
+            info!(target: subsystem::PHYSICS, "Added collider to entity {:?}", entity);
             scene_handle.has_colliders = true;
         } else {
-            warn!("Failed to create collider for entity {:?}", entity);
+            warn!(target: subsystem::PHYSICS, "Failed to create collider for entity {:?}", entity);
         }
     }
     if scene_handle.has_colliders {
-        info!("Added colliders to scene");
+        info!(target: subsystem::PHYSICS, "Added colliders to scene");
     }
 }
 
@@ -205,12 +240,22 @@ fn add_rigid_bodies(
     mut scene_handle: ResMut<SceneHandle>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut query: Query<(Entity, &Handle<Mesh>)>,
+    parents: Query<&Parent>,
+    links: Query<(), With<Link>>,
 ) {
     if scene_handle.has_rigid_bodies {
         return;
     }
 
     for (entity, mesh_handle) in &mut query {
+        // The meshes of the links move with their bodies, built from the joints of the model.
+        if parents
+            .iter_ancestors(entity)
+            .any(|ancestor| links.contains(ancestor))
+        {
+            scene_handle.has_rigid_bodies = true;
+            continue;
+        }
         let mesh = meshes.get_mut(mesh_handle);
         if let Some(_mesh) = mesh {
             commands
@@ -218,14 +263,14 @@ fn add_rigid_bodies(
                 .insert(RigidBody::Dynamic)
                 .insert(AdditionalMassProperties::Mass(1.0))
                 .insert(GravityScale(1.0));
-            info!("Added rigid body to entity {:?}", entity);
+            info!(target: subsystem::PHYSICS, "Added rigid body to entity {:?}", entity);
             scene_handle.has_rigid_bodies = true;
         } else {
-            warn!("Failed to create rigid body for entity {:?}", entity);
+            warn!(target: subsystem::PHYSICS, "Failed to create rigid body for entity {:?}", entity);
         }
     }
     if scene_handle.has_rigid_bodies {
-        info!("Added rigid bodies to scene");
+        info!(target: subsystem::PHYSICS, "Added rigid bodies to scene");
     }
 }
 
@@ -0,0 +1,362 @@
+//! Open-loop tests of controllers: a controller is fed the measurements of a recorded run, step
+//! by step, without simulating the plant, and its outputs are checked against expected values
+//! within tolerances, e.g. to iterate on a controller in seconds instead of full runs.
+//!
+//! A test case names the recorded trace, the controller under test, the channels of the trace
+//! it measures, and the expectations on its outputs: samples, or a channel of the trace such as
+//! the output the controller recorded on the rig. The controllers are stepped at the samples of
+//! their first measured channel, the other channels interpolated in between. Any controller
+//! implementing [`Controller`] can be tested: the position loop and the regulator of the
+//! pendulum are built in.
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::Entity;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    control::{Lqr, PidGains},
+    error::{Error, Result},
+    lqr::{LqrController, LqrSettings},
+    pid_controller::{PidController, PidSettings},
+    run_diff::{self, interpolate},
+    telemetry::{Sample, Telemetry},
+};
+
+/// A controller stepped open-loop on recorded measurements.
+pub trait Controller {
+    /// Names of its outputs, in the order [`Controller::step`] returns them.
+    fn outputs(&self) -> Vec<String>;
+
+    /// Outputs for the next `dt` seconds from the `measurements` at `time`, in the order of
+    /// the measured signals of the test, or `None` when it doesn't command anything.
+    fn step(&mut self, time: f32, dt: f32, measurements: &[f32]) -> Result<Option<Vec<f32>>>;
+}
+
+/// A signal fed to a controller: a channel of the trace, or a constant.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Signal {
+    Constant(f32),
+    Channel(String),
+}
+
+/// The controller under test, and the channels of the trace it measures.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ControllerUnderTest {
+    /// Position loop of a joint, commanding its velocity as the `output`.
+    Pid {
+        gains: PidGains,
+        /// Largest velocity commanded, in rad/s, or 0 for an unlimited one.
+        #[serde(default)]
+        limit: f32,
+        /// Angle of the joint, in rad.
+        angle: String,
+        /// Position aimed at, in rad.
+        setpoint: Signal,
+    },
+    /// Regulator of the pendulum, commanding the `acceleration` and the `velocity` of the arm
+    /// while the pendulum is caught.
+    Lqr {
+        #[serde(default)]
+        settings: LqrSettings,
+        /// Angles of the motor and of the pendulum joints, in rad.
+        motor: String,
+        pendulum: String,
+    },
+}
+
+impl ControllerUnderTest {
+    /// The measured signals, in the order the controller reads them.
+    pub fn measurements(&self) -> Vec<Signal> {
+        match self {
+            Self::Pid {
+                angle, setpoint, ..
+            } => vec![Signal::Channel(angle.clone()), setpoint.clone()],
+            Self::Lqr {
+                motor, pendulum, ..
+            } => vec![
+                Signal::Channel(motor.clone()),
+                Signal::Channel(pendulum.clone()),
+            ],
+        }
+    }
+
+    /// The controller, stepping by about `dt` seconds.
+    pub fn build(&self, dt: f32) -> Result<Box<dyn Controller>> {
+        let controller: Box<dyn Controller> = match self {
+            Self::Pid { gains, limit, .. } => {
+                let settings = PidSettings {
+                    gains: *gains,
+                    limit: *limit,
+                    ..PidSettings::default()
+                };
+                Box::new(PidController::new(settings.pid(), ""))
+            }
+            Self::Lqr { settings, .. } => Box::new(LqrUnderTest {
+                lqr: settings.solve(settings.sample_period(dt))?,
+                settings: settings.clone(),
+                controller: LqrController::new(Entity::PLACEHOLDER, ""),
+            }),
+        };
+        Ok(controller)
+    }
+}
+
+impl Controller for PidController {
+    fn outputs(&self) -> Vec<String> {
+        vec!["output".to_string()]
+    }
+
+    fn step(&mut self, _time: f32, dt: f32, measurements: &[f32]) -> Result<Option<Vec<f32>>> {
+        let [angle, target] = measurements else {
+            return Ok(None);
+        };
+        Ok(Some(vec![self.update(*target, *angle, dt)]))
+    }
+}
+
+struct LqrUnderTest {
+    lqr: Lqr,
+    settings: LqrSettings,
+    controller: LqrController,
+}
+
+impl Controller for LqrUnderTest {
+    fn outputs(&self) -> Vec<String> {
+        vec!["acceleration".to_string(), "velocity".to_string()]
+    }
+
+    fn step(&mut self, _time: f32, dt: f32, measurements: &[f32]) -> Result<Option<Vec<f32>>> {
+        let [motor, pendulum] = measurements else {
+            return Ok(None);
+        };
+        let command = self
+            .controller
+            .update(&self.lqr, &self.settings, [*motor, *pendulum], dt);
+        Ok(command.map(Vec::from))
+    }
+}
+
+/// Values an output of the controller is expected to take.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Expectation {
+    /// Name of the output.
+    pub output: String,
+    /// Channel of the trace holding the expected values, instead of the `samples`.
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Expected `[time, value]` samples.
+    #[serde(default)]
+    pub samples: Vec<Sample>,
+    /// Largest deviation allowed.
+    pub tolerance: f32,
+}
+
+/// A test case of a controller against a recorded trace.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScriptTest {
+    /// Recorded run, relative to the case file: a session directory or a telemetry file.
+    pub trace: PathBuf,
+    pub controller: ControllerUnderTest,
+    /// Window of the trace the controller is stepped over, in s.
+    #[serde(default)]
+    pub start: Option<f32>,
+    #[serde(default)]
+    pub end: Option<f32>,
+    pub expectations: Vec<Expectation>,
+}
+
+impl ScriptTest {
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        let mut test: Self =
+            serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))?;
+        if let Some(directory) = path.parent() {
+            test.trace = directory.join(&test.trace);
+        }
+        Ok(test)
+    }
+
+    /// Reads the trace and runs the test on it.
+    pub fn run(&self) -> Result<Report> {
+        let trace = run_diff::read_run(&self.trace)?;
+        self.run_on(&trace)
+    }
+
+    /// Steps the controller on the measurements of `trace` and checks its outputs.
+    pub fn run_on(&self, trace: &Telemetry) -> Result<Report> {
+        let invalid = |message: String| Error::Config {
+            name: "script_test".to_string(),
+            message,
+        };
+        let measurements = self.controller.measurements();
+        let channel = |name: &String| {
+            trace
+                .channels
+                .get(name)
+                .filter(|samples| !samples.is_empty())
+                .ok_or_else(|| invalid(format!("no `{name}` channel in the trace")))
+        };
+        let signals = measurements
+            .iter()
+            .map(|signal| match signal {
+                Signal::Channel(name) => channel(name).map(Some),
+                Signal::Constant(_) => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let steps: Vec<f32> = signals
+            .iter()
+            .flatten()
+            .next()
+            .ok_or_else(|| invalid("the controller measures no channel".to_string()))?
+            .iter()
+            .map(|[time, _]| *time)
+            .filter(|time| {
+                self.start.is_none_or(|start| *time >= start)
+                    && self.end.is_none_or(|end| *time <= end)
+            })
+            .collect();
+        let [first, second, ..] = steps[..] else {
+            return Err(invalid("fewer than two steps in the trace".to_string()));
+        };
+
+        let mut controller = self.controller.build(second - first)?;
+        let names = controller.outputs();
+        let mut outputs = Telemetry::default();
+        let mut previous = first - (second - first);
+        for time in steps {
+            let values: Option<Vec<f32>> = measurements
+                .iter()
+                .zip(&signals)
+                .map(|(signal, samples)| match (signal, samples) {
+                    (Signal::Constant(value), _) => Some(*value),
+                    (_, Some(samples)) => interpolate(samples, time),
+                    (_, None) => None,
+                })
+                .collect();
+            let dt = time - previous;
+            previous = time;
+            let (Some(values), true) = (values, dt > 0.0) else {
+                continue;
+            };
+            let Some(commands) = controller.step(time, dt, &values)? else {
+                continue;
+            };
+            for (name, value) in names.iter().zip(commands) {
+                outputs.record(name, time, value);
+            }
+        }
+
+        let checks = self
+            .expectations
+            .iter()
+            .map(|expectation| {
+                if !names.contains(&expectation.output) {
+                    return Err(invalid(format!("no `{}` output", expectation.output)));
+                }
+                let expected = match &expectation.channel {
+                    Some(name) => channel(name)?.as_slice(),
+                    None => expectation.samples.as_slice(),
+                };
+                Ok(Check::of(
+                    expectation,
+                    outputs
+                        .channels
+                        .get(&expectation.output)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                    expected,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Report { outputs, checks })
+    }
+}
+
+/// Deviation of an output from its expected values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub output: String,
+    pub tolerance: f32,
+    /// Number of expected samples compared.
+    pub samples: usize,
+    /// Largest deviation, its time, and the time of the first deviation beyond the tolerance.
+    pub max: f32,
+    pub max_time: f32,
+    pub first_failure: Option<f32>,
+    /// Expected samples the output doesn't cover.
+    pub missing: usize,
+}
+
+impl Check {
+    fn of(expectation: &Expectation, output: &[Sample], expected: &[Sample]) -> Self {
+        let mut check = Self {
+            output: expectation.output.clone(),
+            tolerance: expectation.tolerance,
+            samples: 0,
+            max: 0.0,
+            max_time: 0.0,
+            first_failure: None,
+            missing: 0,
+        };
+        for [time, value] in expected {
+            let Some(actual) = interpolate(output, *time) else {
+                check.missing += 1;
+                continue;
+            };
+            check.samples += 1;
+            let deviation = (actual - value).abs();
+            if deviation > check.max || deviation.is_nan() {
+                check.max = deviation;
+                check.max_time = *time;
+            }
+            if (deviation.is_nan() || deviation > check.tolerance) && check.first_failure.is_none()
+            {
+                check.first_failure = Some(*time);
+            }
+        }
+        check
+    }
+
+    pub fn passed(&self) -> bool {
+        self.first_failure.is_none() && self.missing == 0 && self.samples > 0
+    }
+}
+
+/// Outputs of a controller over a trace, and their checks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub outputs: Telemetry,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(Check::passed)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            let verdict = if check.passed() { "ok" } else { "FAILED" };
+            write!(
+                f,
+                "  {}: {verdict}, {} samples, largest deviation {:.4} at {:.3} s (tolerance {})",
+                check.output, check.samples, check.max, check.max_time, check.tolerance
+            )?;
+            if let Some(time) = check.first_failure {
+                write!(f, ", first beyond at {time:.3} s")?;
+            }
+            if check.missing > 0 {
+                write!(f, ", {} samples not covered", check.missing)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,587 @@
+//! This module runs controllers written as [Rhai](https://rhai.rs) scripts, so a control law can
+//! be tried without recompiling the playground.
+//!
+//! The script defines a `control(state, dt)` function, called after each physics step with the
+//! state of the attached joints of a plant, `[angle, velocity]` of each in order, in rad and
+//! rad/s, and the step in s. It returns the torques of the joints for the next step, in N·m, as
+//! an array, or a number with a single joint. The motors of the joints are released, and the
+//! torques applied as impulses on the two links of each joint, like the friction; they are
+//! recorded as the `script/<link>` telemetry channels. Within `control`, `this` is a map kept
+//! from one call to the next, for integrators and filters; it starts empty for each plant.
+//!
+//! The script is read from the configuration directory, and reloaded whenever the file changes,
+//! so saving it in an editor swaps the control law while the plant runs. A script that doesn't
+//! compile is reported and the previous one keeps running; a script that fails while running is
+//! reported once, and the joints coast until it's reloaded. The script and the attached links are
+//! configured in `scripted_controller.json`.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST, FLOAT};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin, control,
+    error::{Error, ErrorEvent},
+    estimation::{self, EstimationSet, JointEstimate},
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link},
+    sensors::{ControlSet, Encoder, SensorSet},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the torques, followed by the name of the link.
+pub const SCRIPT_PREFIX: &str = "script/";
+/// Function of the script called after each physics step.
+pub const CONTROL_FN: &str = "control";
+/// Largest number of operations of a call, so a script stuck in a loop fails instead of hanging
+/// the simulation.
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// Script written by the panel when there's none yet: a PD loop holding the first joint at 0.
+pub const TEMPLATE: &str = r#"// Called after each physics step with the state of the attached joints,
+// [angle, velocity] of each in order, in rad and rad/s, and the step in s.
+// Returns the torques of the joints for the next step, in N·m.
+// `this` is a map kept between the calls, e.g. for an integrator.
+fn control(state, dt) {
+    let kp = 2.0;
+    let kd = 0.2;
+    [-kp * state[0] - kd * state[1]]
+}
+"#;
+
+pub struct ScriptedControllerPlugin;
+
+impl Plugin for ScriptedControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControllerScript>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    configure_controllers.run_if(
+                        resource_exists_and_changed::<Persistent<ScriptedControllerSettings>>,
+                    ),
+                    reload_script.run_if(resource_exists::<Persistent<ScriptedControllerSettings>>),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .run_if(resource_exists::<Persistent<ScriptedControllerSettings>>)
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                scripted_controller_panel
+                    .run_if(resource_exists::<Persistent<ScriptedControllerSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the scripted controllers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ScriptedControllerSettings {
+    pub enabled: bool,
+    /// Path of the script, relative to the configuration directory.
+    pub script: String,
+    /// Names of the links whose joints are controlled, in each plant, in the order of the state
+    /// and of the torques.
+    pub links: Vec<String>,
+    /// Largest torque applied, in N·m, or 0 for an unlimited one.
+    pub limit: f32,
+}
+
+impl Default for ScriptedControllerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            script: "controller.rhai".to_string(),
+            links: vec!["motor".to_string()],
+            limit: 1.0,
+        }
+    }
+}
+
+impl ScriptedControllerSettings {
+    /// Path of the script.
+    pub fn path(&self) -> PathBuf {
+        config_plugin::config_dir().join(&self.script)
+    }
+
+    /// The torque applied for the one returned by the script.
+    pub fn saturate(&self, torque: f32) -> f32 {
+        if self.limit > 0.0 {
+            torque.clamp(-self.limit, self.limit)
+        } else {
+            torque
+        }
+    }
+}
+
+/// The compiled script, shared by the controllers of all the plants.
+#[derive(Resource)]
+pub struct ControllerScript {
+    engine: Engine,
+    ast: Option<AST>,
+    /// Path and modification time of the file last read.
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Whether the script failed since it was compiled, so its error is reported once.
+    failed: bool,
+}
+
+impl Default for ControllerScript {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: subsystem::CONTROL, "{text}"));
+        engine.on_debug(|text, _, _| debug!(target: subsystem::CONTROL, "{text}"));
+        Self {
+            engine,
+            ast: None,
+            path: PathBuf::new(),
+            modified: None,
+            failed: false,
+        }
+    }
+}
+
+impl ControllerScript {
+    /// Whether a script is compiled and hasn't failed.
+    pub fn is_running(&self) -> bool {
+        self.ast.is_some() && !self.failed
+    }
+
+    /// Compiles `source`, replacing the script if it defines the control function.
+    pub fn compile(&mut self, source: &str) -> Result<(), String> {
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|error| error.to_string())?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == CONTROL_FN && function.params.len() == 2)
+        {
+            return Err(format!("no `{CONTROL_FN}(state, dt)` function"));
+        }
+        self.ast = Some(ast);
+        self.failed = false;
+        Ok(())
+    }
+
+    /// Compiles the script at `path` if it's another file or it was modified since it was last
+    /// read: returns whether it was compiled.
+    pub fn reload(&mut self, path: &Path) -> Result<bool, Error> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if path == self.path && modified == self.modified {
+            return Ok(false);
+        }
+        if path != self.path {
+            self.ast = None;
+            self.path = path.to_path_buf();
+        }
+        self.modified = modified;
+        let source = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        self.compile(&source).map_err(|message| Error::Script {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        Ok(true)
+    }
+
+    /// Calls the control function with the `state` of the joints and the step `dt`, and `memory`
+    /// as `this`: returns the torques.
+    pub fn call(&self, memory: &mut Dynamic, state: &[f32], dt: f32) -> Result<Vec<f32>, String> {
+        let Some(ast) = &self.ast else {
+            return Err("no script compiled".to_string());
+        };
+        let state: Array = state
+            .iter()
+            .map(|value| Dynamic::from_float(FLOAT::from(*value)))
+            .collect();
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(memory);
+        let output: Dynamic = self
+            .engine
+            .call_fn_with_options(
+                options,
+                &mut Scope::new(),
+                ast,
+                CONTROL_FN,
+                (state, FLOAT::from(dt)),
+            )
+            .map_err(|error| error.to_string())?;
+        let type_name = output.type_name();
+        torques(output)
+            .ok_or_else(|| format!("`{CONTROL_FN}` returned {type_name} instead of torques"))
+    }
+}
+
+/// The torques of the output of a script, an array of numbers or a number.
+fn torques(output: Dynamic) -> Option<Vec<f32>> {
+    let number = |value: &Dynamic| {
+        value
+            .as_float()
+            .map(|value| value as f32)
+            .or_else(|_| value.as_int().map(|value| value as f32))
+            .ok()
+    };
+    if output.is_array() {
+        output.into_array().ok()?.iter().map(number).collect()
+    } else {
+        number(&output).map(|torque| vec![torque])
+    }
+}
+
+/// A joint driven by a script.
+#[derive(Clone, Debug)]
+pub struct ScriptedJoint {
+    pub entity: Entity,
+    pub link: String,
+    /// Telemetry channel of the torque.
+    pub channel: String,
+    /// Last angle within a turn, and the angle over the turns.
+    turns: Option<(f32, f32)>,
+}
+
+impl ScriptedJoint {
+    pub fn new(entity: Entity, link: &Link) -> Self {
+        Self {
+            entity,
+            link: link.name.clone(),
+            channel: plants::namespaced(&link.plant, &format!("{SCRIPT_PREFIX}{}", link.name)),
+            turns: None,
+        }
+    }
+
+    /// Angle over the turns and velocity of the joint, from its angle within a turn measured
+    /// `dt` seconds after the last one.
+    pub fn measure(&mut self, angle: f32, dt: f32) -> [f32; 2] {
+        let previous = self.turns.map(|(_, total)| total);
+        let position = control::unwrap_angle(&mut self.turns, angle);
+        let velocity = previous.map_or(0.0, |previous| (position - previous) / dt);
+        [position, velocity]
+    }
+}
+
+/// The script controller of a plant, on the joint of its first attached link.
+#[derive(Component, Debug)]
+pub struct ScriptedController {
+    pub joints: Vec<ScriptedJoint>,
+    /// `this` of the script.
+    pub memory: Dynamic,
+}
+
+impl ScriptedController {
+    pub fn new(joints: Vec<ScriptedJoint>) -> Self {
+        Self {
+            joints,
+            memory: Dynamic::from_map(Map::new()),
+        }
+    }
+
+    /// Whether it drives the joints of `links`, in this order.
+    fn drives(&self, links: &[String]) -> bool {
+        self.joints.iter().map(|joint| &joint.link).eq(links)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<ScriptedControllerSettings>("scripted_controller", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the controllers, on the joints of the configured links of each plant.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<ScriptedControllerSettings>>,
+    joints: Query<(Entity, &Link, &ImpulseJoint, Option<&ScriptedController>)>,
+) {
+    for (entity, link, _, controller) in &joints {
+        let attached = settings.enabled && settings.links.first() == Some(&link.name);
+        let found = attached.then(|| {
+            settings
+                .links
+                .iter()
+                .map(|name| {
+                    joints
+                        .iter()
+                        .find(|(_, other, _, _)| other.plant == link.plant && other.name == *name)
+                })
+                .collect::<Option<Vec<_>>>()
+        });
+        match (controller, found.flatten()) {
+            (Some(controller), Some(_)) if controller.drives(&settings.links) => {}
+            (_, Some(found)) => {
+                for (joint_entity, _, joint, _) in &found {
+                    // The parent takes the reaction.
+                    for body in [*joint_entity, joint.parent] {
+                        commands
+                            .entity(body)
+                            .insert_if_new(ExternalImpulse::default());
+                    }
+                }
+                let joints = found
+                    .into_iter()
+                    .map(|(entity, link, _, _)| ScriptedJoint::new(entity, link))
+                    .collect();
+                commands
+                    .entity(entity)
+                    .insert(ScriptedController::new(joints));
+                info!(target: subsystem::CONTROL, "Script controls {}", link.path());
+            }
+            (Some(_), None) => {
+                commands.entity(entity).remove::<ScriptedController>();
+                info!(target: subsystem::CONTROL, "Script released {}", link.path());
+            }
+            (None, None) if attached => {
+                warn!(
+                    target: subsystem::CONTROL,
+                    "Links {:?} not all found in {}", settings.links, link.plant
+                );
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Compiles the script whenever its file changes, starting the controllers over.
+fn reload_script(
+    mut commands: Commands,
+    settings: Res<Persistent<ScriptedControllerSettings>>,
+    mut script: ResMut<ControllerScript>,
+    mut controllers: Query<&mut ScriptedController>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let path = settings.path();
+    match script.reload(&path) {
+        Ok(false) => {}
+        Ok(true) => {
+            info!(target: subsystem::CONTROL, "Loaded the controller script {}", path.display());
+            for mut controller in &mut controllers {
+                controller.memory = Dynamic::from_map(Map::new());
+            }
+        }
+        Err(error) => {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+/// Measures the joints after each physics step and applies the torques of the script for the
+/// next one.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<ScriptedControllerSettings>>,
+    mut script: ResMut<ControllerScript>,
+    mut controllers: Query<&mut ScriptedController>,
+    mut joints: Query<(&mut ImpulseJoint, &Transform)>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_scripted_controllers").entered();
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt <= 0.0 || !script.is_running() {
+        return;
+    }
+    for mut controller in &mut controllers {
+        let angles = controller
+            .joints
+            .iter()
+            .map(|joint| {
+                estimation::joint_angle(context, &encoders, &latencies, &estimates, joint.entity)
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(angles) = angles else {
+            continue;
+        };
+        let state: Vec<f32> = controller
+            .joints
+            .iter_mut()
+            .zip(angles)
+            .flat_map(|(joint, angle)| joint.measure(angle, dt))
+            .collect();
+        let controller = &mut *controller;
+        let torques = script
+            .call(&mut controller.memory, &state, dt)
+            .and_then(|torques| {
+                if torques.len() == controller.joints.len() {
+                    Ok(torques)
+                } else {
+                    Err(format!(
+                        "`{CONTROL_FN}` returned {} torques for {} joints",
+                        torques.len(),
+                        controller.joints.len()
+                    ))
+                }
+            });
+        let torques = match torques {
+            Ok(torques) => torques,
+            Err(message) => {
+                script.failed = true;
+                commands.send_event(ErrorEvent::from(Error::Script {
+                    path: script.path.clone(),
+                    message,
+                }));
+                return;
+            }
+        };
+        for (joint, torque) in controller.joints.iter().zip(torques) {
+            let torque = settings.saturate(torque);
+            let Ok((mut impulse_joint, transform)) = joints.get_mut(joint.entity) else {
+                continue;
+            };
+            impulse_joint
+                .data
+                .as_mut()
+                .set_motor_velocity(JointAxis::AngX, 0.0, 0.0);
+            let axis = (transform.rotation * impulse_joint.data.as_ref().local_axis2())
+                .normalize_or_zero();
+            let torque_impulse = torque * axis * dt;
+            if let Ok(mut impulse) = impulses.get_mut(joint.entity) {
+                impulse.torque_impulse += torque_impulse;
+            }
+            if let Ok(mut impulse) = impulses.get_mut(impulse_joint.parent) {
+                impulse.torque_impulse -= torque_impulse;
+            }
+            if let Some(telemetry) = telemetry.as_mut() {
+                telemetry.record(&joint.channel, clock.elapsed_secs(), torque);
+            }
+        }
+    }
+}
+
+/// Panel to pick the script and attach it to the joints.
+fn scripted_controller_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<ScriptedControllerSettings>>,
+    script: Res<ControllerScript>,
+    controllers: Query<&ScriptedController>,
+    links: Query<&Link, With<ImpulseJoint>>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Scripted controller")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Run the script");
+            ui.horizontal(|ui| {
+                ui.label("Script:");
+                ui.text_edit_singleline(&mut edited.script);
+            });
+            let path = edited.path();
+            if path.exists() {
+                let status = match (script.path == path, script.is_running()) {
+                    (true, true) => "Compiled, reloaded on save",
+                    (true, false) if script.failed => "Failed, fix and save to reload",
+                    _ => "Not compiled",
+                };
+                ui.label(status);
+            } else if ui.button("Create from the template").clicked() {
+                let written = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::write(&path, TEMPLATE));
+                if let Err(error) = written {
+                    commands.send_event(ErrorEvent::from(Error::io(&path, error)));
+                }
+            }
+            ui.label(format!("In {}", path.display()));
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=100.0)
+                    .speed(0.01)
+                    .prefix("Torque limit: ")
+                    .suffix(" N·m"),
+            );
+
+            ui.separator();
+            ui.label("Joints, in the order of the state:");
+            let mut names: Vec<&str> = links.iter().map(|link| link.name.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                let position = edited.links.iter().position(|link| link == name);
+                let mut attached = position.is_some();
+                let label = match position {
+                    Some(index) => format!("{name} (#{index})"),
+                    None => name.to_string(),
+                };
+                if ui.checkbox(&mut attached, label).changed() {
+                    match position {
+                        Some(index) => {
+                            edited.links.remove(index);
+                        }
+                        None => edited.links.push(name.to_string()),
+                    }
+                }
+            }
+
+            ui.separator();
+            for controller in &controllers {
+                for joint in &controller.joints {
+                    let torque = telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(&joint.channel))
+                        .unwrap_or_default();
+                    ui.label(format!("{}: {torque:.3} N·m", joint.channel));
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save(
+                            "scripted_controller",
+                            error,
+                        )));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save(
+                            "scripted_controller",
+                            error,
+                        )));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
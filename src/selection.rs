@@ -0,0 +1,512 @@
+//! This module lets the user click a link of the plants to inspect it and handle it, a focused
+//! view of the properties the world inspector spreads over raw components.
+//!
+//! A click on a mesh, without dragging the camera, selects the link it belongs to, outlined by
+//! the bounds of its meshes; a click on nothing clears the selection. The *Selection* panel shows
+//! the state of the rigid body of the link, the joint it hangs from, its colliders and the
+//! controllers on its joint. It can poke the link at the clicked point, pushing it away from the
+//! camera, and jog its joint with a torque, or a force for a prismatic joint, while a button is
+//! held. Shift-clicks are left to the disturbances, and clicks to the drag of the *Interaction*
+//! panel and to the picking of the *Joint authoring* panel while they're enabled.
+use bevy::{prelude::*, render::primitives::Aabb, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::scripted_controller::ScriptedController;
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    interaction::InteractionSettings,
+    joint_authoring::JointAuthoring,
+    logging::subsystem,
+    lqr::LqrController,
+    mpc::MpcController,
+    pid_controller::PidController,
+    plants::Link,
+    swing_up::SwingUpController,
+    theme::Theme,
+};
+
+/// Farthest link that can be selected, in m.
+const MAX_REACH: f32 = 1000.0;
+/// Farthest the cursor moves between pressing and releasing a click, in logical pixels; any
+/// farther, it's a drag of the camera.
+pub const CLICK_SLOP: f32 = 4.0;
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (pick, selection_panel, handle, highlight)
+                    .chain()
+                    .run_if(resource_exists::<Persistent<SelectionSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the handling of the selected link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SelectionSettings {
+    /// Impulse of a poke, in N·s.
+    pub poke: f32,
+    /// Torque jogging a revolute joint, in N·m, or force jogging a prismatic one, in N.
+    pub jog: f32,
+}
+
+impl Default for SelectionSettings {
+    fn default() -> Self {
+        Self {
+            poke: 0.2,
+            jog: 0.5,
+        }
+    }
+}
+
+impl SelectionSettings {
+    /// Impulse jogging a joint about or along its `axis`, in the world, for `dt` seconds;
+    /// `direction` is 1 forward and -1 backward.
+    pub fn jog_impulse(
+        &self,
+        axis: Vec3,
+        revolute: bool,
+        direction: f32,
+        dt: f32,
+    ) -> ExternalImpulse {
+        let impulse = self.jog * direction * dt * axis.normalize_or_zero();
+        if revolute {
+            ExternalImpulse {
+                impulse: Vec3::ZERO,
+                torque_impulse: impulse,
+            }
+        } else {
+            ExternalImpulse {
+                impulse,
+                torque_impulse: Vec3::ZERO,
+            }
+        }
+    }
+}
+
+/// The selected link, if any.
+#[derive(Debug, Default, Resource)]
+pub struct Selection {
+    pub link: Option<Entity>,
+    /// Clicked point, in the frame of the link.
+    pub local_point: Vec3,
+    /// Direction of the click, from the camera, in the world.
+    pub direction: Vec3,
+    /// Whether the link is poked on this frame.
+    pub poke: bool,
+    /// Direction the joint is jogged in on this frame, 1 forward or -1 backward.
+    pub jog: Option<f32>,
+}
+
+/// Whether releasing the button at `released` after pressing it at `pressed` is a click.
+pub fn is_click(pressed: Vec2, released: Vec2) -> bool {
+    pressed.distance(released) <= CLICK_SLOP
+}
+
+/// The link `entity` belongs to: the first of `entity` and its ancestors that `is_link`.
+pub fn link_of(
+    entity: Entity,
+    parent: impl Fn(Entity) -> Option<Entity>,
+    is_link: impl Fn(Entity) -> bool,
+) -> Option<Entity> {
+    std::iter::successors(Some(entity), |&entity| parent(entity)).find(|&entity| is_link(entity))
+}
+
+/// The free axis of a joint: `AngX` for a revolute joint, `LinX` for a prismatic one.
+fn free_axis(joint: &GenericJoint) -> Option<JointAxis> {
+    let locked = joint.locked_axes();
+    if !locked.contains(JointAxesMask::ANG_X) {
+        Some(JointAxis::AngX)
+    } else if !locked.contains(JointAxesMask::LIN_X) {
+        Some(JointAxis::LinX)
+    } else {
+        None
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SelectionSettings>("selection", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Selects the link under the cursor when it's clicked, unless it's over the interface.
+#[allow(clippy::too_many_arguments)]
+fn pick(
+    mut egui: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    contexts: Query<&RapierContext>,
+    parents: Query<&Parent>,
+    links: Query<(&Link, &Transform)>,
+    interaction: Option<Res<Persistent<InteractionSettings>>>,
+    authoring: Option<Res<JointAuthoring>>,
+    mut pressed: Local<Option<Vec2>>,
+    mut selection: ResMut<Selection>,
+) {
+    if selection.link.is_some_and(|link| !links.contains(link)) {
+        selection.link = None;
+    }
+    let cursor = windows.get_single().ok().and_then(Window::cursor_position);
+    if buttons.just_pressed(MouseButton::Left) {
+        let taken = interaction.is_some_and(|interaction| interaction.enabled)
+            || authoring.is_some_and(|authoring| authoring.picking)
+            || keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        *pressed = cursor.filter(|_| !taken && !egui.ctx_mut().is_pointer_over_area());
+    }
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let (Some(pressed), Some(cursor)) = (pressed.take(), cursor) else {
+        return;
+    };
+    if !is_click(pressed, cursor) {
+        return;
+    }
+    // The camera whose viewport holds the cursor, when the window is split.
+    let ray = cameras
+        .iter()
+        .find(|(camera, _)| {
+            camera.is_active
+                && camera
+                    .logical_viewport_rect()
+                    .map_or(true, |rect| rect.contains(cursor))
+        })
+        .and_then(|(camera, transform)| camera.viewport_to_world(transform, cursor).ok());
+    let (Some(ray), Ok(context)) = (ray, contexts.get_single()) else {
+        return;
+    };
+    let hit = context.cast_ray(
+        ray.origin,
+        *ray.direction,
+        MAX_REACH,
+        true,
+        QueryFilter::default().exclude_sensors(),
+    );
+    let picked = hit.and_then(|(entity, depth)| {
+        let link = link_of(
+            entity,
+            |entity| parents.get(entity).ok().map(Parent::get),
+            |entity| links.contains(entity),
+        )?;
+        Some((link, depth))
+    });
+    let Some((link, depth)) = picked else {
+        selection.link = None;
+        return;
+    };
+    let Ok((name, transform)) = links.get(link) else {
+        return;
+    };
+    debug!(target: subsystem::PHYSICS, "Selected {}", name.path());
+    selection.link = Some(link);
+    selection.local_point =
+        transform.rotation.inverse() * (ray.get_point(depth) - transform.translation);
+    selection.direction = *ray.direction;
+}
+
+/// Pokes the selected link and jogs its joint, as requested by the panel.
+fn handle(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<SelectionSettings>>,
+    mut selection: ResMut<Selection>,
+    mut links: Query<
+        (
+            &Transform,
+            Option<&ImpulseJoint>,
+            Option<&mut ExternalImpulse>,
+        ),
+        With<Link>,
+    >,
+) {
+    let poke = std::mem::take(&mut selection.poke);
+    let jog = selection.jog.take();
+    if !poke && jog.is_none() {
+        return;
+    }
+    let Some(link) = selection.link else {
+        return;
+    };
+    let Ok((transform, joint, impulse)) = links.get_mut(link) else {
+        return;
+    };
+    let mut applied = ExternalImpulse::default();
+    if poke {
+        let point = transform.translation + transform.rotation * selection.local_point;
+        applied = ExternalImpulse::at_point(
+            settings.poke * selection.direction.normalize_or_zero(),
+            point,
+            transform.translation,
+        );
+    }
+    let joint = joint.map(|joint| joint.data.as_ref());
+    if let (Some(direction), Some(joint)) = (jog, joint) {
+        if let Some(axis) = free_axis(joint) {
+            let jogged = settings.jog_impulse(
+                transform.rotation * joint.local_axis2(),
+                axis == JointAxis::AngX,
+                direction,
+                clock.delta_secs(),
+            );
+            applied.impulse += jogged.impulse;
+            applied.torque_impulse += jogged.torque_impulse;
+        }
+    }
+    match impulse {
+        Some(mut impulse) => {
+            impulse.impulse += applied.impulse;
+            impulse.torque_impulse += applied.torque_impulse;
+        }
+        None => {
+            commands.entity(link).insert(applied);
+        }
+    }
+}
+
+/// Outlines the meshes of the selected link and marks the clicked point.
+fn highlight(
+    mut gizmos: Gizmos,
+    selection: Res<Selection>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform)>,
+    transforms: Query<&GlobalTransform, With<Link>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let Some(link) = selection.link else {
+        return;
+    };
+    let color = theme.map_or_else(|| Theme::default().series(2), |theme| theme.series(2));
+    for entity in std::iter::once(link).chain(children.iter_descendants(link)) {
+        if let Ok((aabb, transform)) = meshes.get(entity) {
+            let bounds = Transform::from_translation(aabb.center.into())
+                .with_scale((2.0 * aabb.half_extents).into());
+            gizmos.cuboid(transform.mul_transform(bounds), color);
+        }
+    }
+    if let Ok(transform) = transforms.get(link) {
+        let point = transform.transform_point(selection.local_point);
+        gizmos.sphere(Isometry3d::from_translation(point), 0.02, color);
+    }
+}
+
+/// The controllers that can be on the joint of a link.
+type Controllers<'a> = (
+    Option<&'a PidController>,
+    Option<&'a LqrController>,
+    Option<&'a MpcController>,
+    Option<&'a SwingUpController>,
+);
+
+/// Panel showing the selected link, to poke it and jog its joint.
+#[allow(clippy::too_many_arguments)]
+fn selection_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SelectionSettings>>,
+    mut selection: ResMut<Selection>,
+    links: Query<(
+        &Link,
+        &RigidBody,
+        &Transform,
+        Option<&Velocity>,
+        Option<&ReadMassProperties>,
+        Option<&ImpulseJoint>,
+        Controllers,
+    )>,
+    #[cfg(not(target_arch = "wasm32"))] scripted: Query<(), With<ScriptedController>>,
+    names: Query<&Link>,
+    children: Query<&Children>,
+    colliders: Query<&Collider>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Selection")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let Some(link) = selection.link else {
+                ui.label("Click a link to select it");
+                return;
+            };
+            let Ok((name, body, transform, velocity, mass, joint, controllers)) = links.get(link)
+            else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.heading(name.path());
+                if ui.button("Clear").clicked() {
+                    selection.link = None;
+                }
+            });
+
+            ui.separator();
+            ui.label("Body");
+            let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
+            let velocity = velocity.copied().unwrap_or_default();
+            egui::Grid::new("selection_body").show(ui, |ui| {
+                ui.label("Type");
+                ui.label(format!("{body:?}"));
+                ui.end_row();
+                ui.label("Mass");
+                ui.label(mass.map_or("unknown".to_string(), |mass| {
+                    format!("{:.3} kg", mass.get().mass)
+                }));
+                ui.end_row();
+                ui.label("Position");
+                ui.label(format!("{:.3} m", transform.translation));
+                ui.end_row();
+                ui.label("Yaw, pitch, roll");
+                ui.label(format!(
+                    "{:.1}°, {:.1}°, {:.1}°",
+                    yaw.to_degrees(),
+                    pitch.to_degrees(),
+                    roll.to_degrees()
+                ));
+                ui.end_row();
+                ui.label("Linear velocity");
+                ui.label(format!("{:.3} m/s", velocity.linvel));
+                ui.end_row();
+                ui.label("Angular velocity");
+                ui.label(format!("{:.3} rad/s", velocity.angvel));
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.label("Joint");
+            let axis = joint.and_then(|joint| free_axis(joint.data.as_ref()));
+            match joint {
+                Some(joint) => {
+                    let parent = names
+                        .get(joint.parent)
+                        .map_or("a body".to_string(), Link::path);
+                    let kind = match axis {
+                        Some(JointAxis::AngX) => "Revolute",
+                        Some(_) => "Prismatic",
+                        None => "Fixed",
+                    };
+                    ui.label(format!("{kind}, hanging from {parent}"));
+                    if let Some(motor) = axis.and_then(|axis| joint.data.as_ref().motor(axis)) {
+                        ui.label(format!(
+                            "Motor: target {:.3}, velocity {:.3}, stiffness {:.3}, damping {:.3}",
+                            motor.target_pos, motor.target_vel, motor.stiffness, motor.damping
+                        ));
+                    }
+                }
+                None => {
+                    ui.label("None");
+                }
+            }
+
+            ui.separator();
+            ui.label("Colliders");
+            let shapes: Vec<String> = std::iter::once(link)
+                .chain(children.iter_descendants(link))
+                .filter_map(|entity| colliders.get(entity).ok())
+                .map(|collider| format!("{:?}", collider.raw.shape_type()))
+                .collect();
+            ui.label(if shapes.is_empty() {
+                "None".to_string()
+            } else {
+                shapes.join(", ")
+            });
+
+            ui.separator();
+            ui.label("Controllers");
+            let (pid, lqr, mpc, swing_up) = controllers;
+            let mut controlled: Vec<&str> = [
+                (pid.is_some(), "Position loop"),
+                (lqr.is_some(), "LQR"),
+                (mpc.is_some(), "MPC"),
+                (swing_up.is_some(), "Swing-up"),
+            ]
+            .into_iter()
+            .filter_map(|(present, name)| present.then_some(name))
+            .collect();
+            #[cfg(not(target_arch = "wasm32"))]
+            if scripted.contains(link) {
+                controlled.push("Script");
+            }
+            ui.label(if controlled.is_empty() {
+                "None".to_string()
+            } else {
+                controlled.join(", ")
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.poke)
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .prefix("Impulse: ")
+                        .suffix(" N·s"),
+                );
+                let poke = ui.add_enabled(*body == RigidBody::Dynamic, egui::Button::new("Poke"));
+                if poke
+                    .on_hover_text("Pushes the clicked point away from the camera")
+                    .clicked()
+                {
+                    selection.poke = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                let unit = if axis == Some(JointAxis::LinX) {
+                    "N"
+                } else {
+                    "N·m"
+                };
+                ui.add(
+                    egui::DragValue::new(&mut edited.jog)
+                        .range(0.0..=100.0)
+                        .speed(0.01)
+                        .prefix("Effort: ")
+                        .suffix(format!(" {unit}")),
+                );
+                ui.add_enabled_ui(axis.is_some(), |ui| {
+                    if ui.button("◀ Jog").is_pointer_button_down_on() {
+                        selection.jog = Some(-1.0);
+                    }
+                    if ui.button("Jog ▶").is_pointer_button_down_on() {
+                        selection.jog = Some(1.0);
+                    }
+                });
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("selection", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("selection", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
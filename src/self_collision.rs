@@ -0,0 +1,304 @@
+//! This module checks the links of the articulated plants against each other, as planned or
+//! taught motions can drive a link into another link of the same plant.
+//!
+//! After each step of the simulation, the contacts of the physics between two [`Link`]s of the
+//! same plant are reported: logged as warnings when they start, highlighted in the scene and
+//! listed in the *Self-collision* panel. The links joined to each other touch at their joints,
+//! so they're left out by default. With the safety stop, a self-collision latches the stop of
+//! the plant: its stop setpoints are held at zero, and the taught motions stop, until the
+//! stop is reset from the panel.
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClockSet,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::{subsystem, LogLevel},
+    plants::{self, Link},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    teach::TeachPlayback,
+    theme::Theme,
+};
+
+pub struct SelfCollisionPlugin;
+
+impl Plugin for SelfCollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelfCollisions>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                check
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<SelfCollisionSettings>>),
+            )
+            .add_systems(
+                Update,
+                (draw_collisions, self_collision_panel)
+                    .run_if(resource_exists::<Persistent<SelfCollisionSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the self-collision checks.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SelfCollisionSettings {
+    pub enabled: bool,
+    /// Whether the contacts between links joined to each other are left out.
+    pub exclude_adjacent: bool,
+    /// Whether a self-collision stops the plant.
+    pub safety_stop: bool,
+    /// Setpoints of the plant held at zero while it's stopped, within its namespace.
+    pub stop: Vec<String>,
+}
+
+impl Default for SelfCollisionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exclude_adjacent: true,
+            safety_stop: false,
+            stop: vec![MOTOR_VELOCITY.to_string()],
+        }
+    }
+}
+
+/// A contact between two links of the same plant.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct SelfCollision {
+    pub plant: String,
+    pub links: [Entity; 2],
+    /// Names of the links.
+    pub names: [String; 2],
+}
+
+/// The self-collisions at the last step, and the plants stopped by them.
+#[derive(Debug, Default, Resource)]
+pub struct SelfCollisions {
+    pub current: BTreeSet<SelfCollision>,
+    /// Plants whose safety stop is latched, until reset.
+    pub stopped: BTreeSet<String>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<SelfCollisionSettings>("self_collision", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Finds the contacts between links of the same plant after each step of the simulation, and
+/// stops the plants colliding with themselves when the safety stop is enabled.
+fn check(
+    settings: Res<Persistent<SelfCollisionSettings>>,
+    contexts: Query<&RapierContext>,
+    links: Query<(&Link, Option<&ImpulseJoint>)>,
+    mut collisions: ResMut<SelfCollisions>,
+    mut setpoints: ResMut<Setpoints>,
+    teach: Option<ResMut<TeachPlayback>>,
+) {
+    let mut current = BTreeSet::new();
+    if let (true, Ok(context)) = (settings.enabled, contexts.get_single()) {
+        let body = |collider: Entity| context.collider_parent(collider).unwrap_or(collider);
+        let joined = |a: Entity, b: Entity| {
+            let parent = |entity: Entity| {
+                links
+                    .get(entity)
+                    .ok()
+                    .and_then(|(_, joint)| joint)
+                    .map(|joint| joint.parent)
+            };
+            parent(a) == Some(b) || parent(b) == Some(a)
+        };
+        for pair in context.contact_pairs() {
+            if !pair.has_any_active_contact() {
+                continue;
+            }
+            let (a, b) = (body(pair.collider1()), body(pair.collider2()));
+            let (Ok((first, _)), Ok((second, _))) = (links.get(a), links.get(b)) else {
+                continue;
+            };
+            if a == b || first.plant != second.plant {
+                continue;
+            }
+            if settings.exclude_adjacent && joined(a, b) {
+                continue;
+            }
+            let (ends, names) = if a < b {
+                ([a, b], [first.name.clone(), second.name.clone()])
+            } else {
+                ([b, a], [second.name.clone(), first.name.clone()])
+            };
+            current.insert(SelfCollision {
+                plant: first.plant.clone(),
+                links: ends,
+                names,
+            });
+        }
+    }
+
+    for collision in current.difference(&collisions.current) {
+        let [first, second] = &collision.names;
+        warn!(
+            target: subsystem::PHYSICS,
+            "Self-collision of {}: {first} and {second}",
+            plant_name(&collision.plant)
+        );
+    }
+    if settings.safety_stop {
+        let colliding: BTreeSet<String> = current
+            .iter()
+            .map(|collision| collision.plant.clone())
+            .collect();
+        let mut stopping = false;
+        for plant in colliding {
+            if !collisions.stopped.contains(&plant) {
+                warn!(target: subsystem::CONTROL, "Safety stop of {}", plant_name(&plant));
+                collisions.stopped.insert(plant);
+                stopping = true;
+            }
+        }
+        // The taught motions are what drives a plant into itself.
+        if let (true, Some(mut teach)) = (stopping, teach) {
+            teach.playback = None;
+            teach.replay = None;
+        }
+    }
+    for plant in &collisions.stopped {
+        for signal in &settings.stop {
+            let signal = plants::namespaced(plant, signal);
+            if setpoints.get(&signal) != Some(0.0) {
+                setpoints.set(&signal, 0.0);
+            }
+        }
+    }
+    collisions.current = current;
+}
+
+/// Name of a plant in the messages: its namespace, unless it's the single plant.
+fn plant_name(plant: &str) -> &str {
+    if plant.is_empty() {
+        "the plant"
+    } else {
+        plant
+    }
+}
+
+/// Highlights the links colliding with each other.
+fn draw_collisions(
+    mut gizmos: Gizmos,
+    collisions: Res<SelfCollisions>,
+    links: Query<&GlobalTransform, With<Link>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let color = theme.map_or_else(
+        || Theme::default().log_level(LogLevel::Error),
+        |theme| theme.log_level(LogLevel::Error),
+    );
+    for collision in &collisions.current {
+        let Ok([first, second]) = links.get_many(collision.links) else {
+            continue;
+        };
+        let (first, second) = (first.translation(), second.translation());
+        gizmos.line(first, second, color);
+        gizmos.sphere(Isometry3d::from_translation(first), 0.1, color);
+        gizmos.sphere(Isometry3d::from_translation(second), 0.1, color);
+    }
+}
+
+/// Panel to configure the checks and reset the safety stops, with a warning over the scene
+/// while the plants collide with themselves.
+fn self_collision_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SelfCollisionSettings>>,
+    mut collisions: ResMut<SelfCollisions>,
+) {
+    let mut edited = settings.get().clone();
+    let ctx = contexts.ctx_mut();
+
+    if !collisions.current.is_empty() || !collisions.stopped.is_empty() {
+        egui::Area::new(egui::Id::new("self_collision_warning"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 8.0))
+            .show(ctx, |ui| {
+                let message = if collisions.stopped.is_empty() {
+                    "Self-collision".to_string()
+                } else {
+                    format!("Self-collision: {} stopped", collisions.stopped.len())
+                };
+                ui.colored_label(ui.visuals().error_fg_color, message);
+            });
+    }
+
+    egui::Window::new("Self-collision")
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut edited.enabled, "Check the links against each other");
+            ui.checkbox(&mut edited.exclude_adjacent, "Leave out the joined links")
+                .on_hover_text("Links joined to each other touch at their joint");
+            ui.checkbox(
+                &mut edited.safety_stop,
+                "Stop the plant on a self-collision",
+            )
+            .on_hover_text(format!(
+                "Holds {} at zero until reset",
+                edited.stop.join(", ")
+            ));
+
+            ui.separator();
+            if collisions.current.is_empty() {
+                ui.label("No self-collision.");
+            }
+            for collision in &collisions.current {
+                let [first, second] = &collision.names;
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("{}: {first} and {second}", plant_name(&collision.plant)),
+                );
+            }
+            let mut reset = None;
+            for plant in &collisions.stopped {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} stopped", plant_name(plant)));
+                    if ui.button("Reset").clicked() {
+                        reset = Some(plant.clone());
+                    }
+                });
+            }
+            if let Some(plant) = reset {
+                info!(target: subsystem::CONTROL, "Safety stop of {} reset", plant_name(&plant));
+                collisions.stopped.remove(&plant);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("self_collision", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("self_collision", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,395 @@
+//! This module runs the motors of the pendulums as DC motors, to compare sensorless and sensored
+//! control: the speed of each motor is estimated from its back-EMF, so a controller can close
+//! its loop on the estimate instead of the encoder.
+//!
+//! The drive of each motor is modelled from the torque it applies and the speed of its encoder:
+//! the current is the torque over the torque constant, and the voltage across the terminals
+//! adds the drops across the winding to the back-EMF. The current is measured with the
+//! resolution of its sensor, and a [`BackEmfObserver`] estimates the speed from the voltage and
+//! the measured current, with its own, possibly wrong, parameters of the motor.
+//!
+//! The speed of the encoder and the estimate are recorded as the `motor/encoder_velocity` and
+//! `motor/estimated_velocity` telemetry channels, with the namespace of the plant, so either is
+//! the `measurement` of a loop of a composition, and plotted against each other in the
+//! *Sensorless* panel.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::BackEmfObserver,
+    error::{Error, ErrorEvent},
+    plants,
+    telemetry::{Telemetry, MOTOR_ANGLE, MOTOR_TORQUE},
+    theme::{to_egui, Theme},
+};
+
+/// Current through the winding of the motor, as measured, in A.
+pub const MOTOR_CURRENT: &str = "motor/current";
+/// Voltage across the terminals of the motor, in V.
+pub const MOTOR_VOLTAGE: &str = "motor/voltage";
+/// Speed of the motor from its encoder, in rad/s.
+pub const ENCODER_VELOCITY: &str = "motor/encoder_velocity";
+/// Speed of the motor estimated from its back-EMF, in rad/s.
+pub const ESTIMATED_VELOCITY: &str = "motor/estimated_velocity";
+/// Number of steps plotted and compared in the sensorless panel.
+const PLOTTED_STEPS: usize = 600;
+
+pub struct SensorlessPlugin;
+
+impl Plugin for SensorlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Estimators>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                estimate
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<SensorlessSettings>>),
+            )
+            .add_systems(
+                Update,
+                sensorless_panel
+                    .run_if(resource_exists::<Persistent<SensorlessSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Electrical parameters of a DC motor.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DcMotor {
+    /// Resistance of the winding, in Ω.
+    pub resistance: f32,
+    /// Inductance of the winding, in H.
+    pub inductance: f32,
+    /// Torque per unit of current, in N·m/A, equal to the back-EMF per unit of speed in V·s/rad.
+    pub torque_constant: f32,
+}
+
+impl Default for DcMotor {
+    fn default() -> Self {
+        Self {
+            resistance: 1.2,
+            inductance: 0.002,
+            torque_constant: 0.5,
+        }
+    }
+}
+
+impl DcMotor {
+    /// Current applying `torque`, in A.
+    pub fn current(&self, torque: f32) -> f32 {
+        if self.torque_constant == 0.0 {
+            0.0
+        } else {
+            torque / self.torque_constant
+        }
+    }
+
+    /// Voltage across the terminals with `current` changing at `current_rate` (A/s) and the
+    /// motor turning at `velocity` (rad/s).
+    pub fn voltage(&self, current: f32, current_rate: f32, velocity: f32) -> f32 {
+        self.resistance * current + self.inductance * current_rate + self.torque_constant * velocity
+    }
+
+    /// Observer of the speed of the motor, believing these parameters.
+    pub fn observer(&self, time_constant: f32) -> BackEmfObserver {
+        BackEmfObserver::new(
+            self.resistance,
+            self.inductance,
+            self.torque_constant,
+            time_constant,
+        )
+    }
+}
+
+/// Represents the configuration of the sensorless estimation.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SensorlessSettings {
+    pub enabled: bool,
+    /// The motor simulated.
+    pub motor: DcMotor,
+    /// The motor as known to the estimator.
+    pub model: DcMotor,
+    /// Resolution of the current sensor, in A, or 0 for an exact one.
+    pub current_resolution: f32,
+    /// Time constant of the filter of the estimate, in seconds.
+    pub time_constant: f32,
+}
+
+impl Default for SensorlessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            motor: DcMotor::default(),
+            model: DcMotor::default(),
+            current_resolution: 0.01,
+            time_constant: 0.02,
+        }
+    }
+}
+
+impl SensorlessSettings {
+    /// Current read by the sensor, rounded to its resolution.
+    pub fn measured_current(&self, current: f32) -> f32 {
+        if self.current_resolution > 0.0 {
+            (current / self.current_resolution).round() * self.current_resolution
+        } else {
+            current
+        }
+    }
+}
+
+/// The drive and the estimator of the motor of a plant.
+#[derive(Clone, Debug)]
+pub struct Estimator {
+    pub observer: BackEmfObserver,
+    /// Time and angle of the encoder at the last step.
+    angle: Option<(f32, f32)>,
+    /// Current at the last step.
+    current: Option<f32>,
+}
+
+/// The estimators of the plants, by namespace.
+#[derive(Debug, Default, Resource)]
+pub struct Estimators(pub BTreeMap<String, Estimator>);
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SensorlessSettings>("sensorless", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Runs the drives and the estimators after each step of the simulation.
+fn estimate(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<SensorlessSettings>>,
+    mut estimators: ResMut<Estimators>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let (true, Some(mut telemetry)) = (settings.enabled, telemetry) else {
+        estimators.0.clear();
+        return;
+    };
+    if clock.delta().is_zero() {
+        return;
+    }
+    // The plants with a motor, by the namespaces of their angles.
+    let namespaces: Vec<String> = telemetry
+        .channels
+        .keys()
+        .filter_map(|channel| channel.strip_suffix(MOTOR_ANGLE))
+        .filter_map(|prefix| match prefix.strip_suffix('/') {
+            Some(namespace) => Some(namespace.to_string()),
+            None => prefix.is_empty().then(String::new),
+        })
+        .collect();
+    let time = clock.elapsed_secs();
+    for namespace in namespaces {
+        let signal = |name: &str| plants::namespaced(&namespace, name);
+        let angle = telemetry
+            .channels
+            .get(&signal(MOTOR_ANGLE))
+            .and_then(|samples| samples.last().copied());
+        let torque = telemetry.latest(&signal(MOTOR_TORQUE));
+        let (Some([angle_time, angle]), Some(torque)) = (angle, torque) else {
+            continue;
+        };
+        let estimator = estimators
+            .0
+            .entry(namespace.clone())
+            .or_insert_with(|| Estimator {
+                observer: settings.model.observer(settings.time_constant),
+                angle: None,
+                current: None,
+            });
+        // Follows the parameters edited in the panel.
+        estimator.observer.resistance = settings.model.resistance;
+        estimator.observer.inductance = settings.model.inductance;
+        estimator.observer.back_emf_constant = settings.model.torque_constant;
+        estimator.observer.time_constant = settings.time_constant;
+
+        let Some((previous_time, previous)) = estimator.angle.replace((angle_time, angle)) else {
+            continue;
+        };
+        let dt = angle_time - previous_time;
+        if dt <= 0.0 {
+            continue;
+        }
+        let velocity = (angle - previous) / dt;
+        let current = settings.motor.current(torque);
+        let rate = estimator
+            .current
+            .map_or(0.0, |previous| (current - previous) / dt);
+        estimator.current = Some(current);
+        let voltage = settings.motor.voltage(current, rate, velocity);
+        let measured = settings.measured_current(current);
+        let estimated = estimator.observer.update(voltage, measured, dt);
+
+        telemetry.record(&signal(MOTOR_CURRENT), time, measured);
+        telemetry.record(&signal(MOTOR_VOLTAGE), time, voltage);
+        telemetry.record(&signal(ENCODER_VELOCITY), time, velocity);
+        telemetry.record(&signal(ESTIMATED_VELOCITY), time, estimated);
+    }
+}
+
+/// Root mean square of the difference between the estimate and the encoder over the last
+/// `steps` samples, in rad/s.
+pub fn rms_error(telemetry: &Telemetry, namespace: &str, steps: usize) -> Option<f32> {
+    let last = |channel: &str| {
+        let samples = telemetry
+            .channels
+            .get(&plants::namespaced(namespace, channel))?;
+        Some(&samples[samples.len().saturating_sub(steps)..])
+    };
+    let (encoder, estimate) = (last(ENCODER_VELOCITY)?, last(ESTIMATED_VELOCITY)?);
+    if encoder.is_empty() || encoder.len() != estimate.len() {
+        return None;
+    }
+    let sum: f32 = encoder
+        .iter()
+        .zip(estimate)
+        .map(|([_, encoder], [_, estimate])| (estimate - encoder).powi(2))
+        .sum();
+    Some((sum / encoder.len() as f32).sqrt())
+}
+
+/// Panel to set the parameters of the motor and of its model, comparing the estimate to the
+/// encoder.
+fn sensorless_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SensorlessSettings>>,
+    estimators: Res<Estimators>,
+    telemetry: Option<Res<Telemetry>>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let mut edited = settings.get().clone();
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+
+    egui::Window::new("Sensorless")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Estimate the speed from the back-EMF");
+
+            ui.separator();
+            egui::Grid::new("sensorless_parameters").show(ui, |ui| {
+                ui.label("");
+                ui.label("Motor");
+                ui.label("Model");
+                ui.end_row();
+                let rows: [(&str, fn(&mut DcMotor) -> &mut f32); 3] = [
+                    ("Resistance (Ω)", |motor| &mut motor.resistance),
+                    ("Inductance (H)", |motor| &mut motor.inductance),
+                    ("Torque constant (N·m/A)", |motor| {
+                        &mut motor.torque_constant
+                    }),
+                ];
+                for (label, parameter) in rows {
+                    ui.label(label);
+                    for motor in [&mut edited.motor, &mut edited.model] {
+                        ui.add(
+                            egui::DragValue::new(parameter(motor))
+                                .range(0.0..=100.0)
+                                .speed(0.001),
+                        );
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.current_resolution)
+                    .range(0.0..=1.0)
+                    .speed(0.001)
+                    .prefix("Current resolution: ")
+                    .suffix(" A"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.time_constant)
+                    .range(0.0..=1.0)
+                    .speed(0.001)
+                    .prefix("Filter time constant: ")
+                    .suffix(" s"),
+            );
+
+            if let Some(telemetry) = &telemetry {
+                for namespace in estimators.0.keys() {
+                    ui.separator();
+                    let name = if namespace.is_empty() {
+                        "Motor"
+                    } else {
+                        namespace
+                    };
+                    match rms_error(telemetry, namespace, PLOTTED_STEPS) {
+                        Some(error) => ui.label(format!("{name}: RMS error {error:.3} rad/s")),
+                        None => ui.label(name),
+                    };
+                    plot_velocities(ui, telemetry, namespace, &theme);
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("sensorless", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("sensorless", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Plots the last speeds of the encoder and the estimate of a plant, on the same scale.
+fn plot_velocities(ui: &mut egui::Ui, telemetry: &Telemetry, namespace: &str, theme: &Theme) {
+    let last = |channel: &str| {
+        let samples = telemetry
+            .channels
+            .get(&plants::namespaced(namespace, channel))
+            .map_or(&[][..], Vec::as_slice);
+        &samples[samples.len().saturating_sub(PLOTTED_STEPS)..]
+    };
+    let series = [last(ENCODER_VELOCITY), last(ESTIMATED_VELOCITY)];
+    let high = series
+        .iter()
+        .flat_map(|samples| samples.iter())
+        .map(|[_, velocity]| velocity.abs())
+        .fold(0.0f32, f32::max)
+        .max(f32::EPSILON);
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(300.0, 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    for (index, samples) in series.into_iter().enumerate() {
+        let points = samples
+            .iter()
+            .enumerate()
+            .map(|(k, [_, velocity])| {
+                egui::pos2(
+                    rect.left() + rect.width() * k as f32 / PLOTTED_STEPS as f32,
+                    rect.center().y - 0.5 * rect.height() * velocity / high,
+                )
+            })
+            .collect();
+        let color = to_egui(theme.series(index));
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+    }
+}
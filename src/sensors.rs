@@ -0,0 +1,798 @@
+//! Simulated sensors, so the controllers see the joints as the real rig measures them rather
+//! than the exact state of the physics.
+//!
+//! An [`Encoder`] on the entity of a revolute joint measures its angle after each physics step:
+//! the angle is disturbed by a Gaussian noise, quantized to the counts of the encoder, sampled
+//! at its rate and delivered after its latency. The controllers read [`joint_angle`], which is
+//! the reading of the encoder of a joint when it has one, and the angle of the joint otherwise.
+//! The readings are recorded as the `encoder/<link>` telemetry channels, to be compared with
+//! the exact angles.
+//!
+//! An [`Imu`] on the entity of a link measures its angular velocity and its specific force, the
+//! acceleration less the gravity, in the frame of the link, as a gyroscope and an accelerometer
+//! at its center of mass would: each axis is offset by a bias and disturbed by a white noise of
+//! the configured density, sampled at the rate of the IMU. The readings are recorded as the
+//! `imu/<link>/gyroscope/<axis>` and `imu/<link>/accelerometer/<axis>` telemetry channels.
+//!
+//! The sensors can be mounted imperfectly, to test the estimators against calibration errors:
+//! an encoder reads its joint through a mounting offset, and its shaft slips now and then by a
+//! random angle, the slips adding up; an IMU is turned from its link by a misalignment. The
+//! mounting errors are configured on each sensor and applied while `mounting_errors` is on.
+//!
+//! The encoders and the IMUs are added to the configured links, as configured in
+//! `sensors.json`. Their noise is drawn from a seeded generator, so runs are reproducible.
+use std::{
+    collections::VecDeque,
+    f32::consts::{PI, TAU},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the readings of the encoders.
+pub const ENCODER_PREFIX: &str = "encoder/";
+/// Prefix of the telemetry channels of the readings of the IMUs.
+pub const IMU_PREFIX: &str = "imu/";
+
+pub struct SensorsPlugin;
+
+impl Plugin for SensorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(PostUpdate, SensorSet.after(SimClockSet::Advance))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (configure_encoders, configure_imus)
+                    .run_if(resource_exists::<Persistent<SensorSettings>>),
+            )
+            .add_systems(PostUpdate, (measure, measure_imus).in_set(SensorSet))
+            .add_systems(
+                Update,
+                sensors_panel
+                    .run_if(resource_exists::<Persistent<SensorSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Where the sensors measure, after the physics step; the controllers run after it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+pub struct SensorSet;
+
+/// Where the controllers read the joints and command their motors, after the sensors.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+pub struct ControlSet;
+
+/// Represents an encoder on the joint of a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct EncoderSettings {
+    /// Name of the link whose joint is measured, in each plant.
+    pub link: String,
+    /// Counts per revolution, or 0 for an exact angle.
+    pub counts_per_revolution: u32,
+    /// Standard deviation of the noise on the angle, in rad.
+    pub noise: f32,
+    /// Samples per second, or 0 to sample every physics step.
+    pub rate: f32,
+    /// Delay from a sample to its reading, in s.
+    pub latency: f32,
+    /// Angle read with the joint at 0, in rad.
+    pub mounting_offset: f32,
+    /// Average slips of the shaft per second.
+    pub slip_rate: f32,
+    /// Standard deviation of the angle of a slip, in rad.
+    pub slip_angle: f32,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            link: "motor".to_string(),
+            counts_per_revolution: 4096,
+            noise: 0.0,
+            rate: 0.0,
+            latency: 0.0,
+            mounting_offset: 0.0,
+            slip_rate: 0.0,
+            slip_angle: 0.0,
+        }
+    }
+}
+
+impl EncoderSettings {
+    /// The encoder mounted exactly on its joint.
+    pub fn ideal_mounting(&self) -> Self {
+        Self {
+            mounting_offset: 0.0,
+            slip_rate: 0.0,
+            slip_angle: 0.0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Represents an IMU on a link.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ImuSettings {
+    /// Name of the link carrying the IMU, in each plant.
+    pub link: String,
+    /// Bias of the gyroscope on each axis, in rad/s.
+    pub gyroscope_bias: Vec3,
+    /// Noise density of the gyroscope, in rad/s/√Hz.
+    pub gyroscope_noise_density: f32,
+    /// Bias of the accelerometer on each axis, in m/s².
+    pub accelerometer_bias: Vec3,
+    /// Noise density of the accelerometer, in m/s²/√Hz.
+    pub accelerometer_noise_density: f32,
+    /// Samples per second, or 0 to sample every physics step.
+    pub rate: f32,
+    /// Rotation of the IMU from its link, as XYZ Euler angles, in rad.
+    pub misalignment: Vec3,
+}
+
+impl Default for ImuSettings {
+    fn default() -> Self {
+        Self {
+            link: "arm".to_string(),
+            gyroscope_bias: Vec3::ZERO,
+            gyroscope_noise_density: 0.0,
+            accelerometer_bias: Vec3::ZERO,
+            accelerometer_noise_density: 0.0,
+            rate: 0.0,
+            misalignment: Vec3::ZERO,
+        }
+    }
+}
+
+impl ImuSettings {
+    /// The IMU mounted square on its link.
+    pub fn ideal_mounting(&self) -> Self {
+        Self {
+            misalignment: Vec3::ZERO,
+            ..self.clone()
+        }
+    }
+
+    /// Rotation of the IMU in the frame of its link.
+    pub fn mounting(&self) -> Quat {
+        let [x, y, z] = self.misalignment.to_array();
+        Quat::from_euler(EulerRot::XYZ, x, y, z)
+    }
+}
+
+/// Represents the configuration of the sensors.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SensorSettings {
+    /// The encoders; the joints without one are measured exactly.
+    pub encoders: Vec<EncoderSettings>,
+    pub imus: Vec<ImuSettings>,
+    /// Whether the mounting offsets, slips and misalignments of the sensors apply.
+    pub mounting_errors: bool,
+    /// Seed of the noise of the first encoder, the next sensors following.
+    pub seed: u64,
+}
+
+impl Default for SensorSettings {
+    fn default() -> Self {
+        Self {
+            encoders: Vec::new(),
+            imus: Vec::new(),
+            mounting_errors: true,
+            seed: 1,
+        }
+    }
+}
+
+/// Xorshift generator of the noise of a sensor.
+#[derive(Clone, Debug)]
+struct Noise(u64);
+
+impl Noise {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    /// A random number in [0, 1).
+    fn uniform(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A centered normal draw of standard deviation `deviation`, by the Box-Muller transform.
+    fn gaussian(&mut self, deviation: f32) -> f32 {
+        if deviation == 0.0 {
+            return 0.0;
+        }
+        let radius = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        deviation * radius * (TAU * self.uniform()).cos()
+    }
+
+    /// A draw of standard deviation `deviation` on each axis.
+    fn vector(&mut self, deviation: f32) -> Vec3 {
+        Vec3::new(
+            self.gaussian(deviation),
+            self.gaussian(deviation),
+            self.gaussian(deviation),
+        )
+    }
+}
+
+/// An encoder measuring the revolute joint of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Encoder {
+    pub settings: EncoderSettings,
+    /// Telemetry channel of the readings.
+    pub channel: String,
+    noise: Noise,
+    /// Time of the next sample.
+    next_sample: Option<f32>,
+    /// Samples not read yet, with the time they are read at.
+    pending: VecDeque<(f32, f32)>,
+    reading: Option<f32>,
+    /// Time of the last measurement.
+    last: Option<f32>,
+    /// Angle the shaft slipped by, in all.
+    slip: f32,
+}
+
+impl Encoder {
+    pub fn new(settings: EncoderSettings, channel: String, seed: u64) -> Self {
+        Self {
+            settings,
+            channel,
+            noise: Noise::new(seed),
+            next_sample: None,
+            pending: VecDeque::new(),
+            reading: None,
+            last: None,
+            slip: 0.0,
+        }
+    }
+
+    /// Angle per count, in rad, or 0 for an exact angle.
+    pub fn resolution(&self) -> f32 {
+        match self.settings.counts_per_revolution {
+            0 => 0.0,
+            counts => TAU / counts as f32,
+        }
+    }
+
+    /// `angle` rounded down to a whole count.
+    pub fn quantize(&self, angle: f32) -> f32 {
+        let resolution = self.resolution();
+        if resolution == 0.0 {
+            return angle;
+        }
+        (angle / resolution).floor() * resolution
+    }
+
+    /// Measures the exact `angle` of the joint at `time`, returning the current reading.
+    ///
+    /// A time before the last one, when the simulation is rewound, starts the encoder over.
+    pub fn measure(&mut self, time: f32, angle: f32) -> Option<f32> {
+        if self.last.is_some_and(|last| time < last) {
+            self.next_sample = None;
+            self.pending.clear();
+            self.reading = None;
+            self.slip = 0.0;
+        }
+        let step = self.last.map_or(0.0, |last| time - last);
+        self.last = Some(time);
+        // The slips are a Poisson process.
+        if self.settings.slip_rate > 0.0
+            && self.noise.uniform() < 1.0 - (-self.settings.slip_rate * step).exp()
+        {
+            self.slip += self.noise.gaussian(self.settings.slip_angle);
+        }
+        if self.next_sample.is_none_or(|next| time >= next - 1e-6) {
+            let mounted = angle + self.settings.mounting_offset + self.slip;
+            let noisy = mounted + self.noise.gaussian(self.settings.noise);
+            // Within a turn, as the joints measure it.
+            let sample = self.quantize(PI - (PI - noisy).rem_euclid(TAU));
+            self.pending
+                .push_back((time + self.settings.latency.max(0.0), sample));
+            // Sampled every step at most.
+            self.next_sample = Some(if self.settings.rate > 0.0 {
+                (self.next_sample.unwrap_or(time) + 1.0 / self.settings.rate).max(time)
+            } else {
+                time
+            });
+        }
+        while let Some(&(at, sample)) = self.pending.front() {
+            if at > time + 1e-6 {
+                break;
+            }
+            self.reading = Some(sample);
+            self.pending.pop_front();
+        }
+        self.reading
+    }
+
+    /// Last angle read, once the first sample arrived.
+    pub fn reading(&self) -> Option<f32> {
+        self.reading
+    }
+
+    /// Angle the shaft slipped by since the start, in rad.
+    pub fn slip(&self) -> f32 {
+        self.slip
+    }
+}
+
+/// A reading of an IMU, in its frame: that of its link, unless misaligned.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImuReading {
+    /// Angular velocity, in rad/s.
+    pub angular_velocity: Vec3,
+    /// Acceleration less the gravity, in m/s².
+    pub specific_force: Vec3,
+}
+
+/// An IMU measuring the motion of the link of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Imu {
+    pub settings: ImuSettings,
+    /// Prefix of the telemetry channels of the readings.
+    pub channel: String,
+    noise: Noise,
+    /// Time and linear velocity of the last measurement, to differentiate.
+    last: Option<(f32, Vec3)>,
+    /// Time of the next sample.
+    next_sample: Option<f32>,
+    reading: Option<ImuReading>,
+}
+
+impl Imu {
+    pub fn new(settings: ImuSettings, channel: String, seed: u64) -> Self {
+        Self {
+            settings,
+            channel,
+            noise: Noise::new(seed),
+            last: None,
+            next_sample: None,
+            reading: None,
+        }
+    }
+
+    /// Measures the motion of the link at `time`, from its `rotation`, its angular and linear
+    /// velocities and the `gravity`, in the world frame, returning the current reading.
+    ///
+    /// The first measurement only starts the differentiation of the velocity, and a time before
+    /// the last one, when the simulation is rewound, starts the IMU over.
+    pub fn measure(
+        &mut self,
+        time: f32,
+        rotation: Quat,
+        angular_velocity: Vec3,
+        linear_velocity: Vec3,
+        gravity: Vec3,
+    ) -> Option<ImuReading> {
+        if self.last.is_some_and(|(last, _)| time < last) {
+            self.last = None;
+            self.next_sample = None;
+            self.reading = None;
+        }
+        let (last, velocity) = self.last.replace((time, linear_velocity))?;
+        let step = time - last;
+        if step <= 0.0 {
+            return self.reading;
+        }
+        if self.next_sample.is_none_or(|next| time >= next - 1e-6) {
+            // The noise is white over the bandwidth of the samples.
+            let period = if self.settings.rate > 0.0 {
+                1.0 / self.settings.rate
+            } else {
+                step
+            };
+            let deviation = |density: f32| density / period.sqrt();
+            let acceleration = (linear_velocity - velocity) / step;
+            let to_imu = (rotation * self.settings.mounting()).inverse();
+            self.reading = Some(ImuReading {
+                angular_velocity: to_imu * angular_velocity
+                    + self.settings.gyroscope_bias
+                    + self
+                        .noise
+                        .vector(deviation(self.settings.gyroscope_noise_density)),
+                specific_force: to_imu * (acceleration - gravity)
+                    + self.settings.accelerometer_bias
+                    + self
+                        .noise
+                        .vector(deviation(self.settings.accelerometer_noise_density)),
+            });
+            self.next_sample = Some(if self.settings.rate > 0.0 {
+                (self.next_sample.unwrap_or(time) + period).max(time)
+            } else {
+                time
+            });
+        }
+        self.reading
+    }
+
+    /// Last reading, once the first sample was taken.
+    pub fn reading(&self) -> Option<ImuReading> {
+        self.reading
+    }
+}
+
+/// Angle of the revolute joint of `entity` as the controllers see it: the measured angle, once
+/// it comes out of the [latency](crate::latency) of its loop, if it has one.
+pub fn joint_angle(
+    context: &RapierContext,
+    encoders: &Query<&Encoder>,
+    latencies: &Query<&LoopLatency>,
+    entity: Entity,
+) -> Option<f32> {
+    match latencies.get(entity) {
+        Ok(latency) => latency.reading(),
+        Err(_) => measured_angle(context, encoders, entity),
+    }
+}
+
+/// Angle of the revolute joint of `entity` as measured: the reading of its encoder, if it has
+/// one, or the exact angle.
+pub fn measured_angle(
+    context: &RapierContext,
+    encoders: &Query<&Encoder>,
+    entity: Entity,
+) -> Option<f32> {
+    match encoders.get(entity) {
+        Ok(encoder) => encoder.reading(),
+        Err(_) => context.impulse_revolute_joint_angle(entity),
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SensorSettings>("sensors", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds the configured encoders to the joints, as they spawn or when the configuration changes.
+fn configure_encoders(
+    mut commands: Commands,
+    settings: Res<Persistent<SensorSettings>>,
+    joints: Query<(Entity, &Link, Option<&Encoder>), With<ImpulseJoint>>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, encoder) in &joints {
+        let configured = settings
+            .encoders
+            .iter()
+            .enumerate()
+            .find(|(_, encoder)| encoder.link == link.name)
+            .map(|(index, encoder)| {
+                if settings.mounting_errors {
+                    (index, encoder.clone())
+                } else {
+                    (index, encoder.ideal_mounting())
+                }
+            });
+        match (configured, encoder) {
+            (Some((_, configured)), Some(encoder)) if encoder.settings == configured => {}
+            (Some((index, configured)), _) => {
+                let channel = format!("{ENCODER_PREFIX}{}", link.name);
+                commands.entity(entity).insert(Encoder::new(
+                    configured,
+                    plants::namespaced(&link.plant, &channel),
+                    settings.seed + index as u64,
+                ));
+                info!(target: subsystem::CONTROL, "Encoder on {}", link.path());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Encoder>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Adds the configured IMUs to the links, as they spawn or when the configuration changes.
+fn configure_imus(
+    mut commands: Commands,
+    settings: Res<Persistent<SensorSettings>>,
+    links: Query<(Entity, &Link, Option<&Imu>), With<RigidBody>>,
+    added: Query<(), Added<RigidBody>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, imu) in &links {
+        let configured = settings
+            .imus
+            .iter()
+            .enumerate()
+            .find(|(_, imu)| imu.link == link.name)
+            .map(|(index, imu)| {
+                if settings.mounting_errors {
+                    (index, imu.clone())
+                } else {
+                    (index, imu.ideal_mounting())
+                }
+            });
+        match (configured, imu) {
+            (Some((_, configured)), Some(imu)) if imu.settings == configured => {}
+            (Some((index, configured)), _) => {
+                let channel = format!("{IMU_PREFIX}{}", link.name);
+                let seed = settings.seed + (settings.encoders.len() + index) as u64;
+                commands
+                    .entity(entity)
+                    .insert(Imu::new(
+                        configured,
+                        plants::namespaced(&link.plant, &channel),
+                        seed,
+                    ))
+                    .insert_if_new(Velocity::default());
+                info!(target: subsystem::CONTROL, "IMU on {}", link.path());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Imu>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step.
+fn measure(
+    clock: Res<SimClock>,
+    mut encoders: Query<(Entity, &mut Encoder)>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let Ok(context) = contexts.get_single() else {
+        return;
+    };
+    if clock.delta().is_zero() {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (entity, mut encoder) in &mut encoders {
+        let Some(angle) = context.impulse_revolute_joint_angle(entity) else {
+            continue;
+        };
+        let (Some(reading), Some(telemetry)) = (encoder.measure(time, angle), telemetry.as_mut())
+        else {
+            continue;
+        };
+        telemetry.record(&encoder.channel, time, reading);
+        if encoder.settings.slip_rate > 0.0 {
+            telemetry.record(&format!("{}/slip", encoder.channel), time, encoder.slip());
+        }
+    }
+}
+
+/// Measures the motion of the links after each physics step.
+fn measure_imus(
+    clock: Res<SimClock>,
+    mut imus: Query<(&mut Imu, &Transform, &Velocity)>,
+    configurations: Query<&RapierConfiguration>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    if clock.delta().is_zero() {
+        return;
+    }
+    let gravity = configurations
+        .iter()
+        .next()
+        .map_or(Vec3::NEG_Y * 9.81, |configuration| configuration.gravity);
+    let time = clock.elapsed_secs();
+    for (mut imu, transform, velocity) in &mut imus {
+        let reading = imu.measure(
+            time,
+            transform.rotation,
+            velocity.angvel,
+            velocity.linvel,
+            gravity,
+        );
+        let (Some(reading), Some(telemetry)) = (reading, telemetry.as_mut()) else {
+            continue;
+        };
+        for (sensor, value) in [
+            ("gyroscope", reading.angular_velocity),
+            ("accelerometer", reading.specific_force),
+        ] {
+            for (axis, value) in ["x", "y", "z"].into_iter().zip(value.to_array()) {
+                telemetry.record(&format!("{}/{sensor}/{axis}", imu.channel), time, value);
+            }
+        }
+    }
+}
+
+/// Panel to tune the encoders and the IMUs.
+fn sensors_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SensorSettings>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Sensors")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut removed = None;
+            for (index, encoder) in edited.encoders.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut encoder.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.counts_per_revolution)
+                            .range(0..=1_000_000)
+                            .prefix("Counts: "),
+                    )
+                    .on_hover_text("Counts per revolution, 0 for an exact angle");
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.noise)
+                            .range(0.0..=1.0)
+                            .speed(0.0001)
+                            .prefix("Noise: ")
+                            .suffix(" rad"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.rate)
+                            .range(0.0..=100_000.0)
+                            .prefix("Rate: ")
+                            .suffix(" Hz"),
+                    )
+                    .on_hover_text("0 to sample every physics step");
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.latency)
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .prefix("Latency: ")
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.mounting_offset)
+                            .range(-PI..=PI)
+                            .speed(0.001)
+                            .prefix("Offset: ")
+                            .suffix(" rad"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.slip_rate)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Slips: ")
+                            .suffix(" /s"),
+                    )
+                    .on_hover_text("Average slips of the shaft per second");
+                    ui.add(
+                        egui::DragValue::new(&mut encoder.slip_angle)
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .prefix("Slip: ")
+                            .suffix(" rad"),
+                    )
+                    .on_hover_text("Standard deviation of the angle of a slip");
+                });
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.encoders.remove(index);
+            }
+            if ui.button("Add an encoder").clicked() {
+                edited.encoders.push(EncoderSettings::default());
+            }
+
+            ui.separator();
+            let mut removed = None;
+            for (index, imu) in edited.imus.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("IMU on");
+                    ui.text_edit_singleline(&mut imu.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                for (label, bias, density, unit) in [
+                    (
+                        "Gyroscope",
+                        &mut imu.gyroscope_bias,
+                        &mut imu.gyroscope_noise_density,
+                        "rad/s",
+                    ),
+                    (
+                        "Accelerometer",
+                        &mut imu.accelerometer_bias,
+                        &mut imu.accelerometer_noise_density,
+                        "m/s²",
+                    ),
+                ] {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{label} bias"));
+                        for value in [&mut bias.x, &mut bias.y, &mut bias.z] {
+                            ui.add(egui::DragValue::new(value).speed(0.001));
+                        }
+                        ui.label(unit);
+                    });
+                    ui.add(
+                        egui::DragValue::new(density)
+                            .range(0.0..=10.0)
+                            .speed(0.0001)
+                            .prefix(format!("{label} noise: "))
+                            .suffix(format!(" {unit}/√Hz")),
+                    );
+                }
+                ui.add(
+                    egui::DragValue::new(&mut imu.rate)
+                        .range(0.0..=100_000.0)
+                        .prefix("Rate: ")
+                        .suffix(" Hz"),
+                )
+                .on_hover_text("0 to sample every physics step");
+                ui.horizontal(|ui| {
+                    ui.label("Misalignment");
+                    let misalignment = &mut imu.misalignment;
+                    for value in [
+                        &mut misalignment.x,
+                        &mut misalignment.y,
+                        &mut misalignment.z,
+                    ] {
+                        ui.add(egui::DragValue::new(value).range(-PI..=PI).speed(0.001));
+                    }
+                    ui.label("rad");
+                });
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.imus.remove(index);
+            }
+            if ui.button("Add an IMU").clicked() {
+                edited.imus.push(ImuSettings::default());
+            }
+
+            ui.separator();
+            ui.checkbox(&mut edited.mounting_errors, "Mounting errors")
+                .on_hover_text("Apply the mounting offsets, slips and misalignments");
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("sensors", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("sensors", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
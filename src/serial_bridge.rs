@@ -0,0 +1,409 @@
+//! Hardware-in-the-loop bridge to a microcontroller on a serial port, so embedded control loops
+//! can be tested against the virtual plant before the real one.
+//!
+//! After every physics step, the simulator sends the counts of the encoders of
+//! `serial_bridge.json` (the motor and the pendulum, by default) in an encoder frame; the
+//! firmware answers with a torque frame, applied to the rotor until the next one. While the
+//! bridge runs, the motor is released, so the torque of the firmware is the only one driving
+//! the rotor.
+//!
+//! Frames are `0xA5`, the kind, a sequence number, the length of the payload, the payload and
+//! the CRC-8 (polynomial `0x07`) of the kind, the sequence number, the length and the payload.
+//! Numbers are little endian:
+//! - encoder frame (kind `0x01`): one `i32` count per encoder, numbered by the simulator;
+//! - torque frame (kind `0x02`): the torque on the rotor, an `f32` in N·m, numbered like the
+//!   encoder frame it answers.
+//!
+//! With `lockstep`, the clock waits for the answer to an encoder frame before the next step, up
+//! to `timeout_ms`, so the firmware can run slower than the simulation. Every torque frame
+//! feeds the [`Watchdog`]; while it is tripped, no torque is applied.
+use std::{
+    f32::consts::TAU,
+    io::{self, Read, Write},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::ExternalImpulse;
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+    setpoints::{Setpoints, MOTOR_RELEASE},
+    telemetry::{Telemetry, MOTOR_ANGLE, PENDULUM_ANGLE},
+    watchdog::Watchdog,
+};
+
+/// First byte of every frame.
+pub const SYNC: u8 = 0xA5;
+/// Kind of the frames of encoder counts, sent by the simulator.
+pub const ENCODER_FRAME: u8 = 0x01;
+/// Kind of the frames of torque, sent by the firmware.
+pub const TORQUE_FRAME: u8 = 0x02;
+/// Telemetry channel of the torque commanded by the firmware, in N·m.
+pub const SERIAL_TORQUE: &str = "serial/torque";
+
+/// Bytes of a frame around its payload.
+const FRAME_OVERHEAD: usize = 5;
+/// Time a read of the port waits for bytes.
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// CRC-8 with the polynomial `0x07`, starting from zero.
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// A frame exchanged with the firmware.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub kind: u8,
+    pub seq: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// The encoder frame carrying `counts`.
+    pub fn encoders(seq: u8, counts: &[i32]) -> Self {
+        Self {
+            kind: ENCODER_FRAME,
+            seq,
+            payload: counts
+                .iter()
+                .flat_map(|count| count.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    /// The torque frame carrying `torque`, in N·m.
+    pub fn torque(seq: u8, torque: f32) -> Self {
+        Self {
+            kind: TORQUE_FRAME,
+            seq,
+            payload: torque.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// The torque of a torque frame, in N·m.
+    pub fn torque_value(&self) -> Option<f32> {
+        if self.kind != TORQUE_FRAME {
+            return None;
+        }
+        Some(f32::from_le_bytes(self.payload.as_slice().try_into().ok()?))
+    }
+
+    /// The frame as sent on the line. Payloads are at most 255 bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.payload.len() + FRAME_OVERHEAD);
+        bytes.extend([SYNC, self.kind, self.seq, self.payload.len() as u8]);
+        bytes.extend(&self.payload);
+        bytes.push(crc8(&bytes[1..]));
+        bytes
+    }
+}
+
+/// Splits the bytes received into frames, skipping those that don't start a valid one.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Appends the bytes received and returns the frames they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        self.buffer.extend(bytes);
+        let mut frames = Vec::new();
+        loop {
+            let start = self
+                .buffer
+                .iter()
+                .position(|&byte| byte == SYNC)
+                .unwrap_or(self.buffer.len());
+            self.buffer.drain(..start);
+            let Some(&len) = self.buffer.get(3) else {
+                return frames;
+            };
+            let size = usize::from(len) + FRAME_OVERHEAD;
+            if self.buffer.len() < size {
+                return frames;
+            }
+            if crc8(&self.buffer[1..size - 1]) == self.buffer[size - 1] {
+                frames.push(Frame {
+                    kind: self.buffer[1],
+                    seq: self.buffer[2],
+                    payload: self.buffer[4..size - 1].to_vec(),
+                });
+                self.buffer.drain(..size);
+            } else {
+                // Not a frame: look for the next start.
+                self.buffer.drain(..1);
+            }
+        }
+    }
+}
+
+/// Count of an encoder of `counts_per_turn` at `angle`, in radians.
+pub fn counts(angle: f32, counts_per_turn: u32) -> i32 {
+    (angle / TAU * counts_per_turn as f32).round() as i32
+}
+
+/// The exchange with the firmware.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SerialBridgeSettings {
+    pub baud_rate: u32,
+    /// Telemetry channels of the angles sent as encoder counts, in order.
+    pub encoders: Vec<String>,
+    pub counts_per_turn: u32,
+    /// Path of the link the torque turns.
+    pub rotor: String,
+    /// Axis of the rotor, in the frame of its link.
+    pub axis: Vec3,
+    /// Largest torque applied, in N·m, whatever the firmware commands.
+    pub max_torque: f32,
+    /// Whether the clock waits for the answer to every encoder frame.
+    pub lockstep: bool,
+    /// Time the clock waits for an answer, in ms.
+    pub timeout_ms: u64,
+}
+
+impl Default for SerialBridgeSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            encoders: vec![MOTOR_ANGLE.to_string(), PENDULUM_ANGLE.to_string()],
+            counts_per_turn: 4096,
+            rotor: "column".to_string(),
+            axis: Vec3::Y,
+            max_torque: 1.0,
+            lockstep: false,
+            timeout_ms: 100,
+        }
+    }
+}
+
+/// Bridges the plant to the firmware on the given serial port.
+pub struct SerialBridgePlugin {
+    /// The port, e.g. `/dev/ttyACM0` or `COM3`.
+    pub port: String,
+    pub settings: SerialBridgeSettings,
+}
+
+impl Plugin for SerialBridgePlugin {
+    fn build(&self, app: &mut App) {
+        let opened = serialport::new(&self.port, self.settings.baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .and_then(|port| Ok((port.try_clone()?, port)));
+        let (reader, writer) = match opened {
+            Ok(ports) => ports,
+            Err(error) => {
+                app.world_mut().send_event(ErrorEvent::from(Error::io(
+                    self.port.clone(),
+                    io::Error::from(error),
+                )));
+                return;
+            }
+        };
+        info!(
+            target: subsystem::IO,
+            "Serial bridge on {} at {} baud", self.port, self.settings.baud_rate
+        );
+        let (frames, receiver) = mpsc::channel();
+        let name = self.port.clone();
+        thread::spawn(move || read_frames(name, reader, frames));
+
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(self.settings.clone())
+            .insert_resource(SerialBridge {
+                port: Mutex::new(writer),
+                frames: Mutex::new(receiver),
+                seq: 0,
+                awaiting: None,
+                torque: 0.0,
+            })
+            .add_systems(PreUpdate, receive_torque)
+            .add_systems(Update, apply_torque)
+            .add_systems(PostUpdate, hold_clock.in_set(SimClockSet::Gate))
+            .add_systems(PostUpdate, send_counts.after(SimClockSet::Advance));
+    }
+}
+
+/// Reads the frames of the firmware until the bridge is dropped or the port fails.
+fn read_frames(name: String, mut port: Box<dyn SerialPort>, frames: mpsc::Sender<Frame>) {
+    let mut decoder = FrameDecoder::default();
+    let mut buffer = [0; 256];
+    loop {
+        match port.read(&mut buffer) {
+            Ok(size) => {
+                for frame in decoder.push(&buffer[..size]) {
+                    if frames.send(frame).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) => {}
+            Err(error) => {
+                warn!(target: subsystem::IO, "Serial bridge on {name} stopped: {error}");
+                return;
+            }
+        }
+    }
+}
+
+/// State of the exchange with the firmware.
+#[derive(Resource)]
+pub struct SerialBridge {
+    port: Mutex<Box<dyn SerialPort>>,
+    frames: Mutex<mpsc::Receiver<Frame>>,
+    /// Sequence number of the next encoder frame.
+    seq: u8,
+    /// Sequence number of the encoder frame waiting for its answer, and when it was sent.
+    awaiting: Option<(u8, Instant)>,
+    /// Last torque commanded, in N·m.
+    torque: f32,
+}
+
+impl SerialBridge {
+    /// Last torque commanded by the firmware, in N·m.
+    pub fn torque(&self) -> f32 {
+        self.torque
+    }
+}
+
+/// Takes the torque of the last frame of the firmware.
+fn receive_torque(
+    mut bridge: ResMut<SerialBridge>,
+    settings: Res<SerialBridgeSettings>,
+    mut watchdog: Option<ResMut<Watchdog>>,
+) {
+    let bridge = &mut *bridge;
+    let frames = bridge
+        .frames
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner());
+    for frame in frames.try_iter() {
+        let Some(torque) = frame.torque_value() else {
+            debug!(target: subsystem::IO, "Ignoring a serial frame of kind {}", frame.kind);
+            continue;
+        };
+        if bridge.awaiting.is_some_and(|(seq, _)| seq == frame.seq) {
+            bridge.awaiting = None;
+        }
+        let refused = watchdog
+            .as_mut()
+            .is_some_and(|watchdog| !watchdog.feed("serial", Instant::now()));
+        bridge.torque = if refused || !torque.is_finite() {
+            0.0
+        } else {
+            torque.clamp(-settings.max_torque, settings.max_torque)
+        };
+    }
+    if watchdog.is_some_and(|watchdog| watchdog.trip().is_some()) {
+        bridge.torque = 0.0;
+    }
+}
+
+/// Keeps the motor released and applies the torque to the rotor, for the next step.
+fn apply_torque(
+    mut commands: Commands,
+    bridge: Res<SerialBridge>,
+    settings: Res<SerialBridgeSettings>,
+    clock: Res<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    mut rotors: Query<(Entity, &Link, &Transform, Option<&mut ExternalImpulse>)>,
+) {
+    if setpoints.get(MOTOR_RELEASE) != Some(1.0) {
+        setpoints.set(MOTOR_RELEASE, 1.0);
+    }
+    let dt = clock.delta_secs();
+    if dt == 0.0 || bridge.torque == 0.0 {
+        return;
+    }
+    let Some((entity, _, transform, impulse)) = rotors
+        .iter_mut()
+        .find(|(_, link, _, _)| link.path() == settings.rotor)
+    else {
+        return;
+    };
+    let torque_impulse =
+        transform.rotation * settings.axis.normalize_or(Vec3::Y) * bridge.torque * dt;
+    match impulse {
+        Some(mut impulse) => impulse.torque_impulse += torque_impulse,
+        None => {
+            commands.entity(entity).insert(ExternalImpulse {
+                torque_impulse,
+                ..default()
+            });
+        }
+    }
+}
+
+/// Holds the clock until the firmware answers, in lockstep.
+fn hold_clock(
+    mut bridge: ResMut<SerialBridge>,
+    settings: Res<SerialBridgeSettings>,
+    mut clock: ResMut<SimClock>,
+) {
+    let Some((seq, sent)) = bridge.awaiting.filter(|_| settings.lockstep) else {
+        return;
+    };
+    if sent.elapsed() < Duration::from_millis(settings.timeout_ms) {
+        clock.hold();
+    } else {
+        warn!(target: subsystem::IO, "No answer to the serial frame {seq}, going on");
+        bridge.awaiting = None;
+    }
+}
+
+/// Sends the encoder counts after every physics step.
+fn send_counts(
+    mut bridge: ResMut<SerialBridge>,
+    settings: Res<SerialBridgeSettings>,
+    clock: Res<SimClock>,
+    mut telemetry: ResMut<Telemetry>,
+) {
+    if clock.delta() == Duration::ZERO {
+        return;
+    }
+    let counts = settings
+        .encoders
+        .iter()
+        .map(|channel| {
+            counts(
+                telemetry.latest(channel).unwrap_or_default(),
+                settings.counts_per_turn,
+            )
+        })
+        .collect::<Vec<_>>();
+    let frame = Frame::encoders(bridge.seq, &counts).encode();
+    let written = bridge
+        .port
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner())
+        .write_all(&frame);
+    if let Err(error) = written {
+        warn!(target: subsystem::IO, "Failed to send the encoder counts: {error}");
+    }
+    bridge.awaiting = Some((bridge.seq, Instant::now()));
+    bridge.seq = bridge.seq.wrapping_add(1);
+    telemetry.record(SERIAL_TORQUE, clock.elapsed_secs(), bridge.torque);
+}
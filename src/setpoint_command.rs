@@ -0,0 +1,352 @@
+//! This module provides a panel to command any setpoint by hand: a value typed in and sent at
+//! once, or a signal generator driving the setpoint over the simulated time.
+//!
+//! The generator plays a step, a square wave, a sine wave, a chirp, sweeping linearly from its
+//! start to its end frequency, or a ramp, rising by its amplitude over each period, all around an
+//! offset. While it plays, the setpoint is written after every physics step, and the generated
+//! value is recorded as the `generator/output` telemetry channel; stopping it puts the setpoint
+//! back on the offset. A chirp stops by itself at the end of its sweep.
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    setpoints::{Setpoints, MOTOR_POSITION, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+
+/// Value of the signal generator.
+pub const GENERATOR_OUTPUT: &str = "generator/output";
+
+pub struct SetpointCommandPlugin;
+
+impl Plugin for SetpointCommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GeneratorPlayback>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                play_generator
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<SetpointCommandSettings>>),
+            )
+            .add_systems(
+                Update,
+                setpoint_command_panel
+                    .run_if(resource_exists::<Persistent<SetpointCommandSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Shape of the generated signal.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratorKind {
+    /// From the offset to the offset plus the amplitude, after the delay.
+    #[default]
+    Step,
+    Square,
+    Sine,
+    /// A sine sweeping linearly from the start to the end frequency over the duration.
+    Chirp,
+    /// A sawtooth, rising by the amplitude over each period.
+    Ramp,
+}
+
+impl GeneratorKind {
+    pub const ALL: [Self; 5] = [
+        Self::Step,
+        Self::Square,
+        Self::Sine,
+        Self::Chirp,
+        Self::Ramp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Step => "Step",
+            Self::Square => "Square",
+            Self::Sine => "Sine",
+            Self::Chirp => "Chirp",
+            Self::Ramp => "Ramp",
+        }
+    }
+}
+
+/// Represents the configuration of the signal generator.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct SignalGenerator {
+    pub kind: GeneratorKind,
+    /// In the unit of the setpoint.
+    pub amplitude: f32,
+    pub offset: f32,
+    /// Frequency of the periodic signals, and at the start of the chirp, in Hz.
+    pub frequency: f32,
+    /// Frequency at the end of the chirp, in Hz.
+    pub end_frequency: f32,
+    /// Duration of the chirp, in s.
+    pub duration: f32,
+    /// Time of the step, in s.
+    pub delay: f32,
+}
+
+impl Default for SignalGenerator {
+    fn default() -> Self {
+        Self {
+            kind: GeneratorKind::Step,
+            amplitude: 1.0,
+            offset: 0.0,
+            frequency: 0.5,
+            end_frequency: 5.0,
+            duration: 10.0,
+            delay: 0.0,
+        }
+    }
+}
+
+impl SignalGenerator {
+    /// Value `elapsed` seconds after the start, or `None` once the chirp is over.
+    pub fn value(&self, elapsed: f32) -> Option<f32> {
+        let elapsed = elapsed.max(0.0);
+        let shape = match self.kind {
+            GeneratorKind::Step => {
+                if elapsed >= self.delay {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            GeneratorKind::Square => {
+                if (self.frequency * elapsed).fract() < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            GeneratorKind::Sine => (TAU * self.frequency * elapsed).sin(),
+            GeneratorKind::Chirp => {
+                if elapsed >= self.duration {
+                    return None;
+                }
+                let sweep = (self.end_frequency - self.frequency) / self.duration;
+                let cycles = self.frequency * elapsed + sweep * elapsed * elapsed / 2.0;
+                (TAU * cycles).sin()
+            }
+            GeneratorKind::Ramp => (self.frequency * elapsed).fract(),
+        };
+        Some(self.offset + self.amplitude * shape)
+    }
+}
+
+/// Represents the configuration of the setpoint command panel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SetpointCommandSettings {
+    /// Setpoint commanded.
+    pub setpoint: String,
+    /// Value sent by hand.
+    pub value: f32,
+    pub generator: SignalGenerator,
+}
+
+impl Default for SetpointCommandSettings {
+    fn default() -> Self {
+        Self {
+            setpoint: MOTOR_VELOCITY.to_string(),
+            value: 0.0,
+            generator: SignalGenerator::default(),
+        }
+    }
+}
+
+/// The generator playing, and the time it started at.
+#[derive(Default, Resource)]
+pub struct GeneratorPlayback {
+    pub playing: Option<f32>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<SetpointCommandSettings>("setpoint_command", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the setpoint from the generator, after each step of the simulation.
+fn play_generator(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<SetpointCommandSettings>>,
+    mut playback: ResMut<GeneratorPlayback>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let Some(started) = playback.playing else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    let generator = &settings.generator;
+    let value = generator.value(time - started).unwrap_or_else(|| {
+        playback.playing = None;
+        generator.offset
+    });
+    if setpoints.get(&settings.setpoint) != Some(value) {
+        setpoints.set(&settings.setpoint, value);
+    }
+    if let Some(mut telemetry) = telemetry {
+        telemetry.record(GENERATOR_OUTPUT, time, value);
+    }
+}
+
+/// Panel to send a value to a setpoint, or drive it from the signal generator.
+fn setpoint_command_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SetpointCommandSettings>>,
+    mut playback: ResMut<GeneratorPlayback>,
+    mut setpoints: ResMut<Setpoints>,
+    clock: Res<SimClock>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Setpoint command")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Setpoint");
+                ui.text_edit_singleline(&mut edited.setpoint);
+                egui::ComboBox::from_id_salt("setpoint_command_setpoint")
+                    .selected_text("Known")
+                    .show_ui(ui, |ui| {
+                        let mut names: Vec<&str> =
+                            setpoints.values.keys().map(String::as_str).collect();
+                        names.extend([MOTOR_VELOCITY, MOTOR_POSITION]);
+                        names.sort_unstable();
+                        names.dedup();
+                        for name in names {
+                            ui.selectable_value(&mut edited.setpoint, name.to_string(), name);
+                        }
+                    });
+            });
+            match setpoints.get(&edited.setpoint) {
+                Some(current) => ui.label(format!("Current value: {current:.4}")),
+                None => ui.label("Not set yet"),
+            };
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut edited.value).speed(0.01));
+                if ui.button("Send").clicked() {
+                    playback.playing = None;
+                    setpoints.set(&edited.setpoint, edited.value);
+                }
+                if ui.button("Zero").clicked() {
+                    playback.playing = None;
+                    setpoints.set(&edited.setpoint, 0.0);
+                }
+            });
+
+            ui.separator();
+            let generator = &mut edited.generator;
+            egui::ComboBox::from_label("Signal")
+                .selected_text(generator.kind.name())
+                .show_ui(ui, |ui| {
+                    for kind in GeneratorKind::ALL {
+                        ui.selectable_value(&mut generator.kind, kind, kind.name());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut generator.amplitude)
+                    .speed(0.01)
+                    .prefix("Amplitude: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut generator.offset)
+                    .speed(0.01)
+                    .prefix("Offset: "),
+            );
+            match generator.kind {
+                GeneratorKind::Step => {
+                    ui.add(
+                        egui::DragValue::new(&mut generator.delay)
+                            .speed(0.1)
+                            .range(0.0..=f32::MAX)
+                            .prefix("Delay: ")
+                            .suffix(" s"),
+                    );
+                }
+                GeneratorKind::Square | GeneratorKind::Sine | GeneratorKind::Ramp => {
+                    ui.add(
+                        egui::Slider::new(&mut generator.frequency, 0.01..=50.0)
+                            .logarithmic(true)
+                            .text("Frequency (Hz)"),
+                    );
+                }
+                GeneratorKind::Chirp => {
+                    ui.add(
+                        egui::Slider::new(&mut generator.frequency, 0.01..=50.0)
+                            .logarithmic(true)
+                            .text("Start frequency (Hz)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut generator.end_frequency, 0.01..=50.0)
+                            .logarithmic(true)
+                            .text("End frequency (Hz)"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut generator.duration)
+                            .speed(0.1)
+                            .range(0.1..=f32::MAX)
+                            .prefix("Duration: ")
+                            .suffix(" s"),
+                    );
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Start").clicked() {
+                    playback.playing = Some(clock.elapsed_secs());
+                }
+                if ui.button("Stop").clicked() && playback.playing.take().is_some() {
+                    setpoints.set(&edited.setpoint, edited.generator.offset);
+                }
+            });
+            if let Some(started) = playback.playing {
+                ui.label(format!(
+                    "Playing for {:.2} s",
+                    clock.elapsed_secs() - started
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("setpoint_command", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands
+                            .send_event(ErrorEvent::from(Error::save("setpoint_command", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,36 @@
+//! Setpoints commanded to the plants.
+//!
+//! Inputs of the plants (e.g. `motor/velocity`) are named values in the [`Setpoints`] resource.
+//! The keyboard, remote operators and fieldbus bridges write them; the plants apply them when
+//! the resource changes.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Setpoint of the velocity of the motor, in rad/s.
+pub const MOTOR_VELOCITY: &str = "motor/velocity";
+/// Setpoint of the position of the motor, in rad over the turns, for its position loop.
+pub const MOTOR_POSITION: &str = "motor/position";
+/// Releases the motor when not zero: it applies no torque, so the arm can be moved by hand.
+pub const MOTOR_RELEASE: &str = "motor/release";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+pub struct Setpoints {
+    pub values: BTreeMap<String, f32>,
+}
+
+impl Setpoints {
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.values.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, value: f32) {
+        match self.values.get_mut(name) {
+            Some(current) => *current = value,
+            None => {
+                self.values.insert(name.to_string(), value);
+            }
+        }
+    }
+}
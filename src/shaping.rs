@@ -0,0 +1,263 @@
+//! Comparison of input shapers on a step of a setpoint, by the residual vibration of a signal.
+//!
+//! The plant is stepped headlessly without a shaper, then through each [`Shaper`] tuned to the
+//! resonance of the unshaped response (or to the configured one). The responses are taken
+//! relative to a run without a step, so the motion left over from the start of the simulation
+//! cancels out. By default, the compliant axis is moved by a pulse of the torque of its motor,
+//! and the ringing of its shaft measured once the move is over.
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compliant_axis::{MOTOR_TORQUE, SHAFT_TORQUE},
+    control::{InputShaper, Shaper},
+    error::{Error, Result},
+    headless::headless_app,
+    plants::Plant,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// A step experiment, from the `shaping.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct ShapingExperiment {
+    /// Built-in plant stepped, unless `--plant` is given.
+    pub plant: String,
+    /// Setpoint stepped.
+    pub reference: String,
+    /// Signal whose vibration is measured.
+    pub output: String,
+    /// Height of the step.
+    pub step: f32,
+    /// Number of physics steps the reference is held before returning to zero, for a
+    /// point-to-point move; held until the end if zero.
+    pub hold_steps: usize,
+    /// Number of physics steps left to the plant to settle before the step.
+    pub settle_steps: usize,
+    /// Number of physics steps recorded from the step on.
+    pub steps: usize,
+    /// Resonance to cancel; estimated from the unshaped response if missing.
+    pub resonance: Option<Resonance>,
+}
+
+impl Default for ShapingExperiment {
+    fn default() -> Self {
+        Self {
+            plant: "compliant_axis".to_string(),
+            reference: MOTOR_TORQUE.to_string(),
+            output: SHAFT_TORQUE.to_string(),
+            step: 1.0,
+            // A quarter of a second, about two thirds of a period of the shaft.
+            hold_steps: 15,
+            settle_steps: 60,
+            steps: 600,
+            resonance: None,
+        }
+    }
+}
+
+/// A lightly damped mode.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Resonance {
+    /// Natural frequency, in Hz.
+    pub frequency: f32,
+    /// Damping ratio.
+    pub damping: f32,
+}
+
+/// Estimates the dominant resonance of a free response from its successive peaks: the
+/// frequency from their spacing, the damping from their logarithmic decrement.
+pub fn estimate_resonance(samples: &[f32], dt: f32) -> Option<Resonance> {
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    // Extrema of the deviation from the mean, alternately maxima and minima.
+    let peaks = samples
+        .windows(3)
+        .enumerate()
+        .filter(|(_, w)| (w[1] - w[0]) * (w[2] - w[1]) < 0.0 || (w[1] != w[0] && w[2] == w[1]))
+        .map(|(i, w)| (i + 1, (w[1] - mean).abs()))
+        .collect::<Vec<_>>();
+    if peaks.len() < 3 {
+        return None;
+    }
+    let half_period = (peaks.last()?.0 - peaks[0].0) as f32 / (peaks.len() - 1) as f32 * dt;
+    // Decrement per period, between every other peak.
+    let decrements = peaks
+        .windows(3)
+        .filter(|w| w[0].1 > 0.0 && w[2].1 > 0.0)
+        .map(|w| (w[0].1 / w[2].1).ln())
+        .collect::<Vec<_>>();
+    let decrement = decrements.iter().sum::<f32>() / decrements.len().max(1) as f32;
+    let decrement = decrement.max(0.0);
+    let damping = decrement / (4.0 * std::f32::consts::PI.powi(2) + decrement.powi(2)).sqrt();
+    let damped_frequency = 1.0 / (2.0 * half_period);
+    Some(Resonance {
+        frequency: damped_frequency / (1.0 - damping * damping).sqrt(),
+        damping,
+    })
+}
+
+/// Half the peak-to-peak excursion of the samples, a measure of the vibration left.
+pub fn residual_vibration(samples: &[f32]) -> f32 {
+    let (low, high) = samples
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+            (low.min(*value), high.max(*value))
+        });
+    if low.is_finite() {
+        (high - low) / 2.0
+    } else {
+        0.0
+    }
+}
+
+/// Response to a step, through a shaper or not.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapedStep {
+    pub shaper: Option<Shaper>,
+    /// Reference applied at each step.
+    pub reference: Vec<f32>,
+    /// Output at each step, relative to a run without a step.
+    pub response: Vec<f32>,
+    /// Residual vibration once the move and the longest shaper are over.
+    pub residual: f32,
+}
+
+/// Steps of every shaper, the unshaped one first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapingComparison {
+    pub dt: f32,
+    pub resonance: Resonance,
+    pub steps: Vec<ShapedStep>,
+}
+
+impl ShapingComparison {
+    /// Writes the references and the responses as CSV, for plotting.
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from("time");
+        for step in &self.steps {
+            let name = step.shaper.map_or("unshaped", Shaper::name);
+            csv.push_str(&format!(",{name} reference,{name} response"));
+        }
+        csv.push('\n');
+        let samples = self
+            .steps
+            .iter()
+            .map(|s| s.response.len())
+            .min()
+            .unwrap_or(0);
+        for k in 0..samples {
+            csv.push_str(&format!("{}", k as f32 * self.dt));
+            for step in &self.steps {
+                csv.push_str(&format!(",{},{}", step.reference[k], step.response[k]));
+            }
+            csv.push('\n');
+        }
+        fs::write(path, csv).map_err(|error| Error::io(path, error))
+    }
+}
+
+/// Steps `plant` without a shaper, then through each shaper.
+pub fn compare(
+    plant: &Plant,
+    experiment: &ShapingExperiment,
+    dt: f32,
+) -> Result<ShapingComparison> {
+    let baseline = run_step(plant, experiment, &mut |_| 0.0, dt).1;
+    let relative = |response: Vec<f32>| {
+        response
+            .iter()
+            .zip(&baseline)
+            .map(|(value, baseline)| value - baseline)
+            .collect::<Vec<_>>()
+    };
+    let (reference, response) = run_step(plant, experiment, &mut |step| step, dt);
+    let unshaped = relative(response);
+
+    // The free response, once the reference is back to zero.
+    let free = unshaped.get(experiment.hold_steps..).unwrap_or_default();
+    let resonance = match experiment.resonance {
+        Some(resonance) => resonance,
+        None => estimate_resonance(free, dt).ok_or_else(|| {
+            Error::Identification(format!(
+                "no resonance in the response of {}",
+                experiment.output
+            ))
+        })?,
+    };
+    let shapers = Shaper::ALL
+        .map(|shaper| InputShaper::new(shaper, resonance.frequency, resonance.damping, dt));
+    // Once the move and the longest shaper are over.
+    let settled =
+        experiment.hold_steps + shapers.iter().map(InputShaper::duration).max().unwrap_or(0);
+
+    let mut steps = vec![ShapedStep {
+        shaper: None,
+        residual: residual_vibration(unshaped.get(settled..).unwrap_or_default()),
+        reference,
+        response: unshaped,
+    }];
+    for (shaper, mut input_shaper) in Shaper::ALL.into_iter().zip(shapers) {
+        let (reference, response) = run_step(
+            plant,
+            experiment,
+            &mut |value| input_shaper.update(value),
+            dt,
+        );
+        let response = relative(response);
+        steps.push(ShapedStep {
+            shaper: Some(shaper),
+            residual: residual_vibration(response.get(settled..).unwrap_or_default()),
+            reference,
+            response,
+        });
+    }
+    Ok(ShapingComparison {
+        dt,
+        resonance,
+        steps,
+    })
+}
+
+/// Runs the experiment with the reference given by `reference` from the unshaped one at each
+/// step, returning the references and the outputs.
+fn run_step(
+    plant: &Plant,
+    experiment: &ShapingExperiment,
+    reference: &mut dyn FnMut(f32) -> f32,
+    dt: f32,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut app = headless_app(dt);
+    (plant.add)(&mut app);
+    app.init_resource::<Setpoints>()
+        .init_resource::<Telemetry>();
+    app.finish();
+    app.cleanup();
+    app.world_mut()
+        .resource_mut::<Setpoints>()
+        .set(&experiment.reference, 0.0);
+    for _ in 0..experiment.settle_steps {
+        app.update();
+    }
+
+    let mut references = Vec::with_capacity(experiment.steps);
+    let mut outputs = Vec::with_capacity(experiment.steps);
+    for step in 0..experiment.steps {
+        let held = experiment.hold_steps == 0 || step < experiment.hold_steps;
+        let value = reference(if held { experiment.step } else { 0.0 });
+        app.world_mut()
+            .resource_mut::<Setpoints>()
+            .set(&experiment.reference, value);
+        app.update();
+        let world = app.world();
+        let output = world
+            .resource::<Telemetry>()
+            .latest(&experiment.output)
+            .or_else(|| world.resource::<Setpoints>().get(&experiment.output));
+        references.push(value);
+        outputs.push(output.unwrap_or_default());
+    }
+    (references, outputs)
+}
@@ -0,0 +1,311 @@
+//! Physics and control on a dedicated thread, decoupled from the rendering.
+//!
+//! With `--sim-thread`, the plant and its controllers are simulated by a headless application
+//! of their own, on a thread paced at the fixed time step, as a [batch run](crate::batch) is.
+//! After every step it sends a snapshot of the state of the bodies, of the clock and of the
+//! telemetry recorded by the step to the window, which holds its own physics and only draws the
+//! latest snapshot, like the client of a [remote session](crate::remote). A frame stalled by a
+//! heavy panel (a big plot, the inspector) then skips snapshots instead of stalling the steps:
+//! the simulation keeps its real-time factor whatever the frame rate.
+//!
+//! The setpoints set in the window (jog, gamepad, HMI...) are sent to the thread when they
+//! change, and pausing the clock or changing its speed does the same to the thread. The
+//! controllers of the thread are configured from their configuration files when it starts; the
+//! panels of the window edit its own copies.
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{ecs::system::SystemState, prelude::*};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    batch,
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    fixed_step::FixedStep,
+    logging::subsystem,
+    plants::Plant,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+/// Simulates `plant` on a dedicated thread, and draws it in this application.
+pub struct SimThreadPlugin {
+    pub plant: Plant,
+    /// Time step of the physics, in seconds.
+    pub dt: f32,
+    /// Seed of the random draws of the simulation.
+    pub seed: u64,
+}
+
+impl Plugin for SimThreadPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.init_resource::<Setpoints>()
+            .insert_resource(SimThread::spawn(self.plant, self.dt, self.seed))
+            .add_systems(PostUpdate, hold_clock.in_set(SimClockSet::Gate))
+            .add_systems(
+                Update,
+                (
+                    receive_snapshots,
+                    send_setpoints.run_if(resource_changed::<Setpoints>),
+                    send_pause,
+                    send_speed,
+                    sim_thread_panel.run_if(has_ui),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// State of the simulation after a step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimSnapshot {
+    pub tick: u64,
+    pub elapsed: Duration,
+    /// State of the dynamic bodies, in spawn order.
+    pub bodies: Vec<BodyState>,
+    /// Samples recorded during the step.
+    pub telemetry: Telemetry,
+}
+
+/// Messages from the window to the simulation thread.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimCommand {
+    Setpoints(Setpoints),
+    Pause(bool),
+    /// Speed of the simulation relative to real time.
+    Speed(f32),
+}
+
+/// Messages from the simulation thread to the window.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimEvent {
+    Snapshot(SimSnapshot),
+    /// The simulation stopped on an error, reported in its log.
+    Stopped(String),
+}
+
+/// Whether the simulation thread runs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimStatus {
+    Running,
+    Stopped(String),
+}
+
+/// The simulation thread, and the last snapshot drawn.
+#[derive(Resource)]
+pub struct SimThread {
+    commands: Sender<SimCommand>,
+    events: Mutex<Receiver<SimEvent>>,
+    pub status: SimStatus,
+    pub tick: u64,
+    /// Snapshots skipped by the last frame, because it took longer than a step.
+    pub skipped: usize,
+    /// Simulated time per real time since the thread started.
+    pub real_time_factor: f32,
+    started: Instant,
+}
+
+impl SimThread {
+    /// Starts simulating `plant` and its controllers, stepping by `dt`.
+    pub fn spawn(plant: Plant, dt: f32, seed: u64) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (events, receiver) = mpsc::channel();
+        thread::spawn(move || simulate(plant, dt, seed, &command_receiver, &events));
+        Self {
+            commands,
+            events: Mutex::new(receiver),
+            status: SimStatus::Running,
+            tick: 0,
+            skipped: 0,
+            real_time_factor: 0.0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn send(&self, command: SimCommand) {
+        // A stopped thread is reported by its last event.
+        let _ = self.commands.send(command);
+    }
+
+    /// The events sent since the last call.
+    pub fn receive(&mut self) -> Vec<SimEvent> {
+        let events = self
+            .events
+            .get_mut()
+            .unwrap_or_else(|error| error.into_inner());
+        events.try_iter().collect()
+    }
+}
+
+/// Steps the application of `plant` in real time until the window goes away, sending a
+/// snapshot after every step.
+fn simulate(
+    plant: Plant,
+    dt: f32,
+    seed: u64,
+    commands: &Receiver<SimCommand>,
+    events: &Sender<SimEvent>,
+) {
+    let mut app = batch::app(&plant, dt, seed);
+    app.add_plugins(BodyStatePlugin);
+    app.world_mut().resource_mut::<FixedStep>().real_time = true;
+    let mut sent = BTreeMap::new();
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(SimCommand::Setpoints(setpoints)) => {
+                    *app.world_mut().resource_mut::<Setpoints>() = setpoints;
+                }
+                Ok(SimCommand::Pause(paused)) => {
+                    let mut clock = app.world_mut().resource_mut::<SimClock>();
+                    if paused {
+                        clock.pause();
+                    } else {
+                        clock.resume();
+                    }
+                }
+                Ok(SimCommand::Speed(speed)) => {
+                    app.world_mut().resource_mut::<SimClock>().set_speed(speed);
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            }
+        }
+        app.update();
+        if app.should_exit().is_some() {
+            let _ = events.send(SimEvent::Stopped(format!(
+                "{} stopped on the error logged above",
+                plant.name
+            )));
+            return;
+        }
+
+        let world = app.world_mut();
+        let mut state = SystemState::<Bodies>::new(world);
+        let bodies = body_state::capture(&state.get(world));
+        let clock = world.resource::<SimClock>();
+        let snapshot = SimSnapshot {
+            tick: clock.tick(),
+            elapsed: clock.elapsed(),
+            bodies,
+            telemetry: recorded_since(world.resource::<Telemetry>(), &mut sent),
+        };
+        if events.send(SimEvent::Snapshot(snapshot)).is_err() {
+            return;
+        }
+    }
+}
+
+/// The samples of `telemetry` past the numbers already `sent` of each channel, which then count
+/// them too.
+fn recorded_since(telemetry: &Telemetry, sent: &mut BTreeMap<String, usize>) -> Telemetry {
+    let mut recorded = Telemetry::default();
+    for (channel, samples) in &telemetry.channels {
+        let count = sent.entry(channel.clone()).or_default();
+        if let Some(new) = samples.get(*count..).filter(|new| !new.is_empty()) {
+            *count = samples.len();
+            recorded.channels.insert(channel.clone(), new.to_vec());
+        }
+    }
+    recorded
+}
+
+/// The thread simulates, this application only draws it.
+fn hold_clock(mut clock: ResMut<SimClock>) {
+    clock.hold();
+}
+
+/// Draws the latest snapshot, and keeps the telemetry of all of them.
+fn receive_snapshots(
+    mut commands: Commands,
+    mut sim: ResMut<SimThread>,
+    mut clock: ResMut<SimClock>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+    mut bodies: BodiesMut,
+) {
+    let mut latest = None;
+    let mut received = 0;
+    for event in sim.receive() {
+        match event {
+            SimEvent::Snapshot(snapshot) => {
+                received += 1;
+                if let Some(telemetry) = telemetry.as_mut() {
+                    telemetry.extend(snapshot.telemetry.clone());
+                }
+                latest = Some(snapshot);
+            }
+            SimEvent::Stopped(reason) => {
+                commands.send_event(ErrorEvent::from(Error::Config {
+                    name: "sim_thread".to_string(),
+                    message: reason.clone(),
+                }));
+                sim.status = SimStatus::Stopped(reason);
+            }
+        }
+    }
+    let Some(snapshot) = latest else {
+        return;
+    };
+    sim.skipped = received - 1;
+    sim.tick = snapshot.tick;
+    sim.real_time_factor =
+        snapshot.elapsed.as_secs_f32() / sim.started.elapsed().as_secs_f32().max(f32::EPSILON);
+    clock.restore(snapshot.tick, snapshot.elapsed);
+    if let Err(count) = body_state::apply(&snapshot.bodies, &mut bodies) {
+        warn!(
+            target: subsystem::PHYSICS,
+            "The simulation has {} bodies but this scene has {count}",
+            snapshot.bodies.len()
+        );
+    }
+}
+
+/// Sends the setpoints changed in this application.
+fn send_setpoints(sim: Res<SimThread>, setpoints: Res<Setpoints>) {
+    sim.send(SimCommand::Setpoints(setpoints.clone()));
+}
+
+/// Pauses and resumes the thread with the clock of this application.
+fn send_pause(sim: Res<SimThread>, clock: Res<SimClock>, mut paused: Local<bool>) {
+    if clock.is_paused() != *paused {
+        *paused = clock.is_paused();
+        sim.send(SimCommand::Pause(*paused));
+    }
+}
+
+/// Runs the thread at the speed of the clock of this application.
+fn send_speed(sim: Res<SimThread>, clock: Res<SimClock>, mut speed: Local<Option<f32>>) {
+    if *speed != Some(clock.speed()) {
+        *speed = Some(clock.speed());
+        sim.send(SimCommand::Speed(clock.speed()));
+    }
+}
+
+fn sim_thread_panel(mut contexts: EguiContexts, sim: Res<SimThread>) {
+    egui::Window::new("Simulation thread")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match &sim.status {
+                SimStatus::Running => ui.label("Running"),
+                SimStatus::Stopped(reason) => {
+                    ui.colored_label(egui::Color32::RED, format!("Stopped: {reason}"))
+                }
+            };
+            ui.label(format!("Step {}", sim.tick));
+            ui.label(format!("Real-time factor: {:.2}", sim.real_time_factor));
+            ui.label(format!("Steps skipped by the last frame: {}", sim.skipped));
+        });
+}
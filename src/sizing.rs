@@ -0,0 +1,403 @@
+//! Actuator sizing: the joints of a plant follow a desired trajectory headlessly, driven by
+//! ideal motors, and the speed and the torque each joint takes at every step make up its
+//! envelope, from which the peak torque, the RMS torque, the peak speed and the peak power
+//! follow. Every motor of a library of datasheet entries is then checked against the envelope
+//! of each joint: its torque-speed curve, through its gearbox and within its current limit,
+//! must cover every point of the motion with a margin, and its RMS current must stay within
+//! its continuous rating.
+//!
+//! The trajectory is given by waypoints of the positions of the joints, joined linearly and
+//! smoothed; the joints move to its start, then track it as a replayed recording. The sizing
+//! is read from `sizing.json`.
+use std::{f32::consts::FRAC_PI_2, fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actuators::DcActuator,
+    batch,
+    error::{Error, Result},
+    plants::Plant,
+    setpoints::Setpoints,
+    teach::{Replay, TaughtJoint, TeachSettings, Trajectory},
+    telemetry::{Telemetry, MOTOR_TORQUE},
+};
+
+/// Longest move of the joints to the start of the trajectory, in seconds.
+const APPROACH_TIMEOUT: f32 = 10.0;
+
+/// A joint driven along the trajectory.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SizedJoint {
+    #[serde(flatten)]
+    pub joint: TaughtJoint,
+    /// Telemetry channel of the torque applied on the joint, in N·m.
+    pub torque: String,
+}
+
+/// Positions of the joints at a time of the trajectory.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Waypoint {
+    /// Time from the start of the trajectory, in seconds.
+    pub time: f32,
+    /// Position of each joint, in the order of the joints.
+    pub positions: Vec<f32>,
+}
+
+/// A motor of the library, as its datasheet describes it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Datasheet {
+    pub name: String,
+    /// The motor and its gearbox; the link is ignored.
+    #[serde(flatten)]
+    pub motor: DcActuator,
+    /// Largest current the motor carries continuously, in A, or 0 for no rating.
+    #[serde(default)]
+    pub continuous_current: f32,
+}
+
+/// Represents the configuration of a sizing.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SizingSettings {
+    pub joints: Vec<SizedJoint>,
+    /// Waypoints of the trajectory, in increasing time.
+    pub waypoints: Vec<Waypoint>,
+    /// Window of the moving average smoothing the trajectory, in seconds.
+    pub smoothing: f32,
+    /// Factor on the torques taken by the motion, covering what the simulation misses.
+    pub margin: f32,
+    pub motors: Vec<Datasheet>,
+    /// Seed of the run.
+    pub seed: u64,
+}
+
+impl Default for SizingSettings {
+    fn default() -> Self {
+        let waypoint = |time: f32, position: f32| Waypoint {
+            time,
+            positions: vec![position],
+        };
+        Self {
+            joints: TeachSettings::default()
+                .joints
+                .into_iter()
+                .map(|joint| SizedJoint {
+                    joint,
+                    torque: MOTOR_TORQUE.to_string(),
+                })
+                .collect(),
+            waypoints: vec![
+                waypoint(0.0, 0.0),
+                waypoint(1.0, FRAC_PI_2),
+                waypoint(2.0, 0.0),
+            ],
+            smoothing: 0.2,
+            margin: 1.2,
+            motors: vec![
+                Datasheet {
+                    name: "Small".to_string(),
+                    motor: DcActuator::default(),
+                    continuous_current: 2.0,
+                },
+                Datasheet {
+                    name: "Large".to_string(),
+                    motor: DcActuator {
+                        stall_torque: 2.0,
+                        no_load_speed: 400.0,
+                        resistance: 0.4,
+                        current_limit: 20.0,
+                        ..default()
+                    },
+                    continuous_current: 8.0,
+                },
+            ],
+            seed: 1,
+        }
+    }
+}
+
+impl SizingSettings {
+    /// The trajectory through the waypoints, sampled every `dt` seconds and smoothed.
+    pub fn trajectory(&self, dt: f32) -> Result<Trajectory> {
+        let invalid = |message: String| Error::Config {
+            name: "sizing".to_string(),
+            message,
+        };
+        let Some(first) = self.waypoints.first() else {
+            return Err(invalid("the trajectory has no waypoints".to_string()));
+        };
+        if self.joints.is_empty() {
+            return Err(invalid("no joint is sized".to_string()));
+        }
+        for (index, waypoint) in self.waypoints.iter().enumerate() {
+            if waypoint.positions.len() != self.joints.len() {
+                return Err(invalid(format!(
+                    "the waypoint at {} s has {} positions for {} joints",
+                    waypoint.time,
+                    waypoint.positions.len(),
+                    self.joints.len()
+                )));
+            }
+            if index > 0 && waypoint.time <= self.waypoints[index - 1].time {
+                return Err(invalid(format!(
+                    "the waypoint at {} s doesn't follow the one before",
+                    waypoint.time
+                )));
+            }
+        }
+        let mut waypoints = Trajectory::default();
+        for waypoint in &self.waypoints {
+            waypoints.push(waypoint.time - first.time, waypoint.positions.clone());
+        }
+        let steps = (waypoints.duration() / dt).round() as usize;
+        let mut trajectory = Trajectory::default();
+        for step in 0..=steps {
+            let time = step as f32 * dt;
+            trajectory.push(time, waypoints.sample(time).0);
+        }
+        Ok(trajectory.smoothed(self.smoothing))
+    }
+}
+
+/// Speed and torque a joint takes along the trajectory.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Envelope {
+    pub joint: String,
+    /// Speed, in rad/s, and torque, in N·m, of the joint at each step.
+    pub points: Vec<[f32; 2]>,
+    /// Time step of the points, in seconds.
+    pub dt: f32,
+}
+
+impl Envelope {
+    pub fn peak_torque(&self) -> f32 {
+        self.points
+            .iter()
+            .map(|[_, torque]| torque.abs())
+            .fold(0.0, f32::max)
+    }
+
+    pub fn rms_torque(&self) -> f32 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+        let squares: f32 = self.points.iter().map(|[_, torque]| torque * torque).sum();
+        (squares / self.points.len() as f32).sqrt()
+    }
+
+    pub fn peak_speed(&self) -> f32 {
+        self.points
+            .iter()
+            .map(|[speed, _]| speed.abs())
+            .fold(0.0, f32::max)
+    }
+
+    /// Largest mechanical power delivered to the joint, in W.
+    pub fn peak_power(&self) -> f32 {
+        self.points
+            .iter()
+            .map(|[speed, torque]| speed * torque)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Whether a motor could drive a joint along the trajectory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub motor: String,
+    pub joint: String,
+    /// Why the motor falls short; none when it could execute the motion.
+    pub shortfalls: Vec<String>,
+}
+
+impl Verdict {
+    pub fn fits(&self) -> bool {
+        self.shortfalls.is_empty()
+    }
+}
+
+/// Checks the motor of `datasheet` against `envelope`, the torques raised by `margin`.
+pub fn check(datasheet: &Datasheet, envelope: &Envelope, margin: f32) -> Verdict {
+    let motor = &datasheet.motor;
+    let mut shortfalls = Vec::new();
+    let top_speed = motor.no_load_speed / motor.gear_ratio;
+    if envelope.peak_speed() > top_speed {
+        shortfalls.push(format!(
+            "a speed of {:.3} rad/s past its no-load speed of {top_speed:.3} rad/s",
+            envelope.peak_speed()
+        ));
+    }
+    let worst = envelope
+        .points
+        .iter()
+        .map(|&[speed, torque]| {
+            let taken = torque.abs() * margin;
+            (taken - motor.torque_limit(speed, torque), taken, speed)
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some((missing, taken, speed)) = worst.filter(|(missing, ..)| *missing > 0.0) {
+        shortfalls.push(format!(
+            "{missing:.3} N·m short of {taken:.3} N·m at {speed:.3} rad/s"
+        ));
+    }
+    let current = motor.current(envelope.rms_torque() * margin);
+    if datasheet.continuous_current > 0.0 && current > datasheet.continuous_current {
+        shortfalls.push(format!(
+            "an RMS current of {current:.3} A past its continuous {:.3} A",
+            datasheet.continuous_current
+        ));
+    }
+    Verdict {
+        motor: datasheet.name.clone(),
+        joint: envelope.joint.clone(),
+        shortfalls,
+    }
+}
+
+/// Outcome of a sizing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub envelopes: Vec<Envelope>,
+    /// Verdict of each motor on each joint, joint by joint.
+    pub verdicts: Vec<Verdict>,
+}
+
+impl Report {
+    /// Writes the envelopes as CSV, one row per joint and step with its speed, its torque and
+    /// its power.
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from("joint,time,speed,torque,power\n");
+        for envelope in &self.envelopes {
+            for (step, [speed, torque]) in envelope.points.iter().enumerate() {
+                csv.push_str(&format!(
+                    "{},{},{speed},{torque},{}\n",
+                    envelope.joint,
+                    step as f32 * envelope.dt,
+                    speed * torque
+                ));
+            }
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        fs::write(path, csv).map_err(|error| Error::io(path, error))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for envelope in &self.envelopes {
+            writeln!(
+                f,
+                "{}: peak torque {:.3} N·m, RMS torque {:.3} N·m, peak speed {:.3} rad/s, peak power {:.3} W",
+                envelope.joint,
+                envelope.peak_torque(),
+                envelope.rms_torque(),
+                envelope.peak_speed(),
+                envelope.peak_power()
+            )?;
+            for verdict in self
+                .verdicts
+                .iter()
+                .filter(|verdict| verdict.joint == envelope.joint)
+            {
+                if verdict.fits() {
+                    writeln!(f, "  {}: fits", verdict.motor)?;
+                } else {
+                    writeln!(f, "  {}: {}", verdict.motor, verdict.shortfalls.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drives the joints of `plant` along the trajectory of `settings`, stepping by `dt`, and
+/// checks the motors of the library against the envelope of each joint.
+pub fn run(plant: &Plant, settings: &SizingSettings, dt: f32) -> Result<Report> {
+    let trajectory = settings.trajectory(dt)?;
+    let duration = trajectory.duration();
+    let joints: Vec<TaughtJoint> = settings
+        .joints
+        .iter()
+        .map(|joint| joint.joint.clone())
+        .collect();
+    let mut replay = Replay::new(trajectory);
+    let mut envelopes: Vec<Envelope> = joints
+        .iter()
+        .map(|joint| Envelope {
+            joint: joint.name.clone(),
+            points: Vec::new(),
+            dt,
+        })
+        .collect();
+    let mut app = batch::app(plant, dt, settings.seed);
+    let mut previous: Option<Vec<f32>> = None;
+    let steps = ((duration + APPROACH_TIMEOUT) / dt).ceil() as usize;
+    for _ in 0..steps {
+        app.update();
+        if app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "sizing".to_string(),
+                message: format!("{} stopped on the error logged above", plant.name),
+            });
+        }
+        let world = app.world_mut();
+        let telemetry = world.resource::<Telemetry>();
+        let latest = |channel: &String| telemetry.latest(channel);
+        // The plant is spawned by the first update.
+        let Some(measured) = joints
+            .iter()
+            .map(|joint| latest(&joint.position))
+            .collect::<Option<Vec<f32>>>()
+        else {
+            continue;
+        };
+        let torques: Vec<f32> = settings
+            .joints
+            .iter()
+            .map(|joint| latest(&joint.torque).unwrap_or_default())
+            .collect();
+        if let Some(previous) = previous.as_ref().filter(|_| replay.is_tracking()) {
+            for (index, envelope) in envelopes.iter_mut().enumerate() {
+                let speed = (measured[index] - previous[index]) / dt;
+                envelope.points.push([speed, torques[index]]);
+            }
+        }
+        if replay.is_done() {
+            break;
+        }
+        let velocities = replay.update(&joints, &measured, dt);
+        previous = Some(measured);
+        let mut setpoints = world.resource_mut::<Setpoints>();
+        for (joint, velocity) in joints.iter().zip(velocities) {
+            setpoints.set(&joint.setpoint, velocity);
+        }
+    }
+    if !replay.is_done() {
+        return Err(Error::Config {
+            name: "sizing".to_string(),
+            message: format!(
+                "the joints didn't reach the start of the trajectory in {APPROACH_TIMEOUT} s"
+            ),
+        });
+    }
+    let verdicts = envelopes
+        .iter()
+        .flat_map(|envelope| {
+            settings
+                .motors
+                .iter()
+                .map(|datasheet| check(datasheet, envelope, settings.margin))
+        })
+        .collect();
+    Ok(Report {
+        envelopes,
+        verdicts,
+    })
+}
@@ -0,0 +1,422 @@
+//! Snapshots of the simulation: the state of the rigid bodies, of the clock, of the setpoints and
+//! of the controllers, taken and restored while the application runs.
+//!
+//! A snapshot is taken with a key (F5 by default) and restored with another one (F6), rewinding
+//! the simulation to it; a third one (F7) resets the scene to its state when it was spawned,
+//! without loading the model again, as does the `reset` action of the
+//! [input bindings](crate::input_bindings). The joints follow from the bodies they connect, and
+//! the controllers keep their gains but take back their integrators and the history of their
+//! derivatives. The telemetry recorded after a restored snapshot is dropped.
+//!
+//! Snapshots can be saved from the *Snapshots* panel to `<data dir>/snapshots`, e.g.
+//! `snapshot-1200.json`, and loaded back into any application running the same plant.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{ecs::system::SystemState, prelude::*};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    body_state::{self, Bodies, BodiesMut, BodyState, BodyStatePlugin},
+    clock::SimClock,
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    input_bindings::{InputAction, InputActions},
+    logging::subsystem,
+    lqr::LqrController,
+    pid_controller::PidController,
+    setpoints::Setpoints,
+    swing_up::{ModeSwitch, SwingUpController},
+    telemetry::Telemetry,
+};
+
+pub struct SnapshotsPlugin;
+
+impl Plugin for SnapshotsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<BodyStatePlugin>() {
+            app.add_plugins(BodyStatePlugin);
+        }
+        app.init_resource::<Snapshots>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(PreUpdate, capture_initial)
+            .add_systems(
+                Update,
+                (
+                    hotkeys.run_if(resource_exists::<Persistent<SnapshotSettings>>),
+                    snapshots_panel
+                        .run_if(resource_exists::<Persistent<SnapshotSettings>>)
+                        .run_if(has_ui),
+                ),
+            );
+    }
+}
+
+/// Represents the configuration of the snapshots.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    /// Keys taking a snapshot, restoring the last one and resetting the scene.
+    pub snapshot_key: KeyCode,
+    pub restore_key: KeyCode,
+    pub reset_key: KeyCode,
+    /// Directory of the saved snapshots, instead of `<data dir>/snapshots`.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        Self {
+            snapshot_key: KeyCode::F5,
+            restore_key: KeyCode::F6,
+            reset_key: KeyCode::F7,
+            directory: None,
+        }
+    }
+}
+
+impl SnapshotSettings {
+    pub fn directory(&self) -> PathBuf {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| data_dir().join("snapshots"))
+    }
+}
+
+/// State of the controllers of a joint; the ones it hasn't are `None`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ControllerStates {
+    pub pid: Option<PidController>,
+    pub lqr: Option<LqrController>,
+    pub swing_up: Option<SwingUpController>,
+    pub mode: Option<ModeSwitch>,
+}
+
+/// State of the simulation at a physics step.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Physics step of the snapshot.
+    pub tick: u64,
+    /// Simulated time of the snapshot.
+    pub elapsed: Duration,
+    /// The dynamic bodies, in spawn order.
+    pub bodies: Vec<BodyState>,
+    pub setpoints: Setpoints,
+    /// The controlled joints, in spawn order.
+    pub controllers: Vec<ControllerStates>,
+}
+
+impl Snapshot {
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+}
+
+/// The snapshot of the scene as it was spawned, and the last one taken or loaded.
+#[derive(Default, Resource)]
+pub struct Snapshots {
+    pub initial: Option<Snapshot>,
+    pub last: Option<Snapshot>,
+}
+
+/// The controlled joints.
+type Controllers<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static mut PidController>,
+        Option<&'static mut LqrController>,
+        Option<&'static mut SwingUpController>,
+        Option<&'static mut ModeSwitch>,
+    ),
+    Or<(
+        With<PidController>,
+        With<LqrController>,
+        With<SwingUpController>,
+        With<ModeSwitch>,
+    )>,
+>;
+
+/// Takes a snapshot of the simulation.
+pub fn capture(world: &mut World) -> Snapshot {
+    let mut state = SystemState::<(Bodies, Controllers)>::new(world);
+    let (bodies, controllers) = state.get_mut(world);
+    let bodies = body_state::capture(&bodies);
+    let mut controllers = controllers.iter().collect::<Vec<_>>();
+    controllers.sort_by_key(|(entity, ..)| *entity);
+    let controllers = controllers
+        .into_iter()
+        .map(|(_, pid, lqr, swing_up, mode)| ControllerStates {
+            pid: pid.cloned(),
+            lqr: lqr.cloned(),
+            swing_up: swing_up.cloned(),
+            mode: mode.cloned(),
+        })
+        .collect();
+    let (tick, elapsed) = world
+        .get_resource::<SimClock>()
+        .map_or((0, Duration::ZERO), |clock| (clock.tick(), clock.elapsed()));
+    Snapshot {
+        tick,
+        elapsed,
+        bodies,
+        setpoints: world
+            .get_resource::<Setpoints>()
+            .cloned()
+            .unwrap_or_default(),
+        controllers,
+    }
+}
+
+/// Restores `snapshot`, rewinding the clock to it.
+///
+/// Nothing is restored if the number of dynamic bodies differs. Controllers missing from the
+/// snapshot start over, e.g. the ones added after the scene was spawned.
+pub fn restore(world: &mut World, snapshot: &Snapshot) -> Result<()> {
+    let mut state = SystemState::<(BodiesMut, Controllers)>::new(world);
+    let (mut bodies, mut controllers) = state.get_mut(world);
+    if let Err(count) = body_state::apply(&snapshot.bodies, &mut bodies) {
+        return Err(Error::Config {
+            name: "snapshots".to_string(),
+            message: format!(
+                "the snapshot has {} bodies but the scene has {count}",
+                snapshot.bodies.len()
+            ),
+        });
+    }
+    let mut controllers = controllers.iter_mut().collect::<Vec<_>>();
+    controllers.sort_by_key(|(entity, ..)| *entity);
+    for (index, (_, pid, lqr, swing_up, mode)) in controllers.into_iter().enumerate() {
+        let saved = snapshot.controllers.get(index);
+        if let Some(mut pid) = pid {
+            pid.restore(saved.and_then(|saved| saved.pid.as_ref()));
+        }
+        if let Some(mut lqr) = lqr {
+            lqr.restore(saved.and_then(|saved| saved.lqr.as_ref()));
+        }
+        if let Some(mut swing_up) = swing_up {
+            swing_up.restore(saved.and_then(|saved| saved.swing_up.as_ref()));
+        }
+        if let Some(mut mode) = mode {
+            mode.restore(saved.and_then(|saved| saved.mode.as_ref()));
+        }
+    }
+
+    if let Some(mut clock) = world.get_resource_mut::<SimClock>() {
+        clock.restore(snapshot.tick, snapshot.elapsed);
+    }
+    world.insert_resource(snapshot.setpoints.clone());
+    if let Some(mut telemetry) = world.get_resource_mut::<Telemetry>() {
+        let time = snapshot.elapsed.as_secs_f32();
+        for samples in telemetry.channels.values_mut() {
+            samples.retain(|[sample, _]| *sample <= time);
+        }
+    }
+    Ok(())
+}
+
+/// Takes a snapshot and keeps it as the last one.
+pub fn take(world: &mut World) {
+    let snapshot = capture(world);
+    info!(target: subsystem::IO, "Snapshot taken at step {}", snapshot.tick);
+    world.resource_mut::<Snapshots>().last = Some(snapshot);
+}
+
+/// Restores the last snapshot.
+pub fn rewind(world: &mut World) {
+    let Some(snapshot) = world.resource::<Snapshots>().last.clone() else {
+        warn!(target: subsystem::IO, "No snapshot to restore");
+        return;
+    };
+    restore_or_report(world, &snapshot);
+}
+
+/// Resets the scene to its state when it was spawned.
+pub fn reset(world: &mut World) {
+    let Some(snapshot) = world.resource::<Snapshots>().initial.clone() else {
+        warn!(target: subsystem::IO, "The scene isn't spawned yet");
+        return;
+    };
+    restore_or_report(world, &snapshot);
+}
+
+fn restore_or_report(world: &mut World, snapshot: &Snapshot) {
+    match restore(world, snapshot) {
+        Ok(()) => info!(target: subsystem::IO, "Restored step {}", snapshot.tick),
+        Err(error) => {
+            world.send_event(ErrorEvent::from(error));
+        }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SnapshotSettings>("snapshots", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Keeps the state of the scene as it is spawned, at the start of the simulated time.
+fn capture_initial(mut commands: Commands, spawned: Query<(), Added<RigidBody>>) {
+    if spawned.is_empty() {
+        return;
+    }
+    commands.queue(|world: &mut World| {
+        let snapshot = Snapshot {
+            tick: 0,
+            elapsed: Duration::ZERO,
+            // The controllers start over.
+            controllers: Vec::new(),
+            ..capture(world)
+        };
+        world.resource_mut::<Snapshots>().initial = Some(snapshot);
+    });
+}
+
+fn hotkeys(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    actions: Option<Res<InputActions>>,
+    settings: Res<Persistent<SnapshotSettings>>,
+) {
+    if keys.just_pressed(settings.snapshot_key) {
+        commands.queue(take);
+    }
+    if keys.just_pressed(settings.restore_key) {
+        commands.queue(rewind);
+    }
+    let reset_action = actions.is_some_and(|actions| actions.just_pressed(InputAction::Reset));
+    if keys.just_pressed(settings.reset_key) || reset_action {
+        commands.queue(reset);
+    }
+}
+
+/// The saved snapshots of `directory`, by name.
+fn saved(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Panel to take, restore, save and load the snapshots, and to reset the scene.
+fn snapshots_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SnapshotSettings>>,
+    mut snapshots: ResMut<Snapshots>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Snapshots")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match &snapshots.last {
+                Some(snapshot) => ui.label(format!(
+                    "Last snapshot at step {} ({:.3} s)",
+                    snapshot.tick,
+                    snapshot.elapsed.as_secs_f32()
+                )),
+                None => ui.label("No snapshot yet"),
+            };
+            ui.horizontal(|ui| {
+                if ui
+                    .button(format!("Snapshot ({:?})", settings.snapshot_key))
+                    .clicked()
+                {
+                    commands.queue(take);
+                }
+                ui.add_enabled_ui(snapshots.last.is_some(), |ui| {
+                    if ui
+                        .button(format!("Restore ({:?})", settings.restore_key))
+                        .clicked()
+                    {
+                        commands.queue(rewind);
+                    }
+                });
+                ui.add_enabled_ui(snapshots.initial.is_some(), |ui| {
+                    if ui
+                        .button(format!("Reset ({:?})", settings.reset_key))
+                        .clicked()
+                    {
+                        commands.queue(reset);
+                    }
+                });
+            });
+
+            ui.separator();
+            let directory = edited.directory();
+            ui.label(format!("Snapshots are saved to {}", directory.display()));
+            ui.add_enabled_ui(snapshots.last.is_some(), |ui| {
+                if ui.button("Save the last snapshot").clicked() {
+                    if let Some(snapshot) = &snapshots.last {
+                        let path = directory.join(format!("snapshot-{}.json", snapshot.tick));
+                        if let Err(error) = snapshot.write(&path) {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+            });
+            for path in saved(&directory) {
+                ui.horizontal(|ui| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    let name = name.into_owned();
+                    ui.label(name);
+                    if ui.button("Load").clicked() {
+                        match Snapshot::read(&path) {
+                            Ok(snapshot) => snapshots.last = Some(snapshot),
+                            Err(error) => {
+                                commands.send_event(ErrorEvent::from(error));
+                            }
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("snapshots", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("snapshots", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
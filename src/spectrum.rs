@@ -0,0 +1,178 @@
+//! Spectral analysis of the telemetry, to find the resonances of a plant from a recording
+//! without identifying a model first.
+//!
+//! A channel is resampled at its mean sample period, stripped of its linear trend, and
+//! transformed by a radix-2 FFT, zero-padded to [`PADDING`] times its length so peaks are located
+//! finer than the resolution of the record. No window is applied: a resonance shows best ringing
+//! down after a move, and a window would widen its peak. The dominant resonance is the highest
+//! peak above the lowest two frequencies the record resolves, whose half-power band lies above
+//! them too, so the ripples of a drift or of a first-order response aren't taken for one; its
+//! damping is estimated from that band, `(f₂ - f₁) / 2f`, which the length of the record bounds
+//! from below.
+use std::f32::consts::{SQRT_2, TAU};
+
+use nalgebra::Complex;
+
+use crate::telemetry::Sample;
+
+/// Fewest samples analyzed.
+pub const MIN_SAMPLES: usize = 64;
+/// Length of the transform, relative to that of the record.
+pub const PADDING: usize = 4;
+
+/// Magnitudes of the frequencies of a channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    /// Spacing of the frequencies, in Hz.
+    pub resolution: f32,
+    /// Magnitudes at each multiple of the resolution, from 0 Hz to the Nyquist frequency.
+    pub magnitudes: Vec<f32>,
+    /// Duration of the record, in seconds.
+    pub duration: f32,
+}
+
+/// A lightly damped peak of a spectrum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resonance {
+    /// In Hz.
+    pub frequency: f32,
+    pub damping: f32,
+}
+
+impl Spectrum {
+    /// The spectrum of `samples`, in time order, if there are at least [`MIN_SAMPLES`] of them
+    /// over some time.
+    pub fn of(samples: &[Sample]) -> Option<Self> {
+        let length = samples.len();
+        if length < MIN_SAMPLES {
+            return None;
+        }
+        let ([first, _], [last, _]) = (samples[0], samples[length - 1]);
+        let period = (last - first) / (length - 1) as f32;
+        if period <= 0.0 {
+            return None;
+        }
+
+        // Linear interpolation at a constant period.
+        let mut next = 1;
+        let values = (0..length)
+            .map(|index| {
+                let time = first + index as f32 * period;
+                while next < length - 1 && samples[next][0] < time {
+                    next += 1;
+                }
+                let ([t0, v0], [t1, v1]) = (samples[next - 1], samples[next]);
+                let span = t1 - t0;
+                if span > 0.0 {
+                    v0 + (v1 - v0) * ((time - t0) / span).clamp(0.0, 1.0)
+                } else {
+                    v1
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Least-squares line through the samples, about their middle.
+        let middle = (length - 1) as f32 / 2.0;
+        let mean = values.iter().sum::<f32>() / length as f32;
+        let (covariance, variance) =
+            values
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(covariance, variance), (index, value)| {
+                    let offset = index as f32 - middle;
+                    (
+                        covariance + offset * (value - mean),
+                        variance + offset * offset,
+                    )
+                });
+        let slope = covariance / variance;
+
+        let size = (length * PADDING).next_power_of_two();
+        let mut transform = vec![Complex::new(0.0, 0.0); size];
+        for (index, value) in values.iter().enumerate() {
+            transform[index].re = value - mean - slope * (index as f32 - middle);
+        }
+        fft(&mut transform);
+        Some(Self {
+            resolution: 1.0 / (size as f32 * period),
+            magnitudes: transform[..=size / 2]
+                .iter()
+                .map(|value| value.norm())
+                .collect(),
+            duration: last - first,
+        })
+    }
+
+    /// Frequency of a bin, in Hz.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.resolution
+    }
+
+    /// The highest peak above the lowest two frequencies the record resolves, if its half-power
+    /// band lies above them too and it isn't too wide to be a resonance.
+    pub fn dominant_resonance(&self) -> Option<Resonance> {
+        let magnitudes = &self.magnitudes;
+        let lowest = ((2.0 / (self.duration * self.resolution)).ceil() as usize).max(1);
+        let peak = (lowest..magnitudes.len().saturating_sub(1))
+            .max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))?;
+        let [before, top, after] = [peak - 1, peak, peak + 1].map(|bin| magnitudes[bin]);
+        if top <= before || top < after {
+            return None;
+        }
+
+        // Vertex of the parabola through the peak and its neighbours.
+        let curvature = before - 2.0 * top + after;
+        let shift = if curvature < 0.0 {
+            0.5 * (before - after) / curvature
+        } else {
+            0.0
+        };
+        let frequency = self.frequency(peak) + shift * self.resolution;
+
+        // Frequencies where the magnitude crosses the half power, interpolated between bins.
+        let half = top / SQRT_2;
+        let below = (lowest..peak).rev().find(|&bin| magnitudes[bin] <= half)?;
+        let above = (peak + 1..magnitudes.len()).find(|&bin| magnitudes[bin] <= half)?;
+        let crossing = |outside: usize, inside: usize| {
+            let (outer, inner) = (magnitudes[outside], magnitudes[inside]);
+            let fraction = (inner - half) / (inner - outer);
+            self.frequency(inside) + (outside as f32 - inside as f32) * fraction * self.resolution
+        };
+        let bandwidth = crossing(above, above - 1) - crossing(below, below + 1);
+        let damping = bandwidth / (2.0 * frequency);
+        (frequency > 0.0 && damping < 1.0).then_some(Resonance { frequency, damping })
+    }
+}
+
+/// Replaces `values`, whose length is a power of two, by their discrete Fourier transform.
+fn fft(values: &mut [Complex<f32>]) {
+    let size = values.len();
+    // Bit-reversed order.
+    let mut reversed = 0;
+    for index in 1..size {
+        let mut bit = size >> 1;
+        while reversed & bit != 0 {
+            reversed ^= bit;
+            bit >>= 1;
+        }
+        reversed |= bit;
+        if index < reversed {
+            values.swap(index, reversed);
+        }
+    }
+    let mut span = 2;
+    while span <= size {
+        let twiddles = (0..span / 2)
+            .map(|index| Complex::from_polar(1.0, -TAU * index as f32 / span as f32))
+            .collect::<Vec<_>>();
+        for block in values.chunks_exact_mut(span) {
+            let (low, high) = block.split_at_mut(span / 2);
+            for ((low, high), twiddle) in low.iter_mut().zip(high).zip(&twiddles) {
+                let product = *high * twiddle;
+                *high = *low - product;
+                *low += product;
+            }
+        }
+        span *= 2;
+    }
+}
@@ -0,0 +1,273 @@
+//! This module draws the state machines of the application live: each one as a graph of its
+//! states with the active one highlighted, and the history of its last transitions, to debug
+//! the sequencing logic.
+//!
+//! The machines drawn are the sequencer of the teach panel and, when the CANopen drive is
+//! emulated, its NMT and CiA 402 power state machines.
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+
+#[cfg(target_os = "linux")]
+use crate::canopen::{CanOpenNode, DriveState, NmtState};
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    teach::TeachPlayback,
+    theme::{to_egui, Theme},
+};
+
+/// Number of transitions kept in the history of each machine.
+pub const HISTORY_LEN: usize = 50;
+
+pub struct StateMachinesPlugin;
+
+impl Plugin for StateMachinesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StateMachines>()
+            .add_systems(
+                Update,
+                (
+                    observe_teach.run_if(resource_exists::<TeachPlayback>),
+                    #[cfg(target_os = "linux")]
+                    observe_canopen.run_if(resource_exists::<CanOpenNode>),
+                ),
+            )
+            .add_systems(Update, state_machines_panel.run_if(has_ui));
+    }
+}
+
+/// A state machine as drawn: its states and the transitions allowed between them.
+#[derive(Debug, PartialEq)]
+pub struct MachineGraph {
+    pub name: &'static str,
+    pub states: &'static [&'static str],
+    /// Transitions, as the indexes of their source and target states.
+    pub transitions: &'static [(usize, usize)],
+}
+
+/// Sequencer of the teach panel.
+pub const TEACH_SEQUENCER: MachineGraph = MachineGraph {
+    name: "Teach sequencer",
+    states: &["Idle", "Moving", "Dwelling"],
+    transitions: &[(0, 1), (1, 2), (2, 1), (1, 0), (2, 0)],
+};
+
+/// NMT state machine of the CANopen node.
+pub const NMT: MachineGraph = MachineGraph {
+    name: "CANopen NMT",
+    states: &["Pre-operational", "Operational", "Stopped"],
+    transitions: &[(0, 1), (1, 0), (0, 2), (2, 0), (1, 2), (2, 1)],
+};
+
+/// CiA 402 power state machine of the CANopen drive.
+pub const CIA_402: MachineGraph = MachineGraph {
+    name: "CiA 402 drive",
+    states: &[
+        "Switch on disabled",
+        "Ready to switch on",
+        "Switched on",
+        "Operation enabled",
+        "Quick stop active",
+        "Fault",
+    ],
+    transitions: &[
+        (0, 1),
+        (1, 2),
+        (2, 3),
+        (3, 2),
+        (2, 1),
+        (3, 1),
+        (1, 0),
+        (2, 0),
+        (3, 0),
+        (3, 4),
+        (4, 3),
+        (4, 0),
+        (5, 0),
+    ],
+};
+
+/// A transition that happened, at a simulated time in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransitionRecord {
+    pub time: f32,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// The active state of a machine and its last transitions, oldest first.
+#[derive(Debug)]
+pub struct MachineTrace {
+    pub graph: &'static MachineGraph,
+    pub active: Option<usize>,
+    pub history: VecDeque<TransitionRecord>,
+}
+
+impl MachineTrace {
+    pub fn new(graph: &'static MachineGraph) -> Self {
+        Self {
+            graph,
+            active: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Records that the machine is in `state` at `time`, keeping the last [`HISTORY_LEN`]
+    /// transitions. The first state observed isn't a transition.
+    pub fn observe(&mut self, state: usize, time: f32) {
+        match self.active {
+            Some(active) if active != state => {
+                if self.history.len() == HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.history.push_back(TransitionRecord {
+                    time,
+                    from: active,
+                    to: state,
+                });
+            }
+            _ => {}
+        }
+        self.active = Some(state);
+    }
+}
+
+/// The machines observed so far, in the order they were first observed.
+#[derive(Debug, Default, Resource)]
+pub struct StateMachines(pub Vec<MachineTrace>);
+
+impl StateMachines {
+    /// Records that the machine of `graph` is in `state` at `time`.
+    pub fn observe(&mut self, graph: &'static MachineGraph, state: usize, time: f32) {
+        let index = match self.0.iter().position(|trace| trace.graph == graph) {
+            Some(index) => index,
+            None => {
+                self.0.push(MachineTrace::new(graph));
+                self.0.len() - 1
+            }
+        };
+        self.0[index].observe(state, time);
+    }
+}
+
+fn observe_teach(
+    clock: Res<SimClock>,
+    teach: Res<TeachPlayback>,
+    mut machines: ResMut<StateMachines>,
+) {
+    let state = match &teach.playback {
+        None => 0,
+        Some(playback) if playback.is_dwelling() => 2,
+        Some(_) => 1,
+    };
+    machines.observe(&TEACH_SEQUENCER, state, clock.elapsed_secs());
+}
+
+#[cfg(target_os = "linux")]
+fn observe_canopen(
+    clock: Res<SimClock>,
+    node: Res<CanOpenNode>,
+    mut machines: ResMut<StateMachines>,
+) {
+    let nmt = match node.drive.nmt_state() {
+        NmtState::PreOperational => 0,
+        NmtState::Operational => 1,
+        NmtState::Stopped => 2,
+    };
+    let drive = match node.drive.state() {
+        DriveState::SwitchOnDisabled => 0,
+        DriveState::ReadyToSwitchOn => 1,
+        DriveState::SwitchedOn => 2,
+        DriveState::OperationEnabled => 3,
+        DriveState::QuickStopActive => 4,
+        DriveState::Fault => 5,
+    };
+    machines.observe(&NMT, nmt, clock.elapsed_secs());
+    machines.observe(&CIA_402, drive, clock.elapsed_secs());
+}
+
+/// Panel drawing every machine observed.
+fn state_machines_panel(
+    mut contexts: EguiContexts,
+    machines: Res<StateMachines>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+
+    egui::Window::new("State machines")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if machines.0.is_empty() {
+                ui.label("No state machine running.");
+            }
+            for trace in &machines.0 {
+                ui.collapsing(trace.graph.name, |ui| {
+                    draw_graph(ui, trace, &theme);
+                    egui::ScrollArea::vertical()
+                        .id_salt(trace.graph.name)
+                        .max_height(100.0)
+                        .show(ui, |ui| {
+                            for record in trace.history.iter().rev() {
+                                ui.monospace(format!(
+                                    "{:>9.3} s  {} → {}",
+                                    record.time,
+                                    trace.graph.states[record.from],
+                                    trace.graph.states[record.to]
+                                ));
+                            }
+                        });
+                });
+            }
+        });
+}
+
+/// Draws the states on a circle, linked by the transitions, with the active state filled and
+/// the last transition highlighted.
+fn draw_graph(ui: &mut egui::Ui, trace: &MachineTrace, theme: &Theme) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(320.0, 240.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let states = trace.graph.states;
+    let radius = 0.5 * rect.height() - 24.0;
+    let position = |state: usize| {
+        let angle = TAU * state as f32 / states.len() as f32 - 0.25 * TAU;
+        rect.center() + radius * egui::vec2(angle.cos() * 1.3, angle.sin())
+    };
+    let text_color = ui.visuals().text_color();
+    let grid = to_egui(theme.grid());
+    let accent = to_egui(theme.series(0));
+    let last = trace.history.back();
+
+    for &(from, to) in trace.graph.transitions {
+        let (start, end) = (position(from), position(to));
+        let direction = (end - start).normalized();
+        // Offset both ways of a pair of transitions so their arrows don't overlap.
+        let offset = 4.0 * direction.rot90();
+        let stroke = if last.is_some_and(|last| (last.from, last.to) == (from, to)) {
+            egui::Stroke::new(2.0, accent)
+        } else {
+            egui::Stroke::new(1.0, grid)
+        };
+        let start = start + 20.0 * direction + offset;
+        let end = end - 20.0 * direction + offset;
+        painter.arrow(start, end - start, stroke);
+    }
+    for (state, name) in states.iter().enumerate() {
+        let center = position(state);
+        if trace.active == Some(state) {
+            painter.circle_filled(center, 16.0, accent);
+        } else {
+            painter.circle_stroke(center, 16.0, egui::Stroke::new(1.0, text_color));
+        }
+        painter.text(
+            center + egui::vec2(0.0, 18.0),
+            egui::Align2::CENTER_TOP,
+            name,
+            egui::FontId::proportional(11.0),
+            text_color,
+        );
+    }
+}
@@ -0,0 +1,444 @@
+//! This module analyzes the response of a channel to the steps of its setpoint, to tune the
+//! controllers on the classic metrics of a step response.
+//!
+//! A step is a change of the watched setpoint, e.g. `motor/position` sent from the setpoint
+//! command panel, by at least the minimum step. The response of the measured channel, e.g.
+//! `motor/angle`, is then collected from the telemetry over the analysis window, or until the
+//! next step, and analyzed on the fraction of the step covered: the rise time, from 10 % to 90 %
+//! of the step, the overshoot past the target, in percent of the step, the settling time, after
+//! which the channel stays within a band around the target, and the steady-state error, averaged
+//! over the last tenth of the window. The *Step response* panel shows the last step analyzed,
+//! and exports it to `<data dir>/step_responses` as the CSV samples of the segment and the JSON
+//! metrics.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{HLine, Legend, Line, Plot, PlotPoints};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    recording::{self, RecordingFormat},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::{Sample, Telemetry, MOTOR_ANGLE},
+    theme::{to_egui, Theme},
+};
+
+pub struct StepResponsePlugin;
+
+impl Plugin for StepResponsePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StepResponse>()
+            .init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                watch_steps
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<StepResponseSettings>>),
+            )
+            .add_systems(
+                Update,
+                step_response_panel
+                    .run_if(resource_exists::<Persistent<StepResponseSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the step-response analysis.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct StepResponseSettings {
+    pub enabled: bool,
+    /// Setpoint whose steps are watched.
+    pub setpoint: String,
+    /// Telemetry channel responding to the steps.
+    pub channel: String,
+    /// Smallest change of the setpoint taken as a step.
+    pub min_step: f32,
+    /// Duration of the response analyzed, in s.
+    pub window: f32,
+    /// Half-width of the settling band around the target, as a fraction of the step.
+    pub band: f32,
+}
+
+impl Default for StepResponseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            setpoint: MOTOR_POSITION.to_string(),
+            channel: MOTOR_ANGLE.to_string(),
+            min_step: 0.01,
+            window: 5.0,
+            band: 0.02,
+        }
+    }
+}
+
+/// The metrics of a step response.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct StepMetrics {
+    /// Value of the channel at the step, and the setpoint stepped to.
+    pub initial: f32,
+    pub target: f32,
+    /// Time from 10 % to 90 % of the step, in s, unless it never gets there.
+    pub rise_time: Option<f32>,
+    /// Largest excursion past the target, in percent of the step.
+    pub overshoot: f32,
+    /// Value of the channel furthest along the step, and when, in s after the step.
+    pub peak: f32,
+    pub peak_time: f32,
+    /// Time after the step from which the channel stays within the band, in s, unless it leaves
+    /// it at the end.
+    pub settling_time: Option<f32>,
+    /// Target minus the final value of the channel, in its unit.
+    pub steady_state_error: f32,
+}
+
+impl StepMetrics {
+    /// The metrics of the response `samples` to a step at `time` from `initial` to `target`,
+    /// settling within `band` times the step, unless there are no samples or no step.
+    pub fn of(samples: &[Sample], time: f32, initial: f32, target: f32, band: f32) -> Option<Self> {
+        let step = target - initial;
+        if samples.is_empty() || step == 0.0 || !step.is_finite() {
+            return None;
+        }
+        // Fraction of the step covered.
+        let covered = |value: f32| (value - initial) / step;
+        let reached = |fraction: f32| {
+            samples
+                .iter()
+                .find(|[_, value]| covered(*value) >= fraction)
+                .map(|[at, _]| *at)
+        };
+        let rise_time = reached(0.1)
+            .zip(reached(0.9))
+            .map(|(start, end)| end - start);
+        let [peak_at, peak] = *samples
+            .iter()
+            .max_by(|[_, a], [_, b]| covered(*a).total_cmp(&covered(*b)))?;
+        let outside = samples
+            .iter()
+            .rposition(|[_, value]| (covered(*value) - 1.0).abs() > band);
+        let settling_time = match outside {
+            Some(index) => samples.get(index + 1).map(|[at, _]| at - time),
+            None => Some(samples[0][0] - time),
+        };
+        let tail = &samples[samples.len() - samples.len().div_ceil(10)..];
+        let last = tail.iter().map(|[_, value]| value).sum::<f32>() / tail.len() as f32;
+        Some(Self {
+            initial,
+            target,
+            rise_time,
+            overshoot: (covered(peak) - 1.0).max(0.0) * 100.0,
+            peak,
+            peak_time: peak_at - time,
+            settling_time,
+            steady_state_error: target - last,
+        })
+    }
+}
+
+/// A step being watched or analyzed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    /// Time of the step, in s.
+    pub time: f32,
+    pub initial: f32,
+    pub target: f32,
+}
+
+/// A step analyzed: its response, and the metrics of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalyzedStep {
+    pub step: Step,
+    pub samples: Vec<Sample>,
+    pub metrics: Option<StepMetrics>,
+}
+
+/// State of the analysis.
+#[derive(Debug, Default, Resource)]
+pub struct StepResponse {
+    /// Value of the setpoint at the last step of the simulation.
+    previous: Option<f32>,
+    /// The step whose response is being collected.
+    pub pending: Option<Step>,
+    pub last: Option<AnalyzedStep>,
+}
+
+/// Samples of `samples` within `window` seconds from the step at `time`.
+fn segment(samples: &[Sample], time: f32, window: f32) -> Vec<Sample> {
+    let start = samples.partition_point(|[at, _]| *at < time);
+    samples[start..]
+        .iter()
+        .take_while(|[at, _]| *at <= time + window)
+        .copied()
+        .collect()
+}
+
+fn analyze(step: Step, telemetry: &Telemetry, settings: &StepResponseSettings) -> AnalyzedStep {
+    let samples = telemetry
+        .channels
+        .get(&settings.channel)
+        .map(|samples| segment(samples, step.time, settings.window))
+        .unwrap_or_default();
+    let metrics = StepMetrics::of(
+        &samples,
+        step.time,
+        step.initial,
+        step.target,
+        settings.band,
+    );
+    AnalyzedStep {
+        step,
+        samples,
+        metrics,
+    }
+}
+
+/// Writes the samples of a step analyzed, time since the step, and its metrics into `directory`,
+/// returning the path of the samples.
+pub fn export(analyzed: &AnalyzedStep, channel: &str, directory: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(directory).map_err(|error| Error::io(directory, error))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = directory.join(recording::file_name(now, RecordingFormat::Csv).replacen(
+        "recording",
+        "step-response",
+        1,
+    ));
+    let mut csv = format!("time,{channel},setpoint\n");
+    for [time, value] in &analyzed.samples {
+        csv.push_str(&format!(
+            "{},{value},{}\n",
+            time - analyzed.step.time,
+            analyzed.step.target
+        ));
+    }
+    fs::write(&path, csv).map_err(|error| Error::io(&path, error))?;
+    let metrics = path.with_extension("json");
+    let json = serde_json::to_string_pretty(&analyzed.metrics).map_err(io::Error::from);
+    json.and_then(|json| fs::write(&metrics, json))
+        .map_err(|error| Error::io(&metrics, error))?;
+    Ok(path)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<StepResponseSettings>("step_response", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Watches the steps of the setpoint, and analyzes their responses once the window is over.
+fn watch_steps(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<StepResponseSettings>>,
+    setpoints: Res<Setpoints>,
+    telemetry: Res<Telemetry>,
+    mut response: ResMut<StepResponse>,
+) {
+    let time = clock.elapsed_secs();
+    let value = setpoints.get(&settings.setpoint);
+    let previous = std::mem::replace(&mut response.previous, value);
+    if !settings.enabled {
+        response.pending = None;
+        return;
+    }
+    let stepped = match (previous, value) {
+        (Some(previous), Some(value)) => (value - previous).abs() >= settings.min_step,
+        _ => false,
+    };
+    let over = response
+        .pending
+        .as_ref()
+        .is_some_and(|step| time - step.time >= settings.window);
+    if stepped || over {
+        // Cut short by the next step.
+        if let Some(step) = response.pending.take() {
+            let analyzed = analyze(step, &telemetry, &settings);
+            if let Some(metrics) = &analyzed.metrics {
+                info!(
+                    target: subsystem::CONTROL,
+                    "Step of {} to {}: overshoot {:.1} %, settling time {}",
+                    settings.setpoint,
+                    metrics.target,
+                    metrics.overshoot,
+                    metrics
+                        .settling_time
+                        .map_or("none".to_string(), |settling| format!("{settling:.3} s"))
+                );
+            }
+            response.last = Some(analyzed);
+        }
+    }
+    if let (true, Some(target)) = (stepped, value) {
+        response.pending = Some(Step {
+            time,
+            initial: telemetry
+                .latest(&settings.channel)
+                .or(previous)
+                .unwrap_or_default(),
+            target,
+        });
+    }
+}
+
+/// Panel to configure the analysis, show the last step analyzed and export it.
+fn step_response_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<StepResponseSettings>>,
+    response: Res<StepResponse>,
+    clock: Res<SimClock>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Step response")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Analyze the steps");
+            ui.horizontal(|ui| {
+                ui.label("Setpoint");
+                ui.text_edit_singleline(&mut edited.setpoint);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Channel");
+                ui.text_edit_singleline(&mut edited.channel);
+            });
+            ui.add(
+                egui::DragValue::new(&mut edited.min_step)
+                    .speed(0.001)
+                    .range(0.0..=f32::MAX)
+                    .prefix("Smallest step: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.window)
+                    .speed(0.1)
+                    .range(0.1..=f32::MAX)
+                    .prefix("Window: ")
+                    .suffix(" s"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.band, 0.005..=0.2)
+                    .text("Settling band (of the step)"),
+            );
+
+            ui.separator();
+            if let Some(step) = &response.pending {
+                ui.label(format!(
+                    "Collecting the step to {:.4}: {:.2} s of {:.2} s",
+                    step.target,
+                    clock.elapsed_secs() - step.time,
+                    edited.window
+                ));
+            } else if response.last.is_none() {
+                ui.label(format!("Waiting for a step of {}", edited.setpoint));
+            }
+            if let Some(analyzed) = &response.last {
+                if analyzed_step_ui(ui, analyzed, &edited, theme.as_deref()) {
+                    let directory = data_dir().join("step_responses");
+                    match export(analyzed, &edited.channel, &directory) {
+                        Ok(path) => info!(
+                            target: subsystem::IO,
+                            "Step response exported to {}",
+                            path.display()
+                        ),
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("step_response", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("step_response", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Shows the metrics and the response of a step analyzed, returning whether it's to be exported.
+fn analyzed_step_ui(
+    ui: &mut egui::Ui,
+    analyzed: &AnalyzedStep,
+    settings: &StepResponseSettings,
+    theme: Option<&Persistent<Theme>>,
+) -> bool {
+    match &analyzed.metrics {
+        Some(metrics) => {
+            let seconds = |time: Option<f32>| {
+                time.map_or("not reached".to_string(), |time| format!("{time:.3} s"))
+            };
+            ui.label(format!(
+                "Step from {:.4} to {:.4} at {:.2} s",
+                metrics.initial, metrics.target, analyzed.step.time
+            ));
+            ui.label(format!(
+                "Rise time (10-90 %): {}",
+                seconds(metrics.rise_time)
+            ));
+            ui.label(format!(
+                "Overshoot: {:.1} %, peak {:.4} at {:.3} s",
+                metrics.overshoot, metrics.peak, metrics.peak_time
+            ));
+            ui.label(format!("Settling time: {}", seconds(metrics.settling_time)));
+            ui.label(format!(
+                "Steady-state error: {:.4}",
+                metrics.steady_state_error
+            ));
+        }
+        None => {
+            ui.label(format!("No samples of {} after the step", settings.channel));
+        }
+    }
+    let color = |index| theme.map(|theme| to_egui(theme.series(index)));
+    let points: Vec<[f64; 2]> = analyzed
+        .samples
+        .iter()
+        .map(|[time, value]| [f64::from(time - analyzed.step.time), f64::from(*value)])
+        .collect();
+    Plot::new("step_response")
+        .legend(Legend::default())
+        .height(160.0)
+        .x_axis_label("Time since the step (s)")
+        .show(ui, |plot_ui| {
+            let mut line = Line::new(PlotPoints::new(points)).name(&settings.channel);
+            let mut target = HLine::new(f64::from(analyzed.step.target)).name(&settings.setpoint);
+            if let (Some(first), Some(second)) = (color(0), color(1)) {
+                line = line.color(first);
+                target = target.color(second);
+            }
+            plot_ui.line(line);
+            plot_ui.hline(target);
+        });
+    ui.button("Export").clicked()
+}
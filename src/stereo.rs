@@ -0,0 +1,212 @@
+//! This module provides a side-by-side stereo view of the scene, for stereoscopes and phone
+//! headsets, to show the plants in depth in outreach and teaching.
+//!
+//! While enabled, the window is split in two halves: the orbit camera renders the left eye, and a
+//! second camera, its child, the right eye, `eye_separation` meters to its right. Both keep the
+//! projection of the orbit camera, which still orbits and pans as usual. The parallel layout puts
+//! each eye on its own side, for viewers with lenses; the cross-eyed layout swaps them, for free
+//! viewing. The drag tool grabs the links in either half.
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+};
+
+pub struct StereoPlugin;
+
+impl Plugin for StereoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                stereo_panel.run_if(has_ui),
+                (spawn_eye, arrange_views).chain(),
+            )
+                .run_if(resource_exists::<Persistent<StereoSettings>>),
+        );
+    }
+}
+
+/// Which half of the window each eye is drawn in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayout {
+    /// The left eye on the left, for viewers with lenses.
+    #[default]
+    Parallel,
+    /// The left eye on the right, for viewing cross-eyed.
+    CrossEyed,
+}
+
+impl StereoLayout {
+    pub const ALL: [Self; 2] = [Self::Parallel, Self::CrossEyed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Parallel => "Parallel",
+            Self::CrossEyed => "Cross-eyed",
+        }
+    }
+}
+
+/// Represents the stereo settings, from the `stereo.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct StereoSettings {
+    pub enabled: bool,
+    pub layout: StereoLayout,
+    /// Distance between the eyes, in m.
+    pub eye_separation: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            layout: StereoLayout::Parallel,
+            eye_separation: 0.064,
+        }
+    }
+}
+
+/// The viewports of the left and the right eye in a window of `size` physical pixels.
+pub fn viewports(size: UVec2, layout: StereoLayout) -> (Viewport, Viewport) {
+    let half = UVec2::new(size.x / 2, size.y);
+    let left = Viewport {
+        physical_position: UVec2::ZERO,
+        physical_size: half,
+        ..default()
+    };
+    let right = Viewport {
+        physical_position: UVec2::new(half.x, 0),
+        physical_size: half,
+        ..default()
+    };
+    match layout {
+        StereoLayout::Parallel => (left, right),
+        StereoLayout::CrossEyed => (right, left),
+    }
+}
+
+/// The camera of the right eye, a child of the orbit camera.
+#[derive(Component)]
+pub struct StereoEye;
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<StereoSettings>("stereo", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Spawns the camera of the right eye when the stereo view is enabled, and despawns it when
+/// disabled, giving the whole window back to the orbit camera.
+fn spawn_eye(
+    mut commands: Commands,
+    settings: Res<Persistent<StereoSettings>>,
+    mut orbits: Query<(Entity, &mut Camera), With<PanOrbitCamera>>,
+    eyes: Query<Entity, With<StereoEye>>,
+) {
+    if settings.enabled && eyes.is_empty() {
+        let Some((orbit, camera)) = orbits.iter().next() else {
+            return;
+        };
+        let order = camera.order + 1;
+        commands.entity(orbit).with_children(|parent| {
+            parent.spawn((
+                Camera3d::default(),
+                Camera { order, ..default() },
+                Transform::from_translation(Vec3::X * settings.eye_separation),
+                StereoEye,
+            ));
+        });
+    } else if !settings.enabled && !eyes.is_empty() {
+        for eye in &eyes {
+            commands.entity(eye).despawn_recursive();
+        }
+        for (_, mut camera) in &mut orbits {
+            camera.viewport = None;
+        }
+    }
+}
+
+/// Splits the window between the eyes, and keeps the right eye on the projection of the orbit
+/// camera.
+fn arrange_views(
+    settings: Res<Persistent<StereoSettings>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut orbits: Query<(&mut Camera, &Projection), (With<PanOrbitCamera>, Without<StereoEye>)>,
+    mut eyes: Query<(&mut Camera, &mut Projection, &mut Transform), With<StereoEye>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Ok((mut eye, mut eye_projection, mut transform)) = eyes.get_single_mut() else {
+        return;
+    };
+    let Some((mut orbit, projection)) = orbits.iter_mut().next() else {
+        return;
+    };
+    let (left, right) = viewports(window.physical_size(), settings.layout);
+    if left.physical_size.x == 0 || left.physical_size.y == 0 {
+        return;
+    }
+    orbit.viewport = Some(left);
+    eye.viewport = Some(right);
+    *eye_projection = projection.clone();
+    transform.translation = Vec3::X * settings.eye_separation;
+}
+
+/// Panel to enable the stereo view and set it up.
+fn stereo_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<StereoSettings>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Stereo")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Side-by-side stereo");
+            egui::ComboBox::from_label("Layout")
+                .selected_text(edited.layout.name())
+                .show_ui(ui, |ui| {
+                    for layout in StereoLayout::ALL {
+                        ui.selectable_value(&mut edited.layout, layout, layout.name());
+                    }
+                });
+            ui.add(
+                egui::Slider::new(&mut edited.eye_separation, 0.0..=0.2).text("Eye separation (m)"),
+            );
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("stereo", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("stereo", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,323 @@
+//! Self-describing binary format of the streamed telemetry logs (`.dtlog`), written by the
+//! autosave as the session runs, and read back by the restore prompt, the run diff and external
+//! tools (`tools/dtlog.py` reads them in Python).
+//!
+//! A log starts with a header: the magic `DTPL`, the version of the format (`u16`) and the
+//! schema, JSON of length `u32`, which declares the channels known when the log starts. Frames
+//! follow, each one a kind (`u8`), the length of its payload (`u32`) and the payload:
+//!
+//! - a channel frame (kind 1) declares one more channel, as the JSON of its schema;
+//! - a samples frame (kind 2) holds samples of a channel: its index (`u16`), in declaration
+//!   order, followed by pairs of a time in seconds (`f32`) and a value of the type of the
+//!   channel.
+//!
+//! Integers are little-endian. Frames of unknown kinds are skipped, so readers of this version
+//! read later ones; the log can be appended to forever, and a truncated last frame, e.g. after
+//! a crash, ends it.
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::{Sample, Telemetry};
+
+/// First bytes of a log.
+pub const MAGIC: [u8; 4] = *b"DTPL";
+/// Version of the format written.
+pub const VERSION: u16 = 1;
+/// Extension of the log files.
+pub const EXTENSION: &str = "dtlog";
+
+const CHANNEL_FRAME: u8 = 1;
+const SAMPLES_FRAME: u8 = 2;
+
+/// Type of the values of a channel.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    #[default]
+    F32,
+    F64,
+    I32,
+    /// A byte, 0 or 1.
+    Bool,
+}
+
+impl ValueType {
+    /// Size of a value, in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            ValueType::F32 | ValueType::I32 => 4,
+            ValueType::F64 => 8,
+            ValueType::Bool => 1,
+        }
+    }
+
+    fn encode(self, value: f32, bytes: &mut Vec<u8>) {
+        match self {
+            ValueType::F32 => bytes.extend(value.to_le_bytes()),
+            ValueType::F64 => bytes.extend(f64::from(value).to_le_bytes()),
+            ValueType::I32 => bytes.extend((value.round() as i32).to_le_bytes()),
+            ValueType::Bool => bytes.push(u8::from(value != 0.0)),
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            ValueType::F32 => f32::from_le_bytes(bytes.try_into().unwrap_or_default()),
+            ValueType::F64 => f64::from_le_bytes(bytes.try_into().unwrap_or_default()) as f32,
+            ValueType::I32 => i32::from_le_bytes(bytes.try_into().unwrap_or_default()) as f32,
+            ValueType::Bool => f32::from(bytes[0]),
+        }
+    }
+}
+
+/// Declaration of a channel.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ChannelSchema {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub value_type: ValueType,
+    /// Nominal rate of the samples, in Hz, if they're regular.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f32>,
+}
+
+impl ChannelSchema {
+    pub fn new(name: &str, value_type: ValueType, rate: Option<f32>) -> Self {
+        Self {
+            name: name.to_string(),
+            value_type,
+            rate,
+        }
+    }
+}
+
+/// Schema of the header of a log.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Schema {
+    pub channels: Vec<ChannelSchema>,
+}
+
+/// A frame of a log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Channel(ChannelSchema),
+    Samples { channel: u16, samples: Vec<Sample> },
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Writes a log to a stream.
+pub struct StreamWriter<W: Write> {
+    inner: W,
+    channels: Vec<ChannelSchema>,
+    indexes: BTreeMap<String, u16>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Writes the header of a log with the channels of `schema`.
+    pub fn new(mut inner: W, schema: Schema) -> io::Result<Self> {
+        let json = serde_json::to_vec(&schema)?;
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&VERSION.to_le_bytes())?;
+        inner.write_all(&(json.len() as u32).to_le_bytes())?;
+        inner.write_all(&json)?;
+        let indexes = schema
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(index, channel)| (channel.name.clone(), index as u16))
+            .collect();
+        Ok(Self {
+            inner,
+            channels: schema.channels,
+            indexes,
+        })
+    }
+
+    /// The channels declared so far.
+    pub fn channels(&self) -> &[ChannelSchema] {
+        &self.channels
+    }
+
+    fn frame(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+        let length = u32::try_from(payload.len()).map_err(|_| invalid("frame too large"))?;
+        self.inner.write_all(&[kind])?;
+        self.inner.write_all(&length.to_le_bytes())?;
+        self.inner.write_all(payload)
+    }
+
+    /// Declares a channel, or gives the index of the channel of that name.
+    pub fn add_channel(&mut self, channel: ChannelSchema) -> io::Result<u16> {
+        if let Some(index) = self.indexes.get(&channel.name) {
+            return Ok(*index);
+        }
+        let index = u16::try_from(self.channels.len()).map_err(|_| invalid("too many channels"))?;
+        self.frame(CHANNEL_FRAME, &serde_json::to_vec(&channel)?)?;
+        self.indexes.insert(channel.name.clone(), index);
+        self.channels.push(channel);
+        Ok(index)
+    }
+
+    /// Writes samples of a declared channel.
+    pub fn write_samples(&mut self, channel: u16, samples: &[Sample]) -> io::Result<()> {
+        let value_type = self
+            .channels
+            .get(usize::from(channel))
+            .ok_or_else(|| invalid(format!("undeclared channel {channel}")))?
+            .value_type;
+        let mut payload = Vec::with_capacity(2 + samples.len() * (4 + value_type.size()));
+        payload.extend(channel.to_le_bytes());
+        for [time, value] in samples {
+            payload.extend(time.to_le_bytes());
+            value_type.encode(*value, &mut payload);
+        }
+        self.frame(SAMPLES_FRAME, &payload)
+    }
+
+    /// Writes the samples of every channel of `telemetry`, declaring the new channels as `f32`
+    /// at `rate`.
+    pub fn write_telemetry(&mut self, telemetry: &Telemetry, rate: Option<f32>) -> io::Result<()> {
+        for (name, samples) in &telemetry.channels {
+            let channel = self.add_channel(ChannelSchema::new(name, ValueType::F32, rate))?;
+            self.write_samples(channel, samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads a log from a stream, frame by frame.
+pub struct StreamReader<R: Read> {
+    inner: R,
+    /// The channels of the header, then those declared by the frames read so far.
+    channels: Vec<ChannelSchema>,
+}
+
+impl<R: Read> StreamReader<R> {
+    /// Reads the header of a log.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        inner.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not a telemetry log"));
+        }
+        let mut version = [0; 2];
+        inner.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version > VERSION {
+            return Err(invalid(format!("unsupported log version {version}")));
+        }
+        let mut schema = vec![0; read_length(&mut inner)?];
+        inner.read_exact(&mut schema)?;
+        let schema: Schema = serde_json::from_slice(&schema)?;
+        Ok(Self {
+            inner,
+            channels: schema.channels,
+        })
+    }
+
+    /// The channels declared so far.
+    pub fn channels(&self) -> &[ChannelSchema] {
+        &self.channels
+    }
+
+    /// The next frame, or `None` at the end of the log or at a truncated frame.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        loop {
+            let mut kind = [0];
+            if self.inner.read(&mut kind)? == 0 {
+                return Ok(None);
+            }
+            let Ok(length) = read_length(&mut self.inner) else {
+                return Ok(None);
+            };
+            let mut payload = Vec::new();
+            (&mut self.inner)
+                .take(length as u64)
+                .read_to_end(&mut payload)?;
+            if payload.len() < length {
+                return Ok(None);
+            }
+            match kind[0] {
+                CHANNEL_FRAME => {
+                    let channel: ChannelSchema = serde_json::from_slice(&payload)?;
+                    self.channels.push(channel.clone());
+                    return Ok(Some(Frame::Channel(channel)));
+                }
+                SAMPLES_FRAME => return self.samples(&payload).map(Some),
+                _ => continue,
+            }
+        }
+    }
+
+    fn samples(&self, payload: &[u8]) -> io::Result<Frame> {
+        let (index, values) = payload
+            .split_first_chunk::<2>()
+            .ok_or_else(|| invalid("empty samples frame"))?;
+        let channel = u16::from_le_bytes(*index);
+        let value_type = self
+            .channels
+            .get(usize::from(channel))
+            .ok_or_else(|| invalid(format!("undeclared channel {channel}")))?
+            .value_type;
+        let samples = values
+            .chunks_exact(4 + value_type.size())
+            .map(|sample| {
+                let (time, value) = sample.split_at(4);
+                [
+                    f32::from_le_bytes(time.try_into().unwrap_or_default()),
+                    value_type.decode(value),
+                ]
+            })
+            .collect();
+        Ok(Frame::Samples { channel, samples })
+    }
+
+    /// Reads the rest of the log as telemetry.
+    pub fn read_telemetry(mut self) -> io::Result<Telemetry> {
+        let mut telemetry = Telemetry::default();
+        for channel in &self.channels {
+            telemetry.channels.entry(channel.name.clone()).or_default();
+        }
+        while let Some(frame) = self.next_frame()? {
+            match frame {
+                Frame::Channel(channel) => {
+                    telemetry.channels.entry(channel.name).or_default();
+                }
+                Frame::Samples { channel, samples } => {
+                    let name = &self.channels[usize::from(channel)].name;
+                    telemetry
+                        .channels
+                        .entry(name.clone())
+                        .or_default()
+                        .extend(samples);
+                }
+            }
+        }
+        telemetry.channels.retain(|_, samples| !samples.is_empty());
+        Ok(telemetry)
+    }
+}
+
+fn read_length(reader: &mut impl Read) -> io::Result<usize> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    Ok(u32::from_le_bytes(length) as usize)
+}
@@ -0,0 +1,445 @@
+//! This module provides a safety supervisor over the joints of the plants, as the safety
+//! controller of a machine does before any controller logic is trusted with real hardware.
+//!
+//! The supervisor stops the plants, like an emergency stop:
+//! - when the e-stop key (F12 by default) or the *E-STOP* button of the *Supervisor* panel is
+//!   pressed;
+//! - when a revolute joint goes beyond its limits of `supervisor.json`: its position, its speed
+//!   relative to the link it hangs from, or the torque its motor is commanded, the pull of the
+//!   motor towards its targets within its largest force;
+//! - when the [watchdog](crate::watchdog) trips, i.e. an external controller (a WebSocket
+//!   client, the serial bridge, an operator...) stops sending its commands, if configured to.
+//!
+//! Once stopped, the motors of every joint are left without force, whatever the controllers
+//! command, and with `freeze`, the links are held where they are. Both last until the stop is
+//! reset from the panel, which gives the motors their force back and releases the links. The
+//! cause of the stop is logged and shown in the panel.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watchdog::Watchdog;
+use crate::{
+    accessibility_plugin::has_ui,
+    actuators,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::Link,
+};
+
+pub struct SupervisorPlugin;
+
+impl Plugin for SupervisorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Supervisor>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                stop_on_request.run_if(resource_exists::<Persistent<SupervisorSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                (check_limits, hold)
+                    .chain()
+                    .after(actuators::drive)
+                    .before(PhysicsSet::SyncBackend)
+                    .run_if(resource_exists::<Persistent<SupervisorSettings>>),
+            )
+            .add_systems(
+                Update,
+                supervisor_panel
+                    .run_if(resource_exists::<Persistent<SupervisorSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Limits of a revolute joint; those left out aren't supervised.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SafetyLimit {
+    /// Name of the link the joint moves.
+    pub link: String,
+    /// Range of its position, in rad.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<[f32; 2]>,
+    /// Largest speed, in rad/s.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_velocity: Option<f32>,
+    /// Largest torque of its motor, in N·m.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_torque: Option<f32>,
+}
+
+impl SafetyLimit {
+    /// The limit the joint at `position` (if known), turning at `velocity` with its motor
+    /// applying `torque`, is beyond, if any.
+    pub fn violation(&self, position: Option<f32>, velocity: f32, torque: f32) -> Option<String> {
+        if let (Some([min, max]), Some(position)) = (self.position, position) {
+            if position < min || position > max {
+                return Some(format!(
+                    "{} at {position:.3} rad, out of [{min}, {max}]",
+                    self.link
+                ));
+            }
+        }
+        if let Some(max) = self.max_velocity {
+            if velocity.abs() > max {
+                return Some(format!(
+                    "{} turning at {velocity:.3} rad/s, over {max}",
+                    self.link
+                ));
+            }
+        }
+        if let Some(max) = self.max_torque {
+            if torque.abs() > max {
+                return Some(format!(
+                    "{} driven with {torque:.3} N·m, over {max}",
+                    self.link
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// Represents the configuration of the supervisor.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SupervisorSettings {
+    /// Whether the limits are supervised.
+    pub enabled: bool,
+    pub limits: Vec<SafetyLimit>,
+    /// Key stopping the plants.
+    pub key: KeyCode,
+    /// Whether the links are held where they are while stopped.
+    pub freeze: bool,
+    /// Whether a trip of the watchdog stops the plants.
+    pub stop_on_watchdog: bool,
+}
+
+impl Default for SupervisorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            limits: vec![SafetyLimit {
+                link: "motor".to_string(),
+                position: None,
+                max_velocity: Some(50.0),
+                max_torque: None,
+            }],
+            key: KeyCode::F12,
+            freeze: false,
+            stop_on_watchdog: true,
+        }
+    }
+}
+
+/// Torque of a joint motor at `position` and `velocity`: the pull of its spring and damper
+/// towards its targets, within its largest force.
+pub fn motor_torque(motor: &JointMotor, position: f32, velocity: f32) -> f32 {
+    let torque = motor.stiffness * (motor.target_pos - position)
+        + motor.damping * (motor.target_vel - velocity);
+    torque.clamp(-motor.max_force, motor.max_force)
+}
+
+/// An emergency stop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop {
+    pub reason: String,
+    /// Simulated time of the stop, in s.
+    pub at: f32,
+}
+
+/// The stop of the plants, if any.
+#[derive(Debug, Default, Resource)]
+pub struct Supervisor {
+    stop: Option<Stop>,
+}
+
+impl Supervisor {
+    /// Stops the plants for `reason`, unless they already are; returns whether they weren't.
+    pub fn stop(&mut self, reason: impl Into<String>, at: f32) -> bool {
+        if self.stop.is_some() {
+            return false;
+        }
+        let reason = reason.into();
+        error!(target: subsystem::CONTROL, "Emergency stop: {reason}");
+        self.stop = Some(Stop { reason, at });
+        true
+    }
+
+    pub fn stopped(&self) -> Option<&Stop> {
+        self.stop.as_ref()
+    }
+
+    /// Clears the stop, giving the plants back to their controllers.
+    pub fn reset(&mut self) {
+        if self.stop.take().is_some() {
+            info!(target: subsystem::CONTROL, "Emergency stop reset");
+        }
+    }
+}
+
+/// Largest force of a joint motor before the stop took it.
+#[derive(Clone, Component, Copy, Debug)]
+struct Held(f32);
+
+/// A link held by the stop.
+#[derive(Clone, Component, Copy, Debug)]
+struct Frozen;
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SupervisorSettings>("supervisor", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Stops the plants on the e-stop key, and on a trip of the watchdog.
+fn stop_on_request(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<SupervisorSettings>>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    #[cfg(not(target_arch = "wasm32"))] watchdog: Option<Res<Watchdog>>,
+    #[cfg(not(target_arch = "wasm32"))] mut tripped: Local<bool>,
+    mut supervisor: ResMut<Supervisor>,
+) {
+    let now = clock.elapsed_secs();
+    if keys.is_some_and(|keys| keys.just_pressed(settings.key)) {
+        supervisor.stop(format!("{:?} pressed", settings.key), now);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let trip = watchdog.as_ref().and_then(|watchdog| watchdog.trip());
+        if let (Some(trip), false) = (trip, *tripped) {
+            if settings.stop_on_watchdog {
+                supervisor.stop(format!("{} went silent", trip.controller), now);
+            }
+        }
+        *tripped = trip.is_some();
+    }
+}
+
+/// Stops the plants when a supervised joint goes beyond its limits.
+fn check_limits(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<SupervisorSettings>>,
+    contexts: Query<&RapierContext>,
+    joints: Query<(Entity, &Link, &Transform, &ImpulseJoint)>,
+    velocities: Query<&Velocity>,
+    mut supervisor: ResMut<Supervisor>,
+) {
+    if !settings.enabled || supervisor.stopped().is_some() || settings.limits.is_empty() {
+        return;
+    }
+    let context = contexts.get_single().ok();
+    for (entity, link, transform, joint) in &joints {
+        let Some(limit) = settings.limits.iter().find(|limit| limit.link == link.name) else {
+            continue;
+        };
+        let data = joint.data.as_ref();
+        if data.locked_axes().contains(JointAxesMask::ANG_X) {
+            continue;
+        }
+        let position = context.and_then(|context| context.impulse_revolute_joint_angle(entity));
+        let axis = transform.rotation * data.local_axis2();
+        let angular = |entity| {
+            velocities
+                .get(entity)
+                .map_or(Vec3::ZERO, |velocity| velocity.angvel)
+        };
+        let velocity = (angular(entity) - angular(joint.parent)).dot(axis);
+        let torque = data.motor(JointAxis::AngX).map_or(0.0, |motor| {
+            motor_torque(motor, position.unwrap_or(motor.target_pos), velocity)
+        });
+        if let Some(violation) = limit.violation(position, velocity, torque) {
+            supervisor.stop(violation, clock.elapsed_secs());
+            return;
+        }
+    }
+}
+
+/// Leaves the motors without force, and holds the links if configured to, while stopped;
+/// restores them once reset.
+fn hold(
+    mut commands: Commands,
+    settings: Res<Persistent<SupervisorSettings>>,
+    supervisor: Res<Supervisor>,
+    mut joints: Query<(Entity, &mut ImpulseJoint, Option<&Held>)>,
+    mut links: Query<
+        (
+            Entity,
+            &mut RigidBody,
+            Option<&mut Velocity>,
+            Option<&Frozen>,
+        ),
+        With<Link>,
+    >,
+) {
+    let stopped = supervisor.stopped().is_some();
+    for (entity, mut joint, held) in &mut joints {
+        let data = joint.data.as_mut();
+        let axis = if data.locked_axes().contains(JointAxesMask::ANG_X) {
+            JointAxis::LinX
+        } else {
+            JointAxis::AngX
+        };
+        let Some(max_force) = data.motor(axis).map(|motor| motor.max_force) else {
+            continue;
+        };
+        match (stopped, held) {
+            (true, None) => {
+                commands.entity(entity).insert(Held(max_force));
+                data.set_motor_max_force(axis, 0.0);
+            }
+            (true, Some(_)) if max_force != 0.0 => {
+                data.set_motor_max_force(axis, 0.0);
+            }
+            (false, Some(Held(max_force))) => {
+                data.set_motor_max_force(axis, *max_force);
+                commands.entity(entity).remove::<Held>();
+            }
+            _ => {}
+        }
+    }
+    for (entity, mut body, velocity, frozen) in &mut links {
+        match (stopped && settings.freeze, frozen) {
+            (true, None) if *body == RigidBody::Dynamic => {
+                *body = RigidBody::Fixed;
+                if let Some(mut velocity) = velocity {
+                    *velocity = Velocity::zero();
+                }
+                commands.entity(entity).insert(Frozen);
+            }
+            (false, Some(_)) => {
+                *body = RigidBody::Dynamic;
+                commands.entity(entity).remove::<Frozen>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Edits an optional limit, supervised while checked.
+fn optional_limit(ui: &mut egui::Ui, label: &str, limit: &mut Option<f32>, default: f32) {
+    let mut checked = limit.is_some();
+    ui.checkbox(&mut checked, label);
+    match (checked, limit.as_mut()) {
+        (true, Some(value)) => {
+            ui.add(egui::DragValue::new(value).range(0.0..=f32::MAX).speed(0.1));
+        }
+        (true, None) => *limit = Some(default),
+        (false, _) => *limit = None,
+    }
+}
+
+/// Panel to stop the plants, reset the stop and set the limits.
+fn supervisor_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    clock: Res<SimClock>,
+    mut settings: ResMut<Persistent<SupervisorSettings>>,
+    mut supervisor: ResMut<Supervisor>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Supervisor")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match supervisor.stopped().cloned() {
+                Some(stop) => {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("Stopped at {:.3} s: {}", stop.at, stop.reason),
+                    );
+                    if ui.button("Reset").clicked() {
+                        supervisor.reset();
+                    }
+                }
+                None => {
+                    let estop = egui::Button::new(
+                        egui::RichText::new("E-STOP")
+                            .strong()
+                            .color(egui::Color32::WHITE),
+                    )
+                    .fill(egui::Color32::from_rgb(200, 30, 30));
+                    if ui
+                        .add(estop)
+                        .on_hover_text(format!("Or press {:?}", edited.key))
+                        .clicked()
+                    {
+                        supervisor.stop("the e-stop button pressed", clock.elapsed_secs());
+                    }
+                }
+            }
+            ui.checkbox(&mut edited.freeze, "Hold the links while stopped");
+            ui.checkbox(&mut edited.stop_on_watchdog, "Stop when the watchdog trips");
+
+            ui.separator();
+            ui.checkbox(&mut edited.enabled, "Supervise the limits of the joints");
+            let mut removed = None;
+            for (index, limit) in edited.limits.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Link");
+                    ui.text_edit_singleline(&mut limit.link);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut checked = limit.position.is_some();
+                    ui.checkbox(&mut checked, "Position (rad)");
+                    match (checked, limit.position.as_mut()) {
+                        (true, Some([min, max])) => {
+                            ui.add(egui::DragValue::new(min).speed(0.01).range(f32::MIN..=*max));
+                            ui.add(egui::DragValue::new(max).speed(0.01).range(*min..=f32::MAX));
+                        }
+                        (true, None) => {
+                            limit.position = Some([-std::f32::consts::PI, std::f32::consts::PI])
+                        }
+                        (false, _) => limit.position = None,
+                    }
+                });
+                ui.horizontal(|ui| {
+                    optional_limit(ui, "Speed (rad/s)", &mut limit.max_velocity, 10.0);
+                    optional_limit(ui, "Torque (N·m)", &mut limit.max_torque, 1.0);
+                });
+            }
+            if let Some(index) = removed {
+                edited.limits.remove(index);
+            }
+            if ui.button("Add limit").clicked() {
+                edited.limits.push(SafetyLimit {
+                    link: "motor".to_string(),
+                    position: None,
+                    max_velocity: None,
+                    max_torque: None,
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("supervisor", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("supervisor", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
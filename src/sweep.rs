@@ -0,0 +1,484 @@
+//! Parameter sweeps: the controllers are run headlessly once per combination of parameters,
+//! and each run is scored by how a telemetry channel reaches its target, to find good gains
+//! automatically.
+//!
+//! The parameters vary over a grid between their bounds or, for a Monte Carlo sweep, are drawn
+//! at random within them. A parameter is named after what it sets:
+//! - `pid/kp`, `pid/ki` or `pid/kd`: a gain of the position loop, which is then closed;
+//! - `initial/<link>`: the initial angle, in rad, of the joint turning the link, e.g.
+//!   `initial/pendulum`;
+//! - anything else: a setpoint, e.g. `motor/position`.
+//!
+//! Every run is scored by the settling time, the overshoot and the RMS error of the channel,
+//! by the energy drawn by the actuators and their efficiency, and by the violations of the
+//! monitored requirements, in a row of the summary table, with its
+//! [resource usage](crate::resource_usage). With a `cost_script`, each run is also scored by a
+//! [cost function](crate::cost_script) written over its recorded signals, and the runs can be
+//! ranked by it. The
+//! sweep is read from `sweep.json`; every run draws from the same seed, so the runs only differ
+//! by their parameters.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actuators::{self, Energy},
+    batch, config_plugin,
+    cost_script::CostScript,
+    error::{Error, Result},
+    fuzzing::Random,
+    monitors::Monitors,
+    pid_controller::PidSettings,
+    plants::{Link, Plant},
+    resource_usage::{ResourceUsage, UsageMeter},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::{Sample, Telemetry, MOTOR_ANGLE},
+};
+
+/// Represents the configuration of a sweep.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SweepSettings {
+    pub parameters: Vec<Range>,
+    /// Number of random draws of a Monte Carlo sweep, or 0 to run the whole grid.
+    pub samples: usize,
+    /// Seed of the draws of the sweep and of every run.
+    pub seed: u64,
+    /// Simulated duration of a run, in s.
+    pub duration: f32,
+    /// Setpoints set at the start of every run, before the parameters.
+    pub setpoints: BTreeMap<String, f32>,
+    /// Channel scored.
+    pub channel: String,
+    /// Value the channel should reach.
+    pub target: f32,
+    /// Half width of the band around the target the channel settles in.
+    pub band: f32,
+    /// Metric the runs are ranked by.
+    pub rank_by: Cost,
+    /// Script of the cost function scoring every run, in the configuration directory.
+    pub cost_script: Option<PathBuf>,
+}
+
+impl Default for SweepSettings {
+    fn default() -> Self {
+        Self {
+            parameters: Vec::new(),
+            samples: 0,
+            seed: 1,
+            duration: 5.0,
+            setpoints: BTreeMap::from([(MOTOR_POSITION.to_string(), 1.0)]),
+            channel: MOTOR_ANGLE.to_string(),
+            target: 1.0,
+            band: 0.02,
+            rank_by: Cost::default(),
+            cost_script: None,
+        }
+    }
+}
+
+/// Values of a parameter.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Range {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    /// Values of the grid, the bounds included; a single one is the lower bound.
+    pub steps: usize,
+}
+
+impl Range {
+    /// The `index`th value of the grid.
+    pub fn value(&self, index: usize) -> f32 {
+        if self.steps <= 1 {
+            return self.min;
+        }
+        self.min + (self.max - self.min) * index as f32 / (self.steps - 1) as f32
+    }
+}
+
+/// Metric the runs of a sweep are ranked by, lowest first.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Cost {
+    SettlingTime,
+    Overshoot,
+    #[default]
+    RmsError,
+    /// Electrical energy drawn by the actuators.
+    Energy,
+    /// Cost returned by the script of the sweep.
+    Script,
+}
+
+/// How a channel reaches its target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    /// Time from the first sample after which the channel stays within the band, unless it
+    /// leaves it at the end.
+    pub settling_time: Option<f32>,
+    /// Largest excursion past the target, away from the first sample, in the unit of the
+    /// channel; any excursion when the channel starts on the target.
+    pub overshoot: f32,
+    pub rms_error: f32,
+}
+
+impl Metrics {
+    /// The metrics of `samples`, unless there are none.
+    pub fn of(samples: &[Sample], target: f32, band: f32) -> Option<Self> {
+        let [start, first] = *samples.first()?;
+        let outside = samples
+            .iter()
+            .rposition(|[_, value]| (value - target).abs() > band);
+        let settling_time = match outside {
+            Some(index) => samples.get(index + 1).map(|[time, _]| time - start),
+            None => Some(0.0),
+        };
+        let past = |value: f32| {
+            if first == target {
+                (value - target).abs()
+            } else {
+                (value - target) * (target - first).signum()
+            }
+        };
+        let overshoot = samples
+            .iter()
+            .map(|[_, value]| past(*value))
+            .fold(0.0, f32::max);
+        let squares: f32 = samples
+            .iter()
+            .map(|[_, value]| (value - target).powi(2))
+            .sum();
+        Some(Self {
+            settling_time,
+            overshoot,
+            rms_error: (squares / samples.len() as f32).sqrt(),
+        })
+    }
+
+    /// Value of the metric `cost`, infinite when the channel doesn't settle or diverges.
+    pub fn cost(&self, cost: Cost) -> f32 {
+        let value = match cost {
+            Cost::SettlingTime => self.settling_time.unwrap_or(f32::INFINITY),
+            Cost::Overshoot => self.overshoot,
+            Cost::RmsError => self.rms_error,
+            // Not metrics of the channel.
+            Cost::Energy | Cost::Script => f32::INFINITY,
+        };
+        if value.is_nan() {
+            f32::INFINITY
+        } else {
+            value
+        }
+    }
+}
+
+/// A run of a sweep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepRun {
+    pub parameters: BTreeMap<String, f32>,
+    /// Metrics of the channel, unless it wasn't recorded.
+    pub metrics: Option<Metrics>,
+    /// Energy of the actuators, unless there are none.
+    pub energy: Option<Energy>,
+    /// Number of violations of the monitored requirements.
+    pub violations: usize,
+    pub usage: ResourceUsage,
+    /// Cost of the run by the cost script, if any.
+    pub scripted: Option<f32>,
+}
+
+impl SweepRun {
+    /// Value of the metric `cost`, infinite when the run lacks it.
+    pub fn cost(&self, cost: Cost) -> f32 {
+        match cost {
+            Cost::Energy => self
+                .energy
+                .map_or(f32::INFINITY, |energy| energy.electrical),
+            Cost::Script => self.scripted.unwrap_or(f32::INFINITY),
+            _ => self
+                .metrics
+                .map_or(f32::INFINITY, |metrics| metrics.cost(cost)),
+        }
+    }
+}
+
+/// The combinations of parameters of `settings`, the whole grid or random draws within the
+/// bounds.
+pub fn combinations(settings: &SweepSettings) -> Vec<BTreeMap<String, f32>> {
+    if settings.samples > 0 {
+        let mut random = Random::new(settings.seed);
+        return (0..settings.samples)
+            .map(|_| {
+                settings
+                    .parameters
+                    .iter()
+                    .map(|range| (range.name.clone(), random.range(range.min, range.max)))
+                    .collect()
+            })
+            .collect();
+    }
+    let count: usize = settings
+        .parameters
+        .iter()
+        .map(|range| range.steps.max(1))
+        .product();
+    (0..count)
+        .map(|mut index| {
+            // The last parameter varies fastest.
+            let mut combination = BTreeMap::new();
+            for range in settings.parameters.iter().rev() {
+                let steps = range.steps.max(1);
+                combination.insert(range.name.clone(), range.value(index % steps));
+                index /= steps;
+            }
+            combination
+        })
+        .collect()
+}
+
+/// Runs `plant` and its controllers with `parameters`, stepping by `dt`, and scores the run.
+pub fn evaluate(
+    plant: &Plant,
+    settings: &SweepSettings,
+    parameters: &BTreeMap<String, f32>,
+    dt: f32,
+) -> Result<SweepRun> {
+    let script = settings
+        .cost_script
+        .as_ref()
+        .map(|path| CostScript::read(&config_plugin::config_dir().join(path)))
+        .transpose()?;
+    let mut meter = UsageMeter::start();
+    let mut app = batch::app(plant, dt, settings.seed);
+    let steps = (settings.duration / dt).round() as usize;
+    // The plant is spawned by the first update, so the parameters apply from the second step.
+    for step in 0..steps {
+        let start = Instant::now();
+        app.update();
+        meter.step(start.elapsed());
+        if app.should_exit().is_some() {
+            return Err(Error::Config {
+                name: "sweep".to_string(),
+                message: format!("{} stopped on the error logged above", plant.name),
+            });
+        }
+        if step == 0 {
+            let world = app.world_mut();
+            for (name, value) in settings.setpoints.iter().chain(parameters) {
+                apply(world, name, *value)?;
+            }
+        }
+    }
+    let energy = actuators::total(app.world_mut());
+    let world = app.world();
+    let telemetry = world.resource::<Telemetry>();
+    let samples = telemetry.channels.get(&settings.channel);
+    let mut run = SweepRun {
+        parameters: parameters.clone(),
+        metrics: samples.and_then(|samples| Metrics::of(samples, settings.target, settings.band)),
+        energy,
+        violations: world.resource::<Monitors>().violations.len(),
+        usage: meter.usage(),
+        scripted: None,
+    };
+    if let Some(script) = &script {
+        run.scripted = Some(script.cost(&run, telemetry, settings.duration)?);
+    }
+    Ok(run)
+}
+
+/// Sets the parameter `name` to `value`.
+pub(crate) fn apply(world: &mut World, name: &str, value: f32) -> Result<()> {
+    let error = |message: String| Error::Config {
+        name: "sweep".to_string(),
+        message,
+    };
+    match name.split_once('/') {
+        Some(("pid", gain)) => {
+            let mut pid = world.resource_mut::<Persistent<PidSettings>>();
+            let pid = pid.get_mut();
+            pid.enabled = true;
+            match gain {
+                "kp" => pid.gains.kp = value,
+                "ki" => pid.gains.ki = value,
+                "kd" => pid.gains.kd = value,
+                _ => return Err(error(format!("no gain {name}, expected kp, ki or kd"))),
+            }
+        }
+        Some(("initial", link)) => turn(world, link, value).map_err(error)?,
+        _ => world.resource_mut::<Setpoints>().set(name, value),
+    }
+    Ok(())
+}
+
+/// Turns the joint nearest to the link at `path`, past the joints that don't turn, by `angle`,
+/// with the bodies on the side of the link; on the other side if the link's is fixed.
+pub(crate) fn turn(world: &mut World, path: &str, angle: f32) -> Result<(), String> {
+    let mut bodies = world.query::<(Entity, &RigidBody, Option<&Link>, Option<&ImpulseJoint>)>();
+    let (mut link, mut fixed, mut joints) = (None, BTreeSet::new(), Vec::new());
+    for (entity, body, name, joint) in bodies.iter(world) {
+        if name.is_some_and(|name| name.path() == path) {
+            link = Some(entity);
+        }
+        if *body == RigidBody::Fixed {
+            fixed.insert(entity);
+        }
+        if let Some(joint) = joint {
+            joints.push((entity, joint.parent, *joint.data.as_ref()));
+        }
+    }
+    let link = link.ok_or_else(|| format!("no link {path}"))?;
+    let turns = |joint: &GenericJoint| !joint.locked_axes().contains(JointAxesMask::ANG_X);
+    // The bodies reached from `start` through the joints `through` lets through.
+    let connected = |start: Entity, through: &dyn Fn(usize, &GenericJoint) -> bool| {
+        let mut reached = BTreeSet::from([start]);
+        loop {
+            let count = reached.len();
+            for (index, (entity, parent, joint)) in joints.iter().enumerate() {
+                if through(index, joint) && (reached.contains(entity) || reached.contains(parent)) {
+                    reached.extend([*entity, *parent]);
+                }
+            }
+            if reached.len() == count {
+                return reached;
+            }
+        }
+    };
+
+    let rigid = connected(link, &|_, joint| !turns(joint));
+    let Some((index, &(entity, parent, joint))) =
+        joints
+            .iter()
+            .enumerate()
+            .find(|(_, (entity, parent, joint))| {
+                turns(joint) && (rigid.contains(entity) || rigid.contains(parent))
+            })
+    else {
+        return Err(format!("no joint turns {path}"));
+    };
+    // The angle turns the body holding the joint relative to its parent.
+    let mut moved = connected(link, &|other, _| other != index);
+    if moved.iter().any(|body| fixed.contains(body)) {
+        let other = if moved.contains(&entity) {
+            parent
+        } else {
+            entity
+        };
+        moved = connected(other, &|other, _| other != index);
+    }
+    let angle = if moved.contains(&entity) {
+        angle
+    } else {
+        -angle
+    };
+
+    let Some(transform) = world.get::<Transform>(entity).copied() else {
+        return Err(format!("the joint turning {path} isn't placed"));
+    };
+    let pivot = transform.transform_point(joint.local_anchor2());
+    let rotation = Quat::from_axis_angle(
+        (transform.rotation * joint.local_axis2()).normalize(),
+        angle,
+    );
+    for body in moved {
+        if let Some(mut transform) = world.get_mut::<Transform>(body) {
+            transform.rotate_around(pivot, rotation);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the combinations of `settings` on `plant`, stepping by `dt`, calling `on_run` with each
+/// run as it ends.
+pub fn sweep(
+    plant: &Plant,
+    settings: &SweepSettings,
+    dt: f32,
+    mut on_run: impl FnMut(usize, &SweepRun),
+) -> Result<Vec<SweepRun>> {
+    let mut runs = Vec::new();
+    for (index, parameters) in combinations(settings).iter().enumerate() {
+        let run = evaluate(plant, settings, parameters, dt)?;
+        on_run(index, &run);
+        runs.push(run);
+    }
+    Ok(runs)
+}
+
+/// Indices of `runs`, best first by `cost`; runs without the metric come last.
+pub fn ranking(runs: &[SweepRun], cost: Cost) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..runs.len()).collect();
+    let value = |index: &usize| runs[*index].cost(cost);
+    indices.sort_by(|a, b| value(a).total_cmp(&value(b)));
+    indices
+}
+
+/// Writes the summary table of `runs` as CSV, one row per run with its parameters, its metrics
+/// and its rank.
+pub fn write_summary(settings: &SweepSettings, runs: &[SweepRun], path: &Path) -> Result<()> {
+    let mut ranks = vec![0; runs.len()];
+    for (rank, index) in ranking(runs, settings.rank_by).into_iter().enumerate() {
+        ranks[index] = rank + 1;
+    }
+    let mut csv = String::from("run");
+    for range in &settings.parameters {
+        csv.push_str(&format!(",{}", range.name));
+    }
+    csv.push_str(",settling_time,overshoot,rms_error,energy,efficiency");
+    if settings.cost_script.is_some() {
+        csv.push_str(",cost");
+    }
+    csv.push_str(&format!(",violations,{},rank\n", ResourceUsage::CSV_HEADER));
+    for (index, run) in runs.iter().enumerate() {
+        csv.push_str(&index.to_string());
+        for range in &settings.parameters {
+            csv.push_str(&format!(",{}", run.parameters[&range.name]));
+        }
+        match run.metrics {
+            Some(metrics) => {
+                let settling = metrics
+                    .settling_time
+                    .map_or(String::new(), |time| time.to_string());
+                csv.push_str(&format!(
+                    ",{settling},{},{}",
+                    metrics.overshoot, metrics.rms_error
+                ));
+            }
+            None => csv.push_str(",,,"),
+        }
+        match run.energy {
+            Some(energy) => {
+                let efficiency = energy
+                    .efficiency()
+                    .map_or(String::new(), |efficiency| efficiency.to_string());
+                csv.push_str(&format!(",{},{efficiency}", energy.electrical));
+            }
+            None => csv.push_str(",,"),
+        }
+        if settings.cost_script.is_some() {
+            let cost = run.scripted.map_or(String::new(), |cost| cost.to_string());
+            csv.push_str(&format!(",{cost}"));
+        }
+        csv.push_str(&format!(
+            ",{},{},{}\n",
+            run.violations,
+            run.usage.csv(),
+            ranks[index]
+        ));
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+    }
+    fs::write(path, csv).map_err(|error| Error::io(path, error))
+}
@@ -0,0 +1,497 @@
+//! This module swings the pendulum up from hanging, pumping energy into it until it nears
+//! upright, then hands over to a stabilizing controller, e.g. the [LQR](crate::lqr) regulator.
+//!
+//! The energy of the pendulum is measured in units of `m g l`, relative to upright at rest: -2
+//! hanging at rest, 0 upright at rest. While swinging up, the arm is accelerated in the
+//! direction that increases the energy, proportionally to the energy missing (the
+//! Åström–Furuta law), with some damping of the arm velocity so the arm doesn't drift.
+//!
+//! The hand-over is decided by a [`ModeSwitch`], a state machine component on the motor joint:
+//! it switches to [`Mode::Balance`] once the pendulum is within the catch angle of upright with
+//! about the energy of upright, and back to [`Mode::SwingUp`] only beyond the release angle, so
+//! it doesn't chatter between the two. Stabilizing controllers on the same entity wait for the
+//! balance mode before commanding the motor.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    error::{Error, ErrorEvent},
+    estimation::{self, EstimationSet, JointEstimate},
+    latency::LoopLatency,
+    logging::subsystem,
+    plants::{self, Link, MOTOR_FACTOR},
+    sensors::{ControlSet, Encoder, SensorSet},
+    state_machines::{MachineGraph, StateMachines},
+    telemetry::Telemetry,
+};
+
+/// Energy of the pendulum relative to upright at rest, in units of `m g l`.
+pub const SWING_UP_ENERGY: &str = "swing_up/energy";
+/// Acceleration of the arm commanded while swinging up, in rad/s².
+pub const SWING_UP_ACCELERATION: &str = "swing_up/acceleration";
+
+/// Mode switch of the swing-up, as drawn in the state machines panel.
+pub const SWING_UP: MachineGraph = MachineGraph {
+    name: "Swing-up",
+    states: &["Swing-up", "Balance"],
+    transitions: &[(0, 1), (1, 0)],
+};
+
+pub struct SwingUpPlugin;
+
+impl Plugin for SwingUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_controllers
+                    .run_if(resource_exists_and_changed::<Persistent<SwingUpSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                run_controllers
+                    .after(SimClockSet::Advance)
+                    .after(SensorSet)
+                    .after(EstimationSet)
+                    .in_set(ControlSet),
+            )
+            .add_systems(
+                Update,
+                swing_up_panel
+                    .run_if(resource_exists::<Persistent<SwingUpSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Thresholds of a [`ModeSwitch`].
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ModeThresholds {
+    /// Largest angle from upright at which the stabilizing controller takes over, in rad.
+    pub catch: f32,
+    /// Angle from upright beyond which the swing-up takes over again, in rad.
+    pub release: f32,
+    /// Largest energy error at which the stabilizing controller takes over.
+    pub energy: f32,
+}
+
+impl Default for ModeThresholds {
+    fn default() -> Self {
+        Self {
+            catch: 0.2,
+            release: 0.3,
+            energy: 0.2,
+        }
+    }
+}
+
+/// Mode of a [`ModeSwitch`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Mode {
+    /// Pumping energy into the pendulum.
+    #[default]
+    SwingUp,
+    /// Stabilizing the pendulum upright.
+    Balance,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        SWING_UP.states[self as usize]
+    }
+}
+
+/// Switches between swinging a pendulum up and balancing it, with hysteresis: the balance
+/// takes over within the catch angle of upright and with the energy of upright, and lets go
+/// beyond the release angle.
+#[derive(Clone, Component, Debug, Default, Deserialize, Serialize)]
+pub struct ModeSwitch {
+    pub thresholds: ModeThresholds,
+    mode: Mode,
+}
+
+impl ModeSwitch {
+    pub fn new(thresholds: ModeThresholds) -> Self {
+        Self {
+            thresholds,
+            mode: Mode::SwingUp,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Takes the mode of `saved`, keeping its own thresholds, or swings up again without it.
+    pub fn restore(&mut self, saved: Option<&Self>) {
+        self.mode = saved.map_or(Mode::SwingUp, |saved| saved.mode);
+    }
+
+    /// Mode for the angle of the pendulum from upright and its energy error.
+    pub fn update(&mut self, angle: f32, energy: f32) -> Mode {
+        let thresholds = &self.thresholds;
+        self.mode = match self.mode {
+            Mode::SwingUp
+                if angle.abs() <= thresholds.catch && energy.abs() <= thresholds.energy =>
+            {
+                Mode::Balance
+            }
+            Mode::Balance if angle.abs() > thresholds.release => Mode::SwingUp,
+            mode => mode,
+        };
+        self.mode
+    }
+}
+
+/// Energy of a pendulum at `angle` from hanging, in rad, turning at `velocity`, in rad/s,
+/// relative to upright at rest, in units of `m g l`, for its natural `frequency` in rad/s.
+pub fn energy_error(frequency: f32, angle: f32, velocity: f32) -> f32 {
+    0.5 * (velocity / frequency).powi(2) - 1.0 - angle.cos()
+}
+
+/// Represents the configuration of the swing-up.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct SwingUpSettings {
+    pub enabled: bool,
+    /// Names of the links whose joints are the motor and the pivot of the pendulum, in each
+    /// plant.
+    pub motor: String,
+    pub pendulum: String,
+    /// Natural frequency of the pendulum hanging, `sqrt(m g l / J)`, in rad/s.
+    pub frequency: f32,
+    /// Acceleration of the arm per unit of energy missing, in rad/s².
+    pub gain: f32,
+    /// Damping of the velocity of the arm, in 1/s.
+    pub damping: f32,
+    /// Largest acceleration of the arm commanded, in rad/s².
+    pub limit: f32,
+    pub thresholds: ModeThresholds,
+}
+
+impl Default for SwingUpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            motor: "motor".to_string(),
+            pendulum: "pivot".to_string(),
+            // That of the embedded pendulum, whose center of mass swings 1 m from the pivot.
+            frequency: 2.0,
+            gain: 5.0,
+            damping: 0.5,
+            limit: 10.0,
+            thresholds: ModeThresholds::default(),
+        }
+    }
+}
+
+/// A swing-up on the motor joint of its entity, pumping energy into the pendulum on the joint
+/// of `pendulum` while the [`ModeSwitch`] of the entity is in [`Mode::SwingUp`].
+#[derive(Clone, Component, Debug, Deserialize, Serialize)]
+pub struct SwingUpController {
+    pub pendulum: Entity,
+    /// Telemetry channels of the energy error and of the acceleration commanded.
+    pub energy: String,
+    pub acceleration: String,
+    /// Last angles of the motor and pendulum joints, within a turn.
+    previous: Option<[f32; 2]>,
+    energy_error: Option<f32>,
+    /// Velocity of the arm commanded, while pumping.
+    velocity: Option<f32>,
+}
+
+impl SwingUpController {
+    /// A swing-up with the signals of a plant.
+    pub fn new(pendulum: Entity, plant: &str) -> Self {
+        Self {
+            pendulum,
+            energy: plants::namespaced(plant, SWING_UP_ENERGY),
+            acceleration: plants::namespaced(plant, SWING_UP_ACCELERATION),
+            previous: None,
+            energy_error: None,
+            velocity: None,
+        }
+    }
+
+    /// Energy error of the pendulum, once its velocity is measured.
+    pub fn energy_error(&self) -> Option<f32> {
+        self.energy_error
+    }
+
+    /// Takes the state of `saved`, keeping its own pendulum and signals, or starts over without
+    /// it.
+    pub fn restore(&mut self, saved: Option<&Self>) {
+        self.previous = saved.and_then(|saved| saved.previous);
+        self.energy_error = saved.and_then(|saved| saved.energy_error);
+        self.velocity = saved.and_then(|saved| saved.velocity);
+    }
+
+    /// Updates the mode from the angles of the motor and pendulum joints, and gives the
+    /// acceleration of the arm and the velocity to command for the next `dt` seconds while
+    /// swinging up.
+    pub fn update(
+        &mut self,
+        settings: &SwingUpSettings,
+        switch: &mut ModeSwitch,
+        [motor, pendulum]: [f32; 2],
+        dt: f32,
+    ) -> Option<[f32; 2]> {
+        let [last_motor, last_pendulum] = self.previous.replace([motor, pendulum])?;
+        let swing = wrap(pendulum - last_pendulum) / dt;
+        let energy = energy_error(settings.frequency, pendulum, swing);
+        self.energy_error = Some(energy);
+        if switch.update(wrap(pendulum - PI), energy) == Mode::Balance {
+            self.velocity = None;
+            return None;
+        }
+        let velocity = self
+            .velocity
+            .unwrap_or_else(|| wrap(motor - last_motor) / dt);
+        // Accelerating the motor joint pushes the pendulum its way below the arm, and the
+        // other way above it.
+        let direction = (swing * pendulum.cos()).signum();
+        let acceleration = (-settings.gain * energy * direction - settings.damping * velocity)
+            .clamp(-settings.limit, settings.limit);
+        let velocity = velocity + acceleration * dt;
+        self.velocity = Some(velocity);
+        Some([acceleration, velocity])
+    }
+}
+
+/// Angle within `(-π, π]`.
+fn wrap(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<SwingUpSettings>("swing_up", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Adds or removes the swing-ups and their mode switches on the motor joints of the plants
+/// with a pendulum joint, and gives them the configured thresholds.
+fn configure_controllers(
+    mut commands: Commands,
+    settings: Res<Persistent<SwingUpSettings>>,
+    mut joints: Query<
+        (
+            Entity,
+            &Link,
+            Option<&SwingUpController>,
+            Option<&mut ModeSwitch>,
+        ),
+        With<ImpulseJoint>,
+    >,
+) {
+    let pendulums: Vec<(String, Entity)> = joints
+        .iter()
+        .filter(|(_, link, ..)| link.name == settings.pendulum)
+        .map(|(entity, link, ..)| (link.plant.clone(), entity))
+        .collect();
+    for (entity, link, controller, switch) in &mut joints {
+        let pendulum = pendulums
+            .iter()
+            .find(|(plant, _)| *plant == link.plant)
+            .map(|(_, pendulum)| *pendulum);
+        let controlled = settings.enabled && link.name == settings.motor;
+        match (controller, pendulum) {
+            (Some(_), _) if controlled => {
+                if let Some(mut switch) = switch {
+                    switch.thresholds = settings.thresholds;
+                }
+            }
+            (Some(_), _) => {
+                commands
+                    .entity(entity)
+                    .remove::<(SwingUpController, ModeSwitch)>();
+                info!(target: subsystem::CONTROL, "Swing-up of {} removed", link.path());
+            }
+            (None, Some(pendulum)) if controlled => {
+                commands.entity(entity).insert((
+                    SwingUpController::new(pendulum, &link.plant),
+                    ModeSwitch::new(settings.thresholds),
+                ));
+                info!(target: subsystem::CONTROL, "Swing-up of {} added", link.path());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Measures the joints after each physics step, switches the modes and, while swinging up,
+/// commands the motors for the next step.
+#[allow(clippy::too_many_arguments)]
+fn run_controllers(
+    clock: Res<SimClock>,
+    settings: Option<Res<Persistent<SwingUpSettings>>>,
+    mut controllers: Query<(
+        Entity,
+        &Link,
+        &mut SwingUpController,
+        &mut ModeSwitch,
+        &mut ImpulseJoint,
+    )>,
+    encoders: Query<&Encoder>,
+    latencies: Query<&LoopLatency>,
+    estimates: Query<&JointEstimate>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+    mut machines: Option<ResMut<StateMachines>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "run_swing_up").entered();
+    let (dt, Some(settings), Ok(context)) = (clock.delta_secs(), settings, contexts.get_single())
+    else {
+        return;
+    };
+    if dt <= 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    for (entity, link, mut controller, mut switch, mut joint) in &mut controllers {
+        let motor = estimation::joint_angle(context, &encoders, &latencies, &estimates, entity);
+        let pendulum = estimation::joint_angle(
+            context,
+            &encoders,
+            &latencies,
+            &estimates,
+            controller.pendulum,
+        );
+        let angles = motor.zip(pendulum);
+        let Some(angles) = angles.map(<[f32; 2]>::from) else {
+            continue;
+        };
+        let mode = switch.mode();
+        let command = controller.update(&settings, &mut switch, angles, dt);
+        if mode != switch.mode() {
+            info!(
+                target: subsystem::CONTROL,
+                "{}: {} at {time:.2} s",
+                link.path(),
+                switch.mode().name()
+            );
+        }
+        if let Some(machines) = machines.as_mut() {
+            machines.observe(&SWING_UP, switch.mode() as usize, time);
+        }
+        if let (Some(telemetry), Some(energy)) = (telemetry.as_mut(), controller.energy_error()) {
+            telemetry.record(&controller.energy, time, energy);
+        }
+        let Some([acceleration, velocity]) = command else {
+            continue;
+        };
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&controller.acceleration, time, acceleration);
+        }
+        joint
+            .data
+            .as_mut()
+            .set_motor_velocity(JointAxis::AngX, velocity, MOTOR_FACTOR);
+    }
+}
+
+/// Panel to tune the swing-up and its mode switch.
+fn swing_up_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<SwingUpSettings>>,
+    controllers: Query<(&SwingUpController, &ModeSwitch)>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Swing-up")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Swing the pendulum up");
+            ui.add(
+                egui::DragValue::new(&mut edited.frequency)
+                    .range(0.01..=100.0)
+                    .speed(0.01)
+                    .prefix("Natural frequency: ")
+                    .suffix(" rad/s"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.gain)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Energy gain: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.damping)
+                    .range(0.0..=100.0)
+                    .speed(0.01)
+                    .prefix("Arm damping: ")
+                    .suffix(" 1/s"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.limit)
+                    .range(0.0..=1000.0)
+                    .speed(0.1)
+                    .prefix("Acceleration limit: ")
+                    .suffix(" rad/s²"),
+            );
+            ui.label("Hand-over to the balance");
+            ui.horizontal(|ui| {
+                let thresholds = &mut edited.thresholds;
+                ui.add(
+                    egui::DragValue::new(&mut thresholds.catch)
+                        .range(0.0..=PI)
+                        .speed(0.01)
+                        .prefix("Catch: ")
+                        .suffix(" rad"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut thresholds.release)
+                        .range(thresholds.catch..=PI)
+                        .speed(0.01)
+                        .prefix("Release: ")
+                        .suffix(" rad"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut thresholds.energy)
+                        .range(0.0..=2.0)
+                        .speed(0.01)
+                        .prefix("Energy: "),
+                );
+            });
+
+            ui.separator();
+            for (controller, switch) in &controllers {
+                ui.label(format!(
+                    "{}: {}, energy {:.2}",
+                    controller.energy,
+                    switch.mode().name(),
+                    controller.energy_error().unwrap_or_default()
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("swing_up", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("swing_up", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,932 @@
+//! This module provides a teach panel, as on the pendant of a robot: the current configuration
+//! of the joints is recorded as a named pose, the poses are arranged in a sequence, and the
+//! sequence is played back.
+//!
+//! Each move of the sequence starts from the measured configuration and follows a trapezoidal
+//! profile per joint, within the velocity and acceleration limits of the joint. The joints are
+//! driven through their velocity setpoints: the velocity of the profile, corrected by a
+//! proportional term on the measured position so that the joints end on the pose.
+//!
+//! With a blend tolerance, the poses are instead joined into a [`BlendedPath`] whose corners are
+//! rounded within the tolerance, followed without stopping at every pose: the joints move
+//! together along the path, on a trapezoidal profile of the distance along it. The path only
+//! stops at the corners too sharp to blend. The contour error, the distance from the measured
+//! configuration to the path, is recorded and plotted in the panel.
+//!
+//! The joints can also be taught by leading them through a motion by hand (lead-through
+//! programming): while recording, the joints are released, and their positions are recorded at
+//! every step as they're dragged around. The recording is smoothed by a moving average, then
+//! replayed: the joints move to its start as to a pose, then track it, driven by the velocity
+//! of the smoothed trajectory corrected by the proportional term on the position.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{BlendedPath, TrapezoidalProfile},
+    error::{Error, ErrorEvent, Result},
+    setpoints::{Setpoints, MOTOR_RELEASE, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_ANGLE},
+    theme::{to_egui, Theme},
+};
+
+/// Distance from the measured configuration to the blended path being played.
+pub const TEACH_CONTOUR_ERROR: &str = "teach/contour_error";
+/// Distance from the measured configuration to the recording being replayed.
+pub const TEACH_TRACKING_ERROR: &str = "teach/tracking_error";
+/// Number of steps plotted in the teach panel.
+const PLOTTED_STEPS: usize = 600;
+
+pub struct TeachPlugin;
+
+impl Plugin for TeachPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeachPlayback>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                (record, play)
+                    .chain()
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<TeachSettings>>),
+            )
+            .add_systems(
+                Update,
+                teach_panel
+                    .run_if(resource_exists::<Persistent<TeachSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// A joint whose position is measured and whose velocity is commanded.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TaughtJoint {
+    pub name: String,
+    /// Telemetry channel measuring the position of the joint.
+    pub position: String,
+    /// Velocity setpoint of the joint.
+    pub setpoint: String,
+    /// Velocity limit of the moves, in units of the position per second.
+    pub max_velocity: f32,
+    /// Acceleration limit of the moves, in units of the position per second squared.
+    pub max_acceleration: f32,
+    /// Gain from the position error to the velocity setpoint, in 1/s.
+    pub gain: f32,
+    /// Setpoint releasing the joint while it's led through by hand, if it can be released.
+    #[serde(default)]
+    pub release: Option<String>,
+}
+
+/// A named configuration of the joints.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Pose {
+    pub name: String,
+    /// Position of each joint, in the order of the joints.
+    pub positions: Vec<f32>,
+}
+
+/// Represents the taught poses and the sequence they are played in.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TeachSettings {
+    pub joints: Vec<TaughtJoint>,
+    pub poses: Vec<Pose>,
+    /// Names of the poses to reach, in order.
+    pub sequence: Vec<String>,
+    /// Time spent on each pose before moving to the next, in seconds.
+    pub dwell: f32,
+    /// Largest distance between a pose and the path blending its corner, in units of the
+    /// positions, or 0 to stop at every pose.
+    pub blend: f32,
+    /// Window of the moving average smoothing the recorded trajectories, in seconds.
+    pub smoothing: f32,
+}
+
+impl Default for TeachSettings {
+    fn default() -> Self {
+        Self {
+            joints: vec![TaughtJoint {
+                name: "Motor".to_string(),
+                position: MOTOR_ANGLE.to_string(),
+                setpoint: MOTOR_VELOCITY.to_string(),
+                max_velocity: std::f32::consts::PI,
+                max_acceleration: std::f32::consts::TAU,
+                gain: 5.0,
+                release: Some(MOTOR_RELEASE.to_string()),
+            }],
+            poses: Vec::new(),
+            sequence: Vec::new(),
+            dwell: 0.5,
+            blend: 0.0,
+            smoothing: 0.2,
+        }
+    }
+}
+
+impl TeachSettings {
+    /// The poses of the sequence, checked against the joints.
+    pub fn sequence_poses(&self) -> Result<Vec<&Pose>> {
+        let invalid = |message: String| Error::Config {
+            name: "teach".to_string(),
+            message,
+        };
+        self.sequence
+            .iter()
+            .map(|name| {
+                let pose = self
+                    .poses
+                    .iter()
+                    .find(|pose| pose.name == *name)
+                    .ok_or_else(|| invalid(format!("the sequence has no pose `{name}`")))?;
+                if pose.positions.len() != self.joints.len() {
+                    return Err(invalid(format!(
+                        "the pose `{name}` has {} positions for {} joints",
+                        pose.positions.len(),
+                        self.joints.len()
+                    )));
+                }
+                Ok(pose)
+            })
+            .collect()
+    }
+
+    /// Checks a recorded trajectory against the joints.
+    pub fn check_recording(&self, trajectory: &Trajectory) -> Result<()> {
+        let invalid = |message: String| Error::Config {
+            name: "teach".to_string(),
+            message,
+        };
+        match trajectory.positions.first() {
+            None => Err(invalid("nothing was recorded".to_string())),
+            Some(positions) if positions.len() != self.joints.len() => Err(invalid(format!(
+                "the recording has {} positions for {} joints",
+                positions.len(),
+                self.joints.len()
+            ))),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Positions of the joints recorded at the steps of the simulation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trajectory {
+    /// Time of each sample, increasing, in seconds.
+    pub times: Vec<f32>,
+    /// Positions of the joints at each sample.
+    pub positions: Vec<Vec<f32>>,
+}
+
+impl Trajectory {
+    pub fn push(&mut self, time: f32, positions: Vec<f32>) {
+        self.times.push(time);
+        self.positions.push(positions);
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Time from the first sample to the last, in seconds.
+    pub fn duration(&self) -> f32 {
+        match (self.times.first(), self.times.last()) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0.0,
+        }
+    }
+
+    /// The trajectory smoothed by a centered moving average over `window` seconds, averaging
+    /// out the jitter of the hand without delaying the motion. The window shrinks towards the
+    /// ends, so the trajectory still starts and ends on the recorded positions.
+    pub fn smoothed(&self, window: f32) -> Self {
+        let (Some(&first), Some(&last)) = (self.times.first(), self.times.last()) else {
+            return self.clone();
+        };
+        let positions = self
+            .times
+            .iter()
+            .map(|&time| {
+                let half = (window.max(0.0) / 2.0).min(time - first).min(last - time);
+                let start = self.times.partition_point(|&t| t < time - half);
+                let end = self.times.partition_point(|&t| t <= time + half);
+                let averaged = &self.positions[start..end];
+                (0..averaged[0].len())
+                    .map(|joint| {
+                        averaged
+                            .iter()
+                            .map(|positions| positions[joint])
+                            .sum::<f32>()
+                            / averaged.len() as f32
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            times: self.times.clone(),
+            positions,
+        }
+    }
+
+    /// Positions and velocities of the joints `time` seconds after the first sample,
+    /// interpolated linearly. Before the first sample and after the last, the joints rest on
+    /// them.
+    ///
+    /// # Panics
+    ///
+    /// When the trajectory is empty.
+    pub fn sample(&self, time: f32) -> (Vec<f32>, Vec<f32>) {
+        let time = self.times[0] + time;
+        let index = self.times.partition_point(|&t| t <= time);
+        if index == 0 || index == self.times.len() {
+            let end = &self.positions[index.saturating_sub(1)];
+            return (end.clone(), vec![0.0; end.len()]);
+        }
+        let span = self.times[index] - self.times[index - 1];
+        let fraction = (time - self.times[index - 1]) / span;
+        let (before, after) = (&self.positions[index - 1], &self.positions[index]);
+        before
+            .iter()
+            .zip(after)
+            .map(|(before, after)| {
+                (
+                    before + fraction * (after - before),
+                    (after - before) / span,
+                )
+            })
+            .unzip()
+    }
+}
+
+/// A move through several poses along a blended path.
+#[derive(Clone, Debug, PartialEq)]
+struct PathMove {
+    path: BlendedPath,
+    /// Profile of the distance along the path.
+    profile: TrapezoidalProfile,
+    /// Indices of the first and the last poses of the path.
+    first: usize,
+    last: usize,
+}
+
+/// The playback of a sequence of poses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playback {
+    targets: Vec<Vec<f32>>,
+    /// Index of the pose being reached.
+    segment: usize,
+    /// Moves of the joints to the pose, planned when the segment starts.
+    profiles: Vec<TrapezoidalProfile>,
+    /// Time since the segment (or the path) started, in seconds.
+    elapsed: f32,
+    /// Tolerance of the corner blends, or 0 to stop at every pose.
+    tolerance: f32,
+    path: Option<PathMove>,
+    contour_error: Option<f32>,
+}
+
+impl Playback {
+    /// Plays the poses, given as the positions of the joints.
+    pub fn new(targets: Vec<Vec<f32>>) -> Self {
+        Self {
+            targets,
+            segment: 0,
+            profiles: Vec::new(),
+            elapsed: 0.0,
+            tolerance: 0.0,
+            path: None,
+            contour_error: None,
+        }
+    }
+
+    /// Blends the corners at the poses within `tolerance` of them, instead of stopping there.
+    pub fn with_blend(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    /// Index of the pose being reached.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.segment >= self.targets.len()
+    }
+
+    /// Whether the pose of the segment has been reached and is being held.
+    pub fn is_dwelling(&self) -> bool {
+        match &self.path {
+            Some(path) => self.elapsed >= path.profile.duration(),
+            None => {
+                !self.profiles.is_empty()
+                    && self
+                        .profiles
+                        .iter()
+                        .all(|profile| self.elapsed >= profile.duration())
+            }
+        }
+    }
+
+    /// Contour error at the last update, while following a blended path.
+    pub fn contour_error(&self) -> Option<f32> {
+        self.contour_error
+    }
+
+    /// Velocity setpoints of the joints for the next `dt` seconds, from their `measured`
+    /// positions. Once every pose has been reached and held for `dwell` seconds, the setpoints
+    /// are zero.
+    pub fn update(
+        &mut self,
+        joints: &[TaughtJoint],
+        measured: &[f32],
+        dwell: f32,
+        dt: f32,
+    ) -> Vec<f32> {
+        if self.tolerance > 0.0 {
+            return self.follow_path(joints, measured, dwell, dt);
+        }
+        if self.profiles.is_empty() {
+            self.plan(joints, measured);
+        }
+        let duration = self
+            .profiles
+            .iter()
+            .map(TrapezoidalProfile::duration)
+            .fold(0.0, f32::max);
+        if self.elapsed >= duration + dwell.max(0.0) {
+            self.segment += 1;
+            self.plan(joints, measured);
+        }
+        if self.is_done() {
+            return vec![0.0; joints.len()];
+        }
+        let setpoints = joints
+            .iter()
+            .zip(&self.profiles)
+            .zip(measured)
+            .map(|((joint, profile), measured)| {
+                let (position, velocity) = profile.sample(self.elapsed);
+                velocity + joint.gain * (position - measured)
+            })
+            .collect();
+        self.elapsed += dt;
+        setpoints
+    }
+
+    /// Plans the moves from the `measured` positions to the pose of the current segment.
+    fn plan(&mut self, joints: &[TaughtJoint], measured: &[f32]) {
+        self.elapsed = 0.0;
+        self.profiles = match self.targets.get(self.segment) {
+            Some(target) => joints
+                .iter()
+                .zip(measured)
+                .zip(target)
+                .map(|((joint, &start), &end)| {
+                    TrapezoidalProfile::new(start, end, joint.max_velocity, joint.max_acceleration)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Setpoints following the blended path through the poses.
+    fn follow_path(
+        &mut self,
+        joints: &[TaughtJoint],
+        measured: &[f32],
+        dwell: f32,
+        dt: f32,
+    ) -> Vec<f32> {
+        if self.path.is_none() {
+            self.plan_path(joints, measured);
+        }
+        if let Some(path) = &self.path {
+            if self.elapsed >= path.profile.duration() + dwell.max(0.0) {
+                self.segment = path.last + 1;
+                self.plan_path(joints, measured);
+            }
+        }
+        let Some(path) = &self.path else {
+            self.contour_error = None;
+            return vec![0.0; joints.len()];
+        };
+        let (distance, speed) = path.profile.sample(self.elapsed);
+        let (position, tangent) = path.path.sample(distance);
+        let passed = path
+            .path
+            .vertices()
+            .iter()
+            .filter(|&&vertex| vertex < distance)
+            .count();
+        self.segment = (path.first + passed).min(path.last);
+        self.contour_error = Some(path.path.distance(measured));
+        let setpoints = joints
+            .iter()
+            .enumerate()
+            .map(|(i, joint)| tangent[i] * speed + joint.gain * (position[i] - measured[i]))
+            .collect();
+        self.elapsed += dt;
+        setpoints
+    }
+
+    /// Plans the path from the `measured` positions through the poses from the current
+    /// segment, up to the first corner too sharp to blend.
+    fn plan_path(&mut self, joints: &[TaughtJoint], measured: &[f32]) {
+        self.elapsed = 0.0;
+        self.path = None;
+        let Some(target) = self.targets.get(self.segment) else {
+            return;
+        };
+        let mut points = vec![measured.to_vec(), target.clone()];
+        let mut last = self.segment;
+        while let Some(next) = self.targets.get(last + 1) {
+            let corner = &points[points.len() - 2..];
+            if !BlendedPath::is_blendable(&corner[0], &corner[1], next) {
+                break;
+            }
+            points.push(next.clone());
+            last += 1;
+        }
+        let path = BlendedPath::new(&points, self.tolerance);
+        // Every joint moves at most as fast as the path, and the blends bound the centripetal
+        // acceleration.
+        let acceleration = joints
+            .iter()
+            .map(|joint| joint.max_acceleration)
+            .fold(f32::INFINITY, f32::min);
+        let mut velocity = joints
+            .iter()
+            .map(|joint| joint.max_velocity)
+            .fold(f32::INFINITY, f32::min);
+        if let Some(radius) = path.min_radius() {
+            velocity = velocity.min((acceleration * radius).sqrt());
+        }
+        self.path = Some(PathMove {
+            profile: TrapezoidalProfile::new(0.0, path.length(), velocity, acceleration),
+            path,
+            first: self.segment,
+            last,
+        });
+    }
+}
+
+/// The replay of a recorded trajectory: the joints move to its start as to a pose, then track it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    trajectory: Trajectory,
+    /// Move to the start of the trajectory.
+    approach: Playback,
+    /// Time since the tracking started, in seconds.
+    elapsed: f32,
+    tracking_error: Option<f32>,
+}
+
+impl Replay {
+    pub fn new(trajectory: Trajectory) -> Self {
+        let start = trajectory.positions.first().cloned();
+        Self {
+            trajectory,
+            approach: Playback::new(start.into_iter().collect()),
+            elapsed: 0.0,
+            tracking_error: None,
+        }
+    }
+
+    /// Whether the start has been reached and the trajectory is being tracked.
+    pub fn is_tracking(&self) -> bool {
+        self.approach.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.approach.is_done() && self.elapsed >= self.trajectory.duration()
+    }
+
+    /// Distance from the measured positions to the trajectory at the last update, while
+    /// tracking it.
+    pub fn tracking_error(&self) -> Option<f32> {
+        self.tracking_error
+    }
+
+    /// Velocity setpoints of the joints for the next `dt` seconds, from their `measured`
+    /// positions. Once the trajectory has been tracked to its end, the setpoints are zero.
+    pub fn update(&mut self, joints: &[TaughtJoint], measured: &[f32], dt: f32) -> Vec<f32> {
+        if !self.approach.is_done() {
+            return self.approach.update(joints, measured, 0.0, dt);
+        }
+        if self.is_done() {
+            return vec![0.0; joints.len()];
+        }
+        let (positions, velocities) = self.trajectory.sample(self.elapsed);
+        self.tracking_error = Some(
+            positions
+                .iter()
+                .zip(measured)
+                .map(|(position, measured)| (position - measured).powi(2))
+                .sum::<f32>()
+                .sqrt(),
+        );
+        let setpoints = joints
+            .iter()
+            .enumerate()
+            .map(|(i, joint)| velocities[i] + joint.gain * (positions[i] - measured[i]))
+            .collect();
+        self.elapsed += dt;
+        setpoints
+    }
+}
+
+/// The sequence being played, or the trajectory being recorded or replayed, if any.
+#[derive(Default, Resource)]
+pub struct TeachPlayback {
+    pub playback: Option<Playback>,
+    pub replay: Option<Replay>,
+    /// The trajectory being led through by hand.
+    pub recording: Option<Trajectory>,
+    /// The last trajectory recorded.
+    pub recorded: Trajectory,
+    /// Whether the setpoints were last written by the playback, and must be stopped.
+    driving: bool,
+    /// Whether the joints were released for the recording, and must be engaged again.
+    releasing: bool,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<TeachSettings>("teach", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Measured positions of the joints.
+fn measure(joints: &[TaughtJoint], telemetry: Option<&Telemetry>) -> Result<Vec<f32>> {
+    joints
+        .iter()
+        .map(|joint| {
+            telemetry
+                .and_then(|telemetry| telemetry.latest(&joint.position))
+                .ok_or_else(|| Error::Config {
+                    name: "teach".to_string(),
+                    message: format!("no telemetry measures `{}`", joint.position),
+                })
+        })
+        .collect()
+}
+
+/// Writes the velocity setpoints of the joints while a sequence plays, after each step of the
+/// simulation.
+fn play(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<TeachSettings>>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+    mut teach: ResMut<TeachPlayback>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    let teach = &mut *teach;
+    if teach.playback.is_none() && teach.replay.is_none() {
+        if teach.driving {
+            for joint in &settings.joints {
+                setpoints.set(&joint.setpoint, 0.0);
+            }
+            teach.driving = false;
+        }
+        return;
+    }
+    // The next step is assumed as long as the last one.
+    let dt = clock.delta_secs();
+    if dt == 0.0 {
+        // Paused, or no step this frame: hold the setpoints until the next one.
+        return;
+    }
+    let measured = match measure(&settings.joints, telemetry.as_deref()) {
+        Ok(measured) => measured,
+        Err(error) => {
+            commands.send_event(ErrorEvent::from(error));
+            teach.playback = None;
+            teach.replay = None;
+            return;
+        }
+    };
+    let (velocities, error) = match (&mut teach.playback, &mut teach.replay) {
+        (Some(playback), _) => (
+            playback.update(&settings.joints, &measured, settings.dwell, dt),
+            playback
+                .contour_error()
+                .map(|error| (TEACH_CONTOUR_ERROR, error)),
+        ),
+        (None, Some(replay)) => (
+            replay.update(&settings.joints, &measured, dt),
+            replay
+                .tracking_error()
+                .map(|error| (TEACH_TRACKING_ERROR, error)),
+        ),
+        (None, None) => return,
+    };
+    for (joint, velocity) in settings.joints.iter().zip(velocities) {
+        setpoints.set(&joint.setpoint, velocity);
+    }
+    if let (Some((channel, error)), Some(telemetry)) = (error, telemetry.as_mut()) {
+        telemetry.record(channel, clock.elapsed_secs(), error);
+    }
+    teach.driving = true;
+    if teach.playback.as_ref().is_some_and(Playback::is_done) {
+        teach.playback = None;
+    }
+    if teach.replay.as_ref().is_some_and(Replay::is_done) {
+        teach.replay = None;
+    }
+}
+
+/// Records the positions of the joints while they're led through by hand, after each step of
+/// the simulation. The joints are released while recording, and engaged again after.
+fn record(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<TeachSettings>>,
+    telemetry: Option<Res<Telemetry>>,
+    mut teach: ResMut<TeachPlayback>,
+    mut setpoints: ResMut<Setpoints>,
+) {
+    let teach = &mut *teach;
+    let released = teach.recording.is_some();
+    if released != teach.releasing {
+        for joint in &settings.joints {
+            if let Some(release) = &joint.release {
+                setpoints.set(release, if released { 1.0 } else { 0.0 });
+            }
+            setpoints.set(&joint.setpoint, 0.0);
+        }
+        teach.releasing = released;
+    }
+    let Some(recording) = &mut teach.recording else {
+        return;
+    };
+    if clock.delta_secs() == 0.0 {
+        return;
+    }
+    match measure(&settings.joints, telemetry.as_deref()) {
+        Ok(positions) => recording.push(clock.elapsed_secs(), positions),
+        Err(error) => {
+            commands.send_event(ErrorEvent::from(error));
+            teach.recording = None;
+        }
+    }
+}
+
+/// Panel to teach poses and play them back.
+fn teach_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<TeachSettings>>,
+    mut teach: ResMut<TeachPlayback>,
+    telemetry: Option<Res<Telemetry>>,
+    theme: Option<Res<Persistent<Theme>>>,
+    mut name: Local<String>,
+) {
+    let mut edited = settings.get().clone();
+    let theme = theme.map(|theme| theme.get().clone()).unwrap_or_default();
+
+    egui::Window::new("Teach")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Pose");
+                ui.text_edit_singleline(&mut *name);
+                if ui.button("Teach pose").clicked() {
+                    match measure(&edited.joints, telemetry.as_deref()) {
+                        Ok(positions) => {
+                            if name.trim().is_empty() {
+                                *name = format!("P{}", edited.poses.len() + 1);
+                            }
+                            let pose = Pose {
+                                name: name.trim().to_string(),
+                                positions,
+                            };
+                            match edited.poses.iter_mut().find(|p| p.name == pose.name) {
+                                Some(taught) => *taught = pose,
+                                None => edited.poses.push(pose),
+                            }
+                            name.clear();
+                        }
+                        Err(error) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.label("Poses");
+            let mut removed = None;
+            for (index, pose) in edited.poses.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let positions: Vec<_> =
+                        pose.positions.iter().map(|p| format!("{p:.3}")).collect();
+                    ui.label(format!("{}: {}", pose.name, positions.join(", ")));
+                    if ui.button("Add to sequence").clicked() {
+                        edited.sequence.push(pose.name.clone());
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                let pose = edited.poses.remove(index);
+                edited.sequence.retain(|name| *name != pose.name);
+            }
+
+            ui.separator();
+            ui.label("Sequence");
+            let mut removed = None;
+            for (index, name) in edited.sequence.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}. {name}", index + 1));
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.sequence.remove(index);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Clear").clicked() {
+                    edited.sequence.clear();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut edited.dwell)
+                        .range(0.0..=60.0)
+                        .speed(0.1)
+                        .prefix("Dwell: ")
+                        .suffix(" s"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.blend)
+                        .range(0.0..=10.0)
+                        .speed(0.01)
+                        .prefix("Blend: "),
+                )
+                .on_hover_text("Largest distance from a pose to the path, 0 to stop on it");
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Play").clicked() {
+                    match edited.sequence_poses() {
+                        Ok(poses) => {
+                            let targets = poses.iter().map(|pose| pose.positions.clone());
+                            teach.replay = None;
+                            teach.playback =
+                                Some(Playback::new(targets.collect()).with_blend(edited.blend));
+                        }
+                        Err(error) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+                if ui.button("Stop").clicked() {
+                    teach.playback = None;
+                    teach.replay = None;
+                }
+            });
+            match (&teach.playback, &teach.replay) {
+                (Some(playback), _) => ui.label(format!(
+                    "Moving to {} ({}/{})",
+                    edited
+                        .sequence
+                        .get(playback.segment())
+                        .map_or("?", String::as_str),
+                    playback.segment() + 1,
+                    edited.sequence.len()
+                )),
+                (None, Some(replay)) if replay.is_tracking() => ui.label("Replaying the recording"),
+                (None, Some(_)) => ui.label("Moving to the start of the recording"),
+                (None, None) => ui.label("Stopped"),
+            };
+            if edited.blend > 0.0 {
+                if let Some(telemetry) = &telemetry {
+                    ui.label("Contour error");
+                    plot_error(ui, telemetry, TEACH_CONTOUR_ERROR, &theme);
+                }
+            }
+
+            ui.separator();
+            ui.label("Lead-through");
+            ui.horizontal(|ui| {
+                if teach.recording.is_some() {
+                    if ui.button("Stop recording").clicked() {
+                        teach.recorded = teach.recording.take().unwrap_or_default();
+                    }
+                } else if ui
+                    .button("Record")
+                    .on_hover_text("Releases the joints, to drag them through the motion")
+                    .clicked()
+                {
+                    teach.playback = None;
+                    teach.replay = None;
+                    teach.recording = Some(Trajectory::default());
+                }
+                if ui
+                    .add_enabled(teach.recording.is_none(), egui::Button::new("Replay"))
+                    .clicked()
+                {
+                    match edited.check_recording(&teach.recorded) {
+                        Ok(()) => {
+                            let trajectory = teach.recorded.smoothed(edited.smoothing);
+                            teach.playback = None;
+                            teach.replay = Some(Replay::new(trajectory));
+                        }
+                        Err(error) => {
+                            commands.send_event(ErrorEvent::from(error));
+                        }
+                    }
+                }
+                ui.add(
+                    egui::DragValue::new(&mut edited.smoothing)
+                        .range(0.0..=5.0)
+                        .speed(0.01)
+                        .prefix("Smoothing: ")
+                        .suffix(" s"),
+                )
+                .on_hover_text("Window of the moving average smoothing the recording");
+            });
+            match &teach.recording {
+                Some(recording) => ui.label(format!("Recording ({} samples)", recording.len())),
+                None if teach.recorded.is_empty() => ui.label("Nothing recorded"),
+                None => ui.label(format!("Recorded {:.1} s", teach.recorded.duration())),
+            };
+            if !teach.recorded.is_empty() {
+                if let Some(telemetry) = &telemetry {
+                    ui.label("Tracking error");
+                    plot_error(ui, telemetry, TEACH_TRACKING_ERROR, &theme);
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("teach", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("teach", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Plots the last errors recorded in `channel`, to verify the tolerance of the blended paths
+/// or the tracking of the recordings.
+fn plot_error(ui: &mut egui::Ui, telemetry: &Telemetry, channel: &str, theme: &Theme) {
+    let samples = telemetry
+        .channels
+        .get(channel)
+        .map_or(&[][..], Vec::as_slice);
+    let samples = &samples[samples.len().saturating_sub(PLOTTED_STEPS)..];
+    let high = samples
+        .iter()
+        .map(|[_, error]| *error)
+        .fold(0.0f32, f32::max)
+        .max(f32::EPSILON);
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(300.0, 100.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let color = to_egui(theme.series(0));
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(k, [_, error])| {
+            egui::pos2(
+                rect.left() + rect.width() * k as f32 / PLOTTED_STEPS as f32,
+                rect.bottom() - rect.height() * error / high,
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+    painter.text(
+        rect.left_top() + egui::vec2(4.0, 4.0),
+        egui::Align2::LEFT_TOP,
+        format!("max {high:.4}"),
+        egui::FontId::proportional(12.0),
+        color,
+    );
+}
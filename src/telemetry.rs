@@ -0,0 +1,111 @@
+//! Telemetry collected during a run.
+//!
+//! Subsystems record samples of named channels (e.g. `pendulum/angle`) into the [`Telemetry`]
+//! resource. Samples are only appended, so consumers (autosave, plots, exporters) can track how
+//! far they have read each channel.
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Angle of the pendulum, in rad.
+pub const PENDULUM_ANGLE: &str = "pendulum/angle";
+/// Torque applied by the motor, in N·m.
+pub const MOTOR_TORQUE: &str = "motor/torque";
+/// Angle of the motor, in rad, counted over several turns.
+pub const MOTOR_ANGLE: &str = "motor/angle";
+
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Telemetry>();
+    }
+}
+
+/// A sample of a channel: time in seconds and value.
+pub type Sample = [f32; 2];
+
+/// Reduces `samples` by `factor` while keeping their envelope: each run of `factor` samples is
+/// replaced by its smallest and its largest samples, in time order, so peaks survive and the
+/// decimated signal still plots like the original. A `factor` of 2 or less keeps every sample.
+pub fn decimate(samples: &[Sample], factor: usize) -> Vec<Sample> {
+    if factor <= 2 {
+        return samples.to_vec();
+    }
+    let mut decimated = Vec::with_capacity(2 * samples.len().div_ceil(factor));
+    for bucket in samples.chunks(factor) {
+        let by_value = |a: &&Sample, b: &&Sample| a[1].total_cmp(&b[1]);
+        let (Some(low), Some(high)) = (
+            bucket.iter().min_by(by_value),
+            bucket.iter().max_by(by_value),
+        ) else {
+            continue;
+        };
+        let (first, second) = if low[0] <= high[0] {
+            (low, high)
+        } else {
+            (high, low)
+        };
+        decimated.push(*first);
+        if second != first {
+            decimated.push(*second);
+        }
+    }
+    decimated
+}
+
+/// The samples of every channel recorded so far, by channel name.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+pub struct Telemetry {
+    pub channels: BTreeMap<String, Vec<Sample>>,
+}
+
+impl Telemetry {
+    /// Appends a sample to a channel, creating it if needed.
+    pub fn record(&mut self, channel: &str, time: f32, value: f32) {
+        match self.channels.get_mut(channel) {
+            Some(samples) => samples.push([time, value]),
+            None => {
+                self.channels
+                    .insert(channel.to_string(), vec![[time, value]]);
+            }
+        }
+    }
+
+    /// Value of the last sample of a channel.
+    pub fn latest(&self, channel: &str) -> Option<f32> {
+        self.channels.get(channel)?.last().map(|[_, value]| *value)
+    }
+
+    /// Number of samples of every channel.
+    pub fn len(&self) -> usize {
+        self.channels.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the samples of another recording.
+    pub fn extend(&mut self, other: Telemetry) {
+        for (channel, samples) in other.channels {
+            self.channels.entry(channel).or_default().extend(samples);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.channels.clear();
+    }
+
+    /// The samples as CSV, one row per sample with its channel, time and value.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("channel,time,value\n");
+        for (channel, samples) in &self.channels {
+            for [time, value] in samples {
+                csv.push_str(&format!("{channel},{time},{value}\n"));
+            }
+        }
+        csv
+    }
+}
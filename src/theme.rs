@@ -0,0 +1,265 @@
+//! This module provides the theme: the colors of the plots, the gizmos and the heatmaps, picked
+//! from a palette selected in the theme panel or in `theme.json`. Besides the default one, the
+//! palettes are safe for the common color vision deficiencies.
+//!
+//! Everything drawing a color takes it from the [`Theme`] resource, so switching the palette
+//! applies everywhere at once.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::{prelude::DebugRenderStyle, render::DebugRenderContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::LogLevel,
+};
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                apply_to_debug_render.run_if(resource_exists::<Persistent<Theme>>),
+                theme_panel
+                    .run_if(resource_exists::<Persistent<Theme>>)
+                    .run_if(has_ui),
+            ),
+        );
+    }
+}
+
+/// A set of colors.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    #[default]
+    Default,
+    /// The palette of Okabe and Ito, with the viridis color map.
+    OkabeIto,
+    /// The bright palette of Paul Tol, with the cividis color map.
+    Tol,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Default, Palette::OkabeIto, Palette::Tol];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "Default",
+            Palette::OkabeIto => "Okabe-Ito (colorblind-safe)",
+            Palette::Tol => "Tol (colorblind-safe)",
+        }
+    }
+
+    /// Colors telling the series of a plot apart, as `0xRRGGBB`.
+    fn series(self) -> &'static [u32] {
+        match self {
+            Palette::Default => &[
+                0x1F77B4, 0xFF7F0E, 0x2CA02C, 0xD62728, 0x9467BD, 0x8C564B, 0xE377C2, 0x7F7F7F,
+            ],
+            Palette::OkabeIto => &[
+                0xE69F00, 0x56B4E9, 0x009E73, 0xF0E442, 0x0072B2, 0xD55E00, 0xCC79A7, 0x999999,
+            ],
+            Palette::Tol => &[
+                0x4477AA, 0xEE6677, 0x228833, 0xCCBB44, 0x66CCEE, 0xAA3377, 0xBBBBBB,
+            ],
+        }
+    }
+
+    /// Colors of a heatmap from its lowest to its highest value, evenly spaced.
+    fn color_map(self) -> &'static [u32] {
+        match self {
+            // Diverging blue to red.
+            Palette::Default => &[0x3B4CC0, 0x8DB0FE, 0xDDDDDD, 0xF49A7B, 0xB40426],
+            // Viridis.
+            Palette::OkabeIto => &[0x440154, 0x3B528B, 0x21918C, 0x5EC962, 0xFDE725],
+            // Cividis.
+            Palette::Tol => &[0x00204D, 0x414D6B, 0x7C7B78, 0xBCAF6F, 0xFFEA46],
+        }
+    }
+
+    /// Colors of the error, warning, info, debug and trace levels.
+    fn levels(self) -> [u32; 5] {
+        match self {
+            Palette::Default => [0xFF8080, 0xFFD700, 0x90EE90, 0xADD8E6, 0xA0A0A0],
+            Palette::OkabeIto => [0xD55E00, 0xF0E442, 0x56B4E9, 0x009E73, 0x999999],
+            Palette::Tol => [0xEE6677, 0xCCBB44, 0x66CCEE, 0x228833, 0xBBBBBB],
+        }
+    }
+
+    /// Colors of the fixed, dynamic and kinematic colliders, and of the joints; the colors of
+    /// rapier without one.
+    fn bodies(self) -> Option<[u32; 4]> {
+        match self {
+            Palette::Default => None,
+            Palette::OkabeIto => Some([0x0072B2, 0xE69F00, 0x56B4E9, 0xCC79A7]),
+            Palette::Tol => Some([0x4477AA, 0xEE6677, 0x66CCEE, 0xCCBB44]),
+        }
+    }
+
+    fn grid(self) -> u32 {
+        match self {
+            Palette::Default | Palette::OkabeIto => 0x808080,
+            Palette::Tol => 0xBBBBBB,
+        }
+    }
+}
+
+/// The theme, from the `theme.json` configuration file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    pub palette: Palette,
+}
+
+impl Theme {
+    /// Color of the series at `index` of a plot, cycling through the palette.
+    pub fn series(&self, index: usize) -> Srgba {
+        let series = self.palette.series();
+        rgb(series[index % series.len()])
+    }
+
+    /// Color of a heatmap for a value normalized to `[0, 1]`.
+    pub fn heatmap(&self, value: f32) -> Srgba {
+        let stops = self.palette.color_map();
+        let position = value.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        if !position.is_finite() {
+            return rgb(stops[0]);
+        }
+        let index = (position as usize).min(stops.len() - 2);
+        rgb(stops[index]).mix(&rgb(stops[index + 1]), position - index as f32)
+    }
+
+    pub fn log_level(&self, level: LogLevel) -> Srgba {
+        let [error, warn, info, debug, trace] = self.palette.levels();
+        rgb(match level {
+            LogLevel::Error => error,
+            LogLevel::Warn => warn,
+            LogLevel::Info => info,
+            LogLevel::Debug => debug,
+            LogLevel::Trace => trace,
+        })
+    }
+
+    /// Color of the reference grid.
+    pub fn grid(&self) -> Srgba {
+        rgb(self.palette.grid())
+    }
+}
+
+/// Converts a color to its egui counterpart.
+pub fn to_egui(color: Srgba) -> egui::Color32 {
+    let [red, green, blue, alpha] = color.to_u8_array();
+    egui::Color32::from_rgba_unmultiplied(red, green, blue, alpha)
+}
+
+fn rgb(hex: u32) -> Srgba {
+    Srgba::rgb_u8((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+/// The hue, saturation, lightness and alpha of a color, as used by the debug render of rapier.
+fn hsla(hex: u32) -> [f32; 4] {
+    let color = Hsla::from(rgb(hex));
+    [color.hue, color.saturation, color.lightness, color.alpha]
+}
+
+fn setup(mut commands: Commands) {
+    let (theme, error) = config_plugin::load_config::<Theme>("theme", true);
+    commands.insert_resource(theme);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Colors the colliders and the joints drawn by the debug render of rapier.
+fn apply_to_debug_render(
+    theme: Res<Persistent<Theme>>,
+    debug_render: Option<ResMut<DebugRenderContext>>,
+) {
+    let Some(mut debug_render) = debug_render else {
+        return;
+    };
+    if !theme.is_changed() && !debug_render.is_added() {
+        return;
+    }
+    let style = &mut debug_render.pipeline.style;
+    let default = DebugRenderStyle::default();
+    let [fixed, dynamic, kinematic, joint] = match theme.palette.bodies() {
+        Some(colors) => colors.map(hsla),
+        None => [
+            default.collider_fixed_color,
+            default.collider_dynamic_color,
+            default.collider_kinematic_color,
+            default.impulse_joint_anchor_color,
+        ],
+    };
+    style.collider_fixed_color = fixed;
+    style.collider_dynamic_color = dynamic;
+    style.collider_kinematic_color = kinematic;
+    style.impulse_joint_anchor_color = joint;
+    style.multibody_joint_anchor_color = joint;
+}
+
+/// Panel to pick the palette at runtime.
+fn theme_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut theme: ResMut<Persistent<Theme>>,
+) {
+    let mut edited = theme.get().clone();
+
+    egui::Window::new("Theme")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Palette")
+                .selected_text(edited.palette.name())
+                .show_ui(ui, |ui| {
+                    for palette in Palette::ALL {
+                        ui.selectable_value(&mut edited.palette, palette, palette.name());
+                    }
+                });
+            // Preview of the series and of the color map.
+            ui.horizontal(|ui| {
+                for index in 0..edited.palette.series().len() {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+                    ui.painter()
+                        .rect_filled(rect, 2.0, to_egui(edited.series(index)));
+                }
+            });
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(128.0, 12.0), egui::Sense::hover());
+            for step in 0..32 {
+                let left = rect.left() + rect.width() * step as f32 / 32.0;
+                let cell = egui::Rect::from_min_size(
+                    egui::pos2(left, rect.top()),
+                    egui::vec2(rect.width() / 32.0, rect.height()),
+                );
+                let color = edited.heatmap(step as f32 / 31.0);
+                ui.painter().rect_filled(cell, 0.0, to_egui(color));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = theme.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("theme", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = theme.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("theme", error)));
+                    }
+                    edited = theme.get().clone();
+                }
+            });
+        });
+
+    if edited != *theme.get() {
+        *theme.get_mut() = edited;
+    }
+}
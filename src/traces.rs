@@ -0,0 +1,411 @@
+//! This module traces the recent paths of points of the plants through the scene: the tip of the
+//! pendulum, the end effector of the arm, the centers of mass of links or of whole plants. Each
+//! path is drawn as a polyline fading out with the age of its samples, which shows limit cycles
+//! and swing-ups at a glance.
+//!
+//! The points are listed in the *Traces* panel, each on a link by name, in every plant that has
+//! it. The panel sets how long the paths are kept, and exports them to
+//! `<data dir>/traces` as CSV.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin::{self, data_dir},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    plants::Link,
+    recording::{self, RecordingFormat},
+    theme::Theme,
+};
+
+pub struct TracesPlugin;
+
+impl Plugin for TracesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Traces>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (record_traces, draw_traces, traces_panel.run_if(has_ui))
+                    .chain()
+                    .run_if(resource_exists::<Persistent<TraceSettings>>),
+            );
+    }
+}
+
+/// Where a traced point is on its link.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceAnchor {
+    /// At an offset from the origin of the link.
+    #[default]
+    Point,
+    /// At the center of mass of the link.
+    CenterOfMass,
+    /// At the center of mass of all the bodies of the plant, whatever the link.
+    PlantCenterOfMass,
+}
+
+impl TraceAnchor {
+    pub const ALL: [Self; 3] = [Self::Point, Self::CenterOfMass, Self::PlantCenterOfMass];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Point => "Point on the link",
+            Self::CenterOfMass => "Center of mass of the link",
+            Self::PlantCenterOfMass => "Center of mass of the plant",
+        }
+    }
+}
+
+/// A point whose path is traced.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct TracedPoint {
+    pub name: String,
+    pub link: String,
+    pub anchor: TraceAnchor,
+    /// Offset of a [`TraceAnchor::Point`] in the frame of the link, in m.
+    pub offset: Vec3,
+}
+
+/// Represents the configuration of the traces.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TraceSettings {
+    pub enabled: bool,
+    pub points: Vec<TracedPoint>,
+    /// Age of the oldest sample kept, in s.
+    pub history: f32,
+    /// Distance a point moves before it's sampled again, in m.
+    pub spacing: f32,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            points: vec![
+                TracedPoint {
+                    name: "pendulum tip".to_string(),
+                    link: "pendulum".to_string(),
+                    anchor: TraceAnchor::Point,
+                    offset: Vec3::Y * 1.5,
+                },
+                TracedPoint {
+                    name: "end effector".to_string(),
+                    link: "forearm".to_string(),
+                    anchor: TraceAnchor::Point,
+                    offset: Vec3::Y * 0.4,
+                },
+                TracedPoint {
+                    name: "center of mass".to_string(),
+                    link: String::new(),
+                    anchor: TraceAnchor::PlantCenterOfMass,
+                    offset: Vec3::ZERO,
+                },
+            ],
+            history: 5.0,
+            spacing: 0.005,
+        }
+    }
+}
+
+/// Recent path of a point: its positions, in m, and the times they were sampled at, in s.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub samples: VecDeque<(f32, Vec3)>,
+}
+
+impl Trace {
+    /// Samples the point at `time` unless it moved less than `spacing` since the last sample,
+    /// then drops the samples older than `history`. Time going back, as on a reset, restarts
+    /// the path.
+    pub fn record(&mut self, time: f32, position: Vec3, history: f32, spacing: f32) {
+        match self.samples.back() {
+            Some(&(last, _)) if time < last => self.samples.clear(),
+            Some(&(_, last)) if last.distance(position) < spacing => {}
+            _ => self.samples.push_back((time, position)),
+        }
+        self.trim(time, history);
+    }
+
+    /// Drops the samples older than `history` at `time`.
+    pub fn trim(&mut self, time: f32, history: f32) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(sampled, _)| time - sampled > history)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The samples with their opacity at `time`, from 1 for a new sample to 0 for one as old as
+    /// `history`.
+    pub fn faded(&self, time: f32, history: f32) -> impl Iterator<Item = (Vec3, f32)> + '_ {
+        self.samples.iter().map(move |&(sampled, position)| {
+            let alpha = if history > 0.0 {
+                1.0 - (time - sampled) / history
+            } else {
+                1.0
+            };
+            (position, alpha.clamp(0.0, 1.0))
+        })
+    }
+}
+
+/// The paths traced, by plant and name of the point.
+#[derive(Default, Resource)]
+pub struct Traces {
+    pub paths: BTreeMap<(String, String), Trace>,
+}
+
+/// Writes the paths into `directory`, one row per sample, returning the path written.
+pub fn export(traces: &Traces, directory: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(directory).map_err(|error| Error::io(directory, error))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = directory.join(recording::file_name(now, RecordingFormat::Csv).replacen(
+        "recording",
+        "traces",
+        1,
+    ));
+    let mut csv = "plant,point,time,x,y,z\n".to_string();
+    for ((plant, point), trace) in &traces.paths {
+        for (time, position) in &trace.samples {
+            csv.push_str(&format!(
+                "{plant},{point},{time},{},{},{}\n",
+                position.x, position.y, position.z
+            ));
+        }
+    }
+    fs::write(&path, csv).map_err(|error| Error::io(&path, error))?;
+    Ok(path)
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<TraceSettings>("traces", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Samples the traced points of every plant.
+fn record_traces(
+    settings: Res<Persistent<TraceSettings>>,
+    time: Res<Time>,
+    links: Query<(&Link, &GlobalTransform, Option<&ReadMassProperties>)>,
+    mut traces: ResMut<Traces>,
+) {
+    if !settings.enabled {
+        traces.paths.clear();
+        return;
+    }
+    let now = time.elapsed_secs();
+    // The centers of mass of the plants, weighted by the masses of their bodies.
+    let mut plants: BTreeMap<&str, (Vec3, f32)> = BTreeMap::new();
+    for (link, transform, properties) in &links {
+        if let Some(properties) = properties {
+            let properties = properties.get();
+            let center = transform.transform_point(properties.local_center_of_mass);
+            let (sum, mass) = plants.entry(link.plant.as_str()).or_default();
+            *sum += center * properties.mass;
+            *mass += properties.mass;
+        }
+    }
+    let mut sampled = Vec::new();
+    for point in &settings.points {
+        match point.anchor {
+            TraceAnchor::PlantCenterOfMass => {
+                for (plant, (sum, mass)) in &plants {
+                    if *mass > 0.0 {
+                        sampled.push((plant.to_string(), &point.name, *sum / *mass));
+                    }
+                }
+            }
+            TraceAnchor::Point | TraceAnchor::CenterOfMass => {
+                for (link, transform, properties) in &links {
+                    if link.name != point.link {
+                        continue;
+                    }
+                    let local = match (point.anchor, properties) {
+                        (TraceAnchor::CenterOfMass, Some(properties)) => {
+                            properties.get().local_center_of_mass
+                        }
+                        (TraceAnchor::CenterOfMass, None) => Vec3::ZERO,
+                        _ => point.offset,
+                    };
+                    sampled.push((
+                        link.plant.clone(),
+                        &point.name,
+                        transform.transform_point(local),
+                    ));
+                }
+            }
+        }
+    }
+    for (plant, name, position) in sampled {
+        traces
+            .paths
+            .entry((plant, name.clone()))
+            .or_default()
+            .record(now, position, settings.history, settings.spacing);
+    }
+    // The paths of the points no longer traced, or whose link is gone, fade out too.
+    for trace in traces.paths.values_mut() {
+        trace.trim(now, settings.history);
+    }
+    traces.paths.retain(|_, trace| !trace.samples.is_empty());
+}
+
+/// Draws each path in the color of its point, fading out with the age of its samples.
+fn draw_traces(
+    mut gizmos: Gizmos,
+    settings: Res<Persistent<TraceSettings>>,
+    time: Res<Time>,
+    traces: Res<Traces>,
+    theme: Option<Res<Persistent<Theme>>>,
+) {
+    let now = time.elapsed_secs();
+    for ((_, name), trace) in &traces.paths {
+        let index = settings
+            .points
+            .iter()
+            .position(|point| &point.name == name)
+            .unwrap_or_default();
+        let color = theme.as_ref().map_or_else(
+            || Theme::default().series(index),
+            |theme| theme.series(index),
+        );
+        let samples: Vec<_> = trace.faded(now, settings.history).collect();
+        for pair in samples.windows(2) {
+            let [(start, start_alpha), (end, end_alpha)] = [pair[0], pair[1]];
+            gizmos.line_gradient(
+                start,
+                end,
+                color.with_alpha(start_alpha),
+                color.with_alpha(end_alpha),
+            );
+        }
+    }
+}
+
+/// Panel to pick the traced points and export their paths.
+fn traces_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<TraceSettings>>,
+    mut traces: ResMut<Traces>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Traces")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Trace the paths");
+            ui.add(
+                egui::Slider::new(&mut edited.history, 0.1..=60.0)
+                    .logarithmic(true)
+                    .text("History (s)"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.spacing, 0.0..=0.1)
+                    .text("Spacing of the samples (m)"),
+            );
+
+            ui.separator();
+            let mut removed = None;
+            for (index, point) in edited.points.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut point.name);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                egui::ComboBox::from_id_salt(("trace_anchor", index))
+                    .selected_text(point.anchor.name())
+                    .show_ui(ui, |ui| {
+                        for anchor in TraceAnchor::ALL {
+                            ui.selectable_value(&mut point.anchor, anchor, anchor.name());
+                        }
+                    });
+                if point.anchor != TraceAnchor::PlantCenterOfMass {
+                    ui.horizontal(|ui| {
+                        ui.label("Link");
+                        ui.text_edit_singleline(&mut point.link);
+                    });
+                }
+                if point.anchor == TraceAnchor::Point {
+                    ui.horizontal(|ui| {
+                        ui.label("Offset (m)");
+                        for (axis, value) in
+                            ["x: ", "y: ", "z: "].into_iter().zip(point.offset.as_mut())
+                        {
+                            ui.add(egui::DragValue::new(value).speed(0.01).prefix(axis));
+                        }
+                    });
+                }
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.points.remove(index);
+            }
+            if ui.button("Add a point").clicked() {
+                edited.points.push(TracedPoint::default());
+            }
+
+            ui.separator();
+            let samples: usize = traces.paths.values().map(|trace| trace.samples.len()).sum();
+            ui.label(format!("{} paths, {samples} samples", traces.paths.len()));
+            ui.horizontal(|ui| {
+                if ui.button("Clear").clicked() {
+                    traces.paths.clear();
+                }
+                if ui
+                    .add_enabled(samples > 0, egui::Button::new("Export"))
+                    .clicked()
+                {
+                    let directory = data_dir().join("traces");
+                    match export(&traces, &directory) {
+                        Ok(path) => {
+                            info!(target: subsystem::IO, "Traces exported to {}", path.display())
+                        }
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("traces", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("traces", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,257 @@
+//! This module provides a trajectory generator, feeding a position setpoint from a motion
+//! planned through waypoints rather than stepping it.
+//!
+//! The motion is a [`Trajectory`] from the current value of the setpoint through the configured
+//! waypoints: trapezoidal or jerk-limited (S-curve) moves stopping at each waypoint, or a cubic or
+//! quintic spline through them, all within the velocity and acceleration limits. While it plays,
+//! the setpoint is written after every physics step, for the position loop to follow; the
+//! velocity of the profile can be written to a velocity setpoint too, as a feedforward. The
+//! planned position, velocity and acceleration are recorded as the `trajectory/position`,
+//! `trajectory/velocity` and `trajectory/acceleration` telemetry channels.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin,
+    control::{MotionLimits, Profile, ProfileKind, Trajectory},
+    error::{Error, ErrorEvent},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::Telemetry,
+};
+
+/// Position of the trajectory being played.
+pub const TRAJECTORY_POSITION: &str = "trajectory/position";
+/// Velocity of the trajectory being played.
+pub const TRAJECTORY_VELOCITY: &str = "trajectory/velocity";
+/// Acceleration of the trajectory being played.
+pub const TRAJECTORY_ACCELERATION: &str = "trajectory/acceleration";
+
+pub struct TrajectoryPlugin;
+
+impl Plugin for TrajectoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrajectoryPlayback>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                play_trajectory
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<TrajectorySettings>>),
+            )
+            .add_systems(
+                Update,
+                trajectory_panel
+                    .run_if(resource_exists::<Persistent<TrajectorySettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Represents the configuration of the trajectory generator.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TrajectorySettings {
+    pub profile: ProfileKind,
+    /// Positions to move through, in the unit of the setpoint.
+    pub waypoints: Vec<f32>,
+    pub limits: MotionLimits,
+    /// Position setpoint following the trajectory.
+    pub setpoint: String,
+    /// Velocity setpoint following the velocity of the trajectory, if any.
+    pub feedforward: Option<String>,
+}
+
+impl Default for TrajectorySettings {
+    fn default() -> Self {
+        Self {
+            profile: ProfileKind::Trapezoidal,
+            waypoints: vec![PI / 2.0, -PI / 2.0, 0.0],
+            limits: MotionLimits {
+                max_velocity: PI,
+                max_acceleration: TAU,
+                max_jerk: 10.0 * TAU,
+            },
+            setpoint: MOTOR_POSITION.to_string(),
+            feedforward: None,
+        }
+    }
+}
+
+impl TrajectorySettings {
+    /// The trajectory from `start` through the waypoints.
+    pub fn trajectory(&self, start: f32) -> Trajectory {
+        let waypoints: Vec<f32> = std::iter::once(start)
+            .chain(self.waypoints.iter().copied())
+            .collect();
+        Trajectory::new(self.profile, &waypoints, self.limits)
+    }
+}
+
+/// The trajectory being played, and the time it started at.
+#[derive(Default, Resource)]
+pub struct TrajectoryPlayback {
+    pub playing: Option<(Trajectory, f32)>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<TrajectorySettings>("trajectory", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the setpoints from the trajectory, after each step of the simulation.
+fn play_trajectory(
+    clock: Res<SimClock>,
+    settings: Res<Persistent<TrajectorySettings>>,
+    mut playback: ResMut<TrajectoryPlayback>,
+    mut setpoints: ResMut<Setpoints>,
+    telemetry: Option<ResMut<Telemetry>>,
+) {
+    let Some((trajectory, started)) = &playback.playing else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    let elapsed = time - started;
+    let state = trajectory.state(elapsed);
+    if setpoints.get(&settings.setpoint) != Some(state.position) {
+        setpoints.set(&settings.setpoint, state.position);
+    }
+    if let Some(feedforward) = &settings.feedforward {
+        if setpoints.get(feedforward) != Some(state.velocity) {
+            setpoints.set(feedforward, state.velocity);
+        }
+    }
+    if let Some(mut telemetry) = telemetry {
+        telemetry.record(TRAJECTORY_POSITION, time, state.position);
+        telemetry.record(TRAJECTORY_VELOCITY, time, state.velocity);
+        telemetry.record(TRAJECTORY_ACCELERATION, time, state.acceleration);
+    }
+    if elapsed >= trajectory.duration() {
+        playback.playing = None;
+    }
+}
+
+/// Panel to plan the trajectory and play it.
+fn trajectory_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<TrajectorySettings>>,
+    mut playback: ResMut<TrajectoryPlayback>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Trajectory")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Profile")
+                .selected_text(edited.profile.name())
+                .show_ui(ui, |ui| {
+                    for kind in ProfileKind::ALL {
+                        ui.selectable_value(&mut edited.profile, kind, kind.name());
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.label("Setpoint");
+                ui.text_edit_singleline(&mut edited.setpoint);
+            });
+            let mut feedforward = edited.feedforward.is_some();
+            ui.checkbox(&mut feedforward, "Velocity feedforward");
+            match (feedforward, &mut edited.feedforward) {
+                (true, Some(setpoint)) => {
+                    ui.text_edit_singleline(setpoint);
+                }
+                (true, None) => edited.feedforward = Some(String::new()),
+                (false, _) => edited.feedforward = None,
+            }
+
+            ui.separator();
+            let limits = &mut edited.limits;
+            ui.add(
+                egui::DragValue::new(&mut limits.max_velocity)
+                    .speed(0.1)
+                    .range(0.0..=f32::MAX)
+                    .prefix("Velocity limit: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut limits.max_acceleration)
+                    .speed(0.1)
+                    .range(0.0..=f32::MAX)
+                    .prefix("Acceleration limit: "),
+            );
+            if edited.profile == ProfileKind::SCurve {
+                ui.add(
+                    egui::DragValue::new(&mut limits.max_jerk)
+                        .speed(1.0)
+                        .range(0.0..=f32::MAX)
+                        .prefix("Jerk limit: "),
+                );
+            }
+
+            ui.separator();
+            ui.label("Waypoints");
+            let mut removed = None;
+            for (index, waypoint) in edited.waypoints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(waypoint).speed(0.01));
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                edited.waypoints.remove(index);
+            }
+            if ui.button("Add a waypoint").clicked() {
+                let last = edited.waypoints.last().copied().unwrap_or_default();
+                edited.waypoints.push(last);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Start").clicked() {
+                    let start = setpoints.get(&edited.setpoint).unwrap_or_default();
+                    playback.playing = Some((edited.trajectory(start), clock.elapsed_secs()));
+                }
+                if ui.button("Stop").clicked() {
+                    playback.playing = None;
+                }
+            });
+            if let Some((trajectory, started)) = &playback.playing {
+                ui.label(format!(
+                    "{:.2} s of {:.2} s",
+                    clock.elapsed_secs() - started,
+                    trajectory.duration()
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("trajectory", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("trajectory", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
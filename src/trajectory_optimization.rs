@@ -0,0 +1,527 @@
+//! This module provides an offline trajectory optimizer, planning the swing-up of the pendulum
+//! by [direct collocation](crate::control::Collocation) before it runs rather than feeding back
+//! as it goes.
+//!
+//! The optimizer finds either the fastest swing-up or the one over a given duration with the
+//! least energy, the integral of the squared torque of the motor, within the torque limit of the
+//! motor. The torque is that accelerating the arm, its inertia times its acceleration; the
+//! reaction of the pendulum is left to the motor. The result is saved as a reference trajectory,
+//! `<name>.json` in the `trajectories` data directory: the angle, velocity, acceleration and
+//! torque of the arm at each node of the collocation, with the angle of the pendulum to expect.
+//!
+//! Played, the arm tracks the reference from where it is, as a recording replayed by the
+//! [teach](crate::teach) panel: its velocity setpoint is the velocity of the reference, fed
+//! forward, corrected by a proportional term on the measured angle. Once the pendulum is
+//! upright, the stabilizing controllers take over. The reference played is recorded as the
+//! `optimized/angle`, `optimized/velocity` and `optimized/torque` telemetry channels.
+use std::{
+    f64::consts::PI,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use nalgebra::Vector4;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SimClockSet},
+    config_plugin::{self, data_dir},
+    control::{Collocation, RotaryPendulum, SwingUp},
+    error::{Error, ErrorEvent, Result},
+    logging::subsystem,
+    lqr,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_ANGLE},
+};
+
+/// Angle of the arm in the reference played, from where it started.
+pub const OPTIMIZED_ANGLE: &str = "optimized/angle";
+/// Velocity of the arm in the reference played.
+pub const OPTIMIZED_VELOCITY: &str = "optimized/velocity";
+/// Torque of the motor fed forward by the reference played.
+pub const OPTIMIZED_TORQUE: &str = "optimized/torque";
+
+pub struct TrajectoryOptimizationPlugin;
+
+impl Plugin for TrajectoryOptimizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OptimizedTrajectory>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PostUpdate,
+                play_reference
+                    .after(SimClockSet::Advance)
+                    .run_if(resource_exists::<Persistent<TrajectoryOptimizationSettings>>),
+            )
+            .add_systems(
+                Update,
+                trajectory_optimization_panel
+                    .run_if(resource_exists::<Persistent<TrajectoryOptimizationSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// What the optimizer minimizes.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// The duration of the swing-up.
+    MinimumTime,
+    /// The integral of the squared torque over the duration of the swing-up.
+    MinimumEnergy,
+}
+
+impl Objective {
+    pub const ALL: [Objective; 2] = [Objective::MinimumTime, Objective::MinimumEnergy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Objective::MinimumTime => "Minimum time",
+            Objective::MinimumEnergy => "Minimum energy",
+        }
+    }
+}
+
+/// Represents the configuration of the trajectory optimizer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TrajectoryOptimizationSettings {
+    pub objective: Objective,
+    /// Duration of the minimum-energy swing-up, and longest duration of the minimum-time one,
+    /// in s.
+    pub duration: f32,
+    /// Number of segments of the collocation.
+    pub segments: usize,
+    /// Angular acceleration of the pendulum per sine of its angle from upright, in 1/s², and
+    /// per acceleration of the arm, upright.
+    pub gravity: f64,
+    pub coupling: f64,
+    /// Largest torque of the motor, in N·m, and inertia of the arm about its joint, in kg·m².
+    pub torque_limit: f32,
+    pub arm_inertia: f32,
+    /// Name of the saved reference trajectory.
+    pub name: String,
+    /// Telemetry channel measuring the angle of the arm, and velocity setpoint driving it.
+    pub position: String,
+    pub setpoint: String,
+    /// Gain from the angle error of the arm to its velocity setpoint, in 1/s.
+    pub gain: f32,
+}
+
+impl Default for TrajectoryOptimizationSettings {
+    fn default() -> Self {
+        let (a, b) = lqr::pendulum_model(2.0, 4.93, 4.0, 9.81);
+        Self {
+            objective: Objective::MinimumTime,
+            duration: 3.0,
+            segments: 40,
+            gravity: a[3][2],
+            coupling: b[3],
+            torque_limit: 10.0,
+            arm_inertia: 0.5,
+            name: "swing_up".to_string(),
+            position: MOTOR_ANGLE.to_string(),
+            setpoint: MOTOR_VELOCITY.to_string(),
+            gain: 5.0,
+        }
+    }
+}
+
+impl TrajectoryOptimizationSettings {
+    /// The reference swinging the pendulum up from hanging at rest.
+    pub fn optimize(&self) -> Result<ReferenceTrajectory> {
+        let invalid = |message: String| Error::Config {
+            name: "trajectory_optimization".to_string(),
+            message,
+        };
+        let model = RotaryPendulum {
+            gravity: self.gravity,
+            coupling: self.coupling,
+        };
+        let limit = f64::from(self.torque_limit / self.arm_inertia);
+        let collocation = Collocation::new(model, self.segments, limit)
+            .filter(|_| self.arm_inertia > 0.0 && self.duration > 0.0)
+            .ok_or_else(|| {
+                invalid(
+                    "the segments must be at least one, and the duration, the torque limit and \
+                     the inertia of the arm positive"
+                        .to_string(),
+                )
+            })?;
+        let hanging = Vector4::new(0.0, 0.0, PI, 0.0);
+        let duration = f64::from(self.duration);
+        let swing_up = match self.objective {
+            Objective::MinimumTime => collocation.minimum_time(&hanging, duration),
+            Objective::MinimumEnergy => collocation.minimum_energy(&hanging, duration),
+        };
+        swing_up
+            .map(|swing_up| ReferenceTrajectory::new(&swing_up, self.arm_inertia))
+            .ok_or_else(|| {
+                invalid(format!(
+                    "the pendulum can't be swung up in {:.2} s under {} N·m",
+                    self.duration, self.torque_limit
+                ))
+            })
+    }
+}
+
+/// A planned motion of the arm, sampled at the nodes of the collocation.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ReferenceTrajectory {
+    /// Time of each sample from the start, increasing, in s.
+    pub times: Vec<f32>,
+    /// Angle of the arm from where it starts, in rad, and its velocity and acceleration.
+    pub angles: Vec<f32>,
+    pub velocities: Vec<f32>,
+    pub accelerations: Vec<f32>,
+    /// Torque of the motor accelerating the arm, to feed forward, in N·m.
+    pub torques: Vec<f32>,
+    /// Angle of the pendulum from upright, in rad.
+    pub pendulum: Vec<f32>,
+}
+
+impl ReferenceTrajectory {
+    /// The reference of a swing-up of an arm of `inertia`, in kg·m².
+    pub fn new(swing_up: &SwingUp, inertia: f32) -> Self {
+        let column = |index: usize| {
+            swing_up
+                .states
+                .iter()
+                .map(|state| state[index] as f32)
+                .collect()
+        };
+        let accelerations: Vec<f32> = swing_up
+            .accelerations
+            .iter()
+            .map(|acceleration| *acceleration as f32)
+            .collect();
+        Self {
+            times: swing_up.times().map(|time| time as f32).collect(),
+            angles: column(0),
+            velocities: column(1),
+            torques: accelerations
+                .iter()
+                .map(|acceleration| inertia * acceleration)
+                .collect(),
+            accelerations,
+            pendulum: column(2),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_str(&json).map_err(|error| Error::io(path, io::Error::from(error)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from);
+        json.and_then(|json| fs::write(path, json))
+            .map_err(|error| Error::io(path, error))
+    }
+
+    /// Time from the first sample to the last, in s.
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or_default()
+    }
+
+    /// Angle, velocity and torque of the arm `time` seconds after the start, interpolated
+    /// linearly. Before the first sample and after the last, the arm rests on them.
+    pub fn sample(&self, time: f32) -> [f32; 3] {
+        if self.times.is_empty() {
+            return [0.0; 3];
+        }
+        let index = self.times.partition_point(|&t| t <= time);
+        if index == 0 || index == self.times.len() {
+            let end = index.saturating_sub(1);
+            return [self.angles[end], 0.0, 0.0];
+        }
+        let (before, after) = (index - 1, index);
+        let fraction = (time - self.times[before]) / (self.times[after] - self.times[before]);
+        let interpolate =
+            |values: &[f32]| values[before] + fraction * (values[after] - values[before]);
+        [
+            interpolate(&self.angles),
+            interpolate(&self.velocities),
+            interpolate(&self.torques),
+        ]
+    }
+}
+
+/// Directory of the saved reference trajectories.
+pub fn directory() -> PathBuf {
+    data_dir().join("trajectories")
+}
+
+/// The last reference optimized or loaded, and its playback.
+#[derive(Default, Resource)]
+pub struct OptimizedTrajectory {
+    pub reference: Option<ReferenceTrajectory>,
+    /// Time the playback started at, and the angle of the arm then.
+    pub playing: Option<(f32, f32)>,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<TrajectoryOptimizationSettings>(
+        "trajectory_optimization",
+        true,
+    );
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Writes the velocity setpoint tracking the reference, after each step of the simulation.
+fn play_reference(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<TrajectoryOptimizationSettings>>,
+    mut optimized: ResMut<OptimizedTrajectory>,
+    mut setpoints: ResMut<Setpoints>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let optimized = &mut *optimized;
+    let (Some(reference), Some((started, start))) = (&optimized.reference, optimized.playing)
+    else {
+        return;
+    };
+    let time = clock.elapsed_secs();
+    let elapsed = time - started;
+    if elapsed >= reference.duration() {
+        setpoints.set(&settings.setpoint, 0.0);
+        optimized.playing = None;
+        return;
+    }
+    let measured = telemetry
+        .as_ref()
+        .and_then(|telemetry| telemetry.latest(&settings.position));
+    let Some(measured) = measured else {
+        optimized.playing = None;
+        commands.send_event(ErrorEvent::from(Error::Config {
+            name: "trajectory_optimization".to_string(),
+            message: format!("no telemetry measures `{}`", settings.position),
+        }));
+        return;
+    };
+    let [angle, velocity, torque] = reference.sample(elapsed);
+    let angle = start + angle;
+    setpoints.set(
+        &settings.setpoint,
+        velocity + settings.gain * (angle - measured),
+    );
+    if let Some(telemetry) = telemetry.as_mut() {
+        telemetry.record(OPTIMIZED_ANGLE, time, angle);
+        telemetry.record(OPTIMIZED_VELOCITY, time, velocity);
+        telemetry.record(OPTIMIZED_TORQUE, time, torque);
+    }
+}
+
+/// Panel to optimize a swing-up, save or load it, and play it.
+fn trajectory_optimization_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<TrajectoryOptimizationSettings>>,
+    mut optimized: ResMut<OptimizedTrajectory>,
+    mut setpoints: ResMut<Setpoints>,
+    clock: Res<SimClock>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Trajectory optimization")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Objective")
+                .selected_text(edited.objective.name())
+                .show_ui(ui, |ui| {
+                    for objective in Objective::ALL {
+                        ui.selectable_value(&mut edited.objective, objective, objective.name());
+                    }
+                });
+            let duration = match edited.objective {
+                Objective::MinimumTime => "Longest duration: ",
+                Objective::MinimumEnergy => "Duration: ",
+            };
+            ui.add(
+                egui::DragValue::new(&mut edited.duration)
+                    .speed(0.1)
+                    .range(0.1..=f32::MAX)
+                    .prefix(duration)
+                    .suffix(" s"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.segments)
+                    .range(1..=200)
+                    .prefix("Segments: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.torque_limit)
+                    .speed(0.1)
+                    .range(0.0..=f32::MAX)
+                    .prefix("Torque limit: ")
+                    .suffix(" N·m"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut edited.arm_inertia)
+                    .speed(0.01)
+                    .range(0.001..=f32::MAX)
+                    .prefix("Arm inertia: ")
+                    .suffix(" kg·m²"),
+            );
+            ui.collapsing("Model", |ui| {
+                ui.add(
+                    egui::DragValue::new(&mut edited.gravity)
+                        .speed(0.01)
+                        .prefix("Gravity: ")
+                        .suffix(" 1/s²"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut edited.coupling)
+                        .speed(0.01)
+                        .prefix("Coupling: "),
+                );
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut edited.name);
+            });
+            let path = directory().join(format!("{}.json", edited.name));
+            ui.horizontal(|ui| {
+                if ui.button("Optimize").clicked() {
+                    match edited.optimize() {
+                        Ok(reference) => {
+                            info!(
+                                target: subsystem::CONTROL,
+                                "Swing-up optimized over {:.2} s",
+                                reference.duration()
+                            );
+                            optimized.reference = Some(reference);
+                            optimized.playing = None;
+                        }
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                }
+                if ui
+                    .add_enabled(
+                        optimized.reference.is_some(),
+                        egui::Button::new("Save reference"),
+                    )
+                    .clicked()
+                {
+                    if let Some(reference) = &optimized.reference {
+                        match reference.write(&path) {
+                            Ok(()) => info!(
+                                target: subsystem::IO,
+                                "Reference trajectory saved to {}",
+                                path.display()
+                            ),
+                            Err(error) => commands.send_event(ErrorEvent::from(error)),
+                        }
+                    }
+                }
+                if ui.button("Load reference").clicked() {
+                    match ReferenceTrajectory::read(&path) {
+                        Ok(reference) => {
+                            optimized.reference = Some(reference);
+                            optimized.playing = None;
+                        }
+                        Err(error) => commands.send_event(ErrorEvent::from(error)),
+                    }
+                }
+            });
+
+            if let Some(reference) = &optimized.reference {
+                let peak = reference
+                    .torques
+                    .iter()
+                    .fold(0.0, |peak: f32, torque| peak.max(torque.abs()));
+                ui.label(format!(
+                    "{:.2} s, peak torque {peak:.2} N·m",
+                    reference.duration()
+                ));
+                reference_plot(ui, reference);
+            }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(optimized.reference.is_some(), egui::Button::new("Play"))
+                    .clicked()
+                {
+                    let start = telemetry
+                        .as_ref()
+                        .and_then(|telemetry| telemetry.latest(&edited.position))
+                        .unwrap_or_default();
+                    optimized.playing = Some((clock.elapsed_secs(), start));
+                }
+                if ui.button("Stop").clicked() && optimized.playing.take().is_some() {
+                    setpoints.set(&edited.setpoint, 0.0);
+                }
+            });
+            if let (Some(reference), Some((started, _))) = (&optimized.reference, optimized.playing)
+            {
+                ui.label(format!(
+                    "{:.2} s of {:.2} s",
+                    clock.elapsed_secs() - started,
+                    reference.duration()
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save(
+                            "trajectory_optimization",
+                            error,
+                        )));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save(
+                            "trajectory_optimization",
+                            error,
+                        )));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
+
+/// Plot of the angles of the arm and of the pendulum, and of the torque, of a reference.
+fn reference_plot(ui: &mut egui::Ui, reference: &ReferenceTrajectory) {
+    let series = |values: &[f32]| -> PlotPoints {
+        reference
+            .times
+            .iter()
+            .zip(values)
+            .map(|(time, value)| [f64::from(*time), f64::from(*value)])
+            .collect()
+    };
+    Plot::new("optimized_trajectory")
+        .height(160.0)
+        .legend(Legend::default())
+        .x_axis_label("Time (s)")
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(series(&reference.angles)).name("Arm (rad)"));
+            plot_ui.line(Line::new(series(&reference.pendulum)).name("Pendulum (rad)"));
+            plot_ui.line(Line::new(series(&reference.torques)).name("Torque (N·m)"));
+        });
+}
@@ -0,0 +1,454 @@
+//! This module couples the joints of a plant through transmissions, so gear- and belt-driven axes
+//! can be modeled: a driver joint, usually the motor, turns a driven joint through a gear ratio.
+//!
+//! Rapier has no constraint between the angles of two joints, so the transmission is a custom
+//! constraint on top of them: a stiff spring and damper between the angle of the driven joint and
+//! that of the driver divided by the ratio, applied as an impulse per step on the driven joint
+//! and, scaled back through the ratio, on the driver, each in reverse on its parent. A gear only
+//! gives as much as its teeth, while a belt stretches, with a lower stiffness, and slips once the
+//! torque it carries exceeds its slip torque: the driven joint then falls behind the driver for
+//! good. The torque carried is recorded as the `transmission/<link>` telemetry channel of the
+//! driven link, and the slip of a belt as `belt_slip/<link>`. The transmissions are configured
+//! in `transmissions.json`.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    actuators,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    plants::{self, Link},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the torques carried by the transmissions.
+pub const TRANSMISSION_PREFIX: &str = "transmission/";
+/// Prefix of the telemetry channels of the slip of the belts.
+pub const SLIP_PREFIX: &str = "belt_slip/";
+
+pub struct TransmissionsPlugin;
+
+impl Plugin for TransmissionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                configure_transmissions.run_if(resource_exists::<Persistent<TransmissionSettings>>),
+            )
+            .add_systems(
+                PostUpdate,
+                apply_transmissions
+                    .before(actuators::drive)
+                    .before(PhysicsSet::SyncBackend),
+            )
+            .add_systems(
+                Update,
+                transmissions_panel
+                    .run_if(resource_exists::<Persistent<TransmissionSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// How the driver turns the driven joint.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransmissionKind {
+    /// Meshing gears, which never slip.
+    #[default]
+    Gear,
+    /// A cable or a belt, which slips past its slip torque.
+    Belt,
+}
+
+/// A transmission between two joints of a plant.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Transmission {
+    /// Name of the link whose joint drives, in each plant.
+    pub driver: String,
+    /// Name of the link whose joint is driven.
+    pub driven: String,
+    pub kind: TransmissionKind,
+    /// Turns of the driver per turn of the driven joint, negative to reverse it.
+    pub ratio: f32,
+    /// Torque per rad the driven joint lags behind, in N·m/rad.
+    pub stiffness: f32,
+    /// Torque per rad/s the driven joint lags behind, in N·m·s/rad.
+    pub damping: f32,
+    /// Torque over which a belt slips, in N·m, or 0 for none.
+    pub slip_torque: f32,
+}
+
+impl Default for Transmission {
+    fn default() -> Self {
+        Self {
+            driver: "motor".to_string(),
+            driven: "pendulum".to_string(),
+            kind: TransmissionKind::Gear,
+            ratio: 2.0,
+            stiffness: 50.0,
+            damping: 0.2,
+            slip_torque: 0.0,
+        }
+    }
+}
+
+impl Transmission {
+    /// Whether the transmission couples two distinct joints through a finite ratio.
+    pub fn is_valid(&self) -> bool {
+        self.driver != self.driven
+            && self.ratio.is_finite()
+            && self.ratio != 0.0
+            && self.stiffness >= 0.0
+            && self.damping >= 0.0
+    }
+
+    /// Torque over which the transmission slips, in N·m, if it does.
+    pub fn slips_over(&self) -> Option<f32> {
+        (self.kind == TransmissionKind::Belt && self.slip_torque > 0.0).then_some(self.slip_torque)
+    }
+}
+
+/// Represents the configuration of the transmissions.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct TransmissionSettings {
+    /// The transmissions; the other joints turn on their own.
+    pub transmissions: Vec<Transmission>,
+}
+
+/// The state of a transmission between steps: the angles of its joints, unwrapped over the
+/// turns, and how far the driven joint slipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Coupler {
+    angles: Option<[f32; 2]>,
+    /// Angle of the driven joint when the driver is at 0, in rad.
+    offset: f32,
+    slip: f32,
+}
+
+impl Coupler {
+    /// Torque the transmission applies on the driven joint, in N·m, with the driver at `driver`
+    /// (rad) turning at `driver_speed` (rad/s) and the driven joint at `driven` turning at
+    /// `driven_speed`. The driver takes the reaction, [`Coupler::driver_torque`]. The joints are
+    /// coupled as they are at the first step.
+    pub fn torque(
+        &mut self,
+        model: &Transmission,
+        [driver, driver_speed]: [f32; 2],
+        [driven, driven_speed]: [f32; 2],
+    ) -> f32 {
+        let [driver, driven] = match self.angles {
+            Some([last_driver, last_driven]) => [
+                last_driver + wrap(driver - last_driver),
+                last_driven + wrap(driven - last_driven),
+            ],
+            None => {
+                self.offset = driven - driver / model.ratio;
+                [driver, driven]
+            }
+        };
+        self.angles = Some([driver, driven]);
+
+        let lag = driver / model.ratio + self.offset - driven;
+        let mut stretch = model.stiffness * lag;
+        if let Some(limit) = model.slips_over() {
+            if stretch.abs() > limit {
+                // The belt slips until it only carries its slip torque.
+                let slipped = lag - stretch.signum() * limit / model.stiffness;
+                self.offset -= slipped;
+                self.slip -= slipped;
+                stretch = stretch.signum() * limit;
+            }
+        }
+        let torque = stretch + model.damping * (driver_speed / model.ratio - driven_speed);
+        match model.slips_over() {
+            Some(limit) => torque.clamp(-limit, limit),
+            None => torque,
+        }
+    }
+
+    /// Torque on the driver of the transmission carrying `torque` (N·m) to the driven joint, so
+    /// the transmission neither makes nor loses power.
+    pub fn driver_torque(model: &Transmission, torque: f32) -> f32 {
+        -torque / model.ratio
+    }
+
+    /// Angle the driven joint slipped by, in rad, negative behind the driver.
+    pub fn slip(&self) -> f32 {
+        self.slip
+    }
+}
+
+/// Angle between -π and π.
+fn wrap(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// The transmission driving the revolute joint of its entity.
+#[derive(Clone, Component, Debug)]
+pub struct Driven {
+    pub model: Transmission,
+    /// Link whose joint drives.
+    pub driver: Entity,
+    pub coupler: Coupler,
+    /// Telemetry channel of the torque carried.
+    pub torque_channel: String,
+    /// Telemetry channel of the slip of a belt.
+    pub slip_channel: String,
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) =
+        config_plugin::load_config::<TransmissionSettings>("transmissions", true);
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Couples the configured joints, as they spawn or when the configuration changes.
+fn configure_transmissions(
+    mut commands: Commands,
+    settings: Res<Persistent<TransmissionSettings>>,
+    joints: Query<(Entity, &Link, &ImpulseJoint, Option<&Driven>)>,
+    added: Query<(), Added<ImpulseJoint>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (entity, link, joint, driven) in &joints {
+        let configured = settings
+            .transmissions
+            .iter()
+            .filter(|transmission| transmission.is_valid())
+            .find(|transmission| transmission.driven == link.name)
+            .and_then(|transmission| {
+                joints
+                    .iter()
+                    .find(|(_, driver, ..)| {
+                        driver.plant == link.plant && driver.name == transmission.driver
+                    })
+                    .map(|(driver, _, driver_joint, _)| (transmission, driver, driver_joint))
+            });
+        match (configured, driven) {
+            (Some((transmission, driver, _)), Some(driven))
+                if driven.model == *transmission && driven.driver == driver => {}
+            (Some((transmission, driver, driver_joint)), _) => {
+                commands
+                    .entity(entity)
+                    .insert(Driven {
+                        model: transmission.clone(),
+                        driver,
+                        coupler: Coupler::default(),
+                        torque_channel: plants::namespaced(
+                            &link.plant,
+                            &format!("{TRANSMISSION_PREFIX}{}", link.name),
+                        ),
+                        slip_channel: plants::namespaced(
+                            &link.plant,
+                            &format!("{SLIP_PREFIX}{}", link.name),
+                        ),
+                    })
+                    .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+                // The driver and the parents take the reactions.
+                for other in [joint.parent, driver, driver_joint.parent] {
+                    commands
+                        .entity(other)
+                        .insert_if_new((Velocity::default(), ExternalImpulse::default()));
+                }
+                info!(
+                    target: subsystem::CONTROL,
+                    "{} drives {} through a {:?} of ratio {}",
+                    plants::namespaced(&link.plant, &transmission.driver),
+                    link.path(),
+                    transmission.kind,
+                    transmission.ratio
+                );
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Driven>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Applies the torques carried by the transmissions, for the next step of the simulation.
+fn apply_transmissions(
+    clock: Res<SimClock>,
+    mut transmissions: Query<(Entity, &mut Driven)>,
+    joints: Query<(&ImpulseJoint, &Transform)>,
+    velocities: Query<&Velocity>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    contexts: Query<&RapierContext>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let (dt, Ok(context)) = (clock.delta_secs(), contexts.get_single()) else {
+        return;
+    };
+    if dt == 0.0 {
+        return;
+    }
+    let time = clock.elapsed_secs();
+    // Axis, parent, angle and speed of the joint of a link.
+    let state = |entity: Entity| {
+        let (joint, transform) = joints.get(entity).ok()?;
+        let angular = |entity: Entity| {
+            velocities
+                .get(entity)
+                .map_or(Vec3::ZERO, |velocity| velocity.angvel)
+        };
+        let axis = (transform.rotation * joint.data.as_ref().local_axis2()).normalize_or_zero();
+        let speed = (angular(entity) - angular(joint.parent)).dot(axis);
+        let angle = context.impulse_revolute_joint_angle(entity)?;
+        Some((axis, joint.parent, [angle, speed]))
+    };
+    for (entity, mut driven) in &mut transmissions {
+        let (Some(driven_state), Some(driver_state)) = (state(entity), state(driven.driver)) else {
+            continue;
+        };
+        let driven = &mut *driven;
+        let torque = driven
+            .coupler
+            .torque(&driven.model, driver_state.2, driven_state.2);
+        let driver_torque = Coupler::driver_torque(&driven.model, torque);
+        for ((axis, parent, _), entity, torque) in [
+            (driven_state, entity, torque),
+            (driver_state, driven.driver, driver_torque),
+        ] {
+            let torque_impulse = torque * axis * dt;
+            if let Ok(mut impulse) = impulses.get_mut(entity) {
+                impulse.torque_impulse += torque_impulse;
+            }
+            if let Ok(mut impulse) = impulses.get_mut(parent) {
+                impulse.torque_impulse -= torque_impulse;
+            }
+        }
+
+        let Some(telemetry) = telemetry.as_mut() else {
+            continue;
+        };
+        telemetry.record(&driven.torque_channel, time, torque);
+        if driven.model.slips_over().is_some() {
+            telemetry.record(&driven.slip_channel, time, driven.coupler.slip());
+        }
+    }
+}
+
+/// Panel to couple the joints through transmissions.
+fn transmissions_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<TransmissionSettings>>,
+) {
+    let mut edited = settings.get().clone();
+    egui::Window::new("Transmissions")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut removed = None;
+            for (index, transmission) in edited.transmissions.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("Driver");
+                    ui.text_edit_singleline(&mut transmission.driver);
+                    ui.label("Driven");
+                    ui.text_edit_singleline(&mut transmission.driven);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                egui::Grid::new(("transmission", index)).show(ui, |ui| {
+                    egui::ComboBox::from_id_salt(("transmission kind", index))
+                        .selected_text(format!("{:?}", transmission.kind))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut transmission.kind,
+                                TransmissionKind::Gear,
+                                "Gear",
+                            );
+                            ui.selectable_value(
+                                &mut transmission.kind,
+                                TransmissionKind::Belt,
+                                "Belt",
+                            );
+                        });
+                    ui.add(
+                        egui::DragValue::new(&mut transmission.ratio)
+                            .range(-100.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Ratio: "),
+                    )
+                    .on_hover_text("Turns of the driver per turn of the driven joint");
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut transmission.stiffness)
+                            .range(0.0..=10000.0)
+                            .speed(0.1)
+                            .prefix("Stiffness: ")
+                            .suffix(" N·m/rad"),
+                    )
+                    .on_hover_text("Lower it if the joints oscillate");
+                    ui.add(
+                        egui::DragValue::new(&mut transmission.damping)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("Damping: ")
+                            .suffix(" N·m·s/rad"),
+                    );
+                    ui.end_row();
+                    if transmission.kind == TransmissionKind::Belt {
+                        ui.add(
+                            egui::DragValue::new(&mut transmission.slip_torque)
+                                .range(0.0..=1000.0)
+                                .speed(0.01)
+                                .prefix("Slip torque: ")
+                                .suffix(" N·m"),
+                        )
+                        .on_hover_text("0 for a belt that never slips");
+                        ui.end_row();
+                    }
+                });
+                if !transmission.is_valid() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "Couple two joints through a nonzero ratio",
+                    );
+                }
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.transmissions.remove(index);
+            }
+            if ui.button("Add a transmission").clicked() {
+                edited.transmissions.push(Transmission::default());
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("transmissions", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("transmissions", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,95 @@
+//! This module provides the transport controls of the simulation: pausing and resuming it,
+//! stepping the physics once, and running it slower or faster than real time, to follow a
+//! controller step by step or in slow motion.
+//!
+//! The *Transport* panel has a button per control, labelled with the keys and the gamepad
+//! buttons of its [input binding](crate::input_bindings), and shows the simulated time, the
+//! physics step and the real-time factor achieved, which falls short of the speed asked for when
+//! the frames can't keep up.
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::{SimClock, SPEEDS},
+    input_bindings::{InputAction, InputBindings},
+};
+
+pub struct TransportPlugin;
+
+impl Plugin for TransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, transport_panel.run_if(has_ui));
+    }
+}
+
+/// The buttons bound to `action`, e.g. ` (Space, Start button)`, or nothing when unbound.
+fn shortcut(bindings: Option<&InputBindings>, action: InputAction) -> String {
+    let buttons: Vec<String> = bindings
+        .into_iter()
+        .flat_map(|bindings| bindings.buttons(action))
+        .map(|button| button.label())
+        .collect();
+    if buttons.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", buttons.join(", "))
+    }
+}
+
+/// Panel to pause, step and change the speed of the simulation.
+fn transport_panel(
+    mut contexts: EguiContexts,
+    mut clock: ResMut<SimClock>,
+    bindings: Option<Res<Persistent<InputBindings>>>,
+) {
+    let bindings = bindings.as_deref().map(|bindings| bindings.get());
+    egui::Window::new("Transport")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let pause = if clock.is_paused() { "Resume" } else { "Pause" };
+                let pause = pause.to_string() + &shortcut(bindings, InputAction::TogglePause);
+                if ui.button(pause).clicked() {
+                    clock.toggle_pause();
+                }
+                let step = "Step".to_string() + &shortcut(bindings, InputAction::Step);
+                if ui
+                    .button(step)
+                    .on_hover_text("Takes a single physics step, pausing the simulation")
+                    .clicked()
+                {
+                    clock.step_once();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Speed");
+                for speed in SPEEDS {
+                    if ui
+                        .selectable_label(clock.speed() == speed, format!("{speed}x"))
+                        .clicked()
+                    {
+                        clock.set_speed(speed);
+                    }
+                }
+            });
+            ui.label(format!(
+                "Slower{}, faster{}",
+                shortcut(bindings, InputAction::Slower),
+                shortcut(bindings, InputAction::Faster)
+            ));
+
+            ui.separator();
+            ui.label(format!(
+                "{:.3} s simulated, step {}",
+                clock.elapsed_secs(),
+                clock.tick()
+            ));
+            ui.label(format!(
+                "Real-time factor: {:.2} for {}x",
+                clock.real_time_factor(),
+                clock.speed()
+            ));
+        });
+}
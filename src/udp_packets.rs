@@ -0,0 +1,323 @@
+//! Fixed-layout UDP packets, as exchanged by the UDP blocks of Simulink or LabVIEW.
+//!
+//! Those blocks send and receive arrays of numbers without any framing, so the layout of the
+//! packets is read from the `udp_packets.json` configuration file instead. A packet is made
+//! of the `header` bytes, of a `u32` counter if `counter` is set, then of one value per
+//! signal, in order. Values are `double` (8 bytes, the default of Simulink) or `single`
+//! (4 bytes). Everything is little endian.
+//!
+//! Received packets carry the setpoints listed in `inputs`. After every physics step, the
+//! simulator sends the signals listed in `outputs` (telemetry channels, or setpoints) to
+//! `remote`, or to the sender of the last packet received.
+//!
+//! With `timestamp`, the values of every packet start with a simulated time, in seconds: the
+//! time of the step for the packets sent, and the time to apply the inputs at for the packets
+//! received, through the [`CommandBuffer`].
+//!
+//! Every packet received feeds the [`Watchdog`]; while it is tripped, the inputs are ignored.
+//! Both directions go through the impaired [`Link`]s of `network.json`.
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    command_buffer::{CommandBuffer, CommandBufferPlugin, TimedCommand},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    network::{Link, NetworkSettings},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+    watchdog::Watchdog,
+};
+
+/// Port used when none is given, the default of the Simulink UDP blocks.
+pub const DEFAULT_PORT: u16 = 25000;
+
+/// Encoding of the values.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    /// IEEE 754 single precision.
+    Single,
+    /// IEEE 754 double precision.
+    #[default]
+    Double,
+}
+
+impl ValueType {
+    fn size(self) -> usize {
+        match self {
+            ValueType::Single => 4,
+            ValueType::Double => 8,
+        }
+    }
+}
+
+/// The layout of the packets.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct PacketLayout {
+    /// Bytes starting every packet, e.g. a magic.
+    #[serde(default)]
+    pub header: String,
+    /// Whether a `u32` counter follows the header.
+    #[serde(default)]
+    pub counter: bool,
+    /// Whether the values start with a simulated time.
+    #[serde(default)]
+    pub timestamp: bool,
+    #[serde(default)]
+    pub value_type: ValueType,
+    /// Setpoints carried by the packets received.
+    pub inputs: Vec<String>,
+    /// Signals carried by the packets sent.
+    pub outputs: Vec<String>,
+    /// Address to send the packets to, instead of the sender of the last packet.
+    #[serde(default)]
+    pub remote: Option<SocketAddr>,
+}
+
+impl Default for PacketLayout {
+    fn default() -> Self {
+        Self {
+            header: String::new(),
+            counter: false,
+            timestamp: false,
+            value_type: ValueType::Double,
+            inputs: vec![MOTOR_VELOCITY.to_string()],
+            outputs: vec![PENDULUM_ANGLE.to_string(), MOTOR_VELOCITY.to_string()],
+            remote: None,
+        }
+    }
+}
+
+impl PacketLayout {
+    fn header_size(&self) -> usize {
+        self.header.len() + if self.counter { 4 } else { 0 }
+    }
+
+    /// Size of a packet carrying `values` values.
+    pub fn packet_size(&self, values: usize) -> usize {
+        self.header_size() + values * self.value_type.size()
+    }
+
+    pub fn encode(&self, counter: u32, values: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.packet_size(values.len()));
+        bytes.extend(self.header.as_bytes());
+        if self.counter {
+            bytes.extend(counter.to_le_bytes());
+        }
+        for &value in values {
+            match self.value_type {
+                ValueType::Single => bytes.extend(value.to_le_bytes()),
+                ValueType::Double => bytes.extend(f64::from(value).to_le_bytes()),
+            }
+        }
+        bytes
+    }
+
+    /// The counter (zero without one) and the inputs of a packet, after its time if it has a
+    /// timestamp.
+    pub fn decode(&self, bytes: &[u8]) -> Option<(u32, Vec<f32>)> {
+        let values = self.inputs.len() + usize::from(self.timestamp);
+        if bytes.len() != self.packet_size(values) || !bytes.starts_with(self.header.as_bytes()) {
+            return None;
+        }
+        let mut offset = self.header.len();
+        let mut counter = 0;
+        if self.counter {
+            counter = u32::from_le_bytes(bytes[offset..offset + 4].try_into().ok()?);
+            offset += 4;
+        }
+        let values = bytes[offset..]
+            .chunks_exact(self.value_type.size())
+            .map(|bytes| match self.value_type {
+                ValueType::Single => bytes.try_into().map(f32::from_le_bytes),
+                ValueType::Double => bytes
+                    .try_into()
+                    .map(|bytes| f64::from_le_bytes(bytes) as f32),
+            })
+            .collect::<Result<_, _>>()
+            .ok()?;
+        Some((counter, values))
+    }
+}
+
+/// Exchanges the packets of the given layout on the given address.
+pub struct UdpPacketsPlugin {
+    /// Local address to listen on.
+    pub bind: SocketAddr,
+    pub layout: PacketLayout,
+    pub network: NetworkSettings,
+}
+
+impl Plugin for UdpPacketsPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match UdpSocket::bind(self.bind).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "UDP packets listening on {}", self.bind);
+        if self.layout.timestamp && !app.is_plugin_added::<CommandBufferPlugin>() {
+            app.add_plugins(CommandBufferPlugin);
+        }
+        app.init_resource::<Setpoints>()
+            .insert_resource(self.layout.clone())
+            .insert_resource(UdpPackets {
+                socket,
+                peer: self.layout.remote,
+                counter: 0,
+                applied: BTreeMap::new(),
+                incoming: Link::new(self.network.incoming.clone(), self.network.seed),
+                outgoing: Link::new(self.network.outgoing.clone(), self.network.seed + 1),
+            })
+            .add_systems(PreUpdate, receive_inputs)
+            .add_systems(PostUpdate, send_outputs.after(SimClockSet::Advance));
+    }
+}
+
+/// State of the exchange with the peer.
+#[derive(Resource)]
+pub struct UdpPackets {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    /// Counter of the next packet sent.
+    counter: u32,
+    /// Setpoints last written from the packets.
+    applied: BTreeMap<String, f32>,
+    /// Packets received, with their sender.
+    incoming: Link<(SocketAddr, Vec<u8>)>,
+    /// Packets to send, with their destination.
+    outgoing: Link<(SocketAddr, Vec<u8>)>,
+}
+
+impl UdpPackets {
+    /// Address the packets are sent to, if known yet.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+}
+
+/// Applies the inputs of the last packet received to the setpoints, or schedules those of
+/// every packet received when they have a timestamp.
+fn receive_inputs(
+    layout: Res<PacketLayout>,
+    mut packets: ResMut<UdpPackets>,
+    clock: Res<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    mut command_buffer: Option<ResMut<CommandBuffer>>,
+    watchdog: Option<ResMut<Watchdog>>,
+) {
+    let mut buffer = [0; 1500];
+    let now = Instant::now();
+    loop {
+        match packets.socket.recv_from(&mut buffer) {
+            Ok((size, from)) => packets
+                .incoming
+                .send((from, buffer[..size].to_vec()), size, now),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                debug!(target: subsystem::IO, "UDP packets receive error: {error}");
+                break;
+            }
+        }
+    }
+    let mut received = Vec::new();
+    for (from, packet) in packets.incoming.receive(now) {
+        match layout.decode(&packet) {
+            Some((_, values)) => {
+                if layout.remote.is_none() && packets.peer != Some(from) {
+                    info!(target: subsystem::IO, "UDP packets exchanged with {from}");
+                    packets.peer = Some(from);
+                }
+                received.push(values);
+            }
+            None => debug!(target: subsystem::IO, "Ignoring a datagram from {from}"),
+        }
+    }
+    if received.is_empty() {
+        return;
+    }
+    if watchdog.is_some_and(|mut watchdog| !watchdog.feed("udp", Instant::now())) {
+        return;
+    }
+    if !layout.timestamp {
+        // Only the last inputs matter.
+        received.drain(..received.len() - 1);
+    }
+    for values in received {
+        let (time, inputs) = if layout.timestamp {
+            (Some(values[0]), &values[1..])
+        } else {
+            (None, &values[..])
+        };
+        for (signal, &value) in layout.inputs.iter().zip(inputs) {
+            // Other inputs (e.g. the keyboard) keep working while the peer doesn't change a value.
+            if !value.is_finite() || packets.applied.get(signal) == Some(&value) {
+                continue;
+            }
+            match (
+                command_buffer.as_deref_mut(),
+                time.filter(|time| time.is_finite()),
+            ) {
+                (Some(command_buffer), Some(time)) => command_buffer.schedule(
+                    TimedCommand {
+                        time,
+                        setpoint: signal.clone(),
+                        value,
+                    },
+                    clock.elapsed_secs(),
+                ),
+                _ => setpoints.set(signal, value),
+            }
+            packets.applied.insert(signal.clone(), value);
+        }
+    }
+}
+
+/// Sends the outputs after every physics step.
+fn send_outputs(
+    layout: Res<PacketLayout>,
+    mut packets: ResMut<UdpPackets>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    let now = Instant::now();
+    // Packets are only produced by the physics steps, but those in flight arrive in real time.
+    if let Some(peer) = packets.peer.filter(|_| clock.delta() > Duration::ZERO) {
+        let time = layout.timestamp.then(|| clock.elapsed_secs());
+        let values = time
+            .into_iter()
+            .chain(layout.outputs.iter().map(|signal| {
+                telemetry
+                    .as_ref()
+                    .and_then(|telemetry| telemetry.latest(signal))
+                    .or_else(|| setpoints.get(signal))
+                    .unwrap_or_default()
+            }))
+            .collect::<Vec<_>>();
+        let packet = layout.encode(packets.counter, &values);
+        packets.counter = packets.counter.wrapping_add(1);
+        let size = packet.len();
+        packets.outgoing.send((peer, packet), size, now);
+    }
+    for (peer, packet) in packets.outgoing.receive(now) {
+        if let Err(error) = packets.socket.send_to(&packet, peer) {
+            warn!(target: subsystem::IO, "Failed to send a UDP packet to {peer}: {error}");
+        }
+    }
+}
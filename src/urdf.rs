@@ -0,0 +1,667 @@
+//! This module loads robots described in URDF, the format of most robot models outside of
+//! Blender.
+//!
+//! The links of the robot are spawned as rigid bodies where its joints place them, its root
+//! fixed to the world, with the inertial properties they declare. Their visuals are drawn from
+//! the primitive shapes or from the glTF meshes they reference (other mesh formats aren't
+//! loaded), and their collision shapes become colliders. The revolute, continuous and
+//! prismatic joints keep their axes and limits, the fixed joints hold their links together.
+//!
+//! The links get a [`Link`] in the namespace of the robot, so the tooling working with the
+//! links of the built-in plants works with the robot too. Each moving joint is driven by the
+//! `<robot>/<joint>/velocity` setpoint, up to the effort of its limits, and its position is
+//! recorded as the `<robot>/<joint>/position` telemetry channel.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    f32::consts::FRAC_PI_2,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, Result},
+    joint_builder::{JointKind, LinkMetadata},
+    logging::subsystem,
+    plants::{self, Link, MOTOR_FACTOR},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+pub struct UrdfPlugin {
+    pub robot: Robot,
+    /// Directory the paths of the meshes are relative to, the one of the URDF file.
+    pub directory: PathBuf,
+}
+
+impl Plugin for UrdfPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(UrdfRobot {
+                robot: self.robot.clone(),
+                directory: self.directory.clone(),
+            })
+            .add_systems(Startup, spawn_robot)
+            .add_systems(Update, drive_joints.run_if(resource_changed::<Setpoints>))
+            .add_systems(PostUpdate, record_joints.after(SimClockSet::Advance));
+    }
+}
+
+/// The robot to spawn.
+#[derive(Resource)]
+struct UrdfRobot {
+    robot: Robot,
+    directory: PathBuf,
+}
+
+/// A robot described in URDF.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Robot {
+    pub name: String,
+    pub links: Vec<UrdfLink>,
+    pub joints: Vec<UrdfJoint>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UrdfLink {
+    pub name: String,
+    pub inertial: Option<Inertial>,
+    pub visuals: Vec<Shape>,
+    pub collisions: Vec<Shape>,
+}
+
+/// Inertial properties of a link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inertial {
+    /// Center of mass and frame of the inertia, in the frame of the link.
+    pub origin: Transform,
+    /// Mass, in kg.
+    pub mass: f32,
+    /// Inertia tensor, `[ixx, ixy, ixz, iyy, iyz, izz]` in kg·m².
+    pub inertia: [f32; 6],
+}
+
+/// A visual or collision shape of a link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shape {
+    /// Pose of the shape, in the frame of the link.
+    pub origin: Transform,
+    pub geometry: Geometry,
+    pub color: Option<Color>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Geometry {
+    Box {
+        size: Vec3,
+    },
+    /// A cylinder along the Z axis.
+    Cylinder {
+        radius: f32,
+        length: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
+    Mesh {
+        filename: String,
+        scale: Vec3,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JointType {
+    Revolute,
+    Continuous,
+    Prismatic,
+    Fixed,
+    Floating,
+    Planar,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UrdfJoint {
+    pub name: String,
+    pub kind: JointType,
+    pub parent: String,
+    pub child: String,
+    /// Pose of the child link, in the frame of the parent link.
+    pub origin: Transform,
+    /// Axis of the joint, in the frame of the child link.
+    pub axis: Vec3,
+    /// Smallest and largest positions, in rad or m.
+    pub limits: Option<[f32; 2]>,
+    /// Largest torque or force of the joint, in N·m or N.
+    pub effort: Option<f32>,
+}
+
+impl UrdfJoint {
+    /// Whether the joint is driven by a setpoint.
+    pub fn is_actuated(&self) -> bool {
+        matches!(
+            self.kind,
+            JointType::Revolute | JointType::Continuous | JointType::Prismatic
+        )
+    }
+
+    /// Position of the joint, from the pose of the child link in the frame of the parent.
+    pub fn position(&self, relative: &Transform) -> f32 {
+        match self.kind {
+            JointType::Prismatic => (relative.translation - self.origin.translation)
+                .dot(self.origin.rotation * self.axis),
+            _ => {
+                // The twist of the rotation from the origin about the axis.
+                let rotation = self.origin.rotation.inverse() * relative.rotation;
+                2.0 * rotation.xyz().dot(self.axis).atan2(rotation.w)
+            }
+        }
+    }
+}
+
+impl Robot {
+    pub fn read(path: &Path) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        Self::parse(&xml)
+    }
+
+    /// Parses a URDF description, checking that its joints make a tree of its links.
+    pub fn parse(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml).map_err(invalid)?;
+        let root = document.root_element();
+        if !root.has_tag_name("robot") {
+            return Err(invalid("the root element isn't a robot"));
+        }
+        let materials: BTreeMap<&str, Color> = children(root, "material")
+            .filter_map(|material| Some((material.attribute("name")?, color(material)?)))
+            .collect();
+        let mut robot = Robot {
+            name: root.attribute("name").unwrap_or_default().to_string(),
+            links: Vec::new(),
+            joints: Vec::new(),
+        };
+        for link in children(root, "link") {
+            robot.links.push(UrdfLink {
+                name: attribute(link, "name")?.to_string(),
+                inertial: child(link, "inertial").map(inertial).transpose()?,
+                visuals: children(link, "visual")
+                    .map(|visual| shape(visual, &materials))
+                    .collect::<Result<_>>()?,
+                collisions: children(link, "collision")
+                    .map(|collision| shape(collision, &materials))
+                    .collect::<Result<_>>()?,
+            });
+        }
+        for joint in children(root, "joint") {
+            robot.joints.push(self::joint(joint)?);
+        }
+        robot.poses()?;
+        Ok(robot)
+    }
+
+    pub fn link(&self, name: &str) -> Option<&UrdfLink> {
+        self.links.iter().find(|link| link.name == name)
+    }
+
+    /// Poses of the links in the frame of the root link, where the joints place them.
+    pub fn poses(&self) -> Result<BTreeMap<String, Transform>> {
+        for joint in &self.joints {
+            for link in [&joint.parent, &joint.child] {
+                if self.link(link).is_none() {
+                    return Err(invalid(format!(
+                        "joint `{}` joins the unknown link `{link}`",
+                        joint.name
+                    )));
+                }
+            }
+        }
+        let roots: Vec<&UrdfLink> = self
+            .links
+            .iter()
+            .filter(|link| !self.joints.iter().any(|joint| joint.child == link.name))
+            .collect();
+        let [root] = roots.as_slice() else {
+            return Err(invalid("the joints don't make a tree of the links"));
+        };
+        let mut poses = BTreeMap::from([(root.name.clone(), Transform::IDENTITY)]);
+        let mut queue = VecDeque::from([root.name.as_str()]);
+        while let Some(parent) = queue.pop_front() {
+            let pose = poses[parent];
+            for joint in self.joints.iter().filter(|joint| joint.parent == parent) {
+                if poses
+                    .insert(joint.child.clone(), pose * joint.origin)
+                    .is_some()
+                {
+                    return Err(invalid(format!("link `{}` has two parents", joint.child)));
+                }
+                queue.push_back(&joint.child);
+            }
+        }
+        if poses.len() != self.links.len() {
+            return Err(invalid("the joints don't make a tree of the links"));
+        }
+        Ok(poses)
+    }
+
+    /// Signal of a joint, e.g. `arm/shoulder/velocity`.
+    pub fn signal(&self, joint: &str, name: &str) -> String {
+        plants::namespaced(&self.name, &format!("{joint}/{name}"))
+    }
+}
+
+/// Principal moments of an inertia tensor `[ixx, ixy, ixz, iyy, iyz, izz]`, and the rotation
+/// of their axes.
+pub fn principal_inertia(inertia: [f32; 6]) -> (Vec3, Quat) {
+    let [ixx, ixy, ixz, iyy, iyz, izz] = inertia;
+    let tensor = nalgebra::Matrix3::new(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz);
+    let eigen = tensor.symmetric_eigen();
+    let mut axes = eigen.eigenvectors;
+    // A rotation, not a reflection.
+    if axes.determinant() < 0.0 {
+        let flipped = -axes.column(0).into_owned();
+        axes.set_column(0, &flipped);
+    }
+    let column = |k: usize| Vec3::new(axes[(0, k)], axes[(1, k)], axes[(2, k)]);
+    let moments = Vec3::new(
+        eigen.eigenvalues[0],
+        eigen.eigenvalues[1],
+        eigen.eigenvalues[2],
+    );
+    (
+        moments,
+        Quat::from_mat3(&Mat3::from_cols(column(0), column(1), column(2))),
+    )
+}
+
+fn invalid(message: impl ToString) -> Error {
+    Error::Model(format!("URDF: {}", message.to_string()))
+}
+
+fn children<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    tag: &'static str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(tag))
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    tag: &'static str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    children(node, tag).next()
+}
+
+fn attribute<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str> {
+    node.attribute(name).ok_or_else(|| {
+        invalid(format!(
+            "`{}` has no `{name}` attribute",
+            node.tag_name().name()
+        ))
+    })
+}
+
+/// Numbers separated by spaces.
+fn numbers<const N: usize>(text: &str) -> Result<[f32; N]> {
+    let numbers: Vec<f32> = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| invalid(format!("`{text}` isn't a list of numbers")))?;
+    numbers
+        .try_into()
+        .map_err(|_| invalid(format!("`{text}` doesn't have {N} numbers")))
+}
+
+fn number(node: roxmltree::Node, name: &str) -> Result<f32> {
+    let [value] = numbers(attribute(node, name)?)?;
+    Ok(value)
+}
+
+fn vector(node: roxmltree::Node, name: &str, default: Vec3) -> Result<Vec3> {
+    node.attribute(name)
+        .map_or(Ok(default), |text| numbers(text).map(Vec3::from_array))
+}
+
+/// Pose of the `origin` element, fixed axis roll, pitch and yaw.
+fn origin(node: roxmltree::Node) -> Result<Transform> {
+    let Some(origin) = child(node, "origin") else {
+        return Ok(Transform::IDENTITY);
+    };
+    let [roll, pitch, yaw] = vector(origin, "rpy", Vec3::ZERO)?.to_array();
+    Ok(
+        Transform::from_translation(vector(origin, "xyz", Vec3::ZERO)?)
+            .with_rotation(Quat::from_euler(EulerRot::ZYX, yaw, pitch, roll)),
+    )
+}
+
+fn color(material: roxmltree::Node) -> Option<Color> {
+    let rgba: [f32; 4] = numbers(child(material, "color")?.attribute("rgba")?).ok()?;
+    Some(Color::srgba(rgba[0], rgba[1], rgba[2], rgba[3]))
+}
+
+fn inertial(node: roxmltree::Node) -> Result<Inertial> {
+    let mass = child(node, "mass").map_or(Ok(0.0), |mass| number(mass, "value"))?;
+    let inertia = match child(node, "inertia") {
+        Some(inertia) => {
+            let mut values = [0.0; 6];
+            for (value, name) in values
+                .iter_mut()
+                .zip(["ixx", "ixy", "ixz", "iyy", "iyz", "izz"])
+            {
+                *value = inertia
+                    .attribute(name)
+                    .map_or(Ok(0.0), |_| number(inertia, name))?;
+            }
+            values
+        }
+        None => [0.0; 6],
+    };
+    Ok(Inertial {
+        origin: origin(node)?,
+        mass,
+        inertia,
+    })
+}
+
+fn shape(node: roxmltree::Node, materials: &BTreeMap<&str, Color>) -> Result<Shape> {
+    let geometry = child(node, "geometry")
+        .and_then(|geometry| geometry.first_element_child())
+        .ok_or_else(|| invalid("a shape has no geometry"))?;
+    let geometry = match geometry.tag_name().name() {
+        "box" => Geometry::Box {
+            size: vector(geometry, "size", Vec3::ONE)?,
+        },
+        "cylinder" => Geometry::Cylinder {
+            radius: number(geometry, "radius")?,
+            length: number(geometry, "length")?,
+        },
+        "sphere" => Geometry::Sphere {
+            radius: number(geometry, "radius")?,
+        },
+        "mesh" => Geometry::Mesh {
+            filename: attribute(geometry, "filename")?.to_string(),
+            scale: vector(geometry, "scale", Vec3::ONE)?,
+        },
+        other => return Err(invalid(format!("unknown geometry `{other}`"))),
+    };
+    // A material is defined in place, or named after one of the robot.
+    let color = child(node, "material").and_then(|material| {
+        color(material).or_else(|| materials.get(material.attribute("name")?).copied())
+    });
+    Ok(Shape {
+        origin: origin(node)?,
+        geometry,
+        color,
+    })
+}
+
+fn joint(node: roxmltree::Node) -> Result<UrdfJoint> {
+    let name = attribute(node, "name")?.to_string();
+    let kind = match attribute(node, "type")? {
+        "revolute" => JointType::Revolute,
+        "continuous" => JointType::Continuous,
+        "prismatic" => JointType::Prismatic,
+        "fixed" => JointType::Fixed,
+        "floating" => JointType::Floating,
+        "planar" => JointType::Planar,
+        other => {
+            return Err(invalid(format!(
+                "joint `{name}` has the unknown type `{other}`"
+            )))
+        }
+    };
+    let link = |tag: &'static str| {
+        child(node, tag)
+            .ok_or_else(|| invalid(format!("joint `{name}` has no {tag}")))
+            .and_then(|link| attribute(link, "link"))
+            .map(str::to_string)
+    };
+    let limit = child(node, "limit");
+    let limits = match (kind, limit) {
+        (JointType::Revolute | JointType::Prismatic, Some(limit)) => Some([
+            limit
+                .attribute("lower")
+                .map_or(Ok(0.0), |_| number(limit, "lower"))?,
+            limit
+                .attribute("upper")
+                .map_or(Ok(0.0), |_| number(limit, "upper"))?,
+        ]),
+        _ => None,
+    };
+    let effort = match limit {
+        Some(limit) if limit.attribute("effort").is_some() => Some(number(limit, "effort")?),
+        _ => None,
+    };
+    Ok(UrdfJoint {
+        parent: link("parent")?,
+        child: link("child")?,
+        origin: origin(node)?,
+        axis: child(node, "axis")
+            .map_or(Ok(Vec3::X), |axis| vector(axis, "xyz", Vec3::X))?
+            .normalize_or(Vec3::X),
+        limits,
+        effort,
+        kind,
+        name,
+    })
+}
+
+/// Resolves the path of a mesh, relative to the directory of the URDF file. A `package://`
+/// path is looked up in the closest ancestor directory named after its package.
+pub fn mesh_path(directory: &Path, filename: &str) -> PathBuf {
+    if let Some(path) = filename.strip_prefix("package://") {
+        let (package, rest) = path.split_once('/').unwrap_or((path, ""));
+        return directory
+            .ancestors()
+            .find(|ancestor| ancestor.file_name().is_some_and(|name| name == package))
+            .unwrap_or(directory)
+            .join(rest);
+    }
+    directory.join(filename.strip_prefix("file://").unwrap_or(filename))
+}
+
+/// A joint moved by a setpoint, on the entity of its child link.
+#[derive(Component)]
+struct Actuator {
+    joint: UrdfJoint,
+    parent: Entity,
+    velocity: String,
+    position: String,
+}
+
+/// URDF cylinders lie along Z, Bevy and Rapier ones along Y.
+fn cylinder_rotation() -> Transform {
+    Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_2))
+}
+
+fn spawn_robot(
+    mut commands: Commands,
+    urdf: Res<UrdfRobot>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let robot = &urdf.robot;
+    let Ok(poses) = robot.poses() else {
+        return;
+    };
+    let mut entities = BTreeMap::new();
+    for link in &robot.links {
+        let is_root = !robot.joints.iter().any(|joint| joint.child == link.name);
+        let mut entity = commands.spawn((
+            poses[&link.name],
+            Visibility::default(),
+            if is_root {
+                RigidBody::Fixed
+            } else {
+                RigidBody::Dynamic
+            },
+            Link {
+                plant: robot.name.clone(),
+                name: link.name.clone(),
+            },
+            Name::new(link.name.clone()),
+        ));
+        if let Some(inertial) = &link.inertial {
+            let (moments, axes) = principal_inertia(inertial.inertia);
+            entity.insert(AdditionalMassProperties::MassProperties(MassProperties {
+                local_center_of_mass: inertial.origin.translation,
+                mass: inertial.mass,
+                principal_inertia_local_frame: inertial.origin.rotation * axes,
+                principal_inertia: moments,
+            }));
+        }
+        entity.with_children(|parent| {
+            for visual in &link.visuals {
+                let material = materials.add(visual.color.unwrap_or(Color::srgb(0.7, 0.7, 0.7)));
+                match &visual.geometry {
+                    Geometry::Box { size } => {
+                        parent.spawn((
+                            Mesh3d(meshes.add(Cuboid::from_size(*size))),
+                            MeshMaterial3d(material),
+                            visual.origin,
+                        ));
+                    }
+                    Geometry::Cylinder { radius, length } => {
+                        parent.spawn((
+                            Mesh3d(meshes.add(Cylinder::new(*radius, *length))),
+                            MeshMaterial3d(material),
+                            visual.origin * cylinder_rotation(),
+                        ));
+                    }
+                    Geometry::Sphere { radius } => {
+                        parent.spawn((
+                            Mesh3d(meshes.add(Sphere::new(*radius))),
+                            MeshMaterial3d(material),
+                            visual.origin,
+                        ));
+                    }
+                    Geometry::Mesh { filename, scale } => {
+                        let path = mesh_path(&urdf.directory, filename);
+                        let gltf = path
+                            .extension()
+                            .is_some_and(|extension| extension == "glb" || extension == "gltf");
+                        if gltf {
+                            parent.spawn((
+                                SceneRoot(
+                                    asset_server.load(GltfAssetLabel::Scene(0).from_asset(path)),
+                                ),
+                                visual.origin.with_scale(*scale),
+                            ));
+                        } else {
+                            warn!(
+                                target: subsystem::IO,
+                                "Mesh {} of link {} isn't a glTF file, it's not drawn",
+                                path.display(),
+                                link.name
+                            );
+                        }
+                    }
+                }
+            }
+            for collision in &link.collisions {
+                let (collider, transform) = match &collision.geometry {
+                    Geometry::Box { size } => (
+                        Collider::cuboid(size.x / 2.0, size.y / 2.0, size.z / 2.0),
+                        collision.origin,
+                    ),
+                    Geometry::Cylinder { radius, length } => (
+                        Collider::cylinder(length / 2.0, *radius),
+                        collision.origin * cylinder_rotation(),
+                    ),
+                    Geometry::Sphere { radius } => (Collider::ball(*radius), collision.origin),
+                    Geometry::Mesh { filename, .. } => {
+                        debug!(
+                            target: subsystem::PHYSICS,
+                            "Collision mesh {filename} of link {} left out",
+                            link.name
+                        );
+                        continue;
+                    }
+                };
+                parent.spawn((collider, transform));
+            }
+        });
+        entities.insert(link.name.as_str(), entity.id());
+    }
+
+    for joint in &robot.joints {
+        let (parent, child) = (
+            entities[joint.parent.as_str()],
+            entities[joint.child.as_str()],
+        );
+        let kind = match joint.kind {
+            JointType::Revolute | JointType::Continuous => JointKind::Revolute,
+            JointType::Prismatic => JointKind::Prismatic,
+            JointType::Fixed => JointKind::Fixed,
+            JointType::Floating => continue,
+            JointType::Planar => {
+                warn!(target: subsystem::PHYSICS, "Planar joint {} left free", joint.name);
+                continue;
+            }
+        };
+        let metadata = LinkMetadata {
+            joint: Some(kind),
+            axis: Some(joint.axis),
+            limits: joint.limits,
+            ..default()
+        };
+        let mut entity = commands.entity(child);
+        entity.insert(ImpulseJoint::new(parent, metadata.joint(joint.origin)));
+        if joint.is_actuated() {
+            entity.insert(Actuator {
+                joint: joint.clone(),
+                parent,
+                velocity: robot.signal(&joint.name, "velocity"),
+                position: robot.signal(&joint.name, "position"),
+            });
+        }
+    }
+    info!(
+        target: subsystem::PHYSICS,
+        "Spawned robot {} with {} links",
+        robot.name,
+        robot.links.len()
+    );
+}
+
+/// Applies the velocity setpoints to the motors of the joints.
+fn drive_joints(setpoints: Res<Setpoints>, mut actuators: Query<(&Actuator, &mut ImpulseJoint)>) {
+    for (actuator, mut joint) in &mut actuators {
+        let Some(velocity) = setpoints.get(&actuator.velocity) else {
+            continue;
+        };
+        let axis = match actuator.joint.kind {
+            JointType::Prismatic => JointAxis::LinX,
+            _ => JointAxis::AngX,
+        };
+        let data = joint.data.as_mut();
+        data.set_motor_velocity(axis, velocity, MOTOR_FACTOR);
+        if let Some(effort) = actuator.joint.effort {
+            data.set_motor_max_force(axis, effort);
+        }
+    }
+}
+
+/// Records the positions of the moving joints after each step of the simulation.
+fn record_joints(
+    clock: Res<SimClock>,
+    actuators: Query<(&Actuator, &GlobalTransform)>,
+    links: Query<&GlobalTransform, With<Link>>,
+    mut telemetry: ResMut<Telemetry>,
+) {
+    for (actuator, transform) in &actuators {
+        let Ok(parent) = links.get(actuator.parent) else {
+            continue;
+        };
+        let relative = transform.reparented_to(parent);
+        telemetry.record(
+            &actuator.position,
+            clock.elapsed_secs(),
+            actuator.joint.position(&relative),
+        );
+    }
+}
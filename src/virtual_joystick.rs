@@ -0,0 +1,180 @@
+//! Virtual joystick reporting signals of the simulation as its axes, through uinput.
+//!
+//! Tools reading HID devices (visualization, teleoperation or game-engine front ends) see a
+//! gamepad named "Motion Control Playground" whose axes follow the signals of the
+//! `joystick.json` configuration file. A signal spans the full travel of its axis over
+//! `[-range, range]` and saturates beyond. Axes are updated after every physics step.
+//!
+//! Creating the device requires write access to `/dev/uinput`, e.g. through the `input` group
+//! or a udev rule.
+use std::{io, path::Path, time::Duration};
+
+use bevy::prelude::*;
+use evdev::{
+    uinput::VirtualDevice, AbsInfo, AbsoluteAxisCode, AbsoluteAxisEvent, AttributeSet, BusType,
+    InputEvent, InputId, KeyCode, UinputAbsSetup,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, PENDULUM_ANGLE},
+};
+
+/// Position of an axis at either end of its travel.
+pub const AXIS_MAX: i32 = 32_767;
+/// Name of the device.
+pub const DEVICE_NAME: &str = "Motion Control Playground";
+
+/// Absolute axis of the device.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    Rx,
+    Ry,
+    Rz,
+}
+
+impl Axis {
+    fn code(self) -> AbsoluteAxisCode {
+        match self {
+            Axis::X => AbsoluteAxisCode::ABS_X,
+            Axis::Y => AbsoluteAxisCode::ABS_Y,
+            Axis::Z => AbsoluteAxisCode::ABS_Z,
+            Axis::Rx => AbsoluteAxisCode::ABS_RX,
+            Axis::Ry => AbsoluteAxisCode::ABS_RY,
+            Axis::Rz => AbsoluteAxisCode::ABS_RZ,
+        }
+    }
+}
+
+/// A signal reported on an axis.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AxisBinding {
+    pub axis: Axis,
+    /// Name of the telemetry channel, or of the setpoint.
+    pub signal: String,
+    /// Value of the signal at the end of the travel.
+    pub range: f32,
+}
+
+impl AxisBinding {
+    /// Position of the axis for a value of the signal.
+    pub fn position(&self, value: f32) -> i32 {
+        if self.range <= 0.0 || !value.is_finite() {
+            return 0;
+        }
+        let ratio = (value / self.range).clamp(-1.0, 1.0);
+        (ratio * AXIS_MAX as f32).round() as i32
+    }
+}
+
+/// The axes of the device, from the `joystick.json` configuration file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub struct JoystickMap {
+    pub axes: Vec<AxisBinding>,
+}
+
+impl Default for JoystickMap {
+    fn default() -> Self {
+        Self {
+            axes: vec![
+                AxisBinding {
+                    axis: Axis::X,
+                    signal: PENDULUM_ANGLE.to_string(),
+                    range: std::f32::consts::PI,
+                },
+                AxisBinding {
+                    axis: Axis::Y,
+                    signal: MOTOR_VELOCITY.to_string(),
+                    range: 10.0,
+                },
+            ],
+        }
+    }
+}
+
+/// Creates the virtual joystick and keeps its axes up to date.
+pub struct VirtualJoystickPlugin {
+    pub map: JoystickMap,
+}
+
+impl Plugin for VirtualJoystickPlugin {
+    fn build(&self, app: &mut App) {
+        let device = match create_device(&self.map) {
+            Ok(device) => device,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(Path::new("/dev/uinput"), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "Virtual joystick \"{DEVICE_NAME}\" created");
+        app.insert_resource(self.map.clone())
+            .insert_resource(VirtualJoystick {
+                device,
+                positions: vec![None; self.map.axes.len()],
+            })
+            .add_systems(PostUpdate, update_axes.after(SimClockSet::Advance));
+    }
+}
+
+fn create_device(map: &JoystickMap) -> io::Result<VirtualDevice> {
+    // Without a button, most tools classify the device as an accelerometer, not a joystick.
+    let mut buttons = AttributeSet::<KeyCode>::new();
+    buttons.insert(KeyCode::BTN_SOUTH);
+    let mut builder = VirtualDevice::builder()?
+        .name(DEVICE_NAME)
+        .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 1))
+        .with_keys(&buttons)?;
+    for binding in &map.axes {
+        let info = AbsInfo::new(0, -AXIS_MAX, AXIS_MAX, 0, 0, 0);
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(binding.axis.code(), info))?;
+    }
+    builder.build()
+}
+
+#[derive(Resource)]
+struct VirtualJoystick {
+    device: VirtualDevice,
+    /// Positions last reported, by axis binding.
+    positions: Vec<Option<i32>>,
+}
+
+fn update_axes(
+    mut joystick: ResMut<VirtualJoystick>,
+    map: Res<JoystickMap>,
+    clock: Res<SimClock>,
+    setpoints: Option<Res<Setpoints>>,
+    telemetry: Option<Res<Telemetry>>,
+) {
+    if clock.delta() == Duration::ZERO {
+        return;
+    }
+    let mut events = Vec::<InputEvent>::new();
+    for (index, binding) in map.axes.iter().enumerate() {
+        let value = telemetry
+            .as_ref()
+            .and_then(|telemetry| telemetry.latest(&binding.signal))
+            .or_else(|| setpoints.as_ref()?.get(&binding.signal))
+            .unwrap_or_default();
+        let position = binding.position(value);
+        // Only changes are reported, as a physical device would.
+        if joystick.positions[index] != Some(position) {
+            joystick.positions[index] = Some(position);
+            events.push(*AbsoluteAxisEvent::new(binding.axis.code(), position));
+        }
+    }
+    if events.is_empty() {
+        return;
+    }
+    if let Err(error) = joystick.device.emit(&events) {
+        warn!(target: subsystem::IO, "Failed to update the virtual joystick: {error}");
+    }
+}
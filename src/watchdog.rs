@@ -0,0 +1,271 @@
+//! This module supervises the external controllers driving the plant, like the watchdog of a
+//! real drive: the operators of a session, the peer of the UDP packets, the Modbus clients and
+//! the firmware of the serial bridge.
+//!
+//! Every message of a controller feeds the watchdog. Once it has fed it, a controller must keep
+//! sending within the timeout of `watchdog.json`; when it goes silent, the watchdog trips: the
+//! safe setpoints are applied (the motor stops, by default), the event is logged and further
+//! commands of that controller are refused until the watchdog is reset from the *Watchdog*
+//! panel, or, with `auto_reset`, until the controller comes back.
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    config_plugin,
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+pub struct WatchdogPlugin;
+
+impl Plugin for WatchdogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                PreUpdate,
+                (
+                    sync_settings.run_if(resource_changed::<Persistent<WatchdogSettings>>),
+                    supervise,
+                )
+                    .chain()
+                    .run_if(resource_exists::<Watchdog>),
+            )
+            .add_systems(
+                Update,
+                watchdog_panel
+                    .run_if(resource_exists::<Persistent<WatchdogSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// Value a setpoint takes when the watchdog trips.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SafeSetpoint {
+    pub signal: String,
+    pub value: f32,
+}
+
+/// Represents the configuration of the watchdog.
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct WatchdogSettings {
+    pub enabled: bool,
+    /// Longest silence of a controller, in seconds.
+    pub timeout: f32,
+    /// Whether a controller coming back after a trip clears it.
+    pub auto_reset: bool,
+    /// Setpoints applied when the watchdog trips.
+    pub reaction: Vec<SafeSetpoint>,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout: 1.0,
+            auto_reset: false,
+            reaction: vec![SafeSetpoint {
+                signal: MOTOR_VELOCITY.to_string(),
+                value: 0.0,
+            }],
+        }
+    }
+}
+
+impl WatchdogSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f32(self.timeout.max(0.0))
+    }
+}
+
+/// A trip of the watchdog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trip {
+    /// Controller that went silent.
+    pub controller: String,
+    pub at: Instant,
+}
+
+/// The controllers supervised and the trip of the watchdog, if any.
+#[derive(Debug, Resource)]
+pub struct Watchdog {
+    pub settings: WatchdogSettings,
+    /// Time of the last message of each controller.
+    controllers: BTreeMap<String, Instant>,
+    trip: Option<Trip>,
+}
+
+impl Watchdog {
+    pub fn new(settings: WatchdogSettings) -> Self {
+        Self {
+            settings,
+            controllers: BTreeMap::new(),
+            trip: None,
+        }
+    }
+
+    /// Records a message of `controller` at `now`, and returns whether its commands are
+    /// accepted, i.e. the watchdog isn't tripped.
+    pub fn feed(&mut self, controller: &str, now: Instant) -> bool {
+        if self.settings.auto_reset
+            && self
+                .trip
+                .as_ref()
+                .is_some_and(|trip| trip.controller == controller)
+        {
+            info!(target: subsystem::CONTROL, "{controller} is back, resetting the watchdog");
+            self.trip = None;
+        }
+        match self.controllers.get_mut(controller) {
+            Some(last) => *last = now,
+            None => {
+                debug!(target: subsystem::CONTROL, "Watching {controller}");
+                self.controllers.insert(controller.to_string(), now);
+            }
+        }
+        !self.settings.enabled || self.trip.is_none()
+    }
+
+    /// Trips the watchdog if a controller has been silent for longer than the timeout at `now`,
+    /// returning the trip. The controller is no longer supervised until it comes back.
+    pub fn check(&mut self, now: Instant) -> Option<Trip> {
+        if !self.settings.enabled || self.trip.is_some() {
+            return None;
+        }
+        let timeout = self.settings.timeout();
+        let (controller, _) = self
+            .controllers
+            .iter()
+            .find(|(_, last)| now.saturating_duration_since(**last) > timeout)?;
+        let controller = controller.clone();
+        self.controllers.remove(&controller);
+        let trip = Trip {
+            controller,
+            at: now,
+        };
+        self.trip = Some(trip.clone());
+        Some(trip)
+    }
+
+    pub fn trip(&self) -> Option<&Trip> {
+        self.trip.as_ref()
+    }
+
+    /// Clears the trip, accepting the commands of the controllers again.
+    pub fn reset(&mut self) {
+        self.trip = None;
+    }
+
+    /// The controllers supervised, with the time of their last message.
+    pub fn controllers(&self) -> impl Iterator<Item = (&str, Instant)> {
+        self.controllers
+            .iter()
+            .map(|(controller, last)| (controller.as_str(), *last))
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<WatchdogSettings>("watchdog", true);
+    commands.insert_resource(Watchdog::new(settings.get().clone()));
+    commands.insert_resource(settings);
+    if let Some(error) = error {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+fn sync_settings(settings: Res<Persistent<WatchdogSettings>>, mut watchdog: ResMut<Watchdog>) {
+    watchdog.settings = settings.get().clone();
+}
+
+/// Applies the safe setpoints when a controller goes silent.
+fn supervise(mut watchdog: ResMut<Watchdog>, mut setpoints: ResMut<Setpoints>) {
+    let Some(trip) = watchdog.check(Instant::now()) else {
+        return;
+    };
+    error!(
+        target: subsystem::CONTROL,
+        "Watchdog tripped: no message from {} for {} s",
+        trip.controller,
+        watchdog.settings.timeout
+    );
+    for safe in &watchdog.settings.reaction {
+        setpoints.set(&safe.signal, safe.value);
+    }
+}
+
+/// Panel showing the controllers supervised and the trip.
+fn watchdog_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<WatchdogSettings>>,
+    mut watchdog: ResMut<Watchdog>,
+) {
+    let mut edited = settings.get().clone();
+
+    egui::Window::new("Watchdog")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut edited.enabled, "Enabled");
+            ui.add(egui::Slider::new(&mut edited.timeout, 0.01..=10.0).text("Timeout (s)"));
+            ui.checkbox(
+                &mut edited.auto_reset,
+                "Reset when the controller comes back",
+            );
+
+            ui.separator();
+            let now = Instant::now();
+            if watchdog.controllers().next().is_none() {
+                ui.label("No controller connected.");
+            }
+            for (controller, last) in watchdog.controllers() {
+                ui.label(format!(
+                    "{controller}: {:.0} ms ago",
+                    now.saturating_duration_since(last).as_secs_f32() * 1000.0
+                ));
+            }
+            if let Some(trip) = watchdog.trip().cloned() {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!(
+                        "Tripped by {}, {:.1} s ago",
+                        trip.controller,
+                        now.saturating_duration_since(trip.at).as_secs_f32()
+                    ),
+                );
+                if ui.button("Reset").clicked() {
+                    info!(target: subsystem::CONTROL, "Watchdog reset");
+                    watchdog.reset();
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("watchdog", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("watchdog", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+}
@@ -0,0 +1,581 @@
+//! Waveforms read from files and played back as references or disturbances, e.g. to replay the
+//! disturbance profile measured on the bench.
+//!
+//! A waveform is read from a CSV file, a time column and a value column, or from a WAV file,
+//! one of its channels on the time of its samples. It's resampled to the rate of the control,
+//! averaging the samples within each period when the file is denser and interpolating between
+//! them when it's sparser, then scaled and offset. While enabled, it's played from its start
+//! time on the simulated clock, holding each sample for a period, and looped if configured: as
+//! a setpoint, e.g. the reference of a controller, or as a force or a torque on a link, applied
+//! as an impulse per step. The played value is recorded as the `waveform/<name>` telemetry
+//! channel.
+//!
+//! The waveforms are configured in `waveforms.json` and read at start, or again from the
+//! *Waveforms* panel.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility_plugin::has_ui,
+    clock::SimClock,
+    config_plugin,
+    error::{Error, ErrorEvent, Result},
+    headless::DEFAULT_TIME_STEP,
+    logging::subsystem,
+    plants::Link,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+
+/// Prefix of the telemetry channels of the played waveforms.
+pub const WAVEFORM_PREFIX: &str = "waveform/";
+
+pub struct WaveformsPlugin;
+
+impl Plugin for WaveformsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Waveforms>()
+            .init_resource::<Setpoints>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                play_waveforms.run_if(resource_exists::<Persistent<WaveformSettings>>),
+            )
+            .add_systems(
+                Update,
+                waveforms_panel
+                    .run_if(resource_exists::<Persistent<WaveformSettings>>)
+                    .run_if(has_ui),
+            );
+    }
+}
+
+/// What a waveform drives.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveformTarget {
+    /// A setpoint, e.g. the reference of a controller.
+    #[default]
+    Setpoint,
+    /// A force on a link, in N.
+    Force,
+    /// A torque on a link, in N·m.
+    Torque,
+}
+
+impl WaveformTarget {
+    pub const ALL: [Self; 3] = [Self::Setpoint, Self::Force, Self::Torque];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Setpoint => "Setpoint",
+            Self::Force => "Force",
+            Self::Torque => "Torque",
+        }
+    }
+}
+
+/// Represents a waveform, where it's read from and what it drives.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct WaveformSource {
+    /// Name of the telemetry channel of the played values, after `waveform/`.
+    pub name: String,
+    /// CSV or WAV file, told apart by its extension.
+    pub path: PathBuf,
+    /// Columns of a CSV file, and the seconds per unit of its time column.
+    pub time_column: String,
+    pub time_unit: f32,
+    pub column: String,
+    pub delimiter: char,
+    /// Channel of a WAV file, from 0.
+    pub channel: usize,
+    /// Samples per second of the played waveform, that of the control.
+    pub rate: f32,
+    /// Applied to the values of the file, `scale * value + offset`.
+    pub scale: f32,
+    pub offset: f32,
+    pub target: WaveformTarget,
+    /// Setpoint set by the waveform.
+    pub setpoint: String,
+    /// Path of the link pushed by the force or the torque.
+    pub link: String,
+    /// Direction of the force, or axis of the torque, in the frame of the link.
+    pub direction: Vec3,
+    /// Time of the run the waveform starts at, in s.
+    pub start: f32,
+    /// Whether the waveform starts over once played.
+    pub looped: bool,
+    pub enabled: bool,
+}
+
+impl Default for WaveformSource {
+    fn default() -> Self {
+        Self {
+            name: "reference".to_string(),
+            path: PathBuf::from("waveform.csv"),
+            time_column: "time".to_string(),
+            time_unit: 1.0,
+            column: "value".to_string(),
+            delimiter: ',',
+            channel: 0,
+            rate: 1.0 / DEFAULT_TIME_STEP,
+            scale: 1.0,
+            offset: 0.0,
+            target: WaveformTarget::Setpoint,
+            setpoint: MOTOR_VELOCITY.to_string(),
+            link: "pendulum".to_string(),
+            direction: Vec3::X,
+            start: 0.0,
+            looped: false,
+            enabled: true,
+        }
+    }
+}
+
+/// Represents the configuration of the waveforms.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct WaveformSettings {
+    pub waveforms: Vec<WaveformSource>,
+}
+
+/// Samples evenly spaced in time, from 0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Waveform {
+    /// Samples per second.
+    pub rate: f32,
+    pub samples: Vec<f32>,
+}
+
+impl Waveform {
+    /// The waveform of the `points`, `[time, value]` sorted by time, at `rate`: the mean of the
+    /// points within each period around a sample, or the interpolation between the points
+    /// around it when there's none.
+    pub fn from_points(points: &[[f32; 2]], rate: f32) -> Self {
+        let Some(&[end, _]) = points.last() else {
+            return Self {
+                rate,
+                samples: Vec::new(),
+            };
+        };
+        let period = 1.0 / rate;
+        let count = (end * rate).floor() as usize + 1;
+        let mut samples = Vec::with_capacity(count);
+        let mut first = 0;
+        for index in 0..count {
+            let time = index as f32 * period;
+            let (from, to) = (time - period / 2.0, time + period / 2.0);
+            while first < points.len() && points[first][0] < from {
+                first += 1;
+            }
+            let within = points[first..]
+                .iter()
+                .take_while(|[t, _]| *t < to)
+                .map(|[_, value]| *value);
+            let (sum, n) = within.fold((0.0, 0), |(sum, n), value| (sum + value, n + 1));
+            let sample = if n > 0 {
+                sum / n as f32
+            } else {
+                match (points.get(first.wrapping_sub(1)), points.get(first)) {
+                    (Some([t0, v0]), Some([t1, v1])) if t1 > t0 => {
+                        v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+                    }
+                    (_, Some([_, value])) | (Some([_, value]), None) => *value,
+                    (None, None) => 0.0,
+                }
+            };
+            samples.push(sample);
+        }
+        Self { rate, samples }
+    }
+
+    /// The waveform at another `rate`.
+    pub fn resample(&self, rate: f32) -> Self {
+        let points: Vec<[f32; 2]> = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(index, value)| [index as f32 / self.rate, *value])
+            .collect();
+        Self::from_points(&points, rate)
+    }
+
+    /// Duration of the waveform, each sample held for a period, in s.
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.rate
+    }
+
+    /// Sample held at `time`, starting over at the end when `looped`, or none outside of the
+    /// waveform.
+    pub fn at(&self, time: f32, looped: bool) -> Option<f32> {
+        if time < 0.0 || self.samples.is_empty() {
+            return None;
+        }
+        let time = if looped {
+            time.rem_euclid(self.duration())
+        } else {
+            time
+        };
+        // Rounded, against the time accumulated step by step falling short of a sample.
+        let index = (time * self.rate + 1e-3).floor() as usize;
+        self.samples.get(index).copied()
+    }
+}
+
+fn invalid(path: &Path, message: String) -> Error {
+    Error::io(path, io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Parses the `[time, value]` points of the columns of `source` in the CSV `text`, on the time
+/// since its first row. Rows without a value are skipped.
+pub fn parse_csv(text: &str, source: &WaveformSource, path: &Path) -> Result<Vec<[f32; 2]>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid(path, "the file is empty".to_string()))?;
+    let header: Vec<&str> = header
+        .split(source.delimiter)
+        .map(|name| name.trim().trim_matches('"'))
+        .collect();
+    let index = |column: &str| {
+        header
+            .iter()
+            .position(|name| *name == column)
+            .ok_or_else(|| invalid(path, format!("the file has no `{column}` column")))
+    };
+    let (time, column) = (index(&source.time_column)?, index(&source.column)?);
+    let mut points: Vec<[f32; 2]> = Vec::new();
+    let mut start = None;
+    for (row, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(source.delimiter).map(str::trim).collect();
+        let Some(Ok(seconds)) = fields.get(time).map(|field| field.parse::<f64>()) else {
+            return Err(invalid(path, format!("row {} has no time", row + 2)));
+        };
+        let seconds = seconds * f64::from(source.time_unit);
+        let start = *start.get_or_insert(seconds);
+        let Some(Ok(value)) = fields.get(column).map(|field| field.parse::<f32>()) else {
+            continue;
+        };
+        let time = (seconds - start) as f32;
+        if points.last().is_some_and(|[last, _]| time < *last) {
+            return Err(invalid(path, format!("row {} goes back in time", row + 2)));
+        }
+        points.push([time, value]);
+    }
+    Ok(points)
+}
+
+/// Parses the `channel` of the WAV `bytes`, integer samples scaled to [-1, 1].
+pub fn parse_wav(bytes: &[u8], channel: usize, path: &Path) -> Result<Waveform> {
+    let invalid = |message: &str| invalid(path, message.to_string());
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at =
+        |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let size = u32_at(at + 4) as usize;
+        let body = at + 8..(at + 8 + size).min(bytes.len());
+        match &bytes[at..at + 4] {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16_at(body.start);
+                // WAVE_FORMAT_EXTENSIBLE, whose subformat starts with the tag.
+                if tag == 0xFFFE && body.len() >= 26 {
+                    tag = u16_at(body.start + 24);
+                }
+                format = Some((
+                    tag,
+                    u16_at(body.start + 2) as usize,
+                    u32_at(body.start + 4),
+                    u16_at(body.start + 14),
+                ));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        at += 8 + size + size % 2;
+    }
+    let (Some((tag, channels, rate, bits)), Some(data)) = (format, data) else {
+        return Err(invalid("no format or no data in the WAV file"));
+    };
+    if channel >= channels {
+        return Err(invalid(&format!("no channel {channel} among {channels}")));
+    }
+    let width = usize::from(bits / 8);
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |sample| (f32::from(sample[0]) - 128.0) / 128.0,
+        (1, 16) => |sample| f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0,
+        (1, 24) => |sample| {
+            (i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8) as f32 / 8388608.0
+        },
+        (1, 32) => |sample| {
+            i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f32 / 2147483648.0
+        },
+        (3, 32) => |sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]),
+        (3, 64) => |sample| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&sample[..8]);
+            f64::from_le_bytes(bytes) as f32
+        },
+        _ => {
+            return Err(invalid(&format!(
+                "unsupported WAV samples: format {tag}, {bits} bits"
+            )))
+        }
+    };
+    let samples = bytes[data]
+        .chunks_exact(width * channels)
+        .map(|frame| decode(&frame[channel * width..]))
+        .collect();
+    Ok(Waveform {
+        rate: rate as f32,
+        samples,
+    })
+}
+
+/// Reads the waveform of `source`, at its rate, scaled and offset.
+pub fn read(source: &WaveformSource) -> Result<Waveform> {
+    let path = &source.path;
+    if source.rate <= 0.0 {
+        return Err(invalid(path, "the rate must be positive".to_string()));
+    }
+    let is_wav = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+    let waveform = if is_wav {
+        let bytes = fs::read(path).map_err(|error| Error::io(path, error))?;
+        parse_wav(&bytes, source.channel, path)?.resample(source.rate)
+    } else {
+        let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        Waveform::from_points(&parse_csv(&text, source, path)?, source.rate)
+    };
+    Ok(Waveform {
+        samples: waveform
+            .samples
+            .iter()
+            .map(|value| source.scale * value + source.offset)
+            .collect(),
+        ..waveform
+    })
+}
+
+/// The waveforms read, for each source of the settings, in order.
+#[derive(Debug, Default, Resource)]
+pub struct Waveforms(pub Vec<Option<Waveform>>);
+
+impl Waveforms {
+    /// Reads the waveforms of `settings`, returning the errors of those that can't be read.
+    pub fn read(settings: &WaveformSettings) -> (Self, Vec<Error>) {
+        let mut errors = Vec::new();
+        let waveforms = settings
+            .waveforms
+            .iter()
+            .map(|source| read(source).map_err(|error| errors.push(error)).ok())
+            .collect();
+        (Self(waveforms), errors)
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let (settings, error) = config_plugin::load_config::<WaveformSettings>("waveforms", true);
+    let (waveforms, errors) = Waveforms::read(&settings);
+    commands.insert_resource(waveforms);
+    commands.insert_resource(settings);
+    for error in error.into_iter().chain(errors) {
+        commands.send_event(ErrorEvent::from(error));
+    }
+}
+
+/// Plays the enabled waveforms at the time of the run.
+fn play_waveforms(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    settings: Res<Persistent<WaveformSettings>>,
+    waveforms: Res<Waveforms>,
+    mut setpoints: ResMut<Setpoints>,
+    mut links: Query<(Entity, &Link, &Transform, Option<&mut ExternalImpulse>)>,
+    mut telemetry: Option<ResMut<Telemetry>>,
+) {
+    let _span = info_span!(target: subsystem::CONTROL, "play_waveforms").entered();
+    let time = clock.elapsed_secs();
+    let dt = clock.delta_secs();
+    for (source, waveform) in settings.waveforms.iter().zip(&waveforms.0) {
+        let Some(waveform) = waveform.as_ref().filter(|_| source.enabled) else {
+            continue;
+        };
+        let Some(value) = waveform.at(time - source.start, source.looped) else {
+            continue;
+        };
+        if let Some(telemetry) = telemetry.as_mut() {
+            telemetry.record(&format!("{WAVEFORM_PREFIX}{}", source.name), time, value);
+        }
+        if source.target == WaveformTarget::Setpoint {
+            if setpoints.get(&source.setpoint) != Some(value) {
+                setpoints.set(&source.setpoint, value);
+            }
+            continue;
+        }
+        if dt == 0.0 {
+            continue;
+        }
+        let Some((entity, _, transform, impulse)) = links
+            .iter_mut()
+            .find(|(_, link, _, _)| link.path() == source.link)
+        else {
+            continue;
+        };
+        let direction = transform.rotation * source.direction.normalize_or(Vec3::X);
+        let disturbance = match source.target {
+            WaveformTarget::Force => ExternalImpulse {
+                impulse: value * direction * dt,
+                torque_impulse: Vec3::ZERO,
+            },
+            _ => ExternalImpulse {
+                impulse: Vec3::ZERO,
+                torque_impulse: value * direction * dt,
+            },
+        };
+        match impulse {
+            Some(mut impulse) => {
+                impulse.impulse += disturbance.impulse;
+                impulse.torque_impulse += disturbance.torque_impulse;
+            }
+            None => {
+                commands.entity(entity).insert(disturbance);
+            }
+        }
+    }
+}
+
+/// Panel to configure the waveforms and read them again.
+fn waveforms_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Persistent<WaveformSettings>>,
+    mut waveforms: ResMut<Waveforms>,
+) {
+    let mut edited = settings.get().clone();
+    let mut reload = false;
+    egui::Window::new("Waveforms")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut removed = None;
+            for (index, source) in edited.waveforms.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut source.enabled, "");
+                    ui.text_edit_singleline(&mut source.name);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                let mut path = source.path.display().to_string();
+                ui.horizontal(|ui| {
+                    ui.label("File");
+                    if ui.text_edit_singleline(&mut path).changed() {
+                        source.path = PathBuf::from(&path);
+                    }
+                });
+                match waveforms.0.get(index) {
+                    Some(Some(waveform)) => {
+                        ui.label(format!(
+                            "{} samples, {:.2} s",
+                            waveform.samples.len(),
+                            waveform.duration()
+                        ));
+                    }
+                    _ => {
+                        ui.label("Not read");
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Columns");
+                    ui.text_edit_singleline(&mut source.time_column);
+                    ui.text_edit_singleline(&mut source.column);
+                })
+                .response
+                .on_hover_text("Time and value columns of a CSV file");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut source.rate)
+                            .range(1.0..=100_000.0)
+                            .prefix("Rate: ")
+                            .suffix(" Hz"),
+                    );
+                    ui.add(egui::DragValue::new(&mut source.scale).prefix("× "));
+                    ui.add(egui::DragValue::new(&mut source.offset).prefix("+ "));
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt(("waveform_target", index))
+                        .selected_text(source.target.name())
+                        .show_ui(ui, |ui| {
+                            for target in WaveformTarget::ALL {
+                                ui.selectable_value(&mut source.target, target, target.name());
+                            }
+                        });
+                    match source.target {
+                        WaveformTarget::Setpoint => ui.text_edit_singleline(&mut source.setpoint),
+                        _ => ui.text_edit_singleline(&mut source.link),
+                    };
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut source.start)
+                            .speed(0.1)
+                            .prefix("Start: ")
+                            .suffix(" s"),
+                    );
+                    ui.checkbox(&mut source.looped, "Loop");
+                });
+                ui.separator();
+            }
+            if let Some(index) = removed {
+                edited.waveforms.remove(index);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add a waveform").clicked() {
+                    edited.waveforms.push(WaveformSource::default());
+                }
+                reload = ui.button("Read the files").clicked();
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    if let Err(error) = settings.persist() {
+                        commands.send_event(ErrorEvent::from(Error::save("waveforms", error)));
+                    }
+                }
+                if ui.button("Revert to defaults").clicked() {
+                    if let Err(error) = settings.revert_to_default() {
+                        commands.send_event(ErrorEvent::from(Error::save("waveforms", error)));
+                    }
+                    edited = settings.get().clone();
+                }
+            });
+        });
+
+    if edited != *settings.get() {
+        *settings.get_mut() = edited;
+    }
+    if reload {
+        let (read, errors) = Waveforms::read(settings.get());
+        *waveforms = read;
+        for error in errors {
+            commands.send_event(ErrorEvent::from(error));
+        }
+    }
+}
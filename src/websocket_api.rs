@@ -0,0 +1,414 @@
+//! WebSocket API to drive the simulation from scripts and notebooks, or to feed external
+//! dashboards.
+//!
+//! An instance started with `--websocket-api` accepts WebSocket clients on its address, on any
+//! path, and streams them the telemetry. Messages are JSON text frames:
+//! - client to instance: [`ApiCommand`]s, e.g. `{"type":"subscribe","channels":["motor/angle"],
+//!   "rate":50}`, `{"type":"setpoint","name":"motor/velocity","value":5.0}`,
+//!   `{"type":"pid_gains","kp":8.0}`, `{"type":"lqr_weights","r":0.5}`, `{"type":"pause"}`,
+//!   `{"type":"resume"}`, `{"type":"reset"}`, `{"type":"disturb","injection":{...}}` and
+//!   `{"type":"heartbeat"}`;
+//! - instance to client: `{"type":"telemetry","time":...,"running":...,"setpoints":{...},
+//!   "channels":{"motor/angle":[[time,value],...],...}}` at the rate of the client, with every
+//!   sample of its channels recorded since the previous one, and `{"type":"error",
+//!   "message":"..."}` when a command can't be applied.
+//!
+//! The telemetry starts with the samples recorded after the client connected, at the rate given
+//! by `--websocket-rate` until the client subscribes with its own. Gain changes apply to the
+//! running controllers like the edits of their panels, without saving them. The server is the
+//! WebSocket stack of the collaborative sessions, on threads of its own: the browser build can't
+//! serve it, but a page can connect to a native instance.
+//!
+//! A client driving the setpoints is an external controller: its setpoints and heartbeats feed
+//! the [watchdog](crate::watchdog), and its setpoints are refused while the watchdog is tripped.
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, time::Real};
+use bevy_persistent::Persistent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{SimClock, SimClockSet},
+    disturbances::{Inject, Injection},
+    error::{Error, ErrorEvent},
+    logging::subsystem,
+    lqr::LqrSettings,
+    pid_controller::PidSettings,
+    remote::{self, SocketResult},
+    setpoints::Setpoints,
+    snapshots::{self, Snapshots},
+    telemetry::{Sample, Telemetry},
+    watchdog::Watchdog,
+};
+
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 5713;
+/// Rate of the telemetry when none is given, in Hz.
+pub const DEFAULT_RATE: f32 = 20.0;
+/// Highest rate of the telemetry, in Hz.
+pub const MAX_RATE: f32 = 1000.0;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiCommand {
+    /// Selects the channels streamed to the client, all when empty, and their rate, in Hz.
+    Subscribe {
+        #[serde(default)]
+        channels: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rate: Option<f32>,
+    },
+    Setpoint {
+        name: String,
+        value: f32,
+    },
+    /// Gains of the position loop, those left out unchanged.
+    PidGains {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kp: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ki: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kd: Option<f32>,
+    },
+    /// Weights of the state and of the input of the regulator, those left out unchanged.
+    LqrWeights {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        q: Option<[f64; 4]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        r: Option<f64>,
+    },
+    Pause,
+    Resume,
+    /// Resets the scene to its state when it was spawned.
+    Reset,
+    /// Injects the configured disturbance, or `injection`, into its link.
+    Disturb {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        injection: Option<Injection>,
+    },
+    /// Keeps the watchdog fed without changing a setpoint.
+    Heartbeat,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiMessage {
+    Telemetry {
+        /// Simulated time, in seconds.
+        time: f32,
+        running: bool,
+        setpoints: BTreeMap<String, f32>,
+        channels: BTreeMap<String, Vec<Sample>>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Serves the WebSocket API on the given address.
+pub struct WebSocketApiPlugin {
+    pub bind: SocketAddr,
+    /// Rate of the telemetry of the clients that don't subscribe with their own, in Hz.
+    pub rate: f32,
+}
+
+impl Plugin for WebSocketApiPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.bind) {
+            Ok(listener) => listener,
+            Err(error) => {
+                app.world_mut()
+                    .send_event(ErrorEvent::from(Error::io(self.bind.to_string(), error)));
+                return;
+            }
+        };
+        info!(target: subsystem::IO, "Serving the WebSocket API on ws://{}/", self.bind);
+        let (events, receiver) = mpsc::channel();
+        thread::spawn(move || accept_clients(listener, events));
+
+        app.init_resource::<Setpoints>()
+            .init_resource::<Telemetry>()
+            .insert_resource(WebSocketApi {
+                events: Mutex::new(receiver),
+                clients: BTreeMap::new(),
+                interval: interval(self.rate),
+            })
+            .add_systems(Update, api_receive)
+            .add_systems(PostUpdate, api_broadcast.after(SimClockSet::Advance));
+    }
+}
+
+/// Time between two messages at `rate` Hz.
+fn interval(rate: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / rate.clamp(f32::EPSILON, MAX_RATE))
+}
+
+enum ApiEvent {
+    Connected {
+        id: u64,
+        address: SocketAddr,
+        outgoing: mpsc::Sender<String>,
+    },
+    Command(u64, ApiCommand),
+    Disconnected(u64),
+}
+
+fn accept_clients(listener: TcpListener, events: mpsc::Sender<ApiEvent>) {
+    for (id, stream) in (0..).zip(listener.incoming()) {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let events = events.clone();
+        thread::spawn(move || {
+            let address = stream.peer_addr().ok();
+            if let Err(error) = handle_client(id, stream, &events) {
+                debug!(target: subsystem::IO, "API client {address:?}: {error}");
+            }
+            let _ = events.send(ApiEvent::Disconnected(id));
+        });
+    }
+}
+
+fn handle_client(id: u64, stream: TcpStream, events: &mpsc::Sender<ApiEvent>) -> SocketResult {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(tungstenite::Error::Io)?;
+    let address = stream.peer_addr().map_err(tungstenite::Error::Io)?;
+    let socket = tungstenite::accept(stream).map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => error,
+        tungstenite::HandshakeError::Interrupted(_) => {
+            tungstenite::Error::Io(io::ErrorKind::WouldBlock.into())
+        }
+    })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(remote::POLL_INTERVAL))
+        .map_err(tungstenite::Error::Io)?;
+    let (outgoing, receiver) = mpsc::channel();
+    let _ = events.send(ApiEvent::Connected {
+        id,
+        address,
+        outgoing,
+    });
+    remote::serve(socket, receiver, |command| {
+        let _ = events.send(ApiEvent::Command(id, command));
+    })
+}
+
+/// A client of the API.
+pub struct ApiClient {
+    pub address: SocketAddr,
+    /// Channels streamed to the client, all when empty.
+    pub channels: Vec<String>,
+    /// Time between two messages of telemetry.
+    pub interval: Duration,
+    /// Real time of the last message of telemetry.
+    last: Option<Duration>,
+    /// Number of samples of each channel already sent, once streaming.
+    sent: Option<BTreeMap<String, usize>>,
+    outgoing: mpsc::Sender<String>,
+}
+
+impl ApiClient {
+    fn send(&self, message: &ApiMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            let _ = self.outgoing.send(text);
+        }
+    }
+}
+
+/// The WebSocket API served by this instance.
+#[derive(Resource)]
+pub struct WebSocketApi {
+    events: Mutex<mpsc::Receiver<ApiEvent>>,
+    pub clients: BTreeMap<u64, ApiClient>,
+    /// Time between two messages of telemetry of the clients without a rate of their own.
+    pub interval: Duration,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn api_receive(
+    mut commands: Commands,
+    mut api: ResMut<WebSocketApi>,
+    mut clock: ResMut<SimClock>,
+    mut setpoints: ResMut<Setpoints>,
+    mut pid: Option<ResMut<Persistent<PidSettings>>>,
+    mut lqr: Option<ResMut<Persistent<LqrSettings>>>,
+    mut injections: Option<ResMut<Events<Inject>>>,
+    mut watchdog: Option<ResMut<Watchdog>>,
+) {
+    let api = &mut *api;
+    let events = api
+        .events
+        .get_mut()
+        .unwrap_or_else(|error| error.into_inner());
+    for event in events.try_iter() {
+        match event {
+            ApiEvent::Connected {
+                id,
+                address,
+                outgoing,
+            } => {
+                info!(target: subsystem::IO, "{address} connected to the WebSocket API");
+                api.clients.insert(
+                    id,
+                    ApiClient {
+                        address,
+                        channels: Vec::new(),
+                        interval: api.interval,
+                        last: None,
+                        sent: None,
+                        outgoing,
+                    },
+                );
+            }
+            ApiEvent::Command(id, command) => {
+                let Some(client) = api.clients.get_mut(&id) else {
+                    continue;
+                };
+                debug!(target: subsystem::CONTROL, "{} sends {command:?}", client.address);
+                let unavailable = |what: &str| Some(format!("No {what} in this instance"));
+                let error = match command {
+                    ApiCommand::Subscribe { channels, rate } => {
+                        client.channels = channels;
+                        if let Some(rate) = rate {
+                            client.interval = interval(rate);
+                        }
+                        None
+                    }
+                    ApiCommand::Setpoint { name, value } => {
+                        if feed_watchdog(watchdog.as_deref_mut(), client) {
+                            setpoints.set(&name, value);
+                            None
+                        } else {
+                            Some(format!("The watchdog tripped, {name} is left unchanged"))
+                        }
+                    }
+                    ApiCommand::PidGains { kp, ki, kd } => match pid.as_mut() {
+                        Some(pid) => {
+                            let gains = &mut pid.get_mut().gains;
+                            gains.kp = kp.unwrap_or(gains.kp);
+                            gains.ki = ki.unwrap_or(gains.ki);
+                            gains.kd = kd.unwrap_or(gains.kd);
+                            None
+                        }
+                        None => unavailable("position loop"),
+                    },
+                    ApiCommand::LqrWeights { q, r } => match lqr.as_mut() {
+                        Some(lqr) => {
+                            let settings = lqr.get_mut();
+                            settings.q = q.unwrap_or(settings.q);
+                            settings.r = r.unwrap_or(settings.r);
+                            None
+                        }
+                        None => unavailable("regulator"),
+                    },
+                    ApiCommand::Pause => {
+                        clock.pause();
+                        None
+                    }
+                    ApiCommand::Resume => {
+                        clock.resume();
+                        None
+                    }
+                    ApiCommand::Reset => {
+                        commands.queue(|world: &mut World| {
+                            if world.contains_resource::<Snapshots>() {
+                                snapshots::reset(world);
+                            } else {
+                                warn!(target: subsystem::IO, "No snapshots to reset the scene to");
+                            }
+                        });
+                        None
+                    }
+                    ApiCommand::Disturb { injection } => match injections.as_mut() {
+                        Some(injections) => {
+                            injections.send(Inject {
+                                injection,
+                                ..default()
+                            });
+                            None
+                        }
+                        None => unavailable("disturbances"),
+                    },
+                    ApiCommand::Heartbeat => {
+                        feed_watchdog(watchdog.as_deref_mut(), client);
+                        None
+                    }
+                };
+                if let Some(message) = error {
+                    client.send(&ApiMessage::Error { message });
+                }
+            }
+            ApiEvent::Disconnected(id) => {
+                if let Some(client) = api.clients.remove(&id) {
+                    info!(
+                        target: subsystem::IO,
+                        "{} disconnected from the WebSocket API", client.address
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Feeds the watchdog with a message of a client, returning whether its commands are accepted.
+fn feed_watchdog(watchdog: Option<&mut Watchdog>, client: &ApiClient) -> bool {
+    watchdog.is_none_or(|watchdog| {
+        watchdog.feed(&format!("websocket/{}", client.address), Instant::now())
+    })
+}
+
+fn api_broadcast(
+    mut api: ResMut<WebSocketApi>,
+    time: Res<Time<Real>>,
+    clock: Res<SimClock>,
+    setpoints: Res<Setpoints>,
+    telemetry: Res<Telemetry>,
+) {
+    let now = time.elapsed();
+    for client in api.clients.values_mut() {
+        if client
+            .last
+            .is_some_and(|last| now.saturating_sub(last) < client.interval)
+        {
+            continue;
+        }
+        client.last = Some(now);
+        // The telemetry starts when the client connects.
+        let sent = client.sent.get_or_insert_with(|| {
+            telemetry
+                .channels
+                .iter()
+                .map(|(name, samples)| (name.clone(), samples.len()))
+                .collect()
+        });
+        let mut channels = BTreeMap::new();
+        for (name, samples) in &telemetry.channels {
+            let sent = sent.entry(name.clone()).or_default();
+            // The telemetry restarts when the session is rewound.
+            if *sent > samples.len() {
+                *sent = 0;
+            }
+            let recent = &samples[*sent..];
+            *sent = samples.len();
+            if client.channels.is_empty() || client.channels.contains(name) {
+                channels.insert(name.clone(), recent.to_vec());
+            }
+        }
+        client.send(&ApiMessage::Telemetry {
+            time: clock.elapsed_secs(),
+            running: !clock.is_paused(),
+            setpoints: setpoints.values.clone(),
+            channels,
+        });
+    }
+}
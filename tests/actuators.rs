@@ -0,0 +1,113 @@
+//! DC motors give less torque as they speed up, within the current limit of their drive, and
+//! draw more energy than the work they do.
+use digital_twin_playground::actuators::{Actuator, DcActuator, Energy};
+
+fn motor() -> DcActuator {
+    DcActuator {
+        stall_torque: 0.5,
+        no_load_speed: 500.0,
+        resistance: 1.2,
+        gear_ratio: 10.0,
+        efficiency: 0.9,
+        current_limit: 0.0,
+        ..Default::default()
+    }
+}
+
+fn assert_close(value: f32, expected: f32) {
+    assert!(
+        (value - expected).abs() < 1e-4,
+        "{value} instead of {expected}"
+    );
+}
+
+#[test]
+fn torque_falls_with_speed() {
+    let motor = motor();
+    assert_close(motor.torque_constant(), 0.034641);
+    assert_close(motor.voltage(), 17.3205);
+    // Stall torque through the gearbox.
+    assert_close(motor.torque_limit(0.0, 1.0), 4.5);
+    // Half the no-load speed of the motor, on the joint.
+    assert_close(motor.torque_limit(25.0, 1.0), 2.25);
+    assert_close(motor.torque_limit(-25.0, -1.0), 2.25);
+    assert_close(motor.torque_limit(60.0, 1.0), 0.0);
+    // Braking, the back-EMF helps the supply.
+    assert_close(motor.torque_limit(25.0, -1.0), 6.75);
+}
+
+#[test]
+fn current_limits_the_torque() {
+    let motor = DcActuator {
+        current_limit: 5.0,
+        ..motor()
+    };
+    let limit = motor.torque_constant() * 5.0 * 9.0;
+    assert_close(motor.torque_limit(0.0, 1.0), limit);
+    assert_close(motor.torque_limit(25.0, -1.0), limit);
+    assert_close(motor.torque_limit(45.0, -1.0), limit);
+    assert_close(motor.torque_limit(45.0, 1.0), 0.45);
+    assert_close(motor.current(limit), 5.0);
+}
+
+#[test]
+fn energy_covers_the_losses() {
+    let motor = motor();
+    // At stall, the winding turns all the power into heat.
+    let current = motor.current(4.5);
+    assert_close(motor.power(4.5, 0.0), 1.2 * current * current);
+
+    let mut actuator = Actuator::new(motor.clone(), "actuator/motor".to_string());
+    actuator.measure(0.0, 0.0);
+    actuator.apply(1.0);
+    // 0.2 rad in 0.1 s.
+    actuator.measure(0.1, 0.2);
+    assert_close(actuator.speed(), 2.0);
+    let energy = actuator.energy();
+    assert_close(energy.mechanical, 0.2);
+    assert_close(energy.electrical, motor.power(1.0, 2.0) * 0.1);
+    let efficiency = energy.efficiency().unwrap();
+    assert!(
+        (0.0..0.9).contains(&efficiency),
+        "efficiency of {efficiency}"
+    );
+
+    // A new run starts from nothing.
+    actuator.measure(0.0, 0.0);
+    assert_eq!(actuator.energy(), Energy::default());
+    assert_eq!(Energy::default().efficiency(), None);
+}
+
+#[test]
+fn winding_heats_up_and_derates() {
+    let motor = motor();
+    assert_close(motor.derating(25.0), 1.0);
+    assert_close(motor.derating(100.0), 1.0);
+    assert_close(motor.derating(115.0), 0.5);
+    assert_close(motor.derating(200.0), 0.0);
+
+    // Over one time constant, the winding covers 63 % of the way to where it settles.
+    let losses = motor.copper_losses(4.5);
+    let settled = 25.0 + losses * 3.0;
+    let heated = motor.heat(25.0, losses, 60.0);
+    assert_close((heated - 25.0) / (settled - 25.0), 1.0 - (-1.0f32).exp());
+
+    let mut actuator = Actuator::new(motor.clone(), "actuator/motor".to_string());
+    actuator.measure(0.0, 0.0);
+    actuator.apply(4.5);
+    for step in 1..=6000 {
+        actuator.measure(step as f32 * 0.1, 0.0);
+    }
+    assert!(actuator.temperature() > 100.0, "{}", actuator.temperature());
+    assert!(actuator.derating() < 1.0);
+    let heat = actuator.energy().heat;
+    assert!(
+        (heat / (losses * 600.0) - 1.0).abs() < 1e-3,
+        "{heat} J of heat"
+    );
+
+    // A new run starts cold.
+    actuator.measure(0.0, 0.0);
+    assert_close(actuator.temperature(), 25.0);
+    assert_close(actuator.derating(), 1.0);
+}
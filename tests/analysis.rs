@@ -0,0 +1,104 @@
+//! The analysis finds the modes the actuators can't move and the sensors can't see.
+use digital_twin_playground::{
+    analysis::{controllability_matrix, dominant_resonance, modes, rank, Analysis},
+    identification::LinearModel,
+};
+use nalgebra::DMatrix;
+
+/// Two decoupled first-order modes: the input drives only the first, the output sees only the
+/// second.
+fn decoupled_model(coupling: f64) -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["u".to_string()],
+        outputs: vec!["y".to_string()],
+        a: DMatrix::from_row_slice(2, 2, &[0.9, 0.0, coupling, 0.5]),
+        b: DMatrix::from_row_slice(2, 1, &[1.0, 0.0]),
+        c: DMatrix::from_row_slice(1, 2, &[0.0, 1.0]),
+        d: DMatrix::zeros(1, 1),
+        input_offsets: vec![0.0],
+        output_offsets: vec![0.0],
+    }
+}
+
+#[test]
+fn coupled_modes_are_controllable_and_observable() {
+    let analysis = Analysis::of(&decoupled_model(0.5), 1.0);
+    assert!(analysis.controllable());
+    assert!(analysis.observable());
+    assert!(analysis
+        .modes
+        .iter()
+        .all(|mode| !mode.weakly_controllable()));
+    assert!(analysis.modes.iter().all(|mode| !mode.weakly_observable()));
+}
+
+#[test]
+fn decoupled_modes_are_reported() {
+    let model = decoupled_model(0.0);
+    assert_eq!(rank(&controllability_matrix(&model)), 1);
+    let analysis = Analysis::of(&model, 1.0);
+    assert_eq!(analysis.controllable_rank, 1);
+    assert_eq!(analysis.observable_rank, 1);
+
+    let modes = modes(&model);
+    let mode = |eigenvalue: f64| {
+        modes
+            .iter()
+            .find(|mode| (mode.eigenvalue.re - eigenvalue).abs() < 1e-9)
+            .unwrap()
+    };
+    // The input drives the mode at 0.9, the output sees the one at 0.5.
+    assert!(!mode(0.9).weakly_controllable());
+    assert!(mode(0.9).weakly_observable());
+    assert!(mode(0.5).weakly_controllable());
+    assert!(!mode(0.5).weakly_observable());
+
+    let report = analysis.to_string();
+    assert!(report.contains("controllability rank 1/2"));
+    assert!(report.contains("(weak)"));
+}
+
+#[test]
+fn gramians_measure_the_reach_of_the_input() {
+    let analysis = Analysis::of(&decoupled_model(0.0), 1.0);
+    let gramian = &analysis.gramians.controllability;
+    // Geometric series of 0.81 over 100 steps.
+    assert!((gramian[(0, 0)] - 1.0 / (1.0 - 0.81)).abs() < 1e-6);
+    assert_eq!(gramian[(1, 1)], 0.0);
+}
+
+#[test]
+fn the_least_damped_resonance_dominates() {
+    let dt = 0.01;
+    // Discrete rotation block of a continuous mode at `frequency` Hz with `damping`.
+    let block = |frequency: f64, damping: f64| {
+        let omega = std::f64::consts::TAU * frequency;
+        let decay = (-damping * omega * dt).exp();
+        let angle = omega * (1.0 - damping * damping).sqrt() * dt;
+        (decay * angle.cos(), decay * angle.sin())
+    };
+    let ((r1, i1), (r2, i2)) = (block(1.0, 0.05), block(3.0, 0.01));
+    #[rustfmt::skip]
+    let a = DMatrix::from_row_slice(5, 5, &[
+        r1, -i1, 0.0, 0.0, 0.0,
+        i1, r1, 0.0, 0.0, 0.0,
+        0.0, 0.0, r2, -i2, 0.0,
+        0.0, 0.0, i2, r2, 0.0,
+        0.0, 0.0, 0.0, 0.0, 0.5,
+    ]);
+    let model = LinearModel {
+        dt: dt as f32,
+        inputs: vec!["u".to_string()],
+        outputs: vec!["y".to_string()],
+        a,
+        b: DMatrix::from_column_slice(5, 1, &[1.0, 0.0, 1.0, 0.0, 1.0]),
+        c: DMatrix::from_row_slice(1, 5, &[1.0, 0.0, 1.0, 0.0, 1.0]),
+        d: DMatrix::zeros(1, 1),
+        input_offsets: vec![0.0],
+        output_offsets: vec![0.0],
+    };
+    let mode = dominant_resonance(&model).unwrap();
+    assert!((mode.frequency - 3.0).abs() < 1e-3, "{mode:?}");
+    assert!((mode.damping - 0.01).abs() < 1e-3, "{mode:?}");
+}
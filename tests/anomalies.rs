@@ -0,0 +1,47 @@
+//! Samples far from the moving average of a signal start anomalies, once it's warmed up.
+use digital_twin_playground::anomalies::{Detector, Monitor};
+
+/// A noisy signal around 1, without randomness.
+fn noise(k: usize) -> f32 {
+    1.0 + 0.1 * (k as f32 * 0.7).sin() * (k as f32 * 1.3).cos()
+}
+
+#[test]
+fn outliers_start_anomalies() {
+    let monitor = Monitor::new("motor/torque");
+    let mut detector = Detector::default();
+    for k in 0..1000 {
+        assert!(!detector.update(&monitor, noise(k)), "sample {k}");
+    }
+    assert!(detector.score < monitor.threshold);
+
+    assert!(detector.update(&monitor, 3.0));
+    assert!(detector.active);
+    // Still out of the limits, but the same anomaly.
+    assert!(!detector.update(&monitor, 3.0));
+    assert_eq!(detector.count, 1);
+
+    // Back to normal.
+    assert!(!detector.update(&monitor, noise(0)));
+    assert!(!detector.active);
+    assert!(detector.update(&monitor, -2.0));
+    assert_eq!(detector.count, 2);
+}
+
+#[test]
+fn warming_up_and_steady_signals_are_not_abnormal() {
+    let monitor = Monitor::new("motor/torque");
+    let mut detector = Detector::default();
+    // A jump during the warm-up.
+    for k in 0..monitor.warmup {
+        let value = if k < monitor.warmup / 2 { 0.0 } else { 5.0 };
+        assert!(!detector.update(&monitor, value));
+    }
+
+    // Constant, to within the smallest deviation.
+    let mut detector = Detector::default();
+    for k in 0..1000 {
+        let value = 2.0 + if k % 2 == 0 { 1e-4 } else { -1e-4 };
+        assert!(!detector.update(&monitor, value), "sample {k}");
+    }
+}
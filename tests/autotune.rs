@@ -0,0 +1,65 @@
+//! A relay closing the loop around an integrator behind a dead time cycles at the ultimate
+//! period of the loop, and the rules turn it into the gains of a PID controller.
+use std::{collections::VecDeque, f32::consts::PI};
+
+use digital_twin_playground::control::{PidGains, RelayFeedback, TuningRule, UltimateGain};
+
+const DT: f32 = 1e-3;
+/// Dead time between the command and the joint, in s.
+const DEAD_TIME: f32 = 0.05;
+
+fn assert_close(value: f32, expected: f32, tolerance: f32) {
+    assert!(
+        (value - expected).abs() <= tolerance * expected.abs(),
+        "{value} instead of {expected}"
+    );
+}
+
+#[test]
+fn relay_finds_the_ultimate_period() {
+    let amplitude = 2.0;
+    let mut relay = RelayFeedback::new(amplitude, 0.0);
+    // Velocities commanded, the oldest first, reaching the joint after the dead time.
+    let mut commands: VecDeque<f32> = vec![0.0; (DEAD_TIME / DT) as usize].into();
+    let mut angle = 0.0;
+    for step in 0..5000 {
+        let output = relay.update(step as f32 * DT, -angle);
+        commands.push_back(output);
+        angle += commands.pop_front().unwrap() * DT;
+    }
+    assert!(relay.cycles() >= 10, "{} cycles", relay.cycles());
+
+    // A triangle of amplitude d·L at a period of 4·L, where the loop has a phase of -180°.
+    let ultimate = relay.ultimate().unwrap();
+    assert_close(ultimate.period, 4.0 * DEAD_TIME, 0.05);
+    assert_close(ultimate.gain, 4.0 / (PI * DEAD_TIME), 0.05);
+}
+
+#[test]
+fn too_few_cycles_measure_nothing() {
+    let mut relay = RelayFeedback::new(1.0, 0.01);
+    assert_eq!(relay.update(0.0, 0.0), 1.0);
+    assert_eq!(relay.update(0.1, -0.02), -1.0);
+    assert_eq!(relay.update(0.2, 0.02), 1.0);
+    assert_eq!(relay.cycles(), 0);
+    assert_eq!(relay.ultimate(), None);
+}
+
+#[test]
+fn rules_give_the_classic_gains() {
+    let ultimate = UltimateGain {
+        gain: 10.0,
+        period: 2.0,
+    };
+    let PidGains { kp, ki, kd } = TuningRule::ZieglerNichols.gains(ultimate);
+    assert_close(kp, 6.0, 1e-6);
+    assert_close(ki, 6.0, 1e-6);
+    assert_close(kd, 1.5, 1e-6);
+
+    let PidGains { kp, ki, kd } = TuningRule::TyreusLuyben.gains(ultimate);
+    assert_close(kp, 10.0 / 2.2, 1e-6);
+    assert_close(ki, 10.0 / 2.2 / 4.4, 1e-6);
+    assert_close(kd, 10.0 / 2.2 * 2.0 / 6.3, 1e-6);
+    // The conservative rule is gentler.
+    assert!(kp < 6.0 && ki < 6.0);
+}
@@ -0,0 +1,62 @@
+//! Headless batch runs write the telemetry of the whole run.
+use std::fs;
+
+use digital_twin_playground::{
+    batch::{self, BatchRun},
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    recording::RecordingFormat,
+    telemetry::PENDULUM_ANGLE,
+};
+
+#[test]
+fn durations_have_units() {
+    assert_eq!(batch::parse_duration("10s"), Ok(10.0));
+    assert_eq!(batch::parse_duration("2.5"), Ok(2.5));
+    assert_eq!(batch::parse_duration("250ms"), Ok(0.25));
+    assert_eq!(batch::parse_duration("2min"), Ok(120.0));
+    assert!(batch::parse_duration("10 days").is_err());
+    assert!(batch::parse_duration("-1s").is_err());
+    assert!(batch::parse_duration("s").is_err());
+}
+
+#[test]
+fn the_format_follows_the_extension() {
+    assert_eq!(
+        batch::format_of("out/results.PARQUET".as_ref()),
+        RecordingFormat::Parquet
+    );
+    assert_eq!(
+        batch::format_of("results.csv".as_ref()),
+        RecordingFormat::Csv
+    );
+    assert_eq!(batch::format_of("results".as_ref()), RecordingFormat::Csv);
+}
+
+#[test]
+fn runs_write_a_row_per_step() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join("batch_run");
+    let run = BatchRun {
+        duration: 0.5,
+        dt: DEFAULT_TIME_STEP,
+        seed: 1,
+        out: dir.join("results.csv"),
+        capture: None,
+        scenario: None,
+    };
+    let summary = batch::run(&plant, &run).unwrap();
+    assert_eq!(summary.steps, 30);
+    assert_eq!(summary.path, run.out);
+
+    let csv = fs::read_to_string(&run.out).unwrap();
+    let mut lines = csv.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with("time,"), "{header}");
+    assert!(header.split(',').any(|column| column == PENDULUM_ANGLE));
+    assert_eq!(lines.count(), summary.rows);
+    assert!(summary.rows >= 29, "{}", summary.rows);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,151 @@
+//! Benchmarks run every registered controller through the scenarios of its type, and rank the
+//! runs of each scenario into a scoreboard.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+};
+
+use digital_twin_playground::{
+    bench::{self, BenchRun, BenchScenario, BenchSettings},
+    control::PidGains,
+    controller_definition::ControllerDefinition,
+    headless::DEFAULT_TIME_STEP,
+    pid_controller::PidSettings,
+    plants,
+    resource_usage::ResourceUsage,
+    sweep::{Cost, Metrics, SweepRun},
+};
+
+fn run(scenario: &str, controller: &str, rms_error: Option<f32>) -> BenchRun {
+    BenchRun {
+        scenario: scenario.to_string(),
+        controller: controller.to_string(),
+        run: SweepRun {
+            parameters: BTreeMap::new(),
+            metrics: rms_error.map(|rms_error| Metrics {
+                settling_time: Some(1.0),
+                overshoot: 0.0,
+                rms_error,
+            }),
+            energy: None,
+            violations: 0,
+            usage: ResourceUsage::default(),
+            scripted: None,
+        },
+    }
+}
+
+#[test]
+fn the_standard_suite_covers_the_built_in_controllers() {
+    let suite = bench::standard();
+    for controller in ["pid", "lqr"] {
+        assert!(suite
+            .iter()
+            .any(|scenario| scenario.controller == controller));
+    }
+    assert!(suite
+        .iter()
+        .all(|scenario| scenario.duration > 0.0 && scenario.band > 0.0));
+    let names: BTreeSet<_> = suite.iter().map(|scenario| &scenario.name).collect();
+    assert_eq!(names.len(), suite.len());
+}
+
+#[test]
+fn runs_are_ranked_within_their_scenario() {
+    let runs = [
+        run("step", "a", Some(0.5)),
+        run("step", "b", Some(0.1)),
+        run("kick", "a", Some(0.2)),
+        run("kick", "b", None),
+    ];
+    assert_eq!(bench::ranks(&runs, Cost::RmsError), vec![2, 1, 1, 2]);
+    let scoreboard = bench::scoreboard(&runs, Cost::RmsError);
+    assert_eq!(scoreboard.len(), 2);
+    // Tied on the mean rank, both won once, and only `a` settled in both.
+    assert!(scoreboard
+        .iter()
+        .all(|score| score.mean_rank == 1.5 && score.wins == 1));
+    let a = scoreboard
+        .iter()
+        .find(|score| score.controller == "a")
+        .unwrap();
+    assert_eq!((a.scenarios, a.settled), (2, 2));
+
+    // A controller without a finite score wins nothing.
+    let runs = [run("step", "a", None)];
+    assert_eq!(bench::scoreboard(&runs, Cost::RmsError)[0].wins, 0);
+}
+
+#[test]
+fn contenders_are_the_defaults_then_the_definitions() {
+    let dir = std::env::temp_dir().join("bench_contenders");
+    let tuned = PidSettings {
+        gains: PidGains {
+            kp: 8.0,
+            ki: 0.0,
+            kd: 0.2,
+        },
+        ..Default::default()
+    };
+    ControllerDefinition::from(&tuned)
+        .write(&dir.join("tuned.json"))
+        .unwrap();
+    let contenders = bench::contenders(&dir);
+    let names: Vec<_> = contenders
+        .iter()
+        .map(|contender| contender.name.as_str())
+        .collect();
+    assert_eq!(names, ["default pid", "default lqr", "tuned"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn benchmarks_write_a_scoreboard_row_per_run() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let scenario = BenchScenario {
+        duration: 0.5,
+        ..bench::standard()
+            .into_iter()
+            .find(|scenario| scenario.plant == plant.name && scenario.controller == "pid")
+            .unwrap()
+    };
+    let settings = BenchSettings {
+        scenarios: vec![
+            scenario.clone(),
+            BenchScenario {
+                plant: "nowhere".to_string(),
+                ..scenario
+            },
+        ],
+        ..Default::default()
+    };
+    let contenders = bench::contenders(&std::env::temp_dir().join("bench_none"));
+    let mut ended = 0;
+    let runs = bench::bench(&[plant], &settings, &contenders, DEFAULT_TIME_STEP, |_| {
+        ended += 1
+    })
+    .unwrap();
+    // Only the position loop runs the step, and the missing plant is skipped.
+    assert_eq!(ended, 1);
+    assert_eq!(runs[0].controller, "default pid");
+    assert!(runs[0].run.metrics.is_some(), "{runs:?}");
+
+    let path = std::env::temp_dir().join("bench").join("scoreboard.csv");
+    bench::write_scoreboard(&runs, Cost::RmsError, &path).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some(
+            "scenario,controller,settling_time,overshoot,rms_error,energy,violations,\
+             cpu_time,peak_memory,step_p50_us,step_p90_us,step_p99_us,step_max_us,rank"
+        )
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("pendulum step,default pid,"));
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,81 @@
+//! Blended paths are continuous, pass within their tolerance of the corners, and don't blend
+//! reversals.
+use digital_twin_playground::control::BlendedPath;
+
+const TOLERANCE: f32 = 0.05;
+
+fn square() -> Vec<Vec<f32>> {
+    vec![
+        vec![0.0, 0.0],
+        vec![1.0, 0.0],
+        vec![1.0, 1.0],
+        vec![2.0, 1.0],
+    ]
+}
+
+#[test]
+fn corners_are_blended_within_the_tolerance() {
+    let path = BlendedPath::new(&square(), TOLERANCE);
+    for corner in [[1.0f32, 0.0], [1.0, 1.0]] {
+        let distance = path.distance(&corner);
+        assert!((distance - TOLERANCE).abs() < 1e-4, "{distance}");
+    }
+    // Shorter than the polyline, ending on its ends.
+    assert!(path.length() < 3.0);
+    assert_eq!(path.sample(0.0).0, vec![0.0, 0.0]);
+    assert_eq!(path.sample(path.length()).0, vec![2.0, 1.0]);
+    assert_eq!(path.vertices().len(), 3);
+    assert!(path.min_radius().is_some_and(|radius| radius > TOLERANCE));
+}
+
+#[test]
+fn paths_are_continuous_with_unit_tangents() {
+    let path = BlendedPath::new(&square(), TOLERANCE);
+    let steps = 2000;
+    let ds = path.length() / steps as f32;
+    let mut previous = path.sample(0.0);
+    for k in 1..=steps {
+        let (position, tangent) = path.sample(k as f32 * ds);
+        let norm = tangent.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4, "{norm}");
+        let jump = position
+            .iter()
+            .zip(&previous.0)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        assert!(jump <= ds * 1.01, "{jump} at {k}");
+        let turn = tangent
+            .iter()
+            .zip(&previous.1)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        assert!(turn < 0.05, "tangent jumps by {turn} at {k}");
+        assert!(path.distance(&position) < 1e-5);
+        previous = (position, tangent);
+    }
+}
+
+#[test]
+fn reversals_and_repeated_points_are_not_blended() {
+    assert!(BlendedPath::is_blendable(
+        &[0.0, 0.0],
+        &[1.0, 0.0],
+        &[1.0, 1.0]
+    ));
+    assert!(!BlendedPath::is_blendable(
+        &[0.0, 0.0],
+        &[1.0, 0.0],
+        &[0.0, 0.0]
+    ));
+    assert!(!BlendedPath::is_blendable(
+        &[0.0, 0.0],
+        &[0.0, 0.0],
+        &[1.0, 0.0]
+    ));
+    // Without tolerance, the path goes through its corners.
+    let path = BlendedPath::new(&square(), 0.0);
+    assert!((path.length() - 3.0).abs() < 1e-6);
+    assert!(path.min_radius().is_none());
+}
@@ -0,0 +1,117 @@
+//! Models are calibrated on logs of the rig, and compared with a reference model on the part of
+//! the log kept for validation.
+use digital_twin_playground::{
+    calibration::{self, CalibrationSettings},
+    identification::LinearModel,
+    telemetry::Telemetry,
+};
+use nalgebra::{DMatrix, DVector};
+
+/// A lightly damped second-order system, standing for the rig.
+fn rig() -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["motor/velocity".to_string()],
+        outputs: vec!["pendulum/angle".to_string()],
+        a: DMatrix::from_row_slice(2, 2, &[0.98, 0.1, -0.2, 0.95]),
+        b: DMatrix::from_row_slice(2, 1, &[0.0, 0.1]),
+        c: DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        d: DMatrix::zeros(1, 1),
+        input_offsets: vec![0.0],
+        output_offsets: vec![0.5],
+    }
+}
+
+/// A log of the rig excited by a pseudo-random binary sequence.
+fn log(rig: &LinearModel) -> Telemetry {
+    let mut random = 1u64;
+    let inputs: Vec<Vec<f64>> = (0..1_000)
+        .map(|_| {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            vec![if random & 1 == 0 { 1.0 } else { -1.0 }]
+        })
+        .collect();
+    let outputs = rig.simulate(&inputs, &DVector::zeros(rig.order()));
+    let mut log = Telemetry::default();
+    for (k, (input, output)) in inputs.iter().zip(&outputs).enumerate() {
+        let time = k as f32 * rig.dt;
+        log.record("motor/velocity", time, input[0] as f32);
+        log.record("pendulum/angle", time, output[0] as f32);
+    }
+    log
+}
+
+fn settings(rig: &LinearModel) -> CalibrationSettings {
+    CalibrationSettings {
+        inputs: rig.inputs.clone(),
+        outputs: rig.outputs.clone(),
+        dt: rig.dt,
+        order: 2,
+        horizon: 6,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn models_are_calibrated_on_the_log() {
+    let rig = rig();
+    let log = log(&rig);
+    let data = calibration::dataset(&log, &rig.inputs, &rig.outputs, rig.dt).unwrap();
+    // The last sample may fall past the log by rounding.
+    let samples = data.inputs.len();
+    assert!((999..=1_000).contains(&samples), "{samples} samples");
+
+    // The simulation misses the damping of the rig.
+    let mut simulation = rig.clone();
+    simulation.a[(1, 1)] = 1.0;
+    let path = std::env::temp_dir().join("calibration_reference.json");
+    simulation.write(&path).unwrap();
+    let settings = CalibrationSettings {
+        reference: Some(path.clone()),
+        ..settings(&rig)
+    };
+    let calibration = calibration::calibrate(&log, &settings).unwrap();
+    let _ = std::fs::remove_file(path);
+
+    assert_eq!(calibration.model.order(), 2);
+    assert_eq!(
+        calibration.estimation_samples + calibration.validation.inputs.len(),
+        samples
+    );
+    assert_eq!(calibration.validation.inputs.len(), 300);
+    let [fit] = calibration.fits.as_slice() else {
+        panic!("one fit per output");
+    };
+    assert_eq!(fit.signal, "pendulum/angle");
+    assert!(fit.calibrated > 99.0, "fit of {} %", fit.calibrated);
+    let reference = fit.reference.unwrap();
+    assert!(reference < fit.calibrated, "reference fit of {reference} %");
+}
+
+#[test]
+fn calibrations_need_the_signals_in_the_log() {
+    let rig = rig();
+    let settings = CalibrationSettings {
+        outputs: vec!["motor/torque".to_string()],
+        ..settings(&rig)
+    };
+    assert!(calibration::calibrate(&log(&rig), &settings).is_err());
+}
+
+#[test]
+fn reference_models_must_map_the_same_signals() {
+    let rig = rig();
+    let mut other = rig.clone();
+    other.inputs = vec!["motor/position".to_string()];
+    let path = std::env::temp_dir().join("calibration_other_reference.json");
+    other.write(&path).unwrap();
+    let settings = CalibrationSettings {
+        reference: Some(path.clone()),
+        ..settings(&rig)
+    };
+    let result = calibration::calibrate(&log(&rig), &settings);
+    let _ = std::fs::remove_file(path);
+    assert!(result.is_err());
+}
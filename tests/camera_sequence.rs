@@ -0,0 +1,63 @@
+//! The camera sequencer interpolates its keyframes with their easings.
+use bevy::prelude::*;
+use digital_twin_playground::camera_sequence::{self, CameraKeyframe, CameraSequence, Easing};
+
+#[test]
+fn easings_go_from_start_to_end() {
+    for easing in Easing::ALL {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+        assert_eq!(easing.apply(2.0), 1.0);
+    }
+    assert_eq!(Easing::Linear.apply(0.25), 0.25);
+    assert!(Easing::EaseIn.apply(0.25) < 0.25);
+    assert!(Easing::EaseOut.apply(0.25) > 0.25);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+}
+
+#[test]
+fn keyframes_are_interpolated_and_held_past_the_ends() {
+    let sequence = CameraSequence {
+        // Out of order.
+        keyframes: vec![
+            CameraKeyframe {
+                time: 4.0,
+                position: Vec3::new(0.0, 4.0, 8.0),
+                target: Vec3::Y,
+                easing: Easing::Linear,
+            },
+            CameraKeyframe {
+                time: 2.0,
+                position: Vec3::new(0.0, 0.0, 4.0),
+                target: Vec3::ZERO,
+                easing: Easing::EaseInOut,
+            },
+        ],
+        ..default()
+    };
+    assert_eq!(sequence.duration(), 4.0);
+    assert_eq!(
+        sequence.pose(0.0),
+        Some((Vec3::new(0.0, 0.0, 4.0), Vec3::ZERO))
+    );
+    assert_eq!(
+        sequence.pose(3.0),
+        Some((Vec3::new(0.0, 2.0, 6.0), Vec3::Y * 0.5))
+    );
+    assert_eq!(
+        sequence.pose(10.0),
+        Some((Vec3::new(0.0, 4.0, 8.0), Vec3::Y))
+    );
+    assert_eq!(CameraSequence::default().pose(1.0), None);
+}
+
+#[test]
+fn orbit_angles_place_the_camera_back_on_its_position() {
+    let target = Vec3::new(1.0, 0.5, -2.0);
+    let position = Vec3::new(4.0, 3.0, 2.0);
+    let (yaw, pitch, radius) = camera_sequence::orbit_angles(position, target);
+    // As the orbit camera places itself.
+    let rotation = Quat::from_rotation_y(yaw) * Quat::from_rotation_x(-pitch);
+    let placed = target + rotation * Vec3::Z * radius;
+    assert!(placed.abs_diff_eq(position, 1e-4), "{placed}");
+}
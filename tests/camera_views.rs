@@ -0,0 +1,60 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    camera_sequence::orbit_angles,
+    camera_views::{inset_viewport, CameraPreset, Corner, Follow, FollowMode},
+};
+
+#[test]
+fn presets_look_at_the_target_from_the_distance() {
+    let target = Vec3::new(1.0, 2.0, 3.0);
+    for preset in CameraPreset::ALL {
+        let position = preset.position(target, 12.0);
+        assert!((position.distance(target) - 12.0).abs() < 1e-4);
+    }
+    let (yaw, pitch, _) = orbit_angles(CameraPreset::Front.position(target, 5.0), target);
+    assert!(yaw.abs() < 1e-6 && pitch.abs() < 1e-6);
+    let (yaw, _, _) = orbit_angles(CameraPreset::Side.position(target, 5.0), target);
+    assert!((yaw - FRAC_PI_2).abs() < 1e-6);
+    let (_, pitch, _) = orbit_angles(CameraPreset::Top.position(target, 5.0), target);
+    assert!((pitch - FRAC_PI_2).abs() < 1e-6);
+}
+
+#[test]
+fn chasing_keeps_the_offset_in_the_world_and_mounting_turns_it() {
+    let link = GlobalTransform::from(
+        Transform::from_xyz(0.0, 1.0, 0.0).with_rotation(Quat::from_rotation_z(FRAC_PI_2)),
+    );
+    let mut follow = Follow {
+        mode: FollowMode::Chase,
+        link: "pendulum".to_string(),
+        point: Vec3::Y,
+        offset: Vec3::X,
+    };
+    // The point, one meter up the link, swings to -X as the link turns about Z.
+    let (position, target) = follow.pose(&link);
+    assert!(target.abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+    assert!(position.abs_diff_eq(Vec3::new(0.0, 1.0, 0.0), 1e-5));
+    follow.mode = FollowMode::Mounted;
+    let (position, _) = follow.pose(&link);
+    assert!(position.abs_diff_eq(Vec3::new(-1.0, 2.0, 0.0), 1e-5));
+}
+
+#[test]
+fn the_inset_sits_in_its_corner_with_the_aspect_of_the_window() {
+    let window = UVec2::new(1600, 1000);
+    let viewport = inset_viewport(window, Corner::BottomRight, 0.3);
+    assert_eq!(viewport.physical_size, UVec2::new(480, 300));
+    assert_eq!(
+        viewport.physical_position,
+        UVec2::new(1600 - 480 - 20, 1000 - 300 - 20)
+    );
+    let viewport = inset_viewport(window, Corner::TopLeft, 0.3);
+    assert_eq!(viewport.physical_position, UVec2::new(20, 20));
+    for corner in Corner::ALL {
+        let viewport = inset_viewport(window, corner, 0.6);
+        let end = viewport.physical_position + viewport.physical_size;
+        assert!(end.x <= window.x && end.y <= window.y);
+    }
+}
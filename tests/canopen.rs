@@ -0,0 +1,94 @@
+//! The emulated CiA 402 drive, driven by the messages a motion stack would send.
+#![cfg(target_os = "linux")]
+
+use digital_twin_playground::canopen::{abort, index, mode, Drive, DriveState, Message, NmtState};
+
+const NODE: u8 = 3;
+
+fn sdo_download(drive: &mut Drive, index: u16, command: u8, value: u32) -> [u8; 8] {
+    let mut request = [command, 0, 0, 0, 0, 0, 0, 0];
+    request[1..3].copy_from_slice(&index.to_le_bytes());
+    request[4..8].copy_from_slice(&value.to_le_bytes());
+    let responses = drive.handle(&Message::new(0x600 + u16::from(NODE), &request));
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].cob_id, 0x580 + u16::from(NODE));
+    responses[0].data.clone().try_into().unwrap()
+}
+
+fn rpdo1(drive: &mut Drive, controlword: u16, target_velocity: i32) {
+    let mut data = controlword.to_le_bytes().to_vec();
+    data.extend_from_slice(&target_velocity.to_le_bytes());
+    assert!(drive
+        .handle(&Message::new(0x200 + u16::from(NODE), &data))
+        .is_empty());
+}
+
+#[test]
+fn drive_is_enabled_through_the_state_machine() {
+    let mut drive = Drive::new(NODE);
+    assert_eq!(drive.boot_up(), Message::new(0x703, &[0]));
+    drive.handle(&Message::new(0x000, &[0x01, NODE]));
+    assert_eq!(drive.nmt_state(), NmtState::Operational);
+
+    // Shutdown, switch on, enable operation.
+    for (controlword, state) in [
+        (0x06, DriveState::ReadyToSwitchOn),
+        (0x07, DriveState::SwitchedOn),
+        (0x0F, DriveState::OperationEnabled),
+    ] {
+        rpdo1(&mut drive, controlword, 2500);
+        assert_eq!(drive.state(), state);
+    }
+    assert_eq!(drive.velocity_demand(), 2.5);
+
+    drive.set_actual_values(0.5, 2.5);
+    let responses = drive.handle(&Message::new(0x080, &[]));
+    assert_eq!(
+        responses[0],
+        Message::new(0x183, &[0x37, 0x02, 0xC4, 0x09, 0, 0])
+    );
+    assert_eq!(
+        responses[1],
+        Message::new(0x283, &[0x37, 0x02, 0xF4, 0x01, 0, 0])
+    );
+
+    // Quick stop.
+    rpdo1(&mut drive, 0x02, 2500);
+    assert_eq!(drive.state(), DriveState::QuickStopActive);
+    assert_eq!(drive.velocity_demand(), 0.0);
+}
+
+#[test]
+fn objects_are_accessed_with_sdo() {
+    let mut drive = Drive::new(NODE);
+    // Expedited download of 1 byte.
+    let response = sdo_download(
+        &mut drive,
+        index::MODES_OF_OPERATION,
+        0x2F,
+        mode::CYCLIC_SYNC_VELOCITY as u32,
+    );
+    assert_eq!(response[0], 0x60);
+    let response = sdo_download(&mut drive, index::MODES_OF_OPERATION_DISPLAY, 0x40, 0);
+    assert_eq!(response[0], 0x4F);
+    assert_eq!(response[4] as i8, mode::CYCLIC_SYNC_VELOCITY);
+
+    // Position mode isn't supported by the plant.
+    let response = sdo_download(&mut drive, index::MODES_OF_OPERATION, 0x2F, 1);
+    assert_eq!(response[0], 0x80);
+    assert_eq!(
+        u32::from_le_bytes(response[4..8].try_into().unwrap()),
+        abort::VALUE_RANGE_EXCEEDED
+    );
+
+    let response = sdo_download(&mut drive, index::STATUSWORD, 0x2B, 0);
+    assert_eq!(
+        u32::from_le_bytes(response[4..8].try_into().unwrap()),
+        abort::READ_ONLY
+    );
+    let response = sdo_download(&mut drive, 0x2000, 0x40, 0);
+    assert_eq!(
+        u32::from_le_bytes(response[4..8].try_into().unwrap()),
+        abort::OBJECT_DOES_NOT_EXIST
+    );
+}
@@ -0,0 +1,39 @@
+use digital_twin_playground::capture::{frame_name, frames_due, CaptureSettings};
+
+#[test]
+fn frames_are_due_at_the_pace_of_the_simulated_time() {
+    assert_eq!(frames_due(0.0, 30.0), 1);
+    assert_eq!(frames_due(0.5, 30.0), 16);
+    // A step landing on a frame time by rounding still takes it.
+    assert_eq!(frames_due(0.1 - 1e-6, 10.0), 2);
+    // At 60 steps per second, every other step takes a frame at 30 frames per second.
+    let due: Vec<_> = (0..5)
+        .map(|step| frames_due(step as f32 / 60.0, 30.0))
+        .collect();
+    assert_eq!(due, [1, 1, 2, 2, 3]);
+    // Slower steps than the frames repeat frames.
+    assert_eq!(frames_due(0.25, 30.0) - frames_due(0.125, 30.0), 4);
+    assert_eq!(frames_due(1.0, 0.0), 0);
+}
+
+#[test]
+fn frames_are_named_in_order() {
+    assert_eq!(frame_name(42), "frame_000042.png");
+    let mut names: Vec<_> = [10, 9, 100].into_iter().map(frame_name).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["frame_000009.png", "frame_000010.png", "frame_000100.png"]
+    );
+}
+
+#[test]
+fn frames_have_even_sizes() {
+    let settings = CaptureSettings {
+        width: 1281,
+        height: 1,
+        ..Default::default()
+    };
+    assert_eq!(settings.size(), (1280, 2));
+    assert_eq!(CaptureSettings::default().size(), (1280, 720));
+}
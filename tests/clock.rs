@@ -0,0 +1,74 @@
+//! The simulation clock follows the physics steps of a headless application.
+use std::time::Duration;
+
+use digital_twin_playground::{
+    clock::{SimClock, SPEEDS},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+};
+
+#[test]
+fn clock_counts_physics_steps_and_stops_while_paused() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.finish();
+    app.cleanup();
+    let dt = Duration::from_secs_f32(DEFAULT_TIME_STEP);
+
+    for _ in 0..120 {
+        app.update();
+    }
+    let clock = app.world().resource::<SimClock>();
+    assert_eq!(clock.tick(), 120);
+    assert_eq!(clock.elapsed(), SimClock::duration_of(120, dt));
+    assert_eq!(SimClock::ticks_in(clock.elapsed(), dt), 120);
+    assert_eq!(clock.shared().elapsed(), clock.elapsed());
+
+    app.world_mut().resource_mut::<SimClock>().pause();
+    for _ in 0..10 {
+        app.update();
+    }
+    let clock = app.world().resource::<SimClock>();
+    assert_eq!(clock.tick(), 120);
+    assert_eq!(clock.delta(), Duration::ZERO);
+}
+
+#[test]
+fn paused_clocks_step_once_on_request() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.finish();
+    app.cleanup();
+    app.update();
+    app.world_mut().resource_mut::<SimClock>().pause();
+    app.update();
+    let tick = app.world().resource::<SimClock>().tick();
+
+    app.world_mut().resource_mut::<SimClock>().step_once();
+    for _ in 0..5 {
+        app.update();
+    }
+    let clock = app.world().resource::<SimClock>();
+    assert_eq!(clock.tick(), tick + 1);
+    assert!(clock.is_paused());
+}
+
+#[test]
+fn speeds_step_through_the_presets() {
+    let mut clock = SimClock::default();
+    assert_eq!(clock.speed(), 1.0);
+    clock.change_speed(false);
+    assert_eq!(clock.speed(), 0.25);
+    clock.change_speed(false);
+    clock.change_speed(false);
+    assert_eq!(clock.speed(), SPEEDS[0]);
+    for _ in 0..5 {
+        clock.change_speed(true);
+    }
+    assert_eq!(clock.speed(), 2.0);
+
+    clock.set_speed(0.5);
+    clock.change_speed(true);
+    assert_eq!(clock.speed(), 1.0);
+    for speed in [0.0, -1.0, f32::NAN] {
+        clock.set_speed(speed);
+        assert_eq!(clock.speed(), 1.0);
+    }
+}
@@ -0,0 +1,85 @@
+//! The generated C code carries the tuned gains, discretized at its sample period.
+use digital_twin_playground::{
+    codegen,
+    control::PidGains,
+    lqr::{LqrDesign, LqrSettings},
+    pid_controller::PidSettings,
+};
+
+#[test]
+fn position_loops_carry_their_gains_and_limits() {
+    let settings = PidSettings {
+        gains: PidGains {
+            kp: 5.0,
+            ki: 0.5,
+            kd: 0.125,
+        },
+        limit: 10.0,
+        ..PidSettings::default()
+    };
+    let generated = codegen::pid(&settings, 0.001);
+    assert_eq!(generated.name, "pid");
+    assert!(generated.header.contains("#define PID_DT 0.001f"));
+    assert!(generated
+        .header
+        .contains("float pid_update(pid_state *state"));
+    for line in [
+        "static const float KP = 5.0f;",
+        "static const float KI = 0.5f;",
+        "static const float KD = 0.125f;",
+        "static const float MIN = -10.0f;",
+        "static const float MAX = 10.0f;",
+    ] {
+        assert!(generated.source.contains(line), "no `{line}`");
+    }
+
+    // Unlimited.
+    let settings = PidSettings {
+        limit: 0.0,
+        ..settings
+    };
+    let generated = codegen::pid(&settings, 0.001);
+    assert!(generated
+        .source
+        .contains("static const float MAX = INFINITY;"));
+}
+
+#[test]
+fn regulators_carry_the_gain_solved_at_their_period() {
+    let settings = LqrSettings::default();
+    let generated = codegen::lqr(&settings, 0.002).unwrap();
+    let gain = settings.solve(0.002).unwrap().gain;
+    let gain: Vec<String> = gain
+        .iter()
+        .map(|gain| format!("{:?}f", *gain as f32))
+        .collect();
+    let line = format!("static const float K[4] = {{{}}};", gain.join(", "));
+    assert!(generated.source.contains(&line), "no `{line}`");
+    assert!(generated.header.contains("#define LQR_DT 0.002f"));
+
+    let directory = std::env::temp_dir().join("codegen_lqr");
+    let [header, source] = generated.write(&directory).unwrap();
+    assert_eq!(std::fs::read_to_string(header).unwrap(), generated.header);
+    assert_eq!(std::fs::read_to_string(source).unwrap(), generated.source);
+
+    // The servo form integrates the arm angle, and feeds it back too.
+    let servo = LqrSettings {
+        design: LqrDesign {
+            integral: Some(1.0),
+            ..Default::default()
+        },
+        ..LqrSettings::default()
+    };
+    let generated = codegen::lqr(&servo, 0.002).unwrap();
+    assert!(generated.source.contains("static const float K[5] = {"));
+    assert!(generated
+        .source
+        .contains("    state->integral += state->arm * LQR_DT;\n    float command"));
+    assert!(generated.source.contains(" + K[4] * state->integral);"));
+
+    let invalid = LqrSettings {
+        r: 0.0,
+        ..LqrSettings::default()
+    };
+    assert!(codegen::lqr(&invalid, 0.002).is_err());
+}
@@ -0,0 +1,72 @@
+//! The meshes of the links get colliders of the shape chosen for them, fitted to the meshes.
+use bevy::prelude::*;
+use digital_twin_playground::{
+    colliders::{
+        fit_primitive, generate, ColliderSettings, ColliderShape, Decomposition, LinkCollider,
+    },
+    joint_builder::LinkMetadata,
+};
+
+#[test]
+fn the_configuration_overrides_the_model() {
+    let link = LinkMetadata::parse("link_base", Some(r#"{"collider": "convex_decomposition"}"#))
+        .unwrap()
+        .unwrap();
+    assert_eq!(link.collider, Some(ColliderShape::ConvexDecomposition));
+
+    let settings = ColliderSettings {
+        shape: ColliderShape::Box,
+        links: vec![LinkCollider {
+            link: "arm".to_string(),
+            shape: ColliderShape::Trimesh,
+        }],
+        ..Default::default()
+    };
+    assert_eq!(settings.shape("pendulum", None), ColliderShape::Box);
+    assert_eq!(
+        settings.shape("pendulum", link.collider),
+        ColliderShape::ConvexDecomposition
+    );
+    assert_eq!(
+        settings.shape("arm", Some(ColliderShape::Capsule)),
+        ColliderShape::Trimesh
+    );
+}
+
+#[test]
+fn primitives_fit_the_bounds() {
+    let cuboid = fit_primitive(ColliderShape::Box, Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0)).unwrap();
+    assert_eq!(
+        cuboid.as_cuboid().unwrap().half_extents(),
+        Vec3::new(1.0, 2.0, 3.0)
+    );
+
+    // Along the longest side, as thick as the widest other one.
+    let center = Vec3::new(0.0, 1.0, 0.0);
+    let capsule = fit_primitive(ColliderShape::Capsule, center, Vec3::new(0.2, 1.0, 0.3)).unwrap();
+    let capsule = capsule.as_capsule().unwrap();
+    assert_eq!(capsule.radius(), 0.3);
+    assert_eq!(capsule.segment().a(), Vec3::new(0.0, 0.3, 0.0));
+    assert_eq!(capsule.segment().b(), Vec3::new(0.0, 1.7, 0.0));
+
+    assert!(fit_primitive(ColliderShape::ConvexHull, Vec3::ZERO, Vec3::ONE).is_none());
+}
+
+#[test]
+fn colliders_are_generated_from_meshes() {
+    let mesh = Mesh::from(Cuboid::new(2.0, 4.0, 6.0));
+    for shape in ColliderShape::ALL {
+        assert!(
+            generate(shape, &mesh, Decomposition::default()).is_some(),
+            "{}",
+            shape.name()
+        );
+    }
+    let cuboid = generate(ColliderShape::Box, &mesh, Decomposition::default()).unwrap();
+    assert_eq!(
+        cuboid.as_cuboid().unwrap().half_extents(),
+        Vec3::new(1.0, 2.0, 3.0)
+    );
+    let hull = generate(ColliderShape::ConvexHull, &mesh, Decomposition::default()).unwrap();
+    assert_eq!(hull.as_convex_polyhedron().unwrap().points().count(), 8);
+}
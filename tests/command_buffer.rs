@@ -0,0 +1,34 @@
+//! Time-stamped commands wait for their time, in order.
+use digital_twin_playground::command_buffer::{CommandBuffer, TimedCommand};
+
+fn command(time: f32, value: f32) -> TimedCommand {
+    TimedCommand {
+        time,
+        setpoint: "motor/velocity".to_string(),
+        value,
+    }
+}
+
+#[test]
+fn commands_are_due_at_their_time() {
+    let mut buffer = CommandBuffer::default();
+    buffer.schedule(command(0.5, 2.0), 0.0);
+    buffer.schedule(command(0.2, 1.0), 0.0);
+    buffer.schedule(command(0.5, 3.0), 0.1);
+    assert_eq!(buffer.len(), 3);
+
+    assert!(buffer.due(0.1).is_empty());
+    assert_eq!(buffer.due(0.2), [command(0.2, 1.0)]);
+    // Commands of the same time keep their order of arrival.
+    assert_eq!(buffer.due(1.0), [command(0.5, 2.0), command(0.5, 3.0)]);
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.late, 0);
+}
+
+#[test]
+fn late_commands_are_due_right_away() {
+    let mut buffer = CommandBuffer::default();
+    buffer.schedule(command(1.0, 1.0), 1.5);
+    assert_eq!(buffer.late, 1);
+    assert_eq!(buffer.due(1.5), [command(1.0, 1.0)]);
+}
@@ -0,0 +1,82 @@
+//! Comparisons compose copies of a plant, each closed by its own controllers, whose links only
+//! collide within their copy.
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    comparison::{self, Comparison, MAX_COPIES},
+    plants,
+};
+
+const TUNINGS: &str = r#"{
+    "plant": "rotary_pendulum",
+    "spacing": [0.0, 0.0, 5.0],
+    "variants": [
+        {"name": "soft", "controllers": [{"measurement": "pendulum/angle",
+            "output": "motor/velocity", "gains": {"kp": 1.0, "ki": 0.0, "kd": 0.0}}]},
+        {"name": "stiff", "controllers": [{"measurement": "pendulum/angle",
+            "output": "motor/velocity", "gains": {"kp": 4.0, "ki": 0.0, "kd": 0.0}}]}
+    ]
+}"#;
+
+fn collide(a: CollisionGroups, b: CollisionGroups) -> bool {
+    !(a.memberships & b.filters).is_empty() && !(b.memberships & a.filters).is_empty()
+}
+
+#[test]
+fn copies_are_composed_side_by_side() {
+    let tunings: Comparison = serde_json::from_str(TUNINGS).unwrap();
+    tunings.validate(&plants::builtin()).unwrap();
+    let composition = tunings.composition();
+    let placed: Vec<_> = composition
+        .plants
+        .iter()
+        .map(|plant| (plant.name.as_str(), plant.plant.as_str(), plant.offset))
+        .collect();
+    assert_eq!(
+        placed,
+        vec![
+            ("soft", "rotary_pendulum", [0.0, 0.0, 0.0]),
+            ("stiff", "rotary_pendulum", [0.0, 0.0, 5.0]),
+        ]
+    );
+    assert_eq!(composition.plants[1].controllers[0].gains.kp, 4.0);
+
+    let single = Comparison {
+        variants: tunings.variants[..1].to_vec(),
+        ..tunings.clone()
+    };
+    assert!(single.validate(&plants::builtin()).is_err());
+    let mut twins = tunings.clone();
+    twins.variants[1].name = "soft".to_string();
+    assert!(twins.validate(&plants::builtin()).is_err());
+    let too_many = Comparison {
+        variants: (0..=MAX_COPIES)
+            .map(|index| {
+                let mut variant = tunings.variants[0].clone();
+                variant.name = format!("copy{index}");
+                variant
+            })
+            .collect(),
+        ..tunings
+    };
+    assert!(too_many.validate(&plants::builtin()).is_err());
+}
+
+#[test]
+fn links_only_collide_within_their_copy() {
+    let ground = CollisionGroups::default();
+    let first = comparison::copy_groups(0, CollisionGroups::default());
+    let second = comparison::copy_groups(1, CollisionGroups::default());
+    assert!(collide(first, ground) && collide(second, ground));
+    assert!(collide(first, first));
+    assert!(!collide(first, second));
+
+    // Links kept clear of each other within a plant stay clear.
+    let clear = CollisionGroups::new(Group::GROUP_2, Group::ALL ^ Group::GROUP_2);
+    let (upper, lower) = (
+        comparison::copy_groups(3, clear),
+        comparison::copy_groups(3, clear),
+    );
+    assert!(!collide(upper, lower));
+    assert!(collide(upper, ground));
+    assert!(!collide(upper, comparison::copy_groups(4, clear)));
+}
@@ -0,0 +1,192 @@
+//! Scene compositions are validated, and their plants step together with namespaced signals.
+use std::path::PathBuf;
+
+use digital_twin_playground::{
+    composition::{CompositeLoops, Composition, CompositionPlugin, MASTER_ANGLE},
+    control::Coupling,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+const LINE: &str = r#"{
+    "plants": [
+        {"name": "feeder", "plant": "rotary_pendulum"},
+        {"name": "conveyor", "plant": "rotary_pendulum", "offset": [10.0, 0.0, 0.0],
+         "controllers": [{"measurement": "motor/angle", "output": "motor/velocity",
+                          "setpoint": 1.0, "gains": {"kp": 5.0, "ki": 0.0, "kd": 0.0},
+                          "limit": 4.0}]},
+        {"name": "receiver", "plant": "rotary_pendulum", "offset": [20.0, 0.0, 0.0]}
+    ],
+    "links": [{"from": "feeder/motor/velocity", "to": "receiver/motor/velocity", "gain": -2.0}]
+}"#;
+
+fn line() -> Composition {
+    serde_json::from_str(LINE).unwrap()
+}
+
+#[test]
+fn signals_are_namespaced() {
+    assert_eq!(
+        plants::namespaced("feeder", "motor/velocity"),
+        "feeder/motor/velocity"
+    );
+    assert_eq!(plants::namespaced("", "motor/velocity"), "motor/velocity");
+}
+
+#[test]
+fn invalid_compositions_are_rejected() {
+    let builtin = plants::builtin();
+    let mut composition = line();
+    if builtin.is_empty() {
+        assert!(composition.validate(&builtin).is_err());
+        return;
+    }
+    assert!(composition.validate(&builtin).is_ok());
+
+    composition.plants[1].name = "feeder".to_string();
+    assert!(composition.validate(&builtin).is_err());
+
+    let mut composition = line();
+    composition.plants[0].plant = "crane".to_string();
+    assert!(composition.validate(&builtin).is_err());
+
+    assert!(Composition::default().validate(&builtin).is_err());
+
+    // Connections must name the plants of the composition.
+    let mut composition = line();
+    composition.links[0].to = "crane/motor/velocity".to_string();
+    assert!(composition.validate(&builtin).is_err());
+
+    // A setpoint is driven by a single link or controller.
+    let mut composition = line();
+    composition.links[0].to = "conveyor/motor/velocity".to_string();
+    assert!(composition.validate(&builtin).is_err());
+}
+
+#[test]
+fn controllers_and_links_drive_the_setpoints() {
+    let mut loops = CompositeLoops::new(&line());
+    let mut telemetry = Telemetry::default();
+    let mut setpoints = Setpoints::default();
+    telemetry.record("conveyor/motor/angle", 0.0, 0.0);
+    setpoints.set("feeder/motor/velocity", 3.0);
+
+    loops.step(&mut telemetry, &mut setpoints, 0.0, DEFAULT_TIME_STEP);
+    // Saturated proportional control towards 1 rad.
+    assert_eq!(setpoints.get("conveyor/motor/velocity"), Some(4.0));
+    assert_eq!(setpoints.get("receiver/motor/velocity"), Some(-6.0));
+    // The loop of a plant doesn't leak into the others.
+    assert_eq!(setpoints.get("motor/velocity"), None);
+}
+
+#[test]
+fn routed_references_drive_the_controllers() {
+    let mut composition = line();
+    composition.plants[1].controllers[0].name = "tracking".to_string();
+    composition.plants[1].controllers[0].measurement = "motor/velocity".to_string();
+    composition.plants[1].controllers[0].output = "motor/torque".to_string();
+    composition.links[0].to = "conveyor/tracking/reference".to_string();
+    assert!(composition.validate(&plants::builtin()).is_ok() || plants::builtin().is_empty());
+
+    let mut loops = CompositeLoops::new(&composition);
+    let mut telemetry = Telemetry::default();
+    let mut setpoints = Setpoints::default();
+    setpoints.set("conveyor/motor/velocity", 0.0);
+    setpoints.set("feeder/motor/velocity", 0.5);
+
+    loops.step(&mut telemetry, &mut setpoints, 0.0, DEFAULT_TIME_STEP);
+    // The conveyor tracks the routed speed of the feeder, not its fixed setpoint, in the same step.
+    assert_eq!(setpoints.get("conveyor/tracking/reference"), Some(-1.0));
+    assert_eq!(setpoints.get("conveyor/motor/torque"), Some(-4.0));
+}
+
+#[test]
+fn composed_plants_step_together() {
+    if plants::builtin().is_empty() {
+        return;
+    }
+    let mut composition = line();
+    composition.links[0].to = "conveyor/motor/velocity".to_string();
+    composition.plants[1].controllers.clear();
+
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(CompositionPlugin { composition });
+    app.finish();
+    app.cleanup();
+    app.world_mut()
+        .resource_mut::<Setpoints>()
+        .set("feeder/motor/velocity", 2.0);
+    for _ in 0..60 {
+        app.update();
+    }
+
+    let telemetry = app.world().resource::<Telemetry>();
+    let feeder = telemetry.latest("feeder/motor/angle").unwrap();
+    let conveyor = telemetry.latest("conveyor/motor/angle").unwrap();
+    assert!(feeder > 0.5, "{feeder}");
+    // The conveyor turns the other way, twice as fast.
+    assert!(conveyor < -1.0, "{conveyor}");
+    assert!(telemetry.latest("motor/angle").is_none());
+}
+
+#[test]
+fn cams_repeat_with_their_rise() {
+    let cam = Coupling::Cam {
+        points: vec![[0.0, 0.0], [1.0, 0.0], [2.0, 1.0]],
+    };
+    assert!(cam.is_valid());
+    assert_eq!(cam.follow(0.5, 1.0), (0.0, 0.0));
+    assert_eq!(cam.follow(1.5, 1.0), (0.5, 1.0));
+    // The next cycle starts where the last one ended, and the one before too.
+    assert_eq!(cam.follow(3.5, 2.0), (1.5, 2.0));
+    assert_eq!(cam.follow(-0.5, 1.0), (-0.5, 1.0));
+
+    let gear = Coupling::Gear {
+        ratio: -0.5,
+        offset: 1.0,
+    };
+    assert_eq!(gear.follow(2.0, 4.0), (0.0, -2.0));
+    assert!(!Coupling::Cam {
+        points: vec![[1.0, 0.0], [1.0, 1.0]]
+    }
+    .is_valid());
+}
+
+#[test]
+fn line_shaft_axes_stay_in_phase() {
+    if plants::builtin().is_empty() {
+        return;
+    }
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/compositions/line_shaft.json");
+    let composition = Composition::read(&path).unwrap();
+    composition.validate(&plants::builtin()).unwrap();
+    let axes: Vec<String> = composition
+        .plants
+        .iter()
+        .map(|plant| plants::namespaced(&plant.name, "phase_error"))
+        .collect();
+
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(CompositionPlugin { composition });
+    app.finish();
+    app.cleanup();
+    for _ in 0..300 {
+        app.update();
+    }
+
+    let telemetry = app.world().resource::<Telemetry>();
+    let master = telemetry.latest(MASTER_ANGLE).unwrap();
+    assert!((master - 5.0).abs() < 0.1, "{master}");
+    for axis in &axes {
+        // Settled over the last second, dwells and rises of the cam included.
+        let samples = &telemetry.channels[axis];
+        let worst = samples[samples.len() - 60..]
+            .iter()
+            .map(|[_, error]| error.abs())
+            .fold(0.0f32, f32::max);
+        assert!(worst < 0.2, "{axis}: {worst}");
+    }
+}
@@ -0,0 +1,80 @@
+//! Contacts of the links are followed from start to end, with their peak force and duration.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    contacts::{Contact, Contacts, ContactsPlugin, HISTORY_LENGTH},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants::Link,
+};
+
+fn link(name: &str, x: f32) -> impl Bundle {
+    (
+        RigidBody::Dynamic,
+        Collider::ball(0.5),
+        GravityScale(0.0),
+        Transform::from_xyz(x, 0.0, 0.0),
+        Link {
+            plant: String::new(),
+            name: name.to_string(),
+        },
+    )
+}
+
+#[test]
+fn contacts_are_reported_as_they_end() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(ContactsPlugin);
+    app.finish();
+    app.cleanup();
+    app.update();
+    // Overlapping, the links push each other apart.
+    app.world_mut().spawn(link("first", 0.0));
+    app.world_mut().spawn(link("second", 0.9));
+    app.update();
+    app.update();
+
+    let contacts = app.world().resource::<Contacts>();
+    assert_eq!(contacts.current.len(), 1);
+    assert_eq!(contacts.count, 1);
+    let contact = contacts.current.values().next().unwrap();
+    let mut names = contact.names.clone();
+    names.sort();
+    assert_eq!(names, ["first".to_string(), "second".to_string()]);
+    assert!(contact.peak_force > 0.0);
+
+    for _ in 0..100 {
+        app.update();
+    }
+    let contacts = app.world().resource::<Contacts>();
+    assert!(contacts.current.is_empty());
+    assert_eq!(contacts.count, 1);
+    let [contact] = contacts.history.iter().collect::<Vec<_>>()[..] else {
+        panic!("one contact expected, got {:?}", contacts.history);
+    };
+    assert!(contact.duration > 0.0);
+    assert!(contact.peak_force > 0.0);
+}
+
+#[test]
+fn contacts_keep_their_peak_force() {
+    let bodies = [Entity::from_raw(1), Entity::from_raw(2)];
+    let names = ["arm".to_string(), "end_stop".to_string()];
+    let mut contact = Contact::new(bodies, names, 1.0, 2.0);
+    contact.update(1.5, 10.0);
+    contact.update(2.0, 4.0);
+    assert_eq!((contact.force, contact.peak_force), (4.0, 10.0));
+    assert_eq!(contact.duration, 1.0);
+    assert_eq!(contact.channel(), "contact/arm-end_stop");
+
+    let mut contacts = Contacts::default();
+    for index in 0..=HISTORY_LENGTH {
+        contacts.current.insert(bodies, contact.clone());
+        let ended = contacts.end(bodies, 3.0 + index as f32).unwrap();
+        assert_eq!(ended.duration, 2.0 + index as f32);
+    }
+    // The oldest are dropped.
+    assert_eq!(contacts.history.len(), HISTORY_LENGTH);
+    assert_eq!(contacts.history[0].duration, 3.0);
+    contacts.clear();
+    assert!(contacts.history.is_empty());
+}
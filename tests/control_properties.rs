@@ -0,0 +1,134 @@
+//! Property-based tests of the control blocks.
+//!
+//! Requires the `mcp-core` feature: `cargo test --features mcp-core`.
+use digital_twin_playground::control::{
+    strategies::*, Discretization, LowPassFilter, NotchFilter, Pid, PidGains, Saturation,
+};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn saturation_bounds_output(limits in saturation(), value in -1e6f32..1e6) {
+        let output = limits.apply(value);
+        prop_assert!(output >= limits.min && output <= limits.max);
+        prop_assert_eq!(limits.apply(output), output);
+        if value >= limits.min && value <= limits.max {
+            prop_assert_eq!(output, value);
+        }
+    }
+
+    #[test]
+    fn saturation_is_monotonic(limits in saturation(), a in -1e3f32..1e3, b in -1e3f32..1e3) {
+        let (low, high) = if a <= b { (a, b) } else { (b, a) };
+        prop_assert!(limits.apply(low) <= limits.apply(high));
+    }
+
+    #[test]
+    fn pid_output_and_integral_stay_within_limits(
+        gains in pid_gains(),
+        limits in saturation(),
+        dt in sample_time(),
+        setpoints in signal(200),
+        measurements in signal(200),
+    ) {
+        let mut pid = Pid::new(gains, limits);
+        for (setpoint, measurement) in setpoints.into_iter().zip(measurements) {
+            let output = pid.update(setpoint, measurement, dt);
+            prop_assert!(output >= limits.min && output <= limits.max);
+            prop_assert!(pid.integral() >= limits.min && pid.integral() <= limits.max);
+        }
+    }
+
+    #[test]
+    fn pid_recovers_from_saturation(
+        ki in 1.0f32..100.0,
+        dt in sample_time(),
+        error in 1.0f32..100.0,
+    ) {
+        let gains = PidGains { kp: 0.0, ki, kd: 0.0 };
+        let limits = Saturation::symmetric(1.0);
+        let mut pid = Pid::new(gains, limits);
+        // Drive the output into saturation for a long time.
+        for _ in 0..1_000 {
+            pid.update(error, 0.0, dt);
+        }
+        // Without windup, the integral can't exceed the limit, so reversing the error takes
+        // the integrator back to zero in a bounded number of steps.
+        let bound = (limits.max / (ki * error * dt)).ceil() as usize + 1;
+        let mut steps = 0;
+        while pid.integral() > 0.0 && steps <= bound {
+            pid.update(-error, 0.0, dt);
+            steps += 1;
+        }
+        prop_assert!(steps <= bound, "integrator took {steps} steps to unwind (bound {bound})");
+    }
+
+    #[test]
+    fn stable_filters_keep_bounded_inputs_bounded(
+        cutoff in cutoff_frequency(),
+        dt in sample_time(),
+        discretization in discretization(),
+        input in signal(500),
+    ) {
+        let mut filter = LowPassFilter::new(cutoff, dt, discretization);
+        let wc_dt = std::f32::consts::TAU * cutoff * dt;
+        // Where these hold, the filter output is a convex combination of past inputs.
+        let convex = match discretization {
+            Discretization::ForwardEuler => wc_dt <= 1.0,
+            Discretization::ZeroOrderHold => true,
+            Discretization::Tustin => wc_dt <= 2.0,
+        };
+        prop_assume!(convex);
+        prop_assert!(filter.is_stable());
+        let bound = input.iter().fold(0.0f32, |bound, value| bound.max(value.abs()));
+        for value in input {
+            let output = filter.update(value);
+            prop_assert!(output.abs() <= bound * (1.0 + 1e-4) + 1e-4);
+        }
+    }
+
+    #[test]
+    fn exact_discretizations_are_always_stable(
+        cutoff in cutoff_frequency(),
+        dt in sample_time(),
+    ) {
+        prop_assert!(LowPassFilter::new(cutoff, dt, Discretization::ZeroOrderHold).is_stable());
+        prop_assert!(LowPassFilter::new(cutoff, dt, Discretization::Tustin).is_stable());
+    }
+
+    #[test]
+    fn discretizations_agree_at_high_rates(
+        cutoff in cutoff_frequency(),
+        discretization in discretization(),
+    ) {
+        let wc = std::f32::consts::TAU * cutoff;
+        // A thousand samples per time constant.
+        let dt = 1e-3 / wc;
+        let mut filter = LowPassFilter::new(cutoff, dt, discretization);
+        for step in 1..=5_000 {
+            let output = filter.update(1.0);
+            let exact = 1.0 - (-wc * dt * step as f32).exp();
+            prop_assert!(
+                (output - exact).abs() < 1e-2,
+                "step {step}: {output} vs continuous {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn notches_are_stable_and_pass_constants(
+        frequency in cutoff_frequency(),
+        dt in sample_time(),
+        zero_damping in 0.0f32..0.5,
+        pole_damping in 0.1f32..1.0,
+        value in -1e3f32..1e3,
+    ) {
+        // Below the Nyquist frequency.
+        prop_assume!(frequency * dt < 0.45);
+        let mut filter = NotchFilter::new(frequency, zero_damping, pole_damping, dt);
+        prop_assert!(filter.is_stable());
+        filter.reset(value);
+        let output = filter.update(value);
+        prop_assert!((output - value).abs() <= 1e-3 * (1.0 + value.abs()), "{output} vs {value}");
+    }
+}
@@ -0,0 +1,105 @@
+//! Controllers convert to and from their definitions, in the format shared by the panels, the
+//! code generation and the sessions.
+use digital_twin_playground::{
+    codegen,
+    control::PidGains,
+    controller_definition::{self, ControllerDefinition, ControllerType, LQR_PENDULUM},
+    error::Error,
+    lqr::{LqrDesign, LqrSettings, Riccati, WeightedOutput},
+    pid_controller::PidSettings,
+};
+
+#[test]
+fn settings_round_trip_through_definitions() {
+    let pid = PidSettings {
+        enabled: false,
+        link: "wrist".to_string(),
+        gains: PidGains {
+            kp: 3.0,
+            ki: 0.25,
+            kd: 0.5,
+        },
+        limit: 4.0,
+    };
+    let definition = ControllerDefinition::from(&pid);
+    assert_eq!(definition.controller.name(), "pid");
+    assert_eq!(definition.binding("joint").unwrap(), "wrist");
+    assert_eq!(
+        PidSettings::try_from(&definition).unwrap(),
+        PidSettings {
+            enabled: true,
+            ..pid
+        }
+    );
+
+    let lqr = LqrSettings {
+        r: 2.0,
+        design: LqrDesign {
+            integral: Some(0.5),
+            outputs: WeightedOutput::angles(),
+            riccati: Riccati::Continuous,
+        },
+        sample_rate: Some(200.0),
+        ..LqrSettings::default()
+    };
+    let definition = ControllerDefinition::from(&lqr);
+    assert_eq!(definition.sample_period(0.01), 0.005);
+    assert_eq!(
+        LqrSettings::try_from(&definition).unwrap(),
+        LqrSettings {
+            enabled: true,
+            ..lqr
+        }
+    );
+    // A regulator isn't a position loop.
+    assert!(matches!(
+        PidSettings::try_from(&definition),
+        Err(Error::Config { .. })
+    ));
+
+    let mut unbound = definition;
+    unbound.bindings.remove(LQR_PENDULUM);
+    assert!(matches!(
+        LqrSettings::try_from(&unbound),
+        Err(Error::Config { .. })
+    ));
+}
+
+#[test]
+fn definitions_are_read_from_json() {
+    let definition: ControllerDefinition = serde_json::from_str(
+        r#"{
+            "name": "fast",
+            "type": "pid",
+            "gains": { "kp": 5.0, "ki": 0.5, "kd": 0.125 },
+            "limit": 10.0,
+            "sample_rate": 1000.0,
+            "bindings": { "joint": "arm" }
+        }"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        definition.controller,
+        ControllerType::Pid { limit, .. } if limit == 10.0
+    ));
+    assert_eq!(definition.sample_period(0.01), 0.001);
+
+    let json = serde_json::to_value(&definition).unwrap();
+    assert_eq!(json["type"], "pid");
+    assert_eq!(
+        serde_json::from_value::<ControllerDefinition>(json).unwrap(),
+        definition
+    );
+
+    // The code is generated at the rate of the definition.
+    let generated = codegen::generate(&definition, 0.01).unwrap();
+    assert!(generated.header.contains("#define PID_DT 0.001f"));
+    assert!(generated.header.contains("Position loop of `arm`"));
+
+    let directory = std::env::temp_dir().join("controller_definition");
+    let path = directory.join("fast.json");
+    definition.write(&path).unwrap();
+    assert_eq!(ControllerDefinition::read(&path).unwrap(), definition);
+    let definitions = controller_definition::definitions(&directory);
+    assert_eq!(definitions, vec![(path, definition)]);
+}
@@ -0,0 +1,88 @@
+//! Cost scripts score the runs of a sweep over their recorded signals.
+use std::{collections::BTreeMap, path::Path};
+
+use digital_twin_playground::{
+    cost_script::CostScript,
+    resource_usage::ResourceUsage,
+    sweep::{self, Cost, Metrics, SweepRun},
+    telemetry::Telemetry,
+};
+
+fn run(kp: f32) -> SweepRun {
+    SweepRun {
+        parameters: BTreeMap::from([("pid/kp".to_string(), kp)]),
+        metrics: Some(Metrics {
+            settling_time: None,
+            overshoot: 0.25,
+            rms_error: 0.5,
+        }),
+        energy: None,
+        violations: 2,
+        usage: ResourceUsage::default(),
+        scripted: None,
+    }
+}
+
+fn telemetry() -> Telemetry {
+    let mut telemetry = Telemetry::default();
+    telemetry.channels.insert(
+        "motor/angle".to_string(),
+        vec![[0.0, 0.0], [0.5, 0.5], [1.0, 1.5]],
+    );
+    telemetry
+}
+
+fn compile(source: &str) -> CostScript {
+    CostScript::compile(source, Path::new("cost.rhai")).unwrap()
+}
+
+#[test]
+fn scripts_see_the_signals_and_scores_of_the_run() {
+    let script = compile(
+        r#"
+        fn cost(run) {
+            let angle = run.signals["motor/angle"];
+            let error = 0.0;
+            for i in 0..angle.value.len() {
+                error += angle.time[i] * abs(angle.value[i] - 1.0);
+            }
+            let settled = if run.metrics.settling_time == () { 100.0 } else { 0.0 };
+            error + run.parameters["pid/kp"] + run.metrics.overshoot + run.violations
+                + settled + run.duration
+        }
+        "#,
+    );
+    let cost = script.cost(&run(3.0), &telemetry(), 2.0).unwrap();
+    // 0.25 + 0.5 of error, 3 of gain, 0.25 of overshoot, 2 violations, unsettled, 2 s.
+    assert!((cost - 108.0).abs() < 1e-4, "{cost}");
+}
+
+#[test]
+fn scripts_need_the_cost_function() {
+    assert!(CostScript::compile("fn score(run) { 0.0 }", Path::new("cost.rhai")).is_err());
+    assert!(CostScript::compile("fn cost() { 0.0 }", Path::new("cost.rhai")).is_err());
+    assert!(CostScript::compile("fn cost(run) {", Path::new("cost.rhai")).is_err());
+}
+
+#[test]
+fn costs_that_are_not_numbers_rank_last() {
+    let telemetry = telemetry();
+    let cost = |source: &str| compile(source).cost(&run(1.0), &telemetry, 1.0).unwrap();
+    assert_eq!(cost("fn cost(run) { 3 }"), 3.0);
+    assert_eq!(cost("fn cost(run) { \"low\" }"), f32::INFINITY);
+    assert_eq!(cost("fn cost(run) { sqrt(-1.0) }"), f32::INFINITY);
+    assert!(compile("fn cost(run) { throw \"no\" }")
+        .cost(&run(1.0), &telemetry, 1.0)
+        .is_err());
+}
+
+#[test]
+fn runs_rank_by_their_scripted_cost() {
+    let scripted = |cost: Option<f32>| SweepRun {
+        scripted: cost,
+        ..run(1.0)
+    };
+    let runs = [scripted(Some(2.0)), scripted(None), scripted(Some(0.5))];
+    assert_eq!(sweep::ranking(&runs, Cost::Script), vec![2, 0, 1]);
+    assert_eq!(runs[0].cost(Cost::Script), 2.0);
+}
@@ -0,0 +1,70 @@
+//! Coupling the loops of two parallel axes through their synchronization error keeps the beam
+//! they carry square, where independent loops let it rack.
+use digital_twin_playground::control::{CrossCoupling, Pid, PidGains, Saturation};
+
+const DT: f32 = 1e-3;
+
+/// Two carriages with unequal friction, tied by a beam that resists racking like a spring.
+#[derive(Default)]
+struct Gantry {
+    positions: [f32; 2],
+    velocities: [f32; 2],
+}
+
+impl Gantry {
+    const MASS: f32 = 1.0;
+    const FRICTION: [f32; 2] = [5.0, 15.0];
+    const BEAM_STIFFNESS: f32 = 200.0;
+
+    fn step(&mut self, forces: [f32; 2]) {
+        let racking = Self::BEAM_STIFFNESS * (self.positions[0] - self.positions[1]);
+        let beam = [-racking, racking];
+        for axis in 0..2 {
+            let force = forces[axis] + beam[axis] - Self::FRICTION[axis] * self.velocities[axis];
+            self.velocities[axis] += force / Self::MASS * DT;
+            self.positions[axis] += self.velocities[axis] * DT;
+        }
+    }
+}
+
+fn pid(kp: f32, ki: f32, kd: f32) -> Pid {
+    Pid::new(PidGains { kp, ki, kd }, Saturation::symmetric(500.0))
+}
+
+/// Runs both axes along a ramp, then holds; returns the largest synchronization error.
+fn peak_racking(coupling: Pid) -> f32 {
+    let axis = pid(400.0, 200.0, 40.0);
+    let mut controller = CrossCoupling::parallel([axis.clone(), axis], coupling);
+    let mut gantry = Gantry::default();
+    let mut peak = 0.0f32;
+    for step in 0..4_000 {
+        let setpoint = 0.5 * (step as f32 * DT).min(2.0);
+        let forces = controller.update([setpoint; 2], gantry.positions, DT);
+        gantry.step(forces);
+        peak = peak.max(controller.contour_error().abs());
+    }
+    peak
+}
+
+#[test]
+fn coupling_reduces_racking() {
+    let independent = peak_racking(pid(0.0, 0.0, 0.0));
+    let coupled = peak_racking(pid(1_000.0, 500.0, 20.0));
+    assert!(coupled < 0.5 * independent, "{coupled} vs {independent}");
+}
+
+#[test]
+fn linear_contour_error_is_the_distance_to_the_line() {
+    let mut controller = CrossCoupling::linear(
+        [pid(1.0, 0.0, 0.0), pid(1.0, 0.0, 0.0)],
+        pid(0.0, 0.0, 0.0),
+        std::f32::consts::FRAC_PI_4,
+    );
+    // On the diagonal, behind the setpoint: no contour error.
+    controller.update([1.0, 1.0], [0.5, 0.5], DT);
+    assert!(controller.contour_error().abs() < 1e-6);
+    // Off the diagonal by one unit.
+    let offset = std::f32::consts::FRAC_1_SQRT_2;
+    controller.update([1.0, 1.0], [1.0 + offset, 1.0 - offset], DT);
+    assert!((controller.contour_error().abs() - 1.0).abs() < 1e-6);
+}
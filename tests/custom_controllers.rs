@@ -0,0 +1,96 @@
+//! Custom controllers are registered by name and stepped on the state of their joints.
+use bevy::prelude::Entity;
+use digital_twin_playground::{
+    custom_controllers::{
+        self, Controller, CustomController, CustomJoint, HoldController, JointState,
+    },
+    error::Error,
+    plants::Link,
+};
+
+/// Counts its steps, and returns one torque too many after the first.
+#[derive(Default)]
+struct Counting {
+    links: usize,
+    steps: usize,
+}
+
+impl Controller for Counting {
+    fn init(&mut self, links: &[String]) {
+        self.links = links.len();
+    }
+
+    fn reset(&mut self) {
+        self.steps = 0;
+    }
+
+    fn step(&mut self, _state: &[JointState], _dt: f32) -> Vec<f32> {
+        self.steps += 1;
+        vec![self.steps as f32; self.links + usize::from(self.steps > 1)]
+    }
+}
+
+fn joint(name: &str) -> CustomJoint {
+    let link = Link {
+        plant: "rotary_pendulum".to_string(),
+        name: name.to_string(),
+    };
+    CustomJoint::new(Entity::PLACEHOLDER, &link)
+}
+
+#[test]
+fn controllers_are_registered_by_name() {
+    custom_controllers::register("test_counting", "Counts its steps", || {
+        Box::<Counting>::default()
+    })
+    .unwrap();
+    assert!(custom_controllers::find("test_counting").is_some());
+    assert!(custom_controllers::find("hold").is_some());
+    assert!(matches!(
+        custom_controllers::register("hold", "Taken", || Box::<Counting>::default()),
+        Err(Error::Config { .. })
+    ));
+
+    let registration = custom_controllers::find("test_counting").unwrap();
+    let mut controller = CustomController::new(&registration, vec![joint("motor")]);
+    assert_eq!(controller.joints[0].channel, "rotary_pendulum/custom/motor");
+    assert_eq!(controller.step(&[0.0], 0.01), Ok(vec![1.0]));
+    assert!(controller.step(&[0.0], 0.01).is_err());
+    controller.reset();
+    assert_eq!(controller.step(&[0.0], 0.01), Ok(vec![1.0]));
+}
+
+#[test]
+fn joints_are_measured_over_the_turns() {
+    let mut joint = joint("motor");
+    assert_eq!(
+        joint.measure(3.1, 0.1),
+        JointState {
+            position: 3.1,
+            velocity: 0.0
+        }
+    );
+    // Wrapping past π goes on turning forwards.
+    let state = joint.measure(-3.1, 0.1);
+    assert!((state.position - (std::f32::consts::TAU - 3.1)).abs() < 1e-5);
+    assert!(state.velocity > 0.0);
+}
+
+#[test]
+fn hold_pulls_the_joints_back_to_zero() {
+    let mut hold = HoldController::default();
+    let torques = hold.step(
+        &[
+            JointState {
+                position: 1.0,
+                velocity: 0.0,
+            },
+            JointState {
+                position: 0.0,
+                velocity: -1.0,
+            },
+        ],
+        0.01,
+    );
+    assert_eq!(torques, vec![-2.0, 0.2]);
+}
@@ -0,0 +1,122 @@
+//! A browser loads the dashboard, follows the telemetry and pauses the run.
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    clock::SimClock,
+    dashboard::{
+        Dashboard, DashboardCommand, DashboardMessage, DashboardPlugin, MAX_HEADER_LENGTH,
+    },
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+use tungstenite::Message;
+
+/// A dashboard on a port picked by the system, and its address.
+fn dashboard(read_only: bool) -> (App, SocketAddr) {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(DashboardPlugin {
+        bind: "127.0.0.1:0".parse().unwrap(),
+        read_only,
+    });
+    app.finish();
+    app.cleanup();
+    let address = app.world().resource::<Dashboard>().address;
+    (app, address)
+}
+
+/// Updates the application until `done` holds.
+fn run_until(app: &mut App, done: impl Fn(&App) -> bool) {
+    for _ in 0..2_000 {
+        app.update();
+        if done(app) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+    panic!("the dashboard doesn't make progress");
+}
+
+fn get(address: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: {address}\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn pages_follow_and_command_the_run() {
+    let (mut app, address) = dashboard(false);
+
+    // The page is served by a thread of the dashboard.
+    let page = get(address, "/");
+    assert!(page.starts_with("HTTP/1.1 200 OK"), "{page}");
+    assert!(page.contains("new WebSocket"));
+    assert!(get(address, "/missing").starts_with("HTTP/1.1 404"));
+
+    app.world_mut()
+        .resource_mut::<Telemetry>()
+        .record("test/value", 0.0, 1.5);
+    let (mut socket, _) = tungstenite::connect(format!("ws://{address}/ws")).unwrap();
+    run_until(&mut app, |app| {
+        !app.world().resource::<Dashboard>().viewers.is_empty()
+    });
+    // The first update holds the recent telemetry.
+    for _ in 0..100 {
+        app.update();
+        thread::sleep(Duration::from_millis(5));
+    }
+    let text = socket.read().unwrap().into_text().unwrap();
+    let DashboardMessage::Update { channels, .. } = serde_json::from_str(&text).unwrap();
+    assert_eq!(channels["test/value"], vec![[0.0, 1.5]]);
+
+    for command in [
+        DashboardCommand::Setpoint {
+            name: MOTOR_VELOCITY.to_string(),
+            value: 2.5,
+        },
+        DashboardCommand::Pause,
+    ] {
+        let text = serde_json::to_string(&command).unwrap();
+        socket.send(Message::text(text)).unwrap();
+    }
+    run_until(&mut app, |app| {
+        app.world().resource::<SimClock>().is_paused()
+            && app.world().resource::<Setpoints>().get(MOTOR_VELOCITY) == Some(2.5)
+    });
+}
+
+#[test]
+fn read_only_dashboards_ignore_commands() {
+    let (mut app, address) = dashboard(true);
+    let (mut socket, _) = tungstenite::connect(format!("ws://{address}/ws")).unwrap();
+    run_until(&mut app, |app| {
+        !app.world().resource::<Dashboard>().viewers.is_empty()
+    });
+    let text = serde_json::to_string(&DashboardCommand::Pause).unwrap();
+    socket.send(Message::text(text)).unwrap();
+    for _ in 0..50 {
+        app.update();
+        thread::sleep(Duration::from_millis(2));
+    }
+    assert!(!app.world().resource::<SimClock>().is_paused());
+}
+
+#[test]
+fn requests_with_oversized_headers_are_dropped() {
+    let (_app, address) = dashboard(true);
+    let mut stream = TcpStream::connect(address).unwrap();
+    let padding = "a".repeat(MAX_HEADER_LENGTH);
+    // The server may close the connection before the whole request is written.
+    let _ = write!(stream, "GET / HTTP/1.1\r\nX-Padding: {padding}\r\n\r\n");
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(!response.starts_with("HTTP/1.1 200"), "{response}");
+}
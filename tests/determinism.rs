@@ -0,0 +1,47 @@
+//! The reference scenario hashes identically when replayed, and comparisons locate divergences.
+use digital_twin_playground::{
+    determinism::{compare, run_reference, Comparison, CHECKPOINT_INTERVAL},
+    plants,
+};
+
+const STEPS: usize = 3 * CHECKPOINT_INTERVAL;
+
+#[test]
+fn replays_are_identical() {
+    for plant in plants::builtin() {
+        let first = run_reference(&plant, STEPS);
+        let second = run_reference(&plant, STEPS);
+        assert_eq!(first.hash, second.hash, "{}", plant.name);
+        assert_eq!(first.checkpoints.len(), 3);
+        assert_eq!(compare(&first, &second), Comparison::Identical);
+    }
+}
+
+#[test]
+fn comparison_locates_divergence() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let reference = run_reference(&plant, STEPS);
+
+    let mut drifted = reference.clone();
+    drifted.hash = "drifted".to_string();
+    for checkpoint in &mut drifted.checkpoints[1..] {
+        checkpoint.hash = "drifted".to_string();
+        checkpoint.poses[0][0] += 0.5;
+    }
+    assert_eq!(
+        compare(&reference, &drifted),
+        Comparison::Diverged {
+            step: 2 * CHECKPOINT_INTERVAL,
+            max_deviation: 0.5
+        }
+    );
+
+    let mut shorter = reference.clone();
+    shorter.steps = CHECKPOINT_INTERVAL;
+    assert!(matches!(
+        compare(&reference, &shorter),
+        Comparison::Incomparable(_)
+    ));
+}
@@ -0,0 +1,98 @@
+//! The cogging torque repeats with the rotor angle, and the imbalance force grows with the square
+//! of the speed.
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use digital_twin_playground::disturbances::{
+    DisturbanceSettings, Harmonic, Injection, InjectionKind,
+};
+
+fn harmonic(order: u32, amplitude: f32) -> Harmonic {
+    Harmonic {
+        order,
+        amplitude,
+        phase: 0.0,
+    }
+}
+
+#[test]
+fn cogging_repeats_with_the_slots() {
+    let settings = DisturbanceSettings {
+        cogging: vec![harmonic(6, 0.2), harmonic(12, 0.05)],
+        ..Default::default()
+    };
+    let period = TAU / 6.0;
+    for angle in [0.1, 0.7, 2.0, -3.0] {
+        let torque = settings.cogging_torque(angle);
+        assert!((settings.cogging_torque(angle + period) - torque).abs() < 1e-4);
+    }
+    // At the peak of the first harmonic, the second one is at a zero.
+    assert!((settings.cogging_torque(FRAC_PI_2 / 6.0) - 0.2).abs() < 1e-5);
+    assert_eq!(settings.cogging_torque(0.0), 0.0);
+
+    let smooth = DisturbanceSettings {
+        cogging: vec![],
+        ..Default::default()
+    };
+    assert_eq!(smooth.cogging_torque(1.0), 0.0);
+}
+
+#[test]
+fn imbalance_grows_with_the_square_of_the_speed() {
+    let settings = DisturbanceSettings {
+        imbalance: 0.01,
+        ..Default::default()
+    };
+    assert_eq!(settings.imbalance_force(0.0), 0.0);
+    assert!((settings.imbalance_force(10.0) - 1.0).abs() < 1e-6);
+    assert!((settings.imbalance_force(-20.0) - 4.0).abs() < 1e-6);
+}
+
+#[test]
+fn injected_forces_last_their_duration() {
+    let force = Injection {
+        kind: InjectionKind::Force,
+        amplitude: 3.0,
+        duration: 0.5,
+        ..Default::default()
+    };
+    assert_eq!(force.magnitude(0.0), Some(3.0));
+    assert_eq!(force.magnitude(0.49), Some(3.0));
+    assert_eq!(force.magnitude(0.5), None);
+    assert_eq!(force.magnitude(-0.1), None);
+
+    let impulse = Injection {
+        kind: InjectionKind::Impulse,
+        ..force
+    };
+    assert_eq!(impulse.magnitude(0.0), None);
+}
+
+#[test]
+fn chirps_sweep_their_frequencies() {
+    let chirp = Injection {
+        kind: InjectionKind::Chirp,
+        amplitude: 2.0,
+        duration: 10.0,
+        start_frequency: 1.0,
+        end_frequency: 3.0,
+        ..Default::default()
+    };
+    assert_eq!(chirp.magnitude(0.0), Some(0.0));
+    // A quarter of a cycle at 1 Hz, the sweep adding a tenth of a cycle per second squared.
+    let quarter = (-1.0 + (1.0f32 + 0.4 * 0.25).sqrt()) / 0.2;
+    assert!((chirp.magnitude(quarter).unwrap() - 2.0).abs() < 1e-4);
+    // The zeros get closer as the frequency grows: 1 Hz at the start, 3 Hz at the end.
+    let crossings = |from: f32, to: f32| {
+        let steps = ((to - from) / 1e-3) as usize;
+        (0..steps)
+            .filter(|step| {
+                let time = from + *step as f32 * 1e-3;
+                let [a, b] = [time, time + 1e-3].map(|time| chirp.magnitude(time).unwrap());
+                a.signum() != b.signum()
+            })
+            .count()
+    };
+    assert_eq!(crossings(0.1, 1.1), 2);
+    assert_eq!(crossings(8.9, 9.9), 6);
+    assert_eq!(chirp.magnitude(10.0), None);
+}
@@ -0,0 +1,110 @@
+//! Closing the position loop on the load-side encoder removes the deflection of a compliant
+//! transmission, which a loop on the motor-side encoder alone leaves.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::Entity;
+use digital_twin_playground::{
+    control::{DualLoop, Pid, PidGains, Saturation},
+    dual_loop_controller::DualLoopController,
+};
+
+const DT: f32 = 1e-3;
+/// Torque resisting the load, in N·m.
+const LOAD_TORQUE: f32 = 0.5;
+/// Stiffness of the transmission, in N·m/rad.
+const STIFFNESS: f32 = 50.0;
+
+/// A motor driving a load through a torsional spring.
+#[derive(Default)]
+struct CompliantAxis {
+    motor_position: f32,
+    motor_velocity: f32,
+    load_position: f32,
+    load_velocity: f32,
+}
+
+impl CompliantAxis {
+    const MOTOR_INERTIA: f32 = 0.01;
+    const LOAD_INERTIA: f32 = 0.05;
+    const DAMPING: f32 = 0.05;
+
+    fn step(&mut self, torque: f32) {
+        let twist = self.motor_position - self.load_position;
+        let spring = STIFFNESS * twist + Self::DAMPING * (self.motor_velocity - self.load_velocity);
+        self.motor_velocity += (torque - spring) / Self::MOTOR_INERTIA * DT;
+        self.load_velocity += (spring - LOAD_TORQUE) / Self::LOAD_INERTIA * DT;
+        self.motor_position += self.motor_velocity * DT;
+        self.load_position += self.load_velocity * DT;
+    }
+}
+
+fn controller() -> DualLoop {
+    let position = Pid::new(
+        PidGains {
+            kp: 5.0,
+            ki: 5.0,
+            kd: 0.0,
+        },
+        Saturation::symmetric(10.0),
+    );
+    let velocity = Pid::new(
+        PidGains {
+            kp: 0.5,
+            ki: 5.0,
+            kd: 0.0,
+        },
+        Saturation::symmetric(5.0),
+    );
+    DualLoop::new(position, velocity, 1.0)
+}
+
+/// Runs the axis towards `target` for `seconds`, closing the position loop on the load
+/// encoder or on the motor one, and returns the final load position.
+fn run(target: f32, seconds: f32, on_load: bool) -> f32 {
+    let mut axis = CompliantAxis::default();
+    let mut controller = controller();
+    for _ in 0..(seconds / DT) as usize {
+        let position = if on_load {
+            axis.load_position
+        } else {
+            axis.motor_position
+        };
+        let torque = controller.update(target, position, axis.motor_velocity, DT);
+        axis.step(torque);
+    }
+    axis.load_position
+}
+
+#[test]
+fn load_side_loop_removes_the_deflection() {
+    let error = (run(1.0, 20.0, true) - 1.0).abs();
+    assert!(error < 1e-3, "{error}");
+}
+
+#[test]
+fn motor_side_loop_leaves_the_deflection() {
+    let error = run(1.0, 20.0, false) - 1.0;
+    // The spring twists by the load torque over its stiffness.
+    let deflection = -LOAD_TORQUE / STIFFNESS;
+    assert!((error - deflection).abs() < 1e-3, "{error} vs {deflection}");
+}
+
+#[test]
+fn controller_closes_the_loops_on_the_angles_within_a_turn() {
+    let wrap = |angle: f32| (angle + PI).rem_euclid(TAU) - PI;
+    let mut axis = CompliantAxis::default();
+    let mut controller = DualLoopController::new(controller(), Entity::PLACEHOLDER, "");
+    // Past a half turn, so the angles of the joints wrap around.
+    let target = 4.0;
+    for _ in 0..(20.0 / DT) as usize {
+        let torque = controller.update(
+            target,
+            wrap(axis.motor_position),
+            wrap(axis.load_position),
+            DT,
+        );
+        axis.step(torque);
+    }
+    let error = (axis.load_position - target).abs();
+    assert!(error < 1e-3, "{error}");
+}
@@ -0,0 +1,39 @@
+//! Environments step the plant by actions and observe its telemetry.
+use digital_twin_playground::{
+    environment::{Environment, EnvironmentSettings},
+    plants,
+};
+
+#[test]
+fn actions_step_the_plant() {
+    if plants::builtin().is_empty() {
+        return;
+    }
+    let settings = EnvironmentSettings {
+        substeps: 4,
+        ..Default::default()
+    };
+    let mut environment = Environment::new(settings.clone()).unwrap();
+    let start = environment.time();
+    let mut observation = environment.observation();
+    for _ in 0..50 {
+        observation = environment.step(&[5.0]).unwrap();
+    }
+    assert_eq!(observation.len(), settings.observations.len());
+    assert!((environment.time() - start - 200.0 * settings.dt).abs() < 1e-3);
+    // The motor turned at the commanded velocity.
+    assert!(observation[0] > 0.5, "{observation:?}");
+
+    assert!(environment.step(&[1.0, 2.0]).is_err());
+    environment.reset(7).unwrap();
+    assert!(environment.time() < 2.0 * settings.dt);
+}
+
+#[test]
+fn unknown_plants_are_rejected() {
+    let settings = EnvironmentSettings {
+        plant: "unknown".to_string(),
+        ..Default::default()
+    };
+    assert!(Environment::new(settings).is_err());
+}
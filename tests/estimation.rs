@@ -0,0 +1,69 @@
+//! The extended Kalman filter recovers the velocities of the rotary pendulum from noisy angles.
+use std::f64::consts::{PI, TAU};
+
+use digital_twin_playground::{
+    estimation::{Ekf, PendulumModel},
+    lqr::LqrSettings,
+};
+use nalgebra::Vector4;
+
+#[test]
+fn filters_track_swinging_pendulums() {
+    let model = PendulumModel::of(&LqrSettings::default());
+    let dt = 0.01;
+    let mut random = 1u64;
+    let mut noise = move || {
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        0.02 * ((random >> 11) as f64 / (1u64 << 53) as f64 - 0.5)
+    };
+    // Hanging, swinging, the arm driven back and forth.
+    let mut state = Vector4::new(0.0, 0.0, 0.5, 0.0);
+    let mut filter = Ekf::new(Vector4::new(0.0, 0.0, 0.5, 0.0));
+    for step in 1..=500 {
+        let command = 2.0 * (step as f64 * dt * 3.0).sin();
+        let acceleration = (command - state[1]) / dt;
+        let rate = state[3]
+            + dt * (model.gravity * (state[2] - PI).sin()
+                + model.coupling * (state[2] - PI).cos() * acceleration);
+        state = Vector4::new(state[0] + dt * command, command, state[2] + dt * rate, rate);
+
+        filter.predict(&model, Some(command), [1.0, 1.0], dt);
+        filter.correct(0, state[0] + noise(), 1e-4 / 3.0);
+        filter.correct(2, state[2] + noise(), 1e-4 / 3.0);
+        if step > 200 {
+            let mut error = filter.state - state;
+            // The filter keeps the angles within a turn.
+            for angle in [0, 2] {
+                error[angle] = PI - (PI - error[angle]).rem_euclid(TAU);
+            }
+            assert!(error[0].abs() < 0.01, "arm off by {}", error[0]);
+            assert!(error[1].abs() < 0.01, "arm velocity off by {}", error[1]);
+            assert!(error[2].abs() < 0.02, "pendulum off by {}", error[2]);
+            assert!(
+                error[3].abs() < 0.2,
+                "pendulum velocity off by {}",
+                error[3]
+            );
+        }
+    }
+}
+
+#[test]
+fn angles_wrap_around_upright() {
+    let mut filter = Ekf::new(Vector4::new(0.0, 0.0, PI - 0.01, 0.0));
+    filter.correct(2, -PI + 0.01, 1e-2);
+    // Half way across the turn, not across the circle.
+    let angle = filter.state[2];
+    assert!(PI - angle.abs() < 0.02, "angle of {angle}");
+    assert!(angle.abs() <= PI);
+
+    let model = PendulumModel {
+        gravity: 0.0,
+        coupling: 0.0,
+    };
+    let mut filter = Ekf::new(Vector4::new(0.0, 0.0, PI - 0.005, 1.0));
+    filter.predict(&model, None, [0.0, 0.0], 0.01);
+    assert!((filter.state[2] + PI - 0.005).abs() < 1e-9);
+}
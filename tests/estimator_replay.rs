@@ -0,0 +1,85 @@
+//! Recorded readings replay into the filter alone, against the exact states of the run.
+use std::f64::consts::PI;
+
+use digital_twin_playground::{
+    estimation::{EstimationSettings, PendulumModel},
+    estimator_replay::{self, EstimatorReplaySettings},
+    lqr::LqrSettings,
+    telemetry::Telemetry,
+};
+
+/// A swinging pendulum, its arm driven back and forth, recorded every `dt` seconds.
+fn recorded_run(dt: f64) -> Telemetry {
+    let model = PendulumModel::of(&LqrSettings::default());
+    let mut random = 1u64;
+    let mut noise = move || {
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        0.002 * ((random >> 11) as f64 / (1u64 << 53) as f64 - 0.5)
+    };
+    let mut run = Telemetry::default();
+    let [mut arm, mut velocity, mut angle, mut rate] = [0.0, 0.0, 0.5, 0.0];
+    for step in 0..400 {
+        let time = (step as f64 * dt) as f32;
+        run.record("encoder/motor", time, (arm + noise()) as f32);
+        run.record("encoder/pivot", time, (angle + noise()) as f32);
+        run.record("estimate/motor/true_angle", time, arm as f32);
+        run.record("estimate/pivot/true_angle", time, angle as f32);
+        let command = 2.0 * (step as f64 * dt * 3.0).sin();
+        run.record("pid/output", time, command as f32);
+
+        let acceleration = (command - velocity) / dt;
+        rate += dt
+            * (model.gravity * (angle - PI).sin()
+                + model.coupling * (angle - PI).cos() * acceleration);
+        velocity = command;
+        arm += dt * velocity;
+        angle += dt * rate;
+    }
+    run
+}
+
+#[test]
+fn replays_recover_the_velocities_of_the_run() {
+    let run = recorded_run(0.01);
+    let settings = EstimatorReplaySettings {
+        command: Some("pid/output".to_string()),
+        reading_noise: 6e-4,
+        ..Default::default()
+    };
+    let estimation = EstimationSettings {
+        arm_noise: 1.0,
+        pendulum_noise: 1.0,
+        ..Default::default()
+    };
+    let model = PendulumModel::of(&LqrSettings::default());
+    let replay = estimator_replay::replay(&run, &settings, &estimation, &model).unwrap();
+    assert_eq!(replay.steps.len(), 400);
+    // The first step has no velocity to compare with.
+    assert!(replay.steps[0].truth[1].is_none());
+    let [arm, velocity, angle, rate] = replay.rms().map(Option::unwrap);
+    assert!(arm < 0.005, "arm off by {arm}");
+    assert!(velocity < 0.05, "arm velocity off by {velocity}");
+    assert!(angle < 0.005, "pendulum off by {angle}");
+    assert!(rate < 0.3, "pendulum velocity off by {rate}");
+    assert!(replay
+        .to_string()
+        .starts_with("400 steps replayed over 3.990 s"));
+}
+
+#[test]
+fn replays_need_the_readings_but_not_the_truth() {
+    let mut run = recorded_run(0.01);
+    run.channels
+        .retain(|channel, _| channel.starts_with("encoder/"));
+    let settings = EstimatorReplaySettings::default();
+    let estimation = EstimationSettings::default();
+    let model = PendulumModel::of(&LqrSettings::default());
+    let replay = estimator_replay::replay(&run, &settings, &estimation, &model).unwrap();
+    assert_eq!(replay.rms(), [None; 4]);
+    assert!(replay.to_string().contains("no truth in the run"));
+
+    run.channels.remove("encoder/pivot");
+    assert!(estimator_replay::replay(&run, &settings, &estimation, &model).is_err());
+}
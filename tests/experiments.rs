@@ -0,0 +1,88 @@
+//! Experiments run in the background, one after the other, reporting their progress.
+use std::{
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use digital_twin_playground::{
+    batch::BatchRun,
+    experiments::{ExperimentKind, ExperimentQueue, ExperimentStatus},
+    headless::DEFAULT_TIME_STEP,
+    plants,
+};
+
+#[test]
+fn queued_runs_complete_in_the_background() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join("experiment_queue");
+    let batch = |name: &str| {
+        ExperimentKind::Batch(BatchRun {
+            duration: 0.2,
+            dt: DEFAULT_TIME_STEP,
+            seed: 1,
+            out: dir.join(name),
+            capture: None,
+            scenario: None,
+        })
+    };
+    let mut queue = ExperimentQueue::spawn();
+    let first = queue.enqueue(plant, batch("first.csv"));
+    let second = queue.enqueue(plant, batch("second.csv"));
+    assert_ne!(first, second);
+
+    let mut completed = Vec::new();
+    let start = Instant::now();
+    while completed.len() < 2 && start.elapsed() < Duration::from_secs(60) {
+        for completion in queue.poll() {
+            assert!(completion.result.is_ok(), "{:?}", completion.result);
+            completed.push(completion.id);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    // One after the other, in the order they were queued.
+    assert_eq!(completed, vec![first, second]);
+    assert!(queue
+        .experiments
+        .iter()
+        .all(|experiment| matches!(experiment.status, ExperimentStatus::Done(_))));
+    assert!(dir.join("second.csv").exists());
+
+    queue.clear_finished();
+    assert!(queue.experiments.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn queued_experiments_can_be_removed_before_they_start() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join("experiment_removed");
+    let mut queue = ExperimentQueue::spawn();
+    let batch = |duration: f32, name: &str| {
+        ExperimentKind::Batch(BatchRun {
+            duration,
+            dt: DEFAULT_TIME_STEP,
+            seed: 1,
+            out: dir.join(name),
+            capture: None,
+            scenario: None,
+        })
+    };
+    let running = queue.enqueue(plant, batch(1.0, "running.csv"));
+    let removed = queue.enqueue(plant, batch(0.2, "removed.csv"));
+    queue.remove(removed);
+
+    let start = Instant::now();
+    let mut completed = Vec::new();
+    while completed.is_empty() && start.elapsed() < Duration::from_secs(60) {
+        completed.extend(queue.poll().into_iter().map(|completion| completion.id));
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(completed, vec![running]);
+    assert_eq!(queue.experiments[1].status, ExperimentStatus::Removed);
+    assert!(!dir.join("removed.csv").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
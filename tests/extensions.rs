@@ -0,0 +1,83 @@
+//! Extensions register plants, sensors and controllers from outside the playground.
+use bevy::prelude::*;
+use digital_twin_playground::{
+    error::Error,
+    extensions::{self, Extension, ExtensionsPlugin, API_VERSION},
+    plants::{self, Plant},
+};
+
+#[derive(Resource)]
+struct Conveyor;
+
+#[derive(Resource)]
+struct LoadCell;
+
+#[derive(Resource)]
+struct Mpc;
+
+fn conveyor() -> Plant {
+    Plant {
+        name: "test_conveyor",
+        add: |app| {
+            app.insert_resource(Conveyor);
+        },
+        compose: |app, _| {
+            app.insert_resource(Conveyor);
+        },
+        spawn: |world| {
+            world.insert_resource(Conveyor);
+        },
+    }
+}
+
+#[test]
+fn registered_extensions_provide_plants_sensors_and_controllers() {
+    let extension = Extension::new("test_conveyors", "0.1.0")
+        .description("A belt conveyor")
+        .plant(conveyor())
+        .sensor("load_cell", "Weight on the belt", |app| {
+            app.insert_resource(LoadCell);
+        })
+        .controller("mpc", "Model predictive control of the belt", |app| {
+            app.insert_resource(Mpc);
+        });
+    extensions::register(extension).unwrap();
+    assert!(plants::available()
+        .iter()
+        .any(|plant| plant.name == "test_conveyor"));
+    assert!(!plants::builtin()
+        .iter()
+        .any(|plant| plant.name == "test_conveyor"));
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionsPlugin);
+    assert!(app.world().contains_resource::<LoadCell>());
+    assert!(app.world().contains_resource::<Mpc>());
+    // Plants are added by the compositions only.
+    assert!(!app.world().contains_resource::<Conveyor>());
+
+    // Names are unique.
+    assert!(matches!(
+        extensions::register(Extension::new("test_conveyors", "0.2.0")),
+        Err(Error::Config { .. })
+    ));
+    assert!(matches!(
+        extensions::register(Extension::new("test_other_conveyors", "0.1.0").plant(conveyor())),
+        Err(Error::Config { .. })
+    ));
+}
+
+#[test]
+fn extensions_for_another_interface_are_rejected() {
+    let extension = Extension {
+        api_version: API_VERSION + 1,
+        ..Extension::new("test_future", "1.0.0")
+    };
+    assert!(matches!(
+        extensions::register(extension),
+        Err(Error::Config { .. })
+    ));
+    assert!(extensions::registered()
+        .iter()
+        .all(|extension| extension.name != "test_future"));
+}
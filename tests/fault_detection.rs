@@ -0,0 +1,98 @@
+//! Faults are detected from the residuals of an observer of the plant, beyond the spread of the
+//! residuals without fault.
+use digital_twin_playground::{
+    fault_detection::{self, Detector, Fault, FaultKind, Observer},
+    identification::{Dataset, LinearModel},
+};
+use nalgebra::{DMatrix, DVector};
+
+fn model() -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["u".to_string()],
+        outputs: vec!["position".to_string(), "force".to_string()],
+        a: DMatrix::from_row_slice(2, 2, &[0.98, 0.1, -0.2, 0.95]),
+        b: DMatrix::from_row_slice(2, 1, &[0.0, 0.1]),
+        c: DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.5, 0.2]),
+        d: DMatrix::from_row_slice(2, 1, &[0.0, 0.3]),
+        input_offsets: vec![0.0],
+        output_offsets: vec![1.0, -2.0],
+    }
+}
+
+/// A run of the model from a state the observer doesn't know, with a little measurement noise.
+fn data(model: &LinearModel) -> Dataset {
+    let mut random = 1u64;
+    let mut uniform = move || {
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        (random >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    };
+    let inputs: Vec<Vec<f64>> = (0..1_000)
+        .map(|k| vec![if (k / 20) % 2 == 0 { 1.0 } else { -1.0 }])
+        .collect();
+    let mut outputs = model.simulate(&inputs, &DVector::from_vec(vec![0.5, -0.5]));
+    for outputs in &mut outputs {
+        for output in outputs {
+            *output += 1e-3 * uniform();
+        }
+    }
+    Dataset {
+        dt: model.dt,
+        inputs,
+        outputs,
+    }
+}
+
+#[test]
+fn observers_track_the_model() {
+    let model = model();
+    let mut observer = Observer::new(model.clone(), 1e-4, 1e-2).unwrap();
+    let residuals = fault_detection::residuals(&mut observer, &data(&model));
+    // The initial state is forgotten.
+    for residuals in &residuals[400..] {
+        for residual in residuals {
+            assert!(residual.abs() < 0.01, "residual of {residual}");
+        }
+    }
+}
+
+#[test]
+fn sensor_faults_raise_the_alarm() {
+    let model = model();
+    let mut observer = Observer::new(model.clone(), 1e-4, 1e-2).unwrap();
+    let nominal = data(&model);
+    let residuals = fault_detection::residuals(&mut observer, &nominal);
+    let thresholds = fault_detection::thresholds(&residuals[400..], 5.0);
+    let mut detector = Detector::new(thresholds, 5);
+    assert!(!detector.alarms(&residuals[400..]).contains(&true));
+
+    let mut faulty = nominal.clone();
+    fault_detection::inject(&mut faulty, 0, FaultKind::Bias { value: 0.1 }, 600);
+    let alarms = detector.alarms(&fault_detection::residuals(&mut observer, &faulty));
+    let first = alarms[400..].iter().position(|alarm| *alarm).unwrap() + 400;
+    assert!((604..620).contains(&first), "alarm at {first}");
+}
+
+#[test]
+fn faults_change_the_signals() {
+    let mut data = Dataset {
+        dt: 0.5,
+        inputs: vec![vec![0.0]; 4],
+        outputs: (0..4).map(|k| vec![k as f64]).collect(),
+    };
+    fault_detection::inject(&mut data, 0, FaultKind::Stuck, 1);
+    assert_eq!(
+        data.outputs,
+        vec![vec![0.0], vec![1.0], vec![1.0], vec![1.0]]
+    );
+    assert_eq!(FaultKind::Drift { rate: 2.0 }.apply(1.0, 0.5, 0.0), 2.0);
+    assert_eq!(FaultKind::Gain { factor: 0.5 }.apply(4.0, 1.0, 0.0), 2.0);
+
+    let fault: Fault = serde_json::from_str(
+        r#"{ "signal": "pendulum/angle", "kind": "bias", "value": 0.05, "start": 20.0 }"#,
+    )
+    .unwrap();
+    assert_eq!(fault.kind, FaultKind::Bias { value: 0.05 });
+}
@@ -0,0 +1,64 @@
+//! A PLC exchanges the process image with a headless simulator over the loopback interface.
+use std::{net::UdpSocket, thread, time::Duration};
+
+use digital_twin_playground::{
+    fieldbus::{FieldbusPlugin, Inputs, Outputs, ENABLE_OPERATION, OPERATION_ENABLED},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    network::NetworkSettings,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+#[test]
+fn plc_commands_the_motor() {
+    let address = "127.0.0.1:47031".parse().unwrap();
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(FieldbusPlugin {
+        bind: address,
+        network: NetworkSettings::default(),
+    });
+    app.finish();
+    app.cleanup();
+
+    let plc = UdpSocket::bind("127.0.0.1:0").unwrap();
+    plc.set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+    let outputs = Outputs {
+        control_word: ENABLE_OPERATION,
+        target_velocity: 1500,
+    };
+    plc.send_to(&outputs.encode(7), address).unwrap();
+
+    let mut buffer = [0; 64];
+    for _ in 0..1_000 {
+        app.update();
+        if let Ok(size) = plc.recv(&mut buffer) {
+            let (cycle, inputs) = Inputs::decode(&buffer[..size]).unwrap();
+            assert_eq!(cycle, 7);
+            assert_eq!(inputs.status_word & OPERATION_ENABLED, OPERATION_ENABLED);
+            assert_eq!(inputs.velocity_demand, 1500);
+            assert_eq!(
+                app.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+                Some(1.5)
+            );
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("the simulator doesn't answer");
+}
+
+#[test]
+fn frames_round_trip() {
+    let outputs = Outputs {
+        control_word: ENABLE_OPERATION,
+        target_velocity: -3141,
+    };
+    assert_eq!(Outputs::decode(&outputs.encode(42)), Some((42, outputs)));
+    let inputs = Inputs {
+        status_word: OPERATION_ENABLED,
+        actual_position: 1571,
+        velocity_demand: -3141,
+    };
+    assert_eq!(Inputs::decode(&inputs.encode(43)), Some((43, inputs)));
+    assert_eq!(Outputs::decode(&inputs.encode(43)), None);
+}
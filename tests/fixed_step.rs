@@ -0,0 +1,102 @@
+//! In the fixed-step mode, the physics steps by the same time step whatever the frame rate, and
+//! runs are bit-identical.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    clock::SimClock,
+    fixed_step::{FixedStep, FixedStepPlugin},
+    headless::headless_app,
+    plants::{self, Plant},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+const DT: f32 = 1.0 / 120.0;
+
+/// Runs `plant` for `steps` frames lasting `frame` seconds, returning the poses of its dynamic
+/// bodies after every frame.
+fn run(plant: &Plant, frame: f32, steps: usize) -> (App, Vec<Vec<Transform>>) {
+    let mut app = headless_app(frame);
+    (plant.add)(&mut app);
+    app.add_plugins(FixedStepPlugin {
+        dt: DT,
+        seed: 7,
+        real_time: false,
+    })
+    .init_resource::<Setpoints>();
+    app.finish();
+    app.cleanup();
+
+    let mut trajectory = Vec::new();
+    for step in 0..steps {
+        let velocity = if step < steps / 2 { 8.0 } else { -4.0 };
+        app.world_mut()
+            .resource_mut::<Setpoints>()
+            .set(MOTOR_VELOCITY, velocity);
+        app.update();
+        let world = app.world_mut();
+        let mut bodies = world
+            .query::<(Entity, &RigidBody, &Transform)>()
+            .iter(world)
+            .filter(|(_, body, _)| **body == RigidBody::Dynamic)
+            .map(|(entity, _, transform)| (entity, *transform))
+            .collect::<Vec<_>>();
+        bodies.sort_by_key(|(entity, _)| *entity);
+        trajectory.push(bodies.into_iter().map(|(_, pose)| pose).collect());
+    }
+    (app, trajectory)
+}
+
+#[test]
+fn steps_ignore_the_frame_rate() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let (app, _) = run(&plant, 1.0 / 30.0, 120);
+    let clock = app.world().resource::<SimClock>();
+    assert_eq!(clock.tick(), 120);
+    assert!((clock.elapsed_secs() - 1.0).abs() < 1e-4);
+    assert_eq!(
+        app.world().resource::<FixedStep>(),
+        &FixedStep {
+            dt: DT,
+            seed: 7,
+            real_time: false
+        }
+    );
+}
+
+#[test]
+fn runs_are_bit_identical_across_frame_rates() {
+    for plant in plants::builtin() {
+        let (_, slow) = run(&plant, 1.0 / 24.0, 90);
+        let (_, fast) = run(&plant, 1.0 / 144.0, 90);
+        assert!(!slow.is_empty());
+        for (slow, fast) in slow.iter().zip(&fast) {
+            for (slow, fast) in slow.iter().zip(fast) {
+                assert_eq!(
+                    slow.translation.to_array().map(f32::to_bits),
+                    fast.translation.to_array().map(f32::to_bits),
+                    "{}",
+                    plant.name
+                );
+                assert_eq!(
+                    slow.rotation.to_array().map(f32::to_bits),
+                    fast.rotation.to_array().map(f32::to_bits),
+                    "{}",
+                    plant.name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid_steps_are_rejected() {
+    let mut app = headless_app(DT);
+    app.add_plugins(FixedStepPlugin {
+        dt: 0.0,
+        seed: 1,
+        real_time: false,
+    });
+    assert!(app.world().get_resource::<FixedStep>().is_none());
+}
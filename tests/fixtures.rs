@@ -0,0 +1,89 @@
+//! Fixtures pull the end effector to their closest point, and leave it free inside of them.
+use bevy::math::Vec3;
+use digital_twin_playground::fixtures::{Fixture, FixtureSettings, Geometry};
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(actual.abs_diff_eq(expected, 1e-5), "{actual} vs {expected}");
+}
+
+#[test]
+fn planes_hold_the_end_effector_on_them() {
+    let plane = Geometry::Plane {
+        point: Vec3::Y,
+        normal: Vec3::Y * 2.0,
+    };
+    assert_near(
+        plane.correction(Vec3::new(5.0, 3.0, 2.0)),
+        Vec3::new(0.0, -2.0, 0.0),
+    );
+    assert_near(plane.correction(Vec3::new(5.0, 1.0, 2.0)), Vec3::ZERO);
+}
+
+#[test]
+fn corridors_surround_their_segment() {
+    let corridor = Geometry::Corridor {
+        start: Vec3::ZERO,
+        end: Vec3::Z * 10.0,
+        radius: 1.0,
+    };
+    assert_near(
+        corridor.correction(Vec3::new(3.0, 0.0, 5.0)),
+        Vec3::new(-2.0, 0.0, 0.0),
+    );
+    assert_near(corridor.correction(Vec3::new(0.5, 0.0, 5.0)), Vec3::ZERO);
+    // Past its end, towards the end.
+    assert_near(
+        corridor.correction(Vec3::Z * 12.0),
+        Vec3::new(0.0, 0.0, -1.0),
+    );
+}
+
+#[test]
+fn funnels_narrow_to_their_apex() {
+    let funnel = Geometry::Funnel {
+        apex: Vec3::ZERO,
+        axis: Vec3::Y,
+        half_angle: 45f32.to_radians(),
+    };
+    assert_near(funnel.correction(Vec3::new(0.5, 1.0, 0.0)), Vec3::ZERO);
+    // To the side of the cone.
+    assert_near(
+        funnel.correction(Vec3::new(2.0, 1.0, 0.0)),
+        Vec3::new(-0.5, 0.5, 0.0),
+    );
+    assert_near(
+        funnel.correction(Vec3::new(10.0, -0.1, 0.0)),
+        Vec3::new(-5.05, 5.05, 0.0),
+    );
+    // Behind the apex.
+    assert_near(
+        funnel.correction(Vec3::new(1.0, -3.0, 0.0)),
+        Vec3::new(-1.0, 3.0, 0.0),
+    );
+}
+
+#[test]
+fn forces_add_up_to_the_largest_force() {
+    let mut settings = FixtureSettings::default();
+    settings.fixtures.push(Fixture {
+        name: "Wall".to_string(),
+        enabled: true,
+        stiffness: 10.0,
+        geometry: Geometry::Plane {
+            point: Vec3::ZERO,
+            normal: Vec3::X,
+        },
+    });
+    // 100 N/m down to the floor, 10 N/m back to the wall.
+    let force = settings.force(Vec3::new(0.1, 1.2, 0.0));
+    assert_near(force, Vec3::new(-1.0, -20.0, 0.0));
+    assert_near(
+        settings.force(Vec3::new(0.0, 10.0, 0.0)),
+        Vec3::new(0.0, -settings.max_force, 0.0),
+    );
+    settings.fixtures[0].enabled = false;
+    assert_near(
+        settings.force(Vec3::new(0.1, 1.2, 0.0)),
+        Vec3::new(-1.0, 0.0, 0.0),
+    );
+}
@@ -0,0 +1,37 @@
+//! Relative transforms between frames, in the axes of Bevy and of Blender.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use digital_twin_playground::frames::{in_blender_axes, relative};
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(actual.distance(expected) < 1e-5, "{actual} vs {expected}");
+}
+
+#[test]
+fn relative_transforms_compose_back() {
+    let from = Transform::from_xyz(1.0, 2.0, 3.0).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+    let to = Transform::from_xyz(-1.0, 0.5, 2.0).with_rotation(Quat::from_rotation_z(0.3));
+    let between = relative(&from, &to);
+    let composed = from.mul_transform(between);
+    assert_near(composed.translation, to.translation);
+    assert!(composed.rotation.angle_between(to.rotation) < 1e-5);
+    // Turned a quarter turn about Y, the frame has its Z along the X of the world.
+    let ahead = relative(&from, &Transform::from_xyz(2.0, 2.0, 3.0));
+    assert_near(ahead.translation, Vec3::Z);
+    assert_eq!(relative(&to, &to).translation, Vec3::ZERO);
+}
+
+#[test]
+fn blender_axes_are_z_up() {
+    // Up in Bevy is Z in Blender, and forward (+Z) is -Y.
+    let up = in_blender_axes(&Transform::from_xyz(0.0, 1.0, 0.0));
+    assert_near(up.translation, Vec3::Z);
+    let forward = in_blender_axes(&Transform::from_xyz(0.0, 0.0, 1.0));
+    assert_near(forward.translation, -Vec3::Y);
+    // A turn about the vertical stays one.
+    let turn = in_blender_axes(&Transform::from_rotation(Quat::from_rotation_y(0.5)));
+    let (axis, angle) = turn.rotation.to_axis_angle();
+    assert_near(axis, Vec3::Z);
+    assert!((angle - 0.5).abs() < 1e-5);
+}
@@ -0,0 +1,57 @@
+//! The frequency response is measured from the fundamental of the response at each frequency of
+//! a log-spaced sweep.
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use digital_twin_playground::frequency_response::{self, BodePoint, FrequencyResponseSettings};
+
+#[test]
+fn the_sweep_is_log_spaced() {
+    let settings = FrequencyResponseSettings {
+        start_frequency: 0.1,
+        end_frequency: 10.0,
+        points: 3,
+        settle_cycles: 1,
+        measure_cycles: 3,
+        ..Default::default()
+    };
+    let frequencies = settings.frequencies();
+    assert_eq!(frequencies.len(), 3);
+    for (frequency, expected) in frequencies.iter().zip([0.1, 1.0, 10.0]) {
+        assert!((frequency - expected).abs() < 1e-4, "{frequency}");
+    }
+    assert!((settings.duration(0.5) - 8.0).abs() < 1e-5);
+}
+
+#[test]
+fn the_fundamental_gives_the_gain_and_the_phase() {
+    let frequency = 2.0;
+    // Two cycles of a response halved and lagging by a quarter turn, on an offset, with a
+    // harmonic.
+    let samples: Vec<[f32; 2]> = (0..=1000)
+        .map(|index| {
+            let time = index as f32 / 1000.0;
+            let phase = TAU * frequency * time;
+            [
+                time,
+                3.0 + 0.5 * (phase - FRAC_PI_2).sin() + 0.1 * (3.0 * phase).sin(),
+            ]
+        })
+        .collect();
+    let (gain, phase) = frequency_response::fundamental(&samples, frequency, 1.0).unwrap();
+    assert!((gain - 0.5).abs() < 1e-3, "{gain}");
+    assert!((phase + FRAC_PI_2).abs() < 1e-2, "{phase}");
+
+    assert!(frequency_response::fundamental(&samples[..1], frequency, 1.0).is_none());
+    assert!(frequency_response::fundamental(&samples, frequency, 0.0).is_none());
+}
+
+#[test]
+fn the_phase_is_unwrapped_along_the_sweep() {
+    let mut points = Vec::new();
+    frequency_response::push_point(&mut points, 1.0, 1.0, -170f32.to_radians());
+    frequency_response::push_point(&mut points, 2.0, 0.1, 170f32.to_radians());
+    assert_eq!(points[0].gain, 0.0);
+    assert!((points[1].gain + 20.0).abs() < 1e-4);
+    let BodePoint { phase, .. } = points[1];
+    assert!((phase + 190.0).abs() < 1e-3, "{phase}");
+}
@@ -0,0 +1,49 @@
+//! Joints resist their motion with friction, and their motors turn freely across the backlash.
+use digital_twin_playground::friction::{Backlash, JointFriction};
+
+fn joint() -> JointFriction {
+    JointFriction {
+        coulomb: 0.1,
+        viscous: 0.01,
+        stiction: 0.3,
+        stribeck_speed: 0.5,
+        stick_speed: 0.01,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn friction_opposes_the_motion() {
+    let joint = joint();
+    assert_eq!(joint.torque(0.0), 0.0);
+    // Breaking away, the stiction holds.
+    assert!((joint.torque(0.01) + 0.3).abs() < 1e-3);
+    // Slipping fast, the Coulomb and the viscous friction remain.
+    assert!((joint.torque(10.0) + 0.2).abs() < 1e-5);
+    assert!((joint.torque(-10.0) - 0.2).abs() < 1e-5);
+    // Within the stick speed, the dry friction falls with the speed.
+    assert!((joint.torque(0.005) + 0.15).abs() < 1e-3);
+    for speed in [-5.0, -0.1, 0.001, 0.1, 5.0] {
+        assert!(joint.torque(speed) * speed < 0.0, "at {speed} rad/s");
+    }
+}
+
+#[test]
+fn motors_turn_freely_across_the_backlash() {
+    let dt = 1.0 / 64.0;
+    let mut backlash = Backlash::new(0.125);
+    // From the middle of the play, the motor reaches its edge after four steps.
+    let engaged: Vec<bool> = (0..6).map(|_| backlash.step(1.0, 0.0, dt)).collect();
+    assert_eq!(engaged, vec![false, false, false, true, true, true]);
+    assert_eq!(backlash.gap(), 0.0625);
+    // Driving the joint along, the gears stay engaged.
+    assert!(backlash.step(1.0, 1.0, dt));
+    // Reversing, the motor crosses the whole play first.
+    let crossing = (0..20)
+        .take_while(|_| !backlash.step(-1.0, 0.0, dt))
+        .count();
+    assert_eq!(crossing, 7);
+
+    let mut none = Backlash::new(0.0);
+    assert!(none.step(1.0, 0.0, dt) && none.step(-1.0, 0.0, dt));
+}
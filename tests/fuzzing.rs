@@ -0,0 +1,97 @@
+//! Fuzzed scenarios are reproducible and bounded, and failing ones are minimized.
+use digital_twin_playground::{
+    fuzzing::{self, Bound, FuzzSettings, Requirement},
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    setpoints::MOTOR_VELOCITY,
+    telemetry::PENDULUM_ANGLE,
+};
+
+fn settings() -> FuzzSettings {
+    FuzzSettings {
+        duration: 1.0,
+        parameters: vec![Bound {
+            name: MOTOR_VELOCITY.to_string(),
+            min: -2.0,
+            max: 2.0,
+        }],
+        kicks: 4,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn scenarios_follow_from_the_seed_within_bounds() {
+    let settings = settings();
+    for index in 0..50 {
+        let scenario = fuzzing::generate(&settings, index);
+        assert_eq!(scenario, fuzzing::generate(&settings, index));
+        let velocity = scenario.parameters[MOTOR_VELOCITY];
+        assert!((-2.0..=2.0).contains(&velocity), "{velocity}");
+        assert!(scenario.kicks.len() <= 1 + settings.kicks);
+        assert_eq!(scenario.kicks[0].time, 0.0);
+        for kick in &scenario.kicks {
+            assert!((0.0..settings.duration).contains(&kick.time));
+            assert!(kick.impulse.length() <= settings.impulse + 1e-6);
+        }
+        assert!(scenario.kicks.is_sorted_by(|a, b| a.time <= b.time));
+    }
+    assert_ne!(
+        fuzzing::generate(&settings, 0),
+        fuzzing::generate(&settings, 1)
+    );
+}
+
+#[test]
+fn requirements_hold_after_their_start() {
+    let requirement = Requirement {
+        channel: PENDULUM_ANGLE.to_string(),
+        min: Some(-0.5),
+        max: Some(0.5),
+        after: 3.0,
+    };
+    assert!(!requirement.violated(1.0, 2.0));
+    assert!(requirement.violated(3.0, 0.6));
+    assert!(requirement.violated(4.0, -0.6));
+    assert!(!requirement.violated(4.0, 0.4));
+}
+
+#[test]
+fn failures_are_minimized() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let settings = FuzzSettings {
+        // The angle of the pendulum is never negative: every scenario fails once checked.
+        requirements: vec![Requirement {
+            channel: PENDULUM_ANGLE.to_string(),
+            min: None,
+            max: Some(-1.0),
+            after: 0.2,
+        }],
+        shrink_runs: 20,
+        ..settings()
+    };
+    let scenario = fuzzing::generate(&settings, 0);
+    let failure = fuzzing::check(&plant, &scenario, &settings.requirements, DEFAULT_TIME_STEP)
+        .unwrap()
+        .unwrap();
+    assert!(failure.time >= 0.2 && failure.time < 0.25, "{failure}");
+
+    let (minimized, failure) =
+        fuzzing::minimize(&plant, &settings, DEFAULT_TIME_STEP, scenario, failure).unwrap();
+    assert!(minimized.kicks.is_empty(), "{minimized:?}");
+    assert!(minimized.parameters.is_empty());
+    assert!(minimized.duration < 0.25);
+    // The minimized scenario replays the failure.
+    assert_eq!(
+        fuzzing::check(
+            &plant,
+            &minimized,
+            &settings.requirements,
+            DEFAULT_TIME_STEP
+        )
+        .unwrap(),
+        Some(failure)
+    );
+}
@@ -0,0 +1,92 @@
+//! Commands follow their inputs past the deadzone, up to their full-scale value.
+use bevy::input::gamepad::{GamepadButton, GamepadInput};
+use digital_twin_playground::gamepad_mapping::{GamepadMapping, InputSampler, TeleopCommand};
+
+fn command(deadzone: f32, invert: bool) -> TeleopCommand {
+    TeleopCommand {
+        name: "x".to_string(),
+        setpoint: "x/velocity".to_string(),
+        input: None,
+        scale: 10.0,
+        deadzone,
+        invert,
+    }
+}
+
+#[test]
+fn deadzone_is_ignored() {
+    let command = command(0.2, false);
+    for position in [0.0, 0.1, -0.2, f32::NAN] {
+        assert_eq!(command.value(position), 0.0, "{position}");
+    }
+}
+
+#[test]
+fn travel_past_the_deadzone_is_scaled() {
+    let command = command(0.2, false);
+    assert!((command.value(0.6) - 5.0).abs() < 1e-5);
+    assert!((command.value(-1.0) + 10.0).abs() < 1e-5);
+    // Saturates beyond the end of the travel.
+    assert!((command.value(1.5) - 10.0).abs() < 1e-5);
+}
+
+#[test]
+fn inverted_commands_change_sign() {
+    let command = command(0.0, true);
+    assert!((command.value(0.5) + 5.0).abs() < 1e-5);
+}
+
+#[test]
+fn mapping_round_trips_through_json() {
+    let mut mapping = GamepadMapping::default();
+    mapping.commands.push(TeleopCommand {
+        input: Some(GamepadInput::Button(GamepadButton::RightTrigger2)),
+        ..command(0.05, false)
+    });
+    let json = serde_json::to_string(&mapping).unwrap();
+    assert_eq!(
+        serde_json::from_str::<GamepadMapping>(&json).unwrap(),
+        mapping
+    );
+}
+
+#[test]
+fn inputs_are_sampled_on_the_ticks_whatever_the_frame_rate() {
+    // Reads of a ramp of the input, every 1/60 s and every 1/144 s, sampled at 10 Hz.
+    let sampled = |frame_rate: f64, interpolate: bool| {
+        let mut sampler = InputSampler::default();
+        let mut samples = Vec::new();
+        for frame in 0..=(frame_rate as usize) {
+            let time = frame as f64 / frame_rate;
+            samples.extend(sampler.read(time, time as f32, 0.1, interpolate));
+        }
+        samples
+    };
+    let (slow, fast) = (sampled(60.0, true), sampled(144.0, true));
+    assert_eq!(slow.len(), 11);
+    assert_eq!(fast.len(), 11);
+    for ((time, slow), (_, fast)) in slow.iter().zip(&fast) {
+        assert!((slow - *time as f32).abs() < 1e-4, "{time}: {slow}");
+        assert!((fast - *time as f32).abs() < 1e-4, "{time}: {fast}");
+    }
+
+    // Holding gives the last read at or before the tick.
+    for (time, value) in sampled(144.0, false) {
+        let time = time as f32;
+        assert!(
+            value <= time + 1e-5 && value > time - 1.0 / 144.0,
+            "{time}: {value}"
+        );
+    }
+}
+
+#[test]
+fn no_tick_passed_holds_the_last_sample() {
+    let mut sampler = InputSampler::default();
+    assert_eq!(sampler.read(0.0, 1.0, 0.1, false), [(0.0, 1.0)]);
+    assert!(sampler.read(0.05, 2.0, 0.1, false).is_empty());
+    assert_eq!(sampler.value(), Some(1.0));
+    let samples = sampler.read(0.1, 3.0, 0.1, false);
+    assert_eq!(samples.len(), 1);
+    assert_eq!(sampler.value(), Some(3.0));
+}
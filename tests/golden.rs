@@ -0,0 +1,229 @@
+//! Golden regression tests for the built-in plants.
+//!
+//! Each plant is loaded headlessly, simulated for a few seconds and the poses of its rigid
+//! bodies are compared against a stored trace. A frame rendered offscreen is compared the same
+//! way, within a tolerance, but it needs a GPU adapter, which the CI runners lack, so that test
+//! only runs with `cargo test -- --ignored`.
+//!
+//! Run with `UPDATE_GOLDENS=1` to (re)generate the stored goldens after an intended change.
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+    },
+};
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, offscreen_app, DEFAULT_TIME_STEP},
+    plants,
+};
+use serde::{Deserialize, Serialize};
+
+/// Number of physics steps simulated for the trace.
+const TRACE_STEPS: usize = 300;
+/// A sample of the trace is recorded every `TRACE_DECIMATION` steps.
+const TRACE_DECIMATION: usize = 10;
+/// Maximum absolute difference allowed between a traced value and its golden.
+const TRACE_TOLERANCE: f32 = 1e-3;
+/// Maximum mean absolute difference allowed between a frame and its golden, per channel.
+const FRAME_TOLERANCE: f64 = 2.0;
+const FRAME_SIZE: u32 = 256;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Trace {
+    dt: f32,
+    /// Translation and rotation of every dynamic body, in spawn order, for each sample.
+    samples: Vec<Vec<[f32; 7]>>,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("goldens")
+        .join(name)
+}
+
+fn update_goldens() -> bool {
+    std::env::var_os("UPDATE_GOLDENS").is_some()
+}
+
+fn record_trace(app: &mut App) -> Trace {
+    let mut samples = Vec::new();
+    for step in 0..TRACE_STEPS {
+        app.update();
+        if step % TRACE_DECIMATION != 0 {
+            continue;
+        }
+        let world = app.world_mut();
+        let mut bodies = world
+            .query::<(Entity, &RigidBody, &Transform)>()
+            .iter(world)
+            .filter(|(_, body, _)| **body == RigidBody::Dynamic)
+            .map(|(entity, _, transform)| {
+                let t = transform.translation;
+                let r = transform.rotation;
+                (entity, [t.x, t.y, t.z, r.x, r.y, r.z, r.w])
+            })
+            .collect::<Vec<_>>();
+        bodies.sort_by_key(|(entity, _)| *entity);
+        samples.push(bodies.into_iter().map(|(_, pose)| pose).collect());
+    }
+    Trace {
+        dt: DEFAULT_TIME_STEP,
+        samples,
+    }
+}
+
+fn compare_traces(name: &str, golden: &Trace, actual: &Trace) {
+    assert_eq!(golden.dt, actual.dt, "{name}: time step changed");
+    assert_eq!(
+        golden.samples.len(),
+        actual.samples.len(),
+        "{name}: number of samples changed"
+    );
+    for (index, (expected, found)) in golden.samples.iter().zip(&actual.samples).enumerate() {
+        assert_eq!(
+            expected.len(),
+            found.len(),
+            "{name}: number of bodies changed at sample {index}"
+        );
+        for (body, (expected, found)) in expected.iter().zip(found).enumerate() {
+            for (expected, found) in expected.iter().zip(found) {
+                assert!(
+                    (expected - found).abs() <= TRACE_TOLERANCE,
+                    "{name}: body {body} diverged at sample {index}: expected {expected:?}, found {found:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn physics_traces_match_goldens() {
+    for plant in plants::builtin() {
+        let name = plant.name;
+        let mut app = headless_app(DEFAULT_TIME_STEP);
+        (plant.add)(&mut app);
+        app.finish();
+        app.cleanup();
+
+        let trace = record_trace(&mut app);
+        let path = golden_path(&format!("{name}.trace.json"));
+        if update_goldens() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, serde_json::to_string(&trace).unwrap()).unwrap();
+            continue;
+        }
+        let golden = fs::read_to_string(&path).unwrap_or_else(|error| {
+            panic!(
+                "{name}: missing golden {} ({error}), run with UPDATE_GOLDENS=1",
+                path.display()
+            )
+        });
+        let golden: Trace = serde_json::from_str(&golden).unwrap();
+        compare_traces(name, &golden, &trace);
+    }
+}
+
+fn capture_frame(app: &mut App) -> Image {
+    let mut target = Image::new_fill(
+        Extent3d {
+            width: FRAME_SIZE,
+            height: FRAME_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    target.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::RENDER_ATTACHMENT;
+    let target = app.world_mut().resource_mut::<Assets<Image>>().add(target);
+    app.world_mut().spawn((
+        Camera3d::default(),
+        Camera {
+            target: RenderTarget::Image(target.clone()),
+            ..default()
+        },
+        Transform::from_xyz(10.0, 10.0, 10.0).looking_at(Vec3::new(0.0, 3.0, 0.0), Vec3::Y),
+    ));
+
+    // Let the scene settle and the pipelines compile before capturing.
+    for _ in 0..TRACE_DECIMATION {
+        app.update();
+    }
+
+    let captured = Arc::new(Mutex::new(None));
+    let sink = captured.clone();
+    app.world_mut().spawn(Screenshot::image(target)).observe(
+        move |trigger: Trigger<ScreenshotCaptured>| {
+            *sink.lock().unwrap() = Some(trigger.event().0.clone());
+        },
+    );
+    for _ in 0..TRACE_STEPS {
+        app.update();
+        if let Some(image) = captured.lock().unwrap().take() {
+            return image;
+        }
+    }
+    panic!("no frame was captured");
+}
+
+#[test]
+#[ignore = "requires a GPU adapter"]
+fn frames_match_goldens() {
+    for plant in plants::builtin() {
+        let name = plant.name;
+        let mut app = offscreen_app(DEFAULT_TIME_STEP);
+        (plant.add)(&mut app);
+        app.finish();
+        app.cleanup();
+
+        let frame = capture_frame(&mut app)
+            .try_into_dynamic()
+            .unwrap()
+            .into_rgba8();
+        let path = golden_path(&format!("{name}.png"));
+        if update_goldens() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            frame.save(&path).unwrap();
+            continue;
+        }
+        let golden = image::open(&path)
+            .unwrap_or_else(|error| {
+                panic!(
+                    "{name}: missing golden {} ({error}), run with UPDATE_GOLDENS=1",
+                    path.display()
+                )
+            })
+            .into_rgba8();
+        assert_eq!(
+            golden.dimensions(),
+            frame.dimensions(),
+            "{name}: frame size"
+        );
+        let total_difference: u64 = golden
+            .as_raw()
+            .iter()
+            .zip(frame.as_raw())
+            .map(|(expected, found)| u64::from(expected.abs_diff(*found)))
+            .sum();
+        let mean_difference = total_difference as f64 / golden.as_raw().len() as f64;
+        assert!(
+            mean_difference <= FRAME_TOLERANCE,
+            "{name}: frame differs from golden by {mean_difference:.2} per channel"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! The reference governor keeps the constrained signals within their limits and still reaches
+//! the raw reference when it can.
+use digital_twin_playground::{
+    control::Saturation,
+    governor::{Constraint, Notch, ReferenceGovernor},
+    identification::LinearModel,
+};
+use nalgebra::{DMatrix, DVector};
+
+/// A first-order velocity loop, whose torque is proportional to the tracking error.
+fn closed_loop() -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["velocity/setpoint".to_string()],
+        outputs: vec!["velocity".to_string(), "torque".to_string()],
+        a: DMatrix::from_row_slice(1, 1, &[0.9]),
+        b: DMatrix::from_row_slice(1, 1, &[0.1]),
+        c: DMatrix::from_row_slice(2, 1, &[1.0, -5.0]),
+        d: DMatrix::from_row_slice(2, 1, &[0.0, 5.0]),
+        input_offsets: vec![0.0],
+        output_offsets: vec![0.0, 0.0],
+    }
+}
+
+fn torque_limit(limit: f32) -> Vec<Constraint> {
+    vec![Constraint {
+        signal: "torque".to_string(),
+        limits: Saturation::symmetric(limit),
+    }]
+}
+
+/// Runs the loop for `steps` steps towards `raw`, returning the governed references and the
+/// torques.
+fn run(governor: &mut ReferenceGovernor, raw: f32, steps: usize) -> (Vec<f32>, Vec<f64>) {
+    let model = closed_loop();
+    let mut state = DVector::zeros(1);
+    let mut references = Vec::new();
+    let mut torques = Vec::new();
+    for _ in 0..steps {
+        let reference = governor.update(raw);
+        let input = DVector::from_element(1, f64::from(reference));
+        torques.push((&model.c * &state + &model.d * &input)[1]);
+        state = &model.a * &state + &model.b * &input;
+        references.push(reference);
+    }
+    (references, torques)
+}
+
+#[test]
+fn constraints_hold_on_steps() {
+    let mut governor = ReferenceGovernor::new(closed_loop(), &torque_limit(10.0), 50).unwrap();
+    let (references, torques) = run(&mut governor, 10.0, 500);
+    // Without the governor, the step would ask for 50.
+    let peak = torques
+        .iter()
+        .fold(0.0f64, |peak, torque| peak.max(torque.abs()));
+    // Within the precision of the reference.
+    assert!(peak <= 10.0 + 1e-4, "{peak}");
+    assert!(references[0] < 10.0);
+    assert!((references.last().unwrap() - 10.0).abs() < 1e-3);
+}
+
+#[test]
+fn loose_constraints_pass_the_reference() {
+    let mut governor = ReferenceGovernor::new(closed_loop(), &torque_limit(100.0), 50).unwrap();
+    assert_eq!(governor.update(10.0), 10.0);
+    assert_eq!(governor.fraction(), 1.0);
+}
+
+#[test]
+fn unknown_signals_are_rejected() {
+    let constraints = vec![Constraint {
+        signal: "current".to_string(),
+        limits: Saturation::symmetric(1.0),
+    }];
+    assert!(ReferenceGovernor::new(closed_loop(), &constraints, 50).is_err());
+}
+
+#[test]
+fn notches_cancel_their_center() {
+    let notch = Notch {
+        frequency: 1.0,
+        zero_damping: 0.0,
+        pole_damping: Notch::POLE_DAMPING,
+    };
+    let mut filter = notch.filter(0.01).unwrap();
+    // A sine at the center, past the transient of the notch.
+    let peak = (0..2_000)
+        .map(|step| filter.update((std::f32::consts::TAU * step as f32 * 0.01).sin()))
+        .skip(1_000)
+        .fold(0.0f32, |peak, output| peak.max(output.abs()));
+    assert!(peak < 1e-2, "{peak}");
+    // Above the Nyquist frequency.
+    assert!(Notch {
+        frequency: 60.0,
+        ..notch
+    }
+    .filter(0.01)
+    .is_err());
+}
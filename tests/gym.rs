@@ -0,0 +1,71 @@
+//! Gyms reward the steps and end the episodes as configured.
+use digital_twin_playground::gym::{Gym, GymSettings, PoleBalancing, Reward, Scene, Termination};
+
+#[test]
+fn rewards_follow_the_observation_and_the_action() {
+    let upright = [0.0; 4];
+    let tilted = [0.0, 0.0, std::f32::consts::FRAC_PI_2, 0.0];
+    assert_eq!(Reward::Alive.of(&tilted, 1.0), 1.0);
+    assert_eq!(Reward::Upright.of(&upright, 0.0), 1.0);
+    assert!(Reward::Upright.of(&tilted, 0.0).abs() < 1e-6);
+    let quadratic = Reward::Quadratic {
+        q: [1.0, 0.0, 10.0, 0.0],
+        r: 0.5,
+    };
+    assert_eq!(quadratic.of(&[2.0, 5.0, 0.5, 5.0], 2.0), -(4.0 + 2.5 + 2.0));
+}
+
+#[test]
+fn episodes_end_beyond_the_limits() {
+    let termination = Termination {
+        angle: Some(0.2),
+        position: Some(1.0),
+        max_steps: Some(10),
+    };
+    assert!(!termination.done(&[0.5, 3.0, 0.1, 3.0], 5));
+    assert!(termination.done(&[0.5, 0.0, -0.3, 0.0], 5));
+    assert!(termination.done(&[-1.5, 0.0, 0.0, 0.0], 5));
+    assert!(termination.done(&[0.0; 4], 10));
+    assert!(termination.done(&[0.0, f32::NAN, 0.0, 0.0], 1));
+    let unbounded = Termination {
+        angle: None,
+        position: None,
+        max_steps: None,
+    };
+    assert!(!unbounded.done(&[100.0, 0.0, 3.0, 0.0], 100_000));
+}
+
+#[test]
+fn an_unbalanced_cart_pole_falls() {
+    let settings = GymSettings {
+        scene: Scene::CartPole,
+        reward: Reward::Alive,
+        termination: Termination {
+            angle: Some(0.2),
+            position: None,
+            max_steps: Some(1000),
+        },
+        initial_angle: [0.05, 0.1],
+        ..Default::default()
+    };
+    let mut gym = PoleBalancing::new(settings).unwrap();
+    let observation = gym.reset().unwrap();
+    assert!((0.05..=0.1).contains(&observation[2]), "{observation:?}");
+    let (mut steps, mut total) = (0, 0.0);
+    loop {
+        let (observation, reward, done) = gym.step(0.0).unwrap();
+        steps += 1;
+        total += reward;
+        if done {
+            // The pole fell on the side it was tilted to.
+            assert!(observation[2] > 0.2, "{observation:?}");
+            break;
+        }
+    }
+    assert!(steps < 1000);
+    assert_eq!(total, steps as f32);
+
+    // Each reset starts a new episode.
+    gym.reset().unwrap();
+    assert!(gym.time() < 2.0 * gym.settings.dt);
+}
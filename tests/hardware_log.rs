@@ -0,0 +1,84 @@
+//! Logs of the hardware are mapped to channels and aligned on the simulated runs.
+use std::path::Path;
+
+use digital_twin_playground::{
+    hardware_log::{self, ColumnMapping, HardwareLogSettings},
+    telemetry::Telemetry,
+};
+
+const LOG: &str = "t_ms;enc;setpoint\n\
+                   1000;0;0\n\
+                   1010;100;\n\
+                   1020;200;1\n\
+                   1030;;1\n";
+
+fn settings() -> HardwareLogSettings {
+    HardwareLogSettings {
+        delimiter: ';',
+        time_column: "t_ms".to_string(),
+        time_unit: 0.001,
+        columns: vec![
+            ColumnMapping {
+                column: "enc".to_string(),
+                channel: "motor/angle".to_string(),
+                scale: 0.01,
+                offset: 1.0,
+            },
+            ColumnMapping {
+                column: "setpoint".to_string(),
+                channel: "setpoint".to_string(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn columns_are_mapped_to_channels() {
+    let log = hardware_log::parse(LOG, &settings(), Path::new("log.csv")).unwrap();
+    let angle = &log.channels["motor/angle"];
+    assert_eq!(angle.len(), 3);
+    assert_eq!(angle[0], [0.0, 1.0]);
+    assert!((angle[2][0] - 0.02).abs() < 1e-6);
+    assert!((angle[2][1] - 3.0).abs() < 1e-6);
+    assert_eq!(log.channels["setpoint"].len(), 3);
+
+    let missing = HardwareLogSettings {
+        time_column: "time".to_string(),
+        ..settings()
+    };
+    assert!(hardware_log::parse(LOG, &missing, Path::new("log.csv")).is_err());
+    assert!(hardware_log::parse("", &settings(), Path::new("log.csv")).is_err());
+}
+
+#[test]
+fn logs_are_aligned_on_a_marker() {
+    let log = hardware_log::parse(LOG, &settings(), Path::new("log.csv")).unwrap();
+    // The run steps its setpoint at 0.5 s, the log at 0.02 s.
+    let mut run = Telemetry::default();
+    for step in 0..100 {
+        let time = step as f32 * 0.01;
+        run.record("setpoint", time, if time < 0.495 { 0.0 } else { 1.0 });
+        run.record("motor/angle", time, 1.0 + time);
+    }
+    let aligned = HardwareLogSettings {
+        marker: Some("setpoint".to_string()),
+        offset: 0.1,
+        ..settings()
+    };
+    let shift = hardware_log::shift(&log, &run, &aligned).unwrap();
+    assert!((shift - 0.58).abs() < 1e-5, "{shift}");
+    let shifted = hardware_log::shifted(&log, shift);
+    assert!((shifted.channels["motor/angle"][0][0] - 0.58).abs() < 1e-5);
+
+    // Without a marker, the log starts at the offset.
+    assert_eq!(hardware_log::shift(&log, &run, &settings()).unwrap(), 0.0);
+    let diff = hardware_log::compare(&log, &run, &settings()).unwrap();
+    let angle = diff
+        .channels
+        .iter()
+        .find(|channel| channel.channel == "motor/angle")
+        .unwrap();
+    assert!((angle.max - 1.98).abs() < 1e-4, "{}", angle.max);
+}
@@ -0,0 +1,52 @@
+//! Operator panels are laid out from JSON, and their widgets read the signals they are bound to.
+use digital_twin_playground::{
+    hmi::{signal_value, HmiLayout, Widget},
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+};
+
+const LAYOUT: &str = r#"{
+  "widgets": [
+    { "type": "lamp", "label": "Overload", "signal": "motor/torque", "threshold": 15.0 },
+    { "type": "display", "label": "Speed", "signal": "motor/velocity", "scale": 60.0, "unit": "rpm", "decimals": 0 },
+    { "type": "slider", "label": "Speed", "signal": "motor/velocity", "min": -1.0, "max": 1.0 },
+    { "type": "button", "label": "Stop", "signal": "motor/velocity", "value": 0.0 }
+  ]
+}"#;
+
+#[test]
+fn widgets_read_their_signals() {
+    let layout: HmiLayout = serde_json::from_str(LAYOUT).unwrap();
+    layout.validate().unwrap();
+    let mut telemetry = Telemetry::default();
+    telemetry.record("motor/torque", 0.0, 10.0);
+    telemetry.record("motor/torque", 0.1, -20.0);
+    let mut setpoints = Setpoints::default();
+    setpoints.set("motor/velocity", 0.5);
+
+    let Widget::Lamp(lamp) = &layout.widgets[0] else {
+        panic!("{:?}", layout.widgets[0]);
+    };
+    let torque = signal_value(&lamp.signal, Some(&telemetry), &setpoints);
+    assert!(lamp.is_lit(torque));
+    assert!(!lamp.is_lit(None));
+
+    // Setpoints are read when no telemetry has the signal.
+    let Widget::Display(display) = &layout.widgets[1] else {
+        panic!("{:?}", layout.widgets[1]);
+    };
+    let speed = signal_value(&display.signal, Some(&telemetry), &setpoints);
+    assert_eq!(display.format(speed), "30 rpm");
+    assert_eq!(display.format(None), "--- rpm");
+}
+
+#[test]
+fn invalid_layouts_are_rejected() {
+    let mut layout: HmiLayout = serde_json::from_str(LAYOUT).unwrap();
+    if let Widget::Slider(slider) = &mut layout.widgets[2] {
+        slider.max = slider.min;
+    }
+    assert!(layout.validate().is_err());
+    assert!(HmiLayout::default().validate().is_ok());
+    assert!(serde_json::from_str::<HmiLayout>(r#"{ "widgets": [{ "type": "gauge" }] }"#).is_err());
+}
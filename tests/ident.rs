@@ -0,0 +1,88 @@
+//! The physical parameters of a plant are fitted on a recorded run by least squares.
+use std::f64::consts::TAU;
+
+use digital_twin_playground::{
+    ident::{self, IdentSettings, ModelStructure},
+    telemetry::Telemetry,
+};
+
+const DT: f64 = 0.002;
+
+/// A run of `input` driving the plant of `acceleration`, integrated finely.
+fn run(input: impl Fn(f64) -> f64, acceleration: impl Fn(f64, f64, f64) -> f64) -> Telemetry {
+    let (mut output, mut derivative) = (0.0, 0.0);
+    let mut run = Telemetry::default();
+    for k in 0..5000 {
+        let time = k as f64 * DT;
+        let u = input(time);
+        for (name, value) in [("input", u), ("output", output)] {
+            run.channels
+                .entry(name.to_string())
+                .or_default()
+                .push([time as f32, value as f32]);
+        }
+        for _ in 0..10 {
+            derivative += acceleration(output, derivative, u) * DT / 10.0;
+            output += derivative * DT / 10.0;
+        }
+    }
+    run
+}
+
+fn settings(model: ModelStructure) -> IdentSettings {
+    IdentSettings {
+        model,
+        input: "input".to_string(),
+        output: "output".to_string(),
+        dt: DT as f32,
+        smoothing: 1,
+        ..Default::default()
+    }
+}
+
+fn assert_near(fit: &ident::Fit, name: &str, expected: f64, tolerance: f64) {
+    let parameter = fit.parameters.iter().find(|p| p.name == name).unwrap();
+    assert!(
+        (parameter.value - expected).abs() <= tolerance,
+        "{name}: {} instead of {expected}",
+        parameter.value
+    );
+}
+
+#[test]
+fn a_second_order_response_gives_its_transfer_function() {
+    let (frequency, damping, gain, offset) = (3.0, 0.2, 2.0, 0.5);
+    let square = |time: f64| if time % 4.0 < 2.0 { 1.0 } else { -0.5 };
+    let run = run(square, |y, dy, u| {
+        frequency * frequency * (gain * u + offset - y) - 2.0 * damping * frequency * dy
+    });
+    let fit = ident::fit(&run, &settings(ModelStructure::SecondOrder)).unwrap();
+    assert_near(&fit, "natural frequency", frequency, 0.03);
+    assert_near(&fit, "damping ratio", damping, 0.005);
+    assert_near(&fit, "gain", gain, 0.02);
+    assert_near(&fit, "offset", offset, 0.01);
+    assert!(fit.output_fit > 95.0, "{fit}");
+    assert_eq!(fit.simulated.len(), 5000);
+}
+
+#[test]
+fn a_pendulum_gives_its_mass_length_and_friction() {
+    let (mass, length, viscous, coulomb) = (0.5, 0.4, 0.02, 0.01);
+    let inertia = mass * length * length;
+    let torque = |time: f64| 0.3 * (TAU * 0.7 * time).sin() + 0.2 * (TAU * 1.9 * time).sin();
+    let run = run(torque, |theta, omega, u| {
+        (u - mass * 9.81 * length * theta.sin() - viscous * omega - coulomb * omega.signum())
+            / inertia
+    });
+    let fit = ident::fit(&run, &settings(ModelStructure::Pendulum)).unwrap();
+    assert_near(&fit, "mass", mass, 0.01);
+    assert_near(&fit, "length", length, 0.005);
+    assert_near(&fit, "viscous friction", viscous, 0.002);
+    assert_near(&fit, "Coulomb friction", coulomb, 0.003);
+}
+
+#[test]
+fn missing_channels_are_reported() {
+    let settings = settings(ModelStructure::SecondOrder);
+    assert!(ident::fit(&Telemetry::default(), &settings).is_err());
+}
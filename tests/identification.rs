@@ -0,0 +1,94 @@
+//! Subspace identification recovers known linear systems from their input/output data.
+use digital_twin_playground::identification::{fit_percent, identify, Dataset, LinearModel};
+use nalgebra::{DMatrix, DVector};
+
+/// A lightly damped second-order system with a direct feedthrough on its second output.
+fn reference_model() -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["u".to_string()],
+        outputs: vec!["position".to_string(), "force".to_string()],
+        a: DMatrix::from_row_slice(2, 2, &[0.98, 0.1, -0.2, 0.95]),
+        b: DMatrix::from_row_slice(2, 1, &[0.0, 0.1]),
+        c: DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.5, 0.2]),
+        d: DMatrix::from_row_slice(2, 1, &[0.0, 0.3]),
+        input_offsets: vec![0.0],
+        output_offsets: vec![1.0, -2.0],
+    }
+}
+
+/// Pseudo-random binary excitation.
+fn excitation(seed: u64, samples: usize) -> Vec<Vec<f64>> {
+    let mut random = seed;
+    (0..samples)
+        .map(|_| {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            vec![if random & 1 == 0 { 1.0 } else { -1.0 }]
+        })
+        .collect()
+}
+
+fn dataset(model: &LinearModel, seed: u64) -> Dataset {
+    let inputs = excitation(seed, 800);
+    let outputs = model.simulate(&inputs, &DVector::zeros(model.order()));
+    Dataset {
+        dt: model.dt,
+        inputs,
+        outputs,
+    }
+}
+
+#[test]
+fn recovers_a_second_order_system() {
+    let reference = reference_model();
+    let model = identify(
+        &dataset(&reference, 1),
+        &reference.inputs,
+        &reference.outputs,
+        2,
+        6,
+    )
+    .unwrap();
+    assert_eq!(model.order(), 2);
+
+    // The state basis is arbitrary, but the poles and the responses aren't.
+    let mut poles = model.a.complex_eigenvalues();
+    let mut expected = reference.a.complex_eigenvalues();
+    poles.as_mut_slice().sort_by(|a, b| a.im.total_cmp(&b.im));
+    expected
+        .as_mut_slice()
+        .sort_by(|a, b| a.im.total_cmp(&b.im));
+    assert!((poles - expected).norm() < 1e-3);
+
+    let validation = dataset(&reference, 2);
+    let initial_state =
+        model.estimate_initial_state(&validation.inputs[..20], &validation.outputs[..20]);
+    let predicted = model.simulate(&validation.inputs, &initial_state);
+    for fit in fit_percent(&validation.outputs, &predicted) {
+        assert!(fit > 99.0, "fit of {fit} %");
+    }
+}
+
+#[test]
+fn rejects_unexcited_orders() {
+    let reference = reference_model();
+    let result = identify(
+        &dataset(&reference, 1),
+        &reference.inputs,
+        &reference.outputs,
+        8,
+        6,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn models_survive_a_round_trip() {
+    let reference = reference_model();
+    let path = std::env::temp_dir().join("identification_round_trip.json");
+    reference.write(&path).unwrap();
+    assert_eq!(LinearModel::read(&path).unwrap(), reference);
+    let _ = std::fs::remove_file(path);
+}
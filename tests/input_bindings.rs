@@ -0,0 +1,80 @@
+//! Actions follow the buttons bound to them, and the gamepad axes drive torques past their
+//! deadzone.
+use bevy::{input::gamepad::GamepadButton, prelude::KeyCode};
+use digital_twin_playground::input_bindings::{
+    InputAction, InputActions, InputBindings, InputButton, TorqueCommand,
+};
+
+#[test]
+fn actions_are_pressed_by_any_of_their_buttons() {
+    let bindings = InputBindings::default();
+    let pressed = bindings.pressed(|button| {
+        matches!(
+            button,
+            InputButton::Key(KeyCode::Space | KeyCode::KeyA)
+                | InputButton::Gamepad(GamepadButton::Start)
+        )
+    });
+    assert_eq!(
+        pressed,
+        vec![InputAction::OrbitLeft, InputAction::TogglePause]
+    );
+    assert!(bindings
+        .buttons(InputAction::TogglePause)
+        .any(|button| button == InputButton::Gamepad(GamepadButton::Start)));
+
+    let disabled = InputBindings {
+        enabled: false,
+        ..bindings
+    };
+    assert!(disabled.pressed(|_| true).is_empty());
+}
+
+#[test]
+fn actions_are_just_pressed_once() {
+    let mut actions = InputActions::default();
+    actions.update(vec![InputAction::Step, InputAction::JogPositive]);
+    assert!(actions.just_pressed(InputAction::Step));
+    assert_eq!(
+        actions.direction(InputAction::JogPositive, InputAction::JogNegative),
+        1.0
+    );
+
+    actions.update(vec![InputAction::Step, InputAction::JogNegative]);
+    assert!(actions.pressed(InputAction::Step) && !actions.just_pressed(InputAction::Step));
+    assert!(actions.just_pressed(InputAction::JogNegative));
+    assert_eq!(
+        actions.direction(InputAction::JogPositive, InputAction::JogNegative),
+        -1.0
+    );
+
+    actions.update(Vec::new());
+    assert!(!actions.pressed(InputAction::Step));
+}
+
+#[test]
+fn torques_follow_the_axes_past_their_deadzone() {
+    let torque = TorqueCommand {
+        scale: 2.0,
+        deadzone: 0.2,
+        ..Default::default()
+    };
+    assert_eq!(torque.torque(0.1), 0.0);
+    assert!((torque.torque(0.6) - 1.0).abs() < 1e-5);
+    assert!((torque.torque(-1.5) + 2.0).abs() < 1e-5);
+    let inverted = TorqueCommand {
+        invert: true,
+        ..torque
+    };
+    assert!((inverted.torque(0.6) + 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn bindings_round_trip_through_json() {
+    let bindings = InputBindings::default();
+    let json = serde_json::to_string(&bindings).unwrap();
+    assert_eq!(
+        serde_json::from_str::<InputBindings>(&json).unwrap(),
+        bindings
+    );
+}
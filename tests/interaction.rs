@@ -0,0 +1,34 @@
+//! The spring dragging a link pulls towards the cursor, damped and limited.
+use bevy::math::Vec3;
+use digital_twin_playground::interaction::InteractionSettings;
+
+#[test]
+fn springs_pull_towards_the_cursor() {
+    let settings = InteractionSettings::default();
+    let force = settings.spring_force(Vec3::ZERO, Vec3::X, Vec3::ZERO);
+    assert_eq!(force, settings.stiffness * Vec3::X);
+    assert_eq!(
+        settings.spring_force(Vec3::X, Vec3::X, Vec3::ZERO),
+        Vec3::ZERO
+    );
+}
+
+#[test]
+fn springs_are_damped() {
+    let settings = InteractionSettings::default();
+    // Moving towards the cursor, the damping holds the point back.
+    let moving = settings.spring_force(Vec3::ZERO, Vec3::X, Vec3::X);
+    assert!(moving.x < settings.stiffness, "{moving}");
+    assert_eq!(moving.y, 0.0);
+}
+
+#[test]
+fn forces_are_limited() {
+    let settings = InteractionSettings {
+        max_force: 10.0,
+        ..Default::default()
+    };
+    let force = settings.spring_force(Vec3::ZERO, Vec3::new(0.0, 100.0, 100.0), Vec3::ZERO);
+    assert!((force.length() - 10.0).abs() < 1e-4, "{force}");
+    assert!(force.y > 0.0 && (force.y - force.z).abs() < 1e-4);
+}
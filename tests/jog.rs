@@ -0,0 +1,45 @@
+//! Incremental moves cover their distance without exceeding the jog speed.
+use digital_twin_playground::jog::{IncrementalMove, JogAxis, JogUnit};
+
+const DT: f32 = 1.0 / 60.0;
+
+/// Runs a move to its end, returning the distance covered and the peak velocity.
+fn run(mut incremental: IncrementalMove) -> (f32, f32) {
+    let (mut covered, mut peak) = (0.0f32, 0.0f32);
+    for _ in 0..10_000 {
+        if incremental.is_done() {
+            break;
+        }
+        let velocity = incremental.velocity(DT);
+        incremental.advance(velocity, DT);
+        covered += velocity * DT;
+        peak = peak.max(velocity.abs());
+    }
+    assert!(incremental.is_done());
+    (covered, peak)
+}
+
+#[test]
+fn moves_cover_their_distance() {
+    for distance in [0.1f32, 1.0, 10.0, -10.0] {
+        let distance = distance.to_radians();
+        let speed = 30f32.to_radians();
+        let (covered, peak) = run(IncrementalMove::new(distance, speed));
+        assert!((covered - distance).abs() < 1e-5, "{covered} vs {distance}");
+        assert!(peak <= speed * (1.0 + 1e-6), "{peak}");
+    }
+}
+
+#[test]
+fn jog_speed_respects_the_velocity_limit() {
+    let axis = JogAxis {
+        name: "x".to_string(),
+        setpoint: "x/velocity".to_string(),
+        unit: JogUnit::Millimeters,
+        speed: 500.0,
+        max_velocity: 200.0,
+    };
+    assert!((axis.velocity() - 0.2).abs() < 1e-6);
+    // Stopped moves don't move.
+    assert!(IncrementalMove::new(1.0, 0.0).is_done());
+}
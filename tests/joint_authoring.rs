@@ -0,0 +1,53 @@
+//! Joints authored in the application hold the links at their authored relative pose.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{joint_authoring::AuthoredJoint, joint_builder::JointKind};
+
+fn authored(kind: JointKind) -> AuthoredJoint {
+    AuthoredJoint {
+        parent: "feeder/base".to_string(),
+        child: "feeder/arm".to_string(),
+        kind,
+        anchor: Vec3::new(0.0, 1.0, 0.0),
+        axis: Vec3::X,
+        limits: Some([-1.0, 1.0]),
+        // The arm is above the base, turned a quarter turn about the vertical.
+        child_pose: Transform::from_xyz(0.0, 1.5, 0.0)
+            .with_rotation(Quat::from_rotation_y(FRAC_PI_2)),
+    }
+}
+
+#[test]
+fn anchors_and_axes_match_in_both_frames() {
+    let joint = authored(JointKind::Revolute);
+    let child_anchor = joint.child_anchor();
+    assert!(child_anchor.abs_diff_eq(Vec3::new(0.0, -0.5, 0.0), 1e-6));
+    // The anchor is the same point of the world seen from either link.
+    let parent = Transform::from_xyz(2.0, 0.0, 0.0);
+    let child = parent * joint.child_pose;
+    assert!(parent
+        .transform_point(joint.anchor)
+        .abs_diff_eq(child.transform_point(child_anchor), 1e-5));
+
+    let built = joint.joint();
+    let generic = built.as_ref();
+    assert!(generic.local_anchor1().abs_diff_eq(joint.anchor, 1e-6));
+    assert!(generic.local_anchor2().abs_diff_eq(child_anchor, 1e-6));
+    assert!(generic.local_axis1().abs_diff_eq(Vec3::X, 1e-6));
+    // The arm sees the axis of the base turned with it.
+    assert!(generic.local_axis2().abs_diff_eq(Vec3::Z, 1e-6));
+    assert_eq!(
+        generic.limits(JointAxis::AngX).map(|limits| limits.min),
+        Some(-1.0)
+    );
+}
+
+#[test]
+fn joints_survive_the_configuration_file() {
+    let joint = authored(JointKind::Fixed);
+    let json = serde_json::to_string(&joint).unwrap();
+    assert_eq!(serde_json::from_str::<AuthoredJoint>(&json).unwrap(), joint);
+    assert!(json.contains(r#""kind":"fixed""#), "{json}");
+}
@@ -0,0 +1,113 @@
+//! The links described by the nodes of a glTF scene become rigid bodies joined to each other.
+use bevy::{gltf::GltfExtras, prelude::*};
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    joint_builder::{BodyKind, JointBuilderPlugin, JointKind, LinkMetadata},
+    plants::Link,
+};
+
+fn node(name: &str, extras: Option<&str>, translation: Vec3) -> impl Bundle {
+    (
+        Name::new(name.to_string()),
+        Transform::from_translation(translation),
+        GltfExtras {
+            value: extras.unwrap_or("{}").to_string(),
+        },
+    )
+}
+
+#[test]
+fn nodes_describe_links() {
+    assert_eq!(
+        LinkMetadata::parse("Cube.001", Some(r#"{"axis": 1}"#)).unwrap(),
+        None
+    );
+    let link = LinkMetadata::parse("link_arm", None).unwrap().unwrap();
+    assert_eq!(link.name(), "arm");
+    assert_eq!(link.joint, None);
+
+    let link = LinkMetadata::parse(
+        "Pendulum",
+        Some(r#"{"link": "pendulum", "joint": "revolute", "axis": [1, 0, 0], "limits": [-1, 1]}"#),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(link.name(), "pendulum");
+    assert_eq!(link.body, BodyKind::Dynamic);
+    assert_eq!(link.joint, Some(JointKind::Revolute));
+    assert_eq!(link.axis, Some(Vec3::X));
+    assert_eq!(link.limits, Some([-1.0, 1.0]));
+
+    assert!(LinkMetadata::parse("link_arm", Some(r#"{"joint": "hinge"}"#)).is_err());
+}
+
+#[test]
+fn scenes_are_articulated() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(JointBuilderPlugin);
+    app.finish();
+    app.cleanup();
+    app.update();
+
+    let world = app.world_mut();
+    let base = world
+        .spawn(node(
+            "Base",
+            Some(r#"{"link": "base", "body": "fixed"}"#),
+            Vec3::ZERO,
+        ))
+        .id();
+    let column = world
+        .spawn(node(
+            "link_column",
+            Some(r#"{"joint": "revolute", "axis": [0, 1, 0], "mass": 2.0}"#),
+            Vec3::Y,
+        ))
+        .set_parent(base)
+        .id();
+    let arm = world
+        .spawn(node("link_arm", None, Vec3::new(0.5, 0.0, 0.0)))
+        .set_parent(column)
+        .id();
+    let pendulum = world
+        .spawn(node(
+            "Pendulum",
+            Some(r#"{"link": "pendulum", "parent": "column", "joint": "spherical", "multibody": true}"#),
+            Vec3::new(1.0, 0.0, 0.0),
+        ))
+        .set_parent(base)
+        .id();
+    app.update();
+
+    let world = app.world();
+    let name = |entity| world.get::<Link>(entity).unwrap().name.clone();
+    assert_eq!(name(base), "base");
+    assert_eq!(name(column), "column");
+    assert_eq!(name(arm), "arm");
+    assert_eq!(name(pendulum), "pendulum");
+    assert_eq!(*world.get::<RigidBody>(base).unwrap(), RigidBody::Fixed);
+    assert_eq!(*world.get::<RigidBody>(arm).unwrap(), RigidBody::Dynamic);
+    assert!(world.get::<ImpulseJoint>(base).is_none());
+    assert!(world.get::<AdditionalMassProperties>(column).is_some());
+
+    // Joined to their ancestors, where the scene places them.
+    let joint = world.get::<ImpulseJoint>(column).unwrap();
+    assert_eq!(joint.parent, base);
+    assert!(joint
+        .data
+        .as_ref()
+        .local_anchor1()
+        .abs_diff_eq(Vec3::Y, 1e-5));
+    let joint = world.get::<ImpulseJoint>(arm).unwrap();
+    assert_eq!(joint.parent, column);
+    assert!(joint
+        .data
+        .as_ref()
+        .local_anchor1()
+        .abs_diff_eq(Vec3::new(0.5, 0.0, 0.0), 1e-5));
+    // Or to the link they name.
+    let joint = world.get::<MultibodyJoint>(pendulum).unwrap();
+    assert_eq!(joint.parent, column);
+    assert!(world.get::<ImpulseJoint>(pendulum).is_none());
+}
@@ -0,0 +1,62 @@
+//! Joints are at a limit within its margin, pushed back out of the soft limits, and driven into
+//! them by motors pulling towards them.
+use bevy_rapier3d::prelude::JointMotor;
+use digital_twin_playground::joint_limits::{
+    driven_into, limit_side, soft_limit_force, JointLimitSettings, LimitSide,
+};
+
+const RANGE: [f32; 2] = [-1.0, 2.0];
+
+#[test]
+fn positions_within_the_margin_are_at_the_limit() {
+    assert_eq!(limit_side(0.5, RANGE, 0.1), None);
+    assert_eq!(limit_side(1.95, RANGE, 0.1), Some(LimitSide::Upper));
+    assert_eq!(limit_side(2.5, RANGE, 0.1), Some(LimitSide::Upper));
+    assert_eq!(limit_side(-0.95, RANGE, 0.1), Some(LimitSide::Lower));
+    assert_eq!(limit_side(-3.0, RANGE, 0.1), Some(LimitSide::Lower));
+    assert_eq!(limit_side(1.95, RANGE, 0.0), None);
+}
+
+#[test]
+fn soft_limits_push_back_and_brake_towards_the_limit() {
+    let settings = JointLimitSettings {
+        margin: 0.1,
+        stiffness: 100.0,
+        damping: 10.0,
+        ..Default::default()
+    };
+    assert_eq!(soft_limit_force(0.5, 3.0, RANGE, &settings), 0.0);
+    // 0.05 into the margin of the upper limit, moving towards it at 1.
+    let force = soft_limit_force(1.95, 1.0, RANGE, &settings);
+    assert!((force - (-5.0 - 10.0)).abs() < 1e-4, "{force}");
+    // Leaving the limit isn't braked.
+    let force = soft_limit_force(1.95, -1.0, RANGE, &settings);
+    assert!((force + 5.0).abs() < 1e-4, "{force}");
+    let force = soft_limit_force(-1.2, -2.0, RANGE, &settings);
+    assert!((force - (30.0 + 20.0)).abs() < 1e-4, "{force}");
+}
+
+#[test]
+fn motors_pulling_towards_a_limit_drive_into_it() {
+    let velocity = |target_vel| JointMotor {
+        target_vel,
+        damping: 1000.0,
+        ..Default::default()
+    };
+    assert!(driven_into(LimitSide::Upper, 1.95, &velocity(1.0)));
+    assert!(!driven_into(LimitSide::Upper, 1.95, &velocity(-1.0)));
+    assert!(driven_into(LimitSide::Lower, -0.95, &velocity(-1.0)));
+
+    let position = |target_pos| JointMotor {
+        target_pos,
+        stiffness: 1000.0,
+        ..Default::default()
+    };
+    assert!(driven_into(LimitSide::Upper, 1.95, &position(2.5)));
+    assert!(!driven_into(LimitSide::Upper, 1.95, &position(1.0)));
+    assert!(!driven_into(
+        LimitSide::Lower,
+        -0.95,
+        &JointMotor::default()
+    ));
+}
@@ -0,0 +1,129 @@
+//! The kinematic playback poses the links from the positions of their joints, with the dynamics
+//! off, read from sliders or from trajectory files.
+use std::{
+    collections::BTreeMap,
+    f32::consts::{FRAC_PI_2, PI},
+    path::Path,
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    kinematic_playback::{self, JointTrajectory, KinematicPlayback, KinematicPlaybackPlugin},
+    plants::Link,
+};
+
+#[test]
+fn trajectory_files_are_interpolated_between_rows() {
+    let path = Path::new("reach.csv");
+    let text = "t,upper_arm,forearm\n10.0,0.0,1.0\n11.0,1.0,3.0\n\n13.0,2.0,3.0\n";
+    let trajectory = JointTrajectory::parse(text, "t", ',', path).unwrap();
+    assert_eq!(trajectory.joints, ["upper_arm", "forearm"]);
+    assert_eq!(trajectory.duration(), 3.0);
+    assert_eq!(trajectory.positions(0.5), [0.5, 2.0]);
+    assert_eq!(trajectory.positions(2.0), [1.5, 3.0]);
+    // Held past the ends.
+    assert_eq!(trajectory.positions(-1.0), [0.0, 1.0]);
+    assert_eq!(trajectory.positions(5.0), [2.0, 3.0]);
+
+    assert!(JointTrajectory::parse(text, "time", ',', path).is_err());
+    assert!(JointTrajectory::parse("t,arm\n0.0,1.0\n1.0\n", "t", ',', path).is_err());
+    assert!(JointTrajectory::parse("t,arm\n1.0,0.0\n0.0,1.0\n", "t", ',', path).is_err());
+    assert!(JointTrajectory::parse("t,arm\n", "t", ',', path).is_err());
+}
+
+#[test]
+fn bodies_are_posed_through_the_frames_of_their_joints() {
+    let revolute = RevoluteJointBuilder::new(Vec3::Z)
+        .local_anchor1(Vec3::Y)
+        .local_anchor2(Vec3::Y * 0.5)
+        .build()
+        .data;
+    let base = Transform::IDENTITY;
+    let assembled = kinematic_playback::joint_pose(&base, &revolute, 0.0);
+    assert!(assembled.translation.abs_diff_eq(Vec3::Y * 0.5, 1e-5));
+    assert!(assembled.rotation.abs_diff_eq(Quat::IDENTITY, 1e-5));
+    // A quarter turn about the pivot.
+    let turned = kinematic_playback::joint_pose(&base, &revolute, FRAC_PI_2);
+    assert!(turned
+        .translation
+        .abs_diff_eq(Vec3::new(0.5, 1.0, 0.0), 1e-5));
+    assert!(turned
+        .rotation
+        .abs_diff_eq(Quat::from_rotation_z(FRAC_PI_2), 1e-5));
+
+    let prismatic = PrismaticJointBuilder::new(Vec3::X)
+        .local_anchor1(Vec3::Y)
+        .build()
+        .data;
+    let parent = Transform::from_xyz(1.0, 0.0, 0.0).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+    let slid = kinematic_playback::joint_pose(&parent, &prismatic, 0.3);
+    assert!(slid.translation.abs_diff_eq(Vec3::new(0.0, 0.3, 0.0), 1e-5));
+    assert!(slid.rotation.abs_diff_eq(parent.rotation, 1e-5));
+}
+
+#[test]
+fn the_mode_poses_the_links_and_puts_them_back() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(KinematicPlaybackPlugin);
+    app.finish();
+    app.cleanup();
+    app.update();
+
+    let world = app.world_mut();
+    let base = world
+        .spawn((
+            RigidBody::Fixed,
+            Transform::IDENTITY,
+            Collider::cuboid(0.1, 0.1, 0.1),
+        ))
+        .id();
+    let arm = world
+        .spawn((
+            RigidBody::Dynamic,
+            Collider::cuboid(0.05, 0.5, 0.05),
+            GravityScale(0.0),
+            Transform::from_xyz(0.0, 0.5, 0.0),
+            ImpulseJoint::new(
+                base,
+                RevoluteJointBuilder::new(Vec3::Z)
+                    .local_anchor1(Vec3::Y)
+                    .local_anchor2(Vec3::Y * 0.5),
+            ),
+            Link {
+                plant: String::new(),
+                name: "arm".to_string(),
+            },
+        ))
+        .id();
+    app.update();
+
+    let mut playback = app.world_mut().resource_mut::<KinematicPlayback>();
+    playback.enabled = true;
+    playback.positions = BTreeMap::from([("arm".to_string(), -PI / 2.0)]);
+    app.update();
+    app.update();
+    let world = app.world();
+    assert!(world.resource::<KinematicPlayback>().is_active());
+    assert_eq!(
+        *world.get::<RigidBody>(arm).unwrap(),
+        RigidBody::KinematicPositionBased
+    );
+    let posed = world.get::<Transform>(arm).unwrap().translation;
+    assert!(
+        posed.abs_diff_eq(Vec3::new(-0.5, 1.0, 0.0), 1e-3),
+        "{posed}"
+    );
+    let joints = &world.resource::<KinematicPlayback>().joints;
+    assert_eq!(joints.len(), 1);
+    assert_eq!(joints[0].path, "arm");
+
+    app.world_mut().resource_mut::<KinematicPlayback>().enabled = false;
+    app.update();
+    let world = app.world();
+    assert!(!world.resource::<KinematicPlayback>().is_active());
+    assert_eq!(*world.get::<RigidBody>(arm).unwrap(), RigidBody::Dynamic);
+    let restored = world.get::<Transform>(arm).unwrap().translation;
+    assert!(restored.abs_diff_eq(Vec3::Y * 0.5, 1e-3), "{restored}");
+}
@@ -0,0 +1,70 @@
+//! The forward kinematics of a planar arm, and the inverse kinematics reaching its targets.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::math::Vec3;
+use digital_twin_playground::kinematics::{ChainJoint, IkSolver, JointKind, KinematicChain};
+
+/// A two-link planar arm pointing up: an upper arm of 1 m and a forearm of 0.8 m, both turning
+/// about Z.
+fn planar_arm() -> KinematicChain {
+    let joint = |name: &str, anchor: Vec3| ChainJoint {
+        name: name.to_string(),
+        kind: JointKind::Revolute,
+        anchor,
+        axis: Vec3::Z,
+        reference: 0.0,
+        limits: None,
+        locked: false,
+    };
+    KinematicChain {
+        joints: vec![joint("upper_arm", Vec3::ZERO), joint("forearm", Vec3::Y)],
+        tip: Vec3::Y * 1.8,
+    }
+}
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(actual.distance(expected) < 1e-5, "{actual} vs {expected}");
+}
+
+#[test]
+fn forward_kinematics_turns_the_links() {
+    let arm = planar_arm();
+    assert_near(arm.end_effector(&[0.0, 0.0]), Vec3::Y * 1.8);
+    assert_near(arm.end_effector(&[FRAC_PI_2, 0.0]), -Vec3::X * 1.8);
+    let points = arm.forward(&[0.0, FRAC_PI_2]);
+    assert_near(points[1], Vec3::Y);
+    assert_near(points[2], Vec3::new(-0.8, 1.0, 0.0));
+}
+
+#[test]
+fn reachable_targets_are_reached() {
+    let arm = planar_arm();
+    let target = Vec3::new(1.0, 1.0, 0.0);
+    let solution = arm.solve(&[0.0, 0.0], target, &IkSolver::default());
+    assert!(solution.converged, "{solution:?}");
+    assert!(arm.end_effector(&solution.positions).distance(target) <= 1e-3);
+}
+
+#[test]
+fn out_of_reach_targets_are_approached() {
+    let arm = planar_arm();
+    let solution = arm.solve(&[0.0, 0.0], Vec3::X * 3.0, &IkSolver::default());
+    assert!(!solution.converged);
+    // Stretched towards the target, 1.2 m short of it.
+    assert!(solution.error < 1.25, "{}", solution.error);
+}
+
+#[test]
+fn locked_and_limited_joints_are_respected() {
+    let mut arm = planar_arm();
+    arm.joints[1].locked = true;
+    let target = Vec3::new(1.2f32.cos(), 1.2f32.sin(), 0.0) * 1.8;
+    let solution = arm.solve(&[0.0, 0.0], target, &IkSolver::default());
+    assert!(solution.converged, "{solution:?}");
+    assert_eq!(solution.positions[1], 0.0);
+
+    let mut arm = planar_arm();
+    arm.joints[0].limits = Some([-0.5, 0.5]);
+    let solution = arm.solve(&[0.0, 0.0], Vec3::new(-1.5, 0.5, 0.0), &IkSolver::default());
+    assert!((-0.5..=0.5).contains(&solution.positions[0]));
+}
@@ -0,0 +1,81 @@
+//! The paths of the control loops deliver their values after their dead time, the freshest
+//! first.
+use digital_twin_playground::latency::{
+    DeadTime, LoopLatency, LoopLatencySettings, NetworkLatency, PathLatency,
+};
+
+#[test]
+fn values_arrive_after_the_delay() {
+    let mut path = DeadTime::new(
+        PathLatency {
+            delay: 0.02,
+            jitter: 0.0,
+        },
+        1,
+    );
+    let mut received = Vec::new();
+    for step in 0..10 {
+        let time = step as f32 * 0.01;
+        path.send(time, step as f32);
+        received.push(path.receive(time));
+    }
+    // Two steps late.
+    assert_eq!(received[..2], [None, None]);
+    for (step, value) in received.iter().enumerate().skip(2) {
+        assert_eq!(*value, Some((step - 2) as f32));
+    }
+    assert_eq!(path.pending(), 2);
+
+    // A new run starts empty.
+    assert_eq!(path.receive(0.0), None);
+    assert_eq!(path.pending(), 0);
+}
+
+#[test]
+fn jitter_never_delivers_stale_values() {
+    let mut path = DeadTime::new(
+        PathLatency {
+            delay: 0.01,
+            jitter: 0.05,
+        },
+        7,
+    );
+    let mut last = None;
+    for step in 0..1000 {
+        let time = step as f32 * 0.001;
+        path.send(time, time);
+        if let Some(value) = path.receive(time) {
+            assert!(time - value >= 0.01 - 1e-5, "{value} read at {time}");
+            assert!(time - value <= 0.06 + 1e-5, "{value} read at {time}");
+            assert!(
+                last.is_none_or(|last| value >= last),
+                "{value} after {last:?}"
+            );
+            last = Some(value);
+        }
+    }
+    assert!(last.is_some());
+}
+
+#[test]
+fn loops_through_the_network_take_the_latency_of_the_links() {
+    let network = NetworkLatency {
+        sensing: PathLatency {
+            delay: 0.02,
+            jitter: 0.001,
+        },
+        actuation: PathLatency {
+            delay: 0.01,
+            jitter: 0.0,
+        },
+    };
+    let settings = LoopLatencySettings {
+        network: true,
+        ..LoopLatencySettings::default()
+    }
+    .resolve(&network);
+    assert_eq!(settings.sensing, network.sensing);
+    assert_eq!(settings.actuation, network.actuation);
+    let latency = LoopLatency::new(settings, "latency/motor".to_string(), 1);
+    assert!((latency.dead_time() - 0.03).abs() < 1e-6);
+}
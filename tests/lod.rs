@@ -0,0 +1,39 @@
+//! The instances of the plants share their meshes and materials, and are drawn through
+//! low-detail stand-ins far from the camera.
+use bevy::prelude::*;
+use digital_twin_playground::lod;
+
+#[test]
+fn meshes_and_materials_drawing_the_same_share_a_key() {
+    let cube = Mesh::from(Cuboid::new(1.0, 1.0, 1.0));
+    assert_eq!(
+        lod::mesh_key(&cube),
+        lod::mesh_key(&Mesh::from(Cuboid::new(1.0, 1.0, 1.0)))
+    );
+    assert_ne!(
+        lod::mesh_key(&cube),
+        lod::mesh_key(&Mesh::from(Cuboid::new(1.0, 2.0, 1.0)))
+    );
+
+    let grey = StandardMaterial::from(Color::srgb_u8(124, 124, 124));
+    assert_eq!(
+        lod::material_key(&grey),
+        lod::material_key(&StandardMaterial::from(Color::srgb_u8(124, 124, 124)))
+    );
+    assert_ne!(
+        lod::material_key(&grey),
+        lod::material_key(&StandardMaterial::from(Color::srgb_u8(200, 0, 0)))
+    );
+}
+
+#[test]
+fn stand_ins_bound_the_detailed_meshes() {
+    assert!(lod::proxy(&Mesh::from(Cuboid::new(1.0, 1.0, 1.0))).is_none());
+
+    let cylinder = Mesh::from(Cylinder::new(0.25, 3.0)).translated_by(Vec3::X);
+    let proxy = lod::proxy(&cylinder).unwrap();
+    assert!(proxy.count_vertices() < cylinder.count_vertices());
+    let aabb = proxy.compute_aabb().unwrap();
+    assert!(Vec3::from(aabb.center).abs_diff_eq(Vec3::X, 1e-5));
+    assert!(Vec3::from(aabb.half_extents).abs_diff_eq(Vec3::new(0.25, 1.5, 0.25), 1e-5));
+}
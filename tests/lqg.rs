@@ -0,0 +1,109 @@
+//! Output feedback: steady-state Kalman filters of linear models, and the poles of the regulator
+//! on the estimated state of the pendulum.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    control::{self, Observer},
+    estimation::EstimationSettings,
+    lqg,
+    lqr::{LqrController, LqrDesign, LqrSettings},
+};
+use nalgebra::DMatrix;
+
+fn scalar(value: f64) -> DMatrix<f64> {
+    DMatrix::from_element(1, 1, value)
+}
+
+#[test]
+fn scalar_kalman_filters_are_solved() {
+    // The dual of the scalar regulator: the predicted variance is the golden ratio.
+    let observer =
+        Observer::kalman(&scalar(1.0), &scalar(1.0), &scalar(1.0), &scalar(1.0)).unwrap();
+    let ratio = (1.0 + 5f64.sqrt()) / 2.0;
+    assert!((observer.predicted[0] - ratio).abs() < 1e-9);
+    assert!((observer.gain[0] - ratio / (1.0 + ratio)).abs() < 1e-9);
+    assert!((observer.corrected[0] - ratio / (1.0 + ratio)).abs() < 1e-9);
+    let error = observer.error_dynamics(&scalar(1.0), &scalar(1.0));
+    assert!((error[0] - 1.0 / (1.0 + ratio)).abs() < 1e-9);
+
+    // An unstable mode the output doesn't see can't be estimated.
+    assert!(Observer::kalman(&scalar(2.0), &scalar(0.0), &scalar(1.0), &scalar(1.0)).is_none());
+}
+
+#[test]
+fn poles_are_given_as_continuous_modes() {
+    let poles = control::poles(&scalar(0.9), 0.1);
+    assert_eq!(poles.len(), 1);
+    assert!((poles[0].frequency - -(0.9f64.ln()) / 0.1 / std::f64::consts::TAU).abs() < 1e-12);
+    assert!((poles[0].damping - 1.0).abs() < 1e-12);
+    assert!(poles[0].stable());
+
+    // A rotation by a tenth of a turn per step, slightly decaying: an oscillation of 1 Hz.
+    let (sin, cos) = (std::f64::consts::TAU / 10.0).sin_cos();
+    let rotation = DMatrix::from_row_slice(2, 2, &[cos, -sin, sin, cos]) * 0.99;
+    let poles = control::poles(&rotation, 0.1);
+    assert_eq!(poles.len(), 1);
+    assert!(
+        (poles[0].frequency - 1.0).abs() < 0.01,
+        "{}",
+        poles[0].frequency
+    );
+    assert!(poles[0].damping > 0.0 && poles[0].damping < 0.05);
+    assert!(!control::poles(&scalar(1.1), 0.1)[0].stable());
+}
+
+#[test]
+fn the_default_lqg_is_stable() {
+    let step = 1.0 / 60.0;
+    let (lqr, estimation) = (LqrSettings::default(), EstimationSettings::default());
+    let summary = lqg::summarize(&lqr, &estimation, [1e-6, 1e-6], step).unwrap();
+    assert!(summary.stable(), "{summary:?}");
+    assert_eq!(summary.period, step);
+    assert_eq!(summary.observer_gain.shape(), (4, 2));
+    // The angles are estimated about as well as they're read, the velocities from them.
+    let [arm, velocity, angle, rate] = summary.deviations;
+    assert!(arm > 0.0 && arm <= 1e-3 && angle > 0.0 && angle <= 1e-3);
+    assert!(velocity.is_finite() && velocity > 0.0 && rate.is_finite() && rate > 0.0);
+
+    // The servo form regulates the integral of the arm angle too, sampled at its own rate.
+    let servo = LqrSettings {
+        design: LqrDesign {
+            integral: Some(1.0),
+            ..Default::default()
+        },
+        sample_rate: Some(20.0),
+        ..lqr
+    };
+    let summary = lqg::summarize(&servo, &estimation, [1e-6, 1e-6], step).unwrap();
+    assert!(summary.stable(), "{summary:?}");
+    assert_eq!(summary.period, 0.05);
+    let order: usize = summary
+        .regulator
+        .iter()
+        .map(|pole| if pole.eigenvalue.im > 0.0 { 2 } else { 1 })
+        .sum();
+    assert_eq!(order, 5);
+}
+
+#[test]
+fn regulators_feed_back_estimated_velocities() {
+    let settings = LqrSettings::default();
+    let dt = 1.0 / 60.0;
+    let lqr = settings.solve(dt).unwrap();
+    let angles = [[0.0, PI - 0.01], [0.001, PI - 0.009]];
+    let run = |velocities: Option<[f32; 2]>| {
+        let mut controller = LqrController::new(Entity::PLACEHOLDER, "");
+        controller.update_with(&lqr, &settings, angles[0], velocities, dt);
+        controller.update_with(&lqr, &settings, angles[1], velocities, dt)
+    };
+    // The velocities differentiated from the angles, or given.
+    let differentiated = run(None).unwrap();
+    let given = run(Some([0.001 / dt, 0.001 / dt])).unwrap();
+    assert!(
+        (differentiated[0] - given[0]).abs() < 1e-3,
+        "{differentiated:?} {given:?}"
+    );
+    let still = run(Some([0.0, 0.0])).unwrap();
+    assert_ne!(still[0], differentiated[0]);
+}
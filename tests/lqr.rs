@@ -0,0 +1,235 @@
+//! Regulators solve the Riccati equation of discretized models, and balance the pendulum only
+//! within their capture angle.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    control::{self, Lqr},
+    lqr::{LqrController, LqrDesign, LqrSettings, Riccati, WeightedOutput},
+};
+use nalgebra::{DMatrix, DVector};
+
+fn scalar(value: f64) -> DMatrix<f64> {
+    DMatrix::from_element(1, 1, value)
+}
+
+/// Whether the state of the closed loop `x' = (A - B K) x` decays.
+fn stable(a: &DMatrix<f64>, b: &DMatrix<f64>, lqr: &Lqr) -> bool {
+    let closed = a - b * &lqr.gain;
+    let mut state = DVector::from_element(a.nrows(), 1.0);
+    for _ in 0..5000 {
+        state = &closed * state;
+    }
+    state.norm() < 1e-6
+}
+
+#[test]
+fn scalar_riccati_equations_are_solved() {
+    // P = 1 + P - P² / (1 + P) gives the golden ratio, and K = P / (1 + P).
+    let lqr = Lqr::new(&scalar(1.0), &scalar(1.0), &scalar(1.0), &scalar(1.0)).unwrap();
+    let ratio = (1.0 + 5f64.sqrt()) / 2.0;
+    assert!(
+        (lqr.gain[0] - ratio / (1.0 + ratio)).abs() < 1e-9,
+        "{}",
+        lqr.gain
+    );
+    assert_eq!(
+        lqr.command(&DVector::from_element(1, 2.0))[0],
+        -2.0 * lqr.gain[0]
+    );
+
+    // An unstable mode without any input can't be stabilized.
+    assert!(Lqr::new(&scalar(2.0), &scalar(0.0), &scalar(1.0), &scalar(1.0)).is_none());
+}
+
+#[test]
+fn continuous_riccati_equations_are_solved() {
+    // 2 P - P² + 1 = 0 gives P = K = 1 + √2.
+    let lqr = Lqr::continuous(&scalar(1.0), &scalar(1.0), &scalar(1.0), &scalar(1.0)).unwrap();
+    assert!(
+        (lqr.gain[0] - (1.0 + 2f64.sqrt())).abs() < 1e-9,
+        "{}",
+        lqr.gain
+    );
+
+    // The double integrator weighed by the identity has the gain `[1, √3]`.
+    let a = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    let b = DMatrix::from_column_slice(2, 1, &[0.0, 1.0]);
+    let lqr = Lqr::continuous(&a, &b, &DMatrix::identity(2, 2), &scalar(1.0)).unwrap();
+    assert!((lqr.gain[0] - 1.0).abs() < 1e-9, "{}", lqr.gain);
+    assert!((lqr.gain[1] - 3f64.sqrt()).abs() < 1e-9, "{}", lqr.gain);
+
+    assert!(Lqr::continuous(&scalar(2.0), &scalar(0.0), &scalar(1.0), &scalar(1.0)).is_none());
+}
+
+#[test]
+fn models_are_discretized_with_a_zero_order_hold() {
+    let a = DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+    let b = DMatrix::from_column_slice(2, 1, &[0.0, 1.0]);
+    let (ad, bd) = control::discretize(&a, &b, 0.1);
+    assert!((&ad - DMatrix::from_row_slice(2, 2, &[1.0, 0.1, 0.0, 1.0])).norm() < 1e-12);
+    assert!((&bd - DMatrix::from_column_slice(2, 1, &[0.005, 0.1])).norm() < 1e-12);
+    let lqr = Lqr::new(&ad, &bd, &DMatrix::identity(2, 2), &scalar(0.1)).unwrap();
+    assert!(stable(&ad, &bd, &lqr));
+
+    // A long step of a stiff mode is still accurate.
+    let (ad, _) = control::discretize(&scalar(-3.0), &scalar(1.0), 2.0);
+    assert!((ad[0] - (-6f64).exp()).abs() < 1e-12);
+}
+
+#[test]
+fn the_default_model_is_balanced() {
+    let settings = LqrSettings::default();
+    let dt = 1.0 / 60.0;
+    let lqr = settings.solve(dt).unwrap();
+    let a = DMatrix::from_fn(4, 4, |row, column| settings.a[row][column]);
+    let b = DMatrix::from_column_slice(4, 1, &settings.b);
+    let (a, b) = control::discretize(&a, &b, f64::from(dt));
+    assert!(stable(&a, &b, &lqr));
+
+    let invalid = LqrSettings {
+        r: 0.0,
+        ..Default::default()
+    };
+    assert!(invalid.solve(dt).is_err());
+}
+
+#[test]
+fn regulators_take_their_design_forms() {
+    let dt = 1.0 / 60.0;
+    let settings = LqrSettings::default();
+    // Weighing the outputs of the state one by one is weighing the state.
+    let outputs = LqrSettings {
+        design: LqrDesign {
+            outputs: settings
+                .q
+                .iter()
+                .enumerate()
+                .map(|(index, weight)| {
+                    let mut c = [0.0; 4];
+                    c[index] = 1.0;
+                    WeightedOutput { c, weight: *weight }
+                })
+                .collect(),
+            ..Default::default()
+        },
+        ..settings.clone()
+    };
+    let (plain, weighed) = (settings.solve(dt).unwrap(), outputs.solve(dt).unwrap());
+    assert!((&plain.gain - &weighed.gain).norm() < 1e-9);
+    let angles = LqrSettings {
+        design: LqrDesign {
+            outputs: WeightedOutput::angles(),
+            ..Default::default()
+        },
+        ..settings.clone()
+    };
+    assert_ne!(angles.solve(dt).unwrap(), plain);
+
+    // The servo form integrates the arm angle into a fifth state, and balances it too.
+    let servo = LqrSettings {
+        design: LqrDesign {
+            integral: Some(1.0),
+            ..Default::default()
+        },
+        ..settings.clone()
+    };
+    let lqr = servo.solve(dt).unwrap();
+    assert_eq!(lqr.gain.ncols(), 5);
+    let mut a = DMatrix::from_fn(4, 4, |row, column| settings.a[row][column])
+        .insert_row(4, 0.0)
+        .insert_column(4, 0.0);
+    a[(4, 0)] = 1.0;
+    let b = DMatrix::from_column_slice(4, 1, &settings.b).insert_row(4, 0.0);
+    let (ad, bd) = control::discretize(&a, &b, f64::from(dt));
+    assert!(stable(&ad, &bd, &lqr));
+
+    // Ignoring the sampling, the continuous gain is that of the fast discrete one.
+    let continuous = LqrSettings {
+        design: LqrDesign {
+            riccati: Riccati::Continuous,
+            ..Default::default()
+        },
+        ..settings.clone()
+    };
+    let fast = settings.solve(1e-3).unwrap();
+    let gain = continuous.solve(dt).unwrap().gain;
+    assert!(
+        (&gain - &fast.gain).norm() < 1e-2 * gain.norm(),
+        "{gain} {}",
+        fast.gain
+    );
+    assert_eq!(continuous.solve(0.1).unwrap().gain, gain);
+
+    let invalid = LqrSettings {
+        design: LqrDesign {
+            integral: Some(-1.0),
+            ..Default::default()
+        },
+        ..settings
+    };
+    assert!(invalid.solve(dt).is_err());
+}
+
+#[test]
+fn regulators_hold_their_acceleration_between_samples() {
+    let dt = 1.0 / 60.0;
+    let settings = LqrSettings {
+        sample_rate: Some(20.0),
+        ..Default::default()
+    };
+    assert_eq!(settings.sample_period(dt), 0.05);
+    let lqr = settings.solve(settings.sample_period(dt)).unwrap();
+    let mut controller = LqrController::new(Entity::PLACEHOLDER, "");
+    assert_eq!(
+        controller.update(&lqr, &settings, [0.0, PI - 0.1], dt),
+        None
+    );
+    let [sampled, velocity] = controller
+        .update(&lqr, &settings, [0.0, PI - 0.1], dt)
+        .unwrap();
+    // The two steps in between hold the acceleration, whatever the angles.
+    for step in 1..=2 {
+        let [held, integrated] = controller
+            .update(&lqr, &settings, [0.0, PI - 0.2], dt)
+            .unwrap();
+        assert_eq!(held, sampled);
+        assert!((integrated - (velocity + sampled * dt * step as f32)).abs() < 1e-6);
+    }
+    let [resampled, _] = controller
+        .update(&lqr, &settings, [0.0, PI - 0.2], dt)
+        .unwrap();
+    assert_ne!(resampled, sampled);
+}
+
+#[test]
+fn pendulums_are_caught_within_the_capture_angle() {
+    let settings = LqrSettings::default();
+    let dt = 1.0 / 60.0;
+    let lqr = settings.solve(dt).unwrap();
+    let mut controller = LqrController::new(Entity::PLACEHOLDER, "feeder");
+    assert_eq!(controller.angle, "feeder/lqr/angle");
+
+    // The velocities need a first measurement.
+    assert_eq!(
+        controller.update(&lqr, &settings, [0.0, PI - 0.1], dt),
+        None
+    );
+    let [acceleration, velocity] = controller
+        .update(&lqr, &settings, [0.0, PI - 0.1], dt)
+        .unwrap();
+    assert!(controller.engaged());
+    // Leaning back, towards the negative angles, is corrected by accelerating the arm back too.
+    assert!(
+        acceleration < 0.0 && acceleration >= -settings.limit,
+        "{acceleration}"
+    );
+    assert_eq!(velocity, acceleration * dt);
+
+    // The angle wraps around upright.
+    assert!(controller
+        .update(&lqr, &settings, [0.0, -PI + 0.1], dt)
+        .is_some());
+    assert_eq!(controller.update(&lqr, &settings, [0.0, 1.0], dt), None);
+    assert!(!controller.engaged());
+}
@@ -0,0 +1,65 @@
+//! Overrides replace the mass properties of the links, and restore them when removed.
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    mass_overrides::{MassOverride, MassOverrides, MassOverridesPlugin, OriginalMass},
+    plants::Link,
+};
+
+#[test]
+fn links_take_the_overridden_mass_and_get_it_back() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(MassOverridesPlugin);
+    let arm = app
+        .world_mut()
+        .spawn((
+            RigidBody::Dynamic,
+            Collider::cuboid(0.5, 0.5, 0.5),
+            ColliderMassProperties::Mass(1.0),
+            Link {
+                plant: "feeder".to_string(),
+                name: "arm".to_string(),
+            },
+            Transform::default(),
+        ))
+        .id();
+    app.update();
+    let mass = |app: &App| app.world().get::<ReadMassProperties>(arm).unwrap().mass;
+    assert!((mass(&app) - 1.0).abs() < 1e-5);
+
+    let settings = MassOverrides {
+        enabled: true,
+        overrides: vec![MassOverride {
+            link: "arm".to_string(),
+            mass: 3.0,
+            center_of_mass: Vec3::new(0.0, -0.25, 0.0),
+            inertia: Vec3::splat(0.5),
+        }],
+    };
+    *app.world_mut()
+        .resource_mut::<Persistent<MassOverrides>>()
+        .get_mut() = settings;
+    for _ in 0..3 {
+        app.update();
+    }
+    assert!((mass(&app) - 3.0).abs() < 1e-5);
+    let center = app
+        .world()
+        .get::<ReadMassProperties>(arm)
+        .unwrap()
+        .local_center_of_mass;
+    assert!((center.y + 0.25).abs() < 1e-5, "{center}");
+    assert!(app.world().get::<OriginalMass>(arm).is_some());
+
+    app.world_mut()
+        .resource_mut::<Persistent<MassOverrides>>()
+        .get_mut()
+        .enabled = false;
+    for _ in 0..3 {
+        app.update();
+    }
+    assert!((mass(&app) - 1.0).abs() < 1e-5);
+    assert!(app.world().get::<OriginalMass>(arm).is_none());
+}
@@ -0,0 +1,117 @@
+//! Files written by older releases are migrated to the current formats.
+use std::{fs, time::Duration};
+
+use digital_twin_playground::{
+    autosave::SessionSnapshot,
+    error::Error,
+    migration::{self, Format, Migration, VERSIONS_FILE},
+};
+use serde_json::{json, Value};
+
+fn rename_gain(value: &mut Value) -> Result<(), String> {
+    let settings = value.as_object_mut().ok_or("not an object")?;
+    if let Some(gain) = settings.remove("gain") {
+        settings.insert("kp".to_string(), gain);
+    }
+    Ok(())
+}
+
+fn scale_limit(value: &mut Value) -> Result<(), String> {
+    let limit = value["limit"].as_f64().ok_or("no limit")?;
+    value["limit"] = json!(limit * 2.0);
+    Ok(())
+}
+
+const LOOP: Format = Format {
+    name: "loop",
+    migrations: &[
+        Migration {
+            description: "the gain is the proportional gain",
+            apply: rename_gain,
+        },
+        Migration {
+            description: "the limit is in rad/s",
+            apply: scale_limit,
+        },
+    ],
+};
+
+#[test]
+fn documents_are_migrated_from_their_version() {
+    let mut document = json!({ "gain": 2.0, "limit": 1.5 });
+    let migrated = LOOP.migrate_document(&mut document).unwrap();
+    assert_eq!(migrated.len(), 2);
+    assert_eq!(document, json!({ "kp": 2.0, "limit": 3.0, "version": 2 }));
+
+    // Only the later migrations apply to later versions.
+    let mut document = json!({ "kp": 2.0, "limit": 1.5, "version": 1 });
+    assert_eq!(
+        LOOP.migrate_document(&mut document).unwrap(),
+        ["the limit is in rad/s"]
+    );
+    assert_eq!(document["limit"], 3.0);
+
+    let mut newer = json!({ "kp": 2.0, "version": 3 });
+    assert!(matches!(
+        LOOP.migrate_document(&mut newer),
+        Err(Error::Config { .. })
+    ));
+    let mut broken = json!({ "gain": 2.0 });
+    assert!(matches!(
+        LOOP.migrate_document(&mut broken),
+        Err(Error::Config { .. })
+    ));
+}
+
+#[test]
+fn sessions_from_before_the_clock_keep_loading() {
+    let mut session = json!({ "time": 2.0, "bodies": [] });
+    migration::SESSION.migrate_document(&mut session).unwrap();
+    let snapshot: SessionSnapshot = serde_json::from_value(session).unwrap();
+    assert_eq!(snapshot.tick, 120);
+    assert_eq!(snapshot.elapsed, Duration::from_secs(2));
+
+    // Sessions written since are left as they are.
+    let current = SessionSnapshot {
+        tick: 7,
+        ..SessionSnapshot::default()
+    };
+    let mut session = migration::SESSION.to_value(&current).unwrap();
+    assert_eq!(session["version"], 1);
+    session.as_object_mut().unwrap().remove("version");
+    migration::SESSION.migrate_document(&mut session).unwrap();
+    assert_eq!(
+        serde_json::from_value::<SessionSnapshot>(session).unwrap(),
+        current
+    );
+}
+
+#[test]
+fn configuration_files_are_migrated_once() {
+    let directory = std::env::temp_dir().join("migration_configs");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("loop.json");
+    fs::write(&path, r#"{ "gain": 2.0, "limit": 1.5 }"#).unwrap();
+
+    let (migrated, errors) = migration::migrate_configs(&directory, &[LOOP]);
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(migrated.len(), 1);
+    assert_eq!((migrated[0].from, migrated[0].to), (0, 2));
+    let config: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(config, json!({ "kp": 2.0, "limit": 3.0 }));
+    let original: Value =
+        serde_json::from_slice(&fs::read(&migrated[0].original).unwrap()).unwrap();
+    assert_eq!(original, json!({ "gain": 2.0, "limit": 1.5 }));
+    let versions: Value =
+        serde_json::from_slice(&fs::read(directory.join(VERSIONS_FILE)).unwrap()).unwrap();
+    assert_eq!(versions, json!({ "loop": 2 }));
+
+    // The versions are recorded, so the next start leaves the file alone.
+    let (migrated, errors) = migration::migrate_configs(&directory, &[LOOP]);
+    assert!(migrated.is_empty() && errors.is_empty());
+    assert_eq!(
+        serde_json::from_slice::<Value>(&fs::read(&path).unwrap()).unwrap(),
+        config
+    );
+}
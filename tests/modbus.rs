@@ -0,0 +1,114 @@
+//! A Modbus TCP client reads and writes the registers of a headless simulator.
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    modbus::{exception, process, ModbusMap, ModbusPlugin, ModbusServer, Register, RegisterFormat},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+
+/// Sends a request and updates the application until the response comes.
+fn transact(app: &mut App, stream: &mut TcpStream, transaction: u16, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = transaction.to_be_bytes().to_vec();
+    frame.extend([0, 0]);
+    frame.extend((pdu.len() as u16 + 1).to_be_bytes());
+    frame.push(1);
+    frame.extend(pdu);
+    stream.write_all(&frame).unwrap();
+
+    let mut header = [0; 7];
+    for _ in 0..1_000 {
+        app.update();
+        if stream.read_exact(&mut header).is_ok() {
+            assert_eq!(header[0..2], transaction.to_be_bytes());
+            let length = u16::from_be_bytes([header[4], header[5]]);
+            let mut response = vec![0; usize::from(length) - 1];
+            stream.read_exact(&mut response).unwrap();
+            return response;
+        }
+    }
+    panic!("the server doesn't answer");
+}
+
+#[test]
+fn client_writes_and_reads_the_velocity() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(ModbusPlugin {
+        bind: "127.0.0.1:0".parse().unwrap(),
+        map: ModbusMap::default(),
+    });
+    app.finish();
+    app.cleanup();
+    let address = app.world().resource::<ModbusServer>().address;
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+
+    // Write single register 0: 1500 mrad/s.
+    let request = [0x06, 0, 0, 0x05, 0xDC];
+    assert_eq!(transact(&mut app, &mut stream, 1, &request), request);
+    assert_eq!(
+        app.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+        Some(1.5)
+    );
+    // Read holding register 0.
+    assert_eq!(
+        transact(&mut app, &mut stream, 2, &[0x03, 0, 0, 0, 1]),
+        [0x03, 2, 0x05, 0xDC]
+    );
+}
+
+#[test]
+fn registers_follow_the_map() {
+    let map = ModbusMap {
+        holding_registers: BTreeMap::from([(
+            10,
+            Register {
+                signal: "gain".to_string(),
+                format: RegisterFormat::Float32,
+                scale: 1.0,
+            },
+        )]),
+        input_registers: ModbusMap::default().input_registers,
+    };
+    let mut setpoints = Setpoints::default();
+    let mut telemetry = Telemetry::default();
+    telemetry.record("pendulum/angle", 0.0, -0.25);
+
+    // Write multiple registers 10-11: 2.5 as a float.
+    let request = [0x10, 0, 10, 0, 2, 4, 0x40, 0x20, 0, 0];
+    assert_eq!(
+        process(&map, &request, &mut setpoints, Some(&telemetry)),
+        request[..5]
+    );
+    assert_eq!(setpoints.get("gain"), Some(2.5));
+
+    // Reading half of the float is refused, as is writing an unmapped register.
+    assert_eq!(
+        process(&map, &[0x03, 0, 11, 0, 1], &mut setpoints, None),
+        [0x83, exception::ILLEGAL_DATA_ADDRESS]
+    );
+    assert_eq!(
+        process(&map, &[0x06, 0, 0, 0, 1], &mut setpoints, None),
+        [0x86, exception::ILLEGAL_DATA_ADDRESS]
+    );
+    assert_eq!(
+        process(&map, &[0x05, 0, 0, 0xFF, 0], &mut setpoints, None),
+        [0x85, exception::ILLEGAL_FUNCTION]
+    );
+
+    // Input register 0: -250 mrad.
+    assert_eq!(
+        process(&map, &[0x04, 0, 0, 0, 1], &mut setpoints, Some(&telemetry)),
+        [0x04, 2, 0xFF, 0x06]
+    );
+}
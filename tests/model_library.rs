@@ -0,0 +1,57 @@
+//! The built-in plants spawn their links, and the library switches between them at runtime.
+use bevy::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    model_library::{ModelLibrary, ModelLibraryPlugin},
+    plants::{self, Link},
+};
+
+fn link_names(app: &mut App) -> Vec<String> {
+    let world = app.world_mut();
+    let mut names: Vec<String> = world
+        .query::<&Link>()
+        .iter(world)
+        .map(|link| link.name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn builtin_plants_spawn_their_links() {
+    for plant in plants::builtin() {
+        let mut app = headless_app(DEFAULT_TIME_STEP);
+        (plant.add)(&mut app);
+        app.update();
+        assert!(!link_names(&mut app).is_empty(), "{}", plant.name);
+    }
+}
+
+#[test]
+fn plants_are_switched_at_runtime() {
+    if !plants::builtin()
+        .iter()
+        .any(|plant| plant.name == "cart_pole")
+    {
+        return;
+    }
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(ModelLibraryPlugin {
+        plant: Some("rotary_pendulum".to_string()),
+    });
+    app.update();
+    assert!(link_names(&mut app).contains(&"pendulum".to_string()));
+
+    app.world_mut().resource_mut::<ModelLibrary>().requested = Some("cart_pole".to_string());
+    app.update();
+    assert_eq!(link_names(&mut app), ["cart", "pole"]);
+    assert_eq!(
+        app.world().resource::<ModelLibrary>().current.as_deref(),
+        Some("cart_pole")
+    );
+    // The switched plant steps.
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(link_names(&mut app), ["cart", "pole"]);
+}
@@ -0,0 +1,117 @@
+//! Requirements in temporal logic parse, and their violations are timestamped.
+use std::f32::consts::PI;
+
+use digital_twin_playground::{
+    monitors::{Comparison, Condition, Monitors, Requirement, Temporal, Violation},
+    telemetry::{Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+};
+
+#[test]
+fn requirements_parse() {
+    assert_eq!(
+        Requirement::parse("always |pendulum/angle| < 5° after 3 s"),
+        Ok(Requirement {
+            temporal: Temporal::Always,
+            condition: Condition::Compare {
+                channel: PENDULUM_ANGLE.to_string(),
+                absolute: true,
+                comparison: Comparison::Less,
+                value: 5.0 * PI / 180.0,
+            },
+            from: 3.0,
+            until: f32::INFINITY,
+        })
+    );
+    let requirement =
+        Requirement::parse("never motor/torque>=20 or motor/torque <= -20 for more than 500ms")
+            .unwrap();
+    assert_eq!(requirement.temporal, Temporal::Never { tolerance: 0.5 });
+    assert!(matches!(requirement.condition, Condition::Or(..)));
+    let requirement =
+        Requirement::parse("eventually motor/angle > 1.5 within 2 s between 1 s and 4 s").unwrap();
+    assert_eq!(requirement.temporal, Temporal::Eventually { within: 2.0 });
+    assert_eq!((requirement.from, requirement.until), (1.0, 4.0));
+
+    assert!(Requirement::parse("sometimes motor/angle > 1").is_err());
+    assert!(Requirement::parse("always |motor/angle > 1").is_err());
+    assert!(Requirement::parse("eventually motor/angle > 1").is_err());
+    assert!(Requirement::parse("always motor/angle > 1 now").is_err());
+}
+
+/// Monitors `requirement` on `channel` taking `values` at steps of 0.1 s.
+fn violations(requirement: &str, channel: &str, values: &[f32]) -> Vec<Violation> {
+    let (mut monitors, errors) = Monitors::new(&[requirement.to_string()]);
+    assert!(errors.is_empty(), "{errors:?}");
+    let mut telemetry = Telemetry::default();
+    for (step, value) in values.iter().enumerate() {
+        let time = step as f32 / 10.0;
+        telemetry.record(channel, time, *value);
+        monitors.update(time, &telemetry);
+    }
+    monitors.violations
+}
+
+fn violation(requirement: &str, start: f32, end: Option<f32>) -> Violation {
+    Violation {
+        requirement: requirement.to_string(),
+        start,
+        end,
+    }
+}
+
+#[test]
+fn violations_are_timestamped() {
+    let always = "always |pendulum/angle| < 1 after 0.2 s";
+    assert_eq!(
+        violations(
+            always,
+            PENDULUM_ANGLE,
+            &[5.0, 0.0, 0.0, -2.0, -2.0, 0.5, 3.0]
+        ),
+        [
+            violation(always, 0.3, Some(0.5)),
+            violation(always, 0.6, None)
+        ]
+    );
+
+    let never = "never |motor/torque| >= 10 for more than 0.15 s";
+    assert_eq!(
+        violations(
+            never,
+            MOTOR_TORQUE,
+            &[10.0, 10.0, 0.0, 10.0, -10.0, 10.0, 0.0]
+        ),
+        [violation(never, 0.5, Some(0.6))]
+    );
+
+    let eventually = "eventually motor/torque > 1 within 0.25 s";
+    assert_eq!(
+        violations(eventually, MOTOR_TORQUE, &[0.0, 0.0, 0.0, 0.0, 2.0, 0.0]),
+        [violation(eventually, 0.3, None)]
+    );
+    assert!(violations(eventually, MOTOR_TORQUE, &[0.0, 2.0, 0.0, 0.0]).is_empty());
+}
+
+#[test]
+fn invalid_requirements_are_reported() {
+    let (monitors, errors) = Monitors::new(&[
+        "always motor/torque < 1".to_string(),
+        "always motor/torque".to_string(),
+    ]);
+    assert_eq!(monitors.monitors.len(), 1);
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn rewinding_forgets_later_violations() {
+    let requirement = "always motor/torque < 1";
+    let (mut monitors, _) = Monitors::new(&[requirement.to_string()]);
+    let mut telemetry = Telemetry::default();
+    telemetry.record(MOTOR_TORQUE, 1.0, 2.0);
+    monitors.update(1.0, &telemetry);
+    telemetry.record(MOTOR_TORQUE, 2.0, 3.0);
+    monitors.update(2.0, &telemetry);
+    assert_eq!(monitors.between(1.5, 3.0).len(), 1);
+    monitors.update(0.5, &Telemetry::default());
+    assert!(monitors.violations.is_empty());
+}
@@ -0,0 +1,267 @@
+//! Model predictive controllers match the regulator without active bounds, and plan within
+//! them otherwise, whatever their solver. The nonlinear one swings the pendulum up.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    control::{self, InteriorPoint, Lqr, Mpc, Nmpc, ProjectedGradient, QpStatus},
+    mpc::{MpcController, MpcSettings, MpcSolver, MpcVariant},
+};
+use nalgebra::{DMatrix, DVector, Vector4};
+
+/// The default model of the pendulum, discretized at `dt`, and its weights.
+fn pendulum(dt: f32) -> [DMatrix<f64>; 4] {
+    let settings = MpcSettings::default();
+    let a = DMatrix::from_fn(4, 4, |row, column| settings.a[row][column]);
+    let b = DMatrix::from_column_slice(4, 1, &settings.b);
+    let (a, b) = control::discretize(&a, &b, f64::from(dt));
+    let q = DMatrix::from_diagonal(&DVector::from_column_slice(&settings.q));
+    [a, b, q, DMatrix::from_element(1, 1, settings.r)]
+}
+
+#[test]
+fn unbounded_plans_start_with_the_regulator_command() {
+    let [a, b, q, r] = pendulum(1.0 / 60.0);
+    let lqr = Lqr::new(&a, &b, &q, &r).unwrap();
+    let mut mpc = Mpc::new(&a, &b, &q, &r, 20, &[-1e6], &[1e6]).unwrap();
+    let state = DVector::from_column_slice(&[0.1, 0.0, 0.05, -0.1]);
+    let command = mpc.command(&state)[0];
+    let expected = lqr.command(&state)[0];
+    assert!(
+        (command - expected).abs() < 1e-6 * expected.abs(),
+        "{command} {expected}"
+    );
+    assert_eq!(mpc.plan().shape(), (20, 1));
+    assert!(mpc.iterations() < 500);
+
+    assert!(Mpc::new(&a, &b, &q, &r, 0, &[-1.0], &[1.0]).is_none());
+    assert!(Mpc::new(&a, &b, &q, &r, 20, &[1.0], &[-1.0]).is_none());
+}
+
+#[test]
+fn bounded_plans_balance_the_pendulum_within_the_limit() {
+    let [a, b, q, r] = pendulum(1.0 / 60.0);
+    let mut mpc = Mpc::new(&a, &b, &q, &r, 20, &[-1.0], &[1.0]).unwrap();
+    let mut state = DVector::from_column_slice(&[0.0, 0.0, 0.25, 0.0]);
+    let mut saturated = false;
+    for _ in 0..900 {
+        let command = mpc.command(&state);
+        assert!(command[0].abs() <= 1.0 + 1e-12, "{command}");
+        assert!(mpc.plan().iter().all(|input| input.abs() <= 1.0 + 1e-12));
+        saturated |= command[0].abs() > 1.0 - 1e-9;
+        state = &a * state + &b * command;
+    }
+    assert!(saturated);
+    assert!(state.norm() < 1e-2, "{state}");
+
+    let invalid = MpcSettings {
+        horizon: 0,
+        ..Default::default()
+    };
+    assert!(invalid.solve(1.0 / 60.0).is_err());
+}
+
+#[test]
+fn pendulums_are_caught_within_the_capture_angle() {
+    let settings = MpcSettings::default();
+    let dt = 1.0 / 60.0;
+    let mpc = settings.solve(dt).unwrap();
+    let mut controller = MpcController::new(Entity::PLACEHOLDER, "feeder");
+    assert_eq!(controller.angle, "feeder/mpc/angle");
+
+    assert_eq!(
+        controller.update(&mpc, &settings, [0.0, PI - 0.1], dt),
+        None
+    );
+    let [acceleration, velocity] = controller
+        .update(&mpc, &settings, [0.0, PI - 0.1], dt)
+        .unwrap();
+    assert!(controller.engaged());
+    assert!(
+        acceleration < 0.0 && acceleration >= -settings.limit,
+        "{acceleration}"
+    );
+    assert_eq!(velocity, acceleration * dt);
+    assert_eq!(controller.plan().len(), settings.horizon);
+
+    assert_eq!(controller.update(&mpc, &settings, [0.0, 1.0], dt), None);
+    assert!(!controller.engaged());
+}
+
+#[test]
+fn interior_points_plan_like_the_projected_gradient() {
+    let [a, b, q, r] = pendulum(1.0 / 60.0);
+    let lqr = Lqr::new(&a, &b, &q, &r).unwrap();
+    let state = DVector::from_column_slice(&[0.1, 0.0, 0.05, -0.1]);
+    let mut unbounded = Mpc::new(&a, &b, &q, &r, 20, &[-1e6], &[1e6])
+        .unwrap()
+        .with_solver(Box::new(InteriorPoint::default()))
+        .unwrap();
+    let command = unbounded.command(&state)[0];
+    let expected = lqr.command(&state)[0];
+    assert!(
+        (command - expected).abs() < 1e-6 * expected.abs(),
+        "{command} {expected}"
+    );
+    assert_eq!(unbounded.solver(), "interior point");
+
+    let mut gradient = Mpc::new(&a, &b, &q, &r, 20, &[-1.0], &[1.0]).unwrap();
+    let mut interior = gradient
+        .clone()
+        .with_solver(Box::new(InteriorPoint::default()))
+        .unwrap();
+    let state = DVector::from_column_slice(&[0.0, 0.0, 0.25, 0.0]);
+    let (expected, command) = (gradient.command(&state), interior.command(&state));
+    assert!(
+        (expected[0] - command[0]).abs() < 1e-6,
+        "{expected} {command}"
+    );
+    assert!((gradient.plan() - interior.plan()).amax() < 1e-4);
+    assert_eq!(interior.status(), QpStatus::Solved);
+}
+
+#[test]
+fn arm_limits_keep_the_arm_near_where_the_pendulum_was_caught() {
+    let [a, b, q, r] = pendulum(1.0 / 60.0);
+    let free = f64::INFINITY;
+    let bounds = |limit: f64| ([-limit, -free, -free, -free], [limit, free, free, free]);
+    let (lower, upper) = bounds(0.3);
+    let mut mpc = Mpc::new(&a, &b, &q, &r, 20, &[-20.0], &[20.0])
+        .unwrap()
+        .with_solver(Box::new(InteriorPoint::default()))
+        .unwrap()
+        .with_state_bounds(&lower, &upper)
+        .unwrap();
+    let mut unlimited = Mpc::new(&a, &b, &q, &r, 20, &[-20.0], &[20.0]).unwrap();
+    let mut state = DVector::from_column_slice(&[0.0, 0.0, 0.2, 0.0]);
+    let mut free_state = state.clone();
+    let (mut widest, mut free_widest) = (0.0_f64, 0.0_f64);
+    for _ in 0..120 {
+        let command = mpc.command(&state);
+        assert_eq!(mpc.status(), QpStatus::Solved);
+        assert!(command[0].abs() <= 20.0 + 1e-9, "{command}");
+        state = &a * state + &b * command;
+        widest = widest.max(state[0].abs());
+        free_state = &a * &free_state + &b * unlimited.command(&free_state);
+        free_widest = free_widest.max(free_state[0].abs());
+    }
+    assert!(widest <= 0.3 + 1e-6, "{widest}");
+    assert!(free_widest > 0.35, "{free_widest}");
+
+    // The arm can't stop within a milliradian at that speed: the limit is dropped.
+    let (lower, upper) = bounds(1e-3);
+    let mut infeasible = Mpc::new(&a, &b, &q, &r, 20, &[-1.0], &[1.0])
+        .unwrap()
+        .with_solver(Box::new(InteriorPoint::default()))
+        .unwrap()
+        .with_state_bounds(&lower, &upper)
+        .unwrap();
+    let command = infeasible.command(&DVector::from_column_slice(&[0.0, 1.0, 0.0, 0.0]));
+    assert_eq!(infeasible.status(), QpStatus::Infeasible);
+    assert!(command[0].abs() <= 1.0 + 1e-9, "{command}");
+
+    let gradient = Mpc::new(&a, &b, &q, &r, 20, &[-1.0], &[1.0])
+        .unwrap()
+        .with_solver(Box::new(ProjectedGradient::default()));
+    assert!(gradient.unwrap().with_state_bounds(&lower, &upper).is_err());
+    let settings = MpcSettings {
+        arm_limit: Some(0.5),
+        ..Default::default()
+    };
+    assert!(settings.solve(1.0 / 60.0).is_err());
+    let settings = MpcSettings {
+        solver: MpcSolver::InteriorPoint,
+        ..settings
+    };
+    assert_eq!(
+        settings.solve(1.0 / 60.0).unwrap().solver(),
+        "interior point"
+    );
+    if cfg!(not(feature = "osqp")) {
+        let settings = MpcSettings {
+            solver: MpcSolver::Osqp,
+            ..settings
+        };
+        assert!(settings.solve(1.0 / 60.0).is_err());
+    }
+}
+
+/// Runs the nonlinear controller of `settings` on its own model for `duration` seconds from the
+/// pendulum hanging, returning the final state and the widest angle of the arm.
+fn swing_up(settings: &MpcSettings, duration: f64) -> (Vector4<f64>, f64) {
+    let mut nmpc = settings.solve_nonlinear(1.0 / 60.0).unwrap();
+    let limit = f64::from(settings.limit);
+    let mut state = Vector4::new(0.0, 0.0, std::f64::consts::PI - 0.01, 0.0);
+    let mut widest = 0.0_f64;
+    for _ in 0..(duration / nmpc.interval).round() as usize {
+        let mut measured = state;
+        measured[2] = measured[2].sin().atan2(measured[2].cos());
+        let acceleration = nmpc.command(&measured);
+        assert!(acceleration.abs() <= limit + 1e-9, "{acceleration}");
+        assert!(nmpc.iterations() <= settings.nonlinear.iterations);
+        state = nmpc.model.step(&state, acceleration, nmpc.interval);
+        widest = widest.max(state[0].abs());
+    }
+    state[2] = state[2].sin().atan2(state[2].cos());
+    (state, widest)
+}
+
+#[test]
+fn nonlinear_plans_swing_the_pendulum_up_and_balance_it() {
+    let settings = MpcSettings {
+        variant: MpcVariant::Nonlinear,
+        ..Default::default()
+    };
+    let (state, widest) = swing_up(&settings, 8.0);
+    assert!(state[2].abs() < 0.05 && state[3].abs() < 0.1, "{state}");
+    assert!(widest > 0.6, "{widest}");
+
+    let limited = MpcSettings {
+        solver: MpcSolver::InteriorPoint,
+        arm_limit: Some(0.6),
+        ..settings.clone()
+    };
+    let (state, widest) = swing_up(&limited, 8.0);
+    assert!(state[2].abs() < 0.05 && state[3].abs() < 0.1, "{state}");
+    assert!(widest <= 0.6 + 1e-2, "{widest}");
+
+    let nmpc = settings.solve_nonlinear(1.0 / 60.0).unwrap();
+    assert_eq!(nmpc.horizon, settings.nonlinear.horizon);
+    assert_eq!(nmpc.plan().len(), nmpc.horizon);
+    assert_eq!(nmpc.trajectory().len(), nmpc.horizon + 1);
+    assert!(Nmpc::new(nmpc.model, 0, 0.05, [[1.0; 4]; 2], 0.1, 10.0).is_none());
+    assert!(Nmpc::new(nmpc.model, 40, 0.05, [[1.0; 4]; 2], 0.0, 10.0).is_none());
+    assert!(nmpc.with_arm_limit(0.5).is_err());
+}
+
+#[test]
+fn nonlinear_controllers_hold_their_command_over_an_interval() {
+    let settings = MpcSettings {
+        variant: MpcVariant::Nonlinear,
+        ..Default::default()
+    };
+    let dt = 1.0 / 60.0;
+    let nmpc = settings.solve_nonlinear(dt).unwrap();
+    let mut controller = MpcController::new(Entity::PLACEHOLDER, "feeder");
+    assert_eq!(controller.solve_time, "feeder/mpc/solve_time");
+
+    assert_eq!(
+        controller.update_nonlinear(&nmpc, &settings, [0.0, 0.05], dt),
+        None
+    );
+    let mut commands = Vec::new();
+    for _ in 0..settings.nonlinear.interval {
+        let command = controller.update_nonlinear(&nmpc, &settings, [0.0, 0.05], dt);
+        commands.push(command.unwrap()[0]);
+        if commands.len() == 1 {
+            assert!(controller.solve_time().is_some());
+            assert!(controller.iterations() >= 1);
+        } else {
+            assert!(controller.solve_time().is_none());
+        }
+    }
+    // Hanging still, next to straight down, the pendulum is pumped.
+    assert!(commands[0] != 0.0 && commands.iter().all(|command| *command == commands[0]));
+    assert!(controller.engaged());
+    assert_eq!(controller.plan().len(), settings.nonlinear.horizon);
+}
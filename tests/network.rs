@@ -0,0 +1,75 @@
+//! Impaired links lose, delay and reorder datagrams as configured.
+use std::time::{Duration, Instant};
+
+use digital_twin_playground::network::{Link, LinkSettings};
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn perfect_link_delivers_immediately() {
+    let now = Instant::now();
+    let mut link = Link::new(LinkSettings::default(), 1);
+    for datagram in 0..10 {
+        link.send(datagram, 8, now);
+    }
+    assert_eq!(link.receive(now), (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn delay_holds_datagrams() {
+    let start = Instant::now();
+    let settings = LinkSettings {
+        delay: 0.05,
+        ..LinkSettings::default()
+    };
+    let mut link = Link::new(settings, 1);
+    link.send("a", 8, start);
+    link.send("b", 8, start + ms(10));
+    assert!(link.receive(start + ms(40)).is_empty());
+    assert_eq!(link.receive(start + ms(55)), ["a"]);
+    assert_eq!(link.receive(start + ms(60)), ["b"]);
+    assert_eq!(link.in_flight(), 0);
+}
+
+#[test]
+fn bandwidth_queues_datagrams() {
+    let start = Instant::now();
+    // 1000 bytes/s: a datagram of 10 bytes takes 10 ms.
+    let settings = LinkSettings {
+        bandwidth: 1000.0,
+        ..LinkSettings::default()
+    };
+    let mut link = Link::new(settings, 1);
+    for datagram in 0..5 {
+        link.send(datagram, 10, start);
+    }
+    assert_eq!(link.receive(start + ms(25)), [0, 1]);
+    assert_eq!(link.receive(start + ms(51)), [2, 3, 4]);
+}
+
+#[test]
+fn loss_and_jitter_are_reproducible() {
+    let start = Instant::now();
+    let settings = LinkSettings {
+        loss: 0.3,
+        jitter: 0.02,
+        ..LinkSettings::default()
+    };
+    let run = |seed| {
+        let mut link = Link::new(settings.clone(), seed);
+        for datagram in 0..1000 {
+            link.send(datagram, 8, start + ms(datagram));
+        }
+        link.receive(start + ms(2000))
+    };
+    let delivered = run(7);
+    assert_eq!(delivered, run(7));
+    let lost = 1000 - delivered.len();
+    assert!((250..350).contains(&lost), "{lost} datagrams lost");
+    assert!(
+        delivered.windows(2).any(|pair| pair[0] > pair[1]),
+        "the jitter reorders datagrams"
+    );
+}
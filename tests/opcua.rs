@@ -0,0 +1,65 @@
+//! An OPC UA client opens a connection to a headless simulator.
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    opcua::{OpcUaPlugin, OpcUaSettings, OpcUaUser},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+/// The `Hello` message of the OPC UA connection protocol.
+fn hello(endpoint: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    // Protocol version, buffer sizes, maximum message size and chunk count.
+    for value in [0u32, 65_535, 65_535, 0, 0] {
+        body.extend(value.to_le_bytes());
+    }
+    body.extend((endpoint.len() as i32).to_le_bytes());
+    body.extend(endpoint.as_bytes());
+    let mut message = b"HELF".to_vec();
+    message.extend((body.len() as u32 + 8).to_le_bytes());
+    message.extend(body);
+    message
+}
+
+#[test]
+fn server_acknowledges_connections() {
+    let address: SocketAddr = "127.0.0.1:47051".parse().unwrap();
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(OpcUaPlugin {
+        bind: address,
+        settings: OpcUaSettings {
+            users: vec![OpcUaUser {
+                name: "operator".to_string(),
+                password: "secret".to_string(),
+                write: true,
+            }],
+        },
+    });
+    app.finish();
+    app.cleanup();
+    app.world_mut()
+        .resource_mut::<Setpoints>()
+        .set(MOTOR_VELOCITY, 1.5);
+    // Let the variables of the signals be created.
+    for _ in 0..20 {
+        app.update();
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(&hello(&format!("opc.tcp://{address}/")))
+        .unwrap();
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(&header[0..4], b"ACKF");
+}
@@ -0,0 +1,27 @@
+//! Arcs of the overlays sweep about their axis from their start, in the sense of their angle.
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::prelude::*;
+use digital_twin_playground::overlays::arc;
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(actual.distance(expected) < 1e-5, "{actual} vs {expected}");
+}
+
+#[test]
+fn arcs_sweep_about_their_axis() {
+    let center = Vec3::new(1.0, 2.0, 3.0);
+    let points = arc(center, Vec3::X, Vec3::Y * 5.0, FRAC_PI_2, 0.5);
+    assert_near(points[0], center + Vec3::Y * 0.5);
+    // A quarter turn about X brings Y onto Z, by the right-hand rule.
+    assert_near(*points.last().unwrap(), center + Vec3::Z * 0.5);
+    assert!(points
+        .iter()
+        .all(|point| (point.distance(center) - 0.5).abs() < 1e-5));
+
+    let backwards = arc(center, Vec3::X, Vec3::Y, -FRAC_PI_2, 0.5);
+    assert_near(*backwards.last().unwrap(), center - Vec3::Z * 0.5);
+    // Longer arcs are drawn with more segments, and empty ones still have their ends.
+    assert!(arc(center, Vec3::X, Vec3::Y, PI, 0.5).len() > points.len());
+    assert_eq!(arc(center, Vec3::X, Vec3::Y, 0.0, 0.5).len(), 2);
+}
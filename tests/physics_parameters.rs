@@ -0,0 +1,125 @@
+//! Physical parameters set the gravity, the solver and the links, and restore what the model had
+//! once unset.
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    physics_parameters::{
+        BodyParameters, OriginalBody, OriginalContact, PhysicsParameters, PhysicsParametersPlugin,
+    },
+    plants::Link,
+};
+
+#[test]
+fn unset_parameters_keep_the_damping_of_the_model() {
+    let parameters = BodyParameters {
+        link: "arm".to_string(),
+        angular_damping: Some(0.5),
+        ..Default::default()
+    };
+    let original = Damping {
+        linear_damping: 0.1,
+        angular_damping: 0.2,
+    };
+    let damping = parameters.damping(Some(original)).unwrap();
+    assert_eq!(
+        (damping.linear_damping, damping.angular_damping),
+        (0.1, 0.5)
+    );
+    assert!(parameters.is_set());
+
+    let unset = BodyParameters {
+        link: "arm".to_string(),
+        ..Default::default()
+    };
+    assert!(unset.damping(None).is_none());
+    let settings = PhysicsParameters {
+        bodies: vec![unset],
+        ..Default::default()
+    };
+    assert!(settings.find("arm").is_none());
+}
+
+#[test]
+fn links_take_the_parameters_and_get_them_back() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(PhysicsParametersPlugin);
+    let arm = app
+        .world_mut()
+        .spawn((
+            RigidBody::Dynamic,
+            Damping {
+                linear_damping: 0.1,
+                angular_damping: 0.2,
+            },
+            Link {
+                plant: "feeder".to_string(),
+                name: "arm".to_string(),
+            },
+            Transform::default(),
+        ))
+        .id();
+    let mesh = app
+        .world_mut()
+        .spawn((Collider::ball(0.1), Transform::default()))
+        .set_parent(arm)
+        .id();
+    app.update();
+    let gravity = |app: &mut App| {
+        app.world_mut()
+            .query::<&RapierConfiguration>()
+            .single(app.world())
+            .gravity
+    };
+    let model_gravity = gravity(&mut app);
+
+    *app.world_mut()
+        .resource_mut::<Persistent<PhysicsParameters>>()
+        .get_mut() = PhysicsParameters {
+        gravity: Some(Vec3::new(0.0, -1.62, 0.0)),
+        solver_iterations: Some(8),
+        bodies: vec![BodyParameters {
+            link: "arm".to_string(),
+            angular_damping: Some(0.5),
+            friction: Some(0.9),
+            ..Default::default()
+        }],
+    };
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(gravity(&mut app), Vec3::new(0.0, -1.62, 0.0));
+    let iterations = app
+        .world_mut()
+        .query::<&RapierContext>()
+        .single(app.world())
+        .integration_parameters
+        .num_solver_iterations
+        .get();
+    assert_eq!(iterations, 8);
+    let damping = *app.world().get::<Damping>(arm).unwrap();
+    assert_eq!(
+        (damping.linear_damping, damping.angular_damping),
+        (0.1, 0.5)
+    );
+    assert_eq!(app.world().get::<Friction>(mesh).unwrap().coefficient, 0.9);
+    assert!(app.world().get::<OriginalBody>(arm).is_some());
+    assert!(app.world().get::<OriginalContact>(mesh).is_some());
+
+    *app.world_mut()
+        .resource_mut::<Persistent<PhysicsParameters>>()
+        .get_mut() = PhysicsParameters::default();
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(gravity(&mut app), model_gravity);
+    let damping = *app.world().get::<Damping>(arm).unwrap();
+    assert_eq!(
+        (damping.linear_damping, damping.angular_damping),
+        (0.1, 0.2)
+    );
+    assert!(app.world().get::<Friction>(mesh).is_none());
+    assert!(app.world().get::<OriginalBody>(arm).is_none());
+    assert!(app.world().get::<OriginalContact>(mesh).is_none());
+}
@@ -0,0 +1,98 @@
+//! Position loops count the turns of their joint, saturate without winding up, and hold the
+//! motor of the pendulum at its setpoint.
+use bevy::prelude::*;
+use digital_twin_playground::{
+    control::{Pid, PidGains, Saturation},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    pid_controller::{PidController, PidControllerPlugin, PidSettings, PID_ERROR},
+    plants::{self, Link},
+    setpoints::{Setpoints, MOTOR_POSITION},
+    telemetry::{Telemetry, MOTOR_ANGLE},
+};
+
+fn proportional(kp: f32, limits: Saturation) -> PidController {
+    let gains = PidGains {
+        kp,
+        ki: 0.0,
+        kd: 0.0,
+    };
+    PidController::new(Pid::new(gains, limits), "")
+}
+
+#[test]
+fn angles_are_counted_over_the_turns() {
+    let mut controller = proportional(2.0, Saturation::default());
+    assert_eq!(controller.position(), None);
+    assert_eq!(controller.update(1.0, 0.0, 0.01), 2.0);
+    controller.unwrap(3.0);
+    // Past a half turn, the angle within the turn wraps around.
+    let position = controller.unwrap(-3.0);
+    assert!(
+        (position - (std::f32::consts::TAU - 3.0)).abs() < 1e-5,
+        "{position}"
+    );
+    assert_eq!(controller.position(), Some(position));
+
+    let controller = PidController::new(PidSettings::default().pid(), "feeder");
+    assert_eq!(controller.setpoint, "feeder/motor/position");
+    assert_eq!(controller.error, "feeder/pid/error");
+}
+
+#[test]
+fn outputs_saturate_without_winding_up() {
+    let settings = PidSettings {
+        gains: PidGains {
+            kp: 1.0,
+            ki: 10.0,
+            kd: 0.0,
+        },
+        limit: 2.0,
+        ..Default::default()
+    };
+    let mut controller = PidController::new(settings.pid(), "");
+    for _ in 0..1000 {
+        assert_eq!(controller.update(100.0, 0.0, 0.01), 2.0);
+    }
+    assert!(controller.pid.integral() <= 2.0);
+    // The output leaves the limit as soon as the error reverses.
+    assert!(controller.update(0.0, 1.0, 0.01) < 2.0);
+
+    let unlimited = PidSettings {
+        limit: 0.0,
+        ..Default::default()
+    };
+    assert_eq!(unlimited.limits(), Saturation::default());
+}
+
+#[test]
+fn motors_are_held_at_their_setpoint() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    (plant.add)(&mut app);
+    app.add_plugins(PidControllerPlugin);
+    app.finish();
+    app.cleanup();
+    app.update();
+
+    let world = app.world_mut();
+    let mut links = world.query::<(Entity, &Link)>();
+    let motor = links
+        .iter(world)
+        .find(|(_, link)| link.name == PidSettings::default().link)
+        .map(|(entity, _)| entity)
+        .unwrap();
+    world
+        .entity_mut(motor)
+        .insert(PidController::new(PidSettings::default().pid(), ""));
+    world.resource_mut::<Setpoints>().set(MOTOR_POSITION, 1.0);
+    for _ in 0..300 {
+        app.update();
+    }
+
+    let telemetry = app.world().resource::<Telemetry>();
+    let angle = telemetry.latest(MOTOR_ANGLE).unwrap();
+    assert!((angle - 1.0).abs() < 0.05, "{angle}");
+    assert!(telemetry.latest(PID_ERROR).unwrap().abs() < 0.05);
+}
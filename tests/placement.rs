@@ -0,0 +1,67 @@
+//! Placements are ranked by how well their sensors see, and their actuators move, the model.
+use digital_twin_playground::{
+    identification::LinearModel,
+    placement::{evaluate, lqr_cost, select, Candidate, Metric, Placement},
+};
+use nalgebra::DMatrix;
+
+/// An unstable mode driven by the first input, a stable one by the second; the first output
+/// sees the unstable mode, the second output barely sees the stable one.
+fn model() -> LinearModel {
+    LinearModel {
+        dt: 0.01,
+        inputs: vec!["u1".to_string(), "u2".to_string()],
+        outputs: vec!["y1".to_string(), "y2".to_string()],
+        a: DMatrix::from_row_slice(2, 2, &[1.01, 0.0, 0.0, 0.9]),
+        b: DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.0, 1.0]),
+        c: DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.0, 0.1]),
+        d: DMatrix::zeros(2, 2),
+        input_offsets: vec![0.0, 0.0],
+        output_offsets: vec![0.0, 0.0],
+    }
+}
+
+fn candidate(name: &str, inputs: &[&str], outputs: &[&str]) -> Candidate {
+    Candidate {
+        name: name.to_string(),
+        inputs: inputs.iter().map(|s| s.to_string()).collect(),
+        outputs: outputs.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+#[test]
+fn sensors_seeing_more_rank_first() {
+    let placement = Placement {
+        metric: Metric::ObservabilityTrace,
+        candidates: vec![
+            candidate("weak", &["u1"], &["y2"]),
+            candidate("strong", &["u1"], &["y1"]),
+        ],
+    };
+    let evaluations = evaluate(&model(), &placement).unwrap();
+    assert_eq!(evaluations[0].name, "strong");
+    assert!(evaluations[0].score > evaluations[1].score);
+}
+
+#[test]
+fn actuators_missing_an_unstable_mode_cost_infinitely() {
+    let model = model();
+    let reaching = select(&model, &candidate("reaching", &["u1"], &["y1", "y2"])).unwrap();
+    let missing = select(&model, &candidate("missing", &["u2"], &["y1", "y2"])).unwrap();
+    assert!(lqr_cost(&reaching).is_finite());
+    assert_eq!(lqr_cost(&missing), f64::INFINITY);
+
+    let placement = Placement {
+        metric: Metric::LqrCost,
+        candidates: vec![
+            candidate("missing", &["u2"], &["y1", "y2"]),
+            candidate("reaching", &["u1"], &["y1", "y2"]),
+        ],
+    };
+    assert_eq!(evaluate(&model, &placement).unwrap()[0].name, "reaching");
+}
+
+#[test]
+fn unknown_signals_are_rejected() {
+    assert!(select(&model(), &candidate("typo", &["u1"], &["y3"])).is_err());
+}
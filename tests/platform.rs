@@ -0,0 +1,38 @@
+//! Files are opened and the telemetry exported the same way on every platform.
+use std::fs;
+
+use digital_twin_playground::{
+    platform::{self, TELEMETRY_FILE},
+    telemetry::Telemetry,
+};
+
+#[test]
+fn models_are_told_by_their_extension() {
+    assert!(platform::is_model("rotary_pendulum.glb"));
+    assert!(platform::is_model("Arm.GLTF"));
+    assert!(!platform::is_model("pid.json"));
+    assert!(!platform::is_model("glb"));
+}
+
+#[test]
+fn the_telemetry_is_exported_as_csv() {
+    let mut telemetry = Telemetry::default();
+    telemetry.record("pendulum/angle", 0.0, 0.5);
+    telemetry.record("pendulum/angle", 0.25, 0.25);
+    telemetry.record("motor/torque", 0.0, -1.5);
+    let csv = telemetry.to_csv();
+    assert_eq!(
+        csv,
+        "channel,time,value\nmotor/torque,0,-1.5\npendulum/angle,0,0.5\npendulum/angle,0.25,0.25\n"
+    );
+
+    let directory = std::env::temp_dir().join("digital-twin-playground-platform");
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(TELEMETRY_FILE);
+    let saved = platform::save_file(&path, csv.as_bytes(), "text/csv").unwrap();
+    assert_eq!(saved, path.display().to_string());
+    assert_eq!(fs::read_to_string(&path).unwrap(), csv);
+
+    let missing = directory.join("missing").join(TELEMETRY_FILE);
+    assert!(platform::save_file(&missing, csv.as_bytes(), "text/csv").is_err());
+}
@@ -0,0 +1,76 @@
+//! The telemetry is streamed to PlotJuggler in UDP datagrams.
+use std::{collections::BTreeMap, net::UdpSocket, thread, time::Duration};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plotjuggler::{Encoding, PlotJugglerPlugin, PlotJugglerSettings, StreamedSignal},
+    telemetry::Telemetry,
+};
+use serde_json::{json, Value};
+
+#[test]
+fn datagrams_hold_the_time_and_the_signals_due() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(2)))
+        .unwrap();
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(PlotJugglerPlugin {
+        remote: receiver.local_addr().unwrap(),
+        settings: PlotJugglerSettings {
+            rate: 1000.0,
+            signals: vec![
+                StreamedSignal {
+                    name: "test/fast".to_string(),
+                    rate: None,
+                },
+                StreamedSignal {
+                    name: "test/slow".to_string(),
+                    rate: Some(0.01),
+                },
+            ],
+            ..default()
+        },
+    });
+    let mut datagrams = Vec::new();
+    let mut buffer = [0; 1500];
+    for step in 0..50 {
+        let mut telemetry = app.world_mut().resource_mut::<Telemetry>();
+        telemetry.record("test/fast", 0.0, step as f32);
+        telemetry.record("test/slow", 0.0, 2.0);
+        telemetry.record("test/other", 0.0, 3.0);
+        app.update();
+        thread::sleep(Duration::from_millis(2));
+        if let Ok(size) = receiver.recv(&mut buffer) {
+            datagrams.push(serde_json::from_slice::<Value>(&buffer[..size]).unwrap());
+        }
+    }
+    assert!(datagrams.len() > 1, "{datagrams:?}");
+    assert_eq!(datagrams[0]["test/slow"], json!(2.0));
+    assert!(datagrams.iter().all(|datagram| {
+        datagram["timestamp"].is_number() && datagram.get("test/other").is_none()
+    }));
+    // The slow signal isn't due again during the run.
+    assert!(datagrams[1..]
+        .iter()
+        .all(|datagram| datagram.get("test/slow").is_none() && datagram["test/fast"].is_number()));
+}
+
+#[test]
+fn message_pack_datagrams_are_maps_of_doubles() {
+    let values = BTreeMap::from([("motor/angle".to_string(), 0.5)]);
+    let mut expected = vec![0x82, 0xa9];
+    expected.extend(b"timestamp");
+    expected.push(0xcb);
+    expected.extend(1.5f64.to_be_bytes());
+    expected.push(0xab);
+    expected.extend(b"motor/angle");
+    expected.push(0xcb);
+    expected.extend(0.5f64.to_be_bytes());
+    assert_eq!(Encoding::MessagePack.encode(1.5, &values), expected);
+    assert_eq!(
+        serde_json::from_slice::<Value>(&Encoding::Json.encode(1.5, &values)).unwrap(),
+        json!({ "timestamp": 1.5, "motor/angle": 0.5 })
+    );
+}
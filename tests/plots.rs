@@ -0,0 +1,30 @@
+//! Setpoints are plotted as steps, held until the time plotted.
+use digital_twin_playground::plots::SetpointHistory;
+
+#[test]
+fn setpoints_are_recorded_on_change() {
+    let mut history = SetpointHistory::default();
+    history.record("motor/velocity", 0.0, 1.0);
+    history.record("motor/velocity", 0.5, 1.0);
+    history.record("motor/velocity", 1.0, 2.0);
+    assert_eq!(
+        history.series["motor/velocity"],
+        [[0.0, 1.0], [1.0, 1.0], [1.0, 2.0]]
+    );
+}
+
+#[test]
+fn setpoints_are_held_until_the_time_plotted() {
+    let mut history = SetpointHistory::default();
+    history.record("motor/position", 0.0, 0.0);
+    history.record("motor/position", 2.0, 1.5);
+    assert_eq!(
+        history.samples("motor/position", 1.0),
+        [[0.0, 0.0], [1.0, 0.0]]
+    );
+    assert_eq!(
+        history.samples("motor/position", 3.0),
+        [[0.0, 0.0], [2.0, 0.0], [2.0, 1.5], [3.0, 1.5]]
+    );
+    assert!(history.samples("missing", 3.0).is_empty());
+}
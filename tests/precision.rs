@@ -0,0 +1,48 @@
+//! The analytic model of the double pendulum conserves its energy, and its `f32` build follows
+//! the `f64` one until the chaos amplifies their rounding.
+use digital_twin_playground::{
+    determinism::CHECKPOINT_INTERVAL,
+    precision::{run_comparison, DoublePendulumModel},
+};
+
+const GRAVITY: f64 = 9.81;
+const DT: f32 = 1.0 / 600.0;
+
+#[test]
+fn model_conserves_energy() {
+    let mut model = DoublePendulumModel::<f64>::released(GRAVITY);
+    let energy = model.energy();
+    for _ in 0..10_000 {
+        model.step(1e-3);
+    }
+    assert!((model.energy() - energy).abs() < 1e-6, "{}", model.energy());
+}
+
+#[test]
+fn precisions_diverge_over_a_long_run() {
+    let mut single = DoublePendulumModel::<f32>::released(GRAVITY);
+    let mut double = DoublePendulumModel::<f64>::released(GRAVITY);
+    let divergence = |single: &DoublePendulumModel<f32>, double: &DoublePendulumModel<f64>| {
+        (f64::from(single.state[0]) - double.state[0]).abs()
+    };
+    let mut largest = 0.0f64;
+    for step in 1..=(60.0 / DT) as usize {
+        single.step(DT);
+        double.step(DT.into());
+        if step == (1.0 / DT) as usize {
+            assert!(divergence(&single, &double) < 1e-4);
+        }
+        largest = largest.max(divergence(&single, &double));
+    }
+    assert!(largest > 0.1, "{largest}");
+}
+
+#[test]
+fn comparison_checkpoints_the_divergences() {
+    let report = run_comparison(2 * CHECKPOINT_INTERVAL);
+    assert_eq!(report.checkpoints.len(), 2);
+    for checkpoint in &report.checkpoints {
+        assert!(checkpoint.rapier_divergence.is_finite());
+        assert!(checkpoint.single_divergence < 1e-3);
+    }
+}
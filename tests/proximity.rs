@@ -0,0 +1,47 @@
+//! Distances between links are measured between their shapes, and near misses counted once.
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::proximity::{distance, LinkPair, Proximity};
+
+#[test]
+fn distances_are_measured_between_the_shapes() {
+    let ball = Collider::ball(0.5);
+    let origin = GlobalTransform::IDENTITY;
+    let away = GlobalTransform::from_xyz(2.0, 0.0, 0.0);
+    let measured = distance(&ball, &origin, &ball, &away).unwrap();
+    assert!((measured - 1.0).abs() < 1e-5, "{measured}");
+
+    // A bar turned towards the ball reaches into it.
+    let bar = Collider::cuboid(0.1, 1.0, 0.1);
+    let turned = GlobalTransform::from(
+        Transform::from_xyz(0.6, 0.0, 0.0).with_rotation(Quat::from_rotation_z(-FRAC_PI_2)),
+    );
+    assert!((distance(&bar, &origin, &ball, &away).unwrap() - 1.4).abs() < 1e-5);
+    assert_eq!(distance(&bar, &turned, &ball, &away), Some(0.0));
+}
+
+#[test]
+fn near_misses_are_counted_once() {
+    let pair = LinkPair {
+        first: "pendulum".to_string(),
+        second: "column".to_string(),
+        near_miss: 0.5,
+    };
+    assert_eq!(pair.channel(), "distance/pendulum-column");
+    let mut proximity = Proximity::default();
+    let misses: Vec<bool> = [1.0, 0.4, 0.3, 0.6, 0.2]
+        .into_iter()
+        .map(|distance| proximity.update(&pair, Some(distance)))
+        .collect();
+    assert_eq!(misses, [false, true, false, false, true]);
+    let measured = &proximity.pairs[&pair.channel()];
+    assert_eq!(measured.near_misses, 2);
+    assert_eq!(measured.closest, Some(0.2));
+
+    // A missing link doesn't forget the closest distance.
+    assert!(!proximity.update(&pair, None));
+    assert_eq!(proximity.pairs[&pair.channel()].latest, None);
+    assert_eq!(proximity.pairs[&pair.channel()].closest, Some(0.2));
+}
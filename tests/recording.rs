@@ -0,0 +1,97 @@
+//! Recordings have a row per sampled time, a column per channel, and timestamped names.
+use std::fs;
+
+use digital_twin_playground::{
+    recording::{self, Recorder, RecordingFormat, RecordingSettings},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::{Telemetry, MOTOR_TORQUE, PENDULUM_ANGLE},
+};
+
+#[test]
+fn recordings_are_named_after_their_start() {
+    assert_eq!(
+        recording::file_name(1_700_000_000, RecordingFormat::Csv),
+        "recording-2023-11-14_22-13-20.csv"
+    );
+    assert_eq!(
+        recording::file_name(951_782_400, RecordingFormat::Parquet),
+        "recording-2000-02-29_00-00-00.parquet"
+    );
+}
+
+#[test]
+fn rows_hold_the_samples_of_each_step() {
+    let dir = std::env::temp_dir().join("recording_rows");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("recording.csv");
+    let mut telemetry = Telemetry::default();
+    // Samples recorded before the recording starts are left out.
+    telemetry.record(PENDULUM_ANGLE, 0.0, 9.0);
+    let settings = RecordingSettings {
+        channels: vec![PENDULUM_ANGLE.to_string(), MOTOR_TORQUE.to_string()],
+        ..Default::default()
+    };
+    let mut recorder = Recorder::start(path.clone(), &settings, &telemetry).unwrap();
+
+    let mut setpoints = Setpoints::default();
+    setpoints.set(MOTOR_VELOCITY, 2.0);
+    telemetry.record(PENDULUM_ANGLE, 0.5, 0.1);
+    telemetry.record(MOTOR_TORQUE, 0.5, -1.0);
+    telemetry.record(PENDULUM_ANGLE, 1.0, 0.2);
+    telemetry.record("unselected", 1.0, 5.0);
+    recorder.record(&telemetry, &setpoints).unwrap();
+    telemetry.record(MOTOR_TORQUE, 1.5, -3.0);
+    recorder.record(&telemetry, &setpoints).unwrap();
+    assert_eq!(recorder.rows(), 3);
+    assert_eq!(recorder.finish().unwrap(), path);
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "time,pendulum/angle,motor/torque,setpoint/motor/velocity\n\
+         0.5,0.1,-1,2\n\
+         1,0.2,,2\n\
+         1.5,,-3,2\n"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn every_channel_is_recorded_by_default() {
+    let dir = std::env::temp_dir().join("recording_default");
+    let path = dir.join("recording.csv");
+    let mut telemetry = Telemetry::default();
+    let settings = RecordingSettings {
+        setpoints: false,
+        ..Default::default()
+    };
+    let mut recorder = Recorder::start(path.clone(), &settings, &telemetry).unwrap();
+    // Nothing is written until there are samples.
+    recorder.record(&telemetry, &Setpoints::default()).unwrap();
+    assert!(recorder.columns().is_none());
+    telemetry.record(MOTOR_TORQUE, 0.0, 1.0);
+    telemetry.record(PENDULUM_ANGLE, 0.0, 2.0);
+    recorder.record(&telemetry, &Setpoints::default()).unwrap();
+    assert_eq!(
+        recorder.columns().unwrap(),
+        [MOTOR_TORQUE.to_string(), PENDULUM_ANGLE.to_string()]
+    );
+    recorder.finish().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "time,motor/torque,pendulum/angle\n0,1,2\n"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parquet_needs_its_feature() {
+    let settings = RecordingSettings {
+        format: RecordingFormat::Parquet,
+        ..Default::default()
+    };
+    let path = std::env::temp_dir()
+        .join("recording_parquet")
+        .join("recording.parquet");
+    let recorder = Recorder::start(path, &settings, &Telemetry::default());
+    assert_eq!(recorder.is_ok(), cfg!(feature = "parquet"));
+}
@@ -0,0 +1,131 @@
+//! A headless client joins a headless host over the loopback interface.
+use std::{net::SocketAddr, thread, time::Duration};
+
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use digital_twin_playground::{
+    clock::SimClock,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    remote::{
+        ClientRole, Permissions, RemoteClientPlugin, RemoteHost, RemoteHostPlugin, RemoteSession,
+        SessionSettings, SessionStatus,
+    },
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+};
+
+fn finish(mut app: App) -> App {
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Updates both applications until `done` holds.
+fn run_until(host: &mut App, client: &mut App, done: impl Fn(&App, &App) -> bool) {
+    for _ in 0..2_000 {
+        host.update();
+        client.update();
+        if done(host, client) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+    panic!("the session doesn't make progress");
+}
+
+#[test]
+fn operator_follows_and_commands_the_host() {
+    let address: SocketAddr = "127.0.0.1:47021".parse().unwrap();
+    let mut host = headless_app(DEFAULT_TIME_STEP);
+    host.add_plugins(RemoteHostPlugin { bind: address });
+    let mut host = finish(host);
+    let mut client = headless_app(DEFAULT_TIME_STEP);
+    client.add_plugins(RemoteClientPlugin {
+        host: address,
+        name: "test".to_string(),
+        role: ClientRole::Operator,
+    });
+    let mut client = finish(client);
+
+    run_until(&mut host, &mut client, |host, client| {
+        client.world().resource::<RemoteSession>().status
+            == SessionStatus::Connected(Some(ClientRole::Operator))
+            && client.world().resource::<SimClock>().tick() > 10
+            && host.world().resource::<RemoteHost>().clients.len() == 1
+    });
+    // The client doesn't simulate on its own: its clock comes from the host.
+    assert!(
+        client.world().resource::<SimClock>().tick() <= host.world().resource::<SimClock>().tick()
+    );
+
+    client
+        .world_mut()
+        .resource_mut::<Setpoints>()
+        .set(MOTOR_VELOCITY, 2.5);
+    run_until(&mut host, &mut client, |host, _| {
+        host.world().resource::<Setpoints>().get(MOTOR_VELOCITY) == Some(2.5)
+    });
+}
+
+#[test]
+fn seats_permit_their_setpoints() {
+    let settings = SessionSettings {
+        operators: Permissions::none(),
+        seats: [(
+            "alice".to_string(),
+            Permissions {
+                setpoints: vec!["motor/*".to_string(), "pendulum/angle".to_string()],
+                gains: false,
+                disturbances: true,
+            },
+        )]
+        .into(),
+        ..default()
+    };
+    let alice = settings.permissions("alice");
+    assert!(alice.may_set(MOTOR_VELOCITY));
+    assert!(alice.may_set("pendulum/angle"));
+    assert!(!alice.may_set("pendulum/velocity"));
+    assert!(!settings.permissions("bob").may_set(MOTOR_VELOCITY));
+    assert!(Permissions::default().may_set("anything"));
+}
+
+#[test]
+fn operators_without_a_seat_are_refused() {
+    let address: SocketAddr = "127.0.0.1:47022".parse().unwrap();
+    let mut host = headless_app(DEFAULT_TIME_STEP);
+    host.add_plugins(RemoteHostPlugin { bind: address });
+    let mut host = finish(host);
+    host.world_mut()
+        .resource_mut::<Persistent<SessionSettings>>()
+        .get_mut()
+        .operators = Permissions::none();
+    let mut client = headless_app(DEFAULT_TIME_STEP);
+    client.add_plugins(RemoteClientPlugin {
+        host: address,
+        name: "test".to_string(),
+        role: ClientRole::Operator,
+    });
+    let mut client = finish(client);
+
+    run_until(&mut host, &mut client, |_, client| {
+        client.world().resource::<RemoteSession>().status
+            == SessionStatus::Connected(Some(ClientRole::Operator))
+    });
+    assert_eq!(
+        client.world().resource::<RemoteSession>().permissions,
+        Permissions::none()
+    );
+    client
+        .world_mut()
+        .resource_mut::<Setpoints>()
+        .set(MOTOR_VELOCITY, 2.5);
+    for _ in 0..50 {
+        host.update();
+        client.update();
+        thread::sleep(Duration::from_millis(2));
+    }
+    assert_ne!(
+        host.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+        Some(2.5)
+    );
+}
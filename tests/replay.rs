@@ -0,0 +1,117 @@
+//! Trajectories keep the last steps, and playbacks scrub, play and step through them.
+use std::{fs, time::Duration};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    body_state::BodyState,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants,
+    replay::{self, Frame, Playback, ReplayPlugin, Trajectory},
+};
+
+fn frame(tick: u64) -> Frame {
+    Frame {
+        tick,
+        elapsed: Duration::from_millis(10 * tick),
+        bodies: vec![BodyState {
+            translation: Vec3::X * tick as f32,
+            rotation: Quat::IDENTITY,
+            linear_velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+        }],
+    }
+}
+
+fn trajectory() -> Trajectory {
+    let mut trajectory = Trajectory::default();
+    for tick in 1..=10 {
+        trajectory.push(frame(tick), 5);
+    }
+    trajectory
+}
+
+#[test]
+fn trajectories_keep_the_last_steps() {
+    let mut trajectory = trajectory();
+    assert_eq!(trajectory.frames.len(), 5);
+    assert_eq!(trajectory.frames[0].tick, 6);
+    assert_eq!(trajectory.span(), Some((0.06, 0.1)));
+    assert_eq!(trajectory.index_at(0.075), Some(1));
+    assert_eq!(trajectory.index_at(0.0), Some(0));
+    assert_eq!(trajectory.index_at(1.0), Some(4));
+
+    // A rewound simulation starts over.
+    trajectory.push(frame(3), 5);
+    assert_eq!(trajectory.frames.len(), 1);
+
+    let path = std::env::temp_dir().join("replay").join("trajectory.json");
+    let trajectory = self::trajectory();
+    trajectory.write(&path).unwrap();
+    assert_eq!(Trajectory::read(&path).unwrap(), trajectory);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn playbacks_play_and_step_within_the_trajectory() {
+    let trajectory = trajectory();
+    let mut playback = Playback::new(&trajectory);
+    assert!((playback.time - 0.1).abs() < 1e-6);
+    assert!(!playback.playing);
+
+    playback.step(-2, &trajectory);
+    assert_eq!(trajectory.index_at(playback.time), Some(2));
+    playback.step(-10, &trajectory);
+    assert_eq!(trajectory.index_at(playback.time), Some(0));
+
+    playback.playing = true;
+    playback.speed = 0.5;
+    playback.advance(0.05, &trajectory);
+    assert!((playback.time - 0.085).abs() < 1e-6, "{}", playback.time);
+    assert!(playback.playing);
+    // Playing stops at the end.
+    playback.advance(1.0, &trajectory);
+    assert!(!playback.playing);
+    assert!((playback.time - 0.1).abs() < 1e-6);
+    playback.step(1, &trajectory);
+    assert_eq!(trajectory.index_at(playback.time), Some(4));
+}
+
+#[test]
+fn playback_holds_the_physics_and_returns_to_live() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    (plant.add)(&mut app);
+    app.add_plugins(ReplayPlugin);
+    app.finish();
+    app.cleanup();
+    for _ in 0..30 {
+        app.update();
+    }
+    let frames = app.world().resource::<Trajectory>().frames.len();
+    assert!(frames > 10, "{frames}");
+    let live = app
+        .world()
+        .resource::<Trajectory>()
+        .frames
+        .back()
+        .cloned()
+        .unwrap();
+
+    replay::start(app.world_mut());
+    let trajectory = app.world().resource::<Trajectory>().clone();
+    app.world_mut()
+        .resource_mut::<Playback>()
+        .step(-10, &trajectory);
+    for _ in 0..5 {
+        app.update();
+    }
+    // The physics is held and the trajectory kept.
+    assert_eq!(app.world().resource::<Trajectory>().frames.len(), frames);
+    replay::stop(app.world_mut());
+    assert!(app.world().get_resource::<Playback>().is_none());
+    app.update();
+    let resumed = app.world().resource::<Trajectory>().frames.back().cloned();
+    assert_eq!(resumed.map(|frame| frame.tick), Some(live.tick + 1));
+}
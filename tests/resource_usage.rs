@@ -0,0 +1,54 @@
+//! Runs report the CPU time and the memory they took, and the distribution of their steps.
+use std::time::Duration;
+
+use digital_twin_playground::resource_usage::{
+    parse_cpu_time, parse_peak_memory, percentile, ResourceUsage, UsageMeter,
+};
+
+#[test]
+fn percentiles_are_taken_by_the_nearest_rank() {
+    let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+    assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+    assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+    assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+    assert_eq!(percentile(&sorted, 0.0), Duration::from_millis(1));
+    assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+}
+
+#[test]
+fn usage_is_read_from_proc() {
+    let stat = "4242 (digital twin) R 1 4242 4242 0 -1 4194304 1500 0 0 0 250 50 0 0 20 0 9 0";
+    assert_eq!(parse_cpu_time(stat), Some(Duration::from_secs(3)));
+    assert_eq!(parse_cpu_time("4242 (truncated"), None);
+
+    let status =
+        "Name:\tplayground\nVmPeak:\t  900000 kB\nVmHWM:\t  204800 kB\nVmRSS:\t  102400 kB\n";
+    assert_eq!(parse_peak_memory(status), Some(200 * 1024 * 1024));
+    assert_eq!(parse_peak_memory("Name:\tplayground\n"), None);
+}
+
+#[test]
+fn meters_report_the_steps_of_a_run() {
+    let mut meter = UsageMeter::start();
+    for millis in [3, 1, 2, 10] {
+        meter.step(Duration::from_millis(millis));
+    }
+    let usage = meter.usage();
+    assert_eq!(usage.steps, 4);
+    assert_eq!(usage.step_p50, Duration::from_millis(2));
+    assert_eq!(usage.step_max, Duration::from_millis(10));
+    if cfg!(target_os = "linux") {
+        assert!(usage.cpu_time.is_some() && usage.peak_memory.is_some());
+    }
+
+    let usage = ResourceUsage {
+        step_p50: Duration::from_micros(120),
+        ..usage
+    };
+    let columns = usage.csv();
+    assert_eq!(
+        columns.split(',').count(),
+        ResourceUsage::CSV_HEADER.split(',').count()
+    );
+    assert!(columns.ends_with(",120,10000,10000,10000"), "{columns}");
+}
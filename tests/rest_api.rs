@@ -0,0 +1,155 @@
+//! A script drives a headless instance through its HTTP API.
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    clock::SimClock,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    rest_api::{self, ChannelMetrics, RestApi, RestApiPlugin, Route},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+};
+use serde_json::{json, Value};
+
+#[test]
+fn requests_are_routed() {
+    assert_eq!(rest_api::route("GET", "/status"), Ok(Route::Status));
+    assert_eq!(
+        rest_api::route("PUT", "/parameters/arm/motor/velocity"),
+        Ok(Route::SetParameter("arm/motor/velocity".to_string()))
+    );
+    assert_eq!(rest_api::route("POST", "/runs/"), Ok(Route::StartRun));
+    assert_eq!(
+        rest_api::route("POST", "/runs/3/stop"),
+        Ok(Route::StopRun(3))
+    );
+    assert_eq!(
+        rest_api::route("GET", "/runs/3/metrics?format=json"),
+        Ok(Route::Metrics(3))
+    );
+    assert_eq!(rest_api::route("DELETE", "/runs"), Err(405));
+    assert_eq!(rest_api::route("GET", "/runs/last"), Err(404));
+    assert_eq!(rest_api::route("GET", "/"), Err(404));
+}
+
+#[test]
+fn metrics_cover_the_run() {
+    let samples = [[0.0, 10.0], [1.0, 3.0], [2.0, -4.0], [3.0, 10.0]];
+    assert_eq!(
+        ChannelMetrics::of(&samples, 1.0, 2.0),
+        Some(ChannelMetrics {
+            samples: 2,
+            min: -4.0,
+            max: 3.0,
+            mean: -0.5,
+            rms: 12.5f32.sqrt(),
+            last: -4.0,
+        })
+    );
+    assert_eq!(ChannelMetrics::of(&samples, 4.0, 5.0), None);
+}
+
+/// Sends a request from another thread while the application updates, returning the status and
+/// the body of the answer.
+fn request(
+    app: &mut App,
+    address: SocketAddr,
+    method: &str,
+    path: &str,
+    body: Value,
+) -> (u16, Value) {
+    let (method, path) = (method.to_string(), path.to_string());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let body = body.to_string();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: {address}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        sender.send(response).unwrap();
+    });
+    for _ in 0..2_000 {
+        app.update();
+        if let Ok(response) = receiver.try_recv() {
+            let status = response[9..12].parse().unwrap();
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            return (status, serde_json::from_str(body).unwrap());
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+    panic!("the API doesn't answer");
+}
+
+#[test]
+fn scripts_run_campaigns() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(RestApiPlugin {
+        bind: "127.0.0.1:0".parse().unwrap(),
+    });
+    app.finish();
+    app.cleanup();
+    let address = app.world().resource::<RestApi>().address;
+
+    let (status, _) = request(&mut app, address, "GET", "/plants", Value::Null);
+    assert_eq!(status, 200);
+    let (status, body) = request(
+        &mut app,
+        address,
+        "PUT",
+        "/parameters/motor/velocity",
+        json!({"value": 1.5}),
+    );
+    assert_eq!((status, &body["value"]), (200, &json!(1.5)));
+    assert_eq!(
+        app.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+        Some(1.5)
+    );
+
+    let run = json!({"name": "step", "duration": 0.1, "parameters": {"motor/velocity": 3.0}});
+    let (status, body) = request(&mut app, address, "POST", "/runs", run.clone());
+    assert_eq!(status, 201, "{body}");
+    assert_eq!(body["name"], "step");
+    assert_eq!(
+        app.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+        Some(3.0)
+    );
+    // One run at a time.
+    assert_eq!(request(&mut app, address, "POST", "/runs", run).0, 409);
+
+    let start = body["start"].as_f64().unwrap() as f32;
+    app.world_mut()
+        .resource_mut::<Telemetry>()
+        .record("test/value", start + 0.05, 2.0);
+    // The run stops on its own after its duration, pausing the clock.
+    for _ in 0..1_000 {
+        app.update();
+        if app.world().resource::<SimClock>().is_paused() {
+            break;
+        }
+    }
+    let (status, body) = request(&mut app, address, "GET", "/runs/0", Value::Null);
+    assert_eq!(status, 200);
+    assert!(
+        body["end"].as_f64().unwrap() as f32 >= start + 0.1,
+        "{body}"
+    );
+
+    let (status, body) = request(&mut app, address, "GET", "/runs/0/metrics", Value::Null);
+    assert_eq!(status, 200);
+    assert_eq!(body["test/value"]["mean"], json!(2.0));
+    assert_eq!(
+        request(&mut app, address, "GET", "/runs/1", Value::Null).0,
+        404
+    );
+}
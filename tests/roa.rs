@@ -0,0 +1,118 @@
+//! Regions of attraction are bounded at their confidence and refined along their boundary.
+use std::{collections::BTreeMap, fs};
+
+use digital_twin_playground::{
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    roa::{self, RoaSettings},
+    sweep::Range,
+};
+
+fn settings() -> RoaSettings {
+    let range = |name: &str| Range {
+        name: name.to_string(),
+        min: -1.0,
+        max: 1.0,
+        steps: 8,
+    };
+    RoaSettings {
+        x: range("x"),
+        y: range("y"),
+        samples: 2,
+        refinements: 2,
+        refine_samples: 6,
+        ..Default::default()
+    }
+}
+
+/// A disk of radius 0.7.
+fn disk(parameters: &BTreeMap<String, f32>) -> bool {
+    parameters["x"].powi(2) + parameters["y"].powi(2) < 0.49
+}
+
+#[test]
+fn fractions_are_bounded_at_their_confidence() {
+    assert!((roa::z_score(0.95) - 1.96).abs() < 1e-3);
+    assert!((roa::z_score(0.99) - 2.576).abs() < 1e-3);
+    let [lower, upper] = roa::wilson(10, 10, 1.96);
+    assert!((lower - 0.7225).abs() < 1e-3, "{lower}");
+    assert_eq!(upper, 1.0);
+    assert_eq!(roa::wilson(0, 0, 1.96), [0.0, 1.0]);
+    // More samples, tighter bounds.
+    let [low, high] = roa::wilson(50, 100, 1.96);
+    let [wide_low, wide_high] = roa::wilson(5, 10, 1.96);
+    assert!(wide_low < low && low < 0.5 && 0.5 < high && high < wide_high);
+}
+
+#[test]
+fn samples_concentrate_on_the_boundary() {
+    let settings = settings();
+    let region = roa::estimate(&settings, |parameters| Ok(disk(parameters))).unwrap();
+    assert_eq!(region.cells.len(), 64);
+    assert_eq!(
+        region,
+        roa::estimate(&settings, |parameters| Ok(disk(parameters))).unwrap()
+    );
+
+    // Far from the disk, and in its middle, the first pass is enough.
+    assert_eq!(region.cells[0].samples, 2);
+    assert_eq!(region.cells[0].attracted, 0);
+    let centre = &region.cells[3 * 8 + 3];
+    assert_eq!((centre.samples, centre.attracted), (2, 2));
+    // The boundary may have moved during the last pass, but it was mostly refined.
+    let boundary = region.boundary();
+    let refined = boundary
+        .iter()
+        .filter(|&&index| region.cells[index].samples > 2)
+        .count();
+    assert!(2 * refined > boundary.len(), "{refined} of {boundary:?}");
+
+    let [lower, area, upper] = region.area();
+    let exact = std::f32::consts::PI * 0.49;
+    assert!(lower < area && area < upper);
+    assert!((area - exact).abs() < 0.3, "{area}");
+    assert!(lower < exact && exact < upper);
+}
+
+#[test]
+fn estimates_are_exported() {
+    let settings = settings();
+    let region = roa::estimate(&settings, |parameters| Ok(disk(parameters))).unwrap();
+    let path = std::env::temp_dir().join("roa").join("region.csv");
+    region.write_csv(&path).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("x_min,x_max,y_min,y_max,samples,attracted,fraction,fraction_min,fraction_max")
+    );
+    let first = lines.next().unwrap();
+    assert!(first.starts_with("-1,-0.75,-1,-0.75,2,0,0,"), "{first}");
+    assert_eq!(lines.count(), 63);
+    fs::remove_file(path).unwrap();
+
+    let invalid = RoaSettings {
+        confidence: 1.0,
+        ..settings
+    };
+    assert!(roa::estimate(&invalid, |_| Ok(true)).is_err());
+}
+
+#[test]
+fn plants_are_mapped() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let mut settings = RoaSettings {
+        samples: 1,
+        refinements: 1,
+        refine_samples: 1,
+        duration: 0.2,
+        ..Default::default()
+    };
+    settings.x.steps = 2;
+    settings.y.steps = 1;
+    let region = roa::map(&plant, &settings, DEFAULT_TIME_STEP).unwrap();
+    assert_eq!(region.cells.len(), 2);
+    assert!(region.cells.iter().all(|cell| cell.samples >= 1));
+}
@@ -0,0 +1,96 @@
+//! Recorded runs are compared channel by channel, aligned on their time or on an event.
+use std::fs;
+
+use digital_twin_playground::{
+    run_diff::{self, Alignment, RunDiff},
+    telemetry::Telemetry,
+};
+
+/// A run stepping its setpoint at `step` seconds, with a response of `gain` times the setpoint.
+fn run(step: f32, gain: f32) -> Telemetry {
+    let mut telemetry = Telemetry::default();
+    for k in 0..200 {
+        let time = k as f32 / 64.0;
+        let setpoint = if time >= step { 1.0 } else { 0.0 };
+        telemetry.record("setpoint", time, setpoint);
+        telemetry.record("response", time, gain * setpoint);
+    }
+    telemetry
+}
+
+#[test]
+fn samples_are_interpolated() {
+    let samples = [[0.0, 0.0], [1.0, 2.0], [2.0, 0.0]];
+    assert_eq!(run_diff::interpolate(&samples, 0.5), Some(1.0));
+    assert_eq!(run_diff::interpolate(&samples, 1.0), Some(2.0));
+    assert_eq!(run_diff::interpolate(&samples, 1.75), Some(0.5));
+    assert_eq!(run_diff::interpolate(&samples, -0.1), None);
+    assert_eq!(run_diff::interpolate(&samples, 2.1), None);
+}
+
+#[test]
+fn identical_runs_do_not_deviate() {
+    let diff = RunDiff::compare(&run(0.5, 2.0), &run(0.5, 2.0), &Alignment::Time).unwrap();
+    assert_eq!(diff.channels.len(), 2);
+    assert_eq!(diff.max(), 0.0);
+    assert!(diff.only_first.is_empty() && diff.only_second.is_empty());
+}
+
+#[test]
+fn deviations_are_measured() {
+    let mut second = run(0.5, 1.5);
+    second.record("extra", 0.0, 1.0);
+    let diff = RunDiff::compare(&run(0.5, 2.0), &second, &Alignment::Time).unwrap();
+    let response = diff
+        .channels
+        .iter()
+        .find(|channel| channel.channel == "response")
+        .unwrap();
+    assert!((response.max - 0.5).abs() < 1e-6);
+    assert!((response.max_time - 0.5).abs() < 1e-6);
+    // The 168 samples after the step differ by 0.5.
+    let rms = 0.5 * (168.0f32 / 200.0).sqrt();
+    assert!((response.rms - rms).abs() < 1e-5, "{}", response.rms);
+    assert_eq!(diff.only_second, ["extra"]);
+}
+
+#[test]
+fn runs_are_aligned_on_events() {
+    let (first, second) = (run(0.5, 2.0), run(0.75, 2.0));
+    let by_time = RunDiff::compare(&first, &second, &Alignment::Time).unwrap();
+    assert_eq!(by_time.max(), 2.0);
+
+    let marker = Alignment::Event {
+        channel: "setpoint".to_string(),
+        threshold: 0.0,
+    };
+    let aligned = RunDiff::compare(&first, &second, &marker).unwrap();
+    assert_eq!(aligned.offset, 0.25);
+    assert_eq!(aligned.max(), 0.0);
+
+    let missing = Alignment::Event {
+        channel: "trigger".to_string(),
+        threshold: 0.0,
+    };
+    assert!(RunDiff::compare(&first, &second, &missing).is_err());
+}
+
+#[test]
+fn runs_are_read_and_differences_written() {
+    let dir = std::env::temp_dir().join("run_diff_round_trip");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("telemetry-00000.json");
+    fs::write(&path, serde_json::to_vec(&run(0.5, 2.0)).unwrap()).unwrap();
+    // A telemetry file, or a session directory of them.
+    assert_eq!(run_diff::read_run(&path).unwrap(), run(0.5, 2.0));
+    assert_eq!(run_diff::read_run(&dir).unwrap(), run(0.5, 2.0));
+    assert!(run_diff::read_run(&dir.join("missing.json")).is_err());
+
+    let diff = RunDiff::compare(&run(0.5, 2.0), &run(0.5, 1.0), &Alignment::Time).unwrap();
+    let csv = dir.join("diff.csv");
+    diff.write_csv(&csv).unwrap();
+    let csv = fs::read_to_string(csv).unwrap();
+    assert!(csv.starts_with("channel,time,difference\n"));
+    assert!(csv.contains("response,1,1\n"));
+    fs::remove_dir_all(dir).unwrap();
+}
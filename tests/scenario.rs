@@ -0,0 +1,153 @@
+//! Scenarios declare an experiment in RON, YAML or JSON, and runs follow them.
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use digital_twin_playground::{
+    batch::{self, BatchRun},
+    control::PidGains,
+    controller_definition::{ControllerDefinition, ControllerType},
+    error::Error,
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    scenario::ScenarioDefinition,
+    setpoints::MOTOR_POSITION,
+};
+
+const RON: &str = r#"(
+    plant: "rotary_pendulum",
+    composition: "line.json",
+    initial: { "pendulum": 0.2 },
+    setpoints: { "motor/position": 1.0 },
+    duration: 2.5,
+    seed: 7,
+)"#;
+
+const YAML: &str = "
+plant: rotary_pendulum
+composition: line.json
+initial:
+  pendulum: 0.2
+setpoints:
+  motor/position: 1.0
+duration: 2.5
+seed: 7
+";
+
+const JSON: &str = r#"{
+  "plant": "rotary_pendulum",
+  "composition": "line.json",
+  "initial": { "pendulum": 0.2 },
+  "setpoints": { "motor/position": 1.0 },
+  "duration": 2.5,
+  "seed": 7
+}"#;
+
+#[test]
+fn scenarios_read_the_same_in_every_format() {
+    let dir = std::env::temp_dir().join("scenario_formats");
+    fs::create_dir_all(&dir).unwrap();
+    let scenarios: Vec<ScenarioDefinition> = [("s.ron", RON), ("s.yaml", YAML), ("s.json", JSON)]
+        .into_iter()
+        .map(|(name, text)| {
+            let path = dir.join(name);
+            fs::write(&path, text).unwrap();
+            ScenarioDefinition::read(&path).unwrap()
+        })
+        .collect();
+    assert_eq!(scenarios[0], scenarios[1]);
+    assert_eq!(scenarios[1], scenarios[2]);
+    let scenario = &scenarios[0];
+    assert_eq!(scenario.plant.as_deref(), Some("rotary_pendulum"));
+    assert_eq!(scenario.initial["pendulum"], 0.2);
+    assert_eq!(scenario.duration, Some(2.5));
+    assert_eq!(scenario.dt, None);
+    // Paths are relative to the scenario.
+    assert_eq!(scenario.composition, Some(dir.join("line.json")));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn pid(bindings: BTreeMap<String, String>) -> ControllerDefinition {
+    ControllerDefinition {
+        name: "pid".to_string(),
+        controller: ControllerType::Pid {
+            gains: PidGains {
+                kp: 8.0,
+                ki: 0.0,
+                kd: 0.2,
+            },
+            limit: 10.0,
+        },
+        sample_rate: None,
+        bindings,
+    }
+}
+
+#[test]
+fn scenarios_are_validated() {
+    let available = plants::available();
+    let valid = ScenarioDefinition {
+        controllers: vec![pid(BTreeMap::from([(
+            "joint".to_string(),
+            "motor".to_string(),
+        )]))],
+        duration: Some(1.0),
+        ..Default::default()
+    };
+    assert!(valid.validate(&available).is_ok());
+
+    let invalid = [
+        ScenarioDefinition {
+            plant: Some("test_missing".to_string()),
+            ..valid.clone()
+        },
+        ScenarioDefinition {
+            composition: Some(PathBuf::from("line.json")),
+            urdf: Some(PathBuf::from("robot.urdf")),
+            ..valid.clone()
+        },
+        ScenarioDefinition {
+            controllers: vec![pid(BTreeMap::new())],
+            ..valid.clone()
+        },
+        ScenarioDefinition {
+            duration: Some(0.0),
+            ..valid.clone()
+        },
+    ];
+    for scenario in invalid {
+        assert!(
+            matches!(scenario.validate(&available), Err(Error::Config { .. })),
+            "{scenario:?}"
+        );
+    }
+}
+
+#[test]
+fn batch_runs_follow_the_scenario() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join("scenario_run");
+    let run = BatchRun {
+        duration: 0.5,
+        dt: DEFAULT_TIME_STEP,
+        seed: 1,
+        out: dir.join("results.csv"),
+        capture: None,
+        scenario: Some(ScenarioDefinition {
+            setpoints: BTreeMap::from([(MOTOR_POSITION.to_string(), 0.75)]),
+            ..Default::default()
+        }),
+    };
+    batch::run(&plant, &run).unwrap();
+
+    let csv = fs::read_to_string(&run.out).unwrap();
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+    let column = header
+        .iter()
+        .position(|column| *column == format!("setpoint/{MOTOR_POSITION}"))
+        .expect("the setpoint of the scenario is recorded");
+    let last = lines.last().unwrap().split(',').nth(column).unwrap();
+    assert_eq!(last.parse::<f32>().unwrap(), 0.75);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,98 @@
+//! The changes of a reloaded model, and what they break.
+use bevy::prelude::*;
+use digital_twin_playground::{
+    joint_builder::{JointKind, LinkMetadata},
+    scene_diff::{self, Binding, Breakage, ModelChange, ModelSummary, NodeSummary},
+};
+
+fn node(parent: Option<&str>, x: f32, link: Option<LinkMetadata>) -> NodeSummary {
+    NodeSummary {
+        parent: parent.map(str::to_string),
+        transform: Transform::from_xyz(x, 0.0, 0.0),
+        link,
+    }
+}
+
+fn link(name: &str, mass: f32, parent: Option<&str>) -> Option<LinkMetadata> {
+    Some(LinkMetadata {
+        link: Some(name.to_string()),
+        mass: Some(mass),
+        joint: Some(JointKind::Revolute),
+        parent: parent.map(str::to_string),
+        ..default()
+    })
+}
+
+fn model(nodes: Vec<(&str, NodeSummary)>) -> ModelSummary {
+    ModelSummary {
+        nodes: nodes
+            .into_iter()
+            .map(|(name, node)| (name.to_string(), node))
+            .collect(),
+    }
+}
+
+#[test]
+fn changes_are_listed_and_broken_bindings_highlighted() {
+    let before = model(vec![
+        ("Base", node(None, 0.0, link("base", 5.0, None))),
+        ("Arm", node(Some("Base"), 1.0, link("arm", 1.0, None))),
+        (
+            "Pendulum",
+            node(Some("Arm"), 2.0, link("pendulum", 0.2, Some("arm"))),
+        ),
+        ("Lamp", node(None, 3.0, None)),
+    ]);
+    let after = model(vec![
+        ("Base", node(None, 0.5, link("base", 5.0, None))),
+        ("Rotor", node(Some("Base"), 1.0, link("rotor", 1.0, None))),
+        (
+            "Pendulum",
+            node(Some("Rotor"), 2.0, link("pendulum", 0.3, Some("arm"))),
+        ),
+        ("Camera", node(None, 4.0, None)),
+    ]);
+
+    let changes = scene_diff::diff(&before, &after);
+    assert!(changes.contains(&ModelChange::Renamed {
+        from: "Arm".to_string(),
+        to: "Rotor".to_string()
+    }));
+    assert!(changes.contains(&ModelChange::Added("Camera".to_string())));
+    assert!(changes.contains(&ModelChange::Removed("Lamp".to_string())));
+    assert!(changes.contains(&ModelChange::Inertia {
+        link: "pendulum".to_string(),
+        before: Some(0.2),
+        after: Some(0.3)
+    }));
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, ModelChange::Moved { node, .. } if node == "Base")));
+    assert_eq!(changes.len(), 5, "{changes:?}");
+
+    let bindings = [
+        Binding {
+            setting: "position loop".to_string(),
+            link: "arm".to_string(),
+        },
+        // Not a link of this model before either.
+        Binding {
+            setting: "fixtures".to_string(),
+            link: "gripper".to_string(),
+        },
+    ];
+    assert_eq!(
+        scene_diff::breakages(&before, &after, &bindings),
+        [
+            Breakage::Joint {
+                link: "pendulum".to_string(),
+                parent: "arm".to_string()
+            },
+            Breakage::Binding {
+                setting: "position loop".to_string(),
+                link: "arm".to_string()
+            }
+        ]
+    );
+    assert!(scene_diff::diff(&before, &before).is_empty());
+}
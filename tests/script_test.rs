@@ -0,0 +1,120 @@
+//! Controllers are stepped open-loop on recorded traces and their outputs checked against the
+//! expected values.
+use digital_twin_playground::{
+    control::PidGains,
+    error::Error,
+    script_test::{ControllerUnderTest, Expectation, ScriptTest, Signal},
+    telemetry::Telemetry,
+};
+
+/// The angle of a joint ramping from 0 to 1 rad over a second, sampled at 10 Hz.
+fn trace() -> Telemetry {
+    let mut trace = Telemetry::default();
+    for k in 0..=10 {
+        let time = k as f32 / 10.0;
+        trace.record("motor/angle", time, time);
+        trace.record("rig/output", time, 2.0 * (0.5 - time));
+    }
+    trace
+}
+
+fn test(expectations: Vec<Expectation>) -> ScriptTest {
+    ScriptTest {
+        trace: "trace.csv".into(),
+        controller: ControllerUnderTest::Pid {
+            gains: PidGains {
+                kp: 2.0,
+                ki: 0.0,
+                kd: 0.0,
+            },
+            limit: 0.0,
+            angle: "motor/angle".to_string(),
+            setpoint: Signal::Constant(0.5),
+        },
+        start: None,
+        end: None,
+        expectations,
+    }
+}
+
+#[test]
+fn outputs_are_checked_against_the_trace() {
+    let report = test(vec![Expectation {
+        output: "output".to_string(),
+        channel: Some("rig/output".to_string()),
+        samples: Vec::new(),
+        tolerance: 1e-5,
+    }])
+    .run_on(&trace())
+    .unwrap();
+    assert!(report.passed(), "{report}");
+    assert_eq!(report.checks[0].samples, 11);
+    assert_eq!(report.outputs.channels["output"].len(), 11);
+}
+
+#[test]
+fn deviations_beyond_the_tolerance_fail() {
+    let report = test(vec![Expectation {
+        output: "output".to_string(),
+        channel: None,
+        samples: vec![[0.0, 1.0], [0.55, 0.0], [0.8, -1.0]],
+        tolerance: 0.01,
+    }])
+    .run_on(&trace())
+    .unwrap();
+    assert!(!report.passed());
+    let check = &report.checks[0];
+    assert_eq!(check.first_failure, Some(0.55));
+    assert!(
+        (check.max - 0.4).abs() < 1e-5,
+        "largest deviation {}",
+        check.max
+    );
+
+    // Expected samples past the trace aren't covered.
+    let report = test(vec![Expectation {
+        output: "output".to_string(),
+        channel: None,
+        samples: vec![[0.5, 0.0], [2.0, 0.0]],
+        tolerance: 0.01,
+    }])
+    .run_on(&trace())
+    .unwrap();
+    assert_eq!(report.checks[0].missing, 1);
+    assert!(!report.passed());
+}
+
+#[test]
+fn missing_channels_and_outputs_are_reported() {
+    let mut case = test(Vec::new());
+    case.controller = ControllerUnderTest::Pid {
+        gains: PidGains::default(),
+        limit: 0.0,
+        angle: "pendulum/angle".to_string(),
+        setpoint: Signal::Constant(0.0),
+    };
+    assert!(matches!(case.run_on(&trace()), Err(Error::Config { .. })));
+
+    let case = test(vec![Expectation {
+        output: "torque".to_string(),
+        channel: None,
+        samples: vec![[0.0, 0.0]],
+        tolerance: 0.0,
+    }]);
+    assert!(matches!(case.run_on(&trace()), Err(Error::Config { .. })));
+
+    let case: ScriptTest = serde_json::from_str(
+        r#"{
+            "trace": "run.csv",
+            "controller": { "kind": "pid", "gains": { "kp": 1.0, "ki": 0.0, "kd": 0.0 },
+                            "angle": "motor/angle", "setpoint": "motor/position" },
+            "start": 1.0,
+            "expectations": [{ "output": "output", "channel": "pid/output", "tolerance": 0.01 }]
+        }"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        case.controller,
+        ControllerUnderTest::Pid { setpoint: Signal::Channel(ref channel), .. } if channel == "motor/position"
+    ));
+}
@@ -0,0 +1,82 @@
+//! Controller scripts compute torques from the state of the joints, keep their memory between
+//! calls, and are reloaded when their file changes.
+use std::{fs, thread, time::Duration};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    error::Error,
+    plants::Link,
+    scripted_controller::{
+        ControllerScript, ScriptedController, ScriptedControllerSettings, ScriptedJoint, TEMPLATE,
+    },
+};
+
+#[test]
+fn scripts_compute_torques_from_the_state() {
+    let mut script = ControllerScript::default();
+    script.compile(TEMPLATE).unwrap();
+    let mut memory = ScriptedController::new(Vec::new()).memory;
+    let torques = script.call(&mut memory, &[0.5, -1.0], 0.01).unwrap();
+    assert!((torques[0] + 0.8).abs() < 1e-6, "{torques:?}");
+
+    // `this` is kept between the calls.
+    script
+        .compile("fn control(state, dt) { this.count = (this.count ?? 0) + 1; this.count }")
+        .unwrap();
+    assert_eq!(script.call(&mut memory, &[], 0.01), Ok(vec![1.0]));
+    assert_eq!(script.call(&mut memory, &[], 0.01), Ok(vec![2.0]));
+}
+
+#[test]
+fn broken_scripts_are_reported() {
+    let mut script = ControllerScript::default();
+    assert!(script.compile("fn control(state, dt) { [").is_err());
+    assert!(script.compile("fn other(state) { 0.0 }").is_err());
+    assert!(!script.is_running());
+
+    let mut memory = ScriptedController::new(Vec::new()).memory;
+    script.compile("fn control(state, dt) { loop {} }").unwrap();
+    assert!(script.call(&mut memory, &[], 0.01).is_err());
+    script
+        .compile(r#"fn control(state, dt) { "torque" }"#)
+        .unwrap();
+    assert!(script.call(&mut memory, &[], 0.01).is_err());
+    // The limit saturates the torques.
+    let settings = ScriptedControllerSettings::default();
+    assert_eq!(settings.saturate(-5.0), -settings.limit);
+}
+
+#[test]
+fn scripts_are_reloaded_when_they_change() {
+    let path = std::env::temp_dir()
+        .join("scripted_controller")
+        .join("controller.rhai");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "fn control(state, dt) { 1.0 }").unwrap();
+    let mut script = ControllerScript::default();
+    assert!(script.reload(&path).unwrap());
+    assert!(!script.reload(&path).unwrap());
+
+    // A script that doesn't compile leaves the previous one running.
+    thread::sleep(Duration::from_millis(20));
+    fs::write(&path, "fn control(state, dt) {").unwrap();
+    assert!(matches!(script.reload(&path), Err(Error::Script { .. })));
+    let mut memory = ScriptedController::new(Vec::new()).memory;
+    assert_eq!(script.call(&mut memory, &[], 0.01), Ok(vec![1.0]));
+}
+
+#[test]
+fn joints_count_their_turns() {
+    let mut joint = ScriptedJoint::new(
+        Entity::PLACEHOLDER,
+        &Link {
+            plant: String::new(),
+            name: "motor".to_string(),
+        },
+    );
+    assert_eq!(joint.channel, "script/motor");
+    assert_eq!(joint.measure(3.0, 0.1), [3.0, 0.0]);
+    let [position, velocity] = joint.measure(-3.0, 0.1);
+    assert!((position - (std::f32::consts::TAU - 3.0)).abs() < 1e-5);
+    assert!((velocity - (std::f32::consts::TAU - 6.0) / 0.1).abs() < 1e-3);
+}
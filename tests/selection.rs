@@ -0,0 +1,46 @@
+//! Clicks select the link of the clicked body, whose joint can be jogged.
+use bevy::{
+    ecs::entity::Entity,
+    math::{Vec2, Vec3},
+};
+use digital_twin_playground::selection::{self, SelectionSettings, CLICK_SLOP};
+
+#[test]
+fn drags_are_not_clicks() {
+    let pressed = Vec2::new(100.0, 100.0);
+    assert!(selection::is_click(pressed, pressed));
+    assert!(selection::is_click(pressed, pressed + Vec2::X * CLICK_SLOP));
+    assert!(!selection::is_click(pressed, pressed + Vec2::Y * 20.0));
+}
+
+#[test]
+fn clicks_select_the_link_of_the_collider() {
+    // A collider, under a mesh node, under the link.
+    let [link, node, collider] = [1, 2, 3].map(Entity::from_raw);
+    let parent = |entity: Entity| match entity.index() {
+        2 => Some(link),
+        3 => Some(node),
+        _ => None,
+    };
+    let is_link = |entity: Entity| entity == link;
+    assert_eq!(selection::link_of(collider, parent, is_link), Some(link));
+    assert_eq!(selection::link_of(link, parent, is_link), Some(link));
+    assert_eq!(
+        selection::link_of(Entity::from_raw(4), parent, is_link),
+        None
+    );
+}
+
+#[test]
+fn jogs_turn_revolute_joints_and_slide_prismatic_ones() {
+    let settings = SelectionSettings {
+        jog: 2.0,
+        ..Default::default()
+    };
+    let turn = settings.jog_impulse(Vec3::Z * 3.0, true, -1.0, 0.5);
+    assert_eq!(turn.torque_impulse, Vec3::NEG_Z);
+    assert_eq!(turn.impulse, Vec3::ZERO);
+    let slide = settings.jog_impulse(Vec3::X, false, 1.0, 0.5);
+    assert_eq!(slide.impulse, Vec3::X);
+    assert_eq!(slide.torque_impulse, Vec3::ZERO);
+}
@@ -0,0 +1,113 @@
+//! Contacts between the links of a plant are reported, and stop it when asked to.
+use bevy::prelude::*;
+use bevy_persistent::Persistent;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants::Link,
+    self_collision::{SelfCollisionPlugin, SelfCollisionSettings, SelfCollisions},
+    setpoints::Setpoints,
+};
+
+fn link(plant: &str, name: &str, x: f32, y: f32) -> impl Bundle {
+    (
+        RigidBody::Dynamic,
+        Collider::ball(0.5),
+        GravityScale(0.0),
+        Transform::from_xyz(x, y, 0.0),
+        Link {
+            plant: plant.to_string(),
+            name: name.to_string(),
+        },
+    )
+}
+
+/// Steps overlapping links once, returning the names of the colliding links of the arm and
+/// the app.
+fn step(settings: SelfCollisionSettings) -> (Vec<[String; 2]>, App) {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(SelfCollisionPlugin);
+    app.finish();
+    app.cleanup();
+    app.update();
+    *app.world_mut()
+        .resource_mut::<Persistent<SelfCollisionSettings>>()
+        .get_mut() = settings;
+    let mut setpoints = app.world_mut().resource_mut::<Setpoints>();
+    setpoints.set("arm/motor/velocity", 2.0);
+    setpoints.set("crane/motor/velocity", 2.0);
+
+    let world = app.world_mut();
+    let upper = world.spawn(link("arm", "upper", 0.0, 0.0)).id();
+    // Joined to the upper link, and touching it.
+    world.spawn((
+        link("arm", "lower", 0.0, 0.8),
+        ImpulseJoint::new(upper, FixedJointBuilder::new().local_anchor1(Vec3::Y * 0.8)),
+    ));
+    world.spawn(link("arm", "tool", 0.8, 0.0));
+    // Another plant touching the arm.
+    world.spawn(link("crane", "hook", -0.8, 0.0));
+    app.update();
+
+    let names = app
+        .world()
+        .resource::<SelfCollisions>()
+        .current
+        .iter()
+        .map(|collision| {
+            assert_eq!(collision.plant, "arm");
+            let mut names = collision.names.clone();
+            names.sort();
+            names
+        })
+        .collect();
+    (names, app)
+}
+
+#[test]
+fn contacts_within_a_plant_are_reported() {
+    let (mut names, _) = step(SelfCollisionSettings::default());
+    assert_eq!(names, vec![["tool".to_string(), "upper".to_string()]]);
+
+    (names, _) = step(SelfCollisionSettings {
+        exclude_adjacent: false,
+        ..Default::default()
+    });
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            ["lower".to_string(), "upper".to_string()],
+            ["tool".to_string(), "upper".to_string()]
+        ]
+    );
+
+    let (names, _) = step(SelfCollisionSettings {
+        enabled: false,
+        ..Default::default()
+    });
+    assert!(names.is_empty());
+}
+
+#[test]
+fn safety_stops_latch() {
+    let (_, mut app) = step(SelfCollisionSettings {
+        safety_stop: true,
+        ..Default::default()
+    });
+    let setpoints = app.world().resource::<Setpoints>();
+    assert_eq!(setpoints.get("arm/motor/velocity"), Some(0.0));
+    assert_eq!(setpoints.get("crane/motor/velocity"), Some(2.0));
+
+    // Held until reset.
+    app.world_mut()
+        .resource_mut::<Setpoints>()
+        .set("arm/motor/velocity", 2.0);
+    app.update();
+    let world = app.world();
+    assert!(world.resource::<SelfCollisions>().stopped.contains("arm"));
+    assert_eq!(
+        world.resource::<Setpoints>().get("arm/motor/velocity"),
+        Some(0.0)
+    );
+}
@@ -0,0 +1,74 @@
+//! The back-EMF of a DC motor gives its speed without an encoder, as well as its model is known.
+use digital_twin_playground::sensorless::{DcMotor, SensorlessSettings};
+
+const DT: f32 = 0.001;
+
+/// Estimates the speed of `motor` turning at `velocity` with `current`, both functions of the
+/// time, through an observer of `model`, returning the largest error over the last second of
+/// 2 s.
+fn largest_error(
+    motor: &DcMotor,
+    model: &DcMotor,
+    velocity: impl Fn(f32) -> f32,
+    current: impl Fn(f32) -> f32,
+) -> f32 {
+    let mut observer = model.observer(0.005);
+    let mut previous = current(0.0);
+    let mut error = 0.0f32;
+    for k in 1..=(2.0 / DT) as usize {
+        let time = k as f32 * DT;
+        let i = current(time);
+        let voltage = motor.voltage(i, (i - previous) / DT, velocity(time));
+        previous = i;
+        let estimate = observer.update(voltage, i, DT);
+        if time > 1.0 {
+            error = error.max((estimate - velocity(time)).abs());
+        }
+    }
+    error
+}
+
+#[test]
+fn known_motors_are_estimated() {
+    let motor = DcMotor::default();
+    let error = largest_error(&motor, &motor, |_| 10.0, |t| 2.0 * (3.0 * t).cos());
+    assert!(error < 1e-3, "{error}");
+
+    // The filter lags behind a changing speed.
+    let error = largest_error(
+        &motor,
+        &motor,
+        |t| 10.0 * (2.0 * t).sin(),
+        |t| 2.0 * (3.0 * t).cos(),
+    );
+    assert!(error < 0.15, "{error}");
+}
+
+#[test]
+fn resistance_errors_bias_the_estimate_under_load() {
+    let motor = DcMotor::default();
+    let model = DcMotor {
+        resistance: motor.resistance + 0.1,
+        ..motor.clone()
+    };
+    // The drop across the missing resistance over the back-EMF constant.
+    let bias = 0.1 * 2.0 / motor.torque_constant;
+    let error = largest_error(&motor, &model, |_| 10.0, |_| 2.0);
+    assert!((error - bias).abs() < 1e-3, "{error} vs {bias}");
+    let error = largest_error(&motor, &model, |_| 10.0, |_| 0.0);
+    assert!(error < 1e-3, "{error}");
+}
+
+#[test]
+fn currents_are_read_to_the_resolution_of_the_sensor() {
+    let settings = SensorlessSettings {
+        current_resolution: 0.1,
+        ..Default::default()
+    };
+    assert!((settings.measured_current(1.234) - 1.2).abs() < 1e-6);
+    assert!((settings.measured_current(-0.06) + 0.1).abs() < 1e-6);
+
+    let motor = DcMotor::default();
+    assert!((motor.current(1.0) - 2.0).abs() < 1e-6);
+    assert!((motor.voltage(2.0, 0.0, 10.0) - 7.4).abs() < 1e-5);
+}
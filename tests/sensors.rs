@@ -0,0 +1,205 @@
+//! Encoders quantize the joint angles, disturb them with noise and deliver them at their rate,
+//! after their latency. IMUs measure the motion of their link in its frame.
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::math::{Quat, Vec3};
+use digital_twin_playground::sensors::{Encoder, EncoderSettings, Imu, ImuSettings};
+
+fn encoder(settings: EncoderSettings) -> Encoder {
+    Encoder::new(settings, "encoder/motor".to_string(), 1)
+}
+
+fn assert_reading(reading: Option<f32>, expected: Option<f32>) {
+    match (reading, expected) {
+        (Some(reading), Some(expected)) => {
+            assert!((reading - expected).abs() < 1e-5, "{reading} != {expected}")
+        }
+        _ => assert_eq!(reading, expected),
+    }
+}
+
+#[test]
+fn encoders_quantize_the_angle() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 8,
+        ..Default::default()
+    });
+    assert_eq!(encoder.resolution(), TAU / 8.0);
+    assert_reading(encoder.measure(0.0, 0.9), Some(TAU / 8.0));
+    assert_reading(encoder.measure(0.01, -0.1), Some(-TAU / 8.0));
+
+    let mut exact = self::encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        ..Default::default()
+    });
+    assert_reading(exact.measure(0.0, 0.123), Some(0.123));
+}
+
+#[test]
+fn encoder_noise_has_the_configured_deviation() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        noise: 0.01,
+        ..Default::default()
+    });
+    let samples: Vec<f32> = (0..10_000)
+        .filter_map(|step| encoder.measure(step as f32 * 0.001, 0.0))
+        .collect();
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    let deviation =
+        (samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / samples.len() as f32).sqrt();
+    assert!(mean.abs() < 0.001, "mean {mean}");
+    assert!((deviation - 0.01).abs() < 0.001, "deviation {deviation}");
+}
+
+#[test]
+fn encoders_hold_their_reading_between_samples() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        rate: 10.0,
+        ..Default::default()
+    });
+    assert_reading(encoder.measure(0.0, 0.0), Some(0.0));
+    assert_reading(encoder.measure(0.05, 0.5), Some(0.0));
+    assert_reading(encoder.measure(0.1, 1.0), Some(1.0));
+    assert_reading(encoder.measure(0.15, 1.5), Some(1.0));
+}
+
+#[test]
+fn encoders_deliver_their_samples_after_the_latency() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        latency: 0.02,
+        ..Default::default()
+    });
+    assert_reading(encoder.measure(0.0, 0.0), None);
+    assert_reading(encoder.measure(0.01, 0.1), None);
+    assert_reading(encoder.measure(0.02, 0.2), Some(0.0));
+    assert_reading(encoder.measure(0.03, 0.3), Some(0.1));
+
+    // Rewinding starts the encoder over.
+    assert_reading(encoder.measure(0.0, 0.0), None);
+}
+
+const GRAVITY: Vec3 = Vec3::new(0.0, -9.81, 0.0);
+
+#[test]
+fn imus_measure_in_the_frame_of_their_link() {
+    let mut imu = Imu::new(ImuSettings::default(), "imu/arm".to_string(), 1);
+    // The link turns about the vertical, its x axis pointing up.
+    let rotation = Quat::from_rotation_z(FRAC_PI_2);
+    let spin = Vec3::Y;
+    assert_eq!(imu.measure(0.0, rotation, spin, Vec3::ZERO, GRAVITY), None);
+    let reading = imu
+        .measure(0.01, rotation, spin, Vec3::ZERO, GRAVITY)
+        .unwrap();
+    assert!(reading.angular_velocity.abs_diff_eq(Vec3::X, 1e-5));
+    assert!(reading
+        .specific_force
+        .abs_diff_eq(Vec3::new(9.81, 0.0, 0.0), 1e-4));
+
+    // Accelerating along x, without gravity.
+    let mut imu = Imu::new(ImuSettings::default(), "imu/arm".to_string(), 1);
+    imu.measure(0.0, Quat::IDENTITY, Vec3::ZERO, Vec3::ZERO, Vec3::ZERO);
+    let reading = imu
+        .measure(0.1, Quat::IDENTITY, Vec3::ZERO, Vec3::X, Vec3::ZERO)
+        .unwrap();
+    assert!(reading
+        .specific_force
+        .abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-4));
+}
+
+#[test]
+fn imu_readings_have_a_bias_and_noise() {
+    let settings = ImuSettings {
+        gyroscope_bias: Vec3::new(0.01, 0.0, -0.02),
+        accelerometer_noise_density: 0.01,
+        ..Default::default()
+    };
+    let mut imu = Imu::new(settings, "imu/arm".to_string(), 1);
+    let readings: Vec<_> = (0..10_000)
+        .filter_map(|step| {
+            imu.measure(
+                step as f32 * 0.01,
+                Quat::IDENTITY,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Vec3::ZERO,
+            )
+        })
+        .collect();
+    assert!(readings
+        .iter()
+        .all(|reading| reading.angular_velocity == Vec3::new(0.01, 0.0, -0.02)));
+    // A density of 0.01 over a bandwidth of 100 Hz.
+    let forces: Vec<f32> = readings
+        .iter()
+        .map(|reading| reading.specific_force.x)
+        .collect();
+    let mean = forces.iter().sum::<f32>() / forces.len() as f32;
+    let deviation =
+        (forces.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / forces.len() as f32).sqrt();
+    assert!(mean.abs() < 0.01, "mean {mean}");
+    assert!((deviation - 0.1).abs() < 0.01, "deviation {deviation}");
+}
+
+#[test]
+fn encoders_read_through_their_mounting_offset() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        mounting_offset: 0.1,
+        ..Default::default()
+    });
+    assert_reading(encoder.measure(0.0, 0.5), Some(0.6));
+    assert_eq!(
+        encoder.settings.ideal_mounting(),
+        EncoderSettings {
+            counts_per_revolution: 0,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn encoder_slips_add_up() {
+    let mut encoder = encoder(EncoderSettings {
+        counts_per_revolution: 0,
+        slip_rate: 10.0,
+        slip_angle: 0.05,
+        ..Default::default()
+    });
+    let mut slips = 0;
+    let mut last = 0.0;
+    for step in 0..10_000 {
+        let reading = encoder.measure(step as f32 * 0.001, 0.0).unwrap();
+        assert!((reading - encoder.slip()).abs() < 1e-6);
+        if reading != last {
+            slips += 1;
+            last = reading;
+        }
+    }
+    // About 10 slips per second, for 10 s.
+    assert!((70..130).contains(&slips), "{slips} slips");
+
+    // Rewinding starts the encoder over.
+    assert_reading(encoder.measure(0.0, 0.0), Some(0.0));
+    assert_eq!(encoder.slip(), 0.0);
+}
+
+#[test]
+fn misaligned_imus_measure_in_their_own_frame() {
+    let settings = ImuSettings {
+        misalignment: Vec3::new(0.0, 0.0, FRAC_PI_2),
+        ..Default::default()
+    };
+    let mut imu = Imu::new(settings, "imu/arm".to_string(), 1);
+    imu.measure(0.0, Quat::IDENTITY, Vec3::X, Vec3::ZERO, GRAVITY);
+    let reading = imu
+        .measure(0.01, Quat::IDENTITY, Vec3::X, Vec3::ZERO, GRAVITY)
+        .unwrap();
+    // Turned a quarter turn about z, the IMU sees the x axis of the link as its -y axis.
+    assert!(reading.angular_velocity.abs_diff_eq(-Vec3::Y, 1e-5));
+    assert!(reading
+        .specific_force
+        .abs_diff_eq(Vec3::new(9.81, 0.0, 0.0), 1e-4));
+}
@@ -0,0 +1,41 @@
+//! Frames exchanged with the firmware of the hardware-in-the-loop bridge.
+use std::f32::consts::PI;
+
+use digital_twin_playground::serial_bridge::{
+    self, crc8, Frame, FrameDecoder, ENCODER_FRAME, SYNC,
+};
+
+#[test]
+fn encoder_frames_carry_the_counts() {
+    let bytes = Frame::encoders(7, &[1024, -1]).encode();
+    assert_eq!(
+        bytes[..4],
+        [SYNC, ENCODER_FRAME, 7, 8],
+        "sync, kind, sequence number and length"
+    );
+    assert_eq!(bytes[4..8], 1024i32.to_le_bytes());
+    assert_eq!(bytes[8..12], (-1i32).to_le_bytes());
+    assert_eq!(bytes[12], crc8(&bytes[1..12]));
+    // The check value of CRC-8 with the polynomial 0x07.
+    assert_eq!(crc8(b"123456789"), 0xF4);
+    assert_eq!(serial_bridge::counts(PI / 2.0, 4096), 1024);
+    assert_eq!(serial_bridge::counts(-2.0 * PI, 4096), -4096);
+}
+
+#[test]
+fn torque_frames_are_found_in_a_noisy_stream() {
+    let mut decoder = FrameDecoder::default();
+    let frame = Frame::torque(3, 0.25).encode();
+    let mut corrupted = Frame::torque(4, 1.0).encode();
+    corrupted[5] ^= 0x10;
+
+    // Garbage, a corrupted frame, then a valid one split over two reads.
+    let mut stream = vec![0x00, SYNC, 0x42];
+    stream.extend(&corrupted);
+    stream.extend(&frame[..3]);
+    assert!(decoder.push(&stream).is_empty());
+    let frames = decoder.push(&frame[3..]);
+    assert_eq!(frames, [Frame::torque(3, 0.25)]);
+    assert_eq!(frames[0].torque_value(), Some(0.25));
+    assert_eq!(Frame::encoders(3, &[0]).torque_value(), None);
+}
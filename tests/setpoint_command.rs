@@ -0,0 +1,55 @@
+//! The signal generator of the setpoint command panel.
+use digital_twin_playground::setpoint_command::{GeneratorKind, SignalGenerator};
+
+fn generator(kind: GeneratorKind) -> SignalGenerator {
+    SignalGenerator {
+        kind,
+        amplitude: 2.0,
+        offset: 1.0,
+        frequency: 0.5,
+        end_frequency: 2.5,
+        duration: 4.0,
+        delay: 1.0,
+    }
+}
+
+fn approx(value: Option<f32>, expected: f32) -> bool {
+    value.is_some_and(|value| (value - expected).abs() < 1e-4)
+}
+
+#[test]
+fn periodic_signals_repeat_around_the_offset() {
+    let step = generator(GeneratorKind::Step);
+    assert_eq!(step.value(0.5), Some(1.0));
+    assert_eq!(step.value(1.0), Some(3.0));
+    assert_eq!(step.value(100.0), Some(3.0));
+
+    let square = generator(GeneratorKind::Square);
+    assert_eq!(square.value(0.5), Some(3.0));
+    assert_eq!(square.value(1.5), Some(-1.0));
+    assert_eq!(square.value(2.5), Some(3.0));
+
+    let sine = generator(GeneratorKind::Sine);
+    assert!(approx(sine.value(0.0), 1.0));
+    assert!(approx(sine.value(0.5), 3.0));
+    assert!(approx(sine.value(1.5), -1.0));
+
+    let ramp = generator(GeneratorKind::Ramp);
+    assert!(approx(ramp.value(0.0), 1.0));
+    assert!(approx(ramp.value(1.0), 2.0));
+    assert!(approx(ramp.value(2.5), 1.5));
+}
+
+#[test]
+fn chirps_sweep_their_frequencies_then_stop() {
+    let chirp = generator(GeneratorKind::Chirp);
+    // Half a cycle of 0.5 Hz at first: 0.5 t + 0.25 t² cycles.
+    assert!(approx(chirp.value(0.0), 1.0));
+    assert!(approx(
+        chirp.value(2.0),
+        1.0 + 2.0 * (std::f32::consts::TAU * 2.0).sin()
+    ));
+    let late = (0.5 * 3.5 + 0.25 * 3.5 * 3.5) * std::f32::consts::TAU;
+    assert!(approx(chirp.value(3.5), 1.0 + 2.0 * late.sin()));
+    assert_eq!(chirp.value(4.0), None);
+}
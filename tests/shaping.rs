@@ -0,0 +1,102 @@
+//! Input shapers cancel the vibration of the mode they are tuned to, and the resonance of a free
+//! response is recovered from its peaks.
+use digital_twin_playground::{
+    compliant_axis,
+    control::{InputShaper, Shaper},
+    headless::DEFAULT_TIME_STEP,
+    shaping::{self, estimate_resonance, residual_vibration, ShapingExperiment},
+};
+
+const DT: f32 = 1.0 / 600.0;
+const FREQUENCY: f32 = 1.0;
+const DAMPING: f32 = 0.02;
+
+/// Position of a lightly damped mass-spring with unit static gain, stepped by `reference`.
+fn respond(reference: impl Fn(usize) -> f32, steps: usize) -> Vec<f32> {
+    let omega = 2.0 * std::f32::consts::PI * FREQUENCY;
+    let (mut position, mut velocity) = (0.0f32, 0.0f32);
+    (0..steps)
+        .map(|step| {
+            let acceleration =
+                omega * omega * (reference(step) - position) - 2.0 * DAMPING * omega * velocity;
+            velocity += acceleration * DT;
+            position += velocity * DT;
+            position
+        })
+        .collect()
+}
+
+#[test]
+fn impulses_sum_to_one() {
+    for shaper in Shaper::ALL {
+        let shaper = InputShaper::new(shaper, FREQUENCY, DAMPING, DT);
+        let sum: f32 = shaper
+            .impulses()
+            .iter()
+            .map(|(_, amplitude)| amplitude)
+            .sum();
+        assert!((sum - 1.0).abs() < 1e-6, "{sum}");
+        assert_eq!(shaper.impulses()[0].0, 0);
+    }
+    // Half a damped period between the two impulses of the ZV shaper.
+    let zv = InputShaper::new(Shaper::Zv, FREQUENCY, 0.0, DT);
+    assert_eq!(zv.impulses()[1].0, 300);
+}
+
+#[test]
+fn shapers_suppress_the_residual_vibration() {
+    let steps = 6_000;
+    let settled = 1_200;
+    let unshaped = respond(|_| 1.0, steps);
+    let unshaped = residual_vibration(&unshaped[settled..]);
+    for shaper in Shaper::ALL {
+        let mut input_shaper = InputShaper::new(shaper, FREQUENCY, DAMPING, DT);
+        let references = (0..steps)
+            .map(|_| input_shaper.update(1.0))
+            .collect::<Vec<_>>();
+        let shaped = respond(|step| references[step], steps);
+        let shaped = residual_vibration(&shaped[settled..]);
+        // The extra-insensitive shaper lets 5 % through by design.
+        assert!(
+            shaped < 0.06 * unshaped,
+            "{}: {shaped} / {unshaped}",
+            shaper.name()
+        );
+    }
+}
+
+#[test]
+fn resonance_is_recovered_from_a_free_response() {
+    let response = respond(|_| 1.0, 6_000);
+    let resonance = estimate_resonance(&response, DT).unwrap();
+    assert!(
+        (resonance.frequency - FREQUENCY).abs() < 0.02,
+        "{resonance:?}"
+    );
+    assert!((resonance.damping - DAMPING).abs() < 0.005, "{resonance:?}");
+}
+
+#[test]
+fn shapers_quiet_the_shaft_of_the_compliant_axis() {
+    let comparison = shaping::compare(
+        &compliant_axis::plant(),
+        &ShapingExperiment::default(),
+        DEFAULT_TIME_STEP,
+    )
+    .unwrap();
+    // The geared motor against the arm, through the stiffness of the shaft.
+    let resonance = comparison.resonance;
+    assert!((2.0..3.5).contains(&resonance.frequency), "{resonance:?}");
+    let [unshaped, shaped @ ..] = &comparison.steps[..] else {
+        panic!("no step");
+    };
+    for step in shaped {
+        assert!(
+            step.residual < 0.5 * unshaped.residual,
+            "{:?}: {} / {}",
+            step.shaper,
+            step.residual,
+            unshaped.residual
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! The simulation thread steps in real time and sends a snapshot of every step, whatever the
+//! window does.
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use digital_twin_playground::{
+    plants::Plant,
+    sim_thread::{SimCommand, SimEvent, SimThread},
+};
+
+const DT: f32 = 0.01;
+
+/// A plant without bodies.
+fn empty() -> Plant {
+    Plant {
+        name: "empty",
+        add: |_| {},
+        compose: |_, _| {},
+        spawn: |_| {},
+    }
+}
+
+/// The ticks of the snapshots received within `duration`.
+fn ticks(sim: &mut SimThread, duration: Duration) -> Vec<u64> {
+    let start = Instant::now();
+    let mut ticks = Vec::new();
+    while start.elapsed() < duration {
+        for event in sim.receive() {
+            match event {
+                SimEvent::Snapshot(snapshot) => {
+                    let elapsed = snapshot.elapsed.as_secs_f32();
+                    assert!((elapsed - DT * snapshot.tick as f32).abs() < 1e-4);
+                    ticks.push(snapshot.tick);
+                }
+                SimEvent::Stopped(reason) => panic!("{reason}"),
+            }
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    ticks
+}
+
+#[test]
+fn steps_are_paced_and_sent_in_order() {
+    let mut sim = SimThread::spawn(empty(), DT, 1);
+    // A frame stalled for 300 ms doesn't stall the steps: they are all sent when it ends.
+    thread::sleep(Duration::from_millis(300));
+    let ticks = ticks(&mut sim, Duration::from_millis(200));
+    assert!(
+        ticks.windows(2).all(|pair| pair[1] == pair[0] + 1),
+        "{ticks:?}"
+    );
+    let last = *ticks.last().unwrap();
+    // At most 50 steps in 500 ms, fewer with the startup of the application.
+    assert!((10..=55).contains(&last), "{last}");
+
+    sim.send(SimCommand::Pause(true));
+    thread::sleep(Duration::from_millis(50));
+    sim.receive();
+    let paused = ticks(&mut sim, Duration::from_millis(100));
+    let paused_at = *paused.last().unwrap();
+    assert!(paused.iter().all(|tick| *tick == paused_at), "{paused:?}");
+    sim.send(SimCommand::Pause(false));
+    assert!(ticks(&mut sim, Duration::from_millis(100)).last() > Some(&paused_at));
+}
@@ -0,0 +1,105 @@
+//! Motors are checked against the torque and the speed a trajectory takes.
+use digital_twin_playground::{
+    actuators::DcActuator,
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    sizing::{self, Datasheet, Envelope, SizingSettings, Waypoint},
+};
+
+fn datasheet() -> Datasheet {
+    Datasheet {
+        name: "Test".to_string(),
+        motor: DcActuator {
+            stall_torque: 0.5,
+            no_load_speed: 500.0,
+            resistance: 1.2,
+            gear_ratio: 10.0,
+            efficiency: 0.9,
+            current_limit: 0.0,
+            ..Default::default()
+        },
+        continuous_current: 0.0,
+    }
+}
+
+fn envelope(points: Vec<[f32; 2]>) -> Envelope {
+    Envelope {
+        joint: "Motor".to_string(),
+        points,
+        dt: 0.01,
+    }
+}
+
+#[test]
+fn trajectories_join_the_waypoints() {
+    let settings = SizingSettings {
+        smoothing: 0.0,
+        ..Default::default()
+    };
+    let trajectory = settings.trajectory(0.01).unwrap();
+    assert_eq!(trajectory.len(), 201);
+    let (positions, _) = trajectory.sample(0.5);
+    assert!((positions[0] - std::f32::consts::FRAC_PI_4).abs() < 1e-3);
+
+    let mut settings = SizingSettings::default();
+    settings.waypoints.push(Waypoint {
+        time: 1.5,
+        positions: vec![0.0],
+    });
+    assert!(settings.trajectory(0.01).is_err());
+    settings.waypoints.pop();
+    settings.waypoints[1].positions.push(0.0);
+    assert!(settings.trajectory(0.01).is_err());
+}
+
+#[test]
+fn motors_must_cover_the_envelope() {
+    // Half the stall torque through the gearbox at half the no-load speed is on the curve.
+    let motion = envelope(vec![[0.0, 1.0], [25.0, 2.0], [-10.0, -1.5]]);
+    assert_eq!(motion.peak_torque(), 2.0);
+    assert_eq!(motion.peak_speed(), 25.0);
+    assert_eq!(motion.peak_power(), 50.0);
+    let verdict = sizing::check(&datasheet(), &motion, 1.0);
+    assert!(verdict.fits(), "{verdict:?}");
+
+    // With a margin, the fast point is out of reach.
+    let verdict = sizing::check(&datasheet(), &motion, 1.2);
+    assert_eq!(verdict.shortfalls.len(), 1, "{verdict:?}");
+
+    let fast = envelope(vec![[60.0, 0.0]]);
+    assert!(!sizing::check(&datasheet(), &fast, 1.0).fits());
+
+    let rated = Datasheet {
+        continuous_current: 1.0,
+        ..datasheet()
+    };
+    let verdict = sizing::check(&rated, &motion, 1.0);
+    assert_eq!(verdict.shortfalls.len(), 1, "{verdict:?}");
+    assert!(verdict.shortfalls[0].contains("RMS current"));
+}
+
+#[test]
+fn sizings_report_every_motor_on_every_joint() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let settings = SizingSettings::default();
+    let report = sizing::run(&plant, &settings, DEFAULT_TIME_STEP).unwrap();
+    let [envelope] = report.envelopes.as_slice() else {
+        panic!("one envelope per joint");
+    };
+    assert!(
+        envelope.points.len() > 100,
+        "{} points",
+        envelope.points.len()
+    );
+    assert!(envelope.peak_speed() > 0.5, "{envelope:?}");
+    assert_eq!(report.verdicts.len(), settings.motors.len());
+
+    let path = std::env::temp_dir().join("sizing").join("envelopes.csv");
+    report.write_csv(&path).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(csv.lines().next(), Some("joint,time,speed,torque,power"));
+    assert_eq!(csv.lines().count(), envelope.points.len() + 1);
+    std::fs::remove_file(path).unwrap();
+}
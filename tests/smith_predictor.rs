@@ -0,0 +1,106 @@
+//! A Smith predictor keeps a loop tuned for the plant alone stable behind a dead time that
+//! destabilizes the same controller closed on the measurement.
+use std::collections::VecDeque;
+
+use digital_twin_playground::control::{Pid, PidGains, Saturation, SmithPredictor};
+
+const DT: f32 = 0.01;
+const GAIN: f32 = 2.0;
+const TIME_CONSTANT: f32 = 0.5;
+const DEAD_TIME: f32 = 0.3;
+
+/// A first-order lag behind a dead time.
+struct DelayedLag {
+    output: f32,
+    inputs: VecDeque<f32>,
+}
+
+impl DelayedLag {
+    fn new() -> Self {
+        Self {
+            output: 0.0,
+            inputs: VecDeque::from(vec![0.0; (DEAD_TIME / DT).round() as usize]),
+        }
+    }
+
+    fn step(&mut self, input: f32) -> f32 {
+        self.inputs.push_back(input);
+        let input = self.inputs.pop_front().unwrap();
+        let decay = (-DT / TIME_CONSTANT).exp();
+        self.output = decay * self.output + (1.0 - decay) * GAIN * input;
+        self.output
+    }
+}
+
+fn controller() -> Pid {
+    Pid::new(
+        PidGains {
+            kp: 2.0,
+            ki: 4.0,
+            kd: 0.0,
+        },
+        Saturation::symmetric(10.0),
+    )
+}
+
+/// Runs a unit step for 10 s and returns the peak and the final output.
+fn step_response(mut update: impl FnMut(f32) -> f32) -> (f32, f32) {
+    let mut plant = DelayedLag::new();
+    let mut output = 0.0f32;
+    let mut peak = 0.0f32;
+    for _ in 0..(10.0 / DT) as usize {
+        output = plant.step(update(output));
+        peak = peak.max(output);
+    }
+    (peak, output)
+}
+
+#[test]
+fn predictor_compensates_the_dead_time() {
+    let mut predictor = SmithPredictor::new(controller(), GAIN, TIME_CONSTANT, DEAD_TIME, DT);
+    assert_eq!(predictor.dead_time(), 30);
+    let (peak, output) = step_response(|measurement| predictor.update(1.0, measurement, DT));
+    assert!(peak < 1.01, "overshoot to {peak}");
+    assert!((output - 1.0).abs() < 1e-3, "{output}");
+}
+
+#[test]
+fn dead_time_destabilizes_the_plain_loop() {
+    let mut pid = controller();
+    let (peak, _) = step_response(|measurement| pid.update(1.0, measurement, DT));
+    assert!(peak > 2.0, "peak of {peak}");
+}
+
+#[test]
+fn without_dead_time_the_predictor_is_the_controller() {
+    let mut predictor = SmithPredictor::new(controller(), GAIN, TIME_CONSTANT, 0.0, DT);
+    let mut pid = controller();
+    for measurement in [0.0, 0.3, 0.8, 1.1] {
+        assert_eq!(
+            predictor.update(1.0, measurement, DT),
+            pid.update(1.0, measurement, DT)
+        );
+    }
+}
+
+#[test]
+fn predictor_compensates_the_dead_time_of_an_integrator() {
+    let proportional = Pid::new(
+        PidGains {
+            kp: 4.0,
+            ki: 0.0,
+            kd: 0.0,
+        },
+        Saturation::symmetric(10.0),
+    );
+    let mut predictor = SmithPredictor::new(proportional, 1.0, f32::INFINITY, DEAD_TIME, DT);
+    let mut inputs = VecDeque::from(vec![0.0; predictor.dead_time()]);
+    let (mut output, mut peak) = (0.0f32, 0.0f32);
+    for _ in 0..(10.0 / DT) as usize {
+        inputs.push_back(predictor.update(1.0, output, DT));
+        output += inputs.pop_front().unwrap() * DT;
+        peak = peak.max(output);
+    }
+    assert!(peak < 1.01, "overshoot to {peak}");
+    assert!((output - 1.0).abs() < 1e-3, "{output}");
+}
@@ -0,0 +1,166 @@
+//! Snapshots bring back the bodies, the clock, the setpoints and the state of the controllers,
+//! and the scene can be reset to its spawned state.
+use std::{fs, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    clock::SimClock,
+    control::{Pid, PidGains, Saturation},
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    pid_controller::{PidController, PidControllerPlugin},
+    plants,
+    setpoints::Setpoints,
+    snapshots::{self, Snapshot, Snapshots, SnapshotsPlugin},
+    telemetry::Telemetry,
+};
+
+fn world() -> World {
+    let mut world = World::new();
+    world.insert_resource(SimClock::default());
+    world.init_resource::<Setpoints>();
+    world.init_resource::<Telemetry>();
+    for x in [0.0, 1.0] {
+        world.spawn((
+            RigidBody::Dynamic,
+            Transform::from_xyz(x, 0.0, 0.0),
+            Velocity::default(),
+        ));
+    }
+    world.spawn((RigidBody::Fixed, Transform::default()));
+    let gains = PidGains {
+        kp: 1.0,
+        ki: 10.0,
+        kd: 0.0,
+    };
+    let pid = Pid::new(gains, Saturation::default());
+    world.spawn(PidController::new(pid, ""));
+    world
+}
+
+fn controller(world: &mut World) -> PidController {
+    world.query::<&PidController>().single(world).clone()
+}
+
+#[test]
+fn snapshots_rewind_the_simulation() {
+    let mut world = world();
+    world
+        .resource_mut::<SimClock>()
+        .restore(100, Duration::from_secs(1));
+    world.resource_mut::<Setpoints>().set("motor/position", 1.0);
+    world
+        .query::<&mut PidController>()
+        .single_mut(&mut world)
+        .update(1.0, 0.0, 0.1);
+    let snapshot = snapshots::capture(&mut world);
+    assert_eq!(snapshot.tick, 100);
+    assert_eq!(snapshot.bodies.len(), 2);
+    assert_eq!(snapshot.controllers.len(), 1);
+    let integral = controller(&mut world).pid.integral();
+    assert!(integral > 0.0);
+
+    // The simulation goes on, and the gains are tuned.
+    world
+        .resource_mut::<SimClock>()
+        .restore(200, Duration::from_secs(2));
+    world
+        .resource_mut::<Setpoints>()
+        .set("motor/position", -1.0);
+    world
+        .resource_mut::<Telemetry>()
+        .record("motor/angle", 1.5, 0.0);
+    for (mut transform, mut velocity) in world
+        .query::<(&mut Transform, &mut Velocity)>()
+        .iter_mut(&mut world)
+    {
+        transform.translation.y = 3.0;
+        velocity.angvel = Vec3::X;
+    }
+    {
+        let mut controllers = world.query::<&mut PidController>();
+        let mut controller = controllers.single_mut(&mut world);
+        controller.update(-1.0, 0.0, 0.1);
+        controller.pid.gains.kp = 4.0;
+    }
+
+    snapshots::restore(&mut world, &snapshot).unwrap();
+    assert_eq!(snapshots::capture(&mut world).bodies, snapshot.bodies);
+    let clock = world.resource::<SimClock>();
+    assert_eq!(
+        (clock.tick(), clock.elapsed()),
+        (100, Duration::from_secs(1))
+    );
+    assert_eq!(
+        world.resource::<Setpoints>().get("motor/position"),
+        Some(1.0)
+    );
+    assert!(world.resource::<Telemetry>().is_empty());
+    let restored = controller(&mut world);
+    assert_eq!(restored.pid.integral(), integral);
+    assert_eq!(restored.pid.gains.kp, 4.0);
+
+    // Without a saved state, the controllers start over.
+    let empty = Snapshot {
+        controllers: Vec::new(),
+        ..snapshot
+    };
+    snapshots::restore(&mut world, &empty).unwrap();
+    assert_eq!(controller(&mut world).pid.integral(), 0.0);
+}
+
+#[test]
+fn snapshots_of_other_scenes_are_rejected() {
+    let mut world = world();
+    let mut snapshot = snapshots::capture(&mut world);
+    snapshot.bodies.pop();
+    snapshot.tick = 50;
+    assert!(snapshots::restore(&mut world, &snapshot).is_err());
+    assert_eq!(world.resource::<SimClock>().tick(), 0);
+}
+
+#[test]
+fn snapshots_are_saved_and_loaded() {
+    let mut world = world();
+    world.resource_mut::<Setpoints>().set("motor/velocity", 2.0);
+    let snapshot = snapshots::capture(&mut world);
+    let path = std::env::temp_dir()
+        .join("snapshots")
+        .join("snapshot-0.json");
+    snapshot.write(&path).unwrap();
+    let loaded = Snapshot::read(&path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(loaded.bodies, snapshot.bodies);
+    assert_eq!(loaded.setpoints, snapshot.setpoints);
+    snapshots::restore(&mut world, &loaded).unwrap();
+}
+
+#[test]
+fn scenes_are_reset_to_their_spawned_state() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    (plant.add)(&mut app);
+    app.add_plugins((PidControllerPlugin, SnapshotsPlugin));
+    app.finish();
+    app.cleanup();
+    for _ in 0..5 {
+        app.update();
+    }
+    let initial = app.world().resource::<Snapshots>().initial.clone();
+    let initial = initial.expect("the spawned state is kept");
+    assert_eq!(initial.tick, 0);
+    assert!(!initial.bodies.is_empty());
+
+    app.world_mut()
+        .resource_mut::<Setpoints>()
+        .set("motor/velocity", 5.0);
+    for _ in 0..60 {
+        app.update();
+    }
+    assert!(app.world().resource::<SimClock>().tick() > 0);
+    snapshots::reset(app.world_mut());
+    assert_eq!(app.world().resource::<SimClock>().tick(), 0);
+    assert_eq!(snapshots::capture(app.world_mut()).bodies, initial.bodies);
+}
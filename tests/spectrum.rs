@@ -0,0 +1,61 @@
+//! The spectrum of the telemetry locates the resonance ringing in a channel.
+use std::f32::consts::TAU;
+
+use digital_twin_playground::{
+    governor::Notch,
+    spectrum::{Spectrum, MIN_SAMPLES},
+    telemetry::Sample,
+};
+
+/// A mode at `frequency` Hz with `damping` ringing down from a unit deflection, on a drift,
+/// sampled at 60 Hz for `duration` seconds.
+fn ring_down(frequency: f32, damping: f32, duration: f32) -> Vec<Sample> {
+    let omega = TAU * frequency;
+    let damped = omega * (1.0 - damping * damping).sqrt();
+    (0..(duration * 60.0) as usize)
+        .map(|step| {
+            let time = step as f32 / 60.0;
+            let mode = (-damping * omega * time).exp() * (damped * time).cos();
+            [time, mode + 0.3 * time]
+        })
+        .collect()
+}
+
+#[test]
+fn the_ringing_mode_is_the_dominant_resonance() {
+    let spectrum = Spectrum::of(&ring_down(2.0, 0.02, 20.0)).unwrap();
+    let resonance = spectrum.dominant_resonance().unwrap();
+    assert!((resonance.frequency - 2.0).abs() < 0.01, "{resonance:?}");
+    assert!((resonance.damping - 0.02).abs() < 0.004, "{resonance:?}");
+
+    let notch = Notch::at(&resonance);
+    assert_eq!(notch.frequency, resonance.frequency);
+    assert_eq!(notch.pole_damping, Notch::POLE_DAMPING);
+    assert!(notch.filter(1.0 / 60.0).is_ok());
+}
+
+#[test]
+fn short_records_bound_the_damping_from_below() {
+    // A lighter mode than 20 s can resolve reads as damped, which widens the notch.
+    let spectrum = Spectrum::of(&ring_down(2.0, 0.001, 20.0)).unwrap();
+    let resonance = spectrum.dominant_resonance().unwrap();
+    assert!((resonance.frequency - 2.0).abs() < 0.01, "{resonance:?}");
+    assert!(resonance.damping > 0.001, "{resonance:?}");
+}
+
+#[test]
+fn channels_without_a_resonance_have_none() {
+    // A first-order response, its spectrum falling from the lowest frequencies.
+    let settling = (0..600)
+        .map(|step| {
+            let time = step as f32 / 60.0;
+            [time, 1.0 - (-time / 0.5).exp()]
+        })
+        .collect::<Vec<_>>();
+    let spectrum = Spectrum::of(&settling).unwrap();
+    assert_eq!(spectrum.dominant_resonance(), None);
+
+    assert_eq!(Spectrum::of(&settling[..MIN_SAMPLES - 1]), None);
+    let frozen = vec![[1.0, 0.5]; MIN_SAMPLES];
+    assert_eq!(Spectrum::of(&frozen), None);
+}
@@ -0,0 +1,55 @@
+//! The traces of the state machines record their transitions.
+use digital_twin_playground::state_machines::{
+    StateMachines, TransitionRecord, CIA_402, HISTORY_LEN, NMT, TEACH_SEQUENCER,
+};
+
+#[test]
+fn transitions_are_recorded() {
+    let mut machines = StateMachines::default();
+    machines.observe(&TEACH_SEQUENCER, 0, 0.0);
+    machines.observe(&TEACH_SEQUENCER, 0, 0.1);
+    machines.observe(&TEACH_SEQUENCER, 1, 0.2);
+    machines.observe(&CIA_402, 0, 0.2);
+    machines.observe(&TEACH_SEQUENCER, 2, 0.3);
+
+    assert_eq!(machines.0.len(), 2);
+    let teach = &machines.0[0];
+    assert_eq!(teach.active, Some(2));
+    assert_eq!(
+        Vec::from(teach.history.clone()),
+        [
+            TransitionRecord {
+                time: 0.2,
+                from: 0,
+                to: 1
+            },
+            TransitionRecord {
+                time: 0.3,
+                from: 1,
+                to: 2
+            },
+        ]
+    );
+    assert!(machines.0[1].history.is_empty());
+}
+
+#[test]
+fn history_keeps_the_last_transitions() {
+    let mut machines = StateMachines::default();
+    for step in 0..2 * HISTORY_LEN {
+        machines.observe(&TEACH_SEQUENCER, step % 2, step as f32);
+    }
+    let history = &machines.0[0].history;
+    assert_eq!(history.len(), HISTORY_LEN);
+    assert_eq!(history.back().unwrap().time, (2 * HISTORY_LEN - 1) as f32);
+}
+
+#[test]
+fn transitions_link_existing_states() {
+    for graph in [&TEACH_SEQUENCER, &NMT, &CIA_402] {
+        for &(from, to) in graph.transitions {
+            assert!(from < graph.states.len() && to < graph.states.len());
+            assert_ne!(from, to);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! The responses to the steps of a setpoint are analyzed and exported.
+use std::fs;
+
+use digital_twin_playground::{
+    step_response::{self, AnalyzedStep, Step, StepMetrics},
+    telemetry::Sample,
+};
+
+/// Response of an underdamped second-order system to a step from 1 to 3 at 10 s, sampled at
+/// 100 Hz for 5 s.
+fn response() -> Vec<Sample> {
+    let (damping, frequency) = (0.3_f32, 4.0_f32);
+    let damped = frequency * (1.0 - damping * damping).sqrt();
+    (0..=500)
+        .map(|index| {
+            let t = index as f32 * 0.01;
+            let decay = (-damping * frequency * t).exp();
+            let unit = 1.0
+                - decay * ((damped * t).cos() + damping * frequency / damped * (damped * t).sin());
+            [10.0 + t, 1.0 + 2.0 * unit]
+        })
+        .collect()
+}
+
+#[test]
+fn metrics_match_a_second_order_response() {
+    let metrics = StepMetrics::of(&response(), 10.0, 1.0, 3.0, 0.02).unwrap();
+    // 100 exp(-ζπ/√(1-ζ²)) % past the target, at π/ωd.
+    let expected = 100.0 * (-0.3 * std::f32::consts::PI / (1.0 - 0.09_f32).sqrt()).exp();
+    assert!((metrics.overshoot - expected).abs() < 0.5, "{metrics:?}");
+    let peak_time = std::f32::consts::PI / (4.0 * (1.0 - 0.09_f32).sqrt());
+    assert!((metrics.peak_time - peak_time).abs() < 0.02, "{metrics:?}");
+    let rise_time = metrics.rise_time.unwrap();
+    assert!(rise_time > 0.2 && rise_time < 0.5, "{metrics:?}");
+    // About 4 / (ζω) to settle within 2 %.
+    let settling_time = metrics.settling_time.unwrap();
+    assert!(settling_time > 2.0 && settling_time < 4.0, "{metrics:?}");
+    assert!(metrics.steady_state_error.abs() < 0.01, "{metrics:?}");
+
+    // Steps down are measured along the step.
+    let down: Vec<Sample> = response()
+        .iter()
+        .map(|[time, value]| [*time, 4.0 - value])
+        .collect();
+    let mirrored = StepMetrics::of(&down, 10.0, 3.0, 1.0, 0.02).unwrap();
+    assert!((mirrored.overshoot - metrics.overshoot).abs() < 1e-3);
+
+    // Short of the target, it neither rises nor settles.
+    let short: Vec<Sample> = (0..100).map(|index| [index as f32 * 0.01, 0.5]).collect();
+    let metrics = StepMetrics::of(&short, 0.0, 0.0, 1.0, 0.02).unwrap();
+    assert_eq!(metrics.rise_time, None);
+    assert_eq!(metrics.settling_time, None);
+    assert_eq!(metrics.overshoot, 0.0);
+    assert_eq!(metrics.steady_state_error, 0.5);
+    assert_eq!(StepMetrics::of(&short, 0.0, 1.0, 1.0, 0.02), None);
+    assert_eq!(StepMetrics::of(&[], 0.0, 0.0, 1.0, 0.02), None);
+}
+
+#[test]
+fn analyzed_steps_are_exported_with_their_metrics() {
+    let samples = response();
+    let analyzed = AnalyzedStep {
+        step: Step {
+            time: 10.0,
+            initial: 1.0,
+            target: 3.0,
+        },
+        metrics: StepMetrics::of(&samples, 10.0, 1.0, 3.0, 0.02),
+        samples,
+    };
+    let dir = std::env::temp_dir().join("step_response_export");
+    let path = step_response::export(&analyzed, "motor/angle", &dir).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("time,motor/angle,setpoint"));
+    assert_eq!(lines.next(), Some("0,1,3"));
+    assert_eq!(lines.count(), 500);
+    let json = fs::read_to_string(path.with_extension("json")).unwrap();
+    assert!(json.contains("\"overshoot\""));
+    assert!(json.contains("\"settling_time\""));
+    fs::remove_dir_all(dir).unwrap();
+}
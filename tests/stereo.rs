@@ -0,0 +1,16 @@
+//! The stereo view splits the window between the eyes.
+use bevy::prelude::*;
+use digital_twin_playground::stereo::{self, StereoLayout};
+
+#[test]
+fn each_eye_gets_half_of_the_window() {
+    let (left, right) = stereo::viewports(UVec2::new(1281, 720), StereoLayout::Parallel);
+    assert_eq!(left.physical_position, UVec2::ZERO);
+    assert_eq!(left.physical_size, UVec2::new(640, 720));
+    assert_eq!(right.physical_position, UVec2::new(640, 0));
+    assert_eq!(right.physical_size, UVec2::new(640, 720));
+
+    let (left, right) = stereo::viewports(UVec2::new(1281, 720), StereoLayout::CrossEyed);
+    assert_eq!(left.physical_position, UVec2::new(640, 0));
+    assert_eq!(right.physical_position, UVec2::ZERO);
+}
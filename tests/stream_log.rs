@@ -0,0 +1,109 @@
+//! Streamed logs describe their channels, read back frame by frame, and survive truncation.
+use std::{fs, io::Cursor};
+
+use digital_twin_playground::{
+    autosave,
+    stream_log::{self, ChannelSchema, Frame, Schema, StreamReader, StreamWriter, ValueType},
+    telemetry::Telemetry,
+};
+
+fn telemetry() -> Telemetry {
+    let mut telemetry = Telemetry::default();
+    for k in 0..100 {
+        let time = k as f32 / 60.0;
+        telemetry.record("motor/angle", time, time.sin());
+        telemetry.record("motor/torque", time, -time);
+    }
+    telemetry
+}
+
+#[test]
+fn logs_start_with_their_schema() {
+    let schema = Schema {
+        channels: vec![ChannelSchema::new(
+            "motor/angle",
+            ValueType::F32,
+            Some(60.0),
+        )],
+    };
+    let log = StreamWriter::new(Vec::new(), schema.clone())
+        .unwrap()
+        .into_inner();
+    assert_eq!(&log[..4], b"DTPL");
+    assert_eq!(&log[4..6], &stream_log::VERSION.to_le_bytes());
+    let json = br#"{"channels":[{"name":"motor/angle","type":"f32","rate":60.0}]}"#;
+    assert_eq!(&log[6..10], &(json.len() as u32).to_le_bytes());
+    assert_eq!(&log[10..], json);
+
+    let reader = StreamReader::new(Cursor::new(log)).unwrap();
+    assert_eq!(reader.channels(), schema.channels);
+    assert!(StreamReader::new(Cursor::new(b"{\"channels\":[]}".to_vec())).is_err());
+}
+
+#[test]
+fn telemetry_is_read_back() {
+    let mut writer = StreamWriter::new(Vec::new(), Schema::default()).unwrap();
+    writer.write_telemetry(&telemetry(), Some(60.0)).unwrap();
+    // Channels are declared once, even when written again.
+    writer.write_telemetry(&telemetry(), Some(60.0)).unwrap();
+    assert_eq!(writer.channels().len(), 2);
+    let log = writer.into_inner();
+
+    let mut twice = telemetry();
+    twice.extend(telemetry());
+    let reader = StreamReader::new(Cursor::new(log)).unwrap();
+    assert_eq!(reader.read_telemetry().unwrap(), twice);
+}
+
+#[test]
+fn values_keep_their_type() {
+    let mut writer = StreamWriter::new(Vec::new(), Schema::default()).unwrap();
+    let flag = writer
+        .add_channel(ChannelSchema::new("fault", ValueType::Bool, None))
+        .unwrap();
+    let count = writer
+        .add_channel(ChannelSchema::new("count", ValueType::I32, None))
+        .unwrap();
+    writer
+        .write_samples(flag, &[[0.0, 0.0], [1.0, 0.5]])
+        .unwrap();
+    writer.write_samples(count, &[[0.0, 2.6]]).unwrap();
+    assert!(writer.write_samples(7, &[[0.0, 1.0]]).is_err());
+
+    let mut reader = StreamReader::new(Cursor::new(writer.into_inner())).unwrap();
+    let frames: Vec<Frame> = std::iter::from_fn(|| reader.next_frame().unwrap()).collect();
+    assert_eq!(
+        frames[2],
+        Frame::Samples {
+            channel: flag,
+            samples: vec![[0.0, 0.0], [1.0, 1.0]]
+        }
+    );
+    assert_eq!(
+        frames[3],
+        Frame::Samples {
+            channel: count,
+            samples: vec![[0.0, 3.0]]
+        }
+    );
+}
+
+#[test]
+fn unknown_and_truncated_frames_are_skipped() {
+    let mut writer = StreamWriter::new(Vec::new(), Schema::default()).unwrap();
+    writer.write_telemetry(&telemetry(), None).unwrap();
+    let mut log = writer.into_inner();
+    // A frame of a later version, then a frame cut short by a crash.
+    log.extend([9, 3, 0, 0, 0, 1, 2, 3]);
+    log.extend([2, 100, 0, 0, 0, 0, 0]);
+    let reader = StreamReader::new(Cursor::new(log.clone())).unwrap();
+    assert_eq!(reader.read_telemetry().unwrap(), telemetry());
+
+    let dir = std::env::temp_dir().join("stream_log_session");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("telemetry.dtlog");
+    fs::write(&path, log).unwrap();
+    assert_eq!(autosave::read_telemetry_file(&path).unwrap(), telemetry());
+    assert_eq!(autosave::read_telemetry(&dir).unwrap(), telemetry());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,59 @@
+//! The supervisor stops the plants beyond the limits of their joints.
+use bevy_rapier3d::prelude::JointMotor;
+use digital_twin_playground::supervisor::{self, SafetyLimit, Supervisor};
+
+fn limit() -> SafetyLimit {
+    SafetyLimit {
+        link: "motor".to_string(),
+        position: Some([-1.0, 1.0]),
+        max_velocity: Some(10.0),
+        max_torque: Some(2.0),
+    }
+}
+
+#[test]
+fn joints_within_their_limits_are_left_alone() {
+    assert_eq!(limit().violation(Some(0.5), -9.0, 1.5), None);
+    // Without a position, only the speed and the torque are supervised.
+    assert_eq!(limit().violation(None, 0.0, 0.0), None);
+}
+
+#[test]
+fn every_limit_is_supervised() {
+    let limit = limit();
+    assert!(limit.violation(Some(1.2), 0.0, 0.0).is_some());
+    assert!(limit.violation(Some(-1.2), 0.0, 0.0).is_some());
+    assert!(limit.violation(Some(0.0), -11.0, 0.0).is_some());
+    assert!(limit.violation(Some(0.0), 0.0, 2.5).is_some());
+    let unlimited = SafetyLimit {
+        position: None,
+        max_velocity: None,
+        max_torque: None,
+        ..limit
+    };
+    assert_eq!(unlimited.violation(Some(100.0), 100.0, 100.0), None);
+}
+
+#[test]
+fn motor_torques_are_limited() {
+    let motor = JointMotor {
+        target_vel: 2.0,
+        target_pos: 0.0,
+        stiffness: 0.0,
+        damping: 0.5,
+        max_force: 0.8,
+        ..Default::default()
+    };
+    assert!((supervisor::motor_torque(&motor, 0.0, 1.0) - 0.5).abs() < 1e-6);
+    assert_eq!(supervisor::motor_torque(&motor, 0.0, -10.0), 0.8);
+}
+
+#[test]
+fn the_first_stop_holds_until_reset() {
+    let mut supervisor = Supervisor::default();
+    assert!(supervisor.stop("E-STOP pressed", 1.0));
+    assert!(!supervisor.stop("motor over speed", 2.0));
+    assert_eq!(supervisor.stopped().unwrap().reason, "E-STOP pressed");
+    supervisor.reset();
+    assert!(supervisor.stopped().is_none());
+}
@@ -0,0 +1,142 @@
+//! Sweeps cover their parameters, score the response of a channel and rank the runs.
+use std::{collections::BTreeMap, fs};
+
+use digital_twin_playground::{
+    headless::DEFAULT_TIME_STEP,
+    plants,
+    resource_usage::ResourceUsage,
+    sweep::{self, Cost, Metrics, Range, SweepRun, SweepSettings},
+};
+
+fn settings() -> SweepSettings {
+    SweepSettings {
+        parameters: vec![
+            Range {
+                name: "pid/kp".to_string(),
+                min: 1.0,
+                max: 5.0,
+                steps: 3,
+            },
+            Range {
+                name: "initial/pendulum".to_string(),
+                min: 0.0,
+                max: 0.5,
+                steps: 2,
+            },
+        ],
+        duration: 0.5,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn grids_cover_the_bounds() {
+    let combinations = sweep::combinations(&settings());
+    assert_eq!(combinations.len(), 6);
+    let values = |index: usize| {
+        let combination = &combinations[index];
+        [combination["pid/kp"], combination["initial/pendulum"]]
+    };
+    assert_eq!(values(0), [1.0, 0.0]);
+    assert_eq!(values(1), [1.0, 0.5]);
+    assert_eq!(values(2), [3.0, 0.0]);
+    assert_eq!(values(5), [5.0, 0.5]);
+
+    let baseline = SweepSettings::default();
+    assert_eq!(sweep::combinations(&baseline), vec![BTreeMap::new()]);
+}
+
+#[test]
+fn monte_carlo_draws_follow_from_the_seed_within_bounds() {
+    let settings = SweepSettings {
+        samples: 20,
+        ..settings()
+    };
+    let combinations = sweep::combinations(&settings);
+    assert_eq!(combinations.len(), 20);
+    assert_eq!(combinations, sweep::combinations(&settings));
+    for combination in &combinations {
+        assert!((1.0..=5.0).contains(&combination["pid/kp"]));
+        assert!((0.0..=0.5).contains(&combination["initial/pendulum"]));
+    }
+    assert_ne!(combinations[0], combinations[1]);
+}
+
+#[test]
+fn responses_are_scored() {
+    let samples = [[1.0, 0.0], [2.0, 0.8], [3.0, 1.2], [4.0, 0.99], [5.0, 1.01]];
+    let metrics = Metrics::of(&samples, 1.0, 0.02).unwrap();
+    assert_eq!(metrics.settling_time, Some(3.0));
+    assert!((metrics.overshoot - 0.2).abs() < 1e-6);
+    let rms = ((1.0 + 0.04 + 0.04 + 0.0001 + 0.0001) / 5.0f32).sqrt();
+    assert!((metrics.rms_error - rms).abs() < 1e-6);
+
+    // A response leaving the band at the end never settles.
+    let metrics = Metrics::of(&samples[..3], 1.0, 0.02).unwrap();
+    assert_eq!(metrics.settling_time, None);
+    assert_eq!(metrics.cost(Cost::SettlingTime), f32::INFINITY);
+    // From the target, any excursion overshoots.
+    let metrics = Metrics::of(&[[0.0, 0.0], [1.0, -0.3]], 0.0, 0.02).unwrap();
+    assert!((metrics.overshoot - 0.3).abs() < 1e-6);
+    assert_eq!(Metrics::of(&[], 1.0, 0.02), None);
+}
+
+#[test]
+fn runs_are_ranked_by_cost() {
+    let run = |rms_error: f32| SweepRun {
+        parameters: BTreeMap::new(),
+        metrics: Some(Metrics {
+            settling_time: Some(1.0),
+            overshoot: 0.0,
+            rms_error,
+        }),
+        energy: None,
+        violations: 0,
+        usage: ResourceUsage::default(),
+        scripted: None,
+    };
+    let unscored = SweepRun {
+        metrics: None,
+        ..run(0.0)
+    };
+    let runs = [run(0.5), unscored, run(f32::NAN), run(0.1)];
+    assert_eq!(sweep::ranking(&runs, Cost::RmsError), vec![3, 0, 1, 2]);
+}
+
+#[test]
+fn sweeps_write_a_summary_row_per_run() {
+    let Some(plant) = plants::builtin().into_iter().next() else {
+        return;
+    };
+    let settings = SweepSettings {
+        parameters: vec![settings().parameters[1].clone()],
+        ..settings()
+    };
+    let mut ended = Vec::new();
+    let runs = sweep::sweep(&plant, &settings, DEFAULT_TIME_STEP, |index, _| {
+        ended.push(index)
+    })
+    .unwrap();
+    assert_eq!(ended, vec![0, 1]);
+    assert!(runs.iter().all(|run| run.metrics.is_some()), "{runs:?}");
+
+    let path = std::env::temp_dir().join("sweep").join("summary.csv");
+    sweep::write_summary(&settings, &runs, &path).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some(
+            "run,initial/pendulum,settling_time,overshoot,rms_error,energy,efficiency,violations,\
+             cpu_time,peak_memory,step_p50_us,step_p90_us,step_p99_us,step_max_us,rank"
+        )
+    );
+    assert_eq!(lines.count(), 2);
+    fs::remove_file(path).unwrap();
+
+    // Unknown gains and links are reported.
+    for name in ["pid/kx", "initial/nowhere"] {
+        let parameters = BTreeMap::from([(name.to_string(), 1.0)]);
+        assert!(sweep::evaluate(&plant, &settings, &parameters, DEFAULT_TIME_STEP).is_err());
+    }
+}
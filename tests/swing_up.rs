@@ -0,0 +1,82 @@
+//! The swing-up pumps energy into the pendulum, and its mode switch hands over to the
+//! regulator near upright, with hysteresis.
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    lqr::{LqrController, LqrSettings},
+    swing_up::{self, Mode, ModeSwitch, ModeThresholds, SwingUpController, SwingUpSettings},
+};
+
+#[test]
+fn energies_are_relative_to_upright() {
+    assert_eq!(swing_up::energy_error(2.0, 0.0, 0.0), -2.0);
+    assert!(swing_up::energy_error(2.0, PI, 0.0).abs() < 1e-6);
+    // Hanging, turning fast enough to reach upright.
+    assert!(swing_up::energy_error(2.0, 0.0, 4.0).abs() < 1e-6);
+}
+
+#[test]
+fn modes_switch_with_hysteresis() {
+    let mut switch = ModeSwitch::new(ModeThresholds {
+        catch: 0.2,
+        release: 0.4,
+        energy: 0.1,
+    });
+    assert_eq!(switch.mode(), Mode::SwingUp);
+    // Near upright, but too fast to be caught.
+    assert_eq!(switch.update(0.1, 0.5), Mode::SwingUp);
+    assert_eq!(switch.update(0.1, 0.05), Mode::Balance);
+    // Between the catch and release angles, the mode holds either way.
+    assert_eq!(switch.update(0.3, 0.5), Mode::Balance);
+    assert_eq!(switch.update(-0.5, 0.0), Mode::SwingUp);
+    assert_eq!(switch.update(0.3, 0.0), Mode::SwingUp);
+}
+
+/// Angle within `(-π, π]`.
+fn wrap(angle: f32) -> f32 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+#[test]
+fn pendulums_are_swung_up_and_balanced() {
+    let (swing_up, lqr_settings) = (SwingUpSettings::default(), LqrSettings::default());
+    let dt = 1.0 / 60.0;
+    let lqr = lqr_settings.solve(dt).unwrap();
+    let mut switch = ModeSwitch::new(swing_up.thresholds);
+    let mut pumping = SwingUpController::new(Entity::PLACEHOLDER, "");
+    let mut balancing = LqrController::new(Entity::PLACEHOLDER, "");
+
+    // The embedded pendulum, driven by the acceleration of its motor joint, from hanging.
+    let (omega2, coupling) = (lqr_settings.a[3][2] as f32, -lqr_settings.b[3] as f32);
+    let [mut arm, mut arm_velocity, mut angle, mut velocity] = [0.0f32; 4];
+    let mut balanced = 0.0;
+    for _ in 0..30 * 60 {
+        let angles = [wrap(arm), wrap(angle)];
+        let command = match pumping.update(&swing_up, &mut switch, angles, dt) {
+            Some(command) => {
+                balancing.observe(angles);
+                Some(command)
+            }
+            None => balancing.update(&lqr, &lqr_settings, angles, dt),
+        };
+        let acceleration = command.map_or(0.0, |[acceleration, _]| acceleration);
+        for _ in 0..10 {
+            let step = dt / 10.0;
+            let swing = -omega2 * angle.sin() + coupling * acceleration * angle.cos();
+            arm_velocity += acceleration * step;
+            arm += arm_velocity * step;
+            velocity += swing * step;
+            angle += velocity * step;
+        }
+        balanced = if balancing.engaged() {
+            balanced + dt
+        } else {
+            0.0
+        };
+    }
+    assert_eq!(switch.mode(), Mode::Balance);
+    assert!(balanced > 10.0, "{balanced}");
+    assert!(wrap(angle - PI).abs() < 0.01, "{angle}");
+    assert!(arm_velocity.abs() < 0.1, "{arm_velocity}");
+}
@@ -0,0 +1,155 @@
+//! Taught sequences reach each of their poses, recordings are smoothed and tracked, and both
+//! are checked against the joints.
+use digital_twin_playground::teach::{
+    Playback, Pose, Replay, TaughtJoint, TeachSettings, Trajectory,
+};
+
+const DT: f32 = 1.0 / 60.0;
+
+#[test]
+fn playback_reaches_each_pose() {
+    let settings = TeachSettings::default();
+    let targets = [1.0f32, -2.0, 0.5];
+    let mut playback = Playback::new(targets.iter().map(|&t| vec![t]).collect());
+    // An axis tracking its velocity setpoint, with a step of delay.
+    let mut position = 0.0f32;
+    let mut reached = Vec::new();
+    for _ in 0..100_000 {
+        if playback.is_done() {
+            break;
+        }
+        let segment = playback.segment();
+        let velocity = playback.update(&settings.joints, &[position], settings.dwell, DT)[0];
+        if playback.segment() != segment {
+            reached.push(position);
+        }
+        position += velocity * DT;
+    }
+    assert!(playback.is_done());
+    assert_eq!(reached.len(), targets.len());
+    for (position, target) in reached.iter().zip(targets) {
+        assert!((position - target).abs() < 1e-3, "{position} vs {target}");
+    }
+}
+
+/// Plays a sequence on two axes tracking their velocity setpoints, returning the final
+/// positions, the number of stops and the largest contour error.
+fn play_planar(mut playback: Playback, joints: &[TaughtJoint]) -> (Vec<f32>, usize, f32) {
+    let mut position = vec![0.0f32; 2];
+    let (mut stops, mut moving, mut contour_error) = (0, false, 0.0f32);
+    for _ in 0..100_000 {
+        if playback.is_done() {
+            break;
+        }
+        let velocity = playback.update(joints, &position, 0.5, DT);
+        contour_error = contour_error.max(playback.contour_error().unwrap_or(0.0));
+        let speed = velocity.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if moving && speed < 1e-3 {
+            stops += 1;
+        }
+        moving = speed >= 1e-3;
+        for (position, velocity) in position.iter_mut().zip(velocity) {
+            *position += velocity * DT;
+        }
+    }
+    assert!(playback.is_done());
+    (position, stops, contour_error)
+}
+
+#[test]
+fn blended_playback_doesnt_stop_at_the_corners() {
+    let joint = TeachSettings::default().joints[0].clone();
+    let joints = vec![joint.clone(), joint];
+    let targets = vec![vec![1.0, 0.0], vec![1.0, 1.0], vec![0.0, 1.0]];
+
+    let (_, stops, contour_error) = play_planar(Playback::new(targets.clone()), &joints);
+    assert_eq!(stops, 3);
+    assert_eq!(contour_error, 0.0);
+
+    let tolerance = 0.05;
+    let (position, stops, contour_error) =
+        play_planar(Playback::new(targets).with_blend(tolerance), &joints);
+    // Only the end of the path stops, on the last pose.
+    assert_eq!(stops, 1);
+    assert!(contour_error < tolerance, "{contour_error}");
+    assert!((position[0] - 0.0).abs() < 1e-2 && (position[1] - 1.0).abs() < 1e-2);
+}
+
+#[test]
+fn sequences_are_checked_against_the_joints() {
+    let mut settings = TeachSettings {
+        poses: vec![Pose {
+            name: "home".to_string(),
+            positions: vec![0.0],
+        }],
+        sequence: vec!["home".to_string(), "home".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(settings.sequence_poses().unwrap().len(), 2);
+    settings.sequence.push("away".to_string());
+    assert!(settings.sequence_poses().is_err());
+    settings.sequence.pop();
+    settings.poses[0].positions.push(1.0);
+    assert!(settings.sequence_poses().is_err());
+}
+
+/// A motion led through by hand from rest at 1 to rest at 2, the hand shaking on the way.
+fn led_through() -> Trajectory {
+    let mut trajectory = Trajectory::default();
+    for k in 0..=120 {
+        let time = k as f32 * DT;
+        let shake = match k {
+            12..=108 if k % 2 == 0 => 0.01,
+            12..=108 => -0.01,
+            _ => 0.0,
+        };
+        trajectory.push(10.0 + time, vec![smooth_move(time) + shake]);
+    }
+    trajectory
+}
+
+fn smooth_move(time: f32) -> f32 {
+    1.5 - 0.5 * (std::f32::consts::FRAC_PI_2 * time).cos()
+}
+
+#[test]
+fn smoothing_keeps_the_ends_of_the_recordings() {
+    let recorded = led_through();
+    let smoothed = recorded.smoothed(0.2);
+    assert_eq!(smoothed.positions.first(), recorded.positions.first());
+    assert_eq!(smoothed.positions.last(), recorded.positions.last());
+    // The jitter is averaged out, the motion kept.
+    for (k, positions) in smoothed.positions.iter().enumerate().take(110).skip(10) {
+        let expected = smooth_move(k as f32 * DT);
+        assert!((positions[0] - expected).abs() < 5e-3, "{k}: {positions:?}");
+    }
+    assert!((smoothed.sample(1.0).0[0] - 1.5).abs() < 5e-3);
+    assert_eq!(smoothed.sample(5.0), (vec![2.0], vec![0.0]));
+}
+
+#[test]
+fn replays_track_the_recordings() {
+    let settings = TeachSettings::default();
+    let trajectory = led_through().smoothed(settings.smoothing);
+    settings.check_recording(&trajectory).unwrap();
+    let mut replay = Replay::new(trajectory);
+    // The axis starts away from the recording, and moves to its start first.
+    let mut position = 0.0f32;
+    let mut worst = 0.0f32;
+    for _ in 0..100_000 {
+        if replay.is_done() {
+            break;
+        }
+        let velocity = replay.update(&settings.joints, &[position], DT)[0];
+        worst = worst.max(replay.tracking_error().unwrap_or(0.0));
+        position += velocity * DT;
+    }
+    assert!(replay.is_done());
+    assert!(worst < 2e-2, "{worst}");
+    assert!((position - 2.0).abs() < 1e-2, "{position}");
+
+    assert!(settings.check_recording(&Trajectory::default()).is_err());
+    let mut two_joints = Trajectory::default();
+    two_joints.push(0.0, vec![0.0, 0.0]);
+    assert!(settings.check_recording(&two_joints).is_err());
+}
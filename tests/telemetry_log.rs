@@ -0,0 +1,62 @@
+//! Long logs are decimated without losing their envelope, and compressed sessions read back.
+use std::fs;
+
+use digital_twin_playground::{
+    autosave,
+    telemetry::{self, Telemetry},
+};
+
+#[test]
+fn decimation_keeps_the_envelope() {
+    let samples: Vec<[f32; 2]> = (0..1000)
+        .map(|k| {
+            let time = k as f32 * 0.01;
+            // A slow sine with a single spike.
+            let spike = if k == 437 { 5.0 } else { 0.0 };
+            [time, (time * 0.5).sin() + spike]
+        })
+        .collect();
+    let decimated = telemetry::decimate(&samples, 50);
+    assert_eq!(decimated.len(), 2 * 1000 / 50);
+    assert!(decimated.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+    // Each bucket keeps its extremes, the spike among them.
+    assert!(decimated.contains(&samples[437]));
+    for (bucket, kept) in samples.chunks(50).zip(decimated.chunks(2)) {
+        let high = bucket.iter().map(|[_, v]| *v).fold(f32::MIN, f32::max);
+        let low = bucket.iter().map(|[_, v]| *v).fold(f32::MAX, f32::min);
+        assert!(kept.iter().any(|[_, v]| *v == high));
+        assert!(kept.iter().any(|[_, v]| *v == low));
+    }
+
+    assert_eq!(telemetry::decimate(&samples, 1), samples);
+    assert_eq!(telemetry::decimate(&samples[..3], 10).len(), 2);
+}
+
+#[test]
+fn compressed_chunks_are_read_back() {
+    let mut first = Telemetry::default();
+    let mut second = Telemetry::default();
+    for k in 0..100 {
+        first.record("motor/angle", k as f32, k as f32 * 0.1);
+        second.record("motor/angle", (100 + k) as f32, 10.0 + k as f32 * 0.1);
+    }
+    let dir = std::env::temp_dir().join("telemetry_log_chunks");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("telemetry-00000.json"),
+        serde_json::to_vec(&first).unwrap(),
+    )
+    .unwrap();
+    let compressed = ruzstd::encoding::compress_to_vec(
+        serde_json::to_vec(&second).unwrap().as_slice(),
+        ruzstd::encoding::CompressionLevel::Fastest,
+    );
+    fs::write(dir.join("telemetry-00001.json.zst"), compressed).unwrap();
+
+    let path = dir.join("telemetry-00001.json.zst");
+    assert_eq!(autosave::read_telemetry_file(&path).unwrap(), second);
+    let mut whole = first.clone();
+    whole.extend(second);
+    assert_eq!(autosave::read_telemetry(&dir).unwrap(), whole);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,29 @@
+//! The colors of the theme, for every palette.
+use bevy::color::Srgba;
+use digital_twin_playground::theme::{Palette, Theme};
+
+#[test]
+fn heatmap_spans_the_color_map() {
+    for palette in Palette::ALL {
+        let theme = Theme { palette };
+        // Out of range and invalid values saturate instead of panicking.
+        assert_eq!(theme.heatmap(-1.0), theme.heatmap(0.0));
+        assert_eq!(theme.heatmap(2.0), theme.heatmap(1.0));
+        assert_eq!(theme.heatmap(f32::NAN), theme.heatmap(0.0));
+        assert_ne!(theme.heatmap(0.0), theme.heatmap(1.0));
+    }
+    let viridis = Theme {
+        palette: Palette::OkabeIto,
+    };
+    assert_eq!(viridis.heatmap(0.0), Srgba::rgb_u8(0x44, 0x01, 0x54));
+    assert_eq!(viridis.heatmap(1.0), Srgba::rgb_u8(0xFD, 0xE7, 0x25));
+}
+
+#[test]
+fn series_cycle_through_the_palette() {
+    let theme = Theme {
+        palette: Palette::Tol,
+    };
+    assert_ne!(theme.series(0), theme.series(1));
+    assert_eq!(theme.series(0), theme.series(7));
+}
@@ -0,0 +1,56 @@
+//! Traces keep the recent samples of their points, fading with age, and export them as CSV.
+#![cfg(not(target_arch = "wasm32"))]
+use std::fs;
+
+use bevy::prelude::*;
+use digital_twin_playground::traces::{self, Trace, Traces};
+
+#[test]
+fn traces_keep_their_recent_samples() {
+    let mut trace = Trace::default();
+    for step in 0..100 {
+        let time = step as f32 * 0.125;
+        trace.record(time, Vec3::X * time, 2.0, 0.01);
+    }
+    // Only the last two seconds are kept, the oldest faded out.
+    assert_eq!(trace.samples.len(), 17);
+    let faded: Vec<_> = trace.faded(12.375, 2.0).collect();
+    assert_eq!(faded[0].1, 0.0);
+    assert_eq!(faded.last().unwrap().1, 1.0);
+    assert!(faded.windows(2).all(|pair| pair[0].1 < pair[1].1));
+
+    // A point standing still isn't sampled again, and its path ages out.
+    trace.record(13.0, Vec3::X * 12.375, 2.0, 0.01);
+    assert_eq!(trace.samples.back(), Some(&(12.375, Vec3::X * 12.375)));
+    trace.trim(20.0, 2.0);
+    assert!(trace.samples.is_empty());
+
+    // Time going back restarts the path.
+    trace.record(5.0, Vec3::ZERO, 2.0, 0.01);
+    trace.record(1.0, Vec3::Y, 2.0, 0.01);
+    assert_eq!(trace.samples, [(1.0, Vec3::Y)]);
+}
+
+#[test]
+fn traces_are_exported_one_row_per_sample() {
+    let mut traces = Traces::default();
+    let mut trace = Trace::default();
+    trace.record(0.5, Vec3::new(1.0, 2.0, 3.0), 5.0, 0.0);
+    trace.record(1.0, Vec3::new(1.5, 2.0, 3.0), 5.0, 0.0);
+    traces
+        .paths
+        .insert(("feeder".to_string(), "pendulum tip".to_string()), trace);
+    let dir = std::env::temp_dir().join("traces_export");
+    let path = traces::export(&traces, &dir).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "plant,point,time,x,y,z",
+            "feeder,pendulum tip,0.5,1,2,3",
+            "feeder,pendulum tip,1,1.5,2,3",
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,115 @@
+//! Trapezoidal and S-curve profiles, and the trajectories through waypoints, end on their target
+//! without exceeding their limits.
+use digital_twin_playground::control::{
+    MotionLimits, Profile, ProfileKind, SCurveProfile, Trajectory, TrapezoidalProfile,
+};
+
+const DT: f32 = 1e-3;
+
+/// Samples a profile over its duration, returning the peak velocity and acceleration.
+fn peaks(profile: &TrapezoidalProfile) -> (f32, f32) {
+    let (mut velocity, mut acceleration) = (0.0f32, 0.0f32);
+    let mut previous = profile.sample(0.0).1;
+    let steps = (profile.duration() / DT).ceil() as usize;
+    for step in 1..=steps {
+        let (_, v) = profile.sample(step as f32 * DT);
+        velocity = velocity.max(v.abs());
+        acceleration = acceleration.max((v - previous).abs() / DT);
+        previous = v;
+    }
+    (velocity, acceleration)
+}
+
+#[test]
+fn profiles_reach_their_target_within_limits() {
+    for (start, end) in [(0.0f32, 10.0f32), (2.0, -3.0), (1.0, 1.1)] {
+        let profile = TrapezoidalProfile::new(start, end, 2.0, 4.0);
+        assert_eq!(profile.sample(0.0), (start, 0.0));
+        let (position, velocity) = profile.sample(profile.duration());
+        assert!((position - end).abs() < 1e-5, "{position} vs {end}");
+        assert!(velocity.abs() < 1e-5);
+        let (peak_velocity, peak_acceleration) = peaks(&profile);
+        assert!(peak_velocity <= 2.0 * (1.0 + 1e-5), "{peak_velocity}");
+        assert!(
+            peak_acceleration <= 4.0 * (1.0 + 1e-2),
+            "{peak_acceleration}"
+        );
+    }
+}
+
+#[test]
+fn short_moves_are_triangular() {
+    // Reaching 2 m/s at 4 m/s² takes 1 m, half of the move would need 0.5 m.
+    let profile = TrapezoidalProfile::new(0.0, 0.5, 2.0, 4.0);
+    let half = profile.duration() / 2.0;
+    assert!((half - (0.25f32 / 2.0).sqrt()).abs() < 1e-6);
+    let (position, velocity) = profile.sample(half);
+    assert!((position - 0.25).abs() < 1e-6);
+    assert!((velocity - (0.25f32 * 8.0).sqrt()).abs() < 1e-5);
+    // Without limits, nothing moves.
+    let still = TrapezoidalProfile::new(1.0, 5.0, 0.0, 4.0);
+    assert_eq!(still.duration(), 0.0);
+    assert_eq!(still.sample(1.0), (1.0, 0.0));
+}
+
+/// Samples a motion over its duration, returning the peak velocity, acceleration and jerk.
+fn motion_peaks(profile: &impl Profile) -> (f32, f32, f32) {
+    let (mut velocity, mut acceleration, mut jerk) = (0.0f32, 0.0f32, 0.0f32);
+    let mut previous = profile.state(0.0);
+    let steps = (profile.duration() / DT).ceil() as usize;
+    for step in 1..=steps {
+        let state = profile.state(step as f32 * DT);
+        velocity = velocity.max(state.velocity.abs());
+        acceleration = acceleration.max(state.acceleration.abs());
+        jerk = jerk.max((state.acceleration - previous.acceleration).abs() / DT);
+        previous = state;
+    }
+    (velocity, acceleration, jerk)
+}
+
+#[test]
+fn s_curves_reach_their_target_within_limits() {
+    for (start, end) in [(0.0f32, 10.0f32), (2.0, -3.0), (1.0, 1.1), (0.0, 0.05)] {
+        let profile = SCurveProfile::new(start, end, 2.0, 4.0, 40.0);
+        let last = profile.state(profile.duration());
+        assert!(
+            (last.position - end).abs() < 1e-5,
+            "{} vs {end}",
+            last.position
+        );
+        assert!(last.velocity.abs() < 1e-5 && last.acceleration.abs() < 1e-4);
+        let (velocity, acceleration, jerk) = motion_peaks(&profile);
+        assert!(velocity <= 2.0 * (1.0 + 1e-5), "{velocity}");
+        assert!(acceleration <= 4.0 * (1.0 + 1e-5), "{acceleration}");
+        // The acceleration ramps within a sample of the limit of the jerk.
+        assert!(jerk <= 40.0 * (1.0 + 1e-2), "{jerk}");
+    }
+    // Slower than the trapezoid, for the ramps of the acceleration.
+    let trapezoid = TrapezoidalProfile::new(0.0, 10.0, 2.0, 4.0);
+    assert!(SCurveProfile::new(0.0, 10.0, 2.0, 4.0, 40.0).duration() > trapezoid.duration());
+}
+
+#[test]
+fn trajectories_pass_through_their_waypoints_within_limits() {
+    let waypoints = [0.0f32, 1.0, 3.0, 2.0, 5.0];
+    let limits = MotionLimits::default();
+    for kind in ProfileKind::ALL {
+        let trajectory = Trajectory::new(kind, &waypoints, limits);
+        assert!((trajectory.end() - 5.0).abs() < 1e-4, "{kind:?}");
+        let end = trajectory.state(trajectory.duration());
+        assert!(end.velocity.abs() < 1e-4, "{kind:?}: {}", end.velocity);
+        for waypoint in &waypoints[1..4] {
+            let steps = (trajectory.duration() / DT) as usize;
+            let closest = (0..=steps)
+                .map(|step| (trajectory.state(step as f32 * DT).position - waypoint).abs())
+                .fold(f32::MAX, f32::min);
+            assert!(closest < 1e-2, "{kind:?} misses {waypoint} by {closest}");
+        }
+        let (velocity, acceleration, _) = motion_peaks(&trajectory);
+        assert!(velocity <= limits.max_velocity * (1.0 + 1e-2), "{kind:?}");
+        assert!(
+            acceleration <= limits.max_acceleration * (1.0 + 1e-2),
+            "{kind:?}"
+        );
+    }
+}
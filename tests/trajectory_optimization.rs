@@ -0,0 +1,131 @@
+//! Swing-ups optimized by direct collocation get the pendulum upright within the limit of the
+//! arm, and are saved as references the arm tracks.
+use std::f64::consts::PI;
+
+use digital_twin_playground::{
+    control::{Collocation, RotaryPendulum, SwingUp},
+    trajectory_optimization::{Objective, ReferenceTrajectory, TrajectoryOptimizationSettings},
+};
+use nalgebra::Vector4;
+
+fn hanging() -> Vector4<f64> {
+    Vector4::new(0.0, 0.0, PI, 0.0)
+}
+
+fn pendulum() -> RotaryPendulum {
+    let settings = TrajectoryOptimizationSettings::default();
+    RotaryPendulum {
+        gravity: settings.gravity,
+        coupling: settings.coupling,
+    }
+}
+
+/// The state at the end of the swing-up, simulated in open loop in steps of a millisecond.
+fn simulate(model: &RotaryPendulum, swing_up: &SwingUp) -> Vector4<f64> {
+    let segments = swing_up.accelerations.len() - 1;
+    let steps = (swing_up.duration * 1000.0).round() as usize;
+    let dt = swing_up.duration / steps as f64;
+    let mut state = hanging();
+    for step in 0..steps {
+        let position = (step as f64 + 0.5) / steps as f64 * segments as f64;
+        let node = (position.floor() as usize).min(segments - 1);
+        let fraction = position - node as f64;
+        let [before, after] = [node, node + 1].map(|node| swing_up.accelerations[node]);
+        state = model.step(&state, before + fraction * (after - before), dt);
+    }
+    state
+}
+
+#[test]
+fn minimum_energy_swing_ups_get_upright_within_the_limit() {
+    let model = pendulum();
+    let collocation = Collocation::new(model, 40, 20.0).unwrap();
+    let swing_up = collocation.minimum_energy(&hanging(), 1.5).unwrap();
+    assert!(swing_up.reaches());
+    assert_eq!(swing_up.states.len(), 41);
+    assert!((swing_up.times().last().unwrap() - 1.5).abs() < 1e-12);
+    assert!(swing_up
+        .accelerations
+        .iter()
+        .all(|acceleration| acceleration.abs() <= 20.0 + 1e-9));
+
+    let end = simulate(&model, &swing_up);
+    assert!(end[2].abs() < 0.1, "{end}");
+    assert!(end[3].abs() < 0.2, "{end}");
+
+    let slower = collocation.minimum_energy(&hanging(), 2.0).unwrap();
+    assert!(
+        slower.effort() < swing_up.effort(),
+        "{} {}",
+        slower.effort(),
+        swing_up.effort()
+    );
+
+    assert!(Collocation::new(model, 0, 20.0).is_none());
+    assert!(Collocation::new(model, 40, 0.0).is_none());
+}
+
+#[test]
+fn minimum_time_swing_ups_saturate_the_limit() {
+    let collocation = Collocation::new(pendulum(), 20, 20.0).unwrap();
+    let fastest = collocation.minimum_time(&hanging(), 2.0).unwrap();
+    assert!(
+        fastest.duration > 0.7 && fastest.duration < 0.9,
+        "{}",
+        fastest.duration
+    );
+    let peak = fastest
+        .accelerations
+        .iter()
+        .fold(0.0, |peak: f64, acceleration| peak.max(acceleration.abs()));
+    assert!(peak > 19.9, "{peak}");
+    assert!(collocation
+        .minimum_energy(&hanging(), fastest.duration - 0.05)
+        .is_none());
+
+    assert!(collocation.minimum_time(&hanging(), 0.5).is_none());
+}
+
+#[test]
+fn references_are_saved_and_sampled() {
+    let settings = TrajectoryOptimizationSettings {
+        objective: Objective::MinimumEnergy,
+        duration: 1.5,
+        segments: 20,
+        ..Default::default()
+    };
+    let reference = settings.optimize().unwrap();
+    assert_eq!(reference.times.len(), 21);
+    assert!((reference.duration() - 1.5).abs() < 1e-6);
+    for (torque, acceleration) in reference.torques.iter().zip(&reference.accelerations) {
+        assert!((torque - settings.arm_inertia * acceleration).abs() < 1e-6);
+    }
+    assert!(reference.pendulum.last().unwrap().abs() < 1e-3);
+
+    // The arm rests on the ends outside the reference, and moves linearly between the nodes.
+    assert_eq!(reference.sample(-1.0), [0.0; 3]);
+    let end = *reference.angles.last().unwrap();
+    assert_eq!(reference.sample(2.0), [end, 0.0, 0.0]);
+    let middle = 0.5 * (reference.times[3] + reference.times[4]);
+    let [angle, velocity, torque] = reference.sample(middle);
+    assert!((angle - 0.5 * (reference.angles[3] + reference.angles[4])).abs() < 1e-5);
+    assert!((velocity - 0.5 * (reference.velocities[3] + reference.velocities[4])).abs() < 1e-5);
+    assert!((torque - 0.5 * (reference.torques[3] + reference.torques[4])).abs() < 1e-5);
+
+    let path = std::env::temp_dir()
+        .join("trajectory_optimization")
+        .join("swing_up.json");
+    reference.write(&path).unwrap();
+    assert_eq!(ReferenceTrajectory::read(&path).unwrap(), reference);
+
+    let weak = TrajectoryOptimizationSettings {
+        duration: 0.3,
+        ..settings.clone()
+    };
+    assert!(weak.optimize().is_err());
+    let limp = TrajectoryOptimizationSettings {
+        torque_limit: 0.0,
+        ..settings
+    };
+    assert!(limp.optimize().is_err());
+}
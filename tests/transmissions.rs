@@ -0,0 +1,97 @@
+//! Transmissions couple a driven joint to its driver through a ratio, gears holding and belts
+//! slipping past their slip torque.
+use digital_twin_playground::transmissions::{Coupler, Transmission, TransmissionKind};
+
+fn gear() -> Transmission {
+    Transmission {
+        ratio: 2.0,
+        stiffness: 10.0,
+        damping: 0.0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn gears_pull_the_driven_joint_along() {
+    let model = gear();
+    let mut coupler = Coupler::default();
+    // Coupled as they are.
+    assert_eq!(coupler.torque(&model, [0.5, 0.0], [1.0, 0.0]), 0.0);
+    // The driver turns by 0.4 rad, so the driven joint lags by 0.2 rad.
+    let torque = coupler.torque(&model, [0.9, 0.0], [1.0, 0.0]);
+    assert!((torque - 2.0).abs() < 1e-5);
+    // The driver takes the reaction, through the ratio.
+    assert!((Coupler::driver_torque(&model, torque) + 1.0).abs() < 1e-5);
+    // Following along, the driven joint feels nothing.
+    assert!(coupler.torque(&model, [0.9, 0.0], [1.2, 0.0]).abs() < 1e-5);
+    // Damping resists the speeds apart.
+    let damped = Transmission {
+        damping: 0.5,
+        ..model
+    };
+    assert!((coupler.torque(&damped, [0.9, 2.0], [1.2, 0.0]) - 0.5).abs() < 1e-5);
+    assert_eq!(coupler.slip(), 0.0);
+}
+
+#[test]
+fn gears_follow_the_driver_over_the_turns() {
+    let model = gear();
+    let mut coupler = Coupler::default();
+    coupler.torque(&model, [0.0, 0.0], [0.0, 0.0]);
+    // Joint angles wrap around at π: the driver goes round a whole turn by steps.
+    let mut driver = 0.0_f32;
+    for _ in 0..20 {
+        driver += 0.1 * std::f32::consts::PI;
+        let wrapped = (driver + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU)
+            - std::f32::consts::PI;
+        let driven = driver / 2.0;
+        assert!(coupler.torque(&model, [wrapped, 0.0], [driven, 0.0]).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn belts_slip_past_their_slip_torque() {
+    let model = Transmission {
+        kind: TransmissionKind::Belt,
+        ratio: 1.0,
+        stiffness: 10.0,
+        damping: 0.0,
+        slip_torque: 1.0,
+        ..Default::default()
+    };
+    let mut coupler = Coupler::default();
+    coupler.torque(&model, [0.0, 0.0], [0.0, 0.0]);
+    // Within its slip torque, the belt stretches.
+    assert!((coupler.torque(&model, [0.05, 0.0], [0.0, 0.0]) - 0.5).abs() < 1e-5);
+    assert_eq!(coupler.slip(), 0.0);
+    // Past it, the belt slips, only carrying its slip torque.
+    assert!((coupler.torque(&model, [0.5, 0.0], [0.0, 0.0]) - 1.0).abs() < 1e-5);
+    assert!((coupler.slip() + 0.4).abs() < 1e-5);
+    // The driven joint stays behind for good.
+    assert!((coupler.torque(&model, [0.5, 0.0], [0.1, 0.0])).abs() < 1e-5);
+
+    // A gear never slips.
+    let gear = Transmission {
+        kind: TransmissionKind::Gear,
+        ..model
+    };
+    let mut coupler = Coupler::default();
+    coupler.torque(&gear, [0.0, 0.0], [0.0, 0.0]);
+    assert!((coupler.torque(&gear, [0.5, 0.0], [0.0, 0.0]) - 5.0).abs() < 1e-5);
+    assert_eq!(coupler.slip(), 0.0);
+}
+
+#[test]
+fn transmissions_need_two_joints_and_a_ratio() {
+    assert!(Transmission::default().is_valid());
+    let zero = Transmission {
+        ratio: 0.0,
+        ..Default::default()
+    };
+    assert!(!zero.is_valid());
+    let itself = Transmission {
+        driver: "pendulum".to_string(),
+        ..Default::default()
+    };
+    assert!(!itself.is_valid());
+}
@@ -0,0 +1,78 @@
+//! A Simulink-like peer exchanges fixed-layout packets with a headless simulator.
+use std::{net::UdpSocket, thread, time::Duration};
+
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    network::NetworkSettings,
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::PENDULUM_ANGLE,
+    udp_packets::{PacketLayout, UdpPacketsPlugin, ValueType},
+};
+
+#[test]
+fn peer_commands_the_motor() {
+    let address = "127.0.0.1:47061".parse().unwrap();
+    let layout = PacketLayout::default();
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(UdpPacketsPlugin {
+        bind: address,
+        layout: layout.clone(),
+        network: NetworkSettings::default(),
+    });
+    app.finish();
+    app.cleanup();
+
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    peer.set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+    // A `double` array of one element, as sent by the Simulink UDP Send block.
+    peer.send_to(&1.25f64.to_le_bytes(), address).unwrap();
+
+    let mut buffer = [0; 64];
+    for _ in 0..1_000 {
+        app.update();
+        if let Ok(size) = peer.recv(&mut buffer) {
+            assert_eq!(size, 16);
+            let velocity = f64::from_le_bytes(buffer[8..16].try_into().unwrap());
+            assert_eq!(velocity, 1.25);
+            assert_eq!(
+                app.world().resource::<Setpoints>().get(MOTOR_VELOCITY),
+                Some(1.25)
+            );
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("the simulator doesn't answer");
+}
+
+#[test]
+fn packets_round_trip() {
+    let layout = PacketLayout {
+        header: "SIM1".to_string(),
+        counter: true,
+        timestamp: false,
+        value_type: ValueType::Single,
+        inputs: vec![MOTOR_VELOCITY.to_string(), PENDULUM_ANGLE.to_string()],
+        outputs: Vec::new(),
+        remote: None,
+    };
+    let packet = layout.encode(9, &[0.5, -2.0]);
+    assert_eq!(packet.len(), layout.packet_size(2));
+    assert_eq!(&packet[..4], b"SIM1");
+    assert_eq!(layout.decode(&packet), Some((9, vec![0.5, -2.0])));
+    assert_eq!(layout.decode(&packet[1..]), None);
+    assert_eq!(layout.decode(&layout.encode(9, &[0.5])), None);
+}
+
+#[test]
+fn timestamped_packets_start_with_their_time() {
+    let layout = PacketLayout {
+        timestamp: true,
+        ..PacketLayout::default()
+    };
+    let packet = layout.encode(0, &[1.5, 0.25]);
+    assert_eq!(layout.decode(&packet), Some((0, vec![1.5, 0.25])));
+    // The time comes on top of the inputs.
+    assert_eq!(layout.decode(&layout.encode(0, &[0.25])), None);
+}
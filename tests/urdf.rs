@@ -0,0 +1,206 @@
+//! URDF robots are parsed, and spawned as rigid bodies joined like their links.
+use std::{f32::consts::FRAC_PI_2, path::Path};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use digital_twin_playground::{
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    plants::Link,
+    setpoints::Setpoints,
+    telemetry::Telemetry,
+    urdf::{self, Geometry, JointType, Robot, UrdfPlugin},
+};
+
+const ARM: &str = r#"<?xml version="1.0"?>
+<robot name="arm">
+  <material name="blue"><color rgba="0 0 1 1"/></material>
+  <link name="base">
+    <visual>
+      <geometry><cylinder radius="0.1" length="0.2"/></geometry>
+      <material name="blue"/>
+    </visual>
+  </link>
+  <link name="upper">
+    <inertial>
+      <origin xyz="0 0 0.25"/>
+      <mass value="2.0"/>
+      <inertia ixx="0.05" ixy="0" ixz="0" iyy="0.05" iyz="0" izz="0.01"/>
+    </inertial>
+    <visual>
+      <origin xyz="0 0 0.25"/>
+      <geometry><box size="0.05 0.05 0.5"/></geometry>
+    </visual>
+    <collision>
+      <origin xyz="0 0 0.25"/>
+      <geometry><box size="0.05 0.05 0.5"/></geometry>
+    </collision>
+  </link>
+  <link name="slider">
+    <inertial><mass value="0.5"/></inertial>
+    <visual><geometry><mesh filename="package://arm/meshes/slider.stl"/></geometry></visual>
+  </link>
+  <joint name="shoulder" type="revolute">
+    <parent link="base"/>
+    <child link="upper"/>
+    <origin xyz="0 0 0.1" rpy="0 0 1.5707963"/>
+    <axis xyz="0 1 0"/>
+    <limit lower="-1.5" upper="1.5" effort="20" velocity="2"/>
+  </joint>
+  <joint name="extension" type="prismatic">
+    <parent link="upper"/>
+    <child link="slider"/>
+    <origin xyz="0 0 0.5"/>
+    <axis xyz="0 0 1"/>
+    <limit lower="0" upper="0.2" effort="50" velocity="0.5"/>
+  </joint>
+</robot>"#;
+
+#[test]
+fn robots_are_parsed() {
+    let robot = Robot::parse(ARM).unwrap();
+    assert_eq!(robot.name, "arm");
+    assert_eq!(robot.links.len(), 3);
+
+    let base = robot.link("base").unwrap();
+    assert_eq!(base.inertial, None);
+    assert_eq!(
+        base.visuals[0].geometry,
+        Geometry::Cylinder {
+            radius: 0.1,
+            length: 0.2
+        }
+    );
+    assert_eq!(
+        base.visuals[0].color,
+        Some(Color::srgba(0.0, 0.0, 1.0, 1.0))
+    );
+    let upper = robot.link("upper").unwrap();
+    let inertial = upper.inertial.as_ref().unwrap();
+    assert_eq!(inertial.mass, 2.0);
+    assert_eq!(inertial.origin.translation, Vec3::new(0.0, 0.0, 0.25));
+    assert_eq!(upper.collisions.len(), 1);
+
+    let shoulder = &robot.joints[0];
+    assert_eq!(shoulder.kind, JointType::Revolute);
+    assert_eq!(shoulder.axis, Vec3::Y);
+    assert_eq!(shoulder.limits, Some([-1.5, 1.5]));
+    assert_eq!(shoulder.effort, Some(20.0));
+    assert!(robot.joints[1].is_actuated());
+    assert_eq!(
+        robot.signal("shoulder", "velocity"),
+        "arm/shoulder/velocity"
+    );
+}
+
+#[test]
+fn joints_place_the_links() {
+    let poses = Robot::parse(ARM).unwrap().poses().unwrap();
+    assert_eq!(poses["base"], Transform::IDENTITY);
+    assert!(poses["upper"]
+        .translation
+        .abs_diff_eq(Vec3::new(0.0, 0.0, 0.1), 1e-6));
+    // The yaw of the shoulder turns the links after it.
+    let (yaw, _, _) = poses["slider"].rotation.to_euler(EulerRot::ZYX);
+    assert!((yaw - FRAC_PI_2).abs() < 1e-5, "{yaw}");
+    assert!(poses["slider"]
+        .translation
+        .abs_diff_eq(Vec3::new(0.0, 0.0, 0.6), 1e-6));
+}
+
+#[test]
+fn joint_positions_follow_their_axes() {
+    let robot = Robot::parse(ARM).unwrap();
+    let shoulder = &robot.joints[0];
+    assert!(shoulder.position(&shoulder.origin).abs() < 1e-6);
+    let turned = shoulder.origin * Transform::from_rotation(Quat::from_rotation_y(0.3));
+    assert!((shoulder.position(&turned) - 0.3).abs() < 1e-5);
+
+    let extension = &robot.joints[1];
+    let extended = extension.origin * Transform::from_xyz(0.0, 0.0, 0.15);
+    assert!((extension.position(&extended) - 0.15).abs() < 1e-6);
+}
+
+#[test]
+fn inertia_tensors_are_diagonalized() {
+    let (moments, axes) = urdf::principal_inertia([2.0, 0.0, 0.0, 3.0, 0.0, 4.0]);
+    let mut sorted = moments.to_array();
+    sorted.sort_by(f32::total_cmp);
+    assert_eq!(sorted, [2.0, 3.0, 4.0]);
+    assert!(axes.is_normalized());
+
+    // Products of inertia tilt the principal axes.
+    let (moments, axes) = urdf::principal_inertia([2.0, 1.0, 0.0, 2.0, 0.0, 1.0]);
+    let mut sorted = moments.to_array();
+    sorted.sort_by(f32::total_cmp);
+    assert!((sorted[0] - 1.0).abs() < 1e-5 && (sorted[2] - 3.0).abs() < 1e-5);
+    assert!((Mat3::from_quat(axes).determinant() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn mesh_paths_are_resolved() {
+    let directory = Path::new("/robots/arm/urdf");
+    assert_eq!(
+        urdf::mesh_path(directory, "package://arm/meshes/slider.glb"),
+        Path::new("/robots/arm/meshes/slider.glb")
+    );
+    assert_eq!(
+        urdf::mesh_path(directory, "meshes/slider.glb"),
+        Path::new("/robots/arm/urdf/meshes/slider.glb")
+    );
+}
+
+#[test]
+fn invalid_robots_are_rejected() {
+    assert!(Robot::parse("<robot").is_err());
+    assert!(Robot::parse(r#"<model name="arm"/>"#).is_err());
+    // A joint to an unknown link.
+    assert!(
+        Robot::parse(&ARM.replace(r#"<child link="slider"/>"#, r#"<child link="hand"/>"#)).is_err()
+    );
+    // Two roots.
+    assert!(
+        Robot::parse(&ARM.replace(r#"<parent link="upper"/>"#, r#"<parent link="slider"/>"#))
+            .is_err()
+    );
+    assert!(Robot::parse(&ARM.replace(r#"type="prismatic""#, r#"type="ball""#)).is_err());
+    assert!(
+        Robot::parse(&ARM.replace(r#"<mass value="2.0"/>"#, r#"<mass value="heavy"/>"#)).is_err()
+    );
+}
+
+#[test]
+fn robots_are_spawned_and_driven() {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(UrdfPlugin {
+        robot: Robot::parse(ARM).unwrap(),
+        directory: Default::default(),
+    });
+    app.finish();
+    app.cleanup();
+    app.update();
+
+    let world = app.world_mut();
+    let mut links = world.query::<(Entity, &Link, &RigidBody)>();
+    let links: Vec<(Entity, Link, RigidBody)> = links
+        .iter(world)
+        .map(|(entity, link, body)| (entity, link.clone(), *body))
+        .collect();
+    assert_eq!(links.len(), 3);
+    for (entity, link, body) in &links {
+        assert_eq!(link.plant, "arm");
+        let root = link.name == "base";
+        assert_eq!(*body == RigidBody::Fixed, root, "{}", link.name);
+        assert_eq!(world.get::<ImpulseJoint>(*entity).is_some(), !root);
+    }
+
+    world
+        .resource_mut::<Setpoints>()
+        .set("arm/extension/velocity", 0.1);
+    for _ in 0..60 {
+        app.update();
+    }
+    let telemetry = app.world().resource::<Telemetry>();
+    let extension = telemetry.latest("arm/extension/position").unwrap();
+    assert!(extension > 0.02 && extension <= 0.2 + 1e-3, "{extension}");
+    assert!(telemetry.latest("arm/shoulder/position").is_some());
+}
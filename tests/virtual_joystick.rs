@@ -0,0 +1,21 @@
+//! Signals map onto the travel of the axes of the virtual joystick.
+#![cfg(target_os = "linux")]
+use digital_twin_playground::virtual_joystick::{Axis, AxisBinding, AXIS_MAX};
+
+#[test]
+fn signals_span_the_travel_of_the_axes() {
+    let binding = AxisBinding {
+        axis: Axis::X,
+        signal: "pendulum/angle".to_string(),
+        range: 2.0,
+    };
+    assert_eq!(binding.position(0.0), 0);
+    assert_eq!(
+        binding.position(1.0),
+        (AXIS_MAX as f32 / 2.0).round() as i32
+    );
+    assert_eq!(binding.position(-2.0), -AXIS_MAX);
+    // Values beyond the range saturate.
+    assert_eq!(binding.position(5.0), AXIS_MAX);
+    assert_eq!(binding.position(f32::NAN), 0);
+}
@@ -0,0 +1,58 @@
+//! The watchdog trips on a silent controller and refuses its commands until reset.
+use std::time::{Duration, Instant};
+
+use digital_twin_playground::watchdog::{Watchdog, WatchdogSettings};
+
+fn watchdog(auto_reset: bool) -> Watchdog {
+    Watchdog::new(WatchdogSettings {
+        timeout: 0.1,
+        auto_reset,
+        ..WatchdogSettings::default()
+    })
+}
+
+#[test]
+fn silent_controller_trips_the_watchdog() {
+    let start = Instant::now();
+    let ms = |ms: u64| start + Duration::from_millis(ms);
+    let mut watchdog = watchdog(false);
+    assert_eq!(watchdog.check(ms(1_000)), None, "nothing supervised yet");
+
+    assert!(watchdog.feed("udp", ms(0)));
+    assert!(watchdog.feed("modbus", ms(50)));
+    assert!(watchdog.feed("udp", ms(90)));
+    assert_eq!(watchdog.check(ms(140)), None);
+
+    let trip = watchdog.check(ms(160)).unwrap();
+    assert_eq!(trip.controller, "modbus");
+    assert_eq!(watchdog.trip(), Some(&trip));
+    // Tripped, every controller is refused, even once the silent one is back.
+    assert!(!watchdog.feed("udp", ms(170)));
+    assert!(!watchdog.feed("modbus", ms(170)));
+    assert_eq!(watchdog.check(ms(1_000)), None);
+
+    watchdog.reset();
+    assert!(watchdog.feed("modbus", ms(1_010)));
+}
+
+#[test]
+fn controller_coming_back_resets_the_watchdog() {
+    let start = Instant::now();
+    let mut watchdog = watchdog(true);
+    watchdog.feed("session/bob", start);
+    assert!(watchdog.check(start + Duration::from_millis(200)).is_some());
+    assert!(!watchdog.feed("udp", start + Duration::from_millis(210)));
+    assert!(watchdog.feed("session/bob", start + Duration::from_millis(220)));
+    assert_eq!(watchdog.trip(), None);
+}
+
+#[test]
+fn disabled_watchdog_never_trips() {
+    let start = Instant::now();
+    let mut watchdog = Watchdog::new(WatchdogSettings {
+        enabled: false,
+        ..WatchdogSettings::default()
+    });
+    watchdog.feed("udp", start);
+    assert_eq!(watchdog.check(start + Duration::from_secs(60)), None);
+}
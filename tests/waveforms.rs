@@ -0,0 +1,102 @@
+//! Waveforms are read from CSV and WAV files and resampled to the rate of the control.
+use std::path::Path;
+
+use digital_twin_playground::waveforms::{self, Waveform, WaveformSource};
+
+#[test]
+fn denser_waveforms_are_averaged_and_sparser_ones_interpolated() {
+    // 1 kHz, alternating around 1.
+    let points: Vec<[f32; 2]> = (0..=1000)
+        .map(|index| {
+            [
+                index as f32 * 0.001,
+                1.0 + if index % 2 == 0 { 0.5 } else { -0.5 },
+            ]
+        })
+        .collect();
+    let waveform = Waveform::from_points(&points, 10.0);
+    assert_eq!(waveform.samples.len(), 11);
+    // The alternation averages out, but on the half periods of the ends.
+    assert!(waveform.samples[1..10]
+        .iter()
+        .all(|sample| (sample - 1.0).abs() < 0.01));
+
+    let ramp = Waveform::from_points(&[[0.0, 0.0], [1.0, 10.0]], 4.0);
+    assert_eq!(ramp.samples, [0.0, 2.5, 5.0, 7.5, 10.0]);
+    let held = Waveform {
+        rate: 100.0,
+        samples: vec![2.0; 101],
+    };
+    assert_eq!(held.resample(10.0).samples, [2.0; 11]);
+}
+
+#[test]
+fn samples_are_held_for_a_period() {
+    let waveform = Waveform {
+        rate: 10.0,
+        samples: vec![1.0, 2.0, 3.0],
+    };
+    assert_eq!(waveform.duration(), 0.3);
+    assert_eq!(waveform.at(-0.01, false), None);
+    assert_eq!(waveform.at(0.0, false), Some(1.0));
+    assert_eq!(waveform.at(0.15, false), Some(2.0));
+    assert_eq!(waveform.at(0.35, false), None);
+    assert_eq!(waveform.at(0.35, true), Some(1.0));
+}
+
+#[test]
+fn csv_columns_are_read_on_the_time_of_the_first_row() {
+    let text = "t_ms;torque;other\n1000;0.5;x\n1010;;x\n1020;1.5;x\n";
+    let source = WaveformSource {
+        time_column: "t_ms".to_string(),
+        time_unit: 0.001,
+        column: "torque".to_string(),
+        delimiter: ';',
+        ..Default::default()
+    };
+    let points = waveforms::parse_csv(text, &source, Path::new("bench.csv")).unwrap();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0], [0.0, 0.5]);
+    assert!((points[1][0] - 0.02).abs() < 1e-6);
+
+    let missing = WaveformSource {
+        column: "force".to_string(),
+        ..source
+    };
+    assert!(waveforms::parse_csv(text, &missing, Path::new("bench.csv")).is_err());
+}
+
+fn wav(channels: u16, rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((36 + data.len() as u32).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(channels.to_le_bytes());
+    bytes.extend(rate.to_le_bytes());
+    bytes.extend((rate * 2 * u32::from(channels)).to_le_bytes());
+    bytes.extend((2 * channels).to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+#[test]
+fn wav_channels_are_read_at_their_rate() {
+    let bytes = wav(2, 8000, &[16384, -32768, -16384, 0]);
+    let path = Path::new("bench.wav");
+    let right = waveforms::parse_wav(&bytes, 1, path).unwrap();
+    assert_eq!(right.rate, 8000.0);
+    assert_eq!(right.samples, [-1.0, 0.0]);
+    let left = waveforms::parse_wav(&bytes, 0, path).unwrap();
+    assert_eq!(left.samples, [0.5, -0.5]);
+
+    assert!(waveforms::parse_wav(&bytes, 2, path).is_err());
+    assert!(waveforms::parse_wav(b"not a wav", 0, path).is_err());
+}
@@ -0,0 +1,130 @@
+//! A script subscribes to the telemetry over the WebSocket API and commands the run.
+use std::{
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use digital_twin_playground::{
+    clock::SimClock,
+    headless::{headless_app, DEFAULT_TIME_STEP},
+    setpoints::{Setpoints, MOTOR_VELOCITY},
+    telemetry::Telemetry,
+    websocket_api::{ApiCommand, ApiMessage, WebSocketApi, WebSocketApiPlugin},
+};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn api(address: SocketAddr) -> (App, Socket) {
+    let mut app = headless_app(DEFAULT_TIME_STEP);
+    app.add_plugins(WebSocketApiPlugin {
+        bind: address,
+        rate: 100.0,
+    });
+    app.finish();
+    app.cleanup();
+    let (socket, _) = tungstenite::connect(format!("ws://{address}")).unwrap();
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(2)))
+            .unwrap();
+    }
+    run_until(&mut app, |app| {
+        !app.world().resource::<WebSocketApi>().clients.is_empty()
+    });
+    (app, socket)
+}
+
+/// Updates the application until `done` holds.
+fn run_until(app: &mut App, done: impl Fn(&App) -> bool) {
+    for _ in 0..2_000 {
+        app.update();
+        if done(app) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+    panic!("the API doesn't make progress");
+}
+
+fn send(socket: &mut Socket, command: &ApiCommand) {
+    let text = serde_json::to_string(command).unwrap();
+    socket.send(Message::text(text)).unwrap();
+}
+
+/// Updates the application until a message `select` picks comes.
+fn receive<T>(app: &mut App, socket: &mut Socket, select: impl Fn(ApiMessage) -> Option<T>) -> T {
+    for _ in 0..2_000 {
+        app.update();
+        match socket.read() {
+            Ok(message) => {
+                let text = message.into_text().unwrap();
+                if let Some(selected) = select(serde_json::from_str(&text).unwrap()) {
+                    return selected;
+                }
+            }
+            Err(tungstenite::Error::Io(_)) => {}
+            Err(error) => panic!("{error}"),
+        }
+    }
+    panic!("no such message");
+}
+
+#[test]
+fn clients_follow_their_channels_and_command_the_run() {
+    let (mut app, mut socket) = api("127.0.0.1:47043".parse().unwrap());
+    send(
+        &mut socket,
+        &ApiCommand::Subscribe {
+            channels: vec!["test/value".to_string()],
+            rate: Some(200.0),
+        },
+    );
+    run_until(&mut app, |app| {
+        app.world().resource::<WebSocketApi>().clients[&0].channels == ["test/value"]
+    });
+    let mut telemetry = app.world_mut().resource_mut::<Telemetry>();
+    telemetry.record("test/value", 0.0, 1.5);
+    telemetry.record("test/other", 0.0, 2.0);
+    let channels = receive(&mut app, &mut socket, |message| match message {
+        ApiMessage::Telemetry { channels, .. } if !channels.is_empty() => Some(channels),
+        _ => None,
+    });
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels["test/value"], vec![[0.0, 1.5]]);
+
+    send(
+        &mut socket,
+        &ApiCommand::Setpoint {
+            name: MOTOR_VELOCITY.to_string(),
+            value: 2.5,
+        },
+    );
+    send(&mut socket, &ApiCommand::Pause);
+    run_until(&mut app, |app| {
+        app.world().resource::<SimClock>().is_paused()
+            && app.world().resource::<Setpoints>().get(MOTOR_VELOCITY) == Some(2.5)
+    });
+}
+
+#[test]
+fn commands_without_their_controller_are_answered_with_an_error() {
+    let (mut app, mut socket) = api("127.0.0.1:47044".parse().unwrap());
+    let command: ApiCommand = serde_json::from_str(r#"{"type":"pid_gains","kp":8.0}"#).unwrap();
+    assert_eq!(
+        command,
+        ApiCommand::PidGains {
+            kp: Some(8.0),
+            ki: None,
+            kd: None
+        }
+    );
+    send(&mut socket, &command);
+    let message = receive(&mut app, &mut socket, |message| match message {
+        ApiMessage::Error { message } => Some(message),
+        _ => None,
+    });
+    assert!(message.contains("position loop"), "{message}");
+}
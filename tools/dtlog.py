@@ -0,0 +1,87 @@
+#!/usr/bin/env python3
+"""Reader of the streamed telemetry logs (.dtlog) of the playground.
+
+The format is described in src/stream_log.rs. Only the standard library is needed:
+
+    import dtlog
+    log = dtlog.read("telemetry.dtlog")
+    times, values = zip(*log.channels["motor/angle"])
+
+Run as a script, it prints the channels of a log:
+
+    python3 tools/dtlog.py ~/.local/share/digital-twin-playground/sessions/current/telemetry.dtlog
+"""
+import json
+import struct
+import sys
+
+MAGIC = b"DTPL"
+VERSION = 1
+CHANNEL_FRAME = 1
+SAMPLES_FRAME = 2
+# Format of the values of each type, for the struct module.
+VALUE_FORMATS = {"f32": "<f", "f64": "<d", "i32": "<i", "bool": "<?"}
+
+
+class Log:
+    """A log read back: the declared channels, in declaration order, and their samples, as
+    (time, value) pairs, by name."""
+
+    def __init__(self, schema):
+        self.schema = list(schema)
+        self.channels = {channel["name"]: [] for channel in self.schema}
+
+
+def read_frames(stream):
+    """Yields the schema of the header, then the frames of a log as (kind, payload) pairs,
+    stopping at the end of the log or at a truncated frame."""
+    if stream.read(4) != MAGIC:
+        raise ValueError("not a telemetry log")
+    (version,) = struct.unpack("<H", stream.read(2))
+    if version > VERSION:
+        raise ValueError(f"unsupported log version {version}")
+    (length,) = struct.unpack("<I", stream.read(4))
+    yield json.loads(stream.read(length))["channels"]
+    while True:
+        header = stream.read(5)
+        if len(header) < 5:
+            return
+        kind, length = struct.unpack("<BI", header)
+        payload = stream.read(length)
+        if len(payload) < length:
+            return
+        yield kind, payload
+
+
+def read(path):
+    """Reads a log file."""
+    with open(path, "rb") as stream:
+        frames = read_frames(stream)
+        log = Log(next(frames))
+        for kind, payload in frames:
+            if kind == CHANNEL_FRAME:
+                channel = json.loads(payload)
+                log.schema.append(channel)
+                log.channels.setdefault(channel["name"], [])
+            elif kind == SAMPLES_FRAME:
+                (index,) = struct.unpack_from("<H", payload)
+                channel = log.schema[index]
+                value = VALUE_FORMATS[channel.get("type", "f32")]
+                sample = struct.Struct("<f" + value[1:])
+                samples = log.channels[channel["name"]]
+                for offset in range(2, len(payload) - sample.size + 1, sample.size):
+                    samples.append(sample.unpack_from(payload, offset))
+            # Frames of later versions are skipped.
+        return log
+
+
+if __name__ == "__main__":
+    for path in sys.argv[1:]:
+        log = read(path)
+        print(path)
+        for channel in log.schema:
+            samples = log.channels[channel["name"]]
+            rate = channel.get("rate")
+            rate = f", {rate:.1f} Hz" if rate else ""
+            span = f", {samples[0][0]:.3f} to {samples[-1][0]:.3f} s" if samples else ""
+            print(f"  {channel['name']} ({channel.get('type', 'f32')}{rate}): {len(samples)} samples{span}")
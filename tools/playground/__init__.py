@@ -0,0 +1,29 @@
+"""Companion package of the playground, to control it and pull its results from scripts and
+notebooks with a few lines:
+
+    from playground import Client, plot_metrics
+
+    api = Client()
+    runs = [api.run_and_wait(f"velocity {v}", 5.0, {"motor/velocity": v}, reset=True)
+            for v in (1.0, 2.0, 4.0)]
+    plot_metrics(api, runs, "pendulum/angle", "rms")
+
+The client talks to the experiment API (`--api`), with the standard library only. The plotting
+helpers need matplotlib (`pip install ./tools[plot]`). The Gymnasium environment of
+`playground.env` steps the plants in process, with the bindings built from python/.
+"""
+from .client import ApiError, Client
+from .plotting import plot_channels, plot_log, plot_metrics, plot_recording, plot_region
+from .recordings import read_recording, read_region
+
+__all__ = [
+    "ApiError",
+    "Client",
+    "plot_channels",
+    "plot_log",
+    "plot_metrics",
+    "plot_recording",
+    "plot_region",
+    "read_recording",
+    "read_region",
+]
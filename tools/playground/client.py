@@ -0,0 +1,106 @@
+"""Client of the experiment API of the playground, described in
+docs/src/user-interface/sessions.md."""
+import json
+import time
+import urllib.error
+import urllib.parse
+import urllib.request
+
+DEFAULT_URL = "http://127.0.0.1:5712"
+
+
+class ApiError(Exception):
+    """An error answered by the API, with its HTTP status."""
+
+    def __init__(self, status, message):
+        super().__init__(f"{status}: {message}")
+        self.status = status
+        self.message = message
+
+
+class Client:
+    """Calls the endpoints of the API, returning the decoded JSON answers."""
+
+    def __init__(self, url=DEFAULT_URL, timeout=10.0):
+        self.url = url.rstrip("/")
+        self.timeout = timeout
+
+    def request(self, method, path, body=None):
+        """Sends a request, returning the decoded answer or raising an ApiError."""
+        data = None if body is None else json.dumps(body).encode()
+        request = urllib.request.Request(
+            self.url + path,
+            data=data,
+            method=method,
+            headers={"Content-Type": "application/json"},
+        )
+        try:
+            with urllib.request.urlopen(request, timeout=self.timeout) as response:
+                return json.loads(response.read())
+        except urllib.error.HTTPError as error:
+            try:
+                message = json.loads(error.read())["error"]
+            except (ValueError, KeyError):
+                message = error.reason
+            raise ApiError(error.code, message) from None
+
+    def status(self):
+        """Simulated time, whether it runs and the run in progress."""
+        return self.request("GET", "/status")
+
+    def plants(self):
+        """Built-in plants and the instances in the scene."""
+        return self.request("GET", "/plants")
+
+    def parameters(self):
+        """Current setpoints, by name."""
+        return self.request("GET", "/parameters")
+
+    def set_parameter(self, name, value):
+        """Sets a setpoint, e.g. `set_parameter("motor/velocity", 2.5)`."""
+        path = "/parameters/" + urllib.parse.quote(name)
+        return self.request("PUT", path, {"value": value})
+
+    def runs(self):
+        """Every run of the session."""
+        return self.request("GET", "/runs")
+
+    def start_run(self, name="", duration=None, parameters=None, reset=False):
+        """Starts a run, applying its parameters and resuming the clock. It stops on its own
+        after `duration` simulated seconds, if given."""
+        body = {"name": name, "reset": reset, "parameters": parameters or {}}
+        if duration is not None:
+            body["duration"] = duration
+        return self.request("POST", "/runs", body)
+
+    def run(self, run):
+        """A run, by id or as returned by the API."""
+        return self.request("GET", f"/runs/{run_id(run)}")
+
+    def stop_run(self, run):
+        """Stops a run, pausing the clock."""
+        return self.request("POST", f"/runs/{run_id(run)}/stop")
+
+    def metrics(self, run):
+        """Samples, min, max, mean, RMS and last value of each channel over a run."""
+        return self.request("GET", f"/runs/{run_id(run)}/metrics")
+
+    def wait(self, run, poll=0.2, timeout=None):
+        """Waits for a run to end, returning it."""
+        deadline = None if timeout is None else time.monotonic() + timeout
+        while True:
+            current = self.run(run)
+            if current["end"] is not None:
+                return current
+            if deadline is not None and time.monotonic() > deadline:
+                raise TimeoutError(f"run {current['id']} is still going")
+            time.sleep(poll)
+
+    def run_and_wait(self, name, duration, parameters=None, reset=False, poll=0.2):
+        """Runs for `duration` simulated seconds, returning the run once it ended."""
+        run = self.start_run(name, duration, parameters, reset)
+        return self.wait(run, poll)
+
+
+def run_id(run):
+    return run["id"] if isinstance(run, dict) else int(run)
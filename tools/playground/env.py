@@ -0,0 +1,72 @@
+"""Gymnasium environment of a plant of the playground, stepped by the Python bindings of the
+simulation core (python/, built with maturin):
+
+    from playground.env import PlaygroundEnv
+
+    def upright(observation, action):
+        return -abs(abs(observation[1]) - 3.14159)
+
+    env = PlaygroundEnv(reward=upright, max_steps=1000, action_bound=10.0)
+    observation, info = env.reset(seed=1)
+    observation, reward, terminated, truncated, info = env.step(env.action_space.sample())
+
+The reward and the termination are functions of the observation and of the action, so the task
+is defined in Python while the plant, its controllers and their configuration files are those of
+the application. Needs gymnasium and numpy (`pip install ./tools[rl]`).
+"""
+import gymnasium
+import numpy as np
+from gymnasium import spaces
+
+import playground_core
+
+
+class PlaygroundEnv(gymnasium.Env):
+    """A plant whose actions set setpoints and whose observations are telemetry channels."""
+
+    metadata = {"render_modes": []}
+
+    def __init__(
+        self,
+        reward,
+        terminated=None,
+        max_steps=None,
+        action_bound=1.0,
+        **simulation,
+    ):
+        """`reward(observation, action)` gives the reward of each step, and
+        `terminated(observation)` ends the episode when true; `max_steps` truncates it.
+        The actions are bounded by `action_bound`, a number or one per setpoint. The other
+        arguments are those of `playground_core.Simulation`: `plant`, `dt`, `substeps`,
+        `actions` and `observations`."""
+        self.simulation = playground_core.Simulation(**simulation)
+        self.reward = reward
+        self.terminated = terminated or (lambda observation: False)
+        self.max_steps = max_steps
+        self.steps = 0
+        bound = np.broadcast_to(
+            np.asarray(action_bound, dtype=np.float32), (len(self.simulation.actions),)
+        )
+        self.action_space = spaces.Box(-bound, bound, dtype=np.float32)
+        self.observation_space = spaces.Box(
+            -np.inf, np.inf, (len(self.simulation.observations),), dtype=np.float32
+        )
+
+    def reset(self, *, seed=None, options=None):
+        super().reset(seed=seed)
+        self.steps = 0
+        if seed is None:
+            seed = int(self.np_random.integers(2**63))
+        observation = np.asarray(self.simulation.reset(seed), dtype=np.float32)
+        return observation, {"time": self.simulation.time}
+
+    def step(self, action):
+        action = np.clip(action, self.action_space.low, self.action_space.high)
+        observation = np.asarray(
+            self.simulation.step([float(value) for value in action]), dtype=np.float32
+        )
+        self.steps += 1
+        reward = float(self.reward(observation, action))
+        terminated = bool(self.terminated(observation))
+        truncated = self.max_steps is not None and self.steps >= self.max_steps
+        return observation, reward, terminated, truncated, {"time": self.simulation.time}
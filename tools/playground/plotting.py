@@ -0,0 +1,69 @@
+"""Plotting helpers, with matplotlib."""
+import dtlog
+
+from .client import run_id
+from .recordings import read_recording, read_region
+
+
+def axes(ax):
+    if ax is not None:
+        return ax
+    import matplotlib.pyplot as plt
+
+    return plt.subplots()[1]
+
+
+def plot_channels(channels, names=None, ax=None):
+    """Plots (time, value) pairs by channel, all of them unless `names` are given."""
+    ax = axes(ax)
+    for name in names or channels:
+        samples = channels.get(name, [])
+        ax.plot([t for t, _ in samples], [v for _, v in samples], label=name)
+    ax.set_xlabel("Time (s)")
+    ax.legend()
+    return ax
+
+
+def plot_log(path, names=None, ax=None):
+    """Plots the channels of a streamed telemetry log (.dtlog)."""
+    return plot_channels(dtlog.read(path).channels, names, ax)
+
+
+def plot_recording(path, names=None, ax=None):
+    """Plots the channels of a CSV recording."""
+    return plot_channels(read_recording(path), names, ax)
+
+
+def plot_metrics(client, runs, channel, metric="rms", ax=None):
+    """Compares a metric of a channel (`min`, `max`, `mean`, `rms` or `last`) across runs, as
+    bars labelled with the names of the runs."""
+    ax = axes(ax)
+    runs = [client.run(run) if not isinstance(run, dict) else run for run in runs]
+    # Runs without samples of the channel have none.
+    values = [
+        client.metrics(run_id(run)).get(channel, {}).get(metric, float("nan")) for run in runs
+    ]
+    ax.bar([run["name"] for run in runs], values)
+    ax.set_ylabel(f"{metric} of {channel}")
+    return ax
+
+
+def plot_region(path, xlabel=None, ylabel=None, ax=None):
+    """Plots a region of attraction (`--map-roa`): each cell is coloured by its attracted
+    fraction and shaded by the width of its confidence interval, so the uncertain cells, along
+    the boundary, stand out."""
+    from matplotlib import colormaps
+    from matplotlib.patches import Rectangle
+
+    ax = axes(ax)
+    colours = colormaps["RdYlGn"]
+    for cell in read_region(path):
+        corner = (cell["x_min"], cell["y_min"])
+        width, height = cell["x_max"] - cell["x_min"], cell["y_max"] - cell["y_min"]
+        ax.add_patch(Rectangle(corner, width, height, color=colours(cell["fraction"])))
+        uncertainty = cell["fraction_max"] - cell["fraction_min"]
+        ax.add_patch(Rectangle(corner, width, height, color="grey", alpha=0.8 * uncertainty))
+    ax.autoscale_view()
+    ax.set_xlabel(xlabel or "x")
+    ax.set_ylabel(ylabel or "y")
+    return ax
@@ -0,0 +1,29 @@
+"""Readers of the CSV files of the playground: the recordings, described in src/recording.rs,
+and the regions of attraction, described in src/roa.rs."""
+import csv
+
+
+def read_recording(path):
+    """Reads a CSV recording into (time, value) pairs by channel, like the logs of dtlog.
+    The setpoints are the `setpoint/<name>` channels."""
+    with open(path, newline="") as file:
+        rows = csv.reader(file)
+        header = next(rows, [])
+        channels = {name: [] for name in header[1:]}
+        for row in rows:
+            time = float(row[0])
+            for name, value in zip(header[1:], row[1:]):
+                # Empty where the channel has no sample at that time.
+                if value:
+                    channels[name].append((time, float(value)))
+        return channels
+
+
+def read_region(path):
+    """Reads a region of attraction (`--map-roa`) into a list of cells, dictionaries of numbers
+    keyed by the columns: the bounds of the cell, its runs and the bounds of its attracted
+    fraction."""
+    with open(path, newline="") as file:
+        return [
+            {name: float(value) for name, value in row.items()} for row in csv.DictReader(file)
+        ]